@@ -19,7 +19,7 @@ pub use crate::merkle_tree::{
     Algorithm, HashElement, MerkleTreeInitialData, MerkleTreeRead, ZERO_HASHES,
 };
 pub use crate::node_manager::{EmptyNodeDatabase, NodeDatabase, NodeManager, NodeTransaction};
-pub use proof::{Proof, RangeProof};
+pub use proof::{Proof, RangeProof, VersionedProof, VersionedRangeProof, PROOF_VERSION_V1};
 pub use sha3::Sha3Algorithm;
 
 pub struct AppendMerkleTree<E: HashElement, A: Algorithm<E>> {
@@ -365,6 +365,17 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
         }
     }
 
+    /// Return the node at the position, or an error if it is out of the tree or unavailable,
+    /// rather than panicking as [`MerkleTreeRead::node`].
+    pub fn try_node(&self, layer: usize, position: usize) -> Result<E> {
+        if layer >= self.height() || position >= self.layer_len(layer) {
+            bail!("Out of bound: layer={} position={}", layer, position);
+        }
+        self.node_manager
+            .get_node(layer, position)
+            .ok_or_else(|| anyhow!("Node unavailable: layer={} position={}", layer, position))
+    }
+
     /// Return a list of subtrees that can be used to rebuild the tree.
     pub fn get_subtrees(&self) -> Vec<(usize, E)> {
         let mut next_index = 0;
@@ -1,6 +1,7 @@
 use crate::{ensure_eq, Algorithm, HashElement};
 use anyhow::{bail, ensure, Result};
 use serde::{Deserialize, Serialize};
+use ssz::{Decode, DecodeError, Encode};
 use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
 
 #[derive(Clone, Debug, Eq, PartialEq, DeriveEncode, DeriveDecode, Deserialize, Serialize)]
@@ -231,3 +232,329 @@ impl<E: HashElement> RangeProof<E> {
         Ok(())
     }
 }
+
+/// Version 1 of the stable proof encoding.
+pub const PROOF_VERSION_V1: u8 = 1;
+
+/// The layout of `Proof` in `PROOF_VERSION_V1`, which never changes along with `Proof`.
+#[derive(DeriveEncode, DeriveDecode)]
+struct ProofV1<T: HashElement> {
+    lemma: Vec<T>,
+    path: Vec<bool>,
+}
+
+impl<T: HashElement> From<&Proof<T>> for ProofV1<T> {
+    fn from(proof: &Proof<T>) -> Self {
+        Self {
+            lemma: proof.lemma.clone(),
+            path: proof.path.clone(),
+        }
+    }
+}
+
+impl<T: HashElement> From<ProofV1<T>> for Proof<T> {
+    fn from(proof: ProofV1<T>) -> Self {
+        Self {
+            lemma: proof.lemma,
+            path: proof.path,
+        }
+    }
+}
+
+/// The layout of `RangeProof` in `PROOF_VERSION_V1`.
+#[derive(DeriveEncode, DeriveDecode)]
+struct RangeProofV1<E: HashElement> {
+    left_proof: ProofV1<E>,
+    right_proof: ProofV1<E>,
+}
+
+/// `Proof` in the stable encoding, i.e. the version byte followed by the ssz payload of the
+/// version, so that peers could still decode proofs once the fields of `Proof` changed.
+///
+/// Proofs of versions unknown yet are decoded as `Unknown` rather than an error, so that the
+/// caller could reject them gracefully.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VersionedProof<T: HashElement> {
+    V1(Proof<T>),
+    Unknown { version: u8, payload: Vec<u8> },
+}
+
+/// `RangeProof` in the stable encoding, see [`VersionedProof`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VersionedRangeProof<E: HashElement> {
+    V1(RangeProof<E>),
+    Unknown { version: u8, payload: Vec<u8> },
+}
+
+impl<T: HashElement> VersionedProof<T> {
+    pub fn version(&self) -> u8 {
+        match self {
+            VersionedProof::V1(_) => PROOF_VERSION_V1,
+            VersionedProof::Unknown { version, .. } => *version,
+        }
+    }
+
+    /// Returns the proof, or an error if the version is unknown.
+    pub fn into_proof(self) -> Result<Proof<T>> {
+        match self {
+            VersionedProof::V1(proof) => Ok(proof),
+            VersionedProof::Unknown { version, .. } => bail!("Unknown proof version {}", version),
+        }
+    }
+}
+
+impl<E: HashElement> VersionedRangeProof<E> {
+    pub fn version(&self) -> u8 {
+        match self {
+            VersionedRangeProof::V1(_) => PROOF_VERSION_V1,
+            VersionedRangeProof::Unknown { version, .. } => *version,
+        }
+    }
+
+    /// Returns the range proof, or an error if the version is unknown.
+    pub fn into_range_proof(self) -> Result<RangeProof<E>> {
+        match self {
+            VersionedRangeProof::V1(proof) => Ok(proof),
+            VersionedRangeProof::Unknown { version, .. } => {
+                bail!("Unknown range proof version {}", version)
+            }
+        }
+    }
+}
+
+/// Proofs are encoded in the latest version.
+impl<T: HashElement> From<Proof<T>> for VersionedProof<T> {
+    fn from(proof: Proof<T>) -> Self {
+        VersionedProof::V1(proof)
+    }
+}
+
+impl<E: HashElement> From<RangeProof<E>> for VersionedRangeProof<E> {
+    fn from(proof: RangeProof<E>) -> Self {
+        VersionedRangeProof::V1(proof)
+    }
+}
+
+fn split_version(bytes: &[u8]) -> Result<(u8, &[u8]), DecodeError> {
+    bytes
+        .split_first()
+        .map(|(version, payload)| (*version, payload))
+        .ok_or(DecodeError::InvalidByteLength {
+            len: 0,
+            expected: 1,
+        })
+}
+
+fn append_versioned(version: u8, payload: &impl Encode, buf: &mut Vec<u8>) {
+    buf.push(version);
+    payload.ssz_append(buf);
+}
+
+impl<T: HashElement> Encode for VersionedProof<T> {
+    fn is_ssz_fixed_len() -> bool {
+        false
+    }
+
+    fn ssz_append(&self, buf: &mut Vec<u8>) {
+        match self {
+            VersionedProof::V1(proof) => {
+                append_versioned(PROOF_VERSION_V1, &ProofV1::from(proof), buf)
+            }
+            VersionedProof::Unknown { version, payload } => {
+                buf.push(*version);
+                buf.extend_from_slice(payload);
+            }
+        }
+    }
+
+    fn ssz_bytes_len(&self) -> usize {
+        match self {
+            VersionedProof::V1(proof) => 1 + ProofV1::from(proof).ssz_bytes_len(),
+            VersionedProof::Unknown { payload, .. } => 1 + payload.len(),
+        }
+    }
+}
+
+impl<T: HashElement> Decode for VersionedProof<T> {
+    fn is_ssz_fixed_len() -> bool {
+        false
+    }
+
+    fn from_ssz_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (version, payload) = split_version(bytes)?;
+        match version {
+            PROOF_VERSION_V1 => Ok(VersionedProof::V1(ProofV1::from_ssz_bytes(payload)?.into())),
+            version => Ok(VersionedProof::Unknown {
+                version,
+                payload: payload.to_vec(),
+            }),
+        }
+    }
+}
+
+impl<E: HashElement> Encode for VersionedRangeProof<E> {
+    fn is_ssz_fixed_len() -> bool {
+        false
+    }
+
+    fn ssz_append(&self, buf: &mut Vec<u8>) {
+        match self {
+            VersionedRangeProof::V1(proof) => append_versioned(
+                PROOF_VERSION_V1,
+                &RangeProofV1 {
+                    left_proof: ProofV1::from(&proof.left_proof),
+                    right_proof: ProofV1::from(&proof.right_proof),
+                },
+                buf,
+            ),
+            VersionedRangeProof::Unknown { version, payload } => {
+                buf.push(*version);
+                buf.extend_from_slice(payload);
+            }
+        }
+    }
+
+    fn ssz_bytes_len(&self) -> usize {
+        match self {
+            VersionedRangeProof::V1(proof) => {
+                1 + RangeProofV1 {
+                    left_proof: ProofV1::from(&proof.left_proof),
+                    right_proof: ProofV1::from(&proof.right_proof),
+                }
+                .ssz_bytes_len()
+            }
+            VersionedRangeProof::Unknown { payload, .. } => 1 + payload.len(),
+        }
+    }
+}
+
+impl<E: HashElement> Decode for VersionedRangeProof<E> {
+    fn is_ssz_fixed_len() -> bool {
+        false
+    }
+
+    fn from_ssz_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (version, payload) = split_version(bytes)?;
+        match version {
+            PROOF_VERSION_V1 => {
+                let proof = RangeProofV1::from_ssz_bytes(payload)?;
+                Ok(VersionedRangeProof::V1(RangeProof {
+                    left_proof: proof.left_proof.into(),
+                    right_proof: proof.right_proof.into(),
+                }))
+            }
+            version => Ok(VersionedRangeProof::Unknown {
+                version,
+                payload: payload.to_vec(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum_types::H256;
+
+    // Golden encodings of `PROOF_VERSION_V1`, which should never change once released, since
+    // peers of older versions decode proofs in this layout.
+    const PROOF_V1_HEX: &str = concat!(
+        "01",       // version
+        "08000000", // offset of lemma
+        "68000000", // offset of path
+        "1111111111111111111111111111111111111111111111111111111111111111",
+        "2222222222222222222222222222222222222222222222222222222222222222",
+        "3333333333333333333333333333333333333333333333333333333333333333",
+        "01", // path
+    );
+    const RANGE_PROOF_V1_HEX: &str = concat!(
+        "01",       // version
+        "08000000", // offset of left proof
+        "50000000", // offset of right proof
+        // left proof
+        "08000000",
+        "48000000",
+        "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        "cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc",
+        // right proof
+        "08000000",
+        "48000000",
+        "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb",
+        "cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc",
+    );
+    const EMPTY_RANGE_PROOF_V1_HEX: &str = "01080000001000000008000000080000000800000008000000";
+
+    fn proof() -> Proof<H256> {
+        Proof::new(
+            vec![
+                H256::repeat_byte(0x11),
+                H256::repeat_byte(0x22),
+                H256::repeat_byte(0x33),
+            ],
+            vec![true],
+        )
+        .unwrap()
+    }
+
+    fn range_proof() -> RangeProof<H256> {
+        let root = H256::repeat_byte(0xcc);
+        RangeProof {
+            left_proof: Proof::new(vec![H256::repeat_byte(0xaa), root], vec![]).unwrap(),
+            right_proof: Proof::new(vec![H256::repeat_byte(0xbb), root], vec![]).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_proof_v1_golden() {
+        let versioned = VersionedProof::from(proof());
+        assert_eq!(hex::encode(versioned.as_ssz_bytes()), PROOF_V1_HEX);
+        assert_eq!(versioned.ssz_bytes_len(), PROOF_V1_HEX.len() / 2);
+
+        let decoded =
+            VersionedProof::<H256>::from_ssz_bytes(&hex::decode(PROOF_V1_HEX).unwrap()).unwrap();
+        assert_eq!(decoded.version(), PROOF_VERSION_V1);
+        assert_eq!(decoded.into_proof().unwrap(), proof());
+    }
+
+    #[test]
+    fn test_range_proof_v1_golden() {
+        for (proof, golden) in [
+            (range_proof(), RANGE_PROOF_V1_HEX),
+            (RangeProof::new_empty(), EMPTY_RANGE_PROOF_V1_HEX),
+        ] {
+            let versioned = VersionedRangeProof::from(proof.clone());
+            assert_eq!(hex::encode(versioned.as_ssz_bytes()), golden);
+            assert_eq!(versioned.ssz_bytes_len(), golden.len() / 2);
+
+            let decoded =
+                VersionedRangeProof::<H256>::from_ssz_bytes(&hex::decode(golden).unwrap()).unwrap();
+            assert_eq!(decoded.into_range_proof().unwrap(), proof);
+        }
+
+        // the v1 payload is the same as the ssz encoding of `RangeProof` today
+        assert_eq!(
+            hex::decode(RANGE_PROOF_V1_HEX).unwrap()[1..],
+            range_proof().as_ssz_bytes()
+        );
+    }
+
+    #[test]
+    fn test_unknown_version() {
+        let mut bytes = hex::decode(RANGE_PROOF_V1_HEX).unwrap();
+        bytes[0] = PROOF_VERSION_V1 + 1;
+
+        let decoded = VersionedRangeProof::<H256>::from_ssz_bytes(&bytes).unwrap();
+        assert_eq!(decoded.version(), PROOF_VERSION_V1 + 1);
+        // encoded back as is
+        assert_eq!(decoded.as_ssz_bytes(), bytes);
+        assert!(decoded.into_range_proof().is_err());
+
+        let decoded = VersionedProof::<H256>::from_ssz_bytes(&[0xff]).unwrap();
+        assert!(decoded.into_proof().is_err());
+
+        assert!(VersionedProof::<H256>::from_ssz_bytes(&[]).is_err());
+        // corrupt payload of a known version
+        bytes[0] = PROOF_VERSION_V1;
+        assert!(VersionedRangeProof::<H256>::from_ssz_bytes(&bytes[..9]).is_err());
+    }
+}
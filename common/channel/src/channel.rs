@@ -5,6 +5,7 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::oneshot;
 use tokio::time::timeout;
+use tracing::Span;
 
 const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
 
@@ -13,7 +14,9 @@ pub type ResponseSender<Res> = oneshot::Sender<Res>;
 #[derive(Debug)]
 pub enum Message<N, Req, Res> {
     Notification(N),
-    Request(Req, ResponseSender<Res>),
+    /// Request with the span of requester, so that logs of handling the request could be
+    /// correlated with the requester, e.g. an RPC call.
+    Request(Req, ResponseSender<Res>, Span),
 }
 
 pub struct Channel<N, Req, Res> {
@@ -60,7 +63,7 @@ impl<N, Req, Res> Sender<N, Req, Res> {
         let (sender, receiver) = oneshot::channel();
 
         self.chan
-            .send(Message::Request(request, sender))
+            .send(Message::Request(request, sender, Span::current()))
             .map_err(|e| Error::SendError(e))?;
 
         timeout(DEFAULT_REQUEST_TIMEOUT, receiver)
@@ -99,7 +102,7 @@ mod tests {
         let task1 = async move {
             match rx.recv().await.expect("not dropped") {
                 Message::Notification(_) => {}
-                Message::Request(Request::GetNumber, sender) => {
+                Message::Request(Request::GetNumber, sender, _) => {
                     sender.send(Response::GetNumber(42)).expect("not dropped");
                 }
             }
@@ -41,7 +41,7 @@ impl<N, Req, Res: Debug> TestReceiver<N, Req, Res> {
 
         match request {
             Message::Notification(..) => panic!("Unexpected message type"),
-            Message::Request(_, resp_sender) => {
+            Message::Request(_, resp_sender, _) => {
                 resp_sender.send(response).expect("Channel closed");
             }
         }
@@ -2,10 +2,12 @@
 extern crate tracing;
 
 mod metrics;
+pub mod shutdown;
 pub mod test_utils;
 
 use futures::channel::mpsc::Sender;
 use futures::prelude::*;
+use shutdown::{ShutdownCoordinator, ShutdownToken};
 use std::sync::Weak;
 use tokio::runtime::{Handle, Runtime};
 
@@ -81,6 +83,8 @@ pub struct TaskExecutor {
     ///
     /// The task must provide a reason for shutting down.
     signal_tx: Sender<ShutdownReason>,
+    /// Coordinates the graceful shutdown of services before `exit` fires.
+    shutdown: ShutdownCoordinator,
 }
 
 impl TaskExecutor {
@@ -100,15 +104,23 @@ impl TaskExecutor {
             handle_provider: handle.into(),
             exit,
             signal_tx,
+            shutdown: Default::default(),
         }
     }
 
+    /// Shares the coordinator to shut down services gracefully, e.g. of the environment.
+    pub fn with_shutdown_coordinator(mut self, shutdown: ShutdownCoordinator) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
     /// Clones the task executor adding a service name.
     pub fn clone_with_name(&self) -> Self {
         TaskExecutor {
             handle_provider: self.handle_provider.clone(),
             exit: self.exit.clone(),
             signal_tx: self.signal_tx.clone(),
+            shutdown: self.shutdown.clone(),
         }
     }
 
@@ -366,4 +378,9 @@ impl TaskExecutor {
     pub fn shutdown_sender(&self) -> Sender<ShutdownReason> {
         self.signal_tx.clone()
     }
+
+    /// Registers a service to shut down gracefully, see `ShutdownCoordinator`.
+    pub fn shutdown_token(&self, service: &'static str) -> ShutdownToken {
+        self.shutdown.token(service)
+    }
 }
@@ -0,0 +1,208 @@
+//! Coordinates the graceful shutdown of services.
+//!
+//! Once the node is requested to shut down, e.g. by SIGTERM, all services are notified via their
+//! `ShutdownToken`, so as to stop accepting new work and flush pending writes, and acknowledge
+//! the token before the exit signal fires and cancels all spawned tasks.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{oneshot, watch};
+use tokio::time::Instant;
+
+/// How a service exited during the graceful shutdown.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ServiceExit {
+    /// The token is acknowledged within the grace period.
+    Clean,
+    /// The token is dropped without acknowledgement, e.g. service exited with error before.
+    Dropped,
+    /// The token is not acknowledged within the grace period.
+    TimedOut,
+}
+
+/// Hands out shutdown tokens to services, and waits for them to acknowledge once the node is
+/// shutting down.
+#[derive(Clone)]
+pub struct ShutdownCoordinator {
+    signal: Arc<watch::Sender<bool>>,
+    acks: Arc<Mutex<Vec<(&'static str, oneshot::Receiver<()>)>>>,
+}
+
+impl Default for ShutdownCoordinator {
+    fn default() -> Self {
+        Self {
+            signal: Arc::new(watch::channel(false).0),
+            acks: Default::default(),
+        }
+    }
+}
+
+impl ShutdownCoordinator {
+    /// Registers a service to shut down gracefully.
+    pub fn token(&self, service: &'static str) -> ShutdownToken {
+        let (ack_send, ack_recv) = oneshot::channel();
+        self.acks.lock().unwrap().push((service, ack_recv));
+
+        ShutdownToken {
+            service,
+            signal: ShutdownSignal(self.signal.subscribe()),
+            ack: ack_send,
+        }
+    }
+
+    /// Notifies all services to shut down, and waits until all registered services acknowledged
+    /// or `grace_period` elapsed.
+    pub async fn shutdown(&self, grace_period: Duration) -> ShutdownSummary {
+        self.signal.send_replace(true);
+
+        let acks = std::mem::take(&mut *self.acks.lock().unwrap());
+        let deadline = Instant::now() + grace_period;
+        let services =
+            futures::future::join_all(acks.into_iter().map(|(service, ack)| async move {
+                let exit = match tokio::time::timeout_at(deadline, ack).await {
+                    Ok(Ok(())) => ServiceExit::Clean,
+                    Ok(Err(_)) => ServiceExit::Dropped,
+                    Err(_) => ServiceExit::TimedOut,
+                };
+                (service, exit)
+            }))
+            .await;
+
+        ShutdownSummary { services }
+    }
+}
+
+/// Notified once the node is shutting down, which could be cloned for sub tasks of a service.
+#[derive(Clone)]
+pub struct ShutdownSignal(watch::Receiver<bool>);
+
+impl ShutdownSignal {
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once the node is shutting down, which is cancel safe to use in `select!`.
+    pub async fn requested(&mut self) {
+        if self.0.wait_for(|requested| *requested).await.is_err() {
+            // coordinator dropped, and shutdown will never be requested
+            futures::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Handed to a service to get notified once the node is shutting down. The service should stop
+/// accepting new work, flush pending writes, and then acknowledge the token.
+pub struct ShutdownToken {
+    service: &'static str,
+    signal: ShutdownSignal,
+    ack: oneshot::Sender<()>,
+}
+
+impl ShutdownToken {
+    pub fn service(&self) -> &'static str {
+        self.service
+    }
+
+    pub fn signal(&self) -> ShutdownSignal {
+        self.signal.clone()
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.signal.is_requested()
+    }
+
+    /// Resolves once the node is shutting down, which is cancel safe to use in `select!`.
+    pub async fn requested(&mut self) {
+        self.signal.requested().await
+    }
+
+    /// Acknowledges that the service has stopped and all pending writes flushed.
+    pub fn ack(self) {
+        debug!(service = self.service, "Service shut down");
+        let _ = self.ack.send(());
+    }
+}
+
+/// Reports how every registered service exited during the graceful shutdown.
+#[derive(Debug, Default)]
+pub struct ShutdownSummary {
+    pub services: Vec<(&'static str, ServiceExit)>,
+}
+
+impl ShutdownSummary {
+    /// Returns `true` if all services exited cleanly.
+    pub fn is_clean(&self) -> bool {
+        self.services
+            .iter()
+            .all(|(_, exit)| *exit == ServiceExit::Clean)
+    }
+
+    fn services_of(&self, exit: ServiceExit) -> Vec<&'static str> {
+        self.services
+            .iter()
+            .filter(|(_, e)| *e == exit)
+            .map(|(service, _)| *service)
+            .collect()
+    }
+}
+
+impl fmt::Display for ShutdownSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "exited cleanly: {:?}, timed out: {:?}, dropped: {:?}",
+            self.services_of(ServiceExit::Clean),
+            self.services_of(ServiceExit::TimedOut),
+            self.services_of(ServiceExit::Dropped)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let coordinator = ShutdownCoordinator::default();
+        let mut clean = coordinator.token("clean");
+        let stuck = coordinator.token("stuck");
+        let dropped = coordinator.token("dropped");
+        assert!(!clean.is_requested());
+
+        let mut signal = clean.signal();
+        let service = tokio::spawn(async move {
+            clean.requested().await;
+            clean.ack();
+        });
+        drop(dropped);
+
+        let summary = coordinator.shutdown(Duration::from_millis(100)).await;
+        service.await.unwrap();
+        assert!(stuck.is_requested());
+        signal.requested().await;
+
+        assert!(!summary.is_clean());
+        assert_eq!(
+            summary.services,
+            vec![
+                ("clean", ServiceExit::Clean),
+                ("stuck", ServiceExit::TimedOut),
+                ("dropped", ServiceExit::Dropped),
+            ]
+        );
+        assert_eq!(
+            summary.to_string(),
+            r#"exited cleanly: ["clean"], timed out: ["stuck"], dropped: ["dropped"]"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_without_services() {
+        let summary = ShutdownCoordinator::default()
+            .shutdown(Duration::from_secs(1))
+            .await;
+        assert!(summary.is_clean());
+    }
+}
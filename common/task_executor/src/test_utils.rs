@@ -42,6 +42,23 @@ impl Default for TestRuntime {
     }
 }
 
+impl TestRuntime {
+    /// Instantiates `Self` using a handle to the runtime it is called in, so that the spawned tasks
+    /// share its clock, e.g. the time paused by `#[tokio::test(start_paused = true)]`.
+    pub fn current() -> Self {
+        let (runtime_shutdown, exit) = exit_future::signal();
+        let (shutdown_tx, _) = futures::channel::mpsc::channel(1);
+
+        let task_executor = TaskExecutor::new(runtime::Handle::current(), exit, shutdown_tx);
+
+        Self {
+            runtime: None,
+            _runtime_shutdown: runtime_shutdown,
+            task_executor,
+        }
+    }
+}
+
 impl Drop for TestRuntime {
     fn drop(&mut self) {
         if let Some(runtime) = self.runtime.take() {
@@ -21,6 +21,25 @@ pub const VERSION: &str = git_version!(
     fallback = "unknown"
 );
 
+/// Semantic version of the node.
+pub const SEMVER: &str = env!("CARGO_PKG_VERSION");
+
+/// Short git commit of this build, or `unknown` if built out of a git repository.
+pub const GIT_COMMIT: &str = env!("ZGS_GIT_COMMIT");
+
+/// Unix timestamp in seconds when this build is compiled.
+pub const BUILD_TIMESTAMP: &str = env!("ZGS_BUILD_TIMESTAMP");
+
+/// Returns `BUILD_TIMESTAMP` in seconds.
+pub fn build_timestamp() -> u64 {
+    BUILD_TIMESTAMP.parse().unwrap_or_default()
+}
+
+/// Returns `SEMVER` with the git commit, e.g. `v0.3.0-67da032`.
+pub fn semver_with_commit() -> String {
+    format!("v{}-{}", SEMVER, GIT_COMMIT)
+}
+
 /// Returns `VERSION`, but with `zgs` prefix and platform information appended to the end.
 ///
 /// ## Example
@@ -30,6 +49,25 @@ pub fn version_with_platform() -> String {
     format!("zgs/{}/{}-{}", VERSION, Target::arch(), Target::os())
 }
 
+/// Returns the agent string advertised via the libp2p identify protocol, which includes the
+/// P2P protocol version, so that peers could detect incompatible nodes.
+///
+/// ## Example
+///
+/// `zgs/v0.3.0-67da032/x86_64-linux/0.4.0`
+pub fn agent_version(p2p_protocol_version: [u8; 3]) -> String {
+    let [major, minor, build] = p2p_protocol_version;
+    format!(
+        "zgs/{}/{}-{}/{}.{}.{}",
+        semver_with_commit(),
+        Target::arch(),
+        Target::os(),
+        major,
+        minor,
+        build
+    )
+}
+
 // #[cfg(test)]
 // mod test {
 //     use super::*;
@@ -46,3 +84,25 @@ pub fn version_with_platform() -> String {
 //         );
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn test_agent_version() {
+        let re = Regex::new(
+            r"^zgs/v[0-9]+\.[0-9]+\.[0-9]+-([[:xdigit:]]{7}|unknown)/[^/]+-[^/]+/0\.4\.0$",
+        )
+        .unwrap();
+        let agent_version = agent_version([0, 4, 0]);
+        assert!(
+            re.is_match(&agent_version),
+            "agent version doesn't match regex: {}",
+            agent_version
+        );
+        assert!(agent_version.starts_with(&format!("zgs/v{}-{}/", SEMVER, GIT_COMMIT)));
+        assert!(build_timestamp() > 0);
+    }
+}
@@ -0,0 +1,122 @@
+use shared_types::DataRoot;
+use std::error::Error as ErrorTrait;
+use std::fmt::{Debug, Display, Formatter};
+
+/// Errors of chunk pool operations.
+///
+/// Chunk pool APIs return `anyhow::Result`, and callers could `downcast_ref` the error to this
+/// type to handle the failure case accordingly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The segment data is empty.
+    EmptySegment,
+    /// The segment data length is not a multiple of chunk size.
+    InvalidSegmentSize(usize),
+    /// The memory cached chunks exceed the limit of whole pool.
+    PoolFull { limit: usize },
+    /// The memory cached chunks exceed the limit of a single file.
+    FileCacheFull { limit: usize },
+    /// The chunks spilled to disk exceed the limit of whole pool.
+    SpillFull { limit: usize },
+    /// Too many segments are writing into store concurrently.
+    TooManyWritings { limit: usize },
+    /// The cached file is not found.
+    FileNotFound(DataRoot),
+    /// The segment index exceeds the total segments of the expected file size.
+    SegmentOutOfRange { index: usize, num_segments: usize },
+    /// The number of chunks in segment does not match with the expected file size.
+    InvalidSegmentLength {
+        index: usize,
+        expected: usize,
+        actual: usize,
+    },
+    /// The segment proof is not of the merkle tree of the expected file size.
+    InvalidProofDepth(usize),
+    /// The file size or total segments do not match with the previously uploaded segment.
+    FileSizeMismatch { expected: usize, actual: usize },
+    /// The segment has already been uploaded or is being uploaded.
+    SegmentAlreadyUploaded(usize),
+    /// A different segment has already been uploaded at the same index.
+    SegmentConflicted(usize),
+    /// The transaction reverted during uploading.
+    TxReverted,
+    /// The node is shutting down, and no more data is accepted.
+    ShuttingDown,
+    /// The node is read-only, and no data is accepted.
+    ReadOnly,
+}
+
+impl Error {
+    /// Returns if the failed operation could succeed when retried later.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Error::PoolFull { .. }
+                | Error::FileCacheFull { .. }
+                | Error::SpillFull { .. }
+                | Error::TooManyWritings { .. }
+        )
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::EmptySegment => write!(f, "data is empty"),
+            Error::InvalidSegmentSize(len) => write!(f, "invalid data length: {}", len),
+            Error::PoolFull { limit } => {
+                write!(f, "exceeds the maximum cached chunks of whole pool: {}", limit)
+            }
+            Error::FileCacheFull { limit } => {
+                write!(f, "exceeds the maximum cached chunks of a file: {}", limit)
+            }
+            Error::SpillFull { limit } => {
+                write!(f, "exceeds the maximum spilled chunks of whole pool: {}", limit)
+            }
+            Error::TooManyWritings { limit } => write!(f, "too many data writing: {}", limit),
+            Error::SegmentOutOfRange {
+                index,
+                num_segments,
+            } => write!(
+                f,
+                "segment index out of bound: index={}, num_segments={}",
+                index, num_segments
+            ),
+            Error::InvalidSegmentLength {
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "invalid number of chunks in segment {}: expected={}, actual={}",
+                index, expected, actual
+            ),
+            Error::InvalidProofDepth(index) => write!(
+                f,
+                "proof of segment {} does not match the tree depth of file size",
+                index
+            ),
+            Error::FileNotFound(root) => write!(f, "file not found in chunk pool: {:?}", root),
+            Error::FileSizeMismatch { expected, actual } => write!(
+                f,
+                "file size in segment doesn't match with file size declared in previous segment. Previous:{}, current:{}",
+                expected, actual
+            ),
+            Error::SegmentAlreadyUploaded(index) => write!(
+                f,
+                "segment has already been uploaded or is being uploaded: {}",
+                index
+            ),
+            Error::SegmentConflicted(index) => write!(
+                f,
+                "segment conflicts with the previously uploaded segment: {}",
+                index
+            ),
+            Error::TxReverted => write!(f, "Transaction reverted, please upload again"),
+            Error::ShuttingDown => write!(f, "Node is shutting down, please upload to other nodes"),
+            Error::ReadOnly => write!(f, "Node is read-only, please upload to other nodes"),
+        }
+    }
+}
+
+impl ErrorTrait for Error {}
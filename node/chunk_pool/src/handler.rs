@@ -2,23 +2,37 @@ use super::mem_pool::MemoryChunkPool;
 use crate::mem_pool::FileID;
 use anyhow::Result;
 use metrics::{Histogram, Sample};
-use network::{NetworkMessage, NetworkSender};
 use shared_types::{ChunkArray, FileProof};
-use std::{sync::Arc, time::Instant};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use storage_async::{ShardConfig, Store};
+use task_executor::shutdown::ShutdownToken;
 use tokio::sync::mpsc::UnboundedReceiver;
 
 lazy_static::lazy_static! {
     pub static ref FINALIZE_FILE_LATENCY: Arc<dyn Histogram> = Sample::ExpDecay(0.015).register("chunk_pool_finalize_file_latency", 1024);
+    pub static ref FINALIZE_BATCH_SIZE: Arc<dyn Histogram> = Sample::ExpDecay(0.015).register("chunk_pool_finalize_batch_size", 1024);
 }
 
+/// Window to accumulate the written files, which are finalized together in one store write.
+const FINALIZE_WINDOW: Duration = Duration::from_millis(100);
+
+/// Maximum number of files finalized together, beyond which the files are finalized at once
+/// without waiting for the window.
+const MAX_FINALIZE_BATCH_SIZE: usize = 256;
+
 /// Handle the cached file when uploaded completely and verified from blockchain.
 /// Generally, the file will be persisted into log store.
 pub struct ChunkPoolHandler {
     receiver: UnboundedReceiver<ChunkPoolMessage>,
     mem_pool: Arc<MemoryChunkPool>,
     log_store: Arc<Store>,
-    sender: NetworkSender,
+    /// Files written into store to finalize in batch.
+    pending: Vec<FileID>,
+    /// When the oldest file in `pending` is written.
+    pending_since: Option<Instant>,
 }
 
 impl ChunkPoolHandler {
@@ -26,18 +40,18 @@ impl ChunkPoolHandler {
         receiver: UnboundedReceiver<ChunkPoolMessage>,
         mem_pool: Arc<MemoryChunkPool>,
         log_store: Arc<Store>,
-        sender: NetworkSender,
     ) -> Self {
         ChunkPoolHandler {
             receiver,
             mem_pool,
             log_store,
-            sender,
+            pending: vec![],
+            pending_since: None,
         }
     }
 
-    async fn handle(&mut self) -> Result<bool> {
-        match self.receiver.recv().await {
+    async fn handle(&mut self, msg: Option<ChunkPoolMessage>) -> Result<bool> {
+        match msg {
             Some(ChunkPoolMessage::FinalizeFile(file_id)) => self.handle_file_id(file_id).await,
             Some(ChunkPoolMessage::ChangeShardConfig(shard_config)) => {
                 self.handle_change_shard_config(shard_config).await;
@@ -47,16 +61,23 @@ impl ChunkPoolHandler {
         }
     }
 
-    /// Writes memory cached chunks into store and finalize transaction.
+    /// Writes memory cached chunks into store, and queues the transaction to finalize in batch.
+    ///
+    /// The flush journal of the file is kept until finalized or failed to finalize, so that a
+    /// file queued but not finalized before crash is finalized after restart, either written
+    /// here or by the write window before.
     /// Note, a separate thread should be spawned to call this method.
     async fn handle_file_id(&mut self, id: FileID) -> Result<bool> {
         debug!(?id, "Received task to finalize transaction");
 
         // TODO(qhz): remove from memory pool after transaction finalized,
         // when store support to write chunks with reference.
-        if let Some(file) = self.mem_pool.remove_cached_file(&id.root).await {
+        if let Some(file) = self.mem_pool.remove_cached_file(&id.root).await? {
             // If there is still cache of chunks, write them into store
             let mut segments: Vec<(ChunkArray, FileProof)> = file.segments.into_values().collect();
+            self.mem_pool
+                .put_flush_journal(id, segments.iter().map(|(seg, _)| seg))
+                .await?;
             while let Some((seg, proof)) = segments.pop() {
                 if !self
                     .log_store
@@ -73,45 +94,120 @@ impl ChunkPoolHandler {
             }
         }
 
+        if self.pending.is_empty() {
+            self.pending_since = Some(Instant::now());
+        }
+        self.pending.push(id);
+        if self.pending.len() >= MAX_FINALIZE_BATCH_SIZE {
+            self.flush_finalization().await;
+        }
+
+        Ok(true)
+    }
+
+    /// Finalizes the queued files in one store write. If failed, e.g. data of some file is
+    /// missing, files are finalized one by one so that a bad file never blocks the others.
+    ///
+    /// The flush journals of the files failed to finalize are removed, which could not be
+    /// finalized after restart either.
+    async fn flush_finalization(&mut self) {
+        let files = std::mem::take(&mut self.pending);
+        self.pending_since = None;
+        if files.is_empty() {
+            return;
+        }
+
+        // skip files reverted since written
         let start = Instant::now();
-        if !self
-            .log_store
-            .finalize_tx_with_hash(id.tx_id.seq, id.tx_id.hash)
-            .await?
-        {
-            return Ok(false);
+        let mut to_finalize = Vec::with_capacity(files.len());
+        let mut failed = vec![];
+        for id in files {
+            match self.log_store.get_tx_by_seq_number(id.tx_id.seq).await {
+                Ok(Some(tx)) if tx.hash() == id.tx_id.hash => to_finalize.push(id),
+                Ok(_) => debug!(?id, "Skip to finalize reverted transaction"),
+                Err(e) => warn!(?id, "Failed to get transaction to finalize, {:?}", e),
+            }
         }
 
+        let tx_seqs = to_finalize.iter().map(|id| id.tx_id.seq).collect();
+        let finalized = match self.log_store.finalize_txs(tx_seqs).await {
+            Ok(()) => to_finalize,
+            Err(e) => {
+                debug!("Failed to finalize transactions in batch, {:?}", e);
+                let mut finalized = vec![];
+                for id in to_finalize {
+                    match self
+                        .log_store
+                        .finalize_tx_with_hash(id.tx_id.seq, id.tx_id.hash)
+                        .await
+                    {
+                        Ok(true) => finalized.push(id),
+                        Ok(false) => {}
+                        Err(e) => {
+                            warn!(?id, "Failed to finalize transaction, {:?}", e);
+                            failed.push(id);
+                        }
+                    }
+                }
+                finalized
+            }
+        };
+
         let elapsed = start.elapsed();
-        debug!(?id, ?elapsed, "Transaction finalized");
+        debug!(
+            num_files = finalized.len(),
+            ?elapsed,
+            "Transactions finalized"
+        );
         FINALIZE_FILE_LATENCY.update_since(start);
+        FINALIZE_BATCH_SIZE.update(finalized.len() as u64);
 
         // always remove file from pool after transaction finalized
-        self.mem_pool.remove_file(&id.root).await;
-
-        let msg = NetworkMessage::AnnounceLocalFile { tx_id: id.tx_id };
-        if let Err(e) = self.sender.send(msg) {
-            error!(
-                "Failed to send NetworkMessage::AnnounceLocalFile message, tx_seq={}, err={}",
-                id.tx_id.seq, e
-            );
+        // The file is announced by router once finalized in store.
+        for id in finalized {
+            self.mem_pool.remove_file(&id.root).await;
         }
 
-        Ok(true)
+        for id in failed {
+            if let Err(e) = self.log_store.remove_flush_journal(id.tx_id.seq).await {
+                warn!(?id, "Failed to remove flush journal, {:?}", e);
+            }
+        }
     }
 
     async fn handle_change_shard_config(&self, shard_config: ShardConfig) {
         self.mem_pool.set_shard_config(shard_config).await
     }
 
-    pub async fn run(mut self) {
+    /// Finalizes transactions until the node is shutting down. Files queued but not finalized
+    /// yet are reconciled via flush journals after restart.
+    pub async fn run(mut self, mut shutdown: ShutdownToken) {
         info!("Worker started to finalize transactions");
 
         loop {
-            if let Err(e) = self.handle().await {
+            let flush_at = self.pending_since.unwrap_or_else(Instant::now) + FINALIZE_WINDOW;
+            let msg = tokio::select! {
+                biased;
+
+                _ = shutdown.requested() => break,
+
+                _ = tokio::time::sleep_until(flush_at.into()), if self.pending_since.is_some() => {
+                    self.flush_finalization().await;
+                    continue;
+                }
+
+                msg = self.receiver.recv() => msg,
+            };
+
+            if let Err(e) = self.handle(msg).await {
                 warn!("Failed to write chunks or finalize transaction, {:?}", e);
             }
         }
+
+        self.flush_finalization().await;
+        self.mem_pool.close().await;
+        info!("Worker stopped with all in-flight writes flushed");
+        shutdown.ack();
     }
 }
 
@@ -1,23 +1,37 @@
 #[macro_use]
 extern crate tracing;
 
+mod error;
 mod handler;
 mod mem_pool;
 
+pub use error::Error;
 pub use handler::{ChunkPoolHandler, ChunkPoolMessage};
-pub use mem_pool::{FileID, MemoryChunkPool, SegmentInfo};
+pub use mem_pool::{
+    ChunkPoolStatus, FileID, MemoryChunkPool, PoolFileState, PoolFileStatus, SegmentCheck,
+    SegmentInfo,
+};
 
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use storage_async::ShardConfig;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Config {
     pub write_window_size: usize,
     pub max_cached_chunks_all: usize,
+    pub max_cached_chunks_per_file: usize,
+    /// Maximum number of chunks spilled to disk for the whole pool.
+    pub max_spilled_chunks_all: usize,
     pub max_writings: usize,
     pub expiration_time_secs: u64,
     pub shard_config: ShardConfig,
+    /// Directory to spill whole files to when the memory caps reached. If not specified,
+    /// new segments are rejected with a retryable error instead.
+    pub spill_dir: Option<PathBuf>,
+    /// Rejects all uploads, e.g. the node serves a snapshot read-only.
+    pub read_only: bool,
 }
 
 impl Config {
@@ -29,12 +43,11 @@ impl Config {
 pub fn unbounded(
     config: Config,
     log_store: Arc<storage_async::Store>,
-    network_send: network::NetworkSender,
 ) -> (Arc<MemoryChunkPool>, ChunkPoolHandler) {
     let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
 
     let mem_pool = Arc::new(MemoryChunkPool::new(config, log_store.clone(), sender));
-    let handler = ChunkPoolHandler::new(receiver, mem_pool.clone(), log_store, network_send);
+    let handler = ChunkPoolHandler::new(receiver, mem_pool.clone(), log_store);
 
     (mem_pool, handler)
 }
@@ -1,9 +1,12 @@
-use super::FileID;
+use super::metrics;
+use super::spill::{PendingSpill, SpillFile, SpillStore};
+use super::{check_received, segment_digest, FileID, PoolFileState, PoolFileStatus, SegmentDigest};
+use crate::error::Error;
 use crate::{Config, SegmentInfo};
 use anyhow::{bail, Result};
 use hashlink::LinkedHashMap;
 use shared_types::{bytes_to_chunks, ChunkArray, DataRoot, FileProof, Transaction, CHUNK_SIZE};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::Add;
 use std::time::{Duration, Instant};
 
@@ -12,28 +15,47 @@ use std::time::{Duration, Instant};
 pub struct MemoryCachedFile {
     pub id: FileID,
     pub chunks_per_segment: usize,
+    /// File size declared by the first uploaded segment, which is validated against the
+    /// transaction once log entry retrieved.
+    pub file_size: usize,
     /// Window to control the cache of each file
     pub segments: HashMap<usize, (ChunkArray, FileProof)>,
+    /// Segments that spilled to disk, which are not in `segments`.
+    spilled_segments: HashSet<usize>,
+    /// Digests of all the cached segments, including spilled segments.
+    digests: HashMap<usize, SegmentDigest>,
     /// Total number of chunks for the cache file, which is updated from log entry.
     pub total_chunks: usize,
     /// Used for garbage collection. It is updated when new segment uploaded.
     expired_at: Instant,
-    /// Number of chunks that's currently cached for this file
+    /// When the first segment cached.
+    cached_at: Instant,
+    /// Number of chunks that's currently cached for this file, including spilled chunks.
     pub cached_chunk_num: usize,
+    /// Number of chunks that spilled to disk for this file.
+    spilled_chunk_num: usize,
+    /// Where the segments spilled to, which is allocated once the file spilled.
+    spill_file: Option<SpillFile>,
 }
 
 impl MemoryCachedFile {
-    fn new(root: DataRoot, timeout: Duration, chunks_per_segment: usize) -> Self {
+    fn new(root: DataRoot, timeout: Duration, chunks_per_segment: usize, file_size: usize) -> Self {
         MemoryCachedFile {
             id: FileID {
                 root,
                 tx_id: Default::default(),
             },
             chunks_per_segment,
+            file_size,
             segments: HashMap::default(),
+            spilled_segments: HashSet::default(),
+            digests: HashMap::default(),
             total_chunks: 0,
             expired_at: Instant::now().add(timeout),
+            cached_at: Instant::now(),
             cached_chunk_num: 0,
+            spilled_chunk_num: 0,
+            spill_file: None,
         }
     }
 
@@ -56,6 +78,93 @@ impl MemoryCachedFile {
     fn should_flush(&self) -> bool {
         self.total_chunks > 0 && self.cached_chunk_num > 0
     }
+
+    /// Returns the number of cached segments, including spilled segments.
+    pub fn num_segments(&self) -> usize {
+        self.segments.len() + self.spilled_segments.len()
+    }
+
+    /// Returns whether the segment has already been cached with the same data, or an error
+    /// if a different segment cached at the same index.
+    pub fn check_received(&self, seg_index: usize, digest: &SegmentDigest) -> Result<bool> {
+        check_received(&self.digests, seg_index, digest)
+    }
+
+    /// Returns the indices of cached segments, including spilled segments.
+    pub fn received_segments(&self) -> Vec<usize> {
+        self.digests.keys().copied().collect()
+    }
+
+    fn is_spilled(&self) -> bool {
+        !self.spilled_segments.is_empty()
+    }
+
+    /// Returns the number of chunks cached in memory.
+    fn memory_chunk_num(&self) -> usize {
+        self.cached_chunk_num - self.spilled_chunk_num
+    }
+
+    fn status(&self, now: Instant) -> PoolFileStatus {
+        let tx_retrieved = self.total_chunks > 0;
+        let state = if tx_retrieved {
+            PoolFileState::Flushing
+        } else if self.is_spilled() {
+            PoolFileState::Spilled
+        } else {
+            PoolFileState::Pending
+        };
+
+        PoolFileStatus {
+            root: self.id.root,
+            tx_seq: tx_retrieved.then_some(self.id.tx_id.seq),
+            state,
+            buffered_segments: self.num_segments(),
+            bytes: self.memory_chunk_num() * CHUNK_SIZE,
+            spilled_bytes: self.spilled_chunk_num * CHUNK_SIZE,
+            age: now.saturating_duration_since(self.cached_at),
+        }
+    }
+
+    /// Moves all memory cached segments along with the new segment to disk, and returns the
+    /// segments to write once the pool lock released.
+    fn spill(
+        &mut self,
+        store: &SpillStore,
+        seg_index: usize,
+        (seg, proof): (ChunkArray, FileProof),
+    ) -> Result<PendingSpill> {
+        let root = self.id.root;
+        let spill_file = self
+            .spill_file
+            .get_or_insert_with(|| store.new_file(&root))
+            .clone();
+
+        let mut pending = spill_file.prepare_write()?;
+        let new_segment = (seg_index, (seg, proof));
+        for (index, (seg, proof)) in self.segments.drain().chain(std::iter::once(new_segment)) {
+            self.spilled_segments.insert(index);
+            self.spilled_chunk_num += seg.data.len() / CHUNK_SIZE;
+            pending.add(index, seg, proof);
+        }
+
+        Ok(pending)
+    }
+
+    /// Reads all spilled segments back into memory, and removes them from disk.
+    pub async fn restore(&mut self) -> Result<()> {
+        let spill_file = match self.spill_file.take() {
+            Some(spill_file) => spill_file,
+            None => return Ok(()),
+        };
+
+        let indices = self.spilled_segments.drain().collect();
+        self.spilled_chunk_num = 0;
+        for (seg_index, segment) in spill_file.restore(indices).await? {
+            self.segments.insert(seg_index, segment);
+        }
+
+        Ok(())
+    }
 }
 
 /// ChunkPoolCache is used to cache small files that log entry not retrieved
@@ -68,14 +177,26 @@ pub struct ChunkPoolCache {
     files: LinkedHashMap<DataRoot, MemoryCachedFile>,
     /// Total number of chunks that cached in the memory pool.
     pub total_chunks: usize,
+    /// Total number of chunks that spilled to disk.
+    pub total_spilled_chunks: usize,
+    /// Used to spill files when memory caps reached. If not configured, new segments are
+    /// rejected instead.
+    spill_store: Option<SpillStore>,
+    /// Spilled files of the dropped files, which are removed once the pool lock released.
+    dropped_spill_files: Vec<SpillFile>,
 }
 
 impl ChunkPoolCache {
     pub fn new(config: Config) -> Self {
+        let spill_store = config.spill_dir.clone().map(SpillStore::new);
+
         ChunkPoolCache {
             config,
             files: LinkedHashMap::default(),
             total_chunks: 0,
+            total_spilled_chunks: 0,
+            spill_store,
+            dropped_spill_files: vec![],
         }
     }
 
@@ -88,12 +209,31 @@ impl ChunkPoolCache {
         self.files.get_mut(root)
     }
 
+    /// Removes the cached file, whose spilled segments should be restored via
+    /// `MemoryCachedFile::restore` once the pool lock released.
     pub fn remove_file(&mut self, root: &DataRoot) -> Option<MemoryCachedFile> {
         let file = self.files.remove(root)?;
         self.update_total_chunks_when_remove_file(&file);
         Some(file)
     }
 
+    /// Removes the cached file without restoring spilled segments, and returns whether the
+    /// file was cached.
+    pub fn drop_file(&mut self, root: &DataRoot) -> bool {
+        match self.files.remove(root) {
+            Some(file) => {
+                self.on_file_dropped(&file);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the status of all cached files from the oldest one.
+    pub fn status(&self, now: Instant) -> Vec<PoolFileStatus> {
+        self.files.values().map(|file| file.status(now)).collect()
+    }
+
     /// Remove files that no new segment uploaded for a long time.
     ///
     /// Note, when log sync delayed, files may be also garbage collected if the
@@ -111,21 +251,59 @@ impl ChunkPoolCache {
             }
 
             if let Some((r, f)) = self.files.pop_front() {
-                self.update_total_chunks_when_remove_file(&f);
+                self.on_file_dropped(&f);
+                metrics::EXPIRED_FILES.inc(1);
                 debug!("Garbage collected for file {}", r);
             }
         }
     }
 
+    fn on_file_dropped(&mut self, file: &MemoryCachedFile) {
+        self.update_total_chunks_when_remove_file(file);
+
+        if let Some(spill_file) = &file.spill_file {
+            self.dropped_spill_files.push(spill_file.clone());
+        }
+    }
+
+    /// Takes the spilled files of dropped files, which should be removed once the pool lock
+    /// released.
+    pub fn take_dropped_spill_files(&mut self) -> Vec<SpillFile> {
+        std::mem::take(&mut self.dropped_spill_files)
+    }
+
+    /// Drops the file if failed to write its spilled segments, which should be uploaded again.
+    pub fn on_spill_failed(&mut self, root: &DataRoot, spill_file: &SpillFile) {
+        let spilled_to = self
+            .files
+            .get(root)
+            .and_then(|file| file.spill_file.as_ref());
+        if spilled_to.map_or(false, |f| f.same(spill_file)) {
+            self.drop_file(root);
+        }
+    }
+
     fn update_total_chunks_when_remove_file(&mut self, file: &MemoryCachedFile) {
-        assert!(self.total_chunks >= file.cached_chunk_num);
-        self.total_chunks -= file.cached_chunk_num;
+        assert!(self.total_chunks >= file.memory_chunk_num());
+        assert!(self.total_spilled_chunks >= file.spilled_chunk_num);
+        self.total_chunks -= file.memory_chunk_num();
+        self.total_spilled_chunks -= file.spilled_chunk_num;
+        self.update_metrics();
+    }
+
+    fn update_metrics(&self) {
+        let spilled_files = self.files.values().filter(|f| f.is_spilled()).count();
+        metrics::CACHED_FILES.update(self.files.len());
+        metrics::CACHED_CHUNKS.update(self.total_chunks);
+        metrics::SPILLED_FILES.update(spilled_files);
+        metrics::SPILLED_CHUNKS.update(self.total_spilled_chunks);
     }
 
-    /// Caches the specified segment in memory.
+    /// Caches the specified segment in memory, or spills it to disk when memory caps reached.
     ///
-    /// Returns if there are cached segments and log entry also retrieved.
-    pub fn cache_segment(&mut self, seg_info: SegmentInfo) -> Result<bool> {
+    /// Returns if there are cached segments and log entry also retrieved, and the segments to
+    /// spill, which should be written once the pool lock released.
+    pub fn cache_segment(&mut self, seg_info: SegmentInfo) -> Result<(bool, Option<PendingSpill>)> {
         // always GC at first
         self.garbage_collect();
 
@@ -134,31 +312,501 @@ impl ChunkPoolCache {
                 seg_info.root,
                 self.config.expiration_time(),
                 seg_info.chunks_per_segment,
+                seg_info.file_size,
             )
         });
 
-        // Segment already cached in memory. Directly return OK
-        if file.segments.contains_key(&seg_info.seg_index) {
-            return Ok(file.should_flush());
+        if file.file_size != seg_info.file_size {
+            bail!(Error::FileSizeMismatch {
+                expected: file.file_size,
+                actual: seg_info.file_size,
+            });
+        }
+
+        // Segment already cached. Directly return OK
+        let seg_index = seg_info.seg_index;
+        let digest = segment_digest(&seg_info.seg_data);
+        if file.check_received(seg_index, &digest)? {
+            return Ok((file.should_flush(), None));
         }
 
-        // Otherwise, just cache segment in memory
         let num_chunks = seg_info.seg_data.len() / CHUNK_SIZE;
+        let mut pending_spill = None;
 
-        // Limits the cached chunks in the memory pool.
-        if self.total_chunks + num_chunks > self.config.max_cached_chunks_all {
-            bail!(
-                "exceeds the maximum cached chunks of whole pool: {}",
-                self.config.max_cached_chunks_all
-            );
+        // Limits the cached chunks in memory for both file and the whole pool.
+        let file_full =
+            file.memory_chunk_num() + num_chunks > self.config.max_cached_chunks_per_file;
+        let pool_full = self.total_chunks + num_chunks > self.config.max_cached_chunks_all;
+
+        if file.is_spilled() || file_full || pool_full {
+            let store = match &self.spill_store {
+                Some(store) => store,
+                None if file_full => bail!(Error::FileCacheFull {
+                    limit: self.config.max_cached_chunks_per_file
+                }),
+                None => bail!(Error::PoolFull {
+                    limit: self.config.max_cached_chunks_all
+                }),
+            };
+
+            // Limits the spilled chunks on disk for the whole pool.
+            let memory_chunks = file.memory_chunk_num();
+            if self.total_spilled_chunks + memory_chunks + num_chunks
+                > self.config.max_spilled_chunks_all
+            {
+                bail!(Error::SpillFull {
+                    limit: self.config.max_spilled_chunks_all
+                });
+            }
+
+            // Spill the whole file to disk, so that it could be restored in sequence later.
+            pending_spill = Some(file.spill(store, seg_index, seg_info.into())?);
+            self.total_chunks -= memory_chunks;
+            self.total_spilled_chunks += memory_chunks + num_chunks;
+        } else {
+            // Otherwise, just cache segment in memory
+            self.total_chunks += num_chunks;
+            file.segments.insert(seg_index, seg_info.into());
         }
 
-        // Cache segment and update the counter for cached chunks.
-        self.total_chunks += num_chunks;
+        // Update the counter for cached chunks.
+        file.digests.insert(seg_index, digest);
         file.cached_chunk_num += num_chunks;
         file.update_expiration_time(self.config.expiration_time());
-        file.segments.insert(seg_info.seg_index, seg_info.into());
+        let should_flush = file.should_flush();
+        self.update_metrics();
+
+        Ok((should_flush, pending_spill))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_types::TransactionBuilder;
+    use std::path::Path;
+    use storage_async::ShardConfig;
+    use tempfile::TempDir;
+
+    fn new_config(expiration_time_secs: u64) -> Config {
+        Config {
+            write_window_size: 4,
+            max_cached_chunks_all: 1024,
+            max_cached_chunks_per_file: 1024,
+            max_spilled_chunks_all: 1024,
+            max_writings: 4,
+            expiration_time_secs,
+            shard_config: ShardConfig::default(),
+            spill_dir: None,
+            read_only: false,
+        }
+    }
+
+    /// Caches the segment, and writes the segments to spill like the pool does.
+    async fn cache_segment(cache: &mut ChunkPoolCache, seg_info: SegmentInfo) -> Result<bool> {
+        let result = cache.cache_segment(seg_info);
+        for spill_file in cache.take_dropped_spill_files() {
+            spill_file.remove().await;
+        }
+
+        let (should_flush, pending_spill) = result?;
+        if let Some(pending) = pending_spill {
+            pending.write().await?;
+        }
+
+        Ok(should_flush)
+    }
+
+    fn new_cache(expiration_time_secs: u64) -> ChunkPoolCache {
+        ChunkPoolCache::new(new_config(expiration_time_secs))
+    }
+
+    fn new_spill_cache(expiration_time_secs: u64, dir: &Path) -> ChunkPoolCache {
+        ChunkPoolCache::new(Config {
+            max_cached_chunks_all: 16,
+            max_cached_chunks_per_file: 8,
+            spill_dir: Some(dir.to_path_buf()),
+            ..new_config(expiration_time_secs)
+        })
+    }
+
+    /// Returns whether any segment of the file spilled on disk.
+    fn is_spilled_on_disk(dir: &Path, root: &DataRoot) -> bool {
+        let prefix = format!("{:?}-", root);
+        std::fs::read_dir(dir.join("zgs_chunk_pool_spill"))
+            .unwrap()
+            .any(|entry| {
+                let entry = entry.unwrap();
+                entry.file_name().to_string_lossy().starts_with(&prefix)
+            })
+    }
+
+    fn new_segment(root: DataRoot, index: usize) -> SegmentInfo {
+        SegmentInfo {
+            root,
+            seg_data: vec![0u8; CHUNK_SIZE * 4],
+            seg_proof: FileProof::new(vec![root], vec![]),
+            seg_index: index,
+            chunks_per_segment: 4,
+            file_size: CHUNK_SIZE * 4096,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pending_file_expiry() {
+        let root = DataRoot::from_low_u64_be(1);
+        let mut cache = new_cache(0);
+        assert!(!cache_segment(&mut cache, new_segment(root, 0))
+            .await
+            .unwrap());
+        assert_eq!(cache.total_chunks, 4);
+
+        cache.garbage_collect();
+        assert!(cache.get_file(&root).is_none());
+        assert_eq!(cache.total_chunks, 0);
+    }
+
+    #[tokio::test]
+    async fn test_pending_file_not_expired() {
+        let root = DataRoot::from_low_u64_be(1);
+        let mut cache = new_cache(300);
+        cache_segment(&mut cache, new_segment(root, 0))
+            .await
+            .unwrap();
+        // duplicated segment is not cached twice
+        cache_segment(&mut cache, new_segment(root, 0))
+            .await
+            .unwrap();
+
+        cache.garbage_collect();
+        assert_eq!(cache.get_file(&root).unwrap().segments.len(), 1);
+        assert_eq!(cache.total_chunks, 4);
+    }
+
+    #[tokio::test]
+    async fn test_pool_full() {
+        let root = DataRoot::from_low_u64_be(1);
+        let mut cache = new_cache(300);
+        for index in 0..256 {
+            cache_segment(&mut cache, new_segment(root, index))
+                .await
+                .unwrap();
+        }
+
+        let err = cache_segment(&mut cache, new_segment(root, 256))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::PoolFull { limit: 1024 })
+        );
+        assert_eq!(cache.total_chunks, 1024);
+    }
+
+    #[tokio::test]
+    async fn test_duplicated_segments() {
+        let root = DataRoot::from_low_u64_be(1);
+        let mut cache = new_cache(300);
+        for _ in 0..100 {
+            for index in [2, 0, 1] {
+                cache_segment(&mut cache, new_segment(root, index))
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let file = cache.get_file(&root).unwrap();
+        assert_eq!(file.num_segments(), 3);
+        assert_eq!(file.cached_chunk_num, 12);
+        assert_eq!(cache.total_chunks, 12);
+
+        let digest = segment_digest(&new_segment(root, 1).seg_data);
+        assert!(file.check_received(1, &digest).unwrap());
+        assert!(!file.check_received(3, &digest).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_conflicted_segment() {
+        let root = DataRoot::from_low_u64_be(1);
+        let mut cache = new_cache(300);
+        for index in 0..3 {
+            cache_segment(&mut cache, new_segment(root, index))
+                .await
+                .unwrap();
+        }
+
+        // same index, but different data
+        for index in 0..3 {
+            let mut segment = new_segment(root, index);
+            segment.seg_data[0] = 1;
+            let err = cache_segment(&mut cache, segment).await.unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<Error>(),
+                Some(&Error::SegmentConflicted(index))
+            );
+        }
+
+        assert_eq!(cache.get_file(&root).unwrap().num_segments(), 3);
+        assert_eq!(cache.total_chunks, 12);
+    }
+
+    #[tokio::test]
+    async fn test_file_size_mismatch() {
+        let root = DataRoot::from_low_u64_be(1);
+        let mut cache = new_cache(300);
+        // segments could be uploaded out of order
+        cache_segment(&mut cache, new_segment(root, 3))
+            .await
+            .unwrap();
+        cache_segment(&mut cache, new_segment(root, 1))
+            .await
+            .unwrap();
+
+        let mut segment = new_segment(root, 0);
+        segment.file_size = CHUNK_SIZE * 1024;
+        let err = cache_segment(&mut cache, segment).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::FileSizeMismatch {
+                expected: CHUNK_SIZE * 4096,
+                actual: CHUNK_SIZE * 1024,
+            })
+        );
+        assert_eq!(cache.get_file(&root).unwrap().num_segments(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_file_cache_full() {
+        let root = DataRoot::from_low_u64_be(1);
+        let mut cache = ChunkPoolCache::new(Config {
+            max_cached_chunks_per_file: 8,
+            ..new_config(300)
+        });
+        cache_segment(&mut cache, new_segment(root, 0))
+            .await
+            .unwrap();
+        cache_segment(&mut cache, new_segment(root, 1))
+            .await
+            .unwrap();
+
+        let err = cache_segment(&mut cache, new_segment(root, 2))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<Error>().unwrap();
+        assert_eq!(err, &Error::FileCacheFull { limit: 8 });
+        assert!(err.is_retryable());
+        assert_eq!(cache.total_chunks, 8);
+
+        // other files are not affected
+        cache_segment(&mut cache, new_segment(DataRoot::from_low_u64_be(2), 0))
+            .await
+            .unwrap();
+        assert_eq!(cache.total_chunks, 12);
+    }
+
+    #[tokio::test]
+    async fn test_spill_and_restore() {
+        let dir = TempDir::new().unwrap();
+        let root = DataRoot::from_low_u64_be(1);
+        let mut cache = new_spill_cache(300, dir.path());
+        cache_segment(&mut cache, new_segment(root, 0))
+            .await
+            .unwrap();
+        cache_segment(&mut cache, new_segment(root, 1))
+            .await
+            .unwrap();
+        assert_eq!((cache.total_chunks, cache.total_spilled_chunks), (8, 0));
+
+        // exceeds the file cap, and spills the whole file
+        cache_segment(&mut cache, new_segment(root, 2))
+            .await
+            .unwrap();
+        assert_eq!((cache.total_chunks, cache.total_spilled_chunks), (0, 12));
+
+        // new segments of spilled file are written to disk directly
+        cache_segment(&mut cache, new_segment(root, 3))
+            .await
+            .unwrap();
+        cache_segment(&mut cache, new_segment(root, 3))
+            .await
+            .unwrap();
+        assert_eq!((cache.total_chunks, cache.total_spilled_chunks), (0, 16));
+        assert_eq!(cache.get_file(&root).unwrap().num_segments(), 4);
+
+        let mut file = cache.remove_file(&root).unwrap();
+        assert_eq!((cache.total_chunks, cache.total_spilled_chunks), (0, 0));
+        file.restore().await.unwrap();
+        assert_eq!(file.segments.len(), 4);
+        for index in 0..4 {
+            let expected: (ChunkArray, FileProof) = new_segment(root, index).into();
+            assert_eq!(file.segments[&index], expected);
+        }
+        assert!(!is_spilled_on_disk(dir.path(), &root));
+    }
+
+    #[tokio::test]
+    async fn test_spill_when_pool_full() {
+        let dir = TempDir::new().unwrap();
+        let mut cache = new_spill_cache(300, dir.path());
+        for i in 0..4 {
+            let root = DataRoot::from_low_u64_be(i);
+            cache_segment(&mut cache, new_segment(root, 0))
+                .await
+                .unwrap();
+        }
+        assert_eq!((cache.total_chunks, cache.total_spilled_chunks), (16, 0));
+
+        // exceeds the pool cap
+        let root = DataRoot::from_low_u64_be(4);
+        cache_segment(&mut cache, new_segment(root, 0))
+            .await
+            .unwrap();
+        assert_eq!((cache.total_chunks, cache.total_spilled_chunks), (16, 4));
+
+        assert!(cache.drop_file(&root));
+        assert_eq!((cache.total_chunks, cache.total_spilled_chunks), (16, 0));
+        assert!(is_spilled_on_disk(dir.path(), &root));
+        for spill_file in cache.take_dropped_spill_files() {
+            spill_file.remove().await;
+        }
+        assert!(!is_spilled_on_disk(dir.path(), &root));
+    }
+
+    #[tokio::test]
+    async fn test_spilled_file_expiry() {
+        let dir = TempDir::new().unwrap();
+        let root = DataRoot::from_low_u64_be(1);
+        let mut cache = ChunkPoolCache::new(Config {
+            max_cached_chunks_per_file: 2,
+            spill_dir: Some(dir.path().to_path_buf()),
+            ..new_config(0)
+        });
+        cache_segment(&mut cache, new_segment(root, 0))
+            .await
+            .unwrap();
+        assert_eq!((cache.total_chunks, cache.total_spilled_chunks), (0, 4));
+        assert!(is_spilled_on_disk(dir.path(), &root));
+
+        cache.garbage_collect();
+        assert!(cache.get_file(&root).is_none());
+        assert_eq!((cache.total_chunks, cache.total_spilled_chunks), (0, 0));
+        for spill_file in cache.take_dropped_spill_files() {
+            spill_file.remove().await;
+        }
+        assert!(!is_spilled_on_disk(dir.path(), &root));
+    }
+
+    #[tokio::test]
+    async fn test_spill_full() {
+        let dir = TempDir::new().unwrap();
+        let mut cache = ChunkPoolCache::new(Config {
+            max_cached_chunks_per_file: 4,
+            max_spilled_chunks_all: 8,
+            spill_dir: Some(dir.path().to_path_buf()),
+            ..new_config(300)
+        });
+        let root = DataRoot::from_low_u64_be(1);
+        cache_segment(&mut cache, new_segment(root, 0))
+            .await
+            .unwrap();
+        cache_segment(&mut cache, new_segment(root, 1))
+            .await
+            .unwrap();
+        assert_eq!((cache.total_chunks, cache.total_spilled_chunks), (0, 8));
+
+        // exceeds the spill cap of whole pool
+        let err = cache_segment(&mut cache, new_segment(root, 2))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<Error>().unwrap();
+        assert_eq!(err, &Error::SpillFull { limit: 8 });
+        assert!(err.is_retryable());
+        assert_eq!((cache.total_chunks, cache.total_spilled_chunks), (0, 8));
+        assert_eq!(cache.get_file(&root).unwrap().num_segments(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_spill_failed() {
+        let dir = TempDir::new().unwrap();
+        let root = DataRoot::from_low_u64_be(1);
+        let mut cache = new_spill_cache(300, dir.path());
+        cache_segment(&mut cache, new_segment(root, 0))
+            .await
+            .unwrap();
+        cache_segment(&mut cache, new_segment(root, 1))
+            .await
+            .unwrap();
+        let (_, pending) = cache.cache_segment(new_segment(root, 2)).unwrap();
+        let spill_file = pending.unwrap().file().clone();
+
+        // the spilled segments are lost, and the file should be uploaded again
+        cache.on_spill_failed(&root, &spill_file);
+        assert!(cache.get_file(&root).is_none());
+        assert_eq!((cache.total_chunks, cache.total_spilled_chunks), (0, 0));
+        assert_eq!(cache.take_dropped_spill_files().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_status() {
+        let dir = TempDir::new().unwrap();
+        let mut cache = new_spill_cache(300, dir.path());
+        let (pending, spilled, flushing) = (
+            DataRoot::from_low_u64_be(1),
+            DataRoot::from_low_u64_be(2),
+            DataRoot::from_low_u64_be(3),
+        );
+        cache_segment(&mut cache, new_segment(pending, 0))
+            .await
+            .unwrap();
+        for index in 0..3 {
+            cache_segment(&mut cache, new_segment(spilled, index))
+                .await
+                .unwrap();
+        }
+        cache_segment(&mut cache, new_segment(flushing, 1))
+            .await
+            .unwrap();
+        let tx = TransactionBuilder::new(7)
+            .data_merkle_root(flushing)
+            .size((CHUNK_SIZE * 4096) as u64)
+            .build_unchecked();
+        cache.get_file_mut(&flushing).unwrap().update_with_tx(&tx);
+
+        let now = Instant::now() + Duration::from_secs(10);
+        let status = cache.status(now);
+        let summary: Vec<_> = status
+            .iter()
+            .map(|f| {
+                (
+                    f.root,
+                    f.tx_seq,
+                    f.state,
+                    f.buffered_segments,
+                    f.bytes,
+                    f.spilled_bytes,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (pending, None, PoolFileState::Pending, 1, 4 * CHUNK_SIZE, 0),
+                (spilled, None, PoolFileState::Spilled, 3, 0, 12 * CHUNK_SIZE),
+                (
+                    flushing,
+                    Some(7),
+                    PoolFileState::Flushing,
+                    1,
+                    4 * CHUNK_SIZE,
+                    0
+                ),
+            ]
+        );
+        assert!(status.iter().all(|f| f.age >= Duration::from_secs(10)));
 
-        Ok(file.should_flush())
+        // dropped files are not reported
+        assert!(cache.drop_file(&spilled));
+        assert_eq!(cache.status(now).len(), 2);
+        assert_eq!(cache.total_spilled_chunks, 0);
     }
 }
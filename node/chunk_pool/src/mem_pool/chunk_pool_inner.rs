@@ -1,19 +1,32 @@
 use super::chunk_cache::{ChunkPoolCache, MemoryCachedFile};
 use super::chunk_write_control::ChunkPoolWriteCtrl;
-use super::FileID;
+use super::metrics;
+use super::spill::SpillFile;
+use super::{segment_digest, ChunkPoolStatus, FileID, SegmentCheck};
+use crate::error::Error;
 use crate::handler::ChunkPoolMessage;
 use crate::Config;
 use anyhow::{anyhow, bail, Result};
 use async_lock::Mutex;
 use log_entry_sync::LogSyncEvent;
 use shared_types::{
-    bytes_to_chunks, compute_segment_size, ChunkArray, DataRoot, FileProof, Transaction, CHUNK_SIZE,
+    bytes_to_chunks, compute_padded_chunk_size, compute_segment_size, ChunkArray, DataRoot,
+    FileProof, Transaction, CHUNK_SIZE,
 };
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
-use storage_async::{ShardConfig, Store};
+use std::time::{Duration, Instant};
+use storage_async::{ChunkRange, FlushJournal, ShardConfig, Store};
 use tokio::sync::broadcast::{error::RecvError, Receiver};
 use tokio::sync::mpsc::UnboundedSender;
 
+/// Number of retries to write a cached segment, after which a warning is logged.
+const CACHED_SEGMENT_WRITE_RETRIES_TO_WARN: usize = 30;
+/// Interval to retry writing a cached segment.
+const CACHED_SEGMENT_WRITE_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+/// Interval to check the in-flight writes when closing the pool.
+const CLOSE_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
 struct Inner {
     config: Config,
     segment_cache: ChunkPoolCache,
@@ -23,9 +36,9 @@ struct Inner {
 impl Inner {
     fn new(config: Config) -> Self {
         Inner {
+            segment_cache: ChunkPoolCache::new(config.clone()),
+            write_control: ChunkPoolWriteCtrl::new(config.clone()),
             config,
-            segment_cache: ChunkPoolCache::new(config),
-            write_control: ChunkPoolWriteCtrl::new(config),
         }
     }
 
@@ -34,35 +47,74 @@ impl Inner {
         self.write_control.total_writings -= 1;
     }
 
-    /// Return the tx seq and all segments that belong to the root.
-    fn get_all_cached_segments_to_write(
-        &mut self,
-        root: &DataRoot,
-    ) -> Result<(FileID, Vec<(ChunkArray, FileProof)>)> {
+    /// Return the cached file to write, whose spilled segments are not restored yet.
+    fn get_all_cached_segments_to_write(&mut self, root: &DataRoot) -> Result<MemoryCachedFile> {
         // Limits the number of writing threads.
         if self.write_control.total_writings >= self.config.max_writings {
-            bail!("too many data writing: {}", self.config.max_writings);
+            bail!(Error::TooManyWritings {
+                limit: self.config.max_writings
+            });
         }
 
         let file = match self.segment_cache.remove_file(root) {
             Some(f) => f,
-            None => bail!("file not found to write into store {:?}", root),
+            None => bail!(Error::FileNotFound(*root)),
         };
-        let id = file.id;
-        let segs = file.segments.into_values().collect();
 
         self.write_control.total_writings += 1;
 
-        Ok((id, segs))
+        Ok(file)
     }
 }
 
+#[derive(Clone)]
 pub struct SegmentInfo {
     pub root: DataRoot,
     pub seg_data: Vec<u8>,
     pub seg_proof: FileProof,
     pub seg_index: usize,
     pub chunks_per_segment: usize,
+    /// File size declared by the uploader, against which the segment proof is validated.
+    pub file_size: usize,
+}
+
+impl SegmentInfo {
+    /// Validates that the segment index, number of chunks and proof depth are consistent with
+    /// the merkle tree of `file_size`, so that a segment proven against a bigger tree is
+    /// rejected before cached or written.
+    pub fn validate_range(&self, file_size: usize) -> Result<()> {
+        let total_chunks = bytes_to_chunks(file_size);
+        let (num_segments, last_segment_chunks) =
+            compute_segment_size(total_chunks, self.chunks_per_segment);
+        if self.seg_index >= num_segments {
+            bail!(Error::SegmentOutOfRange {
+                index: self.seg_index,
+                num_segments,
+            });
+        }
+
+        let expected = if self.seg_index == num_segments - 1 {
+            last_segment_chunks
+        } else {
+            self.chunks_per_segment
+        };
+        let actual = self.seg_data.len() / CHUNK_SIZE;
+        if actual != expected {
+            bail!(Error::InvalidSegmentLength {
+                index: self.seg_index,
+                expected,
+                actual,
+            });
+        }
+
+        // The proof is of the tree of padded chunks, see `SegmentWithProof::validate`.
+        let (padded_chunks, _) = compute_padded_chunk_size(file_size);
+        let (segments_for_proof, _) = compute_segment_size(padded_chunks, self.chunks_per_segment);
+        match self.seg_proof.position(segments_for_proof) {
+            Ok(position) if position == self.seg_index => Ok(()),
+            _ => bail!(Error::InvalidProofDepth(self.seg_index)),
+        }
+    }
 }
 
 impl From<SegmentInfo> for (ChunkArray, FileProof) {
@@ -84,6 +136,21 @@ pub struct MemoryChunkPool {
     inner: Mutex<Inner>,
     log_store: Arc<Store>,
     sender: UnboundedSender<ChunkPoolMessage>,
+    /// Number of the in-flight writes into store, including the flushes of cached segments.
+    writes: AtomicUsize,
+    /// Whether the pool is closed to write, e.g. node is shutting down.
+    closed: AtomicBool,
+    /// Whether the pool never accepts writes, e.g. node is read-only.
+    read_only: bool,
+}
+
+/// Tracks an in-flight write of chunk pool until dropped.
+struct WriteGuard<'a>(&'a AtomicUsize);
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl MemoryChunkPool {
@@ -93,33 +160,79 @@ impl MemoryChunkPool {
         sender: UnboundedSender<ChunkPoolMessage>,
     ) -> Self {
         MemoryChunkPool {
+            read_only: config.read_only,
             inner: Mutex::new(Inner::new(config)),
             log_store,
             sender,
+            writes: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    fn start_write(&self) -> Result<WriteGuard<'_>> {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        let guard = WriteGuard(&self.writes);
+
+        if self.read_only {
+            bail!(Error::ReadOnly);
+        }
+
+        if self.closed.load(Ordering::SeqCst) {
+            bail!(Error::ShuttingDown);
+        }
+
+        Ok(guard)
+    }
+
+    /// Rejects new writes, and waits until all in-flight writes completed, so that no flush
+    /// of cached segments is interrupted by shutdown.
+    pub async fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+
+        while self.writes.load(Ordering::SeqCst) > 0 {
+            tokio::time::sleep(CLOSE_CHECK_INTERVAL).await;
         }
     }
 
     pub fn validate_segment_size(&self, segment: &[u8]) -> Result<()> {
         if segment.is_empty() {
-            bail!("data is empty");
+            bail!(Error::EmptySegment);
         }
 
         if segment.len() % CHUNK_SIZE != 0 {
-            bail!("invalid data length");
+            bail!(Error::InvalidSegmentSize(segment.len()));
         }
 
         Ok(())
     }
 
     pub async fn cache_chunks(&self, seg_info: SegmentInfo) -> Result<()> {
+        let _guard = self.start_write()?;
+        seg_info.validate_range(seg_info.file_size)?;
         let root = seg_info.root;
         debug!("cache_chunks, root={:?} index={}", root, seg_info.seg_index);
-        let should_flush = self
-            .inner
-            .lock()
-            .await
-            .segment_cache
-            .cache_segment(seg_info)?;
+        let (result, dropped) = {
+            let mut inner = self.inner.lock().await;
+            let result = inner.segment_cache.cache_segment(seg_info);
+            (result, inner.segment_cache.take_dropped_spill_files())
+        };
+        remove_spill_files(dropped).await;
+        let (should_flush, pending_spill) = result?;
+
+        // Spilled segments are written out of the pool lock.
+        if let Some(pending) = pending_spill {
+            let spill_file = pending.file().clone();
+            if let Err(e) = pending.write().await {
+                warn!(%root, "Failed to spill segments to disk: {:?}", e);
+                let dropped = {
+                    let mut inner = self.inner.lock().await;
+                    inner.segment_cache.on_spill_failed(&root, &spill_file);
+                    inner.segment_cache.take_dropped_spill_files()
+                };
+                remove_spill_files(dropped).await;
+                return Err(e);
+            }
+        }
 
         // store and finalize the cached file if completed
         if should_flush {
@@ -136,6 +249,23 @@ impl MemoryChunkPool {
         file_id: FileID,
         file_size: usize,
     ) -> Result<()> {
+        let _guard = self.start_write()?;
+        self.write_chunks_inner(seg_info, file_id, file_size).await
+    }
+
+    async fn write_chunks_inner(
+        &self,
+        seg_info: SegmentInfo,
+        file_id: FileID,
+        file_size: usize,
+    ) -> Result<()> {
+        let tx = self
+            .log_store
+            .get_tx_by_seq_number(file_id.tx_id.seq)
+            .await?
+            .ok_or(anyhow!("unexpected tx missing"))?;
+        // Validated against the submitted file size rather than the declared one.
+        seg_info.validate_range(tx.size as usize)?;
         let total_chunks = bytes_to_chunks(file_size);
 
         debug!(
@@ -147,13 +277,7 @@ impl MemoryChunkPool {
 
         //Write the segment in window
         let (total_segments, _) = compute_segment_size(total_chunks, seg_info.chunks_per_segment);
-        let tx_start_index = self
-            .log_store
-            .get_tx_by_seq_number(file_id.tx_id.seq)
-            .await?
-            .ok_or(anyhow!("unexpected tx missing"))?
-            .start_entry_index()
-            / seg_info.chunks_per_segment as u64;
+        let tx_start_index = tx.start_entry_index() / seg_info.chunks_per_segment as u64;
         self.inner.lock().await.write_control.write_segment(
             file_id,
             seg_info.seg_index,
@@ -161,46 +285,39 @@ impl MemoryChunkPool {
             tx_start_index as usize,
         )?;
 
-        // Write memory cached segments into store.
-        // TODO(qhz): error handling
-        // 1. Push the failed segment back to front. (enhance store to return Err(ChunkArray))
-        // 2. Put the incompleted segments back to memory pool.
-        let seg = ChunkArray {
-            data: seg_info.seg_data,
-            start_index: (seg_info.seg_index * seg_info.chunks_per_segment) as u64,
+        let root = seg_info.root;
+        let seg_index = seg_info.seg_index;
+        let digest = segment_digest(&seg_info.seg_data);
+        let result = match self.get_stored_segment(file_id.tx_id.seq, &seg_info).await {
+            // Segments already in store, e.g. uploaded before restart, are not written again,
+            // but still counted so that the file could be finalized once all segments uploaded.
+            Ok(Some(data)) if data == seg_info.seg_data => {
+                metrics::DUPLICATED_SEGMENTS.inc(1);
+                Ok(true)
+            }
+            Ok(Some(_)) => Err(Self::conflicted_segment(&root, seg_index)),
+            Ok(None) => self.put_segment(file_id, seg_info).await,
+            Err(e) => Err(e),
         };
 
-        match self
-            .log_store
-            .put_chunks_with_tx_hash(
-                file_id.tx_id.seq,
-                file_id.tx_id.hash,
-                seg,
-                Some(seg_info.seg_proof.try_into()?),
-            )
-            .await
-        {
+        match result {
             Ok(true) => {}
             Ok(false) => {
                 self.inner
                     .lock()
                     .await
                     .write_control
-                    .on_write_failed(&seg_info.root, seg_info.seg_index);
+                    .on_write_failed(&root, seg_index);
                 // remove the file if transaction reverted
-                self.inner
-                    .lock()
-                    .await
-                    .write_control
-                    .remove_file(&seg_info.root);
-                bail!("Transaction reverted, please upload again");
+                self.inner.lock().await.write_control.remove_file(&root);
+                bail!(Error::TxReverted);
             }
             Err(e) => {
                 self.inner
                     .lock()
                     .await
                     .write_control
-                    .on_write_failed(&seg_info.root, seg_info.seg_index);
+                    .on_write_failed(&root, seg_index);
                 return Err(e);
             }
         }
@@ -210,42 +327,88 @@ impl MemoryChunkPool {
             .lock()
             .await
             .write_control
-            .on_write_succeeded(&seg_info.root, seg_info.seg_index);
+            .on_write_succeeded(&root, seg_index, digest);
 
         // Notify to finalize transaction asynchronously.
         if all_uploaded {
             self.send_finalize_file(file_id).await?;
-            debug!("Queue to finalize transaction for file {}", seg_info.root);
+            debug!("Queue to finalize transaction for file {}", root);
         }
 
         Ok(())
     }
 
-    /// Updates the cached file info when log entry retrieved from blockchain.
+    /// Returns the data of segment if already written into store.
+    async fn get_stored_segment(
+        &self,
+        tx_seq: u64,
+        seg_info: &SegmentInfo,
+    ) -> Result<Option<Vec<u8>>> {
+        let start_index = seg_info.seg_index * seg_info.chunks_per_segment;
+        let end_index = start_index + seg_info.seg_data.len() / CHUNK_SIZE;
+        Ok(self
+            .log_store
+            .get_chunks_by_tx_and_index_range(tx_seq, start_index, end_index)
+            .await?
+            .map(|chunks| chunks.data))
+    }
+
+    async fn put_segment(&self, file_id: FileID, seg_info: SegmentInfo) -> Result<bool> {
+        // TODO(qhz): error handling
+        // 1. Push the failed segment back to front. (enhance store to return Err(ChunkArray))
+        // 2. Put the incompleted segments back to memory pool.
+        let seg = ChunkArray {
+            data: seg_info.seg_data,
+            start_index: (seg_info.seg_index * seg_info.chunks_per_segment) as u64,
+        };
+
+        self.log_store
+            .put_chunks_with_tx_hash(
+                file_id.tx_id.seq,
+                file_id.tx_id.hash,
+                seg,
+                Some(seg_info.seg_proof.try_into()?),
+            )
+            .await
+    }
+
+    /// Updates the cached file info when log entry retrieved from blockchain, and writes all
+    /// the cached segments into store.
     pub async fn update_file_info(&self, tx: &Transaction) -> Result<bool> {
+        let _guard = self.start_write()?;
         info!(
             "start to flush cached segments to log store. data root: {}, tx_seq:{}",
             tx.data_merkle_root, tx.seq
         );
-        let maybe_file = self
-            .inner
-            .lock()
-            .await
-            .segment_cache
-            .remove_file(&tx.data_merkle_root);
+        let maybe_file = self.remove_cached_file(&tx.data_merkle_root).await?;
         if let Some(mut file) = maybe_file {
+            // Segment proofs are validated against the declared file size when cached.
+            if file.file_size != tx.size as usize {
+                metrics::REJECTED_FILES.inc(1);
+                bail!(Error::FileSizeMismatch {
+                    expected: tx.size as usize,
+                    actual: file.file_size,
+                });
+            }
+
             file.update_with_tx(tx);
-            for (seg_index, (seg, proof)) in file.segments.into_iter() {
-                self.write_chunks(
+
+            // Segments may be uploaded out of order, so write them in sequence.
+            let mut segments: Vec<_> = file.segments.into_iter().collect();
+            segments.sort_by_key(|(seg_index, _)| *seg_index);
+            self.put_flush_journal(file.id, segments.iter().map(|(_, (seg, _))| seg))
+                .await?;
+            for (seg_index, (seg, proof)) in segments {
+                self.write_cached_segment(
                     SegmentInfo {
                         root: tx.data_merkle_root,
                         seg_data: seg.data,
                         seg_proof: proof,
                         seg_index,
                         chunks_per_segment: file.chunks_per_segment,
+                        file_size: file.file_size,
                     },
                     file.id,
-                    file.total_chunks * CHUNK_SIZE,
                 )
                 .await?
             }
@@ -257,6 +420,46 @@ impl MemoryChunkPool {
         Ok(true)
     }
 
+    /// Writes a segment that cached before log entry retrieved, and retries as long as too many
+    /// segments are writing concurrently, since the uploader never uploads it again.
+    ///
+    /// Retries are stopped only if the pool closed, and then the segments not written yet are
+    /// left in the flush journal, which are synced from peers after restart.
+    async fn write_cached_segment(&self, seg_info: SegmentInfo, file_id: FileID) -> Result<()> {
+        let mut retries = 0;
+
+        loop {
+            let err = match self
+                .write_chunks_inner(seg_info.clone(), file_id, seg_info.file_size)
+                .await
+            {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+
+            match err.downcast_ref::<Error>() {
+                // Uploaded again by user after log entry retrieved.
+                Some(Error::SegmentAlreadyUploaded(_)) => return Ok(()),
+                Some(e) if e.is_retryable() => {
+                    if self.closed.load(Ordering::SeqCst) {
+                        bail!(Error::ShuttingDown);
+                    }
+
+                    retries += 1;
+                    if retries == CACHED_SEGMENT_WRITE_RETRIES_TO_WARN {
+                        warn!(
+                            root = %seg_info.root,
+                            index = %seg_info.seg_index,
+                            "Cached segment is waiting to write: {}", e
+                        );
+                    }
+                    tokio::time::sleep(CACHED_SEGMENT_WRITE_RETRY_INTERVAL).await;
+                }
+                _ => return Err(err),
+            }
+        }
+    }
+
     pub async fn monitor_log_entry(chunk_pool: Arc<Self>, mut receiver: Receiver<LogSyncEvent>) {
         info!("Start to monitor log entry");
 
@@ -264,6 +467,8 @@ impl MemoryChunkPool {
             match receiver.recv().await {
                 Ok(LogSyncEvent::ReorgDetected { .. }) => {}
                 Ok(LogSyncEvent::Reverted { .. }) => {}
+                Ok(LogSyncEvent::ContractEventSynced { .. }) => {}
+                Ok(LogSyncEvent::BlocksReverted { .. }) => {}
                 Ok(LogSyncEvent::TxSynced { tx }) => {
                     // This may take a while, so execute it asynchronously to ensure the
                     // channel will not be saturated.
@@ -289,14 +494,109 @@ impl MemoryChunkPool {
         }
     }
 
-    pub(crate) async fn remove_cached_file(&self, root: &DataRoot) -> Option<MemoryCachedFile> {
-        self.inner.lock().await.segment_cache.remove_file(root)
+    /// Removes the cached file, and restores all the spilled segments into memory.
+    pub(crate) async fn remove_cached_file(
+        &self,
+        root: &DataRoot,
+    ) -> Result<Option<MemoryCachedFile>> {
+        let maybe_file = self.inner.lock().await.segment_cache.remove_file(root);
+        match maybe_file {
+            Some(mut file) => {
+                file.restore().await?;
+                Ok(Some(file))
+            }
+            None => Ok(None),
+        }
     }
 
     pub(crate) async fn remove_file(&self, root: &DataRoot) -> bool {
-        let mut inner = self.inner.lock().await;
-        inner.segment_cache.remove_file(root).is_some()
-            || inner.write_control.remove_file(root).is_some()
+        let (removed, dropped) = {
+            let mut inner = self.inner.lock().await;
+            let removed = inner.segment_cache.drop_file(root)
+                || inner.write_control.remove_file(root).is_some();
+            (removed, inner.segment_cache.take_dropped_spill_files())
+        };
+        remove_spill_files(dropped).await;
+        removed
+    }
+
+    /// Checks whether the segment has already been uploaded with the same data, so that the
+    /// retried upload could succeed without proof verification or writes.
+    ///
+    /// If the file is not tracked in pool and already finalized, data in store is compared
+    /// when `maybe_tx` is specified. A conflicted segment is not rejected here, since it is
+    /// not proven yet, and should be rejected by [`Self::conflicted_segment`] once proven.
+    pub async fn check_duplicate_segment(
+        &self,
+        root: &DataRoot,
+        seg_index: usize,
+        seg_data: &[u8],
+        chunks_per_segment: usize,
+        maybe_tx: Option<&Transaction>,
+    ) -> Result<SegmentCheck> {
+        let digest = segment_digest(seg_data);
+
+        let received = {
+            let inner = self.inner.lock().await;
+            if let Some(file) = inner.segment_cache.get_file(root) {
+                Some(file.check_received(seg_index, &digest))
+            } else {
+                inner
+                    .write_control
+                    .get_file(root)
+                    .map(|file| file.check_received(seg_index, &digest))
+            }
+        };
+
+        let start_index = seg_index * chunks_per_segment;
+        let end_index = start_index + seg_data.len() / CHUNK_SIZE;
+        // Segments of unfinalized file are compared with store when written, so that the file
+        // could be finalized once all segments uploaded.
+        let finalized_tx = match (&received, maybe_tx) {
+            (None, Some(tx)) => self
+                .log_store
+                .check_tx_completed(tx.seq)
+                .await?
+                .then_some(tx),
+            _ => None,
+        };
+
+        let result = match (received, finalized_tx) {
+            (Some(Ok(true)), _) => SegmentCheck::Duplicated,
+            (Some(Ok(false)), _) => SegmentCheck::New,
+            (Some(Err(_)), _) => SegmentCheck::Conflicted,
+            // Otherwise, invalid segment will be rejected by proof validation later.
+            (None, Some(tx)) if end_index <= bytes_to_chunks(tx.size as usize) => {
+                match self
+                    .log_store
+                    .get_chunks_by_tx_and_index_range(tx.seq, start_index, end_index)
+                    .await?
+                {
+                    Some(chunks) if chunks.data == seg_data => SegmentCheck::Duplicated,
+                    Some(_) => SegmentCheck::Conflicted,
+                    None => SegmentCheck::New,
+                }
+            }
+            _ => SegmentCheck::New,
+        };
+
+        if result == SegmentCheck::Duplicated {
+            metrics::DUPLICATED_SEGMENTS.inc(1);
+        }
+
+        Ok(result)
+    }
+
+    /// Returns the error to reject a proven segment, which differs from the one already
+    /// uploaded at the same index.
+    pub fn conflicted_segment(root: &DataRoot, seg_index: usize) -> anyhow::Error {
+        metrics::CONFLICTED_SEGMENTS.inc(1);
+        warn!(
+            %root,
+            index = %seg_index,
+            "Conflicted segment uploaded"
+        );
+        Error::SegmentConflicted(seg_index).into()
     }
 
     pub async fn check_already_has_cache(&self, root: &DataRoot) -> bool {
@@ -309,12 +609,27 @@ impl MemoryChunkPool {
     }
 
     async fn write_all_cached_chunks_and_finalize(&self, root: DataRoot) -> Result<()> {
-        let (file, mut segments_with_proof) = self
+        let mut cached_file = self
             .inner
             .lock()
             .await
             .get_all_cached_segments_to_write(&root)?;
 
+        if let Err(e) = cached_file.restore().await {
+            self.inner.lock().await.after_flush_cache();
+            return Err(e);
+        }
+        let file = cached_file.id;
+        let mut segments_with_proof: Vec<_> = cached_file.segments.into_values().collect();
+
+        if let Err(e) = self
+            .put_flush_journal(file, segments_with_proof.iter().map(|(seg, _)| seg))
+            .await
+        {
+            self.inner.lock().await.after_flush_cache();
+            return Err(e);
+        }
+
         while let Some((seg, proof)) = segments_with_proof.pop() {
             // TODO(qhz): error handling
             // 1. Push the failed segment back to front. (enhance store to return Err(ChunkArray))
@@ -332,7 +647,7 @@ impl MemoryChunkPool {
                 Ok(true) => {}
                 Ok(false) => {
                     self.inner.lock().await.after_flush_cache();
-                    bail!("Transaction reverted, please upload again");
+                    bail!(Error::TxReverted);
                 }
                 Err(e) => {
                     self.inner.lock().await.after_flush_cache();
@@ -348,11 +663,74 @@ impl MemoryChunkPool {
         Ok(())
     }
 
+    /// Writes the flush journal of segments before flushing them into store, so that a
+    /// half-written file could be reconciled after restart.
+    pub(crate) async fn put_flush_journal<'a>(
+        &self,
+        file_id: FileID,
+        segments: impl Iterator<Item = &'a ChunkArray>,
+    ) -> Result<()> {
+        let ranges = segments
+            .map(|seg| ChunkRange {
+                start: seg.start_index,
+                end: seg.start_index + (seg.data.len() / CHUNK_SIZE) as u64,
+            })
+            .collect();
+
+        self.log_store
+            .put_flush_journal(FlushJournal {
+                tx_seq: file_id.tx_id.seq,
+                data_root: file_id.root,
+                ranges,
+            })
+            .await
+    }
+
+    /// Reconciles the flush journals left by the last run, which indicates that the node
+    /// crashed while flushing cached segments into store.
+    ///
+    /// Journals of reverted files are removed. If all journaled segments has been written,
+    /// the file is queued to finalize, of which the journal is removed once finalized or failed
+    /// to finalize. Otherwise, the journal is removed and the missing ranges of each file are
+    /// returned, which should be synced from peers or uploaded by client again.
+    pub async fn reconcile_flush_journals(&self) -> Result<Vec<(u64, Vec<ChunkRange>)>> {
+        let mut result = vec![];
+
+        for journal in self.log_store.get_flush_journals().await? {
+            let tx_seq = journal.tx_seq;
+            let tx = match self.log_store.get_tx_by_seq_number(tx_seq).await? {
+                Some(tx) if tx.data_merkle_root == journal.data_root => tx,
+                _ => {
+                    info!(%tx_seq, "Remove flush journal of reverted file");
+                    self.log_store.remove_flush_journal(tx_seq).await?;
+                    continue;
+                }
+            };
+
+            let missing = self.log_store.verify_flush_journal(journal).await?;
+            if missing.is_empty() {
+                // Finalization fails if segments not in journal are not uploaded yet.
+                info!(%tx_seq, "Journaled segments flushed, queue to finalize file");
+                self.send_finalize_file(FileID {
+                    root: tx.data_merkle_root,
+                    tx_id: tx.id(),
+                })
+                .await?;
+            } else {
+                warn!(%tx_seq, ?missing, "Journaled segments missing after restart");
+                self.log_store.remove_flush_journal(tx_seq).await?;
+                result.push((tx_seq, missing));
+            }
+        }
+
+        Ok(result)
+    }
+
     pub async fn get_uploaded_seg_num(&self, root: &DataRoot) -> Option<(usize, bool)> {
         let inner = self.inner.lock().await;
 
         if let Some(file) = inner.segment_cache.get_file(root) {
-            Some((file.segments.len(), true))
+            Some((file.num_segments(), true))
         } else {
             inner
                 .write_control
@@ -361,6 +739,60 @@ impl MemoryChunkPool {
         }
     }
 
+    /// Returns the indices of segments cached or written into store via pool, or `None` if the
+    /// file is not tracked in pool.
+    pub async fn get_uploaded_segments(&self, root: &DataRoot) -> Option<Vec<usize>> {
+        let inner = self.inner.lock().await;
+
+        if let Some(file) = inner.segment_cache.get_file(root) {
+            Some(file.received_segments())
+        } else {
+            inner
+                .write_control
+                .get_file(root)
+                .map(|file| file.received_segments())
+        }
+    }
+
+    /// Returns a snapshot of all files in pool, which holds the lock only to copy the status.
+    pub async fn status(&self) -> ChunkPoolStatus {
+        let now = Instant::now();
+        let inner = self.inner.lock().await;
+        let mut files = inner.segment_cache.status(now);
+        files.extend(inner.write_control.status(now));
+
+        ChunkPoolStatus {
+            files,
+            total_bytes: inner.segment_cache.total_chunks * CHUNK_SIZE,
+            total_spilled_bytes: inner.segment_cache.total_spilled_chunks * CHUNK_SIZE,
+            total_writings: inner.write_control.total_writings,
+        }
+    }
+
+    /// Drops the cached segments of a file whose tx is not retrieved yet, e.g. a stuck upload,
+    /// and returns whether dropped. Files to write or being written into store are never
+    /// evicted.
+    pub async fn evict_pending_file(&self, root: &DataRoot) -> bool {
+        let dropped = {
+            let mut inner = self.inner.lock().await;
+            // The tx is retrieved once the total chunks updated.
+            let pending = inner
+                .segment_cache
+                .get_file(root)
+                .map_or(false, |file| file.total_chunks == 0);
+            if !pending {
+                return false;
+            }
+
+            inner.segment_cache.drop_file(root);
+            inner.segment_cache.take_dropped_spill_files()
+        };
+        remove_spill_files(dropped).await;
+
+        info!(%root, "Evicted pending file from chunk pool");
+        true
+    }
+
     async fn send_finalize_file(&self, file_id: FileID) -> Result<()> {
         if let Err(e) = self.sender.send(ChunkPoolMessage::FinalizeFile(file_id)) {
             // Channel receiver will not be dropped until program exit.
@@ -381,3 +813,302 @@ impl MemoryChunkPool {
         }
     }
 }
+
+/// Removes the spilled segments of dropped files, which runs out of the pool lock.
+async fn remove_spill_files(spill_files: Vec<SpillFile>) {
+    for spill_file in spill_files {
+        spill_file.remove().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_types::TransactionBuilder;
+    use storage::log_store::log_manager::{
+        sub_merkle_tree, tx_subtree_root_list_padded, LogConfig,
+    };
+    use storage::log_store::{LogStoreChunkWrite, LogStoreWrite};
+    use storage::LogManager;
+    use task_executor::test_utils::TestRuntime;
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    fn new_config() -> Config {
+        Config {
+            write_window_size: 4,
+            max_cached_chunks_all: 1024,
+            max_cached_chunks_per_file: 1024,
+            max_spilled_chunks_all: 1024,
+            max_writings: 4,
+            expiration_time_secs: 300,
+            shard_config: ShardConfig::default(),
+            spill_dir: None,
+            read_only: false,
+        }
+    }
+
+    /// Creates a pool on the store of a tx of 2 segments, whose data is stored but not
+    /// finalized.
+    fn new_pool(
+        runtime: &TestRuntime,
+    ) -> (
+        MemoryChunkPool,
+        UnboundedReceiver<ChunkPoolMessage>,
+        Arc<LogManager>,
+        Transaction,
+        Vec<u8>,
+    ) {
+        let data: Vec<u8> = (0..8 * CHUNK_SIZE)
+            .map(|i| (i / CHUNK_SIZE) as u8)
+            .collect();
+        let tx = TransactionBuilder::new(0)
+            .size(data.len() as u64)
+            .data_merkle_root(sub_merkle_tree(&data).unwrap().root().into())
+            .start_entry_index(8)
+            .merkle_nodes(tx_subtree_root_list_padded(&data))
+            .build()
+            .unwrap();
+        let log_store = Arc::new(LogManager::memorydb(LogConfig::default()).unwrap());
+        log_store.put_tx(tx.clone()).unwrap();
+        log_store
+            .put_chunks(
+                tx.seq,
+                ChunkArray {
+                    data: data.clone(),
+                    start_index: 0,
+                },
+            )
+            .unwrap();
+
+        let store = Arc::new(Store::new(log_store.clone(), runtime.task_executor.clone()));
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let pool = MemoryChunkPool::new(new_config(), store, sender);
+        (pool, receiver, log_store, tx, data)
+    }
+
+    fn new_tx_segment(tx: &Transaction, data: &[u8], seg_index: usize) -> SegmentInfo {
+        SegmentInfo {
+            root: tx.data_merkle_root,
+            seg_data: data[seg_index * 4 * CHUNK_SIZE..(seg_index + 1) * 4 * CHUNK_SIZE].to_vec(),
+            seg_proof: FileProof::new(vec![], vec![seg_index == 0]),
+            seg_index,
+            chunks_per_segment: 4,
+            file_size: data.len(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_stored_segments() {
+        let runtime = TestRuntime::default();
+        let (pool, mut receiver, _, tx, data) = new_pool(&runtime);
+        let file_id = FileID {
+            root: tx.data_merkle_root,
+            tx_id: tx.id(),
+        };
+
+        // stored segments are not written again, but counted to finalize the file
+        pool.write_chunks(new_tx_segment(&tx, &data, 0), file_id, data.len())
+            .await
+            .unwrap();
+        assert!(receiver.try_recv().is_err());
+
+        let mut conflicted = new_tx_segment(&tx, &data, 1);
+        conflicted.seg_data[0] ^= 1;
+        let err = pool
+            .write_chunks(conflicted, file_id, data.len())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::SegmentConflicted(1))
+        );
+        assert!(receiver.try_recv().is_err());
+
+        pool.write_chunks(new_tx_segment(&tx, &data, 1), file_id, data.len())
+            .await
+            .unwrap();
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(ChunkPoolMessage::FinalizeFile(id)) if id == file_id
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flush_cached_segment_when_too_many_writings() {
+        let runtime = TestRuntime::default();
+        let (pool, _receiver, _, tx, data) = new_pool(&runtime);
+        let pool = Arc::new(pool);
+        pool.cache_chunks(new_tx_segment(&tx, &data, 0))
+            .await
+            .unwrap();
+
+        // occupy all the writing slots by another file
+        let other = FileID {
+            root: DataRoot::from_low_u64_be(1),
+            ..Default::default()
+        };
+        for seg_index in 0..4 {
+            pool.inner
+                .lock()
+                .await
+                .write_control
+                .write_segment(other, seg_index, 4, 0)
+                .unwrap();
+        }
+
+        let flush = tokio::spawn({
+            let pool = pool.clone();
+            let tx = tx.clone();
+            async move { pool.update_file_info(&tx).await }
+        });
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert!(!flush.is_finished());
+
+        pool.inner
+            .lock()
+            .await
+            .write_control
+            .on_write_failed(&other.root, 0);
+        assert!(flush.await.unwrap().unwrap());
+        assert_eq!(
+            pool.check_duplicate_segment(&tx.data_merkle_root, 0, &data[..4 * CHUNK_SIZE], 4, None)
+                .await
+                .unwrap(),
+            SegmentCheck::Duplicated
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stop_flushing_cached_segment_when_closed() {
+        let runtime = TestRuntime::default();
+        let (pool, _receiver, _, tx, data) = new_pool(&runtime);
+        let pool = Arc::new(pool);
+        pool.cache_chunks(new_tx_segment(&tx, &data, 0))
+            .await
+            .unwrap();
+        let other = FileID {
+            root: DataRoot::from_low_u64_be(1),
+            ..Default::default()
+        };
+        for seg_index in 0..4 {
+            pool.inner
+                .lock()
+                .await
+                .write_control
+                .write_segment(other, seg_index, 4, 0)
+                .unwrap();
+        }
+
+        let flush = tokio::spawn({
+            let pool = pool.clone();
+            let tx = tx.clone();
+            async move { pool.update_file_info(&tx).await }
+        });
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        pool.close().await;
+
+        let err = flush.await.unwrap().unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::ShuttingDown));
+    }
+
+    #[tokio::test]
+    async fn test_check_duplicate_segment() {
+        let runtime = TestRuntime::default();
+        let (pool, _receiver, log_store, tx, data) = new_pool(&runtime);
+        let segment = new_tx_segment(&tx, &data, 1);
+        let check = |seg_data: Vec<u8>, maybe_tx: Option<Transaction>| {
+            let pool = &pool;
+            let root = segment.root;
+            async move {
+                pool.check_duplicate_segment(&root, 1, &seg_data, 4, maybe_tx.as_ref())
+                    .await
+                    .unwrap()
+            }
+        };
+        let mut conflicted = segment.seg_data.clone();
+        conflicted[0] ^= 1;
+
+        // unfinalized data is compared when written
+        assert_eq!(
+            check(segment.seg_data.clone(), Some(tx.clone())).await,
+            SegmentCheck::New
+        );
+
+        log_store.finalize_tx(tx.seq).unwrap();
+        assert_eq!(
+            check(segment.seg_data.clone(), None).await,
+            SegmentCheck::New
+        );
+        assert_eq!(
+            check(segment.seg_data.clone(), Some(tx.clone())).await,
+            SegmentCheck::Duplicated
+        );
+        // not rejected until proven
+        assert_eq!(
+            check(conflicted, Some(tx.clone())).await,
+            SegmentCheck::Conflicted
+        );
+    }
+
+    /// Segment of 4 chunks per segment, whose proof path is of the tree from root to leaf.
+    fn new_segment(seg_index: usize, num_chunks: usize, mut path: Vec<bool>) -> SegmentInfo {
+        path.reverse();
+        SegmentInfo {
+            root: DataRoot::from_low_u64_be(1),
+            seg_data: vec![0u8; num_chunks * CHUNK_SIZE],
+            seg_proof: FileProof::new(vec![], path),
+            seg_index,
+            chunks_per_segment: 4,
+            file_size: 0,
+        }
+    }
+
+    #[test]
+    fn test_validate_range() {
+        // 3 segments, and the last one of 1 chunk
+        let file_size = 9 * CHUNK_SIZE - 100;
+        for (index, path) in [(0, vec![true, true]), (1, vec![true, false])] {
+            new_segment(index, 4, path)
+                .validate_range(file_size)
+                .unwrap();
+        }
+
+        // the boundary segment
+        new_segment(2, 1, vec![false])
+            .validate_range(file_size)
+            .unwrap();
+
+        let err = new_segment(3, 1, vec![false])
+            .validate_range(file_size)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::SegmentOutOfRange {
+                index: 3,
+                num_segments: 3
+            })
+        );
+
+        // full segment at the boundary, e.g. of a bigger file
+        let err = new_segment(2, 4, vec![false])
+            .validate_range(file_size)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::InvalidSegmentLength {
+                index: 2,
+                expected: 1,
+                actual: 4
+            })
+        );
+
+        // proven against the tree of 4 segments
+        let err = new_segment(2, 1, vec![false, true])
+            .validate_range(file_size)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::InvalidProofDepth(2))
+        );
+    }
+}
@@ -1,8 +1,10 @@
-use super::FileID;
+use super::{check_received, FileID, PoolFileState, PoolFileStatus, SegmentDigest};
+use crate::error::Error;
 use crate::Config;
 use anyhow::{bail, Result};
 use shared_types::DataRoot;
 use std::collections::HashMap;
+use std::time::Instant;
 use storage_async::ShardConfig;
 
 /// The segment status in sliding window
@@ -91,6 +93,10 @@ pub struct FileWriteCtrl {
     pub id: FileID,
     total_segments: usize,
     window: CtrlWindow,
+    /// Digests of segments that have been written into store.
+    digests: HashMap<usize, SegmentDigest>,
+    /// When the first segment started to write.
+    created_at: Instant,
 }
 
 impl FileWriteCtrl {
@@ -105,12 +111,25 @@ impl FileWriteCtrl {
             id,
             total_segments,
             window: CtrlWindow::new(window_size, shard_config, tx_start_index),
+            digests: HashMap::default(),
+            created_at: Instant::now(),
         }
     }
 
     pub fn uploaded_seg_num(&self) -> usize {
         self.window.left_boundary
     }
+
+    /// Returns whether the segment has already been written with the same data, or an error
+    /// if a different segment written at the same index.
+    pub fn check_received(&self, seg_index: usize, digest: &SegmentDigest) -> Result<bool> {
+        check_received(&self.digests, seg_index, digest)
+    }
+
+    /// Returns the indices of segments written into store.
+    pub fn received_segments(&self) -> Vec<usize> {
+        self.digests.keys().copied().collect()
+    }
 }
 
 /// ChunkPoolWriteCtrl is used to track uploading progress for all files,
@@ -136,6 +155,27 @@ impl ChunkPoolWriteCtrl {
         self.files.get(root)
     }
 
+    /// Returns the status of all files being written.
+    pub fn status(&self, now: Instant) -> Vec<PoolFileStatus> {
+        self.files
+            .values()
+            .map(|file| PoolFileStatus {
+                root: file.id.root,
+                tx_seq: Some(file.id.tx_id.seq),
+                state: PoolFileState::Writing,
+                buffered_segments: file
+                    .window
+                    .slots
+                    .values()
+                    .filter(|status| **status == SlotStatus::Writing)
+                    .count(),
+                bytes: 0,
+                spilled_bytes: 0,
+                age: now.saturating_duration_since(file.created_at),
+            })
+            .collect()
+    }
+
     pub fn remove_file(&mut self, root: &DataRoot) -> Option<FileWriteCtrl> {
         self.files.remove(root)
     }
@@ -160,25 +200,26 @@ impl ChunkPoolWriteCtrl {
         // ensure the tx_id not changed during file uploading
         if file_ctrl.id != id {
             self.files.remove(&id.root);
-            bail!("Transaction reverted when uploading segments, please try again");
+            bail!(Error::TxReverted);
         }
 
         if file_ctrl.total_segments != total_segments {
-            bail!(
-                "file size in segment doesn't match with file size declared in previous segment. Previous total segments:{}, current total segments:{}s",
-                file_ctrl.total_segments,
-                total_segments
-            );
+            bail!(Error::FileSizeMismatch {
+                expected: file_ctrl.total_segments,
+                actual: total_segments,
+            });
         }
 
         // Segment already uploaded.
         if file_ctrl.window.check_duplicate(seg_index) {
-            bail!("segment has already been uploaded or is being uploaded");
+            bail!(Error::SegmentAlreadyUploaded(seg_index));
         }
 
         // Limits the number of writing threads.
         if self.total_writings >= self.config.max_writings {
-            bail!("too many data writing: {}", self.config.max_writings);
+            bail!(Error::TooManyWritings {
+                limit: self.config.max_writings
+            });
         }
 
         file_ctrl.window.start_writing(seg_index)?;
@@ -188,13 +229,19 @@ impl ChunkPoolWriteCtrl {
         Ok(())
     }
 
-    pub fn on_write_succeeded(&mut self, root: &DataRoot, seg_index: usize) -> bool {
+    pub fn on_write_succeeded(
+        &mut self,
+        root: &DataRoot,
+        seg_index: usize,
+        digest: SegmentDigest,
+    ) -> bool {
         let file_ctrl = match self.files.get_mut(root) {
             Some(w) => w,
             None => return false,
         };
 
         file_ctrl.window.finish_writing(seg_index);
+        file_ctrl.digests.insert(seg_index, digest);
 
         assert!(self.total_writings > 0);
         self.total_writings -= 1;
@@ -229,3 +276,76 @@ impl ChunkPoolWriteCtrl {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem_pool::segment_digest;
+    use shared_types::TxID;
+
+    fn new_ctrl() -> ChunkPoolWriteCtrl {
+        ChunkPoolWriteCtrl::new(Config {
+            write_window_size: 4,
+            max_cached_chunks_all: 1024,
+            max_cached_chunks_per_file: 1024,
+            max_spilled_chunks_all: 1024,
+            max_writings: 4,
+            expiration_time_secs: 300,
+            shard_config: ShardConfig::default(),
+            spill_dir: None,
+            read_only: false,
+        })
+    }
+
+    #[test]
+    fn test_check_received() {
+        let mut ctrl = new_ctrl();
+        let id = FileID {
+            root: DataRoot::from_low_u64_be(1),
+            tx_id: Default::default(),
+        };
+        let digest = segment_digest(&[1u8; 256]);
+
+        ctrl.write_segment(id, 0, 2, 0).unwrap();
+        // not received until written into store
+        let file = ctrl.get_file(&id.root).unwrap();
+        assert!(!file.check_received(0, &digest).unwrap());
+
+        assert!(!ctrl.on_write_succeeded(&id.root, 0, digest));
+        let file = ctrl.get_file(&id.root).unwrap();
+        assert!(file.check_received(0, &digest).unwrap());
+        assert!(!file.check_received(1, &digest).unwrap());
+
+        let err = file
+            .check_received(0, &segment_digest(&[2u8; 256]))
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::SegmentConflicted(0))
+        );
+    }
+
+    #[test]
+    fn test_status() {
+        let mut ctrl = new_ctrl();
+        let id = FileID {
+            root: DataRoot::from_low_u64_be(1),
+            tx_id: TxID {
+                seq: 3,
+                hash: Default::default(),
+            },
+        };
+
+        ctrl.write_segment(id, 0, 4, 0).unwrap();
+        ctrl.write_segment(id, 1, 4, 0).unwrap();
+        ctrl.on_write_succeeded(&id.root, 0, segment_digest(&[1u8; 256]));
+
+        let status = ctrl.status(Instant::now());
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].root, id.root);
+        assert_eq!(status[0].tx_seq, Some(3));
+        assert_eq!(status[0].state, PoolFileState::Writing);
+        assert_eq!(status[0].buffered_segments, 1);
+        assert_eq!(ctrl.total_writings, 1);
+    }
+}
@@ -0,0 +1,16 @@
+use std::sync::Arc;
+
+use metrics::{Counter, CounterUsize, Gauge, GaugeUsize};
+
+lazy_static::lazy_static! {
+    pub static ref CACHED_FILES: Arc<dyn Gauge<usize>> = GaugeUsize::register("chunk_pool_cache_files");
+    pub static ref CACHED_CHUNKS: Arc<dyn Gauge<usize>> = GaugeUsize::register("chunk_pool_cache_chunks");
+    pub static ref SPILLED_FILES: Arc<dyn Gauge<usize>> = GaugeUsize::register("chunk_pool_cache_spilled_files");
+    pub static ref SPILLED_CHUNKS: Arc<dyn Gauge<usize>> = GaugeUsize::register("chunk_pool_cache_spilled_chunks");
+
+    pub static ref EXPIRED_FILES: Arc<dyn Counter<usize>> = CounterUsize::register("chunk_pool_cache_expired_files");
+    pub static ref REJECTED_FILES: Arc<dyn Counter<usize>> = CounterUsize::register("chunk_pool_cache_rejected_files");
+
+    pub static ref DUPLICATED_SEGMENTS: Arc<dyn Counter<usize>> = CounterUsize::register("chunk_pool_segment_dedup_hits");
+    pub static ref CONFLICTED_SEGMENTS: Arc<dyn Counter<usize>> = CounterUsize::register("chunk_pool_segment_conflicts");
+}
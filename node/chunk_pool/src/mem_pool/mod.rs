@@ -1,15 +1,113 @@
 mod chunk_cache;
 mod chunk_pool_inner;
 mod chunk_write_control;
+mod metrics;
+mod spill;
 
 pub use chunk_pool_inner::MemoryChunkPool;
 pub use chunk_pool_inner::SegmentInfo;
 
+use crate::error::Error;
+use anyhow::{bail, Result};
 use shared_types::DataRoot;
 use shared_types::TxID;
+use std::collections::HashMap;
+use std::time::Duration;
+use tiny_keccak::{Hasher, Keccak};
 
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct FileID {
     pub root: DataRoot,
     pub tx_id: TxID,
 }
+
+/// Result of checking an uploaded segment against the received ones before proof validation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SegmentCheck {
+    /// No segment received at the same index yet.
+    New,
+    /// The same segment has already been received.
+    Duplicated,
+    /// A different segment has already been received at the same index, which is rejected
+    /// only once the uploaded one is proven.
+    Conflicted,
+}
+
+/// State of a file in chunk pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoolFileState {
+    /// Segments cached in memory, and the tx not retrieved from blockchain yet.
+    Pending,
+    /// Segments cached and spilled to disk, and the tx not retrieved from blockchain yet.
+    Spilled,
+    /// Tx retrieved, and the cached segments are to be written into store.
+    Flushing,
+    /// Segments uploaded after the tx retrieved, which are written into store directly.
+    Writing,
+}
+
+impl PoolFileState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PoolFileState::Pending => "pending",
+            PoolFileState::Spilled => "spilled",
+            PoolFileState::Flushing => "flushing",
+            PoolFileState::Writing => "writing",
+        }
+    }
+}
+
+/// Snapshot of a file in chunk pool.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolFileStatus {
+    pub root: DataRoot,
+    /// `None` if the tx not retrieved yet.
+    pub tx_seq: Option<u64>,
+    pub state: PoolFileState,
+    /// Number of cached segments including spilled ones, or the segments being written into
+    /// store if `Writing`.
+    pub buffered_segments: usize,
+    /// Bytes of the segments cached in memory.
+    pub bytes: usize,
+    /// Bytes of the segments spilled to disk.
+    pub spilled_bytes: usize,
+    /// Time since the first segment received.
+    pub age: Duration,
+}
+
+/// Snapshot of all files in chunk pool, from the oldest cached file to the files being written.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChunkPoolStatus {
+    pub files: Vec<PoolFileStatus>,
+    /// Bytes of all segments cached in memory.
+    pub total_bytes: usize,
+    /// Bytes of all segments spilled to disk.
+    pub total_spilled_bytes: usize,
+    /// Number of segments being written into store.
+    pub total_writings: usize,
+}
+
+/// Digest of segment data, which is used to detect duplicated or conflicted segments.
+type SegmentDigest = [u8; 32];
+
+fn segment_digest(data: &[u8]) -> SegmentDigest {
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    let mut digest = [0u8; 32];
+    hasher.finalize(&mut digest);
+    digest
+}
+
+/// Returns whether the segment has already been received with the same data, or an error
+/// if a different segment received at the same index.
+fn check_received(
+    digests: &HashMap<usize, SegmentDigest>,
+    seg_index: usize,
+    digest: &SegmentDigest,
+) -> Result<bool> {
+    match digests.get(&seg_index) {
+        Some(received) if received == digest => Ok(true),
+        Some(_) => bail!(Error::SegmentConflicted(seg_index)),
+        None => Ok(false),
+    }
+}
@@ -0,0 +1,289 @@
+use anyhow::{anyhow, bail, Result};
+use shared_types::{ChunkArray, DataRoot, FileProof};
+use ssz::{Decode, Encode};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{OwnedRwLockReadGuard, RwLock};
+
+/// Length of the segment header, including the start chunk index and data length.
+const HEADER_SIZE: usize = 16;
+/// Sub directory of the configured spill dir, which holds all the spilled files.
+const SPILL_SUBDIR: &str = "zgs_chunk_pool_spill";
+/// Marker created in the sub directory by spill store. Nothing is cleaned up without it.
+const MARKER_FILE: &str = ".zgs_chunk_pool_spill";
+/// Extension of the spilled segment files.
+const SEGMENT_EXT: &str = "seg";
+
+/// Persists segments of cached files on disk when the memory caps reached, so that
+/// they could be restored once log entry retrieved from blockchain.
+///
+/// Each segment is stored in file `<dir>/zgs_chunk_pool_spill/<root>-<id>/<seg_index>.seg`,
+/// where `id` distinguishes the files cached for the same root at different times.
+pub struct SpillStore {
+    dir: PathBuf,
+    next_id: AtomicU64,
+}
+
+impl SpillStore {
+    /// Creates a spill store in a sub directory of the specified directory. Files spilled in
+    /// the previous run are removed, since the memory pool is not persisted. Only the files
+    /// created by spill store are removed, so that a misconfigured directory never loses
+    /// unrelated data.
+    pub fn new(dir: PathBuf) -> Self {
+        let dir = dir.join(SPILL_SUBDIR);
+
+        if let Err(e) = clean_up(&dir) {
+            warn!(?dir, "Failed to clean up chunk pool spill dir: {:?}", e);
+        }
+
+        if let Err(e) = fs::create_dir_all(&dir).and_then(|_| fs::write(dir.join(MARKER_FILE), b""))
+        {
+            warn!(?dir, "Failed to create chunk pool spill dir: {:?}", e);
+        }
+
+        SpillStore {
+            dir,
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Allocates the location to spill a newly cached file. Cached files of the same root are
+    /// spilled into different directories, so that removing a dropped file never affects the
+    /// one cached later.
+    pub fn new_file(&self, root: &DataRoot) -> SpillFile {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        SpillFile {
+            dir: self.dir.join(format!("{:?}-{}", root, id)),
+            lock: Default::default(),
+        }
+    }
+}
+
+/// Location of a spilled file. Segments are written with shared access, while restoring or
+/// removing the file waits for all the in-flight writes.
+///
+/// All file I/O runs on the blocking threads, so that it never holds the pool lock.
+#[derive(Clone)]
+pub struct SpillFile {
+    dir: PathBuf,
+    lock: Arc<RwLock<()>>,
+}
+
+impl SpillFile {
+    /// Returns whether both refer to the same spilled file.
+    pub fn same(&self, other: &SpillFile) -> bool {
+        Arc::ptr_eq(&self.lock, &other.lock)
+    }
+
+    /// Prepares to write segments once the pool lock released. Never fails unless the file is
+    /// being restored or removed, which happens only after the file removed from pool.
+    pub fn prepare_write(&self) -> Result<PendingSpill> {
+        let guard = self
+            .lock
+            .clone()
+            .try_read_owned()
+            .map_err(|_| anyhow!("spilled file is being removed: {:?}", self.dir))?;
+
+        Ok(PendingSpill {
+            file: self.clone(),
+            _guard: guard,
+            segments: vec![],
+        })
+    }
+
+    /// Reads the specified segments back into memory, and removes the spilled file.
+    pub async fn restore(
+        &self,
+        indices: Vec<usize>,
+    ) -> Result<Vec<(usize, (ChunkArray, FileProof))>> {
+        let _guard = self.lock.write().await;
+        let dir = self.dir.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let result: Result<Vec<_>> = indices
+                .into_iter()
+                .map(|index| read_segment(&dir, index).map(|segment| (index, segment)))
+                .collect();
+            remove_file_dir(&dir);
+            result
+        })
+        .await?
+    }
+
+    /// Removes all spilled segments of the file.
+    pub async fn remove(&self) {
+        let _guard = self.lock.write().await;
+        let dir = self.dir.clone();
+
+        if let Err(e) = tokio::task::spawn_blocking(move || remove_file_dir(&dir)).await {
+            warn!("Failed to remove spilled file: {:?}", e);
+        }
+    }
+}
+
+/// Segments to write into a spilled file after the pool lock released.
+pub struct PendingSpill {
+    file: SpillFile,
+    _guard: OwnedRwLockReadGuard<()>,
+    segments: Vec<(usize, ChunkArray, FileProof)>,
+}
+
+impl PendingSpill {
+    pub fn file(&self) -> &SpillFile {
+        &self.file
+    }
+
+    pub fn add(&mut self, seg_index: usize, seg: ChunkArray, proof: FileProof) {
+        self.segments.push((seg_index, seg, proof));
+    }
+
+    pub async fn write(self) -> Result<()> {
+        tokio::task::spawn_blocking(move || {
+            fs::create_dir_all(&self.file.dir)?;
+            for (seg_index, seg, proof) in self.segments.iter() {
+                write_segment(&self.file.dir, *seg_index, seg, proof)?;
+            }
+            Ok(())
+        })
+        .await?
+    }
+}
+
+fn segment_path(dir: &Path, seg_index: usize) -> PathBuf {
+    dir.join(format!("{}.{}", seg_index, SEGMENT_EXT))
+}
+
+fn write_segment(dir: &Path, seg_index: usize, seg: &ChunkArray, proof: &FileProof) -> Result<()> {
+    let proof = proof.as_ssz_bytes();
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + seg.data.len() + proof.len());
+    bytes.extend_from_slice(&seg.start_index.to_be_bytes());
+    bytes.extend_from_slice(&(seg.data.len() as u64).to_be_bytes());
+    bytes.extend_from_slice(&seg.data);
+    bytes.extend_from_slice(&proof);
+
+    fs::write(segment_path(dir, seg_index), bytes)?;
+
+    Ok(())
+}
+
+fn read_segment(dir: &Path, seg_index: usize) -> Result<(ChunkArray, FileProof)> {
+    let bytes = fs::read(segment_path(dir, seg_index))?;
+    if bytes.len() < HEADER_SIZE {
+        bail!("invalid spilled segment, len = {}", bytes.len());
+    }
+
+    let start_index = u64::from_be_bytes(bytes[..8].try_into()?);
+    let data_len = u64::from_be_bytes(bytes[8..HEADER_SIZE].try_into()?) as usize;
+    if bytes.len() < HEADER_SIZE + data_len {
+        bail!(
+            "invalid spilled segment, len = {}, data_len = {}",
+            bytes.len(),
+            data_len
+        );
+    }
+
+    let data = bytes[HEADER_SIZE..HEADER_SIZE + data_len].to_vec();
+    let proof = FileProof::from_ssz_bytes(&bytes[HEADER_SIZE + data_len..])
+        .map_err(|e| anyhow!("failed to decode spilled segment proof: {:?}", e))?;
+
+    Ok((ChunkArray { data, start_index }, proof))
+}
+
+/// Removes the spilled segments in the directory, and the directory itself if empty then.
+fn remove_segments(dir: &Path) -> std::io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file() && path.extension() == Some(SEGMENT_EXT.as_ref()) {
+            fs::remove_file(&path)?;
+        }
+    }
+
+    // Fails if any file not created by spill store left, which is kept as is.
+    fs::remove_dir(dir)
+}
+
+fn remove_file_dir(dir: &Path) {
+    if let Err(e) = remove_segments(dir) {
+        warn!(?dir, "Failed to remove spilled file: {:?}", e);
+    }
+}
+
+/// Returns whether the directory name is of a spilled file, i.e. `<root>-<id>`.
+fn is_file_dir_name(name: &str) -> bool {
+    match name.rsplit_once('-') {
+        Some((root, id)) => root.len() == 66 && root.starts_with("0x") && id.parse::<u64>().is_ok(),
+        None => false,
+    }
+}
+
+/// Removes the files spilled in the previous run, if the directory is created by spill store.
+fn clean_up(dir: &Path) -> std::io::Result<()> {
+    if !dir.join(MARKER_FILE).is_file() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_file_dir = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, is_file_dir_name);
+        if path.is_dir() && is_file_dir {
+            remove_segments(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_clean_up_spilled_files_only() {
+        let dir = TempDir::new().unwrap();
+        let unrelated = dir.path().join("unrelated");
+        fs::write(&unrelated, b"data").unwrap();
+
+        // the spill dir of last run
+        let spill_dir = dir.path().join(SPILL_SUBDIR);
+        let file_dir = spill_dir.join(format!("{:?}-3", DataRoot::from_low_u64_be(1)));
+        fs::create_dir_all(&file_dir).unwrap();
+        fs::write(spill_dir.join(MARKER_FILE), b"").unwrap();
+        fs::write(segment_path(&file_dir, 0), b"seg").unwrap();
+        let other_dir = spill_dir.join("other");
+        fs::create_dir_all(&other_dir).unwrap();
+        fs::write(other_dir.join("0.seg"), b"data").unwrap();
+
+        SpillStore::new(dir.path().to_path_buf());
+        assert!(unrelated.exists());
+        assert!(!file_dir.exists());
+        assert!(other_dir.join("0.seg").exists());
+    }
+
+    #[test]
+    fn test_no_clean_up_without_marker() {
+        let dir = TempDir::new().unwrap();
+        let file_dir = dir
+            .path()
+            .join(SPILL_SUBDIR)
+            .join(format!("{:?}-0", DataRoot::from_low_u64_be(1)));
+        fs::create_dir_all(&file_dir).unwrap();
+        fs::write(segment_path(&file_dir, 0), b"seg").unwrap();
+
+        SpillStore::new(dir.path().to_path_buf());
+        assert!(segment_path(&file_dir, 0).exists());
+    }
+}
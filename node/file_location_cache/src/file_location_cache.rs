@@ -8,8 +8,10 @@ use rand::seq::IteratorRandom;
 use shared_types::{timestamp_now, TxID};
 use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use storage::config::ShardConfig;
+use tokio::sync::watch;
 
 lazy_static::lazy_static! {
     pub static ref INSERT_QPS: Arc<dyn Meter> = register_meter_with_group("file_location_cache_insert", "qps");
@@ -270,17 +272,31 @@ impl PeerShardConfigCache {
     }
 }
 
+/// Announcements of a file from different peers, i.e. the latest one of each peer.
+pub type PeerAnnouncements = HashMap<PeerId, SignedAnnounceFile>;
+
+fn collect_announcement(announcements: &mut PeerAnnouncements, announcement: SignedAnnounceFile) {
+    let peer_id = announcement.peer_id.clone().into();
+    match announcements.get(&peer_id) {
+        Some(existing) if existing.timestamp >= announcement.timestamp => {}
+        _ => {
+            announcements.insert(peer_id, announcement);
+        }
+    }
+}
+
 pub struct FileLocationCache {
     cache: Mutex<FileCache>,
     peer_cache: Mutex<PeerShardConfigCache>,
+    /// Announcements collected for the watched files, which are not limited by
+    /// `max_entries_per_file` of cache.
+    watchers: Mutex<HashMap<TxID, HashMap<u64, watch::Sender<PeerAnnouncements>>>>,
+    next_watcher_id: AtomicU64,
 }
 
 impl Default for FileLocationCache {
     fn default() -> Self {
-        FileLocationCache {
-            cache: Mutex::new(FileCache::new(Default::default())),
-            peer_cache: Mutex::new(Default::default()),
-        }
+        Self::new(Default::default())
     }
 }
 
@@ -289,6 +305,8 @@ impl FileLocationCache {
         FileLocationCache {
             cache: Mutex::new(FileCache::new(config)),
             peer_cache: Mutex::new(Default::default()),
+            watchers: Default::default(),
+            next_watcher_id: AtomicU64::new(0),
         }
     }
 
@@ -309,6 +327,47 @@ impl FileLocationCache {
         }
 
         TOTAL_CACHED.update(cache.total_announcements as u64);
+        drop(cache);
+
+        let watchers = self.watchers.lock();
+        for tx_id in announcement.tx_ids.iter() {
+            for sender in watchers
+                .get(tx_id)
+                .into_iter()
+                .flat_map(|senders| senders.values())
+            {
+                sender.send_modify(|announcements| {
+                    collect_announcement(announcements, announcement.clone())
+                });
+            }
+        }
+    }
+
+    /// Collects all the announcements of the specified file since now, together with the
+    /// cached ones, until the returned watcher dropped. Unlike [`Self::get_all`], the
+    /// announcements are not limited by `max_entries_per_file`, e.g. to count the replicas.
+    pub fn watch(&self, tx_id: TxID) -> AnnouncementWatcher<'_> {
+        let id = self.next_watcher_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = watch::channel(PeerAnnouncements::new());
+
+        // Registered before the cached ones are collected, so that none is missed in between.
+        self.watchers
+            .lock()
+            .entry(tx_id)
+            .or_default()
+            .insert(id, sender.clone());
+        sender.send_modify(|announcements| {
+            for announcement in self.get_all(tx_id) {
+                collect_announcement(announcements, announcement);
+            }
+        });
+
+        AnnouncementWatcher {
+            cache: self,
+            tx_id,
+            id,
+            receiver,
+        }
     }
 
     pub fn get_one(&self, tx_id: TxID) -> Option<SignedAnnounceFile> {
@@ -337,6 +396,43 @@ impl FileLocationCache {
     }
 }
 
+/// Announcements of a file collected by [`FileLocationCache::watch`].
+pub struct AnnouncementWatcher<'a> {
+    cache: &'a FileLocationCache,
+    tx_id: TxID,
+    id: u64,
+    receiver: watch::Receiver<PeerAnnouncements>,
+}
+
+impl AnnouncementWatcher<'_> {
+    /// Returns the announcements collected so far.
+    pub fn announcements(&self) -> PeerAnnouncements {
+        self.receiver.borrow().clone()
+    }
+
+    /// Waits until the collected announcements satisfy `f`.
+    pub async fn wait_for(&mut self, mut f: impl FnMut(&PeerAnnouncements) -> bool) {
+        while !f(&self.receiver.borrow_and_update()) {
+            // never fails since the sender is kept until dropped
+            if self.receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for AnnouncementWatcher<'_> {
+    fn drop(&mut self) {
+        let mut watchers = self.cache.watchers.lock();
+        if let Some(senders) = watchers.get_mut(&self.tx_id) {
+            senders.remove(&self.id);
+            if senders.is_empty() {
+                watchers.remove(&self.tx_id);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::cmp::Reverse;
@@ -346,7 +442,7 @@ mod tests {
 
     use crate::{test_util::AnnounceFileBuilder, Config};
 
-    use super::{AnnouncementCache, FileCache};
+    use super::{AnnouncementCache, FileCache, FileLocationCache};
 
     fn create_file(peer_id: Option<PeerId>, timestamp: u32) -> SignedAnnounceFile {
         let builder = AnnounceFileBuilder::default().with_timestamp(timestamp);
@@ -609,4 +705,32 @@ mod tests {
             vec![now - 3, now - 2, now - 1],
         );
     }
+
+    #[test]
+    fn test_watch_beyond_file_entries() {
+        let cache = FileLocationCache::new(Config {
+            max_entries_total: 100,
+            max_entries_per_file: 2,
+            entry_expiration_time_secs: 3600,
+        });
+        let now = timestamp_now();
+        let tx1 = TxID::random_hash(1);
+        let peer1 = PeerId::random();
+        cache.insert(create_file_2(tx1, peer1, now - 3));
+
+        let watcher = cache.watch(tx1);
+        assert_eq!(watcher.announcements().len(), 1);
+
+        cache.insert(create_file_2(tx1, PeerId::random(), now - 2));
+        cache.insert(create_file_2(tx1, PeerId::random(), now - 1));
+        cache.insert(create_file_2(tx1, peer1, now));
+        cache.insert(create_file_2(TxID::random_hash(2), PeerId::random(), now));
+        assert_eq!(cache.get_all(tx1).len(), 2);
+        let announcements = watcher.announcements();
+        assert_eq!(announcements.len(), 3);
+        assert_eq!(announcements[&peer1].timestamp, now);
+
+        drop(watcher);
+        assert!(cache.watchers.lock().is_empty());
+    }
 }
@@ -3,7 +3,7 @@ pub mod test_util;
 
 use serde::Deserialize;
 
-pub use crate::file_location_cache::FileLocationCache;
+pub use crate::file_location_cache::{AnnouncementWatcher, FileLocationCache, PeerAnnouncements};
 
 #[derive(Clone, Copy, Debug, Deserialize)]
 #[serde(default)]
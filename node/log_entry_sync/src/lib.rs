@@ -2,10 +2,16 @@ extern crate core;
 
 mod sync_manager;
 
-use ethers::prelude::H160;
+use ethers::prelude::{Http, RetryClient, H160};
 pub use sync_manager::{
-    config::{CacheConfig, LogSyncConfig},
-    LogSyncEvent, LogSyncManager,
+    config::{CacheConfig, LogSyncConfig, QuarantineConfig},
+    contract_event::{ContractEvent, ContractEventKind, ContractLog, EventSubscription},
+    earnings::{Earnings, EarningsTracker},
+    failover_client::{EndpointClient, EndpointStatus, FailoverClient},
+    ingest_policy::{IngestPolicy, PolicyViolation},
+    replay::{export_log_entries, LogRecord},
+    ChainHeads, LogSyncEvent, LogSyncManager, LogSyncMonitor,
 };
 
 pub type ContractAddress = H160;
+pub type RpcClient = FailoverClient<RetryClient<Http>>;
@@ -1,19 +1,38 @@
+use std::path::PathBuf;
 use std::time::Duration;
 
+use crate::sync_manager::contract_event::EventSubscription;
 use crate::ContractAddress;
 
 pub struct LogSyncConfig {
-    pub rpc_endpoint_url: String,
+    /// RPC endpoints of the blockchain, and the first one is used at startup.
+    pub rpc_endpoint_urls: Vec<String>,
+    /// Switch to another endpoint if the active one falls behind by more than this number
+    /// of blocks.
+    pub rpc_max_head_lag: u64,
+    /// Interval to check the latency and head of rpc endpoints.
+    pub rpc_health_check_interval: Duration,
     pub contract_address: ContractAddress,
+    /// Events of other contracts to sync along with the `Submit` events.
+    pub event_subscriptions: Vec<EventSubscription>,
     pub cache_config: CacheConfig,
+    pub quarantine_config: QuarantineConfig,
 
     /// The block number where we start to sync data.
     /// This is usually the block number when Zgs contract is deployed.
     pub start_block_number: u64,
-    /// The number of blocks needed for confirmation on the blockchain.
+    /// The number of blocks needed for confirmation on the blockchain, before the txs are
+    /// written into the store.
     /// This is used to rollback to a stable height if reorg happens during node restart.
     /// TODO(zz): Some blockchains have better confirmation/finalization mechanisms.
-    pub confirmation_block_count: u64,
+    pub ingest_confirmations: u64,
+    /// The number of blocks needed for confirmation before the ingested txs are finalized by
+    /// log sync, which is no less than `ingest_confirmations`. The txs ingested but not
+    /// finalized yet are reverted cleanly if reorged.
+    pub finalize_confirmations: u64,
+    /// Only process logs until the `finalized` block instead of `ingest_confirmations`, in which
+    /// case the txs are finalized once ingested.
+    pub use_finalized_tag: bool,
     /// Maximum number of event logs to poll at a time.
     pub log_page_size: u64,
 
@@ -27,6 +46,8 @@ pub struct LogSyncConfig {
     // the duration between each paginated getLogs RPC call, in ms.
     // This is set to avoid triggering the throttling mechanism in the RPC server.
     pub recover_query_delay: u64,
+    // the maximum number of paginated getLogs RPC calls in flight to catch up.
+    pub recover_concurrency: usize,
 
     // the counter assumed the finalized block behind the latest block
     pub default_finalized_block_count: u64,
@@ -39,6 +60,14 @@ pub struct LogSyncConfig {
 
     // the timeout for blockchain rpc connection
     pub blockchain_rpc_timeout: Duration,
+    /// Recover the rpc connection if no new block applied for this duration, and zero
+    /// disables the stall detection.
+    pub stall_timeout: Duration,
+    /// Replay the recorded logs from this file instead of syncing from blockchain, and stop
+    /// at the end of file.
+    pub replay_file: Option<PathBuf>,
+    /// Only verify the store against blockchain without writing, e.g. the node is read-only.
+    pub verify_only: bool,
 }
 
 #[derive(Clone)]
@@ -49,13 +78,51 @@ pub struct CacheConfig {
     pub tx_seq_ttl: usize,
 }
 
+/// Bounds of the txs delivered ahead of the next tx seq, see `TxQuarantine`.
+#[derive(Clone)]
+pub struct QuarantineConfig {
+    /// Maximum number of txs parked, beyond which the logs are fetched again.
+    pub capacity: usize,
+    /// Logs are fetched again if the gap before the parked txs is not filled within this
+    /// duration.
+    pub window: Duration,
+}
+
+/// Policy to decide the latest stable block, until which logs are processed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfirmationPolicy {
+    /// Blocks with at least the number of confirmations.
+    Confirmations(u64),
+    /// Blocks until the `finalized` block tag.
+    Finalized,
+}
+
 impl LogSyncConfig {
+    pub fn confirmation_policy(&self) -> ConfirmationPolicy {
+        if self.use_finalized_tag {
+            ConfirmationPolicy::Finalized
+        } else {
+            ConfirmationPolicy::Confirmations(self.ingest_confirmations)
+        }
+    }
+
+    /// Returns the number of blocks that finalization falls behind ingestion.
+    pub fn finalize_lag(&self) -> u64 {
+        if self.use_finalized_tag {
+            0
+        } else {
+            self.finalize_confirmations
+                .saturating_sub(self.ingest_confirmations)
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn new(
-        rpc_endpoint_url: String,
+        rpc_endpoint_urls: Vec<String>,
         contract_address: ContractAddress,
         start_block_number: u64,
-        confirmation_block_count: u64,
+        ingest_confirmations: u64,
+        finalize_confirmations: u64,
         cache_config: CacheConfig,
         log_page_size: u64,
         rate_limit_retries: u32,
@@ -67,23 +134,42 @@ impl LogSyncConfig {
         watch_loop_wait_time_ms: u64,
         force_log_sync_from_start_block_number: bool,
         blockchain_rpc_timeout: Duration,
+        rpc_max_head_lag: u64,
+        rpc_health_check_interval: Duration,
+        recover_concurrency: usize,
+        use_finalized_tag: bool,
+        event_subscriptions: Vec<EventSubscription>,
+        stall_timeout: Duration,
+        replay_file: Option<PathBuf>,
+        verify_only: bool,
+        quarantine_config: QuarantineConfig,
     ) -> Self {
         Self {
-            rpc_endpoint_url,
+            rpc_endpoint_urls,
+            rpc_max_head_lag,
+            rpc_health_check_interval,
             contract_address,
+            event_subscriptions,
             cache_config,
+            quarantine_config,
             start_block_number,
-            confirmation_block_count,
+            ingest_confirmations,
+            finalize_confirmations,
+            use_finalized_tag,
             log_page_size,
             rate_limit_retries,
             timeout_retries,
             initial_backoff,
             recover_query_delay,
+            recover_concurrency,
             default_finalized_block_count,
             remove_finalized_block_interval_minutes,
             watch_loop_wait_time_ms,
             force_log_sync_from_start_block_number,
             blockchain_rpc_timeout,
+            stall_timeout,
+            replay_file,
+            verify_only,
         }
     }
 }
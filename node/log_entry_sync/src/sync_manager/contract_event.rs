@@ -0,0 +1,404 @@
+use crate::ContractAddress;
+use anyhow::{anyhow, bail, Result};
+use contract_interface::{DistributeRewardFilter, NewSubmissionFilter, SubmitFilter};
+use ethers::abi::RawLog;
+use ethers::prelude::EthEvent;
+use ethers::types::{Address, Filter, Log, ValueOrArray, H256, U256};
+use std::str::FromStr;
+
+/// Events of other contracts that could be synced along with the `Submit` events of the
+/// flow contract.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContractEventKind {
+    /// `DistributeReward` of the reward contract.
+    DistributeReward,
+    /// `NewSubmission` of the mine contract, emitted once a PoRA answer is accepted.
+    NewSubmission,
+}
+
+impl ContractEventKind {
+    pub fn signature(&self) -> H256 {
+        match self {
+            ContractEventKind::DistributeReward => DistributeRewardFilter::signature(),
+            ContractEventKind::NewSubmission => NewSubmissionFilter::signature(),
+        }
+    }
+
+    fn decode(&self, contract: ContractAddress, log: RawLog) -> Result<ContractEvent> {
+        match self {
+            ContractEventKind::DistributeReward => {
+                let e = DistributeRewardFilter::decode_log(&log)?;
+                Ok(ContractEvent::DistributeReward {
+                    contract,
+                    pricing_index: e.pricing_index,
+                    beneficiary: e.beneficiary,
+                    amount: e.amount,
+                })
+            }
+            ContractEventKind::NewSubmission => {
+                let e = NewSubmissionFilter::decode_log(&log)?;
+                Ok(ContractEvent::NewSubmission {
+                    contract,
+                    epoch: e.epoch.as_u64(),
+                    miner_id: H256(e.miner_id),
+                    epoch_index: e.epoch_index,
+                    recall_position: e.recall_position,
+                })
+            }
+        }
+    }
+}
+
+impl FromStr for ContractEventKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "DistributeReward" => Ok(ContractEventKind::DistributeReward),
+            "NewSubmission" => Ok(ContractEventKind::NewSubmission),
+            _ => bail!("unsupported contract event {}", s),
+        }
+    }
+}
+
+/// Subscription to an event of a contract, in format `<event>@<contract address>`,
+/// e.g. `DistributeReward@0x0000000000000000000000000000000000000001`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EventSubscription {
+    pub kind: ContractEventKind,
+    pub contract: ContractAddress,
+}
+
+impl FromStr for EventSubscription {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, contract) = s
+            .split_once('@')
+            .ok_or_else(|| anyhow!("invalid event subscription {}, expect <event>@<address>", s))?;
+        Ok(EventSubscription {
+            kind: kind.parse()?,
+            contract: contract
+                .parse()
+                .map_err(|e| anyhow!("invalid contract address {}: {:?}", contract, e))?,
+        })
+    }
+}
+
+/// Decoded event of a subscribed contract.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContractEvent {
+    DistributeReward {
+        contract: ContractAddress,
+        pricing_index: U256,
+        beneficiary: Address,
+        amount: U256,
+    },
+    NewSubmission {
+        contract: ContractAddress,
+        epoch: u64,
+        miner_id: H256,
+        epoch_index: U256,
+        recall_position: U256,
+    },
+}
+
+/// Subscribed event with the position of its log on chain, which identifies the log so that
+/// consumers could drop the logs delivered again and revert the logs of reorged blocks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContractLog {
+    pub event: ContractEvent,
+    pub block_number: u64,
+    pub log_index: u64,
+    /// Hash of the tx that emits the log, which correlates the events of the same tx.
+    pub tx_hash: H256,
+}
+
+impl ContractLog {
+    /// Decodes the log if it is emitted by any subscribed event, or returns `None` otherwise.
+    pub fn decode(subscriptions: &[EventSubscription], log: &Log) -> Option<Result<Self>> {
+        let topic = log.topics.first()?;
+        let subscription = subscriptions
+            .iter()
+            .find(|s| s.contract == log.address && s.kind.signature() == *topic)?;
+
+        Some(Self::decode_subscribed(subscription.kind, log))
+    }
+
+    fn decode_subscribed(kind: ContractEventKind, log: &Log) -> Result<Self> {
+        let block_number = log
+            .block_number
+            .ok_or_else(|| anyhow!("block number missing"))?
+            .as_u64();
+        let log_index = log
+            .log_index
+            .ok_or_else(|| anyhow!("log index missing"))?
+            .as_u64();
+        let tx_hash = log
+            .transaction_hash
+            .ok_or_else(|| anyhow!("tx hash missing"))?;
+        let event = kind.decode(
+            log.address,
+            RawLog {
+                topics: log.topics.clone(),
+                data: log.data.to_vec(),
+            },
+        )?;
+        Ok(ContractLog {
+            event,
+            block_number,
+            log_index,
+            tx_hash,
+        })
+    }
+}
+
+/// Extends the filter of the `Submit` events to match the subscribed events as well, so that
+/// they are fetched in the same queries and processed in the block order.
+pub fn subscribe_events(
+    filter: Filter,
+    flow_address: ContractAddress,
+    subscriptions: &[EventSubscription],
+) -> Filter {
+    if subscriptions.is_empty() {
+        return filter.address(flow_address);
+    }
+
+    let mut addresses = vec![flow_address];
+    let mut topics = vec![Some(SubmitFilter::signature())];
+    for subscription in subscriptions {
+        if !addresses.contains(&subscription.contract) {
+            addresses.push(subscription.contract);
+        }
+        let topic = Some(subscription.kind.signature());
+        if !topics.contains(&topic) {
+            topics.push(topic);
+        }
+    }
+
+    filter
+        .address(ValueOrArray::Array(addresses))
+        .topic0(ValueOrArray::Array(topics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::{encode, Token};
+    use ethers::types::U64;
+
+    fn reward_log(contract: ContractAddress, beneficiary: Address, amount: u64) -> Log {
+        Log {
+            address: contract,
+            topics: vec![
+                DistributeRewardFilter::signature(),
+                H256::from_low_u64_be(7),
+                H256::from(beneficiary),
+            ],
+            data: encode(&[Token::Uint(amount.into())]).into(),
+            block_number: Some(U64::from(100)),
+            log_index: Some(U256::from(3)),
+            transaction_hash: Some(H256::from_low_u64_be(9)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_subscription() {
+        let subscription: EventSubscription =
+            "DistributeReward@0x0000000000000000000000000000000000000001"
+                .parse()
+                .unwrap();
+        assert_eq!(subscription.kind, ContractEventKind::DistributeReward);
+        assert_eq!(subscription.contract, ContractAddress::from_low_u64_be(1));
+
+        let subscription: EventSubscription =
+            "NewSubmission@0x0000000000000000000000000000000000000002"
+                .parse()
+                .unwrap();
+        assert_eq!(subscription.kind, ContractEventKind::NewSubmission);
+
+        assert!("DistributeReward".parse::<EventSubscription>().is_err());
+        assert!("Unknown@0x0000000000000000000000000000000000000001"
+            .parse::<EventSubscription>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_decode_distribute_reward() {
+        let contract = ContractAddress::from_low_u64_be(1);
+        let beneficiary = Address::from_low_u64_be(2);
+        let subscriptions = [EventSubscription {
+            kind: ContractEventKind::DistributeReward,
+            contract,
+        }];
+
+        let log = ContractLog::decode(&subscriptions, &reward_log(contract, beneficiary, 500))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            log,
+            ContractLog {
+                event: ContractEvent::DistributeReward {
+                    contract,
+                    pricing_index: 7.into(),
+                    beneficiary,
+                    amount: 500.into(),
+                },
+                block_number: 100,
+                log_index: 3,
+                tx_hash: H256::from_low_u64_be(9),
+            }
+        );
+
+        // Not subscribed on the contract.
+        let other = ContractAddress::from_low_u64_be(3);
+        assert!(
+            ContractLog::decode(&subscriptions, &reward_log(other, beneficiary, 500)).is_none()
+        );
+
+        // Malformed data.
+        let mut malformed = reward_log(contract, beneficiary, 500);
+        malformed.data = vec![1, 2, 3].into();
+        assert!(ContractLog::decode(&subscriptions, &malformed)
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_decode_new_submission() {
+        let contract = ContractAddress::from_low_u64_be(1);
+        let subscriptions = [EventSubscription {
+            kind: ContractEventKind::NewSubmission,
+            contract,
+        }];
+        let log = Log {
+            address: contract,
+            topics: vec![
+                NewSubmissionFilter::signature(),
+                H256::from_low_u64_be(5),
+                H256::from_low_u64_be(6),
+            ],
+            data: encode(&[Token::Uint(1.into()), Token::Uint(4096.into())]).into(),
+            block_number: Some(U64::from(100)),
+            log_index: Some(U256::from(2)),
+            transaction_hash: Some(H256::from_low_u64_be(9)),
+            ..Default::default()
+        };
+
+        let decoded = ContractLog::decode(&subscriptions, &log).unwrap().unwrap();
+        assert_eq!(
+            decoded.event,
+            ContractEvent::NewSubmission {
+                contract,
+                epoch: 5,
+                miner_id: H256::from_low_u64_be(6),
+                epoch_index: 1.into(),
+                recall_position: 4096.into(),
+            }
+        );
+        assert_eq!(decoded.tx_hash, H256::from_low_u64_be(9));
+
+        // The tx hash is required.
+        let mut pending = log;
+        pending.transaction_hash = None;
+        assert!(ContractLog::decode(&subscriptions, &pending)
+            .unwrap()
+            .is_err());
+    }
+
+    /// Decodes the logs in the format of an `eth_getLogs` response, of which the topics are the
+    /// keccak hashes of the event signatures in the contract ABIs.
+    #[test]
+    fn test_decode_rpc_logs() {
+        let logs: Vec<Log> =
+            serde_json::from_str(include_str!("../../tests/golden/contract_logs.json")).unwrap();
+        let reward: ContractAddress = "0x0496d0817bd8519e0de4894dc379d35c35275609"
+            .parse()
+            .unwrap();
+        let mine: ContractAddress = "0x6176aa095c47a7f79dee2ea473b77ebf50035421"
+            .parse()
+            .unwrap();
+        let subscriptions: [EventSubscription; 2] = [
+            "DistributeReward@0x0496d0817bd8519e0de4894dc379d35c35275609"
+                .parse()
+                .unwrap(),
+            "NewSubmission@0x6176aa095c47a7f79dee2ea473b77ebf50035421"
+                .parse()
+                .unwrap(),
+        ];
+
+        let decoded: Vec<ContractLog> = logs
+            .iter()
+            .map(|log| ContractLog::decode(&subscriptions, log).unwrap().unwrap())
+            .collect();
+        let tx_hash: H256 = "0x16a30a01fd1f2808ef48aed5b439c6d42dc880ed261ea72e0261b7436f5db132"
+            .parse()
+            .unwrap();
+        let miner_id: H256 = "0x43df74be7858da13bb08c1289fe488b9843c555538dd1638203725b18a19863e"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            decoded,
+            vec![
+                ContractLog {
+                    event: ContractEvent::DistributeReward {
+                        contract: reward,
+                        pricing_index: 12.into(),
+                        beneficiary: "0x6df6e1f5a9ee46ae5dec5f23e7dbfd3afd2f1b1a"
+                            .parse()
+                            .unwrap(),
+                        amount: 1_000_000_000_000_000u64.into(),
+                    },
+                    block_number: 28614850,
+                    log_index: 3,
+                    tx_hash,
+                },
+                ContractLog {
+                    event: ContractEvent::NewSubmission {
+                        contract: mine,
+                        epoch: 500,
+                        miner_id,
+                        epoch_index: 2.into(),
+                        recall_position: 4096.into(),
+                    },
+                    block_number: 28614850,
+                    log_index: 4,
+                    tx_hash,
+                },
+            ]
+        );
+
+        // Subscribed on the other contract.
+        assert!(ContractLog::decode(&subscriptions[1..], &logs[0]).is_none());
+    }
+
+    #[test]
+    fn test_subscribe_events() {
+        let flow = ContractAddress::from_low_u64_be(1);
+        let submit = SubmitFilter::signature();
+        let filter = Filter::new().topic0(submit);
+        assert_eq!(
+            subscribe_events(filter.clone(), flow, &[]).address,
+            Some(ValueOrArray::Value(flow))
+        );
+
+        let reward = ContractAddress::from_low_u64_be(2);
+        let subscriptions = [EventSubscription {
+            kind: ContractEventKind::DistributeReward,
+            contract: reward,
+        }];
+        let filter = subscribe_events(filter, flow, &subscriptions);
+        assert_eq!(
+            filter.address,
+            Some(ValueOrArray::Array(vec![flow, reward]))
+        );
+        assert_eq!(
+            filter.topics[0],
+            Some(ValueOrArray::Array(vec![
+                Some(submit),
+                Some(DistributeRewardFilter::signature())
+            ]))
+        );
+    }
+}
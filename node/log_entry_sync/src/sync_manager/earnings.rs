@@ -0,0 +1,302 @@
+use crate::sync_manager::contract_event::ContractEvent;
+use crate::LogSyncEvent;
+use ethers::types::{Address, U256};
+use jsonrpsee::tracing::warn;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, RwLock};
+use task_executor::TaskExecutor;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Rewards distributed to a beneficiary.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Earnings {
+    pub beneficiary: Address,
+    pub amount: U256,
+    /// Number of `DistributeReward` events.
+    pub rewards: u64,
+    pub last_block_number: u64,
+}
+
+/// Maximum number of reward logs kept by position, which could be delivered again or reverted
+/// on reorg. Older ones are settled into the earnings of beneficiaries.
+const MAX_RECENT_REWARDS: usize = 4096;
+/// Maximum number of beneficiaries with settled earnings, and the least recently rewarded ones
+/// are dropped.
+const MAX_BENEFICIARIES: usize = 1024;
+
+/// Accumulates rewards of the subscribed `DistributeReward` events.
+///
+/// Rewards are kept in memory, so only the events synced since the node started are counted.
+#[derive(Clone)]
+pub struct EarningsTracker {
+    rewards: Arc<RwLock<Rewards>>,
+}
+
+impl Default for EarningsTracker {
+    fn default() -> Self {
+        Self::with_capacity(MAX_RECENT_REWARDS, MAX_BENEFICIARIES)
+    }
+}
+
+struct Rewards {
+    /// Recent rewards by the position of logs.
+    recent: BTreeMap<(u64, u64), (Address, U256)>,
+    max_recent: usize,
+    /// Earnings of the rewards settled from `recent`.
+    settled: HashMap<Address, Earnings>,
+    /// Beneficiaries of `settled` by the last rewarded block.
+    settled_by_block: BTreeSet<(u64, Address)>,
+    max_beneficiaries: usize,
+    /// Position of the last settled log, and logs until it are ignored if delivered again.
+    settled_until: Option<(u64, u64)>,
+}
+
+impl Rewards {
+    fn insert(&mut self, position: (u64, u64), beneficiary: Address, amount: U256) {
+        if matches!(self.settled_until, Some(settled) if position <= settled) {
+            return;
+        }
+
+        // Logs delivered again just overwrite the same entry.
+        self.recent.insert(position, (beneficiary, amount));
+        while self.recent.len() > self.max_recent {
+            let (position, (beneficiary, amount)) =
+                self.recent.pop_first().expect("rewards not empty");
+            self.settle(position, beneficiary, amount);
+        }
+    }
+
+    fn settle(&mut self, position: (u64, u64), beneficiary: Address, amount: U256) {
+        self.settled_until = Some(position);
+        let earnings = self.settled.entry(beneficiary).or_insert_with(|| Earnings {
+            beneficiary,
+            ..Default::default()
+        });
+        self.settled_by_block
+            .remove(&(earnings.last_block_number, beneficiary));
+        earnings.add(position.0, amount);
+        self.settled_by_block
+            .insert((earnings.last_block_number, beneficiary));
+
+        if self.settled.len() > self.max_beneficiaries {
+            if let Some((_, dropped)) = self.settled_by_block.pop_first() {
+                self.settled.remove(&dropped);
+            }
+        }
+    }
+
+    fn revert(&mut self, block_number: u64) {
+        if matches!(self.settled_until, Some((settled, _)) if settled >= block_number) {
+            warn!(
+                %block_number,
+                "settled rewards reverted, which are still counted in earnings"
+            );
+        }
+        self.recent.split_off(&(block_number, 0));
+    }
+}
+
+impl Earnings {
+    fn add(&mut self, block_number: u64, amount: U256) {
+        self.amount = self.amount.saturating_add(amount);
+        self.rewards += 1;
+        self.last_block_number = self.last_block_number.max(block_number);
+    }
+}
+
+impl EarningsTracker {
+    fn with_capacity(max_recent: usize, max_beneficiaries: usize) -> Self {
+        EarningsTracker {
+            rewards: Arc::new(RwLock::new(Rewards {
+                recent: BTreeMap::new(),
+                max_recent,
+                settled: HashMap::new(),
+                settled_by_block: BTreeSet::new(),
+                max_beneficiaries,
+                settled_until: None,
+            })),
+        }
+    }
+
+    pub fn spawn(
+        executor: &TaskExecutor,
+        mut event_recv: broadcast::Receiver<LogSyncEvent>,
+    ) -> Self {
+        let tracker = EarningsTracker::default();
+        let tracker_cloned = tracker.clone();
+        executor.spawn(
+            async move {
+                loop {
+                    match event_recv.recv().await {
+                        Ok(event) => tracker_cloned.on_event(&event),
+                        Err(RecvError::Lagged(n)) => {
+                            warn!("earnings tracker lagged behind log sync by {} events", n)
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            },
+            "log_sync_earnings_tracker",
+        );
+        tracker
+    }
+
+    fn on_event(&self, event: &LogSyncEvent) {
+        match event {
+            LogSyncEvent::ContractEventSynced { log } => {
+                if let ContractEvent::DistributeReward {
+                    beneficiary,
+                    amount,
+                    ..
+                } = log.event
+                {
+                    self.rewards.write().expect("lock poisoned").insert(
+                        (log.block_number, log.log_index),
+                        beneficiary,
+                        amount,
+                    );
+                }
+            }
+            LogSyncEvent::BlocksReverted { block_number } => {
+                self.rewards
+                    .write()
+                    .expect("lock poisoned")
+                    .revert(*block_number);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the total rewards of each beneficiary.
+    pub fn earnings(&self) -> Vec<Earnings> {
+        let rewards = self.rewards.read().expect("lock poisoned");
+        let mut earnings: BTreeMap<Address, Earnings> = rewards
+            .settled
+            .iter()
+            .map(|(beneficiary, e)| (*beneficiary, e.clone()))
+            .collect();
+        for ((block_number, _), (beneficiary, amount)) in rewards.recent.iter() {
+            earnings
+                .entry(*beneficiary)
+                .or_insert_with(|| Earnings {
+                    beneficiary: *beneficiary,
+                    ..Default::default()
+                })
+                .add(*block_number, *amount);
+        }
+        earnings.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync_manager::contract_event::ContractLog;
+    use crate::ContractAddress;
+
+    fn reward(beneficiary: u64, amount: u64, block_number: u64, log_index: u64) -> LogSyncEvent {
+        LogSyncEvent::ContractEventSynced {
+            log: ContractLog {
+                event: ContractEvent::DistributeReward {
+                    contract: ContractAddress::from_low_u64_be(1),
+                    pricing_index: 0.into(),
+                    beneficiary: Address::from_low_u64_be(beneficiary),
+                    amount: amount.into(),
+                },
+                block_number,
+                log_index,
+                tx_hash: Default::default(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_settle_earnings() {
+        let tracker = EarningsTracker::with_capacity(2, 2);
+        tracker.on_event(&reward(1, 100, 10, 0));
+        tracker.on_event(&reward(2, 50, 11, 0));
+        tracker.on_event(&reward(1, 200, 12, 0));
+        tracker.on_event(&reward(3, 10, 13, 0));
+        {
+            let rewards = tracker.rewards.read().unwrap();
+            assert_eq!(rewards.recent.len(), 2);
+            assert_eq!(rewards.settled_until, Some((11, 0)));
+        }
+
+        // Settled logs delivered again are not counted twice.
+        tracker.on_event(&reward(1, 100, 10, 0));
+        let amounts = |tracker: &EarningsTracker| -> Vec<(u64, u64, u64)> {
+            tracker
+                .earnings()
+                .into_iter()
+                .map(|e| (e.amount.as_u64(), e.rewards, e.last_block_number))
+                .collect()
+        };
+        assert_eq!(
+            amounts(&tracker),
+            vec![(300, 2, 12), (50, 1, 11), (10, 1, 13)]
+        );
+
+        // The least recently rewarded beneficiary is dropped once settled beyond capacity.
+        tracker.on_event(&reward(3, 20, 14, 0));
+        tracker.on_event(&reward(4, 30, 15, 0));
+        assert_eq!(tracker.rewards.read().unwrap().settled.len(), 2);
+        assert_eq!(
+            amounts(&tracker),
+            vec![(300, 2, 12), (30, 2, 14), (30, 1, 15)]
+        );
+
+        // Only the recent rewards are reverted.
+        tracker.on_event(&LogSyncEvent::BlocksReverted { block_number: 12 });
+        assert_eq!(amounts(&tracker), vec![(300, 2, 12), (10, 1, 13)]);
+    }
+
+    #[test]
+    fn test_track_earnings() {
+        let tracker = EarningsTracker::default();
+        tracker.on_event(&reward(1, 100, 10, 0));
+        tracker.on_event(&reward(2, 50, 10, 1));
+        tracker.on_event(&reward(1, 200, 12, 0));
+        // Delivered again.
+        tracker.on_event(&reward(1, 200, 12, 0));
+
+        assert_eq!(
+            tracker.earnings(),
+            vec![
+                Earnings {
+                    beneficiary: Address::from_low_u64_be(1),
+                    amount: 300.into(),
+                    rewards: 2,
+                    last_block_number: 12,
+                },
+                Earnings {
+                    beneficiary: Address::from_low_u64_be(2),
+                    amount: 50.into(),
+                    rewards: 1,
+                    last_block_number: 10,
+                },
+            ]
+        );
+
+        // The reward in block 12 is reorged.
+        tracker.on_event(&LogSyncEvent::BlocksReverted { block_number: 11 });
+        tracker.on_event(&reward(2, 70, 11, 0));
+        assert_eq!(
+            tracker.earnings(),
+            vec![
+                Earnings {
+                    beneficiary: Address::from_low_u64_be(1),
+                    amount: 100.into(),
+                    rewards: 1,
+                    last_block_number: 10,
+                },
+                Earnings {
+                    beneficiary: Address::from_low_u64_be(2),
+                    amount: 120.into(),
+                    rewards: 2,
+                    last_block_number: 11,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,571 @@
+use async_trait::async_trait;
+use ethers::prelude::{JsonRpcClient, ProviderError, U64};
+use ethers::providers::{JsonRpcError, RpcError};
+use jsonrpsee::tracing::{debug, info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FailoverError {
+    #[error("no rpc endpoint configured")]
+    NoEndpoint,
+    #[error(transparent)]
+    Provider(#[from] ProviderError),
+}
+
+impl RpcError for FailoverError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            FailoverError::Provider(e) => e.as_error_response(),
+            FailoverError::NoEndpoint => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            FailoverError::Provider(e) => e.as_serde_error(),
+            FailoverError::NoEndpoint => None,
+        }
+    }
+}
+
+impl From<FailoverError> for ProviderError {
+    fn from(e: FailoverError) -> Self {
+        match e {
+            FailoverError::Provider(e) => e,
+            e => ProviderError::JsonRpcClientError(Box::new(e)),
+        }
+    }
+}
+
+/// Status of a RPC endpoint, which is updated by health check and failed requests.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EndpointStatus {
+    pub url: String,
+    pub active: bool,
+    pub healthy: bool,
+    /// Latency of the last succeeded health check.
+    pub latency: Option<Duration>,
+    /// Latest block number of the last succeeded health check.
+    pub head: Option<u64>,
+    /// Number of failed requests and health checks.
+    pub failures: u64,
+}
+
+/// Creates the client of an endpoint by url.
+type Connector<C> = Box<dyn Fn(&str) -> anyhow::Result<C> + Send + Sync>;
+
+struct Inner<C> {
+    clients: Vec<RwLock<Arc<C>>>,
+    status: RwLock<Vec<EndpointStatus>>,
+    active: AtomicUsize,
+    /// Maximum number of blocks that the active endpoint is allowed to fall behind others.
+    max_head_lag: u64,
+    connector: Option<Connector<C>>,
+}
+
+/// JSON-RPC client over multiple endpoints, which sends requests to the active endpoint and
+/// fails over to others automatically on errors.
+///
+/// The active endpoint is only switched when it fails or its head falls behind others by more
+/// than `max_head_lag` blocks, so that queries in a row are generally served by the same one.
+/// Error responses of JSON-RPC are returned to the caller directly without failover.
+pub struct FailoverClient<C> {
+    inner: Arc<Inner<C>>,
+}
+
+impl<C> Clone for FailoverClient<C> {
+    fn clone(&self) -> Self {
+        FailoverClient {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<C> Debug for FailoverClient<C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FailoverClient")
+            .field("status", &self.status())
+            .finish()
+    }
+}
+
+impl<C: JsonRpcClient> FailoverClient<C> {
+    /// Creates a client with `(url, client)` of all endpoints, and the first one is active.
+    pub fn new(endpoints: Vec<(String, C)>, max_head_lag: u64) -> Self {
+        Self::new_inner(endpoints, max_head_lag, None)
+    }
+
+    /// Creates a client that connects to all the endpoints via `connector`, which is also used
+    /// to recreate the connection of a stalled endpoint.
+    pub fn with_connector(
+        urls: Vec<String>,
+        max_head_lag: u64,
+        connector: impl Fn(&str) -> anyhow::Result<C> + Send + Sync + 'static,
+    ) -> anyhow::Result<Self> {
+        let mut endpoints = Vec::with_capacity(urls.len());
+        for url in urls {
+            let client = connector(&url)?;
+            endpoints.push((url, client));
+        }
+        Ok(Self::new_inner(
+            endpoints,
+            max_head_lag,
+            Some(Box::new(connector)),
+        ))
+    }
+
+    fn new_inner(
+        endpoints: Vec<(String, C)>,
+        max_head_lag: u64,
+        connector: Option<Connector<C>>,
+    ) -> Self {
+        let (status, clients) = endpoints
+            .into_iter()
+            .enumerate()
+            .map(|(index, (url, client))| {
+                let status = EndpointStatus {
+                    url,
+                    active: index == 0,
+                    healthy: true,
+                    ..Default::default()
+                };
+                (status, RwLock::new(Arc::new(client)))
+            })
+            .unzip();
+
+        FailoverClient {
+            inner: Arc::new(Inner {
+                clients,
+                status: RwLock::new(status),
+                active: AtomicUsize::new(0),
+                max_head_lag,
+                connector,
+            }),
+        }
+    }
+
+    pub fn status(&self) -> Vec<EndpointStatus> {
+        self.inner.status.read().expect("lock poisoned").clone()
+    }
+
+    /// Returns the clients of single endpoints in the order that requests are sent to, e.g. to
+    /// cross-check the results of different endpoints.
+    pub fn endpoints(&self) -> Vec<EndpointClient<C>> {
+        self.candidates()
+            .into_iter()
+            .map(|index| EndpointClient {
+                pool: self.clone(),
+                index,
+                client: self.client(index),
+            })
+            .collect()
+    }
+
+    pub fn active_endpoint(&self) -> Option<String> {
+        self.status()
+            .into_iter()
+            .find(|status| status.active)
+            .map(|status| status.url)
+    }
+
+    /// Checks the latency and head of all endpoints periodically, and switches to the best
+    /// endpoint if the active one is unhealthy or falls behind.
+    pub async fn run_health_check(self, interval: Duration) {
+        info!(
+            endpoints = self.inner.clients.len(),
+            "Start to check rpc endpoints"
+        );
+
+        loop {
+            tokio::time::sleep(interval).await;
+            self.check_health(interval).await;
+        }
+    }
+
+    pub async fn check_health(&self, timeout: Duration) {
+        for index in 0..self.inner.clients.len() {
+            let client = self.client(index);
+            let start = Instant::now();
+            let result =
+                tokio::time::timeout(timeout, client.request::<_, U64>("eth_blockNumber", ()))
+                    .await;
+            let latency = start.elapsed();
+
+            match result {
+                Ok(Ok(head)) => self.on_health_checked(index, latency, head.as_u64()),
+                Ok(Err(e)) => {
+                    let e: ProviderError = e.into();
+                    self.on_failure(index, &format!("{:?}", e))
+                }
+                Err(_) => self.on_failure(index, "health check timeout"),
+            }
+        }
+
+        self.select_best();
+    }
+
+    fn client(&self, index: usize) -> Arc<C> {
+        self.inner.clients[index]
+            .read()
+            .expect("lock poisoned")
+            .clone()
+    }
+
+    /// Handles the active endpoint that stops delivering new blocks silently, which fails over
+    /// to the best usable endpoint if any, and recreates the connection of the stalled one.
+    pub fn recover_stalled(&self) {
+        let active = self.inner.active.load(Ordering::SeqCst);
+        if self.inner.clients.is_empty() {
+            return;
+        }
+
+        self.on_failure(active, "no progress");
+        self.select_best();
+
+        if let Some(connector) = &self.inner.connector {
+            let url = self.status()[active].url.clone();
+            match connector(&url) {
+                Ok(client) => {
+                    *self.inner.clients[active].write().expect("lock poisoned") = Arc::new(client);
+                    info!(%url, "Rpc endpoint reconnected");
+                }
+                Err(e) => warn!(%url, "Failed to reconnect rpc endpoint: {:?}", e),
+            }
+        }
+    }
+
+    fn on_health_checked(&self, index: usize, latency: Duration, head: u64) {
+        let mut status = self.inner.status.write().expect("lock poisoned");
+        let status = &mut status[index];
+        if !status.healthy {
+            info!(url = %status.url, %head, "Rpc endpoint recovered");
+        }
+        status.healthy = true;
+        status.latency = Some(latency);
+        status.head = Some(head);
+        debug!(url = %status.url, ?latency, %head, "Rpc endpoint health checked");
+    }
+
+    fn on_failure(&self, index: usize, err: &str) {
+        let mut status = self.inner.status.write().expect("lock poisoned");
+        let status = &mut status[index];
+        if status.healthy {
+            warn!(url = %status.url, %err, "Rpc endpoint became unhealthy");
+        }
+        status.healthy = false;
+        status.failures += 1;
+    }
+
+    /// Returns the endpoint indices in the order to try, starting with the active one and
+    /// followed by the healthy ones with the lowest latency.
+    fn candidates(&self) -> Vec<usize> {
+        let active = self.inner.active.load(Ordering::SeqCst);
+        let status = self.inner.status.read().expect("lock poisoned");
+        if status.is_empty() {
+            return vec![];
+        }
+        let max_head = max_head(&status);
+
+        let mut candidates: Vec<usize> = (0..status.len()).filter(|i| *i != active).collect();
+        candidates.sort_by_key(|i| {
+            let usable = is_usable(&status[*i], max_head, self.inner.max_head_lag);
+            (!usable, status[*i].latency.unwrap_or(Duration::MAX))
+        });
+        candidates.insert(0, active);
+        candidates
+    }
+
+    /// Switches to the usable endpoint with the lowest latency if the active endpoint is
+    /// unhealthy or falls behind.
+    fn select_best(&self) {
+        let active = self.inner.active.load(Ordering::SeqCst);
+        let best = {
+            let status = self.inner.status.read().expect("lock poisoned");
+            let max_head = max_head(&status);
+            if status.is_empty() || is_usable(&status[active], max_head, self.inner.max_head_lag) {
+                return;
+            }
+
+            status
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| is_usable(s, max_head, self.inner.max_head_lag))
+                .min_by_key(|(_, s)| s.latency.unwrap_or(Duration::MAX))
+                .map(|(index, _)| index)
+        };
+
+        match best {
+            Some(index) => self.switch_to(index, "active endpoint unhealthy or behind"),
+            None => warn!("No usable rpc endpoint, keep the active one"),
+        }
+    }
+
+    fn switch_to(&self, index: usize, reason: &str) {
+        let prev = self.inner.active.swap(index, Ordering::SeqCst);
+        if prev == index {
+            return;
+        }
+
+        let mut status = self.inner.status.write().expect("lock poisoned");
+        status[prev].active = false;
+        status[index].active = true;
+        warn!(
+            from = %status[prev].url,
+            to = %status[index].url,
+            %reason,
+            "Rpc endpoint switched"
+        );
+    }
+}
+
+fn max_head(status: &[EndpointStatus]) -> Option<u64> {
+    status
+        .iter()
+        .filter(|s| s.healthy)
+        .filter_map(|s| s.head)
+        .max()
+}
+
+fn is_usable(status: &EndpointStatus, max_head: Option<u64>, max_head_lag: u64) -> bool {
+    match (status.head, max_head) {
+        (Some(head), Some(max_head)) => status.healthy && head + max_head_lag >= max_head,
+        // Not health checked yet.
+        _ => status.healthy,
+    }
+}
+
+#[async_trait]
+impl<C: JsonRpcClient> JsonRpcClient for FailoverClient<C> {
+    type Error = FailoverError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        // Params are encoded in advance so that the request could be sent again.
+        let params = serde_json::to_value(params).map_err(ProviderError::from)?;
+        let mut last_err = FailoverError::NoEndpoint;
+
+        for index in self.candidates() {
+            match self.client(index).request(method, params.clone()).await {
+                Ok(result) => {
+                    self.switch_to(index, "request failed on active endpoint");
+                    return Ok(result);
+                }
+                Err(e) => {
+                    let e: ProviderError = e.into();
+                    if e.as_error_response().is_some() || e.as_serde_error().is_some() {
+                        return Err(e.into());
+                    }
+                    self.on_failure(index, &format!("{}: {:?}", method, e));
+                    last_err = e.into();
+                }
+            }
+        }
+
+        Err(last_err)
+    }
+}
+
+/// Client of a single endpoint of [`FailoverClient`], which never fails over to others, but
+/// failed requests are still recorded in the endpoint status.
+pub struct EndpointClient<C> {
+    pool: FailoverClient<C>,
+    index: usize,
+    client: Arc<C>,
+}
+
+impl<C> Clone for EndpointClient<C> {
+    fn clone(&self) -> Self {
+        EndpointClient {
+            pool: self.pool.clone(),
+            index: self.index,
+            client: self.client.clone(),
+        }
+    }
+}
+
+impl<C> Debug for EndpointClient<C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EndpointClient")
+            .field("url", &self.url())
+            .finish()
+    }
+}
+
+impl<C> EndpointClient<C> {
+    pub fn url(&self) -> String {
+        self.pool.inner.status.read().expect("lock poisoned")[self.index]
+            .url
+            .clone()
+    }
+}
+
+#[async_trait]
+impl<C: JsonRpcClient> JsonRpcClient for EndpointClient<C> {
+    type Error = FailoverError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self.client.request(method, params).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                let e: ProviderError = e.into();
+                if e.as_error_response().is_none() && e.as_serde_error().is_none() {
+                    self.pool
+                        .on_failure(self.index, &format!("{}: {:?}", method, e));
+                }
+                Err(e.into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::prelude::{Middleware, Provider};
+    use ethers::providers::MockProvider;
+
+    fn new_client(max_head_lag: u64) -> (FailoverClient<MockProvider>, Vec<MockProvider>) {
+        let mocks = vec![MockProvider::new(), MockProvider::new()];
+        let endpoints = mocks
+            .iter()
+            .enumerate()
+            .map(|(i, mock)| (format!("http://node{}", i), mock.clone()))
+            .collect();
+        (FailoverClient::new(endpoints, max_head_lag), mocks)
+    }
+
+    fn push_head(mock: &MockProvider, head: u64) {
+        mock.push::<U64, _>(U64::from(head)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failover_on_error() {
+        let (client, mocks) = new_client(10);
+        let provider = Provider::new(client.clone());
+
+        push_head(&mocks[0], 100);
+        assert_eq!(provider.get_block_number().await.unwrap(), 100.into());
+        assert_eq!(client.active_endpoint().unwrap(), "http://node0");
+
+        // The active endpoint starts failing.
+        push_head(&mocks[1], 101);
+        assert_eq!(provider.get_block_number().await.unwrap(), 101.into());
+        assert_eq!(client.active_endpoint().unwrap(), "http://node1");
+        let status = client.status();
+        assert!(!status[0].healthy);
+        assert_eq!(status[0].failures, 1);
+        assert!(status[1].healthy);
+
+        // Stick to the new endpoint even if the previous one recovers.
+        push_head(&mocks[0], 102);
+        push_head(&mocks[1], 102);
+        assert_eq!(provider.get_block_number().await.unwrap(), 102.into());
+        assert_eq!(client.active_endpoint().unwrap(), "http://node1");
+
+        // Switch back once the new endpoint fails as well.
+        assert_eq!(provider.get_block_number().await.unwrap(), 102.into());
+        assert_eq!(client.active_endpoint().unwrap(), "http://node0");
+
+        // All endpoints fail.
+        assert!(provider.get_block_number().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_clients() {
+        let (client, mocks) = new_client(10);
+
+        // The active endpoint goes first.
+        push_head(&mocks[1], 100);
+        assert_eq!(
+            Provider::new(client.clone())
+                .get_block_number()
+                .await
+                .unwrap(),
+            100.into()
+        );
+        let endpoints = client.endpoints();
+        assert_eq!(endpoints.len(), 2);
+        assert_eq!(endpoints[0].url(), "http://node1");
+        assert_eq!(endpoints[1].url(), "http://node0");
+
+        // Never fails over, but the failure is recorded.
+        let provider = Provider::new(endpoints[0].clone());
+        assert!(provider.get_block_number().await.is_err());
+        assert_eq!(client.active_endpoint().unwrap(), "http://node1");
+        assert!(!client.status()[1].healthy);
+        assert_eq!(client.status()[1].failures, 1);
+
+        push_head(&mocks[0], 101);
+        let provider = Provider::new(endpoints[1].clone());
+        assert_eq!(provider.get_block_number().await.unwrap(), 101.into());
+    }
+
+    #[tokio::test]
+    async fn test_recover_stalled() {
+        let mocks = vec![MockProvider::new(), MockProvider::new()];
+        let mocks_cloned = mocks.clone();
+        let client = FailoverClient::with_connector(
+            vec!["http://node0".into(), "http://node1".into()],
+            10,
+            move |url| {
+                let index: usize = url.trim_start_matches("http://node").parse()?;
+                Ok(mocks_cloned[index].clone())
+            },
+        )
+        .unwrap();
+        let provider = Provider::new(client.clone());
+
+        // The active endpoint is frozen at the same head.
+        push_head(&mocks[0], 100);
+        assert_eq!(provider.get_block_number().await.unwrap(), 100.into());
+        client.recover_stalled();
+        assert_eq!(client.active_endpoint().unwrap(), "http://node1");
+        let status = client.status();
+        assert!(!status[0].healthy);
+        assert_eq!(status[0].failures, 1);
+
+        push_head(&mocks[1], 110);
+        assert_eq!(provider.get_block_number().await.unwrap(), 110.into());
+
+        // Keep the only usable endpoint, but reconnect it.
+        client.recover_stalled();
+        assert_eq!(client.active_endpoint().unwrap(), "http://node1");
+        push_head(&mocks[1], 111);
+        assert_eq!(provider.get_block_number().await.unwrap(), 111.into());
+    }
+
+    #[tokio::test]
+    async fn test_failover_on_head_lag() {
+        let (client, mocks) = new_client(10);
+
+        push_head(&mocks[0], 100);
+        push_head(&mocks[1], 110);
+        client.check_health(Duration::from_secs(1)).await;
+        assert_eq!(client.active_endpoint().unwrap(), "http://node0");
+
+        push_head(&mocks[0], 100);
+        push_head(&mocks[1], 111);
+        client.check_health(Duration::from_secs(1)).await;
+        assert_eq!(client.active_endpoint().unwrap(), "http://node1");
+        assert_eq!(client.status()[0].head, Some(100));
+        assert_eq!(client.status()[1].head, Some(111));
+
+        // The failed endpoint is not used even if its head is the highest.
+        push_head(&mocks[0], 200);
+        client.check_health(Duration::from_secs(1)).await;
+        assert_eq!(client.active_endpoint().unwrap(), "http://node0");
+        assert!(!client.status()[1].healthy);
+    }
+}
@@ -0,0 +1,156 @@
+use ethereum_types::Address;
+use shared_types::{DataRoot, Transaction};
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// Policy of the files to store, which is checked once the tx is synced from the chain. The tx
+/// rejected still takes its range in the flow, but the file is never synced, announced or
+/// served by the node.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IngestPolicy {
+    /// Maximum file size in bytes, or unlimited if `None`.
+    pub max_size_bytes: Option<u64>,
+    /// Only the files submitted by these senders are stored, unless empty.
+    pub allowed_senders: HashSet<Address>,
+    pub denied_senders: HashSet<Address>,
+    pub denied_roots: HashSet<DataRoot>,
+}
+
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum PolicyViolation {
+    #[error("file size {size} exceeds the max {max}")]
+    TooLarge { size: u64, max: u64 },
+    /// The sender is unknown for the logs replayed from file.
+    #[error("sender {0:?} not allowed")]
+    SenderNotAllowed(Option<Address>),
+    #[error("sender {0:?} denied")]
+    SenderDenied(Address),
+    #[error("data root {0:?} denied")]
+    RootDenied(DataRoot),
+}
+
+impl IngestPolicy {
+    /// Checks the tx submitted by `sender`, which is `None` if unknown, e.g. replayed from file.
+    pub fn check(&self, tx: &Transaction, sender: Option<Address>) -> Result<(), PolicyViolation> {
+        if self.denied_roots.contains(&tx.data_merkle_root) {
+            return Err(PolicyViolation::RootDenied(tx.data_merkle_root));
+        }
+
+        if let Some(sender) = sender {
+            if self.denied_senders.contains(&sender) {
+                return Err(PolicyViolation::SenderDenied(sender));
+            }
+        }
+        if !self.allowed_senders.is_empty()
+            && !sender.map_or(false, |sender| self.allowed_senders.contains(&sender))
+        {
+            return Err(PolicyViolation::SenderNotAllowed(sender));
+        }
+
+        match self.max_size_bytes {
+            Some(max) if tx.size > max => Err(PolicyViolation::TooLarge { size: tx.size, max }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum_types::H256;
+    use shared_types::TransactionBuilder;
+
+    fn new_tx(size: u64, root: DataRoot) -> Transaction {
+        TransactionBuilder::new(0)
+            .size(size)
+            .data_merkle_root(root)
+            .merkle_nodes(vec![(1, root)])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_default_policy() {
+        let tx = new_tx(256, H256::repeat_byte(1));
+        assert_eq!(IngestPolicy::default().check(&tx, None), Ok(()));
+        assert_eq!(
+            IngestPolicy::default().check(&tx, Some(Address::repeat_byte(1))),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_max_size() {
+        let policy = IngestPolicy {
+            max_size_bytes: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.check(&new_tx(100, H256::repeat_byte(1)), None),
+            Ok(())
+        );
+        assert_eq!(
+            policy.check(&new_tx(101, H256::repeat_byte(1)), None),
+            Err(PolicyViolation::TooLarge {
+                size: 101,
+                max: 100
+            })
+        );
+    }
+
+    #[test]
+    fn test_sender_lists() {
+        let allowed = Address::repeat_byte(1);
+        let denied = Address::repeat_byte(2);
+        let tx = new_tx(256, H256::repeat_byte(1));
+
+        let policy = IngestPolicy {
+            denied_senders: [denied].into(),
+            ..Default::default()
+        };
+        assert_eq!(policy.check(&tx, Some(allowed)), Ok(()));
+        assert_eq!(policy.check(&tx, None), Ok(()));
+        assert_eq!(
+            policy.check(&tx, Some(denied)),
+            Err(PolicyViolation::SenderDenied(denied))
+        );
+
+        // unknown sender is never allowed by the allowlist
+        let policy = IngestPolicy {
+            allowed_senders: [allowed, denied].into(),
+            denied_senders: [denied].into(),
+            ..Default::default()
+        };
+        assert_eq!(policy.check(&tx, Some(allowed)), Ok(()));
+        assert_eq!(
+            policy.check(&tx, Some(denied)),
+            Err(PolicyViolation::SenderDenied(denied))
+        );
+        let other = Address::repeat_byte(3);
+        assert_eq!(
+            policy.check(&tx, Some(other)),
+            Err(PolicyViolation::SenderNotAllowed(Some(other)))
+        );
+        assert_eq!(
+            policy.check(&tx, None),
+            Err(PolicyViolation::SenderNotAllowed(None))
+        );
+    }
+
+    #[test]
+    fn test_denied_roots() {
+        let root = H256::repeat_byte(1);
+        let policy = IngestPolicy {
+            denied_roots: [root].into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.check(&new_tx(256, root), Some(Address::repeat_byte(1))),
+            Err(PolicyViolation::RootDenied(root))
+        );
+        assert_eq!(
+            policy.check(&new_tx(256, H256::repeat_byte(2)), None),
+            Ok(())
+        );
+    }
+}
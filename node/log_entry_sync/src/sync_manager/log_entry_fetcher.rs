@@ -1,17 +1,24 @@
-use crate::sync_manager::log_query::LogQuery;
-use crate::sync_manager::{metrics, RETRY_WAIT_MS};
-use crate::{ContractAddress, LogSyncConfig};
-use anyhow::{anyhow, bail, Result};
+use crate::sync_manager::config::ConfirmationPolicy;
+use crate::sync_manager::contract_event::{subscribe_events, ContractLog, EventSubscription};
+use crate::sync_manager::failover_client::FailoverClient;
+use crate::sync_manager::log_query::{parallel_log_query, retry_backoff, LogQuery};
+use crate::sync_manager::replay::start_replay;
+use crate::sync_manager::LogSyncMonitor;
+use crate::sync_manager::{metrics, MAX_REFETCH_ATTEMPTS, RETRY_WAIT_MS};
+use crate::{ContractAddress, LogSyncConfig, RpcClient};
+use anyhow::{anyhow, bail, Context, Result};
 use append_merkle::{Algorithm, Sha3Algorithm};
 use contract_interface::{SubmissionNode, SubmitFilter, ZgsFlow};
 use ethers::abi::RawLog;
-use ethers::prelude::{BlockNumber, EthLogDecode, Http, Middleware, Provider};
-use ethers::providers::{HttpRateLimitRetryPolicy, RetryClient, RetryClientBuilder};
-use ethers::types::{Block, Log, H256};
+use ethers::prelude::{BlockNumber, EthEvent, Http, JsonRpcClient, Middleware, Provider};
+use ethers::providers::{HttpRateLimitRetryPolicy, RetryClientBuilder};
+use ethers::types::{Address, Block, Log, H256};
 use futures::StreamExt;
 use jsonrpsee::tracing::{debug, error, info, warn};
-use shared_types::{DataRoot, Transaction};
+use shared_types::{DataRoot, Transaction, TransactionBuilder};
 use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use storage::log_store::{tx_store::BlockHashAndSubmissionIndex, Store};
@@ -21,38 +28,95 @@ use tokio::sync::{
     RwLock,
 };
 
+/// Source of the logs to sync, so that the logs of any source are handled in the same way.
+pub enum LogSource {
+    /// Fetches the events from blockchain via the rpc provider.
+    Chain,
+    /// Replays the recorded logs in JSON lines without blockchain rpc, and stops at the end.
+    Replay(Box<dyn BufRead + Send + Sync>),
+}
+
+impl LogSource {
+    /// Replays the configured `replay_file` if any, or fetches from blockchain otherwise.
+    pub fn from_config(config: &LogSyncConfig) -> Result<Self> {
+        match &config.replay_file {
+            Some(path) => {
+                let file =
+                    File::open(path).with_context(|| format!("failed to open {:?}", path))?;
+                Ok(LogSource::Replay(Box::new(BufReader::new(file))))
+            }
+            None => Ok(LogSource::Chain),
+        }
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(self, LogSource::Replay(_))
+    }
+}
+
 pub struct LogEntryFetcher {
     contract_address: ContractAddress,
+    event_subscriptions: Vec<EventSubscription>,
     log_page_size: u64,
-    provider: Arc<Provider<RetryClient<Http>>>,
+    provider: Arc<Provider<RpcClient>>,
+    source: LogSource,
 
-    confirmation_delay: u64,
+    confirmation: ConfirmationPolicy,
+    monitor: LogSyncMonitor,
+    /// Block number to sync logs from, which is also the fork point if all the stored blocks
+    /// are reorged.
+    sync_start_block_number: u64,
 }
 
 impl LogEntryFetcher {
-    pub async fn new(config: &LogSyncConfig) -> Result<Self> {
-        let provider = Arc::new(Provider::new(
-            RetryClientBuilder::default()
-                .rate_limit_retries(config.rate_limit_retries)
-                .timeout_retries(config.timeout_retries)
-                .initial_backoff(Duration::from_millis(config.initial_backoff))
-                .build(
-                    Http::new_with_client(
-                        url::Url::parse(&config.rpc_endpoint_url)?,
-                        reqwest::Client::builder()
-                            .timeout(config.blockchain_rpc_timeout)
-                            .connect_timeout(config.blockchain_rpc_timeout)
-                            .build()?,
-                    ),
-                    Box::new(HttpRateLimitRetryPolicy),
-                ),
-        ));
+    /// Creates a client over all the configured rpc endpoints, each with retries.
+    pub fn new_rpc_client(config: &LogSyncConfig) -> Result<RpcClient> {
+        if config.rpc_endpoint_urls.is_empty() {
+            bail!("no blockchain rpc endpoint configured");
+        }
+
+        let (rate_limit_retries, timeout_retries) =
+            (config.rate_limit_retries, config.timeout_retries);
+        let initial_backoff = Duration::from_millis(config.initial_backoff);
+        let timeout = config.blockchain_rpc_timeout;
+        FailoverClient::with_connector(
+            config.rpc_endpoint_urls.clone(),
+            config.rpc_max_head_lag,
+            move |url| {
+                Ok(RetryClientBuilder::default()
+                    .rate_limit_retries(rate_limit_retries)
+                    .timeout_retries(timeout_retries)
+                    .initial_backoff(initial_backoff)
+                    .build(
+                        Http::new_with_client(
+                            url::Url::parse(url)?,
+                            reqwest::Client::builder()
+                                .timeout(timeout)
+                                .connect_timeout(timeout)
+                                .build()?,
+                        ),
+                        Box::new(HttpRateLimitRetryPolicy),
+                    ))
+            },
+        )
+    }
+
+    pub async fn new(
+        config: &LogSyncConfig,
+        monitor: LogSyncMonitor,
+        source: LogSource,
+    ) -> Result<Self> {
+        let provider = Arc::new(Provider::new(monitor.rpc_client().clone()));
         // TODO: `error` types are removed from the ABI json file.
         Ok(Self {
             contract_address: config.contract_address,
+            event_subscriptions: config.event_subscriptions.clone(),
             provider,
+            source,
             log_page_size: config.log_page_size,
-            confirmation_delay: config.confirmation_block_count,
+            confirmation: config.confirmation_policy(),
+            monitor,
+            sync_start_block_number: config.start_block_number,
         })
     }
 
@@ -65,6 +129,7 @@ impl LogEntryFetcher {
     ) -> UnboundedReceiver<LogFetchProgress> {
         let (reorg_tx, reorg_rx) = tokio::sync::mpsc::unbounded_channel();
         let provider = self.provider.clone();
+        let sync_start_block_number = self.sync_start_block_number;
 
         executor.spawn(
             async move {
@@ -94,6 +159,7 @@ impl LogEntryFetcher {
                                     &reorg_tx,
                                     &block_hash_cache,
                                     provider.as_ref(),
+                                    sync_start_block_number,
                                 )
                                 .await
                                 {
@@ -181,21 +247,15 @@ impl LogEntryFetcher {
                                 ),
                                 processed_block_number,
                             );
-                            let mut pending_keys = vec![];
-                            for (key, _) in block_hash_cache.read().await.iter() {
-                                if *key < safe_block_number {
-                                    pending_keys.push(*key);
-                                } else {
-                                    break;
-                                }
-                            }
-
-                            for key in pending_keys.into_iter() {
-                                if let Err(e) = store.delete_block_hash_by_number(key) {
-                                    error!("remove block tx for number {} error: e={:?}", key, e);
-                                } else {
-                                    block_hash_cache.write().await.remove(&key);
-                                }
+                            // The blocks not loaded into cache at startup are removed as well.
+                            if let Err(e) = store.delete_block_hashes_before(safe_block_number) {
+                                error!(
+                                    "remove block tx before number {} error: e={:?}",
+                                    safe_block_number, e
+                                );
+                            } else {
+                                let mut block_hash_cache = block_hash_cache.write().await;
+                                *block_hash_cache = block_hash_cache.split_off(&safe_block_number);
                             }
                         }
                     }
@@ -216,32 +276,46 @@ impl LogEntryFetcher {
         end_block_number: u64,
         executor: &TaskExecutor,
         log_query_delay: Duration,
+        concurrency: usize,
     ) -> UnboundedReceiver<LogFetchProgress> {
         let provider = self.provider.clone();
         let (recover_tx, recover_rx) = tokio::sync::mpsc::unbounded_channel();
         let contract = self.flow_contract();
+        let subscriptions = self.event_subscriptions.clone();
         let log_page_size = self.log_page_size;
 
         executor.spawn(
             async move {
                 let mut progress = start_block_number;
-                let mut filter = contract
-                    .submit_filter()
-                    .from_block(progress)
-                    .to_block(end_block_number)
-                    .address(contract.address().into())
-                    .filter;
-                let mut stream = LogQuery::new(&provider, &filter, log_query_delay)
-                    .with_page_size(log_page_size);
+                let filter = subscribe_events(
+                    contract.submit_filter().to_block(end_block_number).filter,
+                    contract.address(),
+                    &subscriptions,
+                );
+                let new_stream = |from_block: u64| {
+                    parallel_log_query(
+                        &provider,
+                        &filter,
+                        from_block,
+                        end_block_number,
+                        log_page_size,
+                        concurrency,
+                        log_query_delay,
+                    )
+                    .boxed()
+                };
+                let mut stream = new_stream(progress);
                 info!(
-                    "start_recover starts, start={} end={}",
-                    start_block_number, end_block_number
+                    "start_recover starts, start={} end={} concurrency={}",
+                    start_block_number, end_block_number, concurrency
                 );
                 let (mut block_hash_sent, mut block_number_sent) = (None, None);
+                let mut retries = 0;
                 while let Some(maybe_log) = stream.next().await {
                     let start_time = Instant::now();
                     match maybe_log {
                         Ok(log) => {
+                            retries = 0;
                             let sync_progress =
                                 if log.block_hash.is_some() && log.block_number.is_some() {
                                     if block_hash_sent != log.block_hash
@@ -262,21 +336,15 @@ impl LogEntryFetcher {
                                 };
                             debug!("recover: progress={:?}", sync_progress);
 
-                            match SubmitFilter::decode_log(&RawLog {
-                                topics: log.topics,
-                                data: log.data.to_vec(),
-                            }) {
-                                Ok(event) => {
-                                    if let Err(e) = recover_tx
-                                        .send(submission_event_to_transaction(
-                                            event,
-                                            log.block_number.expect("block number exist").as_u64(),
-                                        ))
-                                        .and_then(|_| match sync_progress {
+                            let (block_hash, block_number) = (log.block_hash, log.block_number);
+                            match decode_log(log, contract.address(), &subscriptions) {
+                                Ok(decoded) => {
+                                    if let Err(e) =
+                                        recover_tx.send(decoded).and_then(|_| match sync_progress {
                                             Some(b) => {
                                                 recover_tx.send(b)?;
-                                                block_hash_sent = log.block_hash;
-                                                block_number_sent = log.block_number;
+                                                block_hash_sent = block_hash;
+                                                block_number_sent = block_number;
                                                 Ok(())
                                             }
                                             None => Ok(()),
@@ -292,11 +360,11 @@ impl LogEntryFetcher {
                             }
                         }
                         Err(e) => {
-                            error!("log query error: e={:?}", e);
-                            filter = filter.from_block(progress).address(contract.address());
-                            stream = LogQuery::new(&provider, &filter, log_query_delay)
-                                .with_page_size(log_page_size);
-                            tokio::time::sleep(Duration::from_millis(RETRY_WAIT_MS)).await;
+                            let backoff = retry_backoff(retries);
+                            retries += 1;
+                            error!("log query error, retry in {:?}: e={:?}", backoff, e);
+                            stream = new_stream(progress);
+                            tokio::time::sleep(backoff).await;
                         }
                     }
                     metrics::RECOVER_LOG.update_since(start_time);
@@ -321,8 +389,11 @@ impl LogEntryFetcher {
         let (watch_tx, watch_rx) = tokio::sync::mpsc::unbounded_channel();
         let contract = self.flow_contract();
         let provider = self.provider.clone();
-        let confirmation_delay = self.confirmation_delay;
+        let confirmation = self.confirmation;
+        let monitor = self.monitor.clone();
         let log_page_size = self.log_page_size;
+        let sync_start_block_number = self.sync_start_block_number;
+        let subscriptions = self.event_subscriptions.clone();
         let mut progress_reset_history = BTreeMap::new();
         executor.spawn(
             async move {
@@ -347,10 +418,13 @@ impl LogEntryFetcher {
                         progress,
                         parent_block_hash,
                         &watch_tx,
-                        confirmation_delay,
+                        confirmation,
+                        &monitor,
                         &contract,
                         &block_hash_cache,
                         log_page_size,
+                        sync_start_block_number,
+                        &subscriptions,
                     )
                     .await
                     {
@@ -378,22 +452,27 @@ impl LogEntryFetcher {
     }
 
     #[allow(clippy::too_many_arguments)]
-    async fn watch_loop(
-        provider: &Provider<RetryClient<Http>>,
+    async fn watch_loop<P: JsonRpcClient>(
+        provider: &Provider<P>,
         from_block_number: u64,
         parent_block_hash: H256,
         watch_tx: &UnboundedSender<LogFetchProgress>,
-        confirmation_delay: u64,
-        contract: &ZgsFlow<Provider<RetryClient<Http>>>,
+        confirmation: ConfirmationPolicy,
+        monitor: &LogSyncMonitor,
+        contract: &ZgsFlow<Provider<P>>,
         block_hash_cache: &Arc<RwLock<BTreeMap<u64, Option<BlockHashAndSubmissionIndex>>>>,
         log_page_size: u64,
+        sync_start_block_number: u64,
+        subscriptions: &[EventSubscription],
     ) -> Result<Option<(u64, H256, Option<Option<u64>>)>> {
         let latest_block_number = provider.get_block_number().await?.as_u64();
+        let to_block_number =
+            confirmed_block_number(provider, confirmation, latest_block_number).await?;
+        monitor.update_heads(latest_block_number, to_block_number);
         debug!(
-            "from block number {}, latest block number {}, confirmation delay {}",
-            from_block_number, latest_block_number, confirmation_delay
+            "from block number {}, latest block number {}, confirmed block number {}, confirmation {:?}",
+            from_block_number, latest_block_number, to_block_number, confirmation
         );
-        let to_block_number = latest_block_number.saturating_sub(confirmation_delay);
         if from_block_number > to_block_number {
             return Ok(None);
         }
@@ -422,6 +501,7 @@ impl LogEntryFetcher {
                 watch_tx,
                 block_hash_cache,
                 provider,
+                sync_start_block_number,
             )
             .await?;
             return Ok(Some((parent_block_number, block_hash, None)));
@@ -462,12 +542,15 @@ impl LogEntryFetcher {
             blocks.insert(block_number, block);
         }
 
-        let filter = contract
-            .submit_filter()
-            .from_block(from_block_number)
-            .to_block(to_block_number)
-            .address(contract.address().into())
-            .filter;
+        let filter = subscribe_events(
+            contract
+                .submit_filter()
+                .from_block(from_block_number)
+                .to_block(to_block_number)
+                .filter,
+            contract.address(),
+            subscriptions,
+        );
         let mut stream = LogQuery::new(provider, &filter, Duration::from_millis(10))
             .with_page_size(log_page_size);
         let mut block_logs: BTreeMap<u64, Vec<Log>> = BTreeMap::new();
@@ -528,10 +611,7 @@ impl LogEntryFetcher {
                             return Ok(progress);
                         }
 
-                        let submit_filter = match SubmitFilter::decode_log(&RawLog {
-                            topics: log.topics,
-                            data: log.data.to_vec(),
-                        }) {
+                        let decoded = match decode_log(log, contract.address(), subscriptions) {
                             Ok(v) => v,
                             Err(e) => {
                                 return {
@@ -541,15 +621,15 @@ impl LogEntryFetcher {
                             }
                         };
 
-                        if first_submission_index.is_none()
-                            || first_submission_index
-                                > Some(submit_filter.submission_index.as_u64())
-                        {
-                            first_submission_index = Some(submit_filter.submission_index.as_u64());
+                        if let LogFetchProgress::Transaction((tx, _, _, _)) = &decoded {
+                            if first_submission_index.is_none()
+                                || first_submission_index > Some(tx.seq)
+                            {
+                                first_submission_index = Some(tx.seq);
+                            }
                         }
 
-                        log_events
-                            .push(submission_event_to_transaction(submit_filter, block_number));
+                        log_events.push(decoded);
                     }
 
                     info!("synced {} events", log_events.len());
@@ -594,15 +674,59 @@ impl LogEntryFetcher {
         Ok(progress)
     }
 
-    pub fn provider(&self) -> &Provider<RetryClient<Http>> {
+    /// Starts to replay the recorded logs, and the channel is closed at the end. Returns `None`
+    /// if the logs are fetched from blockchain, or replayed already.
+    pub fn start_replay(
+        &mut self,
+        executor: &TaskExecutor,
+    ) -> Option<UnboundedReceiver<LogFetchProgress>> {
+        match &mut self.source {
+            LogSource::Replay(reader) => Some(start_replay(
+                std::mem::replace(reader, Box::new(std::io::empty())),
+                executor,
+            )),
+            LogSource::Chain => None,
+        }
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.source.is_replay()
+    }
+
+    pub fn monitor(&self) -> &LogSyncMonitor {
+        &self.monitor
+    }
+
+    pub fn provider(&self) -> &Provider<RpcClient> {
         self.provider.as_ref()
     }
 
-    pub fn flow_contract(&self) -> ZgsFlow<Provider<RetryClient<Http>>> {
+    pub fn flow_contract(&self) -> ZgsFlow<Provider<RpcClient>> {
         ZgsFlow::new(self.contract_address, self.provider.clone())
     }
 }
 
+/// Returns the latest stable block number under the confirmation policy.
+async fn confirmed_block_number<P: JsonRpcClient>(
+    provider: &Provider<P>,
+    confirmation: ConfirmationPolicy,
+    latest_block_number: u64,
+) -> Result<u64> {
+    match confirmation {
+        ConfirmationPolicy::Confirmations(n) => Ok(latest_block_number.saturating_sub(n)),
+        ConfirmationPolicy::Finalized => {
+            let finalized = provider
+                .get_block(BlockNumber::Finalized)
+                .await?
+                .ok_or_else(|| anyhow!("None for finalized block"))?
+                .number
+                .ok_or_else(|| anyhow!("None block number for finalized block"))?
+                .as_u64();
+            Ok(std::cmp::min(finalized, latest_block_number))
+        }
+    }
+}
+
 async fn check_watch_process(
     watch_progress_rx: &mut UnboundedReceiver<u64>,
     progress: &mut u64,
@@ -610,7 +734,7 @@ async fn check_watch_process(
     progress_reset_history: &mut BTreeMap<u64, (Instant, usize)>,
     watch_loop_wait_time_ms: u64,
     block_hash_cache: &Arc<RwLock<BTreeMap<u64, Option<BlockHashAndSubmissionIndex>>>>,
-    provider: &Provider<RetryClient<Http>>,
+    provider: &Provider<RpcClient>,
 ) {
     let mut min_received_progress = None;
     while let Ok(v) = watch_progress_rx.try_recv() {
@@ -634,7 +758,7 @@ async fn check_watch_process(
         let now = Instant::now();
         match progress_reset_history.get_mut(&v) {
             Some((last_update, counter)) => {
-                if *counter >= 3 {
+                if *counter >= MAX_REFETCH_ATTEMPTS {
                     error!("maximum reset attempts have been reached.");
                     watch_progress_rx.close();
                     return;
@@ -701,27 +825,40 @@ async fn check_watch_process(
     progress_reset_history.retain(|k, _| k + 1000 >= *progress);
 }
 
-async fn revert_one_block(
+async fn revert_one_block<P: JsonRpcClient>(
     block_hash: H256,
     block_number: u64,
     watch_tx: &UnboundedSender<LogFetchProgress>,
     block_hash_cache: &Arc<RwLock<BTreeMap<u64, Option<BlockHashAndSubmissionIndex>>>>,
-    provider: &Provider<RetryClient<Http>>,
+    provider: &Provider<P>,
+    sync_start_block_number: u64,
 ) -> Result<(u64, H256), anyhow::Error> {
     debug!("revert block {}, block hash {:?}", block_number, block_hash);
     let block = loop {
-        if let Some(block) = block_hash_cache.read().await.get(&block_number) {
-            if let Some(v) = block {
-                break v.clone();
-            } else {
+        let cached = block_hash_cache.read().await.get(&block_number).cloned();
+        match cached {
+            Some(Some(v)) => break v,
+            Some(None) => {
                 debug!(
                     "block_hash_cache wait for SyncedBlock processed for {}",
                     block_number
                 );
                 tokio::time::sleep(Duration::from_secs(RETRY_WAIT_MS)).await;
             }
-        } else {
-            return Err(anyhow!("None for block {}", block_number));
+            None => {
+                warn!(
+                    "block {} not found in block hash cache, the reorg is deeper than the stored blocks",
+                    block_number
+                );
+                return revert_deep_reorg(
+                    block_number,
+                    watch_tx,
+                    block_hash_cache,
+                    provider,
+                    sync_start_block_number,
+                )
+                .await;
+            }
         }
     };
 
@@ -729,6 +866,7 @@ async fn revert_one_block(
     if let Some(reverted) = block.first_submission_index {
         watch_tx.send(LogFetchProgress::Reverted(reverted))?;
     }
+    watch_tx.send(LogFetchProgress::RevertedBlocks(block_number))?;
 
     let parent_block_number = block_number.saturating_sub(1);
     let parent_block_hash = match block_hash_cache.read().await.get(&parent_block_number) {
@@ -751,38 +889,491 @@ async fn revert_one_block(
     Ok((parent_block_number, parent_block_hash))
 }
 
+/// Recovers from a reorg that is deeper than the stored block hashes, where `block_number` is
+/// reorged but not stored.
+///
+/// The stored blocks before `block_number` are checked against the chain in exponentially
+/// increasing steps to find the latest one that is not reorged, and all the transactions
+/// submitted after it are reverted. If all the stored blocks are reorged, all the
+/// transactions are reverted to sync again from `sync_start_block_number`.
+///
+/// Returns the fork point to sync from.
+async fn revert_deep_reorg<P: JsonRpcClient>(
+    block_number: u64,
+    watch_tx: &UnboundedSender<LogFetchProgress>,
+    block_hash_cache: &Arc<RwLock<BTreeMap<u64, Option<BlockHashAndSubmissionIndex>>>>,
+    provider: &Provider<P>,
+    sync_start_block_number: u64,
+) -> Result<(u64, H256)> {
+    let stored: Vec<(u64, BlockHashAndSubmissionIndex)> = block_hash_cache
+        .read()
+        .await
+        .range(..block_number)
+        .filter_map(|(number, block)| block.clone().map(|b| (*number, b)))
+        .collect();
+
+    let fork_point = find_fork_point(provider, &stored).await?;
+    let (fork_block_number, fork_block_hash) = match fork_point {
+        Some(index) => (stored[index].0, stored[index].1.block_hash),
+        None => {
+            let number = sync_start_block_number.saturating_sub(1);
+            let hash = provider
+                .get_block(number)
+                .await?
+                .ok_or_else(|| anyhow!("None for block {}", number))?
+                .hash
+                .ok_or_else(|| anyhow!("None block hash for block {}", number))?;
+            (number, hash)
+        }
+    };
+
+    // Transactions submitted after the fork point, or all the transactions if no stored
+    // block matches.
+    let reverted = match fork_point {
+        Some(index) => stored[index + 1..]
+            .iter()
+            .find_map(|(_, block)| block.first_submission_index),
+        None => Some(0),
+    };
+    error!(
+        "deep chain reorg, revert to block {} hash {:?}, reorged blocks {}..={}, reverted from tx seq {:?}",
+        fork_block_number,
+        fork_block_hash,
+        fork_block_number + 1,
+        block_number,
+        reverted
+    );
+
+    if let Some(reverted) = reverted {
+        watch_tx.send(LogFetchProgress::Reverted(reverted))?;
+    }
+    watch_tx.send(LogFetchProgress::RevertedBlocks(fork_block_number + 1))?;
+    watch_tx.send(LogFetchProgress::SyncedBlock((
+        fork_block_number,
+        fork_block_hash,
+        None,
+    )))?;
+
+    Ok((fork_block_number, fork_block_hash))
+}
+
+/// Returns the index of the latest block in `stored` (in ascending order) that is still on the
+/// chain, or `None` if all are reorged.
+async fn find_fork_point<P: JsonRpcClient>(
+    provider: &Provider<P>,
+    stored: &[(u64, BlockHashAndSubmissionIndex)],
+) -> Result<Option<usize>> {
+    let matches = |index: usize| {
+        let (number, block) = &stored[index];
+        async move {
+            let hash = provider.get_block(*number).await?.and_then(|b| b.hash);
+            debug!(
+                "check fork point, block number={} hash={:?} stored={:?}",
+                number, hash, block.block_hash
+            );
+            Ok::<_, anyhow::Error>(hash == Some(block.block_hash))
+        }
+    };
+
+    // Walk back from the latest stored block, doubling the step each time.
+    let (mut reorged, mut found) = (stored.len(), None);
+    let mut step = 1;
+    while reorged > 0 {
+        let index = reorged.saturating_sub(step);
+        if matches(index).await? {
+            found = Some(index);
+            break;
+        }
+        reorged = index;
+        step *= 2;
+    }
+
+    // Blocks in `(found, reorged)` are unknown, so binary search the latest matched one.
+    let mut found = match found {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    while found + 1 < reorged {
+        let mid = (found + reorged) / 2;
+        if matches(mid).await? {
+            found = mid;
+        } else {
+            reorged = mid;
+        }
+    }
+
+    Ok(Some(found))
+}
+
 #[derive(Debug)]
 pub enum LogFetchProgress {
     SyncedBlock((u64, H256, Option<Option<u64>>)),
-    Transaction((Transaction, u64)),
+    /// The tx along with the block number, and the hash of the tx and the sender of the `Submit`
+    /// event, which are `None` for the logs replayed from file.
+    Transaction((Transaction, u64, Option<H256>, Option<Address>)),
+    /// Subscribed event of other contracts.
+    ContractLog(ContractLog),
     Reverted(u64),
+    /// All the stored block hashes from the block number are reorged.
+    RevertedBlocks(u64),
+}
+
+/// Decodes a log of the `Submit` event or any subscribed event.
+fn decode_log(
+    log: Log,
+    flow_address: ContractAddress,
+    subscriptions: &[EventSubscription],
+) -> Result<LogFetchProgress> {
+    if log.address == flow_address && log.topics.first() == Some(&SubmitFilter::signature()) {
+        let block_number = log
+            .block_number
+            .ok_or_else(|| anyhow!("block number missing"))?
+            .as_u64();
+        let tx_hash = log.transaction_hash;
+        let event = SubmitFilter::decode_log(&RawLog {
+            topics: log.topics,
+            data: log.data.to_vec(),
+        })?;
+        return submission_event_to_transaction(event, block_number, tx_hash);
+    }
+
+    match ContractLog::decode(subscriptions, &log) {
+        Some(decoded) => Ok(LogFetchProgress::ContractLog(decoded?)),
+        None => bail!("unsubscribed log {:?}", log),
+    }
 }
 
-fn submission_event_to_transaction(e: SubmitFilter, block_number: u64) -> LogFetchProgress {
-    LogFetchProgress::Transaction((
-        Transaction {
-            stream_ids: vec![],
-            data: vec![],
-            data_merkle_root: nodes_to_root(&e.submission.nodes),
-            merkle_nodes: e
-                .submission
+/// The submission accepted by the flow contract is converted as is, even if inconsistent, e.g.
+/// of a zero root. Otherwise, the tx seq would be left as a gap in the log.
+fn submission_event_to_transaction(
+    e: SubmitFilter,
+    block_number: u64,
+    tx_hash: Option<H256>,
+) -> Result<LogFetchProgress> {
+    let tx = TransactionBuilder::new(e.submission_index.as_u64())
+        .data_merkle_root(nodes_to_root(&e.submission.nodes))
+        .merkle_nodes(
+            e.submission
                 .nodes
                 .iter()
                 // the submission height is the height of the root node starting from height 0.
                 .map(|SubmissionNode { root, height }| (height.as_usize() + 1, root.into()))
                 .collect(),
-            start_entry_index: e.start_pos.as_u64(),
-            size: e.submission.length.as_u64(),
-            seq: e.submission_index.as_u64(),
-        },
+        )
+        .start_entry_index(e.start_pos.as_u64())
+        .size(e.submission.length.as_u64())
+        .build_unchecked();
+    Ok(LogFetchProgress::Transaction((
+        tx,
         block_number,
-    ))
+        tx_hash,
+        Some(e.sender),
+    )))
 }
 
+/// Returns the zero root for a submission without nodes.
 fn nodes_to_root(node_list: &[SubmissionNode]) -> DataRoot {
-    let mut root: DataRoot = node_list.last().expect("not empty").root.into();
+    let mut root: DataRoot = match node_list.last() {
+        Some(node) => node.root.into(),
+        None => return DataRoot::zero(),
+    };
     for next_node in node_list[..node_list.len() - 1].iter().rev() {
         root = Sha3Algorithm::parent(&next_node.root.into(), &root);
     }
     root
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use ethers::prelude::{ProviderError, U64};
+    use serde::{de::DeserializeOwned, Serialize};
+    use std::fmt::Debug;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// A chain that only keeps the stored blocks until `fork`, and all the blocks are reorged
+    /// if `fork` is `None`.
+    #[derive(Debug)]
+    struct MockChain {
+        state: Mutex<ChainState>,
+        requests: AtomicUsize,
+    }
+
+    #[derive(Debug)]
+    struct ChainState {
+        fork: Option<u64>,
+        head: u64,
+        finalized: u64,
+    }
+
+    impl MockChain {
+        fn new(fork: Option<u64>) -> Provider<Self> {
+            Self::with_head(fork, 200, 100)
+        }
+
+        fn with_head(fork: Option<u64>, head: u64, finalized: u64) -> Provider<Self> {
+            Provider::new(MockChain {
+                state: Mutex::new(ChainState {
+                    fork,
+                    head,
+                    finalized,
+                }),
+                requests: AtomicUsize::new(0),
+            })
+        }
+
+        fn update(&self, f: impl FnOnce(&mut ChainState)) {
+            f(&mut self.state.lock().unwrap());
+        }
+
+        fn block_hash(&self, number: u64) -> H256 {
+            match self.state.lock().unwrap().fork {
+                Some(fork) if number <= fork => stored_hash(number),
+                _ => H256::from_low_u64_be(number + 1_000_000),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl JsonRpcClient for MockChain {
+        type Error = ProviderError;
+
+        async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+        where
+            T: Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            let params = serde_json::to_value(params)?;
+            let (head, finalized) = {
+                let state = self.state.lock().unwrap();
+                (state.head, state.finalized)
+            };
+
+            let result = match method {
+                "eth_blockNumber" => serde_json::to_value(U64::from(head))?,
+                "eth_getLogs" => serde_json::to_value(Vec::<Log>::new())?,
+                "eth_getBlockByNumber" => {
+                    self.requests.fetch_add(1, Ordering::SeqCst);
+                    let number = match params[0].as_str().expect("block number required") {
+                        "finalized" => finalized,
+                        number => u64::from_str_radix(number.trim_start_matches("0x"), 16).unwrap(),
+                    };
+                    let block = Block::<H256> {
+                        number: Some(number.into()),
+                        hash: Some(self.block_hash(number)),
+                        parent_hash: self.block_hash(number.saturating_sub(1)),
+                        logs_bloom: Some(Default::default()),
+                        ..Default::default()
+                    };
+                    serde_json::to_value(block)?
+                }
+                _ => panic!("unexpected method {}", method),
+            };
+            Ok(serde_json::from_value(result)?)
+        }
+    }
+
+    fn stored_hash(number: u64) -> H256 {
+        H256::from_low_u64_be(number)
+    }
+
+    /// Stores blocks in `[100, 120]`, and each block with an even number has a submission.
+    fn stored_blocks() -> Arc<RwLock<BTreeMap<u64, Option<BlockHashAndSubmissionIndex>>>> {
+        let blocks = (100..=120)
+            .map(|number| {
+                let block = BlockHashAndSubmissionIndex {
+                    block_hash: stored_hash(number),
+                    first_submission_index: (number % 2 == 0).then_some(number - 100),
+                };
+                (number, Some(block))
+            })
+            .collect();
+        Arc::new(RwLock::new(blocks))
+    }
+
+    async fn revert(
+        provider: &Provider<MockChain>,
+        sync_start_block_number: u64,
+    ) -> ((u64, H256), Vec<LogFetchProgress>) {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let fork_point = revert_deep_reorg(
+            121,
+            &tx,
+            &stored_blocks(),
+            provider,
+            sync_start_block_number,
+        )
+        .await
+        .unwrap();
+
+        let mut progress = vec![];
+        while let Ok(p) = rx.try_recv() {
+            progress.push(p);
+        }
+        (fork_point, progress)
+    }
+
+    #[tokio::test]
+    async fn test_revert_deep_reorg() {
+        let provider = MockChain::new(Some(105));
+        let (fork_point, progress) = revert(&provider, 50).await;
+        assert_eq!(fork_point, (105, stored_hash(105)));
+        assert!(matches!(
+            progress[..],
+            [
+                LogFetchProgress::Reverted(6),
+                LogFetchProgress::RevertedBlocks(106),
+                LogFetchProgress::SyncedBlock((105, _, None)),
+            ]
+        ));
+        // Less requests than checking the stored blocks one by one.
+        assert!(provider.as_ref().requests.load(Ordering::SeqCst) <= 10);
+
+        // No submission after the fork point.
+        let provider = MockChain::new(Some(120));
+        let (fork_point, progress) = revert(&provider, 50).await;
+        assert_eq!(fork_point, (120, stored_hash(120)));
+        assert!(matches!(
+            progress[..],
+            [
+                LogFetchProgress::RevertedBlocks(121),
+                LogFetchProgress::SyncedBlock((120, _, None)),
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_revert_deep_reorg_to_genesis() {
+        let provider = MockChain::new(None);
+        let (fork_point, progress) = revert(&provider, 50).await;
+        let genesis_hash = provider.as_ref().block_hash(49);
+        assert_eq!(fork_point, (49, genesis_hash));
+        assert!(matches!(
+            progress[..],
+            [
+                LogFetchProgress::Reverted(0),
+                LogFetchProgress::RevertedBlocks(50),
+                LogFetchProgress::SyncedBlock((49, hash, None)),
+            ] if hash == genesis_hash
+        ));
+    }
+
+    fn new_monitor() -> LogSyncMonitor {
+        LogSyncMonitor::new(
+            FailoverClient::new(vec![], 0),
+            Default::default(),
+            Duration::ZERO,
+        )
+    }
+
+    /// Returns the block number synced to, and all the progress sent.
+    async fn watch(
+        provider: &Arc<Provider<MockChain>>,
+        from_block_number: u64,
+        confirmation: ConfirmationPolicy,
+        monitor: &LogSyncMonitor,
+    ) -> (Option<u64>, Vec<LogFetchProgress>) {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let contract = ZgsFlow::new(ContractAddress::zero(), provider.clone());
+        let parent_block_hash = provider.as_ref().as_ref().block_hash(from_block_number - 1);
+        let synced = LogEntryFetcher::watch_loop(
+            provider.as_ref(),
+            from_block_number,
+            parent_block_hash,
+            &tx,
+            confirmation,
+            monitor,
+            &contract,
+            &Default::default(),
+            100,
+            0,
+            &[],
+        )
+        .await
+        .unwrap()
+        .map(|(number, _, _)| number);
+
+        let mut progress = vec![];
+        while let Ok(p) = rx.try_recv() {
+            progress.push(p);
+        }
+        (synced, progress)
+    }
+
+    #[tokio::test]
+    async fn test_watch_ignore_unconfirmed_reorg() {
+        let provider = Arc::new(MockChain::with_head(Some(u64::MAX), 100, 0));
+        let chain = provider.as_ref().as_ref();
+        let monitor = new_monitor();
+        let confirmation = ConfirmationPolicy::Confirmations(5);
+
+        let (synced, _) = watch(&provider, 90, confirmation, &monitor).await;
+        assert_eq!(synced, Some(95));
+        assert_eq!(monitor.heads().latest, Some(100));
+        assert_eq!(monitor.heads().confirmed, Some(95));
+
+        // The latest 3 blocks are reorged, which are not confirmed yet.
+        chain.update(|state| state.fork = Some(97));
+        let (synced, progress) = watch(&provider, 96, confirmation, &monitor).await;
+        assert_eq!(synced, None);
+        assert!(progress.is_empty());
+
+        chain.update(|state| state.head = 101);
+        let (synced, progress) = watch(&provider, 96, confirmation, &monitor).await;
+        assert_eq!(synced, Some(96));
+        assert!(progress
+            .iter()
+            .all(|p| !matches!(p, LogFetchProgress::Reverted(_))));
+        assert_eq!(monitor.heads().confirmed, Some(96));
+    }
+
+    #[tokio::test]
+    async fn test_watch_until_finalized() {
+        let provider = Arc::new(MockChain::with_head(Some(u64::MAX), 100, 90));
+        let monitor = new_monitor();
+
+        let (synced, _) = watch(&provider, 80, ConfirmationPolicy::Finalized, &monitor).await;
+        assert_eq!(synced, Some(90));
+        assert_eq!(monitor.heads().latest, Some(100));
+        assert_eq!(monitor.heads().confirmed, Some(90));
+
+        let (synced, _) = watch(&provider, 91, ConfirmationPolicy::Finalized, &monitor).await;
+        assert_eq!(synced, None);
+    }
+
+    #[test]
+    fn test_inconsistent_submission_kept() {
+        let submission = |root: [u8; 32], length: u64| SubmitFilter {
+            sender: Address::repeat_byte(1),
+            identity: [0; 32],
+            submission_index: 7.into(),
+            start_pos: 16.into(),
+            length: 16.into(),
+            submission: contract_interface::Submission {
+                length: length.into(),
+                tags: Default::default(),
+                nodes: vec![SubmissionNode {
+                    root,
+                    height: 4.into(),
+                }],
+            },
+        };
+
+        // zero root, and the size mismatches the node
+        for e in [submission([0; 32], 256 * 16), submission([1; 32], 1)] {
+            let expected_root = DataRoot::from(e.submission.nodes[0].root);
+            match submission_event_to_transaction(e, 10, None).unwrap() {
+                LogFetchProgress::Transaction((tx, block_number, _, sender)) => {
+                    assert_eq!(tx.seq, 7);
+                    assert_eq!(tx.data_merkle_root, expected_root);
+                    assert_eq!(tx.merkle_nodes, vec![(5, expected_root)]);
+                    assert_eq!(block_number, 10);
+                    assert_eq!(sender, Some(Address::repeat_byte(1)));
+                }
+                p => panic!("unexpected progress {:?}", p),
+            }
+        }
+    }
+}
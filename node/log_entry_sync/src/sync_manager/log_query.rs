@@ -1,10 +1,18 @@
+use crate::sync_manager::RETRY_WAIT_MS;
 use ethers::prelude::{Filter, JsonRpcClient, Log, Middleware, Provider, ProviderError, U64};
+use ethers::types::FilteredParams;
+use futures::{
+    future,
+    stream::{self, StreamExt},
+};
 use futures_core::stream::Stream;
-use jsonrpsee::tracing::trace;
+use jsonrpsee::tracing::{debug, trace, warn};
 use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 use std::{
-    cmp::min,
+    cmp::{max, min},
     collections::VecDeque,
     pin::Pin,
     task::{Context, Poll},
@@ -16,6 +24,11 @@ pub(crate) type PinBoxFut<'a, T> =
 
 const TOO_MANY_LOGS_ERROR_MSG: [&str; 2] = ["exceeds the max limit of", "too large with more than"];
 
+/// The maximum number of retries of a page in a parallel log query, before the query fails.
+const MAX_PAGE_RETRIES: u32 = 5;
+/// The maximum backoff between the retries of a failed request.
+pub(crate) const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
 /// A log query provides streaming access to historical logs via a paginated
 /// request. For streaming access to future logs, use [`Middleware::watch`] or
 /// [`Middleware::subscribe_logs`]
@@ -159,12 +172,10 @@ where
                         rewake_with_new_state!(ctx, self, LogQueryState::Consume);
                     }
                     Err(err) => {
-                        for msg in TOO_MANY_LOGS_ERROR_MSG.iter() {
-                            if err.to_string().contains(msg) {
-                                self.from_block = *from_block;
-                                self.page_size /= 2;
-                                rewake_with_new_state!(ctx, self, LogQueryState::Consume);
-                            }
+                        if is_too_many_logs(&err) {
+                            self.from_block = *from_block;
+                            self.page_size /= 2;
+                            rewake_with_new_state!(ctx, self, LogQueryState::Consume);
                         }
                         Poll::Ready(Some(Err(LogQueryError::LoadLogsError(err))))
                     }
@@ -220,3 +231,318 @@ where
         }
     }
 }
+
+fn is_too_many_logs(err: &ProviderError) -> bool {
+    let err = err.to_string();
+    TOO_MANY_LOGS_ERROR_MSG.iter().any(|msg| err.contains(msg))
+}
+
+/// Queries historical logs in `[from_block, to_block]` with at most `concurrency` paginated
+/// requests in flight, and yields the logs strictly in block order.
+///
+/// The page size is shared by all requests and halved once the server cannot return all the
+/// logs of a page, so that the following pages are queried with the smaller size. The logs of a
+/// single block that the server cannot return are loaded from the block receipts instead. Failed
+/// requests are retried with backoff, and the stream yields an error and ends once a page fails
+/// [`MAX_PAGE_RETRIES`] times, so that the caller could resume from the last applied block.
+pub fn parallel_log_query<'a, P>(
+    provider: &'a Provider<P>,
+    filter: &Filter,
+    from_block: u64,
+    to_block: u64,
+    page_size: u64,
+    concurrency: usize,
+    delay: Duration,
+) -> impl Stream<Item = Result<Log, LogQueryError<ProviderError>>> + Send + 'a
+where
+    P: JsonRpcClient,
+{
+    let page_size = Arc::new(AtomicU64::new(max(page_size, 1)));
+    let filter = filter.clone();
+
+    // Pages are generated lazily, so the latest page size applies to the next page.
+    let page_size_cloned = page_size.clone();
+    let pages = stream::unfold(from_block, move |from| {
+        let page_size = page_size_cloned.load(Ordering::SeqCst);
+        async move {
+            if from > to_block {
+                return None;
+            }
+            let to = min(from.saturating_add(page_size - 1), to_block);
+            Some(((from, to), to + 1))
+        }
+    });
+
+    pages
+        .map(move |(from, to)| {
+            query_logs_in_range(provider, filter.clone(), from, to, page_size.clone(), delay)
+        })
+        // `buffered` keeps the order of pages regardless of which request completes first.
+        .buffered(max(concurrency, 1))
+        .scan(false, |failed, result| {
+            if *failed {
+                return future::ready(None);
+            }
+            let items: Vec<_> = match result {
+                Ok(logs) => logs.into_iter().map(Ok).collect(),
+                Err(e) => {
+                    *failed = true;
+                    vec![Err(e)]
+                }
+            };
+            future::ready(Some(stream::iter(items)))
+        })
+        .flatten()
+}
+
+/// Returns the backoff before the `retries`-th retry of a failed request, which doubles for
+/// each retry up to [`MAX_RETRY_BACKOFF`].
+pub(crate) fn retry_backoff(retries: u32) -> Duration {
+    Duration::from_millis(RETRY_WAIT_MS)
+        .saturating_mul(2u32.saturating_pow(retries))
+        .min(MAX_RETRY_BACKOFF)
+}
+
+async fn query_logs_in_range<P: JsonRpcClient>(
+    provider: &Provider<P>,
+    filter: Filter,
+    from_block: u64,
+    to_block: u64,
+    page_size: Arc<AtomicU64>,
+    delay: Duration,
+) -> Result<Vec<Log>, LogQueryError<ProviderError>> {
+    let mut logs = vec![];
+    let mut from = from_block;
+    let mut retries = 0;
+
+    while from <= to_block {
+        let to = min(
+            from.saturating_add(page_size.load(Ordering::SeqCst) - 1),
+            to_block,
+        );
+        tokio::time::sleep(delay).await;
+
+        let page_filter = filter.clone().from_block(from).to_block(to);
+        let result = match provider.get_logs(&page_filter).await {
+            Err(e) if is_too_many_logs(&e) && to > from => {
+                let shrunk = max((to - from + 1) / 2, 1);
+                page_size.fetch_min(shrunk, Ordering::SeqCst);
+                debug!("log_query: too many logs, shrink page size to {}", shrunk);
+                continue;
+            }
+            Err(e) if is_too_many_logs(&e) => {
+                debug!("log_query: too many logs in block {}, load receipts", from);
+                get_block_logs(provider, &filter, from).await
+            }
+            result => result,
+        };
+        match result {
+            Ok(page) => {
+                trace!("log_query: from={} to={} logs={}", from, to, page.len());
+                logs.extend(page);
+                from = to + 1;
+                retries = 0;
+            }
+            Err(e) if retries >= MAX_PAGE_RETRIES => {
+                return Err(LogQueryError::LoadLogsError(e));
+            }
+            Err(e) => {
+                let backoff = retry_backoff(retries);
+                retries += 1;
+                warn!(
+                    "log_query: failed to get logs from={} to={}, retry in {:?}, e={:?}",
+                    from, to, backoff, e
+                );
+                tokio::time::sleep(backoff).await;
+            }
+        }
+    }
+
+    Ok(logs)
+}
+
+/// Loads the logs of a block matching the filter from the block receipts, which is not limited
+/// by the number of logs as `eth_getLogs`.
+async fn get_block_logs<P: JsonRpcClient>(
+    provider: &Provider<P>,
+    filter: &Filter,
+    block_number: u64,
+) -> Result<Vec<Log>, ProviderError> {
+    let params = FilteredParams::new(Some(filter.clone()));
+    Ok(provider
+        .get_block_receipts(block_number)
+        .await?
+        .into_iter()
+        .flat_map(|receipt| receipt.logs)
+        .filter(|log| params.filter_address(log) && params.filter_topics(log))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use ethers::types::TransactionReceipt;
+    use serde::{de::DeserializeOwned, Serialize};
+    use std::fmt::Debug;
+    use std::sync::atomic::AtomicUsize;
+    use tokio::time::Instant;
+
+    /// Returns `logs_per_block` logs for each block with a fixed latency, and fails if a page has
+    /// more logs than `max_logs`, or if not `available`.
+    #[derive(Debug)]
+    struct MockClient {
+        latency: Duration,
+        max_logs: u64,
+        logs_per_block: u64,
+        available: bool,
+        requests: AtomicUsize,
+    }
+
+    impl MockClient {
+        fn new(latency: Duration, max_logs: u64) -> Self {
+            Self {
+                latency,
+                max_logs,
+                logs_per_block: 1,
+                available: true,
+                requests: AtomicUsize::new(0),
+            }
+        }
+
+        fn block_logs(&self, block_number: u64) -> Vec<Log> {
+            (0..self.logs_per_block)
+                .map(|i| Log {
+                    block_number: Some(block_number.into()),
+                    log_index: Some(i.into()),
+                    ..Default::default()
+                })
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl JsonRpcClient for MockClient {
+        type Error = ProviderError;
+
+        async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+        where
+            T: Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.latency).await;
+            if !self.available {
+                return Err(ProviderError::CustomError("unavailable".into()));
+            }
+
+            let params = serde_json::to_value(params)?;
+            let parse_number = |number: &serde_json::Value| {
+                let number = number.as_str().expect("block number required");
+                u64::from_str_radix(number.trim_start_matches("0x"), 16).unwrap()
+            };
+            let result = match method {
+                "eth_getLogs" => {
+                    let from = parse_number(&params[0]["fromBlock"]);
+                    let to = parse_number(&params[0]["toBlock"]);
+                    if (to - from + 1) * self.logs_per_block > self.max_logs {
+                        return Err(ProviderError::CustomError(format!(
+                            "query exceeds the max limit of {}",
+                            self.max_logs
+                        )));
+                    }
+                    let logs: Vec<Log> = (from..=to).flat_map(|n| self.block_logs(n)).collect();
+                    serde_json::to_value(logs)?
+                }
+                "eth_getBlockReceipts" => {
+                    let receipt = TransactionReceipt {
+                        logs: self.block_logs(parse_number(&params[0])),
+                        ..Default::default()
+                    };
+                    serde_json::to_value(vec![receipt])?
+                }
+                _ => panic!("unexpected method {}", method),
+            };
+            Ok(serde_json::from_value(result)?)
+        }
+    }
+
+    async fn query_blocks(
+        provider: &Provider<MockClient>,
+        page_size: u64,
+        concurrency: usize,
+    ) -> Result<Vec<u64>, LogQueryError<ProviderError>> {
+        parallel_log_query(
+            provider,
+            &Filter::new(),
+            10,
+            89,
+            page_size,
+            concurrency,
+            Duration::ZERO,
+        )
+        .map(|log| log.map(|log| log.block_number.unwrap().as_u64()))
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_parallel_log_query_in_order() {
+        let latency = Duration::from_millis(50);
+        let mut elapsed = vec![];
+        for concurrency in [1, 2, 4, 8] {
+            let provider = Provider::new(MockClient::new(latency, 100));
+            let start = Instant::now();
+            let blocks = query_blocks(&provider, 10, concurrency).await.unwrap();
+            assert_eq!(blocks, (10..90).collect::<Vec<_>>());
+            elapsed.push(start.elapsed());
+        }
+
+        // 8 pages in total, which could be queried at the same time.
+        assert_eq!(
+            elapsed,
+            vec![latency * 8, latency * 4, latency * 2, latency]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_parallel_log_query_shrink_page() {
+        let provider = Provider::new(MockClient::new(Duration::from_millis(1), 7));
+        let blocks = query_blocks(&provider, 32, 4).await.unwrap();
+        assert_eq!(blocks, (10..90).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_parallel_log_query_large_block() {
+        // Each block has more logs than the server could return.
+        let provider = Provider::new(MockClient {
+            logs_per_block: 3,
+            ..MockClient::new(Duration::from_millis(1), 2)
+        });
+        let blocks = query_blocks(&provider, 8, 2).await.unwrap();
+        let expected: Vec<_> = (10..90).flat_map(|n| [n; 3]).collect();
+        assert_eq!(blocks, expected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_parallel_log_query_retry() {
+        let provider = Provider::new(MockClient {
+            available: false,
+            ..MockClient::new(Duration::from_millis(1), 100)
+        });
+        let start = Instant::now();
+        assert!(query_blocks(&provider, 80, 1).await.is_err());
+
+        // The page is retried with backoff before the query fails.
+        let requests = provider.as_ref().requests.load(Ordering::SeqCst);
+        assert_eq!(requests, MAX_PAGE_RETRIES as usize + 1);
+        let backoff: Duration = (0..MAX_PAGE_RETRIES).map(retry_backoff).sum();
+        assert_eq!(
+            start.elapsed(),
+            backoff + Duration::from_millis(1) * requests as u32
+        );
+        assert_eq!(retry_backoff(u32::MAX), MAX_RETRY_BACKOFF);
+    }
+}
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use metrics::{register_timer, Gauge, GaugeUsize, Timer};
+use metrics::{register_timer, Counter, CounterUsize, Gauge, GaugeUsize, Timer};
 
 lazy_static::lazy_static! {
     pub static ref LOG_MANAGER_HANDLE_DATA_TRANSACTION: Arc<dyn Timer> = register_timer("log_manager_handle_data_transaction");
@@ -10,4 +10,16 @@ lazy_static::lazy_static! {
     pub static ref STORE_PUT_TX_SPEED_IN_BYTES: Arc<dyn Gauge<usize>> = GaugeUsize::register("log_entry_sync_manager_put_tx_speed_in_bytes");
 
     pub static ref RECOVER_LOG: Arc<dyn Timer> = register_timer("log_entry_sync_manager_recover_log");
+
+    pub static ref DUPLICATED_TXS: Arc<dyn Counter<usize>> = CounterUsize::register("log_entry_sync_manager_duplicated_txs");
+    pub static ref MISSING_TXS: Arc<dyn Counter<usize>> = CounterUsize::register("log_entry_sync_manager_missing_txs");
+    pub static ref QUARANTINED_TXS: Arc<dyn Counter<usize>> = CounterUsize::register("log_entry_sync_manager_quarantined_txs");
+    pub static ref QUARANTINE_DROPPED_TXS: Arc<dyn Counter<usize>> = CounterUsize::register("log_entry_sync_manager_quarantine_dropped_txs");
+    pub static ref INVALID_TXS: Arc<dyn Counter<usize>> = CounterUsize::register("log_entry_sync_manager_invalid_txs");
+
+    pub static ref SYNC_LAG_BLOCKS: Arc<dyn Gauge<usize>> = GaugeUsize::register("log_entry_sync_manager_sync_lag_blocks");
+    pub static ref SECONDS_SINCE_PROGRESS: Arc<dyn Gauge<usize>> = GaugeUsize::register("log_entry_sync_manager_seconds_since_progress");
+    pub static ref STALL_RECOVERIES: Arc<dyn Counter<usize>> = CounterUsize::register("log_entry_sync_manager_stall_recoveries");
+
+    pub static ref VERIFY_FAILURES: Arc<dyn Counter<usize>> = CounterUsize::register("log_entry_sync_manager_verify_failures");
 }
@@ -1,24 +1,34 @@
 use crate::sync_manager::config::LogSyncConfig;
+use crate::sync_manager::contract_event::{ContractEventKind, ContractLog};
 use crate::sync_manager::data_cache::DataCache;
-use crate::sync_manager::log_entry_fetcher::{LogEntryFetcher, LogFetchProgress};
+use crate::sync_manager::earnings::{Earnings, EarningsTracker};
+use crate::sync_manager::ingest_policy::IngestPolicy;
+use crate::sync_manager::log_entry_fetcher::{LogEntryFetcher, LogFetchProgress, LogSource};
+use crate::sync_manager::quarantine::{QuarantinedTx, TxQuarantine};
+use crate::sync_manager::verifier::Verifier;
+use crate::sync_manager::watchdog::Watchdog;
+use crate::{FailoverClient, RpcClient};
 use anyhow::{anyhow, bail, Result};
 use ethereum_types::H256;
 use ethers::{prelude::Middleware, types::BlockNumber};
 use futures::FutureExt;
-use jsonrpsee::tracing::{debug, error, warn};
+use jsonrpsee::tracing::{debug, error, info, trace, warn};
 use shared_types::{bytes_to_chunks, ChunkArray, Transaction};
 use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::future::Future;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock as StdRwLock};
 use std::time::{Duration, Instant};
 use storage::log_store::log_manager::PORA_CHUNK_SIZE;
-use storage::log_store::{tx_store::BlockHashAndSubmissionIndex, Store};
+use storage::log_store::tx_store::{BlockHashAndSubmissionIndex, SubmissionContext};
+use storage::log_store::Store;
+use task_executor::shutdown::{ShutdownSignal, ShutdownToken};
 use task_executor::{ShutdownReason, TaskExecutor};
 use thiserror::Error;
 use tokio::sync::broadcast;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
-use tokio::sync::{oneshot, RwLock};
+use tokio::sync::{oneshot, watch, RwLock};
 
 const RETRY_WAIT_MS: u64 = 500;
 
@@ -27,6 +37,9 @@ const RETRY_WAIT_MS: u64 = 500;
 const BROADCAST_CHANNEL_CAPACITY: usize = 25000;
 const CATCH_UP_END_GAP: u64 = 10;
 const CHECK_ROOT_INTERVAL: u64 = 500;
+/// Maximum number of attempts to fetch logs again from the same block once some logs are
+/// found missing, e.g. provider returns incomplete logs across pagination boundaries.
+const MAX_REFETCH_ATTEMPTS: usize = 3;
 
 /// Errors while handle data
 #[derive(Error, Debug)]
@@ -37,6 +50,9 @@ pub enum HandleDataError {
     /// Other Errors
     #[error("{0}")]
     CommonError(#[from] anyhow::Error),
+    /// Stopped since the node is shutting down.
+    #[error("log sync shut down")]
+    Shutdown,
 }
 
 #[derive(Clone, Debug)]
@@ -47,6 +63,133 @@ pub enum LogSyncEvent {
     Reverted { tx_seq: u64 },
     /// Synced a transaction from blockchain
     TxSynced { tx: Transaction },
+    /// Synced a subscribed event of other contracts, which may be delivered more than once.
+    ContractEventSynced { log: ContractLog },
+    /// All the blocks from the block number are reorged, including the subscribed events.
+    BlocksReverted { block_number: u64 },
+}
+
+/// Handle to query the status of log sync from other components.
+#[derive(Clone)]
+pub struct LogSyncMonitor {
+    rpc_client: RpcClient,
+    heads: Arc<StdRwLock<ChainHeads>>,
+    earnings: EarningsTracker,
+    progress: Arc<StdRwLock<SyncProgress>>,
+    /// Whether log sync has caught up with the finalized block since the node started.
+    caught_up: Arc<AtomicBool>,
+    /// Log sync is regarded as stalled if no new block applied for this duration.
+    stall_timeout: Duration,
+}
+
+/// The latest block applied to the store.
+#[derive(Clone, Copy, Debug)]
+struct SyncProgress {
+    block_number: Option<u64>,
+    updated_at: tokio::time::Instant,
+}
+
+/// Block numbers observed by the log entry fetcher.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChainHeads {
+    /// Latest block number of the chain, which may be reorged.
+    pub latest: Option<u64>,
+    /// Latest block number that is stable under the confirmation policy, and logs are only
+    /// processed until it.
+    pub confirmed: Option<u64>,
+}
+
+impl LogSyncMonitor {
+    pub(crate) fn new(
+        rpc_client: RpcClient,
+        earnings: EarningsTracker,
+        stall_timeout: Duration,
+    ) -> Self {
+        LogSyncMonitor {
+            rpc_client,
+            heads: Default::default(),
+            earnings,
+            progress: Arc::new(StdRwLock::new(SyncProgress {
+                block_number: None,
+                updated_at: tokio::time::Instant::now(),
+            })),
+            caught_up: Default::default(),
+            stall_timeout,
+        }
+    }
+
+    pub fn rpc_client(&self) -> &RpcClient {
+        &self.rpc_client
+    }
+
+    pub fn heads(&self) -> ChainHeads {
+        *self.heads.read().expect("lock poisoned")
+    }
+
+    pub(crate) fn update_heads(&self, latest: u64, confirmed: u64) {
+        *self.heads.write().expect("lock poisoned") = ChainHeads {
+            latest: Some(latest),
+            confirmed: Some(confirmed),
+        };
+    }
+
+    /// Returns the latest block number applied to the store.
+    pub fn synced_block_number(&self) -> Option<u64> {
+        self.progress.read().expect("lock poisoned").block_number
+    }
+
+    /// Returns the duration since the last new block applied, or since the node started.
+    pub fn since_last_progress(&self) -> Duration {
+        self.progress
+            .read()
+            .expect("lock poisoned")
+            .updated_at
+            .elapsed()
+    }
+
+    /// Returns the number of blocks that the synced block falls behind the head reported by
+    /// the rpc endpoints.
+    pub fn lag(&self) -> Option<u64> {
+        let head = self
+            .rpc_client
+            .status()
+            .into_iter()
+            .filter_map(|status| status.head)
+            .chain(self.heads().latest)
+            .max()?;
+        Some(head.saturating_sub(self.synced_block_number()?))
+    }
+
+    /// Returns whether log sync has caught up with the finalized block, before which txs are
+    /// synced and finalized in bulk.
+    pub fn is_caught_up(&self) -> bool {
+        self.caught_up.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_caught_up(&self) {
+        self.caught_up.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether no new block is applied for the stall timeout, which is never the case
+    /// if the stall detection is disabled.
+    pub fn is_stalled(&self) -> bool {
+        !self.stall_timeout.is_zero() && self.since_last_progress() >= self.stall_timeout
+    }
+
+    pub(crate) fn update_synced(&self, block_number: u64) {
+        let mut progress = self.progress.write().expect("lock poisoned");
+        if progress.block_number != Some(block_number) {
+            *progress = SyncProgress {
+                block_number: Some(block_number),
+                updated_at: tokio::time::Instant::now(),
+            };
+        }
+    }
+
+    /// Returns the rewards of the subscribed `DistributeReward` events since the node started.
+    pub fn earnings(&self) -> Vec<Earnings> {
+        self.earnings.earnings()
+    }
 }
 
 pub struct LogSyncManager {
@@ -61,6 +204,20 @@ pub struct LogSyncManager {
     event_send: broadcast::Sender<LogSyncEvent>,
 
     block_hash_cache: Arc<RwLock<BTreeMap<u64, Option<BlockHashAndSubmissionIndex>>>>,
+
+    /// Txs delivered ahead of `next_tx_seq`, which are applied once the gap is filled.
+    quarantine: TxQuarantine,
+
+    /// Txs ingested but not confirmed until `finalize_confirmations` passes their blocks,
+    /// mapping the tx seq to the submission block number. Store defers finalizing the txs
+    /// since the first one, no matter who completes the data, e.g. chunk pool or file sync.
+    unconfirmed: BTreeMap<u64, u64>,
+
+    /// Txs rejected by the policy are stored as invalid, which is reloaded at runtime.
+    ingest_policy: watch::Receiver<IngestPolicy>,
+
+    /// Stops handling the fetched logs once the node is shutting down.
+    shutdown: ShutdownSignal,
 }
 
 impl LogSyncManager {
@@ -68,35 +225,110 @@ impl LogSyncManager {
         config: LogSyncConfig,
         executor: TaskExecutor,
         store: Arc<dyn Store>,
-    ) -> Result<(broadcast::Sender<LogSyncEvent>, oneshot::Receiver<()>)> {
+        ingest_policy: watch::Receiver<IngestPolicy>,
+        shutdown: ShutdownToken,
+    ) -> Result<(
+        broadcast::Sender<LogSyncEvent>,
+        oneshot::Receiver<()>,
+        LogSyncMonitor,
+    )> {
         let next_tx_seq = store.next_tx_seq();
 
-        let executor_clone = executor.clone();
-        let mut shutdown_sender = executor.shutdown_sender();
+        // Created in advance so that the endpoint status could be queried via RPC, and the
+        // recorded logs are replayed without any blockchain rpc.
+        let source = LogSource::from_config(&config)?;
+        let replay = source.is_replay();
+        let rpc_client = if replay {
+            FailoverClient::new(vec![], 0)
+        } else {
+            LogEntryFetcher::new_rpc_client(&config)?
+        };
+        if !replay {
+            executor.spawn(
+                rpc_client
+                    .clone()
+                    .run_health_check(config.rpc_health_check_interval),
+                "log_sync_rpc_health_check",
+            );
+        }
 
         let (event_send, _) = broadcast::channel(BROADCAST_CHANNEL_CAPACITY);
         let event_send_cloned = event_send.clone();
+
+        let earnings = if config
+            .event_subscriptions
+            .iter()
+            .any(|s| s.kind == ContractEventKind::DistributeReward)
+        {
+            EarningsTracker::spawn(&executor, event_send.subscribe())
+        } else {
+            EarningsTracker::default()
+        };
+        // The store of verify-only log sync never progresses.
+        let stall_timeout = if config.verify_only {
+            Duration::ZERO
+        } else {
+            config.stall_timeout
+        };
+        let monitor = LogSyncMonitor::new(rpc_client, earnings, stall_timeout);
+        let monitor_cloned = monitor.clone();
+        if !replay {
+            executor.spawn(
+                Watchdog::new(monitor.clone(), monitor.rpc_client().clone())
+                    .run(config.rpc_health_check_interval),
+                "log_sync_watchdog",
+            );
+        }
+
+        let executor_clone = executor.clone();
+        let mut shutdown_sender = executor.shutdown_sender();
         let (catch_up_end_sender, catch_up_end_receiver) = oneshot::channel();
+        let shutdown_signal = shutdown.signal();
 
         // Spawn the task to sync log entries from the blockchain.
         executor.spawn(
             run_and_log(
+                shutdown,
                 move || {
                     shutdown_sender
                         .try_send(ShutdownReason::Failure("log sync failure"))
                         .expect("shutdown send error")
                 },
                 async move {
-                    let log_fetcher = LogEntryFetcher::new(&config).await?;
+                    // Log entries are appended to the flow merkle tree, which may be loading
+                    // in background, so wait without blocking the runtime threads.
+                    if !store.is_flow_tree_ready() {
+                        info!("Log sync waits for the flow tree to load");
+                        let _ = store
+                            .subscribe_flow_tree_ready()
+                            .wait_for(|ready| *ready)
+                            .await;
+                    }
+
+                    let log_fetcher =
+                        LogEntryFetcher::new(&config, monitor_cloned.clone(), source).await?;
+
+                    if config.verify_only {
+                        monitor_cloned.set_caught_up();
+                        if catch_up_end_sender.send(()).is_err() {
+                            warn!("catch_up_end send fails, possibly auto_sync is not enabled");
+                        }
+                        Verifier::new(
+                            log_fetcher,
+                            store,
+                            monitor_cloned,
+                            config.ingest_confirmations,
+                        )
+                        .run(config.rpc_health_check_interval, shutdown_signal)
+                        .await;
+                        return Ok(());
+                    }
+
                     let data_cache = DataCache::new(config.cache_config.clone());
+                    let quarantine = TxQuarantine::new(config.quarantine_config.clone());
 
-                    let block_hash_cache = Arc::new(RwLock::new(
-                        store
-                            .get_block_hashes()?
-                            .into_iter()
-                            .map(|(x, y)| (x, Some(y)))
-                            .collect::<BTreeMap<_, _>>(),
-                    ));
+                    // Loaded once the finalized block is known.
+                    let block_hash_cache = Arc::new(RwLock::new(BTreeMap::new()));
                     let mut log_sync_manager = Self {
                         config,
                         log_fetcher,
@@ -105,7 +337,28 @@ impl LogSyncManager {
                         data_cache,
                         event_send,
                         block_hash_cache,
+                        quarantine,
+                        unconfirmed: BTreeMap::new(),
+                        ingest_policy,
+                        shutdown: shutdown_signal,
                     };
+                    log_sync_manager.load_unconfirmed()?;
+
+                    if let Some(replay_rx) =
+                        log_sync_manager.log_fetcher.start_replay(&executor_clone)
+                    {
+                        log_sync_manager.handle_data(replay_rx, &None).await?;
+                        info!(
+                            "log replay completed, next_tx_seq={}",
+                            log_sync_manager.next_tx_seq
+                        );
+                        monitor_cloned.set_caught_up();
+                        if catch_up_end_sender.send(()).is_err() {
+                            warn!("catch_up_end send fails, possibly auto_sync is not enabled");
+                        }
+                        log_sync_manager.store.start_padding(&executor_clone);
+                        return Ok(());
+                    }
 
                     let (mut start_block_number, mut start_block_hash) =
                         get_start_block_number_with_hash(&log_sync_manager).await?;
@@ -119,6 +372,10 @@ impl LogSyncManager {
                             }
                         };
 
+                    log_sync_manager
+                        .load_block_hash_cache(start_block_number, finalized_block_number)
+                        .await?;
+
                     // Load previous progress from db and check if chain reorg happens after restart.
                     let mut need_handle_reorg = false;
                     if start_block_number <= finalized_block_number {
@@ -219,6 +476,7 @@ impl LogSyncManager {
                                 }
                             }
 
+                            let mut refetches = (start_block_number, 0);
                             while let Err(e) = log_sync_manager
                                 .catch_up_data(
                                     executor_clone.clone(),
@@ -229,6 +487,16 @@ impl LogSyncManager {
                             {
                                 match e {
                                     HandleDataError::SeqError(block_number) => {
+                                        if refetches.0 != block_number {
+                                            refetches = (block_number, 0);
+                                        }
+                                        refetches.1 += 1;
+                                        if refetches.1 > MAX_REFETCH_ATTEMPTS {
+                                            bail!(
+                                                "logs still missing after refetching {} times from block {}",
+                                                MAX_REFETCH_ATTEMPTS, block_number
+                                            );
+                                        }
                                         warn!("seq error occurred, retry from {}", block_number);
                                         start_block_number = block_number;
                                         tokio::time::sleep(Duration::from_secs(1)).await;
@@ -253,6 +521,7 @@ impl LogSyncManager {
                         }
                     };
 
+                    monitor_cloned.set_caught_up();
                     if catch_up_end_sender.send(()).is_err() {
                         warn!("catch_up_end send fails, possibly auto_sync is not enabled");
                     }
@@ -292,16 +561,16 @@ impl LogSyncManager {
             .map(|_| ()),
             "log_sync",
         );
-        Ok((event_send_cloned, catch_up_end_receiver))
+        Ok((event_send_cloned, catch_up_end_receiver, monitor))
     }
 
-    async fn put_tx(&mut self, tx: Transaction) -> Option<bool> {
+    async fn put_tx(&mut self, tx: Transaction, block_number: u64, valid: bool) -> Option<bool> {
         // We call this after process chain reorg, so the sequence number should match.
         match tx.seq.cmp(&self.next_tx_seq) {
             std::cmp::Ordering::Less => Some(true),
             std::cmp::Ordering::Equal => {
                 debug!("log entry sync get entry: {:?}", tx);
-                Some(self.put_tx_inner(tx).await)
+                Some(self.put_tx_inner(tx, block_number, valid).await)
             }
             std::cmp::Ordering::Greater => {
                 error!(
@@ -319,7 +588,10 @@ impl LogSyncManager {
         {
             let store = self.store.clone();
             for seq in tx_seq..self.next_tx_seq {
-                if matches!(store.check_tx_completed(seq), Ok(true)) {
+                // The data of txs waiting for finalization may be complete as well.
+                if self.unconfirmed.contains_key(&seq)
+                    || matches!(store.check_tx_completed(seq), Ok(true))
+                {
                     if let Ok(Some(tx)) = store.get_tx_by_seq_number(seq) {
                         // TODO(zz): Skip reading the rear padding data?
                         if let Ok(Some(data)) =
@@ -346,6 +618,12 @@ impl LogSyncManager {
             return;
         }
         self.next_tx_seq = tx_seq;
+        self.unconfirmed.split_off(&tx_seq);
+        // The parked txs follow the reverted ones, and are delivered again once reorged.
+        let dropped = self.quarantine.clear();
+        if dropped > 0 {
+            metrics::QUARANTINE_DROPPED_TXS.inc(dropped);
+        }
 
         let _ = self.event_send.send(LogSyncEvent::Reverted { tx_seq });
     }
@@ -362,7 +640,32 @@ impl LogSyncManager {
                 0
             };
 
-        while let Some(data) = rx.recv().await {
+        loop {
+            let expires_at = self.quarantine.expires_at();
+            // Logs are handled one by one, so that writes of a log are never interrupted.
+            let data = tokio::select! {
+                biased;
+
+                _ = self.shutdown.requested() => return Err(HandleDataError::Shutdown),
+
+                _ = tokio::time::sleep_until(expires_at.unwrap_or_else(Instant::now).into()),
+                    if expires_at.is_some() =>
+                {
+                    warn!(
+                        parked = self.quarantine.len(),
+                        next_tx_seq = self.next_tx_seq,
+                        "Gap before the quarantined txs not filled in time, fetch logs again"
+                    );
+                    metrics::MISSING_TXS.inc(1);
+                    self.refetch(watch_progress_tx, log_latest_block_number)?;
+                    continue;
+                }
+
+                data = rx.recv() => match data {
+                    Some(data) => data,
+                    None => break,
+                },
+            };
             debug!("handle_data: data={:?}", data);
             match data {
                 LogFetchProgress::SyncedBlock((
@@ -370,6 +673,7 @@ impl LogSyncManager {
                     block_hash,
                     first_submission_index,
                 )) => {
+                    self.log_fetcher.monitor().update_synced(block_number);
                     if first_submission_index.is_some() {
                         self.block_hash_cache.write().await.insert(
                             block_number,
@@ -380,12 +684,19 @@ impl LogSyncManager {
                         );
                     }
 
+                    // Finalized before the progress persisted, so that the txs of the synced
+                    // blocks are reloaded for finalization on crash.
+                    self.finalize_confirmed(block_number);
                     self.store.put_sync_progress((
                         block_number,
                         block_hash,
                         first_submission_index,
                     ))?;
 
+                    // Recorded logs are replayed without blockchain.
+                    if self.log_fetcher.is_replay() {
+                        continue;
+                    }
                     match self.log_fetcher.provider().get_block(block_number).await {
                         Ok(Some(b)) => {
                             if b.number != Some(block_number.into()) {
@@ -401,80 +712,256 @@ impl LogSyncManager {
                         }
                     }
                 }
-                LogFetchProgress::Transaction((tx, block_number)) => {
-                    let mut stop = false;
-                    let start_time = Instant::now();
-                    match self.put_tx(tx.clone()).await {
-                        Some(false) => stop = true,
-                        Some(true) => {
-                            if let Err(e) = self.store.put_log_latest_block_number(block_number) {
-                                warn!("failed to put log latest block number, error={:?}", e);
-                            }
+                LogFetchProgress::Transaction((tx, block_number, tx_hash, sender)) => {
+                    // Logs could be delivered again after the watch stream is recreated, e.g.
+                    // on rpc endpoint failover, or returned more than once by providers across
+                    // pagination boundaries, so skip the processed ones.
+                    if tx.seq < self.next_tx_seq {
+                        debug!(
+                            "skip processed transaction: seq={} next={}",
+                            tx.seq, self.next_tx_seq
+                        );
+                        metrics::DUPLICATED_TXS.inc(1);
+                        continue;
+                    }
 
-                            log_latest_block_number = block_number;
-                        }
-                        _ => {
-                            stop = true;
-                            if let Some(progress_tx) = watch_progress_tx {
-                                if let Err(e) = progress_tx.send(log_latest_block_number) {
-                                    error!("failed to send watch progress, error={:?}", e);
-                                } else {
-                                    continue;
-                                }
-                            } else {
-                                return Err(HandleDataError::SeqError(log_latest_block_number));
-                            }
+                    // Some logs are missing or delivered out of order, so park the tx until the
+                    // gap is filled.
+                    let tx = QuarantinedTx {
+                        tx,
+                        block_number,
+                        tx_hash,
+                        sender,
+                    };
+                    if tx.tx.seq > self.next_tx_seq {
+                        debug!(
+                            "quarantine transaction: seq={} next={}",
+                            tx.tx.seq, self.next_tx_seq
+                        );
+                        if self.quarantine.park(tx) {
+                            metrics::QUARANTINED_TXS.inc(1);
+                        } else {
+                            warn!(
+                                parked = self.quarantine.len(),
+                                next_tx_seq = self.next_tx_seq,
+                                "Quarantine of out-of-order txs full, fetch logs again"
+                            );
+                            metrics::MISSING_TXS.inc(1);
+                            self.refetch(watch_progress_tx, log_latest_block_number)?;
                         }
+                        continue;
                     }
 
-                    if stop {
-                        // Unexpected error.
-                        return Err(anyhow!("log sync write error").into());
+                    // Apply the tx, and then the parked txs that follow it.
+                    let mut next = Some(tx);
+                    while let Some(tx) = next {
+                        if !self.apply_tx(tx, &mut log_latest_block_number).await? {
+                            // The tx is out of order, so fetch logs again from the block of the
+                            // last stored tx.
+                            self.refetch(watch_progress_tx, log_latest_block_number)?;
+                            break;
+                        }
+                        next = self.quarantine.take_next(self.next_tx_seq);
                     }
-                    if let Err(e) = self.event_send.send(LogSyncEvent::TxSynced { tx }) {
-                        // TODO: Do we need to wait until all receivers are initialized?
-                        // Auto-sync and txpool may need this event, but it's possible that
-                        // no receivers will be created.
+                }
+                LogFetchProgress::ContractLog(log) => {
+                    if let Err(e) = self
+                        .event_send
+                        .send(LogSyncEvent::ContractEventSynced { log })
+                    {
                         warn!("log sync broadcast error, error={:?}", e);
                     }
-
-                    metrics::LOG_MANAGER_HANDLE_DATA_TRANSACTION.update_since(start_time);
                 }
                 LogFetchProgress::Reverted(reverted) => {
                     self.process_reverted(reverted).await;
                 }
+                LogFetchProgress::RevertedBlocks(block_number) => {
+                    let mut block_hash_cache = self.block_hash_cache.write().await;
+                    for reverted in block_hash_cache.split_off(&block_number).into_keys() {
+                        if let Err(e) = self.store.delete_block_hash_by_number(reverted) {
+                            error!("remove block tx for number {} error: e={:?}", reverted, e);
+                        }
+                    }
+                    let _ = self
+                        .event_send
+                        .send(LogSyncEvent::BlocksReverted { block_number });
+                }
             }
         }
+
+        // The gap before the parked txs is never filled by the logs of the range.
+        if !self.quarantine.is_empty() && watch_progress_tx.is_none() {
+            warn!(
+                parked = self.quarantine.len(),
+                next_tx_seq = self.next_tx_seq,
+                "Gap before the quarantined txs not filled, fetch logs again"
+            );
+            metrics::MISSING_TXS.inc(1);
+            self.refetch(&None, log_latest_block_number)?;
+        }
         Ok(())
     }
 
-    async fn put_tx_inner(&mut self, tx: Transaction) -> bool {
+    /// Puts the tx of `next_tx_seq` into store and broadcasts it, and returns false if the tx is
+    /// out of order. A tx rejected by the store is never fixed by fetching the logs again, so it
+    /// fails with `CommonError` instead.
+    async fn apply_tx(
+        &mut self,
+        tx: QuarantinedTx,
+        log_latest_block_number: &mut u64,
+    ) -> Result<bool, HandleDataError> {
+        let start_time = Instant::now();
+        let QuarantinedTx {
+            tx,
+            block_number,
+            tx_hash,
+            sender,
+        } = tx;
+        let valid = match self.ingest_policy.borrow().check(&tx, sender) {
+            Ok(()) => true,
+            Err(violation) => {
+                warn!(tx_seq = tx.seq, %violation, "Tx rejected by ingest policy");
+                false
+            }
+        };
+        match self.put_tx(tx.clone(), block_number, valid).await {
+            Some(true) => {}
+            Some(false) => {
+                return Err(anyhow!("log sync write error: failed to put tx {}", tx.seq).into())
+            }
+            None => return Ok(false),
+        }
+        // The store may mark the tx invalid as well, e.g. of too large inline data.
+        let valid = valid && !self.stored_as_invalid(tx.seq);
+
+        if let Err(e) = self.store.put_log_latest_block_number(block_number) {
+            warn!("failed to put log latest block number, error={:?}", e);
+        }
+        if let Some(tx_hash) = tx_hash {
+            let context = SubmissionContext {
+                block_number,
+                tx_hash,
+            };
+            if let Err(e) = self.store.put_submission_context(tx.seq, context) {
+                warn!("failed to put submission context, error={:?}", e);
+            }
+        }
+        *log_latest_block_number = block_number;
+
+        // Invalid files are never synced, so no need to broadcast.
+        if !valid {
+            metrics::LOG_MANAGER_HANDLE_DATA_TRANSACTION.update_since(start_time);
+            return Ok(true);
+        }
+        Self::notify_tx_synced(self.store.clone(), self.event_send.clone(), tx);
+
+        metrics::LOG_MANAGER_HANDLE_DATA_TRANSACTION.update_since(start_time);
+        Ok(true)
+    }
+
+    /// Broadcasts `TxSynced` of the tx put, so that its data is synced from peers. If reverted
+    /// before with the data staged in the revert journal, the tx is restored in background
+    /// instead, since the data could be large, and only synced from peers if not restored, e.g.
+    /// reverted again meanwhile.
+    pub fn notify_tx_synced(
+        store: Arc<dyn Store>,
+        event_send: broadcast::Sender<LogSyncEvent>,
+        tx: Transaction,
+    ) {
+        let send = move |tx: Transaction| {
+            if let Err(e) = event_send.send(LogSyncEvent::TxSynced { tx }) {
+                // TODO: Do we need to wait until all receivers are initialized?
+                // Auto-sync and txpool may need this event, but it's possible that
+                // no receivers will be created.
+                warn!("log sync broadcast error, error={:?}", e);
+            }
+        };
+        if !store.is_reverted_tx_staged(&tx) {
+            send(tx);
+            return;
+        }
+
+        tokio::task::spawn_blocking(move || {
+            let tx_seq = tx.seq;
+            match store.restore_reverted_tx(tx_seq, tx.hash()) {
+                Ok(true) => return,
+                Ok(false) => {}
+                Err(e) => warn!(%tx_seq, "Failed to restore reverted tx: {:?}", e),
+            }
+            send(tx);
+        });
+    }
+
+    /// Drops the parked txs, and fetches logs again from the block of the last stored tx,
+    /// either by notifying the watch of `watch_progress_tx`, or failing with `SeqError`.
+    fn refetch(
+        &mut self,
+        watch_progress_tx: &Option<UnboundedSender<u64>>,
+        log_latest_block_number: u64,
+    ) -> Result<(), HandleDataError> {
+        let dropped = self.quarantine.clear();
+        if dropped > 0 {
+            metrics::QUARANTINE_DROPPED_TXS.inc(dropped);
+        }
+        match watch_progress_tx {
+            Some(progress_tx) => progress_tx.send(log_latest_block_number).map_err(|e| {
+                error!("failed to send watch progress, error={:?}", e);
+                anyhow!("log sync write error").into()
+            }),
+            None => Err(HandleDataError::SeqError(log_latest_block_number)),
+        }
+    }
+
+    /// Puts the tx of `next_tx_seq`, which is stored as invalid if rejected by the ingest policy.
+    async fn put_tx_inner(&mut self, tx: Transaction, block_number: u64, valid: bool) -> bool {
         let start_time = Instant::now();
-        let result = self.store.put_tx(tx.clone());
+        let put_tx = move |store: Arc<dyn Store>, tx: Transaction| {
+            if valid {
+                store.put_tx(tx)
+            } else {
+                store.put_invalid_tx(tx)
+            }
+        };
+        let result = if tx.data.is_empty() {
+            put_tx(self.store.clone(), tx.clone())
+        } else {
+            // Padding and hashing the inline data is slow, so it never blocks the async runtime.
+            let store = self.store.clone();
+            let inline_tx = tx.clone();
+            tokio::task::spawn_blocking(move || put_tx(store, inline_tx))
+                .await
+                .unwrap_or_else(|e| Err(anyhow!("put_tx task failed: {:?}", e)))
+        };
 
         if let Err(e) = result {
             error!("put_tx error: e={:?}", e);
             false
         } else {
-            if let Some(data) = self.data_cache.pop_data(&tx.data_merkle_root) {
+            self.unconfirmed.insert(tx.seq, block_number);
+            if !valid || self.stored_as_invalid(tx.seq) {
+                // The data of invalid txs is neither stored nor finalized.
+                metrics::INVALID_TXS.inc(1);
+            } else if let Some(data) = self.data_cache.pop_data(&tx.data_merkle_root) {
                 let store = self.store.clone();
                 // We are holding a mutable reference of LogSyncManager, so no chain reorg is
                 // possible after put_tx.
-                if let Err(e) = store
-                    .put_chunks_with_tx_hash(
-                        tx.seq,
-                        tx.hash(),
-                        ChunkArray {
-                            data,
-                            start_index: 0,
-                        },
-                        None,
-                    )
-                    .and_then(|_| store.finalize_tx_with_hash(tx.seq, tx.hash()))
-                {
+                if let Err(e) = store.put_chunks_with_tx_hash(
+                    tx.seq,
+                    tx.hash(),
+                    ChunkArray {
+                        data,
+                        start_index: 0,
+                    },
+                    None,
+                ) {
                     error!("put_tx data error: e={:?}", e);
                     return false;
                 }
+                // Deferred by store until confirmed.
+                if let Err(e) = store.finalize_tx_with_hash(tx.seq, tx.hash()) {
+                    error!("finalize_tx error: e={:?}", e);
+                    return false;
+                }
             } else {
                 // check if current node need to save at least one segment
                 let store = self.store.clone();
@@ -554,6 +1041,80 @@ impl LogSyncManager {
         }
     }
 
+    /// Returns whether the stored tx is marked invalid, either by the ingest policy or by the
+    /// store itself.
+    fn stored_as_invalid(&self, tx_seq: u64) -> bool {
+        match self.store.check_tx_invalid(tx_seq) {
+            Ok(invalid) => invalid,
+            Err(e) => {
+                warn!(%tx_seq, "failed to check tx invalid: e={:?}", e);
+                false
+            }
+        }
+    }
+
+    /// Returns the first tx not confirmed to finalize yet.
+    fn next_confirmed_seq(&self) -> u64 {
+        self.unconfirmed
+            .keys()
+            .next()
+            .copied()
+            .unwrap_or(self.next_tx_seq)
+    }
+
+    /// Confirms the txs whose blocks are deep enough to finalize in store, given the latest
+    /// block ingested. The deferred txs are finalized by store in one write.
+    fn finalize_confirmed(&mut self, synced_block_number: u64) {
+        let watermark = match synced_block_number.checked_sub(self.config.finalize_lag()) {
+            Some(watermark) => watermark,
+            None => return,
+        };
+        while let Some(entry) = self.unconfirmed.first_entry() {
+            if *entry.get() > watermark {
+                break;
+            }
+            entry.remove();
+        }
+
+        if let Err(e) = self.store.confirm_finalization(self.next_confirmed_seq()) {
+            warn!(
+                "failed to finalize confirmed txs, left to file sync: e={:?}",
+                e
+            );
+        }
+    }
+
+    /// Loads the txs ingested but not confirmed before restart, and gates their finalization
+    /// in store. The finalizations deferred by store are lost on restart, so the unconfirmed
+    /// txs are finalized again, and the ones without data are left to file sync.
+    fn load_unconfirmed(&mut self) -> Result<()> {
+        if let Some((block_number, _)) = self.store.get_sync_progress()? {
+            let watermark = block_number.saturating_sub(self.config.finalize_lag());
+            for seq in (0..self.next_tx_seq).rev() {
+                match self.store.get_submission_context(seq)? {
+                    Some(context) if context.block_number > watermark => {
+                        self.unconfirmed.insert(seq, context.block_number);
+                    }
+                    _ => break,
+                }
+            }
+        }
+        self.store.confirm_finalization(self.next_confirmed_seq())?;
+
+        for &seq in self.unconfirmed.keys() {
+            if self.store.check_tx_completed(seq)? {
+                continue;
+            }
+            if let Some(tx) = self.store.get_tx_by_seq_number(seq)? {
+                if let Err(e) = self.store.finalize_tx_with_hash(seq, tx.hash()) {
+                    trace!(%seq, "unconfirmed tx not finalized: e={:?}", e);
+                }
+            }
+        }
+        debug!("unconfirmed txs loaded, size={}", self.unconfirmed.len());
+        Ok(())
+    }
+
     async fn get_block(&self, block_number: BlockNumber) -> Result<(u64, H256)> {
         let block = match self.log_fetcher.provider().get_block(block_number).await {
             Ok(Some(block)) => block,
@@ -575,6 +1136,54 @@ impl LogSyncManager {
         ))
     }
 
+    /// Loads the hashes of the blocks since the latest finalized one into cache to detect reorg.
+    ///
+    /// Blocks are read backwards from `start_block_number`, and the loading stops at the first
+    /// block not after `finalized_block_number` whose hash matches the blockchain, since the
+    /// blocks before it will never be reorged.
+    async fn load_block_hash_cache(
+        &self,
+        start_block_number: u64,
+        finalized_block_number: u64,
+    ) -> Result<()> {
+        let mut from_block = start_block_number;
+        loop {
+            // The iterator is dropped before waiting for RPC.
+            let mut blocks = vec![];
+            for r in self.store.iter_block_hashes_rev(from_block) {
+                let (block_number, block) = r?;
+                blocks.push((block_number, block));
+                if block_number <= finalized_block_number {
+                    break;
+                }
+            }
+
+            let candidate = blocks
+                .last()
+                .filter(|(block_number, _)| *block_number <= finalized_block_number)
+                .map(|(block_number, block)| (*block_number, block.block_hash));
+            self.block_hash_cache
+                .write()
+                .await
+                .extend(blocks.into_iter().map(|(x, y)| (x, Some(y))));
+
+            let (block_number, block_hash) = match candidate {
+                Some(candidate) => candidate,
+                None => break,
+            };
+            if self.get_block(block_number.into()).await?.1 == block_hash || block_number == 0 {
+                break;
+            }
+            from_block = block_number - 1;
+        }
+
+        debug!(
+            "block hash cache loaded, size={}",
+            self.block_hash_cache.read().await.len()
+        );
+        Ok(())
+    }
+
     /// Return the ending block number and the parent block hash.
     async fn catch_up_data(
         &mut self,
@@ -588,11 +1197,52 @@ impl LogSyncManager {
                 finalized_block_number,
                 &executor_clone,
                 Duration::from_millis(self.config.recover_query_delay),
+                self.config.recover_concurrency,
             );
             self.handle_data(recover_rx, &None).await?;
+            self.check_num_submissions(start_block_number, finalized_block_number)
+                .await?;
         }
         Ok(())
     }
+
+    /// Checks if any log is missing at the end of the synced range, since a gap is only
+    /// detected by the following logs.
+    async fn check_num_submissions(
+        &self,
+        start_block_number: u64,
+        block_number: u64,
+    ) -> Result<(), HandleDataError> {
+        let num_submissions = match self
+            .log_fetcher
+            .flow_contract()
+            .num_submissions()
+            .block(block_number)
+            .call()
+            .await
+        {
+            Ok(num_submissions) => num_submissions.as_u64(),
+            Err(e) => {
+                warn!("failed to get num submissions, skip the check, e={:?}", e);
+                return Ok(());
+            }
+        };
+
+        if self.next_tx_seq < num_submissions {
+            warn!(
+                "logs missing at the end, expected next tx seq {}, got {}",
+                num_submissions, self.next_tx_seq
+            );
+            metrics::MISSING_TXS.inc(1);
+            let log_latest_block_number = self
+                .store
+                .get_log_latest_block_number()?
+                .unwrap_or(start_block_number);
+            return Err(HandleDataError::SeqError(log_latest_block_number));
+        }
+
+        Ok(())
+    }
 }
 
 async fn get_start_block_number_with_hash(
@@ -608,13 +1258,11 @@ async fn get_start_block_number_with_hash(
     }
 
     if let Some(block_number) = log_sync_manager.store.get_log_latest_block_number()? {
-        if let Some(Some(val)) = log_sync_manager
-            .block_hash_cache
-            .read()
-            .await
-            .get(&block_number)
+        if let Some((block_hash, _)) = log_sync_manager
+            .store
+            .get_block_hash_by_number(block_number)?
         {
-            return Ok((block_number, val.block_hash));
+            return Ok((block_number, block_hash));
         } else {
             warn!("get block hash for block {} from RPC", block_number);
             let block_hash = log_sync_manager.get_block(block_number.into()).await?.1;
@@ -635,7 +1283,10 @@ async fn get_start_block_number_with_hash(
     Ok((start_block_number, start_block_hash))
 }
 
+/// Runs the log sync task, and acknowledges `shutdown` once the task stopped for the node is
+/// shutting down, or completed, e.g. log replay.
 async fn run_and_log<R, E>(
+    shutdown: ShutdownToken,
     mut on_error: impl FnMut(),
     f: impl Future<Output = std::result::Result<R, E>> + Send,
 ) -> Option<R>
@@ -643,17 +1294,685 @@ where
     E: Debug,
 {
     match f.await {
+        Err(_) if shutdown.is_requested() => {
+            info!("log sync stopped for shutdown");
+            shutdown.ack();
+            None
+        }
         Err(e) => {
             error!("log sync failure: e={:?}", e);
             on_error();
             None
         }
-        Ok(r) => Some(r),
+        Ok(r) => {
+            shutdown.ack();
+            Some(r)
+        }
     }
 }
 
 pub(crate) mod config;
+pub(crate) mod contract_event;
 mod data_cache;
+pub(crate) mod earnings;
+pub(crate) mod failover_client;
+pub(crate) mod ingest_policy;
 mod log_entry_fetcher;
 mod log_query;
 mod metrics;
+mod quarantine;
+pub(crate) mod replay;
+mod verifier;
+mod watchdog;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync_manager::config::{CacheConfig, QuarantineConfig};
+    use crate::sync_manager::replay::export_log_entries;
+    use crate::ContractAddress;
+    use ethereum_types::Address;
+    use shared_types::{TransactionBuilder, CHUNK_SIZE};
+    use std::io::Cursor;
+    use storage::log_store::log_manager::{
+        sub_merkle_tree, tx_subtree_root_list_padded, LogConfig,
+    };
+    use storage::LogManager;
+    use task_executor::shutdown::ShutdownCoordinator;
+    use task_executor::test_utils::TestRuntime;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    fn new_config() -> LogSyncConfig {
+        LogSyncConfig::new(
+            vec![],
+            ContractAddress::zero(),
+            0,
+            0,
+            0,
+            CacheConfig {
+                max_data_size: 1024,
+                tx_seq_ttl: 10,
+            },
+            1000,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            false,
+            Duration::from_secs(1),
+            0,
+            Duration::from_secs(1),
+            1,
+            false,
+            vec![],
+            Duration::ZERO,
+            None,
+            false,
+            QuarantineConfig {
+                capacity: 16,
+                window: Duration::from_secs(60),
+            },
+        )
+    }
+
+    async fn new_manager() -> (LogSyncManager, broadcast::Receiver<LogSyncEvent>) {
+        let config = new_config();
+        let monitor = LogSyncMonitor::new(
+            FailoverClient::new(vec![], 0),
+            Default::default(),
+            Duration::ZERO,
+        );
+        let log_fetcher = LogEntryFetcher::new(&config, monitor, LogSource::Chain)
+            .await
+            .unwrap();
+        let (event_send, event_recv) = broadcast::channel(16);
+
+        let mut manager = LogSyncManager {
+            data_cache: DataCache::new(config.cache_config.clone()),
+            quarantine: TxQuarantine::new(config.quarantine_config.clone()),
+            config,
+            log_fetcher,
+            store: Arc::new(LogManager::memorydb(LogConfig::default()).unwrap()),
+            next_tx_seq: 0,
+            event_send,
+            block_hash_cache: Default::default(),
+            unconfirmed: Default::default(),
+            ingest_policy: watch::channel(IngestPolicy::default()).1,
+            shutdown: ShutdownCoordinator::default().token("log_sync").signal(),
+        };
+        manager.load_unconfirmed().unwrap();
+        (manager, event_recv)
+    }
+
+    /// Creates txs of a single chunk, and the tx of seq `n` is submitted in block `10 + n`.
+    fn new_txs(num: u64) -> Vec<(Transaction, u64)> {
+        (0..num)
+            .map(|seq| {
+                let data = vec![seq as u8 + 1; CHUNK_SIZE];
+                let tx = TransactionBuilder::new(seq)
+                    .data_merkle_root(sub_merkle_tree(&data).unwrap().root().into())
+                    .merkle_nodes(tx_subtree_root_list_padded(&data))
+                    .start_entry_index(seq + 1)
+                    .size(data.len() as u64)
+                    .build()
+                    .unwrap();
+                (tx, 10 + seq)
+            })
+            .collect()
+    }
+
+    async fn handle_txs(
+        manager: &mut LogSyncManager,
+        txs: Vec<&(Transaction, u64)>,
+        watch_progress_tx: &Option<UnboundedSender<u64>>,
+    ) -> Result<(), HandleDataError> {
+        let (tx, rx) = unbounded_channel();
+        for (t, block_number) in txs {
+            // Submitted in the tx with the same hash as the block, by the sender of the block
+            // number as well.
+            let tx_hash = H256::from_low_u64_be(*block_number);
+            tx.send(LogFetchProgress::Transaction((
+                t.clone(),
+                *block_number,
+                Some(tx_hash),
+                Some(Address::from_low_u64_be(*block_number)),
+            )))
+            .unwrap();
+        }
+        drop(tx);
+        manager.handle_data(rx, watch_progress_tx).await
+    }
+
+    /// Handles the blocks in range, each of which is synced after the txs submitted in it.
+    async fn handle_blocks(
+        manager: &mut LogSyncManager,
+        txs: &[(Transaction, u64)],
+        blocks: std::ops::RangeInclusive<u64>,
+    ) -> Result<(), HandleDataError> {
+        let (tx, rx) = unbounded_channel();
+        for block_number in blocks {
+            for (t, _) in txs.iter().filter(|(_, b)| *b == block_number) {
+                tx.send(LogFetchProgress::Transaction((
+                    t.clone(),
+                    block_number,
+                    Some(H256::from_low_u64_be(block_number)),
+                    Some(Address::from_low_u64_be(block_number)),
+                )))
+                .unwrap();
+            }
+            tx.send(LogFetchProgress::SyncedBlock((
+                block_number,
+                H256::from_low_u64_be(block_number),
+                None,
+            )))
+            .unwrap();
+        }
+        drop(tx);
+        manager.handle_data(rx, &None).await
+    }
+
+    fn finalized(manager: &LogSyncManager) -> Vec<u64> {
+        (0..manager.store.next_tx_seq())
+            .filter(|seq| manager.store.check_tx_completed(*seq).unwrap())
+            .collect()
+    }
+
+    fn num_synced(event_recv: &mut broadcast::Receiver<LogSyncEvent>) -> usize {
+        let mut num = 0;
+        while let Ok(event) = event_recv.try_recv() {
+            if matches!(event, LogSyncEvent::TxSynced { .. }) {
+                num += 1;
+            }
+        }
+        num
+    }
+
+    #[tokio::test]
+    async fn test_handle_duplicated_logs() {
+        let (mut manager, mut event_recv) = new_manager().await;
+        let txs = new_txs(3);
+
+        let stream = vec![&txs[0], &txs[1], &txs[1], &txs[0], &txs[2], &txs[2]];
+        handle_txs(&mut manager, stream, &None).await.unwrap();
+
+        assert_eq!(manager.next_tx_seq, 3);
+        assert_eq!(manager.store.next_tx_seq(), 3);
+        assert_eq!(num_synced(&mut event_recv), 3);
+        assert_eq!(
+            manager.store.get_submission_context(2).unwrap(),
+            Some(SubmissionContext {
+                block_number: 12,
+                tx_hash: H256::from_low_u64_be(12),
+            })
+        );
+    }
+
+    /// Returns the seqs of txs synced in order.
+    fn synced_seqs(event_recv: &mut broadcast::Receiver<LogSyncEvent>) -> Vec<u64> {
+        let mut seqs = vec![];
+        while let Ok(event) = event_recv.try_recv() {
+            if let LogSyncEvent::TxSynced { tx } = event {
+                seqs.push(tx.seq);
+            }
+        }
+        seqs
+    }
+
+    #[tokio::test]
+    async fn test_ingest_policy() {
+        let (mut manager, mut event_recv) = new_manager().await;
+        let (mut accepted, _) = new_manager().await;
+        let txs = new_txs(6);
+        let (policy_send, policy_recv) = watch::channel(IngestPolicy {
+            denied_roots: [txs[1].0.data_merkle_root].into(),
+            denied_senders: [Address::from_low_u64_be(12)].into(),
+            ..Default::default()
+        });
+        manager.ingest_policy = policy_recv;
+        handle_txs(&mut manager, txs[..3].iter().collect(), &None)
+            .await
+            .unwrap();
+
+        // reloaded at runtime
+        policy_send.send_replace(IngestPolicy {
+            max_size_bytes: Some(CHUNK_SIZE as u64 - 1),
+            ..Default::default()
+        });
+        handle_txs(&mut manager, vec![&txs[3]], &None)
+            .await
+            .unwrap();
+        policy_send.send_replace(IngestPolicy {
+            allowed_senders: [Address::from_low_u64_be(14)].into(),
+            ..Default::default()
+        });
+        handle_txs(&mut manager, vec![&txs[4], &txs[5]], &None)
+            .await
+            .unwrap();
+
+        let invalid: Vec<u64> = (0..6)
+            .filter(|seq| manager.store.check_tx_invalid(*seq).unwrap())
+            .collect();
+        assert_eq!(invalid, vec![1, 2, 3, 5]);
+        assert_eq!(synced_seqs(&mut event_recv), vec![0, 4]);
+
+        // the flow is the same as all the txs accepted
+        handle_txs(&mut accepted, txs.iter().collect(), &None)
+            .await
+            .unwrap();
+        assert_eq!(manager.next_tx_seq, 6);
+        assert_eq!(
+            manager.store.get_context().unwrap(),
+            accepted.store.get_context().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_inline_data() {
+        let (mut manager, mut event_recv) = new_manager().await;
+        manager.store = Arc::new(
+            LogManager::memorydb(LogConfig {
+                max_tx_inline_data_size: CHUNK_SIZE,
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+
+        // a hostile submission of 3 chunks inline, placed after the first tx
+        let txs = new_txs(1);
+        let data = vec![9u8; 3 * CHUNK_SIZE];
+        let oversized = TransactionBuilder::new(1)
+            .data(data.clone())
+            .data_merkle_root(sub_merkle_tree(&data).unwrap().root().into())
+            .merkle_nodes(tx_subtree_root_list_padded(&data))
+            .start_entry_index(2)
+            .size(data.len() as u64)
+            .build()
+            .unwrap();
+        let next = TransactionBuilder::new(2)
+            .data_merkle_root(txs[0].0.data_merkle_root)
+            .merkle_nodes(txs[0].0.merkle_nodes.clone())
+            .start_entry_index(5)
+            .size(CHUNK_SIZE as u64)
+            .build()
+            .unwrap();
+        let stream = [txs[0].clone(), (oversized, 11), (next, 12)];
+        handle_txs(&mut manager, stream.iter().collect(), &None)
+            .await
+            .unwrap();
+
+        // stored without data, and log sync goes on
+        assert_eq!(manager.next_tx_seq, 3);
+        assert!(manager.store.check_tx_invalid(1).unwrap());
+        let stored = manager.store.get_tx_by_seq_number(1).unwrap().unwrap();
+        assert!(stored.data.is_empty());
+        assert_eq!(synced_seqs(&mut event_recv), vec![0, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_handle_out_of_order_logs() {
+        let txs = new_txs(6);
+        let (mut expected, mut expected_recv) = new_manager().await;
+        handle_txs(&mut expected, txs.iter().collect(), &None)
+            .await
+            .unwrap();
+
+        // The txs 5 and 4 are parked until the tx 3 delivered, and applied exactly once.
+        let (mut manager, mut event_recv) = new_manager().await;
+        let stream = vec![
+            &txs[0], &txs[1], &txs[2], &txs[5], &txs[3], &txs[5], &txs[4], &txs[4],
+        ];
+        handle_txs(&mut manager, stream, &None).await.unwrap();
+        assert!(manager.quarantine.is_empty());
+        assert_eq!(manager.next_tx_seq, 6);
+        assert_eq!(manager.store.next_tx_seq(), 6);
+        assert_eq!(
+            manager.store.get_context().unwrap(),
+            expected.store.get_context().unwrap()
+        );
+        assert_eq!(
+            manager.store.get_log_latest_block_number().unwrap(),
+            expected.store.get_log_latest_block_number().unwrap()
+        );
+        for seq in 0..6 {
+            assert_eq!(
+                manager.store.get_submission_context(seq).unwrap(),
+                expected.store.get_submission_context(seq).unwrap()
+            );
+        }
+        assert_eq!(
+            synced_seqs(&mut event_recv),
+            synced_seqs(&mut expected_recv)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_logs_with_quarantine_full() {
+        let (mut manager, mut event_recv) = new_manager().await;
+        manager.quarantine = TxQuarantine::new(QuarantineConfig {
+            capacity: 1,
+            window: Duration::from_secs(60),
+        });
+        let txs = new_txs(4);
+
+        // The tx 3 overflows the quarantine, so fetch again from the block of tx 0.
+        let stream = vec![&txs[0], &txs[2], &txs[3], &txs[1]];
+        let result = handle_txs(&mut manager, stream, &None).await;
+        assert!(matches!(result, Err(HandleDataError::SeqError(10))));
+        assert!(manager.quarantine.is_empty());
+        assert_eq!(manager.next_tx_seq, 1);
+        assert_eq!(synced_seqs(&mut event_recv), vec![0]);
+    }
+
+    #[tokio::test]
+    async fn test_handle_logs_with_tx_rejected() {
+        let (mut manager, mut event_recv) = new_manager().await;
+        let txs = new_txs(3);
+        let mut rejected = txs[1].clone();
+        rejected.0.size = 0;
+
+        // The store rejects the empty tx, which fails instead of fetching logs again.
+        let stream = vec![&txs[0], &rejected, &txs[2]];
+        let result = handle_txs(&mut manager, stream, &None).await;
+        assert!(matches!(result, Err(HandleDataError::CommonError(_))));
+        assert_eq!(manager.next_tx_seq, 1);
+        assert_eq!(manager.store.next_tx_seq(), 1);
+        assert_eq!(synced_seqs(&mut event_recv), vec![0]);
+    }
+
+    #[tokio::test]
+    async fn test_handle_gappy_logs_in_recover() {
+        let (mut manager, mut event_recv) = new_manager().await;
+        let txs = new_txs(4);
+
+        // The tx 1 is missing, so fetch again from the block of tx 0.
+        let stream = vec![&txs[0], &txs[2], &txs[3]];
+        let result = handle_txs(&mut manager, stream, &None).await;
+        assert!(matches!(result, Err(HandleDataError::SeqError(10))));
+        assert_eq!(manager.next_tx_seq, 1);
+        assert_eq!(num_synced(&mut event_recv), 1);
+
+        // Logs fetched again from block 10.
+        let stream = vec![&txs[0], &txs[1], &txs[2], &txs[3]];
+        handle_txs(&mut manager, stream, &None).await.unwrap();
+        assert_eq!(manager.next_tx_seq, 4);
+        assert_eq!(num_synced(&mut event_recv), 3);
+    }
+
+    #[tokio::test]
+    async fn test_handle_gappy_logs_in_watch() {
+        let (mut manager, mut event_recv) = new_manager().await;
+        let txs = new_txs(3);
+        let (progress_tx, mut progress_rx) = unbounded_channel();
+
+        // The tx 2 is applied once the tx 1 delivered within the window.
+        let stream = vec![&txs[0], &txs[2], &txs[1]];
+        handle_txs(&mut manager, stream, &Some(progress_tx.clone()))
+            .await
+            .unwrap();
+        assert!(progress_rx.try_recv().is_err());
+        assert_eq!(manager.next_tx_seq, 3);
+        assert_eq!(num_synced(&mut event_recv), 3);
+
+        // Watch is notified to fetch again from block 12 once the tx 3 is found missing for
+        // the window, and the parked tx 4 is dropped.
+        let (mut manager, mut event_recv) = new_manager().await;
+        manager.quarantine = TxQuarantine::new(QuarantineConfig {
+            capacity: 16,
+            window: Duration::ZERO,
+        });
+        let txs = new_txs(5);
+        let stream = vec![&txs[0], &txs[1], &txs[2], &txs[4], &txs[3]];
+        handle_txs(&mut manager, stream, &Some(progress_tx))
+            .await
+            .unwrap();
+        assert_eq!(progress_rx.try_recv().unwrap(), 12);
+        assert_eq!(manager.next_tx_seq, 4);
+        assert_eq!(num_synced(&mut event_recv), 4);
+    }
+
+    #[tokio::test]
+    async fn test_handle_data_shutdown() {
+        let (mut manager, mut event_recv) = new_manager().await;
+        let coordinator = ShutdownCoordinator::default();
+        let token = coordinator.token("log_sync");
+        manager.shutdown = token.signal();
+        let txs = new_txs(3);
+
+        handle_txs(&mut manager, vec![&txs[0]], &None)
+            .await
+            .unwrap();
+        assert_eq!(num_synced(&mut event_recv), 1);
+
+        // The fetched logs are not handled anymore once the node is shutting down.
+        let shutdown =
+            tokio::spawn(async move { coordinator.shutdown(Duration::from_secs(10)).await });
+        while !token.is_requested() {
+            tokio::task::yield_now().await;
+        }
+        let result = handle_txs(&mut manager, vec![&txs[1], &txs[2]], &None).await;
+        assert!(matches!(result, Err(HandleDataError::Shutdown)));
+        assert_eq!(manager.next_tx_seq, 1);
+        assert_eq!(num_synced(&mut event_recv), 0);
+
+        token.ack();
+        assert!(shutdown.await.unwrap().is_clean());
+    }
+
+    /// Syncs 4 txs, and only the blocks of the first 2 txs are synced in the watch mode.
+    async fn new_source_manager() -> LogSyncManager {
+        let (mut source, _) = new_manager().await;
+        let (tx, rx) = unbounded_channel();
+        for (t, block_number) in new_txs(4) {
+            // Only the blocks of the first 2 txs are synced in the watch mode.
+            let first_submission_index = if t.seq < 2 { Some(Some(t.seq)) } else { None };
+            tx.send(LogFetchProgress::Transaction((t, block_number, None, None)))
+                .unwrap();
+            tx.send(LogFetchProgress::SyncedBlock((
+                block_number,
+                H256::from_low_u64_be(block_number),
+                first_submission_index,
+            )))
+            .unwrap();
+        }
+        drop(tx);
+        source.handle_data(rx, &None).await.unwrap();
+        source
+    }
+
+    #[tokio::test]
+    async fn test_export_and_replay() {
+        let source = new_source_manager().await;
+        let mut exported = vec![];
+        assert_eq!(
+            export_log_entries(source.store.as_ref(), &mut exported).unwrap(),
+            4
+        );
+
+        let (mut replayed, mut event_recv) = new_manager().await;
+        replayed.log_fetcher = LogEntryFetcher::new(
+            &replayed.config,
+            replayed.log_fetcher.monitor().clone(),
+            LogSource::Replay(Box::new(Cursor::new(exported))),
+        )
+        .await
+        .unwrap();
+        let runtime = TestRuntime::default();
+        let rx = replayed
+            .log_fetcher
+            .start_replay(&runtime.task_executor)
+            .unwrap();
+        replayed.handle_data(rx, &None).await.unwrap();
+
+        assert_eq!(replayed.next_tx_seq, 4);
+        assert_eq!(num_synced(&mut event_recv), 4);
+        assert_eq!(
+            replayed.store.get_context().unwrap(),
+            source.store.get_context().unwrap()
+        );
+        assert_eq!(
+            replayed.store.get_sync_progress().unwrap(),
+            Some((13, H256::from_low_u64_be(13)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spawn_replay_without_rpc() {
+        let source = new_source_manager().await;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs.jsonl");
+        export_log_entries(source.store.as_ref(), std::fs::File::create(&path).unwrap()).unwrap();
+
+        // No rpc endpoint is configured at all.
+        let mut config = new_config();
+        config.replay_file = Some(path);
+        let store: Arc<dyn Store> = Arc::new(LogManager::memorydb(LogConfig::default()).unwrap());
+        let runtime = TestRuntime::default();
+        let coordinator = ShutdownCoordinator::default();
+        let (_, catch_up_end, monitor) = LogSyncManager::spawn(
+            config,
+            runtime.task_executor.clone(),
+            store.clone(),
+            watch::channel(IngestPolicy::default()).1,
+            coordinator.token("log_sync"),
+        )
+        .await
+        .unwrap();
+        assert!(monitor.rpc_client().status().is_empty());
+
+        // Stops at the end of file.
+        catch_up_end.await.unwrap();
+        assert!(monitor.is_caught_up());
+        assert_eq!(store.next_tx_seq(), 4);
+        assert_eq!(
+            store.get_context().unwrap(),
+            source.store.get_context().unwrap()
+        );
+        assert_eq!(
+            store.get_sync_progress().unwrap(),
+            Some((13, H256::from_low_u64_be(13)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_finalize_after_more_confirmations() {
+        let (mut manager, mut event_recv) = new_manager().await;
+        manager.config.finalize_confirmations = 2;
+        let txs = new_txs(3);
+        for (tx, _) in &txs {
+            let data = vec![tx.seq as u8 + 1; CHUNK_SIZE];
+            assert!(manager
+                .data_cache
+                .add_data(tx.data_merkle_root, tx.seq, data));
+        }
+
+        // Ingested at once, but finalized 2 blocks later.
+        handle_blocks(&mut manager, &txs, 10..=11).await.unwrap();
+        assert_eq!(manager.next_tx_seq, 2);
+        assert_eq!(num_synced(&mut event_recv), 2);
+        assert!(finalized(&manager).is_empty());
+
+        handle_blocks(&mut manager, &txs, 12..=13).await.unwrap();
+        assert_eq!(manager.next_tx_seq, 3);
+        assert_eq!(finalized(&manager), vec![0, 1]);
+        assert_eq!(manager.unconfirmed.keys().collect::<Vec<_>>(), vec![&2]);
+    }
+
+    #[tokio::test]
+    async fn test_finalize_synced_block_in_batch() {
+        let (mut manager, _) = new_manager().await;
+        let txs: Vec<_> = new_txs(3).into_iter().map(|(tx, _)| (tx, 10)).collect();
+        for (tx, _) in &txs {
+            let data = vec![tx.seq as u8 + 1; CHUNK_SIZE];
+            assert!(manager
+                .data_cache
+                .add_data(tx.data_merkle_root, tx.seq, data));
+        }
+
+        // Not finalized until the block is synced, even without more confirmations.
+        handle_txs(&mut manager, txs.iter().collect(), &None)
+            .await
+            .unwrap();
+        assert!(finalized(&manager).is_empty());
+        assert_eq!(manager.unconfirmed.len(), 3);
+
+        handle_blocks(&mut manager, &[], 10..=10).await.unwrap();
+        assert_eq!(finalized(&manager), vec![0, 1, 2]);
+        assert!(manager.unconfirmed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_finalize_by_chunk_pool_or_file_sync_after_confirmations() {
+        let (mut manager, _) = new_manager().await;
+        manager.config.finalize_confirmations = 2;
+        let txs = new_txs(2);
+
+        // The data is not cached by log sync, but uploaded or synced afterwards.
+        handle_blocks(&mut manager, &txs, 10..=11).await.unwrap();
+        for (tx, _) in &txs {
+            let data = vec![tx.seq as u8 + 1; CHUNK_SIZE];
+            manager
+                .store
+                .put_chunks(
+                    tx.seq,
+                    ChunkArray {
+                        data,
+                        start_index: 0,
+                    },
+                )
+                .unwrap();
+        }
+        // finalized in batch by chunk pool, and one by one by file sync
+        manager.store.finalize_txs(vec![0]).unwrap();
+        assert!(manager
+            .store
+            .finalize_tx_with_hash(1, txs[1].0.hash())
+            .unwrap());
+        assert!(finalized(&manager).is_empty());
+
+        handle_blocks(&mut manager, &txs, 12..=12).await.unwrap();
+        assert_eq!(finalized(&manager), vec![0]);
+        handle_blocks(&mut manager, &txs, 13..=13).await.unwrap();
+        assert_eq!(finalized(&manager), vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_reorg_between_confirmations() {
+        let (mut manager, mut event_recv) = new_manager().await;
+        manager.config.finalize_confirmations = 2;
+        let txs = new_txs(4);
+        for (tx, _) in &txs {
+            let data = vec![tx.seq as u8 + 1; CHUNK_SIZE];
+            assert!(manager
+                .data_cache
+                .add_data(tx.data_merkle_root, tx.seq, data));
+        }
+
+        // Block 10 is finalized, while blocks 11 and 12 are only ingested.
+        handle_blocks(&mut manager, &txs, 10..=12).await.unwrap();
+        assert_eq!(manager.next_tx_seq, 3);
+        assert_eq!(finalized(&manager), vec![0]);
+
+        // The reorg from block 11 lands exactly between the two watermarks.
+        let (tx, rx) = unbounded_channel();
+        tx.send(LogFetchProgress::Reverted(1)).unwrap();
+        drop(tx);
+        manager.handle_data(rx, &None).await.unwrap();
+        assert_eq!(manager.next_tx_seq, 1);
+        assert_eq!(manager.store.next_tx_seq(), 1);
+        assert!(manager.unconfirmed.is_empty());
+        assert_eq!(finalized(&manager), vec![0]);
+        assert_eq!(num_synced(&mut event_recv), 3);
+
+        // The reverted txs are finalized again with the cached data once confirmed.
+        handle_blocks(&mut manager, &txs, 11..=13).await.unwrap();
+        assert_eq!(manager.next_tx_seq, 4);
+        assert_eq!(finalized(&manager), vec![0, 1]);
+
+        handle_blocks(&mut manager, &txs, 14..=15).await.unwrap();
+        assert_eq!(finalized(&manager), vec![0, 1, 2, 3]);
+        assert!(manager.unconfirmed.is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::sync_manager::config::QuarantineConfig;
+use ethereum_types::{Address, H256};
+use shared_types::Transaction;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+/// Tx delivered ahead of the next tx seq, along with its submission block, tx hash and sender.
+pub struct QuarantinedTx {
+    pub tx: Transaction,
+    pub block_number: u64,
+    pub tx_hash: Option<H256>,
+    pub sender: Option<Address>,
+}
+
+/// Txs delivered out of order, e.g. by a load-balanced RPC endpoint, which are parked by seq
+/// until the gap before them is filled. Each tx is taken at most once, so that a tx is never
+/// applied twice even if delivered again.
+pub struct TxQuarantine {
+    txs: BTreeMap<u64, (QuarantinedTx, Instant)>,
+    config: QuarantineConfig,
+}
+
+impl TxQuarantine {
+    pub fn new(config: QuarantineConfig) -> Self {
+        Self {
+            txs: BTreeMap::new(),
+            config,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+
+    /// Parks the tx until taken, and returns false if the quarantine is full. The tx delivered
+    /// again replaces the parked one, but the window starts from the first delivery.
+    pub fn park(&mut self, tx: QuarantinedTx) -> bool {
+        let parked_at = match self.txs.get(&tx.tx.seq) {
+            Some((_, parked_at)) => *parked_at,
+            None if self.txs.len() >= self.config.capacity => return false,
+            None => Instant::now(),
+        };
+        self.txs.insert(tx.tx.seq, (tx, parked_at));
+        true
+    }
+
+    /// Takes the parked tx of `next_tx_seq` if any, and drops the parked txs before it, which
+    /// are applied already.
+    pub fn take_next(&mut self, next_tx_seq: u64) -> Option<QuarantinedTx> {
+        let parked = self.txs.split_off(&next_tx_seq);
+        self.txs = parked;
+        self.txs.remove(&next_tx_seq).map(|(tx, _)| tx)
+    }
+
+    /// Returns the time when the gap is regarded as never filled, i.e. the window passes since
+    /// the earliest tx parked.
+    pub fn expires_at(&self) -> Option<Instant> {
+        self.txs
+            .values()
+            .map(|(_, parked_at)| *parked_at + self.config.window)
+            .min()
+    }
+
+    /// Drops all the parked txs, e.g. to fetch them again, and returns the number dropped.
+    pub fn clear(&mut self) -> usize {
+        let num = self.txs.len();
+        self.txs.clear();
+        num
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_types::TransactionBuilder;
+    use std::time::Duration;
+
+    fn new_tx(seq: u64) -> QuarantinedTx {
+        QuarantinedTx {
+            tx: TransactionBuilder::new(seq)
+                .data_merkle_root(H256::repeat_byte(1))
+                .merkle_nodes(vec![(1, H256::repeat_byte(1))])
+                .start_entry_index(seq)
+                .size(1)
+                .build()
+                .unwrap(),
+            block_number: 10 + seq,
+            tx_hash: None,
+            sender: None,
+        }
+    }
+
+    #[test]
+    fn test_park_and_take() {
+        let mut quarantine = TxQuarantine::new(QuarantineConfig {
+            capacity: 2,
+            window: Duration::from_secs(60),
+        });
+        assert!(quarantine.expires_at().is_none());
+        assert!(quarantine.park(new_tx(5)));
+        assert!(quarantine.park(new_tx(3)));
+        let expires_at = quarantine.expires_at().unwrap();
+
+        // full, but the parked tx could be delivered again
+        assert!(!quarantine.park(new_tx(4)));
+        assert!(quarantine.park(new_tx(5)));
+        assert_eq!(quarantine.len(), 2);
+        assert_eq!(quarantine.expires_at(), Some(expires_at));
+
+        assert!(quarantine.take_next(2).is_none());
+        assert_eq!(quarantine.take_next(3).unwrap().block_number, 13);
+        assert!(quarantine.take_next(3).is_none());
+        assert!(quarantine.take_next(4).is_none());
+        assert_eq!(quarantine.len(), 1);
+
+        // the parked txs before the next seq are dropped
+        assert!(quarantine.take_next(6).is_none());
+        assert!(quarantine.is_empty());
+
+        assert!(quarantine.park(new_tx(7)));
+        assert_eq!(quarantine.clear(), 1);
+        assert!(quarantine.is_empty());
+    }
+}
@@ -0,0 +1,153 @@
+use crate::sync_manager::log_entry_fetcher::LogFetchProgress;
+use anyhow::{anyhow, Context, Result};
+use ethereum_types::H256;
+use jsonrpsee::tracing::{error, info};
+use serde::{Deserialize, Serialize};
+use shared_types::Transaction;
+use std::io::{BufRead, Write};
+use storage::log_store::Store;
+use task_executor::TaskExecutor;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+/// A line of the recorded logs, which is replayed in order.
+///
+/// All the transactions of a block are followed by the record of the block, which is the same
+/// as the order that log sync processes the `Submit` events.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum LogRecord {
+    #[serde(rename_all = "camelCase")]
+    Tx { block_number: u64, tx: Transaction },
+    #[serde(rename_all = "camelCase")]
+    Block {
+        block_number: u64,
+        block_hash: H256,
+        first_submission_index: Option<u64>,
+    },
+}
+
+impl From<LogRecord> for LogFetchProgress {
+    fn from(record: LogRecord) -> Self {
+        match record {
+            LogRecord::Tx { block_number, tx } => {
+                LogFetchProgress::Transaction((tx, block_number, None, None))
+            }
+            LogRecord::Block {
+                block_number,
+                block_hash,
+                first_submission_index,
+            } => LogFetchProgress::SyncedBlock((
+                block_number,
+                block_hash,
+                Some(first_submission_index),
+            )),
+        }
+    }
+}
+
+/// Reads the recorded logs in JSON lines, instead of fetching from blockchain. The channel is
+/// closed at the end, or on any invalid line.
+pub(crate) fn start_replay(
+    reader: Box<dyn BufRead + Send + Sync>,
+    executor: &TaskExecutor,
+) -> UnboundedReceiver<LogFetchProgress> {
+    let (replay_tx, replay_rx) = tokio::sync::mpsc::unbounded_channel();
+    executor.spawn_blocking(
+        move || match read_records(reader, &replay_tx) {
+            Ok(n) => info!("log replay read {} records", n),
+            Err(e) => error!("log replay error: e={:?}", e),
+        },
+        "log replay",
+    );
+    replay_rx
+}
+
+fn read_records(
+    reader: impl BufRead,
+    replay_tx: &UnboundedSender<LogFetchProgress>,
+) -> Result<u64> {
+    let mut n = 0;
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: LogRecord = serde_json::from_str(&line)
+            .with_context(|| format!("invalid record at line {}", index + 1))?;
+        replay_tx
+            .send(record.into())
+            .map_err(|_| anyhow!("log replay channel closed"))?;
+        n += 1;
+    }
+    Ok(n)
+}
+
+/// Exports all the transactions in the store as recorded logs in JSON lines, which could be
+/// replayed into another node via `log_sync_replay_file`. Returns the number of transactions.
+///
+/// The block of a transaction is only known if synced in the watch mode, and others are
+/// recorded in the next known block.
+pub fn export_log_entries(store: &dyn Store, mut writer: impl Write) -> Result<u64> {
+    let mut blocks: Vec<(u64, H256, Option<u64>)> = store
+        .get_block_hashes()?
+        .into_iter()
+        .map(|(number, block)| (number, block.block_hash, block.first_submission_index))
+        .collect();
+    blocks.sort_by_key(|(number, _, _)| *number);
+    if let Some((number, hash)) = store.get_sync_progress()? {
+        if blocks.last().map_or(true, |(last, _, _)| *last < number) {
+            blocks.push((number, hash, None));
+        }
+    }
+
+    let next_tx_seq = store.next_tx_seq();
+    let mut seq = 0;
+    for (index, (block_number, block_hash, first_submission_index)) in blocks.iter().enumerate() {
+        // Transactions until the next block with submissions, including those of the earlier
+        // unknown blocks.
+        let end = if first_submission_index.is_some() || index + 1 == blocks.len() {
+            blocks[index + 1..]
+                .iter()
+                .find_map(|(_, _, first)| *first)
+                .unwrap_or(next_tx_seq)
+        } else {
+            seq
+        };
+        while seq < end {
+            write_tx(store, &mut writer, seq, *block_number)?;
+            seq += 1;
+        }
+
+        write_record(
+            &mut writer,
+            &LogRecord::Block {
+                block_number: *block_number,
+                block_hash: *block_hash,
+                first_submission_index: *first_submission_index,
+            },
+        )?;
+    }
+
+    // No block is known at all.
+    let block_number = store.get_log_latest_block_number()?.unwrap_or_default();
+    while seq < next_tx_seq {
+        write_tx(store, &mut writer, seq, block_number)?;
+        seq += 1;
+    }
+    writer.flush()?;
+
+    Ok(next_tx_seq)
+}
+
+fn write_tx(store: &dyn Store, writer: &mut impl Write, seq: u64, block_number: u64) -> Result<()> {
+    let tx = store
+        .get_tx_by_seq_number(seq)?
+        .ok_or_else(|| anyhow!("tx missing, seq={}", seq))?;
+    write_record(writer, &LogRecord::Tx { block_number, tx })
+}
+
+fn write_record(writer: &mut impl Write, record: &LogRecord) -> Result<()> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
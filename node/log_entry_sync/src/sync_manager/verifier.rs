@@ -0,0 +1,108 @@
+use crate::sync_manager::log_entry_fetcher::LogEntryFetcher;
+use crate::sync_manager::{metrics, LogSyncMonitor};
+use anyhow::{anyhow, Result};
+use ethers::prelude::Middleware;
+use jsonrpsee::tracing::{debug, info, warn};
+use std::sync::Arc;
+use std::time::Duration;
+use storage::log_store::Store;
+use task_executor::shutdown::ShutdownSignal;
+
+/// Verifies the log entries of a read-only store against blockchain, without writing any
+/// progress into the store.
+///
+/// The store is expected to fall behind the chain, e.g. a snapshot, so the lag is only reported
+/// via the monitor, while a reorg of the synced block is warned since the store may serve the
+/// reverted files.
+pub(crate) struct Verifier {
+    log_fetcher: LogEntryFetcher,
+    store: Arc<dyn Store>,
+    monitor: LogSyncMonitor,
+    ingest_confirmations: u64,
+}
+
+impl Verifier {
+    pub(crate) fn new(
+        log_fetcher: LogEntryFetcher,
+        store: Arc<dyn Store>,
+        monitor: LogSyncMonitor,
+        ingest_confirmations: u64,
+    ) -> Self {
+        Verifier {
+            log_fetcher,
+            store,
+            monitor,
+            ingest_confirmations,
+        }
+    }
+
+    pub(crate) async fn run(self, check_interval: Duration, mut shutdown: ShutdownSignal) {
+        info!("Log sync started in verify-only mode");
+
+        loop {
+            if let Err(e) = self.check().await {
+                warn!(?e, "Failed to verify log entries against blockchain");
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(check_interval) => {}
+                _ = shutdown.requested() => return,
+            }
+        }
+    }
+
+    /// Checks the latest synced block and the number of txs of store against blockchain.
+    async fn check(&self) -> Result<()> {
+        let provider = self.log_fetcher.provider();
+        let latest = provider.get_block_number().await?.as_u64();
+        self.monitor
+            .update_heads(latest, latest.saturating_sub(self.ingest_confirmations));
+
+        let (block_number, block_hash) = match self.store.get_sync_progress()? {
+            Some(progress) => progress,
+            None => {
+                warn!("No log sync progress in store");
+                return Ok(());
+            }
+        };
+        self.monitor.update_synced(block_number);
+
+        let block = provider
+            .get_block(block_number)
+            .await?
+            .ok_or_else(|| anyhow!("block {} not found", block_number))?;
+        if block.hash != Some(block_hash) {
+            warn!(
+                block_number,
+                expected = ?block.hash,
+                actual = ?block_hash,
+                "Synced block of store reorged on blockchain"
+            );
+            metrics::VERIFY_FAILURES.inc(1);
+            return Ok(());
+        }
+
+        let num_submissions = self
+            .log_fetcher
+            .flow_contract()
+            .num_submissions()
+            .block(block_number)
+            .call()
+            .await?
+            .as_u64();
+        let next_tx_seq = self.store.next_tx_seq();
+        if next_tx_seq != num_submissions {
+            warn!(
+                block_number,
+                expected = num_submissions,
+                actual = next_tx_seq,
+                "Number of txs in store mismatched with blockchain"
+            );
+            metrics::VERIFY_FAILURES.inc(1);
+        } else {
+            debug!(block_number, next_tx_seq, "Log entries verified");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use crate::sync_manager::{metrics, LogSyncMonitor};
+use crate::FailoverClient;
+use ethers::prelude::JsonRpcClient;
+use jsonrpsee::tracing::warn;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Watches the progress of log sync, and recovers the rpc connection once no new block is
+/// applied for the stall timeout, e.g. the rpc endpoint stops delivering new blocks silently.
+pub(crate) struct Watchdog<C> {
+    monitor: LogSyncMonitor,
+    /// Client that log sync fetches logs via.
+    rpc_client: FailoverClient<C>,
+    last_recovery: Option<Instant>,
+}
+
+impl<C: JsonRpcClient> Watchdog<C> {
+    pub(crate) fn new(monitor: LogSyncMonitor, rpc_client: FailoverClient<C>) -> Self {
+        Watchdog {
+            monitor,
+            rpc_client,
+            last_recovery: None,
+        }
+    }
+
+    pub(crate) async fn run(mut self, check_interval: Duration) {
+        loop {
+            tokio::time::sleep(check_interval).await;
+            self.check();
+        }
+    }
+
+    /// Updates metrics and recovers the rpc connection if stalled, which happens at most once
+    /// within the stall timeout. Returns whether recovered.
+    fn check(&mut self) -> bool {
+        let since_last_progress = self.monitor.since_last_progress();
+        let lag = self.monitor.lag();
+        metrics::SECONDS_SINCE_PROGRESS.update(since_last_progress.as_secs() as usize);
+        metrics::SYNC_LAG_BLOCKS.update(lag.unwrap_or_default() as usize);
+
+        if !self.monitor.is_stalled() {
+            return false;
+        }
+        if matches!(self.last_recovery, Some(t) if t.elapsed() < self.monitor.stall_timeout) {
+            return false;
+        }
+
+        let rpc_client = &self.rpc_client;
+        warn!(
+            synced = ?self.monitor.synced_block_number(),
+            latest = ?self.monitor.heads().latest,
+            ?lag,
+            since_last_progress_secs = since_last_progress.as_secs(),
+            endpoint = ?rpc_client.active_endpoint(),
+            "Log sync stalled, recover rpc connection"
+        );
+        rpc_client.recover_stalled();
+        metrics::STALL_RECOVERIES.inc(1);
+        self.last_recovery = Some(Instant::now());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use ethers::prelude::{Middleware, Provider, ProviderError, U64};
+    use serde::{de::DeserializeOwned, Serialize};
+    use std::fmt::Debug;
+    use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Endpoint of a chain that produces a block per second since `genesis`, which stops
+    /// delivering new blocks silently once frozen.
+    #[derive(Debug)]
+    struct MockEndpoint {
+        genesis: Instant,
+        frozen: Arc<AtomicBool>,
+        head: AtomicU64,
+    }
+
+    #[async_trait]
+    impl JsonRpcClient for MockEndpoint {
+        type Error = ProviderError;
+
+        async fn request<T, R>(&self, method: &str, _params: T) -> Result<R, Self::Error>
+        where
+            T: Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            assert_eq!(method, "eth_blockNumber");
+            if !self.frozen.load(Ordering::SeqCst) {
+                self.head
+                    .store(self.genesis.elapsed().as_secs(), Ordering::SeqCst);
+            }
+            let head = U64::from(self.head.load(Ordering::SeqCst));
+            Ok(serde_json::from_value(serde_json::to_value(head)?)?)
+        }
+    }
+
+    /// Creates a client of 2 endpoints, and the first one stays frozen once `frozen` is set,
+    /// even if reconnected. Counts the connections.
+    fn new_rpc_client(
+        frozen: Arc<AtomicBool>,
+        connections: Arc<AtomicUsize>,
+    ) -> FailoverClient<MockEndpoint> {
+        let genesis = Instant::now();
+        let urls = vec!["http://127.0.0.1:1".into(), "http://127.0.0.1:2".into()];
+        FailoverClient::with_connector(urls, 10, move |url| {
+            connections.fetch_add(1, Ordering::SeqCst);
+            let frozen = if url.ends_with(":1") {
+                frozen.clone()
+            } else {
+                Default::default()
+            };
+            Ok(MockEndpoint {
+                genesis,
+                frozen,
+                head: AtomicU64::new(0),
+            })
+        })
+        .unwrap()
+    }
+
+    fn new_monitor(stall_timeout: Duration) -> LogSyncMonitor {
+        LogSyncMonitor::new(
+            FailoverClient::new(vec![], 0),
+            Default::default(),
+            stall_timeout,
+        )
+    }
+
+    /// Syncs to the head of the active endpoint as log sync does, and returns the head.
+    async fn sync(
+        provider: &Provider<FailoverClient<MockEndpoint>>,
+        monitor: &LogSyncMonitor,
+    ) -> u64 {
+        let head = provider.get_block_number().await.unwrap().as_u64();
+        monitor.update_heads(head, head);
+        monitor.update_synced(head);
+        head
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_recover_frozen_provider() {
+        let frozen = Arc::new(AtomicBool::new(false));
+        let connections = Arc::new(AtomicUsize::new(0));
+        let client = new_rpc_client(frozen.clone(), connections.clone());
+        let provider = Provider::new(client.clone());
+        let monitor = new_monitor(STALL_TIMEOUT);
+        let mut watchdog = Watchdog::new(monitor.clone(), client.clone());
+
+        for _ in 0..5 {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            sync(&provider, &monitor).await;
+            assert!(!watchdog.check());
+        }
+        assert_eq!(monitor.synced_block_number(), Some(5));
+
+        // The provider keeps reporting the same head, which is detected after the stall
+        // timeout.
+        frozen.store(true, Ordering::SeqCst);
+        let frozen_at = Instant::now();
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            assert_eq!(sync(&provider, &monitor).await, 5);
+            if watchdog.check() {
+                break;
+            }
+            assert!(!monitor.is_stalled());
+        }
+        assert_eq!(frozen_at.elapsed(), STALL_TIMEOUT);
+        assert_eq!(monitor.lag(), Some(0));
+        assert_eq!(client.active_endpoint().unwrap(), "http://127.0.0.1:2");
+        assert!(!client.status()[0].healthy);
+        assert_eq!(connections.load(Ordering::SeqCst), 3);
+
+        // Not recovered again until the stall timeout elapses.
+        assert!(!watchdog.check());
+
+        // Progress with the other endpoint.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(sync(&provider, &monitor).await, 16);
+        assert!(!monitor.is_stalled());
+        assert!(!watchdog.check());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stall_detection_disabled() {
+        let frozen = Arc::new(AtomicBool::new(true));
+        let client = new_rpc_client(frozen, Default::default());
+        let provider = Provider::new(client.clone());
+        let monitor = new_monitor(Duration::ZERO);
+        let mut watchdog = Watchdog::new(monitor.clone(), client.clone());
+        for _ in 0..30 {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            assert_eq!(sync(&provider, &monitor).await, 0);
+            assert!(!monitor.is_stalled());
+            assert!(!watchdog.check());
+        }
+        assert_eq!(client.active_endpoint().unwrap(), "http://127.0.0.1:1");
+    }
+}
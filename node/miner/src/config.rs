@@ -13,6 +13,7 @@ use ethers::providers::RetryClient;
 use ethers::providers::RetryClientBuilder;
 use ethers::signers::LocalWallet;
 use ethers::signers::Signer;
+use log_entry_sync::{FailoverClient, RpcClient};
 use storage::config::ShardConfig;
 
 pub struct MinerConfig {
@@ -26,11 +27,21 @@ pub struct MinerConfig {
     pub(crate) iter_batch: usize,
     pub(crate) shard_config: ShardConfig,
     pub(crate) context_query_interval: Duration,
+    /// Keep mining on the last confirmed context for this duration once the context could not
+    /// be confirmed by the rpc endpoints.
+    pub(crate) context_grace_period: Duration,
     pub(crate) rate_limit_retries: u32,
     pub(crate) timeout_retries: u32,
     pub(crate) initial_backoff: u64,
 }
 
+/// Parameters of miner config that could be reloaded at runtime without restarting the node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MinerDynamicConfig {
+    pub cpu_percentage: u64,
+    pub iter_batch: usize,
+}
+
 pub type MineServiceMiddleware = SignerMiddleware<Arc<Provider<RetryClient<Http>>>, LocalWallet>;
 
 impl MinerConfig {
@@ -45,6 +56,7 @@ impl MinerConfig {
         cpu_percentage: u64,
         iter_batch: usize,
         context_query_seconds: u64,
+        context_grace_seconds: u64,
         shard_config: ShardConfig,
         rate_limit_retries: u32,
         timeout_retries: u32,
@@ -61,24 +73,35 @@ impl MinerConfig {
             iter_batch,
             shard_config,
             context_query_interval: Duration::from_secs(context_query_seconds),
+            context_grace_period: Duration::from_secs(context_grace_seconds),
             rate_limit_retries,
             timeout_retries,
             initial_backoff,
         })
     }
 
+    fn make_retry_client(&self) -> Result<RetryClient<Http>, String> {
+        Ok(RetryClientBuilder::default()
+            .rate_limit_retries(self.rate_limit_retries)
+            .timeout_retries(self.timeout_retries)
+            .initial_backoff(Duration::from_millis(self.initial_backoff))
+            .build(
+                Http::from_str(&self.rpc_endpoint_url)
+                    .map_err(|e| format!("Cannot parse blockchain endpoint: {:?}", e))?,
+                Box::new(HttpRateLimitRetryPolicy),
+            ))
+    }
+
     pub(crate) fn make_provider(&self) -> Result<Arc<Provider<RetryClient<Http>>>, String> {
-        Ok(Arc::new(Provider::new(
-            RetryClientBuilder::default()
-                .rate_limit_retries(self.rate_limit_retries)
-                .timeout_retries(self.timeout_retries)
-                .initial_backoff(Duration::from_millis(self.initial_backoff))
-                .build(
-                    Http::from_str(&self.rpc_endpoint_url)
-                        .map_err(|e| format!("Cannot parse blockchain endpoint: {:?}", e))?,
-                    Box::new(HttpRateLimitRetryPolicy),
-                ),
-        )))
+        Ok(Arc::new(Provider::new(self.make_retry_client()?)))
+    }
+
+    /// Creates the rpc client of the only configured endpoint.
+    pub(crate) fn make_rpc_client(&self) -> Result<RpcClient, String> {
+        Ok(FailoverClient::new(
+            vec![(self.rpc_endpoint_url.clone(), self.make_retry_client()?)],
+            0,
+        ))
     }
 
     pub(crate) async fn make_signing_provider(&self) -> Result<MineServiceMiddleware, String> {
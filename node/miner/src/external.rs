@@ -0,0 +1,41 @@
+use crate::recall_range::RecallRange;
+use ethereum_types::{H256, U256};
+use tokio::sync::mpsc;
+
+pub type ReplySender<T> = mpsc::UnboundedSender<T>;
+
+/// Mine puzzle of the current context, which external provers, e.g. on a GPU box, solve with
+/// the sealed data exported from the node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MinePuzzle {
+    pub epoch: u64,
+    pub context_digest: H256,
+    pub context_flow_root: H256,
+    pub flow_length: u64,
+    pub target_quality: U256,
+    pub miner_id: H256,
+    /// Recall range of the local shard and mine range.
+    pub range: RecallRange,
+}
+
+/// Answer found by an external prover, which is validated against the local sealed data before
+/// submitted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExternalAnswer {
+    pub context_digest: H256,
+    pub nonce: H256,
+    /// Index of the hit seal in the recalled load chunk.
+    pub seal_offset: usize,
+    /// Quality computed by the external prover, which must be the same as locally computed.
+    pub quality: Option<U256>,
+}
+
+/// External answer validated and queued for submission.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AcceptedAnswer {
+    pub context_digest: H256,
+    pub nonce: H256,
+    pub recall_position: u64,
+    pub seal_offset: usize,
+    pub quality: U256,
+}
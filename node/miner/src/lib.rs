@@ -5,6 +5,7 @@ extern crate contract_interface;
 extern crate lazy_static;
 
 mod config;
+mod external;
 mod loader;
 mod metrics;
 mod mine;
@@ -12,14 +13,18 @@ mod miner_id;
 mod monitor;
 pub mod pora;
 mod recall_range;
+mod reward;
 mod sealer;
 mod service;
 mod submitter;
 mod watcher;
 
-pub use config::MinerConfig;
+pub use config::{MinerConfig, MinerDynamicConfig};
+pub use external::{AcceptedAnswer, ExternalAnswer, MinePuzzle};
 pub use loader::PoraLoader;
 pub use mine::MineRangeConfig;
 pub use miner_id::load_miner_id;
+pub use recall_range::RecallRange;
 pub use service::{MineService, MinerMessage};
 pub use storage::config::ShardConfig;
+pub use watcher::{MineContextState, MineContextStatus};
@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use storage::error::StoreError;
 use storage::log_store::MineLoadChunk;
 use storage_async::Store;
 
@@ -11,8 +12,15 @@ pub trait PoraLoader: Send + Sync {
 impl PoraLoader for Store {
     async fn load_sealed_data(&self, chunk_index: u64) -> Option<MineLoadChunk> {
         match self.load_sealed_data(chunk_index).await {
-            Ok(Some(chunk)) => Some(chunk),
-            _ => None,
+            Ok(chunk) => chunk,
+            Err(e) => {
+                // The recall position is skipped either way, but only a busy store is expected.
+                match StoreError::of(&e) {
+                    Some(err) if err.is_retryable() => debug!(%chunk_index, %err, "Store busy"),
+                    _ => warn!(%chunk_index, "Failed to load sealed data: {:?}", e),
+                }
+                None
+            }
         }
     }
 }
@@ -3,17 +3,18 @@ use ethereum_types::{H256, U256};
 use rand::{self, Rng};
 use std::time;
 use task_executor::TaskExecutor;
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::{broadcast, mpsc, watch};
 use tokio::time::{sleep, Duration, Instant};
 
 use storage::config::ShardConfig;
 use zgs_spec::{SECTORS_PER_LOAD, SECTORS_PER_MAX_MINING_RANGE, SECTORS_PER_PRICING};
 
+use crate::external::{AcceptedAnswer, ExternalAnswer, MinePuzzle};
 use crate::recall_range::RecallRange;
 use crate::{
     pora::{AnswerWithoutProof, Miner},
     watcher::MineContextMessage,
-    MinerConfig, MinerMessage, PoraLoader,
+    MinerConfig, MinerDynamicConfig, MinerMessage, PoraLoader,
 };
 
 use std::sync::Arc;
@@ -22,6 +23,7 @@ pub struct PoraService {
     mine_context_receiver: broadcast::Receiver<MineContextMessage>,
     mine_answer_sender: mpsc::UnboundedSender<AnswerWithoutProof>,
     msg_recv: broadcast::Receiver<MinerMessage>,
+    config_recv: watch::Receiver<MinerDynamicConfig>,
     loader: Arc<dyn PoraLoader>,
 
     puzzle: Option<PoraPuzzle>,
@@ -55,6 +57,10 @@ impl PoraPuzzle {
     pub fn context_digest(&self) -> H256 {
         H256(self.context.digest)
     }
+
+    pub fn epoch(&self) -> u64 {
+        self.context.epoch.as_u64()
+    }
 }
 #[derive(Clone, Debug, Default)]
 pub struct MineRangeConfig {
@@ -116,6 +122,7 @@ impl PoraService {
         loader: Arc<dyn PoraLoader>,
         config: &MinerConfig,
         miner_id: H256,
+        config_recv: watch::Receiver<MinerDynamicConfig>,
     ) -> mpsc::UnboundedReceiver<AnswerWithoutProof> {
         let (mine_answer_sender, mine_answer_receiver) =
             mpsc::unbounded_channel::<AnswerWithoutProof>();
@@ -128,6 +135,7 @@ impl PoraService {
             mine_context_receiver,
             mine_answer_sender,
             msg_recv,
+            config_recv,
             puzzle: None,
             mine_range,
             miner_id,
@@ -143,7 +151,6 @@ impl PoraService {
         let mut mining_enabled = true;
         let mut channel_opened = true;
 
-        let cpu_percent: u64 = self.cpu_percentage;
         let diastole = sleep(Duration::from_secs(0));
         tokio::pin!(diastole);
 
@@ -173,6 +180,18 @@ impl PoraService {
                             self.mine_range.shard_config = shard_config;
                             self.report_reason_if_mine_stop("update shard");
                         }
+                        Ok(MinerMessage::GetMinePuzzle(reply)) => {
+                            let _ = reply.send(self.mine_puzzle());
+                        }
+                        Ok(MinerMessage::SubmitExternalAnswer(answer, reply)) => {
+                            let result = self.submit_external_answer(answer).await;
+                            if let Err(reason) = &result {
+                                info!(?answer, %reason, "External PoRA answer rejected");
+                            }
+                            let _ = reply.send(result);
+                        }
+                        // Handled by the mine context watcher.
+                        Ok(MinerMessage::GetContextStatus(_)) => {}
                         Err(broadcast::error::RecvError::Closed) => {
                             warn!("Unexpected: Mine service config channel closed.");
                             channel_opened = false;
@@ -183,6 +202,13 @@ impl PoraService {
                     }
                 }
 
+                Ok(()) = self.config_recv.changed() => {
+                    let dynamic = *self.config_recv.borrow_and_update();
+                    info!("Miner config reloaded: {:?}", dynamic);
+                    self.cpu_percentage = dynamic.cpu_percentage;
+                    self.iter_batch = dynamic.iter_batch;
+                }
+
                 maybe_msg = self.mine_context_receiver.recv() => {
                     match maybe_msg {
                         Ok(msg) => {
@@ -201,7 +227,7 @@ impl PoraService {
                 }
 
                 _ = async {}, if mining_enabled
-                                && cpu_percent > 0
+                                && self.cpu_percentage > 0
                                 && self.as_miner().is_ok()
                                 && diastole.is_elapsed() => {
                     let nonce = H256(rand::thread_rng().gen());
@@ -214,7 +240,8 @@ impl PoraService {
                         if self.mine_answer_sender.send(answer).is_err() {
                             warn!("Mine submitter channel closed");
                         }
-                    } else if cpu_percent < 100 {
+                    } else if self.cpu_percentage < 100 {
+                        let cpu_percent = self.cpu_percentage;
                         // 2^64 ns = 500 years
                         let elapsed = timer.elapsed().as_nanos() as u64;
                         let diastole_time = elapsed / cpu_percent * (100 - cpu_percent);
@@ -260,9 +287,235 @@ impl PoraService {
         })
     }
 
+    fn mine_puzzle(&self) -> Option<MinePuzzle> {
+        let miner = self.as_miner().ok()?;
+        Some(MinePuzzle {
+            epoch: miner.context.epoch.as_u64(),
+            context_digest: H256(miner.context.digest),
+            context_flow_root: H256(miner.context.flow_root),
+            flow_length: miner.context.flow_length.as_u64(),
+            target_quality: *miner.target_quality,
+            miner_id: self.miner_id,
+            range: miner.range,
+        })
+    }
+
+    /// Validates the external answer in the same way as the internal hashing, and queues it
+    /// for submission, whose result is recorded in the miner rewards.
+    async fn submit_external_answer(
+        &self,
+        answer: ExternalAnswer,
+    ) -> Result<AcceptedAnswer, String> {
+        let miner = self.as_miner()?;
+        if answer.context_digest != H256(miner.context.digest) {
+            return Err("inconsistent context digest".into());
+        }
+
+        let validated = miner
+            .validate_answer(answer.nonce, answer.seal_offset)
+            .await?;
+        if matches!(answer.quality, Some(quality) if quality != validated.quality) {
+            return Err(format!("quality mismatch, expected {}", validated.quality));
+        }
+
+        let accepted = AcceptedAnswer {
+            context_digest: validated.context_digest,
+            nonce: validated.nonce,
+            recall_position: validated.recall_position,
+            seal_offset: validated.seal_offset,
+            quality: validated.quality,
+        };
+        info!(?accepted, "Hit external PoRA answer");
+        self.mine_answer_sender
+            .send(validated)
+            .map_err(|_| "mine submitter channel closed".to_string())?;
+        Ok(accepted)
+    }
+
     fn report_reason_if_mine_stop(&self, event: &'static str) {
         if let Err(reason) = self.as_miner() {
             info!(reason, "Mine stopped on {}", event);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use storage::log_store::MineLoadChunk;
+    use zgs_spec::SEALS_PER_LOAD;
+
+    const NUM_LOAD_CHUNKS: u64 = 4;
+
+    fn copy_chunk(chunk: &MineLoadChunk) -> MineLoadChunk {
+        MineLoadChunk {
+            loaded_chunk: chunk.loaded_chunk.clone(),
+            availabilities: chunk.availabilities,
+            seal_contexts: chunk.seal_contexts,
+        }
+    }
+
+    #[derive(Default)]
+    struct MemoryLoader(Mutex<HashMap<u64, MineLoadChunk>>);
+
+    #[async_trait]
+    impl PoraLoader for MemoryLoader {
+        async fn load_sealed_data(&self, index: u64) -> Option<MineLoadChunk> {
+            self.0.lock().unwrap().get(&index).map(copy_chunk)
+        }
+    }
+
+    fn create_service(
+        loader: Arc<dyn PoraLoader>,
+        target_quality: U256,
+    ) -> (PoraService, mpsc::UnboundedReceiver<AnswerWithoutProof>) {
+        let (mine_answer_sender, mine_answer_receiver) = mpsc::unbounded_channel();
+        let (_, mine_context_receiver) = broadcast::channel(1);
+        let (_, msg_recv) = broadcast::channel(1);
+        let (_, config_recv) = watch::channel(MinerDynamicConfig {
+            cpu_percentage: 0,
+            iter_batch: 0,
+        });
+        let context = MineContext {
+            epoch: 3.into(),
+            mine_start: 0.into(),
+            flow_root: [1u8; 32],
+            flow_length: (NUM_LOAD_CHUNKS * SECTORS_PER_LOAD as u64).into(),
+            block_digest: [2u8; 32],
+            digest: [3u8; 32],
+        };
+        let service = PoraService {
+            mine_context_receiver,
+            mine_answer_sender,
+            msg_recv,
+            config_recv,
+            loader,
+            puzzle: Some(PoraPuzzle::new(context, target_quality, 1)),
+            mine_range: MineRangeConfig {
+                start_position: Some(0),
+                end_position: Some(u64::MAX),
+                shard_config: Default::default(),
+            },
+            miner_id: H256::from_low_u64_be(4),
+            cpu_percentage: 0,
+            iter_batch: 0,
+        };
+        (service, mine_answer_receiver)
+    }
+
+    /// Solves the puzzle with the exported sealed data, in the same way as the internal hashing.
+    async fn solve_externally(puzzle: &MinePuzzle, exported: MemoryLoader) -> AnswerWithoutProof {
+        let context = MineContext {
+            epoch: puzzle.epoch.into(),
+            mine_start: 0.into(),
+            flow_root: puzzle.context_flow_root.0,
+            flow_length: puzzle.flow_length.into(),
+            block_digest: [0u8; 32],
+            digest: puzzle.context_digest.0,
+        };
+        let mine_range_config = MineRangeConfig {
+            start_position: Some(0),
+            end_position: Some(u64::MAX),
+            shard_config: Default::default(),
+        };
+        let prover = Miner {
+            range: puzzle.range,
+            miner_id: &puzzle.miner_id,
+            context: &context,
+            target_quality: &puzzle.target_quality,
+            loader: &exported,
+            mine_range_config: &mine_range_config,
+        };
+        prover
+            .batch_iteration(H256(rand::thread_rng().gen()), 100_000)
+            .await
+            .expect("no answer found")
+    }
+
+    #[tokio::test]
+    async fn test_external_answer_round_trip() {
+        let local = Arc::new(MemoryLoader::default());
+        for index in 0..NUM_LOAD_CHUNKS {
+            let mut chunk = MineLoadChunk::default();
+            for (seal, available) in chunk
+                .loaded_chunk
+                .iter_mut()
+                .zip(chunk.availabilities.iter_mut())
+            {
+                rand::thread_rng().fill(&mut seal[..]);
+                *available = true;
+            }
+            local.0.lock().unwrap().insert(index, chunk);
+        }
+        let (service, mut answer_recv) = create_service(local.clone(), U256::MAX / 256);
+
+        // Export the puzzle along with the sealed batches.
+        let puzzle = service.mine_puzzle().unwrap();
+        let exported = MemoryLoader::default();
+        for index in 0..NUM_LOAD_CHUNKS {
+            let chunk = local.load_sealed_data(index).await.unwrap();
+            exported.0.lock().unwrap().insert(index, chunk);
+        }
+
+        let found = solve_externally(&puzzle, exported).await;
+        let answer = ExternalAnswer {
+            context_digest: puzzle.context_digest,
+            nonce: found.nonce,
+            seal_offset: found.seal_offset,
+            quality: Some(found.quality),
+        };
+
+        // Rejected unless identical to the local validation.
+        for (invalid, reason) in [
+            (
+                ExternalAnswer {
+                    context_digest: H256::zero(),
+                    ..answer
+                },
+                "inconsistent context digest",
+            ),
+            (
+                ExternalAnswer {
+                    seal_offset: SEALS_PER_LOAD,
+                    ..answer
+                },
+                "seal offset out of bound",
+            ),
+            (
+                ExternalAnswer {
+                    quality: Some(found.quality + 1),
+                    ..answer
+                },
+                "quality mismatch",
+            ),
+        ] {
+            let err = service.submit_external_answer(invalid).await.unwrap_err();
+            assert!(err.starts_with(reason), "{}", err);
+        }
+        assert!(answer_recv.try_recv().is_err());
+
+        let accepted = service.submit_external_answer(answer).await.unwrap();
+        assert_eq!(accepted.recall_position, found.recall_position);
+        assert_eq!(accepted.quality, found.quality);
+        let submitted = answer_recv.try_recv().unwrap();
+        assert_eq!(submitted.nonce, found.nonce);
+        assert_eq!(submitted.recall_position, found.recall_position);
+        assert_eq!(submitted.sealed_data, found.sealed_data);
+
+        // Never submitted if the data could not be proved locally.
+        let load_index = found.recall_position / SECTORS_PER_LOAD as u64;
+        local
+            .0
+            .lock()
+            .unwrap()
+            .get_mut(&load_index)
+            .unwrap()
+            .availabilities[found.seal_offset] = false;
+        let err = service.submit_external_answer(answer).await.unwrap_err();
+        assert_eq!(err, "sealed data not available");
+        assert!(answer_recv.try_recv().is_err());
+    }
+}
@@ -7,7 +7,9 @@ use ethereum_types::{H256, U256};
 use lighthouse_metrics::inc_counter;
 use storage::log_store::MineLoadChunk;
 use tiny_keccak::{Hasher, Keccak};
-use zgs_spec::{BYTES_PER_SCRATCHPAD, BYTES_PER_SEAL, SECTORS_PER_LOAD, SECTORS_PER_SEAL};
+use zgs_spec::{
+    BYTES_PER_SCRATCHPAD, BYTES_PER_SEAL, SEALS_PER_LOAD, SECTORS_PER_LOAD, SECTORS_PER_SEAL,
+};
 
 pub const BLAKE2B_OUTPUT_BYTES: usize = 64;
 pub const KECCAK256_OUTPUT_BYTES: usize = 32;
@@ -37,6 +39,7 @@ pub struct AnswerWithoutProof {
     pub range: RecallRange,
     pub recall_position: u64,
     pub seal_offset: usize,
+    pub quality: U256,
     pub sealed_data: [u8; BYTES_PER_SEAL],
 }
 
@@ -61,13 +64,56 @@ impl<'a> Miner<'a> {
 
     pub async fn iteration(&self, nonce: H256) -> Option<AnswerWithoutProof> {
         inc_counter(&SCRATCH_PAD_ITER_COUNT);
-        let ScratchPad {
-            scratch_pad,
-            recall_seed,
-            pad_seed,
-        } = self.make_scratch_pad(&nonce);
+        let (scratch_pad, recall_position, loaded) = self.load_recall_chunk(&nonce).await?;
 
-        let recall_position = self.range.load_position(recall_seed)?;
+        for (idx, sealed_data) in loaded
+            .loaded_chunk
+            .into_iter()
+            .enumerate()
+            .zip(loaded.availabilities.into_iter())
+            .filter_map(|(data, availiable)| availiable.then_some(data))
+        {
+            if let Some(answer) =
+                self.check_seal(&nonce, &scratch_pad, recall_position, idx, sealed_data)
+            {
+                return Some(answer);
+            }
+        }
+        None
+    }
+
+    /// Validates the answer found by an external prover against the local sealed data in the
+    /// same way as [`Miner::iteration`], so that only the answers that could be proved locally
+    /// are submitted.
+    pub async fn validate_answer(
+        &self,
+        nonce: H256,
+        seal_offset: usize,
+    ) -> Result<AnswerWithoutProof, &'static str> {
+        if seal_offset >= SEALS_PER_LOAD {
+            return Err("seal offset out of bound");
+        }
+        let (scratch_pad, recall_position, loaded) = self
+            .load_recall_chunk(&nonce)
+            .await
+            .ok_or("recall position not in mine range or not stored")?;
+        if !loaded.availabilities[seal_offset] {
+            return Err("sealed data not available");
+        }
+        self.check_seal(
+            &nonce,
+            &scratch_pad,
+            recall_position,
+            seal_offset,
+            loaded.loaded_chunk[seal_offset],
+        )
+        .ok_or("quality not reach the target")
+    }
+
+    async fn load_recall_chunk(&self, nonce: &H256) -> Option<(ScratchPad, u64, MineLoadChunk)> {
+        let scratch_pad = self.make_scratch_pad(nonce);
+
+        let recall_position = self.range.load_position(scratch_pad.recall_seed)?;
         if !self.mine_range_config.is_covered(recall_position).unwrap() {
             trace!(
                 "recall offset not in range: recall_offset={}",
@@ -77,60 +123,58 @@ impl<'a> Miner<'a> {
         }
 
         inc_counter(&LOADING_COUNT);
-        let MineLoadChunk {
-            loaded_chunk,
-            availabilities,
-        } = self
+        let loaded = self
             .loader
             .load_sealed_data(recall_position / SECTORS_PER_LOAD as u64)
             .await?;
+        Some((scratch_pad, recall_position, loaded))
+    }
 
-        let scratch_pad: [[u8; BYTES_PER_SEAL]; BYTES_PER_SCRATCHPAD / BYTES_PER_SEAL] =
-            unsafe { std::mem::transmute(scratch_pad) };
+    fn check_seal(
+        &self,
+        nonce: &H256,
+        scratch_pad: &ScratchPad,
+        recall_position: u64,
+        idx: usize,
+        sealed_data: [u8; BYTES_PER_SEAL],
+    ) -> Option<AnswerWithoutProof> {
+        inc_counter(&PAD_MIX_COUNT);
+        let pad_index = idx % (BYTES_PER_SCRATCHPAD / BYTES_PER_SEAL);
+        let pad = &scratch_pad.scratch_pad[pad_index * BYTES_PER_SEAL..][..BYTES_PER_SEAL];
+
+        let mut mixed_data = sealed_data;
+        // Rust can optimize this loop well.
+        for (x, y) in mixed_data.iter_mut().zip(pad.iter()) {
+            *x ^= y;
+        }
 
-        for ((idx, mut sealed_data), scratch_pad) in loaded_chunk
-            .into_iter()
-            .enumerate()
-            .zip(scratch_pad.iter().cycle())
-            .zip(availabilities.into_iter())
-            .filter_map(|(data, availiable)| availiable.then_some(data))
-        {
-            inc_counter(&PAD_MIX_COUNT);
-            // Rust can optimize this loop well.
-            for (x, y) in sealed_data.iter_mut().zip(scratch_pad.iter()) {
-                *x ^= y;
-            }
+        let quality = self.pora(idx, &mixed_data, scratch_pad.pad_seed);
+        let difficulty_scale_x64 = self
+            .range
+            .difficulty_scale_x64(self.context.flow_length.as_u64());
 
-            let quality = self.pora(idx, &sealed_data, pad_seed);
-            let difficulty_scale_x64 = self
-                .range
-                .difficulty_scale_x64(self.context.flow_length.as_u64());
-
-            if quality <= (self.target_quality / difficulty_scale_x64) << 64 {
-                debug!(
-                    "Find a PoRA valid answer, quality: {}, target_quality {}, scale {:.3}",
-                    U256::MAX / quality,
-                    U256::MAX / self.target_quality,
-                    difficulty_scale_x64.as_u128() as f64 / u64::MAX as f64
-                );
-                inc_counter(&HIT_COUNT);
-                // Undo mix data when find a valid solution
-                for (x, y) in sealed_data.iter_mut().zip(scratch_pad.iter()) {
-                    *x ^= y;
-                }
-                return Some(AnswerWithoutProof {
-                    context_digest: H256::from(self.context.digest),
-                    context_flow_root: self.context.flow_root.into(),
-                    nonce,
-                    miner_id: *self.miner_id,
-                    range: self.range,
-                    recall_position: recall_position + idx as u64 * SECTORS_PER_SEAL as u64,
-                    seal_offset: idx,
-                    sealed_data,
-                });
-            }
+        if quality > (self.target_quality / difficulty_scale_x64) << 64 {
+            return None;
         }
-        None
+
+        debug!(
+            "Find a PoRA valid answer, quality: {}, target_quality {}, scale {:.3}",
+            U256::MAX / quality,
+            U256::MAX / self.target_quality,
+            difficulty_scale_x64.as_u128() as f64 / u64::MAX as f64
+        );
+        inc_counter(&HIT_COUNT);
+        Some(AnswerWithoutProof {
+            context_digest: H256::from(self.context.digest),
+            context_flow_root: self.context.flow_root.into(),
+            nonce: *nonce,
+            miner_id: *self.miner_id,
+            range: self.range,
+            recall_position: recall_position + idx as u64 * SECTORS_PER_SEAL as u64,
+            seal_offset: idx,
+            quality,
+            sealed_data,
+        })
     }
 
     fn make_scratch_pad(&self, nonce: &H256) -> ScratchPad {
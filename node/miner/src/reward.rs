@@ -0,0 +1,268 @@
+use ethereum_types::{H256, U256};
+use log_entry_sync::{ContractEvent, ContractLog, LogSyncEvent};
+use std::sync::Arc;
+use storage::error::Result;
+use storage_async::{MinerReward, Store};
+use task_executor::TaskExecutor;
+use tokio::sync::broadcast;
+
+/// Events of the latest tx synced, which correlates the rewards with the submission emitted in
+/// the same tx regardless of the log order.
+#[derive(Default)]
+struct TxEvents {
+    tx_hash: H256,
+    /// `(epoch, id)` of the submission of this miner.
+    submission: Option<(u64, H256)>,
+    rewards: Vec<(u64, U256)>,
+}
+
+impl TxEvents {
+    fn amount(&self) -> U256 {
+        self.rewards
+            .iter()
+            .fold(U256::zero(), |sum, (_, amount)| sum + amount)
+    }
+}
+
+/// Tracks whether the submitted answers are accepted and the rewards distributed along with them,
+/// based on the `NewSubmission` and `DistributeReward` events subscribed by log sync.
+pub struct RewardTracker {
+    miner_id: H256,
+    store: Arc<Store>,
+    last_tx: TxEvents,
+}
+
+impl RewardTracker {
+    pub fn spawn(
+        executor: TaskExecutor,
+        miner_id: H256,
+        store: Arc<Store>,
+        event_recv: broadcast::Receiver<LogSyncEvent>,
+    ) {
+        let tracker = RewardTracker::new(miner_id, store);
+        executor.spawn(
+            async move { Box::pin(tracker.start(event_recv)).await },
+            "mine_reward_tracker",
+        );
+    }
+
+    fn new(miner_id: H256, store: Arc<Store>) -> Self {
+        Self {
+            miner_id,
+            store,
+            last_tx: Default::default(),
+        }
+    }
+
+    async fn start(mut self, mut event_recv: broadcast::Receiver<LogSyncEvent>) {
+        loop {
+            match event_recv.recv().await {
+                Ok(event) => {
+                    if let Err(e) = self.on_event(event).await {
+                        warn!(?e, "Failed to update miner rewards");
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!(n, "Miner reward tracker lagged behind log sync events");
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    warn!("Miner reward tracker stopped because log sync channel is closed.");
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn on_event(&mut self, event: LogSyncEvent) -> Result<()> {
+        match event {
+            LogSyncEvent::ContractEventSynced { log } => self.on_contract_log(log).await,
+            LogSyncEvent::BlocksReverted { block_number } => {
+                self.last_tx = Default::default();
+                let reverted = self.store.revert_miner_rewards(block_number).await?;
+                if reverted > 0 {
+                    info!(block_number, reverted, "Miner rewards reverted on reorg");
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn on_contract_log(&mut self, log: ContractLog) -> Result<()> {
+        if self.last_tx.tx_hash != log.tx_hash {
+            self.last_tx = TxEvents {
+                tx_hash: log.tx_hash,
+                ..Default::default()
+            };
+        }
+
+        match log.event {
+            ContractEvent::NewSubmission {
+                epoch, miner_id, ..
+            } if miner_id == self.miner_id => {
+                let mut reward = self.find_or_create(epoch, log.tx_hash).await?;
+                reward.block_number = Some(log.block_number);
+                reward.rejected_reason = None;
+                reward.amount = self.last_tx.amount();
+                self.last_tx.submission = Some((reward.epoch, reward.id));
+                info!(epoch, tx_hash = ?log.tx_hash, "PoRA answer accepted");
+                self.store.put_miner_reward(reward).await
+            }
+            ContractEvent::DistributeReward { amount, .. } => {
+                // Logs delivered again replace the same entry.
+                self.last_tx
+                    .rewards
+                    .retain(|(index, _)| *index != log.log_index);
+                self.last_tx.rewards.push((log.log_index, amount));
+
+                let (epoch, id) = match self.last_tx.submission {
+                    Some(submission) => submission,
+                    None => return Ok(()),
+                };
+                let mut reward = self.find_or_create(epoch, id).await?;
+                reward.amount = self.last_tx.amount();
+                self.store.put_miner_reward(reward).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Finds the record of the submission by either id or tx hash, or creates one if the answer
+    /// is not submitted by this node, e.g. another node with the same miner id.
+    async fn find_or_create(&self, epoch: u64, id_or_tx_hash: H256) -> Result<MinerReward> {
+        let existing = self
+            .store
+            .get_miner_rewards(epoch, epoch)
+            .await?
+            .into_iter()
+            .find(|r| r.id == id_or_tx_hash || r.tx_hash == Some(id_or_tx_hash));
+        Ok(existing.unwrap_or(MinerReward {
+            epoch,
+            id: id_or_tx_hash,
+            tx_hash: Some(id_or_tx_hash),
+            block_number: None,
+            rejected_reason: None,
+            amount: U256::zero(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::Address;
+    use storage::log_store::log_manager::LogConfig;
+    use storage::LogManager;
+    use task_executor::test_utils::TestRuntime;
+
+    fn contract_log(
+        event: ContractEvent,
+        block_number: u64,
+        log_index: u64,
+        tx: u64,
+    ) -> LogSyncEvent {
+        LogSyncEvent::ContractEventSynced {
+            log: ContractLog {
+                event,
+                block_number,
+                log_index,
+                tx_hash: H256::from_low_u64_be(tx),
+            },
+        }
+    }
+
+    fn submission(epoch: u64, miner_id: H256) -> ContractEvent {
+        ContractEvent::NewSubmission {
+            contract: Default::default(),
+            epoch,
+            miner_id,
+            epoch_index: U256::zero(),
+            recall_position: U256::zero(),
+        }
+    }
+
+    fn reward(amount: u64) -> ContractEvent {
+        ContractEvent::DistributeReward {
+            contract: Default::default(),
+            pricing_index: U256::zero(),
+            beneficiary: Address::zero(),
+            amount: amount.into(),
+        }
+    }
+
+    #[test]
+    fn test_track_rewards() {
+        let runtime = TestRuntime::default();
+        let executor = runtime.task_executor.clone();
+        let log_store = Arc::new(LogManager::memorydb(LogConfig::default()).unwrap());
+        let store = Arc::new(Store::new(log_store, executor.clone()));
+        let miner_id = H256::from_low_u64_be(1);
+        let other_miner = H256::from_low_u64_be(2);
+
+        executor.handle().unwrap().block_on(async move {
+            // Submitted by this node and pending.
+            let nonce = H256::from_low_u64_be(100);
+            store
+                .put_miner_reward(MinerReward {
+                    epoch: 5,
+                    id: nonce,
+                    tx_hash: Some(H256::from_low_u64_be(10)),
+                    block_number: None,
+                    rejected_reason: None,
+                    amount: U256::zero(),
+                })
+                .await
+                .unwrap();
+
+            let mut tracker = RewardTracker::new(miner_id, store.clone());
+            let events = [
+                // The reward in tx 10 is distributed after the submission.
+                contract_log(submission(5, miner_id), 20, 0, 10),
+                contract_log(reward(300), 20, 1, 10),
+                contract_log(reward(300), 20, 1, 10),
+                // Submission of other miners is ignored.
+                contract_log(reward(400), 21, 0, 11),
+                contract_log(submission(5, other_miner), 21, 1, 11),
+                // Submitted by another node of the same miner, with the reward distributed first.
+                contract_log(reward(500), 22, 0, 12),
+                contract_log(submission(6, miner_id), 22, 1, 12),
+            ];
+            for event in events {
+                tracker.on_event(event).await.unwrap();
+            }
+
+            let rewards = store.get_miner_rewards(0, u64::MAX).await.unwrap();
+            assert_eq!(rewards.len(), 2);
+            assert_eq!(rewards[0].id, nonce);
+            assert_eq!(rewards[0].block_number, Some(20));
+            assert_eq!(rewards[0].amount, 300.into());
+            assert_eq!(rewards[1].epoch, 6);
+            assert_eq!(rewards[1].id, H256::from_low_u64_be(12));
+            assert_eq!(rewards[1].block_number, Some(22));
+            assert_eq!(rewards[1].amount, 500.into());
+
+            // The block of tx 12 reorged, and the submission is included in another block.
+            tracker
+                .on_event(LogSyncEvent::BlocksReverted { block_number: 21 })
+                .await
+                .unwrap();
+            let rewards = store.get_miner_rewards(6, 6).await.unwrap();
+            assert!(!rewards[0].is_accepted());
+            assert_eq!(rewards[0].amount, U256::zero());
+            assert!(store.get_miner_rewards(5, 5).await.unwrap()[0].is_accepted());
+
+            tracker
+                .on_event(contract_log(submission(6, miner_id), 23, 0, 12))
+                .await
+                .unwrap();
+            tracker
+                .on_event(contract_log(reward(500), 23, 1, 12))
+                .await
+                .unwrap();
+            let rewards = store.get_miner_rewards(6, 6).await.unwrap();
+            assert_eq!(rewards.len(), 1);
+            assert_eq!(rewards[0].block_number, Some(23));
+            assert_eq!(rewards[0].amount, 500.into());
+        });
+    }
+}
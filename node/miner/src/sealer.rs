@@ -10,6 +10,7 @@ use storage::{
     log_store::{SealAnswer, SealTask},
 };
 use storage_async::Store;
+use task_executor::shutdown::ShutdownToken;
 use task_executor::TaskExecutor;
 use zgs_spec::SECTORS_PER_SEAL;
 
@@ -19,12 +20,57 @@ const DB_QUERY_PERIOD_ON_NO_TASK: u64 = 1;
 const DB_QUERY_PERIOD_ON_ERROR: u64 = 5;
 const CHAIN_STATUS_QUERY_PERIOD: u64 = 5;
 
+/// Maximum number of seal answers committed in one db write.
+const SEAL_BATCH_MAX_ANSWERS: usize = 1024;
+/// Maximum time that seal answers are held in memory before committed.
+const SEAL_BATCH_MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// Seal answers computed but not committed yet, so that the answers of many load chunks are
+/// committed in one db write during catch-up.
+///
+/// The store keeps the tasks to seal until their answers are committed, so the tasks are pulled
+/// after the pending ones, and pulled again from the start once committed. If the sealer stops
+/// or the commit fails, the uncommitted tasks are simply sealed again.
+#[derive(Default)]
+struct PendingSeals {
+    answers: Vec<SealAnswer>,
+    /// Seal index to pull the next tasks from.
+    next_seal_index: usize,
+    /// Time that the first pending answer is computed.
+    since: Option<Instant>,
+}
+
+impl PendingSeals {
+    fn push(&mut self, answers: Vec<SealAnswer>, next_seal_index: usize, now: Instant) {
+        if self.since.is_none() && !answers.is_empty() {
+            self.since = Some(now);
+        }
+        self.answers.extend(answers);
+        self.next_seal_index = next_seal_index;
+    }
+
+    fn should_commit(&self, now: Instant) -> bool {
+        self.answers.len() >= SEAL_BATCH_MAX_ANSWERS
+            || self
+                .since
+                .map_or(false, |since| now >= since + SEAL_BATCH_MAX_DELAY)
+    }
+
+    /// Takes the answers to commit, after which tasks are pulled from the start.
+    fn take(&mut self) -> Vec<SealAnswer> {
+        self.next_seal_index = 0;
+        self.since = None;
+        std::mem::take(&mut self.answers)
+    }
+}
+
 pub struct Sealer {
     flow_contract: ZgsFlow<Provider<RetryClient<Http>>>,
     store: Arc<Store>,
     context_cache: BTreeMap<u128, EpochRangeWithContextDigest>,
     last_context_flow_length: u64,
     miner_id: H256,
+    pending: PendingSeals,
 }
 
 impl Sealer {
@@ -34,6 +80,7 @@ impl Sealer {
         store: Arc<Store>,
         config: &MinerConfig,
         miner_id: H256,
+        shutdown: ShutdownToken,
     ) {
         let flow_contract = ZgsFlow::new(config.flow_address, provider);
         let sealer = Sealer {
@@ -42,12 +89,16 @@ impl Sealer {
             context_cache: Default::default(),
             last_context_flow_length: 0,
             miner_id,
+            pending: Default::default(),
         };
 
-        executor.spawn(async move { Box::pin(sealer.start()).await }, "data_sealer");
+        executor.spawn(
+            async move { Box::pin(sealer.start(shutdown)).await },
+            "data_sealer",
+        );
     }
 
-    async fn start(mut self) {
+    async fn start(mut self, mut shutdown: ShutdownToken) {
         let db_checker_throttle = sleep(Duration::from_secs(0));
         tokio::pin!(db_checker_throttle);
 
@@ -58,6 +109,9 @@ impl Sealer {
             tokio::select! {
                 biased;
 
+                // pending seal results are committed before shutdown
+                _ = shutdown.requested() => break,
+
                 () = &mut contract_checker_throttle, if !contract_checker_throttle.is_elapsed() => {
                 }
 
@@ -83,6 +137,12 @@ impl Sealer {
                 }
             }
         }
+
+        if let Err(err) = self.commit_pending().await {
+            warn!("Commit seal results failed on shutdown {:?}", err);
+        }
+        info!("Sealer stopped for shutdown");
+        shutdown.ack();
     }
 
     async fn update_flow_length(&mut self) -> Result<()> {
@@ -151,20 +211,30 @@ impl Sealer {
 
     async fn fetch_task(&self) -> Result<Option<Vec<SealTask>>> {
         let seal_index_max = self.last_context_flow_length as usize / SECTORS_PER_SEAL;
-        self.store.pull_seal_chunk(seal_index_max).await
+        self.store
+            .pull_seal_chunk(self.pending.next_seal_index, seal_index_max)
+            .await
     }
 
-    async fn submit_answer(&self, answers: Vec<SealAnswer>) -> Result<()> {
-        self.store.submit_seal_result(answers).await
+    /// Commits the pending seal results, and returns whether any is committed.
+    async fn commit_pending(&mut self) -> Result<bool> {
+        let answers = self.pending.take();
+        if answers.is_empty() {
+            return Ok(false);
+        }
+
+        debug!(target: "seal", "Commit {} seal results", answers.len());
+        self.store.submit_seal_result(answers).await?;
+        Ok(true)
     }
 
     async fn seal_iteration(&mut self) -> Result<bool> {
         let tasks = match self.fetch_task().await? {
             Some(tasks) if !tasks.is_empty() => tasks,
-            _ => {
-                return Ok(false);
-            }
+            // pull again at once if committed, in case of the tasks skipped as pending
+            _ => return self.commit_pending().await,
         };
+        let next_seal_index = tasks.last().expect("tasks not empty").seal_index as usize + 1;
 
         debug!(
             "Get seal tasks at seal index {:?}",
@@ -198,8 +268,61 @@ impl Sealer {
             });
         }
 
-        self.submit_answer(answers).await?;
+        let now = Instant::now();
+        self.pending.push(answers, next_seal_index, now);
+        if self.pending.should_commit(now) {
+            self.commit_pending().await?;
+        }
 
         Ok(true)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zgs_spec::BYTES_PER_SEAL;
+
+    fn answer(seal_index: u64) -> SealAnswer {
+        SealAnswer {
+            seal_index,
+            version: 0,
+            sealed_data: [0; BYTES_PER_SEAL],
+            miner_id: H256::zero(),
+            seal_context: H256::zero(),
+            context_end_seal: u64::MAX,
+        }
+    }
+
+    #[test]
+    fn test_pending_seals() {
+        let mut pending = PendingSeals::default();
+        let now = Instant::now();
+        assert!(!pending.should_commit(now));
+
+        // tasks not ready are skipped without answers
+        pending.push(vec![], 16, now);
+        assert_eq!(pending.next_seal_index, 16);
+        assert!(!pending.should_commit(now + SEAL_BATCH_MAX_DELAY));
+
+        // committed once expired
+        pending.push(vec![answer(16), answer(17)], 32, now);
+        assert!(!pending.should_commit(now + SEAL_BATCH_MAX_DELAY / 2));
+        pending.push(vec![answer(32)], 48, now + SEAL_BATCH_MAX_DELAY / 2);
+        assert!(pending.should_commit(now + SEAL_BATCH_MAX_DELAY));
+
+        let answers = pending.take();
+        assert_eq!(
+            answers.iter().map(|a| a.seal_index).collect::<Vec<_>>(),
+            vec![16, 17, 32]
+        );
+        assert_eq!(pending.next_seal_index, 0);
+        assert!(!pending.should_commit(now + SEAL_BATCH_MAX_DELAY * 2));
+
+        // committed once full
+        let answers = (0..SEAL_BATCH_MAX_ANSWERS as u64).map(answer).collect();
+        pending.push(answers, SEAL_BATCH_MAX_ANSWERS, now);
+        assert!(pending.should_commit(now));
+        assert_eq!(pending.take().len(), SEAL_BATCH_MAX_ANSWERS);
+    }
+}
@@ -1,14 +1,22 @@
+use crate::external::{AcceptedAnswer, ExternalAnswer, MinePuzzle, ReplySender};
 use crate::miner_id::check_and_request_miner_id;
 use crate::monitor::Monitor;
+use crate::reward::RewardTracker;
 use crate::sealer::Sealer;
 use crate::submitter::Submitter;
-use crate::{config::MinerConfig, mine::PoraService, watcher::MineContextWatcher};
+use crate::{
+    config::{MinerConfig, MinerDynamicConfig},
+    mine::PoraService,
+    watcher::{MineContextStatus, MineContextWatcher},
+};
+use log_entry_sync::{LogSyncEvent, RpcClient};
 use network::NetworkSender;
 use std::sync::Arc;
 use std::time::Duration;
 use storage::config::ShardConfig;
 use storage_async::Store;
-use tokio::sync::broadcast;
+use task_executor::shutdown::ShutdownToken;
+use tokio::sync::{broadcast, watch};
 
 #[derive(Clone, Debug)]
 pub enum MinerMessage {
@@ -21,18 +29,38 @@ pub enum MinerMessage {
 
     /// Change shard config
     SetShardConfig(ShardConfig),
+
+    /// Query the mine puzzle for external provers, which is `None` if not mining.
+    GetMinePuzzle(ReplySender<Option<MinePuzzle>>),
+
+    /// Submit the answer of an external prover once validated against the local sealed data.
+    SubmitExternalAnswer(ExternalAnswer, ReplySender<Result<AcceptedAnswer, String>>),
+
+    /// Query the status of the mine context watcher.
+    GetContextStatus(ReplySender<MineContextStatus>),
 }
 
 pub struct MineService;
 
 impl MineService {
+    /// Spawns the miner, which queries the mine context over `rpc_client` if specified, e.g.
+    /// the endpoints of the log entry sync, or the configured endpoint otherwise.
+    #[allow(clippy::too_many_arguments)]
     pub async fn spawn(
         executor: task_executor::TaskExecutor,
         _network_send: NetworkSender,
         config: MinerConfig,
         store: Arc<Store>,
+        config_recv: watch::Receiver<MinerDynamicConfig>,
+        log_sync_recv: Option<broadcast::Receiver<LogSyncEvent>>,
+        rpc_client: Option<RpcClient>,
+        shutdown: ShutdownToken,
     ) -> Result<broadcast::Sender<MinerMessage>, String> {
         let provider = config.make_provider()?;
+        let rpc_client = match rpc_client {
+            Some(rpc_client) => rpc_client,
+            None => config.make_rpc_client()?,
+        };
         let signing_provider = Arc::new(config.make_signing_provider().await?);
 
         let (msg_send, msg_recv) = broadcast::channel(1024);
@@ -44,7 +72,7 @@ impl MineService {
         let mine_context_receiver = MineContextWatcher::spawn(
             executor.clone(),
             msg_recv.resubscribe(),
-            provider.clone(),
+            rpc_client,
             &config,
         );
 
@@ -55,8 +83,16 @@ impl MineService {
             store.clone(),
             &config,
             miner_id,
+            config_recv,
         );
 
+        match log_sync_recv {
+            Some(event_recv) => {
+                RewardTracker::spawn(executor.clone(), miner_id, store.clone(), event_recv)
+            }
+            None => info!("Miner rewards not tracked without log sync"),
+        }
+
         Submitter::spawn(
             executor.clone(),
             mine_answer_receiver,
@@ -67,7 +103,15 @@ impl MineService {
             &config,
         );
 
-        Sealer::spawn(executor.clone(), provider, store, &config, miner_id);
+        // Only sealer writes into store, and other tasks are cancelled once the node shut down.
+        Sealer::spawn(
+            executor.clone(),
+            provider,
+            store,
+            &config,
+            miner_id,
+            shutdown,
+        );
 
         Monitor::spawn(executor, Duration::from_secs(5));
 
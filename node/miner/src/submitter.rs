@@ -9,7 +9,7 @@ use shared_types::FlowRangeProof;
 use std::sync::Arc;
 use std::time::Duration;
 use storage::H256;
-use storage_async::Store;
+use storage_async::{MinerReward, Store};
 use task_executor::TaskExecutor;
 use tokio::sync::{broadcast, mpsc};
 
@@ -59,17 +59,21 @@ impl Submitter {
     }
 
     async fn start(mut self) {
-        let mut current_context_digest: Option<H256> = None;
+        // Digest and epoch of the current context.
+        let mut current_context: Option<(H256, u64)> = None;
         loop {
             tokio::select! {
                 answer_msg = self.mine_answer_receiver.recv() => {
                     match answer_msg {
                         Some(answer) => {
-                            if Some(answer.context_digest) != current_context_digest {
-                                info!("Skip submission because of inconsistent context digest");
-                                continue;
-                            }
-                            if let Err(e) = self.submit_answer(answer).await {
+                            let epoch = match current_context {
+                                Some((digest, epoch)) if digest == answer.context_digest => epoch,
+                                _ => {
+                                    info!("Skip submission because of inconsistent context digest");
+                                    continue;
+                                }
+                            };
+                            if let Err(e) = self.submit_answer(answer, epoch).await {
                                 warn!(e);
                             }
                         }
@@ -83,7 +87,7 @@ impl Submitter {
                 context_msg = self.mine_context_receiver.recv() => {
                     match context_msg {
                         Ok(puzzle) => {
-                            current_context_digest = puzzle.map(|p| p.context_digest());
+                            current_context = puzzle.map(|p| (p.context_digest(), p.epoch()));
                         }
                         Err(broadcast::error::RecvError::Closed) => {
                             warn!("Mine context channel closed.");
@@ -95,7 +99,11 @@ impl Submitter {
         }
     }
 
-    async fn submit_answer(&mut self, mine_answer: AnswerWithoutProof) -> Result<(), String> {
+    async fn submit_answer(
+        &mut self,
+        mine_answer: AnswerWithoutProof,
+        epoch: u64,
+    ) -> Result<(), String> {
         debug!("submit answer: {:?}", mine_answer);
         let sealed_context_digest = self
             .flow_contract
@@ -153,30 +161,74 @@ impl Submitter {
             submission_call.estimate_gas().await
         );
 
-        let pending_transaction: PendingTransaction<'_, _> = submission_call
-            .send()
-            .await
-            .map_err(|e| format!("Fail to send PoRA submission transaction: {:?}", e))?;
+        let mut record = MinerReward {
+            epoch,
+            id: mine_answer.nonce,
+            tx_hash: None,
+            block_number: None,
+            rejected_reason: None,
+            amount: U256::zero(),
+        };
+
+        let pending_transaction: PendingTransaction<'_, _> = match submission_call.send().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                let reason = format!("Fail to send PoRA submission transaction: {:?}", e);
+                self.reject_submission(record, reason.clone()).await;
+                return Err(reason);
+            }
+        };
 
         debug!(
             "Signed submission transaction hash: {:?}",
             pending_transaction.tx_hash()
         );
 
-        let receipt = pending_transaction
+        // Accepted once the `NewSubmission` event is synced.
+        record.tx_hash = Some(pending_transaction.tx_hash());
+        self.record_submission(record.clone()).await;
+
+        let receipt = match pending_transaction
             .retries(SUBMISSION_RETRIES)
             .interval(Duration::from_secs(2))
             .await
-            .map_err(|e| format!("Fail to execute PoRA submission transaction: {:?}", e))?
-            .ok_or(format!(
-                "PoRA submission transaction dropped after {} retries",
-                SUBMISSION_RETRIES
-            ))?;
+        {
+            Ok(Some(receipt)) if receipt.status == Some(0.into()) => {
+                let reason = format!("PoRA submission transaction reverted: {:?}", receipt);
+                self.reject_submission(record, reason.clone()).await;
+                return Err(reason);
+            }
+            Ok(Some(receipt)) => receipt,
+            Ok(None) => {
+                let reason = format!(
+                    "PoRA submission transaction dropped after {} retries",
+                    SUBMISSION_RETRIES
+                );
+                self.reject_submission(record, reason.clone()).await;
+                return Err(reason);
+            }
+            Err(e) => {
+                let reason = format!("Fail to execute PoRA submission transaction: {:?}", e);
+                self.reject_submission(record, reason.clone()).await;
+                return Err(reason);
+            }
+        };
 
         info!("Submit PoRA success, receipt: {:?}", receipt);
 
         Ok(())
     }
+
+    async fn reject_submission(&self, mut record: MinerReward, reason: String) {
+        record.rejected_reason = Some(reason.into_bytes());
+        self.record_submission(record).await;
+    }
+
+    async fn record_submission(&self, record: MinerReward) {
+        if let Err(e) = self.store.put_miner_reward(record).await {
+            warn!(?e, "Failed to record PoRA submission");
+        }
+    }
 }
 
 // TODO: The conversion will be simpler if we optimize range proof structure.
@@ -1,5 +1,6 @@
 #![allow(unused)]
 
+use async_trait::async_trait;
 use contract_interface::{zgs_flow::MineContext, PoraMine, ZgsFlow};
 use ethereum_types::{Address, H256, U256};
 use ethers::{
@@ -7,6 +8,7 @@ use ethers::{
     providers::{JsonRpcClient, Middleware, Provider, StreamExt},
     types::BlockId,
 };
+use log_entry_sync::{EndpointClient, RpcClient};
 use task_executor::TaskExecutor;
 use tokio::{
     sync::{broadcast, mpsc},
@@ -16,6 +18,7 @@ use tokio::{
 
 use crate::{config::MineServiceMiddleware, mine::PoraPuzzle, MinerConfig, MinerMessage};
 use ethers::prelude::{Http, RetryClient};
+use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
@@ -28,13 +31,286 @@ lazy_static! {
         H256::from_str("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470").unwrap();
 }
 
+/// State of the mine context that the miner works on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MineContextState {
+    /// The context is confirmed by the endpoints in the latest query.
+    Live,
+    /// The endpoints disagree on the context, and the last confirmed one is kept.
+    Unconfirmed,
+    /// All endpoints fail, and the last confirmed context is kept in the grace period.
+    Stale,
+    /// No context confirmed in the grace period, so that mining stops.
+    Unavailable,
+}
+
+impl MineContextState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MineContextState::Live => "live",
+            MineContextState::Unconfirmed => "unconfirmed",
+            MineContextState::Stale => "stale",
+            MineContextState::Unavailable => "unavailable",
+        }
+    }
+}
+
+/// Status of the mine context watcher, which is queried by `MinerMessage::GetContextStatus`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MineContextStatus {
+    pub state: MineContextState,
+    /// Epoch of the context that the miner works on, if any.
+    pub epoch: Option<u64>,
+    /// Elapsed time since the context was confirmed last time.
+    pub since_confirmed: Option<Duration>,
+    /// Number of queries that the endpoints disagree on the context.
+    pub disagreements: u64,
+}
+
+/// Endpoint of the blockchain to query the mine context from.
+#[async_trait]
+pub(crate) trait ContextEndpoint: Send + Sync {
+    fn url(&self) -> String;
+
+    /// Returns the latest block number of the endpoint.
+    async fn block_number(&self) -> Result<u64, String>;
+
+    /// Queries the context at the block, so that endpoints are compared at the same height.
+    async fn query_context(&self, block_number: u64) -> Result<MineContextMessage, String>;
+}
+
+/// Pool of endpoints to query the mine context from.
+pub(crate) trait ContextEndpoints: Send + Sync {
+    /// Returns the endpoints in the order to query.
+    fn endpoints(&self) -> Vec<Box<dyn ContextEndpoint>>;
+}
+
+type EndpointProvider = Provider<EndpointClient<RetryClient<Http>>>;
+
+struct RpcContextEndpoint {
+    url: String,
+    provider: Arc<EndpointProvider>,
+    flow_contract: ZgsFlow<EndpointProvider>,
+    mine_contract: PoraMine<EndpointProvider>,
+}
+
+#[async_trait]
+impl ContextEndpoint for RpcContextEndpoint {
+    fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn block_number(&self) -> Result<u64, String> {
+        self.provider
+            .get_block_number()
+            .await
+            .map(|number| number.as_u64())
+            .map_err(|e| format!("Failed to query block number: {:?}", e))
+    }
+
+    async fn query_context(&self, block_number: u64) -> Result<MineContextMessage, String> {
+        let block = BlockId::from(block_number);
+        let context_call = self.flow_contract.make_context_with_result().block(block);
+        let valid_call = self.mine_contract.can_submit().block(block);
+        let quality_call = self.mine_contract.pora_target().block(block);
+        let shards_call = self.mine_contract.max_shards().block(block);
+
+        let (context, can_submit, quality, max_shards) = try_join!(
+            context_call.call(),
+            valid_call.call(),
+            quality_call.call(),
+            shards_call.call()
+        )
+        .map_err(|e| format!("Failed to query mining context: {:?}", e))?;
+        let report = if can_submit && context.digest != EMPTY_HASH.0 {
+            Some(PoraPuzzle::new(context, quality, max_shards))
+        } else {
+            None
+        };
+        Ok(report)
+    }
+}
+
+/// Endpoints of the rpc client shared with the log entry sync.
+struct RpcContextEndpoints {
+    rpc_client: RpcClient,
+    flow_address: Address,
+    mine_address: Address,
+}
+
+impl ContextEndpoints for RpcContextEndpoints {
+    fn endpoints(&self) -> Vec<Box<dyn ContextEndpoint>> {
+        self.rpc_client
+            .endpoints()
+            .into_iter()
+            .map(|client| {
+                let url = client.url();
+                let provider = Arc::new(Provider::new(client));
+                Box::new(RpcContextEndpoint {
+                    url,
+                    provider: provider.clone(),
+                    flow_contract: ZgsFlow::new(self.flow_address, provider.clone()),
+                    mine_contract: PoraMine::new(self.mine_address, provider),
+                }) as Box<dyn ContextEndpoint>
+            })
+            .collect()
+    }
+}
+
+/// Result of a query over the endpoints.
+#[derive(Debug, PartialEq, Eq)]
+enum QueryOutcome {
+    /// The context returned by two endpoints in agreement, or by the only one available.
+    Confirmed(MineContextMessage),
+    Disagreed,
+    Failed,
+}
+
+/// Queries the context from all endpoints concurrently, and cross-checks the first two of them
+/// in order that succeed. The contexts are compared at the lower head of the two, so that
+/// healthy endpoints a few blocks apart agree. Queries beyond `timeout` are counted as failed.
+async fn query_endpoints(
+    endpoints: Vec<Box<dyn ContextEndpoint>>,
+    timeout: Duration,
+) -> QueryOutcome {
+    let query_heads = endpoints
+        .iter()
+        .map(|endpoint| query_with_timeout(endpoint.as_ref(), timeout, endpoint.block_number()));
+    let heads = futures::future::join_all(query_heads).await;
+    let height = match heads.iter().flatten().take(2).min() {
+        Some(height) => *height,
+        None => return QueryOutcome::Failed,
+    };
+
+    // Endpoints behind the height could not serve it.
+    let query_contexts = endpoints
+        .iter()
+        .zip(heads)
+        .filter(|(_, head)| head.map_or(false, |head| head >= height))
+        .map(|(endpoint, _)| async move {
+            let query = endpoint.query_context(height);
+            let report = query_with_timeout(endpoint.as_ref(), timeout, query).await?;
+            Some((endpoint.url(), report))
+        });
+    let mut reports = futures::future::join_all(query_contexts)
+        .await
+        .into_iter()
+        .flatten();
+
+    match (reports.next(), reports.next()) {
+        (Some((_, first)), Some((_, second))) if first == second => QueryOutcome::Confirmed(first),
+        (Some((first_url, first)), Some((url, second))) => {
+            warn!(
+                %first_url,
+                first_epoch = ?first.as_ref().map(|p| p.epoch()),
+                %url,
+                epoch = ?second.as_ref().map(|p| p.epoch()),
+                %height,
+                "Rpc endpoints disagree on mine context"
+            );
+            QueryOutcome::Disagreed
+        }
+        (Some((_, report)), None) => QueryOutcome::Confirmed(report),
+        _ => QueryOutcome::Failed,
+    }
+}
+
+/// Runs a query of the endpoint, which fails if not completed within `timeout`.
+async fn query_with_timeout<T>(
+    endpoint: &dyn ContextEndpoint,
+    timeout: Duration,
+    query: impl Future<Output = Result<T, String>>,
+) -> Option<T> {
+    match tokio::time::timeout(timeout, query).await {
+        Ok(Ok(result)) => Some(result),
+        Ok(Err(err)) => {
+            warn!(url = %endpoint.url(), %err, "Failed to query mine context");
+            None
+        }
+        Err(_) => {
+            warn!(url = %endpoint.url(), ?timeout, "Query of mine context timed out");
+            None
+        }
+    }
+}
+
+/// Tracks the context reported to the miner, which is kept for the grace period once not
+/// confirmed by the endpoints, so that the miner does not idle on a short outage.
+struct ContextTracker {
+    grace_period: Duration,
+    reported: MineContextMessage,
+    state: MineContextState,
+    confirmed_at: Option<Instant>,
+    disagreements: u64,
+}
+
+impl ContextTracker {
+    fn new(grace_period: Duration) -> Self {
+        Self {
+            grace_period,
+            reported: None,
+            state: MineContextState::Unavailable,
+            confirmed_at: None,
+            disagreements: 0,
+        }
+    }
+
+    /// Returns the context to report to the miner if changed.
+    fn on_query(&mut self, outcome: QueryOutcome, now: Instant) -> Option<MineContextMessage> {
+        let state = match outcome {
+            QueryOutcome::Confirmed(report) => {
+                self.state = MineContextState::Live;
+                self.confirmed_at = Some(now);
+                if report == self.reported {
+                    return None;
+                }
+                self.reported = report.clone();
+                return Some(report);
+            }
+            QueryOutcome::Disagreed => {
+                self.disagreements += 1;
+                MineContextState::Unconfirmed
+            }
+            QueryOutcome::Failed => MineContextState::Stale,
+        };
+
+        let in_grace = self
+            .confirmed_at
+            .map_or(false, |at| now.duration_since(at) < self.grace_period);
+        if in_grace {
+            if self.state != state {
+                warn!(
+                    state = state.as_str(),
+                    "Keep mining on the last confirmed context"
+                );
+            }
+            self.state = state;
+            return None;
+        }
+
+        if self.state != MineContextState::Unavailable {
+            warn!("Mine context unavailable, stop mining");
+        }
+        self.state = MineContextState::Unavailable;
+        // The unconfirmed context is never reported on disagreement.
+        self.reported.take().map(|_| None)
+    }
+
+    fn status(&self, now: Instant) -> MineContextStatus {
+        MineContextStatus {
+            state: self.state,
+            epoch: self.reported.as_ref().map(|puzzle| puzzle.epoch()),
+            since_confirmed: self.confirmed_at.map(|at| now.duration_since(at)),
+            disagreements: self.disagreements,
+        }
+    }
+}
+
 pub struct MineContextWatcher {
-    provider: Arc<Provider<RetryClient<Http>>>,
-    flow_contract: ZgsFlow<Provider<RetryClient<Http>>>,
-    mine_contract: PoraMine<Provider<RetryClient<Http>>>,
+    endpoints: Box<dyn ContextEndpoints>,
+    tracker: ContextTracker,
 
     mine_context_sender: broadcast::Sender<MineContextMessage>,
-    last_report: MineContextMessage,
     query_interval: Duration,
 
     msg_recv: broadcast::Receiver<MinerMessage>,
@@ -44,21 +320,22 @@ impl MineContextWatcher {
     pub fn spawn(
         executor: TaskExecutor,
         msg_recv: broadcast::Receiver<MinerMessage>,
-        provider: Arc<Provider<RetryClient<Http>>>,
+        rpc_client: RpcClient,
         config: &MinerConfig,
     ) -> broadcast::Receiver<MineContextMessage> {
-        let mine_contract = PoraMine::new(config.mine_address, provider.clone());
-        let flow_contract = ZgsFlow::new(config.flow_address, provider.clone());
+        let endpoints = RpcContextEndpoints {
+            rpc_client,
+            flow_address: config.flow_address,
+            mine_address: config.mine_address,
+        };
 
         let (mine_context_sender, mine_context_receiver) =
             broadcast::channel::<MineContextMessage>(4096);
         let watcher = MineContextWatcher {
-            provider,
-            flow_contract,
-            mine_contract,
+            endpoints: Box::new(endpoints),
+            tracker: ContextTracker::new(config.context_grace_period),
             mine_context_sender,
             msg_recv,
-            last_report: None,
             query_interval: config.context_query_interval,
         };
         executor.spawn(
@@ -84,6 +361,9 @@ impl MineContextWatcher {
                         Ok(MinerMessage::ToggleMining(enable)) => {
                             mining_enabled = enable;
                         }
+                        Ok(MinerMessage::GetContextStatus(reply)) => {
+                            let _ = reply.send(self.tracker.status(Instant::now()));
+                        }
                         Err(broadcast::error::RecvError::Closed) => {
                             channel_opened = false;
                         }
@@ -105,33 +385,286 @@ impl MineContextWatcher {
     }
 
     async fn query_recent_context(&mut self) -> Result<(), String> {
-        let context_call = self.flow_contract.make_context_with_result();
-        let valid_call = self.mine_contract.can_submit();
-        let quality_call = self.mine_contract.pora_target();
-        let shards_call = self.mine_contract.max_shards();
-
-        let (context, can_submit, quality, max_shards) = try_join!(
-            context_call.call(),
-            valid_call.call(),
-            quality_call.call(),
-            shards_call.call()
-        )
-        .map_err(|e| format!("Failed to query mining context: {:?}", e))?;
-        let report = if can_submit && context.digest != EMPTY_HASH.0 {
-            Some(PoraPuzzle::new(context, quality, max_shards))
-        } else {
-            None
+        // A query slower than the interval is dropped, since the next one is due.
+        let outcome = query_endpoints(self.endpoints.endpoints(), self.query_interval).await;
+        let report = match self.tracker.on_query(outcome, Instant::now()) {
+            Some(report) => report,
+            None => return Ok(()),
         };
 
-        if report == self.last_report {
-            return Ok(());
-        }
-
         self.mine_context_sender
-            .send(report.clone())
+            .send(report)
             .map_err(|e| format!("Failed to send out the most recent mine context: {:?}", e))?;
-        self.last_report = report;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    const GRACE: Duration = Duration::from_secs(60);
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    /// Head of the chains of endpoints by default.
+    const HEAD: u64 = 100;
+
+    fn puzzle(epoch: u64) -> MineContextMessage {
+        let context = MineContext {
+            epoch: epoch.into(),
+            mine_start: 0.into(),
+            flow_root: [1u8; 32],
+            flow_length: 1024.into(),
+            block_digest: [2u8; 32],
+            digest: [epoch as u8; 32],
+        };
+        Some(PoraPuzzle::new(context, U256::MAX, 1))
+    }
+
+    /// Chain seen by an endpoint, whose context changes at the heights of `contexts`.
+    struct MockChain {
+        head: Result<u64, String>,
+        contexts: BTreeMap<u64, MineContextMessage>,
+        delay: Duration,
+    }
+
+    impl MockChain {
+        fn new(result: Result<MineContextMessage, String>) -> Self {
+            let mut chain = Self {
+                head: Ok(HEAD),
+                contexts: BTreeMap::new(),
+                delay: Duration::ZERO,
+            };
+            chain.set(result);
+            chain
+        }
+
+        /// Sets the context of the whole chain, or fails all queries.
+        fn set(&mut self, result: Result<MineContextMessage, String>) {
+            self.contexts.clear();
+            match result {
+                Ok(report) => {
+                    self.head = Ok(HEAD);
+                    self.contexts.insert(0, report);
+                }
+                Err(err) => self.head = Err(err),
+            }
+        }
+    }
+
+    struct MockEndpoint {
+        url: String,
+        chain: Arc<Mutex<MockChain>>,
+    }
+
+    impl MockEndpoint {
+        async fn delay(&self) {
+            let delay = self.chain.lock().unwrap().delay;
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    #[async_trait]
+    impl ContextEndpoint for MockEndpoint {
+        fn url(&self) -> String {
+            self.url.clone()
+        }
+
+        async fn block_number(&self) -> Result<u64, String> {
+            self.delay().await;
+            self.chain.lock().unwrap().head.clone()
+        }
+
+        async fn query_context(&self, block_number: u64) -> Result<MineContextMessage, String> {
+            self.delay().await;
+            let chain = self.chain.lock().unwrap();
+            if block_number > chain.head.clone()? {
+                return Err(format!("block {} not found", block_number));
+            }
+            let (_, report) = chain.contexts.range(..=block_number).next_back().unwrap();
+            Ok(report.clone())
+        }
+    }
+
+    /// Endpoints that are queried in order.
+    #[derive(Clone, Default)]
+    struct MockEndpoints(Vec<Arc<Mutex<MockChain>>>);
+
+    impl MockEndpoints {
+        fn with(results: Vec<Result<MineContextMessage, String>>) -> Self {
+            Self(
+                results
+                    .into_iter()
+                    .map(|r| Arc::new(Mutex::new(MockChain::new(r))))
+                    .collect(),
+            )
+        }
+
+        fn set(&self, index: usize, result: Result<MineContextMessage, String>) {
+            self.0[index].lock().unwrap().set(result);
+        }
+
+        /// Appends blocks to the chain of the endpoint, where the context changes at `head`.
+        fn advance(&self, index: usize, head: u64, report: MineContextMessage) {
+            let mut chain = self.0[index].lock().unwrap();
+            chain.head = Ok(head);
+            chain.contexts.insert(head, report);
+        }
+
+        fn set_delay(&self, index: usize, delay: Duration) {
+            self.0[index].lock().unwrap().delay = delay;
+        }
+    }
+
+    impl ContextEndpoints for MockEndpoints {
+        fn endpoints(&self) -> Vec<Box<dyn ContextEndpoint>> {
+            self.0
+                .iter()
+                .enumerate()
+                .map(|(index, chain)| {
+                    Box::new(MockEndpoint {
+                        url: format!("http://node{}", index),
+                        chain: chain.clone(),
+                    }) as Box<dyn ContextEndpoint>
+                })
+                .collect()
+        }
+    }
+
+    async fn query(endpoints: &MockEndpoints) -> QueryOutcome {
+        query_endpoints(endpoints.endpoints(), TIMEOUT).await
+    }
+
+    #[tokio::test]
+    async fn test_cross_check() {
+        let endpoints = MockEndpoints::with(vec![Ok(puzzle(1)), Ok(puzzle(1)), Ok(puzzle(2))]);
+        assert_eq!(query(&endpoints).await, QueryOutcome::Confirmed(puzzle(1)));
+
+        // Only the first two succeeded endpoints are cross-checked.
+        endpoints.set(1, Err("timeout".into()));
+        assert_eq!(query(&endpoints).await, QueryOutcome::Disagreed);
+
+        // The only endpoint available is trusted.
+        endpoints.set(2, Err("timeout".into()));
+        assert_eq!(query(&endpoints).await, QueryOutcome::Confirmed(puzzle(1)));
+
+        endpoints.set(0, Err("timeout".into()));
+        assert_eq!(query(&endpoints).await, QueryOutcome::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_common_height() {
+        let endpoints = MockEndpoints::with(vec![Ok(puzzle(1)), Ok(puzzle(1))]);
+
+        // The endpoint a block ahead is compared at the head of the other one.
+        endpoints.advance(1, HEAD + 1, puzzle(2));
+        assert_eq!(query(&endpoints).await, QueryOutcome::Confirmed(puzzle(1)));
+
+        endpoints.advance(0, HEAD + 1, puzzle(2));
+        assert_eq!(query(&endpoints).await, QueryOutcome::Confirmed(puzzle(2)));
+
+        // Endpoints still disagree at the same height.
+        endpoints.advance(0, HEAD + 2, puzzle(3));
+        endpoints.advance(1, HEAD + 2, puzzle(4));
+        assert_eq!(query(&endpoints).await, QueryOutcome::Disagreed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_query_timeout() {
+        let endpoints = MockEndpoints::with(vec![Ok(puzzle(1)), Ok(puzzle(2)), Ok(puzzle(1))]);
+
+        // Endpoints are queried concurrently, so that each round of queries takes as long as
+        // the slowest endpoint, i.e. 2 rounds of `TIMEOUT / 2` instead of 6 if sequential.
+        for index in 0..3 {
+            endpoints.set_delay(index, TIMEOUT / 2);
+        }
+        let started_at = Instant::now();
+        assert_eq!(query(&endpoints).await, QueryOutcome::Disagreed);
+        assert!(started_at.elapsed() < TIMEOUT * 2);
+
+        // The endpoint timed out is skipped instead of waited for.
+        endpoints.set_delay(1, TIMEOUT * 10);
+        let started_at = Instant::now();
+        assert_eq!(query(&endpoints).await, QueryOutcome::Confirmed(puzzle(1)));
+        assert!(started_at.elapsed() < TIMEOUT * 2);
+
+        endpoints.set_delay(0, TIMEOUT * 2);
+        endpoints.set_delay(2, TIMEOUT * 2);
+        assert_eq!(query(&endpoints).await, QueryOutcome::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_disagreement() {
+        let endpoints = MockEndpoints::with(vec![Ok(puzzle(1)), Ok(puzzle(1))]);
+        let mut tracker = ContextTracker::new(GRACE);
+        let now = Instant::now();
+        assert_eq!(
+            tracker.on_query(query(&endpoints).await, now),
+            Some(puzzle(1))
+        );
+
+        // The new context is never reported until confirmed.
+        endpoints.set(0, Ok(puzzle(2)));
+        assert_eq!(tracker.on_query(query(&endpoints).await, now), None);
+        let status = tracker.status(now);
+        assert_eq!(status.state, MineContextState::Unconfirmed);
+        assert_eq!(status.epoch, Some(1));
+        assert_eq!(status.disagreements, 1);
+
+        endpoints.set(1, Ok(puzzle(2)));
+        assert_eq!(
+            tracker.on_query(query(&endpoints).await, now),
+            Some(puzzle(2))
+        );
+        assert_eq!(tracker.status(now).state, MineContextState::Live);
+
+        // Mining stops if the disagreement lasts beyond the grace period.
+        endpoints.set(1, Ok(puzzle(3)));
+        assert_eq!(
+            tracker.on_query(query(&endpoints).await, now + GRACE),
+            Some(None)
+        );
+        assert_eq!(tracker.status(now).state, MineContextState::Unavailable);
+        assert_eq!(tracker.status(now).disagreements, 2);
+    }
+
+    #[tokio::test]
+    async fn test_total_outage() {
+        let endpoints = MockEndpoints::with(vec![Ok(puzzle(1)), Ok(puzzle(1))]);
+        let mut tracker = ContextTracker::new(GRACE);
+        let now = Instant::now();
+        assert_eq!(
+            tracker.on_query(query(&endpoints).await, now),
+            Some(puzzle(1))
+        );
+
+        // Keep mining on the last context in the grace period.
+        endpoints.set(0, Err("timeout".into()));
+        endpoints.set(1, Err("timeout".into()));
+        let half = now + GRACE / 2;
+        assert_eq!(tracker.on_query(query(&endpoints).await, half), None);
+        let status = tracker.status(half);
+        assert_eq!(status.state, MineContextState::Stale);
+        assert_eq!(status.epoch, Some(1));
+        assert_eq!(status.since_confirmed, Some(GRACE / 2));
+
+        let expired = now + GRACE;
+        assert_eq!(
+            tracker.on_query(query(&endpoints).await, expired),
+            Some(None)
+        );
+        assert_eq!(tracker.status(expired).state, MineContextState::Unavailable);
+        assert_eq!(tracker.status(expired).epoch, None);
+        assert_eq!(tracker.on_query(query(&endpoints).await, expired), None);
+
+        // Mining resumes once any endpoint recovers.
+        endpoints.set(1, Ok(puzzle(2)));
+        assert_eq!(
+            tracker.on_query(query(&endpoints).await, expired),
+            Some(puzzle(2))
+        );
+        assert_eq!(tracker.status(expired).state, MineContextState::Live);
+    }
+}
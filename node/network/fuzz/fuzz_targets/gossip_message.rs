@@ -0,0 +1,25 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use network::types::{GossipEncoding, GossipKind, GossipTopic};
+use network::{IdentTopic, PubsubMessage};
+
+const KINDS: [GossipKind; 9] = [
+    GossipKind::Example,
+    GossipKind::NewFile,
+    GossipKind::AskFile,
+    GossipKind::FindFile,
+    GossipKind::FindChunks,
+    GossipKind::AnnounceFile,
+    GossipKind::AnnounceShardConfig,
+    GossipKind::AnnounceChunks,
+    GossipKind::RetractFile,
+];
+
+// Gossip messages are decompressed by the snappy transform before decoding.
+fuzz_target!(|data: &[u8]| {
+    for kind in KINDS {
+        let topic = IdentTopic::from(GossipTopic::new(kind, GossipEncoding::SSZSnappy));
+        let _ = PubsubMessage::decode(&topic.hash(), data);
+    }
+});
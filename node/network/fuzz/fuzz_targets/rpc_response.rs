@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use network::rpc::{fuzz, RpcSizeLimits};
+
+fuzz_target!(|data: &[u8]| {
+    fuzz::decode_responses(data, RpcSizeLimits::default());
+});
@@ -1,5 +1,5 @@
 // use crate::error;
-use libp2p::gossipsub::PeerScoreThresholds;
+use libp2p::gossipsub::{PeerScoreThresholds, TopicScoreParams};
 // use std::cmp::max;
 // use std::collections::HashMap;
 // use std::time::Duration;
@@ -29,6 +29,18 @@ pub fn lighthouse_gossip_thresholds() -> PeerScoreThresholds {
     }
 }
 
+/// Builds the score parameters of the `AnnounceFile` topic, which is open to be flooded with
+/// bogus announcements. Peers that keep propagating invalid announcements are out of the gossip
+/// after about 20 invalid messages, and graylisted after about 40 invalid messages, while the
+/// penalty decays by half in about 1 minute.
+pub fn announce_file_topic_score_params() -> TopicScoreParams {
+    TopicScoreParams {
+        invalid_message_deliveries_weight: -20.0,
+        invalid_message_deliveries_decay: 0.99,
+        ..Default::default()
+    }
+}
+
 // pub struct PeerScoreSettings {
 //     // slot: Duration,
 //     epoch: Duration,
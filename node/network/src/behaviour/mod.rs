@@ -1,9 +1,12 @@
-use crate::behaviour::gossipsub_scoring_parameters::lighthouse_gossip_thresholds;
+use crate::behaviour::gossipsub_scoring_parameters::{
+    announce_file_topic_score_params, lighthouse_gossip_thresholds,
+};
 use crate::config::gossipsub_config;
 use crate::discovery::{Discovery, DiscoveryEvent, FIND_NODE_QUERY_CLOSEST_PEERS};
+use crate::nat::ObservedAddresses;
 use crate::peer_manager::{
     config::Config as PeerManagerCfg, peerdb::score::PeerAction, peerdb::score::ReportSource,
-    ConnectionDirection, PeerManager, PeerManagerEvent,
+    ConnectionDirection, PeerManager, PeerManagerEvent, RpcMessageKind,
 };
 use crate::rpc::methods::DataByHashRequest;
 use crate::rpc::methods::GetChunksRequest;
@@ -31,9 +34,10 @@ use libp2p::{
     },
     NetworkBehaviour, PeerId,
 };
-use shared_types::{ChunkArrayWithProof, ShardedFile};
+use shared_types::{ChunkArrayWithProof, ShardConfig, ShardedFile};
 use std::{
     collections::VecDeque,
+    net::{IpAddr, Ipv4Addr},
     sync::Arc,
     task::{Context, Poll},
 };
@@ -48,6 +52,9 @@ pub const TARGET_SUBNET_PEERS: usize = 6;
 
 const MAX_IDENTIFY_ADDRESSES: usize = 10;
 
+/// The number of distinct peers that must agree on the observed external address.
+const MIN_OBSERVED_ADDRESS_REPORTS: usize = 3;
+
 /// Identifier of requests sent by a peer.
 pub type PeerRequestId = (ConnectionId, SubstreamId);
 
@@ -80,6 +87,8 @@ pub enum BehaviourEvent<AppReqId: ReqId> {
         id: AppReqId,
         /// The peer to which this request was sent.
         peer_id: PeerId,
+        /// The error of the failed request, e.g. the error response of the peer.
+        error: RPCError,
     },
     RequestReceived {
         /// The peer that sent the request.
@@ -166,6 +175,12 @@ pub struct Behaviour<AppReqId: ReqId> {
     update_gossipsub_scores: tokio::time::Interval,
     #[behaviour(ignore)]
     gossip_cache: GossipCache,
+    /// The external address of the local node observed by peers.
+    #[behaviour(ignore)]
+    observed_addresses: ObservedAddresses,
+    /// Whether to update the ENR address with the observed external address.
+    #[behaviour(ignore)]
+    enr_address_from_observed: bool,
 }
 
 /// Implements the combined behaviour for the libp2p service.
@@ -185,8 +200,13 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
             )
             .with_cache_size(0)
         } else {
+            let version = &config.network_id.p2p_protocol_version;
             IdentifyConfig::new("eth2/1.0.0".into(), local_key.public())
-                .with_agent_version(zgs_version::version_with_platform())
+                .with_agent_version(zgs_version::agent_version([
+                    version.major,
+                    version.minor,
+                    version.build,
+                ]))
                 .with_cache_size(0)
         };
 
@@ -204,7 +224,8 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
         config.gs_config = gossipsub_config(config.network_load);
 
         // If metrics are enabled for gossipsub build the configuration
-        let snappy_transform = SnappyTransform::new(config.gs_config.max_transmit_size());
+        let snappy_transform = SnappyTransform::new(config.gs_config.max_transmit_size())
+            .with_traffic(network_globals.traffic.clone());
         let mut gossipsub = Gossipsub::new_with_subscription_filter_and_transform(
             MessageAuthenticity::Signed(local_key.clone()),
             config.gs_config.clone(),
@@ -247,7 +268,7 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
         );
         params.topics.insert(
             get_hash(GossipKind::AnnounceFile),
-            TopicScoreParams::default(),
+            announce_file_topic_score_params(),
         );
         params.topics.insert(
             get_hash(GossipKind::AnnounceShardConfig),
@@ -257,6 +278,10 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
             get_hash(GossipKind::AnnounceChunks),
             TopicScoreParams::default(),
         );
+        params.topics.insert(
+            get_hash(GossipKind::RetractFile),
+            TopicScoreParams::default(),
+        );
 
         // Set up a scoring update interval
         let update_gossipsub_scores = tokio::time::interval(params.decay_interval);
@@ -280,7 +305,11 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
         Ok(Behaviour {
             // Sub-behaviours
             gossipsub,
-            eth2_rpc: RPC::new(),
+            eth2_rpc: RPC::new(
+                config.rpc_max_version,
+                config.rpc_size_limits,
+                network_globals.traffic.clone(),
+            ),
             discovery,
             identify: Identify::new(identify_config),
             // Auxiliary fields
@@ -291,6 +320,8 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
             waker: None,
             gossip_cache,
             update_gossipsub_scores,
+            observed_addresses: ObservedAddresses::new(MIN_OBSERVED_ADDRESS_REPORTS),
+            enr_address_from_observed: config.enr_address_from_observed,
         })
     }
 
@@ -306,6 +337,18 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
         &mut self.peer_manager
     }
 
+    /// Updates the local shard config, which is re-published in the local ENR, and demanded
+    /// when dialing discovered peers if files are synced from peers.
+    pub fn update_shard_config(&mut self, shard_config: ShardConfig) {
+        if let Err(e) = self.discovery.update_enr_shard_config(shard_config) {
+            warn!(error = %e, "Failed to update ENR shard config");
+        }
+
+        let mut dial_demand = self.peer_manager.dial_demand();
+        dial_demand.update_shard_config(shard_config);
+        self.peer_manager.set_dial_demand(dial_demand);
+    }
+
     /// Returns the local ENR of the node.
     pub fn local_enr(&self) -> Enr {
         self.network_globals.local_enr()
@@ -453,6 +496,8 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
 
     /// Send a request to a peer over RPC.
     pub fn send_request(&mut self, peer_id: PeerId, request_id: AppReqId, request: Request) {
+        self.peer_manager
+            .record_rpc(&peer_id, RpcMessageKind::RequestSent);
         self.eth2_rpc
             .send_request(peer_id, RequestId::Application(request_id), request.into())
     }
@@ -464,6 +509,8 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
         id: PeerRequestId,
         response: Response,
     ) {
+        self.peer_manager
+            .record_rpc(&peer_id, RpcMessageKind::ResponseSent);
         self.eth2_rpc.send_response(peer_id, id, response.into())
     }
 
@@ -475,6 +522,8 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
         error: RPCResponseErrorCode,
         reason: String,
     ) {
+        self.peer_manager
+            .record_rpc(&peer_id, RpcMessageKind::ResponseSent);
         self.eth2_rpc
             .send_response(peer_id, id, RPCCodedResponse::Error(error, reason.into()))
     }
@@ -553,6 +602,13 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
             Request::GetChunks { .. } => {
                 metrics::inc_counter_vec(&metrics::TOTAL_RPC_REQUESTS, &["get_chunks"])
             }
+            Request::QueryFileStatus { .. } => {
+                metrics::inc_counter_vec(&metrics::TOTAL_RPC_REQUESTS, &["query_file_status"])
+            }
+            Request::GetAnnouncements { .. } => {
+                metrics::inc_counter_vec(&metrics::TOTAL_RPC_REQUESTS, &["get_announcements"])
+            }
+            Request::Ping => metrics::inc_counter_vec(&metrics::TOTAL_RPC_REQUESTS, &["ping"]),
         }
         self.add_event(BehaviourEvent::RequestReceived {
             peer_id,
@@ -568,6 +624,23 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
             waker.wake_by_ref();
         }
     }
+
+    /// Handles the changed external IP address agreed by peers.
+    fn on_external_ip_observed(&mut self, ip: Ipv4Addr) {
+        info!(%ip, "External IP address observed by peers");
+        self.network_globals.nat_status.write().observed_ip = Some(ip);
+
+        if self.enr_address_from_observed {
+            if let Err(e) = self.discovery.update_enr_ip(ip) {
+                warn!(error = %e, "Failed to update ENR address");
+            }
+        }
+
+        let mut multiaddr = Multiaddr::from(IpAddr::V4(ip));
+        multiaddr.push(MProtocol::Tcp(self.network_globals.listen_port_tcp()));
+        self.internal_events
+            .push_back(InternalBehaviourMessage::SocketUpdated(multiaddr));
+    }
 }
 
 /* Behaviour Event Process Implementations
@@ -742,12 +815,14 @@ where
                         );
                         // inform failures of requests comming outside the behaviour
                         if let RequestId::Application(id) = id {
-                            self.add_event(BehaviourEvent::RPCFailed { peer_id, id });
+                            self.add_event(BehaviourEvent::RPCFailed { peer_id, id, error });
                         }
                     }
                 }
             }
             Ok(RPCReceived::Request(id, request)) => {
+                self.peer_manager
+                    .record_rpc(&peer_id, RpcMessageKind::RequestReceived);
                 let peer_request_id = (handler_id, id);
                 match request {
                     /* Behaviour managed protocols: Ping and Metadata */
@@ -778,20 +853,48 @@ where
                         self.propagate_request(peer_request_id, peer_id, Request::Status(msg))
                     }
                     InboundRequest::DataByHash(req) => {
+                        self.peer_manager.record_activity(&peer_id, false);
                         self.propagate_request(peer_request_id, peer_id, Request::DataByHash(req))
                     }
                     InboundRequest::AnswerFile(req) => {
+                        self.peer_manager.record_activity(&peer_id, false);
                         self.propagate_request(peer_request_id, peer_id, Request::AnswerFile(req))
                     }
                     InboundRequest::GetChunks(req) => {
+                        // the peer is syncing file from us
+                        self.peer_manager.record_activity(&peer_id, true);
                         self.propagate_request(peer_request_id, peer_id, Request::GetChunks(req))
                     }
+                    InboundRequest::QueryFileStatus(req) => {
+                        self.peer_manager.record_activity(&peer_id, false);
+                        self.propagate_request(
+                            peer_request_id,
+                            peer_id,
+                            Request::QueryFileStatus(req),
+                        )
+                    }
+                    InboundRequest::GetAnnouncements(req) => {
+                        self.peer_manager.record_activity(&peer_id, false);
+                        self.propagate_request(
+                            peer_request_id,
+                            peer_id,
+                            Request::GetAnnouncements(req),
+                        )
+                    }
+                    // responded by the RPC behaviour
+                    InboundRequest::Unsupported { .. } => {}
                 }
             }
             Ok(RPCReceived::Response(id, resp)) => {
+                self.peer_manager
+                    .record_rpc(&peer_id, RpcMessageKind::ResponseReceived);
                 match resp {
                     /* Behaviour managed protocols */
-                    RPCResponse::Pong(ping) => self.peer_manager.pong_response(&peer_id, ping.data),
+                    RPCResponse::Pong(ping) => {
+                        self.peer_manager.pong_response(&peer_id, ping.data);
+                        // pings sent by the application, e.g. to detect dead sync peers
+                        self.propagate_response(id, peer_id, Response::Pong);
+                    }
                     /* Network propagated protocols */
                     RPCResponse::Status(msg) => {
                         // inform the peer manager that we have received a status from a peer
@@ -800,11 +903,22 @@ where
                         self.propagate_response(id, peer_id, Response::Status(msg));
                     }
                     RPCResponse::DataByHash(resp) => {
+                        self.peer_manager.record_activity(&peer_id, false);
                         self.propagate_response(id, peer_id, Response::DataByHash(Some(resp)))
                     }
                     RPCResponse::Chunks(resp) => {
+                        // we are syncing file from the peer
+                        self.peer_manager.record_activity(&peer_id, true);
                         self.propagate_response(id, peer_id, Response::Chunks(resp))
                     }
+                    RPCResponse::FileStatus(resp) => {
+                        self.peer_manager.record_activity(&peer_id, false);
+                        self.propagate_response(id, peer_id, Response::FileStatus(resp))
+                    }
+                    RPCResponse::Announcements(resp) => {
+                        self.peer_manager.record_activity(&peer_id, false);
+                        self.propagate_response(id, peer_id, Response::Announcements(resp))
+                    }
                 }
             }
             Ok(RPCReceived::EndOfStream(id, termination)) => {
@@ -813,6 +927,10 @@ where
                 };
                 self.propagate_response(id, peer_id, response);
             }
+            Ok(RPCReceived::VersionNegotiated(protocol, version)) => {
+                self.peer_manager
+                    .rpc_version_negotiated(&peer_id, protocol, version);
+            }
         }
     }
 }
@@ -863,6 +981,13 @@ where
                 }
                 // send peer info to the peer manager.
                 self.peer_manager.identify(&peer_id, &info);
+
+                if let Some(ip) = self
+                    .observed_addresses
+                    .observe(peer_id, &info.observed_addr)
+                {
+                    self.on_external_ip_observed(ip);
+                }
             }
             IdentifyEvent::Sent { .. } => {}
             IdentifyEvent::Error { .. } => {}
@@ -989,8 +1114,9 @@ impl<AppReqId: ReqId> NetworkBehaviourEventProcess<PeerManagerEvent> for Behavio
 /// The type of RPC requests the Behaviour informs it has received and allows for sending.
 ///
 // NOTE: This is an application-level wrapper over the lower network level requests that can be
-//       sent. The main difference is the absence of the Ping, Metadata and Goodbye protocols, which don't
-//       leave the Behaviour. For all protocols managed by RPC see `RPCRequest`.
+//       sent. The main difference is the absence of the Metadata and Goodbye protocols, which don't
+//       leave the Behaviour. Ping requests could be sent by the application, but inbound ones are
+//       always responded by the Behaviour. For all protocols managed by RPC see `RPCRequest`.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Request {
     /// A Status message.
@@ -1001,6 +1127,12 @@ pub enum Request {
     AnswerFile(ShardedFile),
     /// A GetChunks request.
     GetChunks(GetChunksRequest),
+    /// A QueryFileStatus request, e.g. to verify the file availability of a peer before sync.
+    QueryFileStatus(QueryFileStatusRequest),
+    /// A GetAnnouncements request, e.g. to backfill the files announced before connected.
+    GetAnnouncements(GetAnnouncementsRequest),
+    /// A Ping request, e.g. to detect dead peers during file sync.
+    Ping,
 }
 
 impl std::convert::From<Request> for OutboundRequest {
@@ -1010,6 +1142,9 @@ impl std::convert::From<Request> for OutboundRequest {
             Request::DataByHash(r) => OutboundRequest::DataByHash(r),
             Request::AnswerFile(r) => OutboundRequest::AnswerFile(r),
             Request::GetChunks(r) => OutboundRequest::GetChunks(r),
+            Request::QueryFileStatus(r) => OutboundRequest::QueryFileStatus(r),
+            Request::GetAnnouncements(r) => OutboundRequest::GetAnnouncements(r),
+            Request::Ping => OutboundRequest::Ping(crate::rpc::Ping { data: 1 }),
         }
     }
 }
@@ -1017,8 +1152,8 @@ impl std::convert::From<Request> for OutboundRequest {
 /// The type of RPC responses the Behaviour informs it has received, and allows for sending.
 ///
 // NOTE: This is an application-level wrapper over the lower network level responses that can be
-//       sent. The main difference is the absense of Pong and Metadata, which don't leave the
-//       Behaviour. For all protocol reponses managed by RPC see `RPCResponse` and
+//       sent. The main difference is the absense of Metadata, which doesn't leave the Behaviour.
+//       Pong responses are only propagated for the Ping requests sent by the application. For all protocol reponses managed by RPC see `RPCResponse` and
 //       `RPCCodedResponse`.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Response {
@@ -1028,6 +1163,12 @@ pub enum Response {
     DataByHash(Option<Box<ZgsData>>),
     /// A response to a GET_CHUNKS request.
     Chunks(ChunkArrayWithProof),
+    /// A response to a QUERY_FILE_STATUS request.
+    FileStatus(Box<FileStatus>),
+    /// A response to a GET_ANNOUNCEMENTS request.
+    Announcements(Announcements),
+    /// A response to a PING request.
+    Pong,
 }
 
 impl std::convert::From<Response> for RPCCodedResponse {
@@ -1039,6 +1180,11 @@ impl std::convert::From<Response> for RPCCodedResponse {
                 None => RPCCodedResponse::StreamTermination(ResponseTermination::DataByHash),
             },
             Response::Chunks(c) => RPCCodedResponse::Success(RPCResponse::Chunks(c)),
+            Response::FileStatus(s) => RPCCodedResponse::Success(RPCResponse::FileStatus(s)),
+            Response::Announcements(a) => RPCCodedResponse::Success(RPCResponse::Announcements(a)),
+            Response::Pong => {
+                RPCCodedResponse::Success(RPCResponse::Pong(crate::rpc::Ping { data: 1 }))
+            }
         }
     }
 }
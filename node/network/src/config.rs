@@ -1,5 +1,6 @@
+use crate::rpc::{RpcSizeLimits, Version};
 use crate::types::GossipKind;
-use crate::{peer_manager, Enr, PeerIdSerialized};
+use crate::{peer_manager, Enr, NodeCapabilities, PeerIdSerialized};
 use directory::{
     DEFAULT_BEACON_NODE_DIR, DEFAULT_HARDCODED_NETWORK, DEFAULT_NETWORK_DIR, DEFAULT_ROOT_DIR,
 };
@@ -11,7 +12,8 @@ use libp2p::gossipsub::{
 use libp2p::Multiaddr;
 use serde_derive::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use shared_types::NetworkIdentity;
+use shared_types::{NetworkIdentity, ShardConfig};
+use std::net::Ipv4Addr;
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -97,9 +99,22 @@ pub struct Config {
     /// Disables the discovery protocol from starting.
     pub disable_discovery: bool,
 
-    /// Attempt to construct external port mappings with UPnP.
+    /// Attempt to construct external port mappings with UPnP, which requires the `upnp`
+    /// feature.
     pub upnp_enabled: bool,
 
+    /// Attempt to construct external port mappings with NAT-PMP, if UPnP not available.
+    pub nat_pmp_enabled: bool,
+
+    /// The NAT-PMP gateway, which defaults to the first address of the local subnet.
+    pub nat_pmp_gateway: Option<Ipv4Addr>,
+
+    /// The lease of port mappings, which are refreshed before expiration.
+    pub port_mapping_lease: Duration,
+
+    /// Whether to update the ENR address once the external address observed by peers changes.
+    pub enr_address_from_observed: bool,
+
     /// Subscribe to all subnets for the duration of the runtime.
     pub subscribe_all_subnets: bool,
 
@@ -129,6 +144,7 @@ pub struct Config {
 
     pub peer_db: peer_manager::peerdb::PeerDBConfig,
     pub peer_manager: peer_manager::config::Config,
+    pub peer_policy: peer_manager::peer_policy::PeerPolicyConfig,
 
     /// Whether to disable network identity in ENR.
     /// This is for test purpose only.
@@ -136,6 +152,27 @@ pub struct Config {
 
     /// Whether to allow find chunks from peers.
     pub find_chunks_enabled: bool,
+
+    /// The local shard config advertised in ENR, if any.
+    #[serde(skip)]
+    pub shard_config: Option<ShardConfig>,
+
+    /// The local node capabilities advertised in ENR.
+    #[serde(skip)]
+    pub capabilities: NodeCapabilities,
+
+    /// Whether files are synced from peers, which demands peers that could serve data of the
+    /// local shard when dialing discovered peers.
+    #[serde(skip)]
+    pub syncs_files: bool,
+
+    /// The highest RPC protocol version to negotiate with peers.
+    /// This is for test purpose only.
+    #[serde(skip)]
+    pub rpc_max_version: Version,
+
+    /// Size limits of RPC messages, which are rejected before decoding once out of limits.
+    pub rpc_size_limits: RpcSizeLimits,
 }
 
 impl Default for Config {
@@ -205,7 +242,11 @@ impl Default for Config {
             trusted_peers: vec![],
             client_version: zgs_version::version_with_platform(),
             disable_discovery: false,
-            upnp_enabled: true,
+            upnp_enabled: false,
+            nat_pmp_enabled: false,
+            nat_pmp_gateway: None,
+            port_mapping_lease: Duration::from_secs(3600),
+            enr_address_from_observed: false,
             network_load: 3,
             private: false,
             subscribe_all_subnets: false,
@@ -216,8 +257,14 @@ impl Default for Config {
             network_id: Default::default(),
             peer_db: Default::default(),
             peer_manager: Default::default(),
+            peer_policy: Default::default(),
             disable_enr_network_id: false,
             find_chunks_enabled: false,
+            shard_config: None,
+            capabilities: NodeCapabilities::all(),
+            syncs_files: true,
+            rpc_max_version: Version::LATEST,
+            rpc_size_limits: Default::default(),
         }
     }
 }
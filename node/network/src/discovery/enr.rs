@@ -3,7 +3,10 @@
 pub use discv5::enr::{CombinedKey, EnrBuilder};
 use ssz::Encode;
 
-use super::enr_ext::{CombinedKeyExt, ENR_CONTENT_KEY_NETWORK_ID};
+use super::enr_ext::{
+    CombinedKeyExt, ENR_CONTENT_KEY_CAPABILITIES, ENR_CONTENT_KEY_NETWORK_ID,
+    ENR_CONTENT_KEY_SHARD_CONFIG,
+};
 use super::{EnrExt, ENR_FILENAME};
 use crate::types::Enr;
 use crate::NetworkConfig;
@@ -35,6 +38,7 @@ pub fn use_or_load_enr(
                         if local_enr.node_id() == disk_enr.node_id() {
                             if compare_enr(local_enr, &disk_enr)
                                 && is_disk_enr_network_id_unchanged(&disk_enr, config)
+                                && is_disk_enr_content_unchanged(local_enr, &disk_enr)
                             {
                                 debug!(file = ?enr_f, "ENR loaded from disk");
                                 // the stored ENR has the same configuration, use it
@@ -104,6 +108,14 @@ pub fn create_enr_builder_from_config<T: EnrKey>(
             &config.network_id.as_ssz_bytes(),
         );
     }
+    // advertise shard config and capabilities, so that peers could dial on demand
+    if let Some(shard_config) = config.shard_config {
+        builder.add_value(ENR_CONTENT_KEY_SHARD_CONFIG, &shard_config.as_ssz_bytes());
+    }
+    builder.add_value(
+        ENR_CONTENT_KEY_CAPABILITIES,
+        &config.capabilities.as_ssz_bytes(),
+    );
     builder
 }
 
@@ -135,6 +147,11 @@ fn is_disk_enr_network_id_unchanged(disk_enr: &Enr, config: &NetworkConfig) -> b
     }
 }
 
+fn is_disk_enr_content_unchanged(local_enr: &Enr, disk_enr: &Enr) -> bool {
+    local_enr.shard_config() == disk_enr.shard_config()
+        && local_enr.capabilities() == disk_enr.capabilities()
+}
+
 /// Loads enr from the given directory
 pub fn load_enr_from_disk(dir: &Path) -> Result<Enr, String> {
     let enr_f = dir.join(ENR_FILENAME);
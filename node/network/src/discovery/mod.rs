@@ -12,6 +12,7 @@ use discv5::{enr::NodeId, Discv5, Discv5Event};
 pub use enr::{
     build_enr, create_enr_builder_from_config, load_enr_from_disk, use_or_load_enr, CombinedKey,
 };
+use enr_ext::ENR_CONTENT_KEY_SHARD_CONFIG;
 pub use enr_ext::{peer_id_to_node_id, CombinedKeyExt, EnrExt};
 pub use libp2p::core::identity::{Keypair, PublicKey};
 
@@ -25,9 +26,11 @@ pub use libp2p::{
     },
 };
 use lru::LruCache;
+use shared_types::ShardConfig;
+use ssz::Encode;
 use std::{
     collections::HashMap,
-    net::{IpAddr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     path::Path,
     pin::Pin,
     sync::Arc,
@@ -47,13 +50,22 @@ pub const FIND_NODE_QUERY_CLOSEST_PEERS: usize = 16;
 
 /// The events emitted by polling discovery.
 pub enum DiscoveryEvent {
-    /// A query has completed. This result contains a mapping of discovered peer IDs to the `min_ttl`
-    /// of the peer if it is specified.
-    QueryResult(HashMap<PeerId, Option<Instant>>),
+    /// A query has completed. This result contains a mapping of discovered peer IDs to the ENR
+    /// and `min_ttl` of the peer if it is specified.
+    QueryResult(HashMap<PeerId, DiscoveredPeer>),
     /// This indicates that our local UDP socketaddr has been updated and we should inform libp2p.
     SocketUpdated(SocketAddr),
 }
 
+/// A peer found by a discovery query.
+#[derive(Debug, Clone)]
+pub struct DiscoveredPeer {
+    /// The ENR of the peer, which is used to filter peers before dialing.
+    pub enr: Enr,
+    /// The `min_ttl` of the peer if it is specified.
+    pub min_ttl: Option<Instant>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 enum QueryType {
     /// We are searching for more peers without ENR or time constraints.
@@ -378,6 +390,34 @@ impl Discovery {
         Ok(())
     }
 
+    /// Updates the local ENR IPv4 address, e.g. once the external address observed by peers
+    /// changed, while the ports remain unchanged.
+    pub fn update_enr_ip(&mut self, ip: Ipv4Addr) -> Result<(), String> {
+        self.discv5
+            .enr_insert("ip", &ip.octets())
+            .map_err(|e| format!("{:?}", e))?;
+
+        // replace the global version
+        *self.network_globals.local_enr.write() = self.discv5.local_enr();
+        // persist modified enr to disk
+        enr::save_enr_to_disk(Path::new(&self.enr_dir), &self.local_enr());
+        Ok(())
+    }
+
+    /// Updates the local ENR shard config, e.g. once resharded by pruner, so that peers could
+    /// discover the latest shard config.
+    pub fn update_enr_shard_config(&mut self, shard_config: ShardConfig) -> Result<(), String> {
+        self.discv5
+            .enr_insert(ENR_CONTENT_KEY_SHARD_CONFIG, &shard_config.as_ssz_bytes())
+            .map_err(|e| format!("{:?}", e))?;
+
+        // replace the global version
+        *self.network_globals.local_enr.write() = self.discv5.local_enr();
+        // persist modified enr to disk
+        enr::save_enr_to_disk(Path::new(&self.enr_dir), &self.local_enr());
+        Ok(())
+    }
+
     // Bans a peer and it's associated seen IP addresses.
     pub fn ban_peer(&mut self, peer_id: &PeerId, ip_addresses: Vec<IpAddr>) {
         // first try and convert the peer_id to a node_id.
@@ -450,7 +490,7 @@ impl Discovery {
     fn process_completed_queries(
         &mut self,
         query: QueryResult,
-    ) -> Option<HashMap<PeerId, Option<Instant>>> {
+    ) -> Option<HashMap<PeerId, DiscoveredPeer>> {
         match query.query_type {
             QueryType::FindPeers => {
                 self.find_peer_active = false;
@@ -460,11 +500,11 @@ impl Discovery {
                     }
                     Ok(r) => {
                         debug!(peers_found = r.len(), "Discovery query completed");
-                        let mut results: HashMap<_, Option<Instant>> = HashMap::new();
-                        r.iter().for_each(|enr| {
+                        let mut results: HashMap<_, DiscoveredPeer> = HashMap::new();
+                        r.into_iter().for_each(|enr| {
                             // cache the found ENR's
                             self.cached_enrs.put(enr.peer_id(), enr.clone());
-                            results.insert(enr.peer_id(), None);
+                            results.insert(enr.peer_id(), DiscoveredPeer { enr, min_ttl: None });
                         });
                         return Some(results);
                     }
@@ -479,7 +519,7 @@ impl Discovery {
     }
 
     /// Drives the queries returning any results from completed queries.
-    fn poll_queries(&mut self, cx: &mut Context) -> Option<HashMap<PeerId, Option<Instant>>> {
+    fn poll_queries(&mut self, cx: &mut Context) -> Option<HashMap<PeerId, DiscoveredPeer>> {
         while let Poll::Ready(Some(query_result)) = self.active_queries.poll_next_unpin(cx) {
             let result = self.process_completed_queries(query_result);
             if result.is_some() {
@@ -24,7 +24,7 @@ mod service;
 pub mod types;
 
 pub use config::gossip_max_size;
-use std::net::SocketAddr;
+use futures::channel::oneshot;
 use std::time::Instant;
 
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
@@ -72,7 +72,10 @@ impl<'de> Deserialize<'de> for PeerIdSerialized {
     }
 }
 
-pub use crate::types::{error, Enr, GossipTopic, NetworkGlobals, PubsubMessage};
+pub use crate::types::{
+    error, Enr, GossipTopic, NetworkGlobals, NetworkTraffic, NodeCapabilities, PeerTraffic,
+    ProtocolTraffic, PubsubMessage, TrafficCounter, TrafficDirection, TrafficStats,
+};
 
 pub use behaviour::{BehaviourEvent, Gossipsub, PeerRequestId, Request, Response};
 pub use config::Config as NetworkConfig;
@@ -86,11 +89,16 @@ pub use libp2p::{core::ConnectedPoint, PeerId, Swarm};
 pub use libp2p::{multiaddr, Multiaddr};
 pub use metrics::scrape_discovery_metrics;
 pub use peer_manager::{
+    connection_budget::ConnectionStats,
+    dial_demand::DialDemand,
+    peer_policy::{PeerPolicy, PeerPolicyConfig},
     peerdb::client::Client,
     peerdb::score::{PeerAction, ReportSource},
     peerdb::PeerDB,
-    ConnectionDirection, PeerConnectionStatus, PeerInfo, PeerManager, SyncInfo, SyncStatus,
+    ConnectionDirection, PeerConnectionStatus, PeerInfo, PeerManager, RpcCounters, SyncInfo,
+    SyncStatus,
 };
+pub use rpc::RpcSizeLimits;
 pub use service::{load_private_key, Context, Libp2pEvent, Service, NETWORK_KEY_FILENAME};
 
 /// Defines the current P2P protocol version.
@@ -112,7 +120,17 @@ pub enum RequestId {
 
 #[derive(Debug, Clone, Copy)]
 pub enum SyncId {
-    SerialSync { tx_id: TxID },
+    SerialSync {
+        tx_id: TxID,
+    },
+    /// Query of the file status to verify a peer before file sync.
+    FileStatus {
+        tx_id: TxID,
+    },
+    /// Ping to detect dead peers of file syncs.
+    Ping,
+    /// Exchange of the files recently announced once connected.
+    Announcements,
 }
 
 /// Types of messages that the network service can receive.
@@ -123,6 +141,9 @@ pub enum NetworkMessage {
         peer_id: PeerId,
         request: Request,
         request_id: RequestId,
+        /// Span of the requester, e.g. a file sync on behalf of an RPC call, to correlate the
+        /// request logs. It is never sent to the peer.
+        span: tracing::Span,
     },
     /// Send a successful Response to the libp2p service.
     SendResponse {
@@ -154,17 +175,19 @@ pub enum NetworkMessage {
     },
     /// Start dialing a new peer.
     DialPeer { address: Multiaddr, peer_id: PeerId },
+    /// Dial a peer and respond once connected or failed to dial, e.g. by admin RPC.
+    ConnectPeer {
+        address: Multiaddr,
+        peer_id: PeerId,
+        sender: oneshot::Sender<Result<(), String>>,
+    },
     /// Disconnect a peer.
     DisconnectPeer { peer_id: PeerId },
-    /// Notify that new file stored in db.
+    /// Notify that new file stored in db, while the files finalized in db are announced by
+    /// router via the finalization bus of store.
     AnnounceLocalFile { tx_id: TxID },
-    /// Called if a known external TCP socket address has been updated.
-    UPnPMappingEstablished {
-        /// The external TCP address has been updated.
-        tcp_socket: Option<SocketAddr>,
-        /// The external UDP address has been updated.
-        udp_socket: Option<SocketAddr>,
-    },
+    /// Called if the external TCP or UDP socket address has been updated by port mapping.
+    PortMappingEstablished { mapping: nat::PortMapping },
 }
 
 pub type NetworkSender = channel::metrics::Sender<NetworkMessage>;
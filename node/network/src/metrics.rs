@@ -64,6 +64,16 @@ lazy_static! {
         "RPC errors per client",
         &["client", "rpc_error", "direction"]
     );
+    pub static ref NETWORK_MESSAGES: Result<IntCounterVec> = try_create_int_counter_vec(
+        "libp2p_messages_total",
+        "Number of RPC and gossip messages per protocol and direction",
+        &["protocol", "direction"]
+    );
+    pub static ref NETWORK_MESSAGE_BYTES: Result<IntCounterVec> = try_create_int_counter_vec(
+        "libp2p_message_bytes_total",
+        "Bytes of RPC and gossip messages per protocol and direction",
+        &["protocol", "direction"]
+    );
     pub static ref TOTAL_RPC_REQUESTS: Result<IntCounterVec> = try_create_int_counter_vec(
         "libp2p_rpc_requests_total",
         "RPC requests total",
@@ -123,6 +133,19 @@ lazy_static! {
     pub static ref NETWORK_OUTBOUND_PEERS: Result<IntGauge> =
         try_create_int_gauge("network_outbound_peers","The number of peers that are currently connected that we dialed.");
 
+    /// The number of peers in the reserved connection slots per role.
+    pub static ref NETWORK_RESERVED_PEERS: Result<IntGaugeVec> = try_create_int_gauge_vec(
+        "network_reserved_peers",
+        "The number of peers that are currently connected in the reserved slots per role.",
+        &["role"]
+    );
+
+    /// The number of peers evicted due to the connection budget.
+    pub static ref PEERS_EVICTED: Result<IntCounter> = try_create_int_counter(
+        "libp2p_peers_evicted_total",
+        "Number of peers disconnected due to over the connection budget"
+    );
+
     /*
      * Peer Reporting
      */
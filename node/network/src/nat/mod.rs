@@ -0,0 +1,222 @@
+//! This houses various NAT hole punching strategies.
+//!
+//! Currently supported strategies:
+//! - UPnP, if built with the `upnp` feature
+//! - NAT-PMP
+//! - External address observed by peers
+
+mod nat_pmp;
+mod observed;
+#[cfg(feature = "upnp")]
+mod upnp;
+
+pub use observed::ObservedAddresses;
+
+use crate::{NetworkConfig, NetworkGlobals, NetworkMessage, NetworkSender};
+use if_addrs::get_if_addrs;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use task_executor::TaskExecutor;
+
+/// Configuration required to construct the port mappings.
+#[derive(Debug, Clone)]
+pub struct PortMappingConfig {
+    /// The local tcp port.
+    tcp_port: u16,
+    /// The local udp port.
+    udp_port: u16,
+    /// Whether discovery is enabled or not.
+    disable_discovery: bool,
+    upnp_enabled: bool,
+    nat_pmp_enabled: bool,
+    /// The NAT-PMP gateway, which defaults to the first address of the local subnet.
+    nat_pmp_gateway: Option<Ipv4Addr>,
+    /// The lease of port mappings, which are refreshed at half of the lease.
+    lease: Duration,
+}
+
+impl PortMappingConfig {
+    /// Returns `None` if port mapping is disabled.
+    pub fn from_config(config: &NetworkConfig) -> Option<Self> {
+        if !config.upnp_enabled && !config.nat_pmp_enabled {
+            return None;
+        }
+
+        if config.upnp_enabled && cfg!(not(feature = "upnp")) {
+            warn!("UPnP is not supported in this build, enable the `upnp` feature to use it");
+        }
+
+        Some(PortMappingConfig {
+            tcp_port: config.libp2p_port,
+            udp_port: config.discovery_port,
+            disable_discovery: config.disable_discovery,
+            upnp_enabled: config.upnp_enabled,
+            nat_pmp_enabled: config.nat_pmp_enabled,
+            nat_pmp_gateway: config.nat_pmp_gateway,
+            lease: config.port_mapping_lease,
+        })
+    }
+
+    fn lease_secs(&self) -> u32 {
+        self.lease.as_secs().min(u32::MAX as u64) as u32
+    }
+}
+
+/// The method by which a port mapping was constructed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingMethod {
+    UPnP,
+    /// NAT-PMP with the gateway address.
+    NatPmp(Ipv4Addr),
+}
+
+impl fmt::Display for MappingMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MappingMethod::UPnP => write!(f, "UPnP"),
+            MappingMethod::NatPmp(_) => write!(f, "NAT-PMP"),
+        }
+    }
+}
+
+/// The external sockets mapped to the local TCP and UDP ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortMapping {
+    pub method: MappingMethod,
+    /// The local TCP port that is mapped.
+    pub local_tcp_port: u16,
+    /// The local UDP port that is mapped.
+    pub local_udp_port: u16,
+    pub tcp_socket: Option<SocketAddr>,
+    pub udp_socket: Option<SocketAddr>,
+}
+
+/// Whether the local node is publicly reachable, which is shared via `NetworkGlobals`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NatStatus {
+    /// The port mapping currently established, if any.
+    pub port_mapping: Option<PortMapping>,
+    /// The external IP address agreed by peers, if any.
+    pub observed_ip: Option<Ipv4Addr>,
+    /// Whether any peer has dialed us.
+    pub inbound_connected: bool,
+}
+
+impl NatStatus {
+    /// The node is considered publicly reachable once a port mapping is established or any peer
+    /// has dialed us.
+    pub fn is_publicly_reachable(&self) -> bool {
+        self.port_mapping.is_some() || self.inbound_connected
+    }
+}
+
+/// Constructs the port mappings at startup, and refreshes them before the lease expires. The
+/// network service is notified whenever the mapped external sockets change.
+pub async fn maintain_port_mappings(
+    executor: TaskExecutor,
+    config: PortMappingConfig,
+    network_globals: Arc<NetworkGlobals>,
+    network_send: NetworkSender,
+) {
+    let refresh_interval = (config.lease / 2).max(Duration::from_secs(60));
+
+    loop {
+        let task_config = config.clone();
+        let mapping = match executor
+            .spawn_blocking_handle(move || construct_mappings(&task_config), "port_mapping")
+        {
+            Some(task) => task.await.unwrap_or_default(),
+            // runtime shutting down
+            None => return,
+        };
+
+        let last_mapping = network_globals.nat_status.read().port_mapping;
+        if mapping != last_mapping {
+            match &mapping {
+                Some(mapping) => info!(
+                    method = %mapping.method,
+                    tcp_socket = ?mapping.tcp_socket,
+                    udp_socket = ?mapping.udp_socket,
+                    "Port mapping established, node is publicly reachable"
+                ),
+                None => warn!(
+                    "Port mapping failed, node may not be publicly reachable unless ports are forwarded manually"
+                ),
+            }
+
+            network_globals.nat_status.write().port_mapping = mapping;
+
+            if let Some(mapping) = mapping {
+                network_send
+                    .send(NetworkMessage::PortMappingEstablished { mapping })
+                    .unwrap_or_else(
+                        |e| debug!(error = %e, "Could not send message to the network service"),
+                    );
+            }
+        }
+
+        tokio::time::sleep(refresh_interval).await;
+    }
+}
+
+/// Attempts to construct external port mappings, trying UPnP first and then NAT-PMP.
+fn construct_mappings(config: &PortMappingConfig) -> Option<PortMapping> {
+    let local_ip = local_ipv4()?;
+    debug!(ip = %local_ip, "Port mapping local IP discovered");
+
+    #[cfg(feature = "upnp")]
+    if config.upnp_enabled {
+        if let Some(mapping) = upnp::construct_mappings(config, local_ip) {
+            return Some(mapping);
+        }
+    }
+
+    if config.nat_pmp_enabled {
+        return nat_pmp::construct_mappings(config, local_ip);
+    }
+
+    None
+}
+
+/// Need to find the local listening address matched with the router subnet.
+fn local_ipv4() -> Option<Ipv4Addr> {
+    let interfaces = match get_if_addrs() {
+        Ok(v) => v,
+        Err(e) => {
+            info!(error = %e, "Port mapping failed to get local interfaces");
+            return None;
+        }
+    };
+
+    // Just use the first IP of the first interface that is not a loopback and not an ipv6
+    // address.
+    let local_ip = interfaces
+        .iter()
+        .filter(|interface| !interface.is_loopback())
+        .find_map(|interface| match interface.ip() {
+            IpAddr::V4(ip) => Some(ip),
+            IpAddr::V6(_) => None,
+        });
+
+    if local_ip.is_none() {
+        info!("Port mapping failed to find local IP address");
+    }
+
+    local_ip
+}
+
+/// Removes the port mappings, e.g. when the node shuts down.
+pub fn remove_mappings(mapping: &PortMapping) {
+    let tcp_port = mapping.tcp_socket.map(|_| mapping.local_tcp_port);
+    let udp_port = mapping.udp_socket.map(|_| mapping.local_udp_port);
+
+    match mapping.method {
+        #[cfg(feature = "upnp")]
+        MappingMethod::UPnP => upnp::remove_mappings(tcp_port, udp_port),
+        #[cfg(not(feature = "upnp"))]
+        MappingMethod::UPnP => {}
+        MappingMethod::NatPmp(gateway) => nat_pmp::remove_mappings(gateway, tcp_port, udp_port),
+    }
+}
@@ -0,0 +1,278 @@
+//! Port mappings via NAT-PMP (RFC 6886), which is supported by many home routers that have
+//! UPnP disabled.
+
+use super::{MappingMethod, PortMapping, PortMappingConfig};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::time::Duration;
+
+/// The port that NAT-PMP gateways listen on.
+const NAT_PMP_PORT: u16 = 5351;
+const VERSION: u8 = 0;
+const OP_EXTERNAL_ADDRESS: u8 = 0;
+const OP_MAP_UDP: u8 = 1;
+const OP_MAP_TCP: u8 = 2;
+/// Responses have the opcode of request plus 128.
+const OP_RESPONSE: u8 = 128;
+const RESULT_SUCCESS: u16 = 0;
+
+/// Requests are retransmitted with the timeout doubled each time.
+const INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Attempts to construct external port mappings with NAT-PMP.
+pub(super) fn construct_mappings(
+    config: &PortMappingConfig,
+    local_ip: Ipv4Addr,
+) -> Option<PortMapping> {
+    let gateway = config
+        .nat_pmp_gateway
+        .unwrap_or_else(|| default_gateway(local_ip));
+    debug!(%gateway, "NAT-PMP Attempting to initialise routes");
+
+    let client = match Client::new(local_ip, SocketAddrV4::new(gateway, NAT_PMP_PORT)) {
+        Ok(client) => client,
+        Err(e) => {
+            info!(error = %e, "NAT-PMP failed to bind local socket");
+            return None;
+        }
+    };
+
+    let external_ip = match client.external_address() {
+        Ok(ip) => ip,
+        Err(e) => {
+            info!(%gateway, error = %e, "NAT-PMP not available");
+            return None;
+        }
+    };
+
+    let tcp_socket = client
+        .map_port(OP_MAP_TCP, config.tcp_port, config.lease_secs())
+        .map_err(|e| info!(error = %e, "NAT-PMP TCP route not set"))
+        .map(|port| SocketAddr::new(external_ip.into(), port))
+        .ok();
+
+    let udp_socket = if !config.disable_discovery {
+        client
+            .map_port(OP_MAP_UDP, config.udp_port, config.lease_secs())
+            .map_err(|e| info!(error = %e, "NAT-PMP UDP route not set"))
+            .map(|port| SocketAddr::new(external_ip.into(), port))
+            .ok()
+    } else {
+        None
+    };
+
+    if tcp_socket.is_none() && udp_socket.is_none() {
+        return None;
+    }
+
+    Some(PortMapping {
+        method: MappingMethod::NatPmp(gateway),
+        local_tcp_port: config.tcp_port,
+        local_udp_port: config.udp_port,
+        tcp_socket,
+        udp_socket,
+    })
+}
+
+/// Removes the specified TCP and UDP port mappings, by requesting a zero lifetime.
+pub(super) fn remove_mappings(gateway: Ipv4Addr, tcp_port: Option<u16>, udp_port: Option<u16>) {
+    debug!("Removing NAT-PMP port mappings");
+    let client = match Client::new(
+        Ipv4Addr::UNSPECIFIED,
+        SocketAddrV4::new(gateway, NAT_PMP_PORT),
+    ) {
+        Ok(client) => client,
+        Err(e) => {
+            debug!(error = %e, "NAT-PMP failed to remove mappings");
+            return;
+        }
+    };
+
+    for (op, port) in [(OP_MAP_TCP, tcp_port), (OP_MAP_UDP, udp_port)] {
+        if let Some(port) = port {
+            match client.map_port(op, port, 0) {
+                Ok(_) => debug!(port, op, "NAT-PMP Removed port mapping"),
+                Err(e) => debug!(port, op, error = %e, "NAT-PMP Failed to remove port mapping"),
+            }
+        }
+    }
+}
+
+/// Routers are usually the first address of the local subnet.
+fn default_gateway(local_ip: Ipv4Addr) -> Ipv4Addr {
+    let [a, b, c, _] = local_ip.octets();
+    Ipv4Addr::new(a, b, c, 1)
+}
+
+struct Client {
+    socket: UdpSocket,
+    gateway: SocketAddrV4,
+}
+
+impl Client {
+    fn new(local_ip: Ipv4Addr, gateway: SocketAddrV4) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(SocketAddrV4::new(local_ip, 0))?;
+        Ok(Self { socket, gateway })
+    }
+
+    fn external_address(&self) -> Result<Ipv4Addr, String> {
+        let response = self.request(&[VERSION, OP_EXTERNAL_ADDRESS], OP_EXTERNAL_ADDRESS, 12)?;
+        Ok(Ipv4Addr::new(
+            response[8],
+            response[9],
+            response[10],
+            response[11],
+        ))
+    }
+
+    /// Maps the internal port to the same external port if possible, and returns the mapped
+    /// external port.
+    fn map_port(&self, op: u8, port: u16, lifetime_secs: u32) -> Result<u16, String> {
+        let response = self.request(&encode_mapping_request(op, port, lifetime_secs), op, 16)?;
+        Ok(u16::from_be_bytes([response[10], response[11]]))
+    }
+
+    /// Sends the request and waits for the response, retransmitting on timeout.
+    fn request(&self, request: &[u8], op: u8, response_len: usize) -> Result<Vec<u8>, String> {
+        let mut timeout = INITIAL_TIMEOUT;
+        let mut buf = [0u8; 16];
+
+        for _ in 0..MAX_ATTEMPTS {
+            self.socket
+                .send_to(request, self.gateway)
+                .map_err(|e| format!("failed to send request: {}", e))?;
+            self.socket
+                .set_read_timeout(Some(timeout))
+                .map_err(|e| format!("failed to set timeout: {}", e))?;
+
+            loop {
+                match self.socket.recv_from(&mut buf) {
+                    Ok((n, from)) if from == SocketAddr::V4(self.gateway) => {
+                        return decode_response(&buf[..n], op, response_len)
+                            .map(|response| response.to_vec());
+                    }
+                    // ignore packets from others
+                    Ok(_) => continue,
+                    Err(_) => break,
+                }
+            }
+
+            timeout *= 2;
+        }
+
+        Err("no response from gateway".into())
+    }
+}
+
+fn encode_mapping_request(op: u8, port: u16, lifetime_secs: u32) -> [u8; 12] {
+    let mut request = [0u8; 12];
+    request[0] = VERSION;
+    request[1] = op;
+    // bytes 2..4 are reserved
+    request[4..6].copy_from_slice(&port.to_be_bytes());
+    request[6..8].copy_from_slice(&port.to_be_bytes());
+    request[8..12].copy_from_slice(&lifetime_secs.to_be_bytes());
+    request
+}
+
+/// Validates the header of a response.
+fn decode_response(response: &[u8], op: u8, response_len: usize) -> Result<&[u8], String> {
+    if response.len() < response_len {
+        return Err(format!("invalid response length {}", response.len()));
+    }
+
+    if response[0] != VERSION || response[1] != OP_RESPONSE + op {
+        return Err(format!(
+            "unexpected response version {} opcode {}",
+            response[0], response[1]
+        ));
+    }
+
+    match u16::from_be_bytes([response[2], response[3]]) {
+        RESULT_SUCCESS => Ok(response),
+        1 => Err("unsupported version".into()),
+        2 => Err("not authorized or refused".into()),
+        3 => Err("network failure".into()),
+        4 => Err("out of resources".into()),
+        5 => Err("unsupported opcode".into()),
+        code => Err(format!("unknown result code {}", code)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Responds to NAT-PMP requests with the specified external IP address.
+    fn fake_gateway(external_ip: Ipv4Addr, result_code: u16) -> SocketAddrV4 {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = match socket.local_addr().unwrap() {
+            SocketAddr::V4(addr) => addr,
+            _ => unreachable!(),
+        };
+
+        thread::spawn(move || {
+            let mut buf = [0u8; 12];
+            while let Ok((n, from)) = socket.recv_from(&mut buf) {
+                let op = buf[1];
+                let mut response = vec![VERSION, OP_RESPONSE + op];
+                response.extend_from_slice(&result_code.to_be_bytes());
+                // seconds since start of epoch
+                response.extend_from_slice(&100u32.to_be_bytes());
+                if op == OP_EXTERNAL_ADDRESS {
+                    response.extend_from_slice(&external_ip.octets());
+                } else {
+                    assert_eq!(n, 12);
+                    // map to the next external port
+                    let port = u16::from_be_bytes([buf[4], buf[5]]);
+                    response.extend_from_slice(&port.to_be_bytes());
+                    response.extend_from_slice(&(port + 1).to_be_bytes());
+                    response.extend_from_slice(&buf[8..12]);
+                }
+                socket.send_to(&response, from).unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn test_map_port() {
+        let external_ip = Ipv4Addr::new(1, 2, 3, 4);
+        let gateway = fake_gateway(external_ip, RESULT_SUCCESS);
+        let client = Client::new(Ipv4Addr::LOCALHOST, gateway).unwrap();
+
+        assert_eq!(client.external_address(), Ok(external_ip));
+        assert_eq!(client.map_port(OP_MAP_TCP, 1234, 3600), Ok(1235));
+        assert_eq!(client.map_port(OP_MAP_UDP, 1234, 0), Ok(1235));
+    }
+
+    #[test]
+    fn test_map_port_refused() {
+        let gateway = fake_gateway(Ipv4Addr::new(1, 2, 3, 4), 2);
+        let client = Client::new(Ipv4Addr::LOCALHOST, gateway).unwrap();
+
+        assert_eq!(
+            client.map_port(OP_MAP_TCP, 1234, 3600),
+            Err("not authorized or refused".into())
+        );
+    }
+
+    #[test]
+    fn test_decode_response() {
+        let request = encode_mapping_request(OP_MAP_TCP, 0x1234, 7200);
+        assert_eq!(
+            request,
+            [0, 2, 0, 0, 0x12, 0x34, 0x12, 0x34, 0, 0, 0x1c, 0x20]
+        );
+
+        // too short
+        assert!(decode_response(&[0, 130, 0, 0], OP_MAP_TCP, 16).is_err());
+        // mismatched opcode
+        assert!(
+            decode_response(&[0u8, 129, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], OP_MAP_TCP, 12).is_err()
+        );
+        assert!(decode_response(&[0u8, 130, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], OP_MAP_TCP, 12).is_ok());
+    }
+}
@@ -0,0 +1,106 @@
+//! External address observed by peers via identify.
+
+use crate::{Multiaddr, PeerId};
+use libp2p::multiaddr::Protocol;
+use lru::LruCache;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+
+/// Maximum number of peers whose observations are retained.
+const MAX_REPORTERS: usize = 64;
+
+/// Tracks the external IP address reported by peers, which is considered changed only if
+/// reported by at least `min_reports` distinct peers, and by the majority of recent reporters,
+/// so that a few malicious or misconfigured peers could not redirect the advertised address.
+pub struct ObservedAddresses {
+    min_reports: usize,
+    reports: LruCache<PeerId, Ipv4Addr>,
+    current: Option<Ipv4Addr>,
+}
+
+impl ObservedAddresses {
+    pub fn new(min_reports: usize) -> Self {
+        Self {
+            min_reports,
+            reports: LruCache::new(MAX_REPORTERS),
+            current: None,
+        }
+    }
+
+    /// Returns the current external IP address agreed by peers, if any.
+    pub fn current(&self) -> Option<Ipv4Addr> {
+        self.current
+    }
+
+    /// Records the address of the local node observed by a peer, and returns the new external
+    /// IP address once changed.
+    pub fn observe(&mut self, peer_id: PeerId, observed_addr: &Multiaddr) -> Option<Ipv4Addr> {
+        let ip = observed_addr.iter().find_map(|protocol| match protocol {
+            Protocol::Ip4(ip) => Some(ip),
+            _ => None,
+        })?;
+
+        // peers in the same LAN observe the local address
+        if ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified() {
+            return None;
+        }
+
+        self.reports.put(peer_id, ip);
+
+        let mut votes: HashMap<Ipv4Addr, usize> = HashMap::new();
+        for (_, ip) in self.reports.iter() {
+            *votes.entry(*ip).or_default() += 1;
+        }
+        let (winner, count) = votes.into_iter().max_by_key(|(_, count)| *count)?;
+        if count < self.min_reports || count * 2 <= self.reports.len() {
+            return None;
+        }
+
+        if self.current == Some(winner) {
+            return None;
+        }
+
+        self.current = Some(winner);
+        Some(winner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(ip: &str) -> Multiaddr {
+        format!("/ip4/{}/tcp/30000", ip).parse().unwrap()
+    }
+
+    #[test]
+    fn test_observe_external_address() {
+        let mut observed = ObservedAddresses::new(2);
+
+        // local addresses are ignored
+        assert_eq!(
+            observed.observe(PeerId::random(), &addr("192.168.1.2")),
+            None
+        );
+
+        // requires reports from distinct peers
+        let peer = PeerId::random();
+        assert_eq!(observed.observe(peer, &addr("1.2.3.4")), None);
+        assert_eq!(observed.observe(peer, &addr("1.2.3.4")), None);
+        assert_eq!(
+            observed.observe(PeerId::random(), &addr("1.2.3.4")),
+            Some("1.2.3.4".parse().unwrap())
+        );
+        assert_eq!(observed.observe(PeerId::random(), &addr("1.2.3.4")), None);
+
+        // changed once the majority agrees
+        assert_eq!(observed.observe(PeerId::random(), &addr("5.6.7.8")), None);
+        assert_eq!(observed.observe(PeerId::random(), &addr("5.6.7.8")), None);
+        assert_eq!(observed.observe(PeerId::random(), &addr("5.6.7.8")), None);
+        assert_eq!(
+            observed.observe(PeerId::random(), &addr("5.6.7.8")),
+            Some("5.6.7.8".parse().unwrap())
+        );
+        assert_eq!(observed.current(), Some("5.6.7.8".parse().unwrap()));
+    }
+}
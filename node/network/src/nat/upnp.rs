@@ -0,0 +1,141 @@
+//! Port mappings via UPnP, which requires the `upnp` feature.
+
+use super::{MappingMethod, PortMapping, PortMappingConfig};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+/// Attempts to construct external port mappings with UPnP.
+pub(super) fn construct_mappings(
+    config: &PortMappingConfig,
+    local_ip: Ipv4Addr,
+) -> Option<PortMapping> {
+    debug!("UPnP Attempting to initialise routes");
+    let gateway = match igd::search_gateway(Default::default()) {
+        Ok(gateway) => gateway,
+        Err(e) => {
+            info!(error = %e, "UPnP not available");
+            return None;
+        }
+    };
+
+    let external_ip = match gateway.get_external_ip() {
+        Ok(ip) => ip,
+        Err(e) => {
+            info!(error = %e, "UPnP failed to get external IP address");
+            return None;
+        }
+    };
+
+    // We add specific port mappings rather than getting the router to arbitrary assign
+    // one.
+    // I've found this to be more reliable. If multiple users are behind a single
+    // router, they should ideally try to set different port numbers.
+    let tcp_socket = add_port_mapping(
+        &gateway,
+        igd::PortMappingProtocol::TCP,
+        SocketAddrV4::new(local_ip, config.tcp_port),
+        config.lease_secs(),
+        "tcp",
+    )
+    .map(|_| SocketAddr::new(external_ip.into(), config.tcp_port))
+    .ok();
+
+    let udp_socket = if !config.disable_discovery {
+        add_port_mapping(
+            &gateway,
+            igd::PortMappingProtocol::UDP,
+            SocketAddrV4::new(local_ip, config.udp_port),
+            config.lease_secs(),
+            "udp",
+        )
+        .map(|_| SocketAddr::new(external_ip.into(), config.udp_port))
+        .ok()
+    } else {
+        None
+    };
+
+    if tcp_socket.is_none() && udp_socket.is_none() {
+        return None;
+    }
+
+    Some(PortMapping {
+        method: MappingMethod::UPnP,
+        local_tcp_port: config.tcp_port,
+        local_udp_port: config.udp_port,
+        tcp_socket,
+        udp_socket,
+    })
+}
+
+/// Sets up a port mapping for a protocol returning the mapped port if successful.
+fn add_port_mapping(
+    gateway: &igd::Gateway,
+    protocol: igd::PortMappingProtocol,
+    socket: SocketAddrV4,
+    lease_secs: u32,
+    protocol_string: &'static str,
+) -> Result<(), ()> {
+    let mapping_string = &format!("lighthouse-{}", protocol_string);
+    for _ in 0..2 {
+        match gateway.add_port(protocol, socket.port(), socket, lease_secs, mapping_string) {
+            Err(e) => {
+                match e {
+                    igd::AddPortError::PortInUse => {
+                        // Try and remove and re-create
+                        debug!(
+                            protocol = protocol_string,
+                            port = socket.port(),
+                            "UPnP port in use, attempting to remap"
+                        );
+                        match gateway.remove_port(protocol, socket.port()) {
+                            Ok(()) => {
+                                debug!(
+                                    protocol = protocol_string,
+                                    port = socket.port(),
+                                    "UPnP Removed port mapping"
+                                )
+                            }
+                            Err(e) => {
+                                debug!(protocol = protocol_string, port = socket.port(), error = %e, "UPnP Port remove failure");
+                                return Err(());
+                            }
+                        }
+                    }
+                    e => {
+                        info!(protocol = protocol_string, error = %e, "UPnP route not set");
+                        return Err(());
+                    }
+                }
+            }
+            Ok(_) => {
+                return Ok(());
+            }
+        }
+    }
+    Err(())
+}
+
+/// Removes the specified TCP and UDP port mappings.
+pub(super) fn remove_mappings(tcp_port: Option<u16>, udp_port: Option<u16>) {
+    debug!("Removing UPnP port mappings");
+    match igd::search_gateway(Default::default()) {
+        Ok(gateway) => {
+            if let Some(tcp_port) = tcp_port {
+                match gateway.remove_port(igd::PortMappingProtocol::TCP, tcp_port) {
+                    Ok(()) => debug!(port = tcp_port, "UPnP Removed TCP port mapping"),
+                    Err(e) => {
+                        debug!(port = tcp_port, error = %e, "UPnP Failed to remove TCP port mapping")
+                    }
+                }
+            }
+            if let Some(udp_port) = udp_port {
+                match gateway.remove_port(igd::PortMappingProtocol::UDP, udp_port) {
+                    Ok(()) => debug!(port = udp_port, "UPnP Removed UDP port mapping"),
+                    Err(e) => {
+                        debug!(port = udp_port, error = %e, "UPnP Failed to remove UDP port mapping")
+                    }
+                }
+            }
+        }
+        Err(e) => debug!(error = %e, "UPnP failed to remove mappings"),
+    }
+}
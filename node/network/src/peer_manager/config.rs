@@ -2,6 +2,9 @@ use std::{fmt::Debug, sync::Arc, time::Duration};
 
 use duration_str::deserialize_duration;
 use libp2p::PeerId;
+
+use super::dial_demand::DialDemand;
+use crate::PeerIdSerialized;
 use serde::{Deserialize, Serialize};
 
 /// The time in seconds between re-status's peers.
@@ -16,6 +19,12 @@ pub const DEFAULT_PING_INTERVAL_INBOUND: u64 = 20;
 /// Default number of peers to connect to.
 pub const DEFAULT_TARGET_PEERS: usize = 50;
 
+/// Default number of connection slots reserved for peers syncing files with us.
+pub const DEFAULT_RESERVED_SYNC_PEERS: usize = 5;
+
+/// Default number of connection slots reserved for mining peers.
+pub const DEFAULT_RESERVED_MINING_PEERS: usize = 2;
+
 /// Configurations for the PeerManager.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
@@ -32,6 +41,24 @@ pub struct Config {
     /// Target number of peers to connect to.
     pub target_peer_count: usize,
 
+    /* Connection budget related configurations */
+    /// Maximum number of peers that dialed us, out of the reserved slots. Defaults to derive
+    /// from the target number of peers.
+    pub max_inbound_peers: Option<usize>,
+    /// Maximum number of peers that we dialed, out of the reserved slots. Defaults to derive
+    /// from the target number of peers.
+    pub max_outbound_peers: Option<usize>,
+    /// Number of connection slots reserved for peers syncing files with us.
+    pub reserved_sync_peers: usize,
+    /// Number of connection slots reserved for the mining peers.
+    pub reserved_mining_peers: usize,
+    /// Peers that are critical for mining.
+    pub mining_peers: Vec<PeerIdSerialized>,
+    /// Peers without any application request or response within this timeout are considered
+    /// idle, and evicted first when over budget.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub peer_idle_timeout: Duration,
+
     /* RPC related configurations */
     /// Time in seconds between status requests sent to peers.
     pub status_interval: u64,
@@ -44,6 +71,9 @@ pub struct Config {
 
     #[serde(skip)]
     pub filters: Filters,
+    /// The initial demand of peers to dial, which could be updated at runtime.
+    #[serde(skip)]
+    pub dial_demand: DialDemand,
 }
 
 impl Default for Config {
@@ -53,10 +83,17 @@ impl Default for Config {
             discovery_enabled: true,
             metrics_enabled: false,
             target_peer_count: DEFAULT_TARGET_PEERS,
+            max_inbound_peers: None,
+            max_outbound_peers: None,
+            reserved_sync_peers: DEFAULT_RESERVED_SYNC_PEERS,
+            reserved_mining_peers: DEFAULT_RESERVED_MINING_PEERS,
+            mining_peers: vec![],
+            peer_idle_timeout: Duration::from_secs(60),
             status_interval: DEFAULT_STATUS_INTERVAL,
             ping_interval_inbound: DEFAULT_PING_INTERVAL_INBOUND,
             ping_interval_outbound: DEFAULT_PING_INTERVAL_OUTBOUND,
             filters: Default::default(),
+            dial_demand: Default::default(),
         }
     }
 }
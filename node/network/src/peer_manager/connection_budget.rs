@@ -0,0 +1,329 @@
+//! Connection budgeting, which caps the inbound and outbound peers separately, and reserves
+//! extra slots for peers that are syncing files with us or critical for mining.
+
+use super::config::Config;
+use super::peerdb::peer_info::ConnectionDirection;
+use super::{PEER_EXCESS_FACTOR, PRIORITY_PEER_EXCESS};
+use crate::PeerId;
+use std::collections::HashSet;
+
+/// The role of a peer that is entitled to the reserved connection slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PeerRole {
+    /// The peer is syncing files with us recently.
+    Sync,
+    /// The peer is configured as critical for mining.
+    Mining,
+}
+
+/// The outcome of admitting a new connected peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    /// The peer occupies a reserved slot of its role.
+    Reserved,
+    /// The peer occupies a slot of its connection direction.
+    Regular,
+    /// The budget of the connection direction is exhausted.
+    Rejected,
+}
+
+/// A connected peer to budget.
+#[derive(Debug, Clone)]
+pub struct BudgetedPeer {
+    pub peer_id: PeerId,
+    pub direction: ConnectionDirection,
+    pub role: Option<PeerRole>,
+    pub score: f64,
+    /// Whether there is no application request or response with the peer recently.
+    pub idle: bool,
+}
+
+/// The current connection counts against the budget.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Number of inbound peers out of the reserved slots.
+    pub inbound: usize,
+    /// Number of outbound peers out of the reserved slots.
+    pub outbound: usize,
+    /// Number of peers in the reserved slots for file sync.
+    pub reserved_sync: usize,
+    /// Number of peers in the reserved slots for mining.
+    pub reserved_mining: usize,
+    pub max_inbound: usize,
+    pub max_outbound: usize,
+    pub max_reserved_sync: usize,
+    pub max_reserved_mining: usize,
+    /// Total number of peers evicted since startup.
+    pub evicted: u64,
+}
+
+/// Caps of connected peers per direction and per role.
+///
+/// A peer with a role occupies a reserved slot of the role if available, where the higher
+/// scored ones are preferred, and otherwise a slot of its connection direction like the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionBudget {
+    pub max_inbound: usize,
+    pub max_outbound: usize,
+    pub reserved_sync: usize,
+    pub reserved_mining: usize,
+}
+
+impl ConnectionBudget {
+    /// Builds the budget from configurations, where the direction caps default to the maximum
+    /// number of peers and the maximum number of dialing peers of the peer manager.
+    pub fn new(config: &Config, target_peers: usize) -> Self {
+        Self {
+            max_inbound: config.max_inbound_peers.unwrap_or_else(|| {
+                (target_peers as f32 * (1.0 + PEER_EXCESS_FACTOR)).ceil() as usize
+            }),
+            max_outbound: config.max_outbound_peers.unwrap_or_else(|| {
+                (target_peers as f32 * (1.0 + PEER_EXCESS_FACTOR + PRIORITY_PEER_EXCESS / 2.0))
+                    .ceil() as usize
+            }),
+            reserved_sync: config.reserved_sync_peers,
+            reserved_mining: config.reserved_mining_peers,
+        }
+    }
+
+    /// Total number of reserved slots.
+    pub fn reserved(&self) -> usize {
+        self.reserved_sync + self.reserved_mining
+    }
+
+    fn reserved_slots(&self, role: PeerRole) -> usize {
+        match role {
+            PeerRole::Sync => self.reserved_sync,
+            PeerRole::Mining => self.reserved_mining,
+        }
+    }
+
+    /// Returns the peers that occupy the reserved slots.
+    fn reserved_peers(&self, peers: &[BudgetedPeer]) -> HashSet<PeerId> {
+        let mut reserved = HashSet::new();
+        for role in [PeerRole::Sync, PeerRole::Mining] {
+            let mut candidates: Vec<&BudgetedPeer> =
+                peers.iter().filter(|p| p.role == Some(role)).collect();
+            candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+            reserved.extend(
+                candidates
+                    .into_iter()
+                    .take(self.reserved_slots(role))
+                    .map(|p| p.peer_id),
+            );
+        }
+        reserved
+    }
+
+    /// Returns the connection counts of the specified connected peers.
+    pub fn stats(&self, peers: &[BudgetedPeer]) -> ConnectionStats {
+        let reserved = self.reserved_peers(peers);
+        let mut stats = ConnectionStats {
+            max_inbound: self.max_inbound,
+            max_outbound: self.max_outbound,
+            max_reserved_sync: self.reserved_sync,
+            max_reserved_mining: self.reserved_mining,
+            ..Default::default()
+        };
+
+        for peer in peers {
+            match (reserved.contains(&peer.peer_id), peer.role, peer.direction) {
+                (true, Some(PeerRole::Sync), _) => stats.reserved_sync += 1,
+                (true, Some(PeerRole::Mining), _) => stats.reserved_mining += 1,
+                (_, _, ConnectionDirection::Incoming) => stats.inbound += 1,
+                (_, _, ConnectionDirection::Outgoing) => stats.outbound += 1,
+            }
+        }
+
+        stats
+    }
+
+    /// Decides whether a new peer is allowed to connect in addition to the connected peers.
+    pub fn admit(
+        &self,
+        peers: &[BudgetedPeer],
+        direction: ConnectionDirection,
+        role: Option<PeerRole>,
+    ) -> Admission {
+        let stats = self.stats(peers);
+
+        let reserved_available = match role {
+            Some(PeerRole::Sync) => stats.reserved_sync < self.reserved_sync,
+            Some(PeerRole::Mining) => stats.reserved_mining < self.reserved_mining,
+            None => false,
+        };
+        if reserved_available {
+            return Admission::Reserved;
+        }
+
+        let available = match direction {
+            ConnectionDirection::Incoming => stats.inbound < self.max_inbound,
+            ConnectionDirection::Outgoing => stats.outbound < self.max_outbound,
+        };
+        if available {
+            Admission::Regular
+        } else {
+            Admission::Rejected
+        }
+    }
+
+    /// Returns the peers to disconnect so that each direction is within its cap. Peers in the
+    /// reserved slots are never evicted. The idle peers are evicted first, and then the lower
+    /// scored ones.
+    pub fn peers_to_evict(&self, peers: &[BudgetedPeer]) -> Vec<PeerId> {
+        let reserved = self.reserved_peers(peers);
+        let mut evicted = vec![];
+
+        for (direction, max) in [
+            (ConnectionDirection::Incoming, self.max_inbound),
+            (ConnectionDirection::Outgoing, self.max_outbound),
+        ] {
+            let mut candidates: Vec<&BudgetedPeer> = peers
+                .iter()
+                .filter(|p| p.direction == direction && !reserved.contains(&p.peer_id))
+                .collect();
+            if candidates.len() <= max {
+                continue;
+            }
+
+            candidates.sort_by(|a, b| b.idle.cmp(&a.idle).then(a.score.total_cmp(&b.score)));
+            let excess = candidates.len() - max;
+            evicted.extend(candidates.into_iter().take(excess).map(|p| p.peer_id));
+        }
+
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(max_inbound: usize, max_outbound: usize) -> ConnectionBudget {
+        ConnectionBudget {
+            max_inbound,
+            max_outbound,
+            reserved_sync: 1,
+            reserved_mining: 1,
+        }
+    }
+
+    fn peer(
+        direction: ConnectionDirection,
+        role: Option<PeerRole>,
+        score: f64,
+        idle: bool,
+    ) -> BudgetedPeer {
+        BudgetedPeer {
+            peer_id: PeerId::random(),
+            direction,
+            role,
+            score,
+            idle,
+        }
+    }
+
+    #[test]
+    fn test_eviction_order() {
+        let budget = budget(2, 1);
+        let active_low = peer(ConnectionDirection::Incoming, None, -10.0, false);
+        let idle_high = peer(ConnectionDirection::Incoming, None, 10.0, true);
+        let idle_low = peer(ConnectionDirection::Incoming, None, 0.0, true);
+        let active_high = peer(ConnectionDirection::Incoming, None, 5.0, false);
+        let active_mid = peer(ConnectionDirection::Incoming, None, 1.0, false);
+        let outbound = peer(ConnectionDirection::Outgoing, None, 0.0, true);
+        let peers = vec![
+            active_low.clone(),
+            idle_high.clone(),
+            idle_low.clone(),
+            active_high.clone(),
+            active_mid.clone(),
+            outbound,
+        ];
+
+        // idle peers first regardless of the score, and then the lowest scored
+        assert_eq!(
+            budget.peers_to_evict(&peers),
+            vec![idle_low.peer_id, idle_high.peer_id, active_low.peer_id]
+        );
+
+        // within budget
+        assert!(budget.peers_to_evict(&peers[3..]).is_empty());
+    }
+
+    #[test]
+    fn test_reserved_peers_not_evicted() {
+        let budget = budget(1, 1);
+        let sync_low = peer(
+            ConnectionDirection::Incoming,
+            Some(PeerRole::Sync),
+            -5.0,
+            true,
+        );
+        let sync_high = peer(
+            ConnectionDirection::Incoming,
+            Some(PeerRole::Sync),
+            5.0,
+            true,
+        );
+        let miner = peer(
+            ConnectionDirection::Incoming,
+            Some(PeerRole::Mining),
+            -5.0,
+            true,
+        );
+        let regular = peer(ConnectionDirection::Incoming, None, 0.0, false);
+        let peers = vec![
+            sync_low.clone(),
+            sync_high.clone(),
+            miner.clone(),
+            regular.clone(),
+        ];
+
+        // the higher scored sync peer occupies the only reserved sync slot
+        let stats = budget.stats(&peers);
+        assert_eq!(stats.reserved_sync, 1);
+        assert_eq!(stats.reserved_mining, 1);
+        assert_eq!(stats.inbound, 2);
+        assert_eq!(budget.peers_to_evict(&peers), vec![sync_low.peer_id]);
+    }
+
+    #[test]
+    fn test_admit() {
+        let budget = budget(1, 1);
+        let peers = vec![peer(ConnectionDirection::Incoming, None, 0.0, false)];
+
+        assert_eq!(
+            budget.admit(&peers, ConnectionDirection::Incoming, None),
+            Admission::Rejected
+        );
+        assert_eq!(
+            budget.admit(&peers, ConnectionDirection::Outgoing, None),
+            Admission::Regular
+        );
+        assert_eq!(
+            budget.admit(
+                &peers,
+                ConnectionDirection::Incoming,
+                Some(PeerRole::Mining)
+            ),
+            Admission::Reserved
+        );
+
+        let mut peers = peers;
+        peers.push(peer(
+            ConnectionDirection::Incoming,
+            Some(PeerRole::Mining),
+            0.0,
+            false,
+        ));
+        assert_eq!(
+            budget.admit(
+                &peers,
+                ConnectionDirection::Incoming,
+                Some(PeerRole::Mining)
+            ),
+            Admission::Rejected
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Demand of peers to dial, which filters the peers found by discovery with the shard config
+//! and capabilities advertised in their ENRs.
+
+use crate::{Enr, EnrExt, NodeCapabilities};
+use shared_types::ShardConfig;
+
+/// Peers that are useful for the current sync, e.g. peers that store data of the local shard.
+///
+/// Peers without the shard config or capabilities in ENR, e.g. nodes of older versions, are
+/// always dialed, whereas peers with invalid ones are not.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DialDemand {
+    /// Shard config that peers should intersect with, if any.
+    pub shard_config: Option<ShardConfig>,
+    /// Capabilities that peers should have.
+    pub capabilities: NodeCapabilities,
+}
+
+impl DialDemand {
+    /// Demand of the local node, which only dials peers that store data of the local shard and
+    /// accept sync requests if files are synced from peers. Otherwise, peers are only useful
+    /// for gossip, and all discovered peers are dialed.
+    pub fn new(shard_config: ShardConfig, syncs_files: bool) -> Self {
+        if syncs_files {
+            DialDemand {
+                shard_config: Some(shard_config),
+                capabilities: NodeCapabilities::all(),
+            }
+        } else {
+            DialDemand::default()
+        }
+    }
+
+    /// Updates the local shard config, which is only demanded if files are synced from peers.
+    pub fn update_shard_config(&mut self, shard_config: ShardConfig) {
+        if self.shard_config.is_some() {
+            self.shard_config = Some(shard_config);
+        }
+    }
+
+    /// Returns whether the peer of the specified ENR is demanded to dial.
+    pub fn matches(&self, enr: &Enr) -> bool {
+        if let Some(shard_config) = self.shard_config {
+            match enr.shard_config() {
+                Some(Ok(peer)) if !intersect(&shard_config, &peer) => return false,
+                Some(Err(_)) => return false,
+                _ => {}
+            }
+        }
+
+        match enr.capabilities() {
+            Some(Ok(capabilities)) => capabilities.contains(self.capabilities),
+            Some(Err(_)) => false,
+            None => true,
+        }
+    }
+}
+
+/// Whether the two shard configs intersect, i.e. store any segment in common. Invalid shard
+/// configs never intersect.
+fn intersect(left: &ShardConfig, right: &ShardConfig) -> bool {
+    let valid = |c: &ShardConfig| c.num_shard.is_power_of_two() && c.shard_id < c.num_shard;
+    if !valid(left) || !valid(right) {
+        return false;
+    }
+
+    let num_shard = left.num_shard.min(right.num_shard);
+    left.shard_id % num_shard == right.shard_id % num_shard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::enr_ext::{ENR_CONTENT_KEY_CAPABILITIES, ENR_CONTENT_KEY_SHARD_CONFIG};
+    use discv5::enr::{CombinedKey, EnrBuilder};
+    use ssz::Encode;
+
+    fn shard_config(shard_id: usize, num_shard: usize) -> ShardConfig {
+        ShardConfig {
+            num_shard,
+            shard_id,
+        }
+    }
+
+    fn build_enr(shard_config: Option<ShardConfig>, capabilities: Option<NodeCapabilities>) -> Enr {
+        let key = CombinedKey::generate_secp256k1();
+        let mut builder = EnrBuilder::new("v4");
+        if let Some(shard_config) = shard_config {
+            builder.add_value(ENR_CONTENT_KEY_SHARD_CONFIG, &shard_config.as_ssz_bytes());
+        }
+        if let Some(capabilities) = capabilities {
+            builder.add_value(ENR_CONTENT_KEY_CAPABILITIES, &capabilities.as_ssz_bytes());
+        }
+        builder.build(&key).unwrap()
+    }
+
+    #[test]
+    fn test_intersect() {
+        assert!(intersect(&shard_config(0, 1), &shard_config(3, 4)));
+        assert!(intersect(&shard_config(1, 2), &shard_config(3, 4)));
+        assert!(!intersect(&shard_config(0, 2), &shard_config(3, 4)));
+        assert!(!intersect(&shard_config(1, 4), &shard_config(2, 4)));
+        assert!(!intersect(&shard_config(0, 3), &shard_config(0, 1)));
+    }
+
+    #[test]
+    fn test_match_shard_config() {
+        let demand = DialDemand {
+            shard_config: Some(shard_config(1, 4)),
+            capabilities: NodeCapabilities::empty(),
+        };
+
+        assert!(demand.matches(&build_enr(None, None)));
+        assert!(demand.matches(&build_enr(Some(shard_config(1, 2)), None)));
+        assert!(demand.matches(&build_enr(Some(shard_config(5, 8)), None)));
+        assert!(!demand.matches(&build_enr(Some(shard_config(0, 2)), None)));
+        assert!(!demand.matches(&build_enr(Some(shard_config(3, 8)), None)));
+
+        // no shard demanded
+        let demand = DialDemand::default();
+        assert!(demand.matches(&build_enr(Some(shard_config(0, 2)), None)));
+    }
+
+    #[test]
+    fn test_match_capabilities() {
+        let demand = DialDemand {
+            shard_config: None,
+            capabilities: NodeCapabilities::SERVES_DATA,
+        };
+
+        assert!(demand.matches(&build_enr(None, None)));
+        assert!(demand.matches(&build_enr(None, Some(NodeCapabilities::all()))));
+        assert!(demand.matches(&build_enr(None, Some(NodeCapabilities::SERVES_DATA))));
+        assert!(!demand.matches(&build_enr(None, Some(NodeCapabilities::ACCEPTS_SYNC))));
+        // RPC-only node
+        assert!(!demand.matches(&build_enr(None, Some(NodeCapabilities::empty()))));
+
+        let demand = DialDemand {
+            shard_config: Some(shard_config(0, 2)),
+            capabilities: NodeCapabilities::all(),
+        };
+        assert!(demand.matches(&build_enr(
+            Some(shard_config(0, 4)),
+            Some(NodeCapabilities::all())
+        )));
+        assert!(!demand.matches(&build_enr(
+            Some(shard_config(0, 4)),
+            Some(NodeCapabilities::SERVES_DATA)
+        )));
+        assert!(!demand.matches(&build_enr(
+            Some(shard_config(1, 4)),
+            Some(NodeCapabilities::all())
+        )));
+    }
+
+    #[test]
+    fn test_local_demand() {
+        let data_peer = build_enr(Some(shard_config(1, 2)), Some(NodeCapabilities::all()));
+        let other_shard = build_enr(Some(shard_config(0, 2)), Some(NodeCapabilities::all()));
+        let rpc_only = build_enr(Some(shard_config(0, 1)), Some(NodeCapabilities::empty()));
+        let no_sync = build_enr(
+            Some(shard_config(0, 1)),
+            Some(NodeCapabilities::SERVES_DATA),
+        );
+
+        let mut demand = DialDemand::new(shard_config(1, 4), true);
+        assert!(demand.matches(&data_peer));
+        assert!(!demand.matches(&other_shard));
+        assert!(!demand.matches(&rpc_only));
+        assert!(!demand.matches(&no_sync));
+
+        demand.update_shard_config(shard_config(0, 4));
+        assert!(!demand.matches(&data_peer));
+        assert!(demand.matches(&other_shard));
+
+        // all peers are dialed for gossip if files are not synced
+        let mut demand = DialDemand::new(shard_config(1, 4), false);
+        demand.update_shard_config(shard_config(0, 4));
+        assert_eq!(demand, DialDemand::default());
+        for enr in [&data_peer, &other_shard, &rpc_only, &no_sync] {
+            assert!(demand.matches(enr));
+        }
+    }
+
+    #[test]
+    fn test_match_invalid_fields() {
+        let key = CombinedKey::generate_secp256k1();
+        let mut builder = EnrBuilder::new("v4");
+        builder.add_value(ENR_CONTENT_KEY_SHARD_CONFIG, &[1u8, 2, 3].to_vec());
+        builder.add_value(ENR_CONTENT_KEY_CAPABILITIES, &[1u8, 2].to_vec());
+        let enr: Enr = builder.build(&key).unwrap();
+
+        assert!(!DialDemand::default().matches(&enr));
+        assert!(!DialDemand {
+            shard_config: Some(shard_config(0, 1)),
+            capabilities: NodeCapabilities::empty(),
+        }
+        .matches(&enr));
+    }
+}
@@ -1,6 +1,7 @@
 //! Implementation of Lighthouse's peer management system.
 
-use crate::rpc::{GoodbyeReason, Protocol, RPCError, RPCResponseErrorCode};
+use crate::discovery::DiscoveredPeer;
+use crate::rpc::{GoodbyeReason, Protocol, RPCError, RPCResponseErrorCode, Version};
 use crate::{error, metrics, Gossipsub};
 use crate::{NetworkGlobals, PeerId};
 use discv5::Enr;
@@ -20,15 +21,20 @@ pub use libp2p::core::{identity::Keypair, Multiaddr};
 // PeerId in hashmaps are no longer permitted by clippy
 pub mod peerdb;
 
+use connection_budget::{Admission, BudgetedPeer, ConnectionBudget, ConnectionStats, PeerRole};
 pub use peerdb::peer_info::{
-    ConnectionDirection, PeerConnectionStatus, PeerConnectionStatus::*, PeerInfo,
+    ConnectionDirection, PeerConnectionStatus, PeerConnectionStatus::*, PeerInfo, RpcCounters,
+    RpcMessageKind,
 };
 use peerdb::score::{PeerAction, ReportSource};
 pub use peerdb::sync_status::{SyncInfo, SyncStatus};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::IpAddr;
 pub mod config;
+pub mod connection_budget;
+pub mod dial_demand;
 mod network_behaviour;
+pub mod peer_policy;
 
 /// This is used in the pruning logic. We avoid pruning peers on sync-committees if doing so would
 /// lower our peer count below this number. Instead we favour a non-uniform distribution of subnet
@@ -71,6 +77,16 @@ pub struct PeerManager {
     metrics_enabled: bool,
 
     filters: config::Filters,
+    /// Filters the discovered peers to dial.
+    dial_demand: dial_demand::DialDemand,
+    /// Caps of connected peers per direction and per role.
+    connection_budget: ConnectionBudget,
+    /// Peers that are entitled to the reserved slots for mining.
+    mining_peers: HashSet<PeerId>,
+    /// The timeout after which peers without activities are considered idle.
+    peer_idle_timeout: Duration,
+    /// Number of peers evicted due to the connection budget.
+    evicted_peers: u64,
 }
 
 /// The events that the `PeerManager` outputs (requests).
@@ -102,15 +118,20 @@ impl PeerManager {
         cfg: config::Config,
         network_globals: Arc<NetworkGlobals>,
     ) -> error::Result<Self> {
+        let connection_budget = ConnectionBudget::new(&cfg, cfg.target_peer_count);
         let config::Config {
             heartbeat_interval,
             discovery_enabled,
             metrics_enabled,
             target_peer_count,
+            mining_peers,
+            peer_idle_timeout,
             status_interval,
             ping_interval_inbound,
             ping_interval_outbound,
             filters,
+            dial_demand,
+            ..
         } = cfg;
 
         // Set up the peer manager heartbeat interval
@@ -127,6 +148,11 @@ impl PeerManager {
             discovery_enabled,
             metrics_enabled,
             filters,
+            dial_demand,
+            connection_budget,
+            mining_peers: mining_peers.into_iter().map(PeerId::from).collect(),
+            peer_idle_timeout,
+            evicted_peers: 0,
         })
     }
 
@@ -250,12 +276,20 @@ impl PeerManager {
     /// with a new `PeerId` which involves a discovery routing table lookup. We could dial the
     /// multiaddr here, however this could relate to duplicate PeerId's etc. If the lookup
     /// proves resource constraining, we should switch to multiaddr dialling here.
+    ///
+    /// Peers that do not match the dial demand, e.g. not covering the demanded shard, are not
+    /// dialed.
     #[allow(clippy::mutable_key_type)]
-    pub fn peers_discovered(&mut self, results: HashMap<PeerId, Option<Instant>>) -> Vec<PeerId> {
+    pub fn peers_discovered(&mut self, results: HashMap<PeerId, DiscoveredPeer>) -> Vec<PeerId> {
         let mut to_dial_peers = Vec::new();
 
         let connected_or_dialing = self.network_globals.connected_or_dialing_peers();
-        for (peer_id, min_ttl) in results {
+        for (peer_id, DiscoveredPeer { enr, min_ttl }) in results {
+            if !self.dial_demand.matches(&enr) {
+                trace!(%peer_id, "Discovered peer not demanded");
+                continue;
+            }
+
             // There are two conditions in deciding whether to dial this peer.
             // 1. If we are less than our max connections. Discovery queries are executed to reach
             //    our target peers, so its fine to dial up to our max peers (which will get pruned
@@ -285,12 +319,28 @@ impl PeerManager {
             to_dial_peers.retain(|peer_id| dial_peer_filter(peer_id));
         }
 
+        {
+            let peer_policy = self.network_globals.peer_policy.read();
+            to_dial_peers.retain(|peer_id| peer_policy.is_allowed(peer_id, None));
+        }
+
         // Queue another discovery if we need to
         self.maintain_peer_count(to_dial_peers.len());
 
         to_dial_peers
     }
 
+    /// Returns the demand of peers to dial.
+    pub fn dial_demand(&self) -> dial_demand::DialDemand {
+        self.dial_demand
+    }
+
+    /// Updates the demand of peers to dial, e.g. once the local shard config changed.
+    pub fn set_dial_demand(&mut self, dial_demand: dial_demand::DialDemand) {
+        debug!(?dial_demand, "Update dial demand");
+        self.dial_demand = dial_demand;
+    }
+
     /// A STATUS message has been received from a peer. This resets the status timer.
     pub fn peer_statusd(&mut self, peer_id: &PeerId) {
         self.status_peers.insert(*peer_id);
@@ -357,14 +407,107 @@ impl PeerManager {
         }
     }
 
+    /// Returns the role of a peer that is entitled to the reserved connection slots, if any.
+    fn peer_role(&self, peer_id: &PeerId, peer_info: Option<&PeerInfo>) -> Option<PeerRole> {
+        if self.mining_peers.contains(peer_id) {
+            return Some(PeerRole::Mining);
+        }
+
+        peer_info
+            .and_then(|info| info.last_sync_activity())
+            .filter(|t| t.elapsed() < self.peer_idle_timeout)
+            .map(|_| PeerRole::Sync)
+    }
+
+    /// Returns the connected peers to budget.
+    fn budgeted_peers(&self) -> Vec<BudgetedPeer> {
+        self.network_globals
+            .peers
+            .read()
+            .connected_peers()
+            .filter_map(|(peer_id, info)| {
+                Some(BudgetedPeer {
+                    peer_id: *peer_id,
+                    direction: *info.connection_direction()?,
+                    role: self.peer_role(peer_id, Some(info)),
+                    score: info.score().score(),
+                    idle: info
+                        .last_activity()
+                        .map_or(true, |t| t.elapsed() >= self.peer_idle_timeout),
+                })
+            })
+            .collect()
+    }
+
+    /// Decides whether a newly connected peer is allowed by the connection budget.
+    pub fn admit_connection(&self, peer_id: &PeerId, direction: ConnectionDirection) -> Admission {
+        let role = {
+            let peers = self.network_globals.peers.read();
+            self.peer_role(peer_id, peers.peer_info(peer_id))
+        };
+        self.connection_budget
+            .admit(&self.budgeted_peers(), direction, role)
+    }
+
+    /// Records an application request or response with the peer, which keeps the peer from
+    /// being evicted as idle. File sync activities entitle the peer to the reserved slots.
+    pub fn record_activity(&mut self, peer_id: &PeerId, sync: bool) {
+        if let Some(peer_info) = self.network_globals.peers.write().peer_info_mut(peer_id) {
+            peer_info.record_activity(sync);
+        }
+    }
+
+    /// Counts an RPC request or response with the peer.
+    pub fn record_rpc(&mut self, peer_id: &PeerId, kind: RpcMessageKind) {
+        if let Some(peer_info) = self.network_globals.peers.write().peer_info_mut(peer_id) {
+            peer_info.record_rpc(kind);
+        }
+    }
+
+    /// Records an RPC or dial error with the peer.
+    fn record_error(&mut self, peer_id: &PeerId, error: String) {
+        if let Some(peer_info) = self.network_globals.peers.write().peer_info_mut(peer_id) {
+            peer_info.record_error(error);
+        }
+    }
+
+    /// Records the negotiated version of an RPC protocol with the peer.
+    pub fn rpc_version_negotiated(
+        &mut self,
+        peer_id: &PeerId,
+        protocol: Protocol,
+        version: Version,
+    ) {
+        if let Some(peer_info) = self.network_globals.peers.write().peer_info_mut(peer_id) {
+            debug!(%peer_id, %protocol, %version, "RPC protocol version negotiated");
+            peer_info.set_rpc_version(protocol, version);
+        }
+    }
+
     /// Updates `PeerInfo` with `identify` information.
     pub fn identify(&mut self, peer_id: &PeerId, info: &IdentifyInfo) {
         if let Some(peer_info) = self.network_globals.peers.write().peer_info_mut(peer_id) {
             let previous_kind = peer_info.client().kind;
+            let previous_agent_string = peer_info.client().agent_string.clone();
             let previous_listening_addresses =
                 peer_info.set_listening_addresses(info.listen_addrs.clone());
             peer_info.set_client(peerdb::client::Client::from_identify_info(info));
 
+            // warn once the peer advertises an incompatible version, but not on every identify
+            if previous_agent_string.as_ref() != Some(&info.agent_version) {
+                let local_version = self.network_globals.network_id().p2p_protocol_version;
+                if let Some(version) = &peer_info.client().p2p_protocol_version {
+                    if !version.is_compatible(&local_version) {
+                        warn!(
+                            peer = %peer_id,
+                            agent_version = %info.agent_version,
+                            %local_version,
+                            "Peer advertises an incompatible P2P protocol version",
+                        );
+                    }
+                }
+            }
+
             if previous_kind != peer_info.client().kind
                 || *peer_info.listening_addresses() != previous_listening_addresses
             {
@@ -409,6 +552,10 @@ impl PeerManager {
         let client = self.network_globals.client(peer_id);
         let score = self.network_globals.peers.read().score(peer_id);
         debug!(%protocol, %err, %client, %peer_id, %score, ?direction, "RPC Error");
+        self.record_error(
+            peer_id,
+            format!("{} {}: {}", direction.as_ref(), protocol, err),
+        );
 
         metrics::inc_counter_vec(
             &metrics::TOTAL_RPC_ERRORS_PER_CLIENT,
@@ -437,29 +584,34 @@ impl PeerManager {
                 // Peer is not complying with the protocol. This is considered a malicious action
                 PeerAction::Fatal
             }
+            RPCError::TooLarge(_) => {
+                // Peer may be configured with larger limits or of a newer version, so it is
+                // only banned if insisting on too large requests.
+                PeerAction::LowToleranceError
+            }
             RPCError::IoError(_e) => {
                 // this could their fault or ours, so we tolerate this
                 PeerAction::HighToleranceError
             }
             RPCError::ErrorResponse(code, _) => match code {
                 RPCResponseErrorCode::Unknown => PeerAction::HighToleranceError,
-                RPCResponseErrorCode::ResourceUnavailable => {
-                    // NOTE: This error only makes sense for the `BlocksByRange` and `BlocksByRoot`
-                    // protocols.
+                RPCResponseErrorCode::ResourceUnavailable => match protocol {
+                    // The peer is still downloading the requested file, or has no announcements
+                    // to exchange, which is not malicious. Sync stops requesting such peers.
+                    Protocol::GetChunks => return,
+                    // NOTE: This error only makes sense for the `BlocksByRange` and
+                    // `BlocksByRoot` protocols.
                     //
                     // If we are syncing, there is no point keeping these peers around and
                     // continually failing to request blocks. We instantly ban them and hope that
                     // by the time the ban lifts, the peers will have completed their backfill
                     // sync.
-                    //
-                    // TODO: Potentially a more graceful way of handling such peers, would be to
-                    // implement a new sync type which tracks these peers and prevents the sync
-                    // algorithms from requesting blocks from them (at least for a set period of
-                    // time, multiple failures would then lead to a ban).
-                    PeerAction::Fatal
-                }
+                    _ => PeerAction::Fatal,
+                },
                 RPCResponseErrorCode::ServerError => PeerAction::MidToleranceError,
                 RPCResponseErrorCode::InvalidRequest => PeerAction::HighToleranceError,
+                // The peer is of an older version, which is not malicious.
+                RPCResponseErrorCode::Unsupported => return,
                 RPCResponseErrorCode::RateLimited => match protocol {
                     Protocol::Ping => PeerAction::MidToleranceError,
                     Protocol::Goodbye => PeerAction::LowToleranceError,
@@ -597,6 +749,27 @@ impl PeerManager {
         }
     }
 
+    /// Updates the connection counts against the connection budget.
+    fn update_connection_stats(&self) {
+        let stats = ConnectionStats {
+            evicted: self.evicted_peers,
+            ..self.connection_budget.stats(&self.budgeted_peers())
+        };
+
+        metrics::set_gauge_vec(
+            &metrics::NETWORK_RESERVED_PEERS,
+            &["sync"],
+            stats.reserved_sync as i64,
+        );
+        metrics::set_gauge_vec(
+            &metrics::NETWORK_RESERVED_PEERS,
+            &["mining"],
+            stats.reserved_mining as i64,
+        );
+
+        *self.network_globals.connection_stats.write() = stats;
+    }
+
     /* Internal functions */
 
     /// Sets a peer as connected as long as their reputation allows it
@@ -710,11 +883,26 @@ impl PeerManager {
             .notify_disconnecting(&peer_id, false);
     }
 
+    /// Disconnects peers over the connection budget of each direction, where the idle and lower
+    /// scored peers are evicted first.
+    fn evict_over_budget_peers(&mut self) {
+        let evicted = self
+            .connection_budget
+            .peers_to_evict(&self.budgeted_peers());
+
+        for peer_id in evicted {
+            debug!(%peer_id, "Evict peer over connection budget");
+            self.evicted_peers += 1;
+            metrics::inc_counter(&metrics::PEERS_EVICTED);
+            self.disconnect_peer(peer_id, GoodbyeReason::TooManyPeers);
+        }
+    }
+
     /// This function checks the status of our current peers and optionally requests a discovery
     /// query if we need to find more peers to maintain the current number of peers
     fn maintain_peer_count(&mut self, dialing_peers: usize) {
-        // Check if we need to do a discovery lookup
-        if self.discovery_enabled {
+        // Check if we need to do a discovery lookup, which is disabled in private network mode
+        if self.discovery_enabled && !self.network_globals.peer_policy.read().is_private() {
             let peer_count = self.network_globals.connected_or_dialing_peers();
             let outbound_only_peer_count = self.network_globals.connected_outbound_only_peers();
             let wanted_peers = if peer_count < self.target_peers.saturating_sub(dialing_peers) {
@@ -1022,9 +1210,14 @@ impl PeerManager {
         // Update peer score metrics;
         self.update_peer_score_metrics();
 
+        // Evict peers over the connection budget of each direction.
+        self.evict_over_budget_peers();
+
         // Prune any excess peers back to our target in such a way that incentivises good scores and
         // a uniform distribution of subnets.
         self.prune_excess_peers();
+
+        self.update_connection_stats();
     }
 
     // Update metrics related to peer scoring.
@@ -1115,6 +1308,8 @@ enum ConnectingType {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{EnrExt, NodeCapabilities};
+    use shared_types::ShardConfig;
 
     async fn build_peer_manager(target_peer_count: usize) -> PeerManager {
         let config = config::Config {
@@ -1229,6 +1424,61 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_peer_manager_evicts_over_budget_peers_during_heartbeat() {
+        let config = config::Config {
+            target_peer_count: 10,
+            discovery_enabled: false,
+            max_inbound_peers: Some(2),
+            reserved_sync_peers: 1,
+            peer_idle_timeout: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let globals = NetworkGlobals::new_test_globals();
+        let mut peer_manager = PeerManager::new(config, Arc::new(globals)).await.unwrap();
+
+        // (score, active, syncing)
+        let peers: Vec<(PeerId, f64, bool, bool)> = vec![
+            (PeerId::random(), -1.0, true, false),
+            (PeerId::random(), 5.0, false, false),
+            (PeerId::random(), 0.0, false, false),
+            (PeerId::random(), 1.0, true, false),
+            (PeerId::random(), -5.0, false, true),
+        ];
+        for (peer_id, score, _, _) in peers.iter() {
+            peer_manager.inject_connect_ingoing(peer_id, "/ip4/0.0.0.0".parse().unwrap(), None);
+            peer_manager
+                .network_globals
+                .peers
+                .write()
+                .peer_info_mut(peer_id)
+                .unwrap()
+                .add_to_score(*score);
+        }
+
+        std::thread::sleep(Duration::from_millis(150));
+        for (peer_id, _, active, syncing) in peers.iter() {
+            if *active || *syncing {
+                peer_manager.record_activity(peer_id, *syncing);
+            }
+        }
+
+        peer_manager.heartbeat();
+
+        // the idle peers are evicted regardless of the score, and the syncing peer is kept in
+        // the reserved slot
+        let connected: Vec<bool> = peers
+            .iter()
+            .map(|(peer_id, _, _, _)| peer_manager.is_connected(peer_id))
+            .collect();
+        assert_eq!(connected, vec![true, false, false, true, true]);
+
+        let stats = peer_manager.network_globals.connection_stats.read().clone();
+        assert_eq!(stats.inbound, 2);
+        assert_eq!(stats.reserved_sync, 1);
+        assert_eq!(stats.evicted, 2);
+    }
+
     #[tokio::test]
     async fn test_peer_manager_remove_unhealthy_peers_brings_peers_below_target() {
         let mut peer_manager = build_peer_manager(3).await;
@@ -1334,4 +1584,132 @@ mod tests {
         // the number of connected peers updates and we will not remove too many peers.
         assert_eq!(peer_manager.network_globals.connected_or_dialing_peers(), 3);
     }
+
+    /// Builds the ENR that a node of the specified shard config and capabilities publishes.
+    fn build_enr(
+        shard_config: Option<ShardConfig>,
+        capabilities: NodeCapabilities,
+    ) -> (PeerId, DiscoveredPeer) {
+        let config = crate::NetworkConfig {
+            shard_config,
+            capabilities,
+            ..Default::default()
+        };
+        let key = discv5::enr::CombinedKey::generate_secp256k1();
+        let enr = crate::discovery::create_enr_builder_from_config(&config, true)
+            .build(&key)
+            .unwrap();
+        let peer_id = enr.peer_id();
+        (peer_id, DiscoveredPeer { enr, min_ttl: None })
+    }
+
+    #[tokio::test]
+    async fn test_peers_discovered_on_demand() {
+        let mut peer_manager = build_peer_manager(10).await;
+        peer_manager.set_dial_demand(dial_demand::DialDemand {
+            shard_config: Some(ShardConfig {
+                num_shard: 2,
+                shard_id: 0,
+            }),
+            capabilities: NodeCapabilities::SERVES_DATA,
+        });
+
+        let shard = |shard_id, num_shard| {
+            Some(ShardConfig {
+                num_shard,
+                shard_id,
+            })
+        };
+        let matched = build_enr(shard(2, 4), NodeCapabilities::all());
+        let unknown_shard = build_enr(None, NodeCapabilities::all());
+        let other_shard = build_enr(shard(1, 4), NodeCapabilities::all());
+        let rpc_only = build_enr(shard(0, 1), NodeCapabilities::ACCEPTS_SYNC);
+
+        let results = HashMap::from([
+            matched.clone(),
+            unknown_shard.clone(),
+            other_shard,
+            rpc_only,
+        ]);
+        let mut to_dial_peers = peer_manager.peers_discovered(results);
+        to_dial_peers.sort();
+        let mut expected = vec![matched.0, unknown_shard.0];
+        expected.sort();
+        assert_eq!(to_dial_peers, expected);
+
+        // dial all peers once not demanded
+        peer_manager.set_dial_demand(Default::default());
+        let results = HashMap::from([
+            build_enr(shard(1, 4), NodeCapabilities::empty()),
+            build_enr(None, NodeCapabilities::ACCEPTS_SYNC),
+        ]);
+        assert_eq!(peer_manager.peers_discovered(results).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_chunks_unavailable_not_penalized() {
+        let mut peer_manager = build_peer_manager(3).await;
+        let peer = PeerId::random();
+        peer_manager.inject_connect_outgoing(&peer, "/ip4/0.0.0.0".parse().unwrap(), None);
+        let score = peer_manager.network_globals.peers.read().score(&peer);
+
+        // peer is still downloading the requested file
+        let unavailable =
+            RPCError::ErrorResponse(RPCResponseErrorCode::ResourceUnavailable, "".into());
+        peer_manager.handle_rpc_error(
+            &peer,
+            Protocol::GetChunks,
+            &unavailable,
+            ConnectionDirection::Outgoing,
+        );
+        assert_eq!(
+            peer_manager.network_globals.peers.read().score(&peer),
+            score
+        );
+
+        peer_manager.handle_rpc_error(
+            &peer,
+            Protocol::Status,
+            &unavailable,
+            ConnectionDirection::Outgoing,
+        );
+        assert!(peer_manager.network_globals.peers.read().score(&peer) < score);
+    }
+
+    #[tokio::test]
+    async fn test_too_large_request_not_banned() {
+        let mut peer_manager = build_peer_manager(3).await;
+        let peer = PeerId::random();
+        peer_manager.inject_connect_ingoing(&peer, "/ip4/0.0.0.0".parse().unwrap(), None);
+        let score = peer_manager.network_globals.peers.read().score(&peer);
+
+        let too_large = RPCError::TooLarge("".into());
+        peer_manager.handle_rpc_error(
+            &peer,
+            Protocol::GetChunks,
+            &too_large,
+            ConnectionDirection::Incoming,
+        );
+        assert!(peer_manager.network_globals.peers.read().score(&peer) < score);
+        assert!(!peer_manager
+            .network_globals
+            .peers
+            .read()
+            .ban_status(&peer)
+            .is_banned());
+
+        // malformed requests are still malicious
+        peer_manager.handle_rpc_error(
+            &peer,
+            Protocol::GetChunks,
+            &RPCError::InvalidData("".into()),
+            ConnectionDirection::Incoming,
+        );
+        assert!(peer_manager
+            .network_globals
+            .peers
+            .read()
+            .ban_status(&peer)
+            .is_banned());
+    }
 }
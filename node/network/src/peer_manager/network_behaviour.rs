@@ -12,8 +12,9 @@ use libp2p::{Multiaddr, PeerId};
 use crate::metrics;
 use crate::rpc::GoodbyeReason;
 
+use super::connection_budget::Admission;
 use super::peerdb::BanResult;
-use super::{PeerManager, PeerManagerEvent, ReportSource};
+use super::{ConnectionDirection, PeerManager, PeerManagerEvent, ReportSource};
 
 impl NetworkBehaviour for PeerManager {
     type ConnectionHandler = DummyConnectionHandler;
@@ -134,10 +135,46 @@ impl NetworkBehaviour for PeerManager {
             BanResult::NotBanned => {}
         }
 
+        // Check the allowlist and denylist, which does not ban the peer so that the policy
+        // could be updated at runtime.
+        let remote_addr = match endpoint {
+            ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr,
+            ConnectedPoint::Dialer { address, .. } => address,
+        };
+        if !self
+            .network_globals
+            .peer_policy
+            .read()
+            .is_allowed_addr(peer_id, remote_addr)
+        {
+            debug!(%peer_id, %remote_addr, "Peer rejected by peer policy");
+            self.disconnect_peer(*peer_id, GoodbyeReason::Banned);
+            return;
+        }
+
+        // Check the connection budget of each direction, where the peers syncing files with us
+        // or critical for mining are entitled to the reserved slots.
+        let direction = if endpoint.is_listener() {
+            ConnectionDirection::Incoming
+        } else {
+            ConnectionDirection::Outgoing
+        };
+        let admission = if other_established > 0 {
+            Admission::Regular
+        } else {
+            self.admit_connection(peer_id, direction)
+        };
+        if admission == Admission::Rejected {
+            debug!(%peer_id, ?direction, "Peer rejected by connection budget");
+            self.disconnect_peer(*peer_id, GoodbyeReason::TooManyPeers);
+            return;
+        }
+
         // Count dialing peers in the limit if the peer dialied us.
         let count_dialing = endpoint.is_listener();
         // Check the connection limits
-        if self.peer_limit_reached(count_dialing)
+        if admission != Admission::Reserved
+            && self.peer_limit_reached(count_dialing)
             && self
                 .network_globals
                 .peers
@@ -157,6 +194,12 @@ impl NetworkBehaviour for PeerManager {
                 self.inject_connect_ingoing(peer_id, send_back_addr.clone(), None);
                 self.events
                     .push(PeerManagerEvent::PeerConnectedIncoming(*peer_id));
+
+                let mut nat_status = self.network_globals.nat_status.write();
+                if !nat_status.inbound_connected {
+                    nat_status.inbound_connected = true;
+                    info!(%peer_id, "Peer dialed us, node is publicly reachable");
+                }
             }
             ConnectedPoint::Dialer { address, .. } => {
                 self.inject_connect_outgoing(peer_id, address.clone(), None);
@@ -167,6 +210,7 @@ impl NetworkBehaviour for PeerManager {
 
         // increment prometheus metrics
         self.update_connected_peer_metrics();
+        self.update_connection_stats();
         metrics::inc_counter(&metrics::PEER_CONNECT_EVENT_COUNT);
     }
     fn inject_connection_closed(
@@ -204,6 +248,7 @@ impl NetworkBehaviour for PeerManager {
 
         // Update the prometheus metrics
         self.update_connected_peer_metrics();
+        self.update_connection_stats();
         metrics::inc_counter(&metrics::PEER_DISCONNECT_EVENT_COUNT);
     }
 
@@ -240,9 +285,10 @@ impl NetworkBehaviour for PeerManager {
         &mut self,
         peer_id: Option<PeerId>,
         _handler: DummyConnectionHandler,
-        _error: &DialError,
+        error: &DialError,
     ) {
         if let Some(peer_id) = peer_id {
+            self.record_error(&peer_id, format!("dial: {}", error));
             if !self.network_globals.peers.read().is_connected(&peer_id) {
                 self.inject_disconnect(&peer_id);
             }
@@ -0,0 +1,243 @@
+//! Allowlist and denylist of peers, which restricts the peers to connect with.
+
+use crate::{Multiaddr, PeerId};
+use libp2p::multiaddr::Protocol;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Configurations of the peer policy. Each entry is either a peer id, or an IP address with
+/// optional prefix length in CIDR notation, e.g. `10.0.0.0/8`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PeerPolicyConfig {
+    /// If not empty, only the listed peers are allowed to connect with, i.e. private network
+    /// mode, in which case discovery is disabled as well.
+    pub allowlist: Vec<String>,
+    /// Peers that are never connected with, which takes precedence over the allowlist.
+    pub denylist: Vec<String>,
+}
+
+/// An entry of the allowlist or denylist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerRule {
+    Peer(PeerId),
+    Network { ip: IpAddr, prefix_len: u8 },
+}
+
+impl PeerRule {
+    fn matches(&self, peer_id: &PeerId, ip: Option<IpAddr>) -> bool {
+        match (self, ip) {
+            (PeerRule::Peer(id), _) => id == peer_id,
+            (PeerRule::Network { ip, prefix_len }, Some(remote)) => {
+                Self::in_network(*ip, *prefix_len, remote)
+            }
+            (PeerRule::Network { .. }, None) => false,
+        }
+    }
+
+    fn in_network(network: IpAddr, prefix_len: u8, ip: IpAddr) -> bool {
+        let (network, ip, bits) = match (network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                (u32::from(network) as u128, u32::from(ip) as u128, 32u32)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(ip), 128),
+            _ => return false,
+        };
+
+        let prefix_len = prefix_len as u32;
+        if prefix_len == 0 {
+            return true;
+        }
+
+        let shift = bits - prefix_len;
+        network >> shift == ip >> shift
+    }
+}
+
+impl FromStr for PeerRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(peer_id) = PeerId::from_str(s) {
+            return Ok(PeerRule::Peer(peer_id));
+        }
+
+        let (ip, prefix_len) = match s.split_once('/') {
+            Some((ip, prefix_len)) => (ip, Some(prefix_len)),
+            None => (s, None),
+        };
+        let ip: IpAddr = ip
+            .parse()
+            .map_err(|_| format!("invalid peer id or CIDR range {}", s))?;
+        let max_prefix_len = if ip.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(v) => v
+                .parse::<u8>()
+                .ok()
+                .filter(|v| *v <= max_prefix_len)
+                .ok_or_else(|| format!("invalid prefix length of CIDR range {}", s))?,
+            None => max_prefix_len,
+        };
+
+        Ok(PeerRule::Network { ip, prefix_len })
+    }
+}
+
+/// Decides whether a peer is allowed to connect with, which is enforced when connection
+/// established, and could be updated at runtime.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerPolicy {
+    allowlist: Vec<PeerRule>,
+    denylist: Vec<PeerRule>,
+}
+
+impl PeerPolicy {
+    pub fn from_config(config: &PeerPolicyConfig) -> Result<Self, String> {
+        let parse = |list: &[String]| {
+            list.iter()
+                .map(|rule| rule.parse())
+                .collect::<Result<Vec<PeerRule>, String>>()
+        };
+
+        Ok(Self {
+            allowlist: parse(&config.allowlist)?,
+            denylist: parse(&config.denylist)?,
+        })
+    }
+
+    /// Whether only the allowlisted peers are allowed.
+    pub fn is_private(&self) -> bool {
+        !self.allowlist.is_empty()
+    }
+
+    /// Returns whether the peer is allowed. If `ip` is not available, e.g. before dialing, the
+    /// CIDR ranges are not checked until connected.
+    pub fn is_allowed(&self, peer_id: &PeerId, ip: Option<IpAddr>) -> bool {
+        if self.denylist.iter().any(|rule| rule.matches(peer_id, ip)) {
+            return false;
+        }
+
+        if self.allowlist.is_empty() {
+            return true;
+        }
+
+        self.allowlist.iter().any(|rule| {
+            rule.matches(peer_id, ip) || (ip.is_none() && matches!(rule, PeerRule::Network { .. }))
+        })
+    }
+
+    /// Returns whether the peer is allowed via all of its IP addresses, e.g. seen from its
+    /// connections, so that a peer connected from several addresses is rejected once any of
+    /// them is not allowed. The CIDR ranges are not checked if no address is seen.
+    pub fn is_allowed_ips(&self, peer_id: &PeerId, ips: impl IntoIterator<Item = IpAddr>) -> bool {
+        let mut ips = ips.into_iter().peekable();
+        if ips.peek().is_none() {
+            return self.is_allowed(peer_id, None);
+        }
+
+        ips.all(|ip| self.is_allowed(peer_id, Some(ip)))
+    }
+
+    /// Returns whether the peer is allowed to connect via the specified address.
+    pub fn is_allowed_addr(&self, peer_id: &PeerId, addr: &Multiaddr) -> bool {
+        let ip = addr.iter().find_map(|protocol| match protocol {
+            Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+            Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+            _ => None,
+        });
+
+        self.is_allowed(peer_id, ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allowlist: Vec<String>, denylist: Vec<String>) -> PeerPolicy {
+        PeerPolicy::from_config(&PeerPolicyConfig {
+            allowlist,
+            denylist,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        let peer_id = PeerId::random();
+        assert_eq!(
+            peer_id.to_base58().parse::<PeerRule>(),
+            Ok(PeerRule::Peer(peer_id))
+        );
+        assert_eq!(
+            "10.0.0.0/8".parse::<PeerRule>(),
+            Ok(PeerRule::Network {
+                ip: "10.0.0.0".parse().unwrap(),
+                prefix_len: 8
+            })
+        );
+        assert_eq!(
+            "::1".parse::<PeerRule>(),
+            Ok(PeerRule::Network {
+                ip: "::1".parse().unwrap(),
+                prefix_len: 128
+            })
+        );
+        assert!("10.0.0.0/33".parse::<PeerRule>().is_err());
+        assert!("invalid".parse::<PeerRule>().is_err());
+    }
+
+    #[test]
+    fn test_denylist() {
+        let denied = PeerId::random();
+        let other = PeerId::random();
+        let policy = policy(vec![], vec![denied.to_base58(), "192.168.1.0/24".into()]);
+        assert!(!policy.is_private());
+
+        assert!(!policy.is_allowed(&denied, None));
+        assert!(policy.is_allowed(&other, None));
+        assert!(!policy.is_allowed(&other, Some("192.168.1.10".parse().unwrap())));
+        assert!(!policy.is_allowed(&other, Some("::ffff:192.168.1.10".parse().unwrap())));
+        assert!(policy.is_allowed(&other, Some("192.168.2.10".parse().unwrap())));
+        assert!(!policy.is_allowed_addr(&other, &"/ip4/192.168.1.1/tcp/1234".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_allowlist() {
+        let allowed = PeerId::random();
+        let denied = PeerId::random();
+        let other = PeerId::random();
+        let policy = policy(
+            vec![allowed.to_base58(), "10.0.0.0/8".into()],
+            vec![denied.to_base58()],
+        );
+        assert!(policy.is_private());
+
+        assert!(policy.is_allowed(&allowed, Some("1.2.3.4".parse().unwrap())));
+        assert!(policy.is_allowed(&other, Some("10.1.2.3".parse().unwrap())));
+        assert!(!policy.is_allowed(&other, Some("1.2.3.4".parse().unwrap())));
+        // denylist takes precedence
+        assert!(!policy.is_allowed(&denied, Some("10.1.2.3".parse().unwrap())));
+        // CIDR ranges are checked when connected
+        assert!(policy.is_allowed(&other, None));
+
+        let policy = self::policy(vec![allowed.to_base58()], vec![]);
+        assert!(!policy.is_allowed(&other, None));
+    }
+
+    #[test]
+    fn test_allowed_ips() {
+        let peer = PeerId::random();
+        let policy = policy(vec!["10.0.0.0/8".into()], vec!["10.0.0.1".into()]);
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        assert!(policy.is_allowed_ips(&peer, vec![ip("10.0.0.2"), ip("10.1.0.1")]));
+        // rejected if any address is out of the allowlist or denied, regardless of the order
+        assert!(!policy.is_allowed_ips(&peer, vec![ip("10.0.0.2"), ip("1.2.3.4")]));
+        assert!(!policy.is_allowed_ips(&peer, vec![ip("1.2.3.4"), ip("10.0.0.2")]));
+        assert!(!policy.is_allowed_ips(&peer, vec![ip("10.0.0.2"), ip("10.0.0.1")]));
+        // CIDR ranges are checked once connected
+        assert!(policy.is_allowed_ips(&peer, vec![]));
+    }
+}
@@ -643,6 +643,28 @@ impl PeerDB {
         );
     }
 
+    /// Sets a peer as connected by a network other than libp2p, e.g. the in-process network of
+    /// tests, which has no peer manager to adjust the connection state.
+    #[doc(hidden)]
+    pub fn __connect_testing_only(
+        &mut self,
+        peer_id: &PeerId,
+        seen_address: Multiaddr,
+        outgoing: bool,
+    ) {
+        if outgoing {
+            self.connect_outgoing(peer_id, seen_address, None);
+        } else {
+            self.connect_ingoing(peer_id, seen_address, None);
+        }
+    }
+
+    /// Sets a peer as disconnected by a network other than libp2p, see `__connect_testing_only`.
+    #[doc(hidden)]
+    pub fn __disconnect_testing_only(&mut self, peer_id: &PeerId) {
+        self.inject_disconnect(peer_id);
+    }
+
     /// The connection state of the peer has been changed. Modify the peer in the db to ensure all
     /// variables are in sync with libp2p.
     /// Updating the state can lead to a `BanOperation` which needs to be processed via the peer
@@ -1,5 +1,6 @@
 use libp2p::identify::IdentifyInfo;
 use serde::Serialize;
+use shared_types::ProtocolVersion;
 use strum::{AsRefStr, EnumIter, IntoStaticStr};
 
 /// Various client and protocol information related to a node.
@@ -13,6 +14,8 @@ pub struct Client {
     pub os_version: String,
     /// The libp2p protocol version.
     pub protocol_version: String,
+    /// The P2P protocol version of zgs node, if advertised in the agent string.
+    pub p2p_protocol_version: Option<ProtocolVersion>,
     /// Identify agent string
     pub agent_string: Option<String>,
 }
@@ -32,6 +35,7 @@ impl Default for Client {
             version: "unknown".into(),
             os_version: "unknown".into(),
             protocol_version: "unknown".into(),
+            p2p_protocol_version: None,
             agent_string: None,
         }
     }
@@ -40,13 +44,15 @@ impl Default for Client {
 impl Client {
     /// Builds a `Client` from `IdentifyInfo`.
     pub fn from_identify_info(info: &IdentifyInfo) -> Self {
-        let (kind, version, os_version) = client_from_agent_version(&info.agent_version);
+        let (kind, version, os_version, p2p_protocol_version) =
+            client_from_agent_version(&info.agent_version);
 
         Client {
             kind,
             version,
             os_version,
             protocol_version: info.protocol_version.clone(),
+            p2p_protocol_version,
             agent_string: Some(info.agent_version.clone()),
         }
     }
@@ -78,25 +84,63 @@ impl std::fmt::Display for ClientKind {
 }
 
 // helper function to identify clients from their agent_version. Returns the client
-// kind and it's associated version and the OS kind.
-fn client_from_agent_version(agent_version: &str) -> (ClientKind, String, String) {
+// kind and it's associated version, the OS kind and the P2P protocol version, which is
+// not advertised by old nodes.
+fn client_from_agent_version(
+    agent_version: &str,
+) -> (ClientKind, String, String, Option<ProtocolVersion>) {
     let mut agent_split = agent_version.split('/');
     match agent_split.next() {
         Some("zgs") => {
             let kind = ClientKind::Zgs;
             let mut version = String::from("unknown");
             let mut os_version = version.clone();
+            let mut p2p_protocol_version = None;
             if let Some(agent_version) = agent_split.next() {
                 version = agent_version.into();
                 if let Some(agent_os_version) = agent_split.next() {
                     os_version = agent_os_version.into();
+                    p2p_protocol_version = agent_split.next().and_then(|v| v.parse().ok());
                 }
             }
-            (kind, version, os_version)
+            (kind, version, os_version, p2p_protocol_version)
         }
         _ => {
             let unknown = String::from("unknown");
-            (ClientKind::Unknown, unknown.clone(), unknown)
+            (ClientKind::Unknown, unknown.clone(), unknown, None)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_from_agent_version() {
+        let agent_version = zgs_version::agent_version([0, 4, 0]);
+        let (kind, version, _, p2p_protocol_version) = client_from_agent_version(&agent_version);
+        assert_eq!(kind, ClientKind::Zgs);
+        assert_eq!(version, zgs_version::semver_with_commit());
+        assert_eq!(
+            p2p_protocol_version,
+            Some(ProtocolVersion {
+                major: 0,
+                minor: 4,
+                build: 0
+            })
+        );
+
+        // agent string of old nodes
+        let (kind, version, os_version, p2p_protocol_version) =
+            client_from_agent_version("zgs/v0.2.0-67da032/x86_64-linux");
+        assert_eq!(kind, ClientKind::Zgs);
+        assert_eq!(version, "v0.2.0-67da032");
+        assert_eq!(os_version, "x86_64-linux");
+        assert_eq!(p2p_protocol_version, None);
+
+        let (kind, _, _, p2p_protocol_version) = client_from_agent_version("eth2/1.0.0");
+        assert_eq!(kind, ClientKind::Unknown);
+        assert_eq!(p2p_protocol_version, None);
+    }
+}
@@ -1,13 +1,14 @@
 use super::client::Client;
 use super::score::{PeerAction, Score, ScoreState};
 use super::sync_status::SyncStatus;
+use crate::rpc::{Protocol, Version};
 use crate::Multiaddr;
 use discv5::Enr;
 use serde::{
     ser::{SerializeStruct, Serializer},
     Serialize,
 };
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::net::{IpAddr, SocketAddr};
 use std::time::Instant;
 use strum::AsRefStr;
@@ -42,6 +43,21 @@ pub struct PeerInfo {
     connection_direction: Option<ConnectionDirection>,
     /// The enr of the peer, if known.
     enr: Option<Enr>,
+    /// The negotiated versions of RPC protocols with the peer.
+    #[serde(skip)]
+    rpc_versions: HashMap<Protocol, Version>,
+    /// The last time of an application request or response with the peer, or connected.
+    #[serde(skip)]
+    last_activity: Option<Instant>,
+    /// The last time of a file sync request or response with the peer.
+    #[serde(skip)]
+    last_sync_activity: Option<Instant>,
+    /// Counters of RPC requests and responses with the peer.
+    #[serde(skip)]
+    rpc_counters: RpcCounters,
+    /// The last RPC or dial error with the peer.
+    #[serde(skip)]
+    last_error: Option<String>,
 }
 
 impl Default for PeerInfo {
@@ -57,6 +73,11 @@ impl Default for PeerInfo {
             is_trusted: false,
             connection_direction: None,
             enr: None,
+            rpc_versions: HashMap::new(),
+            last_activity: None,
+            last_sync_activity: None,
+            rpc_counters: RpcCounters::default(),
+            last_error: None,
         }
     }
 }
@@ -119,6 +140,37 @@ impl PeerInfo {
             .map(|socket_addr| socket_addr.ip())
     }
 
+    /// Returns the negotiated version of an RPC protocol, if any request sent or received.
+    pub fn rpc_version(&self, protocol: Protocol) -> Option<Version> {
+        self.rpc_versions.get(&protocol).copied()
+    }
+
+    /// Returns the negotiated versions of RPC protocols with the peer.
+    pub fn rpc_versions(&self) -> &HashMap<Protocol, Version> {
+        &self.rpc_versions
+    }
+
+    /// Returns the counters of RPC requests and responses with the peer.
+    pub fn rpc_counters(&self) -> &RpcCounters {
+        &self.rpc_counters
+    }
+
+    /// Returns the last RPC or dial error with the peer.
+    pub fn last_error(&self) -> Option<&String> {
+        self.last_error.as_ref()
+    }
+
+    /// Returns the last time of an application request or response with the peer, or the time
+    /// connected if none.
+    pub fn last_activity(&self) -> Option<Instant> {
+        self.last_activity
+    }
+
+    /// Returns the last time of a file sync request or response with the peer.
+    pub fn last_sync_activity(&self) -> Option<Instant> {
+        self.last_sync_activity
+    }
+
     /// Returns the connection status of the peer.
     pub fn connection_status(&self) -> &PeerConnectionStatus {
         &self.connection_status
@@ -223,6 +275,35 @@ impl PeerInfo {
     }
 
     /// Sets the ENR of the peer if one is known.
+    pub(in crate::peer_manager) fn set_rpc_version(
+        &mut self,
+        protocol: Protocol,
+        version: Version,
+    ) {
+        self.rpc_versions.insert(protocol, version);
+    }
+
+    /// Records an application request or response with the peer.
+    // VISIBILITY: The peer manager is able to track the activities of a peer
+    pub(in crate::peer_manager) fn record_activity(&mut self, sync: bool) {
+        let now = Instant::now();
+        self.last_activity = Some(now);
+        if sync {
+            self.last_sync_activity = Some(now);
+        }
+    }
+
+    /// Counts an RPC request or response with the peer.
+    pub(in crate::peer_manager) fn record_rpc(&mut self, kind: RpcMessageKind) {
+        self.rpc_counters.record(kind);
+    }
+
+    /// Records an RPC or dial error with the peer.
+    pub(in crate::peer_manager) fn record_error(&mut self, error: String) {
+        self.rpc_counters.errors += 1;
+        self.last_error = Some(error);
+    }
+
     pub(super) fn set_enr(&mut self, enr: Enr) {
         self.enr = Some(enr)
     }
@@ -285,6 +366,7 @@ impl PeerInfo {
             | Unknown => {
                 self.connection_status = Connected { n_in: 1, n_out: 0 };
                 self.connection_direction = Some(ConnectionDirection::Incoming);
+                self.last_activity = Some(Instant::now());
             }
         }
 
@@ -305,6 +387,7 @@ impl PeerInfo {
             | Unknown => {
                 self.connection_status = Connected { n_in: 0, n_out: 1 };
                 self.connection_direction = Some(ConnectionDirection::Outgoing);
+                self.last_activity = Some(Instant::now());
             }
         }
         if let Some(ip_addr) = seen_address {
@@ -326,8 +409,40 @@ impl PeerInfo {
     }
 }
 
+/// Kinds of RPC messages counted per peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcMessageKind {
+    RequestReceived,
+    RequestSent,
+    ResponseReceived,
+    ResponseSent,
+}
+
+/// Counters of RPC messages with a peer since known.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RpcCounters {
+    pub requests_received: u64,
+    pub requests_sent: u64,
+    pub responses_received: u64,
+    pub responses_sent: u64,
+    /// Number of RPC and dial errors.
+    pub errors: u64,
+}
+
+impl RpcCounters {
+    fn record(&mut self, kind: RpcMessageKind) {
+        let counter = match kind {
+            RpcMessageKind::RequestReceived => &mut self.requests_received,
+            RpcMessageKind::RequestSent => &mut self.requests_sent,
+            RpcMessageKind::ResponseReceived => &mut self.responses_received,
+            RpcMessageKind::ResponseSent => &mut self.responses_sent,
+        };
+        *counter += 1;
+    }
+}
+
 /// Connection Direction of connection.
-#[derive(Debug, Clone, Serialize, AsRefStr)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, AsRefStr)]
 #[strum(serialize_all = "snake_case")]
 pub enum ConnectionDirection {
     /// The connection was established by a peer dialing us.
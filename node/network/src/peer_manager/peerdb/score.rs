@@ -324,6 +324,11 @@ impl Score {
         }
     }
 
+    /// Returns whether the peer should be banned given it's score.
+    pub fn is_banned(&self) -> bool {
+        self.state() == ScoreState::Banned
+    }
+
     pub fn is_good_gossipsub_peer(&self) -> bool {
         match self {
             Self::Max => true,
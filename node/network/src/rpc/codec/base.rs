@@ -2,6 +2,7 @@
 
 use crate::rpc::methods::ErrorType;
 use crate::rpc::{InboundRequest, OutboundRequest, RPCCodedResponse, RPCResponse};
+use crate::types::{TrafficDirection, TrafficRecorder};
 use libp2p::bytes::BufMut;
 use libp2p::bytes::BytesMut;
 use tokio_util::codec::{Decoder, Encoder};
@@ -15,6 +16,32 @@ pub trait OutboundCodec<TItem>: Encoder<TItem> + Decoder {
     ) -> Result<Option<Self::CodecErrorType>, <Self as Decoder>::Error>;
 }
 
+/// Accounts the bytes of every message encoded or decoded by a codec.
+#[derive(Default)]
+struct TrafficMeter {
+    recorder: Option<TrafficRecorder>,
+    /// Bytes consumed for the message being decoded.
+    pending: usize,
+}
+
+impl TrafficMeter {
+    fn on_encoded(&self, bytes: usize) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(TrafficDirection::Outbound, bytes);
+        }
+    }
+
+    fn on_decoded(&mut self, consumed: usize, complete: bool) {
+        self.pending += consumed;
+        if complete {
+            if let Some(recorder) = &self.recorder {
+                recorder.record(TrafficDirection::Inbound, self.pending);
+            }
+            self.pending = 0;
+        }
+    }
+}
+
 /* Global Inbound Codec */
 // This deals with Decoding RPC Requests from other peers and encoding our responses
 
@@ -24,6 +51,7 @@ where
 {
     /// Inner codec for handling various encodings
     inner: TCodec,
+    traffic: TrafficMeter,
 }
 
 impl<TCodec> BaseInboundCodec<TCodec>
@@ -31,7 +59,16 @@ where
     TCodec: Encoder<RPCCodedResponse> + Decoder,
 {
     pub fn new(codec: TCodec) -> Self {
-        BaseInboundCodec { inner: codec }
+        BaseInboundCodec {
+            inner: codec,
+            traffic: Default::default(),
+        }
+    }
+
+    /// Records the requests decoded and the responses encoded.
+    pub fn with_traffic(mut self, recorder: Option<TrafficRecorder>) -> Self {
+        self.traffic.recorder = recorder;
+        self
     }
 }
 
@@ -45,6 +82,7 @@ where
     inner: TOutboundCodec,
     /// Keeps track of the current response code for a chunk.
     current_response_code: Option<u8>,
+    traffic: TrafficMeter,
 }
 
 impl<TOutboundCodec> BaseOutboundCodec<TOutboundCodec>
@@ -55,8 +93,15 @@ where
         BaseOutboundCodec {
             inner: codec,
             current_response_code: None,
+            traffic: Default::default(),
         }
     }
+
+    /// Records the requests encoded and the response chunks decoded.
+    pub fn with_traffic(mut self, recorder: Option<TrafficRecorder>) -> Self {
+        self.traffic.recorder = recorder;
+        self
+    }
 }
 
 /* Implementation of the Encoding/Decoding for the global codecs */
@@ -77,7 +122,9 @@ where
             item.as_u8()
                 .expect("Should never encode a stream termination"),
         );
-        self.inner.encode(item, dst)
+        self.inner.encode(item, dst)?;
+        self.traffic.on_encoded(dst.len());
+        Ok(())
     }
 }
 
@@ -90,7 +137,11 @@ where
     type Error = <TCodec as Decoder>::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        self.inner.decode(src)
+        let len = src.len();
+        let result = self.inner.decode(src);
+        self.traffic
+            .on_decoded(len - src.len(), matches!(result, Ok(Some(_))));
+        result
     }
 }
 
@@ -104,7 +155,10 @@ where
     type Error = <TCodec as Encoder<OutboundRequest>>::Error;
 
     fn encode(&mut self, item: OutboundRequest, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        self.inner.encode(item, dst)
+        let len = dst.len();
+        self.inner.encode(item, dst)?;
+        self.traffic.on_encoded(dst.len() - len);
+        Ok(())
     }
 }
 
@@ -118,6 +172,23 @@ where
     type Error = <TCodec as Decoder>::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let len = src.len();
+        let result = self.decode_chunk(src);
+        self.traffic
+            .on_decoded(len - src.len(), matches!(result, Ok(Some(_))));
+        result
+    }
+}
+
+impl<TCodec> BaseOutboundCodec<TCodec>
+where
+    TCodec:
+        OutboundCodec<OutboundRequest, CodecErrorType = ErrorType> + Decoder<Item = RPCResponse>,
+{
+    fn decode_chunk(
+        &mut self,
+        src: &mut BytesMut,
+    ) -> Result<Option<RPCCodedResponse>, <TCodec as Decoder>::Error> {
         // if we have only received the response code, wait for more bytes
         if src.len() <= 1 {
             return Ok(None);
@@ -204,7 +275,7 @@ mod tests {
             ProtocolId::new(Protocol::Status, Version::V1, Encoding::SSZSnappy);
 
         let mut snappy_outbound_codec =
-            SSZSnappyOutboundCodec::new(snappy_protocol_id, max_rpc_size());
+            SSZSnappyOutboundCodec::new(snappy_protocol_id, RpcSizeLimits::default());
 
         let snappy_decoded_message = snappy_outbound_codec.decode(&mut dst).unwrap_err();
 
@@ -227,33 +298,33 @@ mod tests {
         let protocol_id = ProtocolId::new(Protocol::DataByHash, Version::V1, Encoding::SSZSnappy);
 
         // Response limits
-        let max_rpc_size = max_rpc_size();
+        let limits = RpcSizeLimits::default();
         let limit = protocol_id.rpc_response_limits();
         let mut max = encode_len(limit.max + 1);
-        let mut codec = SSZSnappyOutboundCodec::new(protocol_id.clone(), max_rpc_size);
+        let mut codec = SSZSnappyOutboundCodec::new(protocol_id.clone(), limits);
         assert!(matches!(
             codec.decode(&mut max).unwrap_err(),
             RPCError::InvalidData(_)
         ));
 
         let mut min = encode_len(limit.min - 1);
-        let mut codec = SSZSnappyOutboundCodec::new(protocol_id.clone(), max_rpc_size);
+        let mut codec = SSZSnappyOutboundCodec::new(protocol_id.clone(), limits);
         assert!(matches!(
             codec.decode(&mut min).unwrap_err(),
             RPCError::InvalidData(_)
         ));
 
         // Request limits
-        let limit = protocol_id.rpc_request_limits();
+        let limit = protocol_id.rpc_request_limits(&limits);
         let mut max = encode_len(limit.max + 1);
-        let mut codec = SSZSnappyOutboundCodec::new(protocol_id.clone(), max_rpc_size);
+        let mut codec = SSZSnappyOutboundCodec::new(protocol_id.clone(), limits);
         assert!(matches!(
             codec.decode(&mut max).unwrap_err(),
             RPCError::InvalidData(_)
         ));
 
         let mut min = encode_len(limit.min - 1);
-        let mut codec = SSZSnappyOutboundCodec::new(protocol_id, max_rpc_size);
+        let mut codec = SSZSnappyOutboundCodec::new(protocol_id, limits);
         assert!(matches!(
             codec.decode(&mut min).unwrap_err(),
             RPCError::InvalidData(_)
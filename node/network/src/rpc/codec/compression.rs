@@ -0,0 +1,148 @@
+//! Optional compression of sync responses since `Version::V3`, which is the compression tag
+//! followed by the payload.
+//!
+//! Note, all RPC messages are snappy framed anyway, but zstd compresses chunks of text-heavy
+//! files much better, which matters to peers on metered links.
+
+use crate::rpc::protocol::RPCError;
+use std::io::Read;
+
+/// The payload is not compressed.
+pub(crate) const COMPRESSION_NONE: u8 = 0;
+/// The payload is compressed with zstd.
+pub(crate) const COMPRESSION_ZSTD: u8 = 1;
+
+/// Payloads smaller than this are not worth compressing.
+const COMPRESSION_THRESHOLD: usize = 4096;
+const ZSTD_LEVEL: i32 = 3;
+/// The compressed payload is only used if it saves at least 1/8 of bytes, so that incompressible
+/// data, e.g. encrypted files, is not decompressed by peers in vain.
+const MIN_SAVING_RATIO: usize = 8;
+
+/// Compresses the payload if large and compressible enough, and prepends the compression tag.
+pub(crate) fn compress(payload: &[u8]) -> Vec<u8> {
+    if payload.len() >= COMPRESSION_THRESHOLD {
+        if let Ok(compressed) = zstd::bulk::compress(payload, ZSTD_LEVEL) {
+            if compressed.len() <= payload.len() - payload.len() / MIN_SAVING_RATIO {
+                let mut bytes = Vec::with_capacity(1 + compressed.len());
+                bytes.push(COMPRESSION_ZSTD);
+                bytes.extend_from_slice(&compressed);
+                return bytes;
+            }
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(1 + payload.len());
+    bytes.push(COMPRESSION_NONE);
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Decompresses the tagged payload, which should be at most `max_len` bytes once decompressed.
+///
+/// Returns `RPCError::InvalidData` for unknown compression tags, corrupt frames or frames that
+/// decompress beyond `max_len`, so that the peer is penalized.
+pub(crate) fn decompress(bytes: &[u8], max_len: usize) -> Result<Vec<u8>, RPCError> {
+    let (tag, payload) = bytes
+        .split_first()
+        .ok_or_else(|| RPCError::InvalidData("Empty compressed payload".to_string()))?;
+
+    match *tag {
+        COMPRESSION_NONE => Ok(payload.to_vec()),
+        COMPRESSION_ZSTD => {
+            let invalid =
+                |e: std::io::Error| RPCError::InvalidData(format!("Invalid zstd payload: {}", e));
+            // read at most one byte more than `max_len` to detect oversized frames, rather than
+            // allocating `max_len` bytes in advance
+            let mut decompressed = vec![];
+            zstd::stream::read::Decoder::new(payload)
+                .map_err(invalid)?
+                .take(max_len as u64 + 1)
+                .read_to_end(&mut decompressed)
+                .map_err(invalid)?;
+            if decompressed.len() > max_len {
+                return Err(RPCError::InvalidData(format!(
+                    "Decompressed payload exceeds {} bytes",
+                    max_len
+                )));
+            }
+            Ok(decompressed)
+        }
+        tag => Err(RPCError::InvalidData(format!(
+            "Unknown compression tag {}",
+            tag
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+
+    #[test]
+    fn test_compress_round_trip() {
+        let payload = b"hello world ".repeat(1024);
+        let compressed = compress(&payload);
+        assert_eq!(compressed[0], COMPRESSION_ZSTD);
+        assert!(compressed.len() < payload.len() / 10);
+        assert_eq!(decompress(&compressed, payload.len()), Ok(payload.clone()));
+
+        // too large once decompressed
+        assert!(matches!(
+            decompress(&compressed, payload.len() - 1),
+            Err(RPCError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn test_skip_compression() {
+        // small payload
+        let payload = b"hello world".to_vec();
+        let compressed = compress(&payload);
+        assert_eq!(compressed[0], COMPRESSION_NONE);
+        assert_eq!(decompress(&compressed, payload.len()), Ok(payload));
+
+        // incompressible payload
+        let mut payload = vec![0u8; 64 * 1024];
+        rand::thread_rng().fill_bytes(&mut payload);
+        let compressed = compress(&payload);
+        assert_eq!(compressed[0], COMPRESSION_NONE);
+        assert_eq!(compressed.len(), payload.len() + 1);
+        assert_eq!(decompress(&compressed, payload.len()), Ok(payload));
+    }
+
+    #[test]
+    fn test_decompress_corrupt() {
+        let payload = b"hello world ".repeat(1024);
+        let mut compressed = compress(&payload);
+
+        // unknown compression tag
+        compressed[0] = COMPRESSION_ZSTD + 1;
+        assert!(matches!(
+            decompress(&compressed, payload.len()),
+            Err(RPCError::InvalidData(_))
+        ));
+
+        // corrupt frame
+        compressed[0] = COMPRESSION_ZSTD;
+        compressed.truncate(compressed.len() / 2);
+        assert!(matches!(
+            decompress(&compressed, payload.len()),
+            Err(RPCError::InvalidData(_))
+        ));
+
+        // misdeclared as compressed
+        let mut bytes = vec![COMPRESSION_ZSTD];
+        bytes.extend_from_slice(&payload);
+        assert!(matches!(
+            decompress(&bytes, payload.len()),
+            Err(RPCError::InvalidData(_))
+        ));
+
+        assert!(matches!(
+            decompress(&[], payload.len()),
+            Err(RPCError::InvalidData(_))
+        ));
+    }
+}
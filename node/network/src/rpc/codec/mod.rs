@@ -1,4 +1,5 @@
 pub(crate) mod base;
+pub(crate) mod compression;
 pub(crate) mod ssz_snappy;
 
 use self::base::{BaseInboundCodec, BaseOutboundCodec};
@@ -1,11 +1,15 @@
 use crate::rpc::methods::*;
 use crate::rpc::{
-    codec::base::OutboundCodec,
-    protocol::{Encoding, Protocol, ProtocolId, RPCError, Version, ERROR_TYPE_MAX, ERROR_TYPE_MIN},
+    codec::{base::OutboundCodec, compression},
+    protocol::{
+        Encoding, Protocol, ProtocolId, RPCError, RpcLimits, RpcSizeLimits, Version,
+        ANNOUNCEMENTS_RESPONSE_MIN, CHUNKS_RESPONSE_MAX, ERROR_TYPE_MAX, ERROR_TYPE_MIN,
+        FILE_STATUS_RESPONSE_MIN, VERSIONED_CHUNKS_RESPONSE_MAX,
+    },
 };
 use crate::rpc::{InboundRequest, OutboundRequest, RPCCodedResponse, RPCResponse};
 use libp2p::bytes::BytesMut;
-use shared_types::{ChunkArrayWithProof, ShardedFile};
+use shared_types::{ChunkArrayWithProof, ShardedFile, VersionedChunkArrayWithProof};
 use snap::read::FrameDecoder;
 use snap::write::FrameEncoder;
 use ssz::{Decode, Encode};
@@ -22,12 +26,13 @@ pub struct SSZSnappyInboundCodec {
     protocol: ProtocolId,
     inner: Uvi<usize>,
     len: Option<usize>,
-    /// Maximum bytes that can be sent in one req/resp chunked responses.
-    max_packet_size: usize,
+    /// Size limits of messages, e.g. the maximum bytes that can be sent in one req/resp chunked
+    /// responses.
+    limits: RpcSizeLimits,
 }
 
 impl SSZSnappyInboundCodec {
-    pub fn new(protocol: ProtocolId, max_packet_size: usize) -> Self {
+    pub fn new(protocol: ProtocolId, limits: RpcSizeLimits) -> Self {
         let uvi_codec = Uvi::default();
         // this encoding only applies to ssz_snappy.
         debug_assert_eq!(protocol.encoding, Encoding::SSZSnappy);
@@ -36,7 +41,7 @@ impl SSZSnappyInboundCodec {
             inner: uvi_codec,
             protocol,
             len: None,
-            max_packet_size,
+            limits,
         }
     }
 }
@@ -51,17 +56,25 @@ impl Encoder<RPCCodedResponse> for SSZSnappyInboundCodec {
                 RPCResponse::Status(res) => res.as_ssz_bytes(),
                 RPCResponse::Pong(res) => res.data.as_ssz_bytes(),
                 RPCResponse::DataByHash(res) => res.as_ssz_bytes(),
-                RPCResponse::Chunks(res) => res.as_ssz_bytes(),
+                RPCResponse::Chunks(res) => match self.protocol.version {
+                    Version::V1 | Version::V2 => res.as_ssz_bytes(),
+                    Version::V3 => compression::compress(&res.as_ssz_bytes()),
+                    Version::V4 => compression::compress(
+                        &VersionedChunkArrayWithProof::from(res.clone()).as_ssz_bytes(),
+                    ),
+                },
+                RPCResponse::FileStatus(res) => res.as_ssz_bytes(),
+                RPCResponse::Announcements(res) => res.as_ssz_bytes(),
             },
             RPCCodedResponse::Error(_, err) => err.as_ssz_bytes(),
             RPCCodedResponse::StreamTermination(_) => {
                 unreachable!("Code error - attempting to encode a stream termination")
             }
         };
-        // SSZ encoded bytes should be within `max_packet_size`
-        if bytes.len() > self.max_packet_size {
+        // SSZ encoded bytes should be within `max_message_size`
+        if bytes.len() > self.limits.max_message_size {
             return Err(RPCError::InternalError(
-                "attempting to encode data > max_packet_size",
+                "attempting to encode data > max_message_size",
             ));
         }
 
@@ -92,14 +105,19 @@ impl Decoder for SSZSnappyInboundCodec {
             None => return Ok(None),
         };
 
-        // Should not attempt to decode rpc chunks with `length > max_packet_size` or not within bounds of
+        // Should not attempt to decode rpc chunks with `length > max_message_size` or not within bounds of
         // packet size for ssz container corresponding to `self.protocol`.
-        let ssz_limits = self.protocol.rpc_request_limits();
-        if ssz_limits.is_out_of_bounds(length, self.max_packet_size) {
-            return Err(RPCError::InvalidData(format!(
+        let ssz_limits = self.protocol.rpc_request_limits(&self.limits);
+        if ssz_limits.is_out_of_bounds(length, self.limits.max_message_size) {
+            let err = format!(
                 "RPC request length is out of bounds, length {} bounds = {:?}, protocol = {:?}",
                 length, ssz_limits, self.protocol,
-            )));
+            );
+            return Err(if length < ssz_limits.min {
+                RPCError::InvalidData(err)
+            } else {
+                RPCError::TooLarge(err)
+            });
         }
         // Calculate worst case compression length for given uncompressed length
         let max_compressed_len = snap::raw::max_compress_len(length) as u64;
@@ -118,6 +136,10 @@ impl Decoder for SSZSnappyInboundCodec {
 
                 match self.protocol.version {
                     Version::V1 => handle_v1_request(self.protocol.message_name, &decoded_buffer),
+                    // requests are not changed since `Version::V3`
+                    Version::V2 | Version::V3 | Version::V4 => {
+                        handle_v2_request(self.protocol.message_name, &decoded_buffer, &self.limits)
+                    }
                 }
             }
             Err(e) => handle_error(e, reader.get_ref().get_ref().position(), max_compressed_len),
@@ -130,12 +152,16 @@ pub struct SSZSnappyOutboundCodec {
     inner: Uvi<usize>,
     len: Option<usize>,
     protocol: ProtocolId,
-    /// Maximum bytes that can be sent in one req/resp chunked responses.
-    max_packet_size: usize,
+    /// Size limits of messages, e.g. the maximum bytes that can be sent in one req/resp chunked
+    /// responses.
+    limits: RpcSizeLimits,
+    /// Variant tag of the sync request sent, since responses of all variants share the protocol
+    /// of `GetChunks`.
+    sync_variant: u8,
 }
 
 impl SSZSnappyOutboundCodec {
-    pub fn new(protocol: ProtocolId, max_packet_size: usize) -> Self {
+    pub fn new(protocol: ProtocolId, limits: RpcSizeLimits) -> Self {
         let uvi_codec = Uvi::default();
         // this encoding only applies to ssz_snappy.
         debug_assert_eq!(protocol.encoding, Encoding::SSZSnappy);
@@ -143,8 +169,9 @@ impl SSZSnappyOutboundCodec {
         SSZSnappyOutboundCodec {
             inner: uvi_codec,
             protocol,
-            max_packet_size,
+            limits,
             len: None,
+            sync_variant: SYNC_REQUEST_GET_CHUNKS,
         }
     }
 }
@@ -160,12 +187,33 @@ impl Encoder<OutboundRequest> for SSZSnappyOutboundCodec {
             OutboundRequest::Ping(req) => req.as_ssz_bytes(),
             OutboundRequest::DataByHash(req) => req.hashes.as_ssz_bytes(),
             OutboundRequest::AnswerFile(req) => req.as_ssz_bytes(),
-            OutboundRequest::GetChunks(req) => req.as_ssz_bytes(),
+            OutboundRequest::GetChunks(req) => match self.protocol.version {
+                Version::V1 => req.as_ssz_bytes(),
+                Version::V2 | Version::V3 | Version::V4 => {
+                    encode_v2_sync_request(SYNC_REQUEST_GET_CHUNKS, &req)
+                }
+            },
+            OutboundRequest::QueryFileStatus(req) => match self.protocol.version {
+                // not supported by peers of `Version::V1`
+                Version::V1 => return Err(RPCError::UnsupportedProtocol),
+                Version::V2 | Version::V3 | Version::V4 => {
+                    self.sync_variant = SYNC_REQUEST_QUERY_FILE_STATUS;
+                    encode_v2_sync_request(SYNC_REQUEST_QUERY_FILE_STATUS, &req)
+                }
+            },
+            OutboundRequest::GetAnnouncements(req) => match self.protocol.version {
+                // not supported by peers of `Version::V1`
+                Version::V1 => return Err(RPCError::UnsupportedProtocol),
+                Version::V2 | Version::V3 | Version::V4 => {
+                    self.sync_variant = SYNC_REQUEST_GET_ANNOUNCEMENTS;
+                    encode_v2_sync_request(SYNC_REQUEST_GET_ANNOUNCEMENTS, &req)
+                }
+            },
         };
-        // SSZ encoded bytes should be within `max_packet_size`
-        if bytes.len() > self.max_packet_size {
+        // SSZ encoded bytes should be within `max_message_size`
+        if bytes.len() > self.limits.max_message_size {
             return Err(RPCError::InternalError(
-                "attempting to encode data > max_packet_size",
+                "attempting to encode data > max_message_size",
             ));
         }
 
@@ -200,11 +248,21 @@ impl Decoder for SSZSnappyOutboundCodec {
             None => return Ok(None),
         };
 
-        // Should not attempt to decode rpc chunks with `length > max_packet_size` or not within bounds of
+        // Should not attempt to decode rpc chunks with `length > max_message_size` or not within bounds of
         // packet size for ssz container corresponding to `self.protocol`.
-        let ssz_limits = self.protocol.rpc_response_limits();
+        let ssz_limits = match self.sync_variant {
+            SYNC_REQUEST_QUERY_FILE_STATUS => RpcLimits::new(
+                *FILE_STATUS_RESPONSE_MIN,
+                self.limits.max_file_status_response_size,
+            ),
+            SYNC_REQUEST_GET_ANNOUNCEMENTS => RpcLimits::new(
+                *ANNOUNCEMENTS_RESPONSE_MIN,
+                self.limits.max_announcements_response_size,
+            ),
+            _ => self.protocol.rpc_response_limits(),
+        };
 
-        if ssz_limits.is_out_of_bounds(length, self.max_packet_size) {
+        if ssz_limits.is_out_of_bounds(length, self.limits.max_message_size) {
             return Err(RPCError::InvalidData(format!(
                 "RPC response length is out of bounds, length {}",
                 length,
@@ -225,8 +283,27 @@ impl Decoder for SSZSnappyOutboundCodec {
                 self.len = None;
                 let _read_bytes = src.split_to(n as usize);
 
+                match self.sync_variant {
+                    SYNC_REQUEST_QUERY_FILE_STATUS => {
+                        return Ok(Some(RPCResponse::FileStatus(Box::new(
+                            FileStatus::from_ssz_bytes(&decoded_buffer)?,
+                        ))));
+                    }
+                    SYNC_REQUEST_GET_ANNOUNCEMENTS => {
+                        return Ok(Some(RPCResponse::Announcements(
+                            Announcements::from_ssz_bytes(&decoded_buffer)?,
+                        )));
+                    }
+                    _ => {}
+                }
+
                 match self.protocol.version {
-                    Version::V1 => handle_v1_response(self.protocol.message_name, &decoded_buffer),
+                    // responses are not changed in `Version::V2`
+                    Version::V1 | Version::V2 => {
+                        handle_v1_response(self.protocol.message_name, &decoded_buffer)
+                    }
+                    Version::V3 => handle_v3_response(self.protocol.message_name, &decoded_buffer),
+                    Version::V4 => handle_v4_response(self.protocol.message_name, &decoded_buffer),
                 }
             }
             Err(e) => handle_error(e, reader.get_ref().get_ref().position(), max_compressed_len),
@@ -246,9 +323,12 @@ impl OutboundCodec<OutboundRequest> for SSZSnappyOutboundCodec {
             None => return Ok(None),
         };
 
-        // Should not attempt to decode rpc chunks with `length > max_packet_size` or not within bounds of
+        // Should not attempt to decode rpc chunks with `length > max_message_size` or not within bounds of
         // packet size for ssz container corresponding to `ErrorType`.
-        if length > self.max_packet_size || length > *ERROR_TYPE_MAX || length < *ERROR_TYPE_MIN {
+        if length > self.limits.max_message_size
+            || length > *ERROR_TYPE_MAX
+            || length < *ERROR_TYPE_MIN
+        {
             return Err(RPCError::InvalidData(format!(
                 "RPC Error length is out of bounds, length {}",
                 length
@@ -356,6 +436,58 @@ fn handle_v1_request(
     }
 }
 
+/// Encodes a sync request since `Version::V2`, which is the variant tag followed by the ssz
+/// encoded request.
+fn encode_v2_sync_request(variant: u8, req: &impl Encode) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(1 + req.ssz_bytes_len());
+    bytes.push(variant);
+    req.ssz_append(&mut bytes);
+    bytes
+}
+
+/// Decodes a `Version::V2` `InboundRequest` from the byte stream. Sync requests of unknown
+/// variants are decoded as `InboundRequest::Unsupported` rather than an error, so that the
+/// peer is responded instead of being disconnected.
+///
+/// Requests of known variants are also bounded by the limits of the variant.
+fn handle_v2_request(
+    protocol: Protocol,
+    decoded_buffer: &[u8],
+    limits: &RpcSizeLimits,
+) -> Result<Option<InboundRequest>, RPCError> {
+    match protocol {
+        Protocol::GetChunks => {
+            let (variant, body) = decoded_buffer
+                .split_first()
+                .ok_or_else(|| RPCError::InvalidData("Empty sync request".to_string()))?;
+            if let Some(bounds) = limits.sync_request_limits(*variant) {
+                if body.len() > bounds.max {
+                    return Err(RPCError::TooLarge(format!(
+                        "Sync request is too large, length {} bounds = {:?}, variant = {}",
+                        body.len(),
+                        bounds,
+                        variant,
+                    )));
+                }
+            }
+            match *variant {
+                SYNC_REQUEST_GET_CHUNKS => Ok(Some(InboundRequest::GetChunks(
+                    GetChunksRequest::from_ssz_bytes(body)?,
+                ))),
+                SYNC_REQUEST_QUERY_FILE_STATUS => Ok(Some(InboundRequest::QueryFileStatus(
+                    QueryFileStatusRequest::from_ssz_bytes(body)?,
+                ))),
+                SYNC_REQUEST_GET_ANNOUNCEMENTS => Ok(Some(InboundRequest::GetAnnouncements(
+                    GetAnnouncementsRequest::from_ssz_bytes(body)?,
+                ))),
+                variant => Ok(Some(InboundRequest::Unsupported { protocol, variant })),
+            }
+        }
+        // other protocols are not changed in `Version::V2`
+        _ => handle_v1_request(protocol, decoded_buffer),
+    }
+}
+
 /// Decodes a `Version::V1` `RPCResponse` from the byte stream.
 /// `decoded_buffer` should be an ssz-encoded bytestream with
 // length = length-prefix received in the beginning of the stream.
@@ -387,12 +519,56 @@ fn handle_v1_response(
     }
 }
 
+/// Decodes a `Version::V3` `RPCResponse` from the byte stream, in which chunks responses are
+/// decompressed before decoding.
+fn handle_v3_response(
+    protocol: Protocol,
+    decoded_buffer: &[u8],
+) -> Result<Option<RPCResponse>, RPCError> {
+    match protocol {
+        Protocol::GetChunks => {
+            let decompressed = compression::decompress(decoded_buffer, *CHUNKS_RESPONSE_MAX)?;
+            handle_v1_response(protocol, &decompressed)
+        }
+        // other protocols are not changed in `Version::V3`
+        _ => handle_v1_response(protocol, decoded_buffer),
+    }
+}
+
+/// Decodes a `Version::V4` `RPCResponse` from the byte stream, in which the proofs of chunks
+/// responses are versioned.
+///
+/// Returns `RPCError::UnsupportedProtocol` for proofs of unknown versions, e.g. sent by peers of
+/// newer versions, so that the peer is not penalized.
+fn handle_v4_response(
+    protocol: Protocol,
+    decoded_buffer: &[u8],
+) -> Result<Option<RPCResponse>, RPCError> {
+    match protocol {
+        Protocol::GetChunks => {
+            let decompressed =
+                compression::decompress(decoded_buffer, *VERSIONED_CHUNKS_RESPONSE_MAX)?;
+            let response = VersionedChunkArrayWithProof::from_ssz_bytes(&decompressed)?;
+            match ChunkArrayWithProof::try_from(response) {
+                Ok(response) => Ok(Some(RPCResponse::Chunks(response))),
+                Err(e) => {
+                    debug!(%e, "Unsupported chunks response");
+                    Err(RPCError::UnsupportedProtocol)
+                }
+            }
+        }
+        // other protocols are not changed in `Version::V4`
+        _ => handle_v1_response(protocol, decoded_buffer),
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
     use crate::rpc::protocol::*;
     use crate::rpc::{methods::StatusMessage, Ping};
+    use shared_types::{ChunkArray, FlowRangeProof, FlowVersionedRangeProof};
 
     use snap::write::FrameEncoder;
     use ssz::Encode;
@@ -413,11 +589,9 @@ mod tests {
         message: RPCCodedResponse,
     ) -> Result<BytesMut, RPCError> {
         let snappy_protocol_id = ProtocolId::new(protocol, version, Encoding::SSZSnappy);
-        let max_packet_size = max_rpc_size();
-
         let mut buf = BytesMut::new();
         let mut snappy_inbound_codec =
-            SSZSnappyInboundCodec::new(snappy_protocol_id, max_packet_size);
+            SSZSnappyInboundCodec::new(snappy_protocol_id, RpcSizeLimits::default());
 
         snappy_inbound_codec.encode(message, &mut buf)?;
         Ok(buf)
@@ -453,9 +627,8 @@ mod tests {
         message: &mut BytesMut,
     ) -> Result<Option<RPCResponse>, RPCError> {
         let snappy_protocol_id = ProtocolId::new(protocol, version, Encoding::SSZSnappy);
-        let max_packet_size = max_rpc_size();
         let mut snappy_outbound_codec =
-            SSZSnappyOutboundCodec::new(snappy_protocol_id, max_packet_size);
+            SSZSnappyOutboundCodec::new(snappy_protocol_id, RpcSizeLimits::default());
         // decode message just as snappy message
         snappy_outbound_codec.decode(message)
     }
@@ -590,4 +763,353 @@ mod tests {
             RPCError::InvalidData(_)
         ));
     }
+
+    fn get_chunks_request() -> GetChunksRequest {
+        GetChunksRequest {
+            tx_id: Default::default(),
+            index_start: 0,
+            index_end: 1024,
+            merkle_tx_seq: 1,
+        }
+    }
+
+    /// Encodes the given `GetChunks` request as bytes of the specified version.
+    fn encode_get_chunks(version: Version, req: GetChunksRequest) -> BytesMut {
+        let snappy_protocol_id = ProtocolId::new(Protocol::GetChunks, version, Encoding::SSZSnappy);
+        let mut snappy_outbound_codec =
+            SSZSnappyOutboundCodec::new(snappy_protocol_id, RpcSizeLimits::default());
+
+        let mut buf = BytesMut::new();
+        snappy_outbound_codec
+            .encode(OutboundRequest::GetChunks(req), &mut buf)
+            .unwrap();
+        buf
+    }
+
+    /// Attempts to decode the given bytes as a `GetChunks` request of the specified version.
+    fn decode_get_chunks(
+        version: Version,
+        message: &mut BytesMut,
+    ) -> Result<Option<InboundRequest>, RPCError> {
+        let snappy_protocol_id = ProtocolId::new(Protocol::GetChunks, version, Encoding::SSZSnappy);
+        let mut snappy_inbound_codec =
+            SSZSnappyInboundCodec::new(snappy_protocol_id, RpcSizeLimits::default());
+        snappy_inbound_codec.decode(message)
+    }
+
+    #[test]
+    fn test_encode_then_decode_sync_request() {
+        for version in [Version::V1, Version::V2] {
+            let mut encoded = encode_get_chunks(version, get_chunks_request());
+            assert_eq!(
+                decode_get_chunks(version, &mut encoded),
+                Ok(Some(InboundRequest::GetChunks(get_chunks_request())))
+            );
+        }
+
+        // the variant tag is only prepended since `Version::V2`
+        let v1_len = encode_get_chunks(Version::V1, get_chunks_request()).len();
+        let v2_len = encode_get_chunks(Version::V2, get_chunks_request()).len();
+        assert!(v2_len > v1_len);
+    }
+
+    fn chunks_response(data: Vec<u8>) -> ChunkArrayWithProof {
+        ChunkArrayWithProof {
+            chunks: ChunkArray {
+                data,
+                start_index: 0,
+            },
+            proof: FlowRangeProof::new_empty(),
+        }
+    }
+
+    #[test]
+    fn test_encode_then_decode_chunks_response() {
+        // text-heavy chunks are compressed since `Version::V3`
+        let response = chunks_response(b"hello world ".repeat(64 * 1024));
+        let message = || RPCCodedResponse::Success(RPCResponse::Chunks(response.clone()));
+        for version in [Version::V1, Version::V2, Version::V3, Version::V4] {
+            assert_eq!(
+                encode_then_decode(Protocol::GetChunks, version, message()),
+                Ok(Some(RPCResponse::Chunks(response.clone())))
+            );
+        }
+
+        let v2_len = encode(Protocol::GetChunks, Version::V2, message())
+            .unwrap()
+            .len();
+        let v3_len = encode(Protocol::GetChunks, Version::V3, message())
+            .unwrap()
+            .len();
+        assert!(v3_len < v2_len);
+    }
+
+    /// Wraps the ssz bytes of a chunks response of `Version::V4` as sent by the peer.
+    fn encode_v4_chunks_response(response: &VersionedChunkArrayWithProof) -> BytesMut {
+        let bytes = compression::compress(&response.as_ssz_bytes());
+
+        let mut uvi_codec: Uvi<usize> = Uvi::default();
+        let mut dst = BytesMut::new();
+        uvi_codec.encode(bytes.len(), &mut dst).unwrap();
+        let mut writer = FrameEncoder::new(Vec::new());
+        writer.write_all(&bytes).unwrap();
+        writer.flush().unwrap();
+        dst.extend_from_slice(writer.get_ref());
+        dst
+    }
+
+    #[test]
+    fn test_decode_chunks_response_of_proof_version() {
+        let response = chunks_response(vec![1u8; 256]);
+        let mut dst = encode_v4_chunks_response(&response.clone().into());
+        assert_eq!(
+            decode(Protocol::GetChunks, Version::V4, &mut dst),
+            Ok(Some(RPCResponse::Chunks(response.clone())))
+        );
+
+        // the proof of an unknown version is rejected, but not as invalid data
+        let mut versioned = VersionedChunkArrayWithProof::from(response);
+        versioned.proof = FlowVersionedRangeProof::Unknown {
+            version: 2,
+            payload: vec![0u8; 16],
+        };
+        let mut dst = encode_v4_chunks_response(&versioned);
+        assert_eq!(
+            decode(Protocol::GetChunks, Version::V4, &mut dst),
+            Err(RPCError::UnsupportedProtocol)
+        );
+    }
+
+    #[test]
+    fn test_decode_corrupt_compressed_chunks_response() {
+        let mut bytes = vec![compression::COMPRESSION_ZSTD];
+        bytes.extend_from_slice(&chunks_response(vec![0u8; 8192]).as_ssz_bytes());
+
+        let mut uvi_codec: Uvi<usize> = Uvi::default();
+        let mut dst = BytesMut::new();
+        uvi_codec.encode(bytes.len(), &mut dst).unwrap();
+        let mut writer = FrameEncoder::new(Vec::new());
+        writer.write_all(&bytes).unwrap();
+        writer.flush().unwrap();
+        dst.extend_from_slice(writer.get_ref());
+
+        assert!(matches!(
+            decode(Protocol::GetChunks, Version::V3, &mut dst),
+            Err(RPCError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn test_encode_then_decode_file_status() {
+        let request = QueryFileStatusRequest {
+            tx_id: Default::default(),
+            sample_segment: 1,
+            merkle_tx_seq: 1,
+        };
+        let mut status = FileStatus {
+            tx_id: Default::default(),
+            data_root: ethereum_types::H256::repeat_byte(1),
+            available_segments: FileStatus::segments_bitmap(10, |i| i % 2 == 1),
+            sample: Some(chunks_response(vec![1u8; 8192])),
+        };
+        assert_eq!(status.available_segments.len(), 2);
+        assert!(status.is_segment_available(1));
+        assert!(!status.is_segment_available(2));
+        assert!(!status.is_segment_available(11));
+
+        // the request is not supported by peers of `Version::V1`
+        let protocol_id = ProtocolId::new(Protocol::GetChunks, Version::V1, Encoding::SSZSnappy);
+        let mut outbound_codec = SSZSnappyOutboundCodec::new(protocol_id, RpcSizeLimits::default());
+        assert_eq!(
+            outbound_codec.encode(
+                OutboundRequest::QueryFileStatus(request.clone()),
+                &mut BytesMut::new()
+            ),
+            Err(RPCError::UnsupportedProtocol)
+        );
+
+        for version in [Version::V2, Version::V3, Version::V4] {
+            let protocol_id = ProtocolId::new(Protocol::GetChunks, version, Encoding::SSZSnappy);
+            let mut outbound_codec =
+                SSZSnappyOutboundCodec::new(protocol_id.clone(), RpcSizeLimits::default());
+            let mut buf = BytesMut::new();
+            outbound_codec
+                .encode(OutboundRequest::QueryFileStatus(request.clone()), &mut buf)
+                .unwrap();
+            assert_eq!(
+                decode_get_chunks(version, &mut buf),
+                Ok(Some(InboundRequest::QueryFileStatus(request.clone())))
+            );
+
+            // responses are decoded as file status by the codec that sent the request
+            for sample in [status.sample.clone(), None] {
+                status.sample = sample;
+                let mut inbound_codec =
+                    SSZSnappyInboundCodec::new(protocol_id.clone(), RpcSizeLimits::default());
+                let mut buf = BytesMut::new();
+                inbound_codec
+                    .encode(
+                        RPCCodedResponse::Success(RPCResponse::FileStatus(Box::new(
+                            status.clone(),
+                        ))),
+                        &mut buf,
+                    )
+                    .unwrap();
+                assert_eq!(
+                    outbound_codec.decode(&mut buf),
+                    Ok(Some(RPCResponse::FileStatus(Box::new(status.clone()))))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_then_decode_announcements() {
+        let mut known_txs = TxSeqFilter::new(64);
+        for tx_seq in 0..10 {
+            known_txs.insert(tx_seq);
+        }
+        assert!((0..10).all(|tx_seq| known_txs.contains(tx_seq)));
+        assert!(!TxSeqFilter::default().contains(0));
+        let request = GetAnnouncementsRequest {
+            known_txs,
+            max_announcements: 16,
+        };
+        let announcements = Announcements {
+            shard_config: shared_types::ShardConfig {
+                num_shard: 2,
+                shard_id: 1,
+            },
+            tx_ids: vec![Default::default(); 3],
+        };
+
+        for version in [Version::V2, Version::V3, Version::V4] {
+            let protocol_id = ProtocolId::new(Protocol::GetChunks, version, Encoding::SSZSnappy);
+            let mut outbound_codec =
+                SSZSnappyOutboundCodec::new(protocol_id.clone(), RpcSizeLimits::default());
+            let mut buf = BytesMut::new();
+            outbound_codec
+                .encode(OutboundRequest::GetAnnouncements(request.clone()), &mut buf)
+                .unwrap();
+            assert_eq!(
+                decode_get_chunks(version, &mut buf),
+                Ok(Some(InboundRequest::GetAnnouncements(request.clone())))
+            );
+
+            // responses are decoded as announcements by the codec that sent the request
+            let mut inbound_codec =
+                SSZSnappyInboundCodec::new(protocol_id.clone(), RpcSizeLimits::default());
+            let mut buf = BytesMut::new();
+            inbound_codec
+                .encode(
+                    RPCCodedResponse::Success(RPCResponse::Announcements(announcements.clone())),
+                    &mut buf,
+                )
+                .unwrap();
+            assert_eq!(
+                outbound_codec.decode(&mut buf),
+                Ok(Some(RPCResponse::Announcements(announcements.clone())))
+            );
+        }
+    }
+
+    #[test]
+    fn test_configured_size_limits() {
+        let limits = RpcSizeLimits {
+            max_sync_request_size: 64,
+            max_file_status_response_size: 1024,
+            ..Default::default()
+        };
+        let encode_len = |len: usize| {
+            let mut dst = BytesMut::new();
+            Uvi::<usize>::default().encode(len, &mut dst).unwrap();
+            dst
+        };
+        let protocol_id = ProtocolId::new(Protocol::GetChunks, Version::V4, Encoding::SSZSnappy);
+
+        // rejected by the declared length before reading any byte of the request
+        let mut inbound_codec = SSZSnappyInboundCodec::new(protocol_id.clone(), limits);
+        assert!(matches!(
+            inbound_codec.decode(&mut encode_len(65)),
+            Err(RPCError::TooLarge(_))
+        ));
+        let mut inbound_codec = SSZSnappyInboundCodec::new(protocol_id.clone(), limits);
+        assert!(matches!(
+            inbound_codec.decode(&mut encode_len(0)),
+            Err(RPCError::InvalidData(_))
+        ));
+        let mut inbound_codec = SSZSnappyInboundCodec::new(protocol_id.clone(), limits);
+        assert_eq!(inbound_codec.decode(&mut encode_len(64)), Ok(None));
+
+        let mut outbound_codec = SSZSnappyOutboundCodec::new(protocol_id, limits);
+        let request = QueryFileStatusRequest {
+            tx_id: Default::default(),
+            sample_segment: 0,
+            merkle_tx_seq: 0,
+        };
+        outbound_codec
+            .encode(
+                OutboundRequest::QueryFileStatus(request),
+                &mut BytesMut::new(),
+            )
+            .unwrap();
+        assert!(matches!(
+            outbound_codec.decode(&mut encode_len(1025)),
+            Err(RPCError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn test_sync_request_limits_per_variant() {
+        let limits = RpcSizeLimits {
+            max_announcements_request_size: 64,
+            ..Default::default()
+        };
+        let protocol_id = ProtocolId::new(Protocol::GetChunks, Version::V4, Encoding::SSZSnappy);
+        let encode = |filter_bytes: usize| {
+            let request = OutboundRequest::GetAnnouncements(GetAnnouncementsRequest {
+                known_txs: TxSeqFilter::new(filter_bytes),
+                max_announcements: 16,
+            });
+            let mut outbound_codec = SSZSnappyOutboundCodec::new(protocol_id.clone(), limits);
+            let mut buf = BytesMut::new();
+            outbound_codec.encode(request, &mut buf).unwrap();
+            buf
+        };
+
+        // within the limit of sync requests, but beyond the limit of the variant
+        let mut inbound_codec = SSZSnappyInboundCodec::new(protocol_id.clone(), limits);
+        assert!(matches!(
+            inbound_codec.decode(&mut encode(128)),
+            Err(RPCError::TooLarge(_))
+        ));
+
+        let mut inbound_codec = SSZSnappyInboundCodec::new(protocol_id.clone(), limits);
+        assert!(matches!(
+            inbound_codec.decode(&mut encode(32)),
+            Ok(Some(InboundRequest::GetAnnouncements(_)))
+        ));
+    }
+
+    #[test]
+    fn test_decode_unsupported_sync_request() {
+        let bytes =
+            encode_v2_sync_request(SYNC_REQUEST_GET_ANNOUNCEMENTS + 1, &get_chunks_request());
+
+        let mut uvi_codec: Uvi<usize> = Uvi::default();
+        let mut dst = BytesMut::new();
+        uvi_codec.encode(bytes.len(), &mut dst).unwrap();
+        let mut writer = FrameEncoder::new(Vec::new());
+        writer.write_all(&bytes).unwrap();
+        writer.flush().unwrap();
+        dst.extend_from_slice(writer.get_ref());
+
+        assert_eq!(
+            decode_get_chunks(Version::V2, &mut dst),
+            Ok(Some(InboundRequest::Unsupported {
+                protocol: Protocol::GetChunks,
+                variant: SYNC_REQUEST_GET_ANNOUNCEMENTS + 1,
+            }))
+        );
+    }
 }
@@ -0,0 +1,67 @@
+//! Entry points to run the RPC decoders on arbitrary bytes, e.g. by the cargo-fuzz targets in
+//! `network/fuzz`, which are only built with the `fuzzing` feature.
+//!
+//! Decoders should never panic, but return errors for malformed or oversized messages, so that
+//! the peer is penalized.
+
+use crate::rpc::codec::base::{BaseInboundCodec, BaseOutboundCodec};
+use crate::rpc::codec::ssz_snappy::{SSZSnappyInboundCodec, SSZSnappyOutboundCodec};
+use crate::rpc::methods::{GetAnnouncementsRequest, QueryFileStatusRequest};
+use crate::rpc::outbound::OutboundRequest;
+use crate::rpc::protocol::{Protocol, ProtocolId, RpcSizeLimits, Version};
+use libp2p::bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+fn protocol_ids() -> Vec<ProtocolId> {
+    [
+        Protocol::Status,
+        Protocol::Goodbye,
+        Protocol::Ping,
+        Protocol::DataByHash,
+        Protocol::AnswerFile,
+        Protocol::GetChunks,
+    ]
+    .iter()
+    .flat_map(|protocol| protocol.protocol_ids(Version::LATEST))
+    .collect()
+}
+
+/// Decodes the bytes as requests of every protocol and version, as received from a peer.
+pub fn decode_requests(data: &[u8], limits: RpcSizeLimits) {
+    for protocol_id in protocol_ids() {
+        let mut codec = BaseInboundCodec::new(SSZSnappyInboundCodec::new(protocol_id, limits));
+        let mut src = BytesMut::from(data);
+        while let Ok(Some(_)) = codec.decode(&mut src) {}
+    }
+}
+
+/// Decodes the bytes as responses of every protocol and version, as received from a peer.
+pub fn decode_responses(data: &[u8], limits: RpcSizeLimits) {
+    for protocol_id in protocol_ids() {
+        let mut codec =
+            BaseOutboundCodec::new(SSZSnappyOutboundCodec::new(protocol_id.clone(), limits));
+        let mut src = BytesMut::from(data);
+        while let Ok(Some(_)) = codec.decode(&mut src) {}
+
+        // responses of other sync requests, which share the protocol of `GetChunks`
+        let requests = [
+            OutboundRequest::QueryFileStatus(QueryFileStatusRequest {
+                tx_id: Default::default(),
+                sample_segment: 0,
+                merkle_tx_seq: 0,
+            }),
+            OutboundRequest::GetAnnouncements(GetAnnouncementsRequest {
+                known_txs: Default::default(),
+                max_announcements: 0,
+            }),
+        ];
+        for request in requests {
+            let mut codec =
+                BaseOutboundCodec::new(SSZSnappyOutboundCodec::new(protocol_id.clone(), limits));
+            if codec.encode(request, &mut BytesMut::new()).is_ok() {
+                let mut src = BytesMut::from(data);
+                while let Ok(Some(_)) = codec.decode(&mut src) {}
+            }
+        }
+    }
+}
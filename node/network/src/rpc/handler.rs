@@ -3,7 +3,7 @@
 
 use super::methods::{GoodbyeReason, RPCCodedResponse, RPCResponseErrorCode, ResponseTermination};
 use super::outbound::OutboundRequestContainer;
-use super::protocol::{max_rpc_size, InboundRequest, Protocol, RPCError, RPCProtocol};
+use super::protocol::{InboundRequest, Protocol, RPCError, RPCProtocol, Version};
 use super::{RPCReceived, RPCSend, ReqId};
 use crate::rpc::outbound::{OutboundFramed, OutboundRequest};
 use crate::rpc::protocol::InboundFramed;
@@ -114,6 +114,10 @@ pub struct RPCHandler<Id> {
     /// This keeps track of the number of attempts.
     outbound_io_error_retries: u8,
 
+    /// Negotiated versions of RPC protocols with the peer, which are reported to the behaviour
+    /// once changed.
+    versions: FnvHashMap<Protocol, Version>,
+
     /// Waker, to be sure the handler gets polled when needed.
     waker: Option<std::task::Waker>,
 }
@@ -206,10 +210,19 @@ impl<Id> RPCHandler<Id> {
             state: HandlerState::Active,
             max_dial_negotiated: 8,
             outbound_io_error_retries: 0,
+            versions: FnvHashMap::default(),
             waker: None,
         }
     }
 
+    /// Records the negotiated version of a protocol, and reports to the behaviour if changed.
+    fn on_version_negotiated(&mut self, protocol: Protocol, version: Version) {
+        if self.versions.insert(protocol, version) != Some(version) {
+            self.events_out
+                .push(Ok(RPCReceived::VersionNegotiated(protocol, version)));
+        }
+    }
+
     /// Initiates the handler's shutdown process, sending an optional Goodbye message to the
     /// peer.
     fn shutdown(&mut self, goodbye_reason: Option<(Id, GoodbyeReason)>) {
@@ -310,7 +323,8 @@ where
             return;
         }
 
-        let (req, substream) = substream;
+        let (req, substream, version) = substream;
+        self.on_version_negotiated(req.protocol(), version);
         let expected_responses = req.expected_responses();
 
         // store requests that expect responses
@@ -355,6 +369,8 @@ where
         self.dial_negotiated -= 1;
         let (id, request) = request_info;
         let proto = request.protocol();
+        let (out, version) = out;
+        self.on_version_negotiated(proto, version);
 
         // accept outbound connections only if the handler is not deactivated
         if matches!(self.state, HandlerState::Deactivated) {
@@ -415,6 +431,31 @@ where
         }
     }
 
+    fn inject_listen_upgrade_error(
+        &mut self,
+        _info: Self::InboundOpenInfo,
+        error: ConnectionHandlerUpgrErr<
+            <Self::InboundProtocol as InboundUpgrade<NegotiatedSubstream>>::Error,
+        >,
+    ) {
+        // Malformed or oversized requests are reported, so that the peer is penalized rather
+        // than the substream dropped silently. Other failures, e.g. timeouts, are not the
+        // fault of the peer necessarily.
+        if let ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Apply((proto, error))) = error {
+            if matches!(
+                error,
+                RPCError::SSZDecodeError(_) | RPCError::InvalidData(_) | RPCError::TooLarge(_)
+            ) {
+                self.events_out.push(Err(HandlerErr::Inbound {
+                    id: self.current_inbound_substream_id,
+                    proto,
+                    error,
+                }));
+                self.current_inbound_substream_id.0 += 1;
+            }
+        }
+    }
+
     fn inject_dial_upgrade_error(
         &mut self,
         request_info: Self::OutboundOpenInfo,
@@ -928,7 +969,9 @@ where
                 protocol: SubstreamProtocol::new(
                     OutboundRequestContainer {
                         req: req.clone(),
-                        max_rpc_size: max_rpc_size(),
+                        limits: self.listen_protocol.upgrade().limits,
+                        max_version: self.listen_protocol.upgrade().max_version,
+                        traffic: self.listen_protocol.upgrade().traffic.clone(),
                     },
                     (),
                 )
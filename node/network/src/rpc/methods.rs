@@ -9,7 +9,7 @@ use ssz_types::{
 use std::ops::Deref;
 use strum::IntoStaticStr;
 pub type Hash256 = ethereum_types::H256;
-use shared_types::{ChunkArrayWithProof, NetworkIdentity, TxID};
+use shared_types::{splitmix64, ChunkArrayWithProof, DataRoot, NetworkIdentity, ShardConfig, TxID};
 
 pub use ssz_types::{typenum, typenum::Unsigned, BitList, BitVector, FixedVector};
 
@@ -191,6 +191,115 @@ pub struct GetChunksRequest {
     pub merkle_tx_seq: u64,
 }
 
+/// Variant tag of `GetChunksRequest` in sync requests since `Version::V2`.
+pub const SYNC_REQUEST_GET_CHUNKS: u8 = 0;
+
+/// Request the availability of a file from a peer without downloading it, which is verified by
+/// the segment sampled along with the proof.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct QueryFileStatusRequest {
+    pub tx_id: TxID,
+    /// Index of the segment in file to sample if available.
+    pub sample_segment: u64,
+    pub merkle_tx_seq: u64,
+}
+
+/// Variant tag of `QueryFileStatusRequest` in sync requests since `Version::V2`.
+pub const SYNC_REQUEST_QUERY_FILE_STATUS: u8 = 1;
+
+/// Availability of a file responded by a peer.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct FileStatus {
+    pub tx_id: TxID,
+    /// Data root of the file to serve, or zero if the file not found.
+    pub data_root: DataRoot,
+    /// Bitmap of the segments available in file, in which the bit `i % 8` of byte `i / 8` is
+    /// set if the `i`-th segment is available.
+    pub available_segments: Vec<u8>,
+    /// The sampled segment with proof, or `None` if not available.
+    pub sample: Option<ChunkArrayWithProof>,
+}
+
+impl FileStatus {
+    /// Returns the bitmap of `num_segments` segments, in which segments are available if the
+    /// predicate returns `true`.
+    pub fn segments_bitmap(num_segments: usize, available: impl Fn(usize) -> bool) -> Vec<u8> {
+        let mut bitmap = vec![0u8; (num_segments + 7) / 8];
+        for index in (0..num_segments).filter(|i| available(*i)) {
+            bitmap[index / 8] |= 1 << (index % 8);
+        }
+        bitmap
+    }
+
+    pub fn is_segment_available(&self, index: u64) -> bool {
+        match self.available_segments.get((index / 8) as usize) {
+            Some(byte) => byte & (1 << (index % 8)) != 0,
+            None => false,
+        }
+    }
+}
+
+/// Bloom filter of tx seqs, e.g. the files already known by the requester of announcements, so
+/// that peers could skip them in response. False positives only cause some files not exchanged.
+#[derive(Encode, Decode, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TxSeqFilter {
+    bits: Vec<u8>,
+}
+
+impl TxSeqFilter {
+    const NUM_HASHES: u64 = 3;
+
+    /// Creates an empty filter of `num_bytes` bytes, which matches nothing until inserted.
+    pub fn new(num_bytes: usize) -> Self {
+        Self {
+            bits: vec![0u8; num_bytes],
+        }
+    }
+
+    pub fn insert(&mut self, tx_seq: u64) {
+        for bit in self.bit_indices(tx_seq) {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    pub fn contains(&self, tx_seq: u64) -> bool {
+        !self.bits.is_empty()
+            && self
+                .bit_indices(tx_seq)
+                .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Returns the bits of `tx_seq` by double hashing, or none if the filter is empty.
+    fn bit_indices(&self, tx_seq: u64) -> impl Iterator<Item = usize> {
+        let num_bits = self.bits.len() as u64 * 8;
+        let h1 = splitmix64(tx_seq);
+        let h2 = splitmix64(h1) | 1;
+        (0..Self::NUM_HASHES)
+            .take_while(move |_| num_bits > 0)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+}
+
+/// Request the files recently announced by a peer, e.g. once connected, so that new nodes
+/// could backfill the files announced before they joined.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct GetAnnouncementsRequest {
+    /// Files already known by the requester, which are not responded.
+    pub known_txs: TxSeqFilter,
+    pub max_announcements: u64,
+}
+
+/// Variant tag of `GetAnnouncementsRequest` in sync requests since `Version::V2`.
+pub const SYNC_REQUEST_GET_ANNOUNCEMENTS: u8 = 2;
+
+/// Files recently announced by a peer, the latest first, which are not signed since the peer is
+/// authenticated by the connection.
+#[derive(Encode, Decode, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Announcements {
+    pub shard_config: ShardConfig,
+    pub tx_ids: Vec<TxID>,
+}
+
 /* RPC Handling and Grouping */
 // Collection of enums and structs used by the Codecs to encode/decode RPC messages
 
@@ -207,6 +316,12 @@ pub enum RPCResponse {
 
     /// A response to a GET_CHUNKS request.
     Chunks(ChunkArrayWithProof),
+
+    /// A response to a QUERY_FILE_STATUS request.
+    FileStatus(Box<FileStatus>),
+
+    /// A response to a GET_ANNOUNCEMENTS request.
+    Announcements(Announcements),
 }
 
 /// Indicates which response is being terminated by a stream termination response.
@@ -238,6 +353,8 @@ pub enum RPCResponseErrorCode {
     ServerError,
     /// Error spec'd to indicate that a peer does not have blocks on a requested range.
     ResourceUnavailable,
+    /// The request is of an unknown variant, e.g. sent to a peer of an older version.
+    Unsupported,
     Unknown,
 }
 
@@ -262,6 +379,7 @@ impl RPCCodedResponse {
             1 => RPCResponseErrorCode::InvalidRequest,
             2 => RPCResponseErrorCode::ServerError,
             3 => RPCResponseErrorCode::ResourceUnavailable,
+            4 => RPCResponseErrorCode::Unsupported,
             139 => RPCResponseErrorCode::RateLimited,
             _ => RPCResponseErrorCode::Unknown,
         };
@@ -276,6 +394,8 @@ impl RPCCodedResponse {
                 RPCResponse::Pong(_) => false,
                 RPCResponse::DataByHash(_) => true,
                 RPCResponse::Chunks(_) => false,
+                RPCResponse::FileStatus(_) => false,
+                RPCResponse::Announcements(_) => false,
             },
             RPCCodedResponse::Error(_, _) => true,
             // Stream terminations are part of responses that have chunks
@@ -295,6 +415,7 @@ impl RPCResponseErrorCode {
             RPCResponseErrorCode::InvalidRequest => 1,
             RPCResponseErrorCode::ServerError => 2,
             RPCResponseErrorCode::ResourceUnavailable => 3,
+            RPCResponseErrorCode::Unsupported => 4,
             RPCResponseErrorCode::Unknown => 255,
             RPCResponseErrorCode::RateLimited => 139,
         }
@@ -306,6 +427,7 @@ impl std::fmt::Display for RPCResponseErrorCode {
         let repr = match self {
             RPCResponseErrorCode::InvalidRequest => "The request was invalid",
             RPCResponseErrorCode::ResourceUnavailable => "Resource unavailable",
+            RPCResponseErrorCode::Unsupported => "Unsupported request",
             RPCResponseErrorCode::ServerError => "Server error occurred",
             RPCResponseErrorCode::Unknown => "Unknown error occurred",
             RPCResponseErrorCode::RateLimited => "Rate limited",
@@ -335,6 +457,21 @@ impl std::fmt::Display for RPCResponse {
                     data.chunks.data.len()
                 )
             }
+            RPCResponse::FileStatus(status) => {
+                write!(
+                    f,
+                    "FileStatus Response, tx: {:?}, sampled: {}",
+                    status.tx_id,
+                    status.sample.is_some()
+                )
+            }
+            RPCResponse::Announcements(announcements) => {
+                write!(
+                    f,
+                    "Announcements Response, files: {}",
+                    announcements.tx_ids.len()
+                )
+            }
         }
     }
 }
@@ -4,15 +4,19 @@
 //! direct peer-to-peer communication primarily for sending/receiving chain information for
 //! syncing.
 
+use crate::types::NetworkTraffic;
 use futures::future::FutureExt;
 use handler::{HandlerEvent, RPCHandler};
 use libp2p::core::connection::ConnectionId;
+use libp2p::core::ConnectedPoint;
 use libp2p::swarm::{
-    handler::ConnectionHandler, NetworkBehaviour, NetworkBehaviourAction, NotifyHandler,
-    PollParameters, SubstreamProtocol,
+    handler::ConnectionHandler, IntoConnectionHandler, NetworkBehaviour, NetworkBehaviourAction,
+    NotifyHandler, PollParameters, SubstreamProtocol,
 };
 use libp2p::PeerId;
 use rate_limiter::{RPCRateLimiter as RateLimiter, RPCRateLimiterBuilder, RateLimitedErr};
+use std::marker::PhantomData;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
@@ -22,13 +26,16 @@ pub(crate) use protocol::{InboundRequest, RPCProtocol};
 
 pub use handler::SubstreamId;
 pub use methods::{
-    DataByHashRequest, GetChunksRequest, GoodbyeReason, MaxRequestBlocks, RPCResponseErrorCode,
-    ResponseTermination, StatusMessage, ZgsData, MAX_REQUEST_BLOCKS,
+    Announcements, DataByHashRequest, FileStatus, GetAnnouncementsRequest, GetChunksRequest,
+    GoodbyeReason, MaxRequestBlocks, QueryFileStatusRequest, RPCResponseErrorCode,
+    ResponseTermination, StatusMessage, TxSeqFilter, ZgsData, MAX_REQUEST_BLOCKS,
 };
 pub(crate) use outbound::OutboundRequest;
-pub use protocol::{max_rpc_size, Protocol, RPCError};
+pub use protocol::{max_rpc_size, Protocol, RPCError, RpcSizeLimits, Version};
 
 pub(crate) mod codec;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
 mod handler;
 pub mod methods;
 mod outbound;
@@ -73,6 +80,8 @@ pub enum RPCReceived<Id> {
     Response(Id, RPCResponse),
     /// Marks a request as completed
     EndOfStream(Id, ResponseTermination),
+    /// A different version of the protocol is negotiated with the peer.
+    VersionNegotiated(Protocol, Version),
 }
 
 impl<Id: std::fmt::Debug> std::fmt::Display for RPCSend<Id> {
@@ -106,13 +115,50 @@ pub struct RPCMessage<Id> {
 pub struct RPC<Id: ReqId> {
     /// Rate limiter
     limiter: RateLimiter,
+    /// The highest version of RPC protocols to negotiate with peers.
+    max_version: Version,
+    /// Size limits of RPC messages.
+    limits: RpcSizeLimits,
+    /// Traffic of RPC messages per protocol and peer.
+    traffic: Arc<NetworkTraffic>,
     /// Queue of events to be processed.
-    events: Vec<NetworkBehaviourAction<RPCMessage<Id>, RPCHandler<Id>>>,
+    events: Vec<NetworkBehaviourAction<RPCMessage<Id>, RPCHandlerPrototype<Id>>>,
+}
+
+/// Creates the handler of a connection once established, so that the traffic of the connection
+/// is accounted to the remote peer.
+pub struct RPCHandlerPrototype<Id> {
+    limits: RpcSizeLimits,
+    max_version: Version,
+    traffic: Arc<NetworkTraffic>,
+    _phantom: PhantomData<Id>,
+}
+
+impl<Id: ReqId> IntoConnectionHandler for RPCHandlerPrototype<Id> {
+    type Handler = RPCHandler<Id>;
+
+    fn into_handler(self, remote_peer_id: &PeerId, _endpoint: &ConnectedPoint) -> Self::Handler {
+        RPCHandler::new(SubstreamProtocol::new(
+            RPCProtocol {
+                limits: self.limits,
+                max_version: self.max_version,
+                traffic: Some(self.traffic.recorder("rpc".into(), Some(*remote_peer_id))),
+            },
+            (),
+        ))
+    }
+
+    fn inbound_protocol(&self) -> <Self::Handler as ConnectionHandler>::InboundProtocol {
+        RPCProtocol {
+            limits: self.limits,
+            max_version: self.max_version,
+            traffic: None,
+        }
+    }
 }
 
 impl<Id: ReqId> RPC<Id> {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
+    pub fn new(max_version: Version, limits: RpcSizeLimits, traffic: Arc<NetworkTraffic>) -> Self {
         let limiter = RPCRateLimiterBuilder::new()
             .n_every(Protocol::Ping, 2, Duration::from_secs(10))
             .n_every(Protocol::Status, 5, Duration::from_secs(15))
@@ -124,6 +170,9 @@ impl<Id: ReqId> RPC<Id> {
             .expect("Configuration parameters are valid");
         RPC {
             limiter,
+            max_version,
+            limits,
+            traffic,
             events: Vec::new(),
         }
     }
@@ -170,27 +219,41 @@ impl<Id> NetworkBehaviour for RPC<Id>
 where
     Id: ReqId,
 {
-    type ConnectionHandler = RPCHandler<Id>;
+    type ConnectionHandler = RPCHandlerPrototype<Id>;
     type OutEvent = RPCMessage<Id>;
 
     fn new_handler(&mut self) -> Self::ConnectionHandler {
-        RPCHandler::new(SubstreamProtocol::new(
-            RPCProtocol {
-                max_rpc_size: max_rpc_size(),
-            },
-            (),
-        ))
+        RPCHandlerPrototype {
+            limits: self.limits,
+            max_version: self.max_version,
+            traffic: self.traffic.clone(),
+            _phantom: PhantomData,
+        }
     }
 
     fn inject_event(
         &mut self,
         peer_id: PeerId,
         conn_id: ConnectionId,
-        event: <Self::ConnectionHandler as ConnectionHandler>::OutEvent,
+        event: <RPCHandler<Id> as ConnectionHandler>::OutEvent,
     ) {
         if let Ok(RPCReceived::Request(ref id, ref req)) = event {
             // check if the request is conformant to the quota
             match self.limiter.allows(&peer_id, req) {
+                Ok(()) if matches!(req, InboundRequest::Unsupported { .. }) => {
+                    debug!(request = %req, %peer_id, "Unsupported request");
+
+                    // respond an error code instead of failing the request, so that peers of
+                    // newer versions could fall back
+                    self.send_response(
+                        peer_id,
+                        (conn_id, *id),
+                        RPCCodedResponse::Error(
+                            RPCResponseErrorCode::Unsupported,
+                            "Unsupported request variant".into(),
+                        ),
+                    );
+                }
                 Ok(()) => {
                     // send the event to the user
                     self.events
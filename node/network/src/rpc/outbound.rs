@@ -3,11 +3,13 @@ use super::protocol::Protocol;
 use super::protocol::ProtocolId;
 use super::RPCError;
 use crate::rpc::protocol::Encoding;
+use crate::rpc::protocol::RpcSizeLimits;
 use crate::rpc::protocol::Version;
 use crate::rpc::{
     codec::{base::BaseOutboundCodec, ssz_snappy::SSZSnappyOutboundCodec, OutboundCodec},
     methods::ResponseTermination,
 };
+use crate::types::TrafficRecorder;
 use futures::future::BoxFuture;
 use futures::prelude::{AsyncRead, AsyncWrite};
 use futures::{FutureExt, SinkExt};
@@ -26,7 +28,11 @@ use tokio_util::{
 #[derive(Debug, Clone)]
 pub struct OutboundRequestContainer {
     pub req: OutboundRequest,
-    pub max_rpc_size: usize,
+    pub limits: RpcSizeLimits,
+    /// The highest version of RPC protocols to negotiate.
+    pub max_version: Version,
+    /// Records the traffic of the substream.
+    pub traffic: Option<TrafficRecorder>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -37,6 +43,8 @@ pub enum OutboundRequest {
     DataByHash(DataByHashRequest),
     AnswerFile(ShardedFile),
     GetChunks(GetChunksRequest),
+    QueryFileStatus(QueryFileStatusRequest),
+    GetAnnouncements(GetAnnouncementsRequest),
 }
 
 impl UpgradeInfo for OutboundRequestContainer {
@@ -45,46 +53,15 @@ impl UpgradeInfo for OutboundRequestContainer {
 
     // add further protocols as we support more encodings/versions
     fn protocol_info(&self) -> Self::InfoIter {
-        self.req.supported_protocols()
+        self.req.supported_protocols(self.max_version)
     }
 }
 
 /// Implements the encoding per supported protocol for `RPCRequest`.
 impl OutboundRequest {
-    pub fn supported_protocols(&self) -> Vec<ProtocolId> {
-        match self {
-            // add more protocols when versions/encodings are supported
-            OutboundRequest::Status(_) => vec![ProtocolId::new(
-                Protocol::Status,
-                Version::V1,
-                Encoding::SSZSnappy,
-            )],
-            OutboundRequest::Goodbye(_) => vec![ProtocolId::new(
-                Protocol::Goodbye,
-                Version::V1,
-                Encoding::SSZSnappy,
-            )],
-            OutboundRequest::Ping(_) => vec![ProtocolId::new(
-                Protocol::Ping,
-                Version::V1,
-                Encoding::SSZSnappy,
-            )],
-            OutboundRequest::DataByHash(_) => vec![ProtocolId::new(
-                Protocol::DataByHash,
-                Version::V1,
-                Encoding::SSZSnappy,
-            )],
-            OutboundRequest::AnswerFile(_) => vec![ProtocolId::new(
-                Protocol::AnswerFile,
-                Version::V1,
-                Encoding::SSZSnappy,
-            )],
-            OutboundRequest::GetChunks(_) => vec![ProtocolId::new(
-                Protocol::GetChunks,
-                Version::V1,
-                Encoding::SSZSnappy,
-            )],
-        }
+    /// Returns the supported protocol ids up to `max_version`, the highest version first.
+    pub fn supported_protocols(&self, max_version: Version) -> Vec<ProtocolId> {
+        self.protocol().protocol_ids(max_version)
     }
 
     /* These functions are used in the handler for stream management */
@@ -98,6 +75,8 @@ impl OutboundRequest {
             OutboundRequest::DataByHash(req) => req.hashes.len() as u64,
             OutboundRequest::AnswerFile(_) => 0,
             OutboundRequest::GetChunks(_) => 1,
+            OutboundRequest::QueryFileStatus(_) => 1,
+            OutboundRequest::GetAnnouncements(_) => 1,
         }
     }
 
@@ -110,6 +89,9 @@ impl OutboundRequest {
             OutboundRequest::DataByHash(_) => Protocol::DataByHash,
             OutboundRequest::AnswerFile(_) => Protocol::AnswerFile,
             OutboundRequest::GetChunks(_) => Protocol::GetChunks,
+            // sync request variant since `Version::V2`
+            OutboundRequest::QueryFileStatus(_) => Protocol::GetChunks,
+            OutboundRequest::GetAnnouncements(_) => Protocol::GetChunks,
         }
     }
 
@@ -125,6 +107,8 @@ impl OutboundRequest {
             OutboundRequest::Ping(_) => unreachable!(),
             OutboundRequest::AnswerFile(_) => unreachable!(),
             OutboundRequest::GetChunks(_) => unreachable!(),
+            OutboundRequest::QueryFileStatus(_) => unreachable!(),
+            OutboundRequest::GetAnnouncements(_) => unreachable!(),
         }
     }
 }
@@ -134,24 +118,30 @@ impl OutboundRequest {
 /* Outbound upgrades */
 
 pub type OutboundFramed<TSocket> = Framed<Compat<TSocket>, OutboundCodec>;
+/// The framed substream along with the negotiated version.
+pub type OutboundOutput<TSocket> = (OutboundFramed<TSocket>, Version);
 
 impl<TSocket> OutboundUpgrade<TSocket> for OutboundRequestContainer
 where
     TSocket: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
-    type Output = OutboundFramed<TSocket>;
+    type Output = OutboundOutput<TSocket>;
     type Error = RPCError;
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
     fn upgrade_outbound(self, socket: TSocket, protocol: Self::Info) -> Self::Future {
         // convert to a tokio compatible socket
         let socket = socket.compat();
+        let version = protocol.version;
         let codec = match protocol.encoding {
             Encoding::SSZSnappy => {
-                let ssz_snappy_codec = BaseOutboundCodec::new(SSZSnappyOutboundCodec::new(
-                    protocol,
-                    self.max_rpc_size,
-                ));
+                let traffic = self
+                    .traffic
+                    .clone()
+                    .map(|t| t.with_protocol(format!("rpc/{}", protocol.message_name)));
+                let ssz_snappy_codec =
+                    BaseOutboundCodec::new(SSZSnappyOutboundCodec::new(protocol, self.limits))
+                        .with_traffic(traffic);
                 OutboundCodec::SSZSnappy(ssz_snappy_codec)
             }
         };
@@ -161,7 +151,7 @@ where
         async {
             socket.send(self.req).await?;
             socket.close().await?;
-            Ok(socket)
+            Ok((socket, version))
         }
         .boxed()
     }
@@ -186,6 +176,12 @@ impl std::fmt::Display for OutboundRequest {
             OutboundRequest::GetChunks(req) => {
                 write!(f, "GetChunks: {:?}", req)
             }
+            OutboundRequest::QueryFileStatus(req) => {
+                write!(f, "QueryFileStatus: {:?}", req)
+            }
+            OutboundRequest::GetAnnouncements(req) => {
+                write!(f, "GetAnnouncements: max {}", req.max_announcements)
+            }
         }
     }
 }
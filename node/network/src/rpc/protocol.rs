@@ -4,11 +4,15 @@ use crate::rpc::{
     methods::{MaxErrorLen, ResponseTermination, MAX_CHUNKS_LENGTH, MAX_ERROR_LEN},
     MaxRequestBlocks, MAX_REQUEST_BLOCKS,
 };
+use crate::types::TrafficRecorder;
 use futures::future::BoxFuture;
 use futures::prelude::{AsyncRead, AsyncWrite};
 use futures::{FutureExt, StreamExt};
 use libp2p::core::{InboundUpgrade, ProtocolName, UpgradeInfo};
-use shared_types::{ChunkArray, ChunkArrayWithProof, FlowRangeProof, ShardedFile};
+use serde::{Deserialize, Serialize};
+use shared_types::{
+    ChunkArray, ChunkArrayWithProof, FlowRangeProof, ShardedFile, VersionedChunkArrayWithProof,
+};
 use ssz::Encode;
 use ssz_types::VariableList;
 use std::io;
@@ -60,12 +64,41 @@ lazy_static! {
     }
     .as_ssz_bytes()
     .len();
+    pub static ref VERSIONED_CHUNKS_RESPONSE_MAX: usize = VersionedChunkArrayWithProof {
+        chunks: ChunkArray {
+            data: vec![0u8; MAX_CHUNKS_LENGTH],
+            start_index: 0,
+        },
+        proof: FlowRangeProof::new_empty().into(),
+    }
+    .as_ssz_bytes()
+    .len();
+    pub static ref FILE_STATUS_RESPONSE_MIN: usize = FileStatus {
+        tx_id: Default::default(),
+        data_root: Default::default(),
+        available_segments: vec![],
+        sample: None,
+    }
+    .as_ssz_bytes()
+    .len();
+    pub static ref ANNOUNCEMENTS_RESPONSE_MIN: usize = Announcements::default().as_ssz_bytes().len();
 }
 
 // /// The maximum bytes that can be sent across the RPC pre-merge.
 // pub(crate) const MAX_RPC_SIZE: usize = 1_048_576; // 1M
 /// The maximum bytes that can be sent across the RPC post-merge.
 pub(crate) const MAX_RPC_SIZE: usize = 10 * 1_048_576; // 10M
+/// The maximum bytes of a sync request since `Version::V2`, including requests of variants
+/// unknown yet.
+pub(crate) const MAX_SYNC_REQUEST_V2_LEN: usize = 4096;
+/// The maximum bytes of a `GetAnnouncements` request, i.e. the known txs filter and the
+/// maximum number of announcements to respond.
+const MAX_ANNOUNCEMENTS_REQUEST_LEN: usize = 2048;
+/// The maximum bytes of a `FileStatus` response, i.e. the segments bitmap and the sampled
+/// segment along with the proof.
+const MAX_FILE_STATUS_RESPONSE_LEN: usize = 2 * 1_048_576; // 2M
+/// The maximum bytes of an `Announcements` response, i.e. about 6K tx ids.
+const MAX_ANNOUNCEMENTS_RESPONSE_LEN: usize = 256 * 1024; // 256K
 /// The protocol prefix the RPC protocol id.
 const PROTOCOL_PREFIX: &str = "/zgs/req";
 /// Time allowed for the first byte of a request to arrive before we time out (Time To First Byte).
@@ -79,8 +112,64 @@ pub fn max_rpc_size() -> usize {
     MAX_RPC_SIZE
 }
 
+/// Size limits of RPC messages, which are checked against the length prefix before any byte of
+/// the message is allocated or decoded, so that peers could not exhaust the memory by declaring
+/// huge lengths. Messages out of limits are rejected and the peer is penalized, while requests
+/// only too large are tolerated a few times, since they may be sent by peers configured with
+/// larger limits.
+///
+/// Messages of every type are also bounded by the ssz length of the type, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RpcSizeLimits {
+    /// The maximum bytes of any RPC message.
+    pub max_message_size: usize,
+    /// The maximum bytes of a sync request since `Version::V2`.
+    pub max_sync_request_size: usize,
+    /// The maximum bytes of a `GetAnnouncements` request, excluding the variant tag.
+    pub max_announcements_request_size: usize,
+    /// The maximum bytes of a `FileStatus` response.
+    pub max_file_status_response_size: usize,
+    /// The maximum bytes of an `Announcements` response.
+    pub max_announcements_response_size: usize,
+}
+
+impl Default for RpcSizeLimits {
+    fn default() -> Self {
+        Self {
+            max_message_size: MAX_RPC_SIZE,
+            max_sync_request_size: MAX_SYNC_REQUEST_V2_LEN,
+            max_announcements_request_size: MAX_ANNOUNCEMENTS_REQUEST_LEN,
+            max_file_status_response_size: MAX_FILE_STATUS_RESPONSE_LEN,
+            max_announcements_response_size: MAX_ANNOUNCEMENTS_RESPONSE_LEN,
+        }
+    }
+}
+
+impl RpcSizeLimits {
+    /// Returns the size bounds of a sync request since `Version::V2` of the specified variant,
+    /// excluding the variant tag, or `None` for unknown variants, which are only bounded by
+    /// `max_sync_request_size`.
+    pub fn sync_request_limits(&self, variant: u8) -> Option<RpcLimits> {
+        match variant {
+            SYNC_REQUEST_GET_CHUNKS => Some(RpcLimits::new(
+                <GetChunksRequest as Encode>::ssz_fixed_len(),
+                <GetChunksRequest as Encode>::ssz_fixed_len(),
+            )),
+            SYNC_REQUEST_QUERY_FILE_STATUS => Some(RpcLimits::new(
+                <QueryFileStatusRequest as Encode>::ssz_fixed_len(),
+                <QueryFileStatusRequest as Encode>::ssz_fixed_len(),
+            )),
+            SYNC_REQUEST_GET_ANNOUNCEMENTS => {
+                Some(RpcLimits::new(0, self.max_announcements_request_size))
+            }
+            _ => None,
+        }
+    }
+}
+
 /// Protocol names to be used.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Protocol {
     /// The Status protocol name.
     Status,
@@ -98,10 +187,43 @@ pub enum Protocol {
 }
 
 /// RPC Versions
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Version {
     /// Version 1 of RPC
     V1,
+    /// Version 2 of RPC, in which sync requests are encoded with a variant tag, so that new
+    /// variants could be added without new protocol ids.
+    V2,
+    /// Version 3 of RPC, in which chunks responses are optionally compressed with zstd.
+    V3,
+    /// Version 4 of RPC, in which the proofs of chunks responses are prefixed with the proof
+    /// version, so that the layout of proofs could change without breaking peers.
+    V4,
+}
+
+impl Version {
+    /// The latest version of RPC.
+    pub const LATEST: Version = Version::V4;
+}
+
+impl Protocol {
+    /// Returns the supported versions of the protocol, the highest first, so that the highest
+    /// mutual version is negotiated with peers.
+    pub fn versions(&self) -> &'static [Version] {
+        match self {
+            Protocol::GetChunks => &[Version::V4, Version::V3, Version::V2, Version::V1],
+            _ => &[Version::V1],
+        }
+    }
+
+    /// Returns the protocol ids of supported versions up to `max_version`, the highest first.
+    pub fn protocol_ids(&self, max_version: Version) -> Vec<ProtocolId> {
+        self.versions()
+            .iter()
+            .filter(|version| **version <= max_version)
+            .map(|version| ProtocolId::new(*self, *version, Encoding::SSZSnappy))
+            .collect()
+    }
 }
 
 /// RPC Encondings supported.
@@ -137,6 +259,9 @@ impl std::fmt::Display for Version {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let repr = match self {
             Version::V1 => "1",
+            Version::V2 => "2",
+            Version::V3 => "3",
+            Version::V4 => "4",
         };
         f.write_str(repr)
     }
@@ -144,7 +269,11 @@ impl std::fmt::Display for Version {
 
 #[derive(Debug, Clone)]
 pub struct RPCProtocol {
-    pub max_rpc_size: usize,
+    pub limits: RpcSizeLimits,
+    /// The highest version of RPC protocols to negotiate.
+    pub max_version: Version,
+    /// Records the traffic of substreams with the remote peer.
+    pub traffic: Option<TrafficRecorder>,
 }
 
 impl UpgradeInfo for RPCProtocol {
@@ -153,14 +282,17 @@ impl UpgradeInfo for RPCProtocol {
 
     /// The list of supported RPC protocols for Lighthouse.
     fn protocol_info(&self) -> Self::InfoIter {
-        vec![
-            ProtocolId::new(Protocol::Status, Version::V1, Encoding::SSZSnappy),
-            ProtocolId::new(Protocol::Goodbye, Version::V1, Encoding::SSZSnappy),
-            ProtocolId::new(Protocol::Ping, Version::V1, Encoding::SSZSnappy),
-            ProtocolId::new(Protocol::DataByHash, Version::V1, Encoding::SSZSnappy),
-            ProtocolId::new(Protocol::AnswerFile, Version::V1, Encoding::SSZSnappy),
-            ProtocolId::new(Protocol::GetChunks, Version::V1, Encoding::SSZSnappy),
+        [
+            Protocol::Status,
+            Protocol::Goodbye,
+            Protocol::Ping,
+            Protocol::DataByHash,
+            Protocol::AnswerFile,
+            Protocol::GetChunks,
         ]
+        .iter()
+        .flat_map(|protocol| protocol.protocol_ids(self.max_version))
+        .collect()
     }
 }
 
@@ -201,7 +333,7 @@ pub struct ProtocolId {
 
 impl ProtocolId {
     /// Returns min and max size for messages of given protocol id requests.
-    pub fn rpc_request_limits(&self) -> RpcLimits {
+    pub fn rpc_request_limits(&self, limits: &RpcSizeLimits) -> RpcLimits {
         match self.message_name {
             Protocol::Status => RpcLimits::new(
                 <StatusMessage as Encode>::ssz_fixed_len(),
@@ -224,10 +356,16 @@ impl ProtocolId {
                 <ShardedFile as Encode>::ssz_fixed_len(),
                 <ShardedFile as Encode>::ssz_fixed_len(),
             ),
-            Protocol::GetChunks => RpcLimits::new(
-                <GetChunksRequest as Encode>::ssz_fixed_len(),
-                <GetChunksRequest as Encode>::ssz_fixed_len(),
-            ),
+            Protocol::GetChunks => match self.version {
+                Version::V1 => RpcLimits::new(
+                    <GetChunksRequest as Encode>::ssz_fixed_len(),
+                    <GetChunksRequest as Encode>::ssz_fixed_len(),
+                ),
+                // variant tag followed by the request of any variant
+                Version::V2 | Version::V3 | Version::V4 => {
+                    RpcLimits::new(1, limits.max_sync_request_size)
+                }
+            },
         }
     }
 
@@ -252,7 +390,15 @@ impl ProtocolId {
             ),
 
             Protocol::AnswerFile => RpcLimits::new(0, 0), // AnswerFile request has no response
-            Protocol::GetChunks => RpcLimits::new(*CHUNKS_RESPONSE_MIN, *CHUNKS_RESPONSE_MAX),
+            Protocol::GetChunks => match self.version {
+                Version::V1 | Version::V2 => {
+                    RpcLimits::new(*CHUNKS_RESPONSE_MIN, *CHUNKS_RESPONSE_MAX)
+                }
+                // compression tag followed by the payload, which is never larger than the
+                // uncompressed one
+                Version::V3 => RpcLimits::new(1, 1 + *CHUNKS_RESPONSE_MAX),
+                Version::V4 => RpcLimits::new(1, 1 + *VERSIONED_CHUNKS_RESPONSE_MAX),
+            },
         }
     }
 }
@@ -285,28 +431,35 @@ impl ProtocolName for ProtocolId {
 // The inbound protocol reads the request, decodes it and returns the stream to the protocol
 // handler to respond to once ready.
 
-pub type InboundOutput<TSocket> = (InboundRequest, InboundFramed<TSocket>);
+pub type InboundOutput<TSocket> = (InboundRequest, InboundFramed<TSocket>, Version);
 pub type InboundFramed<TSocket> =
     Framed<std::pin::Pin<Box<TimeoutStream<Compat<TSocket>>>>, InboundCodec>;
 
+/// Error of an inbound substream before the request is decoded, along with the protocol.
+pub type InboundUpgradeError = (Protocol, RPCError);
+
 impl<TSocket> InboundUpgrade<TSocket> for RPCProtocol
 where
     TSocket: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
     type Output = InboundOutput<TSocket>;
-    type Error = RPCError;
+    type Error = InboundUpgradeError;
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
     fn upgrade_inbound(self, socket: TSocket, protocol: ProtocolId) -> Self::Future {
         async move {
             // convert the socket to tokio compatible socket
             let socket = socket.compat();
+            let version = protocol.version;
+            let message_name = protocol.message_name;
             let codec = match protocol.encoding {
                 Encoding::SSZSnappy => {
-                    let ssz_snappy_codec = BaseInboundCodec::new(SSZSnappyInboundCodec::new(
-                        protocol,
-                        self.max_rpc_size,
-                    ));
+                    let traffic = self
+                        .traffic
+                        .map(|t| t.with_protocol(format!("rpc/{}", message_name)));
+                    let ssz_snappy_codec =
+                        BaseInboundCodec::new(SSZSnappyInboundCodec::new(protocol, self.limits))
+                            .with_traffic(traffic);
                     InboundCodec::SSZSnappy(ssz_snappy_codec)
                 }
             };
@@ -318,10 +471,10 @@ where
             match tokio::time::timeout(Duration::from_secs(REQUEST_TIMEOUT), socket.into_future())
                 .await
             {
-                Err(e) => Err(RPCError::from(e)),
-                Ok((Some(Ok(request)), stream)) => Ok((request, stream)),
-                Ok((Some(Err(e)), _)) => Err(e),
-                Ok((None, _)) => Err(RPCError::IncompleteStream),
+                Err(e) => Err((message_name, RPCError::from(e))),
+                Ok((Some(Ok(request)), stream)) => Ok((request, stream, version)),
+                Ok((Some(Err(e)), _)) => Err((message_name, e)),
+                Ok((None, _)) => Err((message_name, RPCError::IncompleteStream)),
             }
         }
         .boxed()
@@ -336,6 +489,14 @@ pub enum InboundRequest {
     DataByHash(DataByHashRequest),
     AnswerFile(ShardedFile),
     GetChunks(GetChunksRequest),
+    QueryFileStatus(QueryFileStatusRequest),
+    GetAnnouncements(GetAnnouncementsRequest),
+    /// Request of an unknown variant, e.g. sent by peers of a newer version, which is responded
+    /// with `RPCResponseErrorCode::Unsupported`.
+    Unsupported {
+        protocol: Protocol,
+        variant: u8,
+    },
 }
 
 impl UpgradeInfo for InboundRequest {
@@ -351,39 +512,7 @@ impl UpgradeInfo for InboundRequest {
 /// Implements the encoding per supported protocol for `RPCRequest`.
 impl InboundRequest {
     pub fn supported_protocols(&self) -> Vec<ProtocolId> {
-        match self {
-            // add more protocols when versions/encodings are supported
-            InboundRequest::Status(_) => vec![ProtocolId::new(
-                Protocol::Status,
-                Version::V1,
-                Encoding::SSZSnappy,
-            )],
-            InboundRequest::Goodbye(_) => vec![ProtocolId::new(
-                Protocol::Goodbye,
-                Version::V1,
-                Encoding::SSZSnappy,
-            )],
-            InboundRequest::Ping(_) => vec![ProtocolId::new(
-                Protocol::Ping,
-                Version::V1,
-                Encoding::SSZSnappy,
-            )],
-            InboundRequest::DataByHash(_) => vec![ProtocolId::new(
-                Protocol::DataByHash,
-                Version::V1,
-                Encoding::SSZSnappy,
-            )],
-            InboundRequest::AnswerFile(_) => vec![ProtocolId::new(
-                Protocol::AnswerFile,
-                Version::V1,
-                Encoding::SSZSnappy,
-            )],
-            InboundRequest::GetChunks(_) => vec![ProtocolId::new(
-                Protocol::GetChunks,
-                Version::V1,
-                Encoding::SSZSnappy,
-            )],
-        }
+        self.protocol().protocol_ids(Version::LATEST)
     }
 
     /* These functions are used in the handler for stream management */
@@ -397,6 +526,9 @@ impl InboundRequest {
             InboundRequest::Ping(_) => 1,
             InboundRequest::AnswerFile(_) => 0,
             InboundRequest::GetChunks(_) => 1,
+            InboundRequest::QueryFileStatus(_) => 1,
+            InboundRequest::GetAnnouncements(_) => 1,
+            InboundRequest::Unsupported { .. } => 1,
         }
     }
 
@@ -409,6 +541,9 @@ impl InboundRequest {
             InboundRequest::DataByHash(_) => Protocol::DataByHash,
             InboundRequest::AnswerFile(_) => Protocol::AnswerFile,
             InboundRequest::GetChunks(_) => Protocol::GetChunks,
+            InboundRequest::QueryFileStatus(_) => Protocol::GetChunks,
+            InboundRequest::GetAnnouncements(_) => Protocol::GetChunks,
+            InboundRequest::Unsupported { protocol, .. } => *protocol,
         }
     }
 
@@ -424,6 +559,9 @@ impl InboundRequest {
             InboundRequest::Ping(_) => unreachable!(),
             InboundRequest::AnswerFile(_) => unreachable!(),
             InboundRequest::GetChunks(_) => unreachable!(),
+            InboundRequest::QueryFileStatus(_) => unreachable!(),
+            InboundRequest::GetAnnouncements(_) => unreachable!(),
+            InboundRequest::Unsupported { .. } => unreachable!(),
         }
     }
 }
@@ -448,6 +586,8 @@ pub enum RPCError {
     IncompleteStream,
     /// Peer sent invalid data.
     InvalidData(String),
+    /// Peer sent a request larger than the size limits.
+    TooLarge(String),
     /// An error occurred due to internal reasons. Ex: timer failure.
     InternalError(&'static str),
     /// Negotiation with this peer timed out.
@@ -486,6 +626,9 @@ impl std::fmt::Display for RPCError {
             RPCError::InvalidData(ref err) => {
                 write!(f, "Peer sent unexpected data: {}", err)
             }
+            RPCError::TooLarge(ref err) => {
+                write!(f, "Peer sent too large data: {}", err)
+            }
             RPCError::IoError(ref err) => write!(f, "IO Error: {}", err),
             RPCError::ErrorResponse(ref code, ref reason) => write!(
                 f,
@@ -521,6 +664,7 @@ impl std::error::Error for RPCError {
             RPCError::UnsupportedProtocol => None,
             RPCError::IncompleteStream => None,
             RPCError::InvalidData(_) => None,
+            RPCError::TooLarge(_) => None,
             RPCError::InternalError(_) => None,
             RPCError::ErrorResponse(_, _) => None,
             RPCError::NegotiationTimeout => None,
@@ -547,6 +691,15 @@ impl std::fmt::Display for InboundRequest {
             InboundRequest::GetChunks(req) => {
                 write!(f, "Get Chunks: {:?}", req)
             }
+            InboundRequest::QueryFileStatus(req) => {
+                write!(f, "Query File Status: {:?}", req)
+            }
+            InboundRequest::GetAnnouncements(req) => {
+                write!(f, "Get Announcements: max {}", req.max_announcements)
+            }
+            InboundRequest::Unsupported { protocol, variant } => {
+                write!(f, "Unsupported: {} variant {}", protocol, variant)
+            }
         }
     }
 }
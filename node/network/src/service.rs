@@ -22,7 +22,9 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::peer_manager::{MIN_OUTBOUND_ONLY_FACTOR, PEER_EXCESS_FACTOR, PRIORITY_PEER_EXCESS};
+use crate::peer_manager::connection_budget::ConnectionBudget;
+use crate::peer_manager::peer_policy::PeerPolicy;
+use crate::peer_manager::{PEER_EXCESS_FACTOR, PRIORITY_PEER_EXCESS};
 
 pub const NETWORK_KEY_FILENAME: &str = "key";
 /// The maximum simultaneous libp2p connections per peer.
@@ -40,6 +42,8 @@ pub enum Libp2pEvent<AppReqId: ReqId> {
     NewListenAddr(Multiaddr),
     /// We reached zero listening addresses.
     ZeroListeners,
+    /// Failed to dial a peer.
+    DialFailed { peer_id: PeerId, error: String },
 }
 
 /// The configuration and state of the libp2p components for the beacon node.
@@ -73,6 +77,9 @@ impl<AppReqId: ReqId> Service<AppReqId> {
 
         let local_peer_id = enr.peer_id();
 
+        let peer_policy = PeerPolicy::from_config(&config.peer_policy)
+            .map_err(|e| format!("Invalid peer policy: {}", e))?;
+
         // set up a collection of variables accessible outside of the network crate
         let network_globals = Arc::new(NetworkGlobals::new(
             enr.clone(),
@@ -85,16 +92,20 @@ impl<AppReqId: ReqId> Service<AppReqId> {
                 .collect(),
             config.peer_db,
             config.network_id.clone(),
+            peer_policy,
         ));
 
-        // try and construct UPnP port mappings if required.
-        if let Some(upnp_config) = crate::nat::UPnPConfig::from_config(config) {
-            if config.upnp_enabled {
-                executor.spawn_blocking(
-                    move || crate::nat::construct_upnp_mappings(upnp_config, network_sender),
-                    "UPnP",
-                );
-            }
+        // try and construct UPnP or NAT-PMP port mappings if required.
+        if let Some(port_mapping_config) = crate::nat::PortMappingConfig::from_config(config) {
+            executor.spawn(
+                crate::nat::maintain_port_mappings(
+                    executor.clone(),
+                    port_mapping_config,
+                    network_globals.clone(),
+                    network_sender,
+                ),
+                "port_mapping",
+            );
         }
 
         info!(
@@ -133,21 +144,22 @@ impl<AppReqId: ReqId> Service<AppReqId> {
                 }
             }
 
-            // sets up the libp2p connection limits
+            // sets up the libp2p connection limits, in which the reserved slots of the
+            // connection budget are shared by both directions
+            let budget = ConnectionBudget::new(&config.peer_manager, config.target_peers);
             let limits = ConnectionLimits::default()
                 .with_max_pending_incoming(Some(5))
                 .with_max_pending_outgoing(Some(16))
                 .with_max_established_incoming(Some(
-                    (config.target_peers as f32
-                        * (1.0 + PEER_EXCESS_FACTOR - MIN_OUTBOUND_ONLY_FACTOR))
-                        .ceil() as u32,
+                    (budget.max_inbound + budget.reserved()) as u32,
                 ))
                 .with_max_established_outgoing(Some(
-                    (config.target_peers as f32 * (1.0 + PEER_EXCESS_FACTOR)).ceil() as u32,
+                    (budget.max_outbound + budget.reserved()) as u32,
                 ))
                 .with_max_established(Some(
                     (config.target_peers as f32 * (1.0 + PEER_EXCESS_FACTOR + PRIORITY_PEER_EXCESS))
-                        .ceil() as u32,
+                        .ceil() as u32
+                        + budget.reserved() as u32,
                 ))
                 .with_max_established_per_peer(Some(MAX_CONNECTIONS_PER_PEER));
 
@@ -249,6 +261,7 @@ impl<AppReqId: ReqId> Service<AppReqId> {
             GossipKind::FindFile,
             GossipKind::AnnounceFile,
             GossipKind::AnnounceShardConfig,
+            GossipKind::RetractFile,
         ];
         if config.find_chunks_enabled {
             topics.push(GossipKind::FindChunks);
@@ -373,6 +386,12 @@ impl<AppReqId: ReqId> Service<AppReqId> {
                 }
                 SwarmEvent::OutgoingConnectionError { peer_id, error } => {
                     debug!(peer_id = ?peer_id,  error = %error, "Failed to dial address");
+                    if let Some(peer_id) = peer_id {
+                        return Libp2pEvent::DialFailed {
+                            peer_id,
+                            error: error.to_string(),
+                        };
+                    }
                 }
                 SwarmEvent::ExpiredListenAddr { address, .. } => {
                     debug!(address = %address, "Listen address expired")
@@ -0,0 +1,73 @@
+use ssz::{Decode, DecodeError, Encode};
+
+/// Capabilities of a node advertised in its ENR, so that peers which are not useful could be
+/// filtered out before dialing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct NodeCapabilities(u8);
+
+impl NodeCapabilities {
+    /// The node stores file data and serves chunks to peers, which is not the case for RPC-only
+    /// nodes.
+    pub const SERVES_DATA: NodeCapabilities = NodeCapabilities(1);
+    /// The node accepts sync requests from peers, e.g. to find files and download chunks.
+    pub const ACCEPTS_SYNC: NodeCapabilities = NodeCapabilities(1 << 1);
+
+    pub const fn empty() -> Self {
+        NodeCapabilities(0)
+    }
+
+    pub const fn all() -> Self {
+        NodeCapabilities(Self::SERVES_DATA.0 | Self::ACCEPTS_SYNC.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether all the capabilities of `other` are included.
+    pub fn contains(&self, other: NodeCapabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn set(&mut self, other: NodeCapabilities, enabled: bool) {
+        if enabled {
+            self.0 |= other.0;
+        } else {
+            self.0 &= !other.0;
+        }
+    }
+}
+
+impl Encode for NodeCapabilities {
+    fn is_ssz_fixed_len() -> bool {
+        true
+    }
+
+    fn ssz_fixed_len() -> usize {
+        <u8 as Encode>::ssz_fixed_len()
+    }
+
+    fn ssz_bytes_len(&self) -> usize {
+        <u8 as Encode>::ssz_fixed_len()
+    }
+
+    fn ssz_append(&self, buf: &mut Vec<u8>) {
+        self.0.ssz_append(buf)
+    }
+}
+
+impl Decode for NodeCapabilities {
+    fn is_ssz_fixed_len() -> bool {
+        true
+    }
+
+    fn ssz_fixed_len() -> usize {
+        <u8 as Decode>::ssz_fixed_len()
+    }
+
+    /// Unknown capabilities of newer versions are ignored.
+    fn from_ssz_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let value = u8::from_ssz_bytes(bytes)?;
+        Ok(NodeCapabilities(value & Self::all().0))
+    }
+}
@@ -1,6 +1,10 @@
 //! A collection of variables that are accessible outside of the network thread itself.
+use crate::nat::NatStatus;
+use crate::peer_manager::connection_budget::ConnectionStats;
+use crate::peer_manager::peer_policy::PeerPolicy;
 use crate::peer_manager::peerdb::PeerDB;
 use crate::peer_manager::peerdb::PeerDBConfig;
+use crate::types::NetworkTraffic;
 use crate::Client;
 use crate::EnrExt;
 use crate::{Enr, GossipTopic, Multiaddr, PeerId};
@@ -8,6 +12,7 @@ use parking_lot::RwLock;
 use shared_types::NetworkIdentity;
 use std::collections::HashSet;
 use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
 
 pub struct NetworkGlobals {
     /// The current local ENR.
@@ -27,6 +32,14 @@ pub struct NetworkGlobals {
 
     /// The id of the storage network.
     pub network_id: RwLock<NetworkIdentity>,
+    /// The allowlist and denylist of peers, which could be updated at runtime.
+    pub peer_policy: RwLock<PeerPolicy>,
+    /// The connection counts against the connection budget, updated by the peer manager.
+    pub connection_stats: RwLock<ConnectionStats>,
+    /// Whether the local node is publicly reachable.
+    pub nat_status: RwLock<NatStatus>,
+    /// Messages and bytes sent and received per protocol and peer.
+    pub traffic: Arc<NetworkTraffic>,
 }
 
 impl NetworkGlobals {
@@ -37,6 +50,7 @@ impl NetworkGlobals {
         trusted_peers: Vec<PeerId>,
         peer_db_config: PeerDBConfig,
         network_id: NetworkIdentity,
+        peer_policy: PeerPolicy,
     ) -> Self {
         NetworkGlobals {
             local_enr: RwLock::new(enr.clone()),
@@ -47,6 +61,10 @@ impl NetworkGlobals {
             peers: RwLock::new(PeerDB::new(peer_db_config, trusted_peers)),
             gossipsub_subscriptions: RwLock::new(HashSet::new()),
             network_id: RwLock::new(network_id),
+            peer_policy: RwLock::new(peer_policy),
+            connection_stats: RwLock::new(ConnectionStats::default()),
+            nat_status: RwLock::new(NatStatus::default()),
+            traffic: Default::default(),
         }
     }
 
@@ -119,6 +137,7 @@ impl NetworkGlobals {
             vec![],
             Default::default(),
             Default::default(),
+            Default::default(),
         )
     }
 }
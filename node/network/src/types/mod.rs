@@ -1,13 +1,20 @@
+mod capabilities;
 pub mod error;
 mod globals;
 mod pubsub;
 mod topics;
+mod traffic;
 
 pub type Enr = discv5::enr::Enr<discv5::enr::CombinedKey>;
 
+pub use capabilities::NodeCapabilities;
 pub use globals::NetworkGlobals;
 pub use pubsub::{
-    AnnounceChunks, AnnounceFile, FindChunks, FindFile, HasSignature, PubsubMessage,
-    SignedAnnounceFile, SignedMessage, SnappyTransform, TimedMessage,
+    AnnounceChunks, AnnounceFile, FindChunks, FindFile, HasSignature, PubsubMessage, RetractFile,
+    SignedAnnounceFile, SignedMessage, SignedRetractFile, SnappyTransform, TimedMessage,
 };
 pub use topics::{GossipEncoding, GossipKind, GossipTopic};
+pub use traffic::{
+    NetworkTraffic, PeerTraffic, ProtocolTraffic, TrafficCounter, TrafficDirection,
+    TrafficRecorder, TrafficStats, DISCOVERY_PROTOCOL,
+};
@@ -1,6 +1,6 @@
 //! Handles the encoding and decoding of pubsub messages.
 
-use crate::types::{GossipEncoding, GossipKind, GossipTopic};
+use crate::types::{GossipEncoding, GossipKind, GossipTopic, NetworkTraffic, TrafficDirection};
 use crate::{Keypair, PublicKey, SigningError, TopicHash};
 use libp2p::{
     gossipsub::{DataTransform, GossipsubMessage, RawGossipsubMessage},
@@ -13,8 +13,14 @@ use ssz_derive::{Decode, Encode};
 use std::{
     io::{Error, ErrorKind},
     ops::Deref,
+    sync::Arc,
 };
 
+/// The maximum bytes of a multiaddr in gossip messages, which is far beyond any valid one.
+const MAX_MULTIADDR_LEN: usize = 1024;
+/// The maximum bytes of a peer id, i.e. the multihash of a public key.
+const MAX_PEER_ID_LEN: usize = 128;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct WrappedMultiaddr(Multiaddr);
 
@@ -50,7 +56,13 @@ impl ssz::Decode for WrappedMultiaddr {
     }
 
     fn from_ssz_bytes(bytes: &[u8]) -> Result<Self, ssz::DecodeError> {
-        // TODO: limit length
+        if bytes.len() > MAX_MULTIADDR_LEN {
+            return Err(ssz::DecodeError::BytesInvalid(format!(
+                "Multiaddr too long: {}",
+                bytes.len()
+            )));
+        }
+
         match Multiaddr::try_from(bytes.to_vec()) {
             Ok(addr) => Ok(WrappedMultiaddr(addr)),
             Err(_) => Err(ssz::DecodeError::BytesInvalid(
@@ -104,7 +116,13 @@ impl ssz::Decode for WrappedPeerId {
     }
 
     fn from_ssz_bytes(bytes: &[u8]) -> Result<Self, ssz::DecodeError> {
-        // TODO: limit length
+        if bytes.len() > MAX_PEER_ID_LEN {
+            return Err(ssz::DecodeError::BytesInvalid(format!(
+                "Peer id too long: {}",
+                bytes.len()
+            )));
+        }
+
         match PeerId::from_bytes(bytes) {
             Ok(addr) => Ok(WrappedPeerId(addr)),
             Err(_) => Err(ssz::DecodeError::BytesInvalid(
@@ -206,8 +224,16 @@ impl<T: Encode + Decode> HasSignature for SignedMessage<T> {
     }
 }
 
+/// Retracts the files announced by the peer, whose txs were reverted by a chain reorg.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct RetractFile {
+    pub tx_ids: Vec<TxID>,
+    pub peer_id: WrappedPeerId,
+}
+
 pub type SignedAnnounceFile = SignedMessage<TimedMessage<AnnounceFile>>;
 type SignedAnnounceFiles = Vec<SignedAnnounceFile>;
+pub type SignedRetractFile = SignedMessage<TimedMessage<RetractFile>>;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PubsubMessage {
@@ -226,18 +252,38 @@ pub enum PubsubMessage {
     AnnounceShardConfig(TimedMessage<ShardConfig>),
     /// Published to network to announce chunks.
     AnnounceChunks(TimedMessage<AnnounceChunks>),
+    /// Published to network to retract the announced files reverted on chain.
+    RetractFile(SignedRetractFile),
 }
 
 // Implements the `DataTransform` trait of gossipsub to employ snappy compression
 pub struct SnappyTransform {
     /// Sets the maximum size we allow gossipsub messages to decompress to.
     max_size_per_message: usize,
+    /// Records the compressed messages per topic kind.
+    traffic: Option<Arc<NetworkTraffic>>,
 }
 
 impl SnappyTransform {
     pub fn new(max_size_per_message: usize) -> Self {
         SnappyTransform {
             max_size_per_message,
+            traffic: None,
+        }
+    }
+
+    pub fn with_traffic(mut self, traffic: Arc<NetworkTraffic>) -> Self {
+        self.traffic = Some(traffic);
+        self
+    }
+
+    fn record(&self, topic: &TopicHash, direction: TrafficDirection, bytes: usize) {
+        if let Some(traffic) = &self.traffic {
+            let kind = match GossipTopic::decode(topic.as_str()) {
+                Ok(topic) => topic.kind().as_ref().to_string(),
+                Err(_) => "unknown".into(),
+            };
+            traffic.record(&format!("gossip/{}", kind), direction, None, bytes);
         }
     }
 }
@@ -248,6 +294,12 @@ impl DataTransform for SnappyTransform {
         &self,
         raw_message: RawGossipsubMessage,
     ) -> Result<GossipsubMessage, std::io::Error> {
+        self.record(
+            &raw_message.topic,
+            TrafficDirection::Inbound,
+            raw_message.data.len(),
+        );
+
         // check the length of the raw bytes
         let len = decompress_len(&raw_message.data)?;
         if len > self.max_size_per_message {
@@ -272,7 +324,7 @@ impl DataTransform for SnappyTransform {
     /// Provides the snappy compression logic to gossipsub.
     fn outbound_transform(
         &self,
-        _topic: &TopicHash,
+        topic: &TopicHash,
         data: Vec<u8>,
     ) -> Result<Vec<u8>, std::io::Error> {
         // Currently we are not employing topic-based compression. Everything is expected to be
@@ -284,7 +336,9 @@ impl DataTransform for SnappyTransform {
             ));
         }
         let mut encoder = Encoder::new();
-        encoder.compress_vec(&data).map_err(Into::into)
+        let compressed = encoder.compress_vec(&data)?;
+        self.record(topic, TrafficDirection::Outbound, compressed.len());
+        Ok(compressed)
     }
 }
 
@@ -306,6 +360,7 @@ impl PubsubMessage {
             PubsubMessage::AnnounceFile(_) => GossipKind::AnnounceFile,
             PubsubMessage::AnnounceChunks(_) => GossipKind::AnnounceChunks,
             PubsubMessage::AnnounceShardConfig(_) => GossipKind::AnnounceShardConfig,
+            PubsubMessage::RetractFile(_) => GossipKind::RetractFile,
         }
     }
 
@@ -355,6 +410,9 @@ impl PubsubMessage {
                         TimedMessage::<ShardConfig>::from_ssz_bytes(data)
                             .map_err(|e| format!("{:?}", e))?,
                     )),
+                    GossipKind::RetractFile => Ok(PubsubMessage::RetractFile(
+                        SignedRetractFile::from_ssz_bytes(data).map_err(|e| format!("{:?}", e))?,
+                    )),
                 }
             }
         }
@@ -376,6 +434,7 @@ impl PubsubMessage {
             PubsubMessage::AnnounceFile(data) => data.as_ssz_bytes(),
             PubsubMessage::AnnounceChunks(data) => data.as_ssz_bytes(),
             PubsubMessage::AnnounceShardConfig(data) => data.as_ssz_bytes(),
+            PubsubMessage::RetractFile(data) => data.as_ssz_bytes(),
         }
     }
 }
@@ -407,6 +466,9 @@ impl std::fmt::Display for PubsubMessage {
             PubsubMessage::AnnounceShardConfig(msg) => {
                 write!(f, "AnnounceShardConfig message: {:?}", msg)
             }
+            PubsubMessage::RetractFile(msg) => {
+                write!(f, "RetractFile message: {:?}", msg)
+            }
         }
     }
 }
@@ -15,6 +15,7 @@ pub const FIND_CHUNKS_TOPIC: &str = "find_chunks_v2";
 pub const ANNOUNCE_FILE_TOPIC: &str = "announce_file_v2";
 pub const ANNOUNCE_CHUNKS_TOPIC: &str = "announce_chunks_v2";
 pub const ANNOUNCE_SHARD_CONFIG_TOPIC: &str = "announce_shard_config_v2";
+pub const RETRACT_FILE_TOPIC: &str = "retract_file_v2";
 
 /// A gossipsub topic which encapsulates the type of messages that should be sent and received over
 /// the pubsub protocol and the way the messages should be encoded.
@@ -39,6 +40,7 @@ pub enum GossipKind {
     AnnounceFile,
     AnnounceShardConfig,
     AnnounceChunks,
+    RetractFile,
 }
 
 /// The known encoding types for gossipsub messages.
@@ -81,6 +83,7 @@ impl GossipTopic {
                 ANNOUNCE_FILE_TOPIC => GossipKind::AnnounceFile,
                 ANNOUNCE_CHUNKS_TOPIC => GossipKind::AnnounceChunks,
                 ANNOUNCE_SHARD_CONFIG_TOPIC => GossipKind::AnnounceShardConfig,
+                RETRACT_FILE_TOPIC => GossipKind::RetractFile,
                 _ => return Err(format!("Unknown topic: {}", topic)),
             };
 
@@ -112,6 +115,7 @@ impl From<GossipTopic> for String {
             GossipKind::AnnounceFile => ANNOUNCE_FILE_TOPIC,
             GossipKind::AnnounceChunks => ANNOUNCE_CHUNKS_TOPIC,
             GossipKind::AnnounceShardConfig => ANNOUNCE_SHARD_CONFIG_TOPIC,
+            GossipKind::RetractFile => RETRACT_FILE_TOPIC,
         };
 
         format!("/{}/{}/{}", TOPIC_PREFIX, kind, encoding)
@@ -133,6 +137,7 @@ impl std::fmt::Display for GossipTopic {
             GossipKind::AnnounceFile => ANNOUNCE_FILE_TOPIC,
             GossipKind::AnnounceChunks => ANNOUNCE_CHUNKS_TOPIC,
             GossipKind::AnnounceShardConfig => ANNOUNCE_SHARD_CONFIG_TOPIC,
+            GossipKind::RetractFile => RETRACT_FILE_TOPIC,
         };
 
         write!(f, "/{}/{}/{}", TOPIC_PREFIX, kind, encoding)
@@ -0,0 +1,329 @@
+//! Accounting of the messages and bytes sent and received per protocol and peer.
+//!
+//! The RPC codecs record every message with the bytes written to or read from the substream,
+//! and the snappy transform of gossipsub records every message with the compressed payload, so
+//! that the bytes exclude only the framing of the transport, e.g. noise and yamux. Gossip
+//! messages are recorded once when published, instead of once per peer forwarded to, and are
+//! not accounted to peers, since the transform never knows the propagation source.
+use crate::metrics;
+use crate::PeerId;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Maximum number of peers to account, beyond which the peer of the least bytes is dropped.
+const MAX_TRACKED_PEERS: usize = 1024;
+
+/// Protocol of discv5, whose packets are counted by discv5 itself.
+pub const DISCOVERY_PROTOCOL: &str = "discovery";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TrafficDirection {
+    Inbound,
+    Outbound,
+}
+
+impl TrafficDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrafficDirection::Inbound => "inbound",
+            TrafficDirection::Outbound => "outbound",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrafficCounter {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+impl TrafficCounter {
+    fn add(&mut self, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
+/// Traffic of a protocol in a direction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProtocolTraffic {
+    /// `rpc/<protocol>`, `gossip/<topic kind>` or `discovery`.
+    pub protocol: String,
+    pub direction: TrafficDirection,
+    pub counter: TrafficCounter,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerTraffic {
+    pub peer_id: PeerId,
+    pub sent: TrafficCounter,
+    pub received: TrafficCounter,
+}
+
+impl PeerTraffic {
+    fn total_bytes(&self) -> u64 {
+        self.sent.bytes + self.received.bytes
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrafficStats {
+    /// Traffic of all protocols, ordered by protocol and direction.
+    pub protocols: Vec<ProtocolTraffic>,
+    /// Peers of the most bytes sent and received.
+    pub top_peers: Vec<PeerTraffic>,
+}
+
+#[derive(Default)]
+struct Inner {
+    protocols: HashMap<(String, TrafficDirection), TrafficCounter>,
+    peers: HashMap<PeerId, PeerTraffic>,
+}
+
+/// Traffic of the local node since started, which is shared by the network behaviours.
+#[derive(Default)]
+pub struct NetworkTraffic {
+    inner: Mutex<Inner>,
+}
+
+impl NetworkTraffic {
+    /// Records a message of the protocol, and accounts it to the peer if specified.
+    pub fn record(
+        &self,
+        protocol: &str,
+        direction: TrafficDirection,
+        peer_id: Option<&PeerId>,
+        bytes: usize,
+    ) {
+        metrics::inc_counter_vec(&metrics::NETWORK_MESSAGES, &[protocol, direction.as_str()]);
+        metrics::inc_counter_vec_by(
+            &metrics::NETWORK_MESSAGE_BYTES,
+            &[protocol, direction.as_str()],
+            bytes as u64,
+        );
+
+        let mut inner = self.inner.lock();
+        inner
+            .protocols
+            .entry((protocol.to_string(), direction))
+            .or_default()
+            .add(bytes);
+
+        let peer_id = match peer_id {
+            Some(peer_id) => peer_id,
+            None => return,
+        };
+        if !inner.peers.contains_key(peer_id) && inner.peers.len() >= MAX_TRACKED_PEERS {
+            let least = inner
+                .peers
+                .iter()
+                .min_by_key(|(_, traffic)| traffic.total_bytes())
+                .map(|(peer_id, _)| *peer_id);
+            if let Some(least) = least {
+                inner.peers.remove(&least);
+            }
+        }
+        let traffic = inner.peers.entry(*peer_id).or_insert_with(|| PeerTraffic {
+            peer_id: *peer_id,
+            sent: Default::default(),
+            received: Default::default(),
+        });
+        match direction {
+            TrafficDirection::Inbound => traffic.received.add(bytes),
+            TrafficDirection::Outbound => traffic.sent.add(bytes),
+        }
+    }
+
+    /// Returns the recorder of a protocol, which accounts the messages to `peer_id` if specified.
+    pub fn recorder(
+        self: &Arc<Self>,
+        protocol: String,
+        peer_id: Option<PeerId>,
+    ) -> TrafficRecorder {
+        TrafficRecorder {
+            traffic: self.clone(),
+            protocol,
+            peer_id,
+        }
+    }
+
+    /// Returns the traffic of all protocols, including discovery, and the `top_peers` peers of
+    /// the most bytes.
+    pub fn stats(&self, top_peers: usize) -> TrafficStats {
+        let inner = self.inner.lock();
+        let mut protocols: Vec<ProtocolTraffic> = inner
+            .protocols
+            .iter()
+            .map(|((protocol, direction), counter)| ProtocolTraffic {
+                protocol: protocol.clone(),
+                direction: *direction,
+                counter: *counter,
+            })
+            .collect();
+
+        let discovery = discv5::metrics::Metrics::from(discv5::Discv5::raw_metrics());
+        protocols.push(ProtocolTraffic {
+            protocol: DISCOVERY_PROTOCOL.into(),
+            direction: TrafficDirection::Inbound,
+            counter: TrafficCounter {
+                messages: discovery.packets_recv as u64,
+                bytes: discovery.bytes_recv as u64,
+            },
+        });
+        protocols.push(ProtocolTraffic {
+            protocol: DISCOVERY_PROTOCOL.into(),
+            direction: TrafficDirection::Outbound,
+            counter: TrafficCounter {
+                messages: discovery.packets_sent as u64,
+                bytes: discovery.bytes_sent as u64,
+            },
+        });
+        protocols.sort_by(|a, b| (&a.protocol, a.direction).cmp(&(&b.protocol, b.direction)));
+
+        let mut peers: Vec<PeerTraffic> = inner.peers.values().cloned().collect();
+        peers.sort_by_key(|traffic| std::cmp::Reverse(traffic.total_bytes()));
+        peers.truncate(top_peers);
+
+        TrafficStats {
+            protocols,
+            top_peers: peers,
+        }
+    }
+}
+
+/// Records the messages of a protocol into [`NetworkTraffic`], e.g. by the codec of a substream.
+#[derive(Clone)]
+pub struct TrafficRecorder {
+    traffic: Arc<NetworkTraffic>,
+    protocol: String,
+    peer_id: Option<PeerId>,
+}
+
+impl TrafficRecorder {
+    pub fn record(&self, direction: TrafficDirection, bytes: usize) {
+        self.traffic
+            .record(&self.protocol, direction, self.peer_id.as_ref(), bytes);
+    }
+
+    /// Returns the recorder of another protocol for the same peer.
+    pub fn with_protocol(&self, protocol: String) -> Self {
+        Self {
+            traffic: self.traffic.clone(),
+            protocol,
+            peer_id: self.peer_id,
+        }
+    }
+}
+
+impl std::fmt::Debug for TrafficRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrafficRecorder")
+            .field("protocol", &self.protocol)
+            .field("peer_id", &self.peer_id)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn protocol_counter(
+        stats: &TrafficStats,
+        protocol: &str,
+        direction: TrafficDirection,
+    ) -> TrafficCounter {
+        stats
+            .protocols
+            .iter()
+            .find(|p| p.protocol == protocol && p.direction == direction)
+            .map(|p| p.counter)
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_record() {
+        let traffic = Arc::new(NetworkTraffic::default());
+        let (peer1, peer2) = (PeerId::random(), PeerId::random());
+        let recorder = traffic.recorder("rpc/status".into(), Some(peer1));
+        recorder.record(TrafficDirection::Outbound, 10);
+        recorder.record(TrafficDirection::Inbound, 20);
+        recorder
+            .with_protocol("rpc/get_chunks".into())
+            .record(TrafficDirection::Inbound, 100);
+        traffic.record(
+            "rpc/get_chunks",
+            TrafficDirection::Outbound,
+            Some(&peer2),
+            300,
+        );
+        traffic.record("gossip/new_file", TrafficDirection::Inbound, None, 7);
+
+        let stats = traffic.stats(1);
+        let counter = |protocol, direction| protocol_counter(&stats, protocol, direction);
+        assert_eq!(
+            counter("rpc/status", TrafficDirection::Outbound),
+            TrafficCounter {
+                messages: 1,
+                bytes: 10
+            }
+        );
+        assert_eq!(
+            counter("rpc/status", TrafficDirection::Inbound),
+            TrafficCounter {
+                messages: 1,
+                bytes: 20
+            }
+        );
+        assert_eq!(
+            counter("rpc/get_chunks", TrafficDirection::Inbound),
+            TrafficCounter {
+                messages: 1,
+                bytes: 100
+            }
+        );
+        assert_eq!(
+            counter("gossip/new_file", TrafficDirection::Inbound),
+            TrafficCounter {
+                messages: 1,
+                bytes: 7
+            }
+        );
+
+        // Only the peer of the most bytes.
+        assert_eq!(stats.top_peers.len(), 1);
+        assert_eq!(stats.top_peers[0].peer_id, peer2);
+        assert_eq!(stats.top_peers[0].sent.bytes, 300);
+
+        let stats = traffic.stats(10);
+        assert_eq!(stats.top_peers.len(), 2);
+        assert_eq!(stats.top_peers[1].peer_id, peer1);
+        assert_eq!(
+            stats.top_peers[1].received,
+            TrafficCounter {
+                messages: 2,
+                bytes: 120
+            }
+        );
+    }
+
+    #[test]
+    fn test_max_tracked_peers() {
+        let traffic = NetworkTraffic::default();
+        let heavy = PeerId::random();
+        traffic.record("rpc/ping", TrafficDirection::Inbound, Some(&heavy), 1000);
+        for _ in 0..MAX_TRACKED_PEERS {
+            traffic.record(
+                "rpc/ping",
+                TrafficDirection::Inbound,
+                Some(&PeerId::random()),
+                1,
+            );
+        }
+
+        let stats = traffic.stats(usize::MAX);
+        assert_eq!(stats.top_peers.len(), MAX_TRACKED_PEERS);
+        assert_eq!(stats.top_peers[0].peer_id, heavy);
+    }
+}
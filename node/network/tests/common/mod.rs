@@ -2,12 +2,13 @@
 
 use libp2p::gossipsub::GossipsubConfigBuilder;
 use network::new_network_channel;
+use network::rpc::Version;
 use network::Enr;
 use network::EnrExt;
 use network::Multiaddr;
 use network::Service as LibP2PService;
-use network::{Libp2pEvent, NetworkConfig};
-use std::sync::Weak;
+use network::{Libp2pEvent, NetworkConfig, NetworkGlobals};
+use std::sync::{Arc, Weak};
 use std::time::Duration;
 use tokio::runtime::Runtime;
 use tracing::{debug, error};
@@ -25,7 +26,18 @@ type ReqId = usize;
 use tempfile::Builder as TempBuilder;
 
 #[allow(unused)]
-pub struct Libp2pInstance(LibP2PService<ReqId>, exit_future::Signal);
+pub struct Libp2pInstance(
+    LibP2PService<ReqId>,
+    exit_future::Signal,
+    Arc<NetworkGlobals>,
+);
+
+impl Libp2pInstance {
+    #[allow(dead_code)]
+    pub fn globals(&self) -> &Arc<NetworkGlobals> {
+        &self.2
+    }
+}
 
 impl std::ops::Deref for Libp2pInstance {
     type Target = LibP2PService<ReqId>;
@@ -64,8 +76,17 @@ pub fn build_config(port: u16, mut boot_nodes: Vec<Enr>) -> NetworkConfig {
 }
 
 pub async fn build_libp2p_instance(rt: Weak<Runtime>, boot_nodes: Vec<Enr>) -> Libp2pInstance {
+    build_libp2p_instance_with_version(rt, boot_nodes, Version::LATEST).await
+}
+
+pub async fn build_libp2p_instance_with_version(
+    rt: Weak<Runtime>,
+    boot_nodes: Vec<Enr>,
+    rpc_max_version: Version,
+) -> Libp2pInstance {
     let port = unused_tcp_port().unwrap();
-    let config = build_config(port, boot_nodes);
+    let mut config = build_config(port, boot_nodes);
+    config.rpc_max_version = rpc_max_version;
     // launch libp2p service
 
     let (signal, exit) = exit_future::signal();
@@ -73,13 +94,10 @@ pub async fn build_libp2p_instance(rt: Weak<Runtime>, boot_nodes: Vec<Enr>) -> L
     let executor = task_executor::TaskExecutor::new(rt, exit, shutdown_tx);
     let libp2p_context = network::Context { config: &config };
     let (sender, _) = new_network_channel();
-    Libp2pInstance(
-        LibP2PService::new(executor, sender, libp2p_context)
-            .await
-            .expect("should build libp2p instance")
-            .2,
-        signal,
-    )
+    let (globals, _, service) = LibP2PService::new(executor, sender, libp2p_context)
+        .await
+        .expect("should build libp2p instance");
+    Libp2pInstance(service, signal, globals)
 }
 
 #[allow(dead_code)]
@@ -116,8 +134,18 @@ pub async fn build_full_mesh(rt: Weak<Runtime>, n: usize) -> Vec<Libp2pInstance>
 // This returns a (sender, receiver) pair.
 #[allow(dead_code)]
 pub async fn build_node_pair(rt: Weak<Runtime>) -> (Libp2pInstance, Libp2pInstance) {
-    let mut sender = build_libp2p_instance(rt.clone(), vec![]).await;
-    let mut receiver = build_libp2p_instance(rt, vec![]).await;
+    build_node_pair_with_versions(rt, Version::LATEST, Version::LATEST).await
+}
+
+// Constructs a pair of nodes that negotiate RPC protocols up to the specified versions.
+#[allow(dead_code)]
+pub async fn build_node_pair_with_versions(
+    rt: Weak<Runtime>,
+    sender_version: Version,
+    receiver_version: Version,
+) -> (Libp2pInstance, Libp2pInstance) {
+    let mut sender = build_libp2p_instance_with_version(rt.clone(), vec![], sender_version).await;
+    let mut receiver = build_libp2p_instance_with_version(rt, vec![], receiver_version).await;
 
     let receiver_multiaddr = receiver.swarm.behaviour_mut().local_enr().multiaddr()[1].clone();
 
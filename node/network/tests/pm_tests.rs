@@ -10,7 +10,9 @@ use common::{
 };
 use network::{
     peer_manager::{config::Config, peerdb::PeerDBConfig, PeerManagerEvent},
-    NetworkGlobals, PeerAction, PeerInfo, PeerManager, ReportSource,
+    rpc::GoodbyeReason,
+    NetworkGlobals, PeerAction, PeerId, PeerInfo, PeerManager, PeerPolicy, PeerPolicyConfig,
+    ReportSource,
 };
 
 use futures::StreamExt;
@@ -195,3 +197,134 @@ async fn banned_peers_consistency() {
         }
     }
 }
+
+/// Drives the peer manager and the peers until `until` accepts an event of the peer manager.
+async fn drive_until(
+    pm_service: &mut Service,
+    swarm_pool: &mut swarm::SwarmPool<DummyBehaviour>,
+    mut until: impl FnMut(PeerManagerEvent) -> bool,
+) {
+    let timeout = tokio::time::sleep(tokio::time::Duration::from_secs(30));
+    futures::pin_mut!(timeout);
+
+    loop {
+        tokio::select! {
+            pm_event = pm_service.select_next_some() => {
+                debug!("[PM] {:?}", pm_event);
+                if let SwarmEvent::Behaviour(Ev(event)) = pm_event {
+                    if until(event) {
+                        return;
+                    }
+                }
+            }
+            Some((_peer_id, _peer_ev)) = swarm_pool.next() => {
+                // we need to poll the swarms to keep the peers going
+            }
+            _ = timeout.as_mut() => {
+                panic!("Test timeout.")
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn peer_policy_gate() {
+    let globals: Arc<NetworkGlobals> = Arc::new(NetworkGlobals::new_test_globals());
+
+    // Build the peer manager.
+    let (mut pm_service, pm_addr) = {
+        let pm_config = Config {
+            discovery_enabled: false,
+            ..Default::default()
+        };
+        let pm = PeerManager::new(pm_config, globals.clone()).await.unwrap();
+        let mut pm_swarm = swarm::new_test_swarm(Behaviour::new(pm));
+        let pm_addr = swarm::bind_listener(&mut pm_swarm).await;
+        let service = Service { swarm: pm_swarm };
+        (service, pm_addr)
+    };
+
+    // Build an allowlisted peer, and a peer not on the allowlist.
+    let mut swarm_pool = swarm::SwarmPool::with_capacity(2);
+    let mut peers = Vec::with_capacity(2);
+    for _ in 0..2 {
+        let mut peer_swarm = swarm::new_test_swarm(DummyBehaviour::with_keep_alive(KeepAlive::Yes));
+        let _peer_addr = swarm::bind_listener(&mut peer_swarm).await;
+        peers.push(swarm_pool.insert(peer_swarm));
+    }
+    let (allowed, rejected) = (peers[0], peers[1]);
+    let set_policy = |allowlist: Vec<PeerId>, denylist: Vec<PeerId>| {
+        *globals.peer_policy.write() = PeerPolicy::from_config(&PeerPolicyConfig {
+            allowlist: allowlist
+                .iter()
+                .map(|peer_id| peer_id.to_base58())
+                .collect(),
+            denylist: denylist.iter().map(|peer_id| peer_id.to_base58()).collect(),
+        })
+        .unwrap();
+    };
+    let dial = |swarm_pool: &mut swarm::SwarmPool<DummyBehaviour>, peer_id: &PeerId| {
+        swarm_pool
+            .get_mut(peer_id)
+            .unwrap()
+            .dial(pm_addr.clone())
+            .unwrap();
+    };
+
+    // In private network mode, the peer not on the allowlist is rejected at the handshake.
+    set_policy(vec![allowed], vec![]);
+    dial(&mut swarm_pool, &allowed);
+    dial(&mut swarm_pool, &rejected);
+    let (mut allowed_connected, mut rejected_disconnected) = (false, false);
+    drive_until(&mut pm_service, &mut swarm_pool, |event| {
+        match event {
+            PeerManagerEvent::PeerConnectedIncoming(peer_id) => {
+                assert_eq!(peer_id, allowed);
+                allowed_connected = true;
+            }
+            PeerManagerEvent::DisconnectPeer(peer_id, reason) => {
+                assert_eq!(peer_id, rejected);
+                assert_eq!(reason, GoodbyeReason::Banned);
+                rejected_disconnected = true;
+            }
+            PeerManagerEvent::Banned(peer_id, _) => panic!("peer {} banned", peer_id),
+            _ => {}
+        }
+        allowed_connected && rejected_disconnected
+    })
+    .await;
+    assert!(globals.peers.read().is_connected(&allowed));
+    assert!(!globals.peers.read().is_connected(&rejected));
+
+    // Deny the allowlisted peer at runtime, which is rejected by the next connection, while
+    // the peer rejected before is not banned, and connects once out of private network mode.
+    set_policy(vec![], vec![allowed]);
+    dial(&mut swarm_pool, &rejected);
+    drive_until(&mut pm_service, &mut swarm_pool, |event| match event {
+        PeerManagerEvent::PeerConnectedIncoming(peer_id) => {
+            assert_eq!(peer_id, rejected);
+            true
+        }
+        PeerManagerEvent::DisconnectPeer(peer_id, _) => panic!("peer {} disconnected", peer_id),
+        _ => false,
+    })
+    .await;
+    dial(&mut swarm_pool, &allowed);
+    let mut allowed_rejected = false;
+    drive_until(&mut pm_service, &mut swarm_pool, |event| match event {
+        PeerManagerEvent::DisconnectPeer(peer_id, reason) => {
+            assert_eq!(peer_id, allowed);
+            assert_eq!(reason, GoodbyeReason::Banned);
+            allowed_rejected = true;
+            false
+        }
+        PeerManagerEvent::PeerDisconnected(peer_id) => {
+            assert_eq!(peer_id, allowed);
+            allowed_rejected
+        }
+        _ => false,
+    })
+    .await;
+    assert!(!globals.peers.read().is_connected(&allowed));
+    assert!(globals.peers.read().is_connected(&rejected));
+}
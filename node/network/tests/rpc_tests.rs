@@ -1,6 +1,7 @@
 #![cfg(test)]
 use network::rpc::methods::*;
-use network::{BehaviourEvent, Libp2pEvent, ReportSource, Request, Response};
+use network::rpc::{Protocol, Version};
+use network::{BehaviourEvent, Libp2pEvent, ReportSource, Request, Response, TrafficDirection};
 use ssz_types::VariableList;
 use std::sync::Arc;
 use std::time::Duration;
@@ -90,6 +91,87 @@ fn test_status_rpc() {
     })
 }
 
+// Tests the messages and bytes of a STATUS RPC accounted on both nodes
+#[test]
+#[traced_test]
+fn test_traffic_accounting() {
+    let rt = Arc::new(Runtime::new().unwrap());
+
+    rt.block_on(async {
+        let (mut sender, mut receiver) = common::build_node_pair(Arc::downgrade(&rt)).await;
+
+        let rpc_request = Request::Status(Default::default());
+        let rpc_response = Response::Status(Default::default());
+
+        let timeout = sleep(Duration::from_secs(30));
+        tokio::pin!(timeout);
+
+        loop {
+            tokio::select! {
+                event = sender.next_event() => match event {
+                    Libp2pEvent::Behaviour(BehaviourEvent::PeerConnectedOutgoing(peer_id)) => {
+                        sender
+                            .swarm
+                            .behaviour_mut()
+                            .send_request(peer_id, 10, rpc_request.clone());
+                    }
+                    Libp2pEvent::Behaviour(BehaviourEvent::ResponseReceived { id: 10, response, .. }) => {
+                        assert_eq!(response, rpc_response);
+                        break;
+                    }
+                    _ => {}
+                },
+                event = receiver.next_event() => {
+                    if let Libp2pEvent::Behaviour(BehaviourEvent::RequestReceived { peer_id, id, request }) = event {
+                        assert_eq!(request, rpc_request);
+                        receiver.swarm.behaviour_mut().send_successful_response(
+                            peer_id,
+                            id,
+                            rpc_response.clone(),
+                        );
+                    }
+                }
+                _ = &mut timeout => {
+                    panic!("Future timed out");
+                }
+            }
+        }
+
+        let status = |node: &common::Libp2pInstance, direction| {
+            let stats = node.globals().traffic.stats(10);
+            stats
+                .protocols
+                .into_iter()
+                .find(|p| p.protocol == "rpc/status" && p.direction == direction)
+                .map(|p| p.counter)
+                .unwrap_or_default()
+        };
+        let sent_request = status(&sender, TrafficDirection::Outbound);
+        let received_request = status(&receiver, TrafficDirection::Inbound);
+        let sent_response = status(&receiver, TrafficDirection::Outbound);
+        let received_response = status(&sender, TrafficDirection::Inbound);
+
+        for counter in [sent_request, received_request, sent_response, received_response] {
+            assert_eq!(counter.messages, 1);
+            assert!(counter.bytes > 0);
+        }
+        assert_eq!(sent_request.bytes, received_request.bytes);
+        assert_eq!(sent_response.bytes, received_response.bytes);
+
+        // Accounted to the remote peer on both nodes.
+        let has_peer = |node: &common::Libp2pInstance, peer_id| {
+            node.globals()
+                .traffic
+                .stats(10)
+                .top_peers
+                .iter()
+                .any(|p| p.peer_id == peer_id)
+        };
+        assert!(has_peer(&sender, receiver.globals().local_peer_id()));
+        assert!(has_peer(&receiver, sender.globals().local_peer_id()));
+    })
+}
+
 // Tests a streamed DataByHash RPC Message
 #[test]
 #[traced_test]
@@ -391,3 +473,81 @@ fn test_goodbye_rpc() {
         }
     })
 }
+
+/// Sends a `GetChunks` request between nodes of different max RPC versions, and waits for both
+/// nodes to negotiate the highest mutual version.
+async fn get_chunks_with_versions(
+    rt: Arc<Runtime>,
+    sender_version: Version,
+    receiver_version: Version,
+) {
+    let (mut sender, mut receiver) = common::build_node_pair_with_versions(
+        Arc::downgrade(&rt),
+        sender_version,
+        receiver_version,
+    )
+    .await;
+
+    let rpc_request = Request::GetChunks(GetChunksRequest {
+        tx_id: Default::default(),
+        index_start: 0,
+        index_end: 1024,
+        merkle_tx_seq: 0,
+    });
+    let expected_version = sender_version.min(receiver_version);
+    let negotiated = |node: &common::Libp2pInstance| {
+        let peers = node.globals().peers.read();
+        let mut peers = peers.connected_peers();
+        matches!(
+            peers.next(),
+            Some((_, info)) if info.rpc_version(Protocol::GetChunks) == Some(expected_version)
+        )
+    };
+
+    let mut received = false;
+    let timeout = sleep(Duration::from_secs(30));
+    tokio::pin!(timeout);
+
+    while !(received && negotiated(&sender) && negotiated(&receiver)) {
+        tokio::select! {
+            event = sender.next_event() => {
+                if let Libp2pEvent::Behaviour(BehaviourEvent::PeerConnectedOutgoing(peer_id)) = event {
+                    debug!("Sending RPC");
+                    sender
+                        .swarm
+                        .behaviour_mut()
+                        .send_request(peer_id, 10, rpc_request.clone());
+                }
+            }
+            event = receiver.next_event() => {
+                if let Libp2pEvent::Behaviour(BehaviourEvent::RequestReceived { request, .. }) = event {
+                    debug!("Receiver Received");
+                    assert_eq!(request, rpc_request);
+                    received = true;
+                }
+            }
+            _ = &mut timeout => {
+                panic!("Future timed out");
+            }
+        }
+    }
+}
+
+// Tests the GetChunks RPC message between nodes of older and newer versions in both directions
+#[test]
+#[traced_test]
+fn test_get_chunks_rpc_version_compat() {
+    let rt = Arc::new(Runtime::new().unwrap());
+
+    rt.block_on(async {
+        get_chunks_with_versions(rt.clone(), Version::V2, Version::V1).await;
+        get_chunks_with_versions(rt.clone(), Version::V1, Version::V2).await;
+        get_chunks_with_versions(rt.clone(), Version::V2, Version::V2).await;
+        get_chunks_with_versions(rt.clone(), Version::V3, Version::V2).await;
+        get_chunks_with_versions(rt.clone(), Version::V1, Version::V3).await;
+        get_chunks_with_versions(rt.clone(), Version::V3, Version::V3).await;
+        get_chunks_with_versions(rt.clone(), Version::V4, Version::V3).await;
+        get_chunks_with_versions(rt.clone(), Version::V1, Version::V4).await;
+        get_chunks_with_versions(rt.clone(), Version::V4, Version::V4).await;
+    })
+}
@@ -3,6 +3,7 @@ use contract_interface::ChunkLinearReward;
 use ethereum_types::Address;
 use ethers::prelude::{Http, Provider};
 use ethers::providers::{HttpRateLimitRetryPolicy, RetryClient, RetryClientBuilder};
+use log_entry_sync::{ContractEvent, LogSyncEvent};
 use miner::MinerMessage;
 use rand::Rng;
 use std::cmp::Ordering;
@@ -13,9 +14,11 @@ use std::time::Duration;
 use storage::config::{ShardConfig, SHARD_CONFIG_KEY};
 use storage::log_store::log_manager::{DATA_DB_KEY, PORA_CHUNK_SIZE};
 use storage_async::Store;
+use task_executor::shutdown::ShutdownToken;
 use task_executor::TaskExecutor;
-use tokio::sync::{broadcast, mpsc};
-use tracing::{debug, error, info};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, watch};
+use tracing::{debug, error, info, warn};
 use zgs_spec::SECTORS_PER_PRICING;
 
 // Start pruning when the db directory size exceeds 0.9 * limit.
@@ -46,10 +49,27 @@ impl PrunerConfig {
     fn start_prune_size(&self) -> u64 {
         (self.max_num_sectors as f32 * PRUNE_THRESHOLD) as u64
     }
+
+    pub fn dynamic(&self) -> PrunerDynamicConfig {
+        PrunerDynamicConfig {
+            max_num_sectors: self.max_num_sectors,
+            batch_size: self.batch_size,
+            batch_wait_time: self.batch_wait_time,
+        }
+    }
+}
+
+/// Prune budget that could be reloaded at runtime without restarting the node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PrunerDynamicConfig {
+    pub max_num_sectors: usize,
+    pub batch_size: usize,
+    pub batch_wait_time: Duration,
 }
 
 pub struct Pruner {
     config: PrunerConfig,
+    config_recv: watch::Receiver<PrunerDynamicConfig>,
     first_rewardable_chunk: u64,
     first_tx_seq: u64,
 
@@ -59,6 +79,9 @@ pub struct Pruner {
     miner_sender: Option<broadcast::Sender<MinerMessage>>,
 
     reward_contract: ChunkLinearReward<Arc<Provider<RetryClient<Http>>>>,
+    /// Rewards distributed by the reward contract, once synced, trigger to check the first
+    /// rewardable chunk without waiting for the check interval.
+    log_sync_recv: broadcast::Receiver<LogSyncEvent>,
 }
 
 impl Pruner {
@@ -67,6 +90,9 @@ impl Pruner {
         mut config: PrunerConfig,
         store: Arc<Store>,
         miner_sender: Option<broadcast::Sender<MinerMessage>>,
+        config_recv: watch::Receiver<PrunerDynamicConfig>,
+        log_sync_recv: broadcast::Receiver<LogSyncEvent>,
+        shutdown: ShutdownToken,
     ) -> Result<mpsc::UnboundedReceiver<PrunerMessage>> {
         if let Some(shard_config) = get_shard_config(store.as_ref()).await? {
             config.shard_config = shard_config;
@@ -89,25 +115,44 @@ impl Pruner {
         let (tx, rx) = mpsc::unbounded_channel();
         let pruner = Pruner {
             config,
+            config_recv,
             first_rewardable_chunk,
             first_tx_seq,
             store,
             sender: tx,
             miner_sender,
             reward_contract,
+            log_sync_recv,
         };
         pruner.put_shard_config().await?;
         executor.spawn(
             async move {
-                pruner.start().await.expect("pruner error");
+                pruner.start(shutdown).await.expect("pruner error");
             },
             "pruner",
         );
         Ok(rx)
     }
 
-    pub async fn start(mut self) -> Result<()> {
+    /// Prunes data periodically until the node is shutting down, which is only checked between
+    /// rounds so that the pruning progress is always persisted.
+    pub async fn start(mut self, mut shutdown: ShutdownToken) -> Result<()> {
+        // The roots pruned before compacted on prune, which is skipped once completed.
+        let compacted = self.store.compact_pruned_data_roots().await?;
+        if compacted > 0 {
+            info!(compacted, "data root index compacted");
+        }
+
         loop {
+            // The prune budget reloaded takes effect from the next round.
+            if self.config_recv.has_changed().unwrap_or(false) {
+                let dynamic = *self.config_recv.borrow_and_update();
+                info!(?dynamic, "prune budget reloaded");
+                self.config.max_num_sectors = dynamic.max_num_sectors;
+                self.config.batch_size = dynamic.batch_size;
+                self.config.batch_wait_time = dynamic.batch_wait_time;
+            }
+
             // Check shard config update and prune unneeded data.
             if let Some(delete_list) = self.maybe_update().await? {
                 info!(new_config = ?self.config.shard_config, "new shard config");
@@ -145,7 +190,18 @@ impl Pruner {
                     error!("handle reward contract read fails, e={:?}", e);
                 }
             };
-            tokio::time::sleep(self.config.check_time).await;
+            let reward_address = self.config.reward_address;
+            tokio::select! {
+                _ = tokio::time::sleep(self.config.check_time) => {}
+                _ = wait_reward_distributed(&mut self.log_sync_recv, reward_address) => {
+                    debug!("reward distributed, check the first rewardable chunk");
+                }
+                _ = shutdown.requested() => {
+                    info!("pruner stopped for shutdown");
+                    shutdown.ack();
+                    return Ok(());
+                }
+            }
         }
     }
 
@@ -282,6 +338,36 @@ async fn get_shard_config(store: &Store) -> Result<Option<ShardConfig>> {
         .await
 }
 
+/// Waits until any reward of the reward contract is distributed, which never returns once log
+/// sync stops.
+async fn wait_reward_distributed(
+    log_sync_recv: &mut broadcast::Receiver<LogSyncEvent>,
+    reward_address: Address,
+) {
+    loop {
+        match log_sync_recv.recv().await {
+            Ok(event) if is_reward_distributed(&event, reward_address) => return,
+            Ok(_) => {}
+            Err(RecvError::Lagged(n)) => {
+                warn!(%n, "pruner lagged behind log sync events");
+                return;
+            }
+            Err(RecvError::Closed) => std::future::pending().await,
+        }
+    }
+}
+
+fn is_reward_distributed(event: &LogSyncEvent, reward_address: Address) -> bool {
+    matches!(
+        event,
+        LogSyncEvent::ContractEventSynced { log }
+            if matches!(
+                log.event,
+                ContractEvent::DistributeReward { contract, .. } if contract == reward_address
+            )
+    )
+}
+
 async fn get_first_rewardable_chunk(store: &Store) -> Result<Option<(u64, u64)>> {
     store
         .get_config_decoded(&FIRST_REWARDABLE_CHUNK_KEY, DATA_DB_KEY)
@@ -292,3 +378,49 @@ async fn get_first_rewardable_chunk(store: &Store) -> Result<Option<(u64, u64)>>
 pub enum PrunerMessage {
     ChangeShardConfig(ShardConfig),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log_entry_sync::ContractLog;
+
+    fn reward_event(contract: Address) -> LogSyncEvent {
+        LogSyncEvent::ContractEventSynced {
+            log: ContractLog {
+                event: ContractEvent::DistributeReward {
+                    contract,
+                    pricing_index: 1.into(),
+                    beneficiary: Address::from_low_u64_be(2),
+                    amount: 100.into(),
+                },
+                block_number: 10,
+                log_index: 0,
+                tx_hash: Default::default(),
+            },
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_wait_reward_distributed() {
+        let reward_address = Address::from_low_u64_be(1);
+        let (send, mut recv) = broadcast::channel(16);
+        send.send(LogSyncEvent::BlocksReverted { block_number: 9 })
+            .unwrap();
+        send.send(reward_event(Address::from_low_u64_be(3)))
+            .unwrap();
+        send.send(reward_event(reward_address)).unwrap();
+        wait_reward_distributed(&mut recv, reward_address).await;
+        assert!(matches!(
+            recv.try_recv(),
+            Err(broadcast::error::TryRecvError::Empty)
+        ));
+
+        // Never returns once log sync stops.
+        drop(send);
+        let waited = tokio::time::timeout(
+            Duration::from_secs(1),
+            wait_reward_distributed(&mut recv, reward_address),
+        );
+        assert!(waited.await.is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use shared_types::TxID;
+use std::collections::BTreeMap;
+use storage::log_store::Store as LogStore;
+
+/// Maximum number of finalized files waiting for confirmations, while the others could still be
+/// found via `FindFile` once confirmed.
+const MAX_PENDING_FILES: usize = 4096;
+
+/// Maximum number of the latest announced files to retract if reverted.
+const MAX_ANNOUNCED_FILES: usize = 4096;
+
+/// `AnnounceGate` defers the `NewFile` announcements of finalized files until their txs are
+/// confirmed by enough blocks in the log sync progress, so that peers are unlikely to queue
+/// syncs of files reorged away on chain.
+///
+/// The latest announced files are tracked, so that they could be retracted once the txs are
+/// reverted by a deeper reorg.
+pub(crate) struct AnnounceGate {
+    /// Files to announce once confirmed, by tx seq.
+    pending: BTreeMap<u64, TxID>,
+    /// Files announced recently, by tx seq.
+    announced: BTreeMap<u64, TxID>,
+}
+
+impl AnnounceGate {
+    pub fn new() -> Self {
+        Self {
+            pending: Default::default(),
+            announced: Default::default(),
+        }
+    }
+
+    /// Returns the files to announce at once, and defers the unconfirmed ones.
+    pub fn admit<F>(&mut self, tx_ids: Vec<TxID>, is_confirmed: F) -> Vec<TxID>
+    where
+        F: Fn(&TxID) -> bool,
+    {
+        let mut confirmed = vec![];
+        for tx_id in tx_ids {
+            if self.pending.is_empty() && is_confirmed(&tx_id) {
+                confirmed.push(tx_id);
+            } else if self.pending.len() < MAX_PENDING_FILES {
+                self.pending.insert(tx_id.seq, tx_id);
+            }
+        }
+
+        // files deferred before are announced first
+        let mut tx_ids = self.release(is_confirmed);
+        tx_ids.extend(confirmed);
+        self.mark_announced(&tx_ids);
+        tx_ids
+    }
+
+    /// Returns the deferred files that are confirmed now.
+    pub fn expire<F>(&mut self, is_confirmed: F) -> Vec<TxID>
+    where
+        F: Fn(&TxID) -> bool,
+    {
+        let tx_ids = self.release(is_confirmed);
+        self.mark_announced(&tx_ids);
+        tx_ids
+    }
+
+    /// Drops the deferred files reverted from `tx_seq`, and returns the announced ones to
+    /// retract.
+    pub fn revert(&mut self, tx_seq: u64) -> Vec<TxID> {
+        self.pending.split_off(&tx_seq);
+        self.announced.split_off(&tx_seq).into_values().collect()
+    }
+
+    /// Releases the deferred files in order of tx seq, until any file unconfirmed, since txs
+    /// of larger seq are never confirmed earlier.
+    fn release<F>(&mut self, is_confirmed: F) -> Vec<TxID>
+    where
+        F: Fn(&TxID) -> bool,
+    {
+        let mut tx_ids = vec![];
+        while let Some(entry) = self.pending.first_entry() {
+            if !is_confirmed(entry.get()) {
+                break;
+            }
+
+            tx_ids.push(entry.remove());
+        }
+        tx_ids
+    }
+
+    fn mark_announced(&mut self, tx_ids: &[TxID]) {
+        for tx_id in tx_ids {
+            self.announced.insert(tx_id.seq, *tx_id);
+        }
+
+        while self.announced.len() > MAX_ANNOUNCED_FILES {
+            self.announced.pop_first();
+        }
+    }
+}
+
+/// Whether the tx is confirmed by `depth` blocks in the log sync progress. Txs without the
+/// submission context, e.g. not synced from chain, are regarded as confirmed.
+pub(crate) fn is_tx_confirmed(store: &dyn LogStore, tx_seq: u64, depth: u64) -> bool {
+    if depth == 0 {
+        return true;
+    }
+
+    let synced_block = match store.get_sync_progress() {
+        Ok(Some((block_number, _))) => block_number,
+        Ok(None) => return false,
+        Err(e) => {
+            warn!(?e, "Failed to get log sync progress");
+            return false;
+        }
+    };
+
+    match store.get_submission_context(tx_seq) {
+        Ok(Some(context)) => context.block_number.saturating_add(depth) <= synced_block,
+        Ok(None) => true,
+        Err(e) => {
+            warn!(%tx_seq, ?e, "Failed to get submission context");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx_ids(num: u64) -> Vec<TxID> {
+        (0..num).map(TxID::random_hash).collect()
+    }
+
+    #[test]
+    fn test_announce_confirmed() {
+        let ids = tx_ids(2);
+        let mut gate = AnnounceGate::new();
+        assert_eq!(gate.admit(ids.clone(), |_| true), ids);
+        assert!(gate.expire(|_| true).is_empty());
+    }
+
+    #[test]
+    fn test_defer_unconfirmed() {
+        let ids = tx_ids(6);
+        let mut gate = AnnounceGate::new();
+        let tx_ids = gate.admit(ids[..3].to_vec(), |id| id.seq <= 1);
+        assert_eq!(tx_ids, ids[..2]);
+
+        // announced in order once confirmed
+        let tx_ids = gate.admit(vec![ids[3]], |id| id.seq <= 3);
+        assert_eq!(tx_ids, ids[2..4]);
+
+        gate.admit(ids[4..].to_vec(), |_| false);
+        assert!(gate.expire(|_| false).is_empty());
+        assert_eq!(gate.expire(|_| true), ids[4..]);
+    }
+
+    #[test]
+    fn test_revert() {
+        let ids = tx_ids(3);
+        let mut gate = AnnounceGate::new();
+        gate.admit(ids.clone(), |id| id.seq <= 1);
+
+        // only the announced files are retracted
+        assert_eq!(gate.revert(1), vec![ids[1]]);
+
+        // reverted files are never announced
+        assert!(gate.expire(|_| true).is_empty());
+        assert_eq!(gate.revert(0), vec![ids[0]]);
+    }
+}
@@ -0,0 +1,403 @@
+use rand::Rng;
+use shared_types::TxID;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use storage::log_store::config::ConfigurableExt;
+use storage::log_store::log_manager::DATA_DB_KEY;
+use storage::log_store::tx_store::TxStatus;
+use storage::log_store::Store;
+
+/// DB key of the next tx seq to scan by the drip announcer.
+const DRIP_ANNOUNCE_CURSOR_KEY: &str = "router.drip_announce_cursor";
+/// DB key of the tx seq to scan until by the drip announcer.
+const DRIP_ANNOUNCE_END_KEY: &str = "router.drip_announce_end";
+
+/// Base interval to announce the next files, along with a random jitter.
+pub(crate) const DRIP_ANNOUNCE_TICK: Duration = Duration::from_secs(1);
+
+/// Maximum number of txs read from store at once.
+const MAX_TXS_PER_READ: usize = 256;
+
+/// Maximum number of reads in a tick, so that the router is not blocked by a long range of
+/// unfinalized txs.
+const MAX_READS_PER_TICK: usize = 16;
+
+/// `DripAnnouncer` announces the locally finalized files at a bounded rate, e.g. the tens of
+/// thousands of files backfilled by a freshly synced node, which would flood the gossip mesh if
+/// announced at once.
+///
+/// Only the txs left to the drip announcer are scanned, i.e. the txs finalized in bursts, while
+/// the files finalized one by one are announced at once and never scanned again. Finalized txs
+/// are scanned in ascending order of seq by a cursor persisted in db, so that a restart neither
+/// starts over nor skips files. Once the scan reaches the end, it starts over from the first tx
+/// after the re-announce interval if enabled, so that all files are re-announced at the same
+/// rate.
+pub(crate) struct DripAnnouncer {
+    store: Arc<dyn Store>,
+    /// Files announced per second, or 0 to disable.
+    rate: u64,
+    jitter: Duration,
+    reannounce_interval: Duration,
+    /// Next tx seq to scan.
+    cursor: u64,
+    /// Tx seq to scan until, i.e. the end of the txs left to the drip announcer.
+    end: u64,
+    /// Number of files allowed to announce, which is accumulated at `rate` and bounded by a
+    /// tick, so that no burst follows an idle period.
+    allowance: f64,
+    last_tick: Instant,
+    next_tick: Instant,
+    /// When to start over the scan once the last tx is scanned.
+    next_pass: Option<Instant>,
+}
+
+impl DripAnnouncer {
+    pub fn new(
+        store: Arc<dyn Store>,
+        rate: u64,
+        jitter: Duration,
+        reannounce_interval: Duration,
+    ) -> Self {
+        Self::new_with_time(store, rate, jitter, reannounce_interval, Instant::now())
+    }
+
+    fn new_with_time(
+        store: Arc<dyn Store>,
+        rate: u64,
+        jitter: Duration,
+        reannounce_interval: Duration,
+        now: Instant,
+    ) -> Self {
+        // Files finalized before the drip announcer introduced are announced once finalized.
+        let next_tx_seq = store.next_tx_seq();
+        let (cursor, end) = match (
+            store.get_config_decoded(&DRIP_ANNOUNCE_CURSOR_KEY, DATA_DB_KEY),
+            store.get_config_decoded(&DRIP_ANNOUNCE_END_KEY, DATA_DB_KEY),
+        ) {
+            (Ok(Some(cursor)), Ok(end)) => (cursor, end.unwrap_or(next_tx_seq)),
+            (Ok(None), Ok(_)) => (next_tx_seq, next_tx_seq),
+            (Err(e), _) | (_, Err(e)) => {
+                warn!(error = ?e, "Failed to load the cursor of drip announcer");
+                (next_tx_seq, next_tx_seq)
+            }
+        };
+
+        let announcer = Self {
+            store,
+            rate,
+            jitter,
+            reannounce_interval,
+            cursor,
+            end,
+            allowance: 0.0,
+            last_tick: now,
+            next_tick: now,
+            next_pass: None,
+        };
+        announcer.save_cursor();
+        announcer
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.rate > 0
+    }
+
+    /// Changes the rate, e.g. on config reload. The scan resumes from the cursor if enabled
+    /// again.
+    pub fn set_rate(&mut self, rate: u64) {
+        self.rate = rate;
+        self.allowance = self.allowance.min(rate as f64);
+    }
+
+    /// Returns when to announce the next files.
+    pub fn next_tick(&self) -> Instant {
+        self.next_tick
+    }
+
+    /// Extends the scan to the txs `[start_seq, end_seq]` finalized in a burst, which are left
+    /// to the drip announcer rather than announced at once.
+    pub fn on_finalized_range(&mut self, start_seq: u64, end_seq: u64) {
+        if self.cursor >= self.end {
+            self.cursor = start_seq;
+            self.end = end_seq + 1;
+        } else {
+            self.cursor = self.cursor.min(start_seq);
+            self.end = self.end.max(end_seq + 1);
+        }
+        self.save_cursor();
+        self.next_pass = None;
+    }
+
+    /// Returns the files to announce within the rate limit.
+    pub fn tick(&mut self) -> Vec<TxID> {
+        self.tick_with_time(Instant::now())
+    }
+
+    fn tick_with_time(&mut self, now: Instant) -> Vec<TxID> {
+        if !self.enabled() || now < self.next_tick {
+            return vec![];
+        }
+
+        let elapsed = now.duration_since(self.last_tick);
+        self.last_tick = now;
+        let jitter = match self.jitter.as_millis() as u64 {
+            0 => Duration::ZERO,
+            max => Duration::from_millis(rand::thread_rng().gen_range(0..=max)),
+        };
+        self.next_tick = now + DRIP_ANNOUNCE_TICK + jitter;
+
+        if let Some(next_pass) = self.next_pass {
+            if now < next_pass {
+                return vec![];
+            }
+            info!("Start over announcing the finalized files");
+            self.next_pass = None;
+            self.cursor = 0;
+            self.end = self.store.next_tx_seq();
+        }
+
+        let max_allowance = self.rate as f64 * (DRIP_ANNOUNCE_TICK + self.jitter).as_secs_f64();
+        self.allowance =
+            (self.allowance + self.rate as f64 * elapsed.as_secs_f64()).min(max_allowance);
+        let limit = self.allowance as usize;
+        if limit == 0 {
+            return vec![];
+        }
+
+        let start_seq = self.cursor;
+        // txs may be reverted after left to the drip announcer
+        let end = self.end.min(self.store.next_tx_seq());
+        let mut tx_ids = vec![];
+        for _ in 0..MAX_READS_PER_TICK {
+            if tx_ids.len() >= limit || self.cursor >= end {
+                break;
+            }
+
+            let max_txs = MAX_TXS_PER_READ.min((end - self.cursor) as usize);
+            let txs = match self.store.get_txs_with_status(self.cursor, max_txs) {
+                Ok(txs) if !txs.is_empty() => txs,
+                Ok(_) => break,
+                Err(e) => {
+                    warn!(cursor = %self.cursor, error = ?e, "Failed to read txs to announce");
+                    break;
+                }
+            };
+            for (tx, status) in txs {
+                if tx_ids.len() >= limit {
+                    break;
+                }
+                self.cursor = tx.seq + 1;
+                if matches!(status, Some(TxStatus::Finalized | TxStatus::ShardFinalized)) {
+                    tx_ids.push(tx.id());
+                }
+            }
+        }
+        self.allowance -= tx_ids.len() as f64;
+
+        if self.cursor >= end {
+            // Later files are announced once finalized.
+            self.cursor = end;
+            self.end = end;
+            self.allowance = 0.0;
+            if !self.reannounce_interval.is_zero() {
+                debug!(%end, "Announced all the finalized files");
+                self.next_pass = Some(now + self.reannounce_interval);
+            }
+        }
+        if self.cursor != start_seq {
+            self.save_cursor();
+        }
+
+        tx_ids
+    }
+
+    fn save_cursor(&self) {
+        if let Err(e) = self
+            .store
+            .set_config_encoded(&DRIP_ANNOUNCE_CURSOR_KEY, &self.cursor, DATA_DB_KEY)
+            .and_then(|_| {
+                self.store
+                    .set_config_encoded(&DRIP_ANNOUNCE_END_KEY, &self.end, DATA_DB_KEY)
+            })
+        {
+            warn!(error = ?e, "Failed to save the cursor of drip announcer");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_types::{ChunkArray, TransactionBuilder, CHUNK_SIZE};
+    use storage::log_store::log_manager::{
+        sub_merkle_tree, tx_subtree_root_list_padded, LogConfig,
+    };
+    use storage::log_store::{LogStoreChunkWrite, LogStoreWrite};
+    use storage::LogManager;
+
+    const NUM_FILES: u64 = 10_000;
+    const RATE: u64 = 100;
+
+    /// Creates a store with single-chunk files, in which every 10th file is not finalized.
+    fn new_store() -> Arc<dyn Store> {
+        let store = LogManager::memorydb(LogConfig::default()).unwrap();
+        for seq in 0..NUM_FILES {
+            let mut data = vec![0u8; CHUNK_SIZE];
+            data[..8].copy_from_slice(&seq.to_be_bytes());
+            let tx = TransactionBuilder::new(seq)
+                .size(CHUNK_SIZE as u64)
+                .data_merkle_root(sub_merkle_tree(&data).unwrap().root().into())
+                .start_entry_index(seq + 1)
+                .merkle_nodes(tx_subtree_root_list_padded(&data))
+                .build()
+                .unwrap();
+            store.put_tx(tx).unwrap();
+            if seq % 10 != 0 {
+                store
+                    .put_chunks(
+                        seq,
+                        ChunkArray {
+                            data,
+                            start_index: 0,
+                        },
+                    )
+                    .unwrap();
+                store.finalize_tx(seq).unwrap();
+            }
+        }
+        Arc::new(store)
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let store = new_store();
+        let jitter = Duration::from_millis(500);
+        let start = Instant::now();
+        let mut announcer =
+            DripAnnouncer::new_with_time(store.clone(), RATE, jitter, Duration::ZERO, start);
+        announcer.on_finalized_range(0, NUM_FILES - 1);
+        let burst = RATE as f64 * (DRIP_ANNOUNCE_TICK + jitter).as_secs_f64();
+
+        let mut announced = vec![];
+        let mut now = start;
+        while (announced.len() as u64) < NUM_FILES * 9 / 10 {
+            now += Duration::from_millis(100);
+            announced.extend(announcer.tick_with_time(now));
+            let allowed = RATE as f64 * now.duration_since(start).as_secs_f64() + burst;
+            assert!(announced.len() as f64 <= allowed);
+            assert!(now.duration_since(start) < Duration::from_secs(200));
+        }
+
+        // all the finalized files are announced once in order
+        let seqs: Vec<u64> = announced.iter().map(|tx_id| tx_id.seq).collect();
+        let expected: Vec<u64> = (0..NUM_FILES).filter(|seq| seq % 10 != 0).collect();
+        assert_eq!(seqs, expected);
+        assert!(announcer
+            .tick_with_time(now + Duration::from_secs(10))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_resume_after_restart() {
+        let store = new_store();
+        let start = Instant::now();
+        let mut announcer = DripAnnouncer::new_with_time(
+            store.clone(),
+            RATE,
+            Duration::ZERO,
+            Duration::ZERO,
+            start,
+        );
+        announcer.on_finalized_range(0, NUM_FILES - 1);
+        let first = announcer.tick_with_time(start + Duration::from_secs(1));
+        assert_eq!(first.len() as u64, RATE);
+
+        // resumed from the persisted cursor
+        let mut announcer = DripAnnouncer::new_with_time(
+            store.clone(),
+            RATE,
+            Duration::ZERO,
+            Duration::ZERO,
+            start,
+        );
+        let second = announcer.tick_with_time(start + Duration::from_secs(1));
+        assert_eq!(second.len() as u64, RATE);
+        assert!(second[0].seq > first.last().unwrap().seq);
+
+        // rewound to the files finalized in a burst
+        announcer.on_finalized_range(5, 5);
+        let third = announcer.tick_with_time(start + Duration::from_secs(2));
+        assert_eq!(third[0].seq, 5);
+    }
+
+    #[test]
+    fn test_skip_files_announced_live() {
+        let store = new_store();
+        let start = Instant::now();
+        let mut announcer =
+            DripAnnouncer::new_with_time(store, RATE, Duration::ZERO, Duration::ZERO, start);
+
+        // files finalized before started are never scanned
+        assert!(announcer
+            .tick_with_time(start + Duration::from_secs(1))
+            .is_empty());
+
+        // only the files finalized in a burst are scanned
+        announcer.on_finalized_range(100, 199);
+        let mut now = start + Duration::from_secs(1);
+        let mut announced = vec![];
+        for _ in 0..10 {
+            now += DRIP_ANNOUNCE_TICK;
+            announced.extend(announcer.tick_with_time(now));
+        }
+        let seqs: Vec<u64> = announced.iter().map(|tx_id| tx_id.seq).collect();
+        let expected: Vec<u64> = (100..200).filter(|seq| seq % 10 != 0).collect();
+        assert_eq!(seqs, expected);
+    }
+
+    #[test]
+    fn test_set_rate() {
+        let store = new_store();
+        let start = Instant::now();
+        let mut announcer =
+            DripAnnouncer::new_with_time(store, 0, Duration::ZERO, Duration::ZERO, start);
+        announcer.on_finalized_range(0, NUM_FILES - 1);
+        assert!(!announcer.enabled());
+        assert!(announcer
+            .tick_with_time(start + Duration::from_secs(1))
+            .is_empty());
+
+        // enabled at runtime, without a burst of the disabled period
+        announcer.set_rate(RATE);
+        assert!(announcer.enabled());
+        let now = start + Duration::from_secs(10);
+        assert_eq!(announcer.tick_with_time(now).len() as u64, RATE);
+
+        // slowed down at runtime
+        announcer.set_rate(RATE / 10);
+        let now = now + Duration::from_secs(1);
+        assert_eq!(announcer.tick_with_time(now).len() as u64, RATE / 10);
+    }
+
+    #[test]
+    fn test_reannounce() {
+        let store = new_store();
+        let interval = Duration::from_secs(3600);
+        let start = Instant::now();
+        let mut announcer =
+            DripAnnouncer::new_with_time(store, NUM_FILES, Duration::ZERO, interval, start);
+        announcer.on_finalized_range(0, NUM_FILES - 1);
+        let mut now = start;
+        let mut num_announced = 0;
+        while num_announced < NUM_FILES * 9 / 10 {
+            now += DRIP_ANNOUNCE_TICK;
+            num_announced += announcer.tick_with_time(now).len() as u64;
+        }
+
+        // start over once the interval elapsed
+        assert!(announcer
+            .tick_with_time(now + interval - DRIP_ANNOUNCE_TICK)
+            .is_empty());
+        assert_eq!(announcer.tick_with_time(now + interval)[0].seq, 1);
+    }
+}
@@ -0,0 +1,389 @@
+use anyhow::{anyhow, Result};
+use network::{Multiaddr, PeerId};
+use ssz::Decode as _;
+use ssz_derive::{Decode, Encode};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use storage::config::ShardConfig;
+use storage::log_store::config::{Configurable, ConfigurableExt};
+use storage::log_store::log_manager::DATA_DB_KEY;
+use storage::log_store::Store;
+use sync::{PeerContribution, PeerStats};
+
+/// DB key of the known peers.
+const KNOWN_PEERS_KEY: &str = "router.known_peers";
+
+/// Known peer is pruned once not connected after dialed for so many times, e.g. restarts.
+pub const MAX_DIAL_ATTEMPTS: u32 = 3;
+
+/// Peer that connected recently, which is reconnected after the node restarts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KnownPeer {
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+    /// Shard config announced by the peer, if any.
+    pub shard_config: Option<ShardConfig>,
+    /// Client version advertised via identify, e.g. `v0.3.0-67da032`, which is not persisted.
+    pub version: Option<String>,
+    /// Unix timestamp in seconds when the peer is connected.
+    pub last_seen: u64,
+    pub score: f64,
+    /// Number of dials since the peer is connected.
+    pub dial_attempts: u32,
+}
+
+#[derive(Encode, Decode)]
+struct KnownPeerEncoded {
+    peer_id: Vec<u8>,
+    addresses: Vec<Vec<u8>>,
+    shard_config: Option<ShardConfig>,
+    last_seen: u64,
+    score: u64,
+    dial_attempts: u32,
+    contribution: PeerContribution,
+}
+
+impl KnownPeerEncoded {
+    fn new(peer: &KnownPeer, contribution: PeerContribution) -> Self {
+        Self {
+            peer_id: peer.peer_id.to_bytes(),
+            addresses: peer.addresses.iter().map(|addr| addr.to_vec()).collect(),
+            shard_config: peer.shard_config,
+            last_seen: peer.last_seen,
+            score: peer.score.to_bits(),
+            dial_attempts: peer.dial_attempts,
+            contribution,
+        }
+    }
+}
+
+impl TryFrom<KnownPeerEncoded> for KnownPeer {
+    type Error = anyhow::Error;
+
+    fn try_from(value: KnownPeerEncoded) -> Result<Self> {
+        let mut addresses = vec![];
+        for addr in value.addresses {
+            addresses.push(Multiaddr::try_from(addr)?);
+        }
+
+        Ok(Self {
+            peer_id: PeerId::from_bytes(&value.peer_id)
+                .map_err(|e| anyhow!("invalid peer id: {:?}", e))?,
+            addresses,
+            shard_config: value.shard_config,
+            version: None,
+            last_seen: value.last_seen,
+            score: f64::from_bits(value.score),
+            dial_attempts: value.dial_attempts,
+        })
+    }
+}
+
+/// Bounded set of known peers persisted in the storage db.
+///
+/// Peers are reconnected when the router starts, along with the discovery, so that a
+/// restarted node could find useful peers quickly. The least recently seen peers are evicted
+/// once the capacity is exceeded, and peers that stay unreachable are pruned after
+/// `MAX_DIAL_ATTEMPTS` dials.
+///
+/// The contribution of known peers to file sync is persisted along with them, and loaded into
+/// the peer stats shared with the sync service.
+#[derive(Clone)]
+pub struct KnownPeers {
+    store: Arc<dyn Store>,
+    max_peers: usize,
+    peers: Arc<RwLock<HashMap<PeerId, KnownPeer>>>,
+    peer_stats: Arc<PeerStats>,
+}
+
+impl KnownPeers {
+    /// Loads known peers from db. Known peers are disabled if `max_peers` is 0.
+    pub fn load(store: Arc<dyn Store>, max_peers: usize) -> Result<Self> {
+        if max_peers == 0 {
+            return Ok(Self {
+                store,
+                max_peers,
+                peers: Default::default(),
+                peer_stats: Default::default(),
+            });
+        }
+
+        let encoded = match store.get_config(KNOWN_PEERS_KEY.as_bytes(), DATA_DB_KEY)? {
+            // e.g. persisted by an older version without the contribution
+            Some(bytes) => Vec::<KnownPeerEncoded>::from_ssz_bytes(&bytes).unwrap_or_else(|e| {
+                warn!(error = ?e, "Failed to decode known peers, start without them");
+                vec![]
+            }),
+            None => vec![],
+        };
+        let mut peers = HashMap::with_capacity(encoded.len());
+        let mut contributions = Vec::with_capacity(encoded.len());
+        for value in encoded {
+            let contribution = value.contribution;
+            match KnownPeer::try_from(value) {
+                Ok(peer) => {
+                    if contribution != PeerContribution::default() {
+                        contributions.push((peer.peer_id, contribution));
+                    }
+                    peers.insert(peer.peer_id, peer);
+                }
+                Err(e) => warn!(error = ?e, "Failed to decode known peer"),
+            }
+        }
+        Self::evict(&mut peers, max_peers);
+        contributions.retain(|(peer_id, _)| peers.contains_key(peer_id));
+
+        Ok(Self {
+            store,
+            max_peers,
+            peers: Arc::new(RwLock::new(peers)),
+            peer_stats: Arc::new(PeerStats::new(contributions)),
+        })
+    }
+
+    /// Returns the contribution of peers to file sync, which is persisted for the known peers.
+    pub fn peer_stats(&self) -> Arc<PeerStats> {
+        self.peer_stats.clone()
+    }
+
+    /// Records a connected peer with its current listening addresses, version and score.
+    pub fn observe(
+        &self,
+        peer_id: PeerId,
+        addresses: Vec<Multiaddr>,
+        shard_config: Option<ShardConfig>,
+        version: Option<String>,
+        score: f64,
+    ) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.observe_at(peer_id, addresses, shard_config, version, score, now);
+    }
+
+    fn observe_at(
+        &self,
+        peer_id: PeerId,
+        addresses: Vec<Multiaddr>,
+        shard_config: Option<ShardConfig>,
+        version: Option<String>,
+        score: f64,
+        now: u64,
+    ) {
+        // not reachable without any address
+        if self.max_peers == 0 || addresses.is_empty() {
+            return;
+        }
+
+        let mut peers = self.peers.write().expect("lock poisoned");
+        let peer = peers.entry(peer_id).or_insert_with(|| KnownPeer {
+            peer_id,
+            addresses: vec![],
+            shard_config: None,
+            version: None,
+            last_seen: now,
+            score,
+            dial_attempts: 0,
+        });
+        peer.addresses = addresses;
+        if shard_config.is_some() {
+            peer.shard_config = shard_config;
+        }
+        if version.is_some() {
+            peer.version = version;
+        }
+        peer.last_seen = now;
+        peer.score = score;
+        peer.dial_attempts = 0;
+
+        Self::evict(&mut peers, self.max_peers);
+    }
+
+    /// Evicts the least recently seen peers, and then the lower scored ones.
+    fn evict(peers: &mut HashMap<PeerId, KnownPeer>, max_peers: usize) {
+        while peers.len() > max_peers {
+            let evicted = peers
+                .values()
+                .min_by(|a, b| {
+                    a.last_seen
+                        .cmp(&b.last_seen)
+                        .then(a.score.total_cmp(&b.score))
+                })
+                .map(|peer| peer.peer_id)
+                .expect("peers not empty");
+            peers.remove(&evicted);
+        }
+    }
+
+    /// Returns the known peers to reconnect, and prunes those dialed for `MAX_DIAL_ATTEMPTS`
+    /// times without being connected. The dial attempts are persisted immediately.
+    pub fn dial_candidates(&self) -> Result<Vec<KnownPeer>> {
+        let candidates = {
+            let mut peers = self.peers.write().expect("lock poisoned");
+            peers.retain(|peer_id, peer| {
+                let reachable = peer.dial_attempts < MAX_DIAL_ATTEMPTS;
+                if !reachable {
+                    debug!(%peer_id, "Prune unreachable known peer");
+                }
+                reachable
+            });
+
+            peers
+                .values_mut()
+                .map(|peer| {
+                    peer.dial_attempts += 1;
+                    peer.clone()
+                })
+                .collect()
+        };
+
+        self.save()?;
+
+        Ok(candidates)
+    }
+
+    /// Persists all known peers in db, along with their current contribution.
+    pub fn save(&self) -> Result<()> {
+        if self.max_peers == 0 {
+            return Ok(());
+        }
+
+        let encoded: Vec<KnownPeerEncoded> = self
+            .peers
+            .read()
+            .expect("lock poisoned")
+            .values()
+            .map(|peer| {
+                let contribution = self.peer_stats.get(&peer.peer_id).unwrap_or_default();
+                KnownPeerEncoded::new(peer, contribution)
+            })
+            .collect();
+        self.store
+            .set_config_encoded(&KNOWN_PEERS_KEY, &encoded, DATA_DB_KEY)
+    }
+
+    /// Returns all known peers, the most recently seen first.
+    pub fn peers(&self) -> Vec<KnownPeer> {
+        let mut peers: Vec<KnownPeer> = self
+            .peers
+            .read()
+            .expect("lock poisoned")
+            .values()
+            .cloned()
+            .collect();
+        peers.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+        peers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storage::log_store::log_manager::LogConfig;
+    use storage::LogManager;
+
+    fn new_store() -> Arc<dyn Store> {
+        Arc::new(LogManager::memorydb(LogConfig::default()).unwrap())
+    }
+
+    fn address(port: u16) -> Vec<Multiaddr> {
+        vec![format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap()]
+    }
+
+    #[test]
+    fn test_persist_across_restart() {
+        let store = new_store();
+        let known_peers = KnownPeers::load(store.clone(), 2).unwrap();
+        assert!(known_peers.peers().is_empty());
+
+        let peer1 = PeerId::random();
+        let peer2 = PeerId::random();
+        let peer3 = PeerId::random();
+        let shard_config = ShardConfig {
+            shard_id: 1,
+            num_shard: 2,
+        };
+        known_peers.observe_at(peer1, address(1001), None, None, 0.0, 100);
+        known_peers.observe_at(peer2, address(1002), Some(shard_config), None, 10.0, 200);
+        let version = Some("v0.3.0-67da032".to_string());
+        known_peers.observe_at(peer3, address(1003), None, version.clone(), 5.0, 300);
+        assert_eq!(known_peers.peers()[0].version, version);
+        // no address to reconnect
+        let unknown = PeerId::random();
+        known_peers.observe_at(unknown, vec![], None, None, 0.0, 400);
+        let peer_stats = known_peers.peer_stats();
+        peer_stats.on_served(peer3, 256);
+        peer_stats.on_received(peer3, Some(1024));
+        peer_stats.on_served(unknown, 512);
+        known_peers.save().unwrap();
+
+        // restart with the same db
+        drop(known_peers);
+        let known_peers = KnownPeers::load(store.clone(), 2).unwrap();
+        let peers = known_peers.peers();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].peer_id, peer3);
+        assert_eq!(peers[0].addresses, address(1003));
+        // version not persisted, until reconnected
+        assert_eq!(peers[0].version, None);
+        // contribution persisted for the known peers only
+        let peer_stats = known_peers.peer_stats();
+        assert_eq!(
+            peer_stats.get(&peer3),
+            Some(PeerContribution {
+                segments_received: 1,
+                bytes_received: 1024,
+                segments_served: 1,
+                bytes_served: 256,
+                ..Default::default()
+            })
+        );
+        assert_eq!(peer_stats.stats().len(), 1);
+        assert_eq!(peers[1].peer_id, peer2);
+        assert_eq!(peers[1].shard_config, Some(shard_config));
+        assert_eq!(peers[1].score, 10.0);
+        assert_eq!(peers[1].last_seen, 200);
+
+        // restart with a smaller capacity
+        let known_peers = KnownPeers::load(store.clone(), 1).unwrap();
+        assert_eq!(known_peers.peers().len(), 1);
+        assert_eq!(known_peers.peers()[0].peer_id, peer3);
+
+        // disabled
+        let known_peers = KnownPeers::load(store, 0).unwrap();
+        assert!(known_peers.peers().is_empty());
+        known_peers.observe_at(peer1, address(1001), None, None, 0.0, 500);
+        assert!(known_peers.peers().is_empty());
+    }
+
+    #[test]
+    fn test_prune_unreachable() {
+        let store = new_store();
+        let known_peers = KnownPeers::load(store.clone(), 10).unwrap();
+        let reachable = PeerId::random();
+        let unreachable = PeerId::random();
+        known_peers.observe_at(reachable, address(1001), None, None, 0.0, 100);
+        known_peers.observe_at(unreachable, address(1002), None, None, 0.0, 100);
+        known_peers.save().unwrap();
+
+        for _ in 0..MAX_DIAL_ATTEMPTS {
+            // restart and reconnect
+            let known_peers = KnownPeers::load(store.clone(), 10).unwrap();
+            assert_eq!(known_peers.dial_candidates().unwrap().len(), 2);
+
+            known_peers.observe_at(reachable, address(1001), None, None, 0.0, 200);
+            known_peers.save().unwrap();
+        }
+
+        let known_peers = KnownPeers::load(store.clone(), 10).unwrap();
+        let candidates = known_peers.dial_candidates().unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].peer_id, reachable);
+        assert_eq!(candidates[0].dial_attempts, 1);
+
+        let known_peers = KnownPeers::load(store, 10).unwrap();
+        assert_eq!(known_peers.peers().len(), 1);
+    }
+}
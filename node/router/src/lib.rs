@@ -1,17 +1,22 @@
 #[macro_use]
 extern crate tracing;
 
+mod announce_gate;
 mod batcher;
+mod drip_announcer;
+mod known_peers;
 mod libp2p_event_handler;
 mod metrics;
 mod peer_manager;
 mod service;
+mod shard_announcer;
 
 use duration_str::deserialize_duration;
 use network::Multiaddr;
 use serde::Deserialize;
 use std::{net::IpAddr, time::Duration};
 
+pub use crate::known_peers::{KnownPeer, KnownPeers};
 pub use crate::service::RouterService;
 
 #[derive(Debug, Clone, Deserialize)]
@@ -27,6 +32,40 @@ pub struct Config {
     pub private_ip_enabled: bool,
     pub check_announced_ip: bool,
     pub public_address: Option<IpAddr>,
+    /// Maximum number of recently connected peers persisted in db to reconnect after
+    /// restart, or 0 to disable.
+    pub max_known_peers: usize,
+    /// Minimum interval to announce the changed shard config, e.g. after resharded, and the
+    /// changes in between are coalesced.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub shard_config_announce_interval: Duration,
+
+    /// Announced files with tx seq beyond the local next tx seq by more than this value are
+    /// ignored without penalizing the peer, whose log sync may be ahead of ours.
+    pub max_announced_tx_seq_ahead: u64,
+
+    /// Number of blocks that a tx is confirmed in the log sync progress before its file is
+    /// announced, so that peers are unlikely to sync files reorged away. Files reverted after
+    /// announced are retracted anyway. Set to 0 to announce at once, e.g. if log entries are
+    /// synced from finalized blocks only.
+    pub announce_confirmation_depth: u64,
+
+    /// Number of the locally finalized files announced per second by scanning the store, e.g.
+    /// the files backfilled by a freshly synced node, or 0 to disable. Files finalized in a burst
+    /// are left to the scan rather than announced at once.
+    pub drip_announce_rate: u64,
+    /// Maximum random delay between the drip announcements of each second.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub drip_announce_jitter: Duration,
+    /// Interval to start over the drip announcements once all the finalized files are scanned,
+    /// so that files are re-announced periodically at `drip_announce_rate`, or 0 to disable.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub file_reannounce_interval: Duration,
+
+    /// Indicates whether to answer queries of files not finalized yet, as long as the queried
+    /// chunks are stored locally. Always follows `serve_partial_files` of the sync config.
+    #[serde(skip)]
+    pub serve_partial_files: bool,
 
     // batcher
     /// Timeout to publish messages in batch
@@ -49,6 +88,14 @@ impl Default for Config {
             private_ip_enabled: false,
             check_announced_ip: false,
             public_address: None,
+            max_known_peers: 100,
+            shard_config_announce_interval: Duration::from_secs(30),
+            max_announced_tx_seq_ahead: 1000,
+            announce_confirmation_depth: 6,
+            drip_announce_rate: 20,
+            drip_announce_jitter: Duration::from_millis(500),
+            file_reannounce_interval: Duration::ZERO,
+            serve_partial_files: true,
 
             batcher_timeout: Duration::from_secs(1),
             batcher_file_capacity: 1,
@@ -62,4 +109,23 @@ impl Config {
         self.private_ip_enabled = enabled;
         self
     }
+
+    pub fn dynamic(&self) -> DynamicConfig {
+        DynamicConfig {
+            shard_config_announce_interval: self.shard_config_announce_interval,
+            drip_announce_rate: self.drip_announce_rate,
+        }
+    }
+
+    pub fn set_dynamic(&mut self, dynamic: DynamicConfig) {
+        self.shard_config_announce_interval = dynamic.shard_config_announce_interval;
+        self.drip_announce_rate = dynamic.drip_announce_rate;
+    }
+}
+
+/// Parameters of router config that could be reloaded at runtime without restarting the node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DynamicConfig {
+    pub shard_config_announce_interval: Duration,
+    pub drip_announce_rate: u64,
 }
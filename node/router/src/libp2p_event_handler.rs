@@ -7,22 +7,27 @@ use file_location_cache::FileLocationCache;
 use network::multiaddr::Protocol;
 use network::types::TimedMessage;
 use network::{
-    rpc::StatusMessage,
+    rpc::{RPCError, StatusMessage},
     types::{
-        AnnounceChunks, AnnounceFile, FindChunks, FindFile, HasSignature, SignedAnnounceFile,
-        SignedMessage,
+        AnnounceChunks, AnnounceFile, FindChunks, FindFile, HasSignature, RetractFile,
+        SignedAnnounceFile, SignedMessage, SignedRetractFile,
     },
     Keypair, MessageAcceptance, MessageId, NetworkGlobals, NetworkMessage, PeerId, PeerRequestId,
-    PublicKey, PubsubMessage, Request, RequestId, Response,
+    PublicKey, PubsubMessage, Request, RequestId, Response, SyncId,
 };
 use network::{Multiaddr, NetworkSender, PeerAction, ReportSource};
-use shared_types::{bytes_to_chunks, timestamp_now, NetworkIdentity, ShardedFile, TxID};
+use shared_types::{
+    bytes_to_chunks, timestamp_now, NetworkIdentity, ShardedFile, Transaction, TxID,
+};
 use storage::config::ShardConfig;
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
 use storage_async::Store;
 use sync::{SyncMessage, SyncSender};
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::{mpsc, RwLock};
+use tracing::Span;
 
+use crate::announce_gate::is_tx_confirmed;
 use crate::batcher::Batcher;
 use crate::metrics::{self, PubsubMsgHandleMetrics};
 use crate::peer_manager::PeerManager;
@@ -211,6 +216,7 @@ impl Libp2pEventHandler {
             peer_id,
             request_id: RequestId::Router(Instant::now()),
             request: Request::Status(status_message),
+            span: Span::current(),
         });
 
         metrics::LIBP2P_SEND_STATUS.mark(1);
@@ -254,6 +260,22 @@ impl Libp2pEventHandler {
                 });
                 metrics::LIBP2P_HANDLE_GET_CHUNKS_REQUEST.mark(1);
             }
+            Request::QueryFileStatus(request) => {
+                self.send_to_sync(SyncMessage::QueryFileStatus {
+                    peer_id,
+                    request_id,
+                    request,
+                });
+                metrics::LIBP2P_HANDLE_QUERY_FILE_STATUS_REQUEST.mark(1);
+            }
+            Request::GetAnnouncements(request) => {
+                self.send_to_sync(SyncMessage::GetAnnouncements {
+                    peer_id,
+                    request_id,
+                    request,
+                });
+                metrics::LIBP2P_HANDLE_GET_ANNOUNCEMENTS_REQUEST.mark(1);
+            }
             Request::AnswerFile(file) => match ShardConfig::try_from(file.shard_config) {
                 Ok(v) => {
                     self.file_location_cache.insert_peer_config(peer_id, v);
@@ -270,6 +292,9 @@ impl Libp2pEventHandler {
             Request::DataByHash(_) => {
                 // ignore
             }
+            Request::Ping => {
+                // responded by the network behaviour
+            }
         }
     }
 
@@ -340,13 +365,51 @@ impl Libp2pEventHandler {
                     response,
                 });
             }
+            Response::FileStatus(response) => {
+                let request_id = match request_id {
+                    RequestId::Sync(since, sync_id) => {
+                        metrics::LIBP2P_HANDLE_FILE_STATUS_RESPONSE.mark(1);
+                        metrics::LIBP2P_HANDLE_FILE_STATUS_RESPONSE_LATENCY.update_since(since);
+                        sync_id
+                    }
+                    _ => unreachable!("All FileStatus responses belong to sync"),
+                };
+
+                self.send_to_sync(SyncMessage::FileStatusResponse {
+                    peer_id,
+                    request_id,
+                    response,
+                });
+            }
+            Response::Announcements(response) => {
+                let request_id = match request_id {
+                    RequestId::Sync(since, sync_id) => {
+                        metrics::LIBP2P_HANDLE_ANNOUNCEMENTS_RESPONSE.mark(1);
+                        metrics::LIBP2P_HANDLE_ANNOUNCEMENTS_RESPONSE_LATENCY.update_since(since);
+                        sync_id
+                    }
+                    _ => unreachable!("All Announcements responses belong to sync"),
+                };
+
+                self.send_to_sync(SyncMessage::AnnouncementsResponse {
+                    peer_id,
+                    request_id,
+                    response,
+                });
+            }
             Response::DataByHash(_) => {
                 // ignore
             }
+            Response::Pong => {
+                if let RequestId::Sync(since, SyncId::Ping) = request_id {
+                    metrics::LIBP2P_HANDLE_PONG_RESPONSE_LATENCY.update_since(since);
+                    self.send_to_sync(SyncMessage::Pong { peer_id });
+                }
+            }
         }
     }
 
-    pub async fn on_rpc_error(&self, peer_id: PeerId, request_id: RequestId) {
+    pub async fn on_rpc_error(&self, peer_id: PeerId, request_id: RequestId, error: RPCError) {
         self.peers.write().await.update(&peer_id);
 
         // Check if the failed RPC belongs to sync
@@ -354,6 +417,7 @@ impl Libp2pEventHandler {
             self.send_to_sync(SyncMessage::RpcError {
                 peer_id,
                 request_id,
+                error,
             });
 
             metrics::LIBP2P_HANDLE_RESPONSE_ERROR_LATENCY.update_since(since);
@@ -394,6 +458,7 @@ impl Libp2pEventHandler {
             PubsubMessage::AnnounceShardConfig(msg) => {
                 self.on_announce_shard_config(propagation_source, source, msg)
             }
+            PubsubMessage::RetractFile(msg) => self.on_retract_file(propagation_source, msg),
         }
     }
 
@@ -464,26 +529,58 @@ impl Libp2pEventHandler {
         }
 
         // check if we have it
-        if matches!(self.store.check_tx_completed(msg.tx_id.seq).await, Ok(true)) {
-            if let Ok(Some(tx)) = self.store.get_tx_by_seq_number(msg.tx_id.seq).await {
-                if tx.id() == msg.tx_id {
-                    trace!(?msg.tx_id, "Found file locally, responding to FindFile query");
-
-                    self.send_to_network(NetworkMessage::SendRequest {
-                        peer_id: from,
-                        request: Request::AnswerFile(ShardedFile {
-                            tx_id: msg.tx_id,
-                            shard_config: my_shard_config.into(),
-                        }),
-                        request_id: RequestId::Router(Instant::now()),
-                    });
-                }
+        if let Ok(Some(tx)) = self.store.get_tx_by_seq_number(msg.tx_id.seq).await {
+            if tx.id() == msg.tx_id && self.is_file_servable(&tx).await {
+                trace!(?msg.tx_id, "Found file locally, responding to FindFile query");
+
+                self.send_to_network(NetworkMessage::SendRequest {
+                    peer_id: from,
+                    request: Request::AnswerFile(ShardedFile {
+                        tx_id: msg.tx_id,
+                        shard_config: my_shard_config.into(),
+                    }),
+                    request_id: RequestId::Router(Instant::now()),
+                    span: Span::current(),
+                });
             }
         }
 
         MessageAcceptance::Ignore
     }
 
+    /// Returns whether the file is finalized locally, or the leading chunks are stored locally
+    /// so that neighbors could sync the file from the start while it is still downloading.
+    async fn is_file_servable(&self, tx: &Transaction) -> bool {
+        if matches!(self.store.check_tx_completed(tx.seq).await, Ok(true)) {
+            return true;
+        }
+
+        if !self.config.serve_partial_files {
+            return false;
+        }
+
+        let num_chunks = bytes_to_chunks(tx.size as usize);
+        let index_end = std::cmp::min(num_chunks, PORA_CHUNK_SIZE);
+        num_chunks > 0 && self.has_chunks(tx.seq, 0, index_end as u64).await
+    }
+
+    /// Returns whether the chunks of a tx are stored locally, regardless of whether the tx is
+    /// finalized. Chunks are never regarded stored during a revert, which may truncate them.
+    async fn has_chunks(&self, tx_seq: u64, index_start: u64, index_end: u64) -> bool {
+        // revert bumps the flow version both before and after the flow truncated
+        let flow_version = self.store.get_store().get_flow_version();
+        if flow_version % 2 == 1 {
+            return false;
+        }
+
+        matches!(
+            self.store
+                .has_chunks_by_tx_and_index_range(tx_seq, index_start as usize, index_end as usize)
+                .await,
+            Ok(true)
+        ) && self.store.get_store().get_flow_version() == flow_version
+    }
+
     async fn construct_announced_ip(&self) -> Option<Multiaddr> {
         // public address configured
         if let Some(ip) = self.config.public_address {
@@ -618,9 +715,15 @@ impl Libp2pEventHandler {
             }
         }
 
-        // check if we have it
+        // check if we have it, and confirmed on chain to announce
         let tx_id = msg.tx_id;
-        if matches!(self.store.check_tx_completed(tx_id.seq).await, Ok(true)) {
+        if matches!(self.store.check_tx_completed(tx_id.seq).await, Ok(true))
+            && is_tx_confirmed(
+                self.store.get_store(),
+                tx_id.seq,
+                self.config.announce_confirmation_depth,
+            )
+        {
             if let Ok(Some(tx)) = self.store.get_tx_by_seq_number(tx_id.seq).await {
                 if tx.id() == tx_id {
                     trace!(?tx_id, "Found file locally, responding to FindFile query");
@@ -648,6 +751,27 @@ impl Libp2pEventHandler {
         MessageAcceptance::Accept
     }
 
+    /// Retracts the announced files reverted on chain, so that peers drop the queued syncs of
+    /// them, and stops exchanging them with the peers connected later.
+    pub fn retract_files(&self, tx_ids: Vec<TxID>) {
+        self.publish_retract_file(tx_ids.clone());
+        self.send_to_sync(SyncMessage::FilesRetracted { tx_ids });
+    }
+
+    /// Publishes the signed `RetractFile` message of the local files reverted on chain.
+    fn publish_retract_file(&self, tx_ids: Vec<TxID>) {
+        let peer_id = *self.network_globals.peer_id.read();
+        let msg = TimedMessage::from(RetractFile {
+            tx_ids,
+            peer_id: peer_id.into(),
+        });
+
+        match SignedMessage::sign_message(msg, &self.local_keypair) {
+            Ok(signed) => self.publish(PubsubMessage::RetractFile(signed)),
+            Err(e) => error!(%e, "Failed to sign RetractFile message"),
+        }
+    }
+
     pub async fn construct_announce_chunks_message(
         &self,
         tx_id: TxID,
@@ -705,19 +829,17 @@ impl Libp2pEventHandler {
             }
         }
 
-        // TODO(qhz): check if there is better way to check existence of requested chunks.
-        match self
-            .store
-            .get_chunks_by_tx_and_index_range(
-                msg.tx_id.seq,
-                msg.index_start as usize,
-                msg.index_end as usize,
-            )
+        let finalized = matches!(self.store.check_tx_completed(tx.seq).await, Ok(true));
+        if !finalized && !self.config.serve_partial_files {
+            return MessageAcceptance::Accept;
+        }
+
+        if !self
+            .has_chunks(msg.tx_id.seq, msg.index_start, msg.index_end)
             .await
         {
-            Ok(Some(_)) => (),
-            _ => return MessageAcceptance::Accept,
-        };
+            return MessageAcceptance::Accept;
+        }
 
         trace!(?msg, "Found chunks to respond FindChunks message");
 
@@ -786,6 +908,16 @@ impl Libp2pEventHandler {
             return MessageAcceptance::Reject;
         }
 
+        // verify signed timestamp, which is neither in the future nor later than resend timestamp
+        let drift = TOLERABLE_DRIFT.num_seconds();
+        let timestamp = i64::from(msg.timestamp);
+        if timestamp > chrono::Utc::now().timestamp() + drift
+            || timestamp > i64::from(msg.resend_timestamp) + drift
+        {
+            debug!(%propagation_source, %msg.timestamp, %msg.resend_timestamp, "Invalid timestamp, rejecting AnnounceFile message");
+            return MessageAcceptance::Reject;
+        }
+
         // verify public ip address if required
         let addr = msg.at.clone().into();
         if !self.config.private_ip_enabled && !Self::contains_public_ip(&addr) {
@@ -806,6 +938,12 @@ impl Libp2pEventHandler {
             Err(_) => return MessageAcceptance::Reject,
         };
 
+        // verify announced files against the local log entries
+        if let Some(result) = self.verify_announced_tx_ids(&msg.tx_ids) {
+            debug!(%propagation_source, "Invalid tx ids in AnnounceFile message");
+            return result;
+        }
+
         // propagate gossip to peers
         let d = duration_since(
             msg.resend_timestamp,
@@ -835,6 +973,75 @@ impl Libp2pEventHandler {
         MessageAcceptance::Accept
     }
 
+    /// Verifies the announced tx ids, which should be either the same as the local log
+    /// entries, or not too far beyond the local next tx seq, e.g. not synced from chain yet.
+    /// Returns the message acceptance if any tx id is invalid.
+    ///
+    /// Only malformed messages are rejected. Tx ids that do not match the local log entries
+    /// are ignored without penalty, since the peer may be honest while its log sync is ahead
+    /// of ours or on the other side of a reorg.
+    fn verify_announced_tx_ids(&self, tx_ids: &[TxID]) -> Option<MessageAcceptance> {
+        if tx_ids.is_empty() {
+            return Some(MessageAcceptance::Reject);
+        }
+
+        let store = self.store.get_store();
+        let next_tx_seq = store.next_tx_seq();
+        for tx_id in tx_ids {
+            if tx_id.seq >= next_tx_seq {
+                if tx_id.seq - next_tx_seq >= self.config.max_announced_tx_seq_ahead {
+                    return Some(MessageAcceptance::Ignore);
+                }
+
+                continue;
+            }
+
+            match store.get_tx_by_seq_number(tx_id.seq) {
+                Ok(Some(tx)) if tx.hash() == tx_id.hash => {}
+                Ok(_) => return Some(MessageAcceptance::Ignore),
+                Err(err) => {
+                    warn!(?err, tx_seq = %tx_id.seq, "Failed to get tx from store");
+                    return Some(MessageAcceptance::Ignore);
+                }
+            }
+        }
+
+        None
+    }
+
+    fn on_retract_file(
+        &self,
+        propagation_source: PeerId,
+        msg: SignedRetractFile,
+    ) -> MessageAcceptance {
+        // verify message signature, so that peers only retract the files announced by themselves
+        if !verify_signature(&msg, &msg.peer_id, propagation_source) {
+            return MessageAcceptance::Reject;
+        }
+
+        // verify timestamp
+        if !metrics::LIBP2P_HANDLE_PUBSUB_RETRACT_FILE.verify_timestamp(
+            propagation_source,
+            msg.timestamp,
+            *PUBSUB_TIMEOUT_NETWORK,
+            None,
+        ) {
+            return MessageAcceptance::Ignore;
+        }
+
+        if msg.tx_ids.is_empty() {
+            return MessageAcceptance::Reject;
+        }
+
+        // notify sync layer, which validates the retracted files against the local log entries
+        self.send_to_sync(SyncMessage::RetractFileGossip {
+            tx_ids: msg.tx_ids.clone(),
+            peer_id: msg.peer_id.clone().into(),
+        });
+
+        MessageAcceptance::Accept
+    }
+
     fn on_announce_shard_config(
         &self,
         propagation_source: PeerId,
@@ -998,9 +1205,13 @@ mod tests {
         NetworkMessage, NetworkReceiver, PeerId, PubsubMessage, Request, RequestId, Response,
         SyncId,
     };
-    use shared_types::{timestamp_now, ChunkArray, ChunkArrayWithProof, FlowRangeProof, TxID};
+    use shared_types::{
+        timestamp_now, ChunkArray, ChunkArrayWithProof, FlowRangeProof, TxID, CHUNK_SIZE,
+    };
     use storage::{
-        log_store::{log_manager::LogConfig, Store},
+        log_store::{
+            log_manager::LogConfig, tx_store::SubmissionContext, LogStoreChunkWrite, Store,
+        },
         LogManager,
     };
     use sync::{test_util::create_2_store, SyncMessage, SyncReceiver, SyncSender};
@@ -1010,7 +1221,7 @@ mod tests {
         RwLock,
     };
 
-    use crate::{peer_manager::PeerManager, Config};
+    use crate::{announce_gate::AnnounceGate, peer_manager::PeerManager, Config};
 
     use super::*;
 
@@ -1081,6 +1292,7 @@ mod tests {
                 vec![],
                 Default::default(),
                 Default::default(),
+                Default::default(),
             );
 
             let listen_addr: Multiaddr = "/ip4/127.0.0.1/tcp/30000".parse().unwrap();
@@ -1095,6 +1307,7 @@ mod tests {
                     peer_id,
                     request,
                     request_id,
+                    ..
                 }) => {
                     assert_eq!(peer_id, expected_peer_id);
                     assert!(matches!(request, Request::Status(..)));
@@ -1294,6 +1507,7 @@ mod tests {
             .on_rpc_error(
                 alice,
                 RequestId::Sync(Instant::now(), SyncId::SerialSync { tx_id: id }),
+                RPCError::StreamTimeout,
             )
             .await;
 
@@ -1301,9 +1515,11 @@ mod tests {
             Ok(Notification(SyncMessage::RpcError {
                 peer_id,
                 request_id,
+                error,
             })) => {
                 assert_eq!(peer_id, alice);
                 assert!(matches!(request_id, SyncId::SerialSync { tx_id } if tx_id == id ));
+                assert_eq!(error, RPCError::StreamTimeout);
             }
             Ok(_) => panic!("Unexpected sync message type received"),
             Err(e) => panic!("No sync message received: {:?}", e),
@@ -1395,6 +1611,60 @@ mod tests {
         ctx.assert_file_announcement_published(tx_id);
     }
 
+    #[tokio::test]
+    async fn test_on_pubsub_ask_file_partial() {
+        let mut ctx = Context::default();
+
+        // prepare store with tx, of which data not stored yet
+        let (store, _, txs, data) = create_2_store(vec![2 * PORA_CHUNK_SIZE]);
+        ctx.store = store.clone();
+
+        let handler = ctx.new_handler();
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let id = MessageId::new(b"dummy message");
+        let message = PubsubMessage::AskFile(
+            ShardedFile {
+                tx_id: txs[0].id(),
+                shard_config: ShardConfig::default().into(),
+            }
+            .into(),
+        );
+
+        let result = handler
+            .on_pubsub_message(alice, bob, &id, message.clone())
+            .await;
+        assert!(matches!(result, MessageAcceptance::Ignore));
+        assert!(matches!(
+            ctx.network_recv.try_recv(),
+            Err(TryRecvError::Empty)
+        ));
+
+        // answer once the leading chunks downloaded, though the tx is not finalized
+        store
+            .put_chunks(
+                txs[0].seq,
+                ChunkArray {
+                    data: data[0][..PORA_CHUNK_SIZE * CHUNK_SIZE].to_vec(),
+                    start_index: 0,
+                },
+            )
+            .unwrap();
+        let result = handler.on_pubsub_message(alice, bob, &id, message).await;
+        assert!(matches!(result, MessageAcceptance::Ignore));
+        match ctx.network_recv.try_recv() {
+            Ok(NetworkMessage::SendRequest {
+                peer_id,
+                request: Request::AnswerFile(file),
+                ..
+            }) => {
+                assert_eq!(peer_id, alice);
+                assert_eq!(file.tx_id, txs[0].id());
+            }
+            Ok(_) => panic!("Unexpected network message type received"),
+            Err(e) => panic!("No network message received: {:?}", e),
+        }
+    }
+
     #[tokio::test]
     async fn test_on_pubsub_announce_file_invalid_sig() {
         let ctx = Context::default();
@@ -1462,4 +1732,249 @@ mod tests {
         // ensure cache updated
         assert_eq!(ctx.file_location_cache.get_all(tx).len(), 1);
     }
+
+    #[tokio::test]
+    async fn test_on_pubsub_retract_file() {
+        let mut ctx = Context::default();
+        let handler = ctx.new_handler();
+
+        // retract the files reverted on chain
+        let tx = TxID::random_hash(412);
+        handler.publish_retract_file(vec![tx]);
+        let mut message = match ctx.network_recv.try_recv() {
+            Ok(NetworkMessage::Publish { mut messages }) => messages.remove(0),
+            Ok(_) => panic!("Unexpected network message type received"),
+            Err(e) => panic!("No network message received: {:?}", e),
+        };
+
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let id = MessageId::new(b"dummy message");
+        let result = handler
+            .on_pubsub_message(alice, bob, &id, message.clone())
+            .await;
+        assert!(matches!(result, MessageAcceptance::Accept));
+
+        // ensure notify to sync layer
+        match ctx.sync_recv.try_recv() {
+            Ok(Notification(SyncMessage::RetractFileGossip { tx_ids, peer_id })) => {
+                assert_eq!(tx_ids, vec![tx]);
+                assert_eq!(peer_id, *ctx.network_globals.peer_id.read());
+            }
+            Ok(_) => panic!("Unexpected sync message type received"),
+            Err(e) => panic!("No sync message received: {:?}", e),
+        }
+
+        // failed to verify signature of changed files
+        if let PubsubMessage::RetractFile(msg) = &mut message {
+            msg.inner.inner.tx_ids.push(TxID::random_hash(413));
+        }
+        let result = handler.on_pubsub_message(alice, bob, &id, message).await;
+        assert!(matches!(result, MessageAcceptance::Reject));
+    }
+
+    #[tokio::test]
+    async fn test_announce_reorg_retract() {
+        let mut ctx = Context::default();
+        let handler = ctx.new_handler();
+        let store = ctx.store.clone();
+        let store = store.as_ref();
+        let is_confirmed = |tx_id: &TxID| is_tx_confirmed(store, tx_id.seq, 2);
+
+        // txs submitted in blocks 10 and 11, while log synced to block 11
+        let tx_ids: Vec<TxID> = (0..2).map(TxID::random_hash).collect();
+        for (tx_id, block_number) in tx_ids.iter().zip([10, 11]) {
+            let context = SubmissionContext {
+                block_number,
+                tx_hash: Default::default(),
+            };
+            store.put_submission_context(tx_id.seq, context).unwrap();
+        }
+        store
+            .put_sync_progress((11, Default::default(), None))
+            .unwrap();
+
+        // announced once confirmed by 2 blocks
+        let mut gate = AnnounceGate::new();
+        assert!(gate.admit(tx_ids.clone(), is_confirmed).is_empty());
+        store
+            .put_sync_progress((12, Default::default(), None))
+            .unwrap();
+        assert_eq!(gate.expire(is_confirmed), tx_ids[..1]);
+        store
+            .put_sync_progress((13, Default::default(), None))
+            .unwrap();
+        assert_eq!(gate.expire(is_confirmed), tx_ids[1..]);
+
+        // retracted once reverted by a deeper reorg
+        let retracted = gate.revert(1);
+        assert_eq!(retracted, tx_ids[1..]);
+        handler.retract_files(retracted);
+        let message = match ctx.network_recv.try_recv() {
+            Ok(NetworkMessage::Publish { mut messages }) => messages.remove(0),
+            Ok(_) => panic!("Unexpected network message type received"),
+            Err(e) => panic!("No network message received: {:?}", e),
+        };
+        match ctx.sync_recv.try_recv() {
+            Ok(Notification(SyncMessage::FilesRetracted { tx_ids: ids })) => {
+                assert_eq!(ids, tx_ids[1..]);
+            }
+            Ok(_) => panic!("Unexpected sync message type received"),
+            Err(e) => panic!("No sync message received: {:?}", e),
+        }
+
+        // peers are notified to drop the queued syncs of the retracted files
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let id = MessageId::new(b"dummy message");
+        let result = handler.on_pubsub_message(alice, bob, &id, message).await;
+        assert!(matches!(result, MessageAcceptance::Accept));
+        match ctx.sync_recv.try_recv() {
+            Ok(Notification(SyncMessage::RetractFileGossip {
+                tx_ids: ids,
+                peer_id,
+            })) => {
+                assert_eq!(ids, tx_ids[1..]);
+                assert_eq!(peer_id, *ctx.network_globals.peer_id.read());
+            }
+            Ok(_) => panic!("Unexpected sync message type received"),
+            Err(e) => panic!("No sync message received: {:?}", e),
+        }
+    }
+
+    fn sign_announce_file(
+        ctx: &Context,
+        tx_ids: Vec<TxID>,
+        timestamp: u32,
+        resend_timestamp: u32,
+    ) -> SignedAnnounceFile {
+        let msg = TimedMessage {
+            inner: AnnounceFile {
+                tx_ids,
+                shard_config: Default::default(),
+                peer_id: (*ctx.network_globals.peer_id.read()).into(),
+                at: ctx.network_globals.listen_multiaddrs.read()[0]
+                    .clone()
+                    .into(),
+            },
+            timestamp,
+        };
+        let mut signed = SignedMessage::sign_message(msg, &ctx.keypair).unwrap();
+        signed.resend_timestamp = resend_timestamp;
+        signed
+    }
+
+    async fn handle_announce_file_msg(
+        handler: &Libp2pEventHandler,
+        file: SignedAnnounceFile,
+    ) -> MessageAcceptance {
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let id = MessageId::new(b"dummy message");
+        let message = PubsubMessage::AnnounceFile(vec![file]);
+        handler.on_pubsub_message(alice, bob, &id, message).await
+    }
+
+    #[tokio::test]
+    async fn test_on_pubsub_announce_file_invalid_timestamp() {
+        let ctx = Context::default();
+        let handler = ctx.new_handler();
+        let tx_id = TxID::random_hash(0);
+        let drift = TOLERABLE_DRIFT.num_seconds() as u32;
+        let now = timestamp_now();
+
+        // signed timestamp too future
+        let file = sign_announce_file(&ctx, vec![tx_id], now + 10 + drift, now + 10 + drift);
+        let result = handle_announce_file_msg(&handler, file).await;
+        assert!(matches!(result, MessageAcceptance::Reject));
+
+        // resent before signed
+        let file = sign_announce_file(&ctx, vec![tx_id], now, now - 10 - drift);
+        let result = handle_announce_file_msg(&handler, file).await;
+        assert!(matches!(result, MessageAcceptance::Reject));
+
+        // resent too late, which is not propagated but not malicious
+        let old = now - 10 - PUBSUB_TIMEOUT_NETWORK.num_seconds() as u32;
+        let file = sign_announce_file(&ctx, vec![tx_id], old, old);
+        let result = handle_announce_file_msg(&handler, file).await;
+        assert!(matches!(result, MessageAcceptance::Ignore));
+
+        // an old announcement resent recently
+        let file = sign_announce_file(&ctx, vec![tx_id], old, now);
+        let result = handle_announce_file_msg(&handler, file).await;
+        assert!(matches!(result, MessageAcceptance::Accept));
+    }
+
+    #[tokio::test]
+    async fn test_on_pubsub_announce_file_empty_tx_ids() {
+        let ctx = Context::default();
+        let handler = ctx.new_handler();
+        let now = timestamp_now();
+
+        let file = sign_announce_file(&ctx, vec![], now, now);
+        let result = handle_announce_file_msg(&handler, file).await;
+        assert!(matches!(result, MessageAcceptance::Reject));
+    }
+
+    #[tokio::test]
+    async fn test_on_pubsub_announce_file_tx_seq_too_far() {
+        let mut ctx = Context::default();
+        let (_, store, txs, _) = create_2_store(vec![1314]);
+        ctx.store = store;
+        let handler = ctx.new_handler();
+        let max_ahead = Config::default().max_announced_tx_seq_ahead;
+        let now = timestamp_now();
+
+        // not synced from chain yet
+        let tx_id = TxID::random_hash(txs.len() as u64 + max_ahead - 1);
+        let file = sign_announce_file(&ctx, vec![tx_id], now, now);
+        let result = handle_announce_file_msg(&handler, file).await;
+        assert!(matches!(result, MessageAcceptance::Accept));
+
+        let tx_id = TxID::random_hash(txs.len() as u64 + max_ahead);
+        let file = sign_announce_file(&ctx, vec![txs[0].id(), tx_id], now, now);
+        let result = handle_announce_file_msg(&handler, file).await;
+        assert!(matches!(result, MessageAcceptance::Ignore));
+        assert!(ctx.file_location_cache.get_all(tx_id).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_on_pubsub_announce_file_peer_ahead_not_penalized() {
+        let mut ctx = Context::default();
+        let (_, store, txs, _) = create_2_store(vec![1314]);
+        ctx.store = store;
+        let handler = ctx.new_handler();
+        let max_ahead = Config::default().max_announced_tx_seq_ahead;
+        let now = timestamp_now();
+
+        // an honest peer whose log sync is far ahead of ours
+        let tx_id = TxID::random_hash(txs.len() as u64 + max_ahead * 2);
+        let file = sign_announce_file(&ctx, vec![tx_id], now, now);
+        let result = handle_announce_file_msg(&handler, file).await;
+        assert!(matches!(result, MessageAcceptance::Ignore));
+        assert!(ctx.file_location_cache.get_all(tx_id).is_empty());
+
+        // the message is neither propagated nor the peer reported
+        assert!(ctx.network_recv.try_recv().is_err());
+        assert!(matches!(ctx.sync_recv.try_recv(), Err(_)));
+    }
+
+    #[tokio::test]
+    async fn test_on_pubsub_announce_file_tx_hash_mismatch() {
+        let mut ctx = Context::default();
+        let (_, store, txs, _) = create_2_store(vec![1314]);
+        ctx.store = store;
+        let handler = ctx.new_handler();
+        let now = timestamp_now();
+
+        // e.g. a peer on the other side of a reorg
+        let bogus = TxID::random_hash(txs[0].seq);
+        let file = sign_announce_file(&ctx, vec![bogus], now, now);
+        let result = handle_announce_file_msg(&handler, file).await;
+        assert!(matches!(result, MessageAcceptance::Ignore));
+        assert!(ctx.file_location_cache.get_all(bogus).is_empty());
+        assert!(matches!(ctx.sync_recv.try_recv(), Err(_)));
+
+        let file = sign_announce_file(&ctx, vec![txs[0].id()], now, now);
+        let result = handle_announce_file_msg(&handler, file).await;
+        assert!(matches!(result, MessageAcceptance::Accept));
+        assert_eq!(ctx.file_location_cache.get_all(txs[0].id()).len(), 1);
+    }
 }
@@ -61,6 +61,15 @@ lazy_static::lazy_static! {
     pub static ref LIBP2P_HANDLE_GET_CHUNKS_REQUEST: Arc<dyn Meter> = register_meter("router_libp2p_handle_get_chunks_request");
     pub static ref LIBP2P_HANDLE_GET_CHUNKS_RESPONSE: Arc<dyn Meter> = register_meter_with_group("router_libp2p_handle_get_chunks_response", "qps");
     pub static ref LIBP2P_HANDLE_GET_CHUNKS_RESPONSE_LATENCY: Arc<dyn Histogram> = Sample::ExpDecay(0.015).register_with_group("router_libp2p_handle_get_chunks_response", "latency", 1024);
+    pub static ref LIBP2P_HANDLE_PONG_RESPONSE_LATENCY: Arc<dyn Histogram> = Sample::ExpDecay(0.015).register("router_libp2p_handle_pong_response_latency", 1024);
+
+    // libp2p_event_handler: query file status
+    pub static ref LIBP2P_HANDLE_QUERY_FILE_STATUS_REQUEST: Arc<dyn Meter> = register_meter("router_libp2p_handle_query_file_status_request");
+    pub static ref LIBP2P_HANDLE_FILE_STATUS_RESPONSE: Arc<dyn Meter> = register_meter_with_group("router_libp2p_handle_file_status_response", "qps");
+    pub static ref LIBP2P_HANDLE_FILE_STATUS_RESPONSE_LATENCY: Arc<dyn Histogram> = Sample::ExpDecay(0.015).register_with_group("router_libp2p_handle_file_status_response", "latency", 1024);
+    pub static ref LIBP2P_HANDLE_GET_ANNOUNCEMENTS_REQUEST: Arc<dyn Meter> = register_meter("router_libp2p_handle_get_announcements_request");
+    pub static ref LIBP2P_HANDLE_ANNOUNCEMENTS_RESPONSE: Arc<dyn Meter> = register_meter_with_group("router_libp2p_handle_announcements_response", "qps");
+    pub static ref LIBP2P_HANDLE_ANNOUNCEMENTS_RESPONSE_LATENCY: Arc<dyn Histogram> = Sample::ExpDecay(0.015).register_with_group("router_libp2p_handle_announcements_response", "latency", 1024);
 
     // libp2p_event_handler: rpc errors
     pub static ref LIBP2P_HANDLE_RESPONSE_ERROR: Arc<dyn Meter> = register_meter_with_group("router_libp2p_handle_response_error", "qps");
@@ -72,6 +81,7 @@ lazy_static::lazy_static! {
     pub static ref LIBP2P_HANDLE_PUBSUB_FIND_CHUNKS: PubsubMsgHandleMetrics = PubsubMsgHandleMetrics::new("find_chunks");
     pub static ref LIBP2P_HANDLE_PUBSUB_ANNOUNCE_CHUNKS: PubsubMsgHandleMetrics = PubsubMsgHandleMetrics::new("announce_chunks");
     pub static ref LIBP2P_HANDLE_PUBSUB_ANNOUNCE_SHARD: PubsubMsgHandleMetrics = PubsubMsgHandleMetrics::new("announce_shard");
+    pub static ref LIBP2P_HANDLE_PUBSUB_RETRACT_FILE: PubsubMsgHandleMetrics = PubsubMsgHandleMetrics::new("retract_file");
 
     // libp2p_event_handler: find & announce file
     pub static ref LIBP2P_HANDLE_PUBSUB_FIND_FILE: PubsubMsgHandleMetrics = PubsubMsgHandleMetrics::new("find_file");
@@ -1,10 +1,21 @@
+use crate::announce_gate::{is_tx_confirmed, AnnounceGate};
+use crate::drip_announcer::DripAnnouncer;
+use crate::known_peers::KnownPeers;
 use crate::metrics;
-use crate::Config;
+use crate::shard_announcer::ShardAnnouncer;
 use crate::{libp2p_event_handler::Libp2pEventHandler, peer_manager::PeerManager};
+use crate::{Config, DynamicConfig};
 use chunk_pool::ChunkPoolMessage;
 use file_location_cache::FileLocationCache;
-use futures::{channel::mpsc::Sender, prelude::*};
+use futures::{
+    channel::{mpsc::Sender, oneshot},
+    prelude::*,
+};
+use log_entry_sync::{LogSyncEvent, LogSyncMonitor};
 use miner::MinerMessage;
+use network::libp2p::swarm::dial_opts::DialOpts;
+use network::nat::PortMapping;
+use network::peer_manager::peerdb::client::ClientKind;
 use network::rpc::GoodbyeReason;
 use network::PeerId;
 use network::{
@@ -12,20 +23,34 @@ use network::{
     NetworkSender, PubsubMessage, RequestId, Service as LibP2PService, Swarm,
 };
 use pruner::PrunerMessage;
-use shared_types::ShardedFile;
+use shared_types::{ShardedFile, TxID};
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use storage::log_store::finalization_bus::{FinalizationEvent, FinalizationSubscriber};
+use storage::log_store::tx_store::TxStatus;
 use storage::log_store::Store as LogStore;
 use storage_async::Store;
 use sync::{SyncMessage, SyncSender};
 use task_executor::ShutdownReason;
 use tokio::sync::mpsc::UnboundedSender;
-use tokio::sync::{broadcast, mpsc, RwLock};
-use tokio::time::interval;
+use tokio::sync::{broadcast, mpsc, watch, RwLock};
+use tokio::time::{interval, sleep_until, Instant};
+
+/// Interval to record the connected peers as known peers.
+const KNOWN_PEERS_UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Maximum number of the latest files announced for the finalized txs coalesced into a range,
+/// while the others could still be found via `FindFile`.
+const MAX_ANNOUNCED_FILES_IN_RANGE: u64 = 1024;
 
 /// Service that handles communication between internal services and the libp2p service.
 pub struct RouterService {
     config: Config,
 
+    /// Receives the dynamic parameters of router config reloaded at runtime.
+    config_recv: watch::Receiver<DynamicConfig>,
+
     /// The underlying libp2p service that drives all the network interactions.
     libp2p: LibP2PService<RequestId>,
 
@@ -38,17 +63,43 @@ pub struct RouterService {
     /// The receiver channel for Zgs to communicate with the pruner service.
     pruner_recv: Option<mpsc::UnboundedReceiver<PrunerMessage>>,
 
+    /// Log sync events to retract the announced files once reverted.
+    log_sync_recv: Option<broadcast::Receiver<LogSyncEvent>>,
+
+    /// Status of log sync, e.g. whether caught up.
+    log_sync_monitor: Option<LogSyncMonitor>,
+
     /// All connected peers.
     peers: Arc<RwLock<PeerManager>>,
 
     /// Handler for libp2p events.
     libp2p_event_handler: Libp2pEventHandler,
 
-    /// Stores potentially created UPnP mappings to be removed on shutdown. (TCP port and UDP
-    /// port).
-    upnp_mappings: (Option<u16>, Option<u16>),
+    /// Stores potentially created port mappings to be removed on shutdown.
+    port_mapping: Option<PortMapping>,
 
     store: Arc<dyn LogStore>,
+
+    /// Files finalized in store to announce.
+    finalization_recv: FinalizationSubscriber,
+
+    file_location_cache: Arc<FileLocationCache>,
+
+    /// Recently connected peers persisted in db.
+    known_peers: KnownPeers,
+
+    /// Rate limits the announcements of the local shard config.
+    shard_announcer: ShardAnnouncer,
+
+    /// Defers the announcements of finalized files until confirmed on chain.
+    announce_gate: AnnounceGate,
+
+    /// Announces the finalized files in store at a bounded rate.
+    drip_announcer: DripAnnouncer,
+
+    /// Senders to respond once the peers dialed by `NetworkMessage::ConnectPeer` are connected
+    /// or failed to dial.
+    pending_connects: HashMap<PeerId, Vec<oneshot::Sender<Result<(), String>>>>,
 }
 
 impl RouterService {
@@ -63,20 +114,34 @@ impl RouterService {
         _miner_send: Option<broadcast::Sender<MinerMessage>>,
         chunk_pool_send: UnboundedSender<ChunkPoolMessage>,
         pruner_recv: Option<mpsc::UnboundedReceiver<PrunerMessage>>,
+        log_sync_recv: Option<broadcast::Receiver<LogSyncEvent>>,
+        log_sync_monitor: Option<LogSyncMonitor>,
         store: Arc<dyn LogStore>,
         file_location_cache: Arc<FileLocationCache>,
         local_keypair: Keypair,
+        known_peers: KnownPeers,
         config: Config,
+        config_recv: watch::Receiver<DynamicConfig>,
     ) {
         let peers = Arc::new(RwLock::new(PeerManager::new(config.clone())));
+        let shard_announcer = ShardAnnouncer::new(config.shard_config_announce_interval);
+        let drip_announcer = DripAnnouncer::new(
+            store.clone(),
+            config.drip_announce_rate,
+            config.drip_announce_jitter,
+            config.file_reannounce_interval,
+        );
 
         // create the network service and spawn the task
         let router = RouterService {
             config: config.clone(),
+            config_recv,
             libp2p,
             network_globals: network_globals.clone(),
             network_recv,
             pruner_recv,
+            log_sync_recv,
+            log_sync_monitor,
             peers: peers.clone(),
             libp2p_event_handler: Libp2pEventHandler::new(
                 config,
@@ -86,11 +151,18 @@ impl RouterService {
                 chunk_pool_send,
                 local_keypair,
                 Store::new(store.clone(), executor.clone()),
-                file_location_cache,
+                file_location_cache.clone(),
                 peers,
             ),
-            upnp_mappings: (None, None),
+            port_mapping: None,
+            finalization_recv: store.subscribe_finalization(),
             store,
+            file_location_cache,
+            known_peers,
+            shard_announcer,
+            announce_gate: AnnounceGate::new(),
+            drip_announcer,
+            pending_connects: HashMap::new(),
         };
 
         // spawn service
@@ -102,8 +174,13 @@ impl RouterService {
     async fn main(mut self, mut shutdown_sender: Sender<ShutdownReason>) {
         let mut heartbeat_service = interval(self.config.heartbeat_interval);
         let mut heartbeat_batcher = interval(self.config.batcher_timeout);
+        let mut heartbeat_known_peers = interval(KNOWN_PEERS_UPDATE_INTERVAL);
+
+        self.dial_known_peers();
 
         loop {
+            let next_drip_announce = Instant::from_std(self.drip_announcer.next_tick());
+
             tokio::select! {
                 // handle a message sent to the network
                 Some(msg) = self.network_recv.recv() => self.on_network_msg(msg, &mut shutdown_sender).await,
@@ -113,11 +190,29 @@ impl RouterService {
 
                 Some(msg) = Self::try_recv(&mut self.pruner_recv) => self.on_pruner_msg(msg).await,
 
+                // retract announced files once reverted
+                Some(event) = Self::try_recv_log_sync(&mut self.log_sync_recv) => self.on_log_sync_event(event),
+
+                // announce files finalized in store
+                event = self.finalization_recv.recv() => self.on_finalization_event(event),
+
                 // heartbeat for service
                 _ = heartbeat_service.tick() => self.on_heartbeat().await,
 
                 // heartbeat for expire file batcher
                 _ = heartbeat_batcher.tick() => self.libp2p_event_handler.expire_batcher().await,
+
+                // record connected peers for reconnection after restart
+                _ = heartbeat_known_peers.tick() => self.update_known_peers(),
+
+                // dynamic config reloaded
+                Ok(()) = self.config_recv.changed() => {
+                    let dynamic = *self.config_recv.borrow_and_update();
+                    self.on_config_reloaded(dynamic);
+                }
+
+                // announce the finalized files in store within the rate limit
+                _ = sleep_until(next_drip_announce), if self.drip_announcer.enabled() => self.drip_announce(),
             }
         }
     }
@@ -129,6 +224,21 @@ impl RouterService {
         }
     }
 
+    async fn try_recv_log_sync(
+        maybe_recv: &mut Option<broadcast::Receiver<LogSyncEvent>>,
+    ) -> Option<LogSyncEvent> {
+        let recv = maybe_recv.as_mut()?;
+        loop {
+            match recv.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!(%n, "Router lagged behind log sync events");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
     /// Handle an event received from the network.
     async fn on_libp2p_event(
         &mut self,
@@ -140,11 +250,13 @@ impl RouterService {
         match ev {
             Libp2pEvent::Behaviour(event) => match event {
                 BehaviourEvent::PeerConnectedOutgoing(peer_id) => {
+                    self.on_connect_result(peer_id, Ok(()));
                     self.libp2p_event_handler
                         .on_peer_connected(peer_id, true)
                         .await;
                 }
                 BehaviourEvent::PeerConnectedIncoming(peer_id) => {
+                    self.on_connect_result(peer_id, Ok(()));
                     self.libp2p_event_handler
                         .on_peer_connected(peer_id, false)
                         .await;
@@ -179,8 +291,10 @@ impl RouterService {
                         .on_rpc_response(peer_id, id, response)
                         .await;
                 }
-                BehaviourEvent::RPCFailed { id, peer_id } => {
-                    self.libp2p_event_handler.on_rpc_error(peer_id, id).await;
+                BehaviourEvent::RPCFailed { id, peer_id, error } => {
+                    self.libp2p_event_handler
+                        .on_rpc_error(peer_id, id, error)
+                        .await;
                 }
                 BehaviourEvent::StatusPeer(peer_id) => {
                     self.libp2p_event_handler.send_status(peer_id);
@@ -210,6 +324,9 @@ impl RouterService {
                     .write()
                     .push(multiaddr);
             }
+            Libp2pEvent::DialFailed { peer_id, error } => {
+                self.on_connect_result(peer_id, Err(error));
+            }
             Libp2pEvent::ZeroListeners => {
                 let _ = shutdown_sender
                     .send(ShutdownReason::Failure(
@@ -241,7 +358,9 @@ impl RouterService {
                 peer_id,
                 request,
                 request_id,
+                span,
             } => {
+                span.in_scope(|| debug!(%peer_id, ?request_id, "Sending request"));
                 self.libp2p.send_request(peer_id, request_id, request);
                 metrics::SERVICE_ROUTE_NETWORK_MESSAGE_SEND_REQUEST.mark(1);
             }
@@ -332,6 +451,37 @@ impl RouterService {
                     };
                 }
             }
+            NetworkMessage::ConnectPeer {
+                address,
+                peer_id,
+                sender,
+            } => {
+                metrics::SERVICE_ROUTE_NETWORK_MESSAGE_DIAL_PEER.mark(1);
+
+                if self.libp2p.swarm.is_connected(&peer_id) {
+                    let _ = sender.send(Ok(()));
+                } else {
+                    let opts = DialOpts::peer_id(peer_id)
+                        .addresses(vec![address.clone()])
+                        .build();
+                    match Swarm::dial(&mut self.libp2p.swarm, opts) {
+                        Ok(()) => {
+                            debug!(%address, %peer_id, "Dialing libp2p peer");
+                            // drop senders of requests that timed out already
+                            self.pending_connects
+                                .retain(|_, senders| senders.iter().any(|s| !s.is_canceled()));
+                            self.pending_connects
+                                .entry(peer_id)
+                                .or_default()
+                                .push(sender);
+                        }
+                        Err(err) => {
+                            info!(%address, error = ?err, "Failed to dial peer");
+                            let _ = sender.send(Err(err.to_string()));
+                        }
+                    }
+                }
+            }
             NetworkMessage::DisconnectPeer { peer_id } => {
                 self.disconnect_peer(peer_id);
             }
@@ -345,14 +495,11 @@ impl RouterService {
                 metrics::SERVICE_ROUTE_NETWORK_MESSAGE_ANNOUNCE_LOCAL_FILE.mark(1);
                 debug!(?new_file, "Publish NewFile message");
             }
-            NetworkMessage::UPnPMappingEstablished {
-                tcp_socket,
-                udp_socket,
-            } => {
+            NetworkMessage::PortMappingEstablished { mapping } => {
                 metrics::SERVICE_ROUTE_NETWORK_MESSAGE_UPNP.mark(1);
-                self.upnp_mappings = (tcp_socket.map(|s| s.port()), udp_socket.map(|s| s.port()));
+                self.port_mapping = Some(mapping);
                 // If there is an external TCP port update, modify our local ENR.
-                if let Some(tcp_socket) = tcp_socket {
+                if let Some(tcp_socket) = mapping.tcp_socket {
                     if let Err(e) = self
                         .libp2p
                         .swarm
@@ -363,7 +510,7 @@ impl RouterService {
                         warn!(error = %e, "Failed to update ENR");
                     }
                 }
-                if let Some(udp_socket) = udp_socket {
+                if let Some(udp_socket) = mapping.udp_socket {
                     if let Err(e) = self
                         .libp2p
                         .swarm
@@ -384,14 +531,40 @@ impl RouterService {
                 self.libp2p_event_handler
                     .send_to_chunk_pool(ChunkPoolMessage::ChangeShardConfig(shard_config));
 
-                let shard_config = shared_types::ShardConfig::from(shard_config);
-                self.libp2p_event_handler
-                    .publish(PubsubMessage::AnnounceShardConfig(shard_config.into()));
+                // re-publish ENR and dial peers of the new shard config
+                self.libp2p
+                    .swarm
+                    .behaviour_mut()
+                    .update_shard_config(shard_config.into());
+
+                if let Some(shard_config) = self.shard_announcer.update(shard_config) {
+                    self.announce_shard_config(shard_config);
+                }
             }
         }
     }
 
+    fn announce_shard_config(&self, shard_config: storage::config::ShardConfig) {
+        debug!(?shard_config, "Announce shard config");
+        let shard_config = shared_types::ShardConfig::from(shard_config);
+        self.libp2p_event_handler
+            .publish(PubsubMessage::AnnounceShardConfig(shard_config.into()));
+    }
+
     async fn on_heartbeat(&mut self) {
+        // announce the shard config changed within the rate limit interval
+        if let Some(shard_config) = self.shard_announcer.expire() {
+            self.announce_shard_config(shard_config);
+        }
+
+        // announce the deferred files once confirmed on chain
+        let store = self.store.as_ref();
+        let depth = self.config.announce_confirmation_depth;
+        let tx_ids = self
+            .announce_gate
+            .expire(|tx_id| is_tx_confirmed(store, tx_id.seq, depth));
+        self.announce_files(tx_ids);
+
         let expired_peers = self.peers.write().await.expired_peers();
 
         let num_expired_peers = expired_peers.len() as u64;
@@ -405,6 +578,186 @@ impl RouterService {
         }
     }
 
+    /// Reconnects the known peers of the last run, whose shard config is matched if any.
+    fn dial_known_peers(&mut self) {
+        let candidates = match self.known_peers.dial_candidates() {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                warn!(error = ?e, "Failed to update known peers");
+                return;
+            }
+        };
+
+        let shard_config = self.store.get_shard_config();
+        let num_candidates = candidates.len();
+        for peer in candidates {
+            if self.libp2p.swarm.is_connected(&peer.peer_id) {
+                continue;
+            }
+
+            if matches!(peer.shard_config, Some(v) if !shard_config.intersect(&v)) {
+                continue;
+            }
+
+            let peer_id = peer.peer_id;
+            let opts = DialOpts::peer_id(peer_id).addresses(peer.addresses).build();
+            if let Err(err) = Swarm::dial(&mut self.libp2p.swarm, opts) {
+                debug!(%peer_id, error = ?err, "Failed to dial known peer");
+            }
+        }
+
+        info!(%num_candidates, "Dialing known peers");
+    }
+
+    fn on_config_reloaded(&mut self, dynamic: DynamicConfig) {
+        info!(?dynamic, "Router config reloaded");
+        self.config.set_dynamic(dynamic);
+        self.shard_announcer
+            .set_interval(dynamic.shard_config_announce_interval);
+        self.drip_announcer.set_rate(dynamic.drip_announce_rate);
+    }
+
+    /// Records the connected peers that behave well as known peers, and persists them in db.
+    fn update_known_peers(&mut self) {
+        for (peer_id, info) in self.network_globals.peers.read().connected_peers() {
+            let score = info.score().score();
+            if score < 0.0 {
+                continue;
+            }
+
+            self.known_peers.observe(
+                *peer_id,
+                info.listening_addresses().clone(),
+                self.file_location_cache.get_peer_config(peer_id),
+                match info.client().kind {
+                    ClientKind::Zgs => Some(info.client().version.clone()),
+                    ClientKind::Unknown => None,
+                },
+                score,
+            );
+        }
+
+        if let Err(e) = self.known_peers.save() {
+            warn!(error = ?e, "Failed to save known peers");
+        }
+    }
+
+    /// Announces the files finalized in store to peers. Files finalized in bulk, e.g. while log
+    /// sync is catching up, are left to the drip announcer if enabled.
+    fn on_finalization_event(&mut self, event: FinalizationEvent) {
+        let tx_ids = match event {
+            FinalizationEvent::Finalized(tx_ids) => {
+                let catching_up = matches!(&self.log_sync_monitor, Some(m) if !m.is_caught_up());
+                if self.drip_announcer.enabled() && catching_up {
+                    let seqs = tx_ids.iter().map(|tx_id| tx_id.seq);
+                    if let (Some(start_seq), Some(end_seq)) = (seqs.clone().min(), seqs.max()) {
+                        debug!(%start_seq, %end_seq, "Drip announce files finalized during catch-up");
+                        self.drip_announcer.on_finalized_range(start_seq, end_seq);
+                    }
+                    return;
+                }
+                tx_ids
+            }
+            FinalizationEvent::FinalizedRange {
+                start_seq,
+                end_seq,
+                num_txs,
+            } => {
+                if self.drip_announcer.enabled() {
+                    debug!(%start_seq, %end_seq, %num_txs, "Drip announce coalesced finalized files");
+                    self.drip_announcer.on_finalized_range(start_seq, end_seq);
+                    return;
+                }
+
+                debug!(%start_seq, %end_seq, %num_txs, "Announce coalesced finalized files");
+                self.finalized_tx_ids(start_seq, end_seq)
+            }
+        };
+
+        let store = self.store.as_ref();
+        let depth = self.config.announce_confirmation_depth;
+        let tx_ids = self
+            .announce_gate
+            .admit(tx_ids, |tx_id| is_tx_confirmed(store, tx_id.seq, depth));
+        self.announce_files(tx_ids);
+    }
+
+    /// Announces the next finalized files scanned in store within the rate limit.
+    fn drip_announce(&mut self) {
+        let tx_ids = self.drip_announcer.tick();
+        let store = self.store.as_ref();
+        let depth = self.config.announce_confirmation_depth;
+        let tx_ids = self
+            .announce_gate
+            .admit(tx_ids, |tx_id| is_tx_confirmed(store, tx_id.seq, depth));
+        self.announce_files(tx_ids);
+    }
+
+    /// Publishes `NewFile` messages of the finalized files to peers, which are exchanged by
+    /// sync with the peers connected later as well.
+    fn announce_files(&mut self, tx_ids: Vec<TxID>) {
+        if tx_ids.is_empty() {
+            return;
+        }
+
+        let shard_config = self.store.get_shard_config();
+        metrics::SERVICE_ROUTE_NETWORK_MESSAGE_ANNOUNCE_LOCAL_FILE.mark(tx_ids.len());
+        let msgs = tx_ids
+            .iter()
+            .map(|&tx_id| {
+                let new_file = ShardedFile {
+                    tx_id,
+                    shard_config: shard_config.into(),
+                };
+                PubsubMessage::NewFile(new_file.into())
+            })
+            .collect::<Vec<_>>();
+        debug!(num_files = msgs.len(), "Publish NewFile messages");
+        self.libp2p.swarm.behaviour_mut().publish(msgs);
+        self.libp2p_event_handler
+            .send_to_sync(SyncMessage::FilesAnnounced { tx_ids });
+    }
+
+    /// Retracts the announced files once the txs are reverted on chain, so that peers drop the
+    /// queued syncs of them.
+    fn on_log_sync_event(&mut self, event: LogSyncEvent) {
+        if let LogSyncEvent::Reverted { tx_seq } = event {
+            let tx_ids = self.announce_gate.revert(tx_seq);
+            if !tx_ids.is_empty() {
+                info!(%tx_seq, num_files = tx_ids.len(), "Retract announced files reverted on chain");
+                self.libp2p_event_handler.retract_files(tx_ids);
+            }
+        }
+    }
+
+    /// Reads the latest finalized txs in `[start_seq, end_seq]` from store.
+    fn finalized_tx_ids(&self, start_seq: u64, end_seq: u64) -> Vec<TxID> {
+        let start_seq = start_seq.max(end_seq.saturating_sub(MAX_ANNOUNCED_FILES_IN_RANGE - 1));
+        let mut tx_ids = vec![];
+        for seq in start_seq..=end_seq {
+            match (
+                self.store.get_tx_status(seq),
+                self.store.get_tx_by_seq_number(seq),
+            ) {
+                (Ok(Some(TxStatus::Finalized | TxStatus::ShardFinalized)), Ok(Some(tx))) => {
+                    tx_ids.push(tx.id())
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    warn!(%seq, ?e, "Failed to read finalized tx to announce");
+                }
+                _ => {}
+            }
+        }
+        tx_ids
+    }
+
+    /// Responds to the pending `NetworkMessage::ConnectPeer` requests of the peer, if any.
+    fn on_connect_result(&mut self, peer_id: PeerId, result: Result<(), String>) {
+        for sender in self.pending_connects.remove(&peer_id).unwrap_or_default() {
+            let _ = sender.send(result.clone());
+        }
+    }
+
     fn disconnect_peer(&mut self, peer_id: PeerId) {
         let pm = self.libp2p.swarm.behaviour_mut().peer_manager_mut();
         if pm.is_connected(&peer_id) {
@@ -416,7 +769,10 @@ impl RouterService {
 impl Drop for RouterService {
     fn drop(&mut self) {
         info!("Router service shutdown");
+        self.update_known_peers();
         // attempt to remove port mappings
-        network::nat::remove_mappings(self.upnp_mappings.0, self.upnp_mappings.1);
+        if let Some(mapping) = &self.port_mapping {
+            network::nat::remove_mappings(mapping);
+        }
     }
 }
@@ -0,0 +1,111 @@
+use std::time::{Duration, Instant};
+use storage::config::ShardConfig;
+
+/// `ShardAnnouncer` rate limits the announcements of the local shard config, so that peers
+/// are notified promptly once the coverage changes, e.g. resharded by pruner, without gossip
+/// storms on frequent changes.
+///
+/// At most one announcement is published within `interval`, and the changes in between are
+/// coalesced so that only the latest shard config is announced when the interval elapses.
+pub(crate) struct ShardAnnouncer {
+    interval: Duration,
+    last_announced: Option<Instant>,
+    pending: Option<ShardConfig>,
+}
+
+impl ShardAnnouncer {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_announced: None,
+            pending: None,
+        }
+    }
+
+    /// Changes the interval, e.g. on config reload, which applies to the pending announcement
+    /// as well.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Notifies that the shard config changed, and returns the shard config to announce if
+    /// not rate limited.
+    pub fn update(&mut self, shard_config: ShardConfig) -> Option<ShardConfig> {
+        self.update_with_time(shard_config, Instant::now())
+    }
+
+    fn update_with_time(&mut self, shard_config: ShardConfig, now: Instant) -> Option<ShardConfig> {
+        self.pending = Some(shard_config);
+        self.expire_with_time(now)
+    }
+
+    /// Returns the pending shard config to announce once the interval elapsed.
+    pub fn expire(&mut self) -> Option<ShardConfig> {
+        self.expire_with_time(Instant::now())
+    }
+
+    fn expire_with_time(&mut self, now: Instant) -> Option<ShardConfig> {
+        if let Some(last) = self.last_announced {
+            if now.duration_since(last) < self.interval {
+                return None;
+            }
+        }
+
+        let shard_config = self.pending.take()?;
+        self.last_announced = Some(now);
+        Some(shard_config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shard_config(shard_id: usize, num_shard: usize) -> ShardConfig {
+        ShardConfig {
+            shard_id,
+            num_shard,
+        }
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let interval = Duration::from_secs(10);
+        let mut announcer = ShardAnnouncer::new(interval);
+        let now = Instant::now();
+
+        // announce the first change immediately
+        assert_eq!(
+            announcer.update_with_time(shard_config(0, 2), now),
+            Some(shard_config(0, 2))
+        );
+        assert_eq!(announcer.expire_with_time(now + interval), None);
+
+        // coalesce changes within interval
+        assert_eq!(
+            announcer.update_with_time(shard_config(0, 4), now + Duration::from_secs(1)),
+            None
+        );
+        assert_eq!(
+            announcer.update_with_time(shard_config(0, 8), now + Duration::from_secs(2)),
+            None
+        );
+        assert_eq!(
+            announcer.expire_with_time(now + Duration::from_secs(9)),
+            None
+        );
+        assert_eq!(
+            announcer.expire_with_time(now + interval),
+            Some(shard_config(0, 8))
+        );
+
+        // nothing changed
+        assert_eq!(announcer.expire_with_time(now + interval * 3), None);
+
+        // announce immediately once the interval elapsed
+        assert_eq!(
+            announcer.update_with_time(shard_config(0, 16), now + interval * 3),
+            Some(shard_config(0, 16))
+        );
+    }
+}
@@ -1,17 +1,97 @@
-use crate::types::{LocationInfo, NetworkInfo, PeerInfo};
+use crate::types::{
+    AcceptedAnswerInfo, BlockProgressPage, ChunkPoolInfo, ConfigReloadReport, EarningsInfo,
+    ExportedFile, ExportedMerkleState, ExternalAnswerInfo, FileFilter, FlowBatchMismatch, JobInfo,
+    JobParams, KnownPeerInfo, LocationInfo, LogSyncStatus, MinerRewardInfo, MinerStatus,
+    NetworkInfo, NetworkStats, NodeStatus, PeerDetails, PeerInfo, PeerStatsInfo, PeerStatsSort,
+    ReorgEvent, SealedBatchPage, SealedChunk, StoredFilePage,
+};
 use jsonrpsee::core::RpcResult;
 use jsonrpsee::proc_macros::rpc;
+use network::PeerPolicyConfig;
+use shared_types::{DataRoot, TxSeqOrRoot};
 use std::collections::{BTreeMap, HashMap};
-use sync::{FileSyncInfo, SyncServiceState};
+use sync::{
+    FileSyncControlStatus, FileSyncInfo, FileSyncTrace, RepairFlowRangeInfo, ResyncFileInfo,
+    SyncServiceState,
+};
 
 #[rpc(server, client, namespace = "admin")]
 pub trait Rpc {
+    /// Errors of sync related methods: `202` sync error.
     #[method(name = "findFile")]
     async fn find_file(&self, tx_seq: u64) -> RpcResult<()>;
 
     #[method(name = "shutdown")]
     async fn shutdown(&self) -> RpcResult<()>;
 
+    /// Returns the node uptime and the timing breakdown of the last startup, e.g. `db_open`,
+    /// `tx_store_init`, `flow_tree_load`, `last_chunk_rebuild`, `network_init` and
+    /// `peer_discovery`. Phases that complete in background are listed once completed, and the
+    /// status is degraded with `tree loading` until the flow tree is loaded in background.
+    #[method(name = "getStatus")]
+    async fn get_status(&self) -> RpcResult<NodeStatus>;
+
+    /// Reloads the config file, and applies the changed parameters that are dynamic, i.e.
+    /// `sync.max_sync_files`, `sync.max_bandwidth_bytes`, `sync.max_requests_per_peer`,
+    /// `miner_cpu_percentage`, `mine_iter_batch_size`, `db_max_num_sectors`, `prune_batch_size`
+    /// and `prune_batch_wait_time_ms`. Other changed parameters are rejected, which require to
+    /// restart the node. The same happens on `SIGHUP`.
+    ///
+    /// Errors: `-32601` not supported, `-32603` failed to read config file.
+    #[method(name = "reloadConfig")]
+    async fn reload_config(&self) -> RpcResult<ConfigReloadReport>;
+
+    /// Replaces the log filter, e.g. `sync=debug,storage=info`, which is not persisted and
+    /// will be overridden once the log config file changed.
+    ///
+    /// Errors: `-32601` not supported, `-32602` invalid filter.
+    #[method(name = "setLogFilter")]
+    async fn set_log_filter(&self, filter: String) -> RpcResult<()>;
+
+    /// Exports a finalized file to `path`, which is relative to `rpc.export_dir` unless
+    /// absolute, and returns the sha256 of the written file. Existing files are never
+    /// overwritten, and paths out of `rpc.export_dir` are rejected.
+    ///
+    /// Errors: `-32601` not supported if `rpc.export_dir` not configured, `-32602` invalid path,
+    /// `102` file not found, `103` file not finalized, `105` file pruned, `108` file size
+    /// mismatch.
+    #[method(name = "exportFile")]
+    async fn export_file(
+        &self,
+        tx_seq_or_root: TxSeqOrRoot,
+        path: String,
+    ) -> RpcResult<ExportedFile>;
+
+    /// Exports all the nodes of the flow merkle tree, taken at once under the tree lock, in the
+    /// ssz format of `storage::log_store::merkle_state`, so that the trees of nodes could be
+    /// diffed offline by `zgs_node db diff-merkle-state`. The state is written into a new file
+    /// at `path`, resolved in the same way as `admin_exportFile`, or returned inline if `path`
+    /// is omitted, which is limited to 4 MiB.
+    ///
+    /// Errors: `-32601` not supported if `path` given but `rpc.export_dir` not configured,
+    /// `-32602` invalid path or the state too large to return inline.
+    #[method(name = "exportMerkleState")]
+    async fn export_merkle_state(&self, path: Option<String>) -> RpcResult<ExportedMerkleState>;
+
+    /// Starts a long-running job in background, i.e. a `scrub` of the flow or the export of a
+    /// file, which continues once the request returns and resumes from the last checkpoint
+    /// after restart. The progress and result could be polled via `admin_getJob`.
+    ///
+    /// Errors: the same as `admin_verifyFlowRange` and `admin_exportFile` respectively, `201`
+    /// storage error, `205` read-only.
+    #[method(name = "startJob")]
+    async fn start_job(&self, params: JobParams) -> RpcResult<JobInfo>;
+
+    /// Returns the job of `id`, including the finished ones up to `max_finished_jobs` of the
+    /// RPC config, beyond which the oldest finished jobs are removed.
+    #[method(name = "getJob")]
+    async fn get_job(&self, id: u64) -> RpcResult<Option<JobInfo>>;
+
+    /// Cancels the running job, which stops after the current step. A cancelled export removes
+    /// the partially written file. Returns `false` if the job is not found or already finished.
+    #[method(name = "cancelJob")]
+    async fn cancel_job(&self, id: u64) -> RpcResult<bool>;
+
     #[method(name = "startSyncFile")]
     async fn start_sync_file(&self, tx_seq: u64) -> RpcResult<()>;
 
@@ -36,12 +116,190 @@ pub trait Rpc {
     #[method(name = "getSyncInfo")]
     async fn get_sync_info(&self, tx_seq: Option<u64>) -> RpcResult<HashMap<u64, FileSyncInfo>>;
 
+    /// Resync a corrupted file from peers.
+    ///
+    /// If `verify_first` is `true`, only the segments that fail to verify against the file root
+    /// are removed and synced again. Otherwise, all segments are synced again. The file sync
+    /// could be tracked via `admin_getSyncInfo` with the returned `txSeq`, and calling this again
+    /// during the file sync takes no effect.
+    ///
+    /// Errors: `102` tx not found, `105` file pruned, `201` storage error, `202` sync error.
+    #[method(name = "resyncFile")]
+    async fn resync_file(&self, tx_seq: u64, verify_first: bool) -> RpcResult<ResyncFileInfo>;
+
+    /// Verifies the local data of the flow chunks `[start_chunk, end_chunk)` against the flow
+    /// tree, e.g. after the data dir migrated to a new disk, and returns the entry batches in the
+    /// local shard whose data are missing or corrupted. Note that the padding between files is
+    /// reported missing until padded in background after startup. At most 1024 entry batches,
+    /// i.e. `1024 * 1024` chunks, are verified at a time.
+    ///
+    /// Errors: `-32602` invalid or too large range, `201` storage error.
+    #[method(name = "verifyFlowRange")]
+    async fn verify_flow_range(
+        &self,
+        start_chunk: u64,
+        end_chunk: u64, // exclusive
+    ) -> RpcResult<Vec<FlowBatchMismatch>>;
+
+    /// Removes the entry batches that fail `admin_verifyFlowRange`, and syncs only the chunks of
+    /// the removed batches again from peers, which could be tracked via `admin_getSyncInfo`
+    /// with the returned `txSeqs`. Batches shared by several files or padding are skipped, and
+    /// could be repaired via `admin_resyncFile` of the files instead. The range is bounded the
+    /// same as `admin_verifyFlowRange`.
+    ///
+    /// Errors: `-32602` invalid or too large range, `201` storage error, `202` sync error,
+    /// `205` read-only.
+    #[method(name = "repairFlowRange")]
+    async fn repair_flow_range(
+        &self,
+        start_chunk: u64,
+        end_chunk: u64, // exclusive
+    ) -> RpcResult<RepairFlowRangeInfo>;
+
+    /// Cancels the file sync with its requests and peers released, e.g. stuck, which is kept
+    /// as `Failed(Cancelled(Manual))` until retried.
+    #[method(name = "terminateFileSync")]
+    async fn terminate_file_sync(&self, tx_seq: u64) -> RpcResult<FileSyncControlStatus>;
+
+    /// Restarts the file sync at high priority with the request failures cleared, or starts to
+    /// sync the file if not in sync.
+    #[method(name = "retryFileSync")]
+    async fn retry_file_sync(&self, tx_seq: u64) -> RpcResult<FileSyncControlStatus>;
+
+    /// Starts to record the detailed events of the file sync, e.g. peers, requests and proof
+    /// verifications, from scratch, or stops recording if `enable` is `false`. The file synced
+    /// later is also recorded once enabled. Returns whether the file is in sync.
+    #[method(name = "traceFileSync")]
+    async fn trace_file_sync(&self, tx_seq: u64, enable: bool) -> RpcResult<bool>;
+
+    /// Latest events recorded of the file sync traced by `traceFileSync`, or `null` if not
+    /// traced.
+    #[method(name = "getFileSyncTrace")]
+    async fn get_file_sync_trace(&self, tx_seq: u64) -> RpcResult<Option<FileSyncTrace>>;
+
+    /// Files buffered in chunk pool, from the oldest cached file to the files being written
+    /// into store, along with the totals of the pool.
+    #[method(name = "getChunkPoolStatus")]
+    async fn get_chunk_pool_status(&self) -> RpcResult<ChunkPoolInfo>;
+
+    /// Drops the cached segments of an upload whose tx is not retrieved from blockchain yet,
+    /// e.g. a stuck upload. Returns `false` if the file is not pending in chunk pool.
+    #[method(name = "evictPendingFile")]
+    async fn evict_pending_file(&self, data_root: DataRoot) -> RpcResult<bool>;
+
     #[method(name = "getNetworkInfo")]
     async fn get_network_info(&self) -> RpcResult<NetworkInfo>;
 
+    /// Connection counts against the connection budget, i.e. inbound and outbound peers, peers
+    /// in the reserved slots for file sync and mining, and the number of evicted peers. Besides,
+    /// whether the node is publicly reachable, e.g. via UPnP or NAT-PMP port mapping, and the
+    /// messages and bytes per protocol and direction, with the peers of the most RPC traffic.
+    #[method(name = "getNetworkStats")]
+    async fn get_network_stats(&self) -> RpcResult<NetworkStats>;
+
+    /// Status of log sync, including the latest, confirmed and synced block numbers, whether
+    /// log sync is stalled, and the blockchain rpc endpoints with the latency and head of each
+    /// in the last health check.
+    #[method(name = "getLogSyncStatus")]
+    async fn get_log_sync_status(&self) -> RpcResult<LogSyncStatus>;
+
+    /// Rewards of each beneficiary from the `DistributeReward` events subscribed via
+    /// `log_sync_event_subscriptions`, which only covers the events synced since the node started.
+    #[method(name = "getEarnings")]
+    async fn get_earnings(&self) -> RpcResult<Vec<EarningsInfo>>;
+
+    /// PoRA answers submitted in the epochs `[from_epoch, to_epoch]`, which are accepted once the
+    /// `NewSubmission` event of the mine contract is synced, with the rewards from the
+    /// `DistributeReward` events of the same tx. Both events should be subscribed via
+    /// `log_sync_event_subscriptions`.
+    #[method(name = "getMinerRewards")]
+    async fn get_miner_rewards(
+        &self,
+        from_epoch: u64,
+        to_epoch: u64,
+    ) -> RpcResult<Vec<MinerRewardInfo>>;
+
+    /// Whether mining is enabled, and the totals of all the PoRA answers submitted.
+    #[method(name = "getMinerStatus")]
+    async fn get_miner_status(&self) -> RpcResult<MinerStatus>;
+
+    /// Exports the sealed data of the load chunks in `[from_chunk, to_chunk)` along with the
+    /// current mine puzzle, so that PoRA hashing could run on a separate box. At most 16 load
+    /// chunks are returned at a time, and the rest are requested again from `next`.
+    #[method(name = "streamSealedBatches")]
+    async fn stream_sealed_batches(
+        &self,
+        from_chunk: u64,
+        to_chunk: u64,
+    ) -> RpcResult<SealedBatchPage>;
+
+    /// Returns the sealed data of a load chunk as stored without unsealing, along with the seal
+    /// contexts, the miner id and the flow proof of the chunk root, so that the chunk could be
+    /// verified offline. Errors with `FlowEntriesOutOfShard` if the chunk is out of the local
+    /// shard, or `FlowEntriesUnavailable` if not stored locally, e.g. pruned.
+    #[method(name = "getSealedChunk")]
+    async fn get_sealed_chunk(&self, chunk_index: u64) -> RpcResult<SealedChunk>;
+
+    /// Validates the answer found by an external prover against the local sealed data, in the
+    /// same way as the internal hashing, and submits it with the proof generated locally. The
+    /// submission result could be queried by `admin_getMinerRewards`.
+    #[method(name = "submitExternalAnswer")]
+    async fn submit_external_answer(
+        &self,
+        answer: ExternalAnswerInfo,
+    ) -> RpcResult<AcceptedAnswerInfo>;
+
     #[method(name = "getPeers")]
     async fn get_peers(&self) -> RpcResult<HashMap<String, PeerInfo>>;
 
+    /// Dials the peer of the multiaddr, which should end with `/p2p/<peer_id>`, and waits until
+    /// connected. Returns the peer id.
+    ///
+    /// Errors: `-32602` invalid address, `-32603` failed to dial or connect in time.
+    #[method(name = "connectPeer")]
+    async fn connect_peer(&self, address: String) -> RpcResult<String>;
+
+    /// Disconnects the peer gracefully. Returns `false` if the peer is not connected.
+    #[method(name = "disconnectPeer")]
+    async fn disconnect_peer(&self, peer_id: String) -> RpcResult<bool>;
+
+    /// Connection state, negotiated protocols, shard config, score, RPC counters and the last
+    /// error of the peer. Returns `None` if the peer is unknown.
+    #[method(name = "getPeerInfo")]
+    async fn get_peer_info(&self, peer_id: String) -> RpcResult<Option<PeerDetails>>;
+
+    /// Replaces the allowlist and denylist of peers, and disconnects the connected peers that
+    /// are not allowed anymore via any of their addresses. Returns the number of disconnected
+    /// peers. The update is not persisted. Once switched to private network mode, discovery
+    /// queries stop at once, but the node is still advertised via discovery until restarted
+    /// with `network_allowlist` configured.
+    #[method(name = "updatePeerPolicy")]
+    async fn update_peer_policy(&self, policy: PeerPolicyConfig) -> RpcResult<usize>;
+
+    /// Recently connected peers persisted in db, which are reconnected after restart. The most
+    /// recently seen peer comes first.
+    #[method(name = "getKnownPeers")]
+    async fn get_known_peers(&self) -> RpcResult<Vec<KnownPeerInfo>>;
+
+    /// The latest txs reverted on chain reorg since the node started, from the oldest to the
+    /// latest. At most 32 events are kept.
+    #[method(name = "getReorgHistory")]
+    async fn get_reorg_history(&self) -> RpcResult<Vec<ReorgEvent>>;
+
+    /// Lists the block hashes stored by log sync from `from_block` in ascending order, along with
+    /// the sync progress, to inspect the reorg state without stopping the node.
+    ///
+    /// `limit` is capped at 1000.
+    ///
+    /// Errors: `201` storage error.
+    #[method(name = "getBlockProgress")]
+    async fn get_block_progress(
+        &self,
+        from_block: u64,
+        limit: usize,
+    ) -> RpcResult<BlockProgressPage>;
+
+    /// Errors: `102` tx not found, `201` storage error.
     #[method(name = "getFileLocation")]
     async fn get_file_location(
         &self,
@@ -54,4 +312,28 @@ pub trait Rpc {
         &self,
         maybe_prefix: Option<String>,
     ) -> RpcResult<BTreeMap<String, String>>;
+
+    /// List the locally stored files from `start_tx_seq` in ascending order.
+    ///
+    /// `limit` is capped at 1000, and a single call scans a bounded number of txs, so a page
+    /// may contain less files than `limit` while `next_cursor` is not `None`.
+    ///
+    /// Errors: `201` storage error.
+    #[method(name = "listFiles")]
+    async fn list_files(
+        &self,
+        start_tx_seq: u64,
+        limit: usize,
+        filter: Option<FileFilter>,
+    ) -> RpcResult<StoredFilePage>;
+
+    /// Returns the contribution of peers to file sync, which is kept across restarts for the
+    /// known peers, in descending order of `sort_by`, which defaults to `bytesReceived`. At most
+    /// `limit` peers are returned if specified.
+    #[method(name = "getPeerStats")]
+    async fn get_peer_stats(
+        &self,
+        sort_by: Option<PeerStatsSort>,
+        limit: Option<usize>,
+    ) -> RpcResult<Vec<PeerStatsInfo>>;
 }
@@ -0,0 +1,184 @@
+use crate::error::{self, RpcErrorCode};
+use crate::types::ExportedFile;
+use futures::{Stream, StreamExt};
+use jsonrpsee::core::RpcResult;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use shared_types::Transaction;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+/// Resolves the path to export a file, which is relative to the export directory unless
+/// absolute. Paths out of the export directory are rejected, including those via `..` or
+/// symbolic links.
+pub(crate) fn resolve_export_path(export_dir: &Path, path: &str) -> Result<PathBuf, String> {
+    let export_dir = export_dir
+        .canonicalize()
+        .map_err(|e| format!("invalid export directory: {}", e))?;
+    let path = export_dir.join(path);
+    let file_name = path.file_name().ok_or("file name missing")?;
+    let parent = path
+        .parent()
+        .ok_or("parent directory missing")?
+        .canonicalize()
+        .map_err(|e| format!("invalid parent directory: {}", e))?;
+    if !parent.starts_with(&export_dir) {
+        return Err(format!(
+            "out of the export directory {}",
+            export_dir.display()
+        ));
+    }
+
+    Ok(parent.join(file_name))
+}
+
+/// Maximum size of the merkle state returned inline by `admin_exportMerkleState`.
+pub(crate) const MAX_INLINE_MERKLE_STATE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Creates a new file at `path`, which fails if the path already exists.
+async fn create_new_file(path: &Path) -> RpcResult<File> {
+    match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .await
+    {
+        Ok(file) => Ok(file),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            Err(error::invalid_params("path", "file already exists"))
+        }
+        Err(e) => Err(error::internal_error(format!(
+            "Failed to create file: {:?}",
+            e
+        ))),
+    }
+}
+
+/// Writes the file data into a new file at `path`, which fails if the path already exists.
+/// The written file is removed unless the whole file is exported.
+pub(crate) async fn export_file(
+    tx: &Transaction,
+    data: impl Stream<Item = anyhow::Result<Vec<u8>>>,
+    path: &Path,
+) -> RpcResult<ExportedFile> {
+    let mut file = create_new_file(path).await?;
+
+    let result = write_file(&mut file, tx, data).await;
+    drop(file);
+
+    match result {
+        Ok((size, sha256)) => Ok(ExportedFile {
+            tx_seq: tx.seq,
+            path: path.display().to_string(),
+            size,
+            sha256,
+        }),
+        Err(e) => {
+            if let Err(e) = fs::remove_file(path).await {
+                warn!(?e, path = %path.display(), "Failed to remove partially exported file");
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Writes `data` into a new file at `path`, which fails if the path already exists.
+pub(crate) async fn export_bytes(data: &[u8], path: &Path) -> RpcResult<()> {
+    let mut file = create_new_file(path).await?;
+    let result = async {
+        file.write_all(data).await?;
+        file.sync_all().await
+    }
+    .await;
+    drop(file);
+
+    if let Err(e) = result {
+        if let Err(e) = fs::remove_file(path).await {
+            warn!(?e, path = %path.display(), "Failed to remove partially exported file");
+        }
+        return Err(error::internal_error(format!(
+            "Failed to write file: {:?}",
+            e
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the number of bytes written and the hex encoded sha256.
+async fn write_file(
+    file: &mut File,
+    tx: &Transaction,
+    data: impl Stream<Item = anyhow::Result<Vec<u8>>>,
+) -> RpcResult<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+
+    futures::pin_mut!(data);
+    while let Some(batch) = data.next().await {
+        let batch = batch.map_err(error::storage_error)?;
+        file.write_all(&batch)
+            .await
+            .map_err(|e| error::internal_error(format!("Failed to write file: {:?}", e)))?;
+        hasher.update(&batch);
+        size += batch.len() as u64;
+    }
+
+    if size != tx.size {
+        return Err(error::mismatch(
+            RpcErrorCode::FileSizeMismatch,
+            json!(tx.size),
+            json!(size),
+        ));
+    }
+
+    file.sync_all()
+        .await
+        .map_err(|e| error::internal_error(format!("Failed to sync file: {:?}", e)))?;
+
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_resolve_export_path() {
+        let dir = std::env::temp_dir().join(format!("zgs_export_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let export_dir = dir.join("export");
+        fs::create_dir_all(export_dir.join("sub")).unwrap();
+        let export_dir = export_dir.canonicalize().unwrap();
+
+        assert_eq!(
+            resolve_export_path(&export_dir, "a.bin").unwrap(),
+            export_dir.join("a.bin")
+        );
+        assert_eq!(
+            resolve_export_path(&export_dir, "sub/../b.bin").unwrap(),
+            export_dir.join("b.bin")
+        );
+        let absolute = export_dir.join("sub/c.bin");
+        assert_eq!(
+            resolve_export_path(&export_dir, absolute.to_str().unwrap()).unwrap(),
+            absolute
+        );
+
+        assert!(resolve_export_path(&export_dir, "../a.bin").is_err());
+        assert!(resolve_export_path(&export_dir, "sub/..").is_err());
+        assert!(resolve_export_path(&export_dir, "").is_err());
+        assert!(resolve_export_path(&export_dir, "missing/a.bin").is_err());
+        assert!(resolve_export_path(&export_dir, dir.join("a.bin").to_str().unwrap()).is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&dir, export_dir.join("link")).unwrap();
+            assert!(resolve_export_path(&export_dir, "link/a.bin").is_err());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
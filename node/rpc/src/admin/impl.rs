@@ -1,16 +1,57 @@
 use super::api::RpcServer;
-use crate::types::{LocationInfo, NetworkInfo, PeerInfo};
+use super::export;
+use crate::types::{
+    AcceptedAnswerInfo, BlockProgressPage, ChunkPoolInfo, ConfigReloadReport, EarningsInfo,
+    ExportedFile, ExportedMerkleState, ExternalAnswerInfo, FileFilter, FlowBatchMismatch, JobInfo,
+    JobParams, KnownPeerInfo, LocationInfo, LogSyncStatus, MinerRewardInfo, MinerStatus,
+    NetworkInfo, NetworkStats, NodeStatus, PeerDetails, PeerInfo, PeerStatsInfo, PeerStatsSort,
+    ReorgEvent, RpcEndpointInfo, SealedBatch, SealedBatchPage, SealedChunk, StartupPhaseInfo,
+    StoredFile, StoredFilePage, StoredFileStatus,
+};
 use crate::{error, Context};
+use futures::channel::oneshot;
 use futures::prelude::*;
 use jsonrpsee::core::async_trait;
 use jsonrpsee::core::RpcResult;
 use metrics::{DEFAULT_GROUPING_REGISTRY, DEFAULT_REGISTRY};
-use network::{multiaddr::Protocol, Multiaddr};
+use network::{
+    multiaddr::Protocol, EnrExt, Multiaddr, NetworkMessage, PeerId, PeerPolicy, PeerPolicyConfig,
+};
+use serde_json::json;
+use shared_types::{DataRoot, Transaction, TxSeqOrRoot};
 use std::collections::{BTreeMap, HashMap};
 use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 use storage::config::all_shards_available;
-use sync::{FileSyncInfo, SyncRequest, SyncResponse, SyncServiceState};
+use storage::error::StoreError;
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+use storage::log_store::tx_store::TxStatus;
+use sync::{
+    FileSyncControlStatus, FileSyncInfo, FileSyncTrace, RepairFlowRangeInfo, ResyncFileInfo,
+    SyncRequest, SyncResponse, SyncServiceState,
+};
 use task_executor::ShutdownReason;
+use tokio::sync::mpsc;
+use zgs_miner::MinerMessage;
+
+/// Maximum number of files returned by `admin_listFiles`.
+const MAX_LIST_FILES_LIMIT: usize = 1000;
+/// Number of txs read from the store at a time by `admin_listFiles`.
+const LIST_FILES_BATCH_SIZE: usize = 256;
+/// Maximum number of txs scanned by a single `admin_listFiles` call.
+const LIST_FILES_MAX_SCAN: usize = 16 * 1024;
+/// Maximum number of blocks returned by `admin_getBlockProgress`.
+const MAX_BLOCK_PROGRESS_LIMIT: usize = 1000;
+/// Timeout for `admin_connectPeer` to wait until the peer is connected.
+const CONNECT_PEER_TIMEOUT: Duration = Duration::from_secs(10);
+/// Maximum number of load chunks returned by `admin_streamSealedBatches` at a time.
+const MAX_SEALED_BATCHES: u64 = 16;
+/// Number of peers of the most traffic returned by `admin_getNetworkStats`.
+const NETWORK_STATS_TOP_PEERS: usize = 10;
+/// Maximum number of chunks verified by `admin_verifyFlowRange` or `admin_repairFlowRange`.
+const MAX_FLOW_RANGE_CHUNKS: u64 = 1024 * PORA_CHUNK_SIZE as u64;
 
 pub struct RpcServerImpl {
     pub ctx: Context,
@@ -32,7 +73,7 @@ impl RpcServer for RpcServerImpl {
                 if err.is_empty() {
                     Ok(())
                 } else {
-                    Err(error::internal_error(err))
+                    Err(error::sync_error(err))
                 }
             }
             _ => Err(error::internal_error("unexpected response type")),
@@ -51,9 +92,206 @@ impl RpcServer for RpcServerImpl {
             .map_err(|e| error::internal_error(format!("Failed to send shutdown command: {:?}", e)))
     }
 
+    #[tracing::instrument(skip(self), err)]
+    async fn get_status(&self) -> RpcResult<NodeStatus> {
+        info!("admin_getStatus()");
+
+        // the status of the node process is served even if the sync service is unavailable
+        let self_audit = match self.ctx.request_sync(SyncRequest::SelfAuditStatus).await {
+            Ok(SyncResponse::SelfAuditStatus { status }) => status,
+            _ => None,
+        };
+
+        let startup = &self.ctx.startup_phases;
+        Ok(NodeStatus {
+            uptime_secs: startup.uptime().as_secs(),
+            startup_phases: startup
+                .phases()
+                .into_iter()
+                .map(|(name, duration)| StartupPhaseInfo {
+                    name: name.to_string(),
+                    duration_ms: duration.as_millis() as u64,
+                })
+                .collect(),
+            degraded: (!self.ctx.log_store.get_store().is_flow_tree_ready())
+                .then(|| "tree loading".to_string()),
+            self_audit,
+        })
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn reload_config(&self) -> RpcResult<ConfigReloadReport> {
+        info!("admin_reloadConfig()");
+
+        let reloader = self
+            .ctx
+            .config_reloader
+            .as_ref()
+            .ok_or_else(error::not_supported)?;
+        let report = reloader.reload().map_err(error::internal_error)?;
+        info!(%report, "Config reloaded by admin");
+
+        Ok(report)
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn set_log_filter(&self, filter: String) -> RpcResult<()> {
+        info!("admin_setLogFilter({filter})");
+
+        let log_filter = self
+            .ctx
+            .log_filter
+            .as_ref()
+            .ok_or_else(error::not_supported)?;
+        log_filter
+            .set_log_filter(&filter)
+            .map_err(|e| error::invalid_params("filter", e))
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn export_file(
+        &self,
+        tx_seq_or_root: TxSeqOrRoot,
+        path: String,
+    ) -> RpcResult<ExportedFile> {
+        info!("admin_exportFile({tx_seq_or_root:?}, {path})");
+
+        let (tx, path) = self.resolve_export(tx_seq_or_root, &path).await?;
+        let store = &self.ctx.log_store;
+        let data = store.read_file_stream(&tx, self.ctx.config.chunks_per_segment);
+        let exported = export::export_file(&tx, data, &path).await?;
+        info!(
+            tx_seq = tx.seq,
+            path = %exported.path,
+            sha256 = %exported.sha256,
+            "File exported"
+        );
+
+        Ok(exported)
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn export_merkle_state(&self, path: Option<String>) -> RpcResult<ExportedMerkleState> {
+        info!("admin_exportMerkleState({path:?})");
+
+        let path = match path {
+            Some(path) => {
+                let export_dir = self
+                    .ctx
+                    .config
+                    .export_dir
+                    .as_ref()
+                    .ok_or_else(error::not_supported)?;
+                let path = export::resolve_export_path(export_dir, &path)
+                    .map_err(|e| error::invalid_params("path", e))?;
+                Some(path)
+            }
+            None => None,
+        };
+
+        // The state is bounded while being built if returned inline.
+        let max_size = match path {
+            Some(_) => usize::MAX,
+            None => export::MAX_INLINE_MERKLE_STATE_SIZE,
+        };
+        let state = self
+            .ctx
+            .log_store
+            .get_merkle_state(max_size)
+            .await
+            .map_err(|e| match StoreError::of(&e) {
+                Some(StoreError::OutOfRange { bound, .. }) => error::invalid_params(
+                    "path",
+                    format!("merkle state exceeds {} bytes to return inline", bound),
+                ),
+                _ => error::storage_error(e),
+            })?;
+        let data = state.to_bytes();
+        let mut exported = ExportedMerkleState {
+            flow_root: state.flow_root(),
+            flow_length: state.flow_length,
+            flow_version: state.flow_version,
+            size: data.len() as u64,
+            path: None,
+            data: vec![],
+        };
+        match path {
+            Some(path) => {
+                export::export_bytes(&data, &path).await?;
+                exported.path = Some(path.display().to_string());
+                info!(path = %path.display(), size = data.len(), "Merkle state exported");
+            }
+            None => exported.data = data,
+        }
+
+        Ok(exported)
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn start_job(&self, params: JobParams) -> RpcResult<JobInfo> {
+        info!("admin_startJob({params:?})");
+        self.ctx.check_writable()?;
+
+        let params = match params {
+            JobParams::Scrub {
+                start_chunk,
+                end_chunk,
+            } => {
+                let end_chunk = match end_chunk {
+                    Some(end_chunk) => end_chunk,
+                    None => {
+                        self.ctx
+                            .log_store
+                            .get_context()
+                            .await
+                            .map_err(error::storage_error)?
+                            .1
+                    }
+                };
+                check_flow_range(start_chunk, end_chunk)?;
+                JobParams::Scrub {
+                    start_chunk,
+                    end_chunk: Some(end_chunk),
+                }
+            }
+            JobParams::ExportFile {
+                tx_seq_or_root,
+                path,
+            } => {
+                let (tx, path) = self.resolve_export(tx_seq_or_root, &path).await?;
+                if path.exists() {
+                    return Err(error::invalid_params("path", "file already exists"));
+                }
+                JobParams::ExportFile {
+                    tx_seq_or_root: TxSeqOrRoot::TxSeq(tx.seq),
+                    path: path.display().to_string(),
+                }
+            }
+        };
+
+        self.ctx
+            .jobs
+            .start(params)
+            .await
+            .map_err(error::storage_error)
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn get_job(&self, id: u64) -> RpcResult<Option<JobInfo>> {
+        info!("admin_getJob({id})");
+        self.ctx.jobs.get(id).await.map_err(error::storage_error)
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn cancel_job(&self, id: u64) -> RpcResult<bool> {
+        info!("admin_cancelJob({id})");
+        self.ctx.jobs.cancel(id).await.map_err(error::storage_error)
+    }
+
     #[tracing::instrument(skip(self), err)]
     async fn start_sync_file(&self, tx_seq: u64) -> RpcResult<()> {
         info!("admin_startSyncFile({tx_seq})");
+        self.ctx.check_writable()?;
 
         let response = self
             .ctx
@@ -65,7 +303,7 @@ impl RpcServer for RpcServerImpl {
                 if err.is_empty() {
                     Ok(())
                 } else {
-                    Err(error::internal_error(err))
+                    Err(error::sync_error(err))
                 }
             }
             _ => Err(error::internal_error("unexpected response type")),
@@ -80,6 +318,7 @@ impl RpcServer for RpcServerImpl {
         end_index: u64,
     ) -> RpcResult<()> {
         info!("admin_startSyncChunks({tx_seq}, {start_index}, {end_index})");
+        self.ctx.check_writable()?;
 
         let response = self
             .ctx
@@ -95,7 +334,7 @@ impl RpcServer for RpcServerImpl {
                 if err.is_empty() {
                     Ok(())
                 } else {
-                    Err(error::internal_error(err))
+                    Err(error::sync_error(err))
                 }
             }
             _ => Err(error::internal_error("unexpected response type")),
@@ -163,6 +402,179 @@ impl RpcServer for RpcServerImpl {
         }
     }
 
+    #[tracing::instrument(skip(self), err)]
+    async fn resync_file(&self, tx_seq: u64, verify_first: bool) -> RpcResult<ResyncFileInfo> {
+        info!("admin_resyncFile({tx_seq}, {verify_first})");
+        self.ctx.check_writable()?;
+
+        if self
+            .ctx
+            .log_store
+            .get_tx_by_seq_number(tx_seq)
+            .await
+            .map_err(error::storage_error)?
+            .is_none()
+        {
+            return Err(error::file_not_found(json!({ "tx_seq": tx_seq })));
+        }
+
+        if self
+            .ctx
+            .log_store
+            .check_tx_pruned(tx_seq)
+            .await
+            .map_err(error::storage_error)?
+        {
+            return Err(error::file_pruned(tx_seq));
+        }
+
+        if self
+            .ctx
+            .log_store
+            .check_tx_invalid(tx_seq)
+            .await
+            .map_err(error::storage_error)?
+        {
+            return Err(error::file_invalid(tx_seq));
+        }
+
+        let response = self
+            .ctx
+            .request_sync(SyncRequest::ResyncFile {
+                tx_seq,
+                verify_first,
+            })
+            .await?;
+
+        match response {
+            SyncResponse::ResyncFile { result } => result.map_err(error::sync_error),
+            _ => Err(error::internal_error("unexpected response type")),
+        }
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn verify_flow_range(
+        &self,
+        start_chunk: u64,
+        end_chunk: u64,
+    ) -> RpcResult<Vec<FlowBatchMismatch>> {
+        info!("admin_verifyFlowRange({start_chunk}, {end_chunk})");
+        check_flow_range(start_chunk, end_chunk)?;
+
+        let batches = self
+            .ctx
+            .log_store
+            .verify_flow_range(start_chunk, end_chunk)
+            .await
+            .map_err(error::storage_error)?;
+        Ok(batches.into_iter().map(FlowBatchMismatch::new).collect())
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn repair_flow_range(
+        &self,
+        start_chunk: u64,
+        end_chunk: u64,
+    ) -> RpcResult<RepairFlowRangeInfo> {
+        info!("admin_repairFlowRange({start_chunk}, {end_chunk})");
+        self.ctx.check_writable()?;
+        check_flow_range(start_chunk, end_chunk)?;
+
+        // Verified here rather than in the sync service, which never waits for the slow reads.
+        let batches = self
+            .ctx
+            .log_store
+            .verify_flow_range(start_chunk, end_chunk)
+            .await
+            .map_err(error::storage_error)?;
+        if batches.is_empty() {
+            return Ok(Default::default());
+        }
+
+        let response = self
+            .ctx
+            .request_sync(SyncRequest::RepairFlowBatches { batches })
+            .await?;
+
+        match response {
+            SyncResponse::RepairFlowBatches { result } => result.map_err(error::sync_error),
+            _ => Err(error::internal_error("unexpected response type")),
+        }
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn terminate_file_sync(&self, tx_seq: u64) -> RpcResult<FileSyncControlStatus> {
+        info!("admin_terminateFileSync({tx_seq})");
+
+        let response = self
+            .ctx
+            .request_sync(SyncRequest::CancelFileSync { tx_seq })
+            .await?;
+
+        match response {
+            SyncResponse::CancelFileSync { status } => Ok(status),
+            _ => Err(error::internal_error("unexpected response type")),
+        }
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn retry_file_sync(&self, tx_seq: u64) -> RpcResult<FileSyncControlStatus> {
+        info!("admin_retryFileSync({tx_seq})");
+        self.ctx.check_writable()?;
+
+        let response = self
+            .ctx
+            .request_sync(SyncRequest::RetryFileSync { tx_seq })
+            .await?;
+
+        match response {
+            SyncResponse::RetryFileSync { result } => result.map_err(error::sync_error),
+            _ => Err(error::internal_error("unexpected response type")),
+        }
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn trace_file_sync(&self, tx_seq: u64, enable: bool) -> RpcResult<bool> {
+        info!("admin_traceFileSync({tx_seq}, {enable})");
+
+        let response = self
+            .ctx
+            .request_sync(SyncRequest::TraceFileSync { tx_seq, enable })
+            .await?;
+
+        match response {
+            SyncResponse::TraceFileSync { syncing } => Ok(syncing),
+            _ => Err(error::internal_error("unexpected response type")),
+        }
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn get_file_sync_trace(&self, tx_seq: u64) -> RpcResult<Option<FileSyncTrace>> {
+        info!("admin_getFileSyncTrace({tx_seq})");
+
+        let response = self
+            .ctx
+            .request_sync(SyncRequest::GetFileSyncTrace { tx_seq })
+            .await?;
+
+        match response {
+            SyncResponse::FileSyncTrace { trace } => Ok(trace),
+            _ => Err(error::internal_error("unexpected response type")),
+        }
+    }
+
+    async fn get_chunk_pool_status(&self) -> RpcResult<ChunkPoolInfo> {
+        info!("admin_getChunkPoolStatus()");
+
+        Ok(self.ctx.chunk_pool.status().await.into())
+    }
+
+    async fn evict_pending_file(&self, data_root: DataRoot) -> RpcResult<bool> {
+        info!("admin_evictPendingFile({data_root:?})");
+
+        Ok(self.ctx.chunk_pool.evict_pending_file(&data_root).await)
+    }
+
     #[tracing::instrument(skip(self), err)]
     async fn get_network_info(&self) -> RpcResult<NetworkInfo> {
         info!("admin_getNetworkInfo()");
@@ -184,6 +596,205 @@ impl RpcServer for RpcServerImpl {
         })
     }
 
+    async fn get_network_stats(&self) -> RpcResult<NetworkStats> {
+        info!("admin_getNetworkStats()");
+
+        let stats = self.ctx.network_globals.connection_stats.read().clone();
+        let nat_status = self.ctx.network_globals.nat_status.read().clone();
+        let traffic = self
+            .ctx
+            .network_globals
+            .traffic
+            .stats(NETWORK_STATS_TOP_PEERS);
+        Ok(NetworkStats::new(&stats, &nat_status, &traffic))
+    }
+
+    async fn get_log_sync_status(&self) -> RpcResult<LogSyncStatus> {
+        info!("admin_getLogSyncStatus()");
+
+        let monitor = match &self.ctx.log_sync {
+            Some(monitor) => monitor,
+            None => return Ok(LogSyncStatus::default()),
+        };
+        let rpc_client = monitor.rpc_client();
+        let heads = monitor.heads();
+
+        Ok(LogSyncStatus {
+            latest_block_number: heads.latest,
+            confirmed_block_number: heads.confirmed,
+            synced_block_number: monitor.synced_block_number(),
+            lag_blocks: monitor.lag(),
+            seconds_since_progress: monitor.since_last_progress().as_secs(),
+            stalled: monitor.is_stalled(),
+            active_endpoint: rpc_client.active_endpoint(),
+            endpoints: rpc_client
+                .status()
+                .into_iter()
+                .map(|status| RpcEndpointInfo {
+                    url: status.url,
+                    active: status.active,
+                    healthy: status.healthy,
+                    latency_ms: status.latency.map(|latency| latency.as_millis() as u64),
+                    head: status.head,
+                    failures: status.failures,
+                })
+                .collect(),
+        })
+    }
+
+    async fn get_earnings(&self) -> RpcResult<Vec<EarningsInfo>> {
+        info!("admin_getEarnings()");
+
+        let monitor = match &self.ctx.log_sync {
+            Some(monitor) => monitor,
+            None => return Ok(vec![]),
+        };
+
+        Ok(monitor
+            .earnings()
+            .into_iter()
+            .map(|e| EarningsInfo {
+                beneficiary: e.beneficiary,
+                amount: e.amount,
+                rewards: e.rewards,
+                last_block_number: e.last_block_number,
+            })
+            .collect())
+    }
+
+    async fn get_miner_rewards(
+        &self,
+        from_epoch: u64,
+        to_epoch: u64,
+    ) -> RpcResult<Vec<MinerRewardInfo>> {
+        info!("admin_getMinerRewards({from_epoch}, {to_epoch})");
+
+        if from_epoch > to_epoch {
+            return Err(error::invalid_params(
+                "to_epoch",
+                "should not be less than from_epoch",
+            ));
+        }
+
+        let rewards = self
+            .ctx
+            .log_store
+            .get_miner_rewards(from_epoch, to_epoch)
+            .await
+            .map_err(error::storage_error)?;
+        Ok(rewards.into_iter().map(Into::into).collect())
+    }
+
+    async fn get_miner_status(&self) -> RpcResult<MinerStatus> {
+        info!("admin_getMinerStatus()");
+
+        let summary = self
+            .ctx
+            .log_store
+            .get_miner_reward_summary()
+            .await
+            .map_err(error::storage_error)?;
+        let mut status = MinerStatus::new(self.ctx.mine_service_sender.is_some(), summary);
+        if self.ctx.mine_service_sender.is_some() {
+            let context = self.request_miner(MinerMessage::GetContextStatus).await?;
+            status.context = Some(context.into());
+        }
+        Ok(status)
+    }
+
+    async fn stream_sealed_batches(
+        &self,
+        from_chunk: u64,
+        to_chunk: u64,
+    ) -> RpcResult<SealedBatchPage> {
+        debug!(%from_chunk, %to_chunk, "admin_streamSealedBatches()");
+
+        if from_chunk > to_chunk {
+            return Err(error::invalid_params(
+                "to_chunk",
+                "should not be less than from_chunk",
+            ));
+        }
+
+        let puzzle = self
+            .request_miner(MinerMessage::GetMinePuzzle)
+            .await?
+            .ok_or_else(|| error::internal_error("no mine puzzle to solve"))?;
+
+        let end = to_chunk.min(from_chunk.saturating_add(MAX_SEALED_BATCHES));
+        let mut batches = vec![];
+        for chunk_index in from_chunk..end {
+            if let Some(chunk) = self
+                .ctx
+                .log_store
+                .load_sealed_data(chunk_index)
+                .await
+                .map_err(error::storage_error)?
+            {
+                batches.push(SealedBatch::new(chunk_index, chunk));
+            }
+        }
+
+        Ok(SealedBatchPage {
+            puzzle: puzzle.into(),
+            batches,
+            next: (end < to_chunk).then_some(end),
+        })
+    }
+
+    async fn get_sealed_chunk(&self, chunk_index: u64) -> RpcResult<SealedChunk> {
+        debug!(%chunk_index, "admin_getSealedChunk()");
+
+        let entry_index = chunk_index.saturating_mul(PORA_CHUNK_SIZE as u64);
+        let count = PORA_CHUNK_SIZE as u64;
+        let shard_config = self.ctx.log_store.get_store().get_shard_config();
+        if !shard_config.in_range(chunk_index) {
+            return Err(error::flow_entries_out_of_shard(
+                entry_index,
+                count,
+                shard_config.shard_id,
+                shard_config.num_shard,
+            ));
+        }
+
+        let (_, flow_length) = self
+            .ctx
+            .log_store
+            .get_context()
+            .await
+            .map_err(error::storage_error)?;
+        if entry_index >= flow_length {
+            return Err(error::invalid_params(
+                "chunk_index",
+                format!("exceeds flow length {}", flow_length),
+            ));
+        }
+
+        let sealed = self
+            .ctx
+            .log_store
+            .get_sealed_chunk_with_proof(chunk_index)
+            .await
+            .map_err(error::storage_error)?
+            .ok_or_else(|| error::flow_entries_unavailable(entry_index, count))?;
+
+        Ok(SealedChunk::new(chunk_index, sealed))
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn submit_external_answer(
+        &self,
+        answer: ExternalAnswerInfo,
+    ) -> RpcResult<AcceptedAnswerInfo> {
+        info!(?answer, "admin_submitExternalAnswer()");
+        self.ctx.check_writable()?;
+
+        self.request_miner(|reply| MinerMessage::SubmitExternalAnswer(answer.into(), reply))
+            .await?
+            .map(Into::into)
+            .map_err(|e| error::invalid_params("answer", e))
+    }
+
     async fn get_peers(&self) -> RpcResult<HashMap<String, PeerInfo>> {
         info!("admin_getPeers()");
 
@@ -197,6 +808,158 @@ impl RpcServer for RpcServerImpl {
             .collect())
     }
 
+    async fn connect_peer(&self, address: String) -> RpcResult<String> {
+        info!("admin_connectPeer({address})");
+
+        let address: Multiaddr = address
+            .parse()
+            .map_err(|e| error::invalid_params("address", format!("{:?}", e)))?;
+        let peer_id = match address.iter().last() {
+            Some(Protocol::P2p(hash)) => PeerId::from_multihash(hash)
+                .map_err(|_| error::invalid_params("address", "invalid peer id"))?,
+            _ => return Err(error::invalid_params("address", "peer id not specified")),
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        self.ctx.send_network(NetworkMessage::ConnectPeer {
+            address,
+            peer_id,
+            sender,
+        })?;
+
+        match tokio::time::timeout(CONNECT_PEER_TIMEOUT, receiver).await {
+            Ok(Ok(Ok(()))) => Ok(peer_id.to_base58()),
+            Ok(Ok(Err(e))) => Err(error::internal_error(format!("Failed to dial peer: {}", e))),
+            Ok(Err(_)) => Err(error::internal_error("Network service dropped the request")),
+            Err(_) => Err(error::internal_error("Peer not connected in time")),
+        }
+    }
+
+    async fn disconnect_peer(&self, peer_id: String) -> RpcResult<bool> {
+        info!("admin_disconnectPeer({peer_id})");
+
+        let peer_id = parse_peer_id(&peer_id)?;
+        if !self.ctx.network_globals.peers.read().is_connected(&peer_id) {
+            return Ok(false);
+        }
+
+        self.ctx
+            .send_network(NetworkMessage::DisconnectPeer { peer_id })?;
+
+        Ok(true)
+    }
+
+    async fn get_peer_info(&self, peer_id: String) -> RpcResult<Option<PeerDetails>> {
+        info!("admin_getPeerInfo({peer_id})");
+
+        let peer_id = parse_peer_id(&peer_id)?;
+        let peers = self.ctx.network_globals.peers.read();
+        let info = match peers.peer_info(&peer_id) {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+
+        // the shard config announced by the peer, or advertised in ENR
+        let shard_config = self
+            .ctx
+            .file_location_cache
+            .get_peer_config(&peer_id)
+            .or_else(|| {
+                info.enr()
+                    .and_then(|enr| enr.shard_config())
+                    .and_then(|config| config.ok())
+                    .and_then(|config| config.try_into().ok())
+            });
+
+        Ok(Some(PeerDetails::new(&peer_id, info, shard_config)))
+    }
+
+    async fn update_peer_policy(&self, policy: PeerPolicyConfig) -> RpcResult<usize> {
+        info!("admin_updatePeerPolicy({:?})", policy);
+
+        let policy =
+            PeerPolicy::from_config(&policy).map_err(|e| error::invalid_params("policy", e))?;
+
+        let denied_peers: Vec<_> = self
+            .ctx
+            .network_globals
+            .peers
+            .read()
+            .connected_peers()
+            .filter(|(peer_id, info)| !policy.is_allowed_ips(peer_id, info.seen_ip_addresses()))
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+
+        *self.ctx.network_globals.peer_policy.write() = policy;
+
+        for peer_id in denied_peers.iter() {
+            self.ctx
+                .send_network(NetworkMessage::DisconnectPeer { peer_id: *peer_id })?;
+        }
+
+        Ok(denied_peers.len())
+    }
+
+    async fn get_known_peers(&self) -> RpcResult<Vec<KnownPeerInfo>> {
+        info!("admin_getKnownPeers()");
+
+        Ok(self
+            .ctx
+            .known_peers
+            .as_ref()
+            .map(|known_peers| known_peers.peers().into_iter().map(Into::into).collect())
+            .unwrap_or_default())
+    }
+
+    async fn get_reorg_history(&self) -> RpcResult<Vec<ReorgEvent>> {
+        info!("admin_getReorgHistory()");
+
+        Ok(self
+            .ctx
+            .log_store
+            .get_store()
+            .get_revert_history()
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    async fn get_block_progress(
+        &self,
+        from_block: u64,
+        limit: usize,
+    ) -> RpcResult<BlockProgressPage> {
+        debug!(%from_block, %limit, "admin_getBlockProgress()");
+
+        let limit = limit.min(MAX_BLOCK_PROGRESS_LIMIT);
+        let store = self.ctx.log_store.get_store();
+        let progress = store.get_sync_progress().map_err(error::storage_error)?;
+        let log_latest_block_number = store
+            .get_log_latest_block_number()
+            .map_err(error::storage_error)?;
+
+        // One more block is read to tell whether there is a next page.
+        let mut blocks = self
+            .ctx
+            .log_store
+            .get_block_hashes_from(from_block, limit.saturating_add(1))
+            .await
+            .map_err(error::storage_error)?;
+        let next_block = if blocks.len() > limit {
+            blocks.pop().map(|(block_number, _)| block_number)
+        } else {
+            None
+        };
+
+        Ok(BlockProgressPage {
+            blocks: blocks.into_iter().map(Into::into).collect(),
+            next_block,
+            progress_block_number: progress.map(|(block_number, _)| block_number),
+            progress_block_hash: progress.map(|(_, block_hash)| block_hash),
+            log_latest_block_number,
+        })
+    }
+
     async fn get_file_location(
         &self,
         tx_seq: u64,
@@ -204,10 +967,16 @@ impl RpcServer for RpcServerImpl {
     ) -> RpcResult<Option<Vec<LocationInfo>>> {
         info!("admin_getFileLocation()");
 
-        let tx = match self.ctx.log_store.get_tx_by_seq_number(tx_seq).await? {
+        let tx = match self
+            .ctx
+            .log_store
+            .get_tx_by_seq_number(tx_seq)
+            .await
+            .map_err(error::storage_error)?
+        {
             Some(tx) => tx,
             None => {
-                return Err(error::internal_error("tx not found"));
+                return Err(error::file_not_found(json!({ "tx_seq": tx_seq })));
             }
         };
         let info: Vec<LocationInfo> = self
@@ -283,4 +1052,185 @@ impl RpcServer for RpcServerImpl {
 
         Ok(result)
     }
+
+    async fn list_files(
+        &self,
+        start_tx_seq: u64,
+        limit: usize,
+        filter: Option<FileFilter>,
+    ) -> RpcResult<StoredFilePage> {
+        debug!(%start_tx_seq, %limit, ?filter, "admin_listFiles()");
+
+        let limit = limit.min(MAX_LIST_FILES_LIMIT);
+        let filter = filter.unwrap_or_default();
+        let mut files = Vec::new();
+        let mut cursor = start_tx_seq;
+        let mut scanned = 0;
+
+        // Read in small batches, so that other store operations are not blocked by the listing.
+        while files.len() < limit && scanned < LIST_FILES_MAX_SCAN {
+            let batch = self
+                .ctx
+                .log_store
+                .get_txs_with_status(cursor, LIST_FILES_BATCH_SIZE)
+                .await
+                .map_err(error::storage_error)?;
+            if batch.is_empty() {
+                return Ok(StoredFilePage {
+                    files,
+                    next_cursor: None,
+                });
+            }
+
+            for (tx, status) in batch {
+                cursor = tx.seq + 1;
+                scanned += 1;
+
+                let status = StoredFileStatus::from(status);
+                if filter.matches(status) {
+                    let submission = self
+                        .ctx
+                        .log_store
+                        .get_submission_context(tx.seq)
+                        .await
+                        .map_err(error::storage_error)?;
+                    files.push(StoredFile {
+                        tx_seq: tx.seq,
+                        data_root: tx.data_merkle_root,
+                        size: tx.size,
+                        status,
+                        finalized_block: submission.map(|s| s.block_number),
+                        submission_tx_hash: submission.map(|s| s.tx_hash),
+                    });
+
+                    if files.len() == limit {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let next_cursor = if cursor < self.ctx.log_store.get_store().next_tx_seq() {
+            Some(cursor)
+        } else {
+            None
+        };
+
+        Ok(StoredFilePage { files, next_cursor })
+    }
+
+    async fn get_peer_stats(
+        &self,
+        sort_by: Option<PeerStatsSort>,
+        limit: Option<usize>,
+    ) -> RpcResult<Vec<PeerStatsInfo>> {
+        debug!(?sort_by, ?limit, "admin_getPeerStats()");
+
+        let response = self.ctx.request_sync(SyncRequest::PeerStats).await?;
+        let stats = match response {
+            SyncResponse::PeerStats { stats } => stats,
+            _ => return Err(error::internal_error("unexpected response type")),
+        };
+
+        let mut stats: Vec<PeerStatsInfo> = stats
+            .into_iter()
+            .map(|(peer_id, contribution)| PeerStatsInfo::new(peer_id, contribution))
+            .collect();
+        PeerStatsInfo::sort(&mut stats, sort_by.unwrap_or_default());
+        if let Some(limit) = limit {
+            stats.truncate(limit);
+        }
+
+        Ok(stats)
+    }
+}
+
+impl RpcServerImpl {
+    /// Sends the request to the mine service and waits for the reply.
+    async fn request_miner<T>(
+        &self,
+        request: impl FnOnce(mpsc::UnboundedSender<T>) -> MinerMessage,
+    ) -> RpcResult<T> {
+        let sender = self
+            .ctx
+            .mine_service_sender
+            .as_ref()
+            .ok_or_else(error::not_supported)?;
+        let (reply_send, mut reply_recv) = mpsc::unbounded_channel();
+        sender
+            .send(request(reply_send))
+            .map_err(|_| error::internal_error("mine service stopped"))?;
+        reply_recv
+            .recv()
+            .await
+            .ok_or_else(|| error::internal_error("mine service stopped"))
+    }
+
+    /// Resolves the finalized file and the path to export it into.
+    async fn resolve_export(
+        &self,
+        tx_seq_or_root: TxSeqOrRoot,
+        path: &str,
+    ) -> RpcResult<(Transaction, PathBuf)> {
+        let export_dir = self
+            .ctx
+            .config
+            .export_dir
+            .as_ref()
+            .ok_or_else(error::not_supported)?;
+        let path = export::resolve_export_path(export_dir, path)
+            .map_err(|e| error::invalid_params("path", e))?;
+
+        let store = &self.ctx.log_store;
+        let maybe_tx = match &tx_seq_or_root {
+            TxSeqOrRoot::TxSeq(tx_seq) => store.get_tx_by_seq_number(*tx_seq).await,
+            TxSeqOrRoot::Root(root) => store.get_tx_by_data_root(root).await,
+        }
+        .map_err(error::storage_error)?;
+        let tx = match maybe_tx {
+            Some(tx) => tx,
+            None => {
+                let data = match tx_seq_or_root {
+                    TxSeqOrRoot::TxSeq(tx_seq) => json!({ "tx_seq": tx_seq }),
+                    TxSeqOrRoot::Root(root) => json!({ "root": root }),
+                };
+                return Err(error::file_not_found(data));
+            }
+        };
+
+        // Files finalized in shard are not fully stored on the node.
+        match store
+            .get_tx_status(tx.seq)
+            .await
+            .map_err(error::storage_error)?
+        {
+            Some(TxStatus::Finalized) => {}
+            Some(TxStatus::Pruned) => return Err(error::file_pruned(tx.seq)),
+            Some(TxStatus::Invalid) => return Err(error::file_invalid(tx.seq)),
+            _ => return Err(error::file_not_finalized(tx.seq)),
+        }
+
+        Ok((tx, path))
+    }
+}
+
+fn parse_peer_id(peer_id: &str) -> RpcResult<PeerId> {
+    PeerId::from_str(peer_id).map_err(|e| error::invalid_params("peer_id", format!("{:?}", e)))
+}
+
+fn check_flow_range(start_chunk: u64, end_chunk: u64) -> RpcResult<()> {
+    if start_chunk >= end_chunk {
+        return Err(error::invalid_params(
+            "end_chunk",
+            "end_chunk should be larger than start_chunk",
+        ));
+    }
+    if end_chunk - start_chunk > MAX_FLOW_RANGE_CHUNKS {
+        return Err(error::invalid_params(
+            "end_chunk",
+            format!("range should be at most {} chunks", MAX_FLOW_RANGE_CHUNKS),
+        ));
+    }
+
+    Ok(())
 }
@@ -1,4 +1,5 @@
 mod api;
+mod export;
 mod r#impl;
 
 pub use api::RpcClient;
@@ -0,0 +1,384 @@
+//! Authentication of the admin namespace.
+//!
+//! Two kinds of credentials are supported:
+//! - A shared token in the `Authorization: Bearer <token>` header.
+//! - A signature of an allowed secp256k1 key. The client puts the current unix timestamp in the
+//!   `X-Zgs-Timestamp` header, and the hex encoded 65 bytes signature of the personal message
+//!   `<timestamp><request body>` in the `X-Zgs-Signature` header. A signed message is accepted
+//!   only once, so clients should sign requests of unique ids, e.g. an increasing JSON-RPC id.
+//!
+//! If not configured, only requests from loopback addresses are accepted, see
+//! [`AdminAuth::localhost_only`].
+
+use crate::config::AdminAuthConfig;
+use ethers::core::k256::ecdsa::VerifyingKey;
+use ethers::types::{Address, Signature, H256};
+use ethers::utils::{hash_message, hex, public_key_to_address};
+use hyper::header::{HeaderValue, AUTHORIZATION};
+use hyper::HeaderMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const TIMESTAMP_HEADER: &str = "x-zgs-timestamp";
+pub const SIGNATURE_HEADER: &str = "x-zgs-signature";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuthError {
+    MissingCredentials,
+    InvalidToken,
+    InvalidSignature(String),
+    ExpiredSignature,
+    ReplayedSignature,
+    UnknownKey(Address),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MissingCredentials => write!(f, "missing admin credentials"),
+            AuthError::InvalidToken => write!(f, "invalid admin token"),
+            AuthError::InvalidSignature(e) => write!(f, "invalid signature: {}", e),
+            AuthError::ExpiredSignature => write!(f, "signature timestamp out of range"),
+            AuthError::ReplayedSignature => write!(f, "signed message already used"),
+            AuthError::UnknownKey(address) => write!(f, "key {:?} is not allowed", address),
+        }
+    }
+}
+
+pub struct AdminAuth {
+    token: Option<String>,
+    /// Addresses of the allowed public keys, which are recovered from signatures.
+    allowed: HashSet<Address>,
+    max_signature_age_secs: u64,
+    allow_localhost: bool,
+    /// Hashes of the accepted signed messages, along with the signed timestamps. Messages are
+    /// removed once expired, since they are rejected by timestamp then.
+    seen: Mutex<HashMap<H256, u64>>,
+}
+
+impl AdminAuth {
+    pub fn new(config: &AdminAuthConfig) -> Result<Self, String> {
+        if config.token.is_none() && config.public_keys.is_empty() {
+            return Err("rpc.admin_auth requires a token or public keys".into());
+        }
+
+        let mut allowed = HashSet::new();
+        for key in config.public_keys.iter() {
+            let bytes = hex::decode(key.trim_start_matches("0x"))
+                .map_err(|e| format!("invalid admin public key {}: {:?}", key, e))?;
+            let key = VerifyingKey::from_sec1_bytes(&bytes)
+                .map_err(|e| format!("invalid admin public key {}: {:?}", key, e))?;
+            allowed.insert(public_key_to_address(&key));
+        }
+
+        Ok(Self {
+            token: config.token.clone(),
+            allowed,
+            max_signature_age_secs: config.max_signature_age_secs,
+            allow_localhost: config.allow_localhost,
+            seen: Default::default(),
+        })
+    }
+
+    /// Accepts requests from loopback addresses only, which protects the admin RPCs if
+    /// authentication is not configured.
+    pub fn localhost_only() -> Self {
+        Self {
+            token: None,
+            allowed: HashSet::new(),
+            max_signature_age_secs: 0,
+            allow_localhost: true,
+            seen: Default::default(),
+        }
+    }
+
+    /// Checks the credentials of a request received from `remote_addr`.
+    pub fn authorize(
+        &self,
+        remote_addr: &SocketAddr,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<(), AuthError> {
+        if self.allow_localhost && remote_addr.ip().is_loopback() {
+            return Ok(());
+        }
+
+        if let Some(value) = headers.get(AUTHORIZATION) {
+            return self.check_token(value);
+        }
+
+        match (headers.get(TIMESTAMP_HEADER), headers.get(SIGNATURE_HEADER)) {
+            (Some(timestamp), Some(signature)) => {
+                self.check_signature(timestamp, signature, body, unix_now())
+            }
+            _ => Err(AuthError::MissingCredentials),
+        }
+    }
+
+    fn check_token(&self, value: &HeaderValue) -> Result<(), AuthError> {
+        let expected = self.token.as_ref().ok_or(AuthError::InvalidToken)?;
+        let provided = value
+            .to_str()
+            .ok()
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or(AuthError::InvalidToken)?;
+
+        if constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+            Ok(())
+        } else {
+            Err(AuthError::InvalidToken)
+        }
+    }
+
+    fn check_signature(
+        &self,
+        timestamp: &HeaderValue,
+        signature: &HeaderValue,
+        body: &[u8],
+        now: u64,
+    ) -> Result<(), AuthError> {
+        let timestamp_str = timestamp
+            .to_str()
+            .map_err(|e| AuthError::InvalidSignature(e.to_string()))?;
+        let timestamp =
+            u64::from_str(timestamp_str).map_err(|e| AuthError::InvalidSignature(e.to_string()))?;
+        if now.abs_diff(timestamp) > self.max_signature_age_secs {
+            return Err(AuthError::ExpiredSignature);
+        }
+
+        let signature = signature
+            .to_str()
+            .map_err(|e| AuthError::InvalidSignature(e.to_string()))
+            .and_then(|v| {
+                Signature::from_str(v).map_err(|e| AuthError::InvalidSignature(e.to_string()))
+            })?;
+
+        let mut message = timestamp_str.as_bytes().to_vec();
+        message.extend_from_slice(body);
+        let message_hash = hash_message(message);
+        let address = signature
+            .recover(message_hash)
+            .map_err(|e| AuthError::InvalidSignature(e.to_string()))?;
+
+        if !self.allowed.contains(&address) {
+            return Err(AuthError::UnknownKey(address));
+        }
+
+        // Keyed by the signed message rather than the signature, which is malleable.
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|_, signed_at| now.abs_diff(*signed_at) <= self.max_signature_age_secs);
+        if seen.insert(message_hash, timestamp).is_some() {
+            return Err(AuthError::ReplayedSignature);
+        }
+
+        Ok(())
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::core::k256::elliptic_curve::sec1::ToEncodedPoint;
+    use ethers::signers::{LocalWallet, Signer};
+
+    const KEY_1: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+    const KEY_2: &str = "dcf2cbdd171a21c480aa7f53d77f31bb102282b3ff099c78e3118b37348c72f7";
+
+    const BODY: &[u8] = br#"{"jsonrpc":"2.0","id":1,"method":"admin_getSyncStatus","params":[1]}"#;
+
+    fn remote() -> SocketAddr {
+        "10.0.0.1:40000".parse().unwrap()
+    }
+
+    fn local() -> SocketAddr {
+        "127.0.0.1:40000".parse().unwrap()
+    }
+
+    fn token_auth(token: &str) -> AdminAuth {
+        AdminAuth::new(&AdminAuthConfig {
+            token: Some(token.into()),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+        );
+        headers
+    }
+
+    fn signed(wallet: &LocalWallet, timestamp: u64, body: &[u8]) -> HeaderMap {
+        let mut message = timestamp.to_string().into_bytes();
+        message.extend_from_slice(body);
+        let signature = wallet.sign_hash(hash_message(message)).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            TIMESTAMP_HEADER,
+            HeaderValue::from_str(&timestamp.to_string()).unwrap(),
+        );
+        headers.insert(
+            SIGNATURE_HEADER,
+            HeaderValue::from_str(&signature.to_string()).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_valid_token() {
+        let auth = token_auth("secret");
+        assert_eq!(auth.authorize(&remote(), &bearer("secret"), BODY), Ok(()));
+    }
+
+    #[test]
+    fn test_bad_token() {
+        let auth = token_auth("secret");
+        assert_eq!(
+            auth.authorize(&remote(), &bearer("secreT"), BODY),
+            Err(AuthError::InvalidToken)
+        );
+        assert_eq!(
+            auth.authorize(&remote(), &HeaderMap::new(), BODY),
+            Err(AuthError::MissingCredentials)
+        );
+    }
+
+    #[test]
+    fn test_unauthenticated_localhost() {
+        let auth = token_auth("secret");
+        assert_eq!(auth.authorize(&local(), &HeaderMap::new(), BODY), Ok(()));
+
+        let auth = AdminAuth::new(&AdminAuthConfig {
+            token: Some("secret".into()),
+            allow_localhost: false,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            auth.authorize(&local(), &HeaderMap::new(), BODY),
+            Err(AuthError::MissingCredentials)
+        );
+    }
+
+    #[test]
+    fn test_signed_request() {
+        let wallet = LocalWallet::from_str(KEY_1).unwrap();
+        let public_key = wallet.signer().verifying_key().to_encoded_point(false);
+        let auth = AdminAuth::new(&AdminAuthConfig {
+            public_keys: vec![hex::encode(public_key.as_bytes())],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let now = unix_now();
+        assert_eq!(
+            auth.authorize(&remote(), &signed(&wallet, now, BODY), BODY),
+            Ok(())
+        );
+
+        // replayed within the max signature age
+        assert_eq!(
+            auth.authorize(&remote(), &signed(&wallet, now, BODY), BODY),
+            Err(AuthError::ReplayedSignature)
+        );
+
+        // signature of another body
+        assert!(matches!(
+            auth.authorize(&remote(), &signed(&wallet, now, b"{}"), BODY),
+            Err(AuthError::UnknownKey(_))
+        ));
+
+        // expired timestamp
+        assert_eq!(
+            auth.authorize(&remote(), &signed(&wallet, now - 3600, BODY), BODY),
+            Err(AuthError::ExpiredSignature)
+        );
+
+        // key not in the allowlist
+        let other = LocalWallet::from_str(KEY_2).unwrap();
+        assert_eq!(
+            auth.authorize(&remote(), &signed(&other, now, BODY), BODY),
+            Err(AuthError::UnknownKey(other.address()))
+        );
+    }
+
+    #[test]
+    fn test_replay_after_expired() {
+        let wallet = LocalWallet::from_str(KEY_1).unwrap();
+        let public_key = wallet.signer().verifying_key().to_encoded_point(false);
+        let auth = AdminAuth::new(&AdminAuthConfig {
+            public_keys: vec![hex::encode(public_key.as_bytes())],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let now = unix_now();
+        let headers = signed(&wallet, now, BODY);
+        let check = |now| {
+            auth.check_signature(
+                &headers[TIMESTAMP_HEADER],
+                &headers[SIGNATURE_HEADER],
+                BODY,
+                now,
+            )
+        };
+        assert_eq!(check(now), Ok(()));
+        assert_eq!(check(now + 30), Err(AuthError::ReplayedSignature));
+        assert_eq!(check(now + 31), Err(AuthError::ExpiredSignature));
+
+        // expired messages are dropped
+        let other = signed(&wallet, now + 31, BODY);
+        assert_eq!(
+            auth.check_signature(
+                &other[TIMESTAMP_HEADER],
+                &other[SIGNATURE_HEADER],
+                BODY,
+                now + 31
+            ),
+            Ok(())
+        );
+        assert_eq!(auth.seen.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_localhost_only() {
+        let auth = AdminAuth::localhost_only();
+        assert_eq!(auth.authorize(&local(), &HeaderMap::new(), BODY), Ok(()));
+        assert_eq!(
+            auth.authorize(&remote(), &HeaderMap::new(), BODY),
+            Err(AuthError::MissingCredentials)
+        );
+        assert_eq!(
+            auth.authorize(&remote(), &bearer(""), BODY),
+            Err(AuthError::InvalidToken)
+        );
+    }
+
+    #[test]
+    fn test_no_auth_method() {
+        assert!(AdminAuth::new(&AdminAuthConfig::default()).is_err());
+    }
+}
@@ -1,8 +1,11 @@
-use std::{net::SocketAddr, str::FromStr};
+use std::{net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+use crate::gateway::GatewayLimits;
+use crate::JobConfig;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Config {
     pub enabled: bool,
@@ -10,7 +13,68 @@ pub struct Config {
     pub listen_address_admin: SocketAddr,
     pub chunks_per_segment: usize,
     pub max_request_body_size: u32,
+    /// Maximum size of the response body, including all responses of a batch.
+    pub max_response_body_size: u32,
+    /// Maximum number of concurrent connections of each RPC server.
+    pub max_connections: usize,
     pub max_cache_file_size: usize,
+    pub max_file_info_batch_size: usize,
+    /// Maximum file size of `zgs_uploadSmallFile`, which is also limited by the segment size.
+    pub max_small_file_size: usize,
+    /// Whether to cache files uploaded via `zgs_uploadSmallFile` before the on-chain submission
+    /// is observed.
+    pub allow_pre_submission_cache: bool,
+    /// Authentication of the admin namespace, configured by [rpc.admin_auth] section.
+    /// If not configured, the admin namespace only accepts requests from loopback addresses.
+    /// If not configured, admin RPCs are only protected by the listen address.
+    pub admin_auth: Option<AdminAuthConfig>,
+    /// Timeout in seconds of every storage operation of RPC handlers, or 0 to wait forever.
+    pub storage_timeout_secs: u64,
+    /// Directory that `admin_exportFile` is allowed to write into. If not configured, files
+    /// could not be exported.
+    pub export_dir: Option<PathBuf>,
+    /// Maximum bytes of the segments with proof cached for download, or 0 to disable.
+    pub segment_cache_max_bytes: usize,
+    /// Seconds that an upload session of `zgs_beginUpload` lives since last accessed.
+    pub upload_session_ttl_secs: u64,
+    /// Maximum number of live upload sessions of every client IP.
+    pub max_upload_sessions_per_ip: usize,
+    /// Number of threads to verify the proofs of uploaded segments, or 0 for the number of
+    /// cores.
+    pub proof_verify_threads: usize,
+    /// Maximum number of segment proofs queued or being verified, beyond which uploads are
+    /// rejected with a retryable error.
+    pub max_queued_proof_verifications: usize,
+    /// Maximum number of network queries of `zgs_checkFileReplicas` in every
+    /// `replica_query_wait_ms`, beyond which only the cached announcements are counted.
+    pub max_replica_queries: usize,
+    /// Milliseconds that `zgs_checkFileReplicas` waits for the announcements queried from the
+    /// network.
+    pub replica_query_wait_ms: u64,
+    /// Milliseconds that admin jobs pause between steps, so that a job of hours, e.g. a full
+    /// scrub, does not hog the store.
+    pub job_step_interval_ms: u64,
+    /// Number of finished admin jobs kept for `admin_getJob`, beyond which the oldest ones are
+    /// removed.
+    pub max_finished_jobs: usize,
+}
+
+impl Config {
+    pub fn job_config(&self) -> JobConfig {
+        JobConfig {
+            chunks_per_read: self.chunks_per_segment,
+            step_interval: Duration::from_millis(self.job_step_interval_ms),
+            max_finished_jobs: self.max_finished_jobs,
+        }
+    }
+
+    pub fn gateway_limits(&self) -> GatewayLimits {
+        GatewayLimits {
+            max_request_body_size: self.max_request_body_size,
+            max_response_body_size: self.max_response_body_size,
+            max_connections: self.max_connections,
+        }
+    }
 }
 
 impl Default for Config {
@@ -21,7 +85,48 @@ impl Default for Config {
             listen_address_admin: SocketAddr::from_str("127.0.0.1:5679").unwrap(),
             chunks_per_segment: 1024,
             max_request_body_size: 100 * 1024 * 1024, // 100MB
-            max_cache_file_size: 10 * 1024 * 1024,    // 10MB
+            max_response_body_size: 10 * 1024 * 1024, // 10MB
+            max_connections: 1024,
+            max_cache_file_size: 10 * 1024 * 1024, // 10MB
+            max_file_info_batch_size: 256,
+            max_small_file_size: 256 * 1024, // 256KB
+            allow_pre_submission_cache: false,
+            admin_auth: None,
+            storage_timeout_secs: 30,
+            export_dir: None,
+            segment_cache_max_bytes: 64 * 1024 * 1024, // 64MB
+            upload_session_ttl_secs: 3600,
+            max_upload_sessions_per_ip: 16,
+            proof_verify_threads: 0,
+            max_queued_proof_verifications: 1024,
+            max_replica_queries: 4,
+            replica_query_wait_ms: 1000,
+            job_step_interval_ms: 10,
+            max_finished_jobs: 100,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdminAuthConfig {
+    /// Shared secret expected in the `Authorization: Bearer <token>` header.
+    pub token: Option<String>,
+    /// Hex encoded secp256k1 public keys that are allowed to sign admin requests.
+    pub public_keys: Vec<String>,
+    /// Maximum difference in seconds between the signed timestamp and the local time.
+    pub max_signature_age_secs: u64,
+    /// Whether requests from loopback addresses are accepted without credentials.
+    pub allow_localhost: bool,
+}
+
+impl Default for AdminAuthConfig {
+    fn default() -> Self {
+        Self {
+            token: None,
+            public_keys: vec![],
+            max_signature_age_secs: 30,
+            allow_localhost: true,
         }
     }
 }
@@ -1,7 +1,119 @@
 #![allow(dead_code)]
 
+use chunk_pool::Error as ChunkPoolError;
 use jsonrpsee::core::Error;
 use jsonrpsee::types::error::{CallError, ErrorCode, ErrorObject};
+use serde_json::{json, Value};
+use storage::error::StoreError;
+
+/// Error code returned when an admin request is not authenticated.
+pub const UNAUTHORIZED_CODE: i32 = -32001;
+
+/// Stable error codes of the `zgs` and `admin` namespaces.
+///
+/// Clients should match on the numeric code instead of the error message, so the code of an
+/// existing variant must never change. The error data, if any, is a JSON object with snake
+/// case keys as documented on each variant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RpcErrorCode {
+    /// Segment index out of bound, data: `{index, num_segments}`.
+    SegmentOutOfRange = 101,
+    /// File or transaction not found, data: `{tx_seq}` or `{root}`.
+    FileNotFound = 102,
+    /// File not finalized yet, data: `{tx_seq}`.
+    FileNotFinalized = 103,
+    /// File already uploaded and finalized, data: `{tx_seq}`.
+    FileAlreadyFinalized = 104,
+    /// File already pruned, data: `{tx_seq}`.
+    FilePruned = 105,
+    /// Segment data is empty or has invalid length, data: `{reason}`.
+    InvalidSegment = 106,
+    /// Segment merkle proof is invalid, data: `{reason}`.
+    InvalidProof = 107,
+    /// File size not matched, data: `{expected, actual}`.
+    FileSizeMismatch = 108,
+    /// Data root not matched, data: `{expected, actual}`.
+    RootMismatch = 109,
+    /// Segment already uploaded or being uploaded, data: `{index}`.
+    SegmentAlreadyUploaded = 110,
+    /// File is too large to cache before the transaction is available, data: `{max}`.
+    FileTooLargeToCache = 111,
+    /// Chunk pool is busy and the request could be retried later, data: `{limit}`.
+    ChunkPoolBusy = 112,
+    /// Transaction reverted during uploading, and file should be uploaded again.
+    TxReverted = 113,
+    /// Too many items in a batch request, data: `{max}`.
+    BatchTooLarge = 114,
+    /// File is larger than allowed, data: `{max}`.
+    FileTooLarge = 115,
+    /// Flow entries are out of the local shard, data: `{entry_index, count, shard_id, num_shard}`.
+    FlowEntriesOutOfShard = 116,
+    /// Flow entries are not available locally, e.g. pruned or not synced yet, data:
+    /// `{entry_index, count}`.
+    FlowEntriesUnavailable = 117,
+    /// A different segment has already been uploaded at the same index, data: `{index}`.
+    SegmentConflicted = 118,
+    /// Upload session not found, e.g. expired or completed, and the upload should be begun
+    /// again, data: `{token}`.
+    UploadSessionNotFound = 119,
+    /// Client has too many live upload sessions, data: `{max}`.
+    TooManyUploadSessions = 120,
+    /// Too many segment proofs are being verified, and the request could be retried later,
+    /// data: `{limit}`.
+    ProofVerifierBusy = 121,
+    /// File rejected by the ingest policy of the node, so it is never stored, data: `{tx_seq}`.
+    FileInvalid = 122,
+    /// Failed to access the local storage, data: `{reason}`.
+    StorageError = 201,
+    /// Failed to handle the request by sync service, data: `{reason}`.
+    SyncError = 202,
+    /// Node is shutting down, and the request should be sent to other nodes.
+    NodeShuttingDown = 203,
+    /// Storage operation timed out, e.g. the node is overloaded, and the request could be
+    /// retried later.
+    StorageTimeout = 204,
+    /// Node is read-only, and the request to write should be sent to other nodes.
+    NodeReadOnly = 205,
+}
+
+impl RpcErrorCode {
+    pub fn code(&self) -> i32 {
+        *self as i32
+    }
+}
+
+/// Custom RPC error with a stable code and optional data payload.
+#[derive(Clone, Debug)]
+pub struct RpcError {
+    pub code: RpcErrorCode,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: RpcErrorCode, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
+impl From<RpcError> for Error {
+    fn from(e: RpcError) -> Self {
+        Error::Call(CallError::Custom(ErrorObject::owned(
+            e.code.code(),
+            e.message,
+            e.data,
+        )))
+    }
+}
 
 pub fn not_supported() -> Error {
     Error::Call(CallError::Custom(ErrorObject::borrowed(
@@ -28,3 +140,331 @@ pub fn invalid_params(param: &str, msg: impl std::convert::AsRef<str>) -> Error
         Some(msg.as_ref()),
     )))
 }
+
+pub fn segment_out_of_range(index: usize, num_segments: usize) -> Error {
+    RpcError::new(
+        RpcErrorCode::SegmentOutOfRange,
+        "Segment index out of bound",
+    )
+    .with_data(json!({ "index": index, "num_segments": num_segments }))
+    .into()
+}
+
+pub fn file_not_found(data: Value) -> Error {
+    RpcError::new(RpcErrorCode::FileNotFound, "File not found")
+        .with_data(data)
+        .into()
+}
+
+pub fn file_not_finalized(tx_seq: u64) -> Error {
+    RpcError::new(RpcErrorCode::FileNotFinalized, "File not finalized")
+        .with_data(json!({ "tx_seq": tx_seq }))
+        .into()
+}
+
+pub fn file_already_finalized(tx_seq: u64) -> Error {
+    RpcError::new(
+        RpcErrorCode::FileAlreadyFinalized,
+        "File already uploaded and finalized",
+    )
+    .with_data(json!({ "tx_seq": tx_seq }))
+    .into()
+}
+
+pub fn file_pruned(tx_seq: u64) -> Error {
+    RpcError::new(RpcErrorCode::FilePruned, "File already pruned")
+        .with_data(json!({ "tx_seq": tx_seq }))
+        .into()
+}
+
+pub fn file_invalid(tx_seq: u64) -> Error {
+    RpcError::new(RpcErrorCode::FileInvalid, "File rejected by ingest policy")
+        .with_data(json!({ "tx_seq": tx_seq }))
+        .into()
+}
+
+pub fn invalid_segment(reason: impl std::convert::AsRef<str>) -> Error {
+    RpcError::new(RpcErrorCode::InvalidSegment, "Invalid segment")
+        .with_data(json!({ "reason": reason.as_ref() }))
+        .into()
+}
+
+pub fn invalid_proof(reason: impl std::convert::AsRef<str>) -> Error {
+    RpcError::new(RpcErrorCode::InvalidProof, "Invalid proof")
+        .with_data(json!({ "reason": reason.as_ref() }))
+        .into()
+}
+
+pub fn mismatch(code: RpcErrorCode, expected: Value, actual: Value) -> Error {
+    let message = match code {
+        RpcErrorCode::FileSizeMismatch => "File size mismatch",
+        RpcErrorCode::RootMismatch => "Data root mismatch",
+        _ => "Mismatch",
+    };
+
+    RpcError::new(code, message)
+        .with_data(json!({ "expected": expected, "actual": actual }))
+        .into()
+}
+
+pub fn exceeds_limit(code: RpcErrorCode, max: usize) -> Error {
+    let message = match code {
+        RpcErrorCode::BatchTooLarge => "Batch too large",
+        RpcErrorCode::FileTooLarge => "File too large",
+        RpcErrorCode::FileTooLargeToCache => {
+            "Caching of large file when tx is unavailable is not supported"
+        }
+        RpcErrorCode::TooManyUploadSessions => "Too many upload sessions",
+        _ => "Exceeds limit",
+    };
+
+    RpcError::new(code, message)
+        .with_data(json!({ "max": max }))
+        .into()
+}
+
+/// Maps the storage error at the RPC boundary.
+pub fn storage_error(e: anyhow::Error) -> Error {
+    if let Some(storage_async::Error::Timeout) = e.downcast_ref::<storage_async::Error>() {
+        return RpcError::new(RpcErrorCode::StorageTimeout, e.to_string()).into();
+    }
+
+    match StoreError::of(&e) {
+        Some(StoreError::Pruned { tx_seq }) => return file_pruned(*tx_seq),
+        Some(StoreError::Invalid { tx_seq }) => return file_invalid(*tx_seq),
+        Some(StoreError::Busy) => {
+            return RpcError::new(RpcErrorCode::StorageTimeout, e.to_string()).into()
+        }
+        _ => {}
+    }
+
+    RpcError::new(RpcErrorCode::StorageError, "Storage error")
+        .with_data(json!({ "reason": e.to_string() }))
+        .into()
+}
+
+/// Maps the chunk pool error at the RPC boundary. Errors that are not raised by chunk pool
+/// itself come from the underlying storage.
+pub fn chunk_pool_error(e: anyhow::Error) -> Error {
+    let err = match e.downcast_ref::<ChunkPoolError>() {
+        Some(err) => err,
+        None => return storage_error(e),
+    };
+
+    let message = err.to_string();
+    match err {
+        ChunkPoolError::EmptySegment | ChunkPoolError::InvalidSegmentSize(_) => {
+            invalid_segment(message)
+        }
+        ChunkPoolError::PoolFull { limit }
+        | ChunkPoolError::FileCacheFull { limit }
+        | ChunkPoolError::SpillFull { limit }
+        | ChunkPoolError::TooManyWritings { limit } => {
+            RpcError::new(RpcErrorCode::ChunkPoolBusy, message)
+                .with_data(json!({ "limit": limit }))
+                .into()
+        }
+        ChunkPoolError::SegmentOutOfRange {
+            index,
+            num_segments,
+        } => segment_out_of_range(*index, *num_segments),
+        ChunkPoolError::InvalidSegmentLength { .. } => invalid_segment(message),
+        ChunkPoolError::InvalidProofDepth(_) => invalid_proof(message),
+        ChunkPoolError::FileNotFound(root) => file_not_found(json!({ "root": root })),
+        ChunkPoolError::FileSizeMismatch { expected, actual } => {
+            RpcError::new(RpcErrorCode::FileSizeMismatch, message)
+                .with_data(json!({ "expected": expected, "actual": actual }))
+                .into()
+        }
+        ChunkPoolError::SegmentAlreadyUploaded(index) => {
+            RpcError::new(RpcErrorCode::SegmentAlreadyUploaded, message)
+                .with_data(json!({ "index": index }))
+                .into()
+        }
+        ChunkPoolError::SegmentConflicted(index) => {
+            RpcError::new(RpcErrorCode::SegmentConflicted, message)
+                .with_data(json!({ "index": index }))
+                .into()
+        }
+        ChunkPoolError::TxReverted => RpcError::new(RpcErrorCode::TxReverted, message).into(),
+        ChunkPoolError::ShuttingDown => {
+            RpcError::new(RpcErrorCode::NodeShuttingDown, message).into()
+        }
+        ChunkPoolError::ReadOnly => node_read_only(),
+    }
+}
+
+pub fn flow_entries_out_of_shard(
+    entry_index: u64,
+    count: u64,
+    shard_id: usize,
+    num_shard: usize,
+) -> Error {
+    RpcError::new(
+        RpcErrorCode::FlowEntriesOutOfShard,
+        "Flow entries out of local shard",
+    )
+    .with_data(json!({
+        "entry_index": entry_index,
+        "count": count,
+        "shard_id": shard_id,
+        "num_shard": num_shard,
+    }))
+    .into()
+}
+
+pub fn flow_entries_unavailable(entry_index: u64, count: u64) -> Error {
+    RpcError::new(
+        RpcErrorCode::FlowEntriesUnavailable,
+        "Flow entries not available",
+    )
+    .with_data(json!({ "entry_index": entry_index, "count": count }))
+    .into()
+}
+
+pub fn proof_verifier_busy(limit: usize) -> Error {
+    RpcError::new(
+        RpcErrorCode::ProofVerifierBusy,
+        "Proof verification queue is full",
+    )
+    .with_data(json!({ "limit": limit }))
+    .into()
+}
+
+pub fn upload_session_not_found(token: &str) -> Error {
+    RpcError::new(
+        RpcErrorCode::UploadSessionNotFound,
+        "Upload session not found or expired",
+    )
+    .with_data(json!({ "token": token }))
+    .into()
+}
+
+pub fn node_read_only() -> Error {
+    RpcError::new(
+        RpcErrorCode::NodeReadOnly,
+        ChunkPoolError::ReadOnly.to_string(),
+    )
+    .into()
+}
+
+pub fn sync_error(reason: impl std::convert::AsRef<str>) -> Error {
+    RpcError::new(RpcErrorCode::SyncError, "Sync error")
+        .with_data(json!({ "reason": reason.as_ref() }))
+        .into()
+}
+
+#[cfg(test)]
+pub(crate) fn error_code(e: &Error) -> i32 {
+    match e {
+        Error::Call(CallError::Custom(obj)) => obj.code(),
+        _ => panic!("unexpected error type: {:?}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use shared_types::DataRoot;
+
+    fn error_data(e: &Error) -> Value {
+        match e {
+            Error::Call(CallError::Custom(obj)) => {
+                serde_json::from_str(obj.data().expect("data").get()).unwrap()
+            }
+            _ => panic!("unexpected error type: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_stable_codes() {
+        // Codes are part of the public API and must not change.
+        assert_eq!(RpcErrorCode::SegmentOutOfRange.code(), 101);
+        assert_eq!(RpcErrorCode::FileNotFound.code(), 102);
+        assert_eq!(RpcErrorCode::FileNotFinalized.code(), 103);
+        assert_eq!(RpcErrorCode::FileAlreadyFinalized.code(), 104);
+        assert_eq!(RpcErrorCode::FilePruned.code(), 105);
+        assert_eq!(RpcErrorCode::InvalidSegment.code(), 106);
+        assert_eq!(RpcErrorCode::InvalidProof.code(), 107);
+        assert_eq!(RpcErrorCode::FileSizeMismatch.code(), 108);
+        assert_eq!(RpcErrorCode::RootMismatch.code(), 109);
+        assert_eq!(RpcErrorCode::SegmentAlreadyUploaded.code(), 110);
+        assert_eq!(RpcErrorCode::FileTooLargeToCache.code(), 111);
+        assert_eq!(RpcErrorCode::ChunkPoolBusy.code(), 112);
+        assert_eq!(RpcErrorCode::TxReverted.code(), 113);
+        assert_eq!(RpcErrorCode::BatchTooLarge.code(), 114);
+        assert_eq!(RpcErrorCode::FileTooLarge.code(), 115);
+        assert_eq!(RpcErrorCode::FlowEntriesOutOfShard.code(), 116);
+        assert_eq!(RpcErrorCode::FlowEntriesUnavailable.code(), 117);
+        assert_eq!(RpcErrorCode::SegmentConflicted.code(), 118);
+        assert_eq!(RpcErrorCode::UploadSessionNotFound.code(), 119);
+        assert_eq!(RpcErrorCode::TooManyUploadSessions.code(), 120);
+        assert_eq!(RpcErrorCode::ProofVerifierBusy.code(), 121);
+        assert_eq!(RpcErrorCode::StorageError.code(), 201);
+        assert_eq!(RpcErrorCode::SyncError.code(), 202);
+        assert_eq!(RpcErrorCode::NodeShuttingDown.code(), 203);
+        assert_eq!(RpcErrorCode::StorageTimeout.code(), 204);
+        assert_eq!(RpcErrorCode::NodeReadOnly.code(), 205);
+    }
+
+    #[test]
+    fn test_storage_error() {
+        let err = storage_error(anyhow!(storage_async::Error::Timeout));
+        assert_eq!(error_code(&err), 204);
+
+        let err = storage_error(anyhow!("db corrupted"));
+        assert_eq!(error_code(&err), 201);
+        assert_eq!(error_data(&err), json!({"reason": "db corrupted"}));
+
+        let err = storage_error(anyhow!(StoreError::Pruned { tx_seq: 3 }));
+        assert_eq!(error_code(&err), 105);
+        assert_eq!(error_data(&err), json!({"tx_seq": 3}));
+
+        let err = storage_error(anyhow!(StoreError::Busy));
+        assert_eq!(error_code(&err), 204);
+
+        let err = storage_error(anyhow!(StoreError::tx_not_found(3)));
+        assert_eq!(error_code(&err), 201);
+        assert_eq!(error_data(&err), json!({"reason": "tx 3 not found"}));
+    }
+
+    #[test]
+    fn test_error_data() {
+        let err = segment_out_of_range(5, 3);
+        assert_eq!(error_code(&err), 101);
+        assert_eq!(error_data(&err), json!({"index": 5, "num_segments": 3}));
+
+        let err = file_already_finalized(7);
+        assert_eq!(error_code(&err), 104);
+        assert_eq!(error_data(&err), json!({"tx_seq": 7}));
+    }
+
+    #[test]
+    fn test_chunk_pool_error() {
+        let err = chunk_pool_error(ChunkPoolError::PoolFull { limit: 16 }.into());
+        assert_eq!(error_code(&err), 112);
+        assert_eq!(error_data(&err), json!({"limit": 16}));
+
+        let err = chunk_pool_error(ChunkPoolError::SegmentAlreadyUploaded(2).into());
+        assert_eq!(error_code(&err), 110);
+
+        let err = chunk_pool_error(ChunkPoolError::SegmentConflicted(3).into());
+        assert_eq!(error_code(&err), 118);
+        assert_eq!(error_data(&err), json!({"index": 3}));
+
+        let err = chunk_pool_error(ChunkPoolError::TxReverted.into());
+        assert_eq!(error_code(&err), 113);
+
+        let err = chunk_pool_error(ChunkPoolError::FileNotFound(DataRoot::zero()).into());
+        assert_eq!(error_code(&err), 102);
+
+        let err = chunk_pool_error(ChunkPoolError::ReadOnly.into());
+        assert_eq!(error_code(&err), 205);
+
+        // errors from storage
+        let err = chunk_pool_error(anyhow!("db error"));
+        assert_eq!(error_code(&err), 201);
+        assert_eq!(error_data(&err), json!({"reason": "db error"}));
+    }
+}
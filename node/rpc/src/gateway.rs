@@ -0,0 +1,832 @@
+//! HTTP server which dispatches JSON-RPC requests to the registered methods.
+//!
+//! `jsonrpsee` 0.14 neither exposes the HTTP headers to methods nor allows a middleware to reject
+//! a call, so all RPCs are served by this small server, which:
+//! - authenticates the admin requests if configured, see [`AdminAuth`].
+//! - tags every request with a correlation id, which is taken from the `X-Request-Id` header or
+//!   generated. The id is recorded in the `rpc` span of each call, so that logs of the sync
+//!   requests and storage operations on behalf of the call could be found by a single grep, and
+//!   echoed back in the `X-Request-Id` response header and the data of error responses.
+//! - exposes the client IP to methods via [`client_ip`], e.g. to cap the upload sessions.
+//! - serves the results of download methods in SSZ instead of JSON, if a single call is
+//!   requested with `Accept: application/octet-stream`, see [`BinaryMethods`]. The binary result
+//!   is sent as the body with the same content type, or `404 Not Found` if the result is `None`.
+//!   Errors are still sent as JSON-RPC error objects, and calls of other methods or in a batch
+//!   are served in JSON.
+//!
+//! The limits of the `jsonrpsee` server are enforced as well, see [`GatewayLimits`].
+
+use crate::auth::AdminAuth;
+use crate::error;
+use futures::future::BoxFuture;
+use futures::{FutureExt, StreamExt};
+use hyper::body::HttpBody;
+use hyper::header::{HeaderName, HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, HeaderMap, Method, Request, Response, StatusCode};
+use jsonrpsee::core::server::rpc_module::Methods;
+use jsonrpsee::core::{Error as RpcError, RpcResult};
+use jsonrpsee::types::error::CallError;
+use jsonrpsee::types::Params;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Content type of the SSZ encoded results, which is requested via the `Accept` header.
+pub const BINARY_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Maximum length of the correlation id provided by client.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// Maximum number of calls of a batch that are handled concurrently.
+const MAX_CONCURRENT_BATCH_CALLS: usize = 16;
+
+/// Error code of the responses beyond `max_response_body_size`, the same as `jsonrpsee`.
+const OVERSIZED_RESPONSE_CODE: i32 = -32702;
+
+tokio::task_local! {
+    /// IP of the client that the current call is handled for.
+    static CLIENT_IP: IpAddr;
+}
+
+/// Returns the IP of the client that the current call is handled for, or the unspecified
+/// address if the method is not called via the gateway.
+pub fn client_ip() -> IpAddr {
+    CLIENT_IP
+        .try_with(|ip| *ip)
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+/// Correlates the logs of a request across services, e.g. RPC, sync and storage. It is only
+/// used in logs, and never sent to peers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn generate() -> Self {
+        RequestId(format!("{:016x}", rand::random::<u64>()))
+    }
+
+    /// Takes the id from the `X-Request-Id` header, or generates a new one if not provided or
+    /// invalid, e.g. too long or any character not allowed.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|id| Self::is_valid(id))
+            .map(|id| RequestId(id.to_string()))
+            .unwrap_or_else(Self::generate)
+    }
+
+    fn is_valid(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= MAX_REQUEST_ID_LEN
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Methods that could be served in SSZ instead of JSON, which share the handlers of the JSON-RPC
+/// methods, so that the data is never encoded in JSON on either end.
+pub trait BinaryMethods: Send + Sync {
+    /// Returns the future of the SSZ encoded result, which is `None` if not available, or `None`
+    /// if `method` is not served in binary.
+    fn call(
+        &self,
+        method: &str,
+        params: Params<'_>,
+    ) -> Option<BoxFuture<'static, RpcResult<Option<Vec<u8>>>>>;
+}
+
+/// Limits of a gateway server.
+#[derive(Clone, Copy, Debug)]
+pub struct GatewayLimits {
+    pub max_request_body_size: u32,
+    /// Maximum size of the response body, including all responses of a batch.
+    pub max_response_body_size: u32,
+    /// Maximum number of concurrent connections, beyond which new connections are closed.
+    pub max_connections: usize,
+}
+
+struct Gateway {
+    name: &'static str,
+    methods: Methods,
+    binary: Option<Arc<dyn BinaryMethods>>,
+    auth: Option<AdminAuth>,
+    limits: GatewayLimits,
+}
+
+/// Starts the HTTP server which dispatches requests to `methods`, or to `binary` if requested in
+/// binary, and authenticates requests before dispatching if `auth` is configured.
+///
+/// The returned future completes once `shutdown` resolves and all in-flight requests are
+/// handled.
+pub fn run_gateway(
+    name: &'static str,
+    listen_address: SocketAddr,
+    methods: Methods,
+    binary: Option<Arc<dyn BinaryMethods>>,
+    auth: Option<AdminAuth>,
+    limits: GatewayLimits,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<BoxFuture<'static, ()>, Box<dyn std::error::Error>> {
+    let authenticated = auth.is_some();
+    let gateway = Arc::new(Gateway {
+        name,
+        methods,
+        binary,
+        auth,
+        limits,
+    });
+    let connections = Arc::new(Semaphore::new(limits.max_connections));
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let remote_addr = conn.remote_addr();
+        let gateway = gateway.clone();
+        // The permit is released once the connection closed, and the service dropped.
+        let permit = connections.clone().try_acquire_owned();
+
+        async move {
+            let permit = match permit {
+                Ok(permit) => permit,
+                Err(_) => {
+                    debug!(%remote_addr, "Too many {} RPC connections", gateway.name);
+                    return Err("too many connections");
+                }
+            };
+            Ok(service_fn(move |request| {
+                let _permit = &permit;
+                gateway.clone().handle_request(request, remote_addr)
+            }))
+        }
+    });
+
+    let server = hyper::Server::try_bind(&listen_address)?
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown);
+    info!(%listen_address, %authenticated, "{} RPC server started", name);
+
+    Ok(async move {
+        if let Err(e) = server.await {
+            error!("{} RPC server terminated: {:?}", name, e);
+        }
+    }
+    .boxed())
+}
+
+impl Gateway {
+    async fn handle_request(
+        self: Arc<Self>,
+        request: Request<Body>,
+        remote_addr: SocketAddr,
+    ) -> Result<Response<Body>, Infallible> {
+        let request_id = RequestId::from_headers(request.headers());
+        let mut response = self
+            .handle_request_inner(request, remote_addr, &request_id)
+            .await;
+        if let Ok(value) = HeaderValue::from_str(request_id.as_str()) {
+            response
+                .headers_mut()
+                .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+        }
+        Ok(response)
+    }
+
+    async fn handle_request_inner(
+        &self,
+        request: Request<Body>,
+        remote_addr: SocketAddr,
+        request_id: &RequestId,
+    ) -> Response<Body> {
+        if request.method() != Method::POST {
+            return empty_response(StatusCode::METHOD_NOT_ALLOWED);
+        }
+
+        let declared_size = request
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        let max_request_body_size = self.limits.max_request_body_size;
+        if declared_size.map_or(false, |size| size > max_request_body_size as u64) {
+            return empty_response(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        // the body may be chunked without a declared size, so the limit is checked while reading
+        let (parts, body) = request.into_parts();
+        let body = match read_body(body, max_request_body_size as usize).await {
+            Ok(Some(body)) => body,
+            Ok(None) => return empty_response(StatusCode::PAYLOAD_TOO_LARGE),
+            Err(e) => {
+                debug!(%remote_addr, %request_id, "Failed to read {} RPC request: {:?}", self.name, e);
+                return empty_response(StatusCode::BAD_REQUEST);
+            }
+        };
+
+        let calls = match serde_json::from_slice::<Value>(&body) {
+            Ok(Value::Array(calls)) if !calls.is_empty() => Calls::Batch(calls),
+            Ok(call @ Value::Object(_)) => Calls::Single(call),
+            Ok(_) => {
+                return json_response(
+                    StatusCode::BAD_REQUEST,
+                    error_object(Value::Null, -32600, "Invalid request", None, request_id),
+                )
+            }
+            Err(_) => {
+                return json_response(
+                    StatusCode::BAD_REQUEST,
+                    error_object(Value::Null, -32700, "Parse error", None, request_id),
+                )
+            }
+        };
+
+        if let Some(auth) = &self.auth {
+            if let Err(e) = auth.authorize(&remote_addr, &parts.headers, &body) {
+                warn!(%remote_addr, %request_id, "Unauthorized admin RPC request: {}", e);
+                return json_response(
+                    StatusCode::UNAUTHORIZED,
+                    error_object(
+                        calls.first_id(),
+                        error::UNAUTHORIZED_CODE,
+                        "Unauthorized",
+                        Some(Value::String(e.to_string())),
+                        request_id,
+                    ),
+                );
+            }
+        }
+
+        if accepts_binary(&parts.headers) {
+            if let Calls::Single(call) = &calls {
+                if let Some(response) = self.handle_binary_call(call, remote_addr, request_id).await
+                {
+                    return response;
+                }
+            }
+        }
+
+        let id = calls.first_id();
+        let response = match calls {
+            Calls::Single(call) => self.handle_call(call, remote_addr, request_id).await,
+            Calls::Batch(calls) => {
+                let responses = futures::stream::iter(calls)
+                    .map(|call| self.handle_call(call, remote_addr, request_id))
+                    .buffered(MAX_CONCURRENT_BATCH_CALLS)
+                    .collect()
+                    .await;
+                Value::Array(responses)
+            }
+        };
+
+        let body = response.to_string();
+        if body.len() > self.limits.max_response_body_size as usize {
+            debug!(%request_id, size = body.len(), "{} RPC response too big", self.name);
+            return json_response(
+                StatusCode::OK,
+                oversized_response(id, self.limits.max_response_body_size, request_id),
+            );
+        }
+        json_body_response(StatusCode::OK, body)
+    }
+
+    /// Dispatches a single call in the `rpc` span of the request, and adds the correlation id
+    /// to the error data if any.
+    async fn handle_call(
+        &self,
+        call: Value,
+        remote_addr: SocketAddr,
+        request_id: &RequestId,
+    ) -> Value {
+        let id = call.get("id").cloned().unwrap_or(Value::Null);
+        let method = call
+            .get("method")
+            .and_then(|m| m.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let span = info_span!("rpc", %request_id, %method);
+
+        async move {
+            let started_at = Instant::now();
+            let result = CLIENT_IP
+                .scope(
+                    remote_addr.ip(),
+                    self.methods.raw_json_request(&call.to_string()),
+                )
+                .await;
+            debug!(elapsed = ?started_at.elapsed(), "RPC call handled");
+
+            let response = match result {
+                Ok((response, _)) => response,
+                Err(e) => {
+                    return error_object(
+                        id,
+                        -32603,
+                        "Internal error",
+                        Some(Value::String(e.to_string())),
+                        request_id,
+                    )
+                }
+            };
+
+            match serde_json::from_str::<Value>(&response) {
+                Ok(mut response) => {
+                    if let Some(error) = response.get_mut("error") {
+                        debug!(%error, "RPC call failed");
+                        add_request_id(error, request_id);
+                    }
+                    response
+                }
+                Err(e) => error_object(
+                    id,
+                    -32603,
+                    "Internal error",
+                    Some(Value::String(e.to_string())),
+                    request_id,
+                ),
+            }
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Dispatches a single call to the binary methods in the `rpc` span of the request, or
+    /// returns `None` if the method is not served in binary.
+    async fn handle_binary_call(
+        &self,
+        call: &Value,
+        remote_addr: SocketAddr,
+        request_id: &RequestId,
+    ) -> Option<Response<Body>> {
+        let binary = self.binary.as_ref()?;
+        let method = call.get("method").and_then(|m| m.as_str())?;
+        let params = call.get("params").map(|p| p.to_string());
+        let future = binary.call(method, Params::new(params.as_deref()))?;
+        let span = info_span!("rpc", %request_id, %method, binary = true);
+
+        let response = async move {
+            let started_at = Instant::now();
+            let result = CLIENT_IP.scope(remote_addr.ip(), future).await;
+            debug!(elapsed = ?started_at.elapsed(), "RPC call handled");
+
+            match result {
+                Ok(Some(result)) if result.len() > self.limits.max_response_body_size as usize => {
+                    let id = call.get("id").cloned().unwrap_or(Value::Null);
+                    json_response(
+                        StatusCode::OK,
+                        oversized_response(id, self.limits.max_response_body_size, request_id),
+                    )
+                }
+                Ok(Some(result)) => binary_response(result),
+                Ok(None) => empty_response(StatusCode::NOT_FOUND),
+                Err(e) => {
+                    let id = call.get("id").cloned().unwrap_or(Value::Null);
+                    let mut error = error_value(e);
+                    debug!(%error, "RPC call failed");
+                    add_request_id(&mut error, request_id);
+                    json_response(
+                        StatusCode::OK,
+                        json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+                    )
+                }
+            }
+        }
+        .instrument(span)
+        .await;
+
+        Some(response)
+    }
+}
+
+/// Returns whether the client requests the result in binary.
+fn accepts_binary(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.split(';').next().unwrap_or_default().trim() == BINARY_CONTENT_TYPE)
+}
+
+/// Converts the error of a method into a JSON-RPC error object as `jsonrpsee` does.
+fn error_value(e: RpcError) -> Value {
+    let (code, message, data) = match e {
+        RpcError::Call(CallError::Custom(error)) => {
+            let data = error
+                .data()
+                .and_then(|data| serde_json::from_str::<Value>(data.get()).ok());
+            (error.code(), error.message().to_string(), data)
+        }
+        RpcError::Call(CallError::InvalidParams(e)) => (-32602, e.to_string(), None),
+        e => (-32603, e.to_string(), None),
+    };
+
+    let mut error = json!({ "code": code, "message": message });
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+    error
+}
+
+enum Calls {
+    Single(Value),
+    Batch(Vec<Value>),
+}
+
+impl Calls {
+    fn first_id(&self) -> Value {
+        let call = match self {
+            Calls::Single(call) => Some(call),
+            Calls::Batch(calls) => calls.first(),
+        };
+
+        call.and_then(|c| c.get("id"))
+            .cloned()
+            .unwrap_or(Value::Null)
+    }
+}
+
+/// Adds the correlation id to the data of an error object. Data that is not a JSON object, e.g.
+/// a string reason, is kept as it is, and the id is only available in the response header.
+fn add_request_id(error: &mut Value, request_id: &RequestId) {
+    let error = match error.as_object_mut() {
+        Some(error) => error,
+        None => return,
+    };
+
+    match error.get_mut("data") {
+        Some(Value::Object(data)) => {
+            data.insert("request_id".into(), request_id.as_str().into());
+        }
+        None | Some(Value::Null) => {
+            error.insert("data".into(), json!({ "request_id": request_id.as_str() }));
+        }
+        Some(_) => {}
+    }
+}
+
+fn error_object(
+    id: Value,
+    code: i32,
+    message: &str,
+    data: Option<Value>,
+    request_id: &RequestId,
+) -> Value {
+    let mut error = json!({ "code": code, "message": message });
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+    add_request_id(&mut error, request_id);
+
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+fn oversized_response(id: Value, max_response_body_size: u32, request_id: &RequestId) -> Value {
+    error_object(
+        id,
+        OVERSIZED_RESPONSE_CODE,
+        "Response is too big",
+        Some(json!({ "max_response_body_size": max_response_body_size })),
+        request_id,
+    )
+}
+
+/// Reads the request body, or returns `None` as soon as it is beyond `limit` bytes, without
+/// buffering the rest.
+async fn read_body(mut body: Body, limit: usize) -> hyper::Result<Option<Vec<u8>>> {
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if buf.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(Some(buf))
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+fn binary_response(body: Vec<u8>) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(BINARY_CONTENT_TYPE));
+    response
+}
+
+fn json_response(status: StatusCode, body: Value) -> Response<Body> {
+    json_body_response(status, body.to_string())
+}
+
+fn json_body_response(status: StatusCode, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::Bytes;
+    use hyper::header::AUTHORIZATION;
+    use jsonrpsee::RpcModule;
+
+    /// Serves `test_echo` in binary as the big endian bytes, or `None` if zero.
+    struct BinaryEcho;
+
+    impl BinaryMethods for BinaryEcho {
+        fn call(
+            &self,
+            method: &str,
+            params: Params<'_>,
+        ) -> Option<BoxFuture<'static, RpcResult<Option<Vec<u8>>>>> {
+            if method != "test_echo" {
+                return None;
+            }
+
+            let result = params
+                .one::<u64>()
+                .map(|v| (v > 0).then(|| v.to_be_bytes().to_vec()))
+                .map_err(Into::into);
+            Some(futures::future::ready(result).boxed())
+        }
+    }
+
+    fn gateway() -> Gateway {
+        let mut module = RpcModule::new(());
+        module
+            .register_method("test_echo", |params, _| Ok(params.one::<u64>()?))
+            .unwrap();
+        module
+            .register_method("test_fail", |_, _| -> jsonrpsee::core::RpcResult<()> {
+                Err(error::file_not_finalized(3))
+            })
+            .unwrap();
+        module
+            .register_method("test_repeat", |params, _| {
+                Ok("a".repeat(params.one::<usize>()?))
+            })
+            .unwrap();
+
+        Gateway {
+            name: "Test",
+            methods: module.into(),
+            binary: Some(Arc::new(BinaryEcho)),
+            auth: None,
+            limits: GatewayLimits {
+                max_request_body_size: 1024,
+                max_response_body_size: 1024,
+                max_connections: 1,
+            },
+        }
+    }
+
+    fn post(body: &str, request_id: Option<&str>) -> Request<Body> {
+        let mut builder = Request::post("/");
+        if let Some(id) = request_id {
+            builder = builder.header(REQUEST_ID_HEADER, id);
+        }
+        builder.body(Body::from(body.to_string())).unwrap()
+    }
+
+    async fn call_raw(request: Request<Body>) -> Response<Body> {
+        Arc::new(gateway())
+            .handle_request(request, "127.0.0.1:40000".parse().unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn call(request: Request<Body>) -> (String, Value) {
+        let response = call_raw(request).await;
+        let request_id = response.headers()[REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (request_id, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn test_request_id_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(RequestId::from_headers(&headers).as_str().len(), 16);
+
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("abc-123"));
+        assert_eq!(RequestId::from_headers(&headers).as_str(), "abc-123");
+
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("abc 123"));
+        assert_ne!(RequestId::from_headers(&headers).as_str(), "abc 123");
+
+        let too_long = "a".repeat(MAX_REQUEST_ID_LEN + 1);
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_str(&too_long).unwrap());
+        assert_ne!(RequestId::from_headers(&headers).as_str(), too_long);
+    }
+
+    #[tokio::test]
+    async fn test_echo_request_id() {
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"test_echo","params":[7]}"#;
+        let (request_id, response) = call(post(body, Some("abc-123"))).await;
+        assert_eq!(request_id, "abc-123");
+        assert_eq!(response["result"], 7);
+
+        // generated if not provided
+        let (request_id, _) = call(post(body, None)).await;
+        assert_eq!(request_id.len(), 16);
+    }
+
+    #[tokio::test]
+    async fn test_request_id_in_error_data() {
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"test_fail","params":[]}"#;
+        let (_, response) = call(post(body, Some("abc-123"))).await;
+        assert_eq!(response["error"]["code"], 103);
+        assert_eq!(
+            response["error"]["data"],
+            json!({"tx_seq": 3, "request_id": "abc-123"})
+        );
+
+        // method not found without error data
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"test_unknown","params":[]}"#;
+        let (_, response) = call(post(body, Some("abc-123"))).await;
+        assert_eq!(response["error"]["code"], -32601);
+        assert_eq!(response["error"]["data"], json!({"request_id": "abc-123"}));
+    }
+
+    #[tokio::test]
+    async fn test_batch() {
+        let body = r#"[
+            {"jsonrpc":"2.0","id":1,"method":"test_echo","params":[7]},
+            {"jsonrpc":"2.0","id":2,"method":"test_fail","params":[]}
+        ]"#;
+        let (_, response) = call(post(body, Some("abc-123"))).await;
+        assert_eq!(response[0]["id"], 1);
+        assert_eq!(response[0]["result"], 7);
+        assert_eq!(response[1]["id"], 2);
+        assert_eq!(response[1]["error"]["data"]["request_id"], "abc-123");
+
+        let (_, response) = call(post("[]", None)).await;
+        assert_eq!(response["error"]["code"], -32600);
+
+        let (_, response) = call(post("{", Some("abc-123"))).await;
+        assert_eq!(response["error"]["code"], -32700);
+        assert_eq!(response["error"]["data"]["request_id"], "abc-123");
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response() {
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"test_repeat","params":[900]}"#;
+        let (_, response) = call(post(body, None)).await;
+        assert_eq!(response["result"].as_str().unwrap().len(), 900);
+
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"test_repeat","params":[1100]}"#;
+        let (_, response) = call(post(body, Some("abc-123"))).await;
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["error"]["code"], OVERSIZED_RESPONSE_CODE);
+        assert_eq!(response["error"]["data"]["request_id"], "abc-123");
+
+        // all responses of a batch are limited
+        let repeat = r#"{"jsonrpc":"2.0","id":1,"method":"test_repeat","params":[400]}"#;
+        let (_, response) = call(post(&format!("[{},{}]", repeat, repeat), None)).await;
+        assert_eq!(response[1]["result"].as_str().unwrap().len(), 400);
+        let batch = format!("[{},{},{}]", repeat, repeat, repeat);
+        let (_, response) = call(post(&batch, None)).await;
+        assert_eq!(response["error"]["code"], OVERSIZED_RESPONSE_CODE);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_chunked_request() {
+        // chunked without `Content-Length`, and never ends
+        let (mut sender, body) = Body::channel();
+        let sending = tokio::spawn(async move {
+            while sender.send_data(Bytes::from(vec![b' '; 100])).await.is_ok() {}
+        });
+        let response = call_raw(Request::post("/").body(body).unwrap()).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // stopped reading once beyond the limit
+        drop(response);
+        sending.await.unwrap();
+
+        // chunked within the limit
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            let call = r#"{"jsonrpc":"2.0","id":1,"method":"test_echo","params":[7]}"#;
+            sender
+                .send_data(Bytes::from(vec![b' '; 900]))
+                .await
+                .unwrap();
+            sender.send_data(Bytes::from(call)).await.unwrap();
+        });
+        let (_, response) = call(Request::post("/").body(body).unwrap()).await;
+        assert_eq!(response["result"], 7);
+    }
+
+    #[tokio::test]
+    async fn test_binary() {
+        let binary_post = |body: &str| {
+            let mut request = post(body, Some("abc-123"));
+            request.headers_mut().insert(
+                ACCEPT,
+                HeaderValue::from_static("application/json, application/octet-stream;q=0.9"),
+            );
+            request
+        };
+
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"test_echo","params":[7]}"#;
+        let response = call_raw(binary_post(body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], BINARY_CONTENT_TYPE);
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "abc-123");
+        let result = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(result.as_ref(), 7u64.to_be_bytes());
+
+        // not available
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"test_echo","params":[0]}"#;
+        let response = call_raw(binary_post(body)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // errors in JSON
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"test_echo","params":["a"]}"#;
+        let response = call_raw(binary_post(body)).await;
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        let response: Value =
+            serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await.unwrap())
+                .unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["error"]["code"], -32602);
+        assert_eq!(response["error"]["data"]["request_id"], "abc-123");
+
+        // other methods in JSON
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"test_fail","params":[]}"#;
+        let response = call_raw(binary_post(body)).await;
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+
+        // JSON by default
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"test_echo","params":[7]}"#;
+        let (_, response) = call(post(body, None)).await;
+        assert_eq!(response["result"], 7);
+    }
+
+    #[tokio::test]
+    async fn test_authenticated_batch() {
+        let gateway = Arc::new(Gateway {
+            auth: Some(
+                AdminAuth::new(&crate::config::AdminAuthConfig {
+                    token: Some("secret".into()),
+                    allow_localhost: false,
+                    ..Default::default()
+                })
+                .unwrap(),
+            ),
+            ..gateway()
+        });
+        let batch = r#"[
+            {"jsonrpc":"2.0","id":1,"method":"test_echo","params":[7]},
+            {"jsonrpc":"2.0","id":2,"method":"test_echo","params":[8]}
+        ]"#;
+        let remote_addr = "10.0.0.1:40000".parse().unwrap();
+
+        let request = Request::post("/")
+            .header(AUTHORIZATION, "Bearer secret")
+            .body(Body::from(batch))
+            .unwrap();
+        let response = gateway.clone().handle_request(request, remote_addr).await;
+        let body = hyper::body::to_bytes(response.unwrap().into_body())
+            .await
+            .unwrap();
+        let response: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response[0]["result"], 7);
+        assert_eq!(response[1]["result"], 8);
+
+        let response = gateway
+            .handle_request(post(batch, None), remote_addr)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
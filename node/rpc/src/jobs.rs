@@ -0,0 +1,642 @@
+//! Long-running admin operations, e.g. a full scrub of the flow or a file export, which run in
+//! background as jobs instead of tied to the RPC request that started them.
+//!
+//! Jobs are persisted in store along with their state, progress and checkpoint, which is
+//! updated after every step, so that the running jobs are resumed from the last checkpoint once
+//! the node restarts. Each job type defines its own checkpoint format. Finished jobs are kept
+//! for inspection up to `max_finished_jobs`, beyond which the oldest ones are removed.
+
+use crate::types::{ExportedFile, FlowBatchMismatch, JobInfo, JobParams, JobResult, JobState};
+use anyhow::{anyhow, bail, Result};
+use futures::StreamExt;
+use jsonrpsee::core::async_trait;
+use sha2::{Digest, Sha256};
+use shared_types::{Transaction, TxSeqOrRoot, CHUNK_SIZE};
+use ssz::{Decode, Encode};
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
+use std::collections::HashMap;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use storage::log_store::job_store::{
+    AdminJob, JOB_STATE_CANCELLED, JOB_STATE_COMPLETED, JOB_STATE_FAILED, JOB_STATE_RUNNING,
+};
+use storage::log_store::log_manager::{bytes_to_entries, PORA_CHUNK_SIZE};
+use storage_async::Store;
+use task_executor::TaskExecutor;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::watch;
+
+/// Number of flow chunks verified by a step of scrub.
+const SCRUB_STEP_CHUNKS: u64 = 16 * PORA_CHUNK_SIZE as u64;
+/// Number of reads from the store by a step of export.
+const EXPORT_STEP_READS: usize = 16;
+
+#[derive(Clone, Debug)]
+pub struct JobConfig {
+    /// Number of chunks read from the store at a time by exports.
+    pub chunks_per_read: usize,
+    /// Pause between the steps of a job, so that jobs do not hog the store.
+    pub step_interval: Duration,
+    /// Number of finished jobs kept in store, beyond which the oldest ones are removed.
+    pub max_finished_jobs: usize,
+}
+
+/// Runs a job from its checkpoint, which is created once the job is started or resumed.
+#[async_trait]
+trait JobRunner: Send {
+    /// Runs a step of the job, and updates the checkpoint and progress of `job`, which are
+    /// persisted after the step. Returns `true` once the job completes.
+    async fn step(&mut self, job: &mut AdminJob) -> Result<bool>;
+
+    /// Cleans up once the job failed or cancelled.
+    async fn abort(&mut self) {}
+}
+
+#[derive(Debug, Default, DeriveEncode, DeriveDecode)]
+struct ScrubCheckpoint {
+    /// Next flow chunk to verify.
+    next_chunk: u64,
+    /// Batches failed to verify so far, in ascending order.
+    mismatched_batches: Vec<u64>,
+}
+
+struct ScrubRunner {
+    store: Arc<Store>,
+    start_chunk: u64,
+    end_chunk: u64,
+    checkpoint: ScrubCheckpoint,
+}
+
+#[async_trait]
+impl JobRunner for ScrubRunner {
+    async fn step(&mut self, job: &mut AdminJob) -> Result<bool> {
+        let start = self.checkpoint.next_chunk.max(self.start_chunk);
+        if start < self.end_chunk {
+            let end = self.end_chunk.min(start + SCRUB_STEP_CHUNKS);
+            for batch in self.store.verify_flow_range(start, end).await? {
+                // A batch across steps is verified by both steps.
+                if self.checkpoint.mismatched_batches.last() != Some(&batch) {
+                    self.checkpoint.mismatched_batches.push(batch);
+                }
+            }
+            self.checkpoint.next_chunk = end;
+        }
+
+        job.checkpoint = self.checkpoint.as_ssz_bytes();
+        job.progress_done = self.checkpoint.next_chunk - self.start_chunk;
+        Ok(self.checkpoint.next_chunk >= self.end_chunk)
+    }
+}
+
+#[derive(Debug, Default, DeriveEncode, DeriveDecode)]
+struct ExportCheckpoint {
+    /// Number of file chunks written and synced into the file.
+    written_chunks: u64,
+    /// Sha256 of the file data, which is set once completed.
+    sha256: Vec<u8>,
+}
+
+struct ExportRunner {
+    store: Arc<Store>,
+    tx: Transaction,
+    path: PathBuf,
+    chunks_per_read: usize,
+    /// Whether to resume the file written before, or create a new one. The file of a resumed job
+    /// is its own even if the checkpoint is empty, as the node may stop once the file is created
+    /// by the first step but before its checkpoint is persisted.
+    resume: bool,
+    /// File being written along with the hash of the data written, which are restored from the
+    /// checkpoint on the first step.
+    file: Option<(File, Sha256)>,
+    checkpoint: ExportCheckpoint,
+}
+
+impl ExportRunner {
+    fn written_bytes(&self) -> u64 {
+        (self.checkpoint.written_chunks * CHUNK_SIZE as u64).min(self.tx.size)
+    }
+
+    /// Opens the file to write, and hashes the data written before if resumed. The data written
+    /// after the checkpoint is truncated.
+    async fn open(&self) -> Result<(File, Sha256)> {
+        let mut hasher = Sha256::new();
+        if !self.resume {
+            let file = match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&self.path)
+                .await
+            {
+                Ok(file) => file,
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    bail!("file already exists: {}", self.path.display())
+                }
+                Err(e) => bail!("failed to create file: {:?}", e),
+            };
+            return Ok((file, hasher));
+        }
+
+        let written = self.written_bytes();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(written == 0)
+            .open(&self.path)
+            .await
+            .map_err(|e| anyhow!("failed to open the exported file to resume: {:?}", e))?;
+        let mut buf = vec![0u8; 1024 * 1024];
+        let mut remaining = written;
+        while remaining > 0 {
+            let len = remaining.min(buf.len() as u64) as usize;
+            file.read_exact(&mut buf[..len])
+                .await
+                .map_err(|e| anyhow!("exported file shorter than the checkpoint: {:?}", e))?;
+            hasher.update(&buf[..len]);
+            remaining -= len as u64;
+        }
+        file.set_len(written).await?;
+        file.seek(SeekFrom::Start(written)).await?;
+        Ok((file, hasher))
+    }
+}
+
+#[async_trait]
+impl JobRunner for ExportRunner {
+    async fn step(&mut self, job: &mut AdminJob) -> Result<bool> {
+        if self.file.is_none() {
+            self.file = Some(self.open().await?);
+        }
+
+        let num_chunks = bytes_to_entries(self.tx.size);
+        let start = self.checkpoint.written_chunks;
+        let end = num_chunks.min(start + (self.chunks_per_read * EXPORT_STEP_READS) as u64);
+        let (file, hasher) = self.file.as_mut().expect("opened above");
+        if start < end {
+            let data = self
+                .store
+                .read_file_stream_from(&self.tx, start as usize, self.chunks_per_read)
+                .take(EXPORT_STEP_READS);
+            futures::pin_mut!(data);
+            while let Some(batch) = data.next().await {
+                let batch = batch?;
+                file.write_all(&batch).await?;
+                hasher.update(&batch);
+            }
+            // The checkpoint is persisted only after the data is durable.
+            file.sync_data().await?;
+            self.checkpoint.written_chunks = end;
+        }
+
+        let completed = self.checkpoint.written_chunks >= num_chunks;
+        if completed {
+            let size = file.metadata().await?.len();
+            if size != self.tx.size {
+                bail!(
+                    "file size mismatch: expected={}, actual={}",
+                    self.tx.size,
+                    size
+                );
+            }
+            file.sync_all().await?;
+            self.checkpoint.sha256 = hasher.clone().finalize().to_vec();
+        }
+
+        job.checkpoint = self.checkpoint.as_ssz_bytes();
+        job.progress_done = self.written_bytes();
+        Ok(completed)
+    }
+
+    async fn abort(&mut self) {
+        // The file is left intact if not created by this job, e.g. created by others meanwhile.
+        if self.file.take().is_some() || self.resume {
+            remove_exported_file(&self.path).await;
+        }
+    }
+}
+
+async fn remove_exported_file(path: &Path) {
+    match fs::remove_file(path).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => warn!(?e, path = %path.display(), "Failed to remove partially exported file"),
+    }
+}
+
+/// Runs the admin jobs of the node, whose state is persisted in store.
+pub struct JobManager {
+    store: Arc<Store>,
+    executor: TaskExecutor,
+    config: JobConfig,
+    next_id: AtomicU64,
+    /// Cancel signals of the jobs running on this node, by id.
+    running: Mutex<HashMap<u64, Arc<watch::Sender<bool>>>>,
+    /// Notified once any job is updated in store.
+    updates: watch::Sender<()>,
+}
+
+impl JobManager {
+    pub async fn new(store: Arc<Store>, executor: TaskExecutor, config: JobConfig) -> Result<Self> {
+        let next_id = store
+            .get_admin_jobs()
+            .await?
+            .last()
+            .map_or(0, |job| job.id + 1);
+
+        let manager = Self {
+            store,
+            executor,
+            config,
+            next_id: AtomicU64::new(next_id),
+            running: Default::default(),
+            updates: watch::channel(()).0,
+        };
+        manager.prune_finished().await?;
+        Ok(manager)
+    }
+
+    /// Resumes the jobs that were running when the node stopped. Returns the number of jobs
+    /// resumed. Jobs failed to resume, e.g. the file to export reverted, are marked failed.
+    pub async fn resume(self: &Arc<Self>) -> Result<usize> {
+        let mut resumed = 0;
+        for job in self.store.get_admin_jobs().await? {
+            if !job.is_running() || self.running.lock().unwrap().contains_key(&job.id) {
+                continue;
+            }
+
+            let id = job.id;
+            match self.runner(&job, true).await {
+                Ok(runner) => {
+                    info!(%id, "Resume admin job");
+                    self.spawn(job, runner);
+                    resumed += 1;
+                }
+                Err(e) => {
+                    warn!(%id, ?e, "Failed to resume admin job");
+                    abort_not_running(&job).await;
+                    self.finish(job, JOB_STATE_FAILED, Some(e.to_string()))
+                        .await?;
+                }
+            }
+        }
+        Ok(resumed)
+    }
+
+    /// Starts a job of `params`, which should be resolved by the caller, e.g. the path of an
+    /// export is canonical.
+    pub async fn start(self: &Arc<Self>, params: JobParams) -> Result<JobInfo> {
+        let now = now();
+        let mut job = AdminJob {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            state: JOB_STATE_RUNNING,
+            params: serde_json::to_vec(&params)?,
+            checkpoint: vec![],
+            progress_done: 0,
+            progress_total: 0,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        let runner = self.runner(&job, false).await?;
+        job.progress_total = match &params {
+            JobParams::Scrub {
+                start_chunk,
+                end_chunk,
+            } => end_chunk.unwrap_or_default().saturating_sub(*start_chunk),
+            JobParams::ExportFile { .. } => self.file_to_export(&params).await?.size,
+        };
+        self.store.put_admin_job(job.clone()).await?;
+        self.updates.send_replace(());
+        info!(id = %job.id, ?params, "Admin job started");
+
+        let info = job_info(&job)?;
+        self.spawn(job, runner);
+        Ok(info)
+    }
+
+    pub async fn get(&self, id: u64) -> Result<Option<JobInfo>> {
+        match self.store.get_admin_job(id).await? {
+            Some(job) => Ok(Some(job_info(&job)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Cancels the running job, which stops after the current step, or at once if pausing
+    /// between steps. Returns `false` if the job is not found or already finished.
+    pub async fn cancel(&self, id: u64) -> Result<bool> {
+        let job = match self.store.get_admin_job(id).await? {
+            Some(job) if job.is_running() => job,
+            _ => return Ok(false),
+        };
+
+        if let Some(signal) = self.running.lock().unwrap().get(&id) {
+            signal.send_replace(true);
+            return Ok(true);
+        }
+
+        // Not resumed on this node.
+        abort_not_running(&job).await;
+        self.finish(job, JOB_STATE_CANCELLED, None).await?;
+        Ok(true)
+    }
+
+    /// Subscribes the updates of jobs, which are notified once any job is persisted, e.g. to
+    /// wait for the progress of a job instead of polling.
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.updates.subscribe()
+    }
+
+    async fn file_to_export(&self, params: &JobParams) -> Result<Transaction> {
+        let tx_seq = match params {
+            JobParams::ExportFile {
+                tx_seq_or_root: TxSeqOrRoot::TxSeq(tx_seq),
+                ..
+            } => *tx_seq,
+            _ => bail!("unresolved export params: {:?}", params),
+        };
+        self.store
+            .get_tx_by_seq_number(tx_seq)
+            .await?
+            .ok_or_else(|| anyhow!("tx {} not found", tx_seq))
+    }
+
+    /// Creates the runner of the job from its checkpoint, which is either started or `resumed`
+    /// once the node restarts.
+    async fn runner(&self, job: &AdminJob, resumed: bool) -> Result<Box<dyn JobRunner>> {
+        let params: JobParams = serde_json::from_slice(&job.params)?;
+        let runner: Box<dyn JobRunner> = match &params {
+            JobParams::Scrub {
+                start_chunk,
+                end_chunk,
+            } => Box::new(ScrubRunner {
+                store: self.store.clone(),
+                start_chunk: *start_chunk,
+                end_chunk: end_chunk.ok_or_else(|| anyhow!("unresolved scrub range"))?,
+                checkpoint: decode_checkpoint(&job.checkpoint)?,
+            }),
+            JobParams::ExportFile { path, .. } => Box::new(ExportRunner {
+                store: self.store.clone(),
+                tx: self.file_to_export(&params).await?,
+                path: PathBuf::from(path),
+                chunks_per_read: self.config.chunks_per_read.max(1),
+                resume: resumed,
+                file: None,
+                checkpoint: decode_checkpoint(&job.checkpoint)?,
+            }),
+        };
+        Ok(runner)
+    }
+
+    fn spawn(self: &Arc<Self>, job: AdminJob, runner: Box<dyn JobRunner>) {
+        let signal = Arc::new(watch::channel(false).0);
+        self.running.lock().unwrap().insert(job.id, signal.clone());
+
+        let manager = self.clone();
+        self.executor.spawn(
+            async move {
+                let id = job.id;
+                if let Err(e) = manager.run(job, runner, signal).await {
+                    error!(%id, ?e, "Failed to persist admin job");
+                }
+                manager.running.lock().unwrap().remove(&id);
+            },
+            "admin_job",
+        );
+    }
+
+    async fn run(
+        &self,
+        mut job: AdminJob,
+        mut runner: Box<dyn JobRunner>,
+        signal: Arc<watch::Sender<bool>>,
+    ) -> Result<()> {
+        let mut cancelled = signal.subscribe();
+        loop {
+            let is_cancelled = *cancelled.borrow_and_update();
+            if is_cancelled {
+                runner.abort().await;
+                info!(id = %job.id, "Admin job cancelled");
+                return self.finish(job, JOB_STATE_CANCELLED, None).await;
+            }
+
+            match runner.step(&mut job).await {
+                Ok(true) => {
+                    info!(id = %job.id, "Admin job completed");
+                    return self.finish(job, JOB_STATE_COMPLETED, None).await;
+                }
+                Ok(false) => {
+                    job.updated_at = now();
+                    self.store.put_admin_job(job.clone()).await?;
+                    self.updates.send_replace(());
+                }
+                Err(e) => {
+                    warn!(id = %job.id, ?e, "Admin job failed");
+                    runner.abort().await;
+                    return self
+                        .finish(job, JOB_STATE_FAILED, Some(e.to_string()))
+                        .await;
+                }
+            }
+
+            if !self.config.step_interval.is_zero() {
+                tokio::select! {
+                    _ = tokio::time::sleep(self.config.step_interval) => {}
+                    _ = cancelled.changed() => {}
+                }
+            }
+        }
+    }
+
+    async fn finish(&self, mut job: AdminJob, state: u8, error: Option<String>) -> Result<()> {
+        job.state = state;
+        job.error = error.map(String::into_bytes);
+        job.updated_at = now();
+        self.store.put_admin_job(job).await?;
+        self.updates.send_replace(());
+        self.prune_finished().await
+    }
+
+    /// Removes the oldest finished jobs beyond `max_finished_jobs`. The latest finished job is
+    /// always kept, so that the ids of jobs are never reused once the node restarts.
+    async fn prune_finished(&self) -> Result<()> {
+        let finished: Vec<u64> = self
+            .store
+            .get_admin_jobs()
+            .await?
+            .into_iter()
+            .filter(|job| !job.is_running())
+            .map(|job| job.id)
+            .collect();
+        let excess = finished
+            .len()
+            .saturating_sub(self.config.max_finished_jobs.max(1));
+        if excess > 0 {
+            self.store
+                .remove_admin_jobs(finished[..excess].to_vec())
+                .await?;
+            debug!(%excess, "Removed finished admin jobs");
+        }
+        Ok(())
+    }
+}
+
+/// Cleans up the job that is not running on this node once finished, as its runner would do.
+async fn abort_not_running(job: &AdminJob) {
+    // The exported file may be created by the first step even if no checkpoint persisted.
+    if let Ok(JobParams::ExportFile { path, .. }) = serde_json::from_slice(&job.params) {
+        remove_exported_file(Path::new(&path)).await;
+    }
+}
+
+fn decode_checkpoint<T: Decode + Default>(checkpoint: &[u8]) -> Result<T> {
+    if checkpoint.is_empty() {
+        return Ok(T::default());
+    }
+    T::from_ssz_bytes(checkpoint).map_err(|e| anyhow!("invalid job checkpoint: {:?}", e))
+}
+
+fn job_info(job: &AdminJob) -> Result<JobInfo> {
+    let params: JobParams = serde_json::from_slice(&job.params)?;
+    let state = match job.state {
+        JOB_STATE_RUNNING => JobState::Running,
+        JOB_STATE_COMPLETED => JobState::Completed,
+        JOB_STATE_FAILED => JobState::Failed,
+        JOB_STATE_CANCELLED => JobState::Cancelled,
+        state => bail!("invalid job state {}", state),
+    };
+    let result = match &params {
+        JobParams::Scrub { .. } => {
+            let checkpoint: ScrubCheckpoint = decode_checkpoint(&job.checkpoint)?;
+            Some(JobResult::Scrub {
+                mismatches: checkpoint
+                    .mismatched_batches
+                    .into_iter()
+                    .map(FlowBatchMismatch::new)
+                    .collect(),
+            })
+        }
+        JobParams::ExportFile {
+            tx_seq_or_root,
+            path,
+        } if state == JobState::Completed => {
+            let checkpoint: ExportCheckpoint = decode_checkpoint(&job.checkpoint)?;
+            let tx_seq = match tx_seq_or_root {
+                TxSeqOrRoot::TxSeq(tx_seq) => *tx_seq,
+                TxSeqOrRoot::Root(_) => bail!("unresolved export params"),
+            };
+            Some(JobResult::ExportFile(ExportedFile {
+                tx_seq,
+                path: path.clone(),
+                size: job.progress_done,
+                sha256: hex(&checkpoint.sha256),
+            }))
+        }
+        JobParams::ExportFile { .. } => None,
+    };
+
+    Ok(JobInfo {
+        id: job.id,
+        params,
+        state,
+        progress_done: job.progress_done,
+        progress_total: job.progress_total,
+        error: job
+            .error
+            .as_ref()
+            .map(|e| String::from_utf8_lossy(e).into_owned()),
+        result,
+        created_at: job.created_at,
+        updated_at: job.updated_at,
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_info_result() {
+        let mut job = AdminJob {
+            id: 3,
+            state: JOB_STATE_RUNNING,
+            params: serde_json::to_vec(&JobParams::Scrub {
+                start_chunk: 0,
+                end_chunk: Some(4 * PORA_CHUNK_SIZE as u64),
+            })
+            .unwrap(),
+            checkpoint: vec![],
+            progress_done: 0,
+            progress_total: 4 * PORA_CHUNK_SIZE as u64,
+            error: None,
+            created_at: 1,
+            updated_at: 1,
+        };
+
+        // scrub not started yet
+        let info = job_info(&job).unwrap();
+        assert_eq!(info.state, JobState::Running);
+        assert!(
+            matches!(info.result, Some(JobResult::Scrub { mismatches }) if mismatches.is_empty())
+        );
+
+        // partial result of the running scrub
+        job.checkpoint = ScrubCheckpoint {
+            next_chunk: 2 * PORA_CHUNK_SIZE as u64,
+            mismatched_batches: vec![1],
+        }
+        .as_ssz_bytes();
+        let info = job_info(&job).unwrap();
+        match info.result {
+            Some(JobResult::Scrub { mismatches }) => {
+                assert_eq!(mismatches, vec![FlowBatchMismatch::new(1)])
+            }
+            _ => panic!("unexpected result {:?}", info.result),
+        }
+
+        // export without result until completed
+        job.params = serde_json::to_vec(&JobParams::ExportFile {
+            tx_seq_or_root: TxSeqOrRoot::TxSeq(5),
+            path: "/export/a.bin".into(),
+        })
+        .unwrap();
+        job.progress_done = 1000;
+        job.checkpoint = ExportCheckpoint {
+            written_chunks: 4,
+            sha256: vec![],
+        }
+        .as_ssz_bytes();
+        assert!(job_info(&job).unwrap().result.is_none());
+
+        job.state = JOB_STATE_COMPLETED;
+        job.checkpoint = ExportCheckpoint {
+            written_chunks: 4,
+            sha256: vec![0xab, 0x01],
+        }
+        .as_ssz_bytes();
+        match job_info(&job).unwrap().result {
+            Some(JobResult::ExportFile(exported)) => {
+                assert_eq!(exported.tx_seq, 5);
+                assert_eq!(exported.size, 1000);
+                assert_eq!(exported.sha256, "ab01");
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        job.state = 9;
+        assert!(job_info(&job).is_err());
+    }
+}
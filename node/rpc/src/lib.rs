@@ -4,10 +4,20 @@ extern crate tracing;
 extern crate miner as zgs_miner;
 
 mod admin;
+mod auth;
 mod config;
 mod error;
+mod gateway;
+mod jobs;
+mod metrics;
+mod metrics_exporter;
 mod miner;
+mod proof_verifier;
+mod replica_query;
+mod segment_cache;
+mod startup;
 pub mod types;
+mod upload_session;
 mod zgs;
 
 use crate::miner::RpcServer as MinerRpcServer;
@@ -15,23 +25,46 @@ use admin::RpcServer as AdminRpcServer;
 use chunk_pool::MemoryChunkPool;
 use file_location_cache::FileLocationCache;
 use futures::channel::mpsc::Sender;
+use futures::future::BoxFuture;
 use jsonrpsee::core::RpcResult;
-use jsonrpsee::http_server::{HttpServerBuilder, HttpServerHandle};
+use log_entry_sync::LogSyncMonitor;
 use network::{NetworkGlobals, NetworkMessage, NetworkSender};
+use router::KnownPeers;
 use std::error::Error;
+use std::future::Future;
 use std::sync::Arc;
 use storage_async::Store;
 use sync::{SyncRequest, SyncResponse, SyncSender};
+use task_executor::shutdown::ShutdownSignal;
 use task_executor::ShutdownReason;
 use tokio::sync::broadcast;
 use zgs::RpcServer as ZgsRpcServer;
 use zgs_miner::MinerMessage;
 
 pub use admin::RpcClient as ZgsAdminRpcClient;
+pub use config::AdminAuthConfig;
 pub use config::Config as RPCConfig;
+pub use gateway::BINARY_CONTENT_TYPE;
+pub use jobs::{JobConfig, JobManager};
+pub use metrics_exporter::run_metrics_exporter;
 pub use miner::RpcClient as ZgsMinerRpcClient;
+pub use proof_verifier::ProofVerifier;
+pub use replica_query::ReplicaQueryLimiter;
+pub use segment_cache::SegmentCache;
+pub use startup::StartupPhases;
+pub use upload_session::UploadSessions;
 pub use zgs::RpcClient as ZgsRPCClient;
 
+/// Reloads the dynamic parameters of the running node from config file.
+pub trait ConfigReloader: Send + Sync {
+    fn reload(&self) -> Result<types::ConfigReloadReport, String>;
+}
+
+/// Swaps the log filter of the running node.
+pub trait LogFilterSetter: Send + Sync {
+    fn set_log_filter(&self, filter: &str) -> Result<(), String>;
+}
+
 /// A wrapper around all the items required to spawn the HTTP server.
 ///
 /// The server will gracefully handle the case where any fields are `None`.
@@ -46,6 +79,24 @@ pub struct Context {
     pub log_store: Arc<Store>,
     pub shutdown_sender: Sender<ShutdownReason>,
     pub mine_service_sender: Option<broadcast::Sender<MinerMessage>>,
+    pub log_sync: Option<LogSyncMonitor>,
+    pub known_peers: Option<KnownPeers>,
+    pub config_reloader: Option<Arc<dyn ConfigReloader>>,
+    pub log_filter: Option<Arc<dyn LogFilterSetter>>,
+    /// Segments with proof served recently, bounded by `rpc.segment_cache_max_bytes`.
+    pub segment_cache: Arc<SegmentCache>,
+    /// Upload sessions of `zgs_beginUpload`, which expire by `rpc.upload_session_ttl_secs`.
+    pub upload_sessions: Arc<UploadSessions>,
+    /// Verifies the proofs of uploaded segments off the RPC handlers.
+    pub proof_verifier: Arc<ProofVerifier>,
+    /// Limits the network queries of `zgs_checkFileReplicas` by `rpc.max_replica_queries`.
+    pub replica_query_limiter: Arc<ReplicaQueryLimiter>,
+    /// Timing breakdown of the node startup, served by `admin_getStatus`.
+    pub startup_phases: Arc<StartupPhases>,
+    /// Admin jobs started by `admin_startJob`, which are resumed after restart.
+    pub jobs: Arc<JobManager>,
+    /// Whether the node is read-only, which rejects requests to write into the store.
+    pub read_only: bool,
 }
 
 impl Context {
@@ -55,21 +106,37 @@ impl Context {
             .map_err(|e| error::internal_error(format!("Failed to send network message: {:?}", e)))
     }
 
+    /// Rejects the request to write into the store, e.g. file sync, if the node is read-only.
+    pub fn check_writable(&self) -> RpcResult<()> {
+        if self.read_only {
+            Err(error::node_read_only())
+        } else {
+            Ok(())
+        }
+    }
+
     pub async fn request_sync(&self, request: SyncRequest) -> RpcResult<SyncResponse> {
         self.sync_send
             .request(request)
             .await
-            .map_err(|e| error::internal_error(format!("Failed to send sync request: {:?}", e)))
+            .map_err(|e| error::sync_error(format!("Failed to send sync request: {:?}", e)))
     }
 }
 
-pub async fn run_server(
+/// Starts the RPC servers, which stop accepting new requests once `shutdown` requested. Returns
+/// the public server and the admin server if served on a dedicated port.
+pub fn run_server(
     ctx: Context,
-) -> Result<(HttpServerHandle, Option<HttpServerHandle>), Box<dyn Error>> {
+    shutdown: ShutdownSignal,
+) -> Result<(BoxFuture<'static, ()>, Option<BoxFuture<'static, ()>>), Box<dyn Error>> {
     let handles = if ctx.config.listen_address.port() != ctx.config.listen_address_admin.port() {
-        run_server_public_private(ctx).await?
+        run_server_public_private(ctx, shutdown)?
     } else {
-        (run_server_all(ctx).await?, None)
+        if ctx.config.admin_auth.is_some() {
+            return Err("rpc.admin_auth requires a dedicated listen_address_admin port".into());
+        }
+
+        (run_server_all(ctx, shutdown)?, None)
     };
 
     info!("Server started");
@@ -77,35 +144,57 @@ pub async fn run_server(
     Ok(handles)
 }
 
-fn server_builder(ctx: Context) -> HttpServerBuilder {
-    HttpServerBuilder::default().max_request_body_size(ctx.config.max_request_body_size)
+fn shutdown_requested(mut shutdown: ShutdownSignal) -> impl Future<Output = ()> + Send + 'static {
+    async move { shutdown.requested().await }
 }
 
 /// Run a single RPC server for all namespace RPCs.
-async fn run_server_all(ctx: Context) -> Result<HttpServerHandle, Box<dyn Error>> {
+///
+/// The admin RPCs are served only if listening on a loopback address, since they could not be
+/// authenticated on the public port.
+fn run_server_all(
+    ctx: Context,
+    shutdown: ShutdownSignal,
+) -> Result<BoxFuture<'static, ()>, Box<dyn Error>> {
     // public rpc
     let mut zgs = (zgs::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
 
-    // admin rpc
-    let admin = (admin::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
-    zgs.merge(admin)?;
+    if ctx.config.listen_address.ip().is_loopback() {
+        // admin rpc
+        let admin = (admin::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
+        zgs.merge(admin)?;
 
-    // mine rpc if configured
-    if ctx.mine_service_sender.is_some() {
-        let mine = (miner::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
-        zgs.merge(mine)?;
+        // mine rpc if configured
+        if ctx.mine_service_sender.is_some() {
+            let mine = (miner::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
+            zgs.merge(mine)?;
+        }
+    } else {
+        warn!(
+            address = %ctx.config.listen_address,
+            "Admin RPC is disabled, configure a dedicated listen_address_admin to serve it"
+        );
     }
 
-    Ok(server_builder(ctx.clone())
-        .build(ctx.config.listen_address)
-        .await?
-        .start(zgs)?)
+    gateway::run_gateway(
+        "Public",
+        ctx.config.listen_address,
+        zgs.into(),
+        Some(Arc::new(zgs::RpcServerImpl { ctx: ctx.clone() })),
+        None,
+        ctx.config.gateway_limits(),
+        shutdown_requested(shutdown),
+    )
 }
 
 /// Run 2 RPC servers (public & private) for different namespace RPCs.
-async fn run_server_public_private(
+///
+/// The private RPCs are authenticated before dispatching if admin authentication is configured,
+/// or accepted from loopback addresses only otherwise.
+fn run_server_public_private(
     ctx: Context,
-) -> Result<(HttpServerHandle, Option<HttpServerHandle>), Box<dyn Error>> {
+    shutdown: ShutdownSignal,
+) -> Result<(BoxFuture<'static, ()>, Option<BoxFuture<'static, ()>>), Box<dyn Error>> {
     // public rpc
     let zgs = (zgs::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
 
@@ -118,15 +207,37 @@ async fn run_server_public_private(
         admin.merge(mine)?;
     }
 
-    let handle_public = server_builder(ctx.clone())
-        .build(ctx.config.listen_address)
-        .await?
-        .start(zgs)?;
-
-    let handle_private = server_builder(ctx.clone())
-        .build(ctx.config.listen_address_admin)
-        .await?
-        .start(admin)?;
+    let handle_public = gateway::run_gateway(
+        "Public",
+        ctx.config.listen_address,
+        zgs.into(),
+        Some(Arc::new(zgs::RpcServerImpl { ctx: ctx.clone() })),
+        None,
+        ctx.config.gateway_limits(),
+        shutdown_requested(shutdown.clone()),
+    )?;
+
+    let auth = match &ctx.config.admin_auth {
+        Some(auth_config) => auth::AdminAuth::new(auth_config)?,
+        None => {
+            if !ctx.config.listen_address_admin.ip().is_loopback() {
+                warn!(
+                    address = %ctx.config.listen_address_admin,
+                    "Admin RPC only accepts loopback requests without [rpc.admin_auth]"
+                );
+            }
+            auth::AdminAuth::localhost_only()
+        }
+    };
+    let handle_private = gateway::run_gateway(
+        "Admin",
+        ctx.config.listen_address_admin,
+        admin.into(),
+        None,
+        Some(auth),
+        ctx.config.gateway_limits(),
+        shutdown_requested(shutdown),
+    )?;
 
     Ok((handle_public, Some(handle_private)))
 }
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use metrics::{Counter, CounterUsize, Gauge, GaugeUsize, Histogram, Sample};
+
+/// Metrics of the segment cache, which are registered along with the cache.
+pub struct SegmentCacheMetrics {
+    pub hit: Arc<dyn Counter<usize>>,
+    pub miss: Arc<dyn Counter<usize>>,
+    /// Bytes of the segments cached.
+    pub size: Arc<dyn Gauge<usize>>,
+}
+
+impl SegmentCacheMetrics {
+    pub fn register() -> Self {
+        Self {
+            hit: CounterUsize::register("rpc_segment_cache_hit"),
+            miss: CounterUsize::register("rpc_segment_cache_miss"),
+            size: GaugeUsize::register("rpc_segment_cache_size"),
+        }
+    }
+}
+
+/// Metrics of the proof verifier, which are registered along with the verifier.
+pub struct ProofVerifierMetrics {
+    /// Number of segment proofs queued or being verified.
+    pub queue: Arc<dyn Gauge<usize>>,
+    /// Number of segment uploads rejected since the verification queue is full.
+    pub rejected: Arc<dyn Counter<usize>>,
+    /// Time to verify a segment proof, including the time queued.
+    pub latency: Arc<dyn Histogram>,
+}
+
+impl ProofVerifierMetrics {
+    pub fn register() -> Self {
+        Self {
+            queue: GaugeUsize::register("rpc_proof_verify_queue"),
+            rejected: CounterUsize::register("rpc_proof_verify_rejected"),
+            latency: Sample::ExpDecay(0.015).register("rpc_proof_verify_latency", 1024),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! HTTP endpoint that exports all metrics in the Prometheus text format.
+
+use futures::future::{self, BoxFuture};
+use futures::FutureExt;
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use lighthouse_metrics::{Encoder, TextEncoder};
+use metrics::{DEFAULT_GROUPING_REGISTRY, DEFAULT_REGISTRY};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use storage::log_store::ColumnStats;
+use storage_async::Store;
+
+/// Interval to refresh the db column stats in background.
+const DB_STATS_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Prefix of all metrics that are not registered in the prometheus registry.
+const METRIC_PREFIX: &str = "zgs_";
+
+/// Runs the metrics exporter on `listen_address`, and serves `GET /metrics`.
+///
+/// Metrics are read from the registries only, and the db stats are refreshed periodically in
+/// background, so that a scrape never waits for the store.
+pub fn run_metrics_exporter(
+    listen_address: SocketAddr,
+    log_store: Arc<Store>,
+) -> Result<BoxFuture<'static, ()>, Box<dyn std::error::Error>> {
+    let db_stats = Arc::new(RwLock::new(Vec::new()));
+
+    let stats = db_stats.clone();
+    let make_service = make_service_fn(move |_| {
+        let stats = stats.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle_request(request, stats.clone())
+            }))
+        }
+    });
+
+    let server = hyper::Server::try_bind(&listen_address)?.serve(make_service);
+    info!(%listen_address, "Metrics exporter started");
+
+    let server = async move {
+        if let Err(e) = server.await {
+            error!("Metrics exporter terminated: {:?}", e);
+        }
+    };
+
+    Ok(future::join(server, refresh_db_stats(log_store, db_stats))
+        .map(|_| ())
+        .boxed())
+}
+
+async fn refresh_db_stats(log_store: Arc<Store>, db_stats: Arc<RwLock<Vec<ColumnStats>>>) {
+    let mut interval = tokio::time::interval(DB_STATS_REFRESH_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match log_store.get_db_column_stats().await {
+            Ok(stats) => *db_stats.write().expect("lock poisoned") = stats,
+            Err(e) => warn!("Failed to get db column stats: {:?}", e),
+        }
+    }
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    db_stats: Arc<RwLock<Vec<ColumnStats>>>,
+) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .expect("valid response"));
+    }
+
+    let db_stats = db_stats.read().expect("lock poisoned").clone();
+    let text = render(&db_stats);
+
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, TextEncoder::new().format_type())
+        .body(Body::from(text))
+        .expect("valid response"))
+}
+
+/// Renders all metrics in the Prometheus text format.
+pub fn render(db_stats: &[ColumnStats]) -> String {
+    let mut out = String::new();
+
+    for (name, metric) in DEFAULT_REGISTRY.read().get_all() {
+        write_metric(
+            &mut out,
+            name,
+            metric.get_type(),
+            &metric.get_value().to_string(),
+        );
+    }
+
+    for (group_name, metrics) in DEFAULT_GROUPING_REGISTRY.read().get_all() {
+        for (metric_name, metric) in metrics.iter() {
+            write_metric(
+                &mut out,
+                &format!("{}.{}", group_name, metric_name),
+                metric.get_type(),
+                &metric.get_value().to_string(),
+            );
+        }
+    }
+
+    if !db_stats.is_empty() {
+        let name = format!("{}db_column_keys", METRIC_PREFIX);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for stats in db_stats {
+            let _ = writeln!(
+                out,
+                "{}{{db=\"{}\",column=\"{}\"}} {}",
+                name, stats.db, stats.column, stats.num_keys
+            );
+        }
+    }
+
+    // Metrics of network and miner are registered in the prometheus registry.
+    let mut buffer = Vec::new();
+    match TextEncoder::new().encode(&lighthouse_metrics::gather(), &mut buffer) {
+        Ok(()) => out.push_str(&String::from_utf8_lossy(&buffer)),
+        Err(e) => warn!("Failed to encode prometheus metrics: {:?}", e),
+    }
+
+    out
+}
+
+/// Writes a metric of the conflux registry. A metric with a single value is exported as is,
+/// and a metric with multiple fields (e.g. meter or timer) is exported as one gauge per field.
+fn write_metric(out: &mut String, name: &str, metric_type: &str, value: &str) {
+    let name = normalize_name(name);
+
+    if let Ok(value) = value.trim().parse::<f64>() {
+        let (name, metric_type) = match metric_type.to_lowercase().as_str() {
+            "counter" => (format!("{}_total", name), "counter"),
+            "gauge" => (name, "gauge"),
+            _ => (name, "untyped"),
+        };
+        let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+        let _ = writeln!(out, "{} {}", name, value);
+        return;
+    }
+
+    for (field, value) in parse_fields(value) {
+        let name = format!("{}_{}", name, sanitize(&field));
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+}
+
+/// Parses the numeric fields of a composite metric value, e.g. `{count: 3, m1: 0.5}`.
+fn parse_fields(value: &str) -> Vec<(String, f64)> {
+    value
+        .trim()
+        .trim_start_matches('{')
+        .trim_end_matches('}')
+        .split(',')
+        .filter_map(|item| {
+            let (key, value) = item.split_once(|c: char| c == ':' || c == '=')?;
+            let key = key.trim().trim_matches('"');
+            let value = value.trim().trim_matches('"').parse::<f64>().ok()?;
+            (!key.is_empty()).then(|| (key.to_string(), value))
+        })
+        .collect()
+}
+
+/// Normalizes the metric name to `[a-z0-9_]` with the `zgs_` prefix.
+fn normalize_name(name: &str) -> String {
+    let name = sanitize(name);
+    if name.starts_with(METRIC_PREFIX) {
+        name
+    } else {
+        format!("{}{}", METRIC_PREFIX, name)
+    }
+}
+
+/// Lowercases `name` and replaces every run of invalid chars with a single `_`.
+fn sanitize(name: &str) -> String {
+    let mut sanitized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            sanitized.push(c.to_ascii_lowercase());
+        } else if !sanitized.is_empty() && !sanitized.ends_with('_') {
+            sanitized.push('_');
+        }
+    }
+
+    sanitized.trim_end_matches('_').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(
+            normalize_name("log_store_tx_store_put_tx"),
+            "zgs_log_store_tx_store_put_tx"
+        );
+        assert_eq!(
+            normalize_name("router_libp2p_handle_peer_connected.outgoing"),
+            "zgs_router_libp2p_handle_peer_connected_outgoing"
+        );
+        assert_eq!(normalize_name("Sync.Peers-Count"), "zgs_sync_peers_count");
+    }
+
+    #[test]
+    fn test_write_metric() {
+        let mut out = String::new();
+        write_metric(&mut out, "sync_peers", "Gauge", "5");
+        write_metric(&mut out, "chunks", "Counter", "12");
+        write_metric(
+            &mut out,
+            "put_tx",
+            "Timer",
+            "{count: 3, mean: 1.5, unknown: x}",
+        );
+        assert_eq!(
+            out,
+            "# TYPE zgs_sync_peers gauge\nzgs_sync_peers 5\n\
+             # TYPE zgs_chunks_total counter\nzgs_chunks_total 12\n\
+             # TYPE zgs_put_tx_count gauge\nzgs_put_tx_count 3\n\
+             # TYPE zgs_put_tx_mean gauge\nzgs_put_tx_mean 1.5\n"
+        );
+    }
+
+    #[test]
+    fn test_render_db_stats() {
+        let text = render(&[ColumnStats {
+            db: "flow",
+            column: "tx",
+            num_keys: 10,
+        }]);
+        assert!(text.contains("# TYPE zgs_db_column_keys gauge\n"));
+        assert!(text.contains("zgs_db_column_keys{db=\"flow\",column=\"tx\"} 10\n"));
+    }
+}
@@ -0,0 +1,182 @@
+//! Worker pool to verify the merkle proofs of uploaded segments, so that RPC handlers are not
+//! blocked by hashing, and concurrent uploads are verified in parallel across cores.
+
+use crate::error;
+use crate::metrics::ProofVerifierMetrics;
+use crate::types::SegmentWithProof;
+use jsonrpsee::core::RpcResult;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+
+/// Verifies segment proofs on at most `num_workers` blocking threads. Verifications beyond the
+/// workers are queued, and rejected with a retryable error once the queue is full, so that a
+/// burst of uploads could not pile up in memory.
+pub struct ProofVerifier {
+    workers: Arc<Semaphore>,
+    /// Maximum number of verifications queued or running.
+    max_queued: usize,
+    queued: AtomicUsize,
+    metrics: ProofVerifierMetrics,
+}
+
+/// Slot of the verification queue, which is released once dropped.
+struct QueueSlot<'a>(&'a ProofVerifier);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        let queued = self.0.queued.fetch_sub(1, Ordering::SeqCst) - 1;
+        self.0.metrics.queue.update(queued);
+    }
+}
+
+impl ProofVerifier {
+    /// Creates a verifier of `num_workers` threads, or the number of cores if 0.
+    pub fn new(num_workers: usize, max_queued: usize) -> Self {
+        let num_workers = match num_workers {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+
+        Self {
+            workers: Arc::new(Semaphore::new(num_workers)),
+            max_queued,
+            queued: AtomicUsize::new(0),
+            metrics: ProofVerifierMetrics::register(),
+        }
+    }
+
+    /// Verifies the segment on a worker, and returns it back if valid.
+    ///
+    /// Errors: `106` invalid segment, `107` invalid proof, `121` queue full (retryable).
+    pub async fn verify(
+        &self,
+        segment: SegmentWithProof,
+        chunks_per_segment: usize,
+    ) -> RpcResult<SegmentWithProof> {
+        let _slot = self.enqueue()?;
+        let started_at = Instant::now();
+        let permit = self
+            .workers
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore never closed");
+
+        // logs of the verification are correlated with the RPC call
+        let span = tracing::Span::current();
+        let verified = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let _entered = span.enter();
+            let result = segment.validate(chunks_per_segment);
+            (segment, result)
+        })
+        .await;
+        self.metrics.latency.update_since(started_at);
+
+        match verified {
+            Ok((segment, result)) => result.map(|_| segment),
+            Err(e) => Err(error::internal_error(format!(
+                "Proof verification aborted: {:?}",
+                e
+            ))),
+        }
+    }
+
+    fn enqueue(&self) -> RpcResult<QueueSlot<'_>> {
+        let queued = self
+            .queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < self.max_queued).then_some(n + 1)
+            })
+            .map_err(|_| {
+                self.metrics.rejected.inc(1);
+                error::proof_verifier_busy(self.max_queued)
+            })?;
+        self.metrics.queue.update(queued + 1);
+        Ok(QueueSlot(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{error_code, RpcErrorCode};
+    use shared_types::CHUNK_SIZE;
+
+    const CHUNKS_PER_SEGMENT: usize = 1024;
+
+    fn new_segment(seed: u8) -> SegmentWithProof {
+        let data = vec![seed; CHUNKS_PER_SEGMENT * CHUNK_SIZE];
+        let (root, _) =
+            SegmentWithProof::small_file_root(data.clone(), CHUNKS_PER_SEGMENT).unwrap();
+        SegmentWithProof::from_small_file(data, root, CHUNKS_PER_SEGMENT).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let verifier = ProofVerifier::new(2, 16);
+        let segment = new_segment(1);
+        let verified = verifier
+            .verify(segment.clone(), CHUNKS_PER_SEGMENT)
+            .await
+            .unwrap();
+        assert_eq!(verified.root, segment.root);
+
+        let mut invalid = segment;
+        invalid.data[0] ^= 1;
+        let err = verifier
+            .verify(invalid, CHUNKS_PER_SEGMENT)
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(&err), RpcErrorCode::InvalidProof.code());
+        assert_eq!(verifier.queued.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_reject_if_queue_full() {
+        let verifier = ProofVerifier::new(1, 2);
+        let segment = new_segment(1);
+        let (first, second, third) = tokio::join!(
+            verifier.verify(segment.clone(), CHUNKS_PER_SEGMENT),
+            verifier.verify(segment.clone(), CHUNKS_PER_SEGMENT),
+            verifier.verify(segment.clone(), CHUNKS_PER_SEGMENT),
+        );
+        assert!(first.is_ok() && second.is_ok());
+        assert_eq!(
+            error_code(&third.unwrap_err()),
+            RpcErrorCode::ProofVerifierBusy.code()
+        );
+
+        // accepted once the queue drained
+        assert!(verifier.verify(segment, CHUNKS_PER_SEGMENT).await.is_ok());
+    }
+
+    /// Handlers on the runtime are still served while hundreds of segments are verified.
+    #[tokio::test]
+    async fn test_concurrent_verifications() {
+        let verifier = Arc::new(ProofVerifier::new(0, 1024));
+        let segments: Vec<_> = (0..8).map(new_segment).collect();
+
+        let uploads: Vec<_> = (0..400)
+            .map(|i| {
+                let verifier = verifier.clone();
+                let segment = segments[i % segments.len()].clone();
+                tokio::spawn(async move { verifier.verify(segment, CHUNKS_PER_SEGMENT).await })
+            })
+            .collect();
+
+        // The runtime has a single thread, which runs the tasks in order. A task spawned after
+        // the uploads would only run once all proofs verified if verified on the runtime.
+        tokio::spawn(async {}).await.unwrap();
+        assert!(verifier.queued.load(Ordering::SeqCst) > 0);
+        assert!(!uploads.iter().all(|upload| upload.is_finished()));
+
+        for upload in uploads {
+            assert!(upload.await.unwrap().is_ok());
+        }
+        assert_eq!(verifier.queued.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,66 @@
+//! Limit of the network queries of `zgs_checkFileReplicas`, so that RPC clients could not
+//! flood the network with `FindFile` gossip.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Allows a limited number of network queries in every period of `rpc.replica_query_wait_ms`,
+/// for any files, and the queries beyond are answered by the cached announcements only.
+pub struct ReplicaQueryLimiter {
+    permits: Arc<Semaphore>,
+    period: Duration,
+}
+
+impl ReplicaQueryLimiter {
+    pub fn new(max_queries: usize, period: Duration) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_queries)),
+            period,
+        }
+    }
+
+    /// Returns whether the network could be queried now, in which case the query is accounted
+    /// until the period elapsed, even if the caller returns early.
+    pub fn try_acquire(&self) -> bool {
+        match self.permits.clone().try_acquire_owned() {
+            Ok(permit) => {
+                let period = self.period;
+                tokio::spawn(release_after(permit, period));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+async fn release_after(permit: OwnedSemaphorePermit, period: Duration) {
+    tokio::time::sleep(period).await;
+    drop(permit);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_try_acquire() {
+        let limiter = ReplicaQueryLimiter::new(2, Duration::from_secs(10));
+        assert!(limiter.try_acquire());
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+
+        // released one by one once the period elapsed
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+
+        // disabled
+        let limiter = ReplicaQueryLimiter::new(0, Duration::from_secs(10));
+        assert!(!limiter.try_acquire());
+    }
+}
@@ -0,0 +1,316 @@
+//! Cache of the segments with proof served by RPC, so that the same segment of a popular file is
+//! not read and proved again for every download request.
+
+use crate::metrics::SegmentCacheMetrics;
+use crate::types::SegmentWithProof;
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+use std::sync::Mutex;
+
+/// Segment of `(tx_seq, segment_index)`.
+type SegmentKey = (u64, usize);
+
+struct CachedSegment {
+    segment: SegmentWithProof,
+    size: usize,
+    /// Tick of the latest access.
+    accessed_at: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Flow version that all cached segments are read at.
+    version: u64,
+    segments: HashMap<SegmentKey, CachedSegment>,
+    /// Keys of the cached segments by the tick of the latest access, from the least recent.
+    accesses: BTreeMap<u64, SegmentKey>,
+    next_tick: u64,
+    total_size: usize,
+}
+
+impl Inner {
+    /// Drops all segments once the flow version changes, since they may be stale. Returns
+    /// whether dropped.
+    fn advance(&mut self, version: u64) -> bool {
+        if version <= self.version {
+            return false;
+        }
+
+        self.segments.clear();
+        self.accesses.clear();
+        self.total_size = 0;
+        self.version = version;
+        true
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+
+    /// Returns the cached segment, which becomes the most recently used.
+    fn access(&mut self, key: SegmentKey) -> Option<SegmentWithProof> {
+        let tick = self.tick();
+        let cached = self.segments.get_mut(&key)?;
+        self.accesses.remove(&cached.accessed_at);
+        self.accesses.insert(tick, key);
+        cached.accessed_at = tick;
+        Some(cached.segment.clone())
+    }
+
+    fn insert(&mut self, key: SegmentKey, segment: SegmentWithProof, size: usize) {
+        let tick = self.tick();
+        let cached = CachedSegment {
+            segment,
+            size,
+            accessed_at: tick,
+        };
+        if let Some(old) = self.segments.insert(key, cached) {
+            self.accesses.remove(&old.accessed_at);
+            self.total_size -= old.size;
+        }
+        self.accesses.insert(tick, key);
+        self.total_size += size;
+    }
+
+    /// Evicts the least recently used segment, and returns `false` if empty.
+    fn evict(&mut self) -> bool {
+        match self.accesses.pop_first() {
+            Some((_, key)) => {
+                if let Some(evicted) = self.segments.remove(&key) {
+                    self.total_size -= evicted.size;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Bounded LRU cache of segments with proof, which are keyed by the flow version of the store
+/// (see `LogStoreRead::get_flow_version`), so that a segment read before a revert or prune is
+/// never served afterwards.
+///
+/// The flow version is supposed to be read before anything of the segment, including its tx,
+/// is read from store.
+pub struct SegmentCache {
+    max_bytes: usize,
+    inner: Mutex<Inner>,
+    metrics: SegmentCacheMetrics,
+}
+
+impl SegmentCache {
+    /// Creates a cache that holds segments of at most `max_bytes`, which is disabled if 0.
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            inner: Default::default(),
+            metrics: SegmentCacheMetrics::register(),
+        }
+    }
+
+    /// Advances to the flow `version`, and returns whether the segments are cached at it.
+    fn advance(&self, inner: &mut Inner, version: u64) -> bool {
+        if inner.advance(version) {
+            self.metrics.size.update(0);
+        }
+        version == inner.version
+    }
+
+    /// Returns the segment cached at the flow `version`.
+    pub fn get(&self, tx_seq: u64, index: usize, version: u64) -> Option<SegmentWithProof> {
+        if self.max_bytes == 0 {
+            return None;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let cached = if self.advance(&mut inner, version) {
+            inner.access((tx_seq, index))
+        } else {
+            None
+        };
+        match cached {
+            Some(segment) => {
+                self.metrics.hit.inc(1);
+                Some(segment)
+            }
+            None => {
+                self.metrics.miss.inc(1);
+                None
+            }
+        }
+    }
+
+    /// Caches the segment read at the flow `version`, which is ignored if the version is
+    /// outdated already. The least recently used segments are evicted beyond the capacity.
+    pub fn insert(&self, tx_seq: u64, version: u64, segment: SegmentWithProof) {
+        let size = segment_size(&segment);
+        if size > self.max_bytes {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        if !self.advance(&mut inner, version) {
+            return;
+        }
+
+        inner.insert((tx_seq, segment.index), segment, size);
+        while inner.total_size > self.max_bytes && inner.evict() {}
+        self.metrics.size.update(inner.total_size);
+    }
+}
+
+fn segment_size(segment: &SegmentWithProof) -> usize {
+    mem::size_of::<SegmentWithProof>()
+        + segment.data.len()
+        + segment.proof.lemma.len() * 32
+        + segment.proof.path.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_types::{DataRoot, FileProof};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Barrier, RwLock};
+    use std::thread;
+
+    fn segment(root: DataRoot, index: usize, data_size: usize) -> SegmentWithProof {
+        SegmentWithProof {
+            root,
+            data: vec![0; data_size],
+            index,
+            proof: FileProof {
+                lemma: vec![root],
+                path: vec![],
+            },
+            file_size: data_size,
+        }
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let root = DataRoot::from_low_u64_be(1);
+        let size = segment_size(&segment(root, 0, 1024));
+        let cache = SegmentCache::new(2 * size);
+
+        cache.insert(0, 0, segment(root, 0, 1024));
+        cache.insert(0, 0, segment(root, 1, 1024));
+        assert!(cache.get(0, 0, 0).is_some());
+        // Segment 1 is the least recently used.
+        cache.insert(0, 0, segment(root, 2, 1024));
+        assert!(cache.get(0, 1, 0).is_none());
+        assert!(cache.get(0, 0, 0).is_some());
+        assert!(cache.get(0, 2, 0).is_some());
+        assert_eq!(cache.inner.lock().unwrap().total_size, 2 * size);
+
+        // Larger than the capacity.
+        cache.insert(1, 0, segment(root, 0, 3 * size));
+        assert!(cache.get(1, 0, 0).is_none());
+
+        let disabled = SegmentCache::new(0);
+        disabled.insert(0, 0, segment(root, 0, 1024));
+        assert!(disabled.get(0, 0, 0).is_none());
+    }
+
+    #[test]
+    fn test_invalidate_on_new_version() {
+        let cache = SegmentCache::new(1024 * 1024);
+        cache.insert(0, 2, segment(DataRoot::from_low_u64_be(1), 0, 1024));
+        assert!(cache.get(0, 0, 2).is_some());
+
+        // Read before the version changed.
+        assert!(cache.get(0, 0, 1).is_none());
+        cache.insert(0, 1, segment(DataRoot::from_low_u64_be(2), 0, 1024));
+        assert_eq!(
+            cache.get(0, 0, 2).unwrap().root,
+            DataRoot::from_low_u64_be(1)
+        );
+
+        // Never served once reverted.
+        assert!(cache.get(0, 0, 4).is_none());
+        cache.insert(0, 2, segment(DataRoot::from_low_u64_be(1), 0, 1024));
+        assert!(cache.get(0, 0, 4).is_none());
+        assert_eq!(cache.inner.lock().unwrap().total_size, 0);
+    }
+
+    /// Flow of a single tx whose data root changes on every revert, with the version bumped
+    /// before and after the revert as the store does.
+    #[derive(Default)]
+    struct RevertingFlow {
+        version: AtomicU64,
+        root: RwLock<u64>,
+    }
+
+    impl RevertingFlow {
+        fn revert(&self) {
+            self.version.fetch_add(1, Ordering::SeqCst);
+            *self.root.write().unwrap() += 1;
+            self.version.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn read_segment(&self, cache: &SegmentCache, index: usize) -> (u64, SegmentWithProof) {
+            let version = self.version.load(Ordering::SeqCst);
+            if let Some(segment) = cache.get(0, index, version) {
+                return (version, segment);
+            }
+            let root = DataRoot::from_low_u64_be(*self.root.read().unwrap());
+            let segment = segment(root, index, 256);
+            cache.insert(0, version, segment.clone());
+            (version, segment)
+        }
+    }
+
+    #[test]
+    fn test_concurrent_revert() {
+        let flow = Arc::new(RevertingFlow::default());
+        let cache = Arc::new(SegmentCache::new(1024 * 1024));
+        let num_reverts = 1000;
+        let num_readers = 4;
+        let started = Arc::new(Barrier::new(num_readers + 1));
+
+        let readers: Vec<_> = (0..num_readers)
+            .map(|_| {
+                let (flow, cache, started) = (flow.clone(), cache.clone(), started.clone());
+                thread::spawn(move || {
+                    let mut num_checked = 0;
+                    let mut check_segments = || {
+                        for index in 0..4 {
+                            let (version, segment) = flow.read_segment(&cache, index);
+                            // Unless reverted meanwhile, the segment is of the latest root.
+                            let root = DataRoot::from_low_u64_be(*flow.root.read().unwrap());
+                            if flow.version.load(Ordering::SeqCst) == version {
+                                assert_eq!(segment.root, root);
+                                num_checked += 1;
+                            }
+                        }
+                    };
+
+                    // Read once before the reverts start, so that every reader overlaps them.
+                    check_segments();
+                    started.wait();
+                    while flow.version.load(Ordering::SeqCst) < 2 * num_reverts {
+                        check_segments();
+                    }
+                    num_checked
+                })
+            })
+            .collect();
+
+        started.wait();
+        for _ in 0..num_reverts {
+            flow.revert();
+            thread::yield_now();
+        }
+        let num_checked: usize = readers.into_iter().map(|r| r.join().unwrap()).sum();
+        assert!(num_checked > 0);
+
+        // No stale segment once the reverts completed.
+        let version = flow.version.load(Ordering::SeqCst);
+        let latest = DataRoot::from_low_u64_be(num_reverts);
+        for index in 0..4 {
+            assert_eq!(flow.read_segment(&cache, index).1.root, latest);
+            assert_eq!(cache.get(0, index, version).unwrap().root, latest);
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Timing breakdown of the node startup by named phases, e.g. db open and flow tree rebuild,
+//! so that operators could tell which phase a slow startup on a big db is waiting for.
+
+use metrics::{Gauge, GaugeUsize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Phases of the last startup in the order completed, which are logged once recorded and served
+/// by `admin_getStatus`. Phases that complete in background, e.g. the initial peer discovery,
+/// are appended after the node started.
+pub struct StartupPhases {
+    started_at: Instant,
+    phases: Mutex<Vec<(&'static str, Duration)>>,
+}
+
+impl Default for StartupPhases {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            phases: Default::default(),
+        }
+    }
+}
+
+impl StartupPhases {
+    /// Records a completed phase, which is also reported as the metric
+    /// `startup_phase_duration_ms.<name>` in milliseconds.
+    pub fn record(&self, name: &'static str, duration: Duration) {
+        info!(phase = %name, elapsed = ?duration, "Startup phase completed");
+        GaugeUsize::register_with_group("startup_phase_duration_ms", name)
+            .update(duration.as_millis() as usize);
+        self.phases.lock().unwrap().push((name, duration));
+    }
+
+    /// Records a phase that started at `started_at` and completes now.
+    pub fn record_since(&self, name: &'static str, started_at: Instant) {
+        self.record(name, started_at.elapsed());
+    }
+
+    /// Returns the recorded phases in the order completed.
+    pub fn phases(&self) -> Vec<(&'static str, Duration)> {
+        self.phases.lock().unwrap().clone()
+    }
+
+    /// Returns the time elapsed since the startup began.
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+}
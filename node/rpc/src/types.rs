@@ -1,22 +1,33 @@
-use crate::error;
+use crate::error::{self, RpcErrorCode};
 use append_merkle::ZERO_HASHES;
+use chunk_pool::{ChunkPoolStatus, PoolFileStatus};
+use ethers::types::{Address, U256};
 use jsonrpsee::core::RpcResult;
 use merkle_light::hash::Algorithm;
 use merkle_light::merkle::{log2_pow2, next_pow2, MerkleTree};
 use merkle_tree::RawLeafSha3Algorithm;
-use network::Multiaddr;
+use network::{nat::NatStatus, ConnectionStats, Multiaddr, PeerId, TrafficStats};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use shared_types::json::{FlowProofJson, FlowRangeProofJson, TransactionJson};
 use shared_types::{
-    compute_padded_chunk_size, compute_segment_size, DataRoot, FileProof, NetworkIdentity,
-    Transaction, CHUNK_SIZE,
+    compute_padded_chunk_size, compute_segment_size, validate_flow_entries, DataRoot, FileProof,
+    FlowRangeProof, NetworkIdentity, ProtocolVersion, Transaction, TxSeqOrRoot, CHUNK_SIZE,
 };
-use std::collections::HashSet;
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
+use std::collections::{BTreeMap, HashSet};
 use std::hash::Hasher;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::time::Instant;
-use storage::config::ShardConfig;
+use storage::config::{all_shards_available, ShardConfig};
 use storage::log_store::log_manager::bytes_to_entries;
+use storage::log_store::revert_history::RevertEvent;
+use storage::log_store::reward_store::{MinerReward, MinerRewardSummary};
+use storage::log_store::tx_store::{BlockHashAndSubmissionIndex, TxStatus};
+use storage::log_store::{MineLoadChunk, SealedChunkWithProof};
 use storage::H256;
+use sync::{PeerContribution, SelfAuditStatus};
+use zgs_miner::{AcceptedAnswer, ExternalAnswer, MineContextStatus, MinePuzzle};
 
 const ZERO_HASH: [u8; 32] = [
     0xd3, 0x97, 0xb3, 0xb0, 0x43, 0xd8, 0x7f, 0xcd, 0x6f, 0xad, 0x12, 0x91, 0xff, 0xb, 0xfd, 0x16,
@@ -46,22 +57,561 @@ pub struct NetworkInfo {
     pub connected_incoming_peers: usize,
 }
 
+/// Connection counts against the connection budget, where the peers in the reserved slots are
+/// not counted in either direction.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkStats {
+    pub inbound_peers: usize,
+    pub outbound_peers: usize,
+    pub reserved_sync_peers: usize,
+    pub reserved_mining_peers: usize,
+    pub max_inbound_peers: usize,
+    pub max_outbound_peers: usize,
+    pub max_reserved_sync_peers: usize,
+    pub max_reserved_mining_peers: usize,
+    /// Number of peers evicted due to over budget since the node started.
+    pub evicted_peers: u64,
+    /// Whether a port mapping is established or any peer has dialed us.
+    pub publicly_reachable: bool,
+    /// The method of established port mapping, i.e. `UPnP` or `NAT-PMP`.
+    pub port_mapping: Option<String>,
+    pub external_tcp_socket: Option<SocketAddr>,
+    pub external_udp_socket: Option<SocketAddr>,
+    /// The external IP address agreed by peers.
+    pub observed_ip: Option<Ipv4Addr>,
+    /// Messages and bytes per protocol and direction since the node started.
+    pub traffic: Vec<ProtocolTrafficInfo>,
+    /// Peers of the most bytes sent and received over RPC.
+    pub top_peers: Vec<PeerTrafficInfo>,
+}
+
+impl NetworkStats {
+    pub fn new(stats: &ConnectionStats, nat_status: &NatStatus, traffic: &TrafficStats) -> Self {
+        let mapping = nat_status.port_mapping.as_ref();
+        Self {
+            inbound_peers: stats.inbound,
+            outbound_peers: stats.outbound,
+            reserved_sync_peers: stats.reserved_sync,
+            reserved_mining_peers: stats.reserved_mining,
+            max_inbound_peers: stats.max_inbound,
+            max_outbound_peers: stats.max_outbound,
+            max_reserved_sync_peers: stats.max_reserved_sync,
+            max_reserved_mining_peers: stats.max_reserved_mining,
+            evicted_peers: stats.evicted,
+            publicly_reachable: nat_status.is_publicly_reachable(),
+            port_mapping: mapping.map(|m| m.method.to_string()),
+            external_tcp_socket: mapping.and_then(|m| m.tcp_socket),
+            external_udp_socket: mapping.and_then(|m| m.udp_socket),
+            observed_ip: nat_status.observed_ip,
+            traffic: traffic
+                .protocols
+                .iter()
+                .map(|p| ProtocolTrafficInfo {
+                    protocol: p.protocol.clone(),
+                    direction: p.direction.as_str().into(),
+                    messages: p.counter.messages,
+                    bytes: p.counter.bytes,
+                })
+                .collect(),
+            top_peers: traffic
+                .top_peers
+                .iter()
+                .map(|p| PeerTrafficInfo {
+                    peer_id: p.peer_id.to_base58(),
+                    sent_messages: p.sent.messages,
+                    sent_bytes: p.sent.bytes,
+                    received_messages: p.received.messages,
+                    received_bytes: p.received.bytes,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Traffic of a protocol, i.e. `rpc/<protocol>`, `gossip/<topic kind>` or `discovery`, in a
+/// direction.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtocolTrafficInfo {
+    pub protocol: String,
+    /// `inbound` or `outbound`.
+    pub direction: String,
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerTrafficInfo {
+    pub peer_id: String,
+    pub sent_messages: u64,
+    pub sent_bytes: u64,
+    pub received_messages: u64,
+    pub received_bytes: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogSyncStatus {
+    /// Latest block number of the chain, which may be reorged.
+    pub latest_block_number: Option<u64>,
+    /// Logs are only processed until this block number under the confirmation policy.
+    pub confirmed_block_number: Option<u64>,
+    /// Latest block number applied to the store.
+    pub synced_block_number: Option<u64>,
+    /// Number of blocks that the synced block falls behind the head reported by rpc endpoints.
+    pub lag_blocks: Option<u64>,
+    pub seconds_since_progress: u64,
+    /// Whether no new block is applied for `log_sync_stall_timeout_secs`.
+    pub stalled: bool,
+    pub active_endpoint: Option<String>,
+    pub endpoints: Vec<RpcEndpointInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcEndpointInfo {
+    pub url: String,
+    pub active: bool,
+    pub healthy: bool,
+    pub latency_ms: Option<u64>,
+    pub head: Option<u64>,
+    pub failures: u64,
+}
+
+/// Rewards distributed to a beneficiary by the `DistributeReward` events since the node started.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EarningsInfo {
+    pub beneficiary: Address,
+    pub amount: U256,
+    pub rewards: u64,
+    pub last_block_number: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FileInfo {
-    pub tx: Transaction,
+    pub tx: TransactionJson,
     pub finalized: bool,
+    /// Whether only the file data in the shard of the node is stored, in which case `finalized`
+    /// is also `true` while the file is not fully available on the node.
+    pub shard_finalized: bool,
     pub is_cached: bool,
     pub uploaded_seg_num: usize,
     /// Whether file is pruned, in which case `finalized` will be `false`.
     pub pruned: bool,
+    /// Block number of the on-chain submission, if recorded.
+    pub submission_block_number: Option<u64>,
+    /// Hash of the on-chain tx that submitted the file, if recorded.
+    pub submission_tx_hash: Option<H256>,
+    /// Unix timestamp in seconds when the data root is first submitted, which is kept for
+    /// re-submissions of the same root.
+    pub first_seen_at: Option<u64>,
 }
 
+/// File info returned by `zgs_getFileInfoBatch`, which also covers roots that are unknown to
+/// the node.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct Segment(#[serde(with = "base64")] pub Vec<u8>);
+#[serde(rename_all = "camelCase")]
+pub struct FileAvailability {
+    pub root: DataRoot,
+    /// `None` if no submission has been observed for the root.
+    pub tx: Option<TransactionJson>,
+    /// Whether no submission has been observed for the root.
+    /// This distinguishes an unknown root from a known but not finalized one.
+    pub never_submitted: bool,
+    pub finalized: bool,
+    /// Whether only the file data in the shard of the node is stored, in which case `finalized`
+    /// is also `true`.
+    pub shard_finalized: bool,
+    /// Whether file is pruned, in which case `finalized` will be `false`.
+    pub pruned: bool,
+    pub is_cached: bool,
+    pub uploaded_segments: usize,
+    pub total_segments: usize,
+}
+
+impl FileAvailability {
+    pub fn unknown(root: DataRoot) -> Self {
+        Self {
+            root,
+            tx: None,
+            never_submitted: true,
+            finalized: false,
+            shard_finalized: false,
+            pruned: false,
+            is_cached: false,
+            uploaded_segments: 0,
+            total_segments: 0,
+        }
+    }
 
+    /// Builds the availability of a submitted file from its info.
+    pub fn from_file_info(info: FileInfo, chunks_per_segment: usize) -> RpcResult<Self> {
+        let (total_segments, _) =
+            SegmentWithProof::split_file_into_segments(info.tx.size as usize, chunks_per_segment)?;
+
+        Ok(Self {
+            root: info.tx.data_merkle_root,
+            tx: Some(info.tx),
+            never_submitted: false,
+            finalized: info.finalized,
+            shard_finalized: info.shard_finalized,
+            pruned: info.pruned,
+            is_cached: info.is_cached,
+            uploaded_segments: info.uploaded_seg_num,
+            total_segments,
+        })
+    }
+}
+
+/// Upload session returned by `zgs_beginUpload`.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+pub struct UploadSession {
+    /// Token to upload segments and complete the upload with, until the session expires.
+    pub token: String,
+    /// `None` if no submission has been observed for the file yet.
+    pub tx_seq: Option<u64>,
+    pub total_segments: usize,
+    /// Bitmap of the segments available on the node, in which bit `i % 8` of byte `i / 8` is
+    /// set if segment `i` is available.
+    #[serde(with = "base64")]
+    pub uploaded: Vec<u8>,
+    /// Seconds that the session lives since last accessed.
+    pub ttl_secs: u64,
+}
+
+impl UploadSession {
+    /// Returns the bitmap of segments that are available.
+    pub fn bitmap(available: &[bool]) -> Vec<u8> {
+        let mut bitmap = vec![0u8; (available.len() + 7) / 8];
+        for (index, _) in available.iter().enumerate().filter(|(_, a)| **a) {
+            bitmap[index / 8] |= 1 << (index % 8);
+        }
+        bitmap
+    }
+}
+
+/// Result of `zgs_completeUpload`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadCompletion {
+    pub finalized: bool,
+    pub tx_seq: Option<u64>,
+    /// Ranges `[start, end)` of segments that are not available on the node yet, which is empty
+    /// if all segments uploaded and the file is waiting to be finalized.
+    pub missing: Vec<(usize, usize)>,
+}
+
+impl UploadCompletion {
+    /// Returns the ranges of segments that are not available.
+    pub fn missing_ranges(available: &[bool]) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = vec![];
+        for (index, _) in available.iter().enumerate().filter(|(_, a)| !**a) {
+            match ranges.last_mut() {
+                Some((_, end)) if *end == index => *end += 1,
+                _ => ranges.push((index, index + 1)),
+            }
+        }
+        ranges
+    }
+}
+
+/// Returns the `(finalized, shard_finalized, pruned)` flags of the tx status.
+pub fn tx_status_flags(status: Option<TxStatus>) -> (bool, bool, bool) {
+    match status {
+        Some(TxStatus::Finalized) => (true, false, false),
+        Some(TxStatus::ShardFinalized) => (true, true, false),
+        Some(TxStatus::Pruned) => (false, false, true),
+        Some(TxStatus::Invalid) | None => (false, false, false),
+    }
+}
+
+/// Status of the tx data stored on the node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionStatus {
+    /// Data not finalized yet.
+    Pending,
+    Finalized,
+    /// Only the data in the shard of the node is finalized.
+    ShardFinalized,
+    Pruned,
+    /// Rejected by the ingest policy of the node, so the data is never stored.
+    Invalid,
+}
+
+impl From<Option<TxStatus>> for TransactionStatus {
+    fn from(value: Option<TxStatus>) -> Self {
+        match value {
+            Some(TxStatus::Finalized) => TransactionStatus::Finalized,
+            Some(TxStatus::ShardFinalized) => TransactionStatus::ShardFinalized,
+            Some(TxStatus::Pruned) => TransactionStatus::Pruned,
+            Some(TxStatus::Invalid) => TransactionStatus::Invalid,
+            None => TransactionStatus::Pending,
+        }
+    }
+}
+
+/// Subtree of the flow entries submitted by a tx.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleNode {
+    /// Depth of the subtree, which has `2^(depth - 1)` entries.
+    pub depth: usize,
+    pub root: DataRoot,
+}
+
+/// Decoded on-chain submission of a tx, e.g. for explorers, which includes all the fields of
+/// the tx except the in-place data.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionDetail {
+    pub seq: u64,
+    pub stream_ids: Vec<U256>,
+    pub data_merkle_root: DataRoot,
+    /// Subtrees from the largest to the smallest.
+    pub merkle_nodes: Vec<MerkleNode>,
+    pub size: u64,
+    /// Flow entries `[start_entry_index, end_entry_index)` of the tx, including the padding of
+    /// the subtrees.
+    pub start_entry_index: u64,
+    pub end_entry_index: u64,
+    pub status: TransactionStatus,
+}
+
+impl TransactionDetail {
+    pub fn new(tx: Transaction, status: Option<TxStatus>) -> Self {
+        Self {
+            seq: tx.seq,
+            end_entry_index: tx.start_entry_index + tx.num_entries() as u64,
+            stream_ids: tx.stream_ids,
+            data_merkle_root: tx.data_merkle_root,
+            merkle_nodes: tx
+                .merkle_nodes
+                .into_iter()
+                .map(|(depth, root)| MerkleNode { depth, root })
+                .collect(),
+            size: tx.size,
+            start_entry_index: tx.start_entry_index,
+            status: status.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoredFileStatus {
+    Finalized,
+    Pruned,
+    /// Not finalized yet, so the file data is being uploaded or synced.
+    Syncing,
+    /// Rejected by the ingest policy of the node, so the file data is never stored.
+    Invalid,
+}
+
+impl From<Option<TxStatus>> for StoredFileStatus {
+    fn from(value: Option<TxStatus>) -> Self {
+        match value {
+            // Files finalized in shard have all the data the node is responsible for.
+            Some(TxStatus::Finalized | TxStatus::ShardFinalized) => StoredFileStatus::Finalized,
+            Some(TxStatus::Pruned) => StoredFileStatus::Pruned,
+            Some(TxStatus::Invalid) => StoredFileStatus::Invalid,
+            None => StoredFileStatus::Syncing,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFilter {
+    #[default]
+    All,
+    Finalized,
+    Pruned,
+    Syncing,
+}
+
+impl FileFilter {
+    pub fn matches(&self, status: StoredFileStatus) -> bool {
+        match self {
+            FileFilter::All => true,
+            FileFilter::Finalized => status == StoredFileStatus::Finalized,
+            FileFilter::Pruned => status == StoredFileStatus::Pruned,
+            FileFilter::Syncing => status == StoredFileStatus::Syncing,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredFile {
+    pub tx_seq: u64,
+    pub data_root: DataRoot,
+    pub size: u64,
+    pub status: StoredFileStatus,
+    /// Block number of the on-chain submission, if recorded.
+    pub finalized_block: Option<u64>,
+    /// Hash of the on-chain tx that submitted the file, if recorded.
+    pub submission_tx_hash: Option<H256>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredFilePage {
+    pub files: Vec<StoredFile>,
+    /// The `start_tx_seq` to query the next page, or `None` if all txs have been listed.
+    pub next_cursor: Option<u64>,
+}
+
+/// Replicas of a file announced by peers, as returned by `zgs_checkFileReplicas`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileReplicas {
+    pub tx_seq: u64,
+    /// Whether the file is finalized on this node, which is not counted in `peers`.
+    pub local: bool,
+    /// Number of distinct peers that announce the file.
+    pub peers: usize,
+    /// Estimated number of full copies in the network, i.e. the sum of the fraction of file
+    /// that every peer stores in its shard.
+    pub replication_factor: f64,
+    /// Whether the shards of all peers together cover the whole file.
+    pub all_shards_covered: bool,
+    /// Peers by shard, in ascending order of `(num_shard, shard_id)`.
+    pub shards: Vec<ShardReplicas>,
+    /// Timestamp in seconds of the oldest announcement counted, if any.
+    pub oldest_announcement: Option<u32>,
+    /// Timestamp in seconds of the latest announcement counted, if any.
+    pub latest_announcement: Option<u32>,
+    /// Whether the network is queried by this call, which is skipped if rate limited.
+    pub network_queried: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShardReplicas {
+    pub num_shard: usize,
+    pub shard_id: usize,
+    pub peers: usize,
+}
+
+impl FileReplicas {
+    /// Counts the replicas of peers by their shard configs.
+    pub fn new(
+        tx_seq: u64,
+        local: bool,
+        announcements: &[(u32, ShardConfig)],
+        network_queried: bool,
+    ) -> Self {
+        let mut shards: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+        for (_, shard_config) in announcements {
+            *shards
+                .entry((shard_config.num_shard, shard_config.shard_id))
+                .or_default() += 1;
+        }
+        let shard_configs: Vec<ShardConfig> =
+            announcements.iter().map(|(_, config)| *config).collect();
+
+        Self {
+            tx_seq,
+            local,
+            peers: announcements.len(),
+            replication_factor: shard_configs
+                .iter()
+                .map(|config| 1.0 / config.num_shard as f64)
+                .sum(),
+            all_shards_covered: all_shards_available(shard_configs),
+            shards: shards
+                .into_iter()
+                .map(|((num_shard, shard_id), peers)| ShardReplicas {
+                    num_shard,
+                    shard_id,
+                    peers,
+                })
+                .collect(),
+            oldest_announcement: announcements.iter().map(|(ts, _)| *ts).min(),
+            latest_announcement: announcements.iter().map(|(ts, _)| *ts).max(),
+            network_queried,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PeerStatsSort {
+    #[default]
+    BytesReceived,
+    BytesServed,
+    FailedVerifications,
+}
+
+/// Status of the node process, which is not of any service.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeStatus {
+    pub uptime_secs: u64,
+    /// Phases of the last startup in the order completed.
+    pub startup_phases: Vec<StartupPhaseInfo>,
+    /// Reason of the degraded service if any, e.g. `tree loading` while writes are queued until
+    /// the flow merkle tree is loaded.
+    pub degraded: Option<String>,
+    /// Rolling results of the self-audits of the stored data, or `None` if not audited yet.
+    pub self_audit: Option<SelfAuditStatus>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupPhaseInfo {
+    pub name: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerStatsInfo {
+    pub peer_id: String,
+    pub segments_received: u64,
+    pub bytes_received: u64,
+    pub failed_verifications: u64,
+    pub segments_served: u64,
+    pub bytes_served: u64,
+}
+
+impl PeerStatsInfo {
+    pub fn new(peer_id: PeerId, contribution: PeerContribution) -> Self {
+        Self {
+            peer_id: peer_id.to_string(),
+            segments_received: contribution.segments_received,
+            bytes_received: contribution.bytes_received,
+            failed_verifications: contribution.failed_verifications,
+            segments_served: contribution.segments_served,
+            bytes_served: contribution.bytes_served,
+        }
+    }
+
+    fn sort_key(&self, sort_by: PeerStatsSort) -> u64 {
+        match sort_by {
+            PeerStatsSort::BytesReceived => self.bytes_received,
+            PeerStatsSort::BytesServed => self.bytes_served,
+            PeerStatsSort::FailedVerifications => self.failed_verifications,
+        }
+    }
+
+    /// Sorts `stats` in descending order of `sort_by`.
+    pub fn sort(stats: &mut [PeerStatsInfo], sort_by: PeerStatsSort) {
+        stats.sort_by(|a, b| b.sort_key(sort_by).cmp(&a.sort_key(sort_by)));
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Segment(#[serde(with = "base64")] pub Vec<u8>);
+
+/// Segment along with its file merkle proof, which is SSZ encoded once downloaded in binary.
+#[derive(Clone, Debug, Serialize, Deserialize, DeriveEncode, DeriveDecode)]
+#[serde(rename_all = "camelCase")]
 pub struct SegmentWithProof {
     /// File merkle root.
     pub root: DataRoot,
@@ -107,6 +657,63 @@ impl SegmentWithProof {
         }
     }
 
+    /// Builds the only segment of a file which is not larger than one segment, and verifies
+    /// that the file merkle root computed from `data` equals `expected_root`.
+    pub fn from_small_file(
+        data: Vec<u8>,
+        expected_root: DataRoot,
+        chunks_per_segment: usize,
+    ) -> RpcResult<Self> {
+        let file_size = data.len();
+        let (root, data) = Self::small_file_root(data, chunks_per_segment)?;
+        if root != expected_root {
+            return Err(error::mismatch(
+                RpcErrorCode::RootMismatch,
+                json!(expected_root),
+                json!(root),
+            ));
+        }
+
+        Ok(SegmentWithProof {
+            root,
+            data,
+            index: 0,
+            proof: FileProof::new(vec![root], vec![]),
+            file_size,
+        })
+    }
+
+    /// Computes the file merkle root of a single segment file, and returns it along with the
+    /// data padded to the chunk boundary.
+    pub(crate) fn small_file_root(
+        mut data: Vec<u8>,
+        chunks_per_segment: usize,
+    ) -> RpcResult<(DataRoot, Vec<u8>)> {
+        let file_size = data.len();
+        let (num_segments, segment_size) =
+            SegmentWithProof::split_file_into_segments(file_size, chunks_per_segment)?;
+        if num_segments != 1 {
+            return Err(error::exceeds_limit(
+                RpcErrorCode::FileTooLarge,
+                chunks_per_segment * CHUNK_SIZE,
+            ));
+        }
+        data.resize(segment_size, 0);
+
+        let (chunks, _) = compute_padded_chunk_size(file_size);
+        let (_, padded_chunks) = compute_segment_size(chunks, chunks_per_segment);
+        let segment = SegmentWithProof {
+            root: DataRoot::zero(),
+            data,
+            index: 0,
+            proof: FileProof::new(vec![], vec![]),
+            file_size,
+        };
+        let root = segment.calculate_segment_merkle_root(padded_chunks - segment_size / CHUNK_SIZE);
+
+        Ok((root.into(), segment.data))
+    }
+
     fn validate_data_size_and_index(
         &self,
         file_size: usize,
@@ -116,7 +723,7 @@ impl SegmentWithProof {
             SegmentWithProof::split_file_into_segments(file_size, chunks_per_segment)?;
 
         if self.index >= num_segments {
-            return Err(error::invalid_params("index", "index out of bound"));
+            return Err(error::segment_out_of_range(self.index, num_segments));
         }
 
         let data_size = if self.index == num_segments - 1 {
@@ -126,7 +733,7 @@ impl SegmentWithProof {
         };
 
         if self.data.len() != data_size {
-            return Err(error::invalid_params("data", "invalid data length"));
+            return Err(error::invalid_segment("invalid data length"));
         }
 
         Ok(num_segments)
@@ -154,17 +761,17 @@ impl SegmentWithProof {
         // Validate proof data format at first.
         if self.proof.path.is_empty() {
             if self.proof.lemma.len() != 1 {
-                return Err(error::invalid_params("proof", "invalid proof"));
+                return Err(error::invalid_proof("invalid proof"));
             }
         } else if self.proof.lemma.len() != self.proof.path.len() + 2 {
-            return Err(error::invalid_params("proof", "invalid proof"));
+            return Err(error::invalid_proof("invalid proof"));
         }
 
         // Calculate segment merkle root to verify proof.
         let extend_chunk_length = if expected_data_length > self.data.len() {
             let extend_data_length = expected_data_length - self.data.len();
             if extend_data_length % CHUNK_SIZE != 0 {
-                return Err(error::invalid_params("proof", "invalid data len"));
+                return Err(error::invalid_proof("invalid data len"));
             }
 
             extend_data_length / CHUNK_SIZE
@@ -175,9 +782,10 @@ impl SegmentWithProof {
         let segment_root = self.calculate_segment_merkle_root(extend_chunk_length);
         if !self
             .proof
-            .validate(&segment_root, &self.root, self.index, num_segments)?
+            .validate(&segment_root, &self.root, self.index, num_segments)
+            .map_err(|e| error::invalid_proof(e.to_string()))?
         {
-            return Err(error::invalid_params("proof", "validation failed"));
+            return Err(error::invalid_proof("validation failed"));
         }
 
         let chunks_for_file = bytes_to_entries(self.file_size as u64) as usize;
@@ -187,8 +795,7 @@ impl SegmentWithProof {
             segments_for_file,
             log2_pow2(chunks_per_segment),
         ) {
-            return Err(error::invalid_params(
-                "proof",
+            return Err(error::invalid_proof(
                 "invalid proof node value in the padding range",
             ));
         }
@@ -264,6 +871,31 @@ impl SegmentWithProof {
     }
 }
 
+/// Flow entries with a range proof against the flow root, regardless of the file they belong to.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowEntriesWithProof {
+    /// Index of the first flow entry.
+    pub entry_index: u64,
+    #[serde(with = "base64")]
+    /// Data of all the flow entries.
+    pub data: Vec<u8>,
+    /// Range proof of entries against the flow root.
+    pub proof: FlowRangeProofJson,
+    /// Flow root when the proof generated.
+    pub flow_root: DataRoot,
+    /// Number of flow entries when the proof generated.
+    pub flow_length: u64,
+}
+
+impl FlowEntriesWithProof {
+    /// Validates the entries against the flow root.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let proof = FlowRangeProof::try_from(self.proof.clone())?;
+        validate_flow_entries(&self.flow_root, self.entry_index, &self.data, &proof)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PeerInfo {
@@ -293,6 +925,618 @@ impl From<&network::PeerInfo> for PeerInfo {
     }
 }
 
+/// Detailed information of a peer to debug connectivity.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerDetails {
+    pub peer_id: String,
+    #[serde(flatten)]
+    pub info: PeerInfo,
+    /// Negotiated versions of RPC protocols, e.g. `get_chunks` => `2`.
+    pub protocols: BTreeMap<String, String>,
+    pub shard_config: Option<ShardConfig>,
+    pub score: f64,
+    pub requests_received: u64,
+    pub requests_sent: u64,
+    pub responses_received: u64,
+    pub responses_sent: u64,
+    /// Number of RPC and dial errors.
+    pub errors: u64,
+    /// The last RPC or dial error.
+    pub last_error: Option<String>,
+}
+
+impl PeerDetails {
+    pub fn new(
+        peer_id: &network::PeerId,
+        value: &network::PeerInfo,
+        shard_config: Option<ShardConfig>,
+    ) -> Self {
+        let counters = value.rpc_counters();
+        Self {
+            peer_id: peer_id.to_base58(),
+            info: value.into(),
+            protocols: value
+                .rpc_versions()
+                .iter()
+                .map(|(protocol, version)| (protocol.to_string(), version.to_string()))
+                .collect(),
+            shard_config,
+            score: value.score().score(),
+            requests_received: counters.requests_received,
+            requests_sent: counters.requests_sent,
+            responses_received: counters.responses_received,
+            responses_sent: counters.responses_sent,
+            errors: counters.errors,
+            last_error: value.last_error().cloned(),
+        }
+    }
+}
+
+/// Recently connected peer persisted to reconnect after restart.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KnownPeerInfo {
+    pub peer_id: String,
+    pub addresses: Vec<Multiaddr>,
+    pub shard_config: Option<ShardConfig>,
+    /// Client version advertised by the peer, if connected since the node started.
+    pub version: Option<String>,
+    /// Unix timestamp in seconds when the peer is connected.
+    pub last_seen: u64,
+    pub score: f64,
+    /// Number of dials since the peer is connected.
+    pub dial_attempts: u32,
+}
+
+impl From<router::KnownPeer> for KnownPeerInfo {
+    fn from(value: router::KnownPeer) -> Self {
+        Self {
+            peer_id: value.peer_id.to_base58(),
+            addresses: value.addresses,
+            shard_config: value.shard_config,
+            version: value.version,
+            last_seen: value.last_seen,
+            score: value.score,
+            dial_attempts: value.dial_attempts,
+        }
+    }
+}
+
+/// Block hashes stored by log sync to detect reorgs, see `admin_getBlockProgress`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockProgressPage {
+    pub blocks: Vec<BlockProgress>,
+    /// The `from_block` to query the next page, or `None` if all blocks have been listed.
+    pub next_block: Option<u64>,
+    /// Block that log sync has applied to the store.
+    pub progress_block_number: Option<u64>,
+    pub progress_block_hash: Option<H256>,
+    /// Latest block number that log sync has fetched logs to.
+    pub log_latest_block_number: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockProgress {
+    pub block_number: u64,
+    pub block_hash: H256,
+    /// Index of the first `Submit` event in the block, or `None` if no event in the block.
+    pub first_submission_index: Option<u64>,
+}
+
+impl From<(u64, BlockHashAndSubmissionIndex)> for BlockProgress {
+    fn from((block_number, block): (u64, BlockHashAndSubmissionIndex)) -> Self {
+        Self {
+            block_number,
+            block_hash: block.block_hash,
+            first_submission_index: block.first_submission_index,
+        }
+    }
+}
+
+/// Txs reverted from the log store, e.g. on chain reorg.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReorgEvent {
+    /// Unix timestamp in seconds when reverted.
+    pub timestamp: u64,
+    /// The first tx seq reverted.
+    pub min_seq: u64,
+    /// The next tx seq before reverted.
+    pub max_seq: u64,
+    /// Number of tx seqs reverted.
+    pub depth: u64,
+    pub num_txs: usize,
+    /// Number of entry batches truncated from the flow.
+    pub truncated_batches: usize,
+    pub elapsed_ms: u64,
+}
+
+impl From<RevertEvent> for ReorgEvent {
+    fn from(value: RevertEvent) -> Self {
+        Self {
+            timestamp: value.timestamp,
+            min_seq: value.min_seq,
+            max_seq: value.max_seq,
+            depth: value.depth(),
+            num_txs: value.num_txs,
+            truncated_batches: value.truncated_batches,
+            elapsed_ms: value.elapsed.as_millis() as u64,
+        }
+    }
+}
+
+/// File exported by `admin_exportFile`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedFile {
+    pub tx_seq: u64,
+    /// Canonical path of the written file.
+    pub path: String,
+    pub size: u64,
+    /// Hex encoded sha256 of the file data.
+    pub sha256: String,
+}
+
+/// Flow merkle tree exported by `admin_exportMerkleState`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedMerkleState {
+    /// `None` if the flow is empty.
+    pub flow_root: Option<DataRoot>,
+    pub flow_length: u64,
+    pub flow_version: u64,
+    /// Number of bytes of the exported state.
+    pub size: u64,
+    /// Canonical path of the written file, or `None` if returned inline.
+    pub path: Option<String>,
+    /// The state in ssz if returned inline, which is empty if written into a file.
+    #[serde(with = "base64", default, skip_serializing_if = "Vec::is_empty")]
+    pub data: Vec<u8>,
+}
+
+/// File buffered in chunk pool.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkPoolFile {
+    pub data_root: DataRoot,
+    /// `None` if the tx not retrieved from blockchain yet.
+    pub tx_seq: Option<u64>,
+    /// `pending`, `spilled`, `flushing` or `writing`.
+    pub state: String,
+    /// Cached segments including spilled ones, or segments being written if `writing`.
+    pub buffered_segments: usize,
+    /// Bytes cached in memory.
+    pub bytes: usize,
+    pub spilled_bytes: usize,
+    /// Seconds since the first segment received.
+    pub age_secs: u64,
+}
+
+impl From<PoolFileStatus> for ChunkPoolFile {
+    fn from(status: PoolFileStatus) -> Self {
+        Self {
+            data_root: status.root,
+            tx_seq: status.tx_seq,
+            state: status.state.name().to_string(),
+            buffered_segments: status.buffered_segments,
+            bytes: status.bytes,
+            spilled_bytes: status.spilled_bytes,
+            age_secs: status.age.as_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkPoolInfo {
+    pub files: Vec<ChunkPoolFile>,
+    pub total_files: usize,
+    pub total_buffered_segments: usize,
+    pub total_bytes: usize,
+    pub total_spilled_bytes: usize,
+    /// Segments being written into store.
+    pub total_writings: usize,
+}
+
+impl From<ChunkPoolStatus> for ChunkPoolInfo {
+    fn from(status: ChunkPoolStatus) -> Self {
+        Self {
+            total_files: status.files.len(),
+            total_buffered_segments: status.files.iter().map(|f| f.buffered_segments).sum(),
+            total_bytes: status.total_bytes,
+            total_spilled_bytes: status.total_spilled_bytes,
+            total_writings: status.total_writings,
+            files: status.files.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// PoRA answer submitted by the miner, and its outcome on chain.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MinerRewardInfo {
+    pub epoch: u64,
+    /// Nonce of the answer, or hash of the submission tx if not submitted by the node.
+    pub id: H256,
+    pub tx_hash: Option<H256>,
+    /// Block of the `NewSubmission` event if accepted.
+    pub block_number: Option<u64>,
+    /// One of `pending`, `accepted` and `rejected`.
+    pub status: String,
+    pub reason: Option<String>,
+    pub amount: U256,
+}
+
+impl From<MinerReward> for MinerRewardInfo {
+    fn from(value: MinerReward) -> Self {
+        let status = if value.is_accepted() {
+            "accepted"
+        } else if value.is_rejected() {
+            "rejected"
+        } else {
+            "pending"
+        };
+        Self {
+            epoch: value.epoch,
+            id: value.id,
+            tx_hash: value.tx_hash,
+            block_number: value.block_number,
+            status: status.into(),
+            reason: value
+                .rejected_reason
+                .map(|r| String::from_utf8_lossy(&r).into_owned()),
+            amount: value.amount,
+        }
+    }
+}
+
+/// Totals of the PoRA answers submitted by the miner, which are kept across restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MinerStatus {
+    pub enabled: bool,
+    pub submissions: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub pending: u64,
+    pub total_rewards: U256,
+    /// Mine context that the miner works on, which is `None` if the miner is disabled.
+    pub context: Option<MineContextInfo>,
+}
+
+impl MinerStatus {
+    pub fn new(enabled: bool, summary: MinerRewardSummary) -> Self {
+        MinerStatus {
+            enabled,
+            submissions: summary.submissions,
+            accepted: summary.accepted,
+            rejected: summary.rejected,
+            pending: summary.pending,
+            total_rewards: summary.total_rewards,
+            context: None,
+        }
+    }
+}
+
+/// Status of the mine context, which is queried from the blockchain rpc endpoints.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MineContextInfo {
+    /// One of `live`, `unconfirmed`, `stale` and `unavailable`. The miner keeps mining on the
+    /// last confirmed context if `unconfirmed` or `stale`, and stops once `unavailable`.
+    pub state: String,
+    pub epoch: Option<u64>,
+    pub since_confirmed_secs: Option<u64>,
+    /// Number of queries that the rpc endpoints disagree on the context.
+    pub disagreements: u64,
+}
+
+impl From<MineContextStatus> for MineContextInfo {
+    fn from(value: MineContextStatus) -> Self {
+        Self {
+            state: value.state.as_str().into(),
+            epoch: value.epoch,
+            since_confirmed_secs: value.since_confirmed.map(|d| d.as_secs()),
+            disagreements: value.disagreements,
+        }
+    }
+}
+
+/// Mine puzzle of the current context for external PoRA provers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MinePuzzleInfo {
+    pub epoch: u64,
+    pub context_digest: H256,
+    pub context_flow_root: H256,
+    pub flow_length: u64,
+    pub target_quality: U256,
+    pub miner_id: H256,
+    pub start_position: u64,
+    pub mining_length: u64,
+    pub shard_mask: u64,
+    pub shard_id: u64,
+}
+
+impl From<MinePuzzle> for MinePuzzleInfo {
+    fn from(value: MinePuzzle) -> Self {
+        Self {
+            epoch: value.epoch,
+            context_digest: value.context_digest,
+            context_flow_root: value.context_flow_root,
+            flow_length: value.flow_length,
+            target_quality: value.target_quality,
+            miner_id: value.miner_id,
+            start_position: value.range.start_position,
+            mining_length: value.range.mining_length,
+            shard_mask: value.range.shard_mask,
+            shard_id: value.range.shard_id,
+        }
+    }
+}
+
+/// Sealed data of a seal along with the context digest that it is sealed with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SealedData {
+    /// Index of the seal in the load chunk.
+    pub seal_offset: usize,
+    pub seal_context: Option<H256>,
+    #[serde(with = "base64")]
+    pub data: Vec<u8>,
+}
+
+/// Sealed data of a load chunk, where the seals not sealed yet are excluded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SealedBatch {
+    pub chunk_index: u64,
+    pub seals: Vec<SealedData>,
+}
+
+impl SealedBatch {
+    pub fn new(chunk_index: u64, chunk: MineLoadChunk) -> Self {
+        let seals = chunk
+            .loaded_chunk
+            .into_iter()
+            .zip(chunk.availabilities)
+            .zip(chunk.seal_contexts)
+            .enumerate()
+            .filter(|(_, ((_, available), _))| *available)
+            .map(|(seal_offset, ((data, _), seal_context))| SealedData {
+                seal_offset,
+                seal_context,
+                data: data.to_vec(),
+            })
+            .collect();
+        Self { chunk_index, seals }
+    }
+}
+
+/// Sealed data of a load chunk as stored, with the context to verify it offline, i.e. to
+/// recompute the seals of the unsealed data for `miner_id`, and to validate `proof` of the
+/// unsealed chunk root against `flow_root`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SealedChunk {
+    pub chunk_index: u64,
+    /// Miner id that the chunk is sealed for, which is zero if nothing sealed yet.
+    pub miner_id: H256,
+    pub seals: Vec<SealedData>,
+    /// Root of the unsealed load chunk in the flow tree.
+    pub chunk_root: DataRoot,
+    /// Proof of `chunk_root` against `flow_root`.
+    pub proof: FlowProofJson,
+    /// Flow root when the proof generated.
+    pub flow_root: DataRoot,
+    /// Number of flow entries when the proof generated.
+    pub flow_length: u64,
+}
+
+impl SealedChunk {
+    pub fn new(chunk_index: u64, sealed: SealedChunkWithProof) -> Self {
+        Self {
+            chunk_index,
+            miner_id: sealed.miner_id,
+            seals: SealedBatch::new(chunk_index, sealed.chunk).seals,
+            chunk_root: sealed.proof.item(),
+            proof: sealed.proof.into(),
+            flow_root: sealed.flow_root,
+            flow_length: sealed.flow_length,
+        }
+    }
+}
+
+/// Page of the sealed batches exported for external PoRA provers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SealedBatchPage {
+    pub puzzle: MinePuzzleInfo,
+    /// Load chunks in order, excluding the ones not stored locally.
+    pub batches: Vec<SealedBatch>,
+    /// Index of the load chunk to export next, or `None` if all exported.
+    pub next: Option<u64>,
+}
+
+/// Entry batch whose local data is missing or does not match the flow tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowBatchMismatch {
+    pub batch_index: u64,
+    /// First flow chunk of the batch, inclusive.
+    pub start_chunk: u64,
+    /// Last flow chunk of the batch, exclusive.
+    pub end_chunk: u64,
+}
+
+impl FlowBatchMismatch {
+    pub fn new(batch_index: u64) -> Self {
+        let start_chunk = batch_index * PORA_CHUNK_SIZE as u64;
+        Self {
+            batch_index,
+            start_chunk,
+            end_chunk: start_chunk + PORA_CHUNK_SIZE as u64,
+        }
+    }
+}
+
+/// Long-running admin jobs started by `admin_startJob`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum JobParams {
+    /// Verifies the flow chunks `[start_chunk, end_chunk)` in the same way as
+    /// `admin_verifyFlowRange`, where `end_chunk` defaults to the flow length once started.
+    #[serde(rename_all = "camelCase")]
+    Scrub {
+        #[serde(default)]
+        start_chunk: u64,
+        end_chunk: Option<u64>,
+    },
+    /// Exports a finalized file in the same way as `admin_exportFile`.
+    #[serde(rename_all = "camelCase")]
+    ExportFile {
+        tx_seq_or_root: TxSeqOrRoot,
+        path: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Result of a job, which is partial for a running or stopped scrub.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum JobResult {
+    #[serde(rename_all = "camelCase")]
+    Scrub {
+        mismatches: Vec<FlowBatchMismatch>,
+    },
+    ExportFile(ExportedFile),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobInfo {
+    pub id: u64,
+    /// Params of the job, which are resolved once started, e.g. the tx seq and the canonical
+    /// path of an export.
+    pub params: JobParams,
+    pub state: JobState,
+    /// Chunks scrubbed or bytes exported so far.
+    pub progress_done: u64,
+    pub progress_total: u64,
+    pub error: Option<String>,
+    pub result: Option<JobResult>,
+    /// Unix timestamps in seconds.
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// PoRA answer found by an external prover.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalAnswerInfo {
+    pub context_digest: H256,
+    pub nonce: H256,
+    pub seal_offset: usize,
+    /// Quality computed by the prover, which is validated if specified.
+    pub quality: Option<U256>,
+}
+
+impl From<ExternalAnswerInfo> for ExternalAnswer {
+    fn from(value: ExternalAnswerInfo) -> Self {
+        Self {
+            context_digest: value.context_digest,
+            nonce: value.nonce,
+            seal_offset: value.seal_offset,
+            quality: value.quality,
+        }
+    }
+}
+
+/// External PoRA answer validated and queued for submission.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AcceptedAnswerInfo {
+    pub context_digest: H256,
+    pub nonce: H256,
+    pub recall_position: u64,
+    pub seal_offset: usize,
+    pub quality: U256,
+}
+
+impl From<AcceptedAnswer> for AcceptedAnswerInfo {
+    fn from(value: AcceptedAnswer) -> Self {
+        Self {
+            context_digest: value.context_digest,
+            nonce: value.nonce,
+            recall_position: value.recall_position,
+            seal_offset: value.seal_offset,
+            quality: value.quality,
+        }
+    }
+}
+
+/// Version and build info of the node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientVersion {
+    /// Semantic version, e.g. `0.3.0`.
+    pub version: String,
+    /// Short git commit of the build.
+    pub git_commit: String,
+    /// Unix timestamp in seconds when the node is built.
+    pub build_timestamp: u64,
+    /// Agent string advertised via the libp2p identify protocol.
+    pub agent_version: String,
+    pub p2p_protocol_version: ProtocolVersion,
+}
+
+impl ClientVersion {
+    pub fn new(p2p_protocol_version: ProtocolVersion) -> Self {
+        Self {
+            version: zgs_version::SEMVER.into(),
+            git_commit: zgs_version::GIT_COMMIT.into(),
+            build_timestamp: zgs_version::build_timestamp(),
+            agent_version: zgs_version::agent_version([
+                p2p_protocol_version.major,
+                p2p_protocol_version.minor,
+                p2p_protocol_version.build,
+            ]),
+            p2p_protocol_version,
+        }
+    }
+}
+
+/// Changed parameters of config file reloaded at runtime.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigReloadReport {
+    /// Parameters applied without restart.
+    pub applied: Vec<String>,
+    /// Parameters not applied, which require to restart the node.
+    pub rejected: Vec<String>,
+}
+
+impl std::fmt::Display for ConfigReloadReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "applied: {:?}, rejected (restart required): {:?}",
+            self.applied, self.rejected
+        )
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LocationInfo {
@@ -306,6 +1550,7 @@ pub struct Client {
     pub version: String,
     pub os: String,
     pub protocol: String,
+    pub p2p_protocol_version: Option<ProtocolVersion>,
     pub agent: Option<String>,
 }
 
@@ -315,6 +1560,7 @@ impl From<network::Client> for Client {
             version: value.version,
             os: value.os_version,
             protocol: value.protocol_version,
+            p2p_protocol_version: value.p2p_protocol_version,
             agent: value.agent_string,
         }
     }
@@ -379,7 +1625,24 @@ mod base64 {
 
 #[cfg(test)]
 mod tests {
-    use super::Segment;
+    use super::{
+        tx_status_flags, BlockProgress, FileAvailability, FileFilter, FileInfo, FileReplicas,
+        Segment, SegmentWithProof, ShardReplicas, StoredFileStatus, TransactionDetail,
+        UploadCompletion, UploadSession,
+    };
+    use crate::error::{error_code, RpcErrorCode};
+    use ethers::types::U256;
+    use shared_types::{DataRoot, Transaction, TransactionBuilder, CHUNK_SIZE};
+    use storage::config::ShardConfig;
+    use storage::log_store::tx_store::{BlockHashAndSubmissionIndex, TxStatus};
+    use storage::H256;
+
+    fn new_tx(seq: u64, root: DataRoot, size: u64) -> Transaction {
+        TransactionBuilder::new(seq)
+            .data_merkle_root(root)
+            .size(size)
+            .build_unchecked()
+    }
 
     #[test]
     fn test_segment_serde() {
@@ -390,4 +1653,306 @@ mod tests {
         let seg2: Segment = serde_json::from_str("\"aGVsbG8sIHdvcmxk\"").unwrap();
         assert_eq!(String::from_utf8(seg2.0).unwrap().as_str(), "hello, world");
     }
+
+    #[test]
+    fn test_transaction_detail_golden() {
+        let tx = TransactionBuilder::new(u64::MAX)
+            .stream_ids(vec![U256::from(1)])
+            // in-place data is never exposed
+            .data(vec![0xab, 0xcd])
+            .data_merkle_root(DataRoot::from_low_u64_be(1))
+            .merkle_nodes(vec![
+                (3, DataRoot::from_low_u64_be(2)),
+                (1, DataRoot::from_low_u64_be(3)),
+            ])
+            .start_entry_index(1024)
+            .size(5 * CHUNK_SIZE as u64)
+            .build_unchecked();
+        let detail = TransactionDetail::new(tx, Some(TxStatus::ShardFinalized));
+        assert_eq!(detail.end_entry_index, 1029);
+
+        let golden = include_str!("../tests/golden/transaction_detail.json");
+        let expected: serde_json::Value = serde_json::from_str(golden).unwrap();
+        assert_eq!(serde_json::to_value(&detail).unwrap(), expected);
+        assert_eq!(
+            serde_json::from_str::<TransactionDetail>(golden).unwrap(),
+            detail
+        );
+    }
+
+    #[test]
+    fn test_file_availability_batch() {
+        let chunks_per_segment = 4;
+        let size = (CHUNK_SIZE * chunks_per_segment * 2 + 1) as u64;
+        let file = |seq, root, status, uploaded_seg_num| {
+            let (finalized, shard_finalized, pruned) = tx_status_flags(status);
+            let info = FileInfo {
+                tx: new_tx(seq, DataRoot::from_low_u64_be(root), size).into(),
+                finalized,
+                shard_finalized,
+                is_cached: false,
+                uploaded_seg_num,
+                pruned,
+                submission_block_number: None,
+                submission_tx_hash: None,
+                first_seen_at: None,
+            };
+            FileAvailability::from_file_info(info, chunks_per_segment).unwrap()
+        };
+        let batch = vec![
+            file(0, 1, Some(TxStatus::Finalized), 3),
+            file(1, 2, None, 1),
+            file(2, 3, Some(TxStatus::Pruned), 3),
+            FileAvailability::unknown(DataRoot::from_low_u64_be(4)),
+            file(3, 5, Some(TxStatus::ShardFinalized), 3),
+        ];
+
+        let summary: Vec<_> = batch
+            .iter()
+            .map(|f| {
+                (
+                    f.never_submitted,
+                    f.finalized,
+                    f.shard_finalized,
+                    f.pruned,
+                    f.uploaded_segments,
+                    f.total_segments,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (false, true, false, false, 3, 3),
+                (false, false, false, false, 1, 3),
+                (false, false, false, true, 3, 3),
+                (true, false, false, false, 0, 0),
+                (false, true, true, false, 3, 3),
+            ]
+        );
+        assert!(batch[3].tx.is_none());
+
+        // u64 of tx serialized as numbers, as all the other RPC types
+        let json = serde_json::to_value(&batch[1]).unwrap();
+        assert_eq!(json["tx"]["seq"], 1);
+        assert_eq!(json["tx"]["size"], size);
+
+        let json = serde_json::to_value(&batch[3]).unwrap();
+        assert_eq!(json["neverSubmitted"], true);
+        assert_eq!(json["uploadedSegments"], 0);
+        assert_eq!(json["totalSegments"], 0);
+
+        let json = serde_json::to_value(&batch[4]).unwrap();
+        assert_eq!(json["shardFinalized"], true);
+    }
+
+    #[test]
+    fn test_file_replicas() {
+        let shard = |num_shard, shard_id| ShardConfig {
+            num_shard,
+            shard_id,
+        };
+
+        let replicas = FileReplicas::new(
+            1,
+            true,
+            &[(30, shard(1, 0)), (10, shard(2, 1)), (20, shard(4, 0))],
+            false,
+        );
+        assert_eq!(replicas.peers, 3);
+        assert_eq!(replicas.replication_factor, 1.75);
+        assert!(replicas.all_shards_covered);
+        assert_eq!(replicas.oldest_announcement, Some(10));
+        assert_eq!(replicas.latest_announcement, Some(30));
+        assert_eq!(
+            replicas.shards,
+            vec![
+                ShardReplicas {
+                    num_shard: 1,
+                    shard_id: 0,
+                    peers: 1
+                },
+                ShardReplicas {
+                    num_shard: 2,
+                    shard_id: 1,
+                    peers: 1
+                },
+                ShardReplicas {
+                    num_shard: 4,
+                    shard_id: 0,
+                    peers: 1
+                },
+            ]
+        );
+
+        // half of the file is missing
+        let replicas = FileReplicas::new(1, false, &[(10, shard(2, 1)), (20, shard(2, 1))], true);
+        assert_eq!(replicas.peers, 2);
+        assert_eq!(replicas.replication_factor, 1.0);
+        assert!(!replicas.all_shards_covered);
+        assert_eq!(replicas.shards.len(), 1);
+
+        let replicas = FileReplicas::new(1, false, &[], true);
+        assert_eq!(replicas.replication_factor, 0.0);
+        assert!(!replicas.all_shards_covered);
+        assert_eq!(replicas.oldest_announcement, None);
+    }
+
+    #[test]
+    fn test_file_filter() {
+        let all = [
+            StoredFileStatus::Finalized,
+            StoredFileStatus::Pruned,
+            StoredFileStatus::Syncing,
+        ];
+        let matched = |filter: FileFilter| {
+            all.iter()
+                .filter(|status| filter.matches(**status))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(matched(FileFilter::All), all.to_vec());
+        assert_eq!(
+            matched(FileFilter::Finalized),
+            vec![StoredFileStatus::Finalized]
+        );
+        assert_eq!(matched(FileFilter::Pruned), vec![StoredFileStatus::Pruned]);
+        assert_eq!(
+            matched(FileFilter::Syncing),
+            vec![StoredFileStatus::Syncing]
+        );
+
+        assert_eq!(
+            serde_json::from_str::<FileFilter>("\"syncing\"").unwrap(),
+            FileFilter::Syncing
+        );
+        assert_eq!(
+            StoredFileStatus::from(Some(TxStatus::Pruned)),
+            StoredFileStatus::Pruned
+        );
+        assert_eq!(
+            StoredFileStatus::from(Some(TxStatus::ShardFinalized)),
+            StoredFileStatus::Finalized
+        );
+        assert_eq!(StoredFileStatus::from(None), StoredFileStatus::Syncing);
+    }
+
+    #[test]
+    fn test_small_file() {
+        let chunks_per_segment = 16;
+        let data = vec![7u8; CHUNK_SIZE * 3 + 5];
+        let (root, padded) =
+            SegmentWithProof::small_file_root(data.clone(), chunks_per_segment).unwrap();
+        assert_eq!(padded.len(), CHUNK_SIZE * 4);
+
+        let segment =
+            SegmentWithProof::from_small_file(data.clone(), root, chunks_per_segment).unwrap();
+        assert_eq!(segment.file_size, data.len());
+        segment.validate(chunks_per_segment).unwrap();
+
+        // root mismatch
+        assert!(
+            SegmentWithProof::from_small_file(data, DataRoot::zero(), chunks_per_segment).is_err()
+        );
+
+        // oversize
+        let data = vec![7u8; CHUNK_SIZE * chunks_per_segment + 1];
+        assert!(SegmentWithProof::from_small_file(data, root, chunks_per_segment).is_err());
+
+        // empty
+        assert!(SegmentWithProof::from_small_file(vec![], root, chunks_per_segment).is_err());
+    }
+
+    #[test]
+    fn test_segment_error_codes() {
+        let chunks_per_segment = 16;
+        let data = vec![7u8; CHUNK_SIZE * 3];
+        let (root, _) =
+            SegmentWithProof::small_file_root(data.clone(), chunks_per_segment).unwrap();
+        let segment = SegmentWithProof::from_small_file(data, root, chunks_per_segment).unwrap();
+
+        let err =
+            SegmentWithProof::from_small_file(vec![1u8; 16], root, chunks_per_segment).unwrap_err();
+        assert_eq!(error_code(&err), RpcErrorCode::RootMismatch.code());
+
+        let mut out_of_range = segment.clone();
+        out_of_range.index = 1;
+        let err = out_of_range.validate(chunks_per_segment).unwrap_err();
+        assert_eq!(error_code(&err), RpcErrorCode::SegmentOutOfRange.code());
+
+        let mut invalid_data = segment.clone();
+        invalid_data.data.truncate(CHUNK_SIZE);
+        let err = invalid_data.validate(chunks_per_segment).unwrap_err();
+        assert_eq!(error_code(&err), RpcErrorCode::InvalidSegment.code());
+
+        let mut invalid_proof = segment;
+        invalid_proof.data[0] = 0;
+        let err = invalid_proof.validate(chunks_per_segment).unwrap_err();
+        assert_eq!(error_code(&err), RpcErrorCode::InvalidProof.code());
+    }
+
+    #[test]
+    fn test_upload_session_bitmap() {
+        let mut available = vec![false; 10];
+        for index in [0, 1, 4, 8] {
+            available[index] = true;
+        }
+        assert_eq!(
+            UploadSession::bitmap(&available),
+            vec![0b0001_0011, 0b0000_0001]
+        );
+        assert_eq!(
+            UploadCompletion::missing_ranges(&available),
+            vec![(2, 4), (5, 8), (9, 10)]
+        );
+
+        assert!(UploadSession::bitmap(&[]).is_empty());
+        assert!(UploadCompletion::missing_ranges(&[true; 3]).is_empty());
+        assert_eq!(UploadCompletion::missing_ranges(&[false; 3]), vec![(0, 3)]);
+    }
+
+    #[test]
+    fn test_block_progress_serde() {
+        let new_block = |first_submission_index| {
+            BlockProgress::from((
+                7,
+                BlockHashAndSubmissionIndex {
+                    block_hash: H256::from_low_u64_be(1),
+                    first_submission_index,
+                },
+            ))
+        };
+        let hash = format!("{:?}", H256::from_low_u64_be(1));
+
+        let block = new_block(Some(3));
+        let value = serde_json::to_value(&block).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "blockNumber": 7,
+                "blockHash": hash,
+                "firstSubmissionIndex": 3,
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<BlockProgress>(value).unwrap(),
+            block
+        );
+
+        // block without submissions
+        let block = new_block(None);
+        let value = serde_json::to_value(&block).unwrap();
+        assert_eq!(value["firstSubmissionIndex"], serde_json::Value::Null);
+        assert_eq!(
+            serde_json::from_value::<BlockProgress>(value).unwrap(),
+            block
+        );
+
+        // the field may be omitted by clients
+        let block: BlockProgress =
+            serde_json::from_str(&format!(r#"{{"blockNumber":7,"blockHash":"{}"}}"#, hash))
+                .unwrap();
+        assert_eq!(block, new_block(None));
+    }
 }
@@ -0,0 +1,244 @@
+//! Upload sessions assigned by `zgs_beginUpload`, so that a client could resume an interrupted
+//! upload with the server-assigned token, and segments already available on the node are not
+//! verified or written again.
+
+use shared_types::DataRoot;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// File of an upload session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SessionFile {
+    pub root: DataRoot,
+    pub size: usize,
+}
+
+struct Session {
+    file: SessionFile,
+    ip: IpAddr,
+    /// Segments that are available on the node, which are skipped once uploaded again.
+    present: HashSet<usize>,
+    expires_at: Instant,
+}
+
+#[derive(Default)]
+struct Inner {
+    sessions: HashMap<String, Session>,
+    /// Number of live sessions by client IP.
+    num_sessions: HashMap<IpAddr, usize>,
+}
+
+impl Inner {
+    fn remove(&mut self, token: &str) -> Option<Session> {
+        let session = self.sessions.remove(token)?;
+        if let Some(num) = self.num_sessions.get_mut(&session.ip) {
+            *num -= 1;
+            if *num == 0 {
+                self.num_sessions.remove(&session.ip);
+            }
+        }
+        Some(session)
+    }
+
+    fn remove_expired(&mut self, now: Instant) {
+        let expired: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| session.expires_at <= now)
+            .map(|(token, _)| token.clone())
+            .collect();
+        for token in expired {
+            self.remove(&token);
+        }
+    }
+
+    /// Returns the live session, whose expiration is extended once accessed.
+    fn get_mut(&mut self, token: &str, now: Instant, ttl: Duration) -> Option<&mut Session> {
+        if self.sessions.get(token)?.expires_at <= now {
+            self.remove(token);
+            return None;
+        }
+
+        let session = self.sessions.get_mut(token)?;
+        session.expires_at = now + ttl;
+        Some(session)
+    }
+}
+
+/// Upload sessions by token, which expire once not accessed for the TTL. The number of live
+/// sessions of every client IP is capped, so that a client could not exhaust the memory.
+pub struct UploadSessions {
+    ttl: Duration,
+    max_sessions_per_ip: usize,
+    inner: Mutex<Inner>,
+}
+
+impl UploadSessions {
+    pub fn new(ttl: Duration, max_sessions_per_ip: usize) -> Self {
+        Self {
+            ttl,
+            max_sessions_per_ip,
+            inner: Default::default(),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn max_sessions_per_ip(&self) -> usize {
+        self.max_sessions_per_ip
+    }
+
+    /// Returns the token of a new session, or that of the live session of the same file begun
+    /// by the same client, so that an interrupted upload is resumed. Returns `None` if the
+    /// client has too many sessions.
+    pub fn begin(&self, file: SessionFile, ip: IpAddr, now: Instant) -> Option<String> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove_expired(now);
+
+        let resumed = inner
+            .sessions
+            .iter()
+            .find(|(_, session)| session.file == file && session.ip == ip)
+            .map(|(token, _)| token.clone());
+        if let Some(token) = resumed {
+            inner.get_mut(&token, now, self.ttl)?;
+            return Some(token);
+        }
+
+        let num_sessions = inner.num_sessions.entry(ip).or_default();
+        if *num_sessions >= self.max_sessions_per_ip {
+            return None;
+        }
+        *num_sessions += 1;
+
+        let token = generate_token();
+        inner.sessions.insert(
+            token.clone(),
+            Session {
+                file,
+                ip,
+                present: Default::default(),
+                expires_at: now + self.ttl,
+            },
+        );
+        Some(token)
+    }
+
+    /// Returns the file of the live session.
+    pub fn get(&self, token: &str, now: Instant) -> Option<SessionFile> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .get_mut(token, now, self.ttl)
+            .map(|session| session.file)
+    }
+
+    /// Marks the segments available on the node.
+    pub fn mark_present(&self, token: &str, indices: impl IntoIterator<Item = usize>) {
+        if let Some(session) = self.inner.lock().unwrap().sessions.get_mut(token) {
+            session.present.extend(indices);
+        }
+    }
+
+    /// Returns whether the segment is known to be available on the node.
+    pub fn is_present(&self, token: &str, index: usize) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .sessions
+            .get(token)
+            .map_or(false, |session| session.present.contains(&index))
+    }
+
+    /// Ends the session once the file is finalized.
+    pub fn finish(&self, token: &str) {
+        self.inner.lock().unwrap().remove(token);
+    }
+}
+
+fn generate_token() -> String {
+    rand::random::<[u8; 16]>()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn file(root: u64) -> SessionFile {
+        SessionFile {
+            root: DataRoot::from_low_u64_be(root),
+            size: 1024,
+        }
+    }
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, last))
+    }
+
+    #[test]
+    fn test_resume_after_partial() {
+        let sessions = UploadSessions::new(TTL, 4);
+        let now = Instant::now();
+        let token = sessions.begin(file(1), ip(1), now).unwrap();
+        sessions.mark_present(&token, [0, 2]);
+
+        // Resumed by the same client, with the segments uploaded before.
+        let resumed = sessions.begin(file(1), ip(1), now + TTL / 2).unwrap();
+        assert_eq!(resumed, token);
+        assert!(sessions.is_present(&token, 0));
+        assert!(!sessions.is_present(&token, 1));
+        assert!(sessions.is_present(&token, 2));
+
+        // Never shared with other clients or files.
+        assert_ne!(sessions.begin(file(1), ip(2), now).unwrap(), token);
+        assert_ne!(sessions.begin(file(2), ip(1), now).unwrap(), token);
+
+        sessions.finish(&token);
+        assert!(sessions.get(&token, now).is_none());
+        assert_ne!(sessions.begin(file(1), ip(1), now).unwrap(), token);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let sessions = UploadSessions::new(TTL, 1);
+        let now = Instant::now();
+        let token = sessions.begin(file(1), ip(1), now).unwrap();
+
+        // Extended once accessed.
+        assert_eq!(sessions.get(&token, now + TTL / 2), Some(file(1)));
+        assert_eq!(sessions.get(&token, now + TTL), Some(file(1)));
+        assert!(sessions.get(&token, now + TTL * 5 / 2).is_none());
+        assert!(!sessions.is_present(&token, 0));
+
+        // Expired sessions are not counted for the client.
+        let token2 = sessions.begin(file(1), ip(1), now + TTL * 3).unwrap();
+        assert_ne!(token2, token);
+        let token3 = sessions.begin(file(1), ip(1), now + TTL * 5).unwrap();
+        assert_ne!(token3, token2);
+    }
+
+    #[test]
+    fn test_cap_per_ip() {
+        let sessions = UploadSessions::new(TTL, 2);
+        let now = Instant::now();
+        let token = sessions.begin(file(1), ip(1), now).unwrap();
+        sessions.begin(file(2), ip(1), now).unwrap();
+        assert!(sessions.begin(file(3), ip(1), now).is_none());
+        assert!(sessions.begin(file(3), ip(2), now).is_some());
+
+        // Resumed even if the cap reached.
+        assert_eq!(sessions.begin(file(1), ip(1), now), Some(token.clone()));
+
+        sessions.finish(&token);
+        assert!(sessions.begin(file(3), ip(1), now).is_some());
+    }
+}
@@ -1,7 +1,11 @@
-use crate::types::{FileInfo, Segment, SegmentWithProof, Status};
+use crate::types::{
+    ClientVersion, FileAvailability, FileInfo, FileReplicas, FlowEntriesWithProof, Segment,
+    SegmentWithProof, Status, TransactionDetail, UploadCompletion, UploadSession,
+};
 use jsonrpsee::core::RpcResult;
 use jsonrpsee::proc_macros::rpc;
-use shared_types::{DataRoot, FlowProof, TxSeqOrRoot};
+use shared_types::json::FlowProofJson;
+use shared_types::{DataRoot, TxSeqOrRoot};
 use storage::{config::ShardConfig, H256};
 
 #[rpc(server, client, namespace = "zgs")]
@@ -9,9 +13,23 @@ pub trait Rpc {
     #[method(name = "getStatus")]
     async fn get_status(&self) -> RpcResult<Status>;
 
+    /// Returns the node version, build info and P2P protocol version.
+    #[method(name = "clientVersion")]
+    async fn client_version(&self) -> RpcResult<ClientVersion>;
+
+    /// Uploads a segment of file. Uploading the same segment again succeeds immediately.
+    ///
+    /// Errors: `104` file already finalized, `105` file pruned, `106` invalid segment,
+    /// `107` invalid proof, `108` file size mismatch, `109` data root mismatch,
+    /// `110` segment already uploaded, `111` file too large to cache, `112` chunk pool busy
+    /// (retryable), `113` tx reverted, `118` segment conflicted, `121` proof verification busy
+    /// (retryable), `201` storage error.
     #[method(name = "uploadSegment")]
     async fn upload_segment(&self, segment: SegmentWithProof) -> RpcResult<()>;
 
+    /// Uploads a segment of file for the specified tx seq.
+    ///
+    /// Same errors as `uploadSegment`.
     #[method(name = "uploadSegmentByTxSeq")]
     async fn upload_segment_by_tx_seq(
         &self,
@@ -19,9 +37,15 @@ pub trait Rpc {
         tx_seq: u64,
     ) -> RpcResult<()>;
 
+    /// Uploads segments in order and stops at the first failed one.
+    ///
+    /// Same errors as `uploadSegment`.
     #[method(name = "uploadSegments")]
     async fn upload_segments(&self, segments: Vec<SegmentWithProof>) -> RpcResult<()>;
 
+    /// Uploads segments for the specified tx seq and stops at the first failed one.
+    ///
+    /// Same errors as `uploadSegment`.
     #[method(name = "uploadSegmentsByTxSeq")]
     async fn upload_segments_by_tx_seq(
         &self,
@@ -29,6 +53,52 @@ pub trait Rpc {
         tx_seq: u64,
     ) -> RpcResult<()>;
 
+    /// Uploads a file which is not larger than one segment, and returns the tx seq if the
+    /// on-chain submission is already known.
+    ///
+    /// Errors: `102` submission not found while pre-submission cache is disabled, `109` data
+    /// root mismatch, `115` file too large, and the errors of `uploadSegment`.
+    #[method(name = "uploadSmallFile")]
+    async fn upload_small_file(
+        &self,
+        data: Segment,
+        expected_root: DataRoot,
+    ) -> RpcResult<Option<u64>>;
+
+    /// Begins an upload session of the file, or resumes the live session of the same file begun
+    /// by the same client IP, and returns the session token along with the segments already
+    /// available on the node, e.g. cached in pool or written into store.
+    ///
+    /// Sessions expire once not accessed for `rpc.upload_session_ttl_secs`.
+    ///
+    /// Errors: `105` file pruned, `108` file size mismatch, `120` too many sessions of the
+    /// client, `201` storage error.
+    #[method(name = "beginUpload")]
+    async fn begin_upload(&self, data_root: DataRoot, size: usize) -> RpcResult<UploadSession>;
+
+    /// Uploads segments of the session file in order and stops at the first failed one.
+    /// Segments that are already available on the node are skipped without proof verification.
+    ///
+    /// Errors: `119` session not found or expired, and the errors of `uploadSegment`.
+    #[method(name = "uploadSegmentsWithToken")]
+    async fn upload_segments_with_token(
+        &self,
+        token: String,
+        segments: Vec<SegmentWithProof>,
+    ) -> RpcResult<()>;
+
+    /// Completes the upload session once the file is finalized, which is waited for a while if
+    /// all segments are available. Otherwise, returns the ranges of missing segments, and the
+    /// session is kept to upload them.
+    ///
+    /// Errors: `105` file pruned, `119` session not found or expired, `201` storage error.
+    #[method(name = "completeUpload")]
+    async fn complete_upload(&self, token: String) -> RpcResult<UploadCompletion>;
+
+    /// Downloads chunks in range `[start_index, end_index)`, or `None` if file or chunks not
+    /// available. Served as the raw chunks if requested with `Accept: application/octet-stream`.
+    ///
+    /// Errors: `201` storage error.
     #[method(name = "downloadSegment")]
     async fn download_segment(
         &self,
@@ -37,6 +107,7 @@ pub trait Rpc {
         end_index: usize,
     ) -> RpcResult<Option<Segment>>;
 
+    /// Same as `downloadSegment` but for the specified tx seq.
     #[method(name = "downloadSegmentByTxSeq")]
     async fn download_segment_by_tx_seq(
         &self,
@@ -45,6 +116,10 @@ pub trait Rpc {
         end_index: usize,
     ) -> RpcResult<Option<Segment>>;
 
+    /// Downloads a segment along with its proof, or `None` if file or chunks not available.
+    /// Served in SSZ if requested with `Accept: application/octet-stream`.
+    ///
+    /// Errors: `101` segment index out of range, `201` storage error.
     #[method(name = "downloadSegmentWithProof")]
     async fn download_segment_with_proof(
         &self,
@@ -52,6 +127,7 @@ pub trait Rpc {
         index: usize,
     ) -> RpcResult<Option<SegmentWithProof>>;
 
+    /// Same as `downloadSegmentWithProof` but for the specified tx seq.
     #[method(name = "downloadSegmentWithProofByTxSeq")]
     async fn download_segment_with_proof_by_tx_seq(
         &self,
@@ -68,6 +144,48 @@ pub trait Rpc {
     #[method(name = "getFileInfoByTxSeq")]
     async fn get_file_info_by_tx_seq(&self, tx_seq: u64) -> RpcResult<Option<FileInfo>>;
 
+    /// Returns the file info of multiple data roots in the same order.
+    /// The batch size is limited by `rpc.max_file_info_batch_size`.
+    ///
+    /// Errors: `114` batch too large, `201` storage error.
+    #[method(name = "getFileInfoBatch")]
+    async fn get_file_info_batch(&self, roots: Vec<DataRoot>) -> RpcResult<Vec<FileAvailability>>;
+
+    /// Counts the distinct peers that announce the file of `data_root`, by the announcements
+    /// cached on this node, or `None` if the root is unknown.
+    ///
+    /// If `query_network`, the network is asked for the file with `FindFile` gossip beforehand,
+    /// and all the announcements received within `rpc.replica_query_wait_ms` are counted as
+    /// well, or until `min_peers` counted if specified. The query is skipped if there are
+    /// already `rpc.max_replica_queries` queries within the wait time, as reported by
+    /// `networkQueried`.
+    ///
+    /// Errors: `201` storage error.
+    #[method(name = "checkFileReplicas")]
+    async fn check_file_replicas(
+        &self,
+        data_root: DataRoot,
+        query_network: Option<bool>,
+        min_peers: Option<usize>,
+    ) -> RpcResult<Option<FileReplicas>>;
+
+    /// Returns the decoded submission of the tx, or `None` if the tx is not synced yet.
+    ///
+    /// Errors: `201` storage error.
+    #[method(name = "getTransaction")]
+    async fn get_transaction(&self, tx_seq: u64) -> RpcResult<Option<TransactionDetail>>;
+
+    /// Returns at most `limit` txs from `start_seq` in order, and the next page starts from the
+    /// seq after the last returned tx. The limit is capped at 1000.
+    ///
+    /// Errors: `201` storage error.
+    #[method(name = "getTransactions")]
+    async fn get_transactions(
+        &self,
+        start_seq: u64,
+        limit: usize,
+    ) -> RpcResult<Vec<TransactionDetail>>;
+
     #[method(name = "getShardConfig")]
     async fn get_shard_config(&self) -> RpcResult<ShardConfig>;
 
@@ -76,8 +194,17 @@ pub trait Rpc {
         &self,
         sector_index: u64,
         flow_root: Option<DataRoot>,
-    ) -> RpcResult<FlowProof>;
+    ) -> RpcResult<FlowProofJson>;
 
     #[method(name = "getFlowContext")]
     async fn get_flow_context(&self) -> RpcResult<(H256, u64)>;
+
+    /// Returns `count` flow entries from `entry_index` with a range proof against the latest
+    /// flow root. The number of entries is limited by `rpc.chunks_per_segment`.
+    ///
+    /// Errors: `114` too many entries, `116` entries out of local shard, `117` entries pruned
+    /// or not synced yet, `201` storage error.
+    #[method(name = "getFlowProof")]
+    async fn get_flow_proof(&self, entry_index: u64, count: u64)
+        -> RpcResult<FlowEntriesWithProof>;
 }
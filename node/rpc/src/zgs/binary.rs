@@ -0,0 +1,85 @@
+//! Download methods of the `zgs` namespace served in SSZ, which saves the base64 encoding of the
+//! data in JSON on both ends. `downloadSegment*` returns the raw chunks, and
+//! `downloadSegmentWithProof*` returns the SSZ encoded
+//! [`SegmentWithProof`](crate::types::SegmentWithProof), whose proof is verified by clients as
+//! the JSON one.
+
+use super::api::RpcServer;
+use super::r#impl::RpcServerImpl;
+use crate::gateway::BinaryMethods;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use jsonrpsee::core::RpcResult;
+use jsonrpsee::types::Params;
+use serde::de::DeserializeOwned;
+use shared_types::DataRoot;
+use ssz::Encode;
+use std::future::Future;
+
+impl BinaryMethods for RpcServerImpl {
+    fn call(
+        &self,
+        method: &str,
+        params: Params<'_>,
+    ) -> Option<BoxFuture<'static, RpcResult<Option<Vec<u8>>>>> {
+        let server = RpcServerImpl {
+            ctx: self.ctx.clone(),
+        };
+
+        let future = match method {
+            "zgs_downloadSegment" => dispatch(
+                params,
+                move |(data_root, start_index, end_index): (DataRoot, usize, usize)| async move {
+                    let segment = server
+                        .download_segment(data_root, start_index, end_index)
+                        .await?;
+                    Ok(segment.map(|segment| segment.0))
+                },
+            ),
+            "zgs_downloadSegmentByTxSeq" => dispatch(
+                params,
+                move |(tx_seq, start_index, end_index): (u64, usize, usize)| async move {
+                    let segment = server
+                        .download_segment_by_tx_seq(tx_seq, start_index, end_index)
+                        .await?;
+                    Ok(segment.map(|segment| segment.0))
+                },
+            ),
+            "zgs_downloadSegmentWithProof" => dispatch(
+                params,
+                move |(data_root, index): (DataRoot, usize)| async move {
+                    server.download_segment_with_proof(data_root, index).await
+                },
+            ),
+            "zgs_downloadSegmentWithProofByTxSeq" => {
+                dispatch(params, move |(tx_seq, index): (u64, usize)| async move {
+                    server
+                        .download_segment_with_proof_by_tx_seq(tx_seq, index)
+                        .await
+                })
+            }
+            _ => return None,
+        };
+
+        Some(future)
+    }
+}
+
+/// Parses the positional params, and encodes the result of `handler` in SSZ.
+fn dispatch<P, F, Fut, T>(
+    params: Params<'_>,
+    handler: F,
+) -> BoxFuture<'static, RpcResult<Option<Vec<u8>>>>
+where
+    P: DeserializeOwned,
+    F: FnOnce(P) -> Fut,
+    Fut: Future<Output = RpcResult<Option<T>>> + Send + 'static,
+    T: Encode,
+{
+    match params.parse::<P>() {
+        Ok(params) => handler(params)
+            .map(|result| result.map(|value| value.map(|value| value.as_ssz_bytes())))
+            .boxed(),
+        Err(e) => futures::future::ready(Err(e.into())).boxed(),
+    }
+}
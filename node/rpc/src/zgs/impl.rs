@@ -1,16 +1,33 @@
 use super::api::RpcServer;
-use crate::error;
-use crate::types::{FileInfo, Segment, SegmentWithProof, Status};
+use crate::error::{self, RpcErrorCode};
+use crate::gateway::client_ip;
+use crate::types::{
+    tx_status_flags, ClientVersion, FileAvailability, FileInfo, FileReplicas, FlowEntriesWithProof,
+    Segment, SegmentWithProof, Status, TransactionDetail, UploadCompletion, UploadSession,
+};
+use crate::upload_session::SessionFile;
 use crate::Context;
-use chunk_pool::{FileID, SegmentInfo};
+use chunk_pool::{FileID, MemoryChunkPool, SegmentCheck, SegmentInfo};
 use jsonrpsee::core::async_trait;
 use jsonrpsee::core::RpcResult;
-use shared_types::{DataRoot, FlowProof, Transaction, TxSeqOrRoot, CHUNK_SIZE};
+use network::types::FindFile;
+use network::{NetworkMessage, PubsubMessage};
+use serde_json::json;
+use shared_types::json::FlowProofJson;
+use shared_types::{DataRoot, Transaction, TxSeqOrRoot, CHUNK_SIZE};
 use std::fmt::{Debug, Formatter, Result};
+use std::time::{Duration, Instant};
 use storage::config::ShardConfig;
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
 use storage::log_store::tx_store::TxStatus;
 use storage::{try_option, H256};
 
+/// Maximum number of txs returned by `zgs_getTransactions`.
+const MAX_GET_TRANSACTIONS_LIMIT: usize = 1000;
+
+/// Maximum time that `zgs_completeUpload` waits for the file to be finalized.
+const COMPLETE_UPLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct RpcServerImpl {
     pub ctx: Context,
 }
@@ -24,7 +41,8 @@ impl RpcServer for RpcServerImpl {
             .ctx
             .log_store
             .get_store()
-            .get_sync_progress()?
+            .get_sync_progress()
+            .map_err(error::storage_error)?
             .unwrap_or_default();
 
         let next_tx_seq = self.ctx.log_store.get_store().next_tx_seq();
@@ -38,6 +56,13 @@ impl RpcServer for RpcServerImpl {
         })
     }
 
+    async fn client_version(&self) -> RpcResult<ClientVersion> {
+        debug!("zgs_clientVersion()");
+        Ok(ClientVersion::new(
+            self.ctx.network_globals.network_id().p2p_protocol_version,
+        ))
+    }
+
     async fn upload_segment(&self, segment: SegmentWithProof) -> RpcResult<()> {
         info!(root = %segment.root, index = %segment.index, "zgs_uploadSegment");
         self.put_segment(segment).await
@@ -49,7 +74,12 @@ impl RpcServer for RpcServerImpl {
         tx_seq: u64,
     ) -> RpcResult<()> {
         info!(tx_seq = %tx_seq, index = %segment.index, "zgs_uploadSegmentByTxSeq");
-        let maybe_tx = self.ctx.log_store.get_tx_by_seq_number(tx_seq).await?;
+        let maybe_tx = self
+            .ctx
+            .log_store
+            .get_tx_by_seq_number(tx_seq)
+            .await
+            .map_err(error::storage_error)?;
         self.put_segment_with_maybe_tx(segment, maybe_tx).await
     }
 
@@ -76,7 +106,12 @@ impl RpcServer for RpcServerImpl {
         let indices = SegmentIndexArray::new(&segments);
         info!(%tx_seq, ?indices, "zgs_uploadSegmentsByTxSeq");
 
-        let maybe_tx = self.ctx.log_store.get_tx_by_seq_number(tx_seq).await?;
+        let maybe_tx = self
+            .ctx
+            .log_store
+            .get_tx_by_seq_number(tx_seq)
+            .await
+            .map_err(error::storage_error)?;
         for segment in segments.into_iter() {
             self.put_segment_with_maybe_tx(segment, maybe_tx.clone())
                 .await?;
@@ -85,6 +120,197 @@ impl RpcServer for RpcServerImpl {
         Ok(())
     }
 
+    async fn upload_small_file(
+        &self,
+        data: Segment,
+        expected_root: DataRoot,
+    ) -> RpcResult<Option<u64>> {
+        info!(%expected_root, size = %data.0.len(), "zgs_uploadSmallFile");
+        self.ctx.check_writable()?;
+
+        if data.0.len() > self.ctx.config.max_small_file_size {
+            return Err(error::exceeds_limit(
+                RpcErrorCode::FileTooLarge,
+                self.ctx.config.max_small_file_size,
+            ));
+        }
+
+        let segment = SegmentWithProof::from_small_file(
+            data.0,
+            expected_root,
+            self.ctx.config.chunks_per_segment,
+        )?;
+
+        let maybe_tx = self
+            .ctx
+            .log_store
+            .get_tx_by_data_root(&expected_root)
+            .await
+            .map_err(error::storage_error)?;
+        if maybe_tx.is_none() && !self.ctx.config.allow_pre_submission_cache {
+            return Err(error::file_not_found(json!({ "root": expected_root })));
+        }
+
+        let tx_seq = maybe_tx.as_ref().map(|tx| tx.seq);
+        self.put_segment_with_maybe_tx(segment, maybe_tx).await?;
+
+        Ok(tx_seq)
+    }
+
+    async fn begin_upload(&self, data_root: DataRoot, size: usize) -> RpcResult<UploadSession> {
+        let ip = client_ip();
+        info!(%data_root, %size, %ip, "zgs_beginUpload");
+        self.ctx.check_writable()?;
+
+        let file = SessionFile {
+            root: data_root,
+            size,
+        };
+        let (maybe_tx, available) = self.get_segment_availability(file).await?;
+
+        let sessions = &self.ctx.upload_sessions;
+        let token = sessions.begin(file, ip, Instant::now()).ok_or_else(|| {
+            error::exceeds_limit(
+                RpcErrorCode::TooManyUploadSessions,
+                sessions.max_sessions_per_ip(),
+            )
+        })?;
+        sessions.mark_present(
+            &token,
+            available
+                .iter()
+                .enumerate()
+                .filter(|(_, a)| **a)
+                .map(|(index, _)| index),
+        );
+
+        Ok(UploadSession {
+            token,
+            tx_seq: maybe_tx.map(|tx| tx.seq),
+            total_segments: available.len(),
+            uploaded: UploadSession::bitmap(&available),
+            ttl_secs: sessions.ttl().as_secs(),
+        })
+    }
+
+    async fn upload_segments_with_token(
+        &self,
+        token: String,
+        segments: Vec<SegmentWithProof>,
+    ) -> RpcResult<()> {
+        let indices = SegmentIndexArray::new(&segments);
+        info!(%token, ?indices, "zgs_uploadSegmentsWithToken");
+        self.ctx.check_writable()?;
+
+        let sessions = &self.ctx.upload_sessions;
+        let file = sessions
+            .get(&token, Instant::now())
+            .ok_or_else(|| error::upload_session_not_found(&token))?;
+
+        let maybe_tx = self
+            .ctx
+            .log_store
+            .get_tx_by_data_root(&file.root)
+            .await
+            .map_err(error::storage_error)?;
+        for segment in segments.into_iter() {
+            if segment.root != file.root {
+                return Err(error::mismatch(
+                    RpcErrorCode::RootMismatch,
+                    json!(file.root),
+                    json!(segment.root),
+                ));
+            }
+            if segment.file_size != file.size {
+                return Err(error::mismatch(
+                    RpcErrorCode::FileSizeMismatch,
+                    json!(file.size),
+                    json!(segment.file_size),
+                ));
+            }
+
+            let index = segment.index;
+            if sessions.is_present(&token, index) {
+                debug!(root = %file.root, %index, "Segment already available in session");
+                continue;
+            }
+
+            self.put_segment_with_maybe_tx(segment, maybe_tx.clone())
+                .await?;
+            sessions.mark_present(&token, [index]);
+        }
+
+        Ok(())
+    }
+
+    async fn complete_upload(&self, token: String) -> RpcResult<UploadCompletion> {
+        info!(%token, "zgs_completeUpload");
+
+        let sessions = &self.ctx.upload_sessions;
+        let file = sessions
+            .get(&token, Instant::now())
+            .ok_or_else(|| error::upload_session_not_found(&token))?;
+
+        let (mut maybe_tx, available) = self.get_segment_availability(file).await?;
+        let missing = UploadCompletion::missing_ranges(&available);
+        if !missing.is_empty() {
+            return Ok(UploadCompletion {
+                finalized: false,
+                tx_seq: maybe_tx.map(|tx| tx.seq),
+                missing,
+            });
+        }
+
+        // All segments uploaded, and the file is finalized once written into store. Subscribed
+        // before the store is checked, so that the finalization in between is never missed.
+        let mut finalization_recv = self.ctx.log_store.get_store().subscribe_finalization();
+        let deadline = tokio::time::Instant::now() + COMPLETE_UPLOAD_TIMEOUT;
+        loop {
+            if maybe_tx.is_none() {
+                maybe_tx = self
+                    .ctx
+                    .log_store
+                    .get_tx_by_data_root(&file.root)
+                    .await
+                    .map_err(error::storage_error)?;
+            }
+            if let Some(tx) = &maybe_tx {
+                if self
+                    .ctx
+                    .log_store
+                    .check_tx_completed(tx.seq)
+                    .await
+                    .map_err(error::storage_error)?
+                {
+                    sessions.finish(&token);
+                    return Ok(UploadCompletion {
+                        finalized: true,
+                        tx_seq: Some(tx.seq),
+                        missing: vec![],
+                    });
+                }
+            }
+
+            // Checked again once the tx finalized, or any tx if not submitted yet.
+            loop {
+                let event = match tokio::time::timeout_at(deadline, finalization_recv.recv()).await
+                {
+                    Ok(event) => event,
+                    Err(_) => {
+                        return Ok(UploadCompletion {
+                            finalized: false,
+                            tx_seq: maybe_tx.map(|tx| tx.seq),
+                            missing: vec![],
+                        })
+                    }
+                };
+                if maybe_tx.as_ref().map_or(true, |tx| event.contains(tx.seq)) {
+                    break;
+                }
+            }
+        }
+    }
+
     async fn download_segment(
         &self,
         data_root: DataRoot,
@@ -93,12 +319,12 @@ impl RpcServer for RpcServerImpl {
     ) -> RpcResult<Option<Segment>> {
         info!(%data_root, %start_index, %end_index, "zgs_downloadSegment");
 
-        let tx_seq = try_option!(
-            self.ctx
-                .log_store
-                .get_tx_seq_by_data_root(&data_root)
-                .await?
-        );
+        let tx_seq = try_option!(self
+            .ctx
+            .log_store
+            .get_tx_seq_by_data_root(&data_root)
+            .await
+            .map_err(error::storage_error)?);
 
         self.get_segment_by_tx_seq(tx_seq, start_index, end_index)
             .await
@@ -122,9 +348,15 @@ impl RpcServer for RpcServerImpl {
     ) -> RpcResult<Option<SegmentWithProof>> {
         info!(%data_root, %index, "zgs_downloadSegmentWithProof");
 
-        let tx = try_option!(self.ctx.log_store.get_tx_by_data_root(&data_root).await?);
+        let version = self.ctx.log_store.get_store().get_flow_version();
+        let tx = try_option!(self
+            .ctx
+            .log_store
+            .get_tx_by_data_root(&data_root)
+            .await
+            .map_err(error::storage_error)?);
 
-        self.get_segment_with_proof_by_tx(tx, index).await
+        self.get_segment_with_proof_by_tx(tx, index, version).await
     }
 
     async fn download_segment_with_proof_by_tx_seq(
@@ -134,9 +366,15 @@ impl RpcServer for RpcServerImpl {
     ) -> RpcResult<Option<SegmentWithProof>> {
         info!(%tx_seq, %index, "zgs_downloadSegmentWithProofByTxSeq");
 
-        let tx = try_option!(self.ctx.log_store.get_tx_by_seq_number(tx_seq).await?);
+        let version = self.ctx.log_store.get_store().get_flow_version();
+        let tx = try_option!(self
+            .ctx
+            .log_store
+            .get_tx_by_seq_number(tx_seq)
+            .await
+            .map_err(error::storage_error)?);
 
-        self.get_segment_with_proof_by_tx(tx, index).await
+        self.get_segment_with_proof_by_tx(tx, index, version).await
     }
 
     async fn check_file_finalized(&self, tx_seq_or_root: TxSeqOrRoot) -> RpcResult<Option<bool>> {
@@ -145,17 +383,29 @@ impl RpcServer for RpcServerImpl {
         let seq = match tx_seq_or_root {
             TxSeqOrRoot::TxSeq(v) => v,
             TxSeqOrRoot::Root(v) => {
-                try_option!(self.ctx.log_store.get_tx_seq_by_data_root(&v).await?)
+                try_option!(self
+                    .ctx
+                    .log_store
+                    .get_tx_seq_by_data_root(&v)
+                    .await
+                    .map_err(error::storage_error)?)
             }
         };
 
-        if self.ctx.log_store.check_tx_completed(seq).await? {
+        if self
+            .ctx
+            .log_store
+            .check_tx_completed(seq)
+            .await
+            .map_err(error::storage_error)?
+        {
             Ok(Some(true))
         } else if self
             .ctx
             .log_store
             .get_tx_by_seq_number(seq)
-            .await?
+            .await
+            .map_err(error::storage_error)?
             .is_some()
         {
             Ok(Some(false))
@@ -167,7 +417,12 @@ impl RpcServer for RpcServerImpl {
     async fn get_file_info(&self, data_root: DataRoot) -> RpcResult<Option<FileInfo>> {
         debug!(%data_root, "zgs_getFileInfo");
 
-        let tx = try_option!(self.ctx.log_store.get_tx_by_data_root(&data_root).await?);
+        let tx = try_option!(self
+            .ctx
+            .log_store
+            .get_tx_by_data_root(&data_root)
+            .await
+            .map_err(error::storage_error)?);
 
         Ok(Some(self.get_file_info_by_tx(tx).await?))
     }
@@ -175,11 +430,164 @@ impl RpcServer for RpcServerImpl {
     async fn get_file_info_by_tx_seq(&self, tx_seq: u64) -> RpcResult<Option<FileInfo>> {
         debug!(%tx_seq, "zgs_getFileInfoByTxSeq");
 
-        let tx = try_option!(self.ctx.log_store.get_tx_by_seq_number(tx_seq).await?);
+        let tx = try_option!(self
+            .ctx
+            .log_store
+            .get_tx_by_seq_number(tx_seq)
+            .await
+            .map_err(error::storage_error)?);
 
         Ok(Some(self.get_file_info_by_tx(tx).await?))
     }
 
+    async fn get_transaction(&self, tx_seq: u64) -> RpcResult<Option<TransactionDetail>> {
+        debug!(%tx_seq, "zgs_getTransaction");
+
+        let tx = try_option!(self
+            .ctx
+            .log_store
+            .get_tx_by_seq_number(tx_seq)
+            .await
+            .map_err(error::storage_error)?);
+        let status = self
+            .ctx
+            .log_store
+            .get_tx_status(tx_seq)
+            .await
+            .map_err(error::storage_error)?;
+
+        Ok(Some(TransactionDetail::new(tx, status)))
+    }
+
+    async fn get_transactions(
+        &self,
+        start_seq: u64,
+        limit: usize,
+    ) -> RpcResult<Vec<TransactionDetail>> {
+        debug!(%start_seq, %limit, "zgs_getTransactions");
+
+        let txs = self
+            .ctx
+            .log_store
+            .get_txs_with_status(start_seq, limit.min(MAX_GET_TRANSACTIONS_LIMIT))
+            .await
+            .map_err(error::storage_error)?;
+
+        Ok(txs
+            .into_iter()
+            .map(|(tx, status)| TransactionDetail::new(tx, status))
+            .collect())
+    }
+
+    async fn get_file_info_batch(&self, roots: Vec<DataRoot>) -> RpcResult<Vec<FileAvailability>> {
+        debug!(num = %roots.len(), "zgs_getFileInfoBatch");
+
+        if roots.len() > self.ctx.config.max_file_info_batch_size {
+            return Err(error::exceeds_limit(
+                RpcErrorCode::BatchTooLarge,
+                self.ctx.config.max_file_info_batch_size,
+            ));
+        }
+
+        let txs = self
+            .ctx
+            .log_store
+            .get_txs_by_data_roots(roots.clone())
+            .await
+            .map_err(error::storage_error)?;
+
+        let mut result = Vec::with_capacity(roots.len());
+        for (root, maybe_tx) in roots.into_iter().zip(txs) {
+            let tx = match maybe_tx {
+                Some(tx) => tx,
+                None => {
+                    result.push(FileAvailability::unknown(root));
+                    continue;
+                }
+            };
+
+            let info = self.get_file_info_by_tx(tx).await?;
+            result.push(FileAvailability::from_file_info(
+                info,
+                self.ctx.config.chunks_per_segment,
+            )?);
+        }
+
+        Ok(result)
+    }
+
+    async fn check_file_replicas(
+        &self,
+        data_root: DataRoot,
+        query_network: Option<bool>,
+        min_peers: Option<usize>,
+    ) -> RpcResult<Option<FileReplicas>> {
+        debug!(%data_root, ?query_network, ?min_peers, "zgs_checkFileReplicas");
+
+        let tx = try_option!(self
+            .ctx
+            .log_store
+            .get_tx_by_data_root(&data_root)
+            .await
+            .map_err(error::storage_error)?);
+
+        // Collects all the answers, since the cache keeps only a few announcements per file.
+        let local_peer_id = self.ctx.network_globals.local_peer_id();
+        let mut watcher = self.ctx.file_location_cache.watch(tx.id());
+
+        // Answers are gossiped back, so only wait for a while instead of the whole network.
+        let network_queried =
+            query_network.unwrap_or_default() && self.ctx.replica_query_limiter.try_acquire();
+        if network_queried {
+            self.ctx.send_network(NetworkMessage::Publish {
+                messages: vec![PubsubMessage::FindFile(
+                    FindFile {
+                        tx_id: tx.id(),
+                        maybe_shard_config: None,
+                    }
+                    .into(),
+                )],
+            })?;
+            let min_peers = min_peers.unwrap_or(usize::MAX);
+            let enough_peers = watcher.wait_for(|announcements| {
+                announcements
+                    .keys()
+                    .filter(|peer_id| **peer_id != local_peer_id)
+                    .count()
+                    >= min_peers
+            });
+            let wait = Duration::from_millis(self.ctx.config.replica_query_wait_ms);
+            let _ = tokio::time::timeout(wait, enough_peers).await;
+        }
+
+        let local = self
+            .ctx
+            .log_store
+            .check_tx_completed(tx.seq)
+            .await
+            .map_err(error::storage_error)?;
+        let announcements: Vec<(u32, ShardConfig)> = watcher
+            .announcements()
+            .into_values()
+            .filter(|announcement| *announcement.peer_id != local_peer_id)
+            .filter_map(|announcement| {
+                let shard_config = self
+                    .ctx
+                    .file_location_cache
+                    .get_peer_config(&announcement.peer_id)
+                    .or_else(|| ShardConfig::try_from(announcement.shard_config).ok())?;
+                Some((announcement.timestamp, shard_config))
+            })
+            .collect();
+
+        Ok(Some(FileReplicas::new(
+            tx.seq,
+            local,
+            &announcements,
+            network_queried,
+        )))
+    }
+
     async fn get_shard_config(&self) -> RpcResult<ShardConfig> {
         debug!("zgs_getShardConfig");
         let shard_config = self.ctx.log_store.get_store().get_shard_config();
@@ -190,18 +598,89 @@ impl RpcServer for RpcServerImpl {
         &self,
         sector_index: u64,
         flow_root: Option<DataRoot>,
-    ) -> RpcResult<FlowProof> {
+    ) -> RpcResult<FlowProofJson> {
         let proof = self
             .ctx
             .log_store
             .get_proof_at_root(flow_root, sector_index, 1)
-            .await?;
+            .await
+            .map_err(error::storage_error)?;
         assert_eq!(proof.left_proof, proof.right_proof);
-        Ok(proof.right_proof)
+        Ok(proof.right_proof.into())
     }
 
     async fn get_flow_context(&self) -> RpcResult<(H256, u64)> {
-        Ok(self.ctx.log_store.get_context().await?)
+        self.ctx
+            .log_store
+            .get_context()
+            .await
+            .map_err(error::storage_error)
+    }
+
+    async fn get_flow_proof(
+        &self,
+        entry_index: u64,
+        count: u64,
+    ) -> RpcResult<FlowEntriesWithProof> {
+        debug!(%entry_index, %count, "zgs_getFlowProof");
+
+        if entry_index == 0 {
+            return Err(error::invalid_params(
+                "entry_index",
+                "the first flow entry is reserved",
+            ));
+        }
+
+        if count == 0 {
+            return Err(error::invalid_params("count", "count is zero"));
+        }
+
+        let max_count = self.ctx.config.chunks_per_segment;
+        if count > max_count as u64 {
+            return Err(error::exceeds_limit(RpcErrorCode::BatchTooLarge, max_count));
+        }
+
+        let (_, flow_length) = self
+            .ctx
+            .log_store
+            .get_context()
+            .await
+            .map_err(error::storage_error)?;
+        if entry_index + count > flow_length {
+            return Err(error::invalid_params(
+                "count",
+                format!("exceeds flow length {}", flow_length),
+            ));
+        }
+
+        // Entries out of the local shard are never stored.
+        let shard_config = self.ctx.log_store.get_store().get_shard_config();
+        let start_batch = entry_index / PORA_CHUNK_SIZE as u64;
+        let end_batch = (entry_index + count - 1) / PORA_CHUNK_SIZE as u64;
+        if (start_batch..=end_batch).any(|batch| !shard_config.in_range(batch)) {
+            return Err(error::flow_entries_out_of_shard(
+                entry_index,
+                count,
+                shard_config.shard_id,
+                shard_config.num_shard,
+            ));
+        }
+
+        let (entries, flow_root, flow_length) = self
+            .ctx
+            .log_store
+            .get_flow_entries_with_proof(entry_index, count)
+            .await
+            .map_err(error::storage_error)?
+            .ok_or_else(|| error::flow_entries_unavailable(entry_index, count))?;
+
+        Ok(FlowEntriesWithProof {
+            entry_index: entries.chunks.start_index,
+            data: entries.chunks.data,
+            proof: entries.proof.into(),
+            flow_root,
+            flow_length,
+        })
     }
 }
 
@@ -212,32 +691,44 @@ impl RpcServerImpl {
         file_size: usize,
     ) -> RpcResult<bool> {
         if let Some(tx) = maybe_tx {
-            if tx.size != file_size as u64 {
-                return Err(error::invalid_params(
-                    "file_size",
-                    "segment file size not matched with tx file size",
-                ));
+            // Transaction already finalized for the specified file data root.
+            if self
+                .ctx
+                .log_store
+                .check_tx_completed(tx.seq)
+                .await
+                .map_err(error::storage_error)?
+            {
+                return Err(error::file_already_finalized(tx.seq));
             }
 
-            // Transaction already finalized for the specified file data root.
-            if self.ctx.log_store.check_tx_completed(tx.seq).await? {
-                return Err(error::invalid_params(
-                    "root",
-                    "already uploaded and finalized",
-                ));
+            if self
+                .ctx
+                .log_store
+                .check_tx_pruned(tx.seq)
+                .await
+                .map_err(error::storage_error)?
+            {
+                return Err(error::file_pruned(tx.seq));
             }
 
-            if self.ctx.log_store.check_tx_pruned(tx.seq).await? {
-                return Err(error::invalid_params("root", "already pruned"));
+            if self
+                .ctx
+                .log_store
+                .check_tx_invalid(tx.seq)
+                .await
+                .map_err(error::storage_error)?
+            {
+                return Err(error::file_invalid(tx.seq));
             }
 
             Ok(false)
         } else {
             //Check whether file is small enough to cache in the system
             if file_size > self.ctx.config.max_cache_file_size {
-                return Err(error::invalid_params(
-                    "file_size",
-                    "caching of large file when tx is unavailable is not supported",
+                return Err(error::exceeds_limit(
+                    RpcErrorCode::FileTooLargeToCache,
+                    self.ctx.config.max_cache_file_size,
                 ));
             }
 
@@ -246,43 +737,177 @@ impl RpcServerImpl {
     }
 
     async fn get_file_info_by_tx(&self, tx: Transaction) -> RpcResult<FileInfo> {
-        let (finalized, pruned) = match self.ctx.log_store.get_store().get_tx_status(tx.seq)? {
-            Some(TxStatus::Finalized) => (true, false),
-            Some(TxStatus::Pruned) => (false, true),
-            None => (false, false),
-        };
-
-        let (uploaded_seg_num, is_cached) = match self
+        let status = self
             .ctx
-            .chunk_pool
-            .get_uploaded_seg_num(&tx.data_merkle_root)
-            .await
-        {
-            Some(v) => v,
-            _ => (
-                if finalized || pruned {
-                    let chunks_per_segment = self.ctx.config.chunks_per_segment;
-                    let (num_segments, _) = SegmentWithProof::split_file_into_segments(
-                        tx.size as usize,
-                        chunks_per_segment,
-                    )?;
-                    num_segments
-                } else {
-                    0
-                },
-                false,
-            ),
-        };
+            .log_store
+            .get_store()
+            .get_tx_status(tx.seq)
+            .map_err(error::storage_error)?;
+        let (finalized, shard_finalized, pruned) = tx_status_flags(status);
+        let submission = self
+            .ctx
+            .log_store
+            .get_store()
+            .get_submission_context(tx.seq)
+            .map_err(error::storage_error)?;
+        let first_seen_at = self
+            .ctx
+            .log_store
+            .get_store()
+            .get_data_root_first_seen(&tx.data_merkle_root)
+            .map_err(error::storage_error)?;
+
+        let (uploaded_seg_num, is_cached) = self.get_uploaded_seg_num(&tx, status).await?;
 
         Ok(FileInfo {
-            tx,
+            tx: tx.into(),
             finalized,
+            shard_finalized,
             is_cached,
             uploaded_seg_num,
             pruned,
+            submission_block_number: submission.map(|s| s.block_number),
+            submission_tx_hash: submission.map(|s| s.tx_hash),
+            first_seen_at,
         })
     }
 
+    /// Returns the number of segments of the file available on the node, i.e. cached in pool or
+    /// written into store, and whether the file is cached in pool.
+    async fn get_uploaded_seg_num(
+        &self,
+        tx: &Transaction,
+        status: Option<TxStatus>,
+    ) -> RpcResult<(usize, bool)> {
+        if let Some(v) = self
+            .ctx
+            .chunk_pool
+            .get_uploaded_seg_num(&tx.data_merkle_root)
+            .await
+        {
+            return Ok(v);
+        }
+
+        let (num_segments, _) = SegmentWithProof::split_file_into_segments(
+            tx.size as usize,
+            self.ctx.config.chunks_per_segment,
+        )?;
+        match status {
+            Some(TxStatus::Finalized | TxStatus::ShardFinalized | TxStatus::Pruned) => {
+                Ok((num_segments, false))
+            }
+            Some(TxStatus::Invalid) => Ok((0, false)),
+            // e.g. written into store before restart, or synced from peers
+            None => {
+                let mut available = vec![false; num_segments];
+                self.mark_stored_segments(tx, &mut available).await?;
+                Ok((available.iter().filter(|a| **a).count(), false))
+            }
+        }
+    }
+
+    /// Marks the segments of the tx whose chunks are all written into store, which looks up the
+    /// store for every segment not marked yet.
+    async fn mark_stored_segments(
+        &self,
+        tx: &Transaction,
+        available: &mut [bool],
+    ) -> RpcResult<()> {
+        let chunks_per_segment = self.ctx.config.chunks_per_segment;
+        let (num_segments, last_segment_size) =
+            SegmentWithProof::split_file_into_segments(tx.size as usize, chunks_per_segment)?;
+        for (index, seg_available) in available.iter_mut().enumerate().take(num_segments) {
+            if *seg_available {
+                continue;
+            }
+
+            let start_index = index * chunks_per_segment;
+            let end_index = if index == num_segments - 1 {
+                start_index + last_segment_size / CHUNK_SIZE
+            } else {
+                start_index + chunks_per_segment
+            };
+            *seg_available = self
+                .ctx
+                .log_store
+                .has_chunks_by_tx_and_index_range(tx.seq, start_index, end_index)
+                .await
+                .map_err(error::storage_error)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the tx of the file if submitted, and whether every segment is available on the
+    /// node, i.e. cached in pool or written into store. Segments not tracked in pool are looked
+    /// up in store, so this should not be called for every upload.
+    async fn get_segment_availability(
+        &self,
+        file: SessionFile,
+    ) -> RpcResult<(Option<Transaction>, Vec<bool>)> {
+        let chunks_per_segment = self.ctx.config.chunks_per_segment;
+        let (num_segments, _) =
+            SegmentWithProof::split_file_into_segments(file.size, chunks_per_segment)?;
+
+        let maybe_tx = self
+            .ctx
+            .log_store
+            .get_tx_by_data_root(&file.root)
+            .await
+            .map_err(error::storage_error)?;
+        if let Some(tx) = &maybe_tx {
+            if tx.size != file.size as u64 {
+                return Err(error::mismatch(
+                    RpcErrorCode::FileSizeMismatch,
+                    json!(tx.size),
+                    json!(file.size),
+                ));
+            }
+
+            if self
+                .ctx
+                .log_store
+                .check_tx_completed(tx.seq)
+                .await
+                .map_err(error::storage_error)?
+            {
+                return Ok((maybe_tx, vec![true; num_segments]));
+            }
+
+            if self
+                .ctx
+                .log_store
+                .check_tx_pruned(tx.seq)
+                .await
+                .map_err(error::storage_error)?
+            {
+                return Err(error::file_pruned(tx.seq));
+            }
+
+            if self
+                .ctx
+                .log_store
+                .check_tx_invalid(tx.seq)
+                .await
+                .map_err(error::storage_error)?
+            {
+                return Err(error::file_invalid(tx.seq));
+            }
+        }
+
+        let mut available = vec![false; num_segments];
+        if let Some(indices) = self.ctx.chunk_pool.get_uploaded_segments(&file.root).await {
+            for index in indices.into_iter().filter(|i| *i < num_segments) {
+                available[index] = true;
+            }
+        }
+
+        if let Some(tx) = &maybe_tx {
+            self.mark_stored_segments(tx, &mut available).await?;
+        }
+
+        Ok((maybe_tx, available))
+    }
+
     async fn put_segment(&self, segment: SegmentWithProof) -> RpcResult<()> {
         debug!(root = %segment.root, index = %segment.index, "putSegment");
 
@@ -290,7 +915,8 @@ impl RpcServerImpl {
             .ctx
             .log_store
             .get_tx_by_data_root(&segment.root)
-            .await?;
+            .await
+            .map_err(error::storage_error)?;
 
         self.put_segment_with_maybe_tx(segment, maybe_tx).await
     }
@@ -300,12 +926,47 @@ impl RpcServerImpl {
         segment: SegmentWithProof,
         maybe_tx: Option<Transaction>,
     ) -> RpcResult<()> {
-        self.ctx.chunk_pool.validate_segment_size(&segment.data)?;
+        self.ctx.check_writable()?;
+        self.ctx
+            .chunk_pool
+            .validate_segment_size(&segment.data)
+            .map_err(error::chunk_pool_error)?;
 
         if let Some(tx) = &maybe_tx {
             if tx.data_merkle_root != segment.root {
-                return Err(error::internal_error("data root and tx seq not match"));
+                return Err(error::mismatch(
+                    RpcErrorCode::RootMismatch,
+                    json!(tx.data_merkle_root),
+                    json!(segment.root),
+                ));
             }
+
+            // Validated before cached, even if other segments of the file are cached already.
+            if tx.size != segment.file_size as u64 {
+                return Err(error::mismatch(
+                    RpcErrorCode::FileSizeMismatch,
+                    json!(tx.size),
+                    json!(segment.file_size),
+                ));
+            }
+        }
+
+        // Retried uploads succeed without proof verification or writes.
+        let check = self
+            .ctx
+            .chunk_pool
+            .check_duplicate_segment(
+                &segment.root,
+                segment.index,
+                &segment.data,
+                self.ctx.config.chunks_per_segment,
+                maybe_tx.as_ref(),
+            )
+            .await
+            .map_err(error::chunk_pool_error)?;
+        if check == SegmentCheck::Duplicated {
+            debug!(root = %segment.root, index = %segment.index, "Duplicated segment uploaded");
+            return Ok(());
         }
 
         let mut need_cache = false;
@@ -322,7 +983,18 @@ impl RpcServerImpl {
             need_cache = self.check_need_cache(&maybe_tx, segment.file_size).await?;
         }
 
-        segment.validate(self.ctx.config.chunks_per_segment)?;
+        let segment = self
+            .ctx
+            .proof_verifier
+            .verify(segment, self.ctx.config.chunks_per_segment)
+            .await?;
+
+        // Rejected once proven, so that an invalid segment is reported as invalid proof.
+        if check == SegmentCheck::Conflicted {
+            return Err(error::chunk_pool_error(
+                MemoryChunkPool::conflicted_segment(&segment.root, segment.index),
+            ));
+        }
 
         let seg_info = SegmentInfo {
             root: segment.root,
@@ -330,10 +1002,15 @@ impl RpcServerImpl {
             seg_proof: segment.proof,
             seg_index: segment.index,
             chunks_per_segment: self.ctx.config.chunks_per_segment,
+            file_size: segment.file_size,
         };
 
         if need_cache {
-            self.ctx.chunk_pool.cache_chunks(seg_info).await?;
+            self.ctx
+                .chunk_pool
+                .cache_chunks(seg_info)
+                .await
+                .map_err(error::chunk_pool_error)?;
         } else {
             let file_id = FileID {
                 root: seg_info.root,
@@ -342,7 +1019,8 @@ impl RpcServerImpl {
             self.ctx
                 .chunk_pool
                 .write_chunks(seg_info, file_id, segment.file_size)
-                .await?;
+                .await
+                .map_err(error::chunk_pool_error)?;
         }
         Ok(())
     }
@@ -367,28 +1045,35 @@ impl RpcServerImpl {
             ));
         }
 
-        let segment = try_option!(
-            self.ctx
-                .log_store
-                .get_chunks_by_tx_and_index_range(tx_seq, start_index, end_index)
-                .await?
-        );
+        let segment = try_option!(self
+            .ctx
+            .log_store
+            .get_chunks_by_tx_and_index_range(tx_seq, start_index, end_index)
+            .await
+            .map_err(error::storage_error)?);
 
         Ok(Some(Segment(segment.data)))
     }
 
+    /// Reads the segment with proof, or the cached one if `version` is still the latest flow
+    /// version, which is read before the tx.
     async fn get_segment_with_proof_by_tx(
         &self,
         tx: Transaction,
         index: usize,
+        version: u64,
     ) -> RpcResult<Option<SegmentWithProof>> {
+        if let Some(segment) = self.ctx.segment_cache.get(tx.seq, index, version) {
+            return Ok(Some(segment));
+        }
+
         // validate index
         let chunks_per_segment = self.ctx.config.chunks_per_segment;
         let (num_segments, last_segment_size) =
             SegmentWithProof::split_file_into_segments(tx.size as usize, chunks_per_segment)?;
 
         if index >= num_segments {
-            return Err(error::invalid_params("index", "index out of bound"));
+            return Err(error::segment_out_of_range(index, num_segments));
         }
 
         // calculate chunk start and end index
@@ -400,22 +1085,29 @@ impl RpcServerImpl {
             start_index + chunks_per_segment
         };
 
-        let segment = try_option!(
-            self.ctx
-                .log_store
-                .get_chunks_with_proof_by_tx_and_index_range(tx.seq, start_index, end_index, None)
-                .await?
-        );
+        let segment = try_option!(self
+            .ctx
+            .log_store
+            .get_chunks_with_proof_by_tx_and_index_range(tx.seq, start_index, end_index, None)
+            .await
+            .map_err(error::storage_error)?);
 
-        let proof = tx.compute_segment_proof(&segment, chunks_per_segment)?;
+        let proof = tx
+            .compute_segment_proof(&segment, chunks_per_segment)
+            .map_err(error::storage_error)?;
 
-        Ok(Some(SegmentWithProof {
+        let segment = SegmentWithProof {
             root: tx.data_merkle_root,
             data: segment.chunks.data,
             index,
             proof,
             file_size: tx.size as usize,
-        }))
+        };
+        self.ctx
+            .segment_cache
+            .insert(tx.seq, version, segment.clone());
+
+        Ok(Some(segment))
     }
 }
 
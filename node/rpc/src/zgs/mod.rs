@@ -1,4 +1,5 @@
 mod api;
+mod binary;
 mod r#impl;
 
 pub use api::RpcClient;
@@ -0,0 +1,293 @@
+//! Canonical JSON representations of transactions and proofs, which are shared by RPC and
+//! tooling so that all endpoints expose the same wire format:
+//!
+//! - Field names are camelCase.
+//! - Hashes and byte arrays are `0x` prefixed lowercase hex strings.
+//! - `u64` values are JSON numbers, as all the other RPC types. Note, values above 2^53 are
+//!   exact on the wire but lose precision in javascript clients that parse them as `number`.
+//! - `U256` values are `0x` prefixed hex quantities, e.g. `"0x1"`.
+//!
+//! Note, the derived serde impls of the underlying types are kept for internal formats, e.g.
+//! exported log entries, and should not be exposed by RPC directly.
+
+use crate::{
+    ChunkArray, ChunkArrayWithProof, DataRoot, FlowProof, FlowRangeProof, Transaction,
+    TransactionBuilder, CHUNK_SIZE,
+};
+use anyhow::{bail, Error};
+use ethereum_types::{H256, U256};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionJson {
+    pub stream_ids: Vec<U256>,
+    /// In-place data.
+    #[serde(with = "hex_bytes")]
+    pub data: Vec<u8>,
+    pub data_merkle_root: DataRoot,
+    /// `(subtree_depth, subtree_root)`
+    pub merkle_nodes: Vec<(usize, DataRoot)>,
+    pub start_entry_index: u64,
+    pub size: u64,
+    pub seq: u64,
+}
+
+impl From<Transaction> for TransactionJson {
+    fn from(tx: Transaction) -> Self {
+        Self {
+            stream_ids: tx.stream_ids,
+            data: tx.data,
+            data_merkle_root: tx.data_merkle_root,
+            merkle_nodes: tx.merkle_nodes,
+            start_entry_index: tx.start_entry_index,
+            size: tx.size,
+            seq: tx.seq,
+        }
+    }
+}
+
+/// The tx is converted as is, since it is exposed by the node and never validated again.
+impl From<TransactionJson> for Transaction {
+    fn from(tx: TransactionJson) -> Self {
+        TransactionBuilder::new(tx.seq)
+            .stream_ids(tx.stream_ids)
+            .data(tx.data)
+            .data_merkle_root(tx.data_merkle_root)
+            .merkle_nodes(tx.merkle_nodes)
+            .start_entry_index(tx.start_entry_index)
+            .size(tx.size)
+            .build_unchecked()
+    }
+}
+
+/// Merkle proof of a flow entry, where `lemma` starts with the entry hash and ends with the root.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowProofJson {
+    pub lemma: Vec<H256>,
+    pub path: Vec<bool>,
+}
+
+impl From<FlowProof> for FlowProofJson {
+    fn from(proof: FlowProof) -> Self {
+        Self {
+            lemma: proof.lemma().to_vec(),
+            path: proof.path().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<FlowProofJson> for FlowProof {
+    type Error = Error;
+
+    fn try_from(proof: FlowProofJson) -> Result<Self, Self::Error> {
+        if proof.lemma.is_empty() && proof.path.is_empty() {
+            return Ok(FlowProof::new_empty());
+        }
+
+        FlowProof::new(proof.lemma, proof.path)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowRangeProofJson {
+    pub left_proof: FlowProofJson,
+    pub right_proof: FlowProofJson,
+}
+
+impl From<FlowRangeProof> for FlowRangeProofJson {
+    fn from(proof: FlowRangeProof) -> Self {
+        Self {
+            left_proof: proof.left_proof.into(),
+            right_proof: proof.right_proof.into(),
+        }
+    }
+}
+
+impl TryFrom<FlowRangeProofJson> for FlowRangeProof {
+    type Error = Error;
+
+    fn try_from(proof: FlowRangeProofJson) -> Result<Self, Self::Error> {
+        Ok(FlowRangeProof {
+            left_proof: proof.left_proof.try_into()?,
+            right_proof: proof.right_proof.try_into()?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkArrayJson {
+    /// The length is exactly a multiple of `CHUNK_SIZE`.
+    #[serde(with = "hex_bytes")]
+    pub data: Vec<u8>,
+    pub start_index: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkArrayWithProofJson {
+    pub chunks: ChunkArrayJson,
+    pub proof: FlowRangeProofJson,
+}
+
+impl From<ChunkArrayWithProof> for ChunkArrayWithProofJson {
+    fn from(value: ChunkArrayWithProof) -> Self {
+        Self {
+            chunks: ChunkArrayJson {
+                data: value.chunks.data,
+                start_index: value.chunks.start_index,
+            },
+            proof: value.proof.into(),
+        }
+    }
+}
+
+impl TryFrom<ChunkArrayWithProofJson> for ChunkArrayWithProof {
+    type Error = Error;
+
+    fn try_from(value: ChunkArrayWithProofJson) -> Result<Self, Self::Error> {
+        if value.chunks.data.len() % CHUNK_SIZE != 0 {
+            bail!(
+                "invalid chunks data length {}, expected a multiple of {}",
+                value.chunks.data.len(),
+                CHUNK_SIZE
+            );
+        }
+
+        Ok(ChunkArrayWithProof {
+            chunks: ChunkArray {
+                data: value.chunks.data,
+                start_index: value.chunks.start_index,
+            },
+            proof: value.proof.try_into()?,
+        })
+    }
+}
+
+/// Serializes bytes as a `0x` prefixed lowercase hex string.
+pub mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&format!("0x{}", hex::encode(v)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(d)?;
+        let hex = s
+            .strip_prefix("0x")
+            .ok_or_else(|| D::Error::custom("hex string without 0x prefix"))?;
+        hex::decode(hex).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::DeserializeOwned;
+    use std::fmt::Debug;
+
+    /// Checks that `value` serializes to the golden file exactly, and vice versa.
+    fn assert_golden<T>(value: &T, golden: &str)
+    where
+        T: Serialize + DeserializeOwned + PartialEq + Debug,
+    {
+        let expected: serde_json::Value = serde_json::from_str(golden).unwrap();
+        assert_eq!(serde_json::to_value(value).unwrap(), expected);
+        assert_eq!(&serde_json::from_str::<T>(golden).unwrap(), value);
+    }
+
+    fn hash(v: u64) -> H256 {
+        H256::from_low_u64_be(v)
+    }
+
+    fn flow_proof(item: u64) -> FlowProof {
+        FlowProof::new(vec![hash(item), hash(10), hash(20)], vec![true]).unwrap()
+    }
+
+    #[test]
+    fn test_transaction_golden() {
+        // inconsistent in itself, which is kept as is on the wire, and the seq is a number in
+        // full, though javascript clients would round it
+        let tx = TransactionBuilder::new(u64::MAX)
+            .stream_ids(vec![U256::from(1), U256::from(255)])
+            .data(vec![0xab, 0xcd])
+            .data_merkle_root(hash(1))
+            .merkle_nodes(vec![(3, hash(2)), (1, hash(3))])
+            .start_entry_index(1024)
+            .size(1_000_000)
+            .build_unchecked();
+        let json = TransactionJson::from(tx.clone());
+        assert_golden(&json, include_str!("../tests/golden/transaction.json"));
+        assert_eq!(Transaction::from(json), tx);
+    }
+
+    #[test]
+    fn test_flow_proof_golden() {
+        let proof = flow_proof(1);
+        let json = FlowProofJson::from(proof.clone());
+        assert_golden(&json, include_str!("../tests/golden/flow_proof.json"));
+        assert_eq!(FlowProof::try_from(json).unwrap(), proof);
+
+        // empty proof, e.g. of an empty flow
+        let json = FlowProofJson::from(FlowProof::new_empty());
+        assert_eq!(
+            serde_json::to_string(&json).unwrap(),
+            r#"{"lemma":[],"path":[]}"#
+        );
+        assert_eq!(FlowProof::try_from(json).unwrap(), FlowProof::new_empty());
+    }
+
+    #[test]
+    fn test_chunk_array_with_proof_golden() {
+        let value = ChunkArrayWithProof {
+            chunks: ChunkArray {
+                data: vec![0x11; CHUNK_SIZE * 2],
+                start_index: 4,
+            },
+            proof: FlowRangeProof {
+                left_proof: flow_proof(4),
+                right_proof: flow_proof(5),
+            },
+        };
+        let json = ChunkArrayWithProofJson::from(value.clone());
+        assert_golden(
+            &json,
+            include_str!("../tests/golden/chunk_array_with_proof.json"),
+        );
+        assert_eq!(ChunkArrayWithProof::try_from(json).unwrap(), value);
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        // hex without 0x prefix
+        assert!(
+            serde_json::from_str::<ChunkArrayJson>(r#"{"data":"abcd","startIndex":0}"#).is_err()
+        );
+        // u64 as string
+        assert!(
+            serde_json::from_str::<ChunkArrayJson>(r#"{"data":"0xabcd","startIndex":"0"}"#)
+                .is_err()
+        );
+
+        // lemma and path mismatch
+        let proof = FlowProofJson {
+            lemma: vec![hash(1)],
+            path: vec![true],
+        };
+        assert!(FlowProof::try_from(proof).is_err());
+
+        // data not aligned with chunks
+        let value = ChunkArrayWithProofJson {
+            chunks: ChunkArrayJson {
+                data: vec![0; CHUNK_SIZE + 1],
+                start_index: 0,
+            },
+            proof: FlowRangeProof::new_empty().into(),
+        };
+        assert!(ChunkArrayWithProof::try_from(value).is_err());
+    }
+}
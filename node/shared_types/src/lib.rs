@@ -1,8 +1,14 @@
+pub mod json;
 mod proof;
+mod tx_builder;
+
+pub use proof::validate_flow_entries;
+pub use tx_builder::TransactionBuilder;
 
 use anyhow::{anyhow, bail, Error};
 use append_merkle::{
     AppendMerkleTree, Proof as RawProof, RangeProof as RawRangeProof, Sha3Algorithm,
+    VersionedRangeProof,
 };
 use ethereum_types::{Address, H256, U256};
 use merkle_light::merkle::MerkleTree;
@@ -32,6 +38,7 @@ pub type DataRoot = H256;
 
 pub type FlowProof = RawProof<H256>;
 pub type FlowRangeProof = RawRangeProof<H256>;
+pub type FlowVersionedRangeProof = VersionedRangeProof<H256>;
 pub type Merkle = AppendMerkleTree<H256, Sha3Algorithm>;
 
 // Each chunk is 32 bytes.
@@ -65,6 +72,25 @@ pub fn compute_padded_chunk_size(size_bytes: usize) -> (usize, usize) {
     (padded_chunks, chunks_next_pow2)
 }
 
+/// Splits the padded chunks of a file of `data_size` bytes into the sizes of the subtrees in the
+/// flow, in descending order.
+pub fn split_nodes(data_size: usize) -> Vec<usize> {
+    let (mut padded_chunks, chunks_next_pow2) = compute_padded_chunk_size(data_size);
+    let mut next_chunk_size = chunks_next_pow2;
+
+    let mut nodes = vec![];
+    while padded_chunks > 0 {
+        if padded_chunks >= next_chunk_size {
+            padded_chunks -= next_chunk_size;
+            nodes.push(next_chunk_size);
+        }
+
+        next_chunk_size >>= 1;
+    }
+
+    nodes
+}
+
 pub fn compute_segment_size(chunks: usize, chunks_per_segment: usize) -> (usize, usize) {
     if chunks % chunks_per_segment == 0 {
         (chunks / chunks_per_segment, chunks_per_segment)
@@ -154,6 +180,35 @@ pub struct ChunkArrayWithProof {
     pub proof: FlowRangeProof,
 }
 
+/// `ChunkArrayWithProof` in the stable encoding, in which the proof is versioned, so that peers
+/// could still decode chunks responses once the layout of proofs changed.
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct VersionedChunkArrayWithProof {
+    pub chunks: ChunkArray,
+    pub proof: FlowVersionedRangeProof,
+}
+
+impl From<ChunkArrayWithProof> for VersionedChunkArrayWithProof {
+    fn from(value: ChunkArrayWithProof) -> Self {
+        Self {
+            chunks: value.chunks,
+            proof: value.proof.into(),
+        }
+    }
+}
+
+impl TryFrom<VersionedChunkArrayWithProof> for ChunkArrayWithProof {
+    type Error = Error;
+
+    /// Fails if the proof is of an unknown version.
+    fn try_from(value: VersionedChunkArrayWithProof) -> Result<Self, Self::Error> {
+        Ok(Self {
+            chunks: value.chunks,
+            proof: value.proof.into_range_proof()?,
+        })
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, DeriveEncode, DeriveDecode)]
 pub struct ChunkArray {
     // The length is exactly a multiple of `CHUNK_SIZE`
@@ -283,7 +338,9 @@ impl FileProof {
         Ok(true)
     }
 
-    fn position(&self, total_chunk_count: usize) -> anyhow::Result<usize> {
+    /// Returns the leaf position of the proof path in a tree of `total_chunk_count` leaves, or
+    /// an error if the path length does not match the tree depth at the position.
+    pub fn position(&self, total_chunk_count: usize) -> anyhow::Result<usize> {
         let mut left_chunk_count = total_chunk_count;
         let mut proof_position = 0;
         // TODO: After the first `is_left == true`, the tree depth is fixed.
@@ -331,6 +388,24 @@ impl TryFrom<&FileProof> for RawFileProof<[u8; 32]> {
     }
 }
 
+impl From<RawFileProof<[u8; 32]>> for FileProof {
+    fn from(value: RawFileProof<[u8; 32]>) -> Self {
+        FileProof::new(
+            value.lemma().iter().map(|e| H256(*e)).collect(),
+            value.path().to_vec(),
+        )
+    }
+}
+
+/// Mixes the bits of `x` by SplitMix64, e.g. to hash an integer or to draw the `x`-th number of
+/// a pseudo random sequence. Not cryptographically secure.
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
 pub fn timestamp_now() -> u32 {
     let timestamp = chrono::Utc::now().timestamp();
     u32::try_from(timestamp).expect("The year is between 1970 and 2106")
@@ -396,7 +471,41 @@ pub struct ProtocolVersion {
     pub build: u8,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl ProtocolVersion {
+    /// Peers of different major or minor versions could not communicate, e.g. pubsub messages
+    /// refactored, but the build version only includes compatible changes.
+    pub fn is_compatible(&self, other: &ProtocolVersion) -> bool {
+        self.major == other.major && self.minor == other.minor
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.build)
+    }
+}
+
+impl std::str::FromStr for ProtocolVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split('.')
+            .map(|part| part.parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid protocol version {}: {:?}", s, e))?;
+        match parts[..] {
+            [major, minor, build] => Ok(ProtocolVersion {
+                major,
+                minor,
+                build,
+            }),
+            _ => Err(format!("invalid protocol version {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum TxSeqOrRoot {
     TxSeq(u64),
@@ -455,4 +564,16 @@ mod tests {
             TxSeqOrRoot::Root(v) if v == hash,
         ));
     }
+
+    #[test]
+    fn test_protocol_version() {
+        let v4 = ProtocolVersion::from_str("0.4.0").unwrap();
+        assert_eq!(v4.to_string(), "0.4.0");
+        assert!(v4.is_compatible(&ProtocolVersion::from_str("0.4.1").unwrap()));
+        assert!(!v4.is_compatible(&ProtocolVersion::from_str("0.3.0").unwrap()));
+        assert!(!v4.is_compatible(&ProtocolVersion::from_str("1.4.0").unwrap()));
+
+        assert!(ProtocolVersion::from_str("0.4").is_err());
+        assert!(ProtocolVersion::from_str("0.4.x").is_err());
+    }
 }
@@ -1,4 +1,7 @@
-use crate::{compute_segment_merkle_root, ChunkArrayWithProof, FileProof, Transaction, CHUNK_SIZE};
+use crate::{
+    compute_segment_merkle_root, ChunkArrayWithProof, DataRoot, FileProof, FlowRangeProof,
+    Transaction, CHUNK_SIZE,
+};
 use anyhow::{bail, Result};
 use append_merkle::{Algorithm, Sha3Algorithm};
 use ethereum_types::H256;
@@ -218,3 +221,60 @@ impl Transaction {
         Some(root)
     }
 }
+
+/// Validates that the flow entries `data` starting from flow position `entry_index` are proved
+/// by `proof` under the flow `root`, e.g. the result of `zgs_getFlowProof`.
+///
+/// Note, the flow entry is independent of which transaction owns it.
+pub fn validate_flow_entries(
+    root: &DataRoot,
+    entry_index: u64,
+    data: &[u8],
+    proof: &FlowRangeProof,
+) -> Result<()> {
+    if data.is_empty() || data.len() % CHUNK_SIZE != 0 {
+        bail!("invalid data length: {}", data.len());
+    }
+
+    if proof.root() != *root {
+        bail!(
+            "flow root mismatch: proof_root={:?} provided={:?}",
+            proof.root(),
+            root
+        );
+    }
+
+    let leaves: Vec<H256> = data
+        .chunks_exact(CHUNK_SIZE)
+        .map(Sha3Algorithm::leaf)
+        .collect();
+
+    proof.validate::<Sha3Algorithm>(&leaves, entry_index as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Merkle;
+    use append_merkle::MerkleTreeRead;
+
+    #[test]
+    fn test_validate_flow_entries() {
+        let data: Vec<u8> = (0..8 * CHUNK_SIZE)
+            .map(|i| (i / CHUNK_SIZE) as u8)
+            .collect();
+        let mut leaves = vec![H256::zero()];
+        leaves.extend(data.chunks_exact(CHUNK_SIZE).map(Sha3Algorithm::leaf));
+        let merkle = Merkle::new(leaves, 0, None);
+        let root = merkle.root();
+
+        let proof = merkle.gen_range_proof(3, 6).unwrap();
+        let entries = &data[2 * CHUNK_SIZE..5 * CHUNK_SIZE];
+        validate_flow_entries(&root, 3, entries, &proof).unwrap();
+
+        // wrong position, data or root
+        assert!(validate_flow_entries(&root, 2, entries, &proof).is_err());
+        assert!(validate_flow_entries(&root, 3, &data[..3 * CHUNK_SIZE], &proof).is_err());
+        assert!(validate_flow_entries(&H256::zero(), 3, entries, &proof).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use crate::{split_nodes, DataRoot, Transaction};
+use anyhow::{bail, Result};
+use ethereum_types::U256;
+use merkle_light::merkle::log2_pow2;
+
+/// Builds a [`Transaction`] that is consistent in itself, which is checked by [`Self::build`]:
+///
+/// * The size is not zero, and the subtree depths of `merkle_nodes` are those split from the
+///   size, as the flow contract does.
+/// * The data root is not zero.
+/// * The inline data, if any, is of the size.
+/// * The start entry index is aligned with the first subtree.
+///
+/// The seq and the flow position relative to the previous tx are left to the store.
+///
+/// Txs from authoritative sources, e.g. the submissions accepted by the flow contract, are
+/// built by [`Self::build_unchecked`] instead, since rejecting them only leaves a gap in the
+/// log. Besides, non-test code never constructs a `Transaction` by literal.
+#[derive(Clone, Debug, Default)]
+pub struct TransactionBuilder {
+    stream_ids: Vec<U256>,
+    data: Vec<u8>,
+    data_merkle_root: DataRoot,
+    merkle_nodes: Vec<(usize, DataRoot)>,
+    start_entry_index: u64,
+    size: u64,
+    seq: u64,
+}
+
+impl TransactionBuilder {
+    pub fn new(seq: u64) -> Self {
+        Self {
+            seq,
+            ..Default::default()
+        }
+    }
+
+    pub fn seq(mut self, seq: u64) -> Self {
+        self.seq = seq;
+        self
+    }
+
+    pub fn stream_ids(mut self, stream_ids: Vec<U256>) -> Self {
+        self.stream_ids = stream_ids;
+        self
+    }
+
+    /// In-place data of the tx, whose length should be the size.
+    pub fn data(mut self, data: Vec<u8>) -> Self {
+        self.data = data;
+        self
+    }
+
+    pub fn data_merkle_root(mut self, data_merkle_root: DataRoot) -> Self {
+        self.data_merkle_root = data_merkle_root;
+        self
+    }
+
+    /// `(subtree_depth, subtree_root)` in the order of the flow.
+    pub fn merkle_nodes(mut self, merkle_nodes: Vec<(usize, DataRoot)>) -> Self {
+        self.merkle_nodes = merkle_nodes;
+        self
+    }
+
+    pub fn start_entry_index(mut self, start_entry_index: u64) -> Self {
+        self.start_entry_index = start_entry_index;
+        self
+    }
+
+    /// Size of the file in bytes.
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn build(self) -> Result<Transaction> {
+        if self.size == 0 {
+            bail!("empty tx: tx_seq={}", self.seq);
+        }
+        if self.data_merkle_root.is_zero() {
+            bail!("tx without data root: tx_seq={}", self.seq);
+        }
+        if !self.data.is_empty() && self.data.len() as u64 != self.size {
+            bail!(
+                "inline data inconsistent with tx size: tx_seq={} size={} data_len={}",
+                self.seq,
+                self.size,
+                self.data.len()
+            );
+        }
+
+        let depths: Vec<usize> = self.merkle_nodes.iter().map(|(depth, _)| *depth).collect();
+        let expected_depths: Vec<usize> = split_nodes(self.size as usize)
+            .into_iter()
+            .map(|size| log2_pow2(size) + 1)
+            .collect();
+        if depths != expected_depths {
+            bail!(
+                "tx size inconsistent with merkle nodes: tx_seq={} size={} depths={:?} expected={:?}",
+                self.seq,
+                self.size,
+                depths,
+                expected_depths
+            );
+        }
+        let first_subtree_size = Transaction::num_entries_of_node(depths[0]) as u64;
+        if self.start_entry_index % first_subtree_size != 0 {
+            bail!(
+                "tx start not aligned with the first subtree: tx_seq={} start_entry_index={} subtree_size={}",
+                self.seq,
+                self.start_entry_index,
+                first_subtree_size
+            );
+        }
+
+        Ok(self.build_unchecked())
+    }
+
+    /// Builds the tx as is, which is only for the txs already accepted elsewhere, e.g. by the
+    /// flow contract, and must be kept in the log even if inconsistent.
+    pub fn build_unchecked(self) -> Transaction {
+        Transaction {
+            stream_ids: self.stream_ids,
+            data: self.data,
+            data_merkle_root: self.data_merkle_root,
+            merkle_nodes: self.merkle_nodes,
+            start_entry_index: self.start_entry_index,
+            size: self.size,
+            seq: self.seq,
+        }
+    }
+}
+
+impl From<Transaction> for TransactionBuilder {
+    /// Starts from the fields of `tx`, e.g. to build the same tx at another position.
+    fn from(tx: Transaction) -> Self {
+        Self {
+            stream_ids: tx.stream_ids,
+            data: tx.data,
+            data_merkle_root: tx.data_merkle_root,
+            merkle_nodes: tx.merkle_nodes,
+            start_entry_index: tx.start_entry_index,
+            size: tx.size,
+            seq: tx.seq,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bytes_to_chunks, CHUNK_SIZE};
+    use ethereum_types::H256;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use ssz::{Decode, Encode};
+
+    const NUM_CASES: usize = 1000;
+
+    /// Generates a random valid tx placed at the next position of the flow aligned with its
+    /// first subtree.
+    fn random_tx(rng: &mut StdRng, seq: u64, flow_len: u64) -> Result<Transaction> {
+        // Both small files and files of many subtrees.
+        let size = match rng.gen_range(0..3) {
+            0 => rng.gen_range(1..=CHUNK_SIZE as u64),
+            1 => rng.gen_range(1..=64 * CHUNK_SIZE as u64),
+            _ => rng.gen_range(1..=(1 << 30)),
+        };
+        let merkle_nodes: Vec<(usize, DataRoot)> = split_nodes(size as usize)
+            .into_iter()
+            .map(|size| (log2_pow2(size) + 1, H256::random()))
+            .collect();
+        let first_subtree_size = Transaction::num_entries_of_node(merkle_nodes[0].0) as u64;
+        let data = if size <= 256 && rng.gen() {
+            (0..size).map(|_| rng.gen()).collect()
+        } else {
+            vec![]
+        };
+        TransactionBuilder::new(seq)
+            .stream_ids((0..rng.gen_range(0..3)).map(U256::from).collect())
+            .data(data)
+            .data_merkle_root(H256::random())
+            .merkle_nodes(merkle_nodes)
+            .start_entry_index(
+                (flow_len + first_subtree_size - 1) / first_subtree_size * first_subtree_size,
+            )
+            .size(size)
+            .build()
+    }
+
+    /// Checks the invariants ensured by the builder.
+    fn assert_invariants(tx: &Transaction) {
+        assert!(tx.size > 0);
+        assert!(!tx.data_merkle_root.is_zero());
+        assert!(tx.data.is_empty() || tx.data.len() as u64 == tx.size);
+        let num_entries = tx.num_entries() as u64;
+        let num_chunks = bytes_to_chunks(tx.size as usize) as u64;
+        assert!(num_entries >= num_chunks);
+        // the padding is less than the smallest subtree
+        let last_depth = tx.merkle_nodes.last().unwrap().0;
+        assert!(num_entries - num_chunks < Transaction::num_entries_of_node(last_depth) as u64);
+        // subtrees are in descending order
+        assert!(tx.merkle_nodes.windows(2).all(|w| w[0].0 > w[1].0));
+        assert_eq!(
+            tx.start_entry_index % Transaction::num_entries_of_node(tx.merkle_nodes[0].0) as u64,
+            0
+        );
+    }
+
+    #[test]
+    fn test_random_txs_ssz_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut flow_len = 1;
+        for seq in 0..NUM_CASES as u64 {
+            let tx = random_tx(&mut rng, seq, flow_len).unwrap();
+            assert_invariants(&tx);
+
+            let decoded = Transaction::from_ssz_bytes(&tx.as_ssz_bytes()).unwrap();
+            assert_invariants(&decoded);
+            assert_eq!(decoded, tx);
+            assert_eq!(decoded.hash(), tx.hash());
+            flow_len = tx.start_entry_index + tx.num_entries() as u64;
+        }
+    }
+
+    #[test]
+    fn test_rejected_txs() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for seq in 0..NUM_CASES as u64 {
+            let tx = random_tx(&mut rng, seq, 0).unwrap();
+            let builder = TransactionBuilder::new(seq)
+                .stream_ids(tx.stream_ids.clone())
+                .data_merkle_root(tx.data_merkle_root)
+                .merkle_nodes(tx.merkle_nodes.clone())
+                .start_entry_index(tx.start_entry_index)
+                .size(tx.size);
+            assert_eq!(
+                builder.clone().build().unwrap(),
+                TransactionBuilder::from(tx.clone())
+                    .data(vec![])
+                    .build_unchecked()
+            );
+            assert_eq!(TransactionBuilder::from(tx.clone()).build().unwrap(), tx);
+
+            // size of other subtrees
+            let other_size = tx.size + tx.num_entries() as u64 * CHUNK_SIZE as u64;
+            assert!(builder.clone().size(other_size).build().is_err());
+            assert!(builder.clone().size(0).build().is_err());
+            assert!(builder.clone().merkle_nodes(vec![]).build().is_err());
+            let mut merkle_nodes = tx.merkle_nodes.clone();
+            merkle_nodes.push((1, H256::random()));
+            assert!(builder.clone().merkle_nodes(merkle_nodes).build().is_err());
+            assert!(builder
+                .clone()
+                .data_merkle_root(DataRoot::zero())
+                .build()
+                .is_err());
+            assert!(builder
+                .clone()
+                .data(vec![0; tx.size as usize + 1])
+                .build()
+                .is_err());
+            if tx.merkle_nodes[0].0 > 1 {
+                assert!(builder.start_entry_index(1).build().is_err());
+            }
+        }
+    }
+}
@@ -9,6 +9,52 @@ pub fn cli_app() -> Command {
             arg!(--"blockchain-rpc-endpoint" [URL] "Sets blockchain RPC endpoint (Default: http://127.0.0.1:8545)")
         )
         .arg(arg!(--"db-max-num-chunks" [NUM] "Sets the max number of chunks to store in db (Default: None)"))
+        .arg(arg!(--"export-log-entries" [FILE] "Exports the synced log entries to replay via `log_sync_replay_file`, and exits without starting the node"))
+        .arg(arg!(--"force-reinit" "Wipes and reinitializes the store if initialized for another network, rather than refusing to start"))
+        .subcommand(
+            Command::new("check")
+                .about("Checks the integrity of db, and exits with code 1 if any check failed, which requires the node to be stopped")
+                .arg(arg!(--repair "Repairs the inconsistent txs and flow merkle tree if possible")),
+        )
+        .subcommand(
+            Command::new("db")
+                .about("Inspects or migrates the db in JSON, which requires the node to be stopped")
+                .subcommand_required(true)
+                .subcommand(Command::new("stats").about("Prints the number of keys and size in bytes of each column"))
+                .subcommand(
+                    Command::new("get-tx")
+                        .about("Prints the tx of seq along with its status")
+                        .arg(arg!(<seq> "Seq of the tx")),
+                )
+                .subcommand(
+                    Command::new("get-root")
+                        .about("Prints the seqs and status of all txs of the data root")
+                        .arg(arg!(<root> "Data root in hex")),
+                )
+                .subcommand(Command::new("progress").about("Prints the log sync progress and the flow context"))
+                .subcommand(
+                    Command::new("export-tx-range")
+                        .about("Exports the txs of seq in [from, to) along with their status in JSON lines")
+                        .arg(arg!(<from> "First seq to export"))
+                        .arg(arg!(<to> "Seq to export until, exclusive"))
+                        .arg(arg!(<file> "File to export into")),
+                )
+                .subcommand(
+                    Command::new("export-merkle-state")
+                        .about("Exports all the nodes of the flow merkle tree in the format of `admin_exportMerkleState`")
+                        .arg(arg!(<file> "File to export into")),
+                )
+                .subcommand(
+                    Command::new("migrate-layout")
+                        .about("Copies the db into new dbs of the layout other than `db_unified` configured, which are used once `db_unified` changed"),
+                )
+                .subcommand(
+                    Command::new("diff-merkle-state")
+                        .about("Prints the first differing node of two exported merkle states, which requires no db")
+                        .arg(arg!(<left> "File of the exported merkle state"))
+                        .arg(arg!(<right> "File of the exported merkle state to compare with")),
+                ),
+        )
         .allow_external_subcommands(true)
         .version(zgs_version::VERSION)
 }
@@ -1,22 +1,33 @@
 use super::{Client, RuntimeContext};
+use crate::config::reload::ConfigWatcher;
+use crate::log::LogFilterHandle;
 use chunk_pool::{Config as ChunkPoolConfig, MemoryChunkPool};
 use file_location_cache::FileLocationCache;
-use log_entry_sync::{LogSyncConfig, LogSyncEvent, LogSyncManager};
+use log_entry_sync::{LogSyncConfig, LogSyncEvent, LogSyncManager, LogSyncMonitor};
 use miner::{MineService, MinerConfig, MinerMessage, ShardConfig};
 use network::{
-    self, new_network_channel, Keypair, NetworkConfig, NetworkGlobals, NetworkReceiver,
+    self, new_network_channel, DialDemand, Keypair, NetworkConfig, NetworkGlobals, NetworkReceiver,
     NetworkSender, RequestId, Service as LibP2PService,
 };
 use pruner::{Pruner, PrunerConfig, PrunerMessage};
-use router::RouterService;
-use rpc::RPCConfig;
+use router::{KnownPeers, RouterService};
+use rpc::{RPCConfig, StartupPhases};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use storage::error::StoreError;
+use storage::log_store::audit::FinalizedAudit;
 use storage::log_store::log_manager::LogConfig;
-use storage::log_store::Store;
-use storage::{LogManager, StorageConfig};
-use sync::{SyncSender, SyncService};
+use storage::log_store::write_stall::WriteStallMonitor;
+use storage::log_store::{LogStoreRead, Store};
+use storage::{LogManager, StorageConfig, StoreHandles};
+use sync::{SyncRequest, SyncResponse, SyncSender, SyncService};
+use task_executor::ShutdownReason;
 use tokio::sync::{broadcast, mpsc, oneshot};
 
+/// Interval to check whether any peer connected during the initial peer discovery.
+const PEER_DISCOVERY_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 macro_rules! require {
     ($component:expr, $self:ident, $e:ident) => {
         $self
@@ -46,6 +57,7 @@ struct MinerComponents {
 struct LogSyncComponents {
     send: broadcast::Sender<LogSyncEvent>,
     catch_up_end_recv: Option<oneshot::Receiver<()>>,
+    monitor: LogSyncMonitor,
 }
 
 struct PrunerComponents {
@@ -75,6 +87,16 @@ pub struct ClientBuilder {
     log_sync: Option<LogSyncComponents>,
     pruner: Option<PrunerComponents>,
     chunk_pool: Option<ChunkPoolComponents>,
+    known_peers: Option<KnownPeers>,
+    config_watcher: Option<Arc<ConfigWatcher>>,
+    log_filter: Option<LogFilterHandle>,
+    /// Whether the store is opened read-only, in which case no service writes into it.
+    read_only: bool,
+    finalized_audit: FinalizedAudit,
+    /// Timing breakdown of startup, which is shared with RPC.
+    startup_phases: Arc<StartupPhases>,
+    /// When the network service started, which the initial peer discovery is timed from.
+    network_started_at: Option<Instant>,
 }
 
 impl ClientBuilder {
@@ -84,6 +106,18 @@ impl ClientBuilder {
         self
     }
 
+    /// Specifies the config watcher to reload dynamic parameters of services at runtime.
+    pub fn with_config_watcher(mut self, config_watcher: Arc<ConfigWatcher>) -> Self {
+        self.config_watcher = Some(config_watcher);
+        self
+    }
+
+    /// Specifies the handle to swap the log filter at runtime.
+    pub fn with_log_filter(mut self, log_filter: LogFilterHandle) -> Self {
+        self.log_filter = Some(log_filter);
+        self
+    }
+
     /// Initializes in-memory storage.
     pub fn with_memory_store(mut self) -> Result<Self, String> {
         // TODO(zz): Set config.
@@ -104,20 +138,97 @@ impl ClientBuilder {
         Ok(self)
     }
 
-    /// Initializes RocksDB storage.
-    pub fn with_rocksdb_store(mut self, config: &StorageConfig) -> Result<Self, String> {
-        let store = Arc::new(
-            LogManager::rocksdb(
-                config.log_config.clone(),
-                config.db_dir.join("flow_db"),
-                config.db_dir.join("data_db"),
-            )
-            .map_err(|e| format!("Unable to start RocksDB store: {:?}", e))?,
-        );
+    /// Initializes storage of the configured db engine.
+    pub fn with_store(mut self, config: &StorageConfig) -> Result<Self, String> {
+        let lazy_tree_load = config.lazy_tree_load && self.runtime_context.is_some();
+        let open_store = |reinit: bool| {
+            let started_at = Instant::now();
+            let db = if config.read_only {
+                StoreHandles::open_read_only(config.db_engine, config.db_layout, &config.db_dir)
+            } else {
+                StoreHandles::open(config.db_engine, config.db_layout, &config.db_dir)
+            };
+            db.and_then(|db| {
+                if reinit {
+                    db.clear()?;
+                }
+                self.startup_phases.record_since("db_open", started_at);
+                if lazy_tree_load {
+                    LogManager::with_dbs_deferred(db, config.log_config.clone())
+                } else {
+                    LogManager::with_dbs(db, config.log_config.clone())
+                }
+            })
+            .map_err(|e| format!("Unable to start {:?} store: {:?}", config.db_engine, e))
+        };
+        let mut store = open_store(false)?;
+
+        // A read-only store is verified against blockchain by log sync instead.
+        if let Some(network_id) = config.network_id.filter(|_| !config.read_only) {
+            if let Err(e) = store.check_network_id(&network_id) {
+                let mismatched =
+                    matches!(StoreError::of(&e), Some(StoreError::NetworkMismatch { .. }));
+                if !mismatched {
+                    return Err(format!("Unable to check store network: {:?}", e));
+                }
+                if !config.force_reinit {
+                    return Err(format!(
+                        "Refused to start, {}. Restart with --force-reinit to wipe and \
+                         reinitialize the store",
+                        e
+                    ));
+                }
+                warn!(%e, "Wiping the store to reinitialize it for the configured network");
+                drop(store);
+                store = open_store(true)?;
+                store
+                    .check_network_id(&network_id)
+                    .map_err(|e| format!("Unable to check store network: {:?}", e))?;
+            }
+        }
+
+        let store = Arc::new(store);
+        let store_phases = store.startup_phases();
+        for (name, duration) in &store_phases {
+            self.startup_phases.record(name, *duration);
+        }
+
+        if !store.is_flow_tree_ready() {
+            let executor = require!("store", self, runtime_context).executor.clone();
+            let mut shutdown_sender = executor.shutdown_sender();
+            let startup_phases = self.startup_phases.clone();
+            let loading_store = store.clone();
+            info!("Loading flow tree in background, writes are queued until loaded");
+            executor.spawn_blocking(
+                move || match loading_store.load_flow_tree() {
+                    Ok(()) => {
+                        for (name, duration) in loading_store
+                            .startup_phases()
+                            .into_iter()
+                            .skip(store_phases.len())
+                        {
+                            startup_phases.record(name, duration);
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to load flow tree: {:?}", e);
+                        let _ = shutdown_sender
+                            .try_send(ShutdownReason::Failure("flow tree load failure"));
+                    }
+                },
+                "flow_tree_load",
+            );
+        }
 
         self.store = Some(store.clone());
+        self.read_only = config.read_only;
+        self.finalized_audit = config.finalized_audit;
 
         if let Some(ctx) = self.runtime_context.as_ref() {
+            if !config.read_only {
+                WriteStallMonitor::new(store.handles(), config.write_stall.clone())
+                    .spawn(&ctx.executor);
+            }
             self.async_store = Some(Arc::new(storage_async::Store::new(
                 store,
                 ctx.executor.clone(),
@@ -133,12 +244,27 @@ impl ClientBuilder {
         self
     }
 
+    /// Loads the known peers persisted in store, which will be reconnected by router.
+    pub fn with_known_peers(mut self, max_known_peers: usize) -> Result<Self, String> {
+        let store = require!("known_peers", self, store).clone();
+        let known_peers = KnownPeers::load(store, max_known_peers)
+            .map_err(|e| format!("Unable to load known peers: {:?}", e))?;
+        self.known_peers = Some(known_peers);
+        Ok(self)
+    }
+
     /// Starts the networking stack.
     pub async fn with_network(mut self, mut config: NetworkConfig) -> Result<Self, String> {
         let executor = require!("network", self, runtime_context).clone().executor;
         let store = require!("network", self, store).clone();
         let file_location_cache = require!("network", self, file_location_cache).clone();
 
+        // advertise the local shard config in ENR, and only dial discovered peers that could
+        // serve data of the local shard if files are synced from peers
+        let shard_config = shared_types::ShardConfig::from(store.get_shard_config());
+        config.shard_config = Some(shard_config);
+        config.peer_manager.dial_demand = DialDemand::new(shard_config, config.syncs_files);
+
         // only dial to peers that shard config matched
         config.peer_manager.filters.dial_peer_filter = Some(Arc::new(move |peer_id| {
             match file_location_cache.get_peer_config(peer_id) {
@@ -154,10 +280,13 @@ impl ClientBuilder {
         let (send, recv) = new_network_channel();
 
         // launch libp2p service
+        let started_at = Instant::now();
         let (globals, keypair, libp2p) =
             LibP2PService::new(executor, send.clone(), service_context)
                 .await
                 .map_err(|e| format!("Failed to start network service: {:?}", e))?;
+        self.startup_phases.record_since("network_init", started_at);
+        self.network_started_at = Some(Instant::now());
 
         self.network = Some(NetworkComponents {
             send,
@@ -169,7 +298,14 @@ impl ClientBuilder {
         Ok(self)
     }
 
-    pub async fn with_sync(mut self, config: sync::Config) -> Result<Self, String> {
+    pub async fn with_sync(mut self, mut config: sync::Config) -> Result<Self, String> {
+        // Files are only served to peers, but never synced into the read-only store.
+        if self.read_only {
+            config.auto_sync_enabled = false;
+            config.sync_file_by_rpc_enabled = false;
+            config.sync_file_on_announcement_enabled = false;
+        }
+
         let executor = require!("sync", self, runtime_context).clone().executor;
         let store = require!("sync", self, store).clone();
         let file_location_cache = require!("sync", self, file_location_cache).clone();
@@ -182,30 +318,81 @@ impl ClientBuilder {
             .catch_up_end_recv
             .take()
             .ok_or("sync requires a catch_up_end_recv")?;
+        let config_recv = require!("sync", self, config_watcher).subscribe_sync();
+        let peer_stats = require!("sync", self, known_peers).peer_stats();
 
         let send = SyncService::spawn_with_config(
             config,
-            executor,
+            executor.clone(),
             network_send,
             store,
             file_location_cache,
+            peer_stats,
             event_recv,
             catch_up_end_recv,
+            config_recv,
+            executor.shutdown_token("sync"),
         )
         .await
         .map_err(|e| format!("Failed to start sync service: {:?}", e))?;
+
+        // Files half-written by chunk pool before restart are synced from peers if possible.
+        if let Some(chunk_pool) = self.chunk_pool.as_ref().filter(|_| !self.read_only) {
+            executor.spawn(
+                reconcile_chunk_pool(chunk_pool.chunk_pool.clone(), send.clone()),
+                "chunk_pool_reconcile",
+            );
+        }
+
+        // Finalized files that miss data, e.g. after an unclean shutdown, are synced again.
+        if !self.read_only && self.finalized_audit != FinalizedAudit::Off {
+            executor.spawn(
+                audit_finalized_files(
+                    require!("sync", self, async_store).clone(),
+                    self.finalized_audit,
+                    send.clone(),
+                ),
+                "finalized_audit",
+            );
+        }
+
         self.sync = Some(SyncComponents { send });
 
         Ok(self)
     }
 
     pub async fn with_miner(mut self, config: Option<MinerConfig>) -> Result<Self, String> {
+        if self.read_only && config.is_some() {
+            info!("Miner disabled in read-only mode");
+            return Ok(self);
+        }
+
         if let Some(config) = config {
             let executor = require!("miner", self, runtime_context).clone().executor;
             let network_send = require!("miner", self, network).send.clone();
             let store = self.async_store.as_ref().unwrap().clone();
-
-            let send = MineService::spawn(executor, network_send, config, store).await?;
+            let config_recv = require!("miner", self, config_watcher).subscribe_miner();
+            let log_sync_recv = self
+                .log_sync
+                .as_ref()
+                .map(|log_sync| log_sync.send.subscribe());
+            let rpc_client = self
+                .log_sync
+                .as_ref()
+                .map(|log_sync| log_sync.monitor.rpc_client().clone());
+
+            let shutdown = executor.shutdown_token("miner");
+            let send = MineService::spawn(
+                executor,
+                network_send,
+                config,
+                store,
+                config_recv,
+                log_sync_recv,
+                rpc_client,
+                shutdown,
+            )
+            .await?;
             self.miner = Some(MinerComponents { send });
         }
 
@@ -213,13 +400,29 @@ impl ClientBuilder {
     }
 
     pub async fn with_pruner(mut self, config: Option<PrunerConfig>) -> Result<Self, String> {
+        if self.read_only && config.is_some() {
+            info!("Pruner disabled in read-only mode");
+            return Ok(self);
+        }
+
         if let Some(config) = config {
             let miner_send = self.miner.as_ref().map(|miner| miner.send.clone());
             let store = require!("pruner", self, async_store).clone();
             let executor = require!("pruner", self, runtime_context).clone().executor;
-            let recv = Pruner::spawn(executor, config, store, miner_send)
-                .await
-                .map_err(|e| e.to_string())?;
+            let config_recv = require!("pruner", self, config_watcher).subscribe_pruner();
+            let log_sync_recv = require!("pruner", self, log_sync).send.subscribe();
+            let shutdown = executor.shutdown_token("pruner");
+            let recv = Pruner::spawn(
+                executor,
+                config,
+                store,
+                miner_send,
+                config_recv,
+                log_sync_recv,
+                shutdown,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
             self.pruner = Some(PrunerComponents { owned: Some(recv) });
         }
         Ok(self)
@@ -243,6 +446,8 @@ impl ClientBuilder {
         let chunk_pool_send = require!("router", self, chunk_pool).chunk_pool.sender();
         let store = require!("router", self, store).clone();
         let file_location_cache = require!("router", self, file_location_cache).clone();
+        let known_peers = require!("router", self, known_peers).clone();
+        let config_recv = require!("router", self, config_watcher).subscribe_router();
 
         let network = self.network.as_mut().ok_or("router requires a network")?;
 
@@ -251,6 +456,14 @@ impl ClientBuilder {
             .take() // router takes ownership of libp2p and network_recv
             .ok_or("router requires a network")?;
         let pruner_recv = self.pruner.as_mut().and_then(|pruner| pruner.owned.take());
+        let log_sync_recv = self
+            .log_sync
+            .as_ref()
+            .map(|log_sync| log_sync.send.subscribe());
+        let log_sync_monitor = self
+            .log_sync
+            .as_ref()
+            .map(|log_sync| log_sync.monitor.clone());
         RouterService::spawn(
             executor,
             libp2p,
@@ -261,10 +474,25 @@ impl ClientBuilder {
             miner_send,
             chunk_pool_send,
             pruner_recv,
+            log_sync_recv,
+            log_sync_monitor,
             store,
             file_location_cache,
             network.keypair.clone(),
+            known_peers,
             router_config,
+            config_recv,
+        );
+
+        // Peers are discovered in background once the router started.
+        let network_started_at = self.network_started_at.unwrap_or_else(Instant::now);
+        executor.spawn(
+            wait_for_peer_discovery(
+                network.globals.clone(),
+                self.startup_phases.clone(),
+                network_started_at,
+            ),
+            "startup_peer_discovery",
         );
 
         Ok(self)
@@ -276,11 +504,49 @@ impl ClientBuilder {
         }
 
         let executor = require!("rpc", self, runtime_context).clone().executor;
-        let async_store = require!("rpc", self, async_store).clone();
+        let mut async_store = require!("rpc", self, async_store).clone();
+        if rpc_config.storage_timeout_secs > 0 {
+            let timeout = Duration::from_secs(rpc_config.storage_timeout_secs);
+            async_store = Arc::new(async_store.with_timeout(timeout));
+        }
         let network_send = require!("rpc", self, network).send.clone();
         let mine_send = self.miner.as_ref().map(|x| x.send.clone());
+        let log_sync = self.log_sync.as_ref().map(|x| x.monitor.clone());
         let file_location_cache = require!("rpc", self, file_location_cache).clone();
         let chunk_pool = require!("rpc", self, chunk_pool).chunk_pool.clone();
+        let segment_cache = Arc::new(rpc::SegmentCache::new(rpc_config.segment_cache_max_bytes));
+        let upload_sessions = Arc::new(rpc::UploadSessions::new(
+            Duration::from_secs(rpc_config.upload_session_ttl_secs),
+            rpc_config.max_upload_sessions_per_ip,
+        ));
+        let proof_verifier = Arc::new(rpc::ProofVerifier::new(
+            rpc_config.proof_verify_threads,
+            rpc_config.max_queued_proof_verifications,
+        ));
+        let replica_query_limiter = Arc::new(rpc::ReplicaQueryLimiter::new(
+            rpc_config.max_replica_queries,
+            Duration::from_millis(rpc_config.replica_query_wait_ms),
+        ));
+
+        let jobs = Arc::new(
+            rpc::JobManager::new(
+                async_store.clone(),
+                executor.clone(),
+                rpc_config.job_config(),
+            )
+            .await
+            .map_err(|e| format!("Unable to load admin jobs: {:?}", e))?,
+        );
+        // Jobs could not persist their progress into a read-only store.
+        if !self.read_only {
+            let resumed = jobs
+                .resume()
+                .await
+                .map_err(|e| format!("Unable to resume admin jobs: {:?}", e))?;
+            if resumed > 0 {
+                info!(%resumed, "Admin jobs resumed");
+            }
+        }
 
         let ctx = rpc::Context {
             config: rpc_config,
@@ -292,13 +558,38 @@ impl ClientBuilder {
             chunk_pool,
             shutdown_sender: executor.shutdown_sender(),
             mine_service_sender: mine_send,
+            log_sync,
+            known_peers: self.known_peers.clone(),
+            config_reloader: self
+                .config_watcher
+                .clone()
+                .map(|watcher| watcher as Arc<dyn rpc::ConfigReloader>),
+            log_filter: self
+                .log_filter
+                .clone()
+                .map(|handle| Arc::new(handle) as Arc<dyn rpc::LogFilterSetter>),
+            segment_cache,
+            upload_sessions,
+            proof_verifier,
+            replica_query_limiter,
+            startup_phases: self.startup_phases.clone(),
+            jobs,
+            read_only: self.read_only,
         };
 
-        let (rpc_handle, maybe_admin_rpc_handle) = rpc::run_server(ctx)
-            .await
+        // Stops accepting new requests once the node is shutting down.
+        let shutdown = executor.shutdown_token("rpc");
+        let (rpc_handle, maybe_admin_rpc_handle) = rpc::run_server(ctx, shutdown.signal())
             .map_err(|e| format!("Unable to start HTTP RPC server: {:?}", e))?;
 
-        executor.spawn(rpc_handle, "rpc");
+        executor.spawn(
+            async move {
+                rpc_handle.await;
+                info!("RPC server stopped for shutdown");
+                shutdown.ack();
+            },
+            "rpc",
+        );
         if let Some(admin_rpc_handle) = maybe_admin_rpc_handle {
             executor.spawn(admin_rpc_handle, "rpc_admin");
         }
@@ -306,19 +597,39 @@ impl ClientBuilder {
         Ok(self)
     }
 
+    pub fn with_metrics_exporter(self, listen: Option<SocketAddr>) -> Result<Self, String> {
+        let listen = match listen {
+            Some(listen) => listen,
+            None => return Ok(self),
+        };
+
+        let executor = require!("metrics_exporter", self, runtime_context)
+            .clone()
+            .executor;
+        let async_store = require!("metrics_exporter", self, async_store).clone();
+
+        let exporter = rpc::run_metrics_exporter(listen, async_store)
+            .map_err(|e| format!("Unable to start metrics exporter: {:?}", e))?;
+        executor.spawn(exporter, "metrics_exporter");
+
+        Ok(self)
+    }
+
     pub async fn with_chunk_pool(
         mut self,
         chunk_pool_config: ChunkPoolConfig,
     ) -> Result<Self, String> {
         let executor = require!("rpc", self, runtime_context).clone().executor;
         let async_store = require!("rpc", self, async_store).clone();
-        let network_send = require!("rpc", self, network).send.clone();
         let synced_tx_recv = require!("rpc", self, log_sync).send.subscribe();
 
         let (chunk_pool, chunk_pool_handler) =
-            chunk_pool::unbounded(chunk_pool_config, async_store.clone(), network_send.clone());
+            chunk_pool::unbounded(chunk_pool_config, async_store.clone());
 
-        executor.spawn(chunk_pool_handler.run(), "chunk_pool_handler");
+        executor.spawn(
+            chunk_pool_handler.run(executor.shutdown_token("chunk_pool")),
+            "chunk_pool_handler",
+        );
         executor.spawn(
             MemoryChunkPool::monitor_log_entry(chunk_pool.clone(), synced_tx_recv),
             "chunk_pool_log_monitor",
@@ -332,13 +643,17 @@ impl ClientBuilder {
     pub async fn with_log_sync(mut self, config: LogSyncConfig) -> Result<Self, String> {
         let executor = require!("log_sync", self, runtime_context).clone().executor;
         let store = require!("log_sync", self, store).clone();
-        let (send, catch_up_end_recv) = LogSyncManager::spawn(config, executor, store)
-            .await
-            .map_err(|e| e.to_string())?;
+        let ingest_policy = require!("log_sync", self, config_watcher).subscribe_ingest_policy();
+        let shutdown = executor.shutdown_token("log_sync");
+        let (send, catch_up_end_recv, monitor) =
+            LogSyncManager::spawn(config, executor, store, ingest_policy, shutdown)
+                .await
+                .map_err(|e| e.to_string())?;
 
         self.log_sync = Some(LogSyncComponents {
             send,
             catch_up_end_recv: Some(catch_up_end_recv),
+            monitor,
         });
         Ok(self)
     }
@@ -353,3 +668,83 @@ impl ClientBuilder {
         })
     }
 }
+
+/// Records the initial peer discovery as a startup phase once the first peer connected.
+async fn wait_for_peer_discovery(
+    globals: Arc<NetworkGlobals>,
+    startup_phases: Arc<StartupPhases>,
+    started_at: Instant,
+) {
+    while globals.connected_peers() == 0 {
+        tokio::time::sleep(PEER_DISCOVERY_POLL_INTERVAL).await;
+    }
+
+    startup_phases.record_since("peer_discovery", started_at);
+}
+
+/// Audits the finalized files, and syncs the files demoted for missing data from peers. If failed
+/// to sync, the file should be uploaded by client again.
+async fn audit_finalized_files(
+    store: Arc<storage_async::Store>,
+    audit: FinalizedAudit,
+    sync_send: SyncSender,
+) {
+    let started_at = Instant::now();
+    let report = match store.audit_finalized_txs(audit).await {
+        Ok(report) => report,
+        Err(e) => {
+            error!("Failed to audit finalized files: {:?}", e);
+            return;
+        }
+    };
+    info!(
+        ?audit,
+        audited = report.audited,
+        demoted = report.demoted.len(),
+        elapsed = ?started_at.elapsed(),
+        "Audit of finalized files completed"
+    );
+
+    for tx_seq in report.demoted {
+        match sync_send.request(SyncRequest::SyncFile { tx_seq }).await {
+            Ok(SyncResponse::SyncFile { err }) if err.is_empty() => {
+                info!(%tx_seq, "Sync finalized file that misses data");
+            }
+            result => warn!(
+                %tx_seq,
+                ?result,
+                "Failed to sync finalized file that misses data, client should upload again"
+            ),
+        }
+    }
+}
+
+/// Reconciles the flush journals of chunk pool, and syncs the missing chunks of half-written
+/// files from peers. If failed to sync, the file should be uploaded by client again.
+async fn reconcile_chunk_pool(chunk_pool: Arc<MemoryChunkPool>, sync_send: SyncSender) {
+    let files = match chunk_pool.reconcile_flush_journals().await {
+        Ok(files) => files,
+        Err(e) => {
+            error!("Failed to reconcile chunk pool flush journals: {:?}", e);
+            return;
+        }
+    };
+
+    for (tx_seq, missing) in files {
+        let ranges = missing.iter().map(|r| (r.start, r.end)).collect();
+        let result = sync_send
+            .request(SyncRequest::SyncChunkRanges { tx_seq, ranges })
+            .await;
+
+        match result {
+            Ok(SyncResponse::SyncFile { err }) if err.is_empty() => {
+                info!(%tx_seq, ?missing, "Sync missing chunks of half-written file");
+            }
+            result => warn!(
+                %tx_seq,
+                ?result,
+                "Failed to sync missing chunks of half-written file, client should upload again"
+            ),
+        }
+    }
+}
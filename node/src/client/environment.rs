@@ -7,6 +7,8 @@
 use futures::channel::mpsc::{channel, Receiver, Sender};
 use futures::{future, StreamExt};
 use std::sync::Arc;
+use std::time::Duration;
+use task_executor::shutdown::{ShutdownCoordinator, ShutdownSummary};
 use task_executor::{ShutdownReason, TaskExecutor};
 use tokio::runtime::{Builder as RuntimeBuilder, Runtime};
 
@@ -60,6 +62,7 @@ impl EnvironmentBuilder {
             signal_rx: Some(signal_rx),
             signal: Some(signal),
             exit,
+            shutdown: Default::default(),
         })
     }
 }
@@ -84,6 +87,9 @@ pub struct Environment {
     signal_tx: Sender<ShutdownReason>,
     signal: Option<exit_future::Signal>,
     exit: exit_future::Exit,
+
+    /// Shared by all task executors to shut down services gracefully.
+    shutdown: ShutdownCoordinator,
 }
 
 impl Environment {
@@ -102,7 +108,8 @@ impl Environment {
                 self.runtime().handle().clone(),
                 self.exit.clone(),
                 self.signal_tx.clone(),
-            ),
+            )
+            .with_shutdown_coordinator(self.shutdown.clone()),
         }
     }
 
@@ -140,14 +147,7 @@ impl Environment {
                 Err(e) => error!(error = %e, "Could not register SIGINT handler"),
             }
 
-            // setup for handling a SIGHUP
-            match signal(SignalKind::hangup()) {
-                Ok(hup_stream) => {
-                    let hup = SignalFuture::new(hup_stream, "Received SIGHUP");
-                    handles.push(hup);
-                }
-                Err(e) => error!(error = %e, "Could not register SIGHUP handler"),
-            }
+            // SIGHUP is handled to reload config instead
 
             future::select(inner_shutdown, future::select_all(handles.into_iter())).await
         };
@@ -206,6 +206,15 @@ impl Environment {
         }
     }
 
+    /// Notifies all services to stop accepting new work and flush pending writes, and blocks
+    /// the current thread until they acknowledged or `grace_period` elapsed.
+    ///
+    /// This should be called before `fire_signal`, which cancels all spawned tasks.
+    pub fn shutdown_services(&self, grace_period: Duration) -> ShutdownSummary {
+        self.runtime()
+            .block_on(self.shutdown.shutdown(grace_period))
+    }
+
     /// Shutdown the `tokio` runtime when all tasks are idle.
     pub fn shutdown_on_idle(self) {
         match Arc::try_unwrap(self.runtime) {
@@ -79,6 +79,17 @@ macro_rules! build_config{
 
                 Ok(config)
             }
+
+            /// Returns the names of parameters that differ from `other`.
+            pub fn diff(&self, other: &RawConfiguration) -> Vec<&'static str> {
+                let mut changed = vec![];
+                $(
+                    if self.$name != other.$name {
+                        changed.push(stringify!($name));
+                    }
+                )*
+                changed
+            }
         }
     }
 }
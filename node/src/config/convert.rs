@@ -1,19 +1,25 @@
 #![allow(clippy::field_reassign_with_default)]
 
+use crate::config::NodeMode;
 use crate::ZgsConfig;
 use ethereum_types::{H256, U256};
 use ethers::prelude::{Http, Middleware, Provider};
-use log_entry_sync::{CacheConfig, ContractAddress, LogSyncConfig};
-use miner::MinerConfig;
-use network::{EnrExt, NetworkConfig};
-use pruner::PrunerConfig;
+use log_entry_sync::{
+    CacheConfig, ContractAddress, EventSubscription, IngestPolicy, LogSyncConfig, QuarantineConfig,
+};
+use miner::{MinerConfig, MinerDynamicConfig};
+use network::{EnrExt, NetworkConfig, NodeCapabilities};
+use pruner::{PrunerConfig, PrunerDynamicConfig};
 use shared_types::{NetworkIdentity, ProtocolVersion};
 use std::net::IpAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use storage::config::ShardConfig;
+use storage::log_store::audit::FinalizedAudit;
 use storage::log_store::log_manager::LogConfig;
-use storage::StorageConfig;
+use storage::log_store::write_stall::WriteStallConfig;
+use storage::{DbLayout, StorageConfig};
 
 impl ZgsConfig {
     pub async fn network_config(&self) -> Result<NetworkConfig, String> {
@@ -26,7 +32,10 @@ impl ZgsConfig {
 
         network_config.network_dir = self.network_dir.clone().into();
         network_config.libp2p_port = self.network_libp2p_port;
-        network_config.disable_discovery = self.network_disable_discovery;
+        // Discovery is disabled in private network mode, so that the node is not advertised.
+        let disable_discovery =
+            self.network_disable_discovery || !self.network_allowlist.is_empty();
+        network_config.disable_discovery = disable_discovery;
         network_config.discovery_port = self.network_discovery_port;
         let flow_address = self
             .log_contract_address
@@ -50,7 +59,7 @@ impl ZgsConfig {
         };
         network_config.network_id = local_network_id.clone();
 
-        if !self.network_disable_discovery {
+        if !disable_discovery {
             network_config.enr_tcp_port = Some(self.network_enr_tcp_port);
             network_config.enr_udp_port = Some(self.network_enr_udp_port);
             network_config.enr_address = match &self.network_enr_address {
@@ -60,6 +69,11 @@ impl ZgsConfig {
                         info!(?ipv4_addr, "Auto detect public IP as ENR address");
                         Some(IpAddr::V4(ipv4_addr))
                     }
+                    // the external address is expected to be detected via port mapping
+                    None if self.network_upnp_enabled || self.network_nat_pmp_enabled => {
+                        warn!("Failed to detect public IP address, waiting for port mapping");
+                        None
+                    }
                     None => {
                         return Err(
                             "ENR address not configured and failed to detect public IP address"
@@ -68,8 +82,23 @@ impl ZgsConfig {
                     }
                 },
             };
+            // only if the ENR address is not specified explicitly
+            network_config.enr_address_from_observed = self.network_enr_address.is_none();
         }
 
+        network_config.upnp_enabled = self.network_upnp_enabled;
+        network_config.nat_pmp_enabled = self.network_nat_pmp_enabled;
+        network_config.nat_pmp_gateway = match &self.network_nat_pmp_gateway {
+            Some(gateway) => Some(
+                gateway
+                    .parse()
+                    .map_err(|e| format!("Unable to parse network_nat_pmp_gateway: {:?}", e))?,
+            ),
+            None => None,
+        };
+        network_config.port_mapping_lease =
+            Duration::from_secs(self.network_port_mapping_lease_secs);
+
         network_config.boot_nodes_multiaddr = self
             .network_boot_nodes
             .iter()
@@ -106,21 +135,81 @@ impl ZgsConfig {
 
         network_config.peer_db = self.network_peer_db;
         network_config.peer_manager = self.network_peer_manager.clone();
+        network_config.peer_policy = network::PeerPolicyConfig {
+            allowlist: self.network_allowlist.clone(),
+            denylist: self.network_denylist.clone(),
+        };
+        network_config.rpc_size_limits = self.network_rpc_limits;
         network_config.disable_enr_network_id = self.discv5_disable_enr_network_id;
         network_config.find_chunks_enabled = self.network_find_chunks_enabled;
+        network_config
+            .capabilities
+            .set(NodeCapabilities::SERVES_DATA, self.network_serves_data);
+        network_config.capabilities.set(
+            NodeCapabilities::ACCEPTS_SYNC,
+            self.network_accepts_sync && !self.node_mode()?.is_read_only(),
+        );
+        // Files are never synced into the read-only store.
+        network_config.syncs_files = self.sync.syncs_files() && !self.node_mode()?.is_read_only();
 
         Ok(network_config)
     }
 
+    pub fn node_mode(&self) -> Result<NodeMode, String> {
+        self.node_mode.parse()
+    }
+
     pub fn storage_config(&self) -> Result<StorageConfig, String> {
         let mut log_config = LogConfig::default();
         log_config.flow.merkle_node_cache_capacity = self.merkle_node_cache_capacity;
+        log_config.max_tx_inline_data_size = self.max_tx_inline_data_size;
+        log_config.revert_journal.max_chunks = self.db_revert_journal_max_chunks;
+        log_config.revert_journal.expiry_blocks = self.db_revert_journal_expiry_blocks;
         Ok(StorageConfig {
+            db_engine: self.db_engine.parse()?,
             db_dir: self.db_dir.clone().into(),
+            db_layout: DbLayout::new(self.db_unified),
             log_config,
+            read_only: self.node_mode()?.is_read_only(),
+            finalized_audit: self.finalized_audit()?,
+            lazy_tree_load: self.db_lazy_tree_load,
+            // The network is queried from blockchain on startup.
+            network_id: None,
+            force_reinit: false,
+            write_stall: self.write_stall_config()?,
         })
     }
 
+    fn write_stall_config(&self) -> Result<WriteStallConfig, String> {
+        let min = self.db_write_stall_min_memory_budget_mb;
+        let max = self.db_write_stall_max_memory_budget_mb;
+        if self.db_write_stall_auto_tune && (min == 0 || min > max) {
+            return Err(format!(
+                "Invalid memory budget bounds of db write stall auto-tune, min={} max={}",
+                min, max
+            ));
+        }
+        Ok(WriteStallConfig {
+            poll_interval: Duration::from_secs(self.db_write_stall_poll_interval_secs),
+            persistent_polls: self.db_write_stall_persistent_polls,
+            auto_tune: self.db_write_stall_auto_tune.then_some((min, max)),
+        })
+    }
+
+    fn finalized_audit(&self) -> Result<FinalizedAudit, String> {
+        match self.db_finalized_audit.as_str() {
+            "off" => Ok(FinalizedAudit::Off),
+            "sampled" => Ok(FinalizedAudit::Sampled {
+                samples: self.db_finalized_audit_samples,
+            }),
+            "full" => Ok(FinalizedAudit::Full),
+            mode => Err(format!(
+                "Unknown finalized audit {}, expected off, sampled or full",
+                mode
+            )),
+        }
+    }
+
     pub fn log_sync_config(&self) -> Result<LogSyncConfig, String> {
         let contract_address = self
             .log_contract_address
@@ -132,11 +221,33 @@ impl ZgsConfig {
             // This should be enough if we have about one Zgs tx per block.
             tx_seq_ttl: self.cache_tx_seq_ttl,
         };
+        let mut rpc_endpoint_urls = vec![self.blockchain_rpc_endpoint.clone()];
+        for url in &self.blockchain_rpc_endpoints {
+            if !rpc_endpoint_urls.contains(url) {
+                rpc_endpoint_urls.push(url.clone());
+            }
+        }
+        let event_subscriptions = self
+            .log_sync_event_subscriptions
+            .iter()
+            .map(|s| s.parse::<EventSubscription>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Unable to parse log_sync_event_subscriptions: {:?}", e))?;
+        let finalize_confirmations = self
+            .finalize_confirmation_block_count
+            .unwrap_or(self.confirmation_block_count);
+        if finalize_confirmations < self.confirmation_block_count {
+            return Err(format!(
+                "finalize_confirmation_block_count {} is less than confirmation_block_count {}",
+                finalize_confirmations, self.confirmation_block_count
+            ));
+        }
         Ok(LogSyncConfig::new(
-            self.blockchain_rpc_endpoint.clone(),
+            rpc_endpoint_urls,
             contract_address,
             self.log_sync_start_block_number,
             self.confirmation_block_count,
+            finalize_confirmations,
             cache_config,
             self.log_page_size,
             self.rate_limit_retries,
@@ -148,6 +259,18 @@ impl ZgsConfig {
             self.watch_loop_wait_time_ms,
             self.force_log_sync_from_start_block_number,
             Duration::from_secs(self.blockchain_rpc_timeout_secs),
+            self.blockchain_rpc_max_head_lag,
+            Duration::from_secs(self.blockchain_rpc_health_check_interval_secs),
+            self.recover_concurrency,
+            self.use_finalized_tag,
+            event_subscriptions,
+            Duration::from_secs(self.log_sync_stall_timeout_secs),
+            self.log_sync_replay_file.as_ref().map(PathBuf::from),
+            self.node_mode()?.is_read_only(),
+            QuarantineConfig {
+                capacity: self.log_sync_quarantine_capacity,
+                window: Duration::from_secs(self.log_sync_quarantine_window_secs),
+            },
         ))
     }
 
@@ -196,6 +319,7 @@ impl ZgsConfig {
             cpu_percentage,
             iter_batch,
             context_query_seconds,
+            self.mine_context_grace_seconds,
             shard_config,
             self.rate_limit_retries,
             self.timeout_retries,
@@ -203,19 +327,59 @@ impl ZgsConfig {
         ))
     }
 
+    pub fn ingest_policy(&self) -> Result<IngestPolicy, String> {
+        let parse_senders = |name: &str, senders: &[String]| {
+            senders
+                .iter()
+                .map(|s| s.parse::<ContractAddress>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("Unable to parse {}: {:?}", name, e))
+        };
+        let denied_roots = self
+            .ingest_denied_roots
+            .iter()
+            .map(|s| s.parse::<H256>())
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Unable to parse ingest_denied_roots: {:?}", e))?;
+
+        Ok(IngestPolicy {
+            max_size_bytes: self.ingest_max_size_bytes,
+            allowed_senders: parse_senders("ingest_allowed_senders", &self.ingest_allowed_senders)?,
+            denied_senders: parse_senders("ingest_denied_senders", &self.ingest_denied_senders)?,
+            denied_roots,
+        })
+    }
+
+    pub fn mine_dynamic_config(&self) -> MinerDynamicConfig {
+        MinerDynamicConfig {
+            cpu_percentage: self.miner_cpu_percentage,
+            iter_batch: self.mine_iter_batch_size,
+        }
+    }
+
     pub fn chunk_pool_config(&self) -> Result<chunk_pool::Config, String> {
         Ok(chunk_pool::Config {
             write_window_size: self.chunk_pool_write_window_size,
             max_cached_chunks_all: self.chunk_pool_max_cached_chunks_all,
+            max_cached_chunks_per_file: self.chunk_pool_max_cached_chunks_per_file,
+            max_spilled_chunks_all: self.chunk_pool_max_spilled_chunks_all,
             max_writings: self.chunk_pool_max_writings,
             expiration_time_secs: self.chunk_pool_expiration_time_secs,
             shard_config: self.shard_config()?,
+            spill_dir: self.chunk_pool_spill_dir.as_ref().map(PathBuf::from),
+            read_only: self.node_mode()?.is_read_only(),
         })
     }
 
     pub fn router_config(&self, network_config: &NetworkConfig) -> Result<router::Config, String> {
         let mut router_config = self.router.clone();
         router_config.libp2p_nodes = network_config.libp2p_nodes.to_vec();
+        router_config.serve_partial_files = self.sync.serve_partial_files;
+
+        // log entries are synced from finalized blocks, which are never reorged
+        if self.use_finalized_tag {
+            router_config.announce_confirmation_depth = 0;
+        }
 
         if router_config.public_address.is_none() {
             if let Some(addr) = &self.network_enr_address {
@@ -251,6 +415,15 @@ impl ZgsConfig {
         }
     }
 
+    /// Prune budget, where `max_num_sectors` is 0 if pruner disabled.
+    pub fn pruner_dynamic_config(&self) -> PrunerDynamicConfig {
+        PrunerDynamicConfig {
+            max_num_sectors: self.db_max_num_sectors.unwrap_or_default(),
+            batch_size: self.prune_batch_size,
+            batch_wait_time: Duration::from_millis(self.prune_batch_wait_time_ms),
+        }
+    }
+
     pub fn shard_config(&self) -> Result<ShardConfig, String> {
         self.shard_position.clone().try_into()
     }
@@ -1,9 +1,12 @@
 mod config_macro;
 
 mod convert;
+pub mod reload;
 use config_macro::*;
 use serde::Deserialize;
+use std::net::SocketAddr;
 use std::ops::Deref;
+use std::str::FromStr;
 
 build_config! {
     // network
@@ -19,7 +22,15 @@ build_config! {
     (network_libp2p_nodes, (Vec<String>), vec![])
     (network_private, (bool), false)
     (network_disable_discovery, (bool), false)
+    (network_allowlist, (Vec<String>), vec![])
+    (network_denylist, (Vec<String>), vec![])
     (network_find_chunks_enabled, (bool), false)
+    (network_serves_data, (bool), true)
+    (network_accepts_sync, (bool), true)
+    (network_upnp_enabled, (bool), false)
+    (network_nat_pmp_enabled, (bool), false)
+    (network_nat_pmp_gateway, (Option<String>), None)
+    (network_port_mapping_lease_secs, (u64), 3600)
 
     // discv5
     (discv5_request_timeout_secs, (u64), 5)
@@ -37,7 +48,10 @@ build_config! {
     (log_sync_start_block_number, (u64), 0)
     (force_log_sync_from_start_block_number, (bool), false)
     (confirmation_block_count, (u64), 3)
+    (finalize_confirmation_block_count, (Option<u64>), None)
+    (use_finalized_tag, (bool), false)
     (log_page_size, (u64), 999)
+    (log_sync_event_subscriptions, (Vec<String>), vec![])
     (max_cache_data_size, (usize), 100 * 1024 * 1024) // 100 MB
     (cache_tx_seq_ttl, (usize), 500)
 
@@ -45,30 +59,60 @@ build_config! {
     (timeout_retries, (u32), 100)
     (initial_backoff, (u64), 500)
     (recover_query_delay, (u64), 50)
+    (recover_concurrency, (usize), 1)
 
     (default_finalized_block_count, (u64), 100)
     (remove_finalized_block_interval_minutes, (u64), 30)
     (watch_loop_wait_time_ms, (u64), 500)
 
     (blockchain_rpc_timeout_secs, (u64), 120)
+    (blockchain_rpc_endpoints, (Vec<String>), vec![])
+    (blockchain_rpc_max_head_lag, (u64), 10)
+    (blockchain_rpc_health_check_interval_secs, (u64), 10)
+    (log_sync_stall_timeout_secs, (u64), 600)
+    (log_sync_replay_file, (Option<String>), None)
+    (log_sync_quarantine_capacity, (usize), 1024)
+    (log_sync_quarantine_window_secs, (u64), 30)
+    (ingest_max_size_bytes, (Option<u64>), None)
+    (ingest_allowed_senders, (Vec<String>), vec![])
+    (ingest_denied_senders, (Vec<String>), vec![])
+    (ingest_denied_roots, (Vec<String>), vec![])
 
     // chunk pool
     (chunk_pool_write_window_size, (usize), 4)
     (chunk_pool_max_cached_chunks_all, (usize), 4*1024*1024)    // 1G
+    (chunk_pool_max_cached_chunks_per_file, (usize), 1024*1024)    // 256M
     (chunk_pool_max_writings, (usize), 16)
     (chunk_pool_expiration_time_secs, (u64), 300)   // 5 minutes
+    (chunk_pool_spill_dir, (Option<String>), None)
+    (chunk_pool_max_spilled_chunks_all, (usize), 16*1024*1024)    // 4G
 
     // db
+    (db_engine, (String), "rocksdb".to_string())
     (db_dir, (String), "db".to_string())
+    (db_unified, (bool), false)
+    (db_finalized_audit, (String), "sampled".to_string())
+    (db_finalized_audit_samples, (usize), 256)
     (db_max_num_sectors, (Option<usize>), None)
+    (db_lazy_tree_load, (bool), false)
+    (db_write_stall_poll_interval_secs, (u64), 10)
+    (db_write_stall_persistent_polls, (u32), 6)
+    (db_write_stall_auto_tune, (bool), false)
+    (db_write_stall_min_memory_budget_mb, (usize), 128)
+    (db_write_stall_max_memory_budget_mb, (usize), 512)
     (prune_check_time_s, (u64), 60)
     (prune_batch_size, (usize), 16 * 1024)
     (prune_batch_wait_time_ms, (u64), 1000)
     (merkle_node_cache_capacity, (usize), 32 * 1024 * 1024)
+    (max_tx_inline_data_size, (usize), 256 * 1024)
+    (db_revert_journal_max_chunks, (usize), 0)
+    (db_revert_journal_expiry_blocks, (u64), 1000)
 
     // misc
+    (node_mode, (String), "full".to_string())
     (log_config_file, (String), "log_config".to_string())
     (log_directory, (String), "log".to_string())
+    (shutdown_grace_period_secs, (u64), 10)
 
     // mine
     (mine_contract_address, (String), "".to_string())
@@ -81,9 +125,41 @@ build_config! {
     (shard_position, (Option<String>), None)
 
     (mine_context_query_seconds, (u64), 5)
+    (mine_context_grace_seconds, (u64), 60)
 }
 
-#[derive(Debug, Default, Deserialize)]
+/// Mode of the node, configured by `node_mode`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NodeMode {
+    #[default]
+    Full,
+    /// Serves the data of db, e.g. a snapshot, without accepting any writes. Chunk pool, miner
+    /// and pruner are disabled, and log sync only verifies the db against blockchain.
+    ReadOnly,
+}
+
+impl NodeMode {
+    pub fn is_read_only(&self) -> bool {
+        *self == NodeMode::ReadOnly
+    }
+}
+
+impl FromStr for NodeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(NodeMode::Full),
+            "readonly" => Ok(NodeMode::ReadOnly),
+            _ => Err(format!(
+                "Unknown node mode {}, expected full or readonly",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
 #[serde(default)]
 pub struct ZgsConfig {
     pub raw_conf: RawConfiguration,
@@ -94,6 +170,9 @@ pub struct ZgsConfig {
     /// Network peer manager config, configured by [network_peer_manager] section by `config` crate.
     pub network_peer_manager: network::peer_manager::config::Config,
 
+    /// Size limits of network RPC messages, configured by [network_rpc_limits] section by `config` crate.
+    pub network_rpc_limits: network::RpcSizeLimits,
+
     // router config, configured by [router] section by `config` crate.
     pub router: router::Config,
 
@@ -106,8 +185,15 @@ pub struct ZgsConfig {
     // rpc config, configured by [rpc] section by `config` crate.
     pub rpc: rpc::RPCConfig,
 
+    // log config, configured by [log] section by `config` crate.
+    pub log: crate::log::LogConfig,
+
     // metrics config, configured by [metrics] section by `config` crate.
     pub metrics: metrics::MetricsConfiguration,
+
+    // metrics exporter address, configured by `listen` in [metrics] section.
+    #[serde(skip)]
+    pub metrics_listen: Option<SocketAddr>,
 }
 
 impl Deref for ZgsConfig {
@@ -125,7 +211,7 @@ impl ZgsConfig {
             None => return Err("config file not specified".to_string()),
         };
 
-        let mut config = config::Config::builder()
+        let settings = config::Config::builder()
             .add_source(config::File::with_name(config_file))
             .add_source(
                 config::Environment::with_prefix("ZGS_NODE")
@@ -133,10 +219,23 @@ impl ZgsConfig {
                     .list_separator(" "),
             )
             .build()
-            .map_err(|e| format!("Failed to build config: {:?}", e))?
+            .map_err(|e| format!("Failed to build config: {:?}", e))?;
+
+        // The exporter address is not a field of `metrics::MetricsConfiguration`.
+        let metrics_listen = settings.get_string("metrics.listen").ok();
+
+        let mut config = settings
             .try_deserialize::<ZgsConfig>()
             .map_err(|e| format!("Failed to deserialize config: {:?}", e))?;
 
+        config.metrics_listen = match metrics_listen {
+            Some(addr) => Some(
+                addr.parse()
+                    .map_err(|e| format!("Invalid metrics.listen: {:?}", e))?,
+            ),
+            None => None,
+        };
+
         config.raw_conf = RawConfiguration::parse(matches)?;
 
         Ok(config)
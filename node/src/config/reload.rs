@@ -0,0 +1,279 @@
+//! Reloads the config file at runtime, e.g. on SIGHUP or `admin_reloadConfig`, so that tunable
+//! parameters could be changed without restarting the node, which drops all peers and in-flight
+//! file syncs.
+//!
+//! Only dynamic parameters are applied, and sent to the consuming services via watch channels.
+//! Changes to other parameters, e.g. db paths, network keys and shard config, are rejected, and
+//! take effect only after restart.
+
+use super::ZgsConfig;
+use log_entry_sync::IngestPolicy;
+use miner::MinerDynamicConfig;
+use pruner::PrunerDynamicConfig;
+use rpc::types::ConfigReloadReport;
+use rpc::ConfigReloader;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use task_executor::TaskExecutor;
+use tokio::sync::watch;
+
+/// Parameters that could be changed at runtime, besides `db_max_num_sectors`, of which the
+/// parameters in sections are prefixed by the section name.
+const DYNAMIC_PARAMS: [&str; 13] = [
+    "miner_cpu_percentage",
+    "mine_iter_batch_size",
+    "prune_batch_size",
+    "prune_batch_wait_time_ms",
+    "ingest_max_size_bytes",
+    "ingest_allowed_senders",
+    "ingest_denied_senders",
+    "ingest_denied_roots",
+    "sync.max_sync_files",
+    "sync.max_bandwidth_bytes",
+    "sync.max_requests_per_peer",
+    "router.shard_config_announce_interval",
+    "router.drip_announce_rate",
+];
+
+/// Sections of config file, of which all parameters are static.
+const STATIC_SECTIONS: [&str; 6] = [
+    "network_peer_db",
+    "network_peer_manager",
+    "file_location_cache",
+    "rpc",
+    "log",
+    "metrics",
+];
+
+/// Watches the config file of the running node, and sends dynamic parameters reloaded to the
+/// consuming services.
+pub struct ConfigWatcher {
+    matches: clap::ArgMatches,
+
+    /// Config of the running node, including dynamic parameters applied.
+    running: Mutex<ZgsConfig>,
+
+    sync: watch::Sender<sync::DynamicConfig>,
+    router: watch::Sender<router::DynamicConfig>,
+    miner: watch::Sender<MinerDynamicConfig>,
+    pruner: watch::Sender<PrunerDynamicConfig>,
+    ingest: watch::Sender<IngestPolicy>,
+}
+
+impl ConfigWatcher {
+    pub fn new(matches: clap::ArgMatches, config: ZgsConfig) -> Result<Self, String> {
+        Ok(Self {
+            matches,
+            sync: watch::channel(config.sync.dynamic()).0,
+            router: watch::channel(config.router.dynamic()).0,
+            miner: watch::channel(config.mine_dynamic_config()).0,
+            pruner: watch::channel(config.pruner_dynamic_config()).0,
+            ingest: watch::channel(config.ingest_policy()?).0,
+            running: Mutex::new(config),
+        })
+    }
+
+    pub fn subscribe_sync(&self) -> watch::Receiver<sync::DynamicConfig> {
+        self.sync.subscribe()
+    }
+
+    pub fn subscribe_router(&self) -> watch::Receiver<router::DynamicConfig> {
+        self.router.subscribe()
+    }
+
+    pub fn subscribe_miner(&self) -> watch::Receiver<MinerDynamicConfig> {
+        self.miner.subscribe()
+    }
+
+    pub fn subscribe_pruner(&self) -> watch::Receiver<PrunerDynamicConfig> {
+        self.pruner.subscribe()
+    }
+
+    pub fn subscribe_ingest_policy(&self) -> watch::Receiver<IngestPolicy> {
+        self.ingest.subscribe()
+    }
+
+    /// Reloads the config file, and applies the changed dynamic parameters. Note, the rejected
+    /// parameters are reported again in the next reload until restart.
+    pub fn reload(&self) -> Result<ConfigReloadReport, String> {
+        let reloaded = ZgsConfig::parse(&self.matches)?;
+        // rejects the reload as a whole if the policy is invalid
+        let ingest_policy = reloaded.ingest_policy()?;
+
+        let mut running = self.running.lock().expect("lock poisoned");
+        let report = diff(&running, &reloaded);
+        if report.applied.is_empty() {
+            return Ok(report);
+        }
+
+        running.sync.set_dynamic(reloaded.sync.dynamic());
+        running.router.set_dynamic(reloaded.router.dynamic());
+        let raw = &mut running.raw_conf;
+        raw.miner_cpu_percentage = reloaded.miner_cpu_percentage;
+        raw.mine_iter_batch_size = reloaded.mine_iter_batch_size;
+        if raw.db_max_num_sectors.is_some() && reloaded.db_max_num_sectors.is_some() {
+            raw.db_max_num_sectors = reloaded.db_max_num_sectors;
+        }
+        raw.prune_batch_size = reloaded.prune_batch_size;
+        raw.prune_batch_wait_time_ms = reloaded.prune_batch_wait_time_ms;
+        raw.ingest_max_size_bytes = reloaded.ingest_max_size_bytes;
+        raw.ingest_allowed_senders = reloaded.ingest_allowed_senders.clone();
+        raw.ingest_denied_senders = reloaded.ingest_denied_senders.clone();
+        raw.ingest_denied_roots = reloaded.ingest_denied_roots.clone();
+
+        // only notify services of which parameters changed
+        update(&self.sync, running.sync.dynamic());
+        update(&self.router, running.router.dynamic());
+        update(&self.miner, running.mine_dynamic_config());
+        update(&self.pruner, running.pruner_dynamic_config());
+        update(&self.ingest, ingest_policy);
+
+        Ok(report)
+    }
+}
+
+impl ConfigReloader for ConfigWatcher {
+    fn reload(&self) -> Result<ConfigReloadReport, String> {
+        ConfigWatcher::reload(self)
+    }
+}
+
+fn update<T: PartialEq>(sender: &watch::Sender<T>, value: T) {
+    sender.send_if_modified(|current| {
+        if *current == value {
+            return false;
+        }
+
+        *current = value;
+        true
+    });
+}
+
+/// Compares the reloaded config against the running one, and reports changed parameters that
+/// could be applied or not.
+fn diff(running: &ZgsConfig, reloaded: &ZgsConfig) -> ConfigReloadReport {
+    let mut report = ConfigReloadReport::default();
+
+    for name in running.raw_conf.diff(&reloaded.raw_conf) {
+        let dynamic = match name {
+            // pruner is only enabled or disabled at startup
+            "db_max_num_sectors" => {
+                running.db_max_num_sectors.is_some() && reloaded.db_max_num_sectors.is_some()
+            }
+            _ => DYNAMIC_PARAMS.contains(&name),
+        };
+
+        if dynamic {
+            report.applied.push(name.to_string());
+        } else {
+            report.rejected.push(name.to_string());
+        }
+    }
+
+    let (old, new) = (running.sync.dynamic(), reloaded.sync.dynamic());
+    let sync_params = [
+        (
+            "sync.max_sync_files",
+            old.max_sync_files != new.max_sync_files,
+        ),
+        (
+            "sync.max_bandwidth_bytes",
+            old.max_bandwidth_bytes != new.max_bandwidth_bytes,
+        ),
+        (
+            "sync.max_requests_per_peer",
+            old.max_requests_per_peer != new.max_requests_per_peer,
+        ),
+    ];
+    let (old_router, new_router) = (running.router.dynamic(), reloaded.router.dynamic());
+    let router_params = [
+        (
+            "router.shard_config_announce_interval",
+            old_router.shard_config_announce_interval != new_router.shard_config_announce_interval,
+        ),
+        (
+            "router.drip_announce_rate",
+            old_router.drip_announce_rate != new_router.drip_announce_rate,
+        ),
+    ];
+    for (name, _) in sync_params
+        .into_iter()
+        .chain(router_params)
+        .filter(|(_, changed)| *changed)
+    {
+        if DYNAMIC_PARAMS.contains(&name) {
+            report.applied.push(name.to_string());
+        } else {
+            report.rejected.push(name.to_string());
+        }
+    }
+
+    // section configs do not implement `PartialEq`, so compare the static parameters by debug
+    // format, as other sections
+    let mut sync = reloaded.sync;
+    sync.set_dynamic(old);
+    if changed(&running.sync, &sync) {
+        report.rejected.push("sync".into());
+    }
+    let mut router = reloaded.router.clone();
+    router.set_dynamic(old_router);
+    if changed(&running.router, &router) {
+        report.rejected.push("router".into());
+    }
+
+    let sections = [
+        changed(&running.network_peer_db, &reloaded.network_peer_db),
+        changed(
+            &running.network_peer_manager,
+            &reloaded.network_peer_manager,
+        ),
+        changed(&running.file_location_cache, &reloaded.file_location_cache),
+        changed(&running.rpc, &reloaded.rpc),
+        changed(&running.log, &reloaded.log),
+        changed(&running.metrics, &reloaded.metrics)
+            || running.metrics_listen != reloaded.metrics_listen,
+    ];
+    for (section, changed) in STATIC_SECTIONS.iter().zip(sections) {
+        if changed {
+            report.rejected.push(section.to_string());
+        }
+    }
+
+    report
+}
+
+fn changed<T: Debug>(running: &T, reloaded: &T) -> bool {
+    format!("{:?}", running) != format!("{:?}", reloaded)
+}
+
+/// Reloads the config file on SIGHUP.
+#[cfg(target_family = "unix")]
+pub fn reload_on_sighup(executor: &TaskExecutor, watcher: Arc<ConfigWatcher>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    executor.spawn(
+        async move {
+            let mut hup = match signal(SignalKind::hangup()) {
+                Ok(hup) => hup,
+                Err(e) => {
+                    error!(error = %e, "Could not register SIGHUP handler");
+                    return;
+                }
+            };
+
+            while hup.recv().await.is_some() {
+                match watcher.reload() {
+                    Ok(report) if report.rejected.is_empty() => {
+                        info!(%report, "Config reloaded on SIGHUP")
+                    }
+                    Ok(report) => warn!(%report, "Config reloaded on SIGHUP, but some rejected"),
+                    Err(e) => error!(%e, "Failed to reload config on SIGHUP"),
+                }
+            }
+        },
+        "config_reload",
+    );
+}
+
+#[cfg(not(target_family = "unix"))]
+pub fn reload_on_sighup(_executor: &TaskExecutor, _watcher: Arc<ConfigWatcher>) {}
@@ -0,0 +1,594 @@
+//! Offline inspection of the db of a stopped node via `db` subcommands, which read through the
+//! log store opened read-only, so that the values are decoded the same way as the node does.
+//! The flow merkle tree is only loaded by the subcommands that need it.
+//! Besides, `db migrate-layout` copies the db into the other layout.
+
+use crate::config::ZgsConfig;
+use clap::ArgMatches;
+use serde::Serialize;
+use shared_types::{DataRoot, Transaction};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+use storage::log_store::merkle_state::{diff_merkle_states, MerkleNodeDiff, MerkleState};
+use storage::log_store::tx_store::TxStatus;
+use storage::log_store::LogStoreRead;
+use storage::{migrate_db_layout, LogManager, StoreHandles, H256};
+
+/// Number of txs loaded from store at a time on export.
+const EXPORT_BATCH_SIZE: usize = 1024;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ColumnInfo {
+    db: &'static str,
+    column: &'static str,
+    num_keys: u64,
+    /// `None` if not supported by the db engine.
+    size_bytes: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TxInfo {
+    tx: Transaction,
+    status: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TxSeqInfo {
+    seq: u64,
+    status: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RootInfo {
+    root: DataRoot,
+    txs: Vec<TxSeqInfo>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockInfo {
+    number: u64,
+    hash: H256,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProgressInfo {
+    sync_progress: Option<BlockInfo>,
+    latest_block_number: Option<u64>,
+    next_tx_seq: u64,
+    flow_root: DataRoot,
+    flow_length: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportInfo {
+    file: String,
+    from: u64,
+    to: u64,
+    exported: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MerkleStateInfo {
+    file: String,
+    flow_root: Option<DataRoot>,
+    flow_length: u64,
+    flow_version: u64,
+    size: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MerkleStateDiffInfo {
+    left: MerkleStateInfo,
+    right: MerkleStateInfo,
+    /// `None` if the trees are the same.
+    first_diff: Option<MerkleNodeDiff>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MigrationInfo {
+    layout: &'static str,
+    paths: Vec<String>,
+    num_keys: u64,
+}
+
+/// Runs the `db` subcommand on the configured db, which requires the node to be stopped, and
+/// prints the result in JSON.
+pub fn run(config: &ZgsConfig, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    // Exported states are diffed without the db, e.g. exported from other nodes.
+    if let Some(("diff-merkle-state", matches)) = matches.subcommand() {
+        let left = parse_arg::<String>(matches, "left")?;
+        let right = parse_arg::<String>(matches, "right")?;
+        let stdout = std::io::stdout();
+        return write_json(&mut stdout.lock(), &diff_merkle_state(left, right)?);
+    }
+
+    let storage_config = config.storage_config()?;
+    if let Some(("migrate-layout", _)) = matches.subcommand() {
+        let migration = migrate_db_layout(
+            storage_config.db_engine,
+            storage_config.db_layout,
+            &storage_config.db_dir,
+        )
+        .map_err(|e| format!("Failed to migrate db: {:?}", e))?;
+        let stdout = std::io::stdout();
+        return write_json(
+            &mut stdout.lock(),
+            &MigrationInfo {
+                layout: migration.layout.name(),
+                paths: migration
+                    .paths
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect(),
+                num_keys: migration.num_keys,
+            },
+        );
+    }
+
+    let store = StoreHandles::open_offline(
+        storage_config.db_engine,
+        storage_config.db_layout,
+        &storage_config.db_dir,
+    )
+    .and_then(|db| LogManager::with_dbs_deferred(db, storage_config.log_config))
+    .map_err(|e| format!("Unable to open store: {:?}", e))?;
+
+    let stdout = std::io::stdout();
+    run_command(&store, matches, stdout.lock())
+}
+
+/// Runs the `db` subcommand of `matches` on `store`, and writes the result in JSON.
+fn run_command(
+    store: &LogManager,
+    matches: &ArgMatches,
+    mut writer: impl Write,
+) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("stats", _)) => write_json(&mut writer, &stats(store)?),
+        Some(("get-tx", matches)) => {
+            let seq = parse_arg::<u64>(matches, "seq")?;
+            write_json(&mut writer, &get_tx(store, seq)?)
+        }
+        Some(("get-root", matches)) => {
+            let root = parse_arg::<DataRoot>(matches, "root")?;
+            write_json(&mut writer, &get_root(store, root)?)
+        }
+        Some(("progress", _)) => {
+            load_flow_tree(store)?;
+            write_json(&mut writer, &progress(store)?)
+        }
+        Some(("export-tx-range", matches)) => {
+            let from = parse_arg::<u64>(matches, "from")?;
+            let to = parse_arg::<u64>(matches, "to")?;
+            let file = parse_arg::<String>(matches, "file")?;
+            let file_writer = BufWriter::new(File::create(&file)?);
+            let exported = export_tx_range(store, from, to, file_writer)?;
+            write_json(
+                &mut writer,
+                &ExportInfo {
+                    file,
+                    from,
+                    to,
+                    exported,
+                },
+            )
+        }
+        Some(("export-merkle-state", matches)) => {
+            let file = parse_arg::<String>(matches, "file")?;
+            load_flow_tree(store)?;
+            write_json(&mut writer, &export_merkle_state(store, file)?)
+        }
+        Some((name, _)) => Err(format!("Unknown db subcommand {}", name).into()),
+        None => Err("db subcommand not specified".into()),
+    }
+}
+
+fn load_flow_tree(store: &LogManager) -> Result<(), Box<dyn Error>> {
+    if !store.is_flow_tree_ready() {
+        store
+            .load_flow_tree()
+            .map_err(|e| format!("Unable to load flow merkle tree: {:?}", e))?;
+    }
+    Ok(())
+}
+
+fn parse_arg<T: FromStr>(matches: &ArgMatches, name: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Debug,
+{
+    let value = matches
+        .get_one::<String>(name)
+        .ok_or_else(|| format!("{} not specified", name))?;
+    value
+        .parse()
+        .map_err(|e| format!("Invalid {} {}: {:?}", name, value, e))
+}
+
+fn write_json(writer: &mut impl Write, value: &impl Serialize) -> Result<(), Box<dyn Error>> {
+    serde_json::to_writer_pretty(&mut *writer, value)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+fn status_name(status: Option<TxStatus>) -> Option<&'static str> {
+    status.map(|status| match status {
+        TxStatus::Finalized => "finalized",
+        TxStatus::Pruned => "pruned",
+        TxStatus::ShardFinalized => "shardFinalized",
+        TxStatus::Invalid => "invalid",
+    })
+}
+
+fn stats(store: &LogManager) -> Result<Vec<ColumnInfo>, Box<dyn Error>> {
+    let stats = store.get_db_column_stats()?;
+    let sizes = store.get_db_column_sizes()?;
+    Ok(stats
+        .into_iter()
+        .zip(sizes)
+        .map(|(stats, size_bytes)| ColumnInfo {
+            db: stats.db,
+            column: stats.column,
+            num_keys: stats.num_keys,
+            size_bytes,
+        })
+        .collect())
+}
+
+fn get_tx(store: &LogManager, seq: u64) -> Result<TxInfo, Box<dyn Error>> {
+    let tx = store
+        .get_tx_by_seq_number(seq)?
+        .ok_or_else(|| format!("tx {} not found", seq))?;
+    Ok(TxInfo {
+        tx,
+        status: status_name(store.get_tx_status(seq)?),
+    })
+}
+
+fn get_root(store: &LogManager, root: DataRoot) -> Result<RootInfo, Box<dyn Error>> {
+    let mut txs = vec![];
+    for seq in store.get_tx_seq_list_by_data_root(&root)? {
+        txs.push(TxSeqInfo {
+            seq,
+            status: status_name(store.get_tx_status(seq)?),
+        });
+    }
+    Ok(RootInfo { root, txs })
+}
+
+fn progress(store: &LogManager) -> Result<ProgressInfo, Box<dyn Error>> {
+    let (flow_root, flow_length) = store.get_context()?;
+    Ok(ProgressInfo {
+        sync_progress: store
+            .get_sync_progress()?
+            .map(|(number, hash)| BlockInfo { number, hash }),
+        latest_block_number: store.get_log_latest_block_number()?,
+        next_tx_seq: store.next_tx_seq(),
+        flow_root,
+        flow_length,
+    })
+}
+
+/// Exports the txs of seq in `[from, to)` along with their status in JSON lines, and returns
+/// the number of txs exported. The range is truncated to the stored txs.
+fn export_tx_range(
+    store: &LogManager,
+    from: u64,
+    to: u64,
+    mut writer: impl Write,
+) -> Result<u64, Box<dyn Error>> {
+    let to = to.min(store.next_tx_seq());
+    let mut seq = from;
+    while seq < to {
+        let limit = ((to - seq) as usize).min(EXPORT_BATCH_SIZE);
+        let txs = store.get_txs_with_status(seq, limit)?;
+        if txs.is_empty() {
+            break;
+        }
+        for (tx, status) in txs {
+            seq = tx.seq + 1;
+            serde_json::to_writer(
+                &mut writer,
+                &TxInfo {
+                    tx,
+                    status: status_name(status),
+                },
+            )?;
+            writeln!(writer)?;
+        }
+    }
+    writer.flush()?;
+    Ok(seq.saturating_sub(from))
+}
+
+fn merkle_state_info(file: String, state: &MerkleState, size: usize) -> MerkleStateInfo {
+    MerkleStateInfo {
+        file,
+        flow_root: state.flow_root(),
+        flow_length: state.flow_length,
+        flow_version: state.flow_version,
+        size: size as u64,
+    }
+}
+
+fn export_merkle_state(
+    store: &LogManager,
+    file: String,
+) -> Result<MerkleStateInfo, Box<dyn Error>> {
+    let state = store.get_merkle_state(usize::MAX)?;
+    let data = state.to_bytes();
+    std::fs::write(&file, &data)?;
+    Ok(merkle_state_info(file, &state, data.len()))
+}
+
+fn diff_merkle_state(left: String, right: String) -> Result<MerkleStateDiffInfo, Box<dyn Error>> {
+    let load = |file: String| -> Result<(MerkleStateInfo, MerkleState), Box<dyn Error>> {
+        let data = std::fs::read(&file).map_err(|e| format!("Unable to read {}: {:?}", file, e))?;
+        let state = MerkleState::from_bytes(&data)
+            .map_err(|e| format!("Invalid merkle state {}: {:?}", file, e))?;
+        Ok((merkle_state_info(file, &state, data.len()), state))
+    };
+    let (left, left_state) = load(left)?;
+    let (right, right_state) = load(right)?;
+    Ok(MerkleStateDiffInfo {
+        left,
+        right,
+        first_diff: diff_merkle_states(&left_state, &right_state),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::cli_app;
+    use shared_types::{ChunkArray, TransactionBuilder, CHUNK_SIZE};
+    use storage::log_store::log_manager::{
+        sub_merkle_tree, tx_subtree_root_list_padded, LogConfig,
+    };
+    use storage::log_store::{LogStoreChunkWrite, LogStoreWrite};
+    use storage::DbLayout;
+
+    /// Store of 3 txs, of which the first 2 of the same data are finalized.
+    fn prepare_store() -> LogManager {
+        prepare_store_of(8)
+    }
+
+    /// Same as `prepare_store`, but the last tx is filled with `last_byte`.
+    fn prepare_store_of(last_byte: u8) -> LogManager {
+        let store = LogManager::with_dbs(
+            StoreHandles::memorydb(DbLayout::Split),
+            LogConfig::default(),
+        )
+        .unwrap();
+        for seq in 0..3 {
+            let data = vec![if seq < 2 { 7u8 } else { last_byte }; 2 * CHUNK_SIZE];
+            let merkle_nodes = tx_subtree_root_list_padded(&data);
+            let first_tree_size = 1 << (merkle_nodes[0].0 - 1);
+            let flow_len = store.get_context().unwrap().1;
+            let tx = TransactionBuilder::new(seq)
+                .size(data.len() as u64)
+                .data_merkle_root(sub_merkle_tree(&data).unwrap().root().into())
+                .start_entry_index(flow_len.div_ceil(first_tree_size) * first_tree_size)
+                .merkle_nodes(merkle_nodes)
+                .build()
+                .unwrap();
+            store.put_tx(tx).unwrap();
+            // The tx of the same data as a finalized one is finalized on put.
+            if seq == 0 {
+                let chunks = ChunkArray {
+                    data,
+                    start_index: 0,
+                };
+                store.put_chunks(seq, chunks).unwrap();
+                store.finalize_tx(seq).unwrap();
+            }
+        }
+        store
+            .put_sync_progress((10, H256::from_low_u64_be(10), None))
+            .unwrap();
+        store
+    }
+
+    fn run_json(store: &LogManager, args: &[&str]) -> serde_json::Value {
+        let matches = cli_app()
+            .try_get_matches_from(["zgs_node", "-c", "config.toml", "db"].iter().chain(args))
+            .unwrap();
+        let mut output = vec![];
+        run_command(
+            store,
+            matches.subcommand_matches("db").unwrap(),
+            &mut output,
+        )
+        .unwrap();
+        serde_json::from_slice(&output).unwrap()
+    }
+
+    #[test]
+    fn test_db_stats() {
+        let store = prepare_store();
+        let stats = run_json(&store, &["stats"]);
+        let tx_column = stats
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|c| c["db"] == "flow" && c["column"] == "tx")
+            .unwrap();
+        assert_eq!(tx_column["numKeys"], 3);
+        assert!(tx_column["sizeBytes"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_db_offline_deferred() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db =
+            StoreHandles::open(storage::DbEngine::RocksDb, DbLayout::Split, dir.path()).unwrap();
+        let store = LogManager::with_dbs(db, LogConfig::default()).unwrap();
+        let data = vec![7u8; 2 * CHUNK_SIZE];
+        let tx = TransactionBuilder::new(0)
+            .size(data.len() as u64)
+            .data_merkle_root(sub_merkle_tree(&data).unwrap().root().into())
+            .merkle_nodes(tx_subtree_root_list_padded(&data))
+            .build()
+            .unwrap();
+        store.put_tx(tx).unwrap();
+        let flow_root = store.get_context().unwrap().0;
+        drop(store);
+
+        let db =
+            StoreHandles::open_offline(storage::DbEngine::RocksDb, DbLayout::Split, dir.path())
+                .unwrap();
+        let store = LogManager::with_dbs_deferred(db, LogConfig::default()).unwrap();
+        assert_eq!(run_json(&store, &["get-tx", "0"])["tx"]["seq"], 0);
+        assert!(!store.is_flow_tree_ready());
+
+        let stats = run_json(&store, &["stats"]);
+        let tx_column = stats
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|c| c["db"] == "flow" && c["column"] == "tx")
+            .unwrap();
+        assert!(tx_column["sizeBytes"].as_u64().unwrap() > 0);
+
+        let progress = run_json(&store, &["progress"]);
+        assert_eq!(progress["flowRoot"], serde_json::json!(flow_root));
+        assert!(store.is_flow_tree_ready());
+        // nothing is written under the db dir
+        assert!(!dir.path().join("flow_db.secondary").exists());
+    }
+
+    #[test]
+    fn test_db_get_tx_and_root() {
+        let store = prepare_store();
+        let tx = run_json(&store, &["get-tx", "1"]);
+        assert_eq!(tx["tx"]["seq"], 1);
+        assert_eq!(tx["status"], "finalized");
+
+        let root = store
+            .get_tx_by_seq_number(0)
+            .unwrap()
+            .unwrap()
+            .data_merkle_root;
+        let info = run_json(&store, &["get-root", &format!("{:?}", root)]);
+        assert_eq!(
+            info["txs"],
+            serde_json::json!([
+                {"seq": 0, "status": "finalized"},
+                {"seq": 1, "status": "finalized"},
+            ])
+        );
+
+        let matches = cli_app()
+            .try_get_matches_from(["zgs_node", "-c", "config.toml", "db", "get-tx", "3"])
+            .unwrap();
+        assert!(run_command(&store, matches.subcommand_matches("db").unwrap(), vec![]).is_err());
+    }
+
+    #[test]
+    fn test_db_progress() {
+        let store = prepare_store();
+        let progress = run_json(&store, &["progress"]);
+        assert_eq!(progress["syncProgress"]["number"], 10);
+        assert_eq!(progress["nextTxSeq"], 3);
+        assert_eq!(
+            progress["flowLength"].as_u64().unwrap(),
+            store.get_context().unwrap().1
+        );
+    }
+
+    #[test]
+    fn test_db_export_tx_range() {
+        let store = prepare_store();
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("txs.jsonl");
+        let info = run_json(
+            &store,
+            &["export-tx-range", "1", "10", file.to_str().unwrap()],
+        );
+        assert_eq!(info["exported"], 2);
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&file)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["tx"]["seq"], 1);
+        assert_eq!(lines[0]["status"], "finalized");
+        assert_eq!(lines[1]["tx"]["seq"], 2);
+        assert_eq!(lines[1]["status"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_db_diff_merkle_state() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let export = |store: &LogManager, name: &str| {
+            let file = dir.path().join(name).to_str().unwrap().to_string();
+            let info = run_json(store, &["export-merkle-state", &file]);
+            assert_eq!(
+                info["flowRoot"],
+                serde_json::json!(store.get_context().unwrap().0)
+            );
+            file
+        };
+        let store = prepare_store();
+        let left = export(&store, "left.ssz");
+        let same = export(&prepare_store(), "same.ssz");
+        // the last tx diverged
+        let right = export(&prepare_store_of(9), "right.ssz");
+
+        let diff = serde_json::to_value(diff_merkle_state(left.clone(), same).unwrap()).unwrap();
+        assert_eq!(diff["firstDiff"], serde_json::Value::Null);
+        assert_eq!(diff["left"]["flowLength"], diff["right"]["flowLength"]);
+
+        let diff = serde_json::to_value(diff_merkle_state(left, right).unwrap()).unwrap();
+        let first_diff = &diff["firstDiff"];
+        assert_eq!(first_diff["tree"], "lastChunk");
+        // the differing node covers the entries of the last tx
+        let start_entry_index = store
+            .get_tx_by_seq_number(2)
+            .unwrap()
+            .unwrap()
+            .start_entry_index;
+        let (layer, index) = (
+            first_diff["layer"].as_u64().unwrap(),
+            first_diff["index"].as_u64().unwrap(),
+        );
+        assert_eq!(index << layer, start_entry_index);
+        assert_eq!(
+            first_diff["path"].as_array().unwrap().last().unwrap(),
+            index
+        );
+        assert_ne!(first_diff["left"], first_diff["right"]);
+
+        assert!(diff_merkle_state(
+            dir.path().join("missing").display().to_string(),
+            String::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_db_read_only_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db =
+            StoreHandles::open(storage::DbEngine::RocksDb, DbLayout::Split, dir.path()).unwrap();
+        drop(LogManager::with_dbs(db, LogConfig::default()).unwrap());
+
+        let db =
+            StoreHandles::open(storage::DbEngine::RocksDb, DbLayout::Split, dir.path()).unwrap();
+        let store = LogManager::with_dbs(db.into_read_only(), LogConfig::default()).unwrap();
+        assert_eq!(run_json(&store, &["progress"])["nextTxSeq"], 0);
+    }
+}
@@ -1,24 +1,101 @@
+use serde::Deserialize;
+use std::sync::Arc;
 use task_executor::TaskExecutor;
-use tracing::Level;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
 
 const LOG_RELOAD_PERIOD_SEC: u64 = 30;
 
-pub fn configure(log_level_file: &str, log_directory: &str, executor: TaskExecutor) {
-    let file_appender = tracing_appender::rolling::daily(log_directory, "zgs.log");
-    let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, including fields of the current span and its parents, e.g.
+    /// `peer_id` and `tx_seq` of file sync.
+    Json,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    pub format: LogFormat,
+}
+
+type ReloadFn = dyn Fn(EnvFilter) -> Result<(), String> + Send + Sync;
+
+/// Swaps the log filter at runtime.
+#[derive(Clone)]
+pub struct LogFilterHandle(Arc<ReloadFn>);
+
+impl LogFilterHandle {
+    /// Applies the filter, e.g. `sync=debug,storage=info`. The current filter is kept if the
+    /// filter is invalid.
+    pub fn set_filter(&self, filter: &str) -> Result<(), String> {
+        let filter =
+            EnvFilter::try_new(filter).map_err(|e| format!("Invalid log filter: {}", e))?;
+        (self.0)(filter)
+    }
+}
+
+impl rpc::LogFilterSetter for LogFilterHandle {
+    fn set_log_filter(&self, filter: &str) -> Result<(), String> {
+        self.set_filter(filter)
+    }
+}
+
+/// Builds the subscriber of the specified format, and the handle to swap the filter.
+fn build<W>(format: LogFormat, writer: W) -> (Box<dyn Subscriber + Send + Sync>, LogFilterHandle)
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
     let builder = tracing_subscriber::fmt()
         .with_max_level(Level::TRACE)
         .with_env_filter(EnvFilter::default())
-        .with_writer(non_blocking)
-        .with_ansi(false)
+        .with_writer(writer)
         // .with_file(true)
         // .with_line_number(true)
         // .with_thread_names(true)
-        .with_filter_reloading();
+        .with_ansi(false);
 
-    let handle = builder.reload_handle();
-    builder.init();
+    match format {
+        LogFormat::Text => {
+            let builder = builder.with_filter_reloading();
+            let handle = builder.reload_handle();
+            let reload = move |filter: EnvFilter| handle.reload(filter).map_err(|e| e.to_string());
+            (
+                Box::new(builder.finish()),
+                LogFilterHandle(Arc::new(reload)),
+            )
+        }
+        LogFormat::Json => {
+            let builder = builder
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_filter_reloading();
+            let handle = builder.reload_handle();
+            let reload = move |filter: EnvFilter| handle.reload(filter).map_err(|e| e.to_string());
+            (
+                Box::new(builder.finish()),
+                LogFilterHandle(Arc::new(reload)),
+            )
+        }
+    }
+}
+
+pub fn configure(
+    config: &LogConfig,
+    log_level_file: &str,
+    log_directory: &str,
+    executor: TaskExecutor,
+) -> LogFilterHandle {
+    let file_appender = tracing_appender::rolling::daily(log_directory, "zgs.log");
+    let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+    let (subscriber, handle) = build(config.format, non_blocking);
+    subscriber.init();
 
     let level_file = log_level_file.trim_end().to_string();
 
@@ -27,9 +104,12 @@ pub fn configure(log_level_file: &str, log_directory: &str, executor: TaskExecut
         .unwrap_or_default()
         .trim_end()
         .to_string();
-    let _ = handle.reload(&config);
+    if let Err(e) = handle.set_filter(&config) {
+        println!("Failed to load log config: {}", e);
+    }
 
     // periodically check for config changes
+    let reload_handle = handle.clone();
     executor.spawn(
         async move {
             // move the log writer guard so that it's not dropped.
@@ -57,7 +137,7 @@ pub fn configure(log_level_file: &str, log_directory: &str, executor: TaskExecut
 
                 println!("Updating log config to {:?}", new_config);
 
-                match handle.reload(&new_config) {
+                match reload_handle.set_filter(&new_config) {
                     Ok(()) => config = new_config,
                     Err(e) => {
                         println!("Failed to load new config: {:?}", e);
@@ -67,4 +147,111 @@ pub fn configure(log_level_file: &str, log_directory: &str, executor: TaskExecut
         },
         "log_reload",
     );
+
+    handle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct TestWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for TestWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl TestWriter {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    fn build_with_writer(
+        format: LogFormat,
+    ) -> (
+        Box<dyn Subscriber + Send + Sync>,
+        LogFilterHandle,
+        TestWriter,
+    ) {
+        let writer = TestWriter::default();
+        let make_writer = writer.clone();
+        let (subscriber, handle) = build(format, move || make_writer.clone());
+        (subscriber, handle, writer)
+    }
+
+    #[test]
+    fn test_json_format() {
+        let (subscriber, handle, writer) = build_with_writer(LogFormat::Json);
+        handle.set_filter("info").unwrap();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = info_span!("file_sync", tx_seq = 3).entered();
+            info!(peer_id = "peer", "Received chunks response");
+        });
+
+        let lines = writer.lines();
+        assert_eq!(lines.len(), 1);
+        let log: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(log["level"], "INFO");
+        assert_eq!(log["message"], "Received chunks response");
+        assert_eq!(log["peer_id"], "peer");
+        assert_eq!(log["span"]["name"], "file_sync");
+        assert_eq!(log["span"]["tx_seq"], 3);
+    }
+
+    #[test]
+    fn test_text_format() {
+        let (subscriber, handle, writer) = build_with_writer(LogFormat::Text);
+        handle.set_filter("info").unwrap();
+
+        tracing::subscriber::with_default(subscriber, || {
+            info!(tx_seq = 3, "File synced");
+        });
+
+        let lines = writer.lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("INFO"));
+        assert!(lines[0].contains("File synced tx_seq=3"));
+        assert!(serde_json::from_str::<serde_json::Value>(&lines[0]).is_err());
+    }
+
+    #[test]
+    fn test_set_filter() {
+        let (subscriber, handle, writer) = build_with_writer(LogFormat::Text);
+        handle.set_filter("sync=debug").unwrap();
+
+        tracing::subscriber::with_default(subscriber, || {
+            debug!(target: "sync", "sync debug 1");
+            debug!(target: "storage", "storage debug 1");
+
+            // invalid filter rejected, and the current one kept
+            assert!(handle.set_filter("sync=verbose").is_err());
+            debug!(target: "sync", "sync debug 2");
+
+            handle.set_filter("sync=info,storage=debug").unwrap();
+            debug!(target: "sync", "sync debug 3");
+            debug!(target: "storage", "storage debug 3");
+        });
+
+        let lines = writer.lines();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("sync debug 1"));
+        assert!(lines[1].ends_with("sync debug 2"));
+        assert!(lines[2].ends_with("storage debug 3"));
+    }
 }
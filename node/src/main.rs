@@ -4,15 +4,33 @@ extern crate tracing;
 mod cli;
 mod client;
 mod config;
+mod db;
 mod log;
 
+use crate::config::reload::{self, ConfigWatcher};
 use crate::config::ZgsConfig;
+use crate::log::LogFilterHandle;
 use client::{Client, ClientBuilder, RuntimeContext};
 use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::Arc;
+use std::time::Duration;
+use storage::config::StoreNetworkId;
+use storage::log_store::check;
+use storage::{LogManager, StoreHandles};
 
-async fn start_node(context: RuntimeContext, config: ZgsConfig) -> Result<Client, String> {
+async fn start_node(
+    context: RuntimeContext,
+    config: ZgsConfig,
+    config_watcher: Arc<ConfigWatcher>,
+    log_filter: LogFilterHandle,
+    force_reinit: bool,
+) -> Result<Client, String> {
     let network_config = config.network_config().await?;
-    let storage_config = config.storage_config()?;
+    let mut storage_config = config.storage_config()?;
+    storage_config.network_id = Some(StoreNetworkId::from(&network_config.network_id));
+    storage_config.force_reinit = force_reinit;
     let log_sync_config = config.log_sync_config()?;
     let chunk_pool_config = config.chunk_pool_config()?;
     let miner_config = config.mine_config()?;
@@ -22,7 +40,10 @@ async fn start_node(context: RuntimeContext, config: ZgsConfig) -> Result<Client
 
     ClientBuilder::default()
         .with_runtime_context(context)
-        .with_rocksdb_store(&storage_config)?
+        .with_config_watcher(config_watcher)
+        .with_log_filter(log_filter)
+        .with_store(&storage_config)?
+        .with_known_peers(router_config.max_known_peers)?
         .with_log_sync(log_sync_config)
         .await?
         .with_file_location_cache(config.file_location_cache)
@@ -40,10 +61,48 @@ async fn start_node(context: RuntimeContext, config: ZgsConfig) -> Result<Client
         .await?
         .with_rpc(config.rpc)
         .await?
+        .with_metrics_exporter(config.metrics_listen)?
         .with_router(router_config)?
         .build()
 }
 
+/// Exports the synced log entries from db, which requires the node to be stopped.
+fn export_log_entries(config: &ZgsConfig, file: &str) -> Result<(), Box<dyn Error>> {
+    let storage_config = config.storage_config()?;
+    let store = StoreHandles::open(
+        storage_config.db_engine,
+        storage_config.db_layout,
+        &storage_config.db_dir,
+    )
+    .and_then(|db| LogManager::with_dbs(db, storage_config.log_config))
+    .map_err(|e| format!("Unable to open store: {:?}", e))?;
+
+    let writer = BufWriter::new(File::create(file)?);
+    let num = log_entry_sync::export_log_entries(&store, writer)
+        .map_err(|e| format!("Failed to export log entries: {:?}", e))?;
+    println!("Exported {} transactions to {}", num, file);
+
+    Ok(())
+}
+
+/// Checks the integrity of db, which requires the node to be stopped, and exits with the
+/// exit code of check report.
+fn check_db(config: &ZgsConfig, repair: bool) -> Result<(), Box<dyn Error>> {
+    let storage_config = config.storage_config()?;
+    let shard_config = config.shard_config()?;
+    let report = check::check_db_at(
+        storage_config.db_engine,
+        storage_config.db_layout,
+        &storage_config.db_dir,
+        storage_config.log_config,
+        shard_config,
+        repair,
+    );
+    println!("{}", report);
+
+    std::process::exit(report.exit_code());
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     // Only allow 64-bit targets for compilation, since there are many
     // type conversions between `usize` and `u64`, or even use `usize`
@@ -65,18 +124,40 @@ fn main() -> Result<(), Box<dyn Error>> {
     // CLI, config, and logs
     let matches = cli::cli_app().get_matches();
     let config = ZgsConfig::parse(&matches)?;
+    if let Some(file) = matches.get_one::<String>("export-log-entries") {
+        return export_log_entries(&config, file);
+    }
+    if let Some(matches) = matches.subcommand_matches("check") {
+        return check_db(&config, matches.get_flag("repair"));
+    }
+    if let Some(matches) = matches.subcommand_matches("db") {
+        return db::run(&config, matches);
+    }
     metrics::initialize(config.metrics.clone());
-    log::configure(
+    let log_filter = log::configure(
+        &config.log,
         &config.log_config_file,
         &config.log_directory,
         executor.clone(),
     );
 
     // start services
+    let grace_period = Duration::from_secs(config.shutdown_grace_period_secs);
+    let force_reinit = matches.get_flag("force-reinit");
+    let config_watcher = Arc::new(ConfigWatcher::new(matches.clone(), config.clone())?);
+    reload::reload_on_sighup(&executor, config_watcher.clone());
     executor.clone().spawn(
         async move {
             info!("Starting services...");
-            if let Err(e) = start_node(context.clone(), config).await {
+            if let Err(e) = start_node(
+                context.clone(),
+                config,
+                config_watcher,
+                log_filter,
+                force_reinit,
+            )
+            .await
+            {
                 error!(reason = %e, "Failed to start zgs node");
                 // Ignore the error since it always occurs during normal operation when
                 // shutting down.
@@ -97,6 +178,14 @@ fn main() -> Result<(), Box<dyn Error>> {
     let shutdown_reason = environment.block_until_shutdown_requested()?;
     info!(reason = ?shutdown_reason, "Shutting down...");
 
+    // Services stop accepting new work and flush pending writes before all tasks cancelled.
+    let summary = environment.shutdown_services(grace_period);
+    if summary.is_clean() {
+        info!(%summary, "All services shut down");
+    } else {
+        warn!(%summary, "Some services not shut down gracefully");
+    }
+
     environment.fire_signal();
 
     // Shutdown the environment once all tasks have completed.
@@ -1,23 +1,84 @@
 #[macro_use]
 extern crate tracing;
 
+mod metrics;
+
 use anyhow::bail;
+use futures::stream::{self, Stream};
 use shared_types::{
     Chunk, ChunkArray, ChunkArrayWithProof, DataRoot, FlowProof, FlowRangeProof, Transaction,
+    CHUNK_SIZE,
 };
 use ssz::{Decode, Encode};
-use std::sync::Arc;
-use storage::{error, error::Result, log_store::Store as LogStore, H256};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use storage::{error::Result, log_store::Store as LogStore, H256};
 use task_executor::TaskExecutor;
 use tokio::sync::oneshot;
+use tokio::time::Instant;
 
 pub use storage::config::ShardConfig;
+use storage::log_store::audit::{AuditReport, FinalizedAudit};
 use storage::log_store::config::ConfigurableExt;
-use storage::log_store::{MineLoadChunk, SealAnswer, SealTask};
+pub use storage::log_store::job_store::AdminJob;
+use storage::log_store::log_manager::bytes_to_entries;
+pub use storage::log_store::merkle_state::MerkleState;
+pub use storage::log_store::reward_store::{MinerReward, MinerRewardSummary};
+use storage::log_store::tx_store::TxStatus;
+pub use storage::log_store::tx_store::{
+    BlockHashAndSubmissionIndex, ChunkRange, FlushJournal, SubmissionContext,
+};
+use storage::log_store::{ColumnStats, MineLoadChunk, SealAnswer, SealTask, SealedChunkWithProof};
 
 /// The name of the worker tokio tasks.
 const WORKER_TASK_NAME: &str = "async_storage_worker";
 
+/// Number of operations spawned but not started yet, of all stores.
+static QUEUED_OPERATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Errors of the async storage layer itself, which callers could `downcast_ref` from the
+/// returned `anyhow::Error`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Operation not completed before the deadline. It is skipped if not started yet, e.g. the
+    /// blocking pool is saturated, otherwise it completes in background.
+    Timeout,
+    /// Worker task dropped without result, e.g. the runtime is shutting down.
+    WorkerDropped,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Timeout => write!(f, "Storage operation timed out"),
+            Error::WorkerDropped => write!(f, "Storage worker dropped without result"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Counts an operation as queued until started or dropped without being started, e.g. the
+/// runtime is shutting down.
+struct QueuedOperation;
+
+impl QueuedOperation {
+    fn new() -> Self {
+        let queued = QUEUED_OPERATIONS.fetch_add(1, Ordering::SeqCst) + 1;
+        metrics::QUEUED_OPERATIONS.update(queued);
+        QueuedOperation
+    }
+}
+
+impl Drop for QueuedOperation {
+    fn drop(&mut self) {
+        let queued = QUEUED_OPERATIONS.fetch_sub(1, Ordering::SeqCst) - 1;
+        metrics::QUEUED_OPERATIONS.update(queued);
+    }
+}
+
 macro_rules! delegate {
     (fn $name:tt($($v:ident: $t:ty),*)) => {
         delegate!($name($($v: $t),*) -> ());
@@ -30,6 +91,14 @@ macro_rules! delegate {
     };
 }
 
+/// Finalizations requested but not persisted yet, which are persisted in one write.
+#[derive(Default)]
+struct FinalizeQueue {
+    pending: Vec<(u64, H256, oneshot::Sender<Result<bool>>)>,
+    /// Whether a worker is persisting the requested finalizations.
+    flushing: bool,
+}
+
 #[derive(Clone)]
 pub struct Store {
     /// Log and transaction storage.
@@ -37,27 +106,168 @@ pub struct Store {
 
     /// Tokio executor for spawning worker tasks.
     executor: TaskExecutor,
+
+    /// Timeout of every operation, if any.
+    timeout: Option<Duration>,
+
+    /// Deadline of all operations, if any.
+    deadline: Option<Instant>,
+
+    /// Finalizations to persist in batch, shared by all the clones.
+    finalize_queue: Arc<Mutex<FinalizeQueue>>,
 }
 
 impl Store {
     pub fn new(store: Arc<dyn LogStore>, executor: TaskExecutor) -> Self {
-        Store { store, executor }
+        Store {
+            store,
+            executor,
+            timeout: None,
+            deadline: None,
+            finalize_queue: Default::default(),
+        }
+    }
+
+    /// Returns a store of which every operation fails with `Error::Timeout` if not completed
+    /// within `timeout`.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Store {
+            timeout: Some(timeout),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a store of which all operations fail with `Error::Timeout` if not completed
+    /// before `deadline`, e.g. the deadline of a request.
+    pub fn with_deadline(&self, deadline: Instant) -> Self {
+        Store {
+            deadline: Some(deadline),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the earlier one of timeout and deadline for an operation started now.
+    fn operation_deadline(&self) -> Option<Instant> {
+        let timeout = self.timeout.map(|timeout| Instant::now() + timeout);
+        match (timeout, self.deadline) {
+            (Some(timeout), Some(deadline)) => Some(timeout.min(deadline)),
+            (timeout, deadline) => timeout.or(deadline),
+        }
     }
 
     delegate!(fn check_tx_completed(tx_seq: u64) -> Result<bool>);
     delegate!(fn check_tx_pruned(tx_seq: u64) -> Result<bool>);
+    delegate!(fn check_tx_invalid(tx_seq: u64) -> Result<bool>);
     delegate!(fn get_chunk_by_tx_and_index(tx_seq: u64, index: usize) -> Result<Option<Chunk>>);
     delegate!(fn get_chunks_by_tx_and_index_range(tx_seq: u64, index_start: usize, index_end: usize) -> Result<Option<ChunkArray>>);
+    delegate!(fn has_chunks_by_tx_and_index_range(tx_seq: u64, index_start: usize, index_end: usize) -> Result<bool>);
     delegate!(fn get_chunks_with_proof_by_tx_and_index_range(tx_seq: u64, index_start: usize, index_end: usize, merkle_tx_seq: Option<u64>) -> Result<Option<ChunkArrayWithProof>>);
     delegate!(fn get_tx_by_seq_number(seq: u64) -> Result<Option<Transaction>>);
     delegate!(fn put_chunks(tx_seq: u64, chunks: ChunkArray) -> Result<()>);
     delegate!(fn put_chunks_with_tx_hash(tx_seq: u64, tx_hash: H256, chunks: ChunkArray, maybe_file_proof: Option<FlowProof>) -> Result<bool>);
     delegate!(fn get_chunk_by_flow_index(index: u64, length: u64) -> Result<Option<ChunkArray>>);
+    delegate!(fn get_flow_entries_with_proof(index: u64, length: u64) -> Result<Option<(ChunkArrayWithProof, DataRoot, u64)>>);
+    delegate!(fn get_sealed_chunk_with_proof(chunk_index: u64) -> Result<Option<SealedChunkWithProof>>);
     delegate!(fn finalize_tx(tx_seq: u64) -> Result<()>);
     delegate!(fn prune_tx(tx_seq: u64) -> Result<()>);
+    delegate!(fn compact_pruned_data_roots() -> Result<usize>);
     delegate!(fn finalize_tx_with_hash(tx_seq: u64, tx_hash: H256) -> Result<bool>);
+    delegate!(fn confirm_finalization(next_tx_seq: u64) -> Result<()>);
+    delegate!(fn finalize_txs(tx_seqs: Vec<u64>) -> Result<()>);
     delegate!(fn get_proof_at_root(root: Option<DataRoot>, index: u64, length: u64) -> Result<FlowRangeProof>);
     delegate!(fn get_context() -> Result<(DataRoot, u64)>);
+    delegate!(fn get_txs_with_status(start_seq: u64, limit: usize) -> Result<Vec<(Transaction, Option<TxStatus>)>>);
+    delegate!(fn get_db_column_stats() -> Result<Vec<ColumnStats>>);
+    delegate!(fn get_block_hashes_from(from_block: u64, limit: usize) -> Result<Vec<(u64, BlockHashAndSubmissionIndex)>>);
+    delegate!(fn verify_tx_data(tx_seq: u64) -> Result<Vec<u64>>);
+    delegate!(fn verify_flow_range(start_index: u64, end_index: u64) -> Result<Vec<u64>>);
+    delegate!(fn get_merkle_state(max_size: usize) -> Result<MerkleState>);
+    delegate!(fn get_tx_seq_by_flow_index(index: u64) -> Result<Option<u64>>);
+    delegate!(fn get_tx_status(tx_seq: u64) -> Result<Option<TxStatus>>);
+    delegate!(fn put_flush_journal(journal: FlushJournal) -> Result<()>);
+    delegate!(fn get_flush_journals() -> Result<Vec<FlushJournal>>);
+    delegate!(fn remove_flush_journal(tx_seq: u64) -> Result<()>);
+    delegate!(fn get_miner_rewards(from_epoch: u64, to_epoch: u64) -> Result<Vec<MinerReward>>);
+    delegate!(fn get_miner_reward_summary() -> Result<MinerRewardSummary>);
+    delegate!(fn put_miner_reward(reward: MinerReward) -> Result<()>);
+    delegate!(fn revert_miner_rewards(block_number: u64) -> Result<usize>);
+    delegate!(fn get_admin_job(id: u64) -> Result<Option<AdminJob>>);
+    delegate!(fn get_admin_jobs() -> Result<Vec<AdminJob>>);
+    delegate!(fn put_admin_job(job: AdminJob) -> Result<()>);
+    delegate!(fn remove_admin_jobs(ids: Vec<u64>) -> Result<()>);
+    delegate!(fn get_submission_context(tx_seq: u64) -> Result<Option<SubmissionContext>>);
+
+    /// Same as `finalize_tx_with_hash`, but the finalizations requested concurrently, e.g. by
+    /// different sync tasks, are persisted in one write by `finalize_txs_with_hash`.
+    ///
+    /// Requests are accumulated while a batch is being persisted, so no delay is added to a
+    /// single finalization.
+    pub async fn finalize_tx_batched(&self, tx_seq: u64, tx_hash: H256) -> Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        let deadline = self.operation_deadline();
+        let start_flush = {
+            let mut queue = self.finalize_queue.lock().expect("not poisoned");
+            queue.pending.push((tx_seq, tx_hash, tx));
+            !std::mem::replace(&mut queue.flushing, true)
+        };
+        if start_flush {
+            self.spawn_finalize_flush();
+        }
+
+        let received = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, rx).await {
+                Ok(received) => received,
+                Err(_) => {
+                    metrics::TIMED_OUT_OPERATIONS.inc(1);
+                    bail!(Error::Timeout);
+                }
+            },
+            None => rx.await,
+        };
+
+        received.unwrap_or_else(|_| bail!(Error::WorkerDropped))
+    }
+
+    /// Persists the queued finalizations in batch until the queue is drained.
+    fn spawn_finalize_flush(&self) {
+        let store = self.store.clone();
+        let queue = self.finalize_queue.clone();
+        let queued = QueuedOperation::new();
+
+        self.executor.spawn_blocking(
+            move || {
+                drop(queued);
+                loop {
+                    let batch = {
+                        let mut queue = queue.lock().expect("not poisoned");
+                        if queue.pending.is_empty() {
+                            queue.flushing = false;
+                            return;
+                        }
+                        std::mem::take(&mut queue.pending)
+                    };
+
+                    let txs = batch.iter().map(|(seq, hash, _)| (*seq, *hash)).collect();
+                    match store.finalize_txs_with_hash(txs) {
+                        Ok(results) => {
+                            for ((_, _, sender), result) in batch.into_iter().zip(results) {
+                                let _ = sender.send(result);
+                            }
+                        }
+                        Err(e) => {
+                            // e.g. failed to write the statuses, so retry one by one to have the
+                            // error of each tx
+                            warn!("Failed to finalize txs in batch: {:?}", e);
+                            for (tx_seq, tx_hash, sender) in batch {
+                                let _ = sender.send(store.finalize_tx_with_hash(tx_seq, tx_hash));
+                            }
+                        }
+                    }
+                }
+            },
+            WORKER_TASK_NAME,
+        );
+    }
 
     pub async fn get_tx_seq_by_data_root(&self, data_root: &DataRoot) -> Result<Option<u64>> {
         let root = *data_root;
@@ -71,6 +281,86 @@ impl Store {
             .await
     }
 
+    pub async fn get_txs_by_data_roots(
+        &self,
+        data_roots: Vec<DataRoot>,
+    ) -> Result<Vec<Option<Transaction>>> {
+        self.spawn(move |store| store.get_txs_by_data_roots(&data_roots))
+            .await
+    }
+
+    /// Demotes the finalized txs of missing data, see [`storage::log_store::audit`].
+    pub async fn audit_finalized_txs(&self, audit: FinalizedAudit) -> Result<AuditReport> {
+        self.spawn(move |store| storage::log_store::audit::audit_finalized_txs(store, audit))
+            .await
+    }
+
+    /// Samples the stored chunks uniformly for the self-audit, see
+    /// [`storage::log_store::audit::sample_stored_chunks`].
+    pub async fn sample_stored_chunks(&self, samples: usize) -> Result<Vec<u64>> {
+        self.spawn(move |store| storage::log_store::audit::sample_stored_chunks(store, samples))
+            .await
+    }
+
+    /// Reads the data of a file in batches of `chunks_per_read` chunks, without the padding of
+    /// the last chunk. The stream fails if any chunk is not available, e.g. not finalized or
+    /// pruned, so the caller should check the tx status first.
+    pub fn read_file_stream(
+        &self,
+        tx: &Transaction,
+        chunks_per_read: usize,
+    ) -> impl Stream<Item = Result<Vec<u8>>> + Send + 'static {
+        self.read_file_stream_from(tx, 0, chunks_per_read)
+    }
+
+    /// Same as `read_file_stream`, but starts from the chunk at `start_chunk` of the file, e.g.
+    /// to resume an interrupted export.
+    pub fn read_file_stream_from(
+        &self,
+        tx: &Transaction,
+        start_chunk: usize,
+        chunks_per_read: usize,
+    ) -> impl Stream<Item = Result<Vec<u8>>> + Send + 'static {
+        let store = self.clone();
+        let tx_seq = tx.seq;
+        let size = tx.size as usize;
+        let num_chunks = bytes_to_entries(tx.size) as usize;
+        let chunks_per_read = chunks_per_read.max(1);
+
+        stream::try_unfold(start_chunk, move |start| {
+            let store = store.clone();
+            async move {
+                if start >= num_chunks {
+                    return Ok(None);
+                }
+
+                let end = num_chunks.min(start + chunks_per_read);
+                store
+                    .read_file_batch(tx_seq, size, start, end)
+                    .await
+                    .map(|data| Some((data, end)))
+            }
+        })
+    }
+
+    async fn read_file_batch(
+        &self,
+        tx_seq: u64,
+        size: usize,
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<u8>> {
+        let mut data = match self
+            .get_chunks_by_tx_and_index_range(tx_seq, start, end)
+            .await?
+        {
+            Some(chunks) => chunks.data,
+            None => bail!("chunks [{}, {}) of tx {} not available", start, end, tx_seq),
+        };
+        data.truncate(size - start * CHUNK_SIZE);
+        Ok(data)
+    }
+
     pub async fn get_config_decoded<K: AsRef<[u8]> + Send + Sync, T: Decode + Send + 'static>(
         &self,
         key: &K,
@@ -97,9 +387,10 @@ impl Store {
 
     pub async fn pull_seal_chunk(
         &self,
+        seal_index_min: usize,
         seal_index_max: usize,
     ) -> anyhow::Result<Option<Vec<SealTask>>> {
-        self.spawn(move |store| store.pull_seal_chunk(seal_index_max))
+        self.spawn(move |store| store.pull_seal_chunk(seal_index_min, seal_index_max))
             .await
     }
 
@@ -123,6 +414,26 @@ impl Store {
             .await
     }
 
+    pub async fn reset_tx_data(&self, tx_seq: u64, batch_list: &[u64]) -> Result<()> {
+        let batch_list = batch_list.to_vec();
+        self.spawn(move |store| store.reset_tx_data(tx_seq, &batch_list))
+            .await
+    }
+
+    pub async fn validate_range_proof(
+        &self,
+        tx_seq: u64,
+        data: ChunkArrayWithProof,
+    ) -> Result<bool> {
+        self.spawn(move |store| store.validate_range_proof(tx_seq, &data))
+            .await
+    }
+
+    pub async fn verify_flush_journal(&self, journal: FlushJournal) -> Result<Vec<ChunkRange>> {
+        self.spawn(move |store| store.verify_flush_journal(&journal))
+            .await
+    }
+
     pub async fn update_shard_config(&self, shard_config: ShardConfig) {
         self.spawn(move |store| {
             store.update_shard_config(shard_config);
@@ -139,21 +450,57 @@ impl Store {
     {
         let store = self.store.clone();
         let (tx, rx) = oneshot::channel();
+        let deadline = self.operation_deadline();
+        let queued = QueuedOperation::new();
+        // logs of the operation are correlated with the caller, e.g. an RPC call
+        let span = tracing::Span::current();
+        let submitted_at = Instant::now();
 
         self.executor.spawn_blocking(
             move || {
+                drop(queued);
+                let _entered = span.enter();
+
+                // Skip the operation if the caller has timed out or dropped the future, so as
+                // not to delay the operations queued behind.
+                if tx.is_closed() {
+                    metrics::CANCELLED_OPERATIONS.inc(1);
+                    debug!("Async storage operation cancelled before started");
+                    return;
+                }
+
                 // FIXME(zz): Not all functions need `write`. Refactor store usage.
                 let res = f(&*store);
+                trace!(elapsed = ?submitted_at.elapsed(), "Async storage operation completed");
 
                 if tx.send(res).is_err() {
-                    error!("Unable to complete async storage operation: the receiver dropped");
+                    debug!("Async storage operation completed after the receiver dropped");
                 }
             },
             WORKER_TASK_NAME,
         );
 
-        rx.await
-            .unwrap_or_else(|_| bail!(error::Error::Custom("Receiver error".to_string())))
+        let received = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, rx).await {
+                Ok(received) => received,
+                Err(_) => {
+                    metrics::TIMED_OUT_OPERATIONS.inc(1);
+                    debug!(elapsed = ?submitted_at.elapsed(), "Async storage operation timed out");
+                    bail!(Error::Timeout);
+                }
+            },
+            None => rx.await,
+        };
+
+        received.unwrap_or_else(|_| bail!(Error::WorkerDropped))
+    }
+
+    /// Waits until the flow merkle tree is loaded, e.g. before calling the blocking operations
+    /// that need the tree via `get_store`.
+    pub async fn wait_flow_tree_ready(&self) {
+        let mut ready = self.store.subscribe_flow_tree_ready();
+        // never fails since the sender is owned by the store
+        let _ = ready.wait_for(|ready| *ready).await;
     }
 
     // FIXME(zz): Refactor the lock and async call here.
@@ -161,3 +508,276 @@ impl Store {
         self.store.as_ref()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_types::TransactionBuilder;
+    use std::future::Future;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc;
+    use storage::log_store::log_manager::{
+        sub_merkle_tree, tx_subtree_root_list_padded, LogConfig,
+    };
+    use storage::log_store::{LogStoreRead, LogStoreWrite};
+    use storage::{DbLayout, LogManager, StoreHandles};
+    use tokio::runtime::Runtime;
+
+    /// Creates a store on a runtime of a single blocking thread, so that the blocking pool is
+    /// saturated by a single slow operation. Time is paused and only advanced by the tests.
+    fn saturated_store() -> (Runtime, exit_future::Signal, Store) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .max_blocking_threads(1)
+            .enable_all()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        let (signal, exit) = exit_future::signal();
+        let (shutdown_tx, _) = futures::channel::mpsc::channel(1);
+        let executor = TaskExecutor::new(runtime.handle().clone(), exit, shutdown_tx);
+        let store = Arc::new(LogManager::memorydb(LogConfig::default()).unwrap());
+        (runtime, signal, Store::new(store, executor))
+    }
+
+    /// Spawns an operation which occupies the blocking thread until released.
+    async fn spawn_slow_operation(
+        store: &Store,
+    ) -> (mpsc::Sender<()>, tokio::task::JoinHandle<Result<()>>) {
+        let (started_tx, started_rx) = oneshot::channel();
+        let (release_tx, release_rx) = mpsc::channel();
+        let store = store.clone();
+        let handle = tokio::spawn(async move {
+            store
+                .spawn(move |_| {
+                    let _ = started_tx.send(());
+                    let _ = release_rx.recv();
+                    Ok(())
+                })
+                .await
+        });
+        started_rx.await.unwrap();
+        (release_tx, handle)
+    }
+
+    /// Spawns an operation that records whether it is executed.
+    fn spawn_flagged_operation(
+        store: &Store,
+    ) -> (Arc<AtomicBool>, impl Future<Output = Result<()>>) {
+        let executed = Arc::new(AtomicBool::new(false));
+        let flag = executed.clone();
+        let store = store.clone();
+        let future = async move {
+            store
+                .spawn(move |_| {
+                    flag.store(true, Ordering::SeqCst);
+                    Ok(())
+                })
+                .await
+        };
+        (executed, future)
+    }
+
+    #[test]
+    fn test_finalize_batched() {
+        let (runtime, _signal, store) = saturated_store();
+        let log_store = store.get_store();
+        let mut txs = vec![];
+        for seq in 0..3u64 {
+            let data = seq.to_be_bytes().repeat(CHUNK_SIZE / 8);
+            let tx = TransactionBuilder::new(seq)
+                .size(data.len() as u64)
+                .data_merkle_root(sub_merkle_tree(&data).unwrap().root().into())
+                .start_entry_index(log_store.get_context().unwrap().1)
+                .merkle_nodes(tx_subtree_root_list_padded(&data))
+                .build()
+                .unwrap();
+            log_store.put_tx(tx.clone()).unwrap();
+            log_store
+                .put_chunks(
+                    seq,
+                    ChunkArray {
+                        data,
+                        start_index: 0,
+                    },
+                )
+                .unwrap();
+            txs.push(tx);
+        }
+
+        runtime.block_on(async {
+            // requested while the blocking thread is busy, and persisted together
+            let (release, slow) = spawn_slow_operation(&store).await;
+            let finalizing: Vec<_> = txs
+                .iter()
+                .map(|tx| {
+                    let store = store.clone();
+                    let (seq, hash) = (tx.seq, tx.hash());
+                    tokio::spawn(async move { store.finalize_tx_batched(seq, hash).await })
+                })
+                .collect();
+            let reverted = {
+                let store = store.clone();
+                tokio::spawn(async move { store.finalize_tx_batched(0, H256::zero()).await })
+            };
+
+            release.send(()).unwrap();
+            slow.await.unwrap().unwrap();
+            for handle in finalizing {
+                assert!(handle.await.unwrap().unwrap());
+            }
+            assert!(!reverted.await.unwrap().unwrap());
+            for tx in &txs {
+                assert!(store.check_tx_completed(tx.seq).await.unwrap());
+            }
+        });
+    }
+
+    #[test]
+    fn test_status_while_tree_loading() {
+        // a single worker, which is blocked by a write waiting for the tree
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        let log_store = Arc::new(
+            LogManager::with_dbs_deferred(
+                StoreHandles::memorydb(DbLayout::Split),
+                LogConfig::default(),
+            )
+            .unwrap(),
+        );
+        let (signal, exit) = exit_future::signal();
+        let (shutdown_tx, _) = futures::channel::mpsc::channel(1);
+        let executor = TaskExecutor::new(runtime.handle().clone(), exit, shutdown_tx);
+        let store = Store::new(log_store.clone(), executor);
+
+        runtime.block_on(async {
+            let data = vec![1u8; CHUNK_SIZE];
+            let tx = TransactionBuilder::new(0)
+                .size(data.len() as u64)
+                .data_merkle_root(sub_merkle_tree(&data).unwrap().root().into())
+                .start_entry_index(1)
+                .merkle_nodes(tx_subtree_root_list_padded(&data))
+                .build()
+                .unwrap();
+            let (started_tx, started_rx) = oneshot::channel();
+            let writer = {
+                let store = log_store.clone();
+                tokio::spawn(async move {
+                    started_tx.send(()).unwrap();
+                    store.put_tx(tx)
+                })
+            };
+            started_rx.await.unwrap();
+            let ready = {
+                let store = store.clone();
+                tokio::spawn(async move { store.wait_flow_tree_ready().await })
+            };
+
+            // status is queried on the runtime while the tree is loading
+            let status = {
+                let store = log_store.clone();
+                tokio::spawn(async move { (store.next_tx_seq(), store.is_flow_tree_ready()) })
+            };
+            let status = tokio::time::timeout(Duration::from_secs(5), status)
+                .await
+                .expect("status query blocked by the tree load")
+                .unwrap();
+            assert_eq!(status, (0, false));
+            assert!(!writer.is_finished());
+            assert!(!ready.is_finished());
+
+            // the slow load completes later on another thread
+            let loader = {
+                let store = log_store.clone();
+                std::thread::spawn(move || store.load_flow_tree())
+            };
+            loader.join().unwrap().unwrap();
+            ready.await.unwrap();
+            writer.await.unwrap().unwrap();
+            assert_eq!(store.get_store().next_tx_seq(), 1);
+        });
+        drop(signal);
+    }
+
+    #[test]
+    fn test_timeout_when_saturated() {
+        let (runtime, _signal, store) = saturated_store();
+        runtime.block_on(async {
+            let (release, slow) = spawn_slow_operation(&store).await;
+
+            // queued behind the slow operation
+            let timeout = Duration::from_millis(100);
+            let (executed, queued) = spawn_flagged_operation(&store.with_timeout(timeout));
+            let queued = tokio::spawn(queued);
+            tokio::task::yield_now().await;
+            tokio::time::advance(timeout - Duration::from_millis(1)).await;
+            assert!(!queued.is_finished());
+            tokio::time::advance(Duration::from_millis(1)).await;
+            let err = queued.await.unwrap().unwrap_err();
+            assert_eq!(err.downcast_ref::<Error>(), Some(&Error::Timeout));
+
+            release.send(()).unwrap();
+            slow.await.unwrap().unwrap();
+
+            // timed out operation skipped, and the subsequent operations not blocked
+            assert!(store.get_num_entries().await.is_ok());
+            assert!(!executed.load(Ordering::SeqCst));
+        });
+    }
+
+    #[test]
+    fn test_timeout_when_running() {
+        let (runtime, _signal, store) = saturated_store();
+        runtime.block_on(async {
+            let timeout = Duration::from_millis(100);
+            let (started_tx, started_rx) = oneshot::channel();
+            let (release_tx, release_rx) = mpsc::channel();
+            let running = {
+                let store = store.with_deadline(Instant::now() + timeout);
+                tokio::spawn(async move {
+                    store
+                        .spawn(move |_| {
+                            let _ = started_tx.send(());
+                            let _ = release_rx.recv();
+                            Ok(())
+                        })
+                        .await
+                })
+            };
+            started_rx.await.unwrap();
+
+            // timed out at the deadline while still running
+            tokio::time::advance(timeout - Duration::from_millis(1)).await;
+            assert!(!running.is_finished());
+            tokio::time::advance(Duration::from_millis(1)).await;
+            let err = running.await.unwrap().unwrap_err();
+            assert_eq!(err.downcast_ref::<Error>(), Some(&Error::Timeout));
+
+            // completes once the running operation completed in background
+            release_tx.send(()).unwrap();
+            assert!(store.get_num_entries().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_cancel_on_drop() {
+        let (runtime, _signal, store) = saturated_store();
+        runtime.block_on(async {
+            let (release, slow) = spawn_slow_operation(&store).await;
+
+            let (executed, queued) = spawn_flagged_operation(&store);
+            let queued = tokio::spawn(queued);
+            tokio::task::yield_now().await;
+            queued.abort();
+            assert!(queued.await.unwrap_err().is_cancelled());
+
+            release.send(()).unwrap();
+            slow.await.unwrap().unwrap();
+
+            assert!(store.get_num_entries().await.is_ok());
+            assert!(!executed.load(Ordering::SeqCst));
+        });
+    }
+}
@@ -0,0 +1,12 @@
+use std::sync::Arc;
+
+use metrics::{Counter, CounterUsize, Gauge, GaugeUsize};
+
+lazy_static::lazy_static! {
+    /// Operations spawned but not started yet, which keeps growing once the blocking pool is
+    /// saturated.
+    pub static ref QUEUED_OPERATIONS: Arc<dyn Gauge<usize>> = GaugeUsize::register("storage_async_queued_operations");
+    pub static ref TIMED_OUT_OPERATIONS: Arc<dyn Counter<usize>> = CounterUsize::register("storage_async_timed_out_operations");
+    /// Operations skipped before started, since the caller timed out or dropped the future.
+    pub static ref CANCELLED_OPERATIONS: Arc<dyn Counter<usize>> = CounterUsize::register("storage_async_cancelled_operations");
+}
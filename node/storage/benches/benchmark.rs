@@ -5,14 +5,16 @@ use std::{
 };
 
 use criterion::{criterion_group, criterion_main, Criterion};
+use ethereum_types::H256;
 use rand::{random, Rng};
-use shared_types::{ChunkArray, Transaction, CHUNK_SIZE};
+use shared_types::{ChunkArray, DataRoot, TransactionBuilder, CHUNK_SIZE};
 use storage::{
     log_store::{
         log_manager::{sub_merkle_tree, tx_subtree_root_list_padded, LogConfig},
-        Store,
+        tx_store::TransactionStore,
+        LogStoreRead, LogStoreWrite, Store,
     },
-    LogManager,
+    DbLayout, LogManager, StoreHandles,
 };
 
 fn write_performance(c: &mut Criterion) {
@@ -76,15 +78,13 @@ fn write_performance(c: &mut Criterion) {
                 start_index: 0,
             };
 
-            let tx = Transaction {
-                stream_ids: vec![],
-                size: data_size as u64,
-                data_merkle_root: merkel_root,
-                seq,
-                data: vec![],
-                start_entry_index: start_offset,
-                merkle_nodes: merkel_nodes,
-            };
+            let tx = TransactionBuilder::new(seq)
+                .size(data_size as u64)
+                .data_merkle_root(merkel_root)
+                .start_entry_index(start_offset)
+                .merkle_nodes(merkel_nodes)
+                .build()
+                .unwrap();
 
             store.write().unwrap().put_tx(tx).unwrap();
             store
@@ -140,15 +140,13 @@ fn read_performance(c: &mut Criterion) {
             start_index: 0,
         };
 
-        let tx = Transaction {
-            stream_ids: vec![],
-            size: data_size as u64,
-            data_merkle_root: merkel_root,
-            seq,
-            data: vec![],
-            start_entry_index: start_offset,
-            merkle_nodes: merkel_nodes,
-        };
+        let tx = TransactionBuilder::new(seq)
+            .size(data_size as u64)
+            .data_merkle_root(merkel_root)
+            .start_entry_index(start_offset)
+            .merkle_nodes(merkel_nodes)
+            .build()
+            .unwrap();
 
         store.write().unwrap().put_tx(tx).unwrap();
         store
@@ -180,5 +178,134 @@ fn read_performance(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, write_performance, read_performance);
+fn same_data_root_performance(c: &mut Criterion) {
+    let store = TransactionStore::new(StoreHandles::memorydb(DbLayout::Split)).unwrap();
+
+    let duplicates = 100_000;
+    let new_tx = |seq| {
+        TransactionBuilder::new(seq)
+            .size(CHUNK_SIZE as u64)
+            .data_merkle_root(DataRoot::from_low_u64_be(1))
+            .start_entry_index(seq)
+            .build_unchecked()
+    };
+    for seq in 0..duplicates {
+        store.put_tx_light(new_tx(seq)).unwrap();
+    }
+
+    // The last tx is inserted again, which returns early.
+    let mut group = c.benchmark_group("same data root performance");
+    group.sample_size(10);
+    group.bench_function("put_tx", |b| {
+        b.iter(|| store.put_tx(new_tx(duplicates - 1)).unwrap())
+    });
+    group.bench_function("put_tx_light", |b| {
+        b.iter(|| store.put_tx_light(new_tx(duplicates - 1)).unwrap())
+    });
+}
+
+fn block_hashes_startup_performance(c: &mut Criterion) {
+    if Path::new("db_flow_block_hashes").exists() {
+        fs::remove_dir_all("db_flow_block_hashes").unwrap();
+    }
+    if Path::new("db_data_block_hashes").exists() {
+        fs::remove_dir_all("db_data_block_hashes").unwrap();
+    }
+
+    let store = LogManager::rocksdb(
+        LogConfig::default(),
+        "db_flow_block_hashes",
+        "db_data_block_hashes",
+    )
+    .unwrap();
+
+    let num_blocks = 1_000_000;
+    for block_number in 0..num_blocks {
+        store
+            .put_sync_progress((
+                block_number,
+                H256::from_low_u64_be(block_number),
+                Some(Some(block_number)),
+            ))
+            .unwrap();
+    }
+
+    // Blocks loaded at startup until the finalized one, which is 100 blocks behind the latest.
+    let mut group = c.benchmark_group("block hashes startup performance");
+    group.sample_size(10);
+    group.bench_function("get_block_hashes", |b| {
+        b.iter(|| store.get_block_hashes().unwrap())
+    });
+    group.bench_function("iter_block_hashes_rev", |b| {
+        b.iter(|| {
+            store
+                .iter_block_hashes_rev(num_blocks - 1)
+                .take(101)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        })
+    });
+}
+
+fn finalize_performance(c: &mut Criterion) {
+    for path in ["db_flow_finalize", "db_data_finalize"] {
+        if Path::new(path).exists() {
+            fs::remove_dir_all(path).unwrap();
+        }
+    }
+
+    let store =
+        LogManager::rocksdb(LogConfig::default(), "db_flow_finalize", "db_data_finalize").unwrap();
+
+    // A bulk finalization, e.g. many small files uploaded at once. Txs finalized already are
+    // finalized again in each iteration, which goes through the same checks and writes.
+    let num_txs = 1024;
+    let mut txs = Vec::with_capacity(num_txs);
+    for seq in 0..num_txs as u64 {
+        let data = seq.to_be_bytes().repeat(CHUNK_SIZE / 8);
+        let tx = TransactionBuilder::new(seq)
+            .size(data.len() as u64)
+            .data_merkle_root(sub_merkle_tree(&data).unwrap().root().into())
+            .start_entry_index(store.get_context().unwrap().1)
+            .merkle_nodes(tx_subtree_root_list_padded(&data))
+            .build()
+            .unwrap();
+        store.put_tx(tx.clone()).unwrap();
+        store
+            .put_chunks(
+                seq,
+                ChunkArray {
+                    data,
+                    start_index: 0,
+                },
+            )
+            .unwrap();
+        txs.push((seq, tx.hash()));
+    }
+
+    let mut group = c.benchmark_group("finalize performance");
+    group.sample_size(10);
+    group.bench_function("finalize_tx_with_hash one by one", |b| {
+        b.iter(|| {
+            for (seq, hash) in &txs {
+                assert!(store.finalize_tx_with_hash(*seq, *hash).unwrap());
+            }
+        })
+    });
+    group.bench_function("finalize_txs_with_hash in batch", |b| {
+        b.iter(|| {
+            let results = store.finalize_txs_with_hash(txs.clone()).unwrap();
+            assert!(results.into_iter().all(|result| result.unwrap()));
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    write_performance,
+    read_performance,
+    same_data_root_performance,
+    block_hashes_startup_performance,
+    finalize_performance
+);
 criterion_main!(benches);
@@ -0,0 +1,98 @@
+//! Opens a log store as a library, without async runtime or network, writes a tx with its data,
+//! and produces the proofs of the data against the flow root.
+//!
+//! Run with the store in memory, or at `DB_DIR` in the same layout as the node:
+//!
+//! ```text
+//! cargo run -p storage --no-default-features --example embedded [DB_DIR]
+//! ```
+
+use std::cmp;
+
+use anyhow::{anyhow, bail, Result};
+use shared_types::{ChunkArray, TransactionBuilder, CHUNK_SIZE};
+use storage::log_store::log_manager::{
+    sub_merkle_tree, tx_subtree_root_list_padded, PORA_CHUNK_SIZE,
+};
+use storage::log_store::{LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
+use storage::{DbEngine, LogManager, LogStoreConfig};
+
+fn main() -> Result<()> {
+    let config = match std::env::args().nth(1) {
+        Some(db_dir) => LogStoreConfig::new(DbEngine::RocksDb, db_dir),
+        None => LogStoreConfig::new(DbEngine::Memory, ""),
+    };
+    let tx_seq = run(config)?;
+    println!("tx {} stored and verified", tx_seq);
+    Ok(())
+}
+
+/// Appends a file of 3 segments with the last one partially filled, and verifies the proofs of
+/// all segments. Returns the seq of the file tx.
+fn run(config: LogStoreConfig) -> Result<u64> {
+    let store = LogManager::new(config)?;
+
+    let chunk_count = 2 * PORA_CHUNK_SIZE + 10;
+    let data: Vec<u8> = (0..chunk_count * CHUNK_SIZE)
+        .map(|i| (i % 251) as u8)
+        .collect();
+
+    // The tx is submitted on chain in the node, so that the txs and their positions in the flow
+    // are synced from the contract. Here the tx is appended to the end of the flow instead.
+    let merkle_nodes = tx_subtree_root_list_padded(&data);
+    let (_, flow_len) = store.get_context()?;
+    let first_subtree_size = 1 << (merkle_nodes[0].0 - 1);
+    let tx = TransactionBuilder::new(store.next_tx_seq())
+        .data_merkle_root(sub_merkle_tree(&data)?.root().into())
+        .merkle_nodes(merkle_nodes)
+        .start_entry_index(((flow_len - 1) / first_subtree_size + 1) * first_subtree_size)
+        .size(data.len() as u64)
+        .build()?;
+    store.put_tx(tx.clone())?;
+
+    for start_index in (0..chunk_count).step_by(PORA_CHUNK_SIZE) {
+        let end = cmp::min((start_index + PORA_CHUNK_SIZE) * CHUNK_SIZE, data.len());
+        store.put_chunks(
+            tx.seq,
+            ChunkArray {
+                data: data[start_index * CHUNK_SIZE..end].to_vec(),
+                start_index: start_index as u64,
+            },
+        )?;
+    }
+    store.finalize_tx(tx.seq)?;
+    if !store.check_tx_completed(tx.seq)? {
+        bail!("tx {} not finalized", tx.seq);
+    }
+
+    for start_index in (0..chunk_count).step_by(PORA_CHUNK_SIZE) {
+        let end_index = cmp::min(start_index + PORA_CHUNK_SIZE, chunk_count);
+        let segment = store
+            .get_chunks_with_proof_by_tx_and_index_range(tx.seq, start_index, end_index, None)?
+            .ok_or_else(|| anyhow!("segment {} missing", start_index / PORA_CHUNK_SIZE))?;
+        if !store.validate_range_proof(tx.seq, &segment)? {
+            bail!("invalid proof of segment {}", start_index / PORA_CHUNK_SIZE);
+        }
+    }
+
+    Ok(tx.seq)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_memorydb() {
+        assert_eq!(run(LogStoreConfig::new(DbEngine::Memory, "")).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_embedded_rocksdb() {
+        let db_dir = tempfile::TempDir::new().unwrap();
+        assert_eq!(
+            run(LogStoreConfig::new(DbEngine::RocksDb, db_dir.path())).unwrap(),
+            0
+        );
+    }
+}
@@ -1,14 +1,91 @@
+use crate::handles::{DbLayout, DATA_DB_DIR, FLOW_DB_DIR};
+use crate::log_store::audit::FinalizedAudit;
 use crate::log_store::log_manager::LogConfig;
+use crate::log_store::write_stall::WriteStallConfig;
+use ethereum_types::Address;
 use serde::{Deserialize, Serialize};
 use ssz_derive::{Decode, Encode};
+use std::fmt::{Display, Formatter};
 use std::{cell::RefCell, path::PathBuf, rc::Rc, str::FromStr};
 
 pub const SHARD_CONFIG_KEY: &str = "shard_config";
+pub const NETWORK_ID_KEY: &str = "network_id";
 
 #[derive(Clone)]
 pub struct Config {
+    pub db_engine: DbEngine,
     pub db_dir: PathBuf,
+    pub db_layout: DbLayout,
     pub log_config: LogConfig,
+    /// Opens the dbs read-only, so that writes that would change the db fail.
+    pub read_only: bool,
+    /// Audit of the finalized txs on startup, which is skipped for a read-only store.
+    pub finalized_audit: FinalizedAudit,
+    /// Loads the flow merkle tree in background on startup, during which reads of finalized data
+    /// are served and writes wait for the tree.
+    pub lazy_tree_load: bool,
+    /// Network that the store is initialized for, which is checked on startup if any, see
+    /// [`crate::LogManager::check_network_id`].
+    pub network_id: Option<StoreNetworkId>,
+    /// Wipes and reinitializes the store if initialized for another network, rather than
+    /// refusing to start.
+    pub force_reinit: bool,
+    /// Observation of the kvdb write stalls, which is skipped for a read-only store.
+    pub write_stall: WriteStallConfig,
+}
+
+/// Minimal config to open a log store with [`crate::LogManager::new`], e.g. when the store is
+/// embedded as a library without the node.
+#[derive(Clone, Debug)]
+pub struct LogStoreConfig {
+    pub db_engine: DbEngine,
+    /// Paths of the flow and data dbs, which are ignored by [`DbEngine::Memory`].
+    pub flow_db_path: PathBuf,
+    pub data_db_path: PathBuf,
+    pub shard_config: ShardConfig,
+    /// Maximum number of merkle nodes cached in memory, each of which takes 48 bytes.
+    pub merkle_node_cache_capacity: usize,
+}
+
+impl LogStoreConfig {
+    /// Config of the dbs under `db_dir` in the same layout as the node.
+    pub fn new(db_engine: DbEngine, db_dir: impl Into<PathBuf>) -> Self {
+        let db_dir = db_dir.into();
+        Self {
+            db_engine,
+            flow_db_path: db_dir.join(FLOW_DB_DIR),
+            data_db_path: db_dir.join(DATA_DB_DIR),
+            shard_config: Default::default(),
+            merkle_node_cache_capacity: LogConfig::default().flow.merkle_node_cache_capacity,
+        }
+    }
+}
+
+/// Key-value db engine of the flow and data dbs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DbEngine {
+    #[default]
+    RocksDb,
+    /// Pure Rust db, which requires the `sled-backend` feature.
+    Sled,
+    /// Data is lost after restart, which is only for tests.
+    Memory,
+}
+
+impl FromStr for DbEngine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rocksdb" => Ok(DbEngine::RocksDb),
+            "sled" => Ok(DbEngine::Sled),
+            "memory" => Ok(DbEngine::Memory),
+            _ => Err(format!(
+                "Unknown db engine {}, expected rocksdb, sled or memory",
+                s
+            )),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, Decode, Encode, Serialize, Deserialize, Eq, PartialEq)]
@@ -18,6 +95,34 @@ pub struct ShardConfig {
     pub num_shard: usize,
 }
 
+/// Network of which the store holds the flow, so that a data dir is never reused for another
+/// network, where new submissions would interleave with the old ones.
+#[derive(Clone, Copy, Debug, Decode, Encode, Eq, PartialEq)]
+pub struct StoreNetworkId {
+    pub chain_id: u64,
+    /// Address of the flow contract.
+    pub flow_address: Address,
+}
+
+impl From<&shared_types::NetworkIdentity> for StoreNetworkId {
+    fn from(value: &shared_types::NetworkIdentity) -> Self {
+        Self {
+            chain_id: value.chain_id,
+            flow_address: value.flow_address,
+        }
+    }
+}
+
+impl Display for StoreNetworkId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "chain id {} with flow contract {:?}",
+            self.chain_id, self.flow_address
+        )
+    }
+}
+
 impl Default for ShardConfig {
     fn default() -> Self {
         Self {
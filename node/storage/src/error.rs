@@ -1,42 +1,114 @@
+use crate::config::StoreNetworkId;
 use anyhow;
 use ssz::DecodeError;
 use std::error::Error as ErrorTrait;
 use std::fmt::{Debug, Display, Formatter};
-use std::io::Error as IoError;
 
+/// Store APIs return `anyhow::Result`, and callers could get the [`StoreError`] of a failure
+/// via [`StoreError::of`] to decide how to handle it, instead of matching the error message.
+/// Failures without a [`StoreError`], e.g. of the kv db, are not expected to be handled.
 pub type Result<T> = anyhow::Result<T>;
 
-#[derive(Debug)]
-pub enum Error {
-    Io(IoError),
-    /// A partial chunk batch is written.
-    InvalidBatchBoundary,
-    ValueDecodingError(DecodeError),
-    Custom(String),
+/// Item that a store operation requires.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StoreItem {
+    Tx(u64),
+    /// Data of the tx, which is incomplete.
+    TxData(u64),
+    /// Entry batch of the flow by batch index.
+    Batch(u64),
 }
 
-impl From<IoError> for Error {
-    fn from(e: IoError) -> Self {
-        Error::Io(e)
+impl Display for StoreItem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreItem::Tx(tx_seq) => write!(f, "tx {}", tx_seq),
+            StoreItem::TxData(tx_seq) => write!(f, "data of tx {}", tx_seq),
+            StoreItem::Batch(batch_index) => write!(f, "batch {}", batch_index),
+        }
     }
 }
 
-impl From<DecodeError> for Error {
-    fn from(e: DecodeError) -> Self {
-        Error::ValueDecodingError(e)
+#[derive(Debug)]
+pub enum StoreError {
+    /// The item is not stored, e.g. a tx reverted or not synced yet.
+    NotFound { what: StoreItem },
+    /// The index, e.g. the end of a range, is out of the range bounded by `bound`.
+    OutOfRange {
+        what: &'static str,
+        index: u64,
+        bound: u64,
+    },
+    /// The stored value is undecodable or inconsistent.
+    Corrupt { reason: String },
+    /// The tx does not follow the stored txs, e.g. the txs are reverted on chain reorg. It is
+    /// never put as is, but after the log sync resumes from the reverted txs.
+    ReorgInProgress { expected_seq: u64, tx_seq: u64 },
+    /// The data of the tx is pruned.
+    Pruned { tx_seq: u64 },
+    /// The tx is rejected by the ingest policy, of which the data is never stored.
+    Invalid { tx_seq: u64 },
+    /// The store is too busy, and the operation could be retried later.
+    Busy,
+    /// The store is initialized for another network than configured.
+    NetworkMismatch {
+        stored: StoreNetworkId,
+        configured: StoreNetworkId,
+    },
+}
+
+impl StoreError {
+    pub fn tx_not_found(tx_seq: u64) -> Self {
+        StoreError::NotFound {
+            what: StoreItem::Tx(tx_seq),
+        }
+    }
+
+    /// Returns the store error that `e` is raised with, if any.
+    pub fn of(e: &anyhow::Error) -> Option<&StoreError> {
+        e.downcast_ref::<StoreError>()
+    }
+
+    /// Returns if the failed operation could succeed when retried later as is.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, StoreError::Busy)
     }
 }
 
-impl From<anyhow::Error> for Error {
-    fn from(e: anyhow::Error) -> Self {
-        Error::Custom(e.to_string())
+impl From<DecodeError> for StoreError {
+    fn from(e: DecodeError) -> Self {
+        StoreError::Corrupt {
+            reason: format!("value decoding error: {:?}", e),
+        }
     }
 }
 
-impl Display for Error {
+impl Display for StoreError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "StorageError: {:?}", self)
+        match self {
+            StoreError::NotFound { what } => write!(f, "{} not found", what),
+            StoreError::OutOfRange { what, index, bound } => {
+                write!(f, "{} out of range: index={} bound={}", what, index, bound)
+            }
+            StoreError::Corrupt { reason } => write!(f, "store corrupted: {}", reason),
+            StoreError::ReorgInProgress {
+                expected_seq,
+                tx_seq,
+            } => write!(
+                f,
+                "tx out of order during reorg: expected_seq={} tx_seq={}",
+                expected_seq, tx_seq
+            ),
+            StoreError::Pruned { tx_seq } => write!(f, "tx {} pruned", tx_seq),
+            StoreError::Invalid { tx_seq } => write!(f, "tx {} rejected by ingest policy", tx_seq),
+            StoreError::Busy => write!(f, "store busy"),
+            StoreError::NetworkMismatch { stored, configured } => write!(
+                f,
+                "store initialized for {}, but configured for {}",
+                stored, configured
+            ),
+        }
     }
 }
 
-impl ErrorTrait for Error {}
+impl ErrorTrait for StoreError {}
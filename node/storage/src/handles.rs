@@ -0,0 +1,469 @@
+//! Handles of the flow and data dbs, which are either separate dbs or routed to a single db
+//! with all columns, so that the layout is decided in one place.
+
+use crate::log_store::log_manager::{
+    COL_ADMIN_JOB, COL_BLOCK_PROGRESS, COL_ENTRY_BATCH, COL_FLOW_MPT_NODES, COL_FLUSH_JOURNAL,
+    COL_MINER_REWARD, COL_MINER_REWARD_BLOCK, COL_MISC, COL_NUM, COL_PAD_DATA_LIST,
+    COL_PAD_DATA_SYNC_HEIGH, COL_REVERTED_BATCH, COL_TX, COL_TX_COMPLETED, COL_TX_DATA_ROOT_INDEX,
+    COL_TX_FIRST_SEEN, COL_TX_SUBMISSION,
+};
+use crate::read_only::ReadOnlyDB;
+use crate::rocksdb_read_only::ReadOnlyRocksDB;
+use crate::{open_kvdb, open_kvdb_read_only, DbEngine, ZgsKeyValueDB};
+use anyhow::{bail, Result};
+use kvdb::DBTransaction;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub const FLOW_DB_DIR: &str = "flow_db";
+pub const DATA_DB_DIR: &str = "data_db";
+pub const UNIFIED_DB_DIR: &str = "unified_db";
+
+/// Columns of the flow db. `COL_MISC` is in both dbs, whose keys never overlap.
+pub const FLOW_DB_COLUMNS: [u32; 11] = [
+    COL_TX,
+    COL_TX_DATA_ROOT_INDEX,
+    COL_MISC,
+    COL_FLOW_MPT_NODES,
+    COL_BLOCK_PROGRESS,
+    COL_PAD_DATA_LIST,
+    COL_MINER_REWARD,
+    COL_TX_SUBMISSION,
+    COL_TX_FIRST_SEEN,
+    COL_ADMIN_JOB,
+    COL_MINER_REWARD_BLOCK,
+];
+
+/// Columns of the data db.
+pub const DATA_DB_COLUMNS: [u32; 6] = [
+    COL_ENTRY_BATCH,
+    COL_TX_COMPLETED,
+    COL_MISC,
+    COL_PAD_DATA_SYNC_HEIGH,
+    COL_FLUSH_JOURNAL,
+    COL_REVERTED_BATCH,
+];
+
+/// Number of keys written into the target db at a time on migration.
+const COPY_BATCH_SIZE: usize = 1024;
+/// Extension of the target dbs being migrated, which are renamed once all keys copied.
+const MIGRATING_EXT: &str = "migrating";
+
+/// Layout of the dbs under the db directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DbLayout {
+    /// Flow and data in separate dbs `flow_db` and `data_db`.
+    #[default]
+    Split,
+    /// All columns in a single db `unified_db`, e.g. for simpler backup.
+    Unified,
+}
+
+impl DbLayout {
+    pub fn new(unified: bool) -> Self {
+        if unified {
+            DbLayout::Unified
+        } else {
+            DbLayout::Split
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DbLayout::Split => "split",
+            DbLayout::Unified => "unified",
+        }
+    }
+
+    /// Paths of the dbs of this layout under `db_dir`.
+    pub fn db_paths(&self, db_dir: &Path) -> Vec<PathBuf> {
+        match self {
+            DbLayout::Split => vec![db_dir.join(FLOW_DB_DIR), db_dir.join(DATA_DB_DIR)],
+            DbLayout::Unified => vec![db_dir.join(UNIFIED_DB_DIR)],
+        }
+    }
+
+    /// Paths of the target dbs of this layout during migration.
+    fn migrating_paths(&self, db_dir: &Path) -> Vec<PathBuf> {
+        self.db_paths(db_dir)
+            .into_iter()
+            .map(|p| p.with_extension(MIGRATING_EXT))
+            .collect()
+    }
+
+    pub fn other(&self) -> Self {
+        match self {
+            DbLayout::Split => DbLayout::Unified,
+            DbLayout::Unified => DbLayout::Split,
+        }
+    }
+}
+
+/// Dbs created by [`migrate_db_layout`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DbMigration {
+    pub layout: DbLayout,
+    pub paths: Vec<PathBuf>,
+    pub num_keys: u64,
+}
+
+/// Handles of the flow and data dbs used by the log store.
+#[derive(Clone)]
+pub struct StoreHandles {
+    flow: Arc<dyn ZgsKeyValueDB>,
+    data: Arc<dyn ZgsKeyValueDB>,
+    layout: DbLayout,
+    /// Engine and directory of the dbs, or `None` if not opened from a directory, e.g. tests.
+    location: Option<(DbEngine, PathBuf)>,
+}
+
+impl StoreHandles {
+    pub fn split(flow: Arc<dyn ZgsKeyValueDB>, data: Arc<dyn ZgsKeyValueDB>) -> Self {
+        Self {
+            flow,
+            data,
+            layout: DbLayout::Split,
+            location: None,
+        }
+    }
+
+    pub fn unified(db: Arc<dyn ZgsKeyValueDB>) -> Self {
+        Self {
+            flow: db.clone(),
+            data: db,
+            layout: DbLayout::Unified,
+            location: None,
+        }
+    }
+
+    pub fn memorydb(layout: DbLayout) -> Self {
+        match layout {
+            DbLayout::Split => Self::split(
+                Arc::new(kvdb_memorydb::create(COL_NUM)),
+                Arc::new(kvdb_memorydb::create(COL_NUM)),
+            ),
+            DbLayout::Unified => Self::unified(Arc::new(kvdb_memorydb::create(COL_NUM))),
+        }
+    }
+
+    /// Opens the dbs of `layout` under `db_dir`, which fails if only the dbs of the other layout
+    /// exist, so that the node never starts with empty dbs by misconfiguration.
+    pub fn open(engine: DbEngine, layout: DbLayout, db_dir: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with(engine, layout, db_dir.as_ref(), |engine, path, num_cols| {
+            open_kvdb(engine, path, num_cols)
+        })
+    }
+
+    /// Opens the dbs of `layout` under `db_dir` for reads only, e.g. a snapshot of a running
+    /// node, see [`open_kvdb_read_only`]. The writes that would change the dbs are rejected,
+    /// see [`ReadOnlyDB`].
+    pub fn open_read_only(
+        engine: DbEngine,
+        layout: DbLayout,
+        db_dir: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let db_dir = db_dir.as_ref();
+        if engine != DbEngine::Memory {
+            if let Some(path) = layout.db_paths(db_dir).iter().find(|p| !p.exists()) {
+                bail!("db {:?} not found", path);
+            }
+        }
+        Ok(
+            Self::open_with(engine, layout, db_dir, |engine, path, num_cols| {
+                open_kvdb_read_only(engine, path, num_cols)
+            })?
+            .into_read_only(),
+        )
+    }
+
+    /// Opens the dbs of `layout` under `db_dir` of a stopped node for inspection, which never
+    /// writes under `db_dir`. Rocksdb is opened in its read-only mode, see [`ReadOnlyRocksDB`],
+    /// while the other engines are opened as [`Self::open_read_only`].
+    pub fn open_offline(
+        engine: DbEngine,
+        layout: DbLayout,
+        db_dir: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let db_dir = db_dir.as_ref();
+        if engine != DbEngine::RocksDb {
+            return Self::open_read_only(engine, layout, db_dir);
+        }
+        if let Some(path) = layout.db_paths(db_dir).iter().find(|p| !p.exists()) {
+            bail!("db {:?} not found", path);
+        }
+        Ok(
+            Self::open_with(engine, layout, db_dir, |_, path, num_cols| {
+                Ok(Arc::new(ReadOnlyRocksDB::open(path, num_cols)?))
+            })?
+            .into_read_only(),
+        )
+    }
+
+    fn open_with(
+        engine: DbEngine,
+        layout: DbLayout,
+        db_dir: &Path,
+        open_db: impl Fn(DbEngine, PathBuf, u32) -> std::io::Result<Arc<dyn ZgsKeyValueDB>>,
+    ) -> Result<Self> {
+        if engine != DbEngine::Memory {
+            if !layout.db_paths(db_dir).iter().any(|p| p.exists())
+                && layout.other().db_paths(db_dir).iter().any(|p| p.exists())
+            {
+                bail!(
+                    "found dbs of the {} layout in {:?}, migrate the dbs to the {} layout with \
+                     `zgs_node db migrate-layout` first",
+                    layout.other().name(),
+                    db_dir,
+                    layout.name()
+                );
+            }
+            if let Some(path) = layout.migrating_paths(db_dir).iter().find(|p| p.exists()) {
+                bail!(
+                    "found db {:?} of an interrupted migration, remove it and migrate again",
+                    path
+                );
+            }
+        }
+
+        let mut handles = match layout {
+            DbLayout::Split => Self::split(
+                open_db(engine, db_dir.join(FLOW_DB_DIR), COL_NUM)?,
+                open_db(engine, db_dir.join(DATA_DB_DIR), COL_NUM)?,
+            ),
+            DbLayout::Unified => {
+                Self::unified(open_db(engine, db_dir.join(UNIFIED_DB_DIR), COL_NUM)?)
+            }
+        };
+        handles.location = Some((engine, db_dir.to_path_buf()));
+        Ok(handles)
+    }
+
+    /// Rejects the writes that would change the dbs, see [`ReadOnlyDB`].
+    pub fn into_read_only(self) -> Self {
+        let flow: Arc<dyn ZgsKeyValueDB> = Arc::new(ReadOnlyDB::new(self.flow));
+        let data: Arc<dyn ZgsKeyValueDB> = match self.layout {
+            DbLayout::Split => Arc::new(ReadOnlyDB::new(self.data)),
+            DbLayout::Unified => flow.clone(),
+        };
+        Self { flow, data, ..self }
+    }
+
+    pub fn flow(&self) -> &Arc<dyn ZgsKeyValueDB> {
+        &self.flow
+    }
+
+    pub fn data(&self) -> &Arc<dyn ZgsKeyValueDB> {
+        &self.data
+    }
+
+    pub fn layout(&self) -> DbLayout {
+        self.layout
+    }
+
+    /// Writes the transactions of the flow and data dbs, which is atomic if unified. Otherwise,
+    /// the data db is written first.
+    pub fn write_both(
+        &self,
+        mut flow_tx: DBTransaction,
+        data_tx: DBTransaction,
+    ) -> std::io::Result<()> {
+        match self.layout {
+            DbLayout::Unified => {
+                flow_tx.ops.extend(data_tx.ops);
+                self.flow.write(flow_tx)
+            }
+            DbLayout::Split => {
+                if !data_tx.ops.is_empty() {
+                    self.data.write(data_tx)?;
+                }
+                self.flow.write(flow_tx)
+            }
+        }
+    }
+
+    pub fn location(&self) -> Option<&(DbEngine, PathBuf)> {
+        self.location.as_ref()
+    }
+
+    /// Returns the distinct dbs along with their names.
+    pub fn dbs(&self) -> Vec<(&'static str, &Arc<dyn ZgsKeyValueDB>)> {
+        match self.layout {
+            DbLayout::Split => vec![("flow", &self.flow), ("data", &self.data)],
+            DbLayout::Unified => vec![("unified", &self.flow)],
+        }
+    }
+
+    /// Copies all keys into the dbs of `target`, which may be of the other layout, and returns
+    /// the number of keys copied. `COL_MISC` is copied into both dbs if the target is split.
+    pub fn copy_to(&self, target: &StoreHandles) -> Result<u64> {
+        let mut num_keys = 0;
+        for col in FLOW_DB_COLUMNS {
+            num_keys += copy_column(self.flow.as_ref(), target.flow.as_ref(), col)?;
+        }
+        for col in DATA_DB_COLUMNS {
+            num_keys += copy_column(self.data.as_ref(), target.data.as_ref(), col)?;
+        }
+        Ok(num_keys)
+    }
+
+    /// Deletes all keys of the dbs, so that the store is initialized again once reopened.
+    pub fn clear(&self) -> Result<()> {
+        for (_, db) in self.dbs() {
+            for col in 0..COL_NUM {
+                db.delete_with_prefix(col, &[])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Copies the dbs of `layout` under `db_dir` into new dbs of the other layout, which requires
+/// the node to be stopped. The source dbs are opened exclusively, which fails if the node is
+/// still running, so the copy is consistent and no later write is lost.
+///
+/// The new dbs are copied under temporary names and renamed once all keys copied, so that an
+/// interrupted migration never leaves partial dbs that the node would open. The source dbs are
+/// kept as is, and could be removed once the node restarts with the other layout.
+pub fn migrate_db_layout(
+    engine: DbEngine,
+    layout: DbLayout,
+    db_dir: impl AsRef<Path>,
+) -> Result<DbMigration> {
+    let db_dir = db_dir.as_ref();
+    if engine == DbEngine::Memory {
+        bail!("in-memory dbs not migratable");
+    }
+    if let Some(path) = layout.db_paths(db_dir).iter().find(|p| !p.exists()) {
+        bail!("source db {:?} not found", path);
+    }
+    let target_layout = layout.other();
+    let paths = target_layout.db_paths(db_dir);
+    let migrating_paths = target_layout.migrating_paths(db_dir);
+    if let Some(path) = paths.iter().chain(&migrating_paths).find(|p| p.exists()) {
+        bail!("target db {:?} already exists", path);
+    }
+
+    let source = StoreHandles::open(engine, layout, db_dir)?;
+    let target = match target_layout {
+        DbLayout::Split => StoreHandles::split(
+            open_kvdb(engine, &migrating_paths[0], COL_NUM)?,
+            open_kvdb(engine, &migrating_paths[1], COL_NUM)?,
+        ),
+        DbLayout::Unified => {
+            StoreHandles::unified(open_kvdb(engine, &migrating_paths[0], COL_NUM)?)
+        }
+    };
+    let num_keys = source.copy_to(&target)?;
+    // Close the dbs before renaming.
+    drop(target);
+    drop(source);
+
+    for (from, to) in migrating_paths.iter().zip(&paths) {
+        std::fs::rename(from, to)?;
+    }
+
+    Ok(DbMigration {
+        layout: target_layout,
+        paths,
+        num_keys,
+    })
+}
+
+fn copy_column(source: &dyn ZgsKeyValueDB, target: &dyn ZgsKeyValueDB, col: u32) -> Result<u64> {
+    let mut num_keys = 0;
+    let mut db_tx = target.transaction();
+    for r in source.iter(col) {
+        let (key, value) = r?;
+        db_tx.put(col, &key, &value);
+        num_keys += 1;
+        if db_tx.ops.len() >= COPY_BATCH_SIZE {
+            target.write(db_tx)?;
+            db_tx = target.transaction();
+        }
+    }
+    target.write(db_tx)?;
+    Ok(num_keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_open_layout() {
+        let db_dir = TempDir::new().unwrap();
+        let handles =
+            StoreHandles::open(DbEngine::RocksDb, DbLayout::Split, db_dir.path()).unwrap();
+        assert!(db_dir.path().join(FLOW_DB_DIR).exists());
+        assert_eq!(handles.dbs().len(), 2);
+        drop(handles);
+
+        // The split dbs are never ignored silently.
+        assert!(StoreHandles::open(DbEngine::RocksDb, DbLayout::Unified, db_dir.path()).is_err());
+
+        let db_dir = TempDir::new().unwrap();
+        let handles =
+            StoreHandles::open(DbEngine::RocksDb, DbLayout::Unified, db_dir.path()).unwrap();
+        handles.flow().put(COL_TX, b"flow", b"1").unwrap();
+        handles.data().put(COL_ENTRY_BATCH, b"data", b"2").unwrap();
+        assert_eq!(handles.dbs().len(), 1);
+        assert_eq!(
+            handles.flow().get(COL_ENTRY_BATCH, b"data").unwrap(),
+            Some(b"2".to_vec())
+        );
+        assert!(!db_dir.path().join(DATA_DB_DIR).exists());
+    }
+
+    #[test]
+    fn test_open_interrupted_migration() {
+        let db_dir = TempDir::new().unwrap();
+        drop(StoreHandles::open(DbEngine::RocksDb, DbLayout::Split, db_dir.path()).unwrap());
+        std::fs::create_dir(db_dir.path().join("data_db.migrating")).unwrap();
+        std::fs::remove_dir_all(db_dir.path().join(DATA_DB_DIR)).unwrap();
+
+        assert!(StoreHandles::open(DbEngine::RocksDb, DbLayout::Split, db_dir.path()).is_err());
+        assert!(migrate_db_layout(DbEngine::RocksDb, DbLayout::Split, db_dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_copy_between_layouts() {
+        let split = StoreHandles::memorydb(DbLayout::Split);
+        split.flow().put(COL_TX, b"tx", b"1").unwrap();
+        split.flow().put(COL_MISC, b"flow_key", b"2").unwrap();
+        split.data().put(COL_ENTRY_BATCH, b"batch", b"3").unwrap();
+        split.data().put(COL_MISC, b"data_key", b"4").unwrap();
+
+        let unified = StoreHandles::memorydb(DbLayout::Unified);
+        assert_eq!(split.copy_to(&unified).unwrap(), 4);
+        for (col, key) in [
+            (COL_TX, b"tx".as_slice()),
+            (COL_MISC, b"flow_key"),
+            (COL_ENTRY_BATCH, b"batch"),
+            (COL_MISC, b"data_key"),
+        ] {
+            assert!(unified.flow().get(col, key).unwrap().is_some());
+        }
+
+        let split_again = StoreHandles::memorydb(DbLayout::Split);
+        unified.copy_to(&split_again).unwrap();
+        assert_eq!(
+            split_again.flow().get(COL_TX, b"tx").unwrap(),
+            Some(b"1".to_vec())
+        );
+        assert!(split_again
+            .flow()
+            .get(COL_ENTRY_BATCH, b"batch")
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            split_again.data().get(COL_ENTRY_BATCH, b"batch").unwrap(),
+            Some(b"3".to_vec())
+        );
+        assert!(split_again
+            .data()
+            .get(COL_MISC, b"data_key")
+            .unwrap()
+            .is_some());
+    }
+}
@@ -1,17 +1,54 @@
-use kvdb::KeyValueDB;
+//! Log store of the flow, txs and file data.
+//!
+//! The store could be embedded as a library with `default-features = false`, which drops the
+//! async services of the node (`runtime`) and the metrics registration (`metrics`). See
+//! `examples/embedded.rs` to open a store with [`LogStoreConfig`] and produce proofs of the data.
+
+use kvdb::{DBValue, KeyValueDB};
 
 pub mod config;
 pub mod error;
+pub mod handles;
 pub mod log_store;
+pub mod read_only;
+pub mod rocksdb_read_only;
+#[cfg(feature = "sled-backend")]
+pub mod sled_db;
 
-pub use config::Config as StorageConfig;
+pub use config::{Config as StorageConfig, DbEngine, LogStoreConfig};
+pub use handles::{migrate_db_layout, DbLayout, DbMigration, StoreHandles};
 pub use log_store::log_manager::LogManager;
 
 pub use ethereum_types::H256;
 use kvdb_memorydb::InMemory;
-use kvdb_rocksdb::Database;
+use kvdb_rocksdb::{Database, DatabaseConfig};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Write stall stats of a key-value db, which are accumulated since the db is opened.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriteStallStats {
+    /// Total time in microseconds that writes are delayed or stopped, e.g. by rocksdb once
+    /// compaction falls behind.
+    pub stall_micros: u64,
+}
+
+/// Introspection of the key-value db backends that support it, e.g. rocksdb. The defaults are
+/// no-op for the other backends, e.g. the in-memory db.
+pub trait KvdbIntrospection {
+    /// Returns the write stall stats, or `None` if not supported by the backend.
+    fn write_stall_stats(&self) -> Option<WriteStallStats> {
+        None
+    }
+
+    /// Returns the estimated size in bytes of the column, or `None` if not supported by the
+    /// backend.
+    fn column_size_bytes(&self, _col: u32) -> std::io::Result<Option<u64>> {
+        Ok(None)
+    }
+}
 
-pub trait ZgsKeyValueDB: KeyValueDB {
+pub trait ZgsKeyValueDB: KeyValueDB + KvdbIntrospection {
     fn put(&self, col: u32, key: &[u8], value: &[u8]) -> std::io::Result<()> {
         let mut tx = self.transaction();
         tx.put(col, key, value);
@@ -38,6 +75,12 @@ pub trait ZgsKeyValueDB: KeyValueDB {
         self.write(tx)
     }
 
+    /// Reads the values of the keys in the order of `keys`, which is a single batched lookup on
+    /// the backends that support it.
+    fn multi_get(&self, col: u32, keys: &[&[u8]]) -> std::io::Result<Vec<Option<DBValue>>> {
+        keys.iter().map(|key| self.get(col, key)).collect()
+    }
+
     fn num_keys(&self, col: u32) -> std::io::Result<u64>;
 }
 
@@ -47,8 +90,85 @@ impl ZgsKeyValueDB for Database {
     }
 }
 
+impl KvdbIntrospection for Database {
+    fn write_stall_stats(&self) -> Option<WriteStallStats> {
+        // Statistics are enabled on open, in which the ticker `rocksdb.stall.micros` is named
+        // without the `rocksdb.` prefix.
+        let stats = self.get_statistics();
+        let stall_micros = stats.get("stall.micros")?.count;
+        Some(WriteStallStats { stall_micros })
+    }
+}
+
+impl KvdbIntrospection for InMemory {
+    fn column_size_bytes(&self, col: u32) -> std::io::Result<Option<u64>> {
+        let mut size = 0;
+        for item in self.iter(col) {
+            let (key, value) = item?;
+            size += (key.len() + value.len()) as u64;
+        }
+        Ok(Some(size))
+    }
+}
+
 impl ZgsKeyValueDB for InMemory {
-    fn num_keys(&self, _col: u32) -> std::io::Result<u64> {
-        todo!("not used")
+    fn num_keys(&self, col: u32) -> std::io::Result<u64> {
+        Ok(self.iter(col).count() as u64)
+    }
+}
+
+/// Opens the key-value db of `engine` at `path`, which is ignored by the in-memory db.
+pub fn open_kvdb(
+    engine: DbEngine,
+    path: impl AsRef<Path>,
+    num_cols: u32,
+) -> std::io::Result<Arc<dyn ZgsKeyValueDB>> {
+    match engine {
+        DbEngine::RocksDb => {
+            let mut db_config = DatabaseConfig::with_columns(num_cols);
+            db_config.enable_statistics = true;
+            if let Some(budget) = log_store::write_stall::load_memory_budget(path.as_ref())? {
+                db_config.memory_budget = (0..num_cols).map(|col| (col, budget)).collect();
+            }
+            Ok(Arc::new(Database::open(&db_config, path)?))
+        }
+        #[cfg(feature = "sled-backend")]
+        DbEngine::Sled => Ok(Arc::new(sled_db::SledDB::open(path, num_cols)?)),
+        #[cfg(not(feature = "sled-backend"))]
+        DbEngine::Sled => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "sled backend not enabled, rebuild with `--features sled-backend`",
+        )),
+        DbEngine::Memory => Ok(Arc::new(kvdb_memorydb::create(num_cols))),
+    }
+}
+
+/// Opens the key-value db of `engine` at `path` as a snapshot for reads, which never blocks or
+/// is blocked by the process that writes the db, e.g. a running node.
+///
+/// Rocksdb is opened as a secondary instance, which only sees the data written before opened,
+/// and writes its own info logs into `<path>.secondary/<pid>`. The other engines are opened as
+/// usual, e.g. sled fails if the db is opened by another process.
+pub fn open_kvdb_read_only(
+    engine: DbEngine,
+    path: impl AsRef<Path>,
+    num_cols: u32,
+) -> std::io::Result<Arc<dyn ZgsKeyValueDB>> {
+    match engine {
+        DbEngine::RocksDb => {
+            let path = path.as_ref();
+            let secondary = path
+                .with_extension("secondary")
+                .join(std::process::id().to_string());
+            std::fs::create_dir_all(&secondary)?;
+
+            let mut db_config = DatabaseConfig::with_columns(num_cols);
+            db_config.enable_statistics = true;
+            // required by the secondary instance
+            db_config.max_open_files = -1;
+            db_config.secondary = Some(secondary);
+            Ok(Arc::new(Database::open(&db_config, path)?))
+        }
+        engine => open_kvdb(engine, path, num_cols),
     }
 }
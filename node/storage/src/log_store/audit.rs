@@ -0,0 +1,172 @@
+//! Audit of the finalized txs on startup, which catches the files whose status is persisted as
+//! finalized while some of their data is missing, e.g. after an unclean shutdown or a partial
+//! restore of the db. Such txs are demoted to unfinalized, so that their data is synced again.
+//!
+//! Only the presence of the data is checked, without loading or verifying it, so that the audit
+//! is cheap enough to sample the txs on every startup.
+//!
+//! The stored chunks could be sampled as well for periodic self-audits, which read the sampled
+//! chunks through the proof path instead.
+
+use crate::log_store::log_manager::PORA_CHUNK_SIZE;
+use crate::log_store::tx_store::TxStatus;
+use crate::log_store::Store;
+use anyhow::Result;
+use shared_types::{bytes_to_chunks, splitmix64};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of txs audited in one call of the store.
+const AUDIT_BATCH_SIZE: usize = 1024;
+
+/// Maximum number of random flow indices drawn for each sampled chunk, beyond which the sampling
+/// gives up, e.g. most of the flow is padding or out of the local shard.
+const MAX_DRAWS_PER_SAMPLE: usize = 64;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FinalizedAudit {
+    #[default]
+    Off,
+    /// Audit the latest `samples` txs, which are the most likely to be broken by an unclean
+    /// shutdown, and another `samples` older txs spread over the log.
+    Sampled { samples: usize },
+    /// Audit all txs, which takes a while for a large log.
+    Full,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    /// Number of txs audited, including the unfinalized ones that are skipped.
+    pub audited: usize,
+    /// Finalized txs demoted to unfinalized, in ascending order.
+    pub demoted: Vec<u64>,
+}
+
+/// Audits the finalized txs in `store`, and demotes the ones that miss data in the local shard.
+pub fn audit_finalized_txs(store: &dyn Store, audit: FinalizedAudit) -> Result<AuditReport> {
+    let next_tx_seq = store.next_tx_seq();
+    let tx_seqs: Vec<u64> = match audit {
+        FinalizedAudit::Off => vec![],
+        FinalizedAudit::Full => (0..next_tx_seq).collect(),
+        FinalizedAudit::Sampled { samples } => sample_tx_seqs(next_tx_seq, samples as u64, seed()),
+    };
+
+    let mut report = AuditReport {
+        audited: tx_seqs.len(),
+        demoted: vec![],
+    };
+    for batch in tx_seqs.chunks(AUDIT_BATCH_SIZE) {
+        report.demoted.extend(store.demote_incomplete_txs(batch)?);
+    }
+    Ok(report)
+}
+
+/// Samples up to `samples` flow indices uniformly over the chunks stored in `store`, i.e. the
+/// data of the finalized txs in the local shard, without the padding between or after txs.
+pub fn sample_stored_chunks(store: &dyn Store, samples: usize) -> Result<Vec<u64>> {
+    sample_stored_chunks_with_seed(store, samples, seed())
+}
+
+fn sample_stored_chunks_with_seed(
+    store: &dyn Store,
+    samples: usize,
+    seed: u64,
+) -> Result<Vec<u64>> {
+    let flow_length = match store.next_tx_seq().checked_sub(1) {
+        Some(last_seq) => match store.get_tx_by_seq_number(last_seq)? {
+            Some(tx) => tx.start_entry_index + tx.num_entries() as u64,
+            None => return Ok(vec![]),
+        },
+        None => return Ok(vec![]),
+    };
+    // The first entry is a placeholder without data.
+    if flow_length <= 1 {
+        return Ok(vec![]);
+    }
+
+    // Draw indices over the whole flow and reject the ones not stored, so that the accepted
+    // ones are uniform over the stored chunks.
+    let shard_config = store.get_shard_config();
+    let mut indices = Vec::with_capacity(samples);
+    for draw in 0..samples * MAX_DRAWS_PER_SAMPLE {
+        if indices.len() >= samples {
+            break;
+        }
+
+        let index = 1 + splitmix64(seed.wrapping_add(draw as u64)) % (flow_length - 1);
+        if !shard_config.in_range(index / PORA_CHUNK_SIZE as u64) {
+            continue;
+        }
+        let tx = match store.get_tx_seq_by_flow_index(index)? {
+            Some(tx_seq) => match store.get_tx_by_seq_number(tx_seq)? {
+                Some(tx) => tx,
+                None => continue,
+            },
+            None => continue,
+        };
+        if index >= tx.start_entry_index + bytes_to_chunks(tx.size as usize) as u64 {
+            continue;
+        }
+        if matches!(
+            store.get_tx_status(tx.seq)?,
+            Some(TxStatus::Finalized | TxStatus::ShardFinalized)
+        ) {
+            indices.push(index);
+        }
+    }
+
+    Ok(indices)
+}
+
+/// Returns the latest `samples` tx seqs, and `samples` older ones evenly spread and shifted by
+/// `seed`, so that different startups cover different txs.
+fn sample_tx_seqs(next_tx_seq: u64, samples: u64, seed: u64) -> Vec<u64> {
+    let latest_start = next_tx_seq.saturating_sub(samples);
+    let mut tx_seqs = Vec::new();
+    if samples > 0 && latest_start > 0 {
+        let step = (latest_start / samples).max(1);
+        let mut tx_seq = seed % step;
+        while tx_seq < latest_start && (tx_seqs.len() as u64) < samples {
+            tx_seqs.push(tx_seq);
+            tx_seq += step;
+        }
+    }
+    tx_seqs.extend(latest_start..next_tx_seq);
+    tx_seqs
+}
+
+fn seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_tx_seqs() {
+        // all txs are the latest ones
+        assert_eq!(sample_tx_seqs(3, 5, 7), vec![0, 1, 2]);
+        assert_eq!(sample_tx_seqs(0, 5, 7), Vec::<u64>::new());
+        assert_eq!(sample_tx_seqs(10, 0, 7), Vec::<u64>::new());
+
+        // older txs spread with the seed as offset
+        assert_eq!(sample_tx_seqs(100, 10, 0), {
+            let mut expected: Vec<u64> = (0..90).step_by(9).collect();
+            expected.extend(90..100);
+            expected
+        });
+        let tx_seqs = sample_tx_seqs(100, 10, 4);
+        assert_eq!(tx_seqs.len(), 20);
+        assert_eq!(tx_seqs[0], 4);
+        assert!(tx_seqs.windows(2).all(|w| w[0] < w[1]));
+
+        // fewer older txs than samples
+        assert_eq!(
+            sample_tx_seqs(12, 10, 3),
+            vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! Preflight checks of the db, so that a node broken by an unclean shutdown refuses to start
+//! rather than serving inconsistent data, along with the repair paths of some checks.
+
+use crate::config::{DbEngine, ShardConfig, SHARD_CONFIG_KEY};
+use crate::log_store::log_manager::{LogConfig, COL_MISC, COL_NAMES};
+use crate::log_store::tx_store::TransactionStore;
+use crate::log_store::{LogStoreRead, LogStoreWrite};
+use crate::{DbLayout, LogManager, StoreHandles, ZgsKeyValueDB};
+use anyhow::{anyhow, Result};
+use ethereum_types::H256;
+use ssz::Decode;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use tracing::{debug, error, warn};
+
+pub const CHECK_DB_COLUMNS: &str = "db columns";
+pub const CHECK_TX_STORE: &str = "tx store";
+pub const CHECK_SYNC_PROGRESS: &str = "sync progress";
+pub const CHECK_SHARD_CONFIG: &str = "shard config";
+pub const CHECK_FLOW_ROOT: &str = "flow root";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+    Passed,
+    /// Check failed, with the reason.
+    Failed(String),
+    /// Check failed but repaired, with the reason and the repair applied.
+    Repaired(String),
+    /// Check not run, since the checks it depends on failed.
+    Skipped,
+}
+
+impl CheckStatus {
+    fn label(&self) -> &'static str {
+        match self {
+            CheckStatus::Passed => "PASS",
+            CheckStatus::Failed(_) => "FAIL",
+            CheckStatus::Repaired(_) => "REPAIRED",
+            CheckStatus::Skipped => "SKIP",
+        }
+    }
+
+    fn is_ok(&self) -> bool {
+        matches!(self, CheckStatus::Passed | CheckStatus::Repaired(_))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CheckItem {
+    pub name: &'static str,
+    pub status: CheckStatus,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CheckReport {
+    pub items: Vec<CheckItem>,
+}
+
+impl CheckReport {
+    /// Returns `true` if all checks passed or repaired.
+    pub fn is_healthy(&self) -> bool {
+        self.items.iter().all(|item| item.status.is_ok())
+    }
+
+    /// Exit code of the check command, which is `0` if healthy, or `1` otherwise.
+    pub fn exit_code(&self) -> i32 {
+        if self.is_healthy() {
+            0
+        } else {
+            1
+        }
+    }
+
+    pub fn status(&self, name: &str) -> Option<&CheckStatus> {
+        self.items
+            .iter()
+            .find(|item| item.name == name)
+            .map(|item| &item.status)
+    }
+
+    fn add(&mut self, name: &'static str, status: CheckStatus) {
+        match &status {
+            CheckStatus::Passed | CheckStatus::Skipped => debug!(name, ?status, "DB check"),
+            CheckStatus::Repaired(reason) => warn!(name, %reason, "DB check repaired"),
+            CheckStatus::Failed(reason) => error!(name, %reason, "DB check failed"),
+        }
+        self.items.push(CheckItem { name, status });
+    }
+
+    /// Skips the checks that depend on openable dbs.
+    fn skip_remaining(&mut self) {
+        for name in [
+            CHECK_TX_STORE,
+            CHECK_SYNC_PROGRESS,
+            CHECK_SHARD_CONFIG,
+            CHECK_FLOW_ROOT,
+        ] {
+            self.add(name, CheckStatus::Skipped);
+        }
+    }
+
+    fn is_ok(&self, name: &str) -> bool {
+        self.status(name).map_or(false, |status| status.is_ok())
+    }
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut counts = [0; 4];
+        for item in self.items.iter() {
+            write!(f, "[{:<8}] {}", item.status.label(), item.name)?;
+            match &item.status {
+                CheckStatus::Failed(reason) | CheckStatus::Repaired(reason) => {
+                    writeln!(f, ": {}", reason)?
+                }
+                _ => writeln!(f)?,
+            }
+            counts[match item.status {
+                CheckStatus::Passed => 0,
+                CheckStatus::Repaired(_) => 1,
+                CheckStatus::Failed(_) => 2,
+                CheckStatus::Skipped => 3,
+            }] += 1;
+        }
+        write!(
+            f,
+            "{}: {} passed, {} repaired, {} failed, {} skipped",
+            if self.is_healthy() { "OK" } else { "BROKEN" },
+            counts[0],
+            counts[1],
+            counts[2],
+            counts[3]
+        )
+    }
+}
+
+/// Checks the dbs of `engine` and `layout` under `db_dir`, see `check_db`.
+pub fn check_db_at(
+    engine: DbEngine,
+    layout: DbLayout,
+    db_dir: impl AsRef<Path>,
+    config: LogConfig,
+    shard_config: ShardConfig,
+    repair: bool,
+) -> CheckReport {
+    match StoreHandles::open(engine, layout, db_dir) {
+        Ok(db) => check_db(db, config, shard_config, repair),
+        Err(e) => {
+            let mut report = CheckReport::default();
+            report.add(
+                CHECK_DB_COLUMNS,
+                CheckStatus::Failed(format!("unable to open db: {}", e)),
+            );
+            report.skip_remaining();
+            report
+        }
+    }
+}
+
+/// Runs all checks of the db, which requires the node to be stopped:
+///
+/// - all columns of the dbs are openable;
+/// - txs are decodable and in order until `next_tx_seq`;
+/// - log sync progress is decodable;
+/// - shard config persisted by the pruner is decodable and the same as `shard_config`;
+/// - the flow tree is the same as rebuilt from the root nodes of all txs.
+///
+/// If `repair` is `true`, inconsistent txs are truncated with the log sync progress rewound,
+/// so that they are synced again, and the flow tree is rebuilt from txs.
+pub fn check_db(
+    db: StoreHandles,
+    config: LogConfig,
+    shard_config: ShardConfig,
+    repair: bool,
+) -> CheckReport {
+    let mut report = CheckReport::default();
+
+    report.add(CHECK_DB_COLUMNS, check_columns(&db));
+    if !report.is_ok(CHECK_DB_COLUMNS) {
+        report.skip_remaining();
+        return report;
+    }
+
+    report.add(CHECK_TX_STORE, check_tx_store(&db, repair));
+    report.add(CHECK_SYNC_PROGRESS, check_sync_progress(&db));
+    report.add(
+        CHECK_SHARD_CONFIG,
+        check_shard_config(db.data().as_ref(), shard_config),
+    );
+
+    let status = if report.is_ok(CHECK_TX_STORE) {
+        check_flow_root(db, config, repair)
+    } else {
+        CheckStatus::Skipped
+    };
+    report.add(CHECK_FLOW_ROOT, status);
+
+    report
+}
+
+fn check_columns(db: &StoreHandles) -> CheckStatus {
+    for (db_name, db) in db.dbs() {
+        for (col, col_name) in COL_NAMES.iter().enumerate() {
+            if let Err(e) = db.get(col as u32, &[]) {
+                return CheckStatus::Failed(format!(
+                    "column {} of {} not openable: {}",
+                    col_name, db_name, e
+                ));
+            }
+        }
+    }
+
+    CheckStatus::Passed
+}
+
+fn check_tx_store(db: &StoreHandles, repair: bool) -> CheckStatus {
+    let flow_db = db.flow().as_ref();
+    let scanned = match TransactionStore::read_next_tx_seq(flow_db) {
+        Ok(next_tx_seq) => TransactionStore::scan_txs(flow_db, Some(next_tx_seq)),
+        Err(e) => TransactionStore::scan_txs(flow_db, None)
+            .map(|(num_txs, _)| (num_txs, Some(format!("next tx seq undecodable: {:?}", e)))),
+    };
+    let (num_txs, reason) = match scanned {
+        Ok((_, None)) => return CheckStatus::Passed,
+        Ok((num_txs, Some(reason))) => (num_txs, reason),
+        Err(e) => return CheckStatus::Failed(format!("unable to scan txs: {:?}", e)),
+    };
+
+    if !repair {
+        return CheckStatus::Failed(reason);
+    }
+
+    // Txs are synced again since the block of the first inconsistent tx.
+    let repaired = TransactionStore::rewind_progress(flow_db, num_txs).and_then(|num_txs| {
+        TransactionStore::truncate_txs(db, num_txs)?;
+        Ok(num_txs)
+    });
+    match repaired {
+        Ok(num_txs) => CheckStatus::Repaired(format!(
+            "{}, truncated to {} txs to sync again",
+            reason, num_txs
+        )),
+        Err(e) => CheckStatus::Failed(format!("{}, unable to repair: {:?}", reason, e)),
+    }
+}
+
+fn check_sync_progress(db: &StoreHandles) -> CheckStatus {
+    let tx_store = match TransactionStore::new(db.clone()) {
+        Ok(tx_store) => tx_store,
+        Err(_) => return CheckStatus::Skipped,
+    };
+
+    let checked = tx_store
+        .get_progress()
+        .map_err(|e| anyhow!("sync progress undecodable: {:?}", e))
+        .and_then(|_| {
+            tx_store
+                .get_log_latest_block_number()
+                .map_err(|e| anyhow!("latest block number undecodable: {:?}", e))
+        })
+        .and_then(|_| {
+            tx_store
+                .get_block_hashes()
+                .map_err(|e| anyhow!("block hashes undecodable: {:?}", e))
+        });
+
+    match checked {
+        Ok(_) => CheckStatus::Passed,
+        // the progress is only rebuilt by syncing from the configured start block
+        Err(e) => CheckStatus::Failed(format!("{}, remove the db to sync again", e)),
+    }
+}
+
+fn check_shard_config(data_db: &dyn ZgsKeyValueDB, configured: ShardConfig) -> CheckStatus {
+    let value = match data_db.get(COL_MISC, SHARD_CONFIG_KEY.as_bytes()) {
+        Ok(Some(value)) => value,
+        // not persisted until the pruner started
+        Ok(None) => return CheckStatus::Passed,
+        Err(e) => return CheckStatus::Failed(format!("unable to read: {}", e)),
+    };
+
+    match ShardConfig::from_ssz_bytes(&value) {
+        Ok(persisted) if persisted == configured => CheckStatus::Passed,
+        Ok(persisted) => CheckStatus::Failed(format!(
+            "persisted {}/{} overrides configured {}/{}",
+            persisted.shard_id, persisted.num_shard, configured.shard_id, configured.num_shard
+        )),
+        Err(e) => CheckStatus::Failed(format!("persisted shard config undecodable: {:?}", e)),
+    }
+}
+
+fn check_flow_root(db: StoreHandles, config: LogConfig, repair: bool) -> CheckStatus {
+    let expected = match expected_flow(&db, &config) {
+        Ok(expected) => expected,
+        Err(e) => return CheckStatus::Failed(format!("unable to replay txs: {:?}", e)),
+    };
+
+    let reason = match load_flow(db.clone(), config.clone()) {
+        Ok(actual) if actual == expected => return CheckStatus::Passed,
+        Ok((root, length)) => format!(
+            "flow root {:?} with length {} mismatches {:?} with length {} rebuilt from txs",
+            root, length, expected.0, expected.1
+        ),
+        Err(e) => format!("unable to load flow: {:?}", e),
+    };
+
+    if !repair {
+        return CheckStatus::Failed(reason);
+    }
+
+    let rebuilt = LogManager::rebuild_merkle(db, config).and_then(|store| store.get_context());
+    match rebuilt {
+        Ok(actual) if actual == expected => {
+            CheckStatus::Repaired(format!("{}, flow merkle rebuilt", reason))
+        }
+        Ok((root, length)) => CheckStatus::Failed(format!(
+            "{}, flow root {:?} with length {} after rebuilt",
+            reason, root, length
+        )),
+        Err(e) => CheckStatus::Failed(format!("{}, unable to rebuild: {:?}", reason, e)),
+    }
+}
+
+/// Opens the flow, which may panic on inconsistent dbs.
+fn load_flow(db: StoreHandles, config: LogConfig) -> Result<(H256, u64)> {
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        LogManager::with_dbs(db, config)?.get_context()
+    }))
+    .map_err(|_| anyhow!("panicked"))?
+}
+
+/// Replays the root nodes of all txs into an in-memory flow, whose cost is linear to the
+/// number of txs.
+fn expected_flow(db: &StoreHandles, config: &LogConfig) -> Result<(H256, u64)> {
+    let tx_store = TransactionStore::new(db.clone())?;
+    let reference = LogManager::memorydb(config.clone())?;
+    for seq in 0..tx_store.next_tx_seq() {
+        let tx = tx_store
+            .get_tx_by_seq_number(seq)?
+            .ok_or_else(|| anyhow!("tx missing: seq={}", seq))?;
+        reference.put_tx(tx)?;
+    }
+    reference.get_context()
+}
@@ -8,8 +8,8 @@ use crate::LogManager;
 macro_rules! db_operation {
     ($self:expr, $dest:expr, get, $key:expr) => {{
         let db = match $dest {
-            DATA_DB_KEY => &$self.data_db,
-            FLOW_DB_KEY => &$self.flow_db,
+            DATA_DB_KEY => $self.db.data(),
+            FLOW_DB_KEY => $self.db.flow(),
             _ => return Err(anyhow!("Invalid destination")),
         };
         Ok(db.get(COL_MISC, $key)?)
@@ -17,8 +17,8 @@ macro_rules! db_operation {
 
     ($self:expr, $dest:expr, put, $key:expr, $value:expr) => {{
         let db = match $dest {
-            DATA_DB_KEY => &$self.data_db,
-            FLOW_DB_KEY => &$self.flow_db,
+            DATA_DB_KEY => $self.db.data(),
+            FLOW_DB_KEY => $self.db.flow(),
             _ => return Err(anyhow!("Invalid destination")),
         };
         Ok(db.put(COL_MISC, $key, $value)?)
@@ -26,8 +26,8 @@ macro_rules! db_operation {
 
     ($self:expr, $dest:expr, delete, $key:expr) => {{
         let db = match $dest {
-            DATA_DB_KEY => &$self.data_db,
-            FLOW_DB_KEY => &$self.flow_db,
+            DATA_DB_KEY => $self.db.data(),
+            FLOW_DB_KEY => $self.db.flow(),
             _ => return Err(anyhow!("Invalid destination")),
         };
         Ok(db.delete(COL_MISC, $key)?)
@@ -35,8 +35,8 @@ macro_rules! db_operation {
 
     ($self:expr, $dest:expr, transaction, $tx:expr) => {{
         let db = match $dest {
-            DATA_DB_KEY => &$self.data_db,
-            FLOW_DB_KEY => &$self.flow_db,
+            DATA_DB_KEY => $self.db.data(),
+            FLOW_DB_KEY => $self.db.flow(),
             _ => return Err(anyhow!("Invalid destination")),
         };
         let mut db_tx = db.transaction();
@@ -0,0 +1,303 @@
+use crate::log_store::metrics;
+use parking_lot::Mutex;
+use shared_types::TxID;
+use std::cmp;
+use std::collections::VecDeque;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+#[derive(Clone, Debug)]
+pub struct FinalizationBusConfig {
+    /// Maximum number of txs delivered to a subscriber at a time.
+    pub max_batch_size: usize,
+    /// Maximum time that a finalized tx is queued before delivered.
+    pub max_delay: Duration,
+    /// Maximum number of txs queued for a subscriber, beyond which the queued txs are coalesced
+    /// into a range.
+    pub max_queue_size: usize,
+}
+
+impl Default for FinalizationBusConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 256,
+            max_delay: Duration::from_millis(500),
+            max_queue_size: 4096,
+        }
+    }
+}
+
+/// Txs finalized in the store, delivered to subscribers in batch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FinalizationEvent {
+    /// Txs in the order of finalization.
+    Finalized(Vec<TxID>),
+    /// Summary of the txs finalized while the subscriber lagged behind, which are all in
+    /// `[start_seq, end_seq]`, so subscribers should read the txs from store if needed.
+    FinalizedRange {
+        start_seq: u64,
+        end_seq: u64,
+        num_txs: usize,
+    },
+}
+
+impl FinalizationEvent {
+    /// Returns whether the tx may be finalized in the event, which is always true for the txs in
+    /// a coalesced range.
+    pub fn contains(&self, tx_seq: u64) -> bool {
+        match self {
+            FinalizationEvent::Finalized(tx_ids) => tx_ids.iter().any(|id| id.seq == tx_seq),
+            FinalizationEvent::FinalizedRange {
+                start_seq, end_seq, ..
+            } => (*start_seq..=*end_seq).contains(&tx_seq),
+        }
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    pending: VecDeque<TxID>,
+    /// When the oldest tx in `pending` is queued.
+    since: Option<Instant>,
+    /// `(start_seq, end_seq, num_txs)` of the txs coalesced on overflow.
+    coalesced: Option<(u64, u64, usize)>,
+}
+
+#[derive(Default)]
+struct SubscriberQueue {
+    state: Mutex<QueueState>,
+    notify: Notify,
+}
+
+/// Delivers the finalized txs to subscribers in batch, so that a burst of finalization, e.g.
+/// import, does not wake up subscribers for each tx. The queue of a slow subscriber is bounded
+/// by coalescing the queued txs into a [`FinalizationEvent::FinalizedRange`].
+pub struct FinalizationBus {
+    config: FinalizationBusConfig,
+    subscribers: Mutex<Vec<Weak<SubscriberQueue>>>,
+}
+
+impl FinalizationBus {
+    pub fn new(config: FinalizationBusConfig) -> Self {
+        Self {
+            config,
+            subscribers: Default::default(),
+        }
+    }
+
+    /// Queues the finalized tx for all subscribers without blocking.
+    pub fn publish(&self, tx_id: TxID) {
+        let mut subscribers = self.subscribers.lock();
+        subscribers.retain(|subscriber| match subscriber.upgrade() {
+            Some(queue) => {
+                self.enqueue(&queue, tx_id);
+                true
+            }
+            None => false,
+        });
+    }
+
+    fn enqueue(&self, queue: &SubscriberQueue, tx_id: TxID) {
+        let mut state = queue.state.lock();
+        if state.pending.len() >= self.config.max_queue_size {
+            let mut range = state.coalesced.unwrap_or((u64::MAX, 0, 0));
+            for queued in state.pending.drain(..) {
+                range = (
+                    cmp::min(range.0, queued.seq),
+                    cmp::max(range.1, queued.seq),
+                    range.2 + 1,
+                );
+            }
+            state.coalesced = Some(range);
+            metrics::FINALIZATION_COALESCED.inc(1);
+        }
+
+        let was_empty = state.pending.is_empty();
+        state.pending.push_back(tx_id);
+        if was_empty {
+            state.since = Some(Instant::now());
+        }
+
+        // Only wake up the subscriber to start the delay timer or deliver a full batch.
+        if was_empty || state.pending.len() == self.config.max_batch_size {
+            queue.notify.notify_one();
+        }
+    }
+
+    pub fn subscribe(&self) -> FinalizationSubscriber {
+        let queue = Arc::new(SubscriberQueue::default());
+        self.subscribers.lock().push(Arc::downgrade(&queue));
+        FinalizationSubscriber {
+            config: self.config.clone(),
+            queue,
+        }
+    }
+}
+
+/// Receives the finalized txs, which stops receiving once dropped.
+pub struct FinalizationSubscriber {
+    config: FinalizationBusConfig,
+    queue: Arc<SubscriberQueue>,
+}
+
+impl FinalizationSubscriber {
+    /// Waits until a full batch is queued or the oldest queued tx is delayed long enough.
+    ///
+    /// This is cancel safe, so that it could be used in `tokio::select!`.
+    pub async fn recv(&mut self) -> FinalizationEvent {
+        loop {
+            let wait = match self.try_recv() {
+                Ok(event) => return event,
+                Err(wait) => wait,
+            };
+            match wait {
+                Some(delay) => {
+                    let _ = tokio::time::timeout(delay, self.queue.notify.notified()).await;
+                }
+                None => self.queue.notify.notified().await,
+            }
+        }
+    }
+
+    /// Returns the next event if ready, or the time to wait until ready, which is `None` if no
+    /// tx is queued.
+    fn try_recv(&self) -> Result<FinalizationEvent, Option<Duration>> {
+        let mut state = self.queue.state.lock();
+        if let Some((start_seq, end_seq, num_txs)) = state.coalesced.take() {
+            return Ok(FinalizationEvent::FinalizedRange {
+                start_seq,
+                end_seq,
+                num_txs,
+            });
+        }
+
+        let since = match state.since {
+            Some(since) => since,
+            None => return Err(None),
+        };
+        let elapsed = since.elapsed();
+        if state.pending.len() < self.config.max_batch_size && elapsed < self.config.max_delay {
+            return Err(Some(self.config.max_delay - elapsed));
+        }
+
+        let num_txs = cmp::min(state.pending.len(), self.config.max_batch_size);
+        let batch = state.pending.drain(..num_txs).collect();
+        // The remaining txs are delivered in the next batch without waiting further.
+        if state.pending.is_empty() {
+            state.since = None;
+        }
+        Ok(FinalizationEvent::Finalized(batch))
+    }
+
+    /// Returns the number of txs queued, excluding the coalesced ones.
+    pub fn queue_len(&self) -> usize {
+        self.queue.state.lock().pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum_types::H256;
+
+    fn tx_id(seq: u64) -> TxID {
+        TxID {
+            seq,
+            hash: H256::from_low_u64_be(seq),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batched_delivery() {
+        let bus = FinalizationBus::new(FinalizationBusConfig {
+            max_batch_size: 100,
+            max_delay: Duration::from_millis(50),
+            max_queue_size: 20_000,
+        });
+        let mut subscriber = bus.subscribe();
+
+        let num_txs = 10_000;
+        for seq in 0..num_txs {
+            bus.publish(tx_id(seq));
+        }
+        assert_eq!(subscriber.queue_len(), num_txs as usize);
+
+        let mut received = vec![];
+        while received.len() < num_txs as usize {
+            match subscriber.recv().await {
+                FinalizationEvent::Finalized(batch) => {
+                    assert_eq!(batch.len(), 100);
+                    received.extend(batch);
+                }
+                e => panic!("unexpected event {:?}", e),
+            }
+        }
+        assert_eq!(received, (0..num_txs).map(tx_id).collect::<Vec<_>>());
+
+        // A partial batch is delivered after the delay.
+        bus.publish(tx_id(num_txs));
+        let start = Instant::now();
+        assert_eq!(
+            subscriber.recv().await,
+            FinalizationEvent::Finalized(vec![tx_id(num_txs)])
+        );
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(subscriber.queue_len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_slow_subscriber() {
+        let bus = FinalizationBus::new(FinalizationBusConfig {
+            max_batch_size: 100,
+            max_delay: Duration::from_millis(50),
+            max_queue_size: 1000,
+        });
+        let mut slow = bus.subscribe();
+        let dropped = bus.subscribe();
+        drop(dropped);
+
+        let num_txs = 10_000;
+        for seq in 0..num_txs {
+            bus.publish(tx_id(seq));
+            assert!(slow.queue_len() <= 1000);
+        }
+        assert_eq!(bus.subscribers.lock().len(), 1);
+
+        // The txs out of the queue are summarized in a range.
+        let mut num_received = 0;
+        match slow.recv().await {
+            FinalizationEvent::FinalizedRange {
+                start_seq,
+                end_seq,
+                num_txs,
+            } => {
+                assert_eq!(start_seq, 0);
+                assert_eq!(end_seq + 1, num_txs as u64);
+                num_received += num_txs;
+            }
+            e => panic!("unexpected event {:?}", e),
+        }
+        while slow.queue_len() > 0 {
+            match slow.recv().await {
+                FinalizationEvent::Finalized(batch) => num_received += batch.len(),
+                e => panic!("unexpected event {:?}", e),
+            }
+        }
+        assert_eq!(num_received, num_txs as usize);
+    }
+
+    #[test]
+    fn test_event_contains() {
+        let event = FinalizationEvent::Finalized(vec![tx_id(3), tx_id(5)]);
+        assert!(event.contains(3));
+        assert!(!event.contains(4));
+        let event = FinalizationEvent::FinalizedRange {
+            start_seq: 3,
+            end_seq: 5,
+            num_txs: 2,
+        };
+        assert!(event.contains(4));
+        assert!(!event.contains(6));
+    }
+}
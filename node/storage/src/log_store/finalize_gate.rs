@@ -0,0 +1,49 @@
+//! Gate of tx finalization, so that txs are finalized only once confirmed by log sync, no matter
+//! whether the data is completed by log sync, chunk pool or file sync.
+
+use shared_types::Transaction;
+use std::collections::BTreeMap;
+
+#[derive(Default)]
+pub(crate) struct FinalizeGate {
+    /// Txs since this seq are not confirmed yet, or `None` if finalization is not gated.
+    next_confirmed_seq: Option<u64>,
+    /// Txs of completed data, which are finalized once confirmed.
+    deferred: BTreeMap<u64, Transaction>,
+}
+
+impl FinalizeGate {
+    /// Defers finalizing the tx if not confirmed yet, and returns whether deferred.
+    pub fn defer(&mut self, tx: &Transaction) -> bool {
+        match self.next_confirmed_seq {
+            Some(next_seq) if tx.seq >= next_seq => {
+                self.deferred.insert(tx.seq, tx.clone());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Defers finalizing the txs not confirmed yet, and returns the others.
+    pub fn defer_all(&mut self, txs: &[Transaction]) -> Vec<Transaction> {
+        txs.iter().filter(|tx| !self.defer(tx)).cloned().collect()
+    }
+
+    /// Confirms the txs before `next_seq`, and returns the deferred ones to finalize.
+    pub fn confirm(&mut self, next_seq: u64) -> Vec<Transaction> {
+        self.next_confirmed_seq = Some(next_seq);
+        let unconfirmed = self.deferred.split_off(&next_seq);
+        std::mem::replace(&mut self.deferred, unconfirmed)
+            .into_values()
+            .collect()
+    }
+
+    /// Drops the deferred txs since `tx_seq`, which are reverted, and gates the txs put again
+    /// until confirmed.
+    pub fn revert(&mut self, tx_seq: u64) {
+        self.deferred.split_off(&tx_seq);
+        if let Some(next_seq) = self.next_confirmed_seq.as_mut() {
+            *next_seq = (*next_seq).min(tx_seq);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::config::ShardConfig;
-use crate::error::Error;
+use crate::error::{StoreError, StoreItem};
 use crate::log_store::load_chunk::EntryBatch;
 use crate::log_store::log_manager::{
     bytes_to_entries, COL_ENTRY_BATCH, COL_FLOW_MPT_NODES, COL_PAD_DATA_LIST,
@@ -13,6 +13,7 @@ use crate::{try_option, ZgsKeyValueDB};
 use any::Any;
 use anyhow::{anyhow, bail, Result};
 use append_merkle::{MerkleTreeRead, NodeDatabase, NodeTransaction};
+use ethereum_types::H256;
 use itertools::Itertools;
 use kvdb::DBTransaction;
 use parking_lot::RwLock;
@@ -65,7 +66,9 @@ impl FlowStore {
         let batch = self
             .data_db
             .get_entry_batch(batch_index as u64)?
-            .ok_or_else(|| anyhow!("batch missing, index={}", batch_index))?;
+            .ok_or_else(|| StoreError::NotFound {
+                what: StoreItem::Batch(batch_index as u64),
+            })?;
         let merkle = batch.to_merkle_tree(batch_index == 0)?.ok_or_else(|| {
             anyhow!(
                 "batch data incomplete for building a merkle tree, index={}",
@@ -75,10 +78,68 @@ impl FlowStore {
         merkle.gen_proof(sector_index)
     }
 
+    /// Loads the sealed data of the load chunk along with the miner id it is sealed for.
+    pub fn load_sealed_batch(&self, chunk_index: u64) -> Result<Option<(MineLoadChunk, H256)>> {
+        let batch = try_option!(self.data_db.get_entry_batch(chunk_index)?);
+        let mut mine_chunk = MineLoadChunk::default();
+        for (seal_index, ((sealed, validity), context)) in mine_chunk
+            .loaded_chunk
+            .iter_mut()
+            .zip(mine_chunk.availabilities.iter_mut())
+            .zip(mine_chunk.seal_contexts.iter_mut())
+            .enumerate()
+        {
+            if let Some(data) = batch.get_sealed_data(seal_index as u16) {
+                *validity = true;
+                *sealed = data;
+                *context = batch.get_seal_context_digest(seal_index as u16);
+            }
+        }
+        Ok(Some((mine_chunk, batch.get_seal_miner_id())))
+    }
+
+    /// Returns the root of the entry batch, or `None` if its data or subtree roots are not
+    /// completely stored.
+    pub fn get_batch_root(&self, batch_index: u64) -> Result<Option<DataRoot>> {
+        let batch = try_option!(self.data_db.get_entry_batch(batch_index)?);
+        batch.build_root(batch_index == 0)
+    }
+
     pub fn delete_batch_list(&self, batch_list: &[u64]) -> Result<()> {
         self.seal_manager.delete_batch_list(batch_list);
         self.data_db.delete_batch_list(batch_list)
     }
+
+    /// Removes the data of the sectors `[start_sector, end_sector)` from the entry batch, which is
+    /// shared with other data, and seals the remaining data again.
+    pub fn remove_batch_range(
+        &self,
+        batch_index: u64,
+        start_sector: usize,
+        end_sector: usize,
+    ) -> Result<()> {
+        let mut to_seal_set = self.seal_manager.to_seal_set.write();
+        let batch = match self.data_db.get_entry_batch(batch_index)? {
+            Some(batch) => batch,
+            None => return Ok(()),
+        };
+        let (batch, to_reseal) = batch.remove_range(start_sector, end_sector)?;
+
+        let first_seal_index = batch_index as usize * SEALS_PER_LOAD;
+        for seal_index in first_seal_index..first_seal_index + SEALS_PER_LOAD {
+            to_seal_set.remove(&seal_index);
+        }
+        let new_seal_version = self.seal_manager.inc_seal_version();
+        to_reseal.into_iter().for_each(|x| {
+            to_seal_set.insert(first_seal_index + x as usize, new_seal_version);
+        });
+
+        if batch.is_empty() {
+            self.data_db.delete_batch_list(&[batch_index])
+        } else {
+            self.data_db.put_entry_raw(vec![(batch_index, batch)])
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -134,6 +195,35 @@ impl FlowRead for FlowStore {
         }))
     }
 
+    fn has_entries(&self, index_start: u64, index_end: u64) -> Result<bool> {
+        if index_end <= index_start {
+            bail!(
+                "invalid entry index: start={} end={}",
+                index_start,
+                index_end
+            );
+        }
+        for (start_entry_index, end_entry_index) in
+            batch_iter(index_start, index_end, self.config.batch_size)
+        {
+            let chunk_index = start_entry_index / self.config.batch_size as u64;
+            let mut offset = start_entry_index - chunk_index * self.config.batch_size as u64;
+            let mut length = end_entry_index - start_entry_index;
+
+            // Tempfix: for first chunk, its offset is always 1
+            if chunk_index == 0 && offset == 0 {
+                offset = 1;
+                length -= 1;
+            }
+
+            match self.data_db.get_entry_batch(chunk_index)? {
+                Some(entry_batch) if entry_batch.has_data(offset as usize, length as usize) => {}
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
     fn get_available_entries(&self, index_start: u64, index_end: u64) -> Result<Vec<ChunkArray>> {
         // Both `index_start` and `index_end` are at the batch boundaries, so we do not need
         // to check if the data is within range when we process each batch.
@@ -177,20 +267,9 @@ impl FlowRead for FlowStore {
     }
 
     fn load_sealed_data(&self, chunk_index: u64) -> Result<Option<MineLoadChunk>> {
-        let batch = try_option!(self.data_db.get_entry_batch(chunk_index)?);
-        let mut mine_chunk = MineLoadChunk::default();
-        for (seal_index, (sealed, validity)) in mine_chunk
-            .loaded_chunk
-            .iter_mut()
-            .zip(mine_chunk.availabilities.iter_mut())
-            .enumerate()
-        {
-            if let Some(data) = batch.get_sealed_data(seal_index as u16) {
-                *validity = true;
-                *sealed = data;
-            }
-        }
-        Ok(Some(mine_chunk))
+        Ok(self
+            .load_sealed_batch(chunk_index)?
+            .map(|(mine_chunk, _)| mine_chunk))
     }
 
     fn get_num_entries(&self) -> Result<u64> {
@@ -267,9 +346,10 @@ impl FlowWrite for FlowStore {
         self.data_db.put_entry_batch_list(batch_list)
     }
 
-    fn truncate(&self, start_index: u64) -> crate::error::Result<()> {
+    fn truncate(&self, start_index: u64) -> crate::error::Result<usize> {
         let mut to_seal_set = self.seal_manager.to_seal_set.write();
-        let to_reseal = self.data_db.truncate(start_index, self.config.batch_size)?;
+        let (to_reseal, truncated_batches) =
+            self.data_db.truncate(start_index, self.config.batch_size)?;
 
         to_seal_set.split_off(&(start_index as usize / SECTORS_PER_SEAL));
         let new_seal_version = self.seal_manager.inc_seal_version();
@@ -277,7 +357,7 @@ impl FlowWrite for FlowStore {
         to_reseal.into_iter().for_each(|x| {
             to_seal_set.insert(x, new_seal_version);
         });
-        Ok(())
+        Ok(truncated_batches)
     }
 
     fn update_shard_config(&self, shard_config: ShardConfig) {
@@ -294,11 +374,15 @@ impl FlowWrite for FlowStore {
 }
 
 impl FlowSeal for FlowStore {
-    fn pull_seal_chunk(&self, seal_index_max: usize) -> Result<Option<Vec<SealTask>>> {
+    fn pull_seal_chunk(
+        &self,
+        seal_index_min: usize,
+        seal_index_max: usize,
+    ) -> Result<Option<Vec<SealTask>>> {
         let to_seal_set = self.seal_manager.to_seal_set.read();
         self.seal_manager.update_pull_time();
 
-        let mut to_seal_iter = to_seal_set.iter();
+        let mut to_seal_iter = to_seal_set.range(seal_index_min..);
         let (&first_index, &first_version) = try_option!(to_seal_iter.next());
         if first_index >= seal_index_max {
             return Ok(None);
@@ -330,7 +414,8 @@ impl FlowSeal for FlowStore {
         Ok(Some(tasks))
     }
 
-    fn submit_seal_result(&self, answers: Vec<SealAnswer>) -> Result<()> {
+    fn submit_seal_result(&self, mut answers: Vec<SealAnswer>) -> Result<()> {
+        let start_time = Instant::now();
         let mut to_seal_set = self.seal_manager.to_seal_set.write();
         let is_consistent = |answer: &SealAnswer| {
             to_seal_set
@@ -338,6 +423,9 @@ impl FlowSeal for FlowStore {
                 .map_or(false, |cur_ver| cur_ver == &answer.version)
         };
 
+        // answers of the same load chunk must be adjacent, so that the chunk is updated once
+        answers.sort_by_key(|answer| answer.seal_index);
+
         let mut updated_chunk = vec![];
         let mut removed_seal_index = Vec::new();
         for (load_index, answers_in_chunk) in &answers
@@ -357,12 +445,17 @@ impl FlowSeal for FlowStore {
         }
 
         debug!("Seal chunks: indices = {:?}", removed_seal_index);
+        metrics::SEAL_RESULT_BATCH_SEALS.update(removed_seal_index.len() as u64);
+        metrics::SEAL_RESULT_BATCH_CHUNKS.update(updated_chunk.len() as u64);
+
+        // committed before removed from the seal set, so that the seals are pulled again if the
+        // write failed
+        self.data_db.put_entry_raw(updated_chunk)?;
 
         for idx in removed_seal_index.into_iter() {
             to_seal_set.remove(&idx);
         }
-
-        self.data_db.put_entry_raw(updated_chunk)?;
+        metrics::SUBMIT_SEAL_RESULT.update_since(start_time);
 
         Ok(())
     }
@@ -421,11 +514,19 @@ impl FlowDBStore {
 
     fn get_entry_batch(&self, batch_index: u64) -> Result<Option<EntryBatch>> {
         let raw = try_option!(self.kvdb.get(COL_ENTRY_BATCH, &batch_index.to_be_bytes())?);
-        Ok(Some(EntryBatch::from_ssz_bytes(&raw).map_err(Error::from)?))
+        Ok(Some(
+            EntryBatch::from_ssz_bytes(&raw).map_err(StoreError::from)?,
+        ))
     }
 
-    fn truncate(&self, start_index: u64, batch_size: usize) -> crate::error::Result<Vec<usize>> {
+    /// Returns the seal indices to reseal, and the number of entry batches truncated.
+    fn truncate(
+        &self,
+        start_index: u64,
+        batch_size: usize,
+    ) -> crate::error::Result<(Vec<usize>, usize)> {
         let mut tx = self.kvdb.transaction();
+        let mut truncated_batches = 0;
         let mut start_batch_index = start_index / batch_size as u64;
         let first_batch_offset = start_index as usize % batch_size;
         let mut index_to_reseal = Vec::new();
@@ -436,6 +537,7 @@ impl FlowDBStore {
                     .into_iter()
                     .map(|x| start_batch_index as usize * SEALS_PER_LOAD + x as usize)
                     .collect();
+                truncated_batches += 1;
                 if !first_batch.is_empty() {
                     tx.put(
                         COL_ENTRY_BATCH,
@@ -459,14 +561,15 @@ impl FlowDBStore {
             }
             None => {
                 // The db has no data, so we can just return;
-                return Ok(index_to_reseal);
+                return Ok((index_to_reseal, truncated_batches));
             }
         };
         for batch_index in start_batch_index as usize..=end {
             tx.delete(COL_ENTRY_BATCH, &batch_index.to_be_bytes());
+            truncated_batches += 1;
         }
         self.kvdb.write(tx)?;
-        Ok(index_to_reseal)
+        Ok((index_to_reseal, truncated_batches))
     }
 
     fn delete_batch_list(&self, batch_list: &[u64]) -> Result<()> {
@@ -513,7 +616,7 @@ impl FlowDBStore {
     fn get_pad_data(&self, tx_seq: u64) -> Result<Option<Vec<PadPair>>> {
         match self.kvdb.get(COL_PAD_DATA_LIST, &tx_seq.to_be_bytes())? {
             Some(v) => Ok(Some(
-                Vec::<PadPair>::from_ssz_bytes(&v).map_err(Error::from)?,
+                Vec::<PadPair>::from_ssz_bytes(&v).map_err(StoreError::from)?,
             )),
             None => Ok(None),
         }
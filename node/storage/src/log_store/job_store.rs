@@ -0,0 +1,89 @@
+//! Persistent records of the long-running admin jobs, e.g. a full scrub of the flow or a file
+//! export, so that the jobs survive the RPC request that started them and resume after restart.
+//!
+//! The store only keeps the state and progress of a job along with its params and checkpoint,
+//! which are opaque bytes defined by each job type.
+
+use crate::error::StoreError;
+use crate::log_store::log_manager::COL_ADMIN_JOB;
+use crate::ZgsKeyValueDB;
+use anyhow::Result;
+use ssz::{Decode, Encode};
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
+use std::sync::Arc;
+
+pub const JOB_STATE_RUNNING: u8 = 0;
+pub const JOB_STATE_COMPLETED: u8 = 1;
+pub const JOB_STATE_FAILED: u8 = 2;
+pub const JOB_STATE_CANCELLED: u8 = 3;
+
+/// Admin job persisted in the flow db, keyed by id.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct AdminJob {
+    pub id: u64,
+    /// One of `JOB_STATE_*`. Running jobs are resumed from `checkpoint` after restart.
+    pub state: u8,
+    /// Params of the job, encoded by the job type.
+    pub params: Vec<u8>,
+    /// Checkpoint of the job, encoded by the job type, or empty if not started yet.
+    pub checkpoint: Vec<u8>,
+    pub progress_done: u64,
+    pub progress_total: u64,
+    /// Reason why the job failed.
+    pub error: Option<Vec<u8>>,
+    /// Unix timestamps in seconds.
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl AdminJob {
+    pub fn is_running(&self) -> bool {
+        self.state == JOB_STATE_RUNNING
+    }
+}
+
+/// Persists the admin jobs in the flow db, ordered by id.
+pub struct JobStore {
+    flow_kvdb: Arc<dyn ZgsKeyValueDB>,
+}
+
+impl JobStore {
+    pub fn new(flow_kvdb: Arc<dyn ZgsKeyValueDB>) -> Self {
+        Self { flow_kvdb }
+    }
+
+    /// Insert the job, or replace the one with the same id.
+    pub fn put(&self, job: &AdminJob) -> Result<()> {
+        Ok(self
+            .flow_kvdb
+            .put(COL_ADMIN_JOB, &job.id.to_be_bytes(), &job.as_ssz_bytes())?)
+    }
+
+    pub fn get(&self, id: u64) -> Result<Option<AdminJob>> {
+        match self.flow_kvdb.get(COL_ADMIN_JOB, &id.to_be_bytes())? {
+            Some(value) => Ok(Some(
+                AdminJob::from_ssz_bytes(&value).map_err(StoreError::from)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove the jobs of `ids`, e.g. the finished jobs beyond retention.
+    pub fn remove(&self, ids: &[u64]) -> Result<()> {
+        let mut db_tx = self.flow_kvdb.transaction();
+        for id in ids {
+            db_tx.delete(COL_ADMIN_JOB, &id.to_be_bytes());
+        }
+        Ok(self.flow_kvdb.write(db_tx)?)
+    }
+
+    /// Return all jobs in ascending order of id.
+    pub fn get_all(&self) -> Result<Vec<AdminJob>> {
+        let mut jobs = Vec::new();
+        for r in self.flow_kvdb.iter(COL_ADMIN_JOB) {
+            let (_, value) = r?;
+            jobs.push(AdminJob::from_ssz_bytes(&value).map_err(StoreError::from)?);
+        }
+        Ok(jobs)
+    }
+}
@@ -55,6 +55,20 @@ impl EntryBatch {
         }
     }
 
+    /// Returns the context digest that the data is sealed with, or `None` if not sealed.
+    pub fn get_seal_context_digest(&self, seal_index: u16) -> Option<H256> {
+        if self.seal.is_sealed(seal_index) {
+            self.seal.get_seal_context_digest(seal_index)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the miner id that the batch is sealed for, or zero if nothing sealed yet.
+    pub fn get_seal_miner_id(&self) -> H256 {
+        self.seal.miner_id()
+    }
+
     pub fn get_non_sealed_data(&self, seal_index: u16) -> Option<[u8; BYTES_PER_SEAL]> {
         if !self.seal.is_sealed(seal_index) {
             let loaded_slice = self
@@ -66,6 +80,16 @@ impl EntryBatch {
         }
     }
 
+    /// Check if the data of the given sectors is present, without loading or unsealing it.
+    pub fn has_data(&self, start_sector: usize, length_sector: usize) -> bool {
+        self.data
+            .get(
+                start_sector * BYTES_PER_SECTOR,
+                length_sector * BYTES_PER_SECTOR,
+            )
+            .is_some()
+    }
+
     /// Get unsealed data
     pub fn get_unsealed_data(&self, start_sector: usize, length_sector: usize) -> Option<Vec<u8>> {
         // If the start position is not aligned and is sealed, we need to load one more word (32 bytes) for unsealing
@@ -144,6 +168,34 @@ impl EntryBatch {
         self.truncate_seal(truncated_sector)
     }
 
+    /// Returns a new batch of the data out of the sectors `[start_sector, end_sector)` and the
+    /// subtrees, along with the seal indices to seal again. The data is unsealed in the new batch.
+    pub fn remove_range(&self, start_sector: usize, end_sector: usize) -> Result<(Self, Vec<u16>)> {
+        let mut batch = Self::new(self.seal.load_index());
+        let mut to_seal = Vec::new();
+        for (start, length) in self.data.available_range_entries() {
+            let end = start + length;
+            for (kept_start, kept_end) in
+                [(start, end.min(start_sector)), (start.max(end_sector), end)]
+            {
+                if kept_start < kept_end {
+                    let data = self
+                        .get_unsealed_data(kept_start, kept_end - kept_start)
+                        .expect("available data");
+                    to_seal.extend(batch.insert_data(kept_start, data)?);
+                }
+            }
+        }
+        batch.data.set_subtree_list(
+            self.data
+                .get_subtree_list()
+                .iter()
+                .map(|subtree| (subtree.start_sector, subtree.subtree_height, subtree.root))
+                .collect(),
+        );
+        Ok((batch, to_seal))
+    }
+
     pub fn into_data_list(self, global_start_entry: u64) -> Vec<ChunkArray> {
         self.data
             .available_range_entries()
@@ -403,6 +455,9 @@ mod tests {
         seal(&mut batch, 1, DIGEST1, 2);
 
         check_two_seals(&batch);
+        assert_eq!(batch.get_seal_context_digest(0), Some(DIGEST0));
+        assert_eq!(batch.get_seal_context_digest(1), Some(DIGEST1));
+        assert_eq!(batch.get_seal_context_digest(2), None);
     }
 
     #[test]
@@ -54,6 +54,10 @@ impl SealInfo {
         self.load_index
     }
 
+    pub fn miner_id(&self) -> H256 {
+        self.miner_id
+    }
+
     pub fn global_seal_sector(&self, index: u16) -> u64 {
         (self.load_index as usize * SECTORS_PER_LOAD + index as usize * SECTORS_PER_SEAL) as u64
     }
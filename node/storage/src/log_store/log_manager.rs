@@ -1,23 +1,39 @@
-use crate::config::ShardConfig;
+use crate::config::{DbEngine, LogStoreConfig, ShardConfig, StoreNetworkId, NETWORK_ID_KEY};
+use crate::error::{StoreError, StoreItem};
+use crate::log_store::config::ConfigurableExt;
+#[cfg(feature = "runtime")]
+use crate::log_store::finalization_bus::{
+    FinalizationBus, FinalizationBusConfig, FinalizationSubscriber,
+};
+use crate::log_store::finalize_gate::FinalizeGate;
 use crate::log_store::flow_store::{
-    batch_iter_sharded, FlowConfig, FlowDBStore, FlowStore, PadPair,
+    batch_iter, batch_iter_sharded, FlowConfig, FlowDBStore, FlowStore, PadPair,
+};
+use crate::log_store::job_store::{AdminJob, JobStore};
+use crate::log_store::merkle_state::MerkleState;
+use crate::log_store::revert_history::{RevertEvent, RevertHistory};
+use crate::log_store::revert_journal::{RevertJournal, RevertJournalConfig};
+use crate::log_store::reward_store::{MinerReward, MinerRewardSummary, RewardStore};
+use crate::log_store::tree_gate::TreeGate;
+use crate::log_store::tx_store::{
+    BlockHashAndSubmissionIndex, ChunkRange, FlushJournal, SubmissionContext, TransactionStore,
+    TxStatus,
 };
-use crate::log_store::tx_store::{BlockHashAndSubmissionIndex, TransactionStore, TxStatus};
 use crate::log_store::{
-    FlowRead, FlowSeal, FlowWrite, LogStoreChunkRead, LogStoreChunkWrite, LogStoreRead,
-    LogStoreWrite, MineLoadChunk, SealAnswer, SealTask,
+    ColumnStats, FlowRead, FlowSeal, FlowWrite, LogStoreChunkRead, LogStoreChunkWrite,
+    LogStoreRead, LogStoreWrite, MineLoadChunk, SealAnswer, SealTask, SealedChunkWithProof,
 };
-use crate::{try_option, ZgsKeyValueDB};
+use crate::{open_kvdb, try_option, DbLayout, KvdbIntrospection, StoreHandles};
 use anyhow::{anyhow, bail, Result};
 use append_merkle::{Algorithm, MerkleTreeRead, Sha3Algorithm};
 use ethereum_types::H256;
-use kvdb_rocksdb::{Database, DatabaseConfig};
 use merkle_light::merkle::{log2_pow2, MerkleTree};
 use merkle_tree::RawLeafSha3Algorithm;
 use once_cell::sync::Lazy;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use rayon::iter::ParallelIterator;
 use rayon::prelude::ParallelSlice;
+pub use shared_types::split_nodes;
 use shared_types::{
     bytes_to_chunks, compute_padded_chunk_size, compute_segment_size, Chunk, ChunkArray,
     ChunkArrayWithProof, ChunkWithProof, DataRoot, FlowProof, FlowRangeProof, Merkle, Transaction,
@@ -25,6 +41,7 @@ use shared_types::{
 use std::cmp::Ordering;
 
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -46,15 +63,48 @@ pub const COL_FLOW_MPT_NODES: u32 = 5; // flow db
 pub const COL_BLOCK_PROGRESS: u32 = 6; // flow db
 pub const COL_PAD_DATA_LIST: u32 = 7; // flow db
 pub const COL_PAD_DATA_SYNC_HEIGH: u32 = 8; // data db
-pub const COL_NUM: u32 = 9;
+pub const COL_FLUSH_JOURNAL: u32 = 9; // data db
+pub const COL_MINER_REWARD: u32 = 10; // flow db
+pub const COL_TX_SUBMISSION: u32 = 11; // flow db
+pub const COL_TX_FIRST_SEEN: u32 = 12; // flow db
+pub const COL_REVERTED_BATCH: u32 = 13; // data db
+pub const COL_ADMIN_JOB: u32 = 14; // flow db
+pub const COL_MINER_REWARD_BLOCK: u32 = 15; // flow db
+pub const COL_NUM: u32 = 16;
+
+/// Column names used in metrics, indexed by the column id.
+pub const COL_NAMES: [&str; COL_NUM as usize] = [
+    "tx",
+    "entry_batch",
+    "tx_data_root_index",
+    "tx_completed",
+    "misc",
+    "flow_mpt_nodes",
+    "block_progress",
+    "pad_data_list",
+    "pad_data_sync_height",
+    "flush_journal",
+    "miner_reward",
+    "tx_submission",
+    "tx_first_seen",
+    "reverted_batch",
+    "admin_job",
+    "miner_reward_block",
+];
 
 pub const DATA_DB_KEY: &str = "data_db";
 pub const FLOW_DB_KEY: &str = "flow_db";
-const PAD_DELAY: Duration = Duration::from_secs(2);
+#[cfg(feature = "runtime")]
+const PAD_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
 
 // Process at most 1M entries (256MB) pad data at a time.
 const PAD_MAX_SIZE: usize = 1 << 20;
 
+/// Number of merkle nodes read at once under the tree lock to build a merkle state.
+const MERKLE_STATE_READ_BATCH: usize = 16 * 1024;
+/// Times to build a merkle state before giving up, if txs are reverted meanwhile.
+const MERKLE_STATE_MAX_ATTEMPTS: usize = 3;
+
 static PAD_SEGMENT_ROOT: Lazy<H256> = Lazy::new(|| {
     Merkle::new(
         data_to_merkle_leaves(&[0; ENTRY_SIZE * PORA_CHUNK_SIZE]).unwrap(),
@@ -69,11 +119,23 @@ pub struct UpdateFlowMessage {
 }
 
 pub struct LogManager {
-    pub(crate) flow_db: Arc<dyn ZgsKeyValueDB>,
-    pub(crate) data_db: Arc<dyn ZgsKeyValueDB>,
+    pub(crate) db: StoreHandles,
     tx_store: TransactionStore,
     flow_store: Arc<FlowStore>,
-    merkle: RwLock<MerkleManager>,
+    merkle: TreeGate<MerkleManager>,
+    revert_history: RevertHistory,
+    revert_journal: RevertJournal,
+    reward_store: RewardStore,
+    job_store: JobStore,
+    #[cfg(feature = "runtime")]
+    finalization_bus: FinalizationBus,
+    /// Bumped before and after the served data changes, see `get_flow_version`.
+    flow_version: AtomicU64,
+    finalize_gate: Mutex<FinalizeGate>,
+    max_tx_inline_data_size: usize,
+    merkle_node_cache_capacity: usize,
+    /// Time of each phase to open the log manager, see `startup_phases`.
+    startup_phases: Mutex<Vec<(&'static str, Duration)>>,
 }
 
 struct MerkleManager {
@@ -108,7 +170,12 @@ impl MerkleManager {
         Ok(())
     }
 
-    fn revert_merkle_tree(&mut self, tx_seq: u64, tx_store: &TransactionStore) -> Result<()> {
+    fn revert_merkle_tree(
+        &mut self,
+        tx_seq: u64,
+        tx_store: &TransactionStore,
+        flow_store: &FlowStore,
+    ) -> Result<()> {
         debug!("revert merkle tree {}", tx_seq);
         // Special case for reverting tx_seq == 0
         if tx_seq == u64::MAX {
@@ -122,8 +189,11 @@ impl MerkleManager {
             self.last_chunk_merkle.revert_to(tx_seq)?;
         } else {
             // We are reverting to a position before the current last_chunk.
-            self.last_chunk_merkle =
-                tx_store.rebuild_last_chunk_merkle(self.pora_chunks_merkle.leaves() - 1, tx_seq)?;
+            self.last_chunk_merkle = tx_store.rebuild_last_chunk_merkle(
+                self.pora_chunks_merkle.leaves() - 1,
+                tx_seq,
+                |batch_index| flow_store.get_batch_root(batch_index),
+            )?;
         }
         Ok(())
     }
@@ -159,9 +229,28 @@ impl MerkleManager {
     }
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct LogConfig {
     pub flow: FlowConfig,
+    #[cfg(feature = "runtime")]
+    pub finalization: FinalizationBusConfig,
+    /// Maximum size of the data embedded in a tx, beyond which the tx is stored as invalid
+    /// without its data by `put_tx`.
+    pub max_tx_inline_data_size: usize,
+    /// Journal of the data of reverted txs, which is disabled by default.
+    pub revert_journal: RevertJournalConfig,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            flow: Default::default(),
+            #[cfg(feature = "runtime")]
+            finalization: Default::default(),
+            max_tx_inline_data_size: 256 * 1024,
+            revert_journal: Default::default(),
+        }
+    }
 }
 
 impl LogStoreChunkWrite for LogManager {
@@ -170,17 +259,19 @@ impl LogStoreChunkWrite for LogManager {
         let tx = self
             .tx_store
             .get_tx_by_seq_number(tx_seq)?
-            .ok_or_else(|| anyhow!("put chunks with missing tx: tx_seq={}", tx_seq))?;
+            .ok_or_else(|| StoreError::tx_not_found(tx_seq))?;
+        if self.tx_store.check_tx_invalid(tx_seq)? {
+            bail!(StoreError::Invalid { tx_seq });
+        }
         let (chunks_for_proof, _) = compute_padded_chunk_size(tx.size as usize);
         if chunks.start_index.saturating_mul(ENTRY_SIZE as u64) + chunks.data.len() as u64
             > (chunks_for_proof * ENTRY_SIZE) as u64
         {
-            bail!(
-                "put chunks with data out of tx range: tx_seq={} start_index={} data_len={}",
-                tx_seq,
-                chunks.start_index,
-                chunks.data.len()
-            );
+            bail!(StoreError::OutOfRange {
+                what: "chunks of tx",
+                index: chunks.start_index + bytes_to_entries(chunks.data.len() as u64),
+                bound: chunks_for_proof as u64,
+            });
         }
         // TODO: Use another struct to avoid confusion.
         let mut flow_entry_array = chunks;
@@ -201,20 +292,22 @@ impl LogStoreChunkWrite for LogManager {
         let tx = self
             .tx_store
             .get_tx_by_seq_number(tx_seq)?
-            .ok_or_else(|| anyhow!("put chunks with missing tx: tx_seq={}", tx_seq))?;
+            .ok_or_else(|| StoreError::tx_not_found(tx_seq))?;
         if tx.hash() != tx_hash {
             return Ok(false);
         }
+        if self.tx_store.check_tx_invalid(tx_seq)? {
+            bail!(StoreError::Invalid { tx_seq });
+        }
         let (chunks_for_proof, _) = compute_padded_chunk_size(tx.size as usize);
         if chunks.start_index.saturating_mul(ENTRY_SIZE as u64) + chunks.data.len() as u64
             > (chunks_for_proof * ENTRY_SIZE) as u64
         {
-            bail!(
-                "put chunks with data out of tx range: tx_seq={} start_index={} data_len={}",
-                tx_seq,
-                chunks.start_index,
-                chunks.data.len()
-            );
+            bail!(StoreError::OutOfRange {
+                what: "chunks of tx",
+                index: chunks.start_index + bytes_to_entries(chunks.data.len() as u64),
+                bound: chunks_for_proof as u64,
+            });
         }
         // TODO: Use another struct to avoid confusion.
         let mut flow_entry_array = chunks;
@@ -233,7 +326,11 @@ impl LogStoreChunkWrite for LogManager {
     }
 
     fn remove_chunks_batch(&self, batch_list: &[u64]) -> crate::error::Result<()> {
-        self.flow_store.delete_batch_list(batch_list)
+        // The pruned data is never served afterwards, e.g. from the segment cache of RPC.
+        self.flow_version.fetch_add(1, AtomicOrdering::SeqCst);
+        let result = self.flow_store.delete_batch_list(batch_list);
+        self.flow_version.fetch_add(1, AtomicOrdering::SeqCst);
+        result
     }
 }
 
@@ -259,68 +356,67 @@ impl LogStoreWrite for LogManager {
     /// `put_tx` for the last tx when we restart the node to ensure that it succeeds.
     ///
     fn put_tx(&self, tx: Transaction) -> Result<()> {
-        let start_time = Instant::now();
-        let mut merkle = self.merkle.write();
-        debug!("put_tx: tx={:?}", tx);
-        let expected_seq = self.tx_store.next_tx_seq();
-        if tx.seq != expected_seq {
-            if tx.seq + 1 == expected_seq && !self.check_tx_completed(tx.seq)? {
-                // special case for rerun the last tx during recovery.
-                debug!("recovery with tx_seq={}", tx.seq);
-            } else {
-                // This is not supposed to happen since we have checked the tx seq in log entry sync.
-                error!("tx mismatch, expected={} get={:?}", expected_seq, tx);
-                bail!("unexpected tx!");
-            }
-        }
-        let maybe_same_data_tx_seq = self.tx_store.put_tx(tx.clone())?.first().cloned();
-        // TODO(zz): Should we validate received tx?
-        self.append_subtree_list(
-            tx.seq,
-            tx.start_entry_index,
-            tx.merkle_nodes.clone(),
-            &mut merkle,
-        )?;
-        merkle.commit_merkle(tx.seq)?;
-        debug!(
-            "commit flow root: root={:?}",
-            merkle.pora_chunks_merkle.root()
-        );
-        // Drop the lock because `copy_tx_data` will lock again.
-        drop(merkle);
+        self.put_tx_with_status(tx, false)
+    }
 
-        if let Some(old_tx_seq) = maybe_same_data_tx_seq {
-            if self.check_tx_completed(old_tx_seq)? {
-                self.copy_tx_and_finalize(old_tx_seq, vec![tx.seq])?;
-            }
+    fn put_invalid_tx(&self, tx: Transaction) -> Result<()> {
+        self.put_tx_with_status(tx, true)
+    }
+
+    fn restore_reverted_tx(&self, tx_seq: u64, tx_hash: H256) -> Result<bool> {
+        if !self.revert_journal.is_enabled() || self.check_tx_completed(tx_seq)? {
+            return Ok(false);
         }
-        metrics::PUT_TX.update_since(start_time);
-        Ok(())
+        let tx = self
+            .tx_store
+            .get_tx_by_seq_number(tx_seq)?
+            .ok_or_else(|| StoreError::tx_not_found(tx_seq))?;
+        // Reverted again and replaced by another tx, which is checked by every write as well.
+        if tx.hash() != tx_hash {
+            return Ok(false);
+        }
+        // Staged until restored, so that the tx is not synced from peers meanwhile, and dropped
+        // even if failed to restore.
+        let restored = self.put_staged_tx(&tx, tx_hash);
+        self.revert_journal
+            .discard(vec![(tx.start_entry_index, tx.data_merkle_root)])?;
+        if !restored? {
+            return Ok(false);
+        }
+        metrics::REVERT_JOURNAL_RESTORED.inc(1);
+        info!(%tx_seq, "Restored reverted tx from the journal");
+        Ok(true)
     }
 
     fn finalize_tx(&self, tx_seq: u64) -> Result<()> {
         let tx = self
             .tx_store
             .get_tx_by_seq_number(tx_seq)?
-            .ok_or_else(|| anyhow!("finalize_tx with tx missing: tx_seq={}", tx_seq))?;
+            .ok_or_else(|| StoreError::tx_not_found(tx_seq))?;
+        if self.tx_store.check_tx_invalid(tx_seq)? {
+            bail!(StoreError::Invalid { tx_seq });
+        }
 
         self.padding_rear_data(&tx)?;
 
         let tx_end_index = tx.start_entry_index + bytes_to_entries(tx.size);
-        // TODO: Check completeness without loading all data in memory.
         // TODO: Should we double check the tx merkle root?
         if self.check_data_completed(tx.start_entry_index, tx_end_index)? {
+            if !self.mark_tx_finalized(&tx)? {
+                return Ok(());
+            }
             let same_root_seq_list = self
                 .tx_store
-                .get_tx_seq_list_by_data_root(&tx.data_merkle_root)?;
+                .get_live_tx_seq_list_by_data_root(&tx.data_merkle_root)?;
             // Check if there are other same-root transaction not finalized.
             if same_root_seq_list.first() == Some(&tx_seq) {
                 self.copy_tx_and_finalize(tx_seq, same_root_seq_list[1..].to_vec())?;
             }
-            self.tx_store.finalize_tx(tx_seq)?;
             Ok(())
         } else {
-            bail!("finalize tx with data missing: tx_seq={}", tx_seq)
+            bail!(StoreError::NotFound {
+                what: StoreItem::TxData(tx_seq),
+            })
         }
     }
 
@@ -334,22 +430,26 @@ impl LogStoreWrite for LogManager {
         let tx = self
             .tx_store
             .get_tx_by_seq_number(tx_seq)?
-            .ok_or_else(|| anyhow!("finalize_tx with tx missing: tx_seq={}", tx_seq))?;
+            .ok_or_else(|| StoreError::tx_not_found(tx_seq))?;
         debug!("finalize_tx_with_hash: tx={:?}", tx);
         if tx.hash() != tx_hash {
             return Ok(false);
         }
+        if self.tx_store.check_tx_invalid(tx_seq)? {
+            bail!(StoreError::Invalid { tx_seq });
+        }
 
         self.padding_rear_data(&tx)?;
 
-        // TODO: Check completeness without loading all data in memory.
         // TODO: Should we double check the tx merkle root?
         let tx_end_index = tx.start_entry_index + bytes_to_entries(tx.size);
         if self.check_data_completed(tx.start_entry_index, tx_end_index)? {
-            self.tx_store.finalize_tx(tx_seq)?;
+            if !self.mark_tx_finalized(&tx)? {
+                return Ok(true);
+            }
             let same_root_seq_list = self
                 .tx_store
-                .get_tx_seq_list_by_data_root(&tx.data_merkle_root)?;
+                .get_live_tx_seq_list_by_data_root(&tx.data_merkle_root)?;
             // Check if there are other same-root transaction not finalized.
             if same_root_seq_list.first() == Some(&tx_seq) {
                 self.copy_tx_and_finalize(tx_seq, same_root_seq_list[1..].to_vec())?;
@@ -357,16 +457,170 @@ impl LogStoreWrite for LogManager {
             metrics::FINALIZE_TX_WITH_HASH.update_since(start_time);
             Ok(true)
         } else {
-            bail!("finalize tx hash with data missing: tx_seq={}", tx_seq)
+            bail!(StoreError::NotFound {
+                what: StoreItem::TxData(tx_seq),
+            })
+        }
+    }
+
+    fn finalize_txs(&self, tx_seqs: Vec<u64>) -> Result<()> {
+        let start_time = Instant::now();
+        let txs = tx_seqs
+            .into_iter()
+            .map(|tx_seq| self.prepare_finalize(tx_seq))
+            .collect::<Result<Vec<_>>>()?;
+        self.mark_txs_finalized(&txs)?;
+        metrics::FINALIZE_TXS.update_since(start_time);
+        Ok(())
+    }
+
+    fn finalize_txs_with_hash(&self, txs: Vec<(u64, H256)>) -> Result<Vec<Result<bool>>> {
+        let start_time = Instant::now();
+        let mut results = Vec::with_capacity(txs.len());
+        let mut finalizable = Vec::with_capacity(txs.len());
+        for (tx_seq, tx_hash) in txs {
+            match self.prepare_finalize_with_hash(tx_seq, tx_hash) {
+                Ok(Some(tx)) => {
+                    finalizable.push(tx);
+                    results.push(Ok(true));
+                }
+                Ok(None) => results.push(Ok(false)),
+                Err(e) => results.push(Err(e)),
+            }
         }
+
+        self.mark_txs_finalized(&finalizable)?;
+        metrics::FINALIZE_TXS.update_since(start_time);
+        Ok(results)
+    }
+
+    fn confirm_finalization(&self, next_tx_seq: u64) -> Result<()> {
+        let confirmed = self.finalize_gate.lock().confirm(next_tx_seq);
+        let mut finalizable = vec![];
+        for tx in confirmed {
+            // The confirmed txs are checked again, e.g. marked invalid meanwhile.
+            match self.prepare_finalize_with_hash(tx.seq, tx.hash()) {
+                Ok(Some(tx)) => finalizable.push(tx),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to finalize confirmed tx: {:?}", e),
+            }
+        }
+        self.mark_txs_finalized(&finalizable)
     }
 
     fn prune_tx(&self, tx_seq: u64) -> crate::error::Result<()> {
-        self.tx_store.prune_tx(tx_seq)
+        self.flow_version.fetch_add(1, AtomicOrdering::SeqCst);
+        let result = self.tx_store.prune_tx(tx_seq);
+        self.flow_version.fetch_add(1, AtomicOrdering::SeqCst);
+        result?;
+
+        if let Some(tx) = self.tx_store.get_tx_by_seq_number(tx_seq)? {
+            self.tx_store
+                .compact_data_root_index(&tx.data_merkle_root)?;
+        }
+        Ok(())
+    }
+
+    fn compact_pruned_data_roots(&self) -> Result<usize> {
+        self.tx_store.compact_pruned_data_roots()
+    }
+
+    fn reset_tx_data(&self, tx_seq: u64, batch_list: &[u64]) -> Result<()> {
+        // Hold the lock to avoid inserting data of the batches concurrently.
+        let _merkle = self.merkle.write();
+        let tx = self
+            .tx_store
+            .get_tx_by_seq_number(tx_seq)?
+            .ok_or_else(|| StoreError::tx_not_found(tx_seq))?;
+        if self.tx_store.check_tx_pruned(tx_seq)? {
+            bail!(StoreError::Pruned { tx_seq });
+        }
+        if self.tx_store.check_tx_invalid(tx_seq)? {
+            bail!(StoreError::Invalid { tx_seq });
+        }
+
+        let shard_config = self.flow_store.get_shard_config();
+        let tx_end_index = tx.start_entry_index + tx.num_entries() as u64;
+        let tx_data_end_index = tx.start_entry_index + bytes_to_entries(tx.size);
+        let mut full_batches = Vec::new();
+        let mut edge_ranges = Vec::new();
+        for batch_index in batch_list {
+            if !shard_config.in_range(*batch_index) {
+                bail!(
+                    "reset_tx_data with batch out of shard: tx_seq={} batch_index={}",
+                    tx_seq,
+                    batch_index
+                );
+            }
+            let batch_start = batch_index * PORA_CHUNK_SIZE as u64;
+            let batch_end = batch_start + PORA_CHUNK_SIZE as u64;
+            if batch_end <= tx.start_entry_index || batch_start >= tx_end_index {
+                bail!(
+                    "reset_tx_data with batch out of the tx: tx_seq={} batch_index={}",
+                    tx_seq,
+                    batch_index
+                );
+            }
+            if batch_start >= tx.start_entry_index && batch_end <= tx_end_index {
+                full_batches.push(*batch_index);
+            } else {
+                // Only the tx data is removed from the batch shared with other data.
+                let start_sector = tx.start_entry_index.max(batch_start) - batch_start;
+                let end_sector = tx_data_end_index.clamp(batch_start, batch_end) - batch_start;
+                if start_sector < end_sector {
+                    edge_ranges.push((*batch_index, start_sector as usize, end_sector as usize));
+                }
+            }
+        }
+
+        // Clear the status first, so that a finalized tx never misses data.
+        self.flow_version.fetch_add(1, AtomicOrdering::SeqCst);
+        let result = self
+            .tx_store
+            .unfinalize_tx(tx_seq)
+            .and_then(|_| self.flow_store.delete_batch_list(&full_batches))
+            .and_then(|_| {
+                edge_ranges
+                    .iter()
+                    .try_for_each(|(batch_index, start_sector, end_sector)| {
+                        self.flow_store
+                            .remove_batch_range(*batch_index, *start_sector, *end_sector)
+                    })
+            });
+        self.flow_version.fetch_add(1, AtomicOrdering::SeqCst);
+        result
+    }
+
+    fn demote_incomplete_txs(&self, tx_seqs: &[u64]) -> Result<Vec<u64>> {
+        let mut demoted = Vec::new();
+        for tx_seq in tx_seqs {
+            // Checked without the lock first, since most finalized txs are complete.
+            if !self.finalized_but_incomplete(*tx_seq)? {
+                continue;
+            }
+            // Hold the lock to avoid finalizing the tx concurrently, and check again.
+            let _merkle = self.merkle.write();
+            if !self.finalized_but_incomplete(*tx_seq)? {
+                continue;
+            }
+            warn!(%tx_seq, "finalized tx misses data, demote it to unfinalized");
+            self.flow_version.fetch_add(1, AtomicOrdering::SeqCst);
+            let result = self.tx_store.unfinalize_tx(*tx_seq);
+            self.flow_version.fetch_add(1, AtomicOrdering::SeqCst);
+            result?;
+            demoted.push(*tx_seq);
+        }
+        Ok(demoted)
     }
 
     fn put_sync_progress(&self, progress: (u64, H256, Option<Option<u64>>)) -> Result<()> {
-        self.tx_store.put_progress(progress)
+        let block = progress.0;
+        self.tx_store.put_progress(progress)?;
+        let expired = self.revert_journal.expire(block)?;
+        if expired > 0 {
+            debug!(%block, %expired, "Expired reverted txs from the journal");
+        }
+        Ok(())
     }
 
     fn put_log_latest_block_number(&self, block_number: u64) -> Result<()> {
@@ -376,21 +630,23 @@ impl LogStoreWrite for LogManager {
     /// Return the reverted Transactions in order.
     /// `tx_seq == u64::MAX` is a special case for reverting all transactions.
     fn revert_to(&self, tx_seq: u64) -> Result<Vec<Transaction>> {
+        let start_time = Instant::now();
+        let max_seq = self.tx_store.next_tx_seq();
+        // Staged before locking, since reading the data of large txs takes long.
+        let flow_version = self.get_flow_version();
+        let start = if tx_seq != u64::MAX { tx_seq + 1 } else { 0 };
+        let staged = self.stage_reverted_txs(start, max_seq)?;
         // FIXME(zz): If this revert is triggered by chain reorg after restarts, this will fail.
         let mut merkle = self.merkle.write();
-        merkle.revert_merkle_tree(tx_seq, &self.tx_store)?;
-        merkle.try_initialize(&self.flow_store)?;
-        assert_eq!(
-            Some(merkle.last_chunk_merkle.root()),
-            merkle
-                .pora_chunks_merkle
-                .leaf_at(merkle.pora_chunks_merkle.leaves() - 1)?
-        );
-        let start_index = merkle.last_chunk_start_index() * PORA_CHUNK_SIZE as u64
-            + merkle.last_chunk_merkle.leaves() as u64;
-        self.flow_store.truncate(start_index)?;
-        let start = if tx_seq != u64::MAX { tx_seq + 1 } else { 0 };
-        self.tx_store.remove_tx_after(start)
+        if self.get_flow_version() != flow_version {
+            // The data may be reset or pruned while staged.
+            self.revert_journal.discard(staged)?;
+        }
+        self.flow_version.fetch_add(1, AtomicOrdering::SeqCst);
+        self.finalize_gate.lock().revert(tx_seq);
+        let reverted = self.revert_locked(&mut merkle, tx_seq, max_seq, start_time);
+        self.flow_version.fetch_add(1, AtomicOrdering::SeqCst);
+        reverted
     }
 
     fn validate_and_insert_range_proof(
@@ -413,6 +669,10 @@ impl LogStoreWrite for LogManager {
         self.tx_store.delete_block_hash_by_number(block_number)
     }
 
+    fn delete_block_hashes_before(&self, block_number: u64) -> Result<()> {
+        self.tx_store.delete_block_hashes_before(block_number)
+    }
+
     fn update_shard_config(&self, shard_config: ShardConfig) {
         self.flow_store.update_shard_config(shard_config)
     }
@@ -421,6 +681,7 @@ impl LogStoreWrite for LogManager {
         self.flow_store.submit_seal_result(answers)
     }
 
+    #[cfg(feature = "runtime")]
     fn start_padding(&self, executor: &task_executor::TaskExecutor) {
         let store = self.flow_store.clone();
         executor.spawn(
@@ -456,6 +717,34 @@ impl LogStoreWrite for LogManager {
             "pad_tx",
         );
     }
+
+    fn put_flush_journal(&self, journal: FlushJournal) -> Result<()> {
+        self.tx_store.put_flush_journal(journal)
+    }
+
+    fn remove_flush_journal(&self, tx_seq: u64) -> Result<()> {
+        self.tx_store.remove_flush_journal(tx_seq)
+    }
+
+    fn put_miner_reward(&self, reward: MinerReward) -> Result<()> {
+        self.reward_store.put(&reward)
+    }
+
+    fn revert_miner_rewards(&self, block_number: u64) -> Result<usize> {
+        self.reward_store.revert(block_number)
+    }
+
+    fn put_admin_job(&self, job: AdminJob) -> Result<()> {
+        self.job_store.put(&job)
+    }
+
+    fn remove_admin_jobs(&self, ids: Vec<u64>) -> Result<()> {
+        self.job_store.remove(&ids)
+    }
+
+    fn put_submission_context(&self, tx_seq: u64, context: SubmissionContext) -> Result<()> {
+        self.tx_store.put_submission_context(tx_seq, &context)
+    }
 }
 
 impl LogStoreChunkRead for LogManager {
@@ -479,11 +768,11 @@ impl LogStoreChunkRead for LogManager {
         let tx = try_option!(self.get_tx_by_seq_number(tx_seq)?);
 
         if index_end as u64 > bytes_to_entries(tx.size) {
-            bail!(
-                "end entry index exceeds tx size: end={} tx size={}",
-                index_start,
-                tx.size
-            );
+            bail!(StoreError::OutOfRange {
+                what: "chunks of tx",
+                index: index_end as u64,
+                bound: bytes_to_entries(tx.size),
+            });
         }
 
         let start_flow_index = tx.start_entry_index + index_start as u64;
@@ -497,6 +786,31 @@ impl LogStoreChunkRead for LogManager {
         Ok(Some(tx_chunk))
     }
 
+    fn has_chunks_by_tx_and_index_range(
+        &self,
+        tx_seq: u64,
+        index_start: usize,
+        index_end: usize,
+    ) -> crate::error::Result<bool> {
+        let tx = match self.get_tx_by_seq_number(tx_seq)? {
+            Some(tx) => tx,
+            None => return Ok(false),
+        };
+
+        if index_end as u64 > bytes_to_entries(tx.size) {
+            bail!(StoreError::OutOfRange {
+                what: "chunks of tx",
+                index: index_end as u64,
+                bound: bytes_to_entries(tx.size),
+            });
+        }
+
+        self.flow_store.has_entries(
+            tx.start_entry_index + index_start as u64,
+            tx.start_entry_index + index_end as u64,
+        )
+    }
+
     fn get_chunk_by_data_root_and_index(
         &self,
         _data_root: &DataRoot,
@@ -538,14 +852,42 @@ impl LogStoreRead for LogManager {
 
     fn get_tx_seq_by_data_root(&self, data_root: &DataRoot) -> crate::error::Result<Option<u64>> {
         let seq_list = self.tx_store.get_tx_seq_list_by_data_root(data_root)?;
-        for tx_seq in &seq_list {
-            if self.tx_store.check_tx_completed(*tx_seq)? {
-                // Return the first finalized tx if possible.
-                return Ok(Some(*tx_seq));
+        self.tx_store.select_tx_seq(&seq_list)
+    }
+
+    fn get_tx_seq_by_flow_index(&self, index: u64) -> Result<Option<u64>> {
+        // Txs are appended to the flow in the order of seq.
+        let (mut low, mut high) = (0, self.tx_store.next_tx_seq());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let tx = self
+                .tx_store
+                .get_tx_by_seq_number(mid)?
+                .ok_or_else(|| StoreError::tx_not_found(mid))?;
+            if index < tx.start_entry_index {
+                high = mid;
+            } else if index >= tx.start_entry_index + tx.num_entries() as u64 {
+                low = mid + 1;
+            } else {
+                return Ok(Some(mid));
             }
         }
-        // No tx is finalized, return the first one.
-        Ok(seq_list.first().cloned())
+        Ok(None)
+    }
+
+    fn get_txs_by_data_roots(
+        &self,
+        data_roots: &[DataRoot],
+    ) -> crate::error::Result<Vec<Option<Transaction>>> {
+        let seq_lists = self.tx_store.get_tx_seq_lists_by_data_roots(data_roots)?;
+        let mut txs = Vec::with_capacity(seq_lists.len());
+        for seq_list in seq_lists {
+            txs.push(match self.tx_store.select_tx_seq(&seq_list)? {
+                Some(seq) => self.tx_store.get_tx_by_seq_number(seq)?,
+                None => None,
+            });
+        }
+        Ok(txs)
     }
 
     fn get_chunk_with_proof_by_tx_and_index(
@@ -593,14 +935,213 @@ impl LogStoreRead for LogManager {
         self.tx_store.get_tx_status(tx_seq)
     }
 
+    fn get_flush_journals(&self) -> Result<Vec<FlushJournal>> {
+        self.tx_store.get_flush_journals()
+    }
+
+    fn verify_flush_journal(&self, journal: &FlushJournal) -> Result<Vec<ChunkRange>> {
+        let mut missing = Vec::new();
+        for range in journal.ranges.iter() {
+            let valid = match self.get_chunks_with_proof_by_tx_and_index_range(
+                journal.tx_seq,
+                range.start as usize,
+                range.end as usize,
+                None,
+            ) {
+                Ok(Some(data)) => self
+                    .validate_range_proof(journal.tx_seq, &data)
+                    .unwrap_or(false),
+                Ok(None) => false,
+                Err(e) => {
+                    debug!(
+                        tx_seq = journal.tx_seq,
+                        ?range,
+                        "Failed to verify flushed chunks: {:?}",
+                        e
+                    );
+                    false
+                }
+            };
+            if !valid {
+                missing.push(*range);
+            }
+        }
+        Ok(missing)
+    }
+
+    fn get_revert_history(&self) -> Vec<RevertEvent> {
+        self.revert_history.events()
+    }
+
+    #[cfg(feature = "runtime")]
+    fn subscribe_finalization(&self) -> FinalizationSubscriber {
+        self.finalization_bus.subscribe()
+    }
+
+    fn get_miner_rewards(&self, from_epoch: u64, to_epoch: u64) -> Result<Vec<MinerReward>> {
+        self.reward_store.get_range(from_epoch, to_epoch)
+    }
+
+    fn get_miner_reward_summary(&self) -> Result<MinerRewardSummary> {
+        self.reward_store.summary()
+    }
+
+    fn get_admin_job(&self, id: u64) -> Result<Option<AdminJob>> {
+        self.job_store.get(id)
+    }
+
+    fn get_admin_jobs(&self) -> Result<Vec<AdminJob>> {
+        self.job_store.get_all()
+    }
+
+    fn get_submission_context(&self, tx_seq: u64) -> Result<Option<SubmissionContext>> {
+        self.tx_store.get_submission_context(tx_seq)
+    }
+
+    fn get_data_root_first_seen(&self, data_root: &DataRoot) -> Result<Option<u64>> {
+        self.tx_store.get_first_seen(data_root)
+    }
+
+    fn is_reverted_tx_staged(&self, tx: &Transaction) -> bool {
+        self.revert_journal.is_staged(
+            tx.start_entry_index,
+            &tx.data_merkle_root,
+            bytes_to_entries(tx.size),
+        )
+    }
+
     fn check_tx_completed(&self, tx_seq: u64) -> crate::error::Result<bool> {
         self.tx_store.check_tx_completed(tx_seq)
     }
 
+    fn get_txs_with_status(
+        &self,
+        start_seq: u64,
+        limit: usize,
+    ) -> Result<Vec<(Transaction, Option<TxStatus>)>> {
+        self.tx_store
+            .iter_tx_with_status(start_seq)
+            .take(limit)
+            .collect()
+    }
+
+    fn get_db_column_stats(&self) -> Result<Vec<ColumnStats>> {
+        // Only the db is accessed, so that no lock of the log manager is held.
+        let mut stats = Vec::with_capacity(2 * COL_NUM as usize);
+        for (db_name, db) in self.db.dbs() {
+            for (col, column) in COL_NAMES.iter().enumerate() {
+                stats.push(ColumnStats {
+                    db: db_name,
+                    column,
+                    num_keys: db.num_keys(col as u32)?,
+                });
+            }
+        }
+
+        Ok(stats)
+    }
+
+    fn get_db_layout(&self) -> DbLayout {
+        self.db.layout()
+    }
+
+    fn get_flow_version(&self) -> u64 {
+        self.flow_version.load(AtomicOrdering::SeqCst)
+    }
+
+    fn get_merkle_state(&self, max_size: usize) -> Result<MerkleState> {
+        for _ in 0..MERKLE_STATE_MAX_ATTEMPTS {
+            if let Some(state) = self.build_merkle_state(max_size, MERKLE_STATE_READ_BATCH)? {
+                return Ok(state);
+            }
+        }
+        Err(StoreError::Busy.into())
+    }
+
+    fn verify_tx_data(&self, tx_seq: u64) -> Result<Vec<u64>> {
+        let tx = self
+            .get_tx_by_seq_number(tx_seq)?
+            .ok_or_else(|| StoreError::tx_not_found(tx_seq))?;
+        let tx_end_index = tx.start_entry_index + bytes_to_entries(tx.size);
+
+        let mut corrupted = Vec::new();
+        for (batch_start, batch_end) in batch_iter_sharded(
+            tx.start_entry_index,
+            tx_end_index,
+            PORA_CHUNK_SIZE,
+            self.flow_store.get_shard_config(),
+        ) {
+            let index_start = (batch_start - tx.start_entry_index) as usize;
+            let index_end = (batch_end - tx.start_entry_index) as usize;
+            // The proof cannot be generated if the batch data do not match the batch root.
+            let valid = match self.get_chunks_with_proof_by_tx_and_index_range(
+                tx_seq,
+                index_start,
+                index_end,
+                None,
+            ) {
+                Ok(Some(data)) => self.validate_range_proof(tx_seq, &data).unwrap_or(false),
+                Ok(None) => false,
+                Err(e) => {
+                    warn!(%tx_seq, %batch_start, "Failed to load data with proof: {:?}", e);
+                    false
+                }
+            };
+
+            if !valid {
+                corrupted.push(batch_start / PORA_CHUNK_SIZE as u64);
+            }
+        }
+
+        Ok(corrupted)
+    }
+
+    fn verify_flow_range(&self, start_index: u64, end_index: u64) -> Result<Vec<u64>> {
+        let flow_length = {
+            let merkle = self.merkle.read_recursive();
+            merkle.last_chunk_start_index() + merkle.last_chunk_merkle.leaves() as u64
+        };
+
+        let mut corrupted = Vec::new();
+        for (batch_start, batch_end) in batch_iter_sharded(
+            start_index,
+            end_index.min(flow_length),
+            PORA_CHUNK_SIZE,
+            self.flow_store.get_shard_config(),
+        ) {
+            // The first entry is a placeholder without data.
+            let index = batch_start.max(1);
+            if index >= batch_end {
+                continue;
+            }
+            // The proof cannot be generated if the batch data do not match the batch root.
+            let valid = match self.get_flow_entries_with_proof(index, batch_end - index) {
+                Ok(Some((data, flow_root, _))) => data_to_merkle_leaves(&data.chunks.data)
+                    .and_then(|leaves| {
+                        data.proof
+                            .validate::<Sha3Algorithm>(&leaves, index as usize)
+                    })
+                    .map(|_| data.proof.root() == flow_root)
+                    .unwrap_or(false),
+                Ok(None) => false,
+                Err(e) => {
+                    warn!(%batch_start, "Failed to load flow entries with proof: {:?}", e);
+                    false
+                }
+            };
+
+            if !valid {
+                corrupted.push(batch_start / PORA_CHUNK_SIZE as u64);
+            }
+        }
+
+        Ok(corrupted)
+    }
+
     fn validate_range_proof(&self, tx_seq: u64, data: &ChunkArrayWithProof) -> Result<bool> {
         let tx = self
             .get_tx_by_seq_number(tx_seq)?
-            .ok_or_else(|| anyhow!("tx missing"))?;
+            .ok_or_else(|| StoreError::tx_not_found(tx_seq))?;
         let leaves = data_to_merkle_leaves(&data.chunks.data)?;
         data.proof.validate::<Sha3Algorithm>(
             &leaves,
@@ -629,6 +1170,21 @@ impl LogStoreRead for LogManager {
         self.tx_store.get_block_hashes()
     }
 
+    fn get_block_hashes_from(
+        &self,
+        from_block: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, BlockHashAndSubmissionIndex)>> {
+        self.tx_store.get_block_hashes_from(from_block, limit)
+    }
+
+    fn iter_block_hashes_rev(
+        &self,
+        from_block: u64,
+    ) -> Box<dyn Iterator<Item = Result<(u64, BlockHashAndSubmissionIndex)>> + '_> {
+        Box::new(self.tx_store.iter_block_hashes_rev(from_block))
+    }
+
     fn next_tx_seq(&self) -> u64 {
         self.tx_store.next_tx_seq()
     }
@@ -647,70 +1203,306 @@ impl LogStoreRead for LogManager {
         })
     }
 
-    fn get_context(&self) -> crate::error::Result<(DataRoot, u64)> {
-        let merkle = self.merkle.read_recursive();
-        Ok((
-            merkle.pora_chunks_merkle.root(),
-            merkle.last_chunk_start_index() + merkle.last_chunk_merkle.leaves() as u64,
-        ))
+    fn get_context(&self) -> crate::error::Result<(DataRoot, u64)> {
+        let merkle = self.merkle.read_recursive();
+        Ok((
+            merkle.pora_chunks_merkle.root(),
+            merkle.last_chunk_start_index() + merkle.last_chunk_merkle.leaves() as u64,
+        ))
+    }
+
+    fn get_flow_entries_with_proof(
+        &self,
+        index: u64,
+        length: u64,
+    ) -> Result<Option<(ChunkArrayWithProof, DataRoot, u64)>> {
+        // Hold the lock so that the data and proof are consistent with the flow context.
+        let merkle = self.merkle.read_recursive();
+        let flow_root = merkle.pora_chunks_merkle.root();
+        let flow_length =
+            merkle.last_chunk_start_index() + merkle.last_chunk_merkle.leaves() as u64;
+        // The first entry is a placeholder without data.
+        if index == 0 || length == 0 || index + length > flow_length {
+            bail!(StoreError::OutOfRange {
+                what: "flow entries",
+                index: index.saturating_add(length),
+                bound: flow_length,
+            });
+        }
+
+        let chunks = try_option!(self.flow_store.get_entries(index, index + length)?);
+        let left_proof = self.gen_proof_at_version(index, None)?;
+        let right_proof = self.gen_proof_at_version(index + length - 1, None)?;
+        Ok(Some((
+            ChunkArrayWithProof {
+                chunks,
+                proof: FlowRangeProof {
+                    left_proof,
+                    right_proof,
+                },
+            },
+            flow_root,
+            flow_length,
+        )))
+    }
+
+    fn check_tx_pruned(&self, tx_seq: u64) -> crate::error::Result<bool> {
+        self.tx_store.check_tx_pruned(tx_seq)
+    }
+
+    fn check_tx_invalid(&self, tx_seq: u64) -> crate::error::Result<bool> {
+        self.tx_store.check_tx_invalid(tx_seq)
+    }
+
+    fn pull_seal_chunk(
+        &self,
+        seal_index_min: usize,
+        seal_index_max: usize,
+    ) -> Result<Option<Vec<SealTask>>> {
+        self.flow_store
+            .pull_seal_chunk(seal_index_min, seal_index_max)
+    }
+
+    fn get_num_entries(&self) -> Result<u64> {
+        self.flow_store.get_num_entries()
+    }
+
+    fn load_sealed_data(&self, chunk_index: u64) -> Result<Option<MineLoadChunk>> {
+        self.flow_store.load_sealed_data(chunk_index)
+    }
+
+    fn get_sealed_chunk_with_proof(
+        &self,
+        chunk_index: u64,
+    ) -> Result<Option<SealedChunkWithProof>> {
+        // Hold the lock so that the proof is consistent with the flow context.
+        let merkle = self.merkle.read_recursive();
+        let flow_root = merkle.pora_chunks_merkle.root();
+        let flow_length =
+            merkle.last_chunk_start_index() + merkle.last_chunk_merkle.leaves() as u64;
+        if chunk_index >= merkle.pora_chunks_merkle.leaves() as u64 {
+            bail!(StoreError::OutOfRange {
+                what: "load chunk",
+                index: chunk_index,
+                bound: merkle.pora_chunks_merkle.leaves() as u64,
+            });
+        }
+
+        let (chunk, miner_id) = try_option!(self.flow_store.load_sealed_batch(chunk_index)?);
+        let proof = merkle.pora_chunks_merkle.gen_proof(chunk_index as usize)?;
+        Ok(Some(SealedChunkWithProof {
+            chunk,
+            miner_id,
+            proof,
+            flow_root,
+            flow_length,
+        }))
+    }
+
+    fn get_shard_config(&self) -> ShardConfig {
+        self.flow_store.get_shard_config()
+    }
+
+    fn is_flow_tree_ready(&self) -> bool {
+        self.merkle.is_ready()
+    }
+
+    #[cfg(feature = "runtime")]
+    fn subscribe_flow_tree_ready(&self) -> tokio::sync::watch::Receiver<bool> {
+        self.merkle.subscribe_ready()
+    }
+}
+
+impl LogManager {
+    /// Opens the log store of `config`, which needs neither async runtime nor network, so that
+    /// the store can be embedded as a library.
+    pub fn new(config: LogStoreConfig) -> Result<Self> {
+        let log_config = LogConfig {
+            flow: FlowConfig {
+                merkle_node_cache_capacity: config.merkle_node_cache_capacity,
+                shard_config: Arc::new(RwLock::new(config.shard_config)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        Self::open(
+            config.db_engine,
+            log_config,
+            config.flow_db_path,
+            config.data_db_path,
+        )
+    }
+
+    pub fn rocksdb(
+        config: LogConfig,
+        flow_path: impl AsRef<Path>,
+        data_path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        Self::open(DbEngine::RocksDb, config, flow_path, data_path)
+    }
+
+    /// Opens the flow and data dbs of `engine` at the given paths.
+    pub fn open(
+        engine: DbEngine,
+        config: LogConfig,
+        flow_path: impl AsRef<Path>,
+        data_path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let db = StoreHandles::split(
+            open_kvdb(engine, flow_path, COL_NUM)?,
+            open_kvdb(engine, data_path, COL_NUM)?,
+        );
+        Self::with_dbs(db, config)
+    }
+
+    /// Opens the flow and data dbs of `engine` read-only, e.g. to serve a snapshot, which
+    /// fails if the snapshot was not cleanly stopped and the last tx needs to be put again.
+    pub fn open_read_only(
+        engine: DbEngine,
+        config: LogConfig,
+        flow_path: impl AsRef<Path>,
+        data_path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let db = StoreHandles::split(
+            open_kvdb(engine, flow_path, COL_NUM)?,
+            open_kvdb(engine, data_path, COL_NUM)?,
+        );
+        Self::with_dbs(db.into_read_only(), config)
     }
 
-    fn check_tx_pruned(&self, tx_seq: u64) -> crate::error::Result<bool> {
-        self.tx_store.check_tx_pruned(tx_seq)
+    pub fn memorydb(config: LogConfig) -> Result<Self> {
+        Self::with_dbs(StoreHandles::memorydb(DbLayout::Split), config)
     }
 
-    fn pull_seal_chunk(&self, seal_index_max: usize) -> Result<Option<Vec<SealTask>>> {
-        self.flow_store.pull_seal_chunk(seal_index_max)
+    /// Dbs of the store, e.g. to observe the kvdb stats.
+    pub fn handles(&self) -> &StoreHandles {
+        &self.db
     }
 
-    fn get_num_entries(&self) -> Result<u64> {
-        self.flow_store.get_num_entries()
+    /// Returns the time of each phase to open the log manager, i.e. `tx_store_init`,
+    /// `last_chunk_rebuild` and `flow_tree_load`, in the order completed. The phases of the
+    /// flow tree are missing until loaded, see [`Self::with_dbs_deferred`].
+    pub fn startup_phases(&self) -> Vec<(&'static str, Duration)> {
+        self.startup_phases.lock().clone()
     }
 
-    fn load_sealed_data(&self, chunk_index: u64) -> Result<Option<MineLoadChunk>> {
-        self.flow_store.load_sealed_data(chunk_index)
+    /// Checks that the store is initialized for `network_id`, which is persisted on the first
+    /// run, and fails with [`StoreError::NetworkMismatch`] if initialized for another network.
+    pub fn check_network_id(&self, network_id: &StoreNetworkId) -> Result<()> {
+        match self.get_config_decoded::<_, StoreNetworkId>(&NETWORK_ID_KEY, DATA_DB_KEY)? {
+            Some(stored) if stored != *network_id => bail!(StoreError::NetworkMismatch {
+                stored,
+                configured: *network_id,
+            }),
+            Some(_) => Ok(()),
+            None => {
+                info!(%network_id, "Store initialized for network");
+                self.set_config_encoded(&NETWORK_ID_KEY, network_id, DATA_DB_KEY)
+            }
+        }
     }
 
-    fn get_shard_config(&self) -> ShardConfig {
-        self.flow_store.get_shard_config()
+    /// Returns the seqs of all txs of the data root in ascending order.
+    pub fn get_tx_seq_list_by_data_root(&self, data_root: &DataRoot) -> Result<Vec<u64>> {
+        self.tx_store.get_tx_seq_list_by_data_root(data_root)
     }
-}
 
-impl LogManager {
-    pub fn rocksdb(
-        config: LogConfig,
-        flow_path: impl AsRef<Path>,
-        data_path: impl AsRef<Path>,
-    ) -> Result<Self> {
-        let mut db_config = DatabaseConfig::with_columns(COL_NUM);
-        db_config.enable_statistics = true;
-        let flow_db_source = Arc::new(Database::open(&db_config, flow_path)?);
-        let data_db_source = Arc::new(Database::open(&db_config, data_path)?);
-        Self::new(flow_db_source, data_db_source, config)
+    /// Returns the estimated size in bytes of each column, in the same order as
+    /// `get_db_column_stats`, or `None` if not supported by the db, see
+    /// [`KvdbIntrospection::column_size_bytes`].
+    pub fn get_db_column_sizes(&self) -> Result<Vec<Option<u64>>> {
+        let mut sizes = Vec::with_capacity(2 * COL_NUM as usize);
+        for (_, db) in self.db.dbs() {
+            for col in 0..COL_NUM {
+                sizes.push(db.column_size_bytes(col)?);
+            }
+        }
+        Ok(sizes)
     }
 
-    pub fn memorydb(config: LogConfig) -> Result<Self> {
-        let flow_db = Arc::new(kvdb_memorydb::create(COL_NUM));
-        let data_db = Arc::new(kvdb_memorydb::create(COL_NUM));
-        Self::new(flow_db, data_db, config)
+    /// Rebuilds the flow merkle tree by putting all txs again, which is the repair path of
+    /// merkle nodes inconsistent with txs, e.g. partially written before an unclean shutdown.
+    ///
+    /// The data and status of txs are kept, so finalized files need not to be synced again.
+    pub fn rebuild_merkle(db: StoreHandles, config: LogConfig) -> Result<Self> {
+        let tx_store = TransactionStore::new(db.clone())?;
+        let mut tx_list = Vec::with_capacity(tx_store.next_tx_seq() as usize);
+        for seq in 0..tx_store.next_tx_seq() {
+            let tx = tx_store
+                .get_tx_by_seq_number(seq)?
+                .ok_or_else(|| anyhow!("tx missing: seq={}", seq))?;
+            tx_list.push(tx);
+        }
+        drop(tx_store);
+
+        TransactionStore::reset_tx_index(db.flow().as_ref())?;
+        db.flow().delete_with_prefix(COL_FLOW_MPT_NODES, &[])?;
+        let log_manager = Self::with_dbs(db, config)?;
+        for tx in tx_list {
+            log_manager.put_tx(tx)?;
+        }
+        info!(
+            "Flow merkle rebuilt, state={:?}",
+            log_manager.get_context()?
+        );
+        Ok(log_manager)
     }
 
-    fn new(
-        flow_db_source: Arc<dyn ZgsKeyValueDB>,
-        data_db_source: Arc<dyn ZgsKeyValueDB>,
-        config: LogConfig,
-    ) -> Result<Self> {
-        let tx_store = TransactionStore::new(flow_db_source.clone(), data_db_source.clone())?;
-        let flow_db = Arc::new(FlowDBStore::new(flow_db_source.clone()));
-        let data_db = Arc::new(FlowDBStore::new(data_db_source.clone()));
-        let flow_store = Arc::new(FlowStore::new(
-            flow_db.clone(),
-            data_db.clone(),
-            config.flow.clone(),
-        ));
+    /// Opens the log store on the dbs, e.g. opened by [`StoreHandles::open`] of the configured
+    /// layout.
+    pub fn with_dbs(db: StoreHandles, config: LogConfig) -> Result<Self> {
+        let log_manager = Self::with_dbs_deferred(db, config)?;
+        log_manager.load_flow_tree()?;
+        Ok(log_manager)
+    }
+
+    /// Opens the log store on the dbs without loading the flow merkle tree, which should be
+    /// loaded by [`Self::load_flow_tree`] later, e.g. in background on a big db. Until then,
+    /// reads that need no tree, e.g. txs and chunks of finalized files, are served, while
+    /// writes and reads of proofs wait for the tree.
+    pub fn with_dbs_deferred(db: StoreHandles, config: LogConfig) -> Result<Self> {
+        let started_at = Instant::now();
+        let tx_store = TransactionStore::new(db.clone())?;
+        let flow_db = Arc::new(FlowDBStore::new(db.flow().clone()));
+        let data_db = Arc::new(FlowDBStore::new(db.data().clone()));
+        let revert_journal = RevertJournal::new(db.data().clone(), config.revert_journal)?;
+        let flow_store = Arc::new(FlowStore::new(flow_db, data_db, config.flow.clone()));
+        // Replaced once the tree loaded.
+        let merkle = TreeGate::new(
+            MerkleManager {
+                pora_chunks_merkle: Merkle::new_with_depth(vec![], 1, None),
+                last_chunk_merkle: Merkle::new_with_depth(vec![], 1, None),
+            },
+            false,
+        );
+
+        Ok(Self {
+            reward_store: RewardStore::new(db.flow().clone())?,
+            job_store: JobStore::new(db.flow().clone()),
+            db,
+            tx_store,
+            flow_store,
+            merkle,
+            revert_history: Default::default(),
+            revert_journal,
+            #[cfg(feature = "runtime")]
+            finalization_bus: FinalizationBus::new(config.finalization),
+            flow_version: AtomicU64::new(0),
+            finalize_gate: Default::default(),
+            max_tx_inline_data_size: config.max_tx_inline_data_size,
+            merkle_node_cache_capacity: config.flow.merkle_node_cache_capacity,
+            startup_phases: Mutex::new(vec![("tx_store_init", started_at.elapsed())]),
+        })
+    }
+
+    /// Loads the flow merkle tree of the store opened by [`Self::with_dbs_deferred`], and
+    /// unblocks the writes and reads waiting for the tree.
+    pub fn load_flow_tree(&self) -> Result<()> {
+        self.merkle.begin_load();
+        let started_at = Instant::now();
         // If the last tx `put_tx` does not complete, we will revert it in `pora_chunks_merkle`
         // first and call `put_tx` later.
-        let next_tx_seq = tx_store.next_tx_seq();
+        let next_tx_seq = self.tx_store.next_tx_seq();
         let mut start_tx_seq = if next_tx_seq > 0 {
             Some(next_tx_seq - 1)
         } else {
@@ -719,14 +1511,15 @@ impl LogManager {
         let mut last_tx_to_insert = None;
 
         let mut pora_chunks_merkle = Merkle::new_with_subtrees(
-            flow_db,
-            config.flow.merkle_node_cache_capacity,
+            Arc::new(FlowDBStore::new(self.db.flow().clone())),
+            self.merkle_node_cache_capacity,
             log2_pow2(PORA_CHUNK_SIZE),
         )?;
         if let Some(last_tx_seq) = start_tx_seq {
-            if !tx_store.check_tx_completed(last_tx_seq)? {
+            if !self.tx_store.check_tx_completed(last_tx_seq)? {
                 // Last tx not finalized, we need to check if its `put_tx` is completed.
-                let last_tx = tx_store
+                let last_tx = self
+                    .tx_store
                     .get_tx_by_seq_number(last_tx_seq)?
                     .expect("tx missing");
                 let current_len = pora_chunks_merkle.leaves();
@@ -756,7 +1549,8 @@ impl LogManager {
                             start_tx_seq = None;
                         } else {
                             // truncate until we get the pora chunks merkle for the previous tx.
-                            let previous_tx = tx_store
+                            let previous_tx = self
+                                .tx_store
                                 .get_tx_by_seq_number(last_tx_seq - 1)?
                                 .expect("tx missing");
                             let expected_len = sector_to_segment(
@@ -774,15 +1568,25 @@ impl LogManager {
             }
         }
 
+        let mut flow_tree_load = started_at.elapsed();
+
+        let started_at = Instant::now();
         let last_chunk_merkle = match start_tx_seq {
             Some(tx_seq) => {
-                let tx = tx_store.get_tx_by_seq_number(tx_seq)?.expect("tx missing");
+                let tx = self
+                    .tx_store
+                    .get_tx_by_seq_number(tx_seq)?
+                    .expect("tx missing");
                 if (tx.start_entry_index() + tx.num_entries() as u64) % PORA_CHUNK_SIZE as u64 == 0
                 {
                     // The last chunk should be aligned, so it's empty.
                     Merkle::new_with_depth(vec![], log2_pow2(PORA_CHUNK_SIZE) + 1, None)
                 } else {
-                    tx_store.rebuild_last_chunk_merkle(pora_chunks_merkle.leaves() - 1, tx_seq)?
+                    self.tx_store.rebuild_last_chunk_merkle(
+                        pora_chunks_merkle.leaves() - 1,
+                        tx_seq,
+                        |batch_index| self.flow_store.get_batch_root(batch_index),
+                    )?
                 }
             }
             // Initialize
@@ -791,9 +1595,12 @@ impl LogManager {
                 Merkle::new_with_depth(vec![], 1, None)
             }
         };
+        self.startup_phases
+            .lock()
+            .push(("last_chunk_rebuild", started_at.elapsed()));
 
         debug!(
-            "LogManager::new() with chunk_list_len={} start_tx_seq={:?} last_chunk={}",
+            "LogManager::load_flow_tree() with chunk_list_len={} start_tx_seq={:?} last_chunk={}",
             pora_chunks_merkle.leaves(),
             start_tx_seq,
             last_chunk_merkle.leaves(),
@@ -803,31 +1610,130 @@ impl LogManager {
         }
         // update the merkle root
         pora_chunks_merkle.commit(start_tx_seq);
-        let merkle = RwLock::new(MerkleManager {
+        *self.merkle.write() = MerkleManager {
             pora_chunks_merkle,
             last_chunk_merkle,
-        });
-
-        let log_manager = Self {
-            flow_db: flow_db_source,
-            data_db: data_db_source,
-            tx_store,
-            flow_store,
-            merkle,
         };
 
+        let started_at = Instant::now();
         if let Some(tx) = last_tx_to_insert {
-            log_manager.put_tx(tx)?;
+            self.put_tx(tx)?;
         }
-        log_manager
-            .merkle
-            .write()
-            .try_initialize(&log_manager.flow_store)?;
-        info!(
-            "Log manager initialized, state={:?}",
-            log_manager.get_context()?
+        self.merkle.write().try_initialize(&self.flow_store)?;
+        // The flow tree is loaded before the last chunk rebuilt, and initialized afterwards.
+        flow_tree_load += started_at.elapsed();
+        self.startup_phases
+            .lock()
+            .push(("flow_tree_load", flow_tree_load));
+        self.merkle.set_ready();
+        info!("Log manager initialized, state={:?}", self.get_context()?);
+        Ok(())
+    }
+
+    /// Puts the tx as `put_tx`, or stores it as invalid along with the tx instead of copying the
+    /// data of the same root if `invalid`.
+    fn put_tx_with_status(&self, mut tx: Transaction, invalid: bool) -> Result<()> {
+        let start_time = Instant::now();
+        // A tx with too large inline data is still stored so that log sync never stalls on it,
+        // but as invalid without its data.
+        let invalid = self.drop_oversized_inline_data(&mut tx) || invalid;
+        // Hash the inline data before locking, which is the slowest part of a large tx.
+        let tx = self.with_inline_data_root(tx)?;
+        let mut merkle = self.merkle.write();
+        debug!("put_tx: tx={:?}", tx);
+        let expected_seq = self.tx_store.next_tx_seq();
+        if tx.seq != expected_seq {
+            if tx.seq + 1 == expected_seq && !self.check_tx_completed(tx.seq)? {
+                // special case for rerun the last tx during recovery.
+                debug!("recovery with tx_seq={}", tx.seq);
+            } else {
+                // This is not supposed to happen since we have checked the tx seq in log entry sync.
+                error!("tx mismatch, expected={} get={:?}", expected_seq, tx);
+                bail!(StoreError::ReorgInProgress {
+                    expected_seq,
+                    tx_seq: tx.seq,
+                });
+            }
+        }
+        if let Err(e) = self.check_tx_flow_range(&tx) {
+            metrics::INVALID_TX_FLOW_RANGE.inc(1);
+            error!("reject tx with invalid flow range, tx={:?}: {:?}", tx, e);
+            return Err(e);
+        }
+        // The data is copied from the first tx of the same root not pruned, the same as on
+        // finalization.
+        let status = invalid.then_some(TxStatus::Invalid);
+        let maybe_same_data_tx_seq =
+            if self.tx_store.put_tx_light_with_status(tx.clone(), status)? > 0 {
+                self.tx_store
+                    .get_first_live_tx_seq_by_data_root(&tx.data_merkle_root, tx.seq)?
+            } else {
+                None
+            };
+        self.append_subtree_list(
+            tx.seq,
+            tx.start_entry_index,
+            tx.merkle_nodes.clone(),
+            &mut merkle,
+        )?;
+        merkle.commit_merkle(tx.seq)?;
+        debug!(
+            "commit flow root: root={:?}",
+            merkle.pora_chunks_merkle.root()
         );
-        Ok(log_manager)
+        // Drop the lock because `copy_tx_data` will lock again.
+        drop(merkle);
+
+        // The data of an invalid tx is never stored, neither copied from the same root nor
+        // restored from the revert journal.
+        if invalid {
+            metrics::PUT_TX.update_since(start_time);
+            return Ok(());
+        }
+
+        if let Some(old_tx_seq) = maybe_same_data_tx_seq {
+            if self.check_tx_completed(old_tx_seq)? {
+                self.copy_tx_and_finalize(old_tx_seq, vec![tx.seq])?;
+            }
+        }
+        metrics::PUT_TX.update_since(start_time);
+        Ok(())
+    }
+
+    /// Drops the inline data of the tx if larger than `max_tx_inline_data_size`, and returns
+    /// whether dropped. The size is kept of the inline data, so that the tx still occupies the
+    /// flow range of its merkle nodes.
+    fn drop_oversized_inline_data(&self, tx: &mut Transaction) -> bool {
+        if tx.data.len() <= self.max_tx_inline_data_size {
+            return false;
+        }
+
+        metrics::TX_INLINE_DATA_REJECTED.inc(1);
+        warn!(
+            tx_seq = tx.seq,
+            size = tx.data.len(),
+            "Store tx with too large inline data as invalid"
+        );
+        tx.size = tx.data.len() as u64;
+        tx.data = vec![];
+        true
+    }
+
+    /// Set the size and data root of the tx by its inline data if any, which is rejected if larger
+    /// than `max_tx_inline_data_size`.
+    fn with_inline_data_root(&self, mut tx: Transaction) -> Result<Transaction> {
+        if tx.data.is_empty() {
+            return Ok(tx);
+        }
+
+        let mut padded_data = tx.data.clone();
+        let extra = tx.data.len() % ENTRY_SIZE;
+        if extra != 0 {
+            padded_data.append(&mut vec![0u8; ENTRY_SIZE - extra]);
+        }
+        tx.size = tx.data.len() as u64;
+        tx.data_merkle_root = sub_merkle_tree(&padded_data)?.root().into();
+        Ok(tx)
     }
 
     fn gen_proof(&self, flow_index: u64, maybe_root: Option<DataRoot>) -> Result<FlowProof> {
@@ -934,6 +1840,187 @@ impl LogManager {
         Ok(())
     }
 
+    /// Builds the merkle state by reading at most `read_batch` nodes each time under the tree
+    /// lock. Nodes of complete subtrees never change unless txs are reverted, which bumps the
+    /// flow version, so only the last node of each layer and the tree of the last batch are
+    /// read at once in the end. Returns `None` if the flow version changed meanwhile.
+    pub(crate) fn build_merkle_state(
+        &self,
+        max_size: usize,
+        read_batch: usize,
+    ) -> Result<Option<MerkleState>> {
+        let flow_version = self.get_flow_version();
+        let too_large = |size: usize| StoreError::OutOfRange {
+            what: "merkle state size",
+            index: size as u64,
+            bound: max_size as u64,
+        };
+        let mut pora_chunks: Vec<Vec<H256>> = Vec::new();
+        loop {
+            let merkle = self.merkle.read_recursive();
+            if self.get_flow_version() != flow_version {
+                return Ok(None);
+            }
+
+            let tree = &merkle.pora_chunks_merkle;
+            pora_chunks.resize_with(tree.height(), Vec::new);
+            let mut budget = read_batch;
+            for (layer, nodes) in pora_chunks.iter_mut().enumerate() {
+                let complete = tree.layer_len(layer).saturating_sub(1);
+                let start = nodes.len();
+                let end = complete.min(start + budget);
+                for index in start..end {
+                    nodes.push(tree.try_node(layer, index)?);
+                }
+                budget -= end.saturating_sub(start);
+            }
+            let size = MerkleState::ssz_len(pora_chunks.iter().map(Vec::len));
+            if size > max_size {
+                bail!(too_large(size));
+            }
+            if budget == 0 {
+                continue;
+            }
+
+            for (layer, nodes) in pora_chunks.iter_mut().enumerate() {
+                if let Some(last) = tree.layer_len(layer).checked_sub(1) {
+                    nodes.push(tree.try_node(layer, last)?);
+                }
+            }
+            let last_chunk_tree = &merkle.last_chunk_merkle;
+            let last_chunk = (0..last_chunk_tree.height())
+                .map(|layer| {
+                    (0..last_chunk_tree.layer_len(layer))
+                        .map(|index| last_chunk_tree.try_node(layer, index))
+                        .collect::<Result<Vec<_>>>()
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let state = MerkleState {
+                flow_version,
+                flow_length: merkle.last_chunk_start_index() + last_chunk_tree.leaves() as u64,
+                pora_chunks,
+                last_chunk,
+            };
+            let size = MerkleState::ssz_len(
+                state
+                    .pora_chunks
+                    .iter()
+                    .chain(state.last_chunk.iter())
+                    .map(Vec::len),
+            );
+            if size > max_size {
+                bail!(too_large(size));
+            }
+            return Ok(Some(state));
+        }
+    }
+
+    /// Reverts the merkle tree, flow and txs after `tx_seq`, see `revert_to`.
+    fn revert_locked(
+        &self,
+        merkle: &mut MerkleManager,
+        tx_seq: u64,
+        max_seq: u64,
+        start_time: Instant,
+    ) -> Result<Vec<Transaction>> {
+        merkle.revert_merkle_tree(tx_seq, &self.tx_store, &self.flow_store)?;
+        merkle.try_initialize(&self.flow_store)?;
+        assert_eq!(
+            Some(merkle.last_chunk_merkle.root()),
+            merkle
+                .pora_chunks_merkle
+                .leaf_at(merkle.pora_chunks_merkle.leaves() - 1)?
+        );
+        let start_index = merkle.last_chunk_start_index() * PORA_CHUNK_SIZE as u64
+            + merkle.last_chunk_merkle.leaves() as u64;
+        let start = if tx_seq != u64::MAX { tx_seq + 1 } else { 0 };
+        let truncated_batches = self.flow_store.truncate(start_index)?;
+        let reverted = self.tx_store.remove_tx_after(start)?;
+
+        let event = self.revert_history.record(
+            start,
+            max_seq,
+            reverted.len(),
+            truncated_batches,
+            start_time.elapsed(),
+        );
+        info!(?event, "Reverted txs");
+        Ok(reverted)
+    }
+
+    /// Stages the finalized txs in `[start, end)` in the revert journal before their data is
+    /// truncated from the flow, and returns the keys of the staged txs.
+    fn stage_reverted_txs(&self, start: u64, end: u64) -> Result<Vec<(u64, DataRoot)>> {
+        let mut staged = Vec::new();
+        if !self.revert_journal.is_enabled() {
+            return Ok(staged);
+        }
+        let block = self.tx_store.get_progress()?.map_or(0, |(block, _)| block);
+        for seq in start..end {
+            // Partial data, e.g. of other shards, is synced again.
+            if self.tx_store.get_tx_status(seq)? != Some(TxStatus::Finalized) {
+                continue;
+            }
+            if let Some(tx) = self.tx_store.get_tx_by_seq_number(seq)? {
+                if self
+                    .revert_journal
+                    .stage(&tx, block, self.flow_store.as_ref())?
+                {
+                    debug!(%seq, %block, "Staged reverted tx in the journal");
+                    staged.push((tx.start_entry_index, tx.data_merkle_root));
+                }
+            }
+        }
+        Ok(staged)
+    }
+
+    /// Rejects the tx whose flow range is inconsistent, e.g. a malformed submission, which
+    /// would otherwise overwrite the flow entries of the adjacent txs.
+    fn check_tx_flow_range(&self, tx: &Transaction) -> Result<()> {
+        if tx.size == 0 {
+            bail!("empty tx: tx_seq={}", tx.seq);
+        }
+        // The subtrees are determined by the size, which is also validated by the contract.
+        let depths: Vec<usize> = tx.merkle_nodes.iter().map(|(depth, _)| *depth).collect();
+        let expected_depths: Vec<usize> = split_nodes(tx.size as usize)
+            .into_iter()
+            .map(|size| log2_pow2(size) + 1)
+            .collect();
+        if depths != expected_depths {
+            bail!(
+                "tx size inconsistent with merkle nodes: tx_seq={} size={} depths={:?} expected={:?}",
+                tx.seq,
+                tx.size,
+                depths,
+                expected_depths
+            );
+        }
+        let first_subtree_size = Transaction::num_entries_of_node(depths[0]) as u64;
+        if tx.start_entry_index % first_subtree_size != 0 {
+            bail!(
+                "tx start not aligned with the first subtree: tx_seq={} start_entry_index={} subtree_size={}",
+                tx.seq,
+                tx.start_entry_index,
+                first_subtree_size
+            );
+        }
+
+        if tx.seq > 0 {
+            if let Some(prev_tx) = self.tx_store.get_tx_by_seq_number(tx.seq - 1)? {
+                let prev_end = prev_tx.start_entry_index + prev_tx.num_entries() as u64;
+                if prev_end > tx.start_entry_index {
+                    bail!(
+                        "tx overlaps the previous tx: tx_seq={} start_entry_index={} prev_end_entry_index={}",
+                        tx.seq,
+                        tx.start_entry_index,
+                        prev_end
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
     #[instrument(skip(self, merkle))]
     fn pad_tx(&self, tx_seq: u64, tx_start_index: u64, merkle: &mut MerkleManager) -> Result<()> {
         // Check if we need to pad the flow.
@@ -1100,6 +2187,25 @@ impl LogManager {
         &self.flow_store
     }
 
+    /// Puts the data of the tx staged in the revert journal, and finalizes the tx. Returns false
+    /// if not staged, or the tx is no longer of `tx_hash`.
+    fn put_staged_tx(&self, tx: &Transaction, tx_hash: H256) -> Result<bool> {
+        let chunks = match self.revert_journal.read(
+            tx.start_entry_index,
+            &tx.data_merkle_root,
+            bytes_to_entries(tx.size),
+        )? {
+            Some(chunks) => chunks,
+            None => return Ok(false),
+        };
+        for chunk in chunks {
+            if !self.put_chunks_with_tx_hash(tx.seq, tx_hash, chunk, None)? {
+                return Ok(false);
+            }
+        }
+        self.finalize_tx_with_hash(tx.seq, tx_hash)
+    }
+
     fn padding_rear_data(&self, tx: &Transaction) -> Result<()> {
         let (chunks, _) = compute_padded_chunk_size(tx.size as usize);
         let (segments_for_proof, last_segment_size_for_proof) =
@@ -1171,7 +2277,8 @@ impl LogManager {
             {
                 continue;
             }
-            to_tx_offset_list.push((tx.seq, tx.start_entry_index - old_tx.start_entry_index));
+            let offset = tx.start_entry_index - old_tx.start_entry_index;
+            to_tx_offset_list.push((tx, offset));
         }
         if to_tx_offset_list.is_empty() {
             return Ok(());
@@ -1195,8 +2302,8 @@ impl LogManager {
         }
         // num_entries() includes the rear padding data, so no need for more padding.
 
-        for (seq, _) in to_tx_offset_list {
-            self.tx_store.finalize_tx(seq)?;
+        for (tx, _) in to_tx_offset_list {
+            self.mark_tx_finalized(&tx)?;
         }
 
         metrics::COPY_TX_AND_FINALIZE.update_since(start_time);
@@ -1223,6 +2330,131 @@ impl LogManager {
             .insert_subtree_list_for_batch(index, to_insert_subtrees)
     }
 
+    /// Mark the tx finalized, or finalized in shard if some entries of the tx are out of the
+    /// shard of the node. The data of the tx is expected to be completed.
+    /// Pads the rear data of a tx to finalize, and returns the tx if its data is complete.
+    fn prepare_finalize(&self, tx_seq: u64) -> Result<Transaction> {
+        let tx = self
+            .tx_store
+            .get_tx_by_seq_number(tx_seq)?
+            .ok_or_else(|| StoreError::tx_not_found(tx_seq))?;
+        self.prepare_finalize_tx(tx)
+    }
+
+    /// Same as `prepare_finalize`, but returns `None` if the tx hash mismatches, e.g. reverted.
+    fn prepare_finalize_with_hash(
+        &self,
+        tx_seq: u64,
+        tx_hash: H256,
+    ) -> Result<Option<Transaction>> {
+        let tx = self
+            .tx_store
+            .get_tx_by_seq_number(tx_seq)?
+            .ok_or_else(|| StoreError::tx_not_found(tx_seq))?;
+        if tx.hash() != tx_hash {
+            return Ok(None);
+        }
+        self.prepare_finalize_tx(tx).map(Some)
+    }
+
+    fn prepare_finalize_tx(&self, tx: Transaction) -> Result<Transaction> {
+        if self.tx_store.check_tx_invalid(tx.seq)? {
+            bail!(StoreError::Invalid { tx_seq: tx.seq });
+        }
+
+        self.padding_rear_data(&tx)?;
+
+        let tx_end_index = tx.start_entry_index + bytes_to_entries(tx.size);
+        if !self.check_data_completed(tx.start_entry_index, tx_end_index)? {
+            bail!(StoreError::NotFound {
+                what: StoreItem::TxData(tx.seq),
+            });
+        }
+        Ok(tx)
+    }
+
+    /// Persists the statuses of the prepared txs in one write, so that either all or none of
+    /// them are finalized on crash, and then finalizes the txs of the same roots. Txs not
+    /// confirmed yet are finalized once confirmed, see `confirm_finalization`.
+    fn mark_txs_finalized(&self, txs: &[Transaction]) -> Result<()> {
+        let txs = &self.finalize_gate.lock().defer_all(txs)[..];
+        if txs.is_empty() {
+            return Ok(());
+        }
+
+        let statuses = txs
+            .iter()
+            .map(|tx| (tx.seq, self.finalized_status(tx)))
+            .collect::<Vec<_>>();
+        self.tx_store.put_tx_statuses(&statuses)?;
+        #[cfg(feature = "runtime")]
+        for tx in txs {
+            self.finalization_bus.publish(tx.id());
+        }
+
+        for tx in txs {
+            let same_root_seq_list = self
+                .tx_store
+                .get_live_tx_seq_list_by_data_root(&tx.data_merkle_root)?;
+            // Check if there are other same-root transaction not finalized.
+            if same_root_seq_list.first() == Some(&tx.seq) {
+                self.copy_tx_and_finalize(tx.seq, same_root_seq_list[1..].to_vec())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `false` if the tx is not confirmed yet, which is finalized once confirmed, see
+    /// `confirm_finalization`.
+    /// Returns whether the tx is finalized while its data is missing in the local shard.
+    fn finalized_but_incomplete(&self, tx_seq: u64) -> Result<bool> {
+        if !self.tx_store.check_tx_completed(tx_seq)? {
+            return Ok(false);
+        }
+        let tx = self
+            .tx_store
+            .get_tx_by_seq_number(tx_seq)?
+            .ok_or_else(|| StoreError::tx_not_found(tx_seq))?;
+        let tx_end_index = tx.start_entry_index + bytes_to_entries(tx.size);
+        Ok(!self.check_data_completed(tx.start_entry_index, tx_end_index)?)
+    }
+
+    fn mark_tx_finalized(&self, tx: &Transaction) -> Result<bool> {
+        // e.g. the data of the same root is synced for another tx
+        if self.tx_store.check_tx_invalid(tx.seq)? {
+            bail!(StoreError::Invalid { tx_seq: tx.seq });
+        }
+        if self.finalize_gate.lock().defer(tx) {
+            return Ok(false);
+        }
+        match self.finalized_status(tx) {
+            TxStatus::Finalized => self.tx_store.finalize_tx(tx.seq)?,
+            _ => self.tx_store.finalize_tx_in_shard(tx.seq)?,
+        }
+        #[cfg(feature = "runtime")]
+        self.finalization_bus.publish(tx.id());
+        Ok(true)
+    }
+
+    /// Returns the status of the tx once finalized, which is finalized in shard if some entries
+    /// of the tx are out of the shard of the node.
+    fn finalized_status(&self, tx: &Transaction) -> TxStatus {
+        let tx_end_index = tx.start_entry_index + bytes_to_entries(tx.size);
+        let num_batches = batch_iter(tx.start_entry_index, tx_end_index, PORA_CHUNK_SIZE).len();
+        let num_batches_in_shard = batch_iter_sharded(
+            tx.start_entry_index,
+            tx_end_index,
+            PORA_CHUNK_SIZE,
+            self.flow_store.get_shard_config(),
+        )
+        .len();
+        if num_batches_in_shard == num_batches {
+            TxStatus::Finalized
+        } else {
+            TxStatus::ShardFinalized
+        }
+    }
+
     fn check_data_completed(&self, start: u64, end: u64) -> Result<bool> {
         for (batch_start, batch_end) in batch_iter_sharded(
             start,
@@ -1230,11 +2462,7 @@ impl LogManager {
             PORA_CHUNK_SIZE,
             self.flow_store.get_shard_config(),
         ) {
-            if self
-                .flow_store
-                .get_entries(batch_start, batch_end)?
-                .is_none()
-            {
+            if !self.flow_store.has_entries(batch_start, batch_end)? {
                 return Ok(false);
             }
         }
@@ -1318,23 +2546,6 @@ fn entry_proof(top_proof: &FlowProof, sub_proof: &FlowProof) -> Result<FlowProof
     FlowProof::new(lemma, path)
 }
 
-pub fn split_nodes(data_size: usize) -> Vec<usize> {
-    let (mut padded_chunks, chunks_next_pow2) = compute_padded_chunk_size(data_size);
-    let mut next_chunk_size = chunks_next_pow2;
-
-    let mut nodes = vec![];
-    while padded_chunks > 0 {
-        if padded_chunks >= next_chunk_size {
-            padded_chunks -= next_chunk_size;
-            nodes.push(next_chunk_size);
-        }
-
-        next_chunk_size >>= 1;
-    }
-
-    nodes
-}
-
 pub fn tx_subtree_root_list_padded(data: &[u8]) -> Vec<(usize, DataRoot)> {
     let mut root_list = Vec::new();
     let mut start_index = 0;
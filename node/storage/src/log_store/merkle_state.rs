@@ -0,0 +1,186 @@
+//! Snapshot of the flow merkle tree, which is exported from nodes to diff their trees offline,
+//! e.g. once users report proofs that mismatch between nodes.
+
+use crate::error::StoreError;
+use anyhow::Result;
+use ethereum_types::H256;
+use serde::Serialize;
+use ssz::{Decode, Encode, BYTES_PER_LENGTH_OFFSET};
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
+
+/// Name of the tree over the roots of entry batches in [`MerkleNodeDiff`].
+pub const PORA_CHUNKS_TREE: &str = "poraChunks";
+/// Name of the tree of the entries in the last batch in [`MerkleNodeDiff`].
+pub const LAST_CHUNK_TREE: &str = "lastChunk";
+
+/// All the nodes of the flow merkle tree at the same flow length.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct MerkleState {
+    pub flow_version: u64,
+    pub flow_length: u64,
+    /// Layers of the tree over the roots of entry batches, from the leaves to the root.
+    pub pora_chunks: Vec<Vec<H256>>,
+    /// Layers of the tree of the entries in the last batch, from the leaves to the root.
+    pub last_chunk: Vec<Vec<H256>>,
+}
+
+impl MerkleState {
+    /// Returns the ssz length of a state of which the layers are of the given lengths, so that
+    /// the size is bounded while the state is built.
+    pub fn ssz_len(layer_lens: impl Iterator<Item = usize>) -> usize {
+        // two numbers and the offsets of the two trees
+        2 * 8
+            + 2 * BYTES_PER_LENGTH_OFFSET
+            + layer_lens
+                .map(|len| BYTES_PER_LENGTH_OFFSET + len * H256::len_bytes())
+                .sum::<usize>()
+    }
+
+    /// Returns the flow root, or `None` if the tree is empty.
+    pub fn flow_root(&self) -> Option<H256> {
+        self.pora_chunks
+            .last()
+            .and_then(|root_layer| root_layer.first())
+            .copied()
+    }
+
+    /// Serializes the state in ssz, which is the format exported.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.as_ssz_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::from_ssz_bytes(bytes).map_err(StoreError::from)?)
+    }
+}
+
+/// The first node that differs between two merkle states.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MerkleNodeDiff {
+    /// Either [`PORA_CHUNKS_TREE`] or [`LAST_CHUNK_TREE`].
+    pub tree: &'static str,
+    /// Layer of the node, in which 0 is the layer of leaves.
+    pub layer: usize,
+    pub index: usize,
+    /// Indices of the ancestors of the node in each layer from the root down to the node.
+    pub path: Vec<usize>,
+    /// Node of each state, or `None` if the layer of the state is shorter.
+    pub left: Option<H256>,
+    pub right: Option<H256>,
+}
+
+/// Returns the first differing node of the states, i.e. the leftmost one in the lowest layer,
+/// whose ancestors up to the root differ as well. Returns `None` if the trees are the same.
+///
+/// The root of the last batch is the last leaf of the tree over entry batches, so a difference
+/// there is located further in the tree of the last batch.
+pub fn diff_merkle_states(left: &MerkleState, right: &MerkleState) -> Option<MerkleNodeDiff> {
+    let diff = diff_layers(PORA_CHUNKS_TREE, &left.pora_chunks, &right.pora_chunks)?;
+    let last_leaf = |state: &MerkleState| state.pora_chunks.first().map_or(0, Vec::len);
+    if diff.layer == 0 && diff.index + 1 >= last_leaf(left).min(last_leaf(right)) {
+        if let Some(last_chunk_diff) =
+            diff_layers(LAST_CHUNK_TREE, &left.last_chunk, &right.last_chunk)
+        {
+            return Some(last_chunk_diff);
+        }
+    }
+    Some(diff)
+}
+
+fn diff_layers(
+    tree: &'static str,
+    left: &[Vec<H256>],
+    right: &[Vec<H256>],
+) -> Option<MerkleNodeDiff> {
+    let height = left.len().max(right.len());
+    for layer in 0..height {
+        let (left_layer, right_layer) = (left.get(layer), right.get(layer));
+        let len = left_layer
+            .map_or(0, Vec::len)
+            .max(right_layer.map_or(0, Vec::len));
+        for index in 0..len {
+            let left_node = left_layer.and_then(|nodes| nodes.get(index)).copied();
+            let right_node = right_layer.and_then(|nodes| nodes.get(index)).copied();
+            if left_node != right_node {
+                let path = (layer..height)
+                    .rev()
+                    .map(|l| index >> (l - layer))
+                    .collect();
+                return Some(MerkleNodeDiff {
+                    tree,
+                    layer,
+                    index,
+                    path,
+                    left: left_node,
+                    right: right_node,
+                });
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> MerkleState {
+        let node = |i: u64| H256::from_low_u64_be(i);
+        MerkleState {
+            flow_version: 0,
+            flow_length: 3 * 1024 + 2,
+            pora_chunks: vec![
+                vec![node(1), node(2), node(3), node(4)],
+                vec![node(5), node(6)],
+                vec![node(7)],
+            ],
+            last_chunk: vec![vec![node(8), node(9)], vec![node(4)]],
+        }
+    }
+
+    #[test]
+    fn test_merkle_state_bytes() {
+        let state = state();
+        assert_eq!(MerkleState::from_bytes(&state.to_bytes()).unwrap(), state);
+        assert_eq!(state.flow_root(), Some(H256::from_low_u64_be(7)));
+        assert!(MerkleState::from_bytes(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_diff_merkle_states() {
+        let left = state();
+        assert_eq!(diff_merkle_states(&left, &left), None);
+
+        // an inner node diverged while the leaves are the same
+        let mut right = state();
+        right.pora_chunks[1][1] = H256::repeat_byte(0xff);
+        right.pora_chunks[2][0] = H256::repeat_byte(0xfe);
+        let diff = diff_merkle_states(&left, &right).unwrap();
+        assert_eq!(
+            (diff.tree, diff.layer, diff.index),
+            (PORA_CHUNKS_TREE, 1, 1)
+        );
+        assert_eq!(diff.path, vec![0, 1]);
+        assert_eq!(diff.right, Some(H256::repeat_byte(0xff)));
+
+        // located in the last batch once its root diverged
+        let mut right = state();
+        right.last_chunk[0][1] = H256::repeat_byte(0xff);
+        right.pora_chunks[0][3] = H256::repeat_byte(0xfe);
+        let diff = diff_merkle_states(&left, &right).unwrap();
+        assert_eq!((diff.tree, diff.layer, diff.index), (LAST_CHUNK_TREE, 0, 1));
+        assert_eq!(diff.path, vec![0, 1]);
+
+        // missing leaves of the shorter tree
+        let mut right = state();
+        right.pora_chunks[0].truncate(2);
+        let diff = diff_merkle_states(&left, &right).unwrap();
+        assert_eq!((diff.layer, diff.index), (0, 2));
+        assert_eq!(diff.path, vec![0, 1, 2]);
+        assert_eq!(
+            (diff.left, diff.right),
+            (Some(H256::from_low_u64_be(3)), None)
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use metrics::{register_timer, Gauge, GaugeUsize, Timer};
+use metrics::{register_timer, Counter, CounterUsize, Gauge, GaugeUsize, Histogram, Sample, Timer};
 
 lazy_static::lazy_static! {
     pub static ref PUT_TX: Arc<dyn Timer> = register_timer("log_store_put_tx");
@@ -36,8 +36,47 @@ lazy_static::lazy_static! {
     pub static ref APPEND_ENTRIES: Arc<dyn Timer> = register_timer("log_store_flow_store_append_entries");
 
     pub static ref FINALIZE_TX_WITH_HASH: Arc<dyn Timer> = register_timer("log_store_log_manager_finalize_tx_with_hash");
+    pub static ref FINALIZE_TXS: Arc<dyn Timer> = register_timer("log_store_log_manager_finalize_txs");
 
     pub static ref DATA_TO_MERKLE_LEAVES_SIZE: Arc<dyn Gauge<usize>> = GaugeUsize::register("log_store_data_to_merkle_leaves_size");
 
     pub static ref TX_BY_SEQ_NUMBER: Arc<dyn Timer> = register_timer("log_store_tx_store_get_tx_by_seq_number");
+
+    pub static ref TX_SEQ_LISTS_BY_DATA_ROOTS: Arc<dyn Timer> = register_timer("log_store_tx_store_get_tx_seq_lists_by_data_roots");
+
+    pub static ref REMOVE_TX_AFTER: Arc<dyn Timer> = register_timer("log_store_tx_store_remove_tx_after");
+
+    pub static ref DATA_ROOT_INDEX_COMPACTED: Arc<dyn Counter<usize>> = CounterUsize::register("log_store_tx_store_data_root_index_compacted");
+
+    pub static ref REVERT: Arc<dyn Timer> = register_timer("log_store_log_manager_revert_to");
+
+    pub static ref REVERT_DEPTH: Arc<dyn Histogram> = Sample::ExpDecay(0.015).register("log_store_revert_depth", 1024);
+
+    pub static ref REVERT_TRUNCATED_BATCHES: Arc<dyn Histogram> = Sample::ExpDecay(0.015).register("log_store_revert_truncated_batches", 1024);
+
+    pub static ref REVERTED_TXS: Arc<dyn Counter<usize>> = CounterUsize::register("log_store_reverted_txs");
+
+    pub static ref REVERT_JOURNAL_STAGED: Arc<dyn Counter<usize>> = CounterUsize::register("log_store_revert_journal_staged");
+
+    pub static ref REVERT_JOURNAL_RESTORED: Arc<dyn Counter<usize>> = CounterUsize::register("log_store_revert_journal_restored");
+
+    pub static ref REVERT_JOURNAL_EXPIRED: Arc<dyn Counter<usize>> = CounterUsize::register("log_store_revert_journal_expired");
+
+    pub static ref REVERT_JOURNAL_EVICTED: Arc<dyn Counter<usize>> = CounterUsize::register("log_store_revert_journal_evicted");
+
+    pub static ref INVALID_TX_FLOW_RANGE: Arc<dyn Counter<usize>> = CounterUsize::register("log_store_invalid_tx_flow_range");
+
+    pub static ref TX_INLINE_DATA_REJECTED: Arc<dyn Counter<usize>> = CounterUsize::register("log_store_tx_inline_data_rejected");
+
+    pub static ref SUBMIT_SEAL_RESULT: Arc<dyn Timer> = register_timer("log_store_flow_store_submit_seal_result");
+
+    pub static ref SEAL_RESULT_BATCH_SEALS: Arc<dyn Histogram> = Sample::ExpDecay(0.015).register("log_store_seal_result_batch_seals", 1024);
+
+    pub static ref SEAL_RESULT_BATCH_CHUNKS: Arc<dyn Histogram> = Sample::ExpDecay(0.015).register("log_store_seal_result_batch_chunks", 1024);
+
+    pub static ref KVDB_WRITE_STALL_MICROS: Arc<dyn Counter<usize>> = CounterUsize::register("log_store_kvdb_write_stall_micros");
+
+    pub static ref KVDB_WRITE_STALLS: Arc<dyn Counter<usize>> = CounterUsize::register("log_store_kvdb_write_stalls");
+
+    pub static ref FINALIZATION_COALESCED: Arc<dyn Counter<usize>> = CounterUsize::register("log_store_finalization_coalesced");
 }
@@ -0,0 +1,49 @@
+//! Metrics of the same names as `metrics.rs`, which record nothing if the `metrics` feature is
+//! disabled, e.g. when the store is embedded without the metrics registry of the node.
+
+/// Accepts the updates of any metric type and drops them.
+pub struct Noop;
+
+impl Noop {
+    pub fn update_since<T>(&self, _start: T) {}
+
+    pub fn update<T>(&self, _value: T) {}
+
+    pub fn inc(&self, _value: usize) {}
+}
+
+pub static PUT_TX: Noop = Noop;
+pub static PUT_CHUNKS: Noop = Noop;
+pub static TX_STORE_PUT: Noop = Noop;
+pub static CHECK_TX_COMPLETED: Noop = Noop;
+pub static APPEND_SUBTREE_LIST: Noop = Noop;
+pub static DATA_TO_MERKLE_LEAVES: Noop = Noop;
+pub static COPY_TX_AND_FINALIZE: Noop = Noop;
+pub static PAD_TX: Noop = Noop;
+pub static INSERT_SUBTREE_LIST: Noop = Noop;
+pub static PUT_ENTRY_BATCH_LIST: Noop = Noop;
+pub static APPEND_ENTRIES: Noop = Noop;
+pub static FINALIZE_TX_WITH_HASH: Noop = Noop;
+pub static FINALIZE_TXS: Noop = Noop;
+pub static DATA_TO_MERKLE_LEAVES_SIZE: Noop = Noop;
+pub static TX_BY_SEQ_NUMBER: Noop = Noop;
+pub static TX_SEQ_LISTS_BY_DATA_ROOTS: Noop = Noop;
+pub static REMOVE_TX_AFTER: Noop = Noop;
+pub static DATA_ROOT_INDEX_COMPACTED: Noop = Noop;
+pub static REVERT: Noop = Noop;
+pub static REVERT_DEPTH: Noop = Noop;
+pub static REVERT_TRUNCATED_BATCHES: Noop = Noop;
+pub static REVERTED_TXS: Noop = Noop;
+pub static REVERT_JOURNAL_STAGED: Noop = Noop;
+pub static REVERT_JOURNAL_RESTORED: Noop = Noop;
+pub static REVERT_JOURNAL_EXPIRED: Noop = Noop;
+pub static REVERT_JOURNAL_EVICTED: Noop = Noop;
+pub static INVALID_TX_FLOW_RANGE: Noop = Noop;
+pub static TX_INLINE_DATA_REJECTED: Noop = Noop;
+pub static SUBMIT_SEAL_RESULT: Noop = Noop;
+pub static SEAL_RESULT_BATCH_SEALS: Noop = Noop;
+pub static SEAL_RESULT_BATCH_CHUNKS: Noop = Noop;
+pub static KVDB_WRITE_STALL_MICROS: Noop = Noop;
+pub static KVDB_WRITE_STALLS: Noop = Noop;
+#[cfg(feature = "runtime")]
+pub static FINALIZATION_COALESCED: Noop = Noop;
@@ -9,18 +9,43 @@ use shared_types::{
 use zgs_spec::{BYTES_PER_SEAL, SEALS_PER_LOAD};
 
 use crate::error::Result;
+use crate::handles::DbLayout;
+
+#[cfg(feature = "runtime")]
+use self::finalization_bus::FinalizationSubscriber;
+use self::job_store::AdminJob;
+use self::merkle_state::MerkleState;
+use self::revert_history::RevertEvent;
+use self::reward_store::{MinerReward, MinerRewardSummary};
+use self::tx_store::{
+    BlockHashAndSubmissionIndex, ChunkRange, FlushJournal, SubmissionContext, TxStatus,
+};
 
-use self::tx_store::{BlockHashAndSubmissionIndex, TxStatus};
-
+pub mod audit;
+pub mod check;
 pub mod config;
+#[cfg(feature = "runtime")]
+pub mod finalization_bus;
+mod finalize_gate;
 mod flow_store;
+pub mod job_store;
 pub mod load_chunk;
 pub mod log_manager;
+pub mod merkle_state;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(not(feature = "metrics"))]
+#[path = "metrics_noop.rs"]
 mod metrics;
+pub mod revert_history;
+pub mod revert_journal;
+pub mod reward_store;
 mod seal_task_manager;
 #[cfg(test)]
 mod tests;
+mod tree_gate;
 pub mod tx_store;
+pub mod write_stall;
 
 /// The trait to read the transactions already appended to the log.
 ///
@@ -35,6 +60,10 @@ pub trait LogStoreRead: LogStoreChunkRead {
     /// Otherwise, return the first finalized tx.
     fn get_tx_seq_by_data_root(&self, data_root: &DataRoot) -> Result<Option<u64>>;
 
+    /// Get the seq of the tx whose entries, including the rear padding, contain the flow entry
+    /// `index`. Return `None` if the entry is the padding between txs or beyond the flow.
+    fn get_tx_seq_by_flow_index(&self, index: u64) -> Result<Option<u64>>;
+
     /// If all txs are not finalized, return the first one.
     /// Otherwise, return the first finalized tx.
     fn get_tx_by_data_root(&self, data_root: &DataRoot) -> Result<Option<Transaction>> {
@@ -44,6 +73,10 @@ pub trait LogStoreRead: LogStoreChunkRead {
         }
     }
 
+    /// Batch version of `get_tx_by_data_root`.
+    /// The result is in the same order as `data_roots`, with `None` for unknown roots.
+    fn get_txs_by_data_roots(&self, data_roots: &[DataRoot]) -> Result<Vec<Option<Transaction>>>;
+
     fn get_chunk_with_proof_by_tx_and_index(
         &self,
         tx_seq: u64,
@@ -62,8 +95,18 @@ pub trait LogStoreRead: LogStoreChunkRead {
 
     fn check_tx_pruned(&self, tx_seq: u64) -> Result<bool>;
 
+    /// Returns `true` if the tx is rejected by the ingest policy, see [`TxStatus::Invalid`].
+    fn check_tx_invalid(&self, tx_seq: u64) -> Result<bool>;
+
     fn get_tx_status(&self, tx_seq: u64) -> Result<Option<TxStatus>>;
 
+    /// Return at most `limit` txs and their status from `start_seq` in ascending order.
+    fn get_txs_with_status(
+        &self,
+        start_seq: u64,
+        limit: usize,
+    ) -> Result<Vec<(Transaction, Option<TxStatus>)>>;
+
     fn next_tx_seq(&self) -> u64;
 
     fn get_sync_progress(&self) -> Result<Option<(u64, H256)>>;
@@ -72,8 +115,22 @@ pub trait LogStoreRead: LogStoreChunkRead {
 
     fn get_block_hash_by_number(&self, block_number: u64) -> Result<Option<(H256, Option<u64>)>>;
 
+    /// Loads all the block hashes, which is only intended for internal tooling.
     fn get_block_hashes(&self) -> Result<Vec<(u64, BlockHashAndSubmissionIndex)>>;
 
+    /// Returns at most `limit` block hashes from `from_block` (inclusive) in ascending order.
+    fn get_block_hashes_from(
+        &self,
+        from_block: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, BlockHashAndSubmissionIndex)>>;
+
+    /// Iterates the block hashes from `from_block` (inclusive) backwards lazily.
+    fn iter_block_hashes_rev(
+        &self,
+        from_block: u64,
+    ) -> Box<dyn Iterator<Item = Result<(u64, BlockHashAndSubmissionIndex)>> + '_>;
+
     fn validate_range_proof(&self, tx_seq: u64, data: &ChunkArrayWithProof) -> Result<bool>;
 
     fn get_proof_at_root(
@@ -86,13 +143,108 @@ pub trait LogStoreRead: LogStoreChunkRead {
     /// Return flow root and length.
     fn get_context(&self) -> Result<(DataRoot, u64)>;
 
-    fn pull_seal_chunk(&self, seal_index_max: usize) -> Result<Option<Vec<SealTask>>>;
+    /// Return the flow entries in `[index, index + length)` with the range proof against the
+    /// latest flow root, along with the flow root and length that the proof is generated at.
+    ///
+    /// Return `None` if any entry is not available locally.
+    fn get_flow_entries_with_proof(
+        &self,
+        index: u64,
+        length: u64,
+    ) -> Result<Option<(ChunkArrayWithProof, DataRoot, u64)>>;
+
+    fn pull_seal_chunk(
+        &self,
+        seal_index_min: usize,
+        seal_index_max: usize,
+    ) -> Result<Option<Vec<SealTask>>>;
 
     fn get_num_entries(&self) -> Result<u64>;
 
     fn load_sealed_data(&self, chunk_index: u64) -> Result<Option<MineLoadChunk>>;
 
+    /// Return the sealed data of the load chunk as stored without unsealing, along with the
+    /// proof of the chunk root against the latest flow root.
+    ///
+    /// Return `None` if the load chunk is not stored locally, e.g. pruned or out of the shard.
+    fn get_sealed_chunk_with_proof(&self, chunk_index: u64)
+        -> Result<Option<SealedChunkWithProof>>;
+
     fn get_shard_config(&self) -> ShardConfig;
+
+    /// Returns whether the flow merkle tree is loaded, before which writes and reads of proofs
+    /// wait for the tree, see `LogManager::with_dbs_deferred`.
+    fn is_flow_tree_ready(&self) -> bool;
+
+    /// Subscribe the readiness of the flow merkle tree, so that async callers could wait for the
+    /// tree without blocking the runtime threads.
+    #[cfg(feature = "runtime")]
+    fn subscribe_flow_tree_ready(&self) -> tokio::sync::watch::Receiver<bool>;
+
+    /// Return the estimated number of keys of every column in the flow db and data db.
+    fn get_db_column_stats(&self) -> Result<Vec<ColumnStats>>;
+
+    fn get_db_layout(&self) -> DbLayout;
+
+    /// Return the version of the served data, which is bumped both before and after txs are
+    /// reverted or pruned. Data read between two equal versions is never stale, so that it
+    /// could be cached along with the version.
+    fn get_flow_version(&self) -> u64;
+
+    /// Return all the nodes of the flow merkle tree at the same flow length, see
+    /// [`merkle_state::diff_merkle_states`]. The nodes are read in batches without holding the
+    /// tree lock all the time.
+    ///
+    /// Fails with [`crate::error::StoreError::OutOfRange`] once the state exceeds `max_size`
+    /// bytes, or [`crate::error::StoreError::Busy`] if txs keep being reverted meanwhile.
+    fn get_merkle_state(&self, max_size: usize) -> Result<MerkleState>;
+
+    /// Verify the local data of a tx against the flow merkle tree, and return the indices of
+    /// entry batches whose data are missing or corrupted. Batches out of the local shard are
+    /// skipped.
+    fn verify_tx_data(&self, tx_seq: u64) -> Result<Vec<u64>>;
+
+    /// Verify the local data of the flow entries in `[start_index, end_index)` against the flow
+    /// merkle tree, and return the indices of entry batches whose data are missing or corrupted.
+    /// Batches out of the local shard are skipped, and the range is truncated to the flow length.
+    fn verify_flow_range(&self, start_index: u64, end_index: u64) -> Result<Vec<u64>>;
+
+    /// Return all chunk pool flush journals of files that are not finalized yet.
+    fn get_flush_journals(&self) -> Result<Vec<FlushJournal>>;
+
+    /// Verify the chunk ranges of a flush journal against the flow merkle tree, and return the
+    /// ranges whose data are missing or corrupted.
+    fn verify_flush_journal(&self, journal: &FlushJournal) -> Result<Vec<ChunkRange>>;
+
+    /// Return the latest revert events since the node started, from the oldest to the latest.
+    fn get_revert_history(&self) -> Vec<RevertEvent>;
+
+    /// Subscribe the txs finalized since now, which are delivered in batch.
+    #[cfg(feature = "runtime")]
+    fn subscribe_finalization(&self) -> FinalizationSubscriber;
+
+    /// Return the miner rewards of the epochs in `[from_epoch, to_epoch]`, ordered by epoch.
+    fn get_miner_rewards(&self, from_epoch: u64, to_epoch: u64) -> Result<Vec<MinerReward>>;
+
+    /// Return the totals of all the miner rewards.
+    fn get_miner_reward_summary(&self) -> Result<MinerRewardSummary>;
+
+    /// Return the context of the on-chain submission of the tx, or `None` if not recorded, e.g.
+    /// txs synced before it is recorded or replayed from file.
+    fn get_submission_context(&self, tx_seq: u64) -> Result<Option<SubmissionContext>>;
+
+    /// Return the unix timestamp in seconds when the data root is first seen in a tx, which is
+    /// never reset by re-submissions, and is removed only once all txs of the root reverted.
+    fn get_data_root_first_seen(&self, data_root: &DataRoot) -> Result<Option<u64>>;
+
+    /// Return whether the data of a reverted tx at the same position with the same root as `tx`
+    /// is staged in the revert journal, so that `tx` could be restored by `restore_reverted_tx`.
+    fn is_reverted_tx_staged(&self, tx: &Transaction) -> bool;
+
+    fn get_admin_job(&self, id: u64) -> Result<Option<AdminJob>>;
+
+    /// Return all admin jobs in ascending order of id, including the finished ones.
+    fn get_admin_jobs(&self) -> Result<Vec<AdminJob>>;
 }
 
 pub trait LogStoreChunkRead {
@@ -108,6 +260,15 @@ pub trait LogStoreChunkRead {
         index_end: usize,
     ) -> Result<Option<ChunkArray>>;
 
+    /// Returns whether all chunks in the index range (`index_end` excluded) of a tx are stored
+    /// locally, regardless of whether the tx is finalized.
+    fn has_chunks_by_tx_and_index_range(
+        &self,
+        tx_seq: u64,
+        index_start: usize,
+        index_end: usize,
+    ) -> Result<bool>;
+
     fn get_chunk_by_data_root_and_index(
         &self,
         data_root: &DataRoot,
@@ -131,6 +292,18 @@ pub trait LogStoreWrite: LogStoreChunkWrite {
     /// Store a data entry metadata.
     fn put_tx(&self, tx: Transaction) -> Result<()>;
 
+    /// Store the tx rejected by the ingest policy, which occupies its flow range the same as
+    /// `put_tx`, but is marked as [`TxStatus::Invalid`] at once. Its data is never stored, e.g.
+    /// copied from a finalized tx of the same root, so it is never finalized or served.
+    fn put_invalid_tx(&self, tx: Transaction) -> Result<()>;
+
+    /// Restore the data of the tx put at the same position of a reverted tx with the same root
+    /// from the revert journal, and finalize it. Return whether restored, otherwise the tx is
+    /// synced as usual, e.g. not staged or the tx of `tx_seq` is no longer of `tx_hash`. This
+    /// reads and writes all the data of the tx, so it is called after `put_tx` off the log sync
+    /// loop, during which the tx may be reverted again.
+    fn restore_reverted_tx(&self, tx_seq: u64, tx_hash: H256) -> Result<bool>;
+
     /// Finalize a transaction storage.
     /// This will compute and the merkle tree, check the data root, and persist a part of the merkle
     /// tree for future queries.
@@ -139,8 +312,43 @@ pub trait LogStoreWrite: LogStoreChunkWrite {
     /// the caller is supposed to track chunk statuses and call this after storing all the chunks.
     fn finalize_tx(&self, tx_seq: u64) -> Result<()>;
     fn finalize_tx_with_hash(&self, tx_seq: u64, tx_hash: H256) -> Result<bool>;
+    /// Finalize multiple transactions with their statuses persisted in one write, e.g. for bulk
+    /// finalization, while finalization events are still published for each tx.
+    ///
+    /// This will return error without finalizing any tx if the data of any tx is incomplete.
+    fn finalize_txs(&self, tx_seqs: Vec<u64>) -> Result<()>;
+    /// Finalize the txs of matching hashes with their statuses persisted in one write, and
+    /// return the result of each tx as `finalize_tx_with_hash` does.
+    ///
+    /// Unlike `finalize_txs`, a tx that fails to finalize, e.g. of incomplete data, never blocks
+    /// the others. Error is returned only if the batch fails to persist.
+    fn finalize_txs_with_hash(&self, txs: Vec<(u64, H256)>) -> Result<Vec<Result<bool>>>;
+    /// Confirm the txs before `next_tx_seq` to finalize, e.g. once their blocks are deep enough,
+    /// and finalize the ones deferred. Once called, finalizing the txs not confirmed yet is
+    /// deferred until confirmed, no matter who completes the data, and reported as succeeded.
+    ///
+    /// Finalization is never deferred if this is never called, and the deferred txs are lost
+    /// on restart, which should be finalized again.
+    fn confirm_finalization(&self, next_tx_seq: u64) -> Result<()>;
     /// Mark the tx as pruned, meaning the data will not be stored.
     fn prune_tx(&self, tx_seq: u64) -> Result<()>;
+    /// Compact the seq lists of the data roots whose txs are all pruned to the last seq, and
+    /// return the number of data roots compacted. Only the roots pruned by an earlier version,
+    /// which never compacted on prune, are compacted, and only once for all.
+    fn compact_pruned_data_roots(&self) -> Result<usize>;
+    /// Remove the data of a tx in the given entry batches and clear its finalized status, so that
+    /// the data could be synced again. Only the tx data is removed from the batches shared with
+    /// other data, which are sealed again.
+    ///
+    /// This will return error if the tx is pruned, or any batch is out of the local shard or the
+    /// tx.
+    fn reset_tx_data(&self, tx_seq: u64, batch_list: &[u64]) -> Result<()>;
+    /// Clear the finalized status of the given txs whose data is missing in the local shard, and
+    /// return the demoted ones, so that their data could be synced again.
+    ///
+    /// Txs that are not finalized are skipped. The store is only locked to demote a tx, which
+    /// changes the flow version as well.
+    fn demote_incomplete_txs(&self, tx_seqs: &[u64]) -> Result<Vec<u64>>;
 
     /// Store the progress of synced block number and its hash.
     fn put_sync_progress(&self, progress: (u64, H256, Option<Option<u64>>)) -> Result<()>;
@@ -163,11 +371,37 @@ pub trait LogStoreWrite: LogStoreChunkWrite {
 
     fn delete_block_hash_by_number(&self, block_number: u64) -> Result<()>;
 
+    fn delete_block_hashes_before(&self, block_number: u64) -> Result<()>;
+
     fn update_shard_config(&self, shard_config: ShardConfig);
 
     fn submit_seal_result(&self, answers: Vec<SealAnswer>) -> Result<()>;
 
+    #[cfg(feature = "runtime")]
     fn start_padding(&self, executor: &task_executor::TaskExecutor);
+
+    /// Store the chunk ranges of a file before the chunk pool flushes them into store.
+    /// The journal is removed atomically when the tx is finalized.
+    fn put_flush_journal(&self, journal: FlushJournal) -> Result<()>;
+
+    fn remove_flush_journal(&self, tx_seq: u64) -> Result<()>;
+
+    /// Insert the miner reward, or replace the one with the same epoch and id.
+    fn put_miner_reward(&self, reward: MinerReward) -> Result<()>;
+
+    /// Roll back the miner rewards accepted in the reorged blocks since `block_number`, and
+    /// return the number of rewards rolled back.
+    fn revert_miner_rewards(&self, block_number: u64) -> Result<usize>;
+
+    /// Insert the admin job, or replace the one with the same id, e.g. to update the progress.
+    fn put_admin_job(&self, job: AdminJob) -> Result<()>;
+
+    /// Remove the admin jobs of `ids`, e.g. the finished jobs beyond retention.
+    fn remove_admin_jobs(&self, ids: Vec<u64>) -> Result<()>;
+
+    /// Record the context of the on-chain submission of the tx, which is removed along with the
+    /// tx on revert.
+    fn put_submission_context(&self, tx_seq: u64, context: SubmissionContext) -> Result<()>;
 }
 
 pub trait LogStoreChunkWrite {
@@ -196,10 +430,20 @@ pub trait Store:
 }
 impl<T: LogStoreRead + LogStoreWrite + config::Configurable + Send + Sync + 'static> Store for T {}
 
+/// Key statistics of a db column.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnStats {
+    pub db: &'static str,
+    pub column: &'static str,
+    pub num_keys: u64,
+}
+
 pub struct MineLoadChunk {
     // Use `Vec` instead of array to avoid thread stack overflow.
     pub loaded_chunk: Vec<[u8; BYTES_PER_SEAL]>,
     pub availabilities: [bool; SEALS_PER_LOAD],
+    /// Context digest that each available seal is sealed with.
+    pub seal_contexts: [Option<H256>; SEALS_PER_LOAD],
 }
 
 impl Default for MineLoadChunk {
@@ -207,14 +451,32 @@ impl Default for MineLoadChunk {
         Self {
             loaded_chunk: vec![[0u8; BYTES_PER_SEAL]; SEALS_PER_LOAD],
             availabilities: [false; SEALS_PER_LOAD],
+            seal_contexts: [None; SEALS_PER_LOAD],
         }
     }
 }
 
+/// Sealed data of a load chunk with the context to verify it offline.
+pub struct SealedChunkWithProof {
+    pub chunk: MineLoadChunk,
+    /// The miner id that the chunk is sealed for, which is zero if nothing sealed yet.
+    pub miner_id: H256,
+    /// Proof of the load chunk root against `flow_root`, whose item is the chunk root.
+    pub proof: FlowProof,
+    /// Flow root when the proof generated.
+    pub flow_root: DataRoot,
+    /// Number of flow entries when the proof generated.
+    pub flow_length: u64,
+}
+
 pub trait FlowRead {
     /// Return the entries in the given range. If some data are missing, `Ok(None)` is returned.
     fn get_entries(&self, index_start: u64, index_end: u64) -> Result<Option<ChunkArray>>;
 
+    /// Check if all entries in the given range are present, which is cheaper than `get_entries`
+    /// since no data is copied or unsealed.
+    fn has_entries(&self, index_start: u64, index_end: u64) -> Result<bool>;
+
     /// Return the available entries in the given range.
     /// The `ChunkArray` in the returned list are in order and they will not overlap or be adjacent.
     ///
@@ -241,7 +503,8 @@ pub trait FlowWrite {
 
     /// Remove all the entries after `start_index`.
     /// This is used to remove deprecated data in case of chain reorg.
-    fn truncate(&self, start_index: u64) -> Result<()>;
+    /// Return the number of entry batches truncated.
+    fn truncate(&self, start_index: u64) -> Result<usize>;
 
     /// Update the shard config.
     fn update_shard_config(&self, shard_config: ShardConfig);
@@ -276,12 +539,21 @@ pub struct SealAnswer {
 }
 
 pub trait FlowSeal {
-    /// Pull a seal chunk ready for sealing
+    /// Pull a seal chunk ready for sealing in the seal indices `[seal_index_min, seal_index_max)`
     /// Return the global index (in sector) and the data
-    fn pull_seal_chunk(&self, seal_index_max: usize) -> Result<Option<Vec<SealTask>>>;
+    ///
+    /// Tasks are pulled again until their answers are submitted, so that the seal worker could
+    /// skip the tasks being sealed by `seal_index_min`.
+    fn pull_seal_chunk(
+        &self,
+        seal_index_min: usize,
+        seal_index_max: usize,
+    ) -> Result<Option<Vec<SealTask>>>;
 
     /// Submit sealing result
-
+    ///
+    /// Answers of any load chunks and in any order are committed in one db write, and the
+    /// sealed data is only loaded for mining once committed.
     fn submit_seal_result(&self, answers: Vec<SealAnswer>) -> Result<()>;
 }
 
@@ -296,6 +568,7 @@ pub trait PadDataStoreRead {
 pub trait PadDataStoreWrite {
     fn put_pad_data(&self, data_sizes: &[PadPair], tx_seq: u64) -> Result<()>;
     fn put_pad_data_sync_height(&self, tx_seq: u64) -> Result<()>;
+    #[cfg(feature = "runtime")]
     fn start_padding(&mut self, executor: &task_executor::TaskExecutor);
 }
 
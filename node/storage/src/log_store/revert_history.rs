@@ -0,0 +1,100 @@
+use crate::log_store::metrics;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Maximum number of the latest revert events kept in memory.
+pub const MAX_REVERT_HISTORY: usize = 32;
+
+/// Txs reverted from the log store, e.g. on chain reorg.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RevertEvent {
+    /// Unix timestamp in seconds when reverted.
+    pub timestamp: u64,
+    /// The first tx seq reverted.
+    pub min_seq: u64,
+    /// The next tx seq before reverted.
+    pub max_seq: u64,
+    /// Number of txs reverted, which is less than the depth only if some txs are missing.
+    pub num_txs: usize,
+    /// Number of entry batches truncated from the flow, including the partially truncated one.
+    pub truncated_batches: usize,
+    /// Wall time to revert the merkle tree, flow entries and txs.
+    pub elapsed: Duration,
+}
+
+impl RevertEvent {
+    /// Number of tx seqs reverted.
+    pub fn depth(&self) -> u64 {
+        self.max_seq.saturating_sub(self.min_seq)
+    }
+}
+
+/// The latest revert events, which are lost after restart.
+#[derive(Default)]
+pub(crate) struct RevertHistory {
+    events: Mutex<VecDeque<RevertEvent>>,
+}
+
+impl RevertHistory {
+    pub(crate) fn record(
+        &self,
+        min_seq: u64,
+        max_seq: u64,
+        num_txs: usize,
+        truncated_batches: usize,
+        elapsed: Duration,
+    ) -> RevertEvent {
+        let event = RevertEvent {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            min_seq,
+            max_seq,
+            num_txs,
+            truncated_batches,
+            elapsed,
+        };
+
+        metrics::REVERT_DEPTH.update(event.depth());
+        metrics::REVERT_TRUNCATED_BATCHES.update(truncated_batches as u64);
+        metrics::REVERTED_TXS.inc(num_txs);
+        metrics::REVERT.update(elapsed);
+
+        let mut events = self.events.lock();
+        if events.len() >= MAX_REVERT_HISTORY {
+            events.pop_front();
+        }
+        events.push_back(event.clone());
+
+        event
+    }
+
+    /// Returns the revert events from the oldest to the latest.
+    pub(crate) fn events(&self) -> Vec<RevertEvent> {
+        self.events.lock().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded_history() {
+        let history = RevertHistory::default();
+        for i in 0..MAX_REVERT_HISTORY as u64 + 2 {
+            let event = history.record(i, i + 3, 3, 1, Duration::from_millis(5));
+            assert_eq!(event.depth(), 3);
+        }
+
+        let events = history.events();
+        assert_eq!(events.len(), MAX_REVERT_HISTORY);
+        assert_eq!(events.first().unwrap().min_seq, 2);
+        assert_eq!(
+            events.last().unwrap().min_seq,
+            MAX_REVERT_HISTORY as u64 + 1
+        );
+    }
+}
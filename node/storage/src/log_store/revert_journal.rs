@@ -0,0 +1,294 @@
+//! Journal of the data of the finalized txs reverted from the flow, e.g. on chain reorg, so that
+//! a tx re-inserted at the same position with the same root is restored at once rather than
+//! synced from peers again.
+//!
+//! The data is staged by entry batch in `COL_REVERTED_BATCH` of the data db, keyed by the old
+//! start index and the root of the tx, and dropped once restored, expired after a number of
+//! blocks, or evicted by the newly reverted txs once the journal is full.
+
+use crate::error::StoreError;
+use crate::log_store::flow_store::batch_iter;
+use crate::log_store::log_manager::{
+    bytes_to_entries, COL_REVERTED_BATCH, ENTRY_SIZE, PORA_CHUNK_SIZE,
+};
+use crate::log_store::{metrics, FlowRead};
+use crate::ZgsKeyValueDB;
+use anyhow::Result;
+use ethereum_types::H256;
+use parking_lot::Mutex;
+use shared_types::{ChunkArray, DataRoot, Transaction};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+/// Bytes of the key of a staged tx, i.e. the start index and the root.
+const STAGED_KEY_SIZE: usize = 8 + 32;
+/// Bytes of the key of a staged batch, i.e. the key of the tx and the entry offset in the tx.
+const BATCH_KEY_SIZE: usize = STAGED_KEY_SIZE + 8;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RevertJournalConfig {
+    /// Maximum number of the entries staged, or 0 to disable the journal.
+    pub max_chunks: usize,
+    /// Number of blocks after the revert, beyond which the staged data is dropped.
+    pub expiry_blocks: u64,
+}
+
+impl Default for RevertJournalConfig {
+    fn default() -> Self {
+        Self {
+            max_chunks: 0,
+            expiry_blocks: 1000,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct StagedTx {
+    /// Block of the sync progress when reverted.
+    block: u64,
+    num_chunks: u64,
+}
+
+impl StagedTx {
+    fn to_bytes(self) -> Vec<u8> {
+        let mut value = self.block.to_be_bytes().to_vec();
+        value.extend_from_slice(&self.num_chunks.to_be_bytes());
+        value
+    }
+
+    fn from_bytes(value: &[u8]) -> Result<Self> {
+        if value.len() != 16 {
+            return Err(StoreError::Corrupt {
+                reason: format!("invalid staged tx in revert journal: len={}", value.len()),
+            }
+            .into());
+        }
+        Ok(Self {
+            block: u64::from_be_bytes(value[..8].try_into().unwrap()),
+            num_chunks: u64::from_be_bytes(value[8..].try_into().unwrap()),
+        })
+    }
+}
+
+fn staged_key(start_index: u64, root: &DataRoot) -> Vec<u8> {
+    let mut key = start_index.to_be_bytes().to_vec();
+    key.extend_from_slice(root.as_bytes());
+    key
+}
+
+fn batch_key(start_index: u64, root: &DataRoot, offset: u64) -> Vec<u8> {
+    let mut key = staged_key(start_index, root);
+    key.extend_from_slice(&offset.to_be_bytes());
+    key
+}
+
+/// Key of a staged tx, i.e. the start index and the root.
+type StagedKey = (u64, DataRoot);
+
+/// Index of the staged txs in memory, ordered by the block reverted at as well, so that the
+/// oldest ones are evicted or expired without a scan.
+#[derive(Default)]
+struct StagedIndex {
+    txs: BTreeMap<StagedKey, StagedTx>,
+    by_block: BTreeSet<(u64, StagedKey)>,
+    total_chunks: u64,
+}
+
+impl StagedIndex {
+    fn insert(&mut self, key: StagedKey, tx: StagedTx) {
+        self.remove(&key);
+        self.by_block.insert((tx.block, key));
+        self.total_chunks += tx.num_chunks;
+        self.txs.insert(key, tx);
+    }
+
+    fn remove(&mut self, key: &StagedKey) -> Option<StagedTx> {
+        let tx = self.txs.remove(key)?;
+        self.by_block.remove(&(tx.block, *key));
+        self.total_chunks -= tx.num_chunks;
+        Some(tx)
+    }
+
+    /// Returns the tx reverted at the earliest block.
+    fn oldest(&self) -> Option<(u64, StagedKey)> {
+        self.by_block.first().copied()
+    }
+}
+
+/// The reverted txs staged in the data db, along with an index in memory loaded on open.
+pub(crate) struct RevertJournal {
+    data_kvdb: Arc<dyn ZgsKeyValueDB>,
+    config: RevertJournalConfig,
+    staged: Mutex<StagedIndex>,
+}
+
+impl RevertJournal {
+    pub fn new(data_kvdb: Arc<dyn ZgsKeyValueDB>, config: RevertJournalConfig) -> Result<Self> {
+        let mut staged = StagedIndex::default();
+        for r in data_kvdb.iter(COL_REVERTED_BATCH) {
+            let (key, value) = r?;
+            if key.len() != STAGED_KEY_SIZE {
+                continue;
+            }
+            let start_index = u64::from_be_bytes(key[..8].try_into().unwrap());
+            let root = H256::from_slice(&key[8..]);
+            staged.insert((start_index, root), StagedTx::from_bytes(&value)?);
+        }
+
+        let journal = Self {
+            data_kvdb,
+            config,
+            staged: Mutex::new(staged),
+        };
+        if config.max_chunks == 0 {
+            // Drop the data staged before the journal is disabled.
+            journal.expire(u64::MAX)?;
+        }
+        Ok(journal)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.max_chunks > 0
+    }
+
+    /// Stages the data of the reverted tx, which must be read before truncated from the flow,
+    /// and evicts the oldest staged txs if needed. Returns false if the tx is not staged, e.g.
+    /// larger than the journal or the data is missing.
+    pub fn stage(&self, tx: &Transaction, block: u64, flow: &impl FlowRead) -> Result<bool> {
+        let num_chunks = bytes_to_entries(tx.size);
+        if !self.is_enabled() || num_chunks == 0 || num_chunks > self.config.max_chunks as u64 {
+            return Ok(false);
+        }
+        let start = tx.start_entry_index;
+        let data = match flow.get_entries(start, start + num_chunks)? {
+            Some(data) => data.data,
+            None => return Ok(false),
+        };
+
+        let mut staged = self.staged.lock();
+        let mut db_tx = self.data_kvdb.transaction();
+        let key = (start, tx.data_merkle_root);
+        if staged.remove(&key).is_some() {
+            db_tx.delete_prefix(COL_REVERTED_BATCH, &staged_key(start, &tx.data_merkle_root));
+        }
+        while staged.total_chunks + num_chunks > self.config.max_chunks as u64 {
+            let (_, oldest) = staged.oldest().expect("journal is not empty while full");
+            staged.remove(&oldest);
+            db_tx.delete_prefix(COL_REVERTED_BATCH, &staged_key(oldest.0, &oldest.1));
+            metrics::REVERT_JOURNAL_EVICTED.inc(1);
+        }
+
+        let staged_tx = StagedTx { block, num_chunks };
+        db_tx.put(
+            COL_REVERTED_BATCH,
+            &staged_key(start, &tx.data_merkle_root),
+            &staged_tx.to_bytes(),
+        );
+        // Split by the entry batches of the flow, in which the data is restored.
+        for (batch_start, batch_end) in batch_iter(start, start + num_chunks, PORA_CHUNK_SIZE) {
+            let offset = batch_start - start;
+            db_tx.put(
+                COL_REVERTED_BATCH,
+                &batch_key(start, &tx.data_merkle_root, offset),
+                &data[offset as usize * ENTRY_SIZE..(batch_end - start) as usize * ENTRY_SIZE],
+            );
+        }
+        self.data_kvdb.write(db_tx)?;
+        staged.insert(key, staged_tx);
+        metrics::REVERT_JOURNAL_STAGED.inc(1);
+        Ok(true)
+    }
+
+    /// Returns whether a tx is staged at the start index with the root and the number of entries.
+    pub fn is_staged(&self, start_index: u64, root: &DataRoot, num_chunks: u64) -> bool {
+        matches!(
+            self.staged.lock().txs.get(&(start_index, *root)),
+            Some(tx) if tx.num_chunks == num_chunks
+        )
+    }
+
+    /// Returns the data of the tx staged at the start index with the root and the number of
+    /// entries by entry batch, in which the start index is relative to the tx. The tx is kept
+    /// staged until discarded.
+    pub fn read(
+        &self,
+        start_index: u64,
+        root: &DataRoot,
+        num_chunks: u64,
+    ) -> Result<Option<Vec<ChunkArray>>> {
+        let staged = self.staged.lock();
+        match staged.txs.get(&(start_index, *root)) {
+            Some(tx) if tx.num_chunks == num_chunks => {}
+            _ => return Ok(None),
+        }
+
+        let prefix = staged_key(start_index, root);
+        let mut chunks = Vec::new();
+        for r in self.data_kvdb.iter_with_prefix(COL_REVERTED_BATCH, &prefix) {
+            let (key, value) = r?;
+            if key.len() != BATCH_KEY_SIZE {
+                continue;
+            }
+            chunks.push(ChunkArray {
+                data: value.to_vec(),
+                start_index: u64::from_be_bytes(key[STAGED_KEY_SIZE..].try_into().unwrap()),
+            });
+        }
+        drop(staged);
+
+        let restored: u64 = chunks
+            .iter()
+            .map(|c| bytes_to_entries(c.data.len() as u64))
+            .sum();
+        if restored != num_chunks {
+            return Err(StoreError::Corrupt {
+                reason: format!(
+                    "missing batches in revert journal: start_index={} root={:?}",
+                    start_index, root
+                ),
+            }
+            .into());
+        }
+        Ok(Some(chunks))
+    }
+
+    /// Drops the txs staged more than `expiry_blocks` before the block, and returns the number
+    /// of the txs dropped.
+    pub fn expire(&self, block: u64) -> Result<usize> {
+        let mut staged = self.staged.lock();
+        let expired: Vec<StagedKey> = staged
+            .by_block
+            .iter()
+            .take_while(|(staged_block, _)| {
+                staged_block.saturating_add(self.config.expiry_blocks) < block
+            })
+            .map(|(_, key)| *key)
+            .collect();
+        let expired_len = expired.len();
+        self.drop_staged(&mut staged, expired)?;
+        metrics::REVERT_JOURNAL_EXPIRED.inc(expired_len);
+        Ok(expired_len)
+    }
+
+    /// Drops the staged txs, e.g. whose data changed after staged.
+    pub fn discard(&self, keys: Vec<StagedKey>) -> Result<()> {
+        let mut staged = self.staged.lock();
+        self.drop_staged(&mut staged, keys)
+    }
+
+    fn drop_staged(&self, staged: &mut StagedIndex, keys: Vec<StagedKey>) -> Result<()> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let mut db_tx = self.data_kvdb.transaction();
+        for (start_index, root) in &keys {
+            db_tx.delete_prefix(COL_REVERTED_BATCH, &staged_key(*start_index, root));
+        }
+        self.data_kvdb.write(db_tx)?;
+        for key in &keys {
+            staged.remove(key);
+        }
+        Ok(())
+    }
+}
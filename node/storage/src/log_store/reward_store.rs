@@ -0,0 +1,266 @@
+use crate::error::StoreError;
+use crate::log_store::log_manager::{COL_MINER_REWARD, COL_MINER_REWARD_BLOCK, COL_MISC};
+use crate::ZgsKeyValueDB;
+use anyhow::Result;
+use ethereum_types::{H256, U256};
+use parking_lot::Mutex;
+use ssz::{Decode, Encode};
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
+use std::cmp;
+use std::sync::Arc;
+
+/// Number of the low bits of epochs and block numbers grouped in a bucket, so that the rewards
+/// in a range are read by the key prefix of each bucket.
+const REWARD_BUCKET_BITS: u32 = 16;
+const REWARD_SUMMARY_KEY: &[u8] = b"miner_reward_summary";
+
+/// PoRA answer submitted by the miner, and its outcome on chain.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct MinerReward {
+    pub epoch: u64,
+    /// Identifies the submission in the epoch, which is the nonce of the answer, or the hash of
+    /// the submission tx if the answer is not submitted by the node.
+    pub id: H256,
+    /// `None` if the submission tx is not sent.
+    pub tx_hash: Option<H256>,
+    /// Block of the `NewSubmission` event, or `None` if the answer is not accepted on chain yet.
+    pub block_number: Option<u64>,
+    /// Reason why the answer is rejected, e.g. the submission tx reverted.
+    pub rejected_reason: Option<Vec<u8>>,
+    /// Rewards distributed along with the accepted answer.
+    pub amount: U256,
+}
+
+impl MinerReward {
+    pub fn is_accepted(&self) -> bool {
+        self.block_number.is_some()
+    }
+
+    pub fn is_rejected(&self) -> bool {
+        self.rejected_reason.is_some()
+    }
+
+    fn key(&self) -> Vec<u8> {
+        let mut key = self.epoch.to_be_bytes().to_vec();
+        key.extend_from_slice(self.id.as_bytes());
+        key
+    }
+}
+
+/// Totals of the miner rewards, which are updated along with the rewards.
+#[derive(Clone, Debug, Default, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct MinerRewardSummary {
+    pub submissions: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub pending: u64,
+    pub total_rewards: U256,
+    /// Maximum epoch of the rewards, which bounds the reads of a range.
+    max_epoch: u64,
+    /// Maximum block number of the accepted rewards, which bounds the reverts.
+    max_block: u64,
+}
+
+impl MinerRewardSummary {
+    fn add(&mut self, reward: &MinerReward) {
+        self.submissions += 1;
+        if reward.is_accepted() {
+            self.accepted += 1;
+            self.total_rewards += reward.amount;
+        } else if reward.is_rejected() {
+            self.rejected += 1;
+        } else {
+            self.pending += 1;
+        }
+        self.max_epoch = cmp::max(self.max_epoch, reward.epoch);
+        self.max_block = cmp::max(self.max_block, reward.block_number.unwrap_or_default());
+    }
+
+    fn remove(&mut self, reward: &MinerReward) {
+        self.submissions = self.submissions.saturating_sub(1);
+        if reward.is_accepted() {
+            self.accepted = self.accepted.saturating_sub(1);
+            self.total_rewards = self.total_rewards.saturating_sub(reward.amount);
+        } else if reward.is_rejected() {
+            self.rejected = self.rejected.saturating_sub(1);
+        } else {
+            self.pending = self.pending.saturating_sub(1);
+        }
+    }
+}
+
+/// Persists the miner rewards in the flow db, ordered by epoch, and indexed by the block
+/// number of the accepted ones, so that a range of epochs or a revert reads only the buckets
+/// involved.
+pub struct RewardStore {
+    flow_kvdb: Arc<dyn ZgsKeyValueDB>,
+    /// Serializes the updates of rewards, which read and write the summary.
+    write_lock: Mutex<()>,
+}
+
+impl RewardStore {
+    /// Builds the block index and the summary once if missing, e.g. of the rewards persisted by
+    /// an earlier version. Nothing is written if there is no reward yet.
+    pub fn new(flow_kvdb: Arc<dyn ZgsKeyValueDB>) -> Result<Self> {
+        let store = Self {
+            flow_kvdb,
+            write_lock: Mutex::new(()),
+        };
+
+        if store.flow_kvdb.get(COL_MISC, REWARD_SUMMARY_KEY)?.is_none()
+            && store.flow_kvdb.iter(COL_MINER_REWARD).next().is_some()
+        {
+            let mut summary = MinerRewardSummary::default();
+            let mut db_tx = store.flow_kvdb.transaction();
+            for r in store.flow_kvdb.iter(COL_MINER_REWARD) {
+                let (key, value) = r?;
+                let reward = MinerReward::from_ssz_bytes(&value).map_err(StoreError::from)?;
+                if let Some(block_number) = reward.block_number {
+                    db_tx.put(COL_MINER_REWARD_BLOCK, &block_key(block_number, &key), &[]);
+                }
+                summary.add(&reward);
+            }
+            db_tx.put(COL_MISC, REWARD_SUMMARY_KEY, &summary.as_ssz_bytes());
+            store.flow_kvdb.write(db_tx)?;
+        }
+
+        Ok(store)
+    }
+
+    pub fn summary(&self) -> Result<MinerRewardSummary> {
+        match self.flow_kvdb.get(COL_MISC, REWARD_SUMMARY_KEY)? {
+            Some(value) => {
+                Ok(MinerRewardSummary::from_ssz_bytes(&value).map_err(StoreError::from)?)
+            }
+            None => Ok(MinerRewardSummary::default()),
+        }
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<MinerReward>> {
+        match self.flow_kvdb.get(COL_MINER_REWARD, key)? {
+            Some(value) => Ok(Some(
+                MinerReward::from_ssz_bytes(&value).map_err(StoreError::from)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Insert the reward, or replace the one with the same epoch and id.
+    pub fn put(&self, reward: &MinerReward) -> Result<()> {
+        let _guard = self.write_lock.lock();
+        let key = reward.key();
+        let mut summary = self.summary()?;
+        let mut db_tx = self.flow_kvdb.transaction();
+
+        if let Some(old) = self.get(&key)? {
+            summary.remove(&old);
+            if let Some(block_number) = old.block_number {
+                db_tx.delete(COL_MINER_REWARD_BLOCK, &block_key(block_number, &key));
+            }
+        }
+
+        summary.add(reward);
+        if let Some(block_number) = reward.block_number {
+            db_tx.put(COL_MINER_REWARD_BLOCK, &block_key(block_number, &key), &[]);
+        }
+        db_tx.put(COL_MINER_REWARD, &key, &reward.as_ssz_bytes());
+        db_tx.put(COL_MISC, REWARD_SUMMARY_KEY, &summary.as_ssz_bytes());
+        Ok(self.flow_kvdb.write(db_tx)?)
+    }
+
+    /// Return the rewards of the epochs in `[from_epoch, to_epoch]`.
+    pub fn get_range(&self, from_epoch: u64, to_epoch: u64) -> Result<Vec<MinerReward>> {
+        let min_epoch = match self.flow_kvdb.iter(COL_MINER_REWARD).next() {
+            Some(r) => decode_u64(&r?.0)?,
+            None => return Ok(vec![]),
+        };
+        let from_epoch = cmp::max(from_epoch, min_epoch);
+        let to_epoch = cmp::min(to_epoch, self.summary()?.max_epoch);
+
+        let mut rewards = Vec::new();
+        if from_epoch > to_epoch {
+            return Ok(rewards);
+        }
+
+        // Keys are sorted by the big-endian epoch.
+        for bucket in (from_epoch >> REWARD_BUCKET_BITS)..=(to_epoch >> REWARD_BUCKET_BITS) {
+            for r in self
+                .flow_kvdb
+                .iter_with_prefix(COL_MINER_REWARD, &bucket_prefix(bucket))
+            {
+                let (_, value) = r?;
+                let reward = MinerReward::from_ssz_bytes(&value).map_err(StoreError::from)?;
+                if reward.epoch > to_epoch {
+                    break;
+                }
+                if reward.epoch >= from_epoch {
+                    rewards.push(reward);
+                }
+            }
+        }
+        Ok(rewards)
+    }
+
+    /// Mark the rewards accepted since `block_number` as not accepted, since the blocks are
+    /// reorged. Return the number of the reverted rewards.
+    pub fn revert(&self, block_number: u64) -> Result<usize> {
+        let _guard = self.write_lock.lock();
+        let mut summary = self.summary()?;
+        let mut db_tx = self.flow_kvdb.transaction();
+        let mut reverted = 0;
+
+        if block_number <= summary.max_block {
+            let buckets =
+                (block_number >> REWARD_BUCKET_BITS)..=(summary.max_block >> REWARD_BUCKET_BITS);
+            for bucket in buckets {
+                for r in self
+                    .flow_kvdb
+                    .iter_with_prefix(COL_MINER_REWARD_BLOCK, &bucket_prefix(bucket))
+                {
+                    let (index_key, _) = r?;
+                    if decode_u64(&index_key)? < block_number {
+                        continue;
+                    }
+
+                    let key = &index_key[8..];
+                    if let Some(mut reward) = self.get(key)? {
+                        summary.remove(&reward);
+                        reward.block_number = None;
+                        reward.amount = U256::zero();
+                        summary.add(&reward);
+                        db_tx.put(COL_MINER_REWARD, key, &reward.as_ssz_bytes());
+                        reverted += 1;
+                    }
+                    db_tx.delete(COL_MINER_REWARD_BLOCK, &index_key);
+                }
+            }
+        }
+
+        if reverted > 0 {
+            db_tx.put(COL_MISC, REWARD_SUMMARY_KEY, &summary.as_ssz_bytes());
+        }
+        self.flow_kvdb.write(db_tx)?;
+        Ok(reverted)
+    }
+}
+
+/// Key of the block index, which is the block number followed by the key of the reward.
+fn block_key(block_number: u64, key: &[u8]) -> Vec<u8> {
+    let mut index_key = block_number.to_be_bytes().to_vec();
+    index_key.extend_from_slice(key);
+    index_key
+}
+
+/// Key prefix shared by the epochs or block numbers in `bucket`.
+fn bucket_prefix(bucket: u64) -> Vec<u8> {
+    let key = (bucket << REWARD_BUCKET_BITS).to_be_bytes();
+    key[..(64 - REWARD_BUCKET_BITS) as usize / 8].to_vec()
+}
+
+/// Decodes the big-endian epoch or block number at the beginning of the key.
+fn decode_u64(key: &[u8]) -> Result<u64> {
+    let bytes = key
+        .get(..8)
+        .ok_or_else(|| anyhow::anyhow!("invalid reward key length {}", key.len()))?;
+    Ok(u64::from_be_bytes(bytes.try_into()?))
+}
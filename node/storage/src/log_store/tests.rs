@@ -1,18 +1,48 @@
+use crate::config::{ShardConfig, StoreNetworkId, NETWORK_ID_KEY, SHARD_CONFIG_KEY};
+use crate::error::{StoreError, StoreItem};
+use crate::handles::migrate_db_layout;
+use crate::log_store::audit::{
+    audit_finalized_txs, sample_stored_chunks, AuditReport, FinalizedAudit,
+};
+use crate::log_store::check::{
+    check_db, CheckReport, CheckStatus, CHECK_DB_COLUMNS, CHECK_FLOW_ROOT, CHECK_SHARD_CONFIG,
+    CHECK_SYNC_PROGRESS, CHECK_TX_STORE,
+};
+use crate::log_store::config::ConfigurableExt;
 use crate::log_store::log_manager::{
     data_to_merkle_leaves, sub_merkle_tree, tx_subtree_root_list_padded, LogConfig, LogManager,
-    PORA_CHUNK_SIZE,
+    COL_ENTRY_BATCH, COL_FLOW_MPT_NODES, COL_MISC, COL_NUM, COL_TX, COL_TX_COMPLETED,
+    COL_TX_DATA_ROOT_INDEX, COL_TX_FIRST_SEEN, DATA_DB_KEY, PORA_CHUNK_SIZE,
+};
+use crate::log_store::revert_journal::RevertJournalConfig;
+use crate::log_store::reward_store::MinerReward;
+use crate::log_store::tx_store::{
+    ChunkRange, FlushJournal, SubmissionContext, TransactionStore, TxStatus,
+};
+use crate::log_store::{
+    LogStoreChunkRead, LogStoreChunkWrite, LogStoreRead, LogStoreWrite, SealAnswer,
 };
-use crate::log_store::{LogStoreChunkRead, LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
+use crate::{open_kvdb, DbEngine, DbLayout, KvdbIntrospection, StoreHandles, ZgsKeyValueDB};
 use append_merkle::{Algorithm, AppendMerkleTree, MerkleTreeRead, Sha3Algorithm};
-use ethereum_types::H256;
+use ethereum_types::{H160, H256};
+use kvdb::{DBKeyValue, DBTransaction, DBValue, KeyValueDB};
 use rand::random;
-use shared_types::{compute_padded_chunk_size, ChunkArray, Transaction, CHUNK_SIZE};
+use shared_types::{
+    compute_padded_chunk_size, validate_flow_entries, ChunkArray, DataRoot, Transaction,
+    TransactionBuilder, CHUNK_SIZE,
+};
+use ssz::Encode;
 use std::cmp;
+use std::collections::HashMap;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tempfile::TempDir;
+use zgs_spec::{SEALS_PER_LOAD, SECTORS_PER_SEAL};
 
-#[test]
-fn test_put_get() {
+fn test_put_get(db: &TestDb) {
     let config = LogConfig::default();
-    let store = LogManager::memorydb(config.clone()).unwrap();
+    let store = db.create_store();
     let chunk_count = config.flow.batch_size + config.flow.batch_size / 2 - 1;
     // Aligned with size.
     let start_offset = 1024;
@@ -29,16 +59,14 @@ fn test_put_get() {
     merkle.append_list(data_to_merkle_leaves(&data_padded).unwrap());
     merkle.commit(Some(0));
     let tx_merkle = sub_merkle_tree(&data).unwrap();
-    let tx = Transaction {
-        stream_ids: vec![],
-        size: data_size as u64,
-        data_merkle_root: tx_merkle.root().into(),
-        seq: 0,
-        data: vec![],
-        start_entry_index: start_offset as u64,
+    let tx = TransactionBuilder::new(0)
+        .size(data_size as u64)
+        .data_merkle_root(tx_merkle.root().into())
+        .start_entry_index(start_offset as u64)
         // TODO: This can come from `tx_merkle`.
-        merkle_nodes: tx_subtree_root_list_padded(&data),
-    };
+        .merkle_nodes(tx_subtree_root_list_padded(&data))
+        .build()
+        .unwrap();
     store.put_tx(tx.clone()).unwrap();
     for start_index in (0..chunk_count).step_by(PORA_CHUNK_SIZE) {
         let end = cmp::min((start_index + PORA_CHUNK_SIZE) * CHUNK_SIZE, data.len());
@@ -129,16 +157,198 @@ fn test_root() {
 }
 
 #[test]
-fn test_multi_tx() {
-    let mut store = create_store();
+fn test_put_tx_same_data_root() {
+    let store = TransactionStore::new(StoreHandles::memorydb(DbLayout::Split)).unwrap();
+    let data_root = DataRoot::from_low_u64_be(1);
+    let new_tx = |seq| {
+        TransactionBuilder::new(seq)
+            .size(256)
+            .data_merkle_root(data_root)
+            .start_entry_index(seq * 256)
+            .build_unchecked()
+    };
+
+    assert_eq!(store.put_tx_light(new_tx(0)).unwrap(), 0);
+    assert_eq!(store.put_tx(new_tx(1)).unwrap(), vec![0]);
+    assert_eq!(store.put_tx_light(new_tx(2)).unwrap(), 2);
+    // The last tx is inserted again.
+    assert_eq!(store.put_tx_light(new_tx(2)).unwrap(), 3);
+    assert_eq!(store.put_tx(new_tx(2)).unwrap(), vec![0, 1, 2]);
+    assert!(store.put_tx(new_tx(1)).is_err());
+
+    assert_eq!(
+        store.get_tx_seq_list_by_data_root(&data_root).unwrap(),
+        vec![0, 1, 2]
+    );
+    assert_eq!(
+        store.get_first_tx_seq_by_data_root(&data_root).unwrap(),
+        Some(0)
+    );
+    assert_eq!(
+        store
+            .get_first_tx_seq_by_data_root(&DataRoot::from_low_u64_be(2))
+            .unwrap(),
+        None
+    );
+    assert_eq!(store.next_tx_seq(), 3);
+}
+
+#[test]
+fn test_rebuild_last_chunk_merkle_with_oversized_subtree() {
+    let store = TransactionStore::new(StoreHandles::memorydb(DbLayout::Split)).unwrap();
+    let chunk_size = PORA_CHUNK_SIZE as u64;
+    // The first subtree spans the chunks in [4, 8), followed by a half chunk.
+    let half_chunk_root = DataRoot::from_low_u64_be(1);
+    store
+        .put_tx(
+            TransactionBuilder::new(0)
+                .size((4 * chunk_size + chunk_size / 2) * CHUNK_SIZE as u64)
+                .data_merkle_root(DataRoot::from_low_u64_be(2))
+                .start_entry_index(4 * chunk_size)
+                .merkle_nodes(vec![
+                    (13, DataRoot::from_low_u64_be(3)),
+                    (10, half_chunk_root),
+                ])
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    let batch_root = |batch_index: u64| -> anyhow::Result<Option<DataRoot>> {
+        Ok(Some(DataRoot::from_low_u64_be(100 + batch_index)))
+    };
+
+    // The chunks within the subtree are rebuilt from the batch roots.
+    for chunk_index in 4..8 {
+        let merkle = store
+            .rebuild_last_chunk_merkle(chunk_index, 0, batch_root)
+            .unwrap();
+        assert_eq!(merkle.leaves(), PORA_CHUNK_SIZE);
+        assert_eq!(
+            merkle.root(),
+            batch_root(chunk_index as u64).unwrap().unwrap()
+        );
+    }
+    let err = store
+        .rebuild_last_chunk_merkle(5, 0, |_| Ok(None))
+        .unwrap_err();
+    assert!(err.to_string().contains("rebuild the flow from data"));
+
+    // The chunk after the subtree only contains the half chunk.
+    let merkle = store
+        .rebuild_last_chunk_merkle(8, 0, |_| panic!("batch root not needed"))
+        .unwrap();
+    let mut expected = AppendMerkleTree::<H256, Sha3Algorithm>::new_with_depth(vec![], 11, None);
+    expected.append_subtree(10, half_chunk_root).unwrap();
+    assert_eq!(merkle.leaves(), PORA_CHUNK_SIZE / 2);
+    assert_eq!(merkle.root(), expected.root());
+}
+
+#[test]
+fn test_put_tx_data_root_mismatch() {
+    let store = TransactionStore::new(StoreHandles::memorydb(DbLayout::Split)).unwrap();
+    let new_tx = |root| {
+        TransactionBuilder::new(0)
+            .size(256)
+            .data_merkle_root(DataRoot::from_low_u64_be(root))
+            .build_unchecked()
+    };
+
+    store.put_tx(new_tx(1)).unwrap();
+    store.put_tx(new_tx(1)).unwrap();
+    // Never overwritten by another data root.
+    let err = store.put_tx(new_tx(2)).unwrap_err();
+    assert!(matches!(
+        StoreError::of(&err),
+        Some(StoreError::Corrupt { .. })
+    ));
+    assert_eq!(
+        store
+            .get_tx_by_seq_number(0)
+            .unwrap()
+            .unwrap()
+            .data_merkle_root,
+        DataRoot::from_low_u64_be(1)
+    );
+    assert!(store
+        .get_tx_seq_list_by_data_root(&DataRoot::from_low_u64_be(2))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_put_tx_inline_data() {
+    let store = LogManager::memorydb(LogConfig {
+        max_tx_inline_data_size: 2 * CHUNK_SIZE,
+        ..Default::default()
+    })
+    .unwrap();
+    let new_tx = |seq, data: Vec<u8>| {
+        let mut padded = data.clone();
+        padded.resize(data.len().div_ceil(CHUNK_SIZE) * CHUNK_SIZE, 0);
+        let merkle_nodes = tx_subtree_root_list_padded(&padded);
+        let flow_len = store.get_context().unwrap().1;
+        let first_subtree_size = 1 << (merkle_nodes.first().unwrap().0 - 1);
+        TransactionBuilder::new(seq)
+            .data(data)
+            .start_entry_index(((flow_len - 1) / first_subtree_size + 1) * first_subtree_size)
+            .merkle_nodes(merkle_nodes)
+            .build_unchecked()
+    };
+
+    // The size and data root are recomputed from the inline data.
+    let data = vec![7u8; CHUNK_SIZE + 1];
+    store.put_tx(new_tx(0, data.clone())).unwrap();
+    let mut padded = data.clone();
+    padded.resize(2 * CHUNK_SIZE, 0);
+    let tx = store.get_tx_by_seq_number(0).unwrap().unwrap();
+    assert_eq!(tx.size, data.len() as u64);
+    assert_eq!(
+        tx.data_merkle_root,
+        DataRoot::from(sub_merkle_tree(&padded).unwrap().root())
+    );
+    assert_eq!(
+        store.get_tx_seq_by_data_root(&tx.data_merkle_root).unwrap(),
+        Some(0)
+    );
+
+    // The last tx is put again with other data of the same size.
+    let err = store
+        .put_tx(
+            TransactionBuilder::from(tx.clone())
+                .data(vec![8u8; data.len()])
+                .build()
+                .unwrap(),
+        )
+        .unwrap_err();
+    assert!(matches!(
+        StoreError::of(&err),
+        Some(StoreError::Corrupt { .. })
+    ));
+    assert_eq!(store.get_tx_by_seq_number(0).unwrap().unwrap(), tx);
+
+    // Too large inline data is dropped, and the tx is stored as invalid.
+    store
+        .put_tx(new_tx(1, vec![9u8; 2 * CHUNK_SIZE + 1]))
+        .unwrap();
+    assert_eq!(store.next_tx_seq(), 2);
+    assert!(store.check_tx_invalid(1).unwrap());
+    let tx = store.get_tx_by_seq_number(1).unwrap().unwrap();
+    assert!(tx.data.is_empty());
+    assert_eq!(tx.size, 2 * CHUNK_SIZE as u64 + 1);
+    store.put_tx(new_tx(2, vec![9u8; 2 * CHUNK_SIZE])).unwrap();
+    assert_eq!(store.next_tx_seq(), 3);
+    assert!(!store.check_tx_invalid(2).unwrap());
+}
+
+fn test_multi_tx(db: &TestDb) {
+    let mut store = db.create_store();
     put_tx(&mut store, 3, 0);
     put_tx(&mut store, 3, 1);
     put_tx(&mut store, 5, 2);
 }
 
-#[test]
-fn test_revert() {
-    let mut store = create_store();
+fn test_revert(db: &TestDb) {
+    let mut store = db.create_store();
     put_tx(&mut store, 1, 0);
     store.revert_to(0u64.wrapping_sub(1)).unwrap();
     put_tx(&mut store, 1, 0);
@@ -158,49 +368,2057 @@ fn test_revert() {
     put_tx(&mut store, 1, 2);
 }
 
-#[test]
-fn test_put_tx() {
+fn test_revert_history(db: &TestDb) {
+    let mut store = db.create_store();
+    put_tx(&mut store, 1, 0);
+    put_tx(&mut store, 1024 + 1, 1);
+    put_tx(&mut store, 1, 2);
+    assert_eq!(store.get_flow_version(), 0);
+    assert_eq!(store.revert_to(0).unwrap().len(), 2);
+    assert_eq!(store.revert_to(0u64.wrapping_sub(1)).unwrap().len(), 1);
+    // Bumped before and after every revert.
+    assert_eq!(store.get_flow_version(), 4);
+    // And every prune.
+    store.remove_chunks_batch(&[0]).unwrap();
+    assert_eq!(store.get_flow_version(), 6);
+
+    let history = store.get_revert_history();
+    assert_eq!(history.len(), 2);
+    assert_eq!((history[0].min_seq, history[0].max_seq), (1, 3));
+    assert_eq!(history[0].depth(), 2);
+    assert_eq!(history[0].num_txs, 2);
+    assert!(history[0].truncated_batches > 0);
+    assert_eq!((history[1].min_seq, history[1].max_seq), (0, 1));
+    assert_eq!(history[1].num_txs, 1);
+    assert!(history[0].timestamp <= history[1].timestamp);
+}
+
+fn test_revert_journal(db: &TestDb) {
+    let (flow_db, data_db) = (db.create_db(COL_NUM), db.create_db(COL_NUM));
+    let open = || {
+        let config = LogConfig {
+            revert_journal: RevertJournalConfig {
+                max_chunks: 2 * PORA_CHUNK_SIZE,
+                expiry_blocks: 10,
+            },
+            ..Default::default()
+        };
+        LogManager::with_dbs(
+            StoreHandles::split(flow_db.clone(), data_db.clone()),
+            config,
+        )
+        .unwrap()
+    };
+    let completed = |store: &LogManager, seq: u64| store.check_tx_completed(seq).unwrap();
+    let mut store = open();
+    put_tx(&mut store, 1, 0);
+    put_tx(&mut store, PORA_CHUNK_SIZE + 10, 1);
+    put_tx(&mut store, 5, 2);
+    store
+        .put_sync_progress((100, H256::from_low_u64_be(100), None))
+        .unwrap();
+    store.revert_to(0).unwrap();
+
+    // restored once put at the same position with the same root
+    let restore = |store: &LogManager, tx: &Transaction| {
+        store.restore_reverted_tx(tx.seq, tx.hash()).unwrap()
+    };
+    let (tx, data) = put_tx_without_data(&mut store, PORA_CHUNK_SIZE + 10, 1);
+    assert!(!completed(&store, 1));
+    assert!(store.is_reverted_tx_staged(&tx));
+    // not restored once replaced by another tx meanwhile
+    assert!(!store.restore_reverted_tx(1, H256::zero()).unwrap());
+    assert!(!completed(&store, 1));
+    assert!(restore(&store, &tx));
+    assert!(completed(&store, 1));
+    assert!(!store.is_reverted_tx_staged(&tx));
+    let chunks = store
+        .get_chunks_by_tx_and_index_range(1, 0, PORA_CHUNK_SIZE + 10)
+        .unwrap()
+        .unwrap();
+    assert_eq!(chunks.data, data);
+    let (tx, _) = put_tx_without_data(&mut store, 6, 2);
+    assert!(!store.is_reverted_tx_staged(&tx));
+    assert!(!restore(&store, &tx));
+    assert!(!completed(&store, 2));
+
+    // staged txs are persisted, while the unfinalized tx is not staged
+    drop(store);
+    let mut store = open();
+    store.revert_to(1).unwrap();
+    let (tx, _) = put_tx_without_data(&mut store, 5, 2);
+    assert!(restore(&store, &tx));
+    assert!(completed(&store, 2));
+
+    // evicted for the newly reverted tx once full
+    put_tx(&mut store, 2 * PORA_CHUNK_SIZE, 3);
+    store.revert_to(1).unwrap();
+    let (tx, _) = put_tx_without_data(&mut store, 5, 2);
+    assert!(!restore(&store, &tx));
+    let (tx, _) = put_tx_without_data(&mut store, 2 * PORA_CHUNK_SIZE, 3);
+    assert!(restore(&store, &tx));
+    assert!(completed(&store, 3));
+
+    // expired once the sync progress is beyond the expiry blocks
+    store.revert_to(1).unwrap();
+    store
+        .put_sync_progress((111, H256::from_low_u64_be(111), None))
+        .unwrap();
+    let (tx, _) = put_tx_without_data(&mut store, 5, 2);
+    assert!(!restore(&store, &tx));
+    let (tx, _) = put_tx_without_data(&mut store, 2 * PORA_CHUNK_SIZE, 3);
+    assert!(!restore(&store, &tx));
+    assert!(!completed(&store, 3));
+}
+
+fn test_submission_context(db: &TestDb) {
+    let mut store = db.create_store();
+    let context = |tx_seq: u64| SubmissionContext {
+        block_number: tx_seq + 10,
+        tx_hash: H256::from_low_u64_be(tx_seq + 100),
+    };
+    for tx_seq in 0..3 {
+        put_tx(&mut store, 1, tx_seq);
+        store
+            .put_submission_context(tx_seq, context(tx_seq))
+            .unwrap();
+    }
+    for tx_seq in 0..3 {
+        assert_eq!(
+            store.get_submission_context(tx_seq).unwrap(),
+            Some(context(tx_seq))
+        );
+    }
+
+    store.revert_to(0).unwrap();
+    assert_eq!(store.get_submission_context(0).unwrap(), Some(context(0)));
+    assert_eq!(store.get_submission_context(1).unwrap(), None);
+    assert_eq!(store.get_submission_context(2).unwrap(), None);
+
+    // Txs synced again without context.
+    put_tx(&mut store, 1, 1);
+    assert_eq!(store.get_submission_context(1).unwrap(), None);
+}
+
+fn test_data_root_first_seen(db: &TestDb) {
+    let flow_db = db.create_db(COL_NUM);
+    let mut store = LogManager::with_dbs(
+        StoreHandles::split(flow_db.clone(), db.create_db(COL_NUM)),
+        LogConfig::default(),
+    )
+    .unwrap();
+    let (tx, _) = put_tx_without_data(&mut store, 1, 0);
+    let root = tx.data_merkle_root;
+    assert!(store.get_data_root_first_seen(&root).unwrap().is_some());
+
+    // The root is first seen long ago.
+    let mut db_tx = flow_db.transaction();
+    db_tx.put(COL_TX_FIRST_SEEN, root.as_bytes(), &1u64.to_be_bytes());
+    flow_db.write(db_tx).unwrap();
+
+    // Never reset by re-submissions.
+    let start_entry_index = store.get_context().unwrap().1;
+    store
+        .put_tx(
+            TransactionBuilder::from(tx.clone())
+                .seq(1)
+                .start_entry_index(start_entry_index)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    let (other, _) = put_tx_without_data(&mut store, 1, 2);
+    assert_eq!(store.get_data_root_first_seen(&root).unwrap(), Some(1));
+    assert!(store
+        .get_data_root_first_seen(&other.data_merkle_root)
+        .unwrap()
+        .is_some());
+
+    // Kept until all txs of the root reverted.
+    store.revert_to(0).unwrap();
+    assert_eq!(store.get_data_root_first_seen(&root).unwrap(), Some(1));
+    assert_eq!(
+        store
+            .get_data_root_first_seen(&other.data_merkle_root)
+            .unwrap(),
+        None
+    );
+    store.revert_to(u64::MAX).unwrap();
+    assert_eq!(store.get_data_root_first_seen(&root).unwrap(), None);
+
+    // Seen again once submitted after reverted.
+    store.put_tx(tx).unwrap();
+    assert!(store.get_data_root_first_seen(&root).unwrap().unwrap() > 1);
+}
+
+fn test_finalize_tx_in_shard(db: &TestDb) {
+    let mut store = db.create_store();
+    store.update_shard_config(ShardConfig::new(1, 4).unwrap());
+
+    // The tx is in the first entry batch, which is out of the shard.
+    put_tx(&mut store, 1, 0);
+    // The tx covers the entry batches of all shards.
+    put_tx(&mut store, 4 * PORA_CHUNK_SIZE, 1);
+    for seq in 0..2 {
+        assert_eq!(
+            store.get_tx_status(seq).unwrap(),
+            Some(TxStatus::ShardFinalized)
+        );
+        assert!(store.check_tx_completed(seq).unwrap());
+    }
+
+    let mut store = db.create_store();
+    put_tx(&mut store, 4 * PORA_CHUNK_SIZE, 0);
+    assert_eq!(store.get_tx_status(0).unwrap(), Some(TxStatus::Finalized));
+}
+
+fn test_miner_rewards(db: &TestDb) {
+    let store = db.create_store();
+    let reward = |epoch: u64, id: u64, block_number: Option<u64>| MinerReward {
+        epoch,
+        id: H256::from_low_u64_be(id),
+        tx_hash: Some(H256::from_low_u64_be(id + 100)),
+        block_number,
+        rejected_reason: None,
+        amount: if block_number.is_some() {
+            10.into()
+        } else {
+            0.into()
+        },
+    };
+    store.put_miner_reward(reward(300, 1, Some(20))).unwrap();
+    store.put_miner_reward(reward(2, 2, Some(10))).unwrap();
+    store.put_miner_reward(reward(2, 1, None)).unwrap();
+    store.put_miner_reward(reward(5, 3, Some(15))).unwrap();
+    // Replaced.
+    store.put_miner_reward(reward(5, 3, Some(12))).unwrap();
+
+    let rewards = store.get_miner_rewards(0, u64::MAX).unwrap();
+    assert_eq!(
+        rewards,
+        vec![
+            reward(2, 1, None),
+            reward(2, 2, Some(10)),
+            reward(5, 3, Some(12)),
+            reward(300, 1, Some(20))
+        ]
+    );
+    assert_eq!(store.get_miner_rewards(3, 300).unwrap(), rewards[2..]);
+    assert!(store.get_miner_rewards(6, 299).unwrap().is_empty());
+
+    let summary = store.get_miner_reward_summary().unwrap();
+    assert_eq!(
+        (summary.submissions, summary.accepted, summary.pending),
+        (4, 3, 1)
+    );
+    assert_eq!(summary.total_rewards, 30.into());
+
+    // Across multiple buckets of epochs and blocks.
+    store
+        .put_miner_reward(reward((1 << 20) + 3, 4, Some((1 << 20) + 5)))
+        .unwrap();
+    assert_eq!(store.get_miner_rewards(6, u64::MAX).unwrap().len(), 2);
+    assert_eq!(
+        store.get_miner_rewards(1 << 20, u64::MAX).unwrap(),
+        vec![reward((1 << 20) + 3, 4, Some((1 << 20) + 5))]
+    );
+
+    assert_eq!(store.revert_miner_rewards(11).unwrap(), 3);
+    let rewards = store.get_miner_rewards(0, u64::MAX).unwrap();
+    assert_eq!(rewards[1], reward(2, 2, Some(10)));
+    assert_eq!(rewards[2], reward(5, 3, None));
+    assert_eq!(rewards[3], reward(300, 1, None));
+    assert_eq!(rewards[4], reward((1 << 20) + 3, 4, None));
+    assert_eq!(store.revert_miner_rewards(11).unwrap(), 0);
+
+    let summary = store.get_miner_reward_summary().unwrap();
+    assert_eq!(
+        (summary.submissions, summary.accepted, summary.pending),
+        (5, 1, 4)
+    );
+    assert_eq!(summary.total_rewards, 10.into());
+}
+
+fn test_iter_block_hashes_rev(db: &TestDb) {
+    let store = db.create_store();
+    // Across multiple buckets of blocks.
+    let blocks = [5, 70_000, 70_001, 200_000, (1 << 20) + 3];
+    for (index, block_number) in blocks.iter().enumerate() {
+        let block_hash = H256::from_low_u64_be(*block_number);
+        store
+            .put_sync_progress((*block_number, block_hash, Some(Some(index as u64))))
+            .unwrap();
+    }
+    let iter_rev = |from_block: u64| -> Vec<u64> {
+        store
+            .iter_block_hashes_rev(from_block)
+            .map(|r| {
+                let (block_number, block) = r.unwrap();
+                assert_eq!(block.block_hash, H256::from_low_u64_be(block_number));
+                block_number
+            })
+            .collect()
+    };
+
+    assert_eq!(
+        iter_rev(u64::MAX),
+        vec![(1 << 20) + 3, 200_000, 70_001, 70_000, 5]
+    );
+    assert_eq!(iter_rev(150_000), vec![70_001, 70_000, 5]);
+    assert_eq!(iter_rev(70_000), vec![70_000, 5]);
+    assert!(iter_rev(4).is_empty());
+
+    let get_from = |from_block: u64, limit: usize| -> Vec<u64> {
+        store
+            .get_block_hashes_from(from_block, limit)
+            .unwrap()
+            .into_iter()
+            .map(|(block_number, block)| {
+                assert_eq!(block.block_hash, H256::from_low_u64_be(block_number));
+                block_number
+            })
+            .collect()
+    };
+    assert_eq!(get_from(0, usize::MAX), blocks);
+    assert_eq!(get_from(6, 2), vec![70_000, 70_001]);
+    assert_eq!(get_from(70_001, 3), vec![70_001, 200_000, (1 << 20) + 3]);
+    assert!(get_from((1 << 20) + 4, 10).is_empty());
+    assert!(get_from(0, 0).is_empty());
+
+    store.delete_block_hashes_before(70_001).unwrap();
+    assert_eq!(iter_rev(u64::MAX), vec![(1 << 20) + 3, 200_000, 70_001]);
+    assert_eq!(store.get_block_hashes().unwrap().len(), 3);
+}
+
+fn test_put_tx(db: &TestDb) {
     for i in 0..12 {
         let chunk_count = 0xF << i;
-        let mut store = create_store();
+        let mut store = db.create_store();
         put_tx(&mut store, chunk_count, 0);
     }
 }
 
-fn create_store() -> LogManager {
-    let config = LogConfig::default();
-    LogManager::memorydb(config).unwrap()
+fn test_has_chunks_by_tx_and_index_range(db: &TestDb) {
+    let mut store = db.create_store();
+    let chunk_count = 2 * PORA_CHUNK_SIZE;
+    let (tx, data) = put_tx_without_data(&mut store, chunk_count, 0);
+    assert!(!store.has_chunks_by_tx_and_index_range(0, 0, 1).unwrap());
+
+    // only the first segment is downloaded
+    store
+        .put_chunks(
+            tx.seq,
+            ChunkArray {
+                data: data[..PORA_CHUNK_SIZE * CHUNK_SIZE].to_vec(),
+                start_index: 0,
+            },
+        )
+        .unwrap();
+    assert!(!store.check_tx_completed(tx.seq).unwrap());
+    assert!(store
+        .has_chunks_by_tx_and_index_range(tx.seq, 0, PORA_CHUNK_SIZE)
+        .unwrap());
+    assert!(store
+        .has_chunks_by_tx_and_index_range(tx.seq, 1, PORA_CHUNK_SIZE / 2)
+        .unwrap());
+    assert!(!store
+        .has_chunks_by_tx_and_index_range(tx.seq, 0, PORA_CHUNK_SIZE + 1)
+        .unwrap());
+    assert!(!store
+        .has_chunks_by_tx_and_index_range(tx.seq, PORA_CHUNK_SIZE, chunk_count)
+        .unwrap());
+
+    // out of range or tx not found
+    assert!(store
+        .has_chunks_by_tx_and_index_range(tx.seq, 0, chunk_count + 1)
+        .is_err());
+    assert!(!store.has_chunks_by_tx_and_index_range(1, 0, 1).unwrap());
 }
 
-fn put_tx(store: &mut LogManager, chunk_count: usize, seq: u64) {
-    let data_size = CHUNK_SIZE * chunk_count;
-    let mut data = vec![0u8; data_size];
-    for i in 0..chunk_count {
-        data[i * CHUNK_SIZE..(i * CHUNK_SIZE + 8)].copy_from_slice(&(seq + 1).to_be_bytes());
-    }
-    let tx_merkle = sub_merkle_tree(&data).unwrap();
-    let merkle_nodes = tx_subtree_root_list_padded(&data);
-    let flow_len = store.get_context().unwrap().1;
-    let first_subtree_size = 1 << (merkle_nodes.first().unwrap().0 - 1);
-    let start_entry_index = ((flow_len - 1) / first_subtree_size + 1) * first_subtree_size;
-    let tx = Transaction {
-        stream_ids: vec![],
-        size: data_size as u64,
-        data_merkle_root: tx_merkle.root().into(),
-        seq,
-        data: vec![],
-        start_entry_index,
-        // TODO: This can come from `tx_merkle`.
-        merkle_nodes,
+fn test_put_tx_overlapped(db: &TestDb) {
+    let mut store = db.create_store();
+    let (tx, _) = put_tx_without_data(&mut store, 3, 0);
+    let context = store.get_context().unwrap();
+
+    // The previous tx takes 3 entries, which is more than the gap to the next tx.
+    let data = vec![1u8; CHUNK_SIZE];
+    let overlapped = TransactionBuilder::new(1)
+        .size(data.len() as u64)
+        .data_merkle_root(sub_merkle_tree(&data).unwrap().root().into())
+        .start_entry_index(tx.start_entry_index + 2)
+        .merkle_nodes(tx_subtree_root_list_padded(&data))
+        .build()
+        .unwrap();
+    let e = store.put_tx(overlapped.clone()).unwrap_err();
+    assert!(e.to_string().contains("overlaps the previous tx"), "{}", e);
+    assert_eq!(store.next_tx_seq(), 1);
+    assert_eq!(store.get_context().unwrap(), context);
+
+    // Accepted right after the previous tx.
+    store
+        .put_tx(
+            TransactionBuilder::from(overlapped)
+                .start_entry_index(tx.start_entry_index + 3)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(store.next_tx_seq(), 2);
+}
+
+fn test_put_tx_inconsistent_size(db: &TestDb) {
+    let store = db.create_store();
+    let data = vec![1u8; CHUNK_SIZE];
+    let tx = TransactionBuilder::new(0)
+        .size(data.len() as u64)
+        .data_merkle_root(sub_merkle_tree(&data).unwrap().root().into())
+        .start_entry_index(1)
+        .merkle_nodes(tx_subtree_root_list_padded(&data))
+        .build()
+        .unwrap();
+
+    // The size implies 3 entries, while the merkle nodes cover only 1.
+    let e = store
+        .put_tx(
+            TransactionBuilder::from(tx.clone())
+                .size(3 * CHUNK_SIZE as u64)
+                .build_unchecked(),
+        )
+        .unwrap_err();
+    assert!(
+        e.to_string().contains("inconsistent with merkle nodes"),
+        "{}",
+        e
+    );
+    let e = store
+        .put_tx(
+            TransactionBuilder::from(tx.clone())
+                .merkle_nodes(vec![])
+                .build_unchecked(),
+        )
+        .unwrap_err();
+    assert!(
+        e.to_string().contains("inconsistent with merkle nodes"),
+        "{}",
+        e
+    );
+
+    // The subtree of 2 entries cannot start at an odd index.
+    let data = vec![1u8; 2 * CHUNK_SIZE];
+    let e = store
+        .put_tx(
+            TransactionBuilder::from(tx.clone())
+                .size(data.len() as u64)
+                .merkle_nodes(tx_subtree_root_list_padded(&data))
+                .build_unchecked(),
+        )
+        .unwrap_err();
+    assert!(e.to_string().contains("not aligned"), "{}", e);
+
+    assert_eq!(store.next_tx_seq(), 0);
+    assert!(store.get_tx_by_seq_number(0).unwrap().is_none());
+    store.put_tx(tx).unwrap();
+}
+
+fn test_get_txs_by_data_roots(db: &TestDb) {
+    let mut store = db.create_store();
+    put_tx(&mut store, 3, 0);
+    put_tx(&mut store, 3, 1);
+    store.prune_tx(1).unwrap();
+
+    let tx0 = store.get_tx_by_seq_number(0).unwrap().unwrap();
+    let tx1 = store.get_tx_by_seq_number(1).unwrap().unwrap();
+    let unknown = DataRoot::from_low_u64_be(1);
+    let txs = store
+        .get_txs_by_data_roots(&[tx1.data_merkle_root, unknown, tx0.data_merkle_root])
+        .unwrap();
+    assert_eq!(txs, vec![Some(tx1), None, Some(tx0)]);
+    assert!(store.check_tx_pruned(1).unwrap());
+    assert!(store.check_tx_completed(0).unwrap());
+    assert!(store.get_txs_by_data_roots(&[]).unwrap().is_empty());
+}
+
+fn test_compact_pruned_data_roots(db: &TestDb) {
+    let flow_db = db.create_db(COL_NUM);
+    let mut store = LogManager::with_dbs(
+        StoreHandles::split(flow_db.clone(), db.create_db(COL_NUM)),
+        LogConfig::default(),
+    )
+    .unwrap();
+    put_tx(&mut store, 1, 0);
+    let tx = store.get_tx_by_seq_number(0).unwrap().unwrap();
+    let root = tx.data_merkle_root;
+    let resubmit = |store: &mut LogManager, seq| {
+        let start_entry_index = store.get_context().unwrap().1;
+        store
+            .put_tx(
+                TransactionBuilder::from(tx.clone())
+                    .seq(seq)
+                    .start_entry_index(start_entry_index)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
     };
-    store.put_tx(tx.clone()).unwrap();
-    for start_index in (0..chunk_count).step_by(PORA_CHUNK_SIZE) {
-        let end = cmp::min((start_index + PORA_CHUNK_SIZE) * CHUNK_SIZE, data.len());
-        let chunk_array = ChunkArray {
-            data: data[start_index * CHUNK_SIZE..end].to_vec(),
-            start_index: start_index as u64,
-        };
-        store.put_chunks(tx.seq, chunk_array.clone()).unwrap();
+    resubmit(&mut store, 1);
+    assert!(store.check_tx_completed(1).unwrap());
+
+    // The pruned tx is never selected while others are not pruned.
+    store.prune_tx(0).unwrap();
+    assert_eq!(store.get_tx_seq_by_data_root(&root).unwrap(), Some(1));
+    assert_eq!(
+        store.get_tx_seq_list_by_data_root(&root).unwrap(),
+        vec![0, 1]
+    );
+
+    // Copied from the first tx not pruned on re-submission.
+    resubmit(&mut store, 2);
+    assert!(store.check_tx_completed(2).unwrap());
+    store.prune_tx(2).unwrap();
+
+    // Only the last seq is kept once all pruned, and the root is still reported pruned.
+    store.prune_tx(1).unwrap();
+    assert_eq!(store.get_tx_seq_list_by_data_root(&root).unwrap(), vec![2]);
+    let pruned = store.get_tx_by_data_root(&root).unwrap().unwrap();
+    assert_eq!(pruned.seq, 2);
+    assert!(store.check_tx_pruned(pruned.seq).unwrap());
+
+    // Not copied from the pruned tx on re-submission, but selected once submitted.
+    resubmit(&mut store, 3);
+    assert!(!store.check_tx_completed(3).unwrap());
+    assert_eq!(store.get_tx_seq_by_data_root(&root).unwrap(), Some(3));
+    assert_eq!(
+        store.get_tx_seq_list_by_data_root(&root).unwrap(),
+        vec![2, 3]
+    );
+
+    // The lists of the roots pruned before compacted on prune.
+    store.prune_tx(3).unwrap();
+    let set_seq_list = |seq_list: Vec<u64>| {
+        let mut db_tx = flow_db.transaction();
+        db_tx.delete_prefix(COL_TX_DATA_ROOT_INDEX, root.as_bytes());
+        db_tx.put(
+            COL_TX_DATA_ROOT_INDEX,
+            root.as_bytes(),
+            &seq_list.as_ssz_bytes(),
+        );
+        flow_db.write(db_tx).unwrap();
+    };
+    set_seq_list(vec![0, 1, 2, 3]);
+    assert_eq!(store.compact_pruned_data_roots().unwrap(), 1);
+    assert_eq!(store.get_tx_seq_list_by_data_root(&root).unwrap(), vec![3]);
+
+    // Never scanned again once completed.
+    set_seq_list(vec![0, 1, 2, 3]);
+    assert_eq!(store.compact_pruned_data_roots().unwrap(), 0);
+    assert_eq!(
+        store.get_tx_seq_list_by_data_root(&root).unwrap(),
+        vec![0, 1, 2, 3]
+    );
+}
+
+fn test_data_root_index_append(db: &TestDb) {
+    let (flow_db, data_db) = (db.create_db(COL_NUM), db.create_db(COL_NUM));
+    let handles = StoreHandles::split(flow_db.clone(), data_db);
+    let mut store = LogManager::with_dbs(handles.clone(), LogConfig::default()).unwrap();
+    put_tx(&mut store, 1, 0);
+    let tx = store.get_tx_by_seq_number(0).unwrap().unwrap();
+    let root = tx.data_merkle_root;
+    let resubmit = |store: &mut LogManager, seq| {
+        let start_entry_index = store.get_context().unwrap().1;
+        store
+            .put_tx(
+                TransactionBuilder::from(tx.clone())
+                    .seq(seq)
+                    .start_entry_index(start_entry_index)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+    };
+
+    // The list stored as a whole by an old version is appended.
+    let mut db_tx = flow_db.transaction();
+    db_tx.delete_prefix(COL_TX_DATA_ROOT_INDEX, root.as_bytes());
+    db_tx.put(
+        COL_TX_DATA_ROOT_INDEX,
+        root.as_bytes(),
+        &vec![0u64].as_ssz_bytes(),
+    );
+    flow_db.write(db_tx).unwrap();
+    let tx_store = TransactionStore::new(handles.clone()).unwrap();
+    let unknown = DataRoot::from_low_u64_be(1);
+    assert_eq!(
+        tx_store
+            .get_tx_seq_lists_by_data_roots(&[root, unknown])
+            .unwrap(),
+        vec![vec![0], vec![]]
+    );
+    for seq in 1..4 {
+        resubmit(&mut store, seq);
     }
-    store.finalize_tx(tx.seq).unwrap();
+    assert_eq!(
+        store.get_tx_seq_list_by_data_root(&root).unwrap(),
+        vec![0, 1, 2, 3]
+    );
+    let tx_store = TransactionStore::new(handles.clone()).unwrap();
+    assert_eq!(
+        tx_store.get_first_tx_seq_by_data_root(&root).unwrap(),
+        Some(0)
+    );
+    assert_eq!(
+        tx_store
+            .get_tx_seq_lists_by_data_roots(&[unknown, root])
+            .unwrap(),
+        vec![vec![], vec![0, 1, 2, 3]]
+    );
+    assert!(store.check_tx_completed(3).unwrap());
+    // Only the appended seq and the tail are written rather than the whole list.
+    assert_eq!(
+        flow_db
+            .get(COL_TX_DATA_ROOT_INDEX, root.as_bytes())
+            .unwrap()
+            .unwrap(),
+        vec![0u64].as_ssz_bytes()
+    );
+    assert_eq!(
+        flow_db
+            .iter_with_prefix(COL_TX_DATA_ROOT_INDEX, root.as_bytes())
+            .count(),
+        5
+    );
+
+    // The seqs are removed from both the base list and the appended ones on revert, and
+    // appended again.
+    store.revert_to(1).unwrap();
+    assert_eq!(
+        store.get_tx_seq_list_by_data_root(&root).unwrap(),
+        vec![0, 1]
+    );
+    resubmit(&mut store, 2);
+    assert_eq!(
+        store.get_tx_seq_list_by_data_root(&root).unwrap(),
+        vec![0, 1, 2]
+    );
+    store.revert_to(0u64.wrapping_sub(1)).unwrap();
+    assert!(store
+        .get_tx_seq_list_by_data_root(&root)
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        flow_db
+            .iter_with_prefix(COL_TX_DATA_ROOT_INDEX, root.as_bytes())
+            .count(),
+        0
+    );
+    let tx_store = TransactionStore::new(handles).unwrap();
+    assert_eq!(tx_store.get_first_tx_seq_by_data_root(&root).unwrap(), None);
+}
+
+fn test_get_txs_with_status(db: &TestDb) {
+    let mut store = db.create_store();
+    for seq in 0..5 {
+        put_tx(&mut store, 3, seq);
+    }
+    store.prune_tx(3).unwrap();
+
+    let page = store.get_txs_with_status(0, 2).unwrap();
+    assert_eq!(
+        page.iter().map(|(tx, _)| tx.seq).collect::<Vec<_>>(),
+        vec![0, 1]
+    );
+    let page = store.get_txs_with_status(3, 10).unwrap();
+    assert_eq!(
+        page.iter()
+            .map(|(tx, status)| (tx.seq, *status))
+            .collect::<Vec<_>>(),
+        vec![(3, Some(TxStatus::Pruned)), (4, Some(TxStatus::Finalized))]
+    );
+    assert!(store.get_txs_with_status(5, 10).unwrap().is_empty());
+    assert!(store.get_txs_with_status(100, 10).unwrap().is_empty());
+    assert!(store.get_txs_with_status(0, 0).unwrap().is_empty());
+}
+
+fn test_get_db_column_stats(db: &TestDb) {
+    let mut store = db.create_store();
+    for seq in 0..3 {
+        put_tx(&mut store, 3, seq);
+    }
+
+    let stats = store.get_db_column_stats().unwrap();
+    assert_eq!(stats.len(), 2 * COL_NUM as usize);
+    let tx_stats = stats
+        .iter()
+        .find(|s| s.db == "flow" && s.column == "tx")
+        .unwrap();
+    assert!(tx_stats.num_keys >= 3);
+}
+
+fn test_get_merkle_state(db: &TestDb) {
+    let mut store = db.create_store();
+    put_tx(&mut store, 3 * PORA_CHUNK_SIZE + 5, 0);
+
+    let state = store.get_merkle_state(usize::MAX).unwrap();
+    let (flow_root, flow_length) = store.get_context().unwrap();
+    assert_eq!(state.flow_root(), Some(flow_root));
+    assert_eq!(state.flow_length, flow_length);
+    // the same state is built no matter how many nodes are read at once
+    assert_eq!(
+        store.build_merkle_state(usize::MAX, 1).unwrap(),
+        Some(state.clone())
+    );
+
+    let size = state.to_bytes().len();
+    assert_eq!(store.get_merkle_state(size).unwrap(), state);
+    assert!(matches!(
+        StoreError::of(&store.get_merkle_state(size - 1).unwrap_err()),
+        Some(StoreError::OutOfRange { .. })
+    ));
+}
+
+fn test_verify_and_reset_tx_data(db: &TestDb) {
+    let mut store = db.create_store();
+    // Each tx is aligned and fills 2 entry batches, tx 0 in batch 2 and 3.
+    put_tx(&mut store, 2 * PORA_CHUNK_SIZE, 0);
+    put_tx(&mut store, 2 * PORA_CHUNK_SIZE, 1);
+    put_tx(&mut store, 3, 2);
+    assert!(store.verify_tx_data(0).unwrap().is_empty());
+
+    // Corrupt batch 3 with the data of batch 4.
+    let batch = store
+        .db
+        .data()
+        .get(COL_ENTRY_BATCH, &4u64.to_be_bytes())
+        .unwrap();
+    store
+        .db
+        .data()
+        .put(COL_ENTRY_BATCH, &3u64.to_be_bytes(), &batch.unwrap())
+        .unwrap();
+    assert_eq!(store.verify_tx_data(0).unwrap(), vec![3]);
+    assert!(store.verify_tx_data(1).unwrap().is_empty());
+
+    // Batches out of the tx are not allowed to reset.
+    assert!(store.reset_tx_data(0, &[1]).is_err());
+    assert!(store.reset_tx_data(2, &[7]).is_err());
+
+    let flow_version = store.get_flow_version();
+    store.reset_tx_data(0, &[3]).unwrap();
+    assert!(store.get_flow_version() > flow_version);
+    assert_eq!(store.get_tx_status(0).unwrap(), None);
+    assert!(store
+        .get_chunks_by_tx_and_index_range(0, PORA_CHUNK_SIZE, 2 * PORA_CHUNK_SIZE)
+        .unwrap()
+        .is_none());
+
+    // Put the data again, the same as `put_tx`.
+    let mut data = vec![0u8; CHUNK_SIZE * PORA_CHUNK_SIZE];
+    for chunk in data.chunks_mut(CHUNK_SIZE) {
+        chunk[..8].copy_from_slice(&1u64.to_be_bytes());
+    }
+    store
+        .put_chunks(
+            0,
+            ChunkArray {
+                data,
+                start_index: PORA_CHUNK_SIZE as u64,
+            },
+        )
+        .unwrap();
+    store.finalize_tx(0).unwrap();
+    assert!(store.check_tx_completed(0).unwrap());
+    assert!(store.verify_tx_data(0).unwrap().is_empty());
+
+    // Only the tx data is removed from the batch 6 shared with tx 3.
+    put_tx(&mut store, 3, 3);
+    store.reset_tx_data(2, &[6]).unwrap();
+    assert_eq!(store.get_tx_status(2).unwrap(), None);
+    assert!(store
+        .get_chunks_by_tx_and_index_range(2, 0, 3)
+        .unwrap()
+        .is_none());
+    assert!(store.check_tx_completed(3).unwrap());
+    assert!(store.verify_tx_data(3).unwrap().is_empty());
+
+    let (_, data) = new_tx(store.get_context().unwrap().1, 3, 2);
+    store
+        .put_chunks(
+            2,
+            ChunkArray {
+                data,
+                start_index: 0,
+            },
+        )
+        .unwrap();
+    store.finalize_tx(2).unwrap();
+    assert!(store.verify_tx_data(2).unwrap().is_empty());
+
+    store.prune_tx(1).unwrap();
+    assert!(store.reset_tx_data(1, &[4]).is_err());
+}
+
+fn test_verify_flow_range(db: &TestDb) {
+    let mut store = db.create_store();
+    // Each tx is aligned and fills 2 entry batches, tx 0 in batch 2 and 3, and tx 2 in batch 6.
+    put_tx(&mut store, 2 * PORA_CHUNK_SIZE, 0);
+    put_tx(&mut store, 2 * PORA_CHUNK_SIZE, 1);
+    put_tx(&mut store, 3, 2);
+    let batch_size = PORA_CHUNK_SIZE as u64;
+
+    assert_eq!(store.get_tx_seq_by_flow_index(0).unwrap(), None);
+    assert_eq!(
+        store.get_tx_seq_by_flow_index(2 * batch_size).unwrap(),
+        Some(0)
+    );
+    assert_eq!(
+        store.get_tx_seq_by_flow_index(4 * batch_size - 1).unwrap(),
+        Some(0)
+    );
+    assert_eq!(
+        store.get_tx_seq_by_flow_index(5 * batch_size).unwrap(),
+        Some(1)
+    );
+    assert_eq!(
+        store.get_tx_seq_by_flow_index(6 * batch_size + 2).unwrap(),
+        Some(2)
+    );
+    assert_eq!(
+        store.get_tx_seq_by_flow_index(7 * batch_size).unwrap(),
+        None
+    );
+
+    // The range is truncated to the flow length.
+    assert!(store
+        .verify_flow_range(2 * batch_size, u64::MAX)
+        .unwrap()
+        .is_empty());
+
+    // Corrupt batch 3 with the data of batch 4, and lose batch 5.
+    let batch = store
+        .db
+        .data()
+        .get(COL_ENTRY_BATCH, &4u64.to_be_bytes())
+        .unwrap();
+    store
+        .db
+        .data()
+        .put(COL_ENTRY_BATCH, &3u64.to_be_bytes(), &batch.unwrap())
+        .unwrap();
+    store
+        .db
+        .data()
+        .delete(COL_ENTRY_BATCH, &5u64.to_be_bytes())
+        .unwrap();
+    assert_eq!(
+        store
+            .verify_flow_range(2 * batch_size, 7 * batch_size)
+            .unwrap(),
+        vec![3, 5]
+    );
+
+    // Only the batches overlapped with the range are verified.
+    assert_eq!(
+        store
+            .verify_flow_range(3 * batch_size + 1, 3 * batch_size + 2)
+            .unwrap(),
+        vec![3]
+    );
+    assert!(store
+        .verify_flow_range(4 * batch_size, 5 * batch_size)
+        .unwrap()
+        .is_empty());
+}
+
+fn test_audit_finalized_txs(db: &TestDb) {
+    let mut store = db.create_store();
+    // Each tx is aligned and fills 2 entry batches, tx 0 in batch 2 and 3.
+    put_tx(&mut store, 2 * PORA_CHUNK_SIZE, 0);
+    put_tx(&mut store, 2 * PORA_CHUNK_SIZE, 1);
+    put_tx_without_data(&mut store, 3, 2);
+
+    let report = audit_finalized_txs(&store, FinalizedAudit::Full).unwrap();
+    assert_eq!(
+        report,
+        AuditReport {
+            audited: 3,
+            demoted: vec![]
+        }
+    );
+
+    // Lose batch 3 while tx 0 is still finalized, e.g. by an unclean shutdown.
+    store
+        .db
+        .data()
+        .delete(COL_ENTRY_BATCH, &3u64.to_be_bytes())
+        .unwrap();
+    assert!(store.check_tx_completed(0).unwrap());
+
+    let report = audit_finalized_txs(&store, FinalizedAudit::Off).unwrap();
+    assert_eq!(report, AuditReport::default());
+    // All txs are the latest ones to sample.
+    let flow_version = store.get_flow_version();
+    let report = audit_finalized_txs(&store, FinalizedAudit::Sampled { samples: 3 }).unwrap();
+    assert_eq!(
+        report,
+        AuditReport {
+            audited: 3,
+            demoted: vec![0]
+        }
+    );
+    // The data served for the demoted tx changes.
+    assert_eq!(store.get_flow_version(), flow_version + 2);
+    assert_eq!(store.get_tx_status(0).unwrap(), None);
+    assert!(store.check_tx_completed(1).unwrap());
+
+    // Demoted txs are not audited again.
+    let report = audit_finalized_txs(&store, FinalizedAudit::Full).unwrap();
+    assert!(report.demoted.is_empty());
+
+    // Sync the data again, the same as `put_tx`.
+    let mut data = vec![0u8; CHUNK_SIZE * PORA_CHUNK_SIZE];
+    for chunk in data.chunks_mut(CHUNK_SIZE) {
+        chunk[..8].copy_from_slice(&1u64.to_be_bytes());
+    }
+    store
+        .put_chunks(
+            0,
+            ChunkArray {
+                data,
+                start_index: PORA_CHUNK_SIZE as u64,
+            },
+        )
+        .unwrap();
+    store.finalize_tx(0).unwrap();
+    assert!(store.check_tx_completed(0).unwrap());
+    assert!(store.verify_tx_data(0).unwrap().is_empty());
+}
+
+fn test_sample_stored_chunks(db: &TestDb) {
+    let mut store = db.create_store();
+    assert!(sample_stored_chunks(&store, 10).unwrap().is_empty());
+
+    put_tx(&mut store, 2 * PORA_CHUNK_SIZE, 0);
+    put_tx_without_data(&mut store, 2 * PORA_CHUNK_SIZE, 1);
+    put_tx(&mut store, 3, 2);
+    let txs: Vec<_> = (0..3)
+        .map(|seq| store.get_tx_by_seq_number(seq).unwrap().unwrap())
+        .collect();
+
+    // Only the data of finalized txs is sampled, without the unfinalized tx 1 or the padding.
+    let indices = sample_stored_chunks(&store, 100).unwrap();
+    assert_eq!(indices.len(), 100);
+    for index in indices {
+        let stored = [(&txs[0], 2 * PORA_CHUNK_SIZE), (&txs[2], 3)]
+            .iter()
+            .any(|(tx, chunks)| {
+                (tx.start_entry_index..tx.start_entry_index + *chunks as u64).contains(&index)
+            });
+        assert!(stored, "index {} not stored", index);
+    }
+    assert!(sample_stored_chunks(&store, 0).unwrap().is_empty());
+}
+
+fn test_get_flow_entries_with_proof(db: &TestDb) {
+    let mut store = db.create_store();
+    put_tx(&mut store, 3, 0);
+    put_tx(&mut store, PORA_CHUNK_SIZE + 3, 1);
+    let (flow_root, flow_length) = store.get_context().unwrap();
+
+    let txs: Vec<_> = (0..2)
+        .map(|seq| store.get_tx_by_seq_number(seq).unwrap().unwrap())
+        .collect();
+    for tx in txs.iter() {
+        let (data, root, length) = store
+            .get_flow_entries_with_proof(tx.start_entry_index, 3)
+            .unwrap()
+            .unwrap();
+        assert_eq!((root, length), (flow_root, flow_length));
+        assert_eq!(data.chunks.start_index, tx.start_entry_index);
+        validate_flow_entries(&root, tx.start_entry_index, &data.chunks.data, &data.proof).unwrap();
+    }
+
+    // The entries are not required to be in a single tx.
+    let start = txs[0].start_entry_index;
+    let end = txs[1].start_entry_index + 3;
+    let (data, root, _) = store
+        .get_flow_entries_with_proof(start, end - start)
+        .unwrap()
+        .unwrap();
+    validate_flow_entries(&root, start, &data.chunks.data, &data.proof).unwrap();
+
+    assert!(store.get_flow_entries_with_proof(0, 1).is_err());
+    assert!(store.get_flow_entries_with_proof(1, 0).is_err());
+    assert!(store
+        .get_flow_entries_with_proof(flow_length - 1, 2)
+        .is_err());
+}
+
+fn test_get_sealed_chunk_with_proof(db: &TestDb) {
+    let mut store = db.create_store();
+    // Seal tasks are only scheduled when a seal worker is pulling.
+    assert!(store.pull_seal_chunk(0, usize::MAX).unwrap().is_none());
+    put_tx(&mut store, PORA_CHUNK_SIZE, 0);
+    let tx = store.get_tx_by_seq_number(0).unwrap().unwrap();
+    let chunk_index = tx.start_entry_index / PORA_CHUNK_SIZE as u64;
+
+    let miner_id = H256(random());
+    let context_digest = H256(random());
+    let mut non_sealed = HashMap::new();
+    while let Some(tasks) = store.pull_seal_chunk(0, usize::MAX).unwrap() {
+        let answers = tasks
+            .into_iter()
+            .map(|task| {
+                let mut sealed_data = task.non_sealed_data;
+                zgs_seal::seal(
+                    &mut sealed_data,
+                    &miner_id,
+                    &context_digest,
+                    task.seal_index * SECTORS_PER_SEAL as u64,
+                );
+                non_sealed.insert(task.seal_index, task.non_sealed_data);
+                SealAnswer {
+                    seal_index: task.seal_index,
+                    version: task.version,
+                    sealed_data,
+                    miner_id,
+                    seal_context: context_digest,
+                    context_end_seal: u64::MAX,
+                }
+            })
+            .collect();
+        store.submit_seal_result(answers).unwrap();
+    }
+
+    let sealed = store
+        .get_sealed_chunk_with_proof(chunk_index)
+        .unwrap()
+        .unwrap();
+    assert_eq!(sealed.miner_id, miner_id);
+    assert_eq!(
+        (sealed.flow_root, sealed.flow_length),
+        store.get_context().unwrap()
+    );
+    for seal_offset in 0..SEALS_PER_LOAD {
+        assert!(sealed.chunk.availabilities[seal_offset]);
+        let seal_index = chunk_index * SEALS_PER_LOAD as u64 + seal_offset as u64;
+        let mut expected = non_sealed[&seal_index];
+        zgs_seal::seal(
+            &mut expected,
+            &sealed.miner_id,
+            &sealed.chunk.seal_contexts[seal_offset].unwrap(),
+            seal_index * SECTORS_PER_SEAL as u64,
+        );
+        assert_eq!(sealed.chunk.loaded_chunk[seal_offset], expected);
+    }
+
+    // The proof is of the root of unsealed data.
+    let data = store
+        .get_chunk_by_flow_index(chunk_index * PORA_CHUNK_SIZE as u64, PORA_CHUNK_SIZE as u64)
+        .unwrap()
+        .unwrap()
+        .data;
+    let chunk_root = H256(sub_merkle_tree(&data).unwrap().root());
+    sealed
+        .proof
+        .validate::<Sha3Algorithm>(&chunk_root, chunk_index as usize)
+        .unwrap();
+    assert_eq!(sealed.proof.root(), sealed.flow_root);
+
+    assert!(store.get_sealed_chunk_with_proof(chunk_index + 1).is_err());
+}
+
+fn test_batched_seal_results_after_crash(db: &TestDb) {
+    let mut store = db.create_store();
+    assert!(store.pull_seal_chunk(0, usize::MAX).unwrap().is_none());
+    put_tx(&mut store, 2 * PORA_CHUNK_SIZE, 0);
+
+    let miner_id = H256(random());
+    let context_digest = H256(random());
+    // pulls after the pending tasks as the sealer does, until all tasks are sealed
+    let seal_all = |store: &LogManager| {
+        let mut answers = vec![];
+        let mut seal_index_min = 0;
+        while let Some(tasks) = store.pull_seal_chunk(seal_index_min, usize::MAX).unwrap() {
+            seal_index_min = tasks.last().unwrap().seal_index as usize + 1;
+            answers.extend(tasks.into_iter().map(|task| {
+                let mut sealed_data = task.non_sealed_data;
+                zgs_seal::seal(
+                    &mut sealed_data,
+                    &miner_id,
+                    &context_digest,
+                    task.seal_index * SECTORS_PER_SEAL as u64,
+                );
+                SealAnswer {
+                    seal_index: task.seal_index,
+                    version: task.version,
+                    sealed_data,
+                    miner_id,
+                    seal_context: context_digest,
+                    context_end_seal: u64::MAX,
+                }
+            }));
+        }
+        answers
+    };
+    let is_sealed = |store: &LogManager, seal_index: u64| {
+        let chunk = store
+            .load_sealed_data(seal_index / SEALS_PER_LOAD as u64)
+            .unwrap()
+            .unwrap();
+        chunk.availabilities[seal_index as usize % SEALS_PER_LOAD]
+    };
+
+    // sealer killed between compute and commit
+    let answers = seal_all(&store);
+    let seal_indices: Vec<u64> = answers.iter().map(|answer| answer.seal_index).collect();
+    assert!(seal_indices.len() > SEALS_PER_LOAD);
+    drop(answers);
+    assert!(seal_indices.iter().all(|index| !is_sealed(&store, *index)));
+
+    // sealed again by the restarted sealer, and committed at once in any order
+    let mut answers = seal_all(&store);
+    assert_eq!(
+        answers
+            .iter()
+            .map(|answer| answer.seal_index)
+            .collect::<Vec<_>>(),
+        seal_indices
+    );
+    // sealed twice if the previous sealer resumes with its answers, which are ignored then
+    let duplicated = seal_all(&store);
+    answers.reverse();
+    store.submit_seal_result(answers).unwrap();
+    assert!(seal_indices.iter().all(|index| is_sealed(&store, *index)));
+    assert!(store.pull_seal_chunk(0, usize::MAX).unwrap().is_none());
+
+    store.submit_seal_result(duplicated).unwrap();
+    assert!(seal_indices.iter().all(|index| is_sealed(&store, *index)));
+    assert!(store.pull_seal_chunk(0, usize::MAX).unwrap().is_none());
+}
+
+fn test_flush_journal_after_crash(db: &TestDb) {
+    let fail_writes = Arc::new(AtomicBool::new(false));
+    let flow_db = db.create_db(COL_NUM);
+    let data_db = db.create_db(COL_NUM);
+    let mut store = LogManager::with_dbs(
+        StoreHandles::split(
+            Arc::new(FailingDB::new(flow_db.clone(), fail_writes.clone())),
+            Arc::new(FailingDB::new(data_db.clone(), fail_writes.clone())),
+        ),
+        LogConfig::default(),
+    )
+    .unwrap();
+    put_tx(&mut store, 3, 0);
+
+    // Flush a file of 3 segments, and crash after the first segment written.
+    let chunk_count = 3 * PORA_CHUNK_SIZE;
+    let (tx, data) = put_tx_without_data(&mut store, chunk_count, 1);
+    let ranges: Vec<_> = (0..3)
+        .map(|i| ChunkRange {
+            start: (i * PORA_CHUNK_SIZE) as u64,
+            end: ((i + 1) * PORA_CHUNK_SIZE) as u64,
+        })
+        .collect();
+    let journal = FlushJournal {
+        tx_seq: tx.seq,
+        data_root: tx.data_merkle_root,
+        ranges: ranges.clone(),
+    };
+    store.put_flush_journal(journal.clone()).unwrap();
+    store.put_chunks(tx.seq, segment(&data, 0)).unwrap();
+    fail_writes.store(true, Ordering::SeqCst);
+    assert!(store.put_chunks(tx.seq, segment(&data, 1)).is_err());
+    drop(store);
+
+    // Restart, and only the unwritten segments are reported.
+    let store =
+        LogManager::with_dbs(StoreHandles::split(flow_db, data_db), LogConfig::default()).unwrap();
+    assert_eq!(store.get_flush_journals().unwrap(), vec![journal.clone()]);
+    assert_eq!(
+        store.verify_flush_journal(&journal).unwrap(),
+        ranges[1..].to_vec()
+    );
+    assert!(store.finalize_tx(tx.seq).is_err());
+
+    // Ranges are appended to the existing journal.
+    store
+        .put_flush_journal(FlushJournal {
+            ranges: ranges[1..].to_vec(),
+            ..journal.clone()
+        })
+        .unwrap();
+    assert_eq!(store.get_flush_journals().unwrap(), vec![journal.clone()]);
+    for i in 1..3 {
+        store.put_chunks(tx.seq, segment(&data, i)).unwrap();
+    }
+    assert!(store.verify_flush_journal(&journal).unwrap().is_empty());
+
+    // Journal is removed once finalized.
+    store.finalize_tx(tx.seq).unwrap();
+    assert!(store.get_flush_journals().unwrap().is_empty());
+}
+
+fn test_flush_journal_reverted(db: &TestDb) {
+    let mut store = db.create_store();
+    put_tx(&mut store, 3, 0);
+    let (tx, _) = put_tx_without_data(&mut store, 3, 1);
+    store
+        .put_flush_journal(FlushJournal {
+            tx_seq: tx.seq,
+            data_root: tx.data_merkle_root,
+            ranges: vec![ChunkRange { start: 0, end: 3 }],
+        })
+        .unwrap();
+    assert_eq!(store.get_flush_journals().unwrap().len(), 1);
+
+    store.revert_to(0).unwrap();
+    assert!(store.get_flush_journals().unwrap().is_empty());
+}
+
+fn test_finalize_txs(db: &TestDb) {
+    let mut store = db.create_store();
+    put_tx(&mut store, 3, 0);
+    let mut txs = vec![];
+    for seq in 1..4 {
+        let (tx, data) = put_tx_without_data(&mut store, 3, seq);
+        store
+            .put_flush_journal(FlushJournal {
+                tx_seq: tx.seq,
+                data_root: tx.data_merkle_root,
+                ranges: vec![ChunkRange { start: 0, end: 3 }],
+            })
+            .unwrap();
+        txs.push((tx, data));
+    }
+    for (tx, data) in &txs[..2] {
+        store.put_chunks(tx.seq, segment(data, 0)).unwrap();
+    }
+
+    // Nothing finalized if the data of any tx is missing.
+    assert!(store.finalize_txs(vec![1, 2, 3]).is_err());
+    for seq in 1..4 {
+        assert!(!store.check_tx_completed(seq).unwrap());
+    }
+    assert_eq!(store.get_flush_journals().unwrap().len(), 3);
+
+    store.finalize_txs(vec![1, 2]).unwrap();
+    assert!(store.check_tx_completed(1).unwrap());
+    assert!(store.check_tx_completed(2).unwrap());
+    assert!(!store.check_tx_completed(3).unwrap());
+    let journals = store.get_flush_journals().unwrap();
+    assert_eq!(
+        journals.iter().map(|j| j.tx_seq).collect::<Vec<_>>(),
+        vec![3]
+    );
+}
+
+fn test_finalize_txs_with_hash(db: &TestDb) {
+    let mut store = db.create_store();
+    put_tx(&mut store, 3, 0);
+    let mut txs = vec![];
+    for seq in 1..4 {
+        txs.push(put_tx_without_data(&mut store, 3, seq));
+    }
+    for (tx, data) in &txs[..2] {
+        store.put_chunks(tx.seq, segment(data, 0)).unwrap();
+    }
+
+    // A tx of mismatched hash or incomplete data never blocks the others.
+    let results = store
+        .finalize_txs_with_hash(vec![
+            (1, txs[0].0.hash()),
+            (2, H256::repeat_byte(1)),
+            (3, txs[2].0.hash()),
+        ])
+        .unwrap();
+    assert!(results[0].as_ref().unwrap());
+    assert!(!results[1].as_ref().unwrap());
+    assert!(results[2].is_err());
+    assert!(store.check_tx_completed(1).unwrap());
+    assert!(!store.check_tx_completed(2).unwrap());
+    assert!(!store.check_tx_completed(3).unwrap());
+}
+
+fn test_finalize_txs_after_crash(db: &TestDb) {
+    let fail_writes = Arc::new(AtomicBool::new(false));
+    let flow_db = db.create_db(COL_NUM);
+    let data_db = db.create_db(COL_NUM);
+    let mut store = LogManager::with_dbs(
+        StoreHandles::split(
+            Arc::new(FailingDB::new(flow_db.clone(), fail_writes.clone())),
+            Arc::new(FailingDB::new(data_db.clone(), fail_writes.clone())),
+        ),
+        LogConfig::default(),
+    )
+    .unwrap();
+    put_tx(&mut store, 3, 0);
+    let mut txs = vec![];
+    for seq in 1..4 {
+        let (tx, data) = put_tx_without_data(&mut store, 4, seq);
+        store
+            .put_flush_journal(FlushJournal {
+                tx_seq: tx.seq,
+                data_root: tx.data_merkle_root,
+                ranges: vec![ChunkRange { start: 0, end: 4 }],
+            })
+            .unwrap();
+        store.put_chunks(tx.seq, segment(&data, 0)).unwrap();
+        txs.push((tx.seq, tx.hash()));
+    }
+
+    // Crash while persisting the statuses, and none of the txs is finalized. Txs of 4 chunks
+    // are not padded, so the statuses are the only write to finalize.
+    fail_writes.store(true, Ordering::SeqCst);
+    assert!(store.finalize_txs_with_hash(txs.clone()).is_err());
+    drop(store);
+
+    let store =
+        LogManager::with_dbs(StoreHandles::split(flow_db, data_db), LogConfig::default()).unwrap();
+    for (seq, _) in &txs {
+        assert!(!store.check_tx_completed(*seq).unwrap());
+    }
+    assert_eq!(store.get_flush_journals().unwrap().len(), 3);
+
+    // Finalized all at once after restart, with the journals removed.
+    let results = store.finalize_txs_with_hash(txs.clone()).unwrap();
+    assert!(results.into_iter().all(|result| result.unwrap()));
+    for (seq, _) in &txs {
+        assert!(store.check_tx_completed(*seq).unwrap());
+    }
+    assert!(store.get_flush_journals().unwrap().is_empty());
+}
+
+fn test_store_errors(db: &TestDb) {
+    let mut store = db.create_store();
+    put_tx(&mut store, 3, 0);
+    let (tx, _) = put_tx_without_data(&mut store, 3, 1);
+
+    let err = store.finalize_tx(2).unwrap_err();
+    assert!(matches!(
+        StoreError::of(&err),
+        Some(StoreError::NotFound {
+            what: StoreItem::Tx(2)
+        })
+    ));
+    let err = store.finalize_tx(1).unwrap_err();
+    assert!(matches!(
+        StoreError::of(&err),
+        Some(StoreError::NotFound {
+            what: StoreItem::TxData(1)
+        })
+    ));
+
+    let chunks = ChunkArray {
+        data: vec![0; CHUNK_SIZE],
+        start_index: 1024,
+    };
+    let err = store.put_chunks(1, chunks).unwrap_err();
+    assert!(matches!(
+        StoreError::of(&err),
+        Some(StoreError::OutOfRange { index: 1025, .. })
+    ));
+    let err = store.get_sealed_chunk_with_proof(1024).unwrap_err();
+    assert!(matches!(
+        StoreError::of(&err),
+        Some(StoreError::OutOfRange { index: 1024, .. })
+    ));
+
+    // e.g. put after the txs reverted concurrently
+    let err = store
+        .put_tx(TransactionBuilder::from(tx).seq(5).build().unwrap())
+        .unwrap_err();
+    let store_err = StoreError::of(&err).unwrap();
+    assert!(matches!(
+        store_err,
+        StoreError::ReorgInProgress {
+            expected_seq: 2,
+            tx_seq: 5
+        }
+    ));
+    assert!(!store_err.is_retryable());
+
+    store.prune_tx(0).unwrap();
+    let err = store.reset_tx_data(0, &[]).unwrap_err();
+    assert!(matches!(
+        StoreError::of(&err),
+        Some(StoreError::Pruned { tx_seq: 0 })
+    ));
+}
+
+fn test_put_invalid_tx(db: &TestDb) {
+    let mut store = db.create_store();
+    let mut accepted = db.create_store();
+    put_tx(&mut store, 3, 0);
+    put_tx(&mut accepted, 3, 0);
+
+    // of the same root as the finalized tx 0, whose data is not copied to the invalid tx
+    let (tx, data) = new_tx(store.get_context().unwrap().1, 3, 0);
+    let tx = TransactionBuilder::from(tx).seq(1).build().unwrap();
+    store.put_invalid_tx(tx.clone()).unwrap();
+    accepted.put_tx(tx.clone()).unwrap();
+    assert!(accepted.check_tx_completed(1).unwrap());
+    assert!(!store.check_tx_completed(1).unwrap());
+    assert!(store.check_tx_invalid(1).unwrap());
+    assert_eq!(store.get_tx_status(1).unwrap(), Some(TxStatus::Invalid));
+
+    // the flow is the same as the tx accepted
+    assert_eq!(store.next_tx_seq(), 2);
+    assert_eq!(
+        store.get_context().unwrap(),
+        accepted.get_context().unwrap()
+    );
+
+    let chunks = ChunkArray {
+        data,
+        start_index: 0,
+    };
+    let err = store.put_chunks(1, chunks).unwrap_err();
+    assert!(matches!(
+        StoreError::of(&err),
+        Some(StoreError::Invalid { tx_seq: 1 })
+    ));
+    let err = store.finalize_tx(1).unwrap_err();
+    assert!(matches!(
+        StoreError::of(&err),
+        Some(StoreError::Invalid { tx_seq: 1 })
+    ));
+    assert!(!store.check_tx_completed(1).unwrap());
+
+    // later txs of the same root are still copied from the finalized tx
+    let (tx, _) = new_tx(store.get_context().unwrap().1, 3, 0);
+    store
+        .put_tx(TransactionBuilder::from(tx).seq(2).build().unwrap())
+        .unwrap();
+    assert!(store.check_tx_completed(2).unwrap());
+    assert!(store.check_tx_invalid(1).unwrap());
+}
+
+fn test_stale_tx_status(db: &TestDb) {
+    let (flow_db, data_db) = (db.create_db(COL_NUM), db.create_db(COL_NUM));
+    let open = || {
+        LogManager::with_dbs(
+            StoreHandles::split(flow_db.clone(), data_db.clone()),
+            LogConfig::default(),
+        )
+        .unwrap()
+    };
+    let mut store = open();
+    put_tx(&mut store, 3, 0);
+
+    // the status of the next tx is written, but not the tx, e.g. stopped between the writes
+    data_db
+        .put(
+            COL_TX_COMPLETED,
+            &1u64.to_be_bytes(),
+            &[TxStatus::Invalid.into()],
+        )
+        .unwrap();
+    drop(store);
+
+    let mut store = open();
+    put_tx_without_data(&mut store, 3, 1);
+    assert_eq!(store.get_tx_status(1).unwrap(), None);
+    assert!(!store.check_tx_invalid(1).unwrap());
+}
+
+fn test_check_db_healthy(db: &TestDb) {
+    let (flow_db, data_db) = create_checked_store(db);
+    let report = check(&flow_db, &data_db, false);
+    assert!(report.is_healthy(), "{}", report);
+    assert_eq!(report.exit_code(), 0);
+    assert!(report
+        .items
+        .iter()
+        .all(|item| item.status == CheckStatus::Passed));
+}
+
+fn test_check_db_missing_column(db: &TestDb) {
+    let flow_db = db.create_db(COL_NUM - 1);
+    let data_db = db.create_db(COL_NUM);
+    let report = check(&flow_db, &data_db, true);
+    assert_eq!(report.exit_code(), 1);
+    assert!(matches!(
+        report.status(CHECK_DB_COLUMNS),
+        Some(CheckStatus::Failed(_))
+    ));
+    assert_eq!(report.status(CHECK_FLOW_ROOT), Some(&CheckStatus::Skipped));
+}
+
+fn test_check_db_corrupted_tx(db: &TestDb) {
+    let (flow_db, data_db) = create_checked_store(db);
+    flow_db.put(COL_TX, &2u64.to_be_bytes(), b"broken").unwrap();
+
+    let report = check(&flow_db, &data_db, false);
+    assert!(!report.is_healthy());
+    assert!(matches!(
+        report.status(CHECK_TX_STORE),
+        Some(CheckStatus::Failed(_))
+    ));
+    assert_eq!(report.status(CHECK_FLOW_ROOT), Some(&CheckStatus::Skipped));
+
+    // txs since block 11 are truncated to sync again, then the flow is rebuilt
+    let report = check(&flow_db, &data_db, true);
+    assert!(report.is_healthy(), "{}", report);
+    assert!(matches!(
+        report.status(CHECK_TX_STORE),
+        Some(CheckStatus::Repaired(_))
+    ));
+    assert!(matches!(
+        report.status(CHECK_FLOW_ROOT),
+        Some(CheckStatus::Repaired(_))
+    ));
+
+    let report = check(&flow_db, &data_db, false);
+    assert!(report.is_healthy(), "{}", report);
+    let store =
+        LogManager::with_dbs(StoreHandles::split(flow_db, data_db), LogConfig::default()).unwrap();
+    assert_eq!(store.next_tx_seq(), 1);
+    assert_eq!(
+        store.get_sync_progress().unwrap(),
+        Some((11, H256::from_low_u64_be(11)))
+    );
+    assert_eq!(store.get_tx_status(0).unwrap(), Some(TxStatus::Finalized));
+    assert!(store.get_block_hash_by_number(12).unwrap().is_none());
+}
+
+fn test_check_db_corrupted_merkle(db: &TestDb) {
+    let (flow_db, data_db) = create_checked_store(db);
+    let expected = LogManager::with_dbs(
+        StoreHandles::split(flow_db.clone(), data_db.clone()),
+        LogConfig::default(),
+    )
+    .unwrap()
+    .get_context()
+    .unwrap();
+
+    let nodes: Vec<_> = flow_db
+        .iter(COL_FLOW_MPT_NODES)
+        .map(|r| r.unwrap().0)
+        .filter(|key| !key.starts_with(b"layer_size"))
+        .collect();
+    assert!(!nodes.is_empty());
+    for key in nodes {
+        flow_db
+            .put(COL_FLOW_MPT_NODES, &key, &random::<[u8; 32]>())
+            .unwrap();
+    }
+
+    let report = check(&flow_db, &data_db, false);
+    assert!(matches!(
+        report.status(CHECK_FLOW_ROOT),
+        Some(CheckStatus::Failed(_))
+    ));
+    assert_eq!(report.status(CHECK_TX_STORE), Some(&CheckStatus::Passed));
+
+    let report = check(&flow_db, &data_db, true);
+    assert!(report.is_healthy(), "{}", report);
+    assert!(matches!(
+        report.status(CHECK_FLOW_ROOT),
+        Some(CheckStatus::Repaired(_))
+    ));
+
+    // data of finalized txs are kept
+    let store =
+        LogManager::with_dbs(StoreHandles::split(flow_db, data_db), LogConfig::default()).unwrap();
+    assert_eq!(store.get_context().unwrap(), expected);
+    assert_eq!(store.next_tx_seq(), 3);
+    for seq in 0..3 {
+        assert_eq!(store.get_tx_status(seq).unwrap(), Some(TxStatus::Finalized));
+    }
+    assert!(store.get_chunk_by_tx_and_index(2, 0).unwrap().is_some());
+}
+
+fn test_check_db_not_repairable(db: &TestDb) {
+    let (flow_db, data_db) = create_checked_store(db);
+    flow_db
+        .put(COL_MISC, b"log_sync_progress", b"broken")
+        .unwrap();
+    data_db
+        .put(
+            COL_MISC,
+            SHARD_CONFIG_KEY.as_bytes(),
+            &ShardConfig::new(1, 2).unwrap().as_ssz_bytes(),
+        )
+        .unwrap();
+
+    let report = check(&flow_db, &data_db, true);
+    assert_eq!(report.exit_code(), 1);
+    assert!(matches!(
+        report.status(CHECK_SYNC_PROGRESS),
+        Some(CheckStatus::Failed(_))
+    ));
+    assert!(matches!(
+        report.status(CHECK_SHARD_CONFIG),
+        Some(CheckStatus::Failed(_))
+    ));
+    assert_eq!(report.status(CHECK_FLOW_ROOT), Some(&CheckStatus::Passed));
+    assert!(report.to_string().ends_with("2 failed, 0 skipped"));
+}
+
+/// Key-value db that fails all writes once `fail_writes` set, so as to simulate a crash.
+struct FailingDB {
+    inner: Arc<dyn ZgsKeyValueDB>,
+    fail_writes: Arc<AtomicBool>,
+}
+
+impl FailingDB {
+    fn new(inner: Arc<dyn ZgsKeyValueDB>, fail_writes: Arc<AtomicBool>) -> Self {
+        FailingDB { inner, fail_writes }
+    }
+}
+
+impl KeyValueDB for FailingDB {
+    fn get(&self, col: u32, key: &[u8]) -> IoResult<Option<DBValue>> {
+        self.inner.get(col, key)
+    }
+
+    fn get_by_prefix(&self, col: u32, prefix: &[u8]) -> IoResult<Option<DBValue>> {
+        self.inner.get_by_prefix(col, prefix)
+    }
+
+    fn write(&self, transaction: DBTransaction) -> IoResult<()> {
+        if self.fail_writes.load(Ordering::SeqCst) {
+            return Err(IoError::new(ErrorKind::Other, "injected write failure"));
+        }
+        self.inner.write(transaction)
+    }
+
+    fn iter<'a>(&'a self, col: u32) -> Box<dyn Iterator<Item = IoResult<DBKeyValue>> + 'a> {
+        self.inner.iter(col)
+    }
+
+    fn iter_with_prefix<'a>(
+        &'a self,
+        col: u32,
+        prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = IoResult<DBKeyValue>> + 'a> {
+        self.inner.iter_with_prefix(col, prefix)
+    }
+}
+
+impl KvdbIntrospection for FailingDB {}
+
+impl ZgsKeyValueDB for FailingDB {
+    fn num_keys(&self, col: u32) -> IoResult<u64> {
+        self.inner.num_keys(col)
+    }
+}
+
+fn segment(data: &[u8], seg_index: usize) -> ChunkArray {
+    let start = seg_index * PORA_CHUNK_SIZE;
+    let end = cmp::min((start + PORA_CHUNK_SIZE) * CHUNK_SIZE, data.len());
+    ChunkArray {
+        data: data[start * CHUNK_SIZE..end].to_vec(),
+        start_index: start as u64,
+    }
+}
+
+fn test_unified_layout(db: &TestDb) {
+    let unified_db = db.create_db(COL_NUM);
+    let mut store = LogManager::with_dbs(
+        StoreHandles::unified(unified_db.clone()),
+        LogConfig::default(),
+    )
+    .unwrap();
+    for seq in 0..3 {
+        put_tx(&mut store, 3 * PORA_CHUNK_SIZE, seq);
+    }
+    store
+        .put_sync_progress((10, H256::from_low_u64_be(10), Some(Some(0))))
+        .unwrap();
+    assert_eq!(store.get_db_column_stats().unwrap().len(), COL_NUM as usize);
+    let context = store.get_context().unwrap();
+    drop(store);
+
+    let report = check_db(
+        StoreHandles::unified(unified_db.clone()),
+        LogConfig::default(),
+        ShardConfig::default(),
+        false,
+    );
+    assert!(report.is_healthy(), "{}", report);
+
+    let store =
+        LogManager::with_dbs(StoreHandles::unified(unified_db), LogConfig::default()).unwrap();
+    assert_eq!(store.get_context().unwrap(), context);
+    assert!(store.check_tx_completed(2).unwrap());
+    assert!(store.get_chunk_by_tx_and_index(2, 0).unwrap().is_some());
+}
+
+fn test_lazy_flow_tree_load(db: &TestDb) {
+    let (flow_db, data_db) = (db.create_db(COL_NUM), db.create_db(COL_NUM));
+    let open = |deferred: bool| {
+        let db = StoreHandles::split(flow_db.clone(), data_db.clone());
+        if deferred {
+            LogManager::with_dbs_deferred(db, LogConfig::default()).unwrap()
+        } else {
+            LogManager::with_dbs(db, LogConfig::default()).unwrap()
+        }
+    };
+    let mut store = open(false);
+    assert!(store.is_flow_tree_ready());
+    put_tx(&mut store, 3 * PORA_CHUNK_SIZE, 0);
+    let context = store.get_context().unwrap();
+    drop(store);
+
+    let store = Arc::new(open(true));
+    assert!(!store.is_flow_tree_ready());
+    let phases: Vec<_> = store
+        .startup_phases()
+        .iter()
+        .map(|(name, _)| *name)
+        .collect();
+    assert_eq!(phases, vec!["tx_store_init"]);
+
+    // finalized data is served while the tree is loading
+    assert_eq!(store.get_tx_by_seq_number(0).unwrap().unwrap().seq, 0);
+    assert!(store.check_tx_completed(0).unwrap());
+    let chunks = store
+        .get_chunks_by_tx_and_index_range(0, 0, PORA_CHUNK_SIZE)
+        .unwrap()
+        .unwrap();
+    assert_eq!(chunks.data.len(), PORA_CHUNK_SIZE * CHUNK_SIZE);
+
+    // writes are queued until the tree is loaded
+    let (tx, _) = new_tx(context.1, PORA_CHUNK_SIZE, 1);
+    let writer = {
+        let store = store.clone();
+        std::thread::spawn(move || store.put_tx(tx).unwrap())
+    };
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(!writer.is_finished());
+    assert_eq!(store.next_tx_seq(), 1);
+
+    store.load_flow_tree().unwrap();
+    writer.join().unwrap();
+    assert!(store.is_flow_tree_ready());
+    assert_eq!(store.next_tx_seq(), 2);
+    assert_ne!(store.get_context().unwrap(), context);
+    let phases: Vec<_> = store
+        .startup_phases()
+        .iter()
+        .map(|(name, _)| *name)
+        .collect();
+    assert_eq!(
+        phases,
+        vec!["tx_store_init", "last_chunk_rebuild", "flow_tree_load"]
+    );
+}
+
+fn test_check_network_id(db: &TestDb) {
+    let (flow_db, data_db) = (db.create_db(COL_NUM), db.create_db(COL_NUM));
+    let handles = || StoreHandles::split(flow_db.clone(), data_db.clone());
+    let network_id = StoreNetworkId {
+        chain_id: 16600,
+        flow_address: H160::from_low_u64_be(1),
+    };
+
+    // persisted on the first run
+    let mut store = LogManager::with_dbs(handles(), LogConfig::default()).unwrap();
+    store.check_network_id(&network_id).unwrap();
+    assert_eq!(
+        store
+            .get_config_decoded::<_, StoreNetworkId>(&NETWORK_ID_KEY, DATA_DB_KEY)
+            .unwrap(),
+        Some(network_id)
+    );
+    put_tx(&mut store, 3, 0);
+    drop(store);
+
+    // matched after restart
+    let store = LogManager::with_dbs(handles(), LogConfig::default()).unwrap();
+    store.check_network_id(&network_id).unwrap();
+
+    // refused for another chain or flow contract
+    for other in [
+        StoreNetworkId {
+            chain_id: 16601,
+            ..network_id
+        },
+        StoreNetworkId {
+            flow_address: H160::from_low_u64_be(2),
+            ..network_id
+        },
+    ] {
+        let e = store.check_network_id(&other).unwrap_err();
+        assert!(matches!(
+            StoreError::of(&e),
+            Some(StoreError::NetworkMismatch { stored, configured })
+                if *stored == network_id && *configured == other
+        ));
+    }
+    assert!(store.check_tx_completed(0).unwrap());
+    drop(store);
+
+    // reinitialized for another network once wiped
+    let other = StoreNetworkId {
+        chain_id: 16601,
+        ..network_id
+    };
+    handles().clear().unwrap();
+    let store = LogManager::with_dbs(handles(), LogConfig::default()).unwrap();
+    store.check_network_id(&other).unwrap();
+    assert_eq!(store.next_tx_seq(), 0);
+    assert!(store.get_tx_by_seq_number(0).unwrap().is_none());
+    assert!(store.check_network_id(&network_id).is_err());
+}
+
+#[test]
+fn test_migrate_db_layout() {
+    let db_dir = TempDir::new().unwrap();
+    let open = |layout| {
+        let db = StoreHandles::open(DbEngine::RocksDb, layout, db_dir.path()).unwrap();
+        LogManager::with_dbs(db, LogConfig::default()).unwrap()
+    };
+    let migrate = |layout| migrate_db_layout(DbEngine::RocksDb, layout, db_dir.path());
+    let mut store = open(DbLayout::Split);
+    for seq in 0..3 {
+        put_tx(&mut store, 3 * PORA_CHUNK_SIZE, seq);
+    }
+    let context = store.get_context().unwrap();
+
+    // The dbs are locked by the running store.
+    assert!(migrate(DbLayout::Split).is_err());
+    assert!(!db_dir.path().join("unified_db").exists());
+    drop(store);
+
+    assert!(migrate(DbLayout::Unified).is_err());
+    let migration = migrate(DbLayout::Split).unwrap();
+    assert_eq!(migration.layout, DbLayout::Unified);
+    assert_eq!(migration.paths, vec![db_dir.path().join("unified_db")]);
+    assert!(migration.num_keys > 0);
+    assert!(!db_dir.path().join("unified_db.migrating").exists());
+    // The migrated dbs are never overwritten.
+    assert!(migrate(DbLayout::Split).is_err());
+
+    let store = open(DbLayout::Unified);
+    assert_eq!(store.get_db_layout(), DbLayout::Unified);
+    assert_eq!(store.get_context().unwrap(), context);
+    assert!(store.check_tx_completed(2).unwrap());
+    assert!(store.get_chunk_by_tx_and_index(2, 0).unwrap().is_some());
+
+    assert!(migrate_db_layout(DbEngine::Memory, DbLayout::Split, db_dir.path()).is_err());
+}
+
+#[test]
+fn test_open_read_only_with_writer() {
+    let db_dir = TempDir::new().unwrap();
+    let db = StoreHandles::open(DbEngine::RocksDb, DbLayout::Split, db_dir.path()).unwrap();
+    let mut writer = LogManager::with_dbs(db, LogConfig::default()).unwrap();
+    for seq in 0..2 {
+        put_tx(&mut writer, 3 * PORA_CHUNK_SIZE, seq);
+    }
+
+    // The dbs are locked by the writer.
+    assert!(StoreHandles::open(DbEngine::RocksDb, DbLayout::Split, db_dir.path()).is_err());
+    let open_reader = || {
+        let db = StoreHandles::open_read_only(DbEngine::RocksDb, DbLayout::Split, db_dir.path())
+            .unwrap();
+        LogManager::with_dbs(db, LogConfig::default()).unwrap()
+    };
+    let reader = open_reader();
+    assert_eq!(reader.get_context().unwrap(), writer.get_context().unwrap());
+    assert!(reader.check_tx_completed(1).unwrap());
+    assert!(reader.get_chunk_by_tx_and_index(1, 0).unwrap().is_some());
+    let flow_len = reader.get_context().unwrap().1;
+    assert!(reader.put_tx(new_tx(flow_len, 1, 2).0).is_err());
+
+    // The reader serves the snapshot when opened, while the writer goes on.
+    put_tx(&mut writer, 3 * PORA_CHUNK_SIZE, 2);
+    assert_eq!(reader.next_tx_seq(), 2);
+    assert!(reader.get_tx_by_seq_number(2).unwrap().is_none());
+    assert!(open_reader().check_tx_completed(2).unwrap());
+}
+
+/// Creates a store of 3 finalized txs, where tx 0 is submitted in block 10, and txs 1 and 2 are
+/// submitted in block 11.
+fn create_checked_store(db: &TestDb) -> (Arc<dyn ZgsKeyValueDB>, Arc<dyn ZgsKeyValueDB>) {
+    let flow_db = db.create_db(COL_NUM);
+    let data_db = db.create_db(COL_NUM);
+    let mut store = LogManager::with_dbs(
+        StoreHandles::split(flow_db.clone(), data_db.clone()),
+        LogConfig::default(),
+    )
+    .unwrap();
+    for seq in 0..3 {
+        put_tx(&mut store, 3 * PORA_CHUNK_SIZE, seq);
+    }
+    for (block_number, first_submission_index) in [(10, Some(0)), (11, Some(1)), (12, None)] {
+        store
+            .put_sync_progress((
+                block_number,
+                H256::from_low_u64_be(block_number),
+                Some(first_submission_index),
+            ))
+            .unwrap();
+    }
+    (flow_db, data_db)
+}
+
+fn check(
+    flow_db: &Arc<dyn ZgsKeyValueDB>,
+    data_db: &Arc<dyn ZgsKeyValueDB>,
+    repair: bool,
+) -> CheckReport {
+    check_db(
+        StoreHandles::split(flow_db.clone(), data_db.clone()),
+        LogConfig::default(),
+        ShardConfig::default(),
+        repair,
+    )
+}
+
+/// Creates dbs of the engine to test, which are removed once dropped.
+struct TestDb {
+    engine: DbEngine,
+    dir: TempDir,
+    num_dbs: AtomicUsize,
+}
+
+impl TestDb {
+    fn new(engine: DbEngine) -> Self {
+        TestDb {
+            engine,
+            dir: tempfile::tempdir().unwrap(),
+            num_dbs: AtomicUsize::new(0),
+        }
+    }
+
+    fn create_db(&self, num_cols: u32) -> Arc<dyn ZgsKeyValueDB> {
+        let index = self.num_dbs.fetch_add(1, Ordering::SeqCst);
+        let path = self.dir.path().join(format!("db_{}", index));
+        open_kvdb(self.engine, path, num_cols).unwrap()
+    }
+
+    fn create_store(&self) -> LogManager {
+        LogManager::with_dbs(
+            StoreHandles::split(self.create_db(COL_NUM), self.create_db(COL_NUM)),
+            LogConfig::default(),
+        )
+        .unwrap()
+    }
+}
+
+/// Runs the shared tests against all db engines, e.g. `rocksdb::test_put_get`.
+macro_rules! engine_tests {
+    ($($test:ident),* $(,)?) => {
+        mod memory {
+            $(
+                #[test]
+                fn $test() {
+                    super::$test(&super::TestDb::new(crate::DbEngine::Memory));
+                }
+            )*
+        }
+
+        mod rocksdb {
+            $(
+                #[test]
+                fn $test() {
+                    super::$test(&super::TestDb::new(crate::DbEngine::RocksDb));
+                }
+            )*
+        }
+
+        #[cfg(feature = "sled-backend")]
+        mod sled {
+            $(
+                #[test]
+                fn $test() {
+                    super::$test(&super::TestDb::new(crate::DbEngine::Sled));
+                }
+            )*
+        }
+    };
+}
+
+engine_tests!(
+    test_put_get,
+    test_multi_tx,
+    test_revert,
+    test_revert_history,
+    test_revert_journal,
+    test_submission_context,
+    test_data_root_first_seen,
+    test_finalize_tx_in_shard,
+    test_miner_rewards,
+    test_iter_block_hashes_rev,
+    test_put_tx,
+    test_has_chunks_by_tx_and_index_range,
+    test_put_tx_overlapped,
+    test_put_tx_inconsistent_size,
+    test_get_txs_by_data_roots,
+    test_compact_pruned_data_roots,
+    test_data_root_index_append,
+    test_get_txs_with_status,
+    test_get_db_column_stats,
+    test_get_merkle_state,
+    test_verify_and_reset_tx_data,
+    test_verify_flow_range,
+    test_audit_finalized_txs,
+    test_sample_stored_chunks,
+    test_get_flow_entries_with_proof,
+    test_get_sealed_chunk_with_proof,
+    test_batched_seal_results_after_crash,
+    test_flush_journal_after_crash,
+    test_flush_journal_reverted,
+    test_finalize_txs,
+    test_finalize_txs_with_hash,
+    test_finalize_txs_after_crash,
+    test_store_errors,
+    test_put_invalid_tx,
+    test_stale_tx_status,
+    test_check_db_healthy,
+    test_check_db_missing_column,
+    test_check_db_corrupted_tx,
+    test_check_db_corrupted_merkle,
+    test_check_db_not_repairable,
+    test_unified_layout,
+    test_lazy_flow_tree_load,
+    test_check_network_id,
+);
+
+fn put_tx(store: &mut LogManager, chunk_count: usize, seq: u64) {
+    let (tx, data) = put_tx_without_data(store, chunk_count, seq);
+    for start_index in (0..chunk_count).step_by(PORA_CHUNK_SIZE) {
+        let end = cmp::min((start_index + PORA_CHUNK_SIZE) * CHUNK_SIZE, data.len());
+        let chunk_array = ChunkArray {
+            data: data[start_index * CHUNK_SIZE..end].to_vec(),
+            start_index: start_index as u64,
+        };
+        store.put_chunks(tx.seq, chunk_array.clone()).unwrap();
+    }
+    store.finalize_tx(tx.seq).unwrap();
+}
+
+fn put_tx_without_data(
+    store: &mut LogManager,
+    chunk_count: usize,
+    seq: u64,
+) -> (Transaction, Vec<u8>) {
+    let (tx, data) = new_tx(store.get_context().unwrap().1, chunk_count, seq);
+    store.put_tx(tx.clone()).unwrap();
+    (tx, data)
+}
+
+/// Creates a tx of `chunk_count` chunks appended to the flow of `flow_len` entries.
+fn new_tx(flow_len: u64, chunk_count: usize, seq: u64) -> (Transaction, Vec<u8>) {
+    let data_size = CHUNK_SIZE * chunk_count;
+    let mut data = vec![0u8; data_size];
+    for i in 0..chunk_count {
+        data[i * CHUNK_SIZE..(i * CHUNK_SIZE + 8)].copy_from_slice(&(seq + 1).to_be_bytes());
+    }
+    let tx_merkle = sub_merkle_tree(&data).unwrap();
+    let merkle_nodes = tx_subtree_root_list_padded(&data);
+    let first_subtree_size = 1 << (merkle_nodes.first().unwrap().0 - 1);
+    let start_entry_index = ((flow_len - 1) / first_subtree_size + 1) * first_subtree_size;
+    let tx = TransactionBuilder::new(seq)
+        .size(data_size as u64)
+        .data_merkle_root(tx_merkle.root().into())
+        .start_entry_index(start_entry_index)
+        // TODO: This can come from `tx_merkle`.
+        .merkle_nodes(merkle_nodes)
+        .build()
+        .unwrap();
+    (tx, data)
 }
@@ -0,0 +1,112 @@
+//! Readiness gate of the flow merkle tree, so that the node could start serving reads that do
+//! not need the tree, e.g. chunks of finalized files, while the tree is loaded in background.
+
+use parking_lot::{Condvar, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread::{self, ThreadId};
+#[cfg(feature = "runtime")]
+use tokio::sync::watch;
+
+#[derive(Default)]
+struct GateState {
+    ready: bool,
+    /// Thread that loads the tree, which could lock the tree before ready.
+    loader: Option<ThreadId>,
+}
+
+/// Lock of the flow merkle tree, which waits until the tree is ready to lock it. Writes are
+/// queued this way while the tree is loading, since they all update the tree.
+///
+/// Async callers should wait for `subscribe_ready` before locking the tree. Otherwise, a tokio
+/// worker waiting here hands off its other tasks, e.g. status queries, to another worker.
+pub(crate) struct TreeGate<T> {
+    lock: RwLock<T>,
+    state: Mutex<GateState>,
+    ready_cond: Condvar,
+    #[cfg(feature = "runtime")]
+    ready_send: watch::Sender<bool>,
+}
+
+impl<T> TreeGate<T> {
+    /// Creates the gate of `value`, which must be loaded via `begin_load` and `set_ready`
+    /// before locked by other threads if not `ready`.
+    pub fn new(value: T, ready: bool) -> Self {
+        Self {
+            lock: RwLock::new(value),
+            state: Mutex::new(GateState {
+                ready,
+                loader: None,
+            }),
+            ready_cond: Condvar::new(),
+            #[cfg(feature = "runtime")]
+            ready_send: watch::channel(ready).0,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state.lock().ready
+    }
+
+    /// Returns a receiver that is updated to `true` once the tree is ready.
+    #[cfg(feature = "runtime")]
+    pub fn subscribe_ready(&self) -> watch::Receiver<bool> {
+        self.ready_send.subscribe()
+    }
+
+    /// Allows the current thread to lock the tree to load it.
+    pub fn begin_load(&self) {
+        self.state.lock().loader = Some(thread::current().id());
+    }
+
+    /// Marks the tree loaded, and wakes up the threads waiting to lock it.
+    pub fn set_ready(&self) {
+        let mut state = self.state.lock();
+        state.ready = true;
+        state.loader = None;
+        self.ready_cond.notify_all();
+        #[cfg(feature = "runtime")]
+        self.ready_send.send_replace(true);
+    }
+
+    fn can_lock(state: &GateState) -> bool {
+        state.ready || state.loader == Some(thread::current().id())
+    }
+
+    fn wait_ready(&self) {
+        if Self::can_lock(&self.state.lock()) {
+            return;
+        }
+
+        #[cfg(feature = "runtime")]
+        if is_async_worker() {
+            return tokio::task::block_in_place(|| self.wait_ready_blocking());
+        }
+
+        self.wait_ready_blocking();
+    }
+
+    fn wait_ready_blocking(&self) {
+        let mut state = self.state.lock();
+        while !Self::can_lock(&state) {
+            self.ready_cond.wait(&mut state);
+        }
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.wait_ready();
+        self.lock.write()
+    }
+
+    pub fn read_recursive(&self) -> RwLockReadGuard<'_, T> {
+        self.wait_ready();
+        self.lock.read_recursive()
+    }
+}
+
+/// Returns whether the current thread could be a worker of a multi-thread tokio runtime, of which
+/// the other tasks could be handed off by `block_in_place`.
+#[cfg(feature = "runtime")]
+fn is_async_worker() -> bool {
+    tokio::runtime::Handle::try_current().map_or(false, |handle| {
+        handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread
+    })
+}
@@ -1,32 +1,57 @@
-use crate::error::Error;
+use crate::error::StoreError;
 use crate::log_store::log_manager::{
-    data_to_merkle_leaves, sub_merkle_tree, COL_BLOCK_PROGRESS, COL_MISC, COL_TX, COL_TX_COMPLETED,
-    COL_TX_DATA_ROOT_INDEX, ENTRY_SIZE, PORA_CHUNK_SIZE,
+    data_to_merkle_leaves, COL_BLOCK_PROGRESS, COL_FLUSH_JOURNAL, COL_MISC, COL_TX,
+    COL_TX_COMPLETED, COL_TX_DATA_ROOT_INDEX, COL_TX_FIRST_SEEN, COL_TX_SUBMISSION,
+    PORA_CHUNK_SIZE,
 };
 use crate::log_store::metrics;
-use crate::{try_option, LogManager, ZgsKeyValueDB};
-use anyhow::{anyhow, Result};
+use crate::{try_option, LogManager, StoreHandles, ZgsKeyValueDB};
+use anyhow::{anyhow, bail, Result};
 use append_merkle::{AppendMerkleTree, MerkleTreeRead, Sha3Algorithm};
 use ethereum_types::H256;
+use kvdb::DBTransaction;
 use merkle_light::merkle::log2_pow2;
+use parking_lot::Mutex;
 use shared_types::{DataRoot, Transaction};
 use ssz::{Decode, Encode};
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
 use std::cmp;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use tracing::{error, instrument};
 
 const LOG_SYNC_PROGRESS_KEY: &str = "log_sync_progress";
 const NEXT_TX_KEY: &str = "next_tx_seq";
 const LOG_LATEST_BLOCK_NUMBER_KEY: &str = "log_latest_block_number_key";
+/// Set once the data roots pruned before compacted on prune are all compacted.
+const DATA_ROOT_INDEX_COMPACTED_KEY: &str = "data_root_index_compacted";
+/// The seq list of a data root is stored as the base list under the data root, encoded in ssz as
+/// the concatenation of the little-endian seqs, followed by the seqs appended since, each under
+/// the data root and the big-endian seq, so that a tx is appended without reading or rewriting
+/// the list. The last seq and the number of seqs are kept under the data root and the tail
+/// suffix.
+const TX_SEQ_SIZE: usize = 8;
+const DATA_ROOT_SIZE: usize = 32;
+const DATA_ROOT_SEQ_KEY_SIZE: usize = DATA_ROOT_SIZE + TX_SEQ_SIZE;
+const DATA_ROOT_TAIL_SUFFIX: u8 = 0xff;
+const TAIL_KEY_SIZE: usize = DATA_ROOT_SIZE + 1;
+/// Blocks with the same number except the lowest bits are read at once when the block hashes
+/// are iterated backwards, since the kv db only iterates forwards.
+const BLOCK_HASHES_BUCKET_BITS: u32 = 16;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TxStatus {
     Finalized,
     Pruned,
+    /// All the entries of the tx in the shard of the node are stored, while the others are
+    /// served by the nodes of other shards.
+    ShardFinalized,
+    /// Rejected by the ingest policy of the node, e.g. too large or from a denied sender, so
+    /// the data is never stored, synced or served. The tx still occupies its flow range by its
+    /// subtree roots, as the padding data does.
+    Invalid,
 }
 
 impl From<TxStatus> for u8 {
@@ -34,6 +59,8 @@ impl From<TxStatus> for u8 {
         match value {
             TxStatus::Finalized => 0,
             TxStatus::Pruned => 1,
+            TxStatus::ShardFinalized => 2,
+            TxStatus::Invalid => 3,
         }
     }
 }
@@ -45,7 +72,12 @@ impl TryFrom<u8> for TxStatus {
         match value {
             0 => Ok(TxStatus::Finalized),
             1 => Ok(TxStatus::Pruned),
-            _ => Err(anyhow!("invalid value for tx status {}", value)),
+            2 => Ok(TxStatus::ShardFinalized),
+            3 => Ok(TxStatus::Invalid),
+            _ => Err(StoreError::Corrupt {
+                reason: format!("invalid value for tx status {}", value),
+            }
+            .into()),
         }
     }
 }
@@ -56,71 +88,186 @@ pub struct BlockHashAndSubmissionIndex {
     pub first_submission_index: Option<u64>,
 }
 
+/// Context of the `Submit` event on chain that created the tx, which is kept apart from the tx
+/// so that the encoding of txs is unchanged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct SubmissionContext {
+    pub block_number: u64,
+    pub tx_hash: H256,
+}
+
+/// Chunk range `[start, end)` in a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct ChunkRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Records the chunk ranges of a file to write before the chunk pool flushes the cached
+/// segments into store, so that a half-written file could be recovered after restart.
+///
+/// The journal is removed once the tx finalized or reverted, or reconciled after restart.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct FlushJournal {
+    pub tx_seq: u64,
+    pub data_root: DataRoot,
+    pub ranges: Vec<ChunkRange>,
+}
+
 pub struct TransactionStore {
-    flow_kvdb: Arc<dyn ZgsKeyValueDB>,
-    data_kvdb: Arc<dyn ZgsKeyValueDB>,
+    db: StoreHandles,
     /// This is always updated before writing the database to ensure no intermediate states.
     next_tx_seq: AtomicU64,
+    /// Held to update the seq lists of data roots, of which the tails are read and written back.
+    data_root_index_lock: Mutex<()>,
+    /// Seq of the tx whose status is stored without the tx, which is removed once the tx put
+    /// without status. It happens if stopped between the writes of split dbs, see
+    /// `put_tx_light_with_status`.
+    stale_status_seq: Mutex<Option<u64>>,
 }
 
 impl TransactionStore {
-    pub fn new(
-        flow_kvdb: Arc<dyn ZgsKeyValueDB>,
-        data_kvdb: Arc<dyn ZgsKeyValueDB>,
-    ) -> Result<Self> {
-        let next_tx_seq = flow_kvdb
-            .get(COL_TX, NEXT_TX_KEY.as_bytes())?
-            .map(|a| decode_tx_seq(&a))
-            .unwrap_or(Ok(0))?;
+    pub fn new(db: StoreHandles) -> Result<Self> {
+        let next_tx_seq = Self::read_next_tx_seq(db.flow().as_ref())?;
+        let stale_status_seq = db
+            .data()
+            .get(COL_TX_COMPLETED, &next_tx_seq.to_be_bytes())?
+            .map(|_| next_tx_seq);
         Ok(Self {
-            flow_kvdb,
-            data_kvdb,
+            db,
             next_tx_seq: AtomicU64::new(next_tx_seq),
+            data_root_index_lock: Default::default(),
+            stale_status_seq: Mutex::new(stale_status_seq),
         })
     }
 
     #[instrument(skip(self))]
     /// Return `Ok(Some(tx_seq))` if a previous transaction has the same tx root.
-    pub fn put_tx(&self, mut tx: Transaction) -> Result<Vec<u64>> {
+    pub fn put_tx(&self, tx: Transaction) -> Result<Vec<u64>> {
+        let data_root = tx.data_merkle_root;
+        let count = self.put_tx_light(tx)?;
+        // The txs are put in order, so the previous txs are the first ones of the list.
+        let mut old_tx_seq_list = self.get_tx_seq_list_by_data_root(&data_root)?;
+        old_tx_seq_list.truncate(count);
+        Ok(old_tx_seq_list)
+    }
+
+    /// Same as `put_tx`, but only return the number of the previous txs with the same data root,
+    /// so the seq list is never read, which is costly for a data root with lots of txs.
+    pub fn put_tx_light(&self, tx: Transaction) -> Result<usize> {
+        self.put_tx_light_with_status(tx, None)
+    }
+
+    /// Same as `put_tx_light`, but also puts the status of the tx in the same write, e.g. an
+    /// invalid tx. The status is written first if the dbs are split.
+    pub fn put_tx_light_with_status(
+        &self,
+        tx: Transaction,
+        status: Option<TxStatus>,
+    ) -> Result<usize> {
         let start_time = Instant::now();
 
-        let old_tx_seq_list = self.get_tx_seq_list_by_data_root(&tx.data_merkle_root)?;
-        if old_tx_seq_list.last().is_some_and(|seq| *seq == tx.seq) {
-            // The last tx is inserted again, so no need to process it.
-            self.next_tx_seq.store(tx.seq + 1, Ordering::SeqCst);
-            return Ok(old_tx_seq_list);
+        let mut data_db_tx = self.db.data().transaction();
+        match status {
+            Some(status) => {
+                data_db_tx.put(COL_TX_COMPLETED, &tx.seq.to_be_bytes(), &[status.into()]);
+                data_db_tx.delete(COL_FLUSH_JOURNAL, &tx.seq.to_be_bytes());
+            }
+            None => {
+                let mut stale_status_seq = self.stale_status_seq.lock();
+                if *stale_status_seq == Some(tx.seq) {
+                    data_db_tx.delete(COL_TX_COMPLETED, &tx.seq.to_be_bytes());
+                    *stale_status_seq = None;
+                }
+            }
         }
 
-        let mut db_tx = self.flow_kvdb.transaction();
-        if !tx.data.is_empty() {
-            tx.size = tx.data.len() as u64;
-            let mut padded_data = tx.data.clone();
-            let extra = tx.data.len() % ENTRY_SIZE;
-            if extra != 0 {
-                padded_data.append(&mut vec![0u8; ENTRY_SIZE - extra]);
+        let _index_lock = self.data_root_index_lock.lock();
+        let (old_last, old_count) = self.get_data_root_tail(&tx.data_merkle_root)?;
+        if tx.seq < self.next_tx_seq() {
+            // The tx is inserted again, e.g. on recovery, which never changes its data root.
+            if let Some(old_tx) = self.get_tx_by_seq_number(tx.seq)? {
+                if old_tx.data_merkle_root != tx.data_merkle_root {
+                    bail!(StoreError::Corrupt {
+                        reason: format!(
+                            "data root of tx {} mismatch, stored={:?} new={:?}",
+                            tx.seq, old_tx.data_merkle_root, tx.data_merkle_root
+                        ),
+                    });
+                }
             }
-            let data_root = sub_merkle_tree(&padded_data)?.root();
-            tx.data_merkle_root = data_root.into();
+        }
+        if old_last == Some(tx.seq) {
+            // The last tx is inserted again, so no need to process it except its status.
+            self.next_tx_seq.store(tx.seq + 1, Ordering::SeqCst);
+            if !data_db_tx.ops.is_empty() {
+                self.db.data().write(data_db_tx)?;
+            }
+            return Ok(old_count);
         }
 
+        let mut db_tx = self.db.flow().transaction();
         db_tx.put(COL_TX, &tx.seq.to_be_bytes(), &tx.as_ssz_bytes());
         db_tx.put(COL_TX, NEXT_TX_KEY.as_bytes(), &(tx.seq + 1).to_be_bytes());
         // The list is sorted, and we always call `put_tx` in order.
-        assert!(old_tx_seq_list
-            .last()
-            .map(|last| *last < tx.seq)
-            .unwrap_or(true));
-        let mut new_tx_seq_list = old_tx_seq_list.clone();
-        new_tx_seq_list.push(tx.seq);
+        if let Some(last) = old_last {
+            if last > tx.seq {
+                bail!(
+                    "tx seq {} is put out of order, last tx seq of the data root is {}",
+                    tx.seq,
+                    last
+                );
+            }
+        }
+        let data_root = tx.data_merkle_root.as_bytes();
         db_tx.put(
             COL_TX_DATA_ROOT_INDEX,
-            tx.data_merkle_root.as_bytes(),
-            &new_tx_seq_list.as_ssz_bytes(),
+            &data_root_seq_key(data_root, tx.seq),
+            &[],
         );
+        db_tx.put(
+            COL_TX_DATA_ROOT_INDEX,
+            &data_root_tail_key(data_root),
+            &encode_data_root_tail(tx.seq, old_count + 1),
+        );
+        // Re-submissions of the data root never reset the time first seen.
+        if old_count == 0 {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            db_tx.put(
+                COL_TX_FIRST_SEEN,
+                tx.data_merkle_root.as_bytes(),
+                &now.to_be_bytes(),
+            );
+        }
         self.next_tx_seq.store(tx.seq + 1, Ordering::SeqCst);
-        self.flow_kvdb.write(db_tx)?;
+        self.db.write_both(db_tx, data_db_tx)?;
         metrics::TX_STORE_PUT.update_since(start_time);
-        Ok(old_tx_seq_list)
+        Ok(old_count)
+    }
+
+    /// Return the last seq and the number of seqs of the data root from its tail, or from the
+    /// base list without decoding it if the tail is not kept yet, e.g. stored by an old version.
+    fn get_data_root_tail(&self, data_root: &DataRoot) -> Result<(Option<u64>, usize)> {
+        let data_root = data_root.as_bytes();
+        let flow_kvdb = self.db.flow();
+        if let Some(value) =
+            flow_kvdb.get(COL_TX_DATA_ROOT_INDEX, &data_root_tail_key(data_root))?
+        {
+            let (last, count) = decode_data_root_tail(&value)?;
+            return Ok((Some(last), count));
+        }
+        let base = flow_kvdb
+            .get(COL_TX_DATA_ROOT_INDEX, data_root)?
+            .unwrap_or_default();
+        let count = base.len() / TX_SEQ_SIZE;
+        let last = match count {
+            0 => None,
+            count => encoded_tx_seq_at(&base, count - 1)?,
+        };
+        Ok((last, count))
     }
 
     pub fn get_tx_by_seq_number(&self, seq: u64) -> Result<Option<Transaction>> {
@@ -128,17 +275,33 @@ impl TransactionStore {
         if seq >= self.next_tx_seq() {
             return Ok(None);
         }
-        let value = try_option!(self.flow_kvdb.get(COL_TX, &seq.to_be_bytes())?);
-        let tx = Transaction::from_ssz_bytes(&value).map_err(Error::from)?;
+        let value = try_option!(self.db.flow().get(COL_TX, &seq.to_be_bytes())?);
+        let tx = Transaction::from_ssz_bytes(&value).map_err(StoreError::from)?;
         metrics::TX_BY_SEQ_NUMBER.update_since(start_time);
         Ok(Some(tx))
     }
 
+    /// Iterate the txs and their status from `start_seq` in ascending order.
+    /// Every item is read from the database when it is requested, so the iteration does not
+    /// block writes.
+    pub fn iter_tx_with_status(
+        &self,
+        start_seq: u64,
+    ) -> impl Iterator<Item = Result<(Transaction, Option<TxStatus>)>> + '_ {
+        (start_seq..).map_while(move |seq| match self.get_tx_by_seq_number(seq) {
+            Ok(Some(tx)) => Some(self.get_tx_status(seq).map(|status| (tx, status))),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        })
+    }
+
     pub fn remove_tx_after(&self, min_seq: u64) -> Result<Vec<Transaction>> {
+        let start_time = Instant::now();
+        let _index_lock = self.data_root_index_lock.lock();
         let mut removed_txs = Vec::new();
         let max_seq = self.next_tx_seq();
-        let mut flow_db_tx = self.flow_kvdb.transaction();
-        let mut data_db_tx = self.data_kvdb.transaction();
+        let mut flow_db_tx = self.db.flow().transaction();
+        let mut data_db_tx = self.db.data().transaction();
         let mut modified_merkle_root_map = HashMap::new();
         for seq in min_seq..max_seq {
             let Some(tx) = self.get_tx_by_seq_number(seq)? else {
@@ -146,7 +309,9 @@ impl TransactionStore {
                 break;
             };
             flow_db_tx.delete(COL_TX, &seq.to_be_bytes());
+            flow_db_tx.delete(COL_TX_SUBMISSION, &seq.to_be_bytes());
             data_db_tx.delete(COL_TX_COMPLETED, &seq.to_be_bytes());
+            data_db_tx.delete(COL_FLUSH_JOURNAL, &seq.to_be_bytes());
             // We only remove tx when the blockchain reorgs.
             // If a tx is reverted, all data after it will also be reverted, so we call remove
             // all indices after it.
@@ -160,55 +325,321 @@ impl TransactionStore {
             removed_txs.push(tx);
         }
         for (merkle_root, tx_seq_list) in modified_merkle_root_map {
+            put_tx_seq_list(&mut flow_db_tx, merkle_root.as_bytes(), &tx_seq_list);
             if tx_seq_list.is_empty() {
-                flow_db_tx.delete(COL_TX_DATA_ROOT_INDEX, merkle_root.as_bytes());
-            } else {
-                flow_db_tx.put(
-                    COL_TX_DATA_ROOT_INDEX,
-                    merkle_root.as_bytes(),
-                    &tx_seq_list.as_ssz_bytes(),
-                );
+                flow_db_tx.delete(COL_TX_FIRST_SEEN, merkle_root.as_bytes());
             }
         }
         flow_db_tx.put(COL_TX, NEXT_TX_KEY.as_bytes(), &min_seq.to_be_bytes());
         self.next_tx_seq.store(min_seq, Ordering::SeqCst);
-        self.data_kvdb.write(data_db_tx)?;
-        self.flow_kvdb.write(flow_db_tx)?;
+        self.db.write_both(flow_db_tx, data_db_tx)?;
+        metrics::REMOVE_TX_AFTER.update_since(start_time);
         Ok(removed_txs)
     }
 
+    /// Returns the unix timestamp in seconds when a tx of the data root is put first, which is
+    /// kept until all txs of the data root are reverted.
+    pub fn get_first_seen(&self, data_root: &DataRoot) -> Result<Option<u64>> {
+        let value = try_option!(self
+            .db
+            .flow()
+            .get(COL_TX_FIRST_SEEN, data_root.as_bytes())?);
+        let timestamp = value
+            .as_slice()
+            .try_into()
+            .map_err(|_| StoreError::Corrupt {
+                reason: format!("invalid first seen time of data root {:?}", data_root),
+            })?;
+        Ok(Some(u64::from_be_bytes(timestamp)))
+    }
+
     pub fn get_tx_seq_list_by_data_root(&self, data_root: &DataRoot) -> Result<Vec<u64>> {
-        let value = match self
-            .flow_kvdb
-            .get(COL_TX_DATA_ROOT_INDEX, data_root.as_bytes())?
+        self.iter_tx_seq_by_data_root(data_root).collect()
+    }
+
+    /// Iterate the seqs of the data root in ascending order, of which the appended ones are read
+    /// from the database when requested.
+    fn iter_tx_seq_by_data_root<'a>(
+        &'a self,
+        data_root: &'a DataRoot,
+    ) -> impl Iterator<Item = Result<u64>> + 'a {
+        self.db
+            .flow()
+            .iter_with_prefix(COL_TX_DATA_ROOT_INDEX, data_root.as_bytes())
+            .flat_map(|r| -> Vec<Result<u64>> {
+                let (key, value) = match r {
+                    Ok(kv) => kv,
+                    Err(e) => return vec![Err(e.into())],
+                };
+                match key.len() {
+                    DATA_ROOT_SIZE => match Vec::<u64>::from_ssz_bytes(&value) {
+                        Ok(base) => base.into_iter().map(Ok).collect(),
+                        Err(e) => vec![Err(StoreError::from(e).into())],
+                    },
+                    DATA_ROOT_SEQ_KEY_SIZE => vec![decode_tx_seq(&key[DATA_ROOT_SIZE..])],
+                    // The tail
+                    _ => vec![],
+                }
+            })
+    }
+
+    /// Return the seqs of the data root except the pruned or invalid ones, e.g. to copy the data
+    /// between.
+    pub fn get_live_tx_seq_list_by_data_root(&self, data_root: &DataRoot) -> Result<Vec<u64>> {
+        let mut live = Vec::new();
+        for tx_seq in self.get_tx_seq_list_by_data_root(data_root)? {
+            if self.check_tx_live(tx_seq)? {
+                live.push(tx_seq);
+            }
+        }
+        Ok(live)
+    }
+
+    /// Return the first seq of the data root except `except_seq` neither pruned nor invalid, and
+    /// the seqs after it are never read.
+    pub fn get_first_live_tx_seq_by_data_root(
+        &self,
+        data_root: &DataRoot,
+        except_seq: u64,
+    ) -> Result<Option<u64>> {
+        for tx_seq in self.iter_tx_seq_by_data_root(data_root) {
+            let tx_seq = tx_seq?;
+            if tx_seq != except_seq && self.check_tx_live(tx_seq)? {
+                return Ok(Some(tx_seq));
+            }
+        }
+        Ok(None)
+    }
+
+    fn check_tx_live(&self, tx_seq: u64) -> Result<bool> {
+        Ok(!matches!(
+            self.get_tx_status(tx_seq)?,
+            Some(TxStatus::Pruned | TxStatus::Invalid)
+        ))
+    }
+
+    /// Select the tx of the data root from its seq list to serve lookups by data root, which is
+    /// the first finalized one, or the first neither pruned nor invalid. Only if none is left,
+    /// the last one is selected, so that the root is reported pruned rather than unknown.
+    pub fn select_tx_seq(&self, seq_list: &[u64]) -> Result<Option<u64>> {
+        let mut first_live = None;
+        for tx_seq in seq_list {
+            match self.get_tx_status(*tx_seq)? {
+                Some(TxStatus::Finalized | TxStatus::ShardFinalized) => return Ok(Some(*tx_seq)),
+                Some(TxStatus::Pruned | TxStatus::Invalid) => {}
+                None => {
+                    first_live.get_or_insert(*tx_seq);
+                }
+            }
+        }
+        Ok(first_live.or_else(|| seq_list.last().cloned()))
+    }
+
+    /// Rewrite the seq list of the data root to the last seq only if all the txs are pruned,
+    /// so that the seqs of pruned data never accumulate. Return whether rewritten.
+    pub fn compact_data_root_index(&self, data_root: &DataRoot) -> Result<bool> {
+        let _index_lock = self.data_root_index_lock.lock();
+        let seq_list = self.get_tx_seq_list_by_data_root(data_root)?;
+        if seq_list.len() <= 1 {
+            return Ok(false);
+        }
+        for tx_seq in &seq_list {
+            if !self.check_tx_pruned(*tx_seq)? {
+                return Ok(false);
+            }
+        }
+        let last = seq_list[seq_list.len() - 1];
+        let mut db_tx = self.db.flow().transaction();
+        put_tx_seq_list(&mut db_tx, data_root.as_bytes(), &[last]);
+        self.db.flow().write(db_tx)?;
+        metrics::DATA_ROOT_INDEX_COMPACTED.inc(1);
+        Ok(true)
+    }
+
+    /// Compact the seq lists of all data roots pruned before the lists are compacted on prune,
+    /// and return the number of data roots compacted. The roots are scanned only once, and
+    /// never again once completed.
+    pub fn compact_pruned_data_roots(&self) -> Result<usize> {
+        if self
+            .db
+            .flow()
+            .get(COL_MISC, DATA_ROOT_INDEX_COMPACTED_KEY.as_bytes())?
+            .is_some()
         {
-            Some(v) => v,
-            None => return Ok(Vec::new()),
-        };
-        Ok(Vec::<u64>::from_ssz_bytes(&value).map_err(Error::from)?)
+            return Ok(0);
+        }
+
+        let mut roots = BTreeSet::new();
+        for r in self.db.flow().iter(COL_TX_DATA_ROOT_INDEX) {
+            let (key, value) = r?;
+            // Roots of a single tx are never compacted.
+            let multiple = match key.len() {
+                DATA_ROOT_SIZE => value.len() > TX_SEQ_SIZE,
+                TAIL_KEY_SIZE => decode_data_root_tail(&value)?.1 > 1,
+                _ => false,
+            };
+            if multiple {
+                roots.insert(DataRoot::from_slice(&key[..DATA_ROOT_SIZE]));
+            }
+        }
+        let mut compacted = 0;
+        for root in roots {
+            if self.compact_data_root_index(&root)? {
+                compacted += 1;
+            }
+        }
+        self.db
+            .flow()
+            .put(COL_MISC, DATA_ROOT_INDEX_COMPACTED_KEY.as_bytes(), &[])?;
+        Ok(compacted)
+    }
+
+    /// Return the first tx seq of the data root without decoding the whole seq list.
+    pub fn get_first_tx_seq_by_data_root(&self, data_root: &DataRoot) -> Result<Option<u64>> {
+        let first = self
+            .db
+            .flow()
+            .iter_with_prefix(COL_TX_DATA_ROOT_INDEX, data_root.as_bytes())
+            .next()
+            .transpose()?;
+        match first {
+            Some((key, value)) if key.len() == DATA_ROOT_SIZE => encoded_tx_seq_at(&value, 0),
+            Some((key, _)) if key.len() == DATA_ROOT_SEQ_KEY_SIZE => {
+                Ok(Some(decode_tx_seq(&key[DATA_ROOT_SIZE..])?))
+            }
+            _ => Ok(None),
+        }
     }
 
+    /// Look up the seq lists of multiple data roots in one call.
+    /// The result is in the same order as `data_roots`, with empty lists for unknown roots.
+    pub fn get_tx_seq_lists_by_data_roots(&self, data_roots: &[DataRoot]) -> Result<Vec<Vec<u64>>> {
+        let start_time = Instant::now();
+        // The base lists and tails of all roots are read at once, and only the roots with seqs
+        // appended since the base list are iterated.
+        let tail_keys: Vec<Vec<u8>> = data_roots
+            .iter()
+            .map(|data_root| data_root_tail_key(data_root.as_bytes()))
+            .collect();
+        let keys: Vec<&[u8]> = data_roots
+            .iter()
+            .map(|data_root| data_root.as_bytes())
+            .chain(tail_keys.iter().map(|key| key.as_slice()))
+            .collect();
+        let values = self.db.flow().multi_get(COL_TX_DATA_ROOT_INDEX, &keys)?;
+        let (bases, tails) = values.split_at(data_roots.len());
+
+        let mut seq_lists = Vec::with_capacity(data_roots.len());
+        for ((data_root, base), tail) in data_roots.iter().zip(bases).zip(tails) {
+            let base = match base {
+                Some(base) => Vec::<u64>::from_ssz_bytes(base).map_err(StoreError::from)?,
+                None => vec![],
+            };
+            let appended = match tail {
+                Some(tail) => decode_data_root_tail(tail)?.1 != base.len(),
+                None => false,
+            };
+            if appended {
+                seq_lists.push(self.get_tx_seq_list_by_data_root(data_root)?);
+            } else {
+                seq_lists.push(base);
+            }
+        }
+        metrics::TX_SEQ_LISTS_BY_DATA_ROOTS.update_since(start_time);
+        Ok(seq_lists)
+    }
+
+    /// Marks the tx as finalized and removes its flush journal atomically.
     #[instrument(skip(self))]
     pub fn finalize_tx(&self, tx_seq: u64) -> Result<()> {
-        Ok(self.data_kvdb.put(
-            COL_TX_COMPLETED,
-            &tx_seq.to_be_bytes(),
-            &[TxStatus::Finalized.into()],
-        )?)
+        self.put_tx_status(tx_seq, TxStatus::Finalized)
+    }
+
+    #[instrument(skip(self))]
+    pub fn finalize_tx_in_shard(&self, tx_seq: u64) -> Result<()> {
+        self.put_tx_status(tx_seq, TxStatus::ShardFinalized)
     }
 
     #[instrument(skip(self))]
     pub fn prune_tx(&self, tx_seq: u64) -> Result<()> {
-        Ok(self.data_kvdb.put(
-            COL_TX_COMPLETED,
-            &tx_seq.to_be_bytes(),
-            &[TxStatus::Pruned.into()],
+        self.put_tx_status(tx_seq, TxStatus::Pruned)
+    }
+
+    fn put_tx_status(&self, tx_seq: u64, status: TxStatus) -> Result<()> {
+        self.put_tx_statuses(&[(tx_seq, status)])
+    }
+
+    /// Puts the statuses of multiple txs and removes their flush journals in one write, e.g.
+    /// to finalize txs in bulk.
+    pub fn put_tx_statuses(&self, statuses: &[(u64, TxStatus)]) -> Result<()> {
+        let mut db_tx = self.db.data().transaction();
+        for (tx_seq, status) in statuses {
+            db_tx.put(COL_TX_COMPLETED, &tx_seq.to_be_bytes(), &[(*status).into()]);
+            db_tx.delete(COL_FLUSH_JOURNAL, &tx_seq.to_be_bytes());
+        }
+        Ok(self.db.data().write(db_tx)?)
+    }
+
+    /// Stores the flush journal of a tx. Ranges are appended if there is already a journal
+    /// of the same file.
+    pub fn put_flush_journal(&self, mut journal: FlushJournal) -> Result<()> {
+        if let Some(old) = self.get_flush_journal(journal.tx_seq)? {
+            if old.data_root == journal.data_root {
+                let mut ranges = old.ranges;
+                for range in journal.ranges {
+                    if !ranges.contains(&range) {
+                        ranges.push(range);
+                    }
+                }
+                journal.ranges = ranges;
+            }
+        }
+
+        Ok(self.db.data().put(
+            COL_FLUSH_JOURNAL,
+            &journal.tx_seq.to_be_bytes(),
+            &journal.as_ssz_bytes(),
         )?)
     }
 
+    pub fn get_flush_journal(&self, tx_seq: u64) -> Result<Option<FlushJournal>> {
+        let value = try_option!(self
+            .db
+            .data()
+            .get(COL_FLUSH_JOURNAL, &tx_seq.to_be_bytes())?);
+        let journal = FlushJournal::from_ssz_bytes(&value).map_err(StoreError::from)?;
+        Ok(Some(journal))
+    }
+
+    pub fn get_flush_journals(&self) -> Result<Vec<FlushJournal>> {
+        let mut journals = Vec::new();
+        for r in self.db.data().iter(COL_FLUSH_JOURNAL) {
+            let (_, value) = r?;
+            journals.push(FlushJournal::from_ssz_bytes(&value).map_err(StoreError::from)?);
+        }
+        Ok(journals)
+    }
+
+    pub fn remove_flush_journal(&self, tx_seq: u64) -> Result<()> {
+        Ok(self
+            .db
+            .data()
+            .delete(COL_FLUSH_JOURNAL, &tx_seq.to_be_bytes())?)
+    }
+
+    /// Clear the finalized status of a tx so that its data could be synced again.
+    #[instrument(skip(self))]
+    pub fn unfinalize_tx(&self, tx_seq: u64) -> Result<()> {
+        Ok(self
+            .db
+            .data()
+            .delete(COL_TX_COMPLETED, &tx_seq.to_be_bytes())?)
+    }
+
     pub fn get_tx_status(&self, tx_seq: u64) -> Result<Option<TxStatus>> {
         let value = try_option!(self
-            .data_kvdb
+            .db
+            .data()
             .get(COL_TX_COMPLETED, &tx_seq.to_be_bytes())?);
         match value.first() {
             Some(v) => Ok(Some(TxStatus::try_from(*v)?)),
@@ -216,12 +647,17 @@ impl TransactionStore {
         }
     }
 
+    /// Return `true` if the tx is finalized, or finalized in the shard of the node, in which
+    /// case no more data of the tx is to be stored by the node.
     pub fn check_tx_completed(&self, tx_seq: u64) -> Result<bool> {
         let start_time = Instant::now();
         let status = self.get_tx_status(tx_seq)?;
 
         metrics::CHECK_TX_COMPLETED.update_since(start_time);
-        Ok(matches!(status, Some(TxStatus::Finalized)))
+        Ok(matches!(
+            status,
+            Some(TxStatus::Finalized | TxStatus::ShardFinalized)
+        ))
     }
 
     pub fn check_tx_pruned(&self, tx_seq: u64) -> Result<bool> {
@@ -229,10 +665,33 @@ impl TransactionStore {
         Ok(matches!(status, Some(TxStatus::Pruned)))
     }
 
+    pub fn check_tx_invalid(&self, tx_seq: u64) -> Result<bool> {
+        let status = self.get_tx_status(tx_seq)?;
+        Ok(matches!(status, Some(TxStatus::Invalid)))
+    }
+
     pub fn next_tx_seq(&self) -> u64 {
         self.next_tx_seq.load(Ordering::SeqCst)
     }
 
+    pub fn put_submission_context(&self, tx_seq: u64, context: &SubmissionContext) -> Result<()> {
+        Ok(self.db.flow().put(
+            COL_TX_SUBMISSION,
+            &tx_seq.to_be_bytes(),
+            &context.as_ssz_bytes(),
+        )?)
+    }
+
+    pub fn get_submission_context(&self, tx_seq: u64) -> Result<Option<SubmissionContext>> {
+        Ok(Some(
+            SubmissionContext::from_ssz_bytes(&try_option!(self
+                .db
+                .flow()
+                .get(COL_TX_SUBMISSION, &tx_seq.to_be_bytes())?))
+            .map_err(StoreError::from)?,
+        ))
+    }
+
     #[instrument(skip(self))]
     pub fn put_progress(&self, progress: (u64, H256, Option<Option<u64>>)) -> Result<()> {
         let mut items = vec![(
@@ -248,22 +707,23 @@ impl TransactionStore {
                 (progress.1, p).as_ssz_bytes(),
             ));
         }
-        Ok(self.flow_kvdb.puts(items)?)
+        Ok(self.db.flow().puts(items)?)
     }
 
     #[instrument(skip(self))]
     pub fn get_progress(&self) -> Result<Option<(u64, H256)>> {
         Ok(Some(
             <(u64, H256)>::from_ssz_bytes(&try_option!(self
-                .flow_kvdb
+                .db
+                .flow()
                 .get(COL_MISC, LOG_SYNC_PROGRESS_KEY.as_bytes())?))
-            .map_err(Error::from)?,
+            .map_err(StoreError::from)?,
         ))
     }
 
     #[instrument(skip(self))]
     pub fn put_log_latest_block_number(&self, block_number: u64) -> Result<()> {
-        Ok(self.flow_kvdb.put(
+        Ok(self.db.flow().put(
             COL_MISC,
             LOG_LATEST_BLOCK_NUMBER_KEY.as_bytes(),
             &block_number.as_ssz_bytes(),
@@ -274,9 +734,10 @@ impl TransactionStore {
     pub fn get_log_latest_block_number(&self) -> Result<Option<u64>> {
         Ok(Some(
             <u64>::from_ssz_bytes(&try_option!(self
-                .flow_kvdb
+                .db
+                .flow()
                 .get(COL_MISC, LOG_LATEST_BLOCK_NUMBER_KEY.as_bytes())?))
-            .map_err(Error::from)?,
+            .map_err(StoreError::from)?,
         ))
     }
 
@@ -286,42 +747,140 @@ impl TransactionStore {
     ) -> Result<Option<(H256, Option<u64>)>> {
         Ok(Some(
             <(H256, Option<u64>)>::from_ssz_bytes(&try_option!(self
-                .flow_kvdb
+                .db
+                .flow()
                 .get(COL_BLOCK_PROGRESS, &block_number.to_be_bytes())?))
-            .map_err(Error::from)?,
+            .map_err(StoreError::from)?,
         ))
     }
 
+    /// Loads all the block hashes into memory, which is only intended for internal tooling, e.g.
+    /// export and db check. Use [`Self::iter_block_hashes_rev`] instead on the sync path.
     pub fn get_block_hashes(&self) -> Result<Vec<(u64, BlockHashAndSubmissionIndex)>> {
         let mut block_numbers = vec![];
-        for r in self.flow_kvdb.iter(COL_BLOCK_PROGRESS) {
+        for r in self.db.flow().iter(COL_BLOCK_PROGRESS) {
             let (key, val) = r?;
-            let block_number =
-                u64::from_be_bytes(key.as_ref().try_into().map_err(|e| anyhow!("{:?}", e))?);
-            let val = <(H256, Option<u64>)>::from_ssz_bytes(val.as_ref()).map_err(Error::from)?;
-
-            block_numbers.push((
-                block_number,
-                BlockHashAndSubmissionIndex {
-                    block_hash: val.0,
-                    first_submission_index: val.1,
-                },
-            ));
+            block_numbers.push(decode_block_hash(&key, &val)?);
         }
 
         Ok(block_numbers)
     }
 
+    /// Iterates the block hashes from `from_block` (inclusive) backwards, which reads at most one
+    /// bucket of blocks at a time. `from_block` is bounded by the sync progress.
+    pub fn iter_block_hashes_rev(&self, from_block: u64) -> BlockHashesRev<'_> {
+        let mut iter = BlockHashesRev {
+            flow_kvdb: self.db.flow().as_ref(),
+            from_block,
+            next_bucket: None,
+            min_bucket: 0,
+            buffered: vec![],
+            error: None,
+        };
+
+        let bounds = self.get_progress().and_then(|progress| {
+            let min_block = match self.db.flow().iter(COL_BLOCK_PROGRESS).next() {
+                Some(r) => Some(decode_block_number(&r?.0)?),
+                None => None,
+            };
+            Ok(progress.zip(min_block))
+        });
+        match bounds {
+            Ok(Some(((progress_block, _), min_block))) if min_block <= from_block => {
+                iter.from_block = cmp::min(from_block, progress_block);
+                iter.next_bucket = Some(iter.from_block >> BLOCK_HASHES_BUCKET_BITS);
+                iter.min_bucket = min_block >> BLOCK_HASHES_BUCKET_BITS;
+            }
+            Ok(_) => {}
+            Err(e) => iter.error = Some(e),
+        }
+
+        iter
+    }
+
+    /// Returns at most `limit` block hashes from `from_block` (inclusive) in ascending order,
+    /// which reads one bucket of blocks at a time. Blocks after the sync progress are ignored.
+    pub fn get_block_hashes_from(
+        &self,
+        from_block: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, BlockHashAndSubmissionIndex)>> {
+        let progress_block = match self.get_progress()? {
+            Some((block_number, _)) => block_number,
+            None => return Ok(vec![]),
+        };
+        let min_block = match self.db.flow().iter(COL_BLOCK_PROGRESS).next() {
+            Some(r) => decode_block_number(&r?.0)?,
+            None => return Ok(vec![]),
+        };
+
+        let from_block = cmp::max(from_block, min_block);
+        let mut blocks = vec![];
+        if from_block > progress_block {
+            return Ok(blocks);
+        }
+        for bucket in
+            (from_block >> BLOCK_HASHES_BUCKET_BITS)..=(progress_block >> BLOCK_HASHES_BUCKET_BITS)
+        {
+            let prefix = block_hashes_bucket_prefix(bucket);
+            let mut buffered = vec![];
+            for r in self.db.flow().iter_with_prefix(COL_BLOCK_PROGRESS, &prefix) {
+                let (key, val) = r?;
+                let (block_number, block) = decode_block_hash(&key, &val)?;
+                if (from_block..=progress_block).contains(&block_number) {
+                    buffered.push((block_number, block));
+                }
+            }
+            buffered.sort_by_key(|(block_number, _)| *block_number);
+
+            for block in buffered {
+                if blocks.len() == limit {
+                    return Ok(blocks);
+                }
+                blocks.push(block);
+            }
+        }
+
+        Ok(blocks)
+    }
+
     pub fn delete_block_hash_by_number(&self, block_number: u64) -> Result<()> {
         Ok(self
-            .flow_kvdb
+            .db
+            .flow()
             .delete(COL_BLOCK_PROGRESS, &block_number.to_be_bytes())?)
     }
 
+    /// Deletes the block hashes before `block_number`, which are expected to be finalized.
+    pub fn delete_block_hashes_before(&self, block_number: u64) -> Result<()> {
+        let min_block = match self.db.flow().iter(COL_BLOCK_PROGRESS).next() {
+            Some(r) => decode_block_number(&r?.0)?,
+            None => return Ok(()),
+        };
+        if min_block >= block_number {
+            return Ok(());
+        }
+
+        // Whole buckets are deleted by prefix, so that the cost does not grow with the blocks.
+        let mut db_tx = self.db.flow().transaction();
+        let bucket = block_number >> BLOCK_HASHES_BUCKET_BITS;
+        for b in (min_block >> BLOCK_HASHES_BUCKET_BITS)..bucket {
+            db_tx.delete_prefix(COL_BLOCK_PROGRESS, &block_hashes_bucket_prefix(b));
+        }
+        for n in cmp::max(bucket << BLOCK_HASHES_BUCKET_BITS, min_block)..block_number {
+            db_tx.delete(COL_BLOCK_PROGRESS, &n.to_be_bytes());
+        }
+        Ok(self.db.flow().write(db_tx)?)
+    }
+
     /// Build the merkle tree at `pora_chunk_index` with the data before (including) `tx_seq`.
     /// This first rebuild the tree with the tx root nodes lists by repeatedly checking previous
     /// until we reach the start of this chunk.
     ///
+    /// A tx subtree larger than a PoRA chunk is replaced by the root of the chunk within it,
+    /// which is loaded from the stored entry batch by `batch_root`. An error is returned if the
+    /// batch is not available, since the chunk root cannot be derived from the tx.
+    ///
     /// Note that this can only be called with the last chunk after some transaction is committed,
     /// otherwise the start of this chunk might be within some tx subtree and this will panic.
     // TODO(zz): Fill the last chunk with data.
@@ -329,49 +888,37 @@ impl TransactionStore {
         &self,
         pora_chunk_index: usize,
         mut tx_seq: u64,
+        batch_root: impl Fn(u64) -> Result<Option<DataRoot>>,
     ) -> Result<AppendMerkleTree<H256, Sha3Algorithm>> {
         let last_chunk_start_index = pora_chunk_index as u64 * PORA_CHUNK_SIZE as u64;
         let mut tx_list = Vec::new();
         // Find the first tx within the last chunk.
         loop {
             let tx = self.get_tx_by_seq_number(tx_seq)?.expect("tx not removed");
+            let subtrees = Self::subtrees_in_chunk(&tx, last_chunk_start_index, &batch_root)?;
             match tx.start_entry_index.cmp(&last_chunk_start_index) {
                 cmp::Ordering::Greater => {
-                    tx_list.push((tx_seq, tx.merkle_nodes));
+                    if !subtrees.is_empty() {
+                        tx_list.push((tx_seq, subtrees));
+                    }
                     if tx.start_entry_index >= last_chunk_start_index + PORA_CHUNK_SIZE as u64 {
                         break;
                     }
                 }
                 cmp::Ordering::Equal => {
-                    tx_list.push((tx_seq, tx.merkle_nodes));
+                    if !subtrees.is_empty() {
+                        tx_list.push((tx_seq, subtrees));
+                    }
                     break;
                 }
                 cmp::Ordering::Less => {
-                    // The transaction data crosses a chunk, so we need to find the subtrees
-                    // within the last chunk.
-                    let mut start_index = tx.start_entry_index;
-                    let mut first_index = None;
-                    for (i, (depth, _)) in tx.merkle_nodes.iter().enumerate() {
-                        start_index += 1 << (depth - 1);
-                        if start_index == last_chunk_start_index {
-                            first_index = Some(i + 1);
-                            break;
-                        }
-                    }
-                    // Some means some subtree ends at the chunk boundary.
-                    // None means there are padding data between the tx data and the boundary,
-                    // so no data belongs to the last chunk.
-                    if let Some(first_index) = first_index {
-                        if first_index != tx.merkle_nodes.len() {
-                            tx_list.push((tx_seq, tx.merkle_nodes[first_index..].to_vec()));
-                        } else {
-                            // If the last subtree ends at the chunk boundary, we also do not need
-                            // to add data of this tx to the last chunk.
-                            // This is only possible if the last chunk is empty, because otherwise
-                            // we should have entered the `Equal` condition before and
-                            // have broken the loop.
-                            assert!(tx_list.is_empty());
-                        }
+                    // The transaction data crosses a chunk, so only the subtrees within the last
+                    // chunk are added.
+                    // Nothing is added if the tx data ends before the chunk boundary, because
+                    // there are padding data between them, or if the last subtree ends at the
+                    // chunk boundary.
+                    if !subtrees.is_empty() {
+                        tx_list.push((tx_seq, subtrees));
                     }
                     break;
                 }
@@ -407,10 +954,344 @@ impl TransactionStore {
         }
         Ok(merkle)
     }
+
+    /// Returns the subtrees of `tx` overlapping the PoRA chunk starting at `chunk_start_index`.
+    ///
+    /// Subtrees are aligned with their size, so a subtree larger than a chunk covers the whole
+    /// chunk, and it's replaced by the chunk root loaded by `batch_root`.
+    fn subtrees_in_chunk(
+        tx: &Transaction,
+        chunk_start_index: u64,
+        batch_root: &impl Fn(u64) -> Result<Option<DataRoot>>,
+    ) -> Result<Vec<(usize, DataRoot)>> {
+        let chunk_end_index = chunk_start_index + PORA_CHUNK_SIZE as u64;
+        let chunk_depth = log2_pow2(PORA_CHUNK_SIZE) + 1;
+        let mut subtrees = Vec::new();
+        let mut start_index = tx.start_entry_index;
+        for &(depth, root) in &tx.merkle_nodes {
+            let end_index = start_index + (1 << (depth - 1));
+            if end_index > chunk_start_index && start_index < chunk_end_index {
+                if depth > chunk_depth {
+                    let batch_index = chunk_start_index / PORA_CHUNK_SIZE as u64;
+                    let chunk_root = batch_root(batch_index)?.ok_or_else(|| {
+                        anyhow!(
+                            "root of entry batch {} within the subtree of depth {} of tx {} \
+                             is not available, rebuild the flow from data by resyncing the \
+                             log entries and file data",
+                            batch_index,
+                            depth,
+                            tx.seq
+                        )
+                    })?;
+                    subtrees.push((chunk_depth, chunk_root));
+                } else {
+                    subtrees.push((depth, root));
+                }
+            }
+            start_index = end_index;
+        }
+        Ok(subtrees)
+    }
+}
+
+impl TransactionStore {
+    /// Scans txs in `[0, end_seq)` in order without trusting `next_tx_seq`, and returns the
+    /// number of leading txs that are decodable and not overlapped, along with the reason why
+    /// the next tx is inconsistent. All txs until the first missing one are scanned if
+    /// `end_seq` is `None`.
+    pub fn scan_txs(
+        flow_kvdb: &dyn ZgsKeyValueDB,
+        end_seq: Option<u64>,
+    ) -> Result<(u64, Option<String>)> {
+        let mut seq = 0;
+        let mut next_start_entry_index = 0;
+        while end_seq.map_or(true, |end| seq < end) {
+            let value = match flow_kvdb.get(COL_TX, &seq.to_be_bytes())? {
+                Some(v) => v,
+                None if end_seq.is_none() => break,
+                None => return Ok((seq, Some(format!("tx {} missing", seq)))),
+            };
+            let tx = match Transaction::from_ssz_bytes(&value) {
+                Ok(tx) => tx,
+                Err(e) => return Ok((seq, Some(format!("tx {} undecodable: {:?}", seq, e)))),
+            };
+            if tx.seq != seq {
+                return Ok((seq, Some(format!("tx {} stored with seq {}", seq, tx.seq))));
+            }
+            if tx.start_entry_index < next_start_entry_index {
+                return Ok((
+                    seq,
+                    Some(format!(
+                        "tx {} starts at {} before the end of previous tx {}",
+                        seq, tx.start_entry_index, next_start_entry_index
+                    )),
+                ));
+            }
+            next_start_entry_index = tx.start_entry_index + tx.num_entries() as u64;
+            seq += 1;
+        }
+        Ok((seq, None))
+    }
+
+    /// Removes all txs since `min_seq` and resets `next_tx_seq` to `min_seq`.
+    ///
+    /// Unlike `remove_tx_after`, this is used to repair the db, so txs are not required to be
+    /// decodable, and stale txs after `next_tx_seq` are removed as well.
+    pub fn truncate_txs(db: &StoreHandles, min_seq: u64) -> Result<()> {
+        let (flow_kvdb, data_kvdb) = (db.flow(), db.data());
+        let mut flow_db_tx = flow_kvdb.transaction();
+        let mut data_db_tx = data_kvdb.transaction();
+        for r in flow_kvdb.iter(COL_TX) {
+            let (key, _) = r?;
+            let seq = match decode_tx_seq(&key) {
+                Ok(seq) if seq >= min_seq => seq,
+                // `NEXT_TX_KEY` or retained txs
+                _ => continue,
+            };
+            flow_db_tx.delete(COL_TX, &key);
+            flow_db_tx.delete(COL_TX_SUBMISSION, &key);
+            data_db_tx.delete(COL_TX_COMPLETED, &seq.to_be_bytes());
+            data_db_tx.delete(COL_FLUSH_JOURNAL, &seq.to_be_bytes());
+        }
+        // The seq lists of the data roots, or `None` if any part is undecodable.
+        let mut tx_seq_lists: BTreeMap<Vec<u8>, Option<Vec<u64>>> = BTreeMap::new();
+        for r in flow_kvdb.iter(COL_TX_DATA_ROOT_INDEX) {
+            let (key, value) = r?;
+            if key.len() < DATA_ROOT_SIZE {
+                flow_db_tx.delete(COL_TX_DATA_ROOT_INDEX, &key);
+                continue;
+            }
+            let part = match key.len() {
+                DATA_ROOT_SIZE => Vec::<u64>::from_ssz_bytes(&value).ok(),
+                DATA_ROOT_SEQ_KEY_SIZE => {
+                    decode_tx_seq(&key[DATA_ROOT_SIZE..]).ok().map(|s| vec![s])
+                }
+                _ => Some(vec![]),
+            };
+            let tx_seq_list = tx_seq_lists
+                .entry(key[..DATA_ROOT_SIZE].to_vec())
+                .or_insert_with(|| Some(vec![]));
+            match (tx_seq_list.as_mut(), part) {
+                (Some(tx_seq_list), Some(part)) => tx_seq_list.extend(part),
+                _ => *tx_seq_list = None,
+            }
+        }
+        for (data_root, tx_seq_list) in tx_seq_lists {
+            let mut tx_seq_list = tx_seq_list.unwrap_or_default();
+            let len = tx_seq_list.len();
+            tx_seq_list.retain(|seq| *seq < min_seq);
+            if tx_seq_list.is_empty() {
+                flow_db_tx.delete_prefix(COL_TX_DATA_ROOT_INDEX, &data_root);
+                flow_db_tx.delete(COL_TX_FIRST_SEEN, &data_root);
+            } else if tx_seq_list.len() != len {
+                put_tx_seq_list(&mut flow_db_tx, &data_root, &tx_seq_list);
+            }
+        }
+        flow_db_tx.put(COL_TX, NEXT_TX_KEY.as_bytes(), &min_seq.to_be_bytes());
+        data_kvdb.write(data_db_tx)?;
+        flow_kvdb.write(flow_db_tx)?;
+        Ok(())
+    }
+
+    /// Rewinds the log sync progress to the last block whose first submission is not after
+    /// `tx_seq`, so that txs since the block will be synced again, and returns the seq of the
+    /// first submission. The progress is removed, and 0 is returned if no such block, in which
+    /// case logs are synced from the configured start block.
+    pub fn rewind_progress(flow_kvdb: &dyn ZgsKeyValueDB, tx_seq: u64) -> Result<u64> {
+        let mut blocks = vec![];
+        for r in flow_kvdb.iter(COL_BLOCK_PROGRESS) {
+            let (key, val) = r?;
+            let block_number =
+                u64::from_be_bytes(key.as_ref().try_into().map_err(|e| anyhow!("{:?}", e))?);
+            let val =
+                <(H256, Option<u64>)>::from_ssz_bytes(val.as_ref()).map_err(StoreError::from)?;
+            blocks.push((block_number, val));
+        }
+        blocks.sort_by_key(|(block_number, _)| *block_number);
+
+        let rewind_to = blocks
+            .iter()
+            .rev()
+            .find_map(|(block_number, (block_hash, index))| match index {
+                Some(index) if *index <= tx_seq => Some((*block_number, *block_hash, *index)),
+                _ => None,
+            });
+
+        let mut db_tx = flow_kvdb.transaction();
+        for (block_number, _) in blocks.iter() {
+            if rewind_to.map_or(true, |(rewind_number, _, _)| *block_number > rewind_number) {
+                db_tx.delete(COL_BLOCK_PROGRESS, &block_number.to_be_bytes());
+            }
+        }
+        match rewind_to {
+            Some((block_number, block_hash, _)) => db_tx.put(
+                COL_MISC,
+                LOG_SYNC_PROGRESS_KEY.as_bytes(),
+                &(block_number, block_hash).as_ssz_bytes(),
+            ),
+            None => db_tx.delete(COL_MISC, LOG_SYNC_PROGRESS_KEY.as_bytes()),
+        }
+        flow_kvdb.write(db_tx)?;
+
+        Ok(rewind_to.map_or(0, |(_, _, index)| index))
+    }
+
+    /// Removes the data root index and resets `next_tx_seq` to 0 while keeping txs and their
+    /// status, so that txs could be put again in order to rebuild the flow.
+    pub fn reset_tx_index(flow_kvdb: &dyn ZgsKeyValueDB) -> Result<()> {
+        let mut db_tx = flow_kvdb.transaction();
+        db_tx.delete_prefix(COL_TX_DATA_ROOT_INDEX, &[]);
+        db_tx.put(COL_TX, NEXT_TX_KEY.as_bytes(), &0u64.to_be_bytes());
+        Ok(flow_kvdb.write(db_tx)?)
+    }
+
+    /// Returns `next_tx_seq` persisted in db, or an error if it is undecodable.
+    pub fn read_next_tx_seq(flow_kvdb: &dyn ZgsKeyValueDB) -> Result<u64> {
+        flow_kvdb
+            .get(COL_TX, NEXT_TX_KEY.as_bytes())?
+            .map(|a| decode_tx_seq(&a))
+            .unwrap_or(Ok(0))
+    }
 }
 
 fn decode_tx_seq(data: &[u8]) -> Result<u64> {
-    Ok(u64::from_be_bytes(
-        data.try_into().map_err(|e| anyhow!("{:?}", e))?,
+    Ok(u64::from_be_bytes(data.try_into().map_err(|e| {
+        StoreError::Corrupt {
+            reason: format!("invalid tx seq: {:?}", e),
+        }
+    })?))
+}
+
+fn decode_block_number(key: &[u8]) -> Result<u64> {
+    Ok(u64::from_be_bytes(key.try_into().map_err(|e| {
+        StoreError::Corrupt {
+            reason: format!("invalid block number: {:?}", e),
+        }
+    })?))
+}
+
+fn decode_block_hash(key: &[u8], val: &[u8]) -> Result<(u64, BlockHashAndSubmissionIndex)> {
+    let (block_hash, first_submission_index) =
+        <(H256, Option<u64>)>::from_ssz_bytes(val).map_err(StoreError::from)?;
+    Ok((
+        decode_block_number(key)?,
+        BlockHashAndSubmissionIndex {
+            block_hash,
+            first_submission_index,
+        },
     ))
 }
+
+/// Key prefix shared by the blocks in `bucket`.
+fn block_hashes_bucket_prefix(bucket: u64) -> Vec<u8> {
+    let key = (bucket << BLOCK_HASHES_BUCKET_BITS).to_be_bytes();
+    key[..(64 - BLOCK_HASHES_BUCKET_BITS) as usize / 8].to_vec()
+}
+
+/// Iterator of the block hashes from the latest to the oldest, see
+/// [`TransactionStore::iter_block_hashes_rev`].
+pub struct BlockHashesRev<'a> {
+    flow_kvdb: &'a dyn ZgsKeyValueDB,
+    from_block: u64,
+    /// The bucket to read once `buffered` is consumed, or `None` if all read.
+    next_bucket: Option<u64>,
+    min_bucket: u64,
+    /// Blocks of the current bucket in ascending order.
+    buffered: Vec<(u64, BlockHashAndSubmissionIndex)>,
+    error: Option<anyhow::Error>,
+}
+
+impl BlockHashesRev<'_> {
+    fn read_bucket(&mut self, bucket: u64) -> Result<()> {
+        let prefix = block_hashes_bucket_prefix(bucket);
+        let flow_kvdb = self.flow_kvdb;
+        for r in flow_kvdb.iter_with_prefix(COL_BLOCK_PROGRESS, &prefix) {
+            let (key, val) = r?;
+            let (block_number, block) = decode_block_hash(&key, &val)?;
+            if block_number <= self.from_block {
+                self.buffered.push((block_number, block));
+            }
+        }
+        self.buffered.sort_by_key(|(block_number, _)| *block_number);
+        Ok(())
+    }
+}
+
+impl Iterator for BlockHashesRev<'_> {
+    type Item = Result<(u64, BlockHashAndSubmissionIndex)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            self.next_bucket = None;
+            return Some(Err(e));
+        }
+
+        loop {
+            if let Some(block) = self.buffered.pop() {
+                return Some(Ok(block));
+            }
+
+            let bucket = self.next_bucket?;
+            self.next_bucket = bucket.checked_sub(1).filter(|b| *b >= self.min_bucket);
+            if let Err(e) = self.read_bucket(bucket) {
+                self.next_bucket = None;
+                self.buffered.clear();
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+fn data_root_seq_key(data_root: &[u8], tx_seq: u64) -> Vec<u8> {
+    [data_root, &tx_seq.to_be_bytes()].concat()
+}
+
+fn data_root_tail_key(data_root: &[u8]) -> Vec<u8> {
+    [data_root, &[DATA_ROOT_TAIL_SUFFIX]].concat()
+}
+
+fn encode_data_root_tail(last: u64, count: usize) -> Vec<u8> {
+    [last.to_be_bytes(), (count as u64).to_be_bytes()].concat()
+}
+
+/// Return the last seq and the number of seqs of the data root tail.
+fn decode_data_root_tail(value: &[u8]) -> Result<(u64, usize)> {
+    if value.len() != 2 * TX_SEQ_SIZE {
+        bail!(StoreError::Corrupt {
+            reason: format!("invalid data root tail length {}", value.len()),
+        });
+    }
+    let last = decode_tx_seq(&value[..TX_SEQ_SIZE])?;
+    let count = decode_tx_seq(&value[TX_SEQ_SIZE..])?;
+    Ok((last, count as usize))
+}
+
+/// Rewrite the seq list of the data root as the base list, or remove it if empty.
+fn put_tx_seq_list(db_tx: &mut DBTransaction, data_root: &[u8], tx_seq_list: &[u64]) {
+    db_tx.delete_prefix(COL_TX_DATA_ROOT_INDEX, data_root);
+    if let Some(last) = tx_seq_list.last() {
+        db_tx.put(
+            COL_TX_DATA_ROOT_INDEX,
+            data_root,
+            &tx_seq_list.to_vec().as_ssz_bytes(),
+        );
+        db_tx.put(
+            COL_TX_DATA_ROOT_INDEX,
+            &data_root_tail_key(data_root),
+            &encode_data_root_tail(*last, tx_seq_list.len()),
+        );
+    }
+}
+
+/// Return the tx seq at `index` of the ssz-encoded seq list.
+fn encoded_tx_seq_at(encoded: &[u8], index: usize) -> Result<Option<u64>> {
+    if encoded.len() % TX_SEQ_SIZE != 0 {
+        bail!(StoreError::Corrupt {
+            reason: format!("invalid tx seq list length {}", encoded.len()),
+        });
+    }
+    match encoded.get(index * TX_SEQ_SIZE..(index + 1) * TX_SEQ_SIZE) {
+        Some(bytes) => Ok(Some(u64::from_ssz_bytes(bytes).map_err(StoreError::from)?)),
+        None => Ok(None),
+    }
+}
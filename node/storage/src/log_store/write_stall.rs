@@ -0,0 +1,380 @@
+//! Observation of the write stalls of the key-value dbs. Rocksdb delays or stops the writes once
+//! compaction falls behind, which freezes all the stores without any error but latencies.
+//!
+//! Stall stats are polled via [`KvdbIntrospection`], and a warning is logged once a db enters a
+//! stall. If auto-tune is enabled and stalls persist, the memory budget of each column, i.e. the
+//! write buffers, is raised within the configured bounds. Since kvdb could not change the rocksdb
+//! options at runtime, the budget is persisted beside the db and applied on next open.
+//!
+//! [`KvdbIntrospection`]: crate::KvdbIntrospection
+
+use crate::log_store::metrics;
+use crate::{DbEngine, StoreHandles, ZgsKeyValueDB};
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Extension of the file beside the db, which holds the tuned memory budget in MB.
+const MEMORY_BUDGET_EXTENSION: &str = "memory_budget";
+
+/// Memory budget in MB of each rocksdb column by default, see `kvdb-rocksdb`.
+const DEFAULT_MEMORY_BUDGET_MB: usize = 128;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteStallConfig {
+    /// Interval to poll the stall stats, or zero to disable.
+    pub poll_interval: Duration,
+    /// Number of polls in a row with stalls, after which the stalls are regarded persistent.
+    pub persistent_polls: u32,
+    /// Bounds of the tuned memory budget in MB of each column, or `None` to disable auto-tune.
+    pub auto_tune: Option<(usize, usize)>,
+}
+
+impl Default for WriteStallConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(10),
+            persistent_polls: 6,
+            auto_tune: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WriteStallEvent {
+    Entered {
+        db: &'static str,
+    },
+    Recovered {
+        db: &'static str,
+        stalled_polls: u32,
+    },
+    /// Memory budget raised for the next open.
+    Tuned {
+        db: &'static str,
+        memory_budget_mb: usize,
+    },
+}
+
+struct DbStalls {
+    name: &'static str,
+    db: Arc<dyn ZgsKeyValueDB>,
+    /// Path of the rocksdb to persist the tuned memory budget, or `None` if not opened from a
+    /// directory.
+    path: Option<PathBuf>,
+    last_stall_micros: Option<u64>,
+    stalled_polls: u32,
+    memory_budget_mb: usize,
+}
+
+/// Polls the write stall stats of the dbs that support introspection.
+pub struct WriteStallMonitor {
+    config: WriteStallConfig,
+    dbs: Vec<DbStalls>,
+}
+
+impl WriteStallMonitor {
+    pub fn new(handles: &StoreHandles, config: WriteStallConfig) -> Self {
+        let paths = match handles.location() {
+            Some((DbEngine::RocksDb, db_dir)) => handles.layout().db_paths(db_dir),
+            _ => vec![],
+        };
+        let dbs = handles
+            .dbs()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, (_, db))| db.write_stall_stats().is_some())
+            .map(|(i, (name, db))| {
+                let path = paths.get(i).cloned();
+                let memory_budget_mb = match path.as_deref().map(load_memory_budget) {
+                    Some(Ok(Some(budget))) => budget,
+                    _ => DEFAULT_MEMORY_BUDGET_MB,
+                };
+                DbStalls {
+                    name,
+                    db: db.clone(),
+                    path,
+                    last_stall_micros: None,
+                    stalled_polls: 0,
+                    memory_budget_mb,
+                }
+            })
+            .collect();
+        Self { config, dbs }
+    }
+
+    /// Returns whether any db supports the write stall stats.
+    pub fn is_supported(&self) -> bool {
+        !self.dbs.is_empty()
+    }
+
+    /// Polls the stall stats of all dbs, and returns the events since the last poll.
+    pub fn poll(&mut self) -> Vec<WriteStallEvent> {
+        let mut events = vec![];
+        for stalls in &mut self.dbs {
+            let stall_micros = match stalls.db.write_stall_stats() {
+                Some(stats) => stats.stall_micros,
+                None => continue,
+            };
+            let last = stalls.last_stall_micros.replace(stall_micros);
+            let delta = match last {
+                Some(last) => stall_micros.saturating_sub(last),
+                // Stalls before the first poll are not observed.
+                None => continue,
+            };
+
+            if delta == 0 {
+                if stalls.stalled_polls > 0 {
+                    info!(
+                        db = stalls.name,
+                        stalled_polls = stalls.stalled_polls,
+                        "Recovered from kvdb write stall"
+                    );
+                    events.push(WriteStallEvent::Recovered {
+                        db: stalls.name,
+                        stalled_polls: stalls.stalled_polls,
+                    });
+                    stalls.stalled_polls = 0;
+                }
+                continue;
+            }
+
+            metrics::KVDB_WRITE_STALL_MICROS.inc(delta as usize);
+            stalls.stalled_polls += 1;
+            if stalls.stalled_polls == 1 {
+                metrics::KVDB_WRITE_STALLS.inc(1);
+                warn!(
+                    db = stalls.name,
+                    stall_ms = delta / 1000,
+                    "Kvdb entered write stall, writes of all stores are delayed"
+                );
+                events.push(WriteStallEvent::Entered { db: stalls.name });
+            }
+
+            let persistent = stalls.stalled_polls % self.config.persistent_polls.max(1) == 0;
+            if let Some((min, max)) = self.config.auto_tune.filter(|_| persistent) {
+                if let Some(event) = Self::tune(stalls, min, max) {
+                    events.push(event);
+                }
+            }
+        }
+        events
+    }
+
+    /// Doubles the memory budget of the db within `[min, max]` for the next open.
+    fn tune(stalls: &mut DbStalls, min: usize, max: usize) -> Option<WriteStallEvent> {
+        let path = stalls.path.as_ref()?;
+        let budget = stalls.memory_budget_mb.saturating_mul(2).clamp(min, max);
+        if budget <= stalls.memory_budget_mb {
+            warn!(
+                db = stalls.name,
+                memory_budget_mb = stalls.memory_budget_mb,
+                "Kvdb write stall persists with the max memory budget"
+            );
+            return None;
+        }
+
+        if let Err(e) = save_memory_budget(path, budget) {
+            warn!(db = stalls.name, error = ?e, "Failed to save the tuned memory budget");
+            return None;
+        }
+        warn!(
+            db = stalls.name,
+            memory_budget_mb = budget,
+            "Kvdb write stall persists, raised the memory budget of columns after restart"
+        );
+        stalls.memory_budget_mb = budget;
+        Some(WriteStallEvent::Tuned {
+            db: stalls.name,
+            memory_budget_mb: budget,
+        })
+    }
+
+    /// Polls the stall stats periodically, if enabled and supported by any db.
+    #[cfg(feature = "runtime")]
+    pub fn spawn(mut self, executor: &task_executor::TaskExecutor) {
+        let poll_interval = self.config.poll_interval;
+        if poll_interval.is_zero() || !self.is_supported() {
+            return;
+        }
+
+        executor.spawn(
+            async move {
+                let mut interval = tokio::time::interval(poll_interval);
+                loop {
+                    interval.tick().await;
+                    self.poll();
+                }
+            },
+            "kvdb_write_stall_monitor",
+        );
+    }
+}
+
+fn memory_budget_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension(MEMORY_BUDGET_EXTENSION)
+}
+
+/// Loads the tuned memory budget in MB of each column of the rocksdb at `db_path`, if any.
+pub fn load_memory_budget(db_path: &Path) -> Result<Option<usize>> {
+    let content = match std::fs::read_to_string(memory_budget_path(db_path)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    content.trim().parse().map(Some).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid memory budget of {:?}: {:?}", db_path, e),
+        )
+    })
+}
+
+fn save_memory_budget(db_path: &Path, budget: usize) -> Result<()> {
+    std::fs::write(memory_budget_path(db_path), budget.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_store::log_manager::COL_NUM;
+    use crate::{open_kvdb, DbLayout, KvdbIntrospection, WriteStallStats};
+    use kvdb::{DBKeyValue, DBTransaction, DBValue, KeyValueDB};
+    use kvdb_memorydb::InMemory;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// In-memory db that reports the stall time set by tests.
+    struct StallingDB {
+        inner: InMemory,
+        stall_micros: AtomicU64,
+    }
+
+    impl KeyValueDB for StallingDB {
+        fn get(&self, col: u32, key: &[u8]) -> Result<Option<DBValue>> {
+            self.inner.get(col, key)
+        }
+
+        fn get_by_prefix(&self, col: u32, prefix: &[u8]) -> Result<Option<DBValue>> {
+            self.inner.get_by_prefix(col, prefix)
+        }
+
+        fn write(&self, transaction: DBTransaction) -> Result<()> {
+            self.inner.write(transaction)
+        }
+
+        fn iter<'a>(&'a self, col: u32) -> Box<dyn Iterator<Item = Result<DBKeyValue>> + 'a> {
+            self.inner.iter(col)
+        }
+
+        fn iter_with_prefix<'a>(
+            &'a self,
+            col: u32,
+            prefix: &'a [u8],
+        ) -> Box<dyn Iterator<Item = Result<DBKeyValue>> + 'a> {
+            self.inner.iter_with_prefix(col, prefix)
+        }
+    }
+
+    impl KvdbIntrospection for StallingDB {
+        fn write_stall_stats(&self) -> Option<WriteStallStats> {
+            Some(WriteStallStats {
+                stall_micros: self.stall_micros.load(Ordering::SeqCst),
+            })
+        }
+    }
+
+    impl ZgsKeyValueDB for StallingDB {
+        fn num_keys(&self, col: u32) -> Result<u64> {
+            self.inner.num_keys(col)
+        }
+    }
+
+    #[test]
+    fn test_memory_db_not_supported() {
+        let db = kvdb_memorydb::create(COL_NUM);
+        assert_eq!(db.write_stall_stats(), None);
+
+        let mut monitor =
+            WriteStallMonitor::new(&StoreHandles::memorydb(DbLayout::Split), Default::default());
+        assert!(!monitor.is_supported());
+        assert!(monitor.poll().is_empty());
+    }
+
+    #[test]
+    fn test_stall_events() {
+        let db = Arc::new(StallingDB {
+            inner: kvdb_memorydb::create(COL_NUM),
+            stall_micros: AtomicU64::new(100),
+        });
+        let config = WriteStallConfig {
+            persistent_polls: 2,
+            // no path to persist the tuned budget
+            auto_tune: Some((128, 512)),
+            ..Default::default()
+        };
+        let mut monitor = WriteStallMonitor::new(&StoreHandles::unified(db.clone()), config);
+        assert!(monitor.is_supported());
+
+        // stalls before the first poll are ignored
+        assert!(monitor.poll().is_empty());
+        db.stall_micros.store(2000, Ordering::SeqCst);
+        assert_eq!(
+            monitor.poll(),
+            vec![WriteStallEvent::Entered { db: "unified" }]
+        );
+        db.stall_micros.store(3000, Ordering::SeqCst);
+        assert!(monitor.poll().is_empty());
+        assert_eq!(
+            monitor.poll(),
+            vec![WriteStallEvent::Recovered {
+                db: "unified",
+                stalled_polls: 2
+            }]
+        );
+        assert!(monitor.poll().is_empty());
+    }
+
+    #[test]
+    fn test_memory_budget_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("flow_db");
+        assert_eq!(load_memory_budget(&db_path).unwrap(), None);
+        save_memory_budget(&db_path, 256).unwrap();
+        assert_eq!(load_memory_budget(&db_path).unwrap(), Some(256));
+        std::fs::write(memory_budget_path(&db_path), "abc").unwrap();
+        assert!(load_memory_budget(&db_path).is_err());
+    }
+
+    #[test]
+    #[ignore = "smoke test of rocksdb, run with `cargo test -- --ignored`"]
+    fn test_rocksdb_write_stall_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let handles = StoreHandles::open(DbEngine::RocksDb, DbLayout::Split, dir.path()).unwrap();
+        for (_, db) in handles.dbs() {
+            for i in 0u64..10_000 {
+                db.put(0, &i.to_be_bytes(), &[0u8; 1024]).unwrap();
+            }
+            assert!(db.write_stall_stats().is_some());
+        }
+
+        let config = WriteStallConfig {
+            persistent_polls: 1,
+            auto_tune: Some((128, 512)),
+            ..Default::default()
+        };
+        let mut monitor = WriteStallMonitor::new(&handles, config);
+        assert!(monitor.is_supported());
+        monitor.poll();
+        monitor.poll();
+
+        // the tuned memory budget, if any, is applied on reopen
+        drop(monitor);
+        drop(handles);
+        let flow_db_path = dir.path().join("flow_db");
+        save_memory_budget(&flow_db_path, 256).unwrap();
+        let db = open_kvdb(DbEngine::RocksDb, &flow_db_path, COL_NUM).unwrap();
+        assert!(db.write_stall_stats().is_some());
+    }
+}
@@ -0,0 +1,138 @@
+//! Key-value db that rejects writes, so that a snapshot could be served by a read-only node
+//! without being modified. The underlying rocksdb is opened as a secondary instance, see
+//! [`crate::open_kvdb_read_only`], so that the snapshot could be served along with the node
+//! that writes it.
+//!
+//! Opening the log store rewrites some state, e.g. the merkle root of the latest tx, which is
+//! the same as stored for a cleanly stopped node. Such writes are skipped instead of rejected,
+//! and only the writes that would change the db fail.
+
+use crate::{KvdbIntrospection, WriteStallStats, ZgsKeyValueDB};
+use kvdb::{DBKeyValue, DBOp, DBTransaction, DBValue, KeyValueDB};
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
+
+pub struct ReadOnlyDB {
+    db: Arc<dyn ZgsKeyValueDB>,
+}
+
+impl ReadOnlyDB {
+    pub fn new(db: Arc<dyn ZgsKeyValueDB>) -> Self {
+        Self { db }
+    }
+
+    /// Returns whether the op does not change the db.
+    fn is_noop(&self, op: &DBOp) -> Result<bool> {
+        match op {
+            DBOp::Insert { col, key, value } => {
+                Ok(self.db.get(*col, key)?.as_deref() == Some(value.as_slice()))
+            }
+            DBOp::Delete { col, key } => Ok(self.db.get(*col, key)?.is_none()),
+            DBOp::DeletePrefix { col, prefix } => {
+                Ok(self.db.iter_with_prefix(*col, prefix).next().is_none())
+            }
+        }
+    }
+}
+
+impl KeyValueDB for ReadOnlyDB {
+    fn get(&self, col: u32, key: &[u8]) -> Result<Option<DBValue>> {
+        self.db.get(col, key)
+    }
+
+    fn get_by_prefix(&self, col: u32, prefix: &[u8]) -> Result<Option<DBValue>> {
+        self.db.get_by_prefix(col, prefix)
+    }
+
+    fn write(&self, transaction: DBTransaction) -> Result<()> {
+        for op in &transaction.ops {
+            if !self.is_noop(op)? {
+                return Err(Error::new(
+                    ErrorKind::PermissionDenied,
+                    "db is opened read-only",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn iter<'a>(&'a self, col: u32) -> Box<dyn Iterator<Item = Result<DBKeyValue>> + 'a> {
+        self.db.iter(col)
+    }
+
+    fn iter_with_prefix<'a>(
+        &'a self,
+        col: u32,
+        prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = Result<DBKeyValue>> + 'a> {
+        self.db.iter_with_prefix(col, prefix)
+    }
+}
+
+impl KvdbIntrospection for ReadOnlyDB {
+    fn write_stall_stats(&self) -> Option<WriteStallStats> {
+        self.db.write_stall_stats()
+    }
+
+    fn column_size_bytes(&self, col: u32) -> Result<Option<u64>> {
+        self.db.column_size_bytes(col)
+    }
+}
+
+impl ZgsKeyValueDB for ReadOnlyDB {
+    fn num_keys(&self, col: u32) -> Result<u64> {
+        self.db.num_keys(col)
+    }
+
+    fn multi_get(&self, col: u32, keys: &[&[u8]]) -> Result<Vec<Option<DBValue>>> {
+        self.db.multi_get(col, keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_db() -> ReadOnlyDB {
+        let db = kvdb_memorydb::create(1);
+        db.put(0, b"key1", b"value1").unwrap();
+        db.put(0, b"key2", b"value2").unwrap();
+        ReadOnlyDB::new(Arc::new(db))
+    }
+
+    #[test]
+    fn test_reject_writes() {
+        let db = new_db();
+
+        let err = db.put(0, b"key1", b"value3").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert!(db.put(0, b"key3", b"value3").is_err());
+        assert!(db.delete(0, b"key2").is_err());
+        assert!(db.delete_with_prefix(0, b"key").is_err());
+
+        // the whole transaction rejected
+        let mut tx = db.transaction();
+        tx.put(0, b"key1", b"value1");
+        tx.delete(0, b"key2");
+        assert!(db.write(tx).is_err());
+
+        assert_eq!(db.get(0, b"key1").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(db.get(0, b"key2").unwrap(), Some(b"value2".to_vec()));
+        assert_eq!(db.get(0, b"key3").unwrap(), None);
+        assert_eq!(db.num_keys(0).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_skip_noop_writes() {
+        let db = new_db();
+
+        let mut tx = db.transaction();
+        tx.put(0, b"key1", b"value1");
+        tx.delete(0, b"key3");
+        tx.delete_prefix(0, b"other");
+        db.write(tx).unwrap();
+
+        assert_eq!(db.iter(0).count(), 2);
+    }
+}
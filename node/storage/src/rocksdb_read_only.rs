@@ -0,0 +1,174 @@
+//! Rocksdb opened in the read-only mode of rocksdb, for offline inspection of a stopped node.
+//!
+//! Unlike the secondary instance of [`crate::open_kvdb_read_only`], nothing is written under the
+//! db directory, e.g. info logs, and the data written by the last run is visible, including the
+//! memtables recovered from the write-ahead log. Columns are the column families created by
+//! `kvdb-rocksdb`, i.e. `col<index>`.
+
+use crate::{KvdbIntrospection, ZgsKeyValueDB};
+use kvdb::{DBKey, DBKeyValue, DBTransaction, DBValue, KeyValueDB};
+use rocksdb::{ColumnFamily, Direction, IteratorMode, Options, DB};
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+const ESTIMATE_NUM_KEYS: &str = "rocksdb.estimate-num-keys";
+const ESTIMATE_LIVE_DATA_SIZE: &str = "rocksdb.estimate-live-data-size";
+const CUR_SIZE_ALL_MEM_TABLES: &str = "rocksdb.cur-size-all-mem-tables";
+
+pub struct ReadOnlyRocksDB {
+    db: DB,
+    num_cols: u32,
+}
+
+impl ReadOnlyRocksDB {
+    /// Opens the db at `path`, which fails if not exists.
+    pub fn open(path: impl AsRef<Path>, num_cols: u32) -> Result<Self> {
+        let columns = (0..num_cols).map(column_name);
+        let db = DB::open_cf_for_read_only(&Options::default(), path, columns, false)
+            .map_err(to_io_error)?;
+        Ok(Self { db, num_cols })
+    }
+
+    fn cf(&self, col: u32) -> Result<&ColumnFamily> {
+        if col >= self.num_cols {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("column {} out of range", col),
+            ));
+        }
+        self.db
+            .cf_handle(&column_name(col))
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("column {} not found", col)))
+    }
+
+    fn property(&self, col: u32, name: &str) -> Result<u64> {
+        Ok(self
+            .db
+            .property_int_value_cf(self.cf(col)?, name)
+            .map_err(to_io_error)?
+            .unwrap_or_default())
+    }
+
+    fn scan<'a>(
+        &'a self,
+        col: u32,
+        prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = Result<DBKeyValue>> + 'a> {
+        let cf = match self.cf(col) {
+            Ok(cf) => cf,
+            Err(e) => return Box::new(std::iter::once(Err(e))),
+        };
+        let iter = self
+            .db
+            .iterator_cf(cf, IteratorMode::From(prefix, Direction::Forward))
+            .map(|item| {
+                item.map(|(key, value)| (DBKey::from_slice(&key), value.into_vec()))
+                    .map_err(to_io_error)
+            })
+            .take_while(move |item| match item {
+                Ok((key, _)) => key.starts_with(prefix),
+                Err(_) => true,
+            });
+        Box::new(iter)
+    }
+}
+
+impl KeyValueDB for ReadOnlyRocksDB {
+    fn get(&self, col: u32, key: &[u8]) -> Result<Option<DBValue>> {
+        self.db.get_cf(self.cf(col)?, key).map_err(to_io_error)
+    }
+
+    fn get_by_prefix(&self, col: u32, prefix: &[u8]) -> Result<Option<DBValue>> {
+        self.scan(col, prefix)
+            .next()
+            .transpose()
+            .map(|item| item.map(|(_, value)| value))
+    }
+
+    fn write(&self, _transaction: DBTransaction) -> Result<()> {
+        Err(Error::new(
+            ErrorKind::PermissionDenied,
+            "db is opened read-only",
+        ))
+    }
+
+    fn iter<'a>(&'a self, col: u32) -> Box<dyn Iterator<Item = Result<DBKeyValue>> + 'a> {
+        self.scan(col, &[])
+    }
+
+    fn iter_with_prefix<'a>(
+        &'a self,
+        col: u32,
+        prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = Result<DBKeyValue>> + 'a> {
+        self.scan(col, prefix)
+    }
+}
+
+impl KvdbIntrospection for ReadOnlyRocksDB {
+    fn column_size_bytes(&self, col: u32) -> Result<Option<u64>> {
+        let size = self.property(col, ESTIMATE_LIVE_DATA_SIZE)?
+            + self.property(col, CUR_SIZE_ALL_MEM_TABLES)?;
+        Ok(Some(size))
+    }
+}
+
+impl ZgsKeyValueDB for ReadOnlyRocksDB {
+    fn num_keys(&self, col: u32) -> Result<u64> {
+        self.property(col, ESTIMATE_NUM_KEYS)
+    }
+
+    fn multi_get(&self, col: u32, keys: &[&[u8]]) -> Result<Vec<Option<DBValue>>> {
+        let cf = self.cf(col)?;
+        self.db
+            .multi_get_cf(keys.iter().map(|key| (cf, *key)))
+            .into_iter()
+            .map(|value| value.map_err(to_io_error))
+            .collect()
+    }
+}
+
+fn column_name(col: u32) -> String {
+    format!("col{}", col)
+}
+
+fn to_io_error(e: rocksdb::Error) -> Error {
+    Error::new(ErrorKind::Other, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kvdb_rocksdb::{Database, DatabaseConfig};
+
+    #[test]
+    fn test_read_kvdb_columns() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(&DatabaseConfig::with_columns(2), dir.path()).unwrap();
+        db.put(1, b"key1", b"value1").unwrap();
+        db.put(1, b"key2", b"value2").unwrap();
+        db.put(1, b"other", b"value3").unwrap();
+        drop(db);
+
+        let db = ReadOnlyRocksDB::open(dir.path(), 2).unwrap();
+        assert_eq!(db.get(1, b"key1").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(db.get(0, b"key1").unwrap(), None);
+        assert_eq!(db.iter(1).count(), 3);
+        let keys: Vec<_> = db
+            .iter_with_prefix(1, b"key")
+            .map(|item| item.unwrap().0.to_vec())
+            .collect();
+        assert_eq!(keys, vec![b"key1".to_vec(), b"key2".to_vec()]);
+        assert_eq!(
+            db.get_by_prefix(1, b"oth").unwrap(),
+            Some(b"value3".to_vec())
+        );
+        assert_eq!(
+            db.multi_get(1, &[&b"key2"[..], b"key3", b"key1"]).unwrap(),
+            vec![Some(b"value2".to_vec()), None, Some(b"value1".to_vec())]
+        );
+        assert!(db.column_size_bytes(1).unwrap().unwrap() > 0);
+        assert!(db.put(1, b"key3", b"value").is_err());
+        assert!(db.get(2, b"key1").is_err());
+    }
+}
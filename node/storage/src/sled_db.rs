@@ -0,0 +1,209 @@
+//! Pure Rust key-value db backed by sled, for embedded users on platforms where rocksdb is
+//! painful to build.
+//!
+//! Columns are emulated in a single sled tree by prefixing keys with the column index in
+//! big endian, so that keys of a column are iterated in order, and a transaction across
+//! columns is applied atomically as a sled batch.
+
+use crate::{KvdbIntrospection, ZgsKeyValueDB};
+use kvdb::{DBKey, DBKeyValue, DBOp, DBTransaction, DBValue, KeyValueDB};
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+const COL_PREFIX_LEN: usize = std::mem::size_of::<u32>();
+
+pub struct SledDB {
+    db: sled::Db,
+    num_cols: u32,
+}
+
+impl SledDB {
+    /// Opens the db at `path`, which is created if not exists.
+    pub fn open(path: impl AsRef<Path>, num_cols: u32) -> Result<Self> {
+        let db = sled::Config::new().path(path).open().map_err(to_io_error)?;
+        Ok(Self { db, num_cols })
+    }
+
+    fn prefixed_key(&self, col: u32, key: &[u8]) -> Result<Vec<u8>> {
+        if col >= self.num_cols {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("No such column family: {:?}", col),
+            ));
+        }
+
+        let mut prefixed = Vec::with_capacity(COL_PREFIX_LEN + key.len());
+        prefixed.extend_from_slice(&col.to_be_bytes());
+        prefixed.extend_from_slice(key);
+        Ok(prefixed)
+    }
+
+    fn scan<'a>(
+        &'a self,
+        col: u32,
+        prefix: &[u8],
+    ) -> Box<dyn Iterator<Item = Result<DBKeyValue>> + 'a> {
+        let prefix = match self.prefixed_key(col, prefix) {
+            Ok(prefix) => prefix,
+            Err(e) => return Box::new(std::iter::once(Err(e))),
+        };
+
+        Box::new(self.db.scan_prefix(prefix).map(|item| {
+            let (key, value) = item.map_err(to_io_error)?;
+            Ok((DBKey::from_slice(&key[COL_PREFIX_LEN..]), value.to_vec()))
+        }))
+    }
+}
+
+impl KeyValueDB for SledDB {
+    fn get(&self, col: u32, key: &[u8]) -> Result<Option<DBValue>> {
+        let value = self
+            .db
+            .get(self.prefixed_key(col, key)?)
+            .map_err(to_io_error)?;
+        Ok(value.map(|v| v.to_vec()))
+    }
+
+    fn get_by_prefix(&self, col: u32, prefix: &[u8]) -> Result<Option<DBValue>> {
+        self.scan(col, prefix)
+            .next()
+            .transpose()
+            .map(|item| item.map(|(_, value)| value))
+    }
+
+    fn write(&self, transaction: DBTransaction) -> Result<()> {
+        // Ops are applied in order, so the latter op overrides the former of the same key, and
+        // prefix deletion covers the keys inserted before in the same transaction.
+        let mut ops: BTreeMap<Vec<u8>, Option<DBValue>> = BTreeMap::new();
+        for op in transaction.ops {
+            match op {
+                DBOp::Insert { col, key, value } => {
+                    ops.insert(self.prefixed_key(col, &key)?, Some(value));
+                }
+                DBOp::Delete { col, key } => {
+                    ops.insert(self.prefixed_key(col, &key)?, None);
+                }
+                DBOp::DeletePrefix { col, prefix } => {
+                    let prefix = self.prefixed_key(col, &prefix)?;
+                    for key in self.db.scan_prefix(&prefix).keys() {
+                        ops.insert(key.map_err(to_io_error)?.to_vec(), None);
+                    }
+                    for (_, value) in ops
+                        .range_mut(prefix.clone()..)
+                        .take_while(|(key, _)| key.starts_with(&prefix))
+                    {
+                        *value = None;
+                    }
+                }
+            }
+        }
+
+        let mut batch = sled::Batch::default();
+        for (key, value) in ops {
+            match value {
+                Some(value) => batch.insert(key, value),
+                None => batch.remove(key),
+            }
+        }
+        self.db.apply_batch(batch).map_err(to_io_error)
+    }
+
+    fn iter<'a>(&'a self, col: u32) -> Box<dyn Iterator<Item = Result<DBKeyValue>> + 'a> {
+        self.scan(col, &[])
+    }
+
+    fn iter_with_prefix<'a>(
+        &'a self,
+        col: u32,
+        prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = Result<DBKeyValue>> + 'a> {
+        self.scan(col, prefix)
+    }
+}
+
+impl KvdbIntrospection for SledDB {}
+
+impl ZgsKeyValueDB for SledDB {
+    fn num_keys(&self, col: u32) -> Result<u64> {
+        let mut num_keys = 0;
+        for item in self.scan(col, &[]) {
+            item?;
+            num_keys += 1;
+        }
+        Ok(num_keys)
+    }
+}
+
+fn to_io_error(e: sled::Error) -> Error {
+    Error::new(ErrorKind::Other, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SledDB::open(dir.path(), 2).unwrap();
+
+        let mut tx = db.transaction();
+        tx.put(0, b"key1", b"value1");
+        tx.put(0, b"key2", b"value2");
+        tx.put(1, b"key1", b"value3");
+        db.write(tx).unwrap();
+
+        assert_eq!(db.get(0, b"key1").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(db.get(1, b"key1").unwrap(), Some(b"value3".to_vec()));
+        assert_eq!(db.num_keys(0).unwrap(), 2);
+        assert_eq!(db.num_keys(1).unwrap(), 1);
+        assert_eq!(
+            db.iter(0)
+                .map(|item| item.unwrap().0.to_vec())
+                .collect::<Vec<_>>(),
+            vec![b"key1".to_vec(), b"key2".to_vec()]
+        );
+        assert!(db.get(2, b"key1").is_err());
+        assert!(db.iter(2).next().unwrap().is_err());
+
+        // reopen
+        drop(db);
+        let db = SledDB::open(dir.path(), 2).unwrap();
+        assert_eq!(db.get(0, b"key2").unwrap(), Some(b"value2".to_vec()));
+    }
+
+    #[test]
+    fn test_delete_prefix_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SledDB::open(dir.path(), 1).unwrap();
+        db.puts(vec![
+            (0, b"a1".to_vec(), b"1".to_vec()),
+            (0, b"a2".to_vec(), b"2".to_vec()),
+            (0, b"b1".to_vec(), b"3".to_vec()),
+        ])
+        .unwrap();
+
+        let mut tx = db.transaction();
+        tx.put(0, b"a3", b"4");
+        tx.delete_prefix(0, b"a");
+        tx.put(0, b"a4", b"5");
+        db.write(tx).unwrap();
+
+        assert_eq!(
+            db.iter(0)
+                .map(|item| item.unwrap())
+                .map(|(key, value)| (key.to_vec(), value))
+                .collect::<Vec<_>>(),
+            vec![
+                (b"a4".to_vec(), b"5".to_vec()),
+                (b"b1".to_vec(), b"3".to_vec())
+            ]
+        );
+        assert_eq!(db.get_by_prefix(0, b"b").unwrap(), Some(b"3".to_vec()));
+        assert_eq!(db.get_by_prefix(0, b"c").unwrap(), None);
+
+        db.delete_with_prefix(0, &[]).unwrap();
+        assert_eq!(db.num_keys(0).unwrap(), 0);
+    }
+}
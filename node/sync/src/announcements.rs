@@ -0,0 +1,147 @@
+//! Exchange of the files recently announced with peers once connected, so that new nodes could
+//! sync the files announced before they joined rather than waiting for re-announcements.
+
+use network::PeerId;
+use shared_types::TxID;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+/// Maximum number of the latest announced files cached to serve peers.
+pub(crate) const MAX_RECENT_ANNOUNCEMENTS: usize = 4096;
+/// Number of the latest txs checked for the filter of files known locally in request.
+pub(crate) const KNOWN_TXS_WINDOW: u64 = 1024;
+/// Bytes of the filter of files known locally, i.e. about 3% false positives once all the txs in
+/// `KNOWN_TXS_WINDOW` are known.
+pub(crate) const KNOWN_TXS_FILTER_BYTES: usize = 1024;
+
+/// Files announced by this node recently, along with the rate limits of the exchanges with peers
+/// in both directions.
+pub(crate) struct AnnouncementExchange {
+    /// Files announced by tx seq, in which the oldest are evicted once full.
+    recent: BTreeMap<u64, TxID>,
+    /// Time of the last request sent to each peer.
+    requested: HashMap<PeerId, Instant>,
+    /// Time of the last request served for each peer.
+    served: HashMap<PeerId, Instant>,
+    interval: Duration,
+}
+
+impl AnnouncementExchange {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            recent: Default::default(),
+            requested: Default::default(),
+            served: Default::default(),
+            interval,
+        }
+    }
+
+    pub fn on_announced(&mut self, tx_ids: impl IntoIterator<Item = TxID>) {
+        for tx_id in tx_ids {
+            self.recent.insert(tx_id.seq, tx_id);
+        }
+
+        while self.recent.len() > MAX_RECENT_ANNOUNCEMENTS {
+            self.recent.pop_first();
+        }
+    }
+
+    /// Drops the files retracted once reverted on chain, which are never served to peers.
+    pub fn on_retracted(&mut self, tx_ids: &[TxID]) {
+        for tx_id in tx_ids {
+            if self.recent.get(&tx_id.seq) == Some(tx_id) {
+                self.recent.remove(&tx_id.seq);
+            }
+        }
+    }
+
+    /// Returns the cached files, the latest first.
+    pub fn recent(&self) -> impl Iterator<Item = &TxID> {
+        self.recent.values().rev()
+    }
+
+    /// Returns whether to request announcements from the peer, i.e. not requested within the
+    /// interval, e.g. reconnected soon.
+    pub fn should_request(&mut self, peer_id: PeerId, now: Instant) -> bool {
+        Self::admit(&mut self.requested, peer_id, now, self.interval)
+    }
+
+    /// Returns whether to serve the request of the peer, or rate limited.
+    pub fn admit_request(&mut self, peer_id: PeerId, now: Instant) -> bool {
+        Self::admit(&mut self.served, peer_id, now, self.interval)
+    }
+
+    fn admit(
+        last: &mut HashMap<PeerId, Instant>,
+        peer_id: PeerId,
+        now: Instant,
+        interval: Duration,
+    ) -> bool {
+        // peers beyond the interval are dropped, so that the map is bounded by the peers
+        // exchanged within the interval
+        last.retain(|_, at| now.saturating_duration_since(*at) < interval);
+
+        if last.contains_key(&peer_id) {
+            return false;
+        }
+
+        last.insert(peer_id, now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx_id(seq: u64) -> TxID {
+        TxID {
+            seq,
+            hash: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_recent_announcements() {
+        let mut exchange = AnnouncementExchange::new(Duration::from_secs(60));
+        exchange.on_announced((0..MAX_RECENT_ANNOUNCEMENTS as u64 + 2).map(tx_id));
+        exchange.on_announced([tx_id(5)]);
+
+        let recent: Vec<u64> = exchange.recent().map(|tx_id| tx_id.seq).collect();
+        assert_eq!(recent.len(), MAX_RECENT_ANNOUNCEMENTS);
+        assert_eq!(recent[0], MAX_RECENT_ANNOUNCEMENTS as u64 + 1);
+        assert_eq!(*recent.last().unwrap(), 2);
+
+        // only the retracted file of the same tx is dropped
+        exchange.on_retracted(&[
+            tx_id(MAX_RECENT_ANNOUNCEMENTS as u64 + 1),
+            TxID {
+                seq: 2,
+                hash: shared_types::DataRoot::repeat_byte(1),
+            },
+        ]);
+        let recent: Vec<u64> = exchange.recent().map(|tx_id| tx_id.seq).collect();
+        assert_eq!(recent.len(), MAX_RECENT_ANNOUNCEMENTS - 1);
+        assert_eq!(recent[0], MAX_RECENT_ANNOUNCEMENTS as u64);
+        assert_eq!(*recent.last().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_rate_limits() {
+        let interval = Duration::from_secs(60);
+        let mut exchange = AnnouncementExchange::new(interval);
+        let (peer1, peer2) = (PeerId::random(), PeerId::random());
+        let now = Instant::now();
+
+        assert!(exchange.should_request(peer1, now));
+        assert!(!exchange.should_request(peer1, now + Duration::from_secs(1)));
+        assert!(exchange.should_request(peer2, now));
+
+        // requests sent and served are limited separately
+        assert!(exchange.admit_request(peer1, now));
+        assert!(!exchange.admit_request(peer1, now + Duration::from_secs(1)));
+
+        assert!(exchange.should_request(peer1, now + interval));
+        assert!(exchange.admit_request(peer1, now + interval));
+    }
+}
@@ -105,6 +105,12 @@ impl Batcher {
         trace!(?tx_seq, ?state, "File sync status retrieved");
 
         match state {
+            // restored from the revert journal in background instead of synced from peers
+            None if self.is_reverted_tx_staged(tx_seq)? => {
+                trace!(%tx_seq, "File sync deferred until restored");
+                Ok(None)
+            }
+
             // start file sync if not launched yet
             None => match self
                 .sync_send
@@ -152,6 +158,16 @@ impl Batcher {
         }
     }
 
+    /// Whether the tx is being restored from the revert journal, see
+    /// `LogSyncManager::notify_tx_synced`.
+    fn is_reverted_tx_staged(&self, tx_seq: u64) -> Result<bool> {
+        let store = self.store.get_store();
+        Ok(match store.get_tx_by_seq_number(tx_seq)? {
+            Some(tx) => store.is_reverted_tx_staged(&tx),
+            None => false,
+        })
+    }
+
     pub async fn terminate_file_sync(&self, tx_seq: u64, is_reverted: bool) -> usize {
         match self
             .sync_send
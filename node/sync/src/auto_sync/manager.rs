@@ -22,11 +22,19 @@ use super::{
     sync_store::{Queue, SyncStore},
 };
 
+/// Files announced or retracted by the gossip of neighbors, which are handled in order so that
+/// a retraction never races with the announcement of the same file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewFileEvent {
+    Announced(u64),
+    Retracted(u64),
+}
+
 pub struct AutoSyncManager {
     pub serial: Option<SerialBatcher>,
     pub random: RandomBatcher,
     pub file_announcement_send: UnboundedSender<u64>,
-    pub new_file_send: UnboundedSender<u64>,
+    pub new_file_send: UnboundedSender<NewFileEvent>,
     pub catched_up: Arc<AtomicBool>,
 }
 
@@ -128,12 +136,23 @@ impl AutoSyncManager {
     }
 
     async fn handle_new_file(
-        mut new_file_recv: UnboundedReceiver<u64>,
+        mut new_file_recv: UnboundedReceiver<NewFileEvent>,
         sync_store: Arc<SyncStore>,
     ) {
-        while let Some(tx_seq) = new_file_recv.recv().await {
-            if let Err(err) = sync_store.insert(tx_seq, Queue::Ready).await {
-                warn!(?err, %tx_seq, "Failed to insert new file to ready queue");
+        while let Some(event) = new_file_recv.recv().await {
+            match event {
+                NewFileEvent::Announced(tx_seq) => {
+                    if let Err(err) = sync_store.insert(tx_seq, Queue::Ready).await {
+                        warn!(?err, %tx_seq, "Failed to insert new file to ready queue");
+                    }
+                }
+                NewFileEvent::Retracted(tx_seq) => {
+                    // downgraded rather than removed, since the file of the same seq may still
+                    // be valid on chain and announced by other peers
+                    if let Err(err) = sync_store.downgrade(tx_seq).await {
+                        warn!(?err, %tx_seq, "Failed to downgrade retracted file to pending queue");
+                    }
+                }
             }
         }
     }
@@ -148,3 +167,35 @@ impl AutoSyncManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::tests::TestStoreRuntime;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_handle_retracted_file() {
+        let runtime = TestStoreRuntime::default();
+        let sync_store = Arc::new(SyncStore::new(runtime.store.clone()));
+
+        let (new_file_send, new_file_recv) = unbounded_channel();
+        for event in [
+            NewFileEvent::Announced(1),
+            NewFileEvent::Announced(2),
+            NewFileEvent::Retracted(2),
+            NewFileEvent::Retracted(3),
+        ] {
+            new_file_send.send(event).unwrap();
+        }
+        drop(new_file_send);
+
+        // returns once all the events handled
+        AutoSyncManager::handle_new_file(new_file_recv, sync_store.clone()).await;
+
+        // retracted file is kept to sync at low priority, while unknown one never queued
+        assert_eq!(sync_store.contains(1).await.unwrap(), Some(Queue::Ready));
+        assert_eq!(sync_store.contains(2).await.unwrap(), Some(Queue::Pending));
+        assert_eq!(sync_store.contains(3).await.unwrap(), None);
+    }
+}
@@ -159,6 +159,23 @@ impl SyncStore {
         Ok(added)
     }
 
+    pub async fn downgrade(&self, tx_seq: u64) -> Result<bool> {
+        let async_store = self.store.write().await;
+        let store = async_store.get_store();
+
+        let mut tx = ConfigTx::default();
+
+        if !self.ready_txs.remove(store, Some(&mut tx), tx_seq)? {
+            return Ok(false);
+        }
+
+        let added = self.pending_txs.add(store, Some(&mut tx), tx_seq)?;
+
+        store.exec_configs(tx, DATA_DB_KEY)?;
+
+        Ok(added)
+    }
+
     pub async fn random(&self) -> Result<Option<u64>> {
         let async_store = self.store.read().await;
         let store = async_store.get_store();
@@ -250,6 +267,24 @@ mod tests {
         assert!(!store.upgrade(3).await.unwrap());
     }
 
+    #[tokio::test]
+    async fn test_downgrade() {
+        let runtime = TestStoreRuntime::default();
+        let store = SyncStore::new(runtime.store.clone());
+
+        // cannot downgrade by default
+        assert!(!store.downgrade(3).await.unwrap());
+
+        // cannot downgrade pending tx 3
+        assert_eq!(store.insert(3, Pending).await.unwrap(), NewAdded);
+        assert!(!store.downgrade(3).await.unwrap());
+
+        // can downgrade ready tx 4
+        assert_eq!(store.insert(4, Ready).await.unwrap(), NewAdded);
+        assert!(store.downgrade(4).await.unwrap());
+        assert_eq!(store.contains(4).await.unwrap(), Some(Pending));
+    }
+
     #[tokio::test]
     async fn test_random() {
         let runtime = TestStoreRuntime::default();
@@ -0,0 +1,222 @@
+use crate::Config;
+use network::PeerId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Peers of completed file syncs are still pinged for a while, since they are likely to
+/// serve the next file sync.
+const RECENTLY_ACTIVE_PERIOD: Duration = Duration::from_secs(60);
+
+struct PingState {
+    /// Last time the peer is used by any file sync.
+    last_active: Instant,
+    /// Whether the last ping is awaiting for pong.
+    awaiting_pong: bool,
+    /// Number of pings missed in a row.
+    missed: usize,
+}
+
+/// Peers to ping and peers detected as dead in a round.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PingRound {
+    pub to_ping: Vec<PeerId>,
+    pub missed: Vec<PeerId>,
+    pub dead: Vec<PeerId>,
+}
+
+/// Detects dead peers of active or recently active file syncs by application level pings,
+/// e.g. peers behind aggressive NATs that silently drop the connection.
+///
+/// Peers are pinged once a round, which is triggered every `peer_ping_interval`, and a pong
+/// is expected before the next round. Peers are considered dead once `max_missed_pings` pings
+/// missed in a row, so that a dead peer is detected within `max_missed_pings + 1` rounds.
+pub struct PeerLiveness {
+    enabled: bool,
+    max_missed_pings: usize,
+    peers: HashMap<PeerId, PingState>,
+}
+
+impl PeerLiveness {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            enabled: config.max_missed_pings > 0 && !config.peer_ping_interval.is_zero(),
+            max_missed_pings: config.max_missed_pings,
+            peers: Default::default(),
+        }
+    }
+
+    /// Returns `false` if ping disabled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Marks the peer as used by an active file sync.
+    pub fn on_active(&mut self, peer_id: PeerId) {
+        self.peers
+            .entry(peer_id)
+            .and_modify(|state| state.last_active = Instant::now())
+            .or_insert_with(|| PingState {
+                last_active: Instant::now(),
+                awaiting_pong: false,
+                missed: 0,
+            });
+    }
+
+    /// Handles the pong received, and returns the number of pings missed before.
+    pub fn on_pong(&mut self, peer_id: &PeerId) -> usize {
+        match self.peers.get_mut(peer_id) {
+            Some(state) => {
+                state.awaiting_pong = false;
+                std::mem::take(&mut state.missed)
+            }
+            None => 0,
+        }
+    }
+
+    /// Handles the ping failed, e.g. stream closed, which is counted as missed immediately.
+    /// Returns `true` if the peer is considered dead.
+    pub fn on_ping_failed(&mut self, peer_id: &PeerId) -> bool {
+        let state = match self.peers.get_mut(peer_id) {
+            Some(state) if state.awaiting_pong => state,
+            _ => return false,
+        };
+
+        state.awaiting_pong = false;
+        state.missed += 1;
+
+        if state.missed < self.max_missed_pings {
+            return false;
+        }
+
+        self.peers.remove(peer_id);
+        true
+    }
+
+    pub fn on_peer_disconnected(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+
+    /// Starts a new round to ping peers, and collects peers that missed the ping of the
+    /// last round.
+    pub fn next_round(&mut self) -> PingRound {
+        let mut round = PingRound::default();
+
+        for (peer_id, state) in self.peers.iter_mut() {
+            if state.awaiting_pong {
+                state.awaiting_pong = false;
+                state.missed += 1;
+
+                if state.missed >= self.max_missed_pings {
+                    round.dead.push(*peer_id);
+                    continue;
+                }
+
+                round.missed.push(*peer_id);
+            }
+
+            if state.missed == 0 && state.last_active.elapsed() >= RECENTLY_ACTIVE_PERIOD {
+                continue;
+            }
+
+            state.awaiting_pong = true;
+            round.to_ping.push(*peer_id);
+        }
+
+        // peers dead or inactive for a while
+        self.peers.retain(|_, state| state.awaiting_pong);
+
+        round
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::identity;
+
+    fn random_peer() -> PeerId {
+        identity::Keypair::generate_ed25519().public().to_peer_id()
+    }
+
+    fn create_liveness(max_missed_pings: usize) -> PeerLiveness {
+        PeerLiveness::new(&Config {
+            max_missed_pings,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_dead_peer() {
+        let mut liveness = create_liveness(2);
+        let peer_alive = random_peer();
+        let peer_dead = random_peer();
+        liveness.on_active(peer_alive);
+        liveness.on_active(peer_dead);
+
+        // dead within `max_missed_pings + 1` rounds
+        let mut rounds = vec![];
+        for _ in 0..3 {
+            let mut round = liveness.next_round();
+            round.to_ping.sort();
+            rounds.push(round);
+            assert_eq!(liveness.on_pong(&peer_alive), 0);
+        }
+
+        let mut all_peers = vec![peer_alive, peer_dead];
+        all_peers.sort();
+        assert_eq!(rounds[0].to_ping, all_peers);
+        assert_eq!(rounds[1].to_ping, all_peers);
+        assert_eq!(rounds[1].missed, vec![peer_dead]);
+        assert_eq!(
+            rounds[2],
+            PingRound {
+                to_ping: vec![peer_alive],
+                missed: vec![],
+                dead: vec![peer_dead],
+            }
+        );
+
+        // not pinged anymore
+        assert_eq!(liveness.next_round().to_ping, vec![peer_alive]);
+    }
+
+    #[test]
+    fn test_pong_resets_missed() {
+        let mut liveness = create_liveness(2);
+        let peer_id = random_peer();
+        liveness.on_active(peer_id);
+
+        for _ in 0..5 {
+            let round = liveness.next_round();
+            assert_eq!(round.to_ping, vec![peer_id]);
+            assert!(round.dead.is_empty());
+
+            // pong for every other round
+            if !round.missed.is_empty() {
+                assert_eq!(liveness.on_pong(&peer_id), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ping_failed() {
+        let mut liveness = create_liveness(2);
+        let peer_id = random_peer();
+        liveness.on_active(peer_id);
+
+        // not pinged yet
+        assert!(!liveness.on_ping_failed(&peer_id));
+
+        liveness.next_round();
+        assert!(!liveness.on_ping_failed(&peer_id));
+        liveness.next_round();
+        assert!(liveness.on_ping_failed(&peer_id));
+        assert_eq!(liveness.next_round(), PingRound::default());
+    }
+
+    #[test]
+    fn test_disabled() {
+        assert!(!create_liveness(0).is_enabled());
+        assert!(create_liveness(1).is_enabled());
+    }
+}
@@ -1,13 +1,19 @@
+mod liveness;
 mod metrics;
 mod peers;
+mod scheduler;
 mod serial;
+mod trace;
 
 use std::collections::HashMap;
 
 use peers::PeerState;
 use serde::{Deserialize, Serialize};
 
-pub use serial::{FailureReason, SerialSyncController, SyncState};
+pub use liveness::PeerLiveness;
+pub use scheduler::{RequestScheduler, SyncPriority};
+pub use serial::{CancelReason, FailureReason, SerialSyncController, SyncState};
+pub use trace::{FileSyncTrace, ProofVerification, SyncTraceEvent, SyncTraceRecord, SyncTraces};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -23,6 +23,25 @@ pub enum PeerState {
     Disconnected,
 }
 
+/// Result of querying the file status of a peer, which verifies the file availability claimed by
+/// the peer before downloading chunks from it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileStatusCheck {
+    Unchecked,
+    /// Waiting for the file status with the segment sampled.
+    Querying {
+        sample_segment: u64,
+        since: InstantWrapper,
+    },
+    /// The sampled segment is verified.
+    Verified,
+    /// Failed to verify, e.g. the peer of an older version, which could still be selected to
+    /// sync chunks.
+    Unverified,
+    /// The file is not available or the peer lied about it, which is never selected to sync.
+    Rejected,
+}
+
 #[derive(Debug)]
 struct PeerInfo {
     /// The reported/connected address of the peer.
@@ -33,6 +52,8 @@ struct PeerInfo {
 
     pub shard_config: ShardConfig,
 
+    pub file_status_check: FileStatusCheck,
+
     /// Timestamp of the last state change.
     pub since: InstantWrapper,
 }
@@ -96,6 +117,7 @@ impl SyncPeers {
                 addr,
                 state: PeerState::Found,
                 shard_config,
+                file_status_check: FileStatusCheck::Unchecked,
                 since: Instant::now().into(),
             },
         );
@@ -103,6 +125,37 @@ impl SyncPeers {
         true
     }
 
+    /// Updates the shard config of an existing peer without changing its state, e.g. the peer
+    /// announced a new shard config after resharding. Returns `false` if peer not found.
+    pub fn update_shard_config(&mut self, peer_id: &PeerId, shard_config: ShardConfig) -> bool {
+        match self.peers.get_mut(peer_id) {
+            Some(info) => {
+                if info.shard_config != shard_config {
+                    // the sampled segment may be out of the new shard
+                    info.file_status_check = FileStatusCheck::Unchecked;
+                }
+                info.shard_config = shard_config;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn file_status_check(&self, peer_id: &PeerId) -> Option<FileStatusCheck> {
+        self.peers.get(peer_id).map(|info| info.file_status_check)
+    }
+
+    /// Updates the file status check of an existing peer. Returns `false` if peer not found.
+    pub fn update_file_status_check(&mut self, peer_id: &PeerId, check: FileStatusCheck) -> bool {
+        match self.peers.get_mut(peer_id) {
+            Some(info) => {
+                info.file_status_check = check;
+                true
+            }
+            None => false,
+        }
+    }
+
     #[cfg(test)]
     pub fn add_new_peer(&mut self, peer_id: PeerId, addr: Multiaddr) -> bool {
         self.add_new_peer_with_config(peer_id, addr, Default::default())
@@ -177,6 +230,11 @@ impl SyncPeers {
         all_shards_available(shard_configs)
     }
 
+    /// Removes all peers, e.g. the file sync cancelled.
+    pub fn clear(&mut self) {
+        self.peers.clear();
+    }
+
     pub fn transition(&mut self) {
         let mut bad_peers = vec![];
 
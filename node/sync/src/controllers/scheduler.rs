@@ -0,0 +1,511 @@
+use crate::context::SyncNetworkContext;
+use crate::Config;
+use network::{NetworkMessage, PeerId, Request, RequestId};
+use parking_lot::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::Span;
+
+/// Priority of a file sync, which determines its share of in-flight requests to a peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncPriority {
+    /// File syncs explicitly requested, e.g. chunks sync or resync via RPC.
+    High,
+    /// File syncs in background, e.g. auto sync or triggered by file announcements.
+    Normal,
+}
+
+struct PendingRequest {
+    request_id: RequestId,
+    request: Request,
+    /// Span of the file sync that submitted the request.
+    span: Span,
+}
+
+/// Requests of all file syncs to a single peer.
+#[derive(Default)]
+struct PeerQueues {
+    /// Dispatch time of in-flight requests of each file sync.
+    in_flight: HashMap<u64, VecDeque<Instant>>,
+    /// Requests of each file sync waiting for an in-flight slot.
+    queued: HashMap<u64, VecDeque<PendingRequest>>,
+    /// File syncs with queued requests in round-robin order.
+    active: VecDeque<u64>,
+    /// Number of requests dispatched for the file sync in front of `active` in this round.
+    served: usize,
+}
+
+impl PeerQueues {
+    fn num_in_flight(&self) -> usize {
+        self.in_flight.values().map(|v| v.len()).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.in_flight.is_empty() && self.queued.is_empty()
+    }
+
+    fn remove_queued(&mut self, sync_id: u64) {
+        if self.queued.remove(&sync_id).is_some() {
+            if self.active.front() == Some(&sync_id) {
+                self.served = 0;
+            }
+            self.active.retain(|id| *id != sync_id);
+        }
+    }
+}
+
+struct Inner {
+    max_requests_per_peer: usize,
+    high_priority_weight: usize,
+    normal_priority_weight: usize,
+    next_sync_id: u64,
+    syncs: HashMap<u64, SyncPriority>,
+    peers: HashMap<PeerId, PeerQueues>,
+}
+
+impl Inner {
+    fn weight(&self, sync_id: u64) -> usize {
+        match self.syncs.get(&sync_id) {
+            Some(SyncPriority::High) => self.high_priority_weight,
+            _ => self.normal_priority_weight,
+        }
+    }
+
+    /// Dispatches queued requests to the peer in weighted round-robin order, until all
+    /// in-flight slots of the peer are occupied.
+    fn dispatch(&mut self, peer_id: &PeerId) -> Vec<PendingRequest> {
+        let mut dispatched = vec![];
+
+        let weights: HashMap<u64, usize> = match self.peers.get(peer_id) {
+            Some(queues) => queues
+                .active
+                .iter()
+                .map(|id| (*id, self.weight(*id)))
+                .collect(),
+            None => return dispatched,
+        };
+        let max_in_flight = self.max_requests_per_peer;
+        let queues = self.peers.get_mut(peer_id).expect("peer queues exist");
+        let mut num_in_flight = queues.num_in_flight();
+
+        while num_in_flight < max_in_flight {
+            let sync_id = match queues.active.front() {
+                Some(id) => *id,
+                None => break,
+            };
+
+            let queue = queues
+                .queued
+                .get_mut(&sync_id)
+                .expect("active file sync has queued requests");
+            if let Some(request) = queue.pop_front() {
+                dispatched.push(request);
+                queues
+                    .in_flight
+                    .entry(sync_id)
+                    .or_default()
+                    .push_back(Instant::now());
+                queues.served += 1;
+                num_in_flight += 1;
+            }
+
+            if queue.is_empty() {
+                queues.queued.remove(&sync_id);
+                queues.active.pop_front();
+                queues.served = 0;
+            } else if queues.served >= weights[&sync_id] {
+                // give other file syncs a turn
+                queues.active.rotate_left(1);
+                queues.served = 0;
+            }
+        }
+
+        dispatched
+    }
+
+    /// Removes queued and in-flight requests of a file sync to the peer, and dispatches
+    /// requests of other file syncs to the released slots.
+    fn cancel(&mut self, sync_id: u64, peer_id: &PeerId) -> Vec<PendingRequest> {
+        match self.peers.get_mut(peer_id) {
+            Some(queues) => {
+                queues.in_flight.remove(&sync_id);
+                queues.remove_queued(sync_id);
+            }
+            None => return vec![],
+        }
+
+        self.dispatch_or_gc(peer_id)
+    }
+
+    fn dispatch_or_gc(&mut self, peer_id: &PeerId) -> Vec<PendingRequest> {
+        let dispatched = self.dispatch(peer_id);
+        if matches!(self.peers.get(peer_id), Some(queues) if queues.is_empty()) {
+            self.peers.remove(peer_id);
+        }
+        dispatched
+    }
+}
+
+/// Schedules the outbound requests of all file syncs, so that each file sync gets a fair
+/// share of in-flight requests to a peer, weighted by its priority.
+///
+/// Requests are dispatched to a peer in weighted round-robin order among file syncs, and
+/// queued once `max_requests_per_peer` requests in flight. This prevents a huge file from
+/// monopolizing a fast peer when many files in sync.
+#[derive(Clone)]
+pub struct RequestScheduler {
+    inner: Arc<Mutex<Inner>>,
+    ctx: Arc<SyncNetworkContext>,
+}
+
+impl RequestScheduler {
+    pub fn new(config: &Config, ctx: Arc<SyncNetworkContext>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                max_requests_per_peer: config.max_requests_per_peer.max(1),
+                high_priority_weight: config.high_priority_request_weight.max(1),
+                normal_priority_weight: config.normal_priority_request_weight.max(1),
+                next_sync_id: 0,
+                syncs: Default::default(),
+                peers: Default::default(),
+            })),
+            ctx,
+        }
+    }
+
+    /// Updates the maximum number of in-flight requests to a peer at runtime. Requests already
+    /// in flight are not cancelled if decreased, and queued requests are dispatched at once if
+    /// increased.
+    pub fn set_max_requests_per_peer(&self, max_requests_per_peer: usize) {
+        let dispatched: Vec<_> = {
+            let mut inner = self.inner.lock();
+            inner.max_requests_per_peer = max_requests_per_peer.max(1);
+            let peers: Vec<PeerId> = inner.peers.keys().copied().collect();
+            peers
+                .into_iter()
+                .map(|peer_id| (peer_id, inner.dispatch(&peer_id)))
+                .collect()
+        };
+
+        for (peer_id, requests) in dispatched {
+            self.send(peer_id, requests);
+        }
+    }
+
+    /// Registers a file sync, whose requests are cancelled once the returned handle dropped.
+    pub fn register(&self, priority: SyncPriority) -> SyncRequestHandle {
+        let mut inner = self.inner.lock();
+        let id = inner.next_sync_id;
+        inner.next_sync_id += 1;
+        inner.syncs.insert(id, priority);
+
+        SyncRequestHandle {
+            id,
+            scheduler: self.clone(),
+        }
+    }
+
+    fn send(&self, peer_id: PeerId, requests: Vec<PendingRequest>) {
+        for PendingRequest {
+            request_id,
+            request,
+            span,
+        } in requests
+        {
+            self.ctx.send(NetworkMessage::SendRequest {
+                peer_id,
+                request_id,
+                request,
+                span,
+            });
+        }
+    }
+}
+
+/// Handle of a file sync to submit requests via the `RequestScheduler`.
+pub struct SyncRequestHandle {
+    id: u64,
+    scheduler: RequestScheduler,
+}
+
+impl SyncRequestHandle {
+    /// Submits a request to the peer, which is sent immediately if any in-flight slot
+    /// available. Otherwise, it is queued and returns `false`.
+    pub fn submit(&self, peer_id: PeerId, request_id: RequestId, request: Request) -> bool {
+        let (dispatched, is_queued) = {
+            let mut inner = self.scheduler.inner.lock();
+            let queues = inner.peers.entry(peer_id).or_default();
+            let queue = queues.queued.entry(self.id).or_default();
+            if queue.is_empty() {
+                queues.active.push_back(self.id);
+            }
+            queue.push_back(PendingRequest {
+                request_id,
+                request,
+                span: Span::current(),
+            });
+            let dispatched = inner.dispatch(&peer_id);
+            (
+                dispatched,
+                inner.peers[&peer_id].queued.contains_key(&self.id),
+            )
+        };
+
+        self.scheduler.send(peer_id, dispatched);
+
+        !is_queued
+    }
+
+    /// Releases the in-flight slot of the earliest request to the peer once responded or
+    /// failed, so that the queued requests could be dispatched.
+    pub fn on_completed(&self, peer_id: &PeerId) {
+        let dispatched = {
+            let mut inner = self.scheduler.inner.lock();
+            match inner.peers.get_mut(peer_id) {
+                Some(queues) => {
+                    if let Some(in_flight) = queues.in_flight.get_mut(&self.id) {
+                        in_flight.pop_front();
+                        if in_flight.is_empty() {
+                            queues.in_flight.remove(&self.id);
+                        }
+                    }
+                }
+                None => return,
+            }
+            inner.dispatch_or_gc(peer_id)
+        };
+
+        self.scheduler.send(*peer_id, dispatched);
+    }
+
+    /// Cancels all queued and in-flight requests to the peer, e.g. timeout or peer
+    /// disconnected. Note, responses of cancelled requests may still be received.
+    pub fn cancel(&self, peer_id: &PeerId) {
+        let dispatched = self.scheduler.inner.lock().cancel(self.id, peer_id);
+        self.scheduler.send(*peer_id, dispatched);
+    }
+
+    /// Cancels all queued and in-flight requests to any peer.
+    pub fn cancel_all(&self) {
+        let dispatched: Vec<_> = {
+            let mut inner = self.scheduler.inner.lock();
+            let peers: Vec<PeerId> = inner
+                .peers
+                .iter()
+                .filter(|(_, queues)| {
+                    queues.in_flight.contains_key(&self.id) || queues.queued.contains_key(&self.id)
+                })
+                .map(|(peer_id, _)| *peer_id)
+                .collect();
+            peers
+                .into_iter()
+                .map(|peer_id| (peer_id, inner.cancel(self.id, &peer_id)))
+                .collect()
+        };
+
+        for (peer_id, requests) in dispatched {
+            self.scheduler.send(peer_id, requests);
+        }
+    }
+
+    /// Returns the dispatch time of the earliest in-flight request to the peer, or `None`
+    /// if requests are still queued or already completed.
+    pub fn dispatched_at(&self, peer_id: &PeerId) -> Option<Instant> {
+        self.scheduler
+            .inner
+            .lock()
+            .peers
+            .get(peer_id)?
+            .in_flight
+            .get(&self.id)?
+            .front()
+            .copied()
+    }
+}
+
+impl Drop for SyncRequestHandle {
+    fn drop(&mut self) {
+        self.cancel_all();
+        self.scheduler.inner.lock().syncs.remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::identity;
+    use network::rpc::GetChunksRequest;
+    use network::{new_network_channel, NetworkReceiver, SyncId};
+    use shared_types::TxID;
+    use storage::H256;
+
+    fn create_scheduler(max_requests_per_peer: usize) -> (RequestScheduler, NetworkReceiver) {
+        let (network_send, network_recv) = new_network_channel();
+        let config = Config {
+            max_requests_per_peer,
+            high_priority_request_weight: 2,
+            normal_priority_request_weight: 1,
+            ..Default::default()
+        };
+        let scheduler =
+            RequestScheduler::new(&config, Arc::new(SyncNetworkContext::new(network_send)));
+        (scheduler, network_recv)
+    }
+
+    fn submit(handle: &SyncRequestHandle, peer_id: PeerId, tx_seq: u64, index: u64) -> bool {
+        let tx_id = TxID {
+            seq: tx_seq,
+            hash: H256::zero(),
+        };
+        handle.submit(
+            peer_id,
+            RequestId::Sync(Instant::now(), SyncId::SerialSync { tx_id }),
+            Request::GetChunks(GetChunksRequest {
+                tx_id,
+                index_start: index,
+                index_end: index + 1,
+                merkle_tx_seq: 0,
+            }),
+        )
+    }
+
+    /// Returns the (tx_seq, index_start) of requests sent to the network.
+    fn sent_requests(network_recv: &mut NetworkReceiver, expected_peer: PeerId) -> Vec<(u64, u64)> {
+        let mut requests = vec![];
+        while let Ok(msg) = network_recv.try_recv() {
+            match msg {
+                NetworkMessage::SendRequest {
+                    peer_id,
+                    request: Request::GetChunks(request),
+                    ..
+                } => {
+                    assert_eq!(peer_id, expected_peer);
+                    requests.push((request.tx_id.seq, request.index_start));
+                }
+                msg => panic!("Unexpected message: {:?}", msg),
+            }
+        }
+        requests
+    }
+
+    #[test]
+    fn test_interleave_syncs() {
+        let (scheduler, mut network_recv) = create_scheduler(1);
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let sync1 = scheduler.register(SyncPriority::Normal);
+        let sync2 = scheduler.register(SyncPriority::Normal);
+
+        // huge file submitted first
+        assert!(submit(&sync1, peer_id, 1, 0));
+        for index in 1..4 {
+            assert!(!submit(&sync1, peer_id, 1, index));
+        }
+        for index in 0..2 {
+            assert!(!submit(&sync2, peer_id, 2, index));
+        }
+        assert_eq!(sent_requests(&mut network_recv, peer_id), vec![(1, 0)]);
+        assert!(sync1.dispatched_at(&peer_id).is_some());
+        assert!(sync2.dispatched_at(&peer_id).is_none());
+
+        // complete the only in-flight request one by one
+        let mut order = vec![(1, 0)];
+        while order.len() < 6 {
+            match order.last() {
+                Some((1, _)) => sync1.on_completed(&peer_id),
+                _ => sync2.on_completed(&peer_id),
+            }
+            order.extend(sent_requests(&mut network_recv, peer_id));
+        }
+
+        assert_eq!(order, vec![(1, 0), (1, 1), (2, 0), (1, 2), (2, 1), (1, 3)]);
+
+        sync1.on_completed(&peer_id);
+        assert!(scheduler.inner.lock().peers.is_empty());
+    }
+
+    #[test]
+    fn test_set_max_requests_per_peer() {
+        let (scheduler, mut network_recv) = create_scheduler(1);
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let sync = scheduler.register(SyncPriority::Normal);
+
+        for index in 0..4 {
+            submit(&sync, peer_id, 1, index);
+        }
+        assert_eq!(sent_requests(&mut network_recv, peer_id), vec![(1, 0)]);
+
+        // queued requests dispatched once increased
+        scheduler.set_max_requests_per_peer(3);
+        assert_eq!(
+            sent_requests(&mut network_recv, peer_id),
+            vec![(1, 1), (1, 2)]
+        );
+
+        // in-flight requests kept once decreased
+        scheduler.set_max_requests_per_peer(1);
+        sync.on_completed(&peer_id);
+        sync.on_completed(&peer_id);
+        assert!(sent_requests(&mut network_recv, peer_id).is_empty());
+        sync.on_completed(&peer_id);
+        assert_eq!(sent_requests(&mut network_recv, peer_id), vec![(1, 3)]);
+    }
+
+    #[test]
+    fn test_weighted_priority() {
+        let (scheduler, mut network_recv) = create_scheduler(1);
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let normal = scheduler.register(SyncPriority::Normal);
+        let high = scheduler.register(SyncPriority::High);
+
+        for index in 0..3 {
+            submit(&normal, peer_id, 1, index);
+        }
+        for index in 0..4 {
+            submit(&high, peer_id, 2, index);
+        }
+
+        let mut order = sent_requests(&mut network_recv, peer_id);
+        while order.len() < 7 {
+            match order.last() {
+                Some((1, _)) => normal.on_completed(&peer_id),
+                _ => high.on_completed(&peer_id),
+            }
+            order.extend(sent_requests(&mut network_recv, peer_id));
+        }
+
+        // high priority file sync dispatched twice in each round
+        assert_eq!(
+            order,
+            vec![(1, 0), (1, 1), (2, 0), (2, 1), (1, 2), (2, 2), (2, 3)]
+        );
+    }
+
+    #[test]
+    fn test_cancel_on_drop() {
+        let (scheduler, mut network_recv) = create_scheduler(2);
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let sync1 = scheduler.register(SyncPriority::Normal);
+        let sync2 = scheduler.register(SyncPriority::Normal);
+
+        assert!(submit(&sync1, peer_id, 1, 0));
+        assert!(submit(&sync1, peer_id, 1, 1));
+        assert!(!submit(&sync2, peer_id, 2, 0));
+        assert!(!submit(&sync2, peer_id, 2, 1));
+        assert_eq!(
+            sent_requests(&mut network_recv, peer_id),
+            vec![(1, 0), (1, 1)]
+        );
+
+        // in-flight slots released once file sync terminated
+        drop(sync1);
+        assert_eq!(
+            sent_requests(&mut network_recv, peer_id),
+            vec![(2, 0), (2, 1)]
+        );
+
+        sync2.cancel(&peer_id);
+        assert!(sync2.dispatched_at(&peer_id).is_none());
+        assert!(scheduler.inner.lock().peers.is_empty());
+    }
+}
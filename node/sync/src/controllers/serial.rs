@@ -1,26 +1,41 @@
 use crate::context::SyncNetworkContext;
-use crate::controllers::peers::{PeerState, SyncPeers};
+use crate::controllers::peers::{FileStatusCheck, PeerState, SyncPeers};
+use crate::controllers::scheduler::SyncRequestHandle;
+use crate::controllers::trace::{ProofVerification, SyncTrace, SyncTraceEvent};
 use crate::controllers::{metrics, FileSyncGoal, FileSyncInfo};
-use crate::{Config, InstantWrapper};
+use crate::peer_stats::PeerStats;
+use crate::{Config, DynamicConfig, InstantWrapper};
 use file_location_cache::FileLocationCache;
 use libp2p::swarm::DialError;
 use network::types::FindChunks;
 use network::{
-    multiaddr::Protocol, rpc::GetChunksRequest, types::FindFile, Multiaddr, NetworkMessage,
-    PeerAction, PeerId, PubsubMessage, SyncId as RequestId,
+    multiaddr::Protocol,
+    rpc::{FileStatus, GetChunksRequest, QueryFileStatusRequest},
+    types::FindFile,
+    Multiaddr, NetworkMessage, PeerAction, PeerId, PubsubMessage, SyncId as RequestId,
 };
-use rand::Rng;
-use shared_types::{ChunkArrayWithProof, ShardedFile, TxID, CHUNK_SIZE};
+use parking_lot::Mutex;
+use rand::{seq::IteratorRandom, Rng};
+use shared_types::{bytes_to_chunks, ChunkArrayWithProof, ShardedFile, TxID, CHUNK_SIZE};
 use ssz::Encode;
-use std::{sync::Arc, time::Instant};
+use std::{collections::HashMap, sync::Arc, time::Instant};
+use storage::error::{StoreError, StoreItem};
 use storage::log_store::log_manager::{sector_to_segment, segment_to_sector, PORA_CHUNK_SIZE};
 use storage_async::{ShardConfig, Store};
+use tracing::Span;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum FailureReason {
     DBError(String),
     TxReverted(TxID),
     TimeoutFindFile,
+    Cancelled(CancelReason),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CancelReason {
+    /// Cancelled via admin RPC, e.g. the file sync is stuck.
+    Manual,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -53,6 +68,23 @@ pub enum SyncState {
     },
 }
 
+impl SyncState {
+    /// Name of the state without the timestamps, e.g. `Downloading`, to trace the state changes.
+    fn trace_name(&self) -> String {
+        match self {
+            SyncState::Idle => "Idle".into(),
+            SyncState::FindingPeers { .. } => "FindingPeers".into(),
+            SyncState::FoundPeers => "FoundPeers".into(),
+            SyncState::ConnectingPeers { .. } => "ConnectingPeers".into(),
+            SyncState::AwaitingOutgoingConnection { .. } => "AwaitingOutgoingConnection".into(),
+            SyncState::AwaitingDownload { .. } => "AwaitingDownload".into(),
+            SyncState::Downloading { .. } => "Downloading".into(),
+            SyncState::Completed => "Completed".into(),
+            SyncState::Failed { reason } => format!("Failed({:?})", reason),
+        }
+    }
+}
+
 pub struct SerialSyncController {
     config: Config,
 
@@ -84,11 +116,28 @@ pub struct SerialSyncController {
     /// A network context to contact the network service.
     ctx: Arc<SyncNetworkContext>,
 
+    /// Handle to send requests via the scheduler shared by all file syncs.
+    scheduler: SyncRequestHandle,
+
     /// Log and transaction storage.
     store: Store,
 
     /// Cache for storing and serving gossip messages.
     file_location_cache: Arc<FileLocationCache>,
+
+    /// Contribution of peers to file sync, shared by all file syncs.
+    peer_stats: Arc<PeerStats>,
+
+    /// Span of the file sync, which is a child of the requester span if any, e.g. the RPC call
+    /// to sync file, so that all logs of the file sync could be correlated with the requester.
+    span: Span,
+
+    /// Records the events of the file sync if traced via admin RPC.
+    trace: Option<Arc<Mutex<SyncTrace>>>,
+
+    /// Number of in-flight requests of each peer cancelled by the file sync, e.g. on reset,
+    /// whose late responses are dropped without penalizing the peer.
+    cancelled_requests: HashMap<PeerId, usize>,
 }
 
 impl SerialSyncController {
@@ -98,8 +147,10 @@ impl SerialSyncController {
         tx_start_chunk_in_flow: u64,
         goal: FileSyncGoal,
         ctx: Arc<SyncNetworkContext>,
+        scheduler: SyncRequestHandle,
         store: Store,
         file_location_cache: Arc<FileLocationCache>,
+        peer_stats: Arc<PeerStats>,
     ) -> Self {
         SerialSyncController {
             config,
@@ -113,11 +164,44 @@ impl SerialSyncController {
             state: SyncState::Idle,
             peers: SyncPeers::new(config, ctx.clone(), tx_id, file_location_cache.clone()),
             ctx,
+            scheduler,
             store,
             file_location_cache,
+            peer_stats,
+            span: info_span!("file_sync", tx_seq = tx_id.seq),
+            trace: None,
+            cancelled_requests: Default::default(),
         }
     }
 
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Starts to record the events of the file sync into `trace`, or stops if `None`.
+    pub fn set_trace(&mut self, trace: Option<Arc<Mutex<SyncTrace>>>) {
+        self.trace = trace;
+        self.trace_state();
+    }
+
+    /// Records the event if traced, which is not constructed otherwise.
+    fn trace(&self, event: impl FnOnce() -> SyncTraceEvent) {
+        if let Some(trace) = &self.trace {
+            trace.lock().record(event());
+        }
+    }
+
+    fn trace_state(&self) {
+        self.trace(|| SyncTraceEvent::StateChanged {
+            state: self.state.trace_name(),
+        });
+    }
+
+    /// Applies the sync config reloaded at runtime, e.g. bandwidth limit.
+    pub fn set_dynamic_config(&mut self, dynamic: DynamicConfig) {
+        self.config.set_dynamic(dynamic);
+    }
+
     pub fn get_sync_info(&self) -> FileSyncInfo {
         FileSyncInfo {
             elapsed_secs: self.since.elapsed().as_secs(),
@@ -153,11 +237,38 @@ impl SerialSyncController {
         }
 
         self.failures = 0;
+        self.forget_in_flight_request();
         self.state = SyncState::Idle;
+        self.scheduler.cancel_all();
         // remove disconnected peers
         self.peers.transition();
     }
 
+    /// Cancels the file sync with all requests and peers released, which is kept in the
+    /// failed state until reset or retried.
+    pub fn cancel(&mut self, reason: CancelReason) {
+        info!(%self.tx_seq, ?reason, "File sync cancelled");
+        self.forget_in_flight_request();
+        self.scheduler.cancel_all();
+        self.peers.clear();
+        self.state = SyncState::Failed {
+            reason: FailureReason::Cancelled(reason),
+        };
+    }
+
+    /// Restarts the file sync from the break point with the request failures and peers
+    /// cleared, whose requests are scheduled by `scheduler` from now on, e.g. at a higher
+    /// priority.
+    pub fn retry(&mut self, scheduler: SyncRequestHandle) {
+        info!(%self.tx_seq, %self.next_chunk, "Retry file sync");
+        // Requests of the previous handle are cancelled once dropped.
+        self.forget_in_flight_request();
+        self.scheduler = scheduler;
+        self.failures = 0;
+        self.peers.clear();
+        self.state = SyncState::Idle;
+    }
+
     /// Find more peers to sync chunks. Return whether `FindFile` pubsub message published,
     fn try_find_peers(&mut self) {
         let (published, num_new_peers) = if !self.goal.is_all_chunks() {
@@ -294,6 +405,8 @@ impl SerialSyncController {
             }
         }
 
+        self.try_query_file_status();
+
         // request next chunk array
         let from_chunk = self.next_chunk;
         let to_chunk = std::cmp::min(from_chunk + PORA_CHUNK_SIZE as u64, self.goal.index_end);
@@ -314,6 +427,14 @@ impl SerialSyncController {
         // select a random peer
         let peer_id = match self.select_peer_for_request(&request) {
             Some(peer_id) => peer_id,
+            None if self.is_querying_file_status() => {
+                debug!(%self.tx_seq, "Waiting for file status of peers to request chunks");
+                self.state = SyncState::AwaitingDownload {
+                    since: (Instant::now() + self.config.peer_next_chunks_request_wait_timeout)
+                        .into(),
+                };
+                return;
+            }
             None => {
                 warn!(%self.tx_seq, "No peers available to request chunks");
                 self.state = SyncState::Idle;
@@ -321,14 +442,22 @@ impl SerialSyncController {
             }
         };
 
-        self.ctx.send(NetworkMessage::SendRequest {
-            peer_id,
-            request_id,
-            request: network::Request::GetChunks(request),
+        // the request may be queued if the peer is busy with other file syncs
+        let sent = self
+            .scheduler
+            .submit(peer_id, request_id, network::Request::GetChunks(request));
+        if sent {
+            info!(%self.tx_seq, %from_chunk, %to_chunk, %peer_id, "Sent request to get chunks");
+        } else {
+            info!(%self.tx_seq, %from_chunk, %to_chunk, %peer_id, "Queued request to get chunks");
+        }
+        self.trace(|| SyncTraceEvent::RequestSent {
+            peer_id: peer_id.to_string(),
+            from_chunk,
+            to_chunk,
+            queued: !sent,
         });
 
-        info!(%self.tx_seq, %from_chunk, %to_chunk, %peer_id, "Sent request to get chunks");
-
         self.state = SyncState::Downloading {
             peer_id,
             from_chunk,
@@ -337,6 +466,88 @@ impl SerialSyncController {
         };
     }
 
+    /// Queries the file status of connected peers not checked yet, so that peers lied about the
+    /// file availability are rejected before chunks requested from them.
+    fn try_query_file_status(&mut self) {
+        if !self.config.peer_file_status_check_enabled {
+            return;
+        }
+
+        let committed_tx_seq = self.store.get_store().next_tx_seq().saturating_sub(1);
+
+        for peer_id in self.peers.filter_peers(vec![PeerState::Connected]) {
+            match self.peers.file_status_check(&peer_id) {
+                Some(FileStatusCheck::Unchecked) => {}
+                Some(FileStatusCheck::Querying { since, .. })
+                    if since.elapsed() >= self.config.peer_chunks_download_timeout =>
+                {
+                    info!(%self.tx_seq, %peer_id, "Query file status timeout");
+                    self.peers
+                        .update_file_status_check(&peer_id, FileStatusCheck::Unverified);
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let sample_segment = match self.sample_segment(&peer_id) {
+                Some(index) => index,
+                None => {
+                    self.peers
+                        .update_file_status_check(&peer_id, FileStatusCheck::Unverified);
+                    continue;
+                }
+            };
+
+            self.ctx.send(NetworkMessage::SendRequest {
+                peer_id,
+                request_id: network::RequestId::Sync(
+                    Instant::now(),
+                    RequestId::FileStatus { tx_id: self.tx_id },
+                ),
+                request: network::Request::QueryFileStatus(QueryFileStatusRequest {
+                    tx_id: self.tx_id,
+                    sample_segment,
+                    merkle_tx_seq: committed_tx_seq,
+                }),
+            });
+            self.peers.update_file_status_check(
+                &peer_id,
+                FileStatusCheck::Querying {
+                    sample_segment,
+                    since: Instant::now().into(),
+                },
+            );
+            debug!(%self.tx_seq, %peer_id, %sample_segment, "Sent request to query file status");
+        }
+    }
+
+    /// Randomly selects a segment in file to sync, which is in the shard of the peer.
+    fn sample_segment(&self, peer_id: &PeerId) -> Option<u64> {
+        let shard_config = self.peers.shard_config(peer_id)?;
+        let start_segment = sector_to_segment(self.goal.index_start);
+        let end_segment = (self.goal.index_end as usize + PORA_CHUNK_SIZE - 1) / PORA_CHUNK_SIZE;
+
+        (start_segment..end_segment)
+            .filter(|index| {
+                let sector = self.tx_start_chunk_in_flow + segment_to_sector(*index) as u64;
+                shard_config.in_range(sector_to_segment(sector) as u64)
+            })
+            .choose(&mut rand::thread_rng())
+            .map(|index| index as u64)
+    }
+
+    fn is_querying_file_status(&self) -> bool {
+        self.peers
+            .filter_peers(vec![PeerState::Connected])
+            .iter()
+            .any(|peer_id| {
+                matches!(
+                    self.peers.file_status_check(peer_id),
+                    Some(FileStatusCheck::Querying { .. })
+                )
+            })
+    }
+
     fn ban_peer(&mut self, peer_id: PeerId, reason: &'static str) {
         debug!(%self.tx_seq, %peer_id, %reason, "Ban peer");
         self.ctx.ban_peer(peer_id, reason);
@@ -352,6 +563,9 @@ impl SerialSyncController {
                 .add_new_peer_with_config(peer_id, addr.clone(), shard_config)
             {
                 debug!(%self.tx_seq, %peer_id, %addr, "Found new peer");
+                self.trace(|| SyncTraceEvent::PeerFound {
+                    peer_id: peer_id.to_string(),
+                });
                 true
             } else {
                 // e.g. multiple `AnnounceFile` messages propagated
@@ -404,6 +618,9 @@ impl SerialSyncController {
                 .update_state(&peer_id, PeerState::Connecting, PeerState::Connected)
         {
             info!(%self.tx_seq, %peer_id, "Peer connected");
+            self.trace(|| SyncTraceEvent::PeerConnected {
+                peer_id: peer_id.to_string(),
+            });
         }
     }
 
@@ -416,14 +633,86 @@ impl SerialSyncController {
             Some(old_state) => {
                 info!(%self.tx_seq, %peer_id, ?old_state, "Peer disconnected by remote");
             }
-            None => {}
+            None => return,
+        }
+        self.trace(|| SyncTraceEvent::PeerDisconnected {
+            peer_id: peer_id.to_string(),
+        });
+    }
+
+    /// Handles the peer detected as dead, e.g. pings missed, so that the outstanding request
+    /// is reassigned to other peers immediately.
+    pub fn on_peer_dead(&mut self, peer_id: PeerId) {
+        if let Some(true) =
+            self.peers
+                .update_state(&peer_id, PeerState::Connected, PeerState::Disconnecting)
+        {
+            info!(%self.tx_seq, %peer_id, "Peer dead");
+        }
+    }
+
+    /// Returns the connected peers to sync chunks from, if the file sync is in progress.
+    pub fn active_peers(&self) -> Vec<PeerId> {
+        if self.is_completed_or_failed() {
+            return vec![];
+        }
+
+        self.peers.filter_peers(vec![PeerState::Connected])
+    }
+
+    /// Handles the new shard config announced by a peer. The in-flight request is dropped
+    /// and sent to other peers if the peer could no longer serve the requested chunks.
+    pub fn on_peer_shard_config_changed(&mut self, peer_id: PeerId, shard_config: ShardConfig) {
+        if !self.peers.update_shard_config(&peer_id, shard_config) {
+            return;
+        }
+
+        if let SyncState::Downloading {
+            peer_id: downloading_peer_id,
+            from_chunk,
+            ..
+        } = self.state
+        {
+            let segment_index = sector_to_segment(from_chunk + self.tx_start_chunk_in_flow);
+            if downloading_peer_id == peer_id && !shard_config.in_range(segment_index as u64) {
+                info!(%self.tx_seq, %peer_id, ?shard_config, "Peer no longer serves the requested chunks, try other peers");
+                self.forget_in_flight_request();
+                self.scheduler.cancel(&peer_id);
+                self.state = SyncState::AwaitingDownload {
+                    since: Instant::now().into(),
+                };
+            }
+        }
+    }
+
+    /// Records the in-flight request of the downloading peer before cancelled, so that its late
+    /// response is dropped silently. The request only queued is never sent, and not recorded.
+    fn forget_in_flight_request(&mut self) {
+        if let SyncState::Downloading { peer_id, .. } = self.state {
+            if self.scheduler.dispatched_at(&peer_id).is_some() {
+                *self.cancelled_requests.entry(peer_id).or_default() += 1;
+            }
         }
     }
 
     /// Handle the case that got an unexpected response:
-    /// 1. not in `Downloading` sync state.
-    /// 2. from unexpected peer.
-    fn handle_on_response_mismatch(&self, from_peer_id: PeerId) -> bool {
+    /// 1. of a request cancelled by the file sync, which is dropped silently.
+    /// 2. not in `Downloading` sync state.
+    /// 3. from unexpected peer.
+    fn handle_on_response_mismatch(&mut self, from_peer_id: PeerId) -> bool {
+        if let Some(count) = self.cancelled_requests.get_mut(&from_peer_id) {
+            *count -= 1;
+            if *count == 0 {
+                self.cancelled_requests.remove(&from_peer_id);
+            }
+            debug!(%self.tx_seq, %from_peer_id, "Dropped response of cancelled request");
+            self.trace(|| SyncTraceEvent::ResponseRejected {
+                peer_id: from_peer_id.to_string(),
+                reason: "Request cancelled".into(),
+            });
+            return true;
+        }
+
         match self.state {
             SyncState::Downloading { peer_id, .. } => {
                 if from_peer_id == peer_id {
@@ -433,6 +722,10 @@ impl SerialSyncController {
                 // got response from wrong peer
                 // this can happen if we get a response for a timeout request
                 warn!(%self.tx_seq, %from_peer_id, %peer_id, "Got response from unexpected peer");
+                self.trace(|| SyncTraceEvent::ResponseRejected {
+                    peer_id: from_peer_id.to_string(),
+                    reason: "Peer id mismatch".into(),
+                });
                 self.ctx.report_peer(
                     from_peer_id,
                     PeerAction::LowToleranceError,
@@ -443,6 +736,10 @@ impl SerialSyncController {
             _ => {
                 // Delayed response can enter this.
                 warn!(%self.tx_seq, %from_peer_id, ?self.state, "Got response in unexpected state");
+                self.trace(|| SyncTraceEvent::ResponseRejected {
+                    peer_id: from_peer_id.to_string(),
+                    reason: "Sync state mismatch".into(),
+                });
                 self.ctx.report_peer(
                     from_peer_id,
                     PeerAction::LowToleranceError,
@@ -455,6 +752,10 @@ impl SerialSyncController {
 
     pub async fn on_response(&mut self, from_peer_id: PeerId, response: ChunkArrayWithProof) {
         metrics::SERIAL_SYNC_SEGMENT_BANDWIDTH.mark(response.ssz_bytes_len());
+        self.trace(|| SyncTraceEvent::ResponseReceived {
+            peer_id: from_peer_id.to_string(),
+            bytes: response.chunks.data.len(),
+        });
 
         if self.handle_on_response_mismatch(from_peer_id) {
             return;
@@ -478,7 +779,12 @@ impl SerialSyncController {
         let data_len = response.chunks.data.len();
         if data_len == 0 || data_len % CHUNK_SIZE > 0 {
             warn!(%from_peer_id, %self.tx_seq, %data_len, "Invalid chunk response data length");
+            self.trace(|| SyncTraceEvent::ResponseRejected {
+                peer_id: from_peer_id.to_string(),
+                reason: "Invalid chunk response data length".into(),
+            });
             metrics::SERIAL_SYNC_UNEXPECTED_ERRORS.inc(1);
+            self.scheduler.on_completed(&from_peer_id);
             self.ban_peer(from_peer_id, "Invalid chunk response data length");
             self.state = SyncState::Idle;
             return;
@@ -489,6 +795,10 @@ impl SerialSyncController {
         let end_index = start_index + (data_len / CHUNK_SIZE) as u64;
         if start_index != from_chunk || end_index != to_chunk {
             warn!(%self.tx_seq, "Invalid chunk response range, expected={from_chunk}..{to_chunk}, actual={start_index}..{end_index}");
+            self.trace(|| SyncTraceEvent::ResponseRejected {
+                peer_id: from_peer_id.to_string(),
+                reason: format!("Invalid chunk response range {start_index}..{end_index}"),
+            });
             self.ctx.report_peer(
                 from_peer_id,
                 PeerAction::LowToleranceError,
@@ -497,14 +807,28 @@ impl SerialSyncController {
             return;
         }
 
+        self.scheduler.on_completed(&from_peer_id);
+
         // validate Merkle proofs
         let validation_result = self
             .store
             .get_store()
             .validate_and_insert_range_proof(self.tx_seq, &response);
+        self.trace(|| {
+            let (result, error) = match &validation_result {
+                Ok(true) => (ProofVerification::Valid, None),
+                Ok(false) => (ProofVerification::RootNotFound, None),
+                Err(err) => (ProofVerification::Invalid, Some(err.to_string())),
+            };
+            SyncTraceEvent::ResponseVerified {
+                peer_id: from_peer_id.to_string(),
+                result,
+                error,
+            }
+        });
 
         match validation_result {
-            Ok(true) => {}
+            Ok(true) => self.peer_stats.on_received(from_peer_id, Some(data_len)),
             Ok(false) => {
                 // occurs when remote peer has higher block height
                 info!(%self.tx_seq, "Failed to validate chunks response due to no root found");
@@ -517,6 +841,7 @@ impl SerialSyncController {
             Err(err) => {
                 warn!(%err, %self.tx_seq, "Failed to validate chunks response");
                 metrics::SERIAL_SYNC_UNEXPECTED_ERRORS.inc(1);
+                self.peer_stats.on_received(from_peer_id, None);
                 self.ban_peer(from_peer_id, "Chunk array validation failed");
                 self.state = SyncState::Idle;
                 return;
@@ -548,11 +873,32 @@ impl SerialSyncController {
                 return;
             }
             Err(err) => {
-                error!(%err, %self.tx_seq, "Unexpected DB error while storing chunks");
-                metrics::SERIAL_SYNC_UNEXPECTED_ERRORS.inc(1);
-                self.state = SyncState::Failed {
-                    reason: FailureReason::DBError(err.to_string()),
-                };
+                match StoreError::of(&err) {
+                    Some(e) if e.is_retryable() => {
+                        // e.g. the store is busy, then request the chunks again later
+                        warn!(%err, %self.tx_seq, "Failed to store chunks, retry later");
+                        self.state = SyncState::AwaitingDownload {
+                            since: (Instant::now()
+                                + self.config.peer_next_chunks_request_wait_timeout)
+                                .into(),
+                        };
+                    }
+                    Some(StoreError::NotFound {
+                        what: StoreItem::Tx(_),
+                    }) => {
+                        warn!(%self.tx_seq, ?self.tx_id, "Transaction reverted while storing chunks");
+                        self.state = SyncState::Failed {
+                            reason: FailureReason::TxReverted(self.tx_id),
+                        };
+                    }
+                    _ => {
+                        error!(%err, %self.tx_seq, "Unexpected DB error while storing chunks");
+                        metrics::SERIAL_SYNC_UNEXPECTED_ERRORS.inc(1);
+                        self.state = SyncState::Failed {
+                            reason: FailureReason::DBError(err.to_string()),
+                        };
+                    }
+                }
                 return;
             }
         }
@@ -573,16 +919,14 @@ impl SerialSyncController {
         // finalize tx if all chunks downloaded
         match self
             .store
-            .finalize_tx_with_hash(self.tx_id.seq, self.tx_id.hash)
+            .finalize_tx_batched(self.tx_id.seq, self.tx_id.hash)
             .await
         {
             Ok(true) => {
                 info!(%self.tx_seq, "Succeeded to finalize file");
                 self.state = SyncState::Completed;
                 metrics::SERIAL_SYNC_FILE_COMPLETED.update_since(self.since.0);
-                // notify neighbor nodes about new file completed to sync
-                self.ctx
-                    .send(NetworkMessage::AnnounceLocalFile { tx_id: self.tx_id });
+                // Neighbor nodes are notified by router once finalized in store.
             }
             Ok(false) => {
                 warn!(?self.tx_id, %self.tx_seq, "Transaction reverted during finalize_tx");
@@ -592,6 +936,17 @@ impl SerialSyncController {
                 };
             }
             Err(err) => {
+                if let Some(StoreError::NotFound {
+                    what: StoreItem::Tx(_),
+                }) = StoreError::of(&err)
+                {
+                    warn!(?self.tx_id, %self.tx_seq, "Transaction reverted during finalize_tx");
+                    self.state = SyncState::Failed {
+                        reason: FailureReason::TxReverted(self.tx_id),
+                    };
+                    return;
+                }
+
                 error!(%err, %self.tx_seq, "Unexpected error during finalize_tx");
                 metrics::SERIAL_SYNC_UNEXPECTED_ERRORS.inc(1);
                 self.state = SyncState::Failed {
@@ -601,16 +956,151 @@ impl SerialSyncController {
         }
     }
 
+    pub async fn on_file_status(&mut self, peer_id: PeerId, status: FileStatus) {
+        let sample_segment = match self.peers.file_status_check(&peer_id) {
+            Some(FileStatusCheck::Querying { sample_segment, .. }) => sample_segment,
+            check => {
+                debug!(%self.tx_seq, %peer_id, ?check, "Got file status in unexpected state");
+                return;
+            }
+        };
+
+        let check = match self.verify_file_status(sample_segment, &status).await {
+            Ok(check) => check,
+            Err(reason) => {
+                warn!(%self.tx_seq, %peer_id, %reason, "Peer lied about file availability");
+                metrics::SERIAL_SYNC_UNEXPECTED_ERRORS.inc(1);
+                self.ctx
+                    .report_peer(peer_id, PeerAction::LowToleranceError, reason);
+                FileStatusCheck::Rejected
+            }
+        };
+
+        info!(%self.tx_seq, %peer_id, ?check, "Checked file status");
+        self.peers.update_file_status_check(&peer_id, check);
+        self.on_file_status_checked();
+    }
+
+    /// Handles the failure to query file status, e.g. the peer of an older version, which is
+    /// still allowed to sync chunks.
+    pub fn on_file_status_failed(&mut self, peer_id: PeerId) {
+        if let Some(FileStatusCheck::Querying { .. }) = self.peers.file_status_check(&peer_id) {
+            info!(%self.tx_seq, %peer_id, "Failed to query file status");
+            self.peers
+                .update_file_status_check(&peer_id, FileStatusCheck::Unverified);
+            self.on_file_status_checked();
+        }
+    }
+
+    fn on_file_status_checked(&mut self) {
+        // request chunks at once if waiting for the file status
+        if let SyncState::AwaitingDownload { .. } = self.state {
+            self.state = SyncState::AwaitingDownload {
+                since: Instant::now().into(),
+            };
+        }
+    }
+
+    /// Verifies the file status against the local tx and the sampled segment. Returns the reason
+    /// if the peer lied about the file availability.
+    async fn verify_file_status(
+        &self,
+        sample_segment: u64,
+        status: &FileStatus,
+    ) -> Result<FileStatusCheck, &'static str> {
+        if status.tx_id != self.tx_id {
+            return Err("File status tx mismatch");
+        }
+
+        let tx = match self.store.get_tx_by_seq_number(self.tx_seq).await {
+            Ok(Some(tx)) => tx,
+            Ok(None) => return Ok(FileStatusCheck::Unverified),
+            Err(err) => {
+                warn!(%err, %self.tx_seq, "Failed to get tx to verify file status");
+                return Ok(FileStatusCheck::Unverified);
+            }
+        };
+
+        // e.g. the file removed or not synced yet, which is not a lie
+        if !status.is_segment_available(sample_segment) {
+            return Ok(FileStatusCheck::Rejected);
+        }
+
+        if status.data_root != tx.data_merkle_root {
+            return Err("File status data root mismatch");
+        }
+
+        let sample = match &status.sample {
+            Some(sample) => sample,
+            None => return Err("Sampled segment missing"),
+        };
+
+        let index_start = segment_to_sector(sample_segment as usize) as u64;
+        let index_end = std::cmp::min(
+            index_start + PORA_CHUNK_SIZE as u64,
+            bytes_to_chunks(tx.size as usize) as u64,
+        );
+        if sample.chunks.start_index != index_start
+            || sample.chunks.data.len() as u64 != (index_end - index_start) * CHUNK_SIZE as u64
+        {
+            return Err("Sampled segment range mismatch");
+        }
+
+        match self
+            .store
+            .validate_range_proof(self.tx_seq, sample.clone())
+            .await
+        {
+            Ok(true) => Ok(FileStatusCheck::Verified),
+            // occurs when remote peer has higher block height
+            Ok(false) => Ok(FileStatusCheck::Unverified),
+            Err(_) => Err("Sampled segment validation failed"),
+        }
+    }
+
     pub fn on_request_failed(&mut self, peer_id: PeerId) {
         if self.handle_on_response_mismatch(peer_id) {
             return;
         }
 
+        self.scheduler.on_completed(&peer_id);
         self.handle_response_failure(peer_id, "RPC Error");
     }
 
+    /// Handles the chunks not stored by the peer yet, e.g. a neighbor still downloading the
+    /// file. Such peers are never penalized, but no longer requested for the file once failed
+    /// continuously, until found again.
+    pub fn on_chunks_unavailable(&mut self, peer_id: PeerId) {
+        if self.handle_on_response_mismatch(peer_id) {
+            return;
+        }
+
+        self.scheduler.on_completed(&peer_id);
+        info!(%peer_id, %self.tx_seq, "Chunks not stored by peer yet");
+        self.trace(|| SyncTraceEvent::RequestFailed {
+            peer_id: peer_id.to_string(),
+            reason: "Chunks unavailable".into(),
+        });
+
+        self.failures += 1;
+
+        if self.failures <= self.config.max_request_failures {
+            self.state = SyncState::AwaitingDownload {
+                since: (Instant::now() + self.config.peer_next_chunks_request_wait_timeout).into(),
+            };
+        } else {
+            self.peers
+                .update_state(&peer_id, PeerState::Connected, PeerState::Disconnecting);
+            self.state = SyncState::Idle;
+        }
+    }
+
     fn handle_response_failure(&mut self, peer_id: PeerId, reason: &'static str) {
         info!(%peer_id, %self.tx_seq, %reason, "Chunks request failed");
+        self.trace(|| SyncTraceEvent::RequestFailed {
+            peer_id: peer_id.to_string(),
+            reason: reason.into(),
+        });
 
         self.failures += 1;
 
@@ -626,7 +1116,8 @@ impl SerialSyncController {
         }
     }
 
-    /// Randomly select a `Connected` peer to sync chunks.
+    /// Randomly select a `Connected` peer to sync chunks, preferring the peers with file status
+    /// verified. Peers of which the file status is being queried or rejected are not selected.
     fn select_peer_for_request(&self, request: &GetChunksRequest) -> Option<PeerId> {
         let segment_index = sector_to_segment(request.index_start + self.tx_start_chunk_in_flow);
         let mut peers = self.peers.filter_peers(vec![PeerState::Connected]);
@@ -636,6 +1127,26 @@ impl SerialSyncController {
             None => false,
         });
 
+        peers.retain(|peer_id| {
+            matches!(
+                self.peers.file_status_check(peer_id),
+                Some(FileStatusCheck::Unchecked)
+                    | Some(FileStatusCheck::Verified)
+                    | Some(FileStatusCheck::Unverified)
+            )
+        });
+
+        let verified: Vec<PeerId> = peers
+            .iter()
+            .filter(|peer_id| {
+                self.peers.file_status_check(peer_id) == Some(FileStatusCheck::Verified)
+            })
+            .copied()
+            .collect();
+        if !verified.is_empty() {
+            peers = verified;
+        }
+
         let len = peers.len();
         if len == 0 {
             return None;
@@ -645,9 +1156,20 @@ impl SerialSyncController {
         Some(peers[index])
     }
 
+    /// Timeout is counted since the request dispatched rather than queued in the scheduler.
+    fn is_download_timeout(&self, peer_id: &PeerId) -> bool {
+        match self.scheduler.dispatched_at(peer_id) {
+            Some(dispatched) => dispatched.elapsed() >= self.config.peer_chunks_download_timeout,
+            None => false,
+        }
+    }
+
     pub fn transition(&mut self) {
         use PeerState::*;
 
+        let span = self.span.clone();
+        let _entered = span.enter();
+
         debug!(%self.tx_seq, ?self.state, "transition started");
 
         // update peer connection states
@@ -656,6 +1178,7 @@ impl SerialSyncController {
         let mut completed = false;
 
         while !completed {
+            self.trace_state();
             match self.state {
                 SyncState::Idle => {
                     if self
@@ -737,13 +1260,15 @@ impl SerialSyncController {
                     }
                 }
 
-                SyncState::Downloading { peer_id, since, .. } => {
+                SyncState::Downloading { peer_id, .. } => {
                     if !matches!(self.peers.peer_state(&peer_id), Some(PeerState::Connected)) {
                         // e.g. peer disconnected by remote node
                         debug!(%self.tx_seq, "No peer to continue downloading and try to find other peers to download");
+                        self.scheduler.cancel(&peer_id);
                         self.state = SyncState::Idle;
-                    } else if since.elapsed() >= self.config.peer_chunks_download_timeout {
+                    } else if self.is_download_timeout(&peer_id) {
                         metrics::SERIAL_SYNC_SEGMENT_TIMEOUT.inc(1);
+                        self.scheduler.cancel(&peer_id);
                         self.handle_response_failure(peer_id, "RPC timeout");
                     } else {
                         completed = true;
@@ -754,6 +1279,7 @@ impl SerialSyncController {
             }
         }
 
+        self.trace_state();
         debug!(%self.tx_seq, ?self.state, "transition ended");
     }
 }
@@ -761,11 +1287,15 @@ impl SerialSyncController {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::controllers::{RequestScheduler, SyncPriority};
     use crate::test_util::create_2_store;
     use crate::test_util::tests::create_file_location_cache;
+    use crate::PeerContribution;
     use libp2p::identity;
     use network::{new_network_channel, NetworkReceiver};
     use network::{ReportSource, Request};
+    use std::collections::HashMap;
+    use storage::log_store::finalization_bus::FinalizationEvent;
     use storage::log_store::log_manager::LogConfig;
     use storage::log_store::log_manager::LogManager;
     use storage::log_store::LogStoreRead;
@@ -930,6 +1460,7 @@ mod tests {
                     peer_id,
                     request_id,
                     request,
+                    ..
                 } => {
                     assert_eq!(peer_id, new_peer_id);
                     assert_eq!(
@@ -947,6 +1478,9 @@ mod tests {
                             network::SyncId::SerialSync { tx_id } => {
                                 assert_eq!(tx_id, controller.tx_id);
                             }
+                            sync_id => {
+                                panic!("Not expected sync id: {:?}", sync_id);
+                            }
                         },
                         _ => {
                             panic!("Not expected message: network::RequestId::Sync");
@@ -965,6 +1499,166 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn test_late_response_after_reset() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, mut network_recv) = create_default_controller(task_executor, None);
+
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        controller
+            .peers
+            .add_new_peer(peer_id, "/ip4/127.0.0.1/tcp/10000".parse().unwrap());
+        controller
+            .peers
+            .update_state_force(&peer_id, PeerState::Connected);
+        controller.try_request_next();
+        assert!(matches!(
+            network_recv.try_recv(),
+            Ok(NetworkMessage::SendRequest { .. })
+        ));
+
+        // the late response of the request cancelled on reset is dropped silently
+        controller.reset(None);
+        controller.on_request_failed(peer_id);
+        assert!(network_recv.try_recv().is_err());
+        assert!(controller.cancelled_requests.is_empty());
+
+        // while an unexpected response is still penalized
+        controller.on_request_failed(peer_id);
+        assert!(matches!(
+            network_recv.try_recv(),
+            Ok(NetworkMessage::ReportPeer { peer_id: reported, .. }) if reported == peer_id
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_and_retry() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, mut network_recv) = create_default_controller(task_executor, None);
+
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        controller
+            .peers
+            .add_new_peer(peer_id, "/ip4/127.0.0.1/tcp/10000".parse().unwrap());
+        controller
+            .peers
+            .update_state_force(&peer_id, PeerState::Connected);
+        controller.try_request_next();
+        assert!(matches!(
+            network_recv.recv().await,
+            Some(NetworkMessage::SendRequest { .. })
+        ));
+        assert!(matches!(
+            *controller.get_status(),
+            SyncState::Downloading { .. }
+        ));
+
+        // Stuck in downloading with requests failed.
+        controller.failures = 2;
+        controller.cancel(CancelReason::Manual);
+        assert_eq!(
+            *controller.get_status(),
+            SyncState::Failed {
+                reason: FailureReason::Cancelled(CancelReason::Manual)
+            }
+        );
+        assert!(controller.is_completed_or_failed());
+        assert_eq!(controller.peers.count(&[PeerState::Connected]), 0);
+
+        let scheduler = RequestScheduler::new(&controller.config, controller.ctx.clone())
+            .register(SyncPriority::High);
+        controller.retry(scheduler);
+        assert_eq!(*controller.get_status(), SyncState::Idle);
+        assert_eq!(controller.failures, 0);
+        assert_eq!(controller.next_chunk, 0);
+    }
+
+    #[tokio::test]
+    async fn test_peer_shard_config_shrunk() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, mut network_recv) = create_default_controller(task_executor, None);
+
+        let peer_id1 = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let peer_id2 = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+
+        controller.peers.add_new_peer(peer_id1, addr.clone());
+        controller
+            .peers
+            .update_state_force(&peer_id1, PeerState::Connected);
+        controller.try_request_next();
+        assert!(matches!(
+            network_recv.try_recv(),
+            Ok(NetworkMessage::SendRequest { peer_id, .. }) if peer_id == peer_id1
+        ));
+
+        controller.peers.add_new_peer(peer_id2, addr);
+        controller
+            .peers
+            .update_state_force(&peer_id2, PeerState::Connected);
+
+        // unknown peer or shard config still in range
+        controller.on_peer_shard_config_changed(
+            identity::Keypair::generate_ed25519().public().to_peer_id(),
+            ShardConfig {
+                shard_id: 1,
+                num_shard: 2,
+            },
+        );
+        controller.on_peer_shard_config_changed(
+            peer_id1,
+            ShardConfig {
+                shard_id: 0,
+                num_shard: 2,
+            },
+        );
+        assert!(matches!(
+            controller.state,
+            SyncState::Downloading { peer_id, .. } if peer_id == peer_id1
+        ));
+
+        // peer 1 could not serve the requested chunks any more
+        let shrunk = ShardConfig {
+            shard_id: 1,
+            num_shard: 2,
+        };
+        controller.on_peer_shard_config_changed(peer_id1, shrunk);
+        assert_eq!(controller.peers.shard_config(&peer_id1), Some(shrunk));
+        assert_eq!(
+            controller.peers.peer_state(&peer_id1),
+            Some(PeerState::Connected)
+        );
+        assert!(matches!(
+            controller.state,
+            SyncState::AwaitingDownload { .. }
+        ));
+
+        // request from peer 2 instead
+        controller.transition();
+        assert!(matches!(
+            controller.state,
+            SyncState::Downloading { peer_id, .. } if peer_id == peer_id2
+        ));
+        assert!(matches!(
+            network_recv.try_recv(),
+            Ok(NetworkMessage::SendRequest { peer_id, .. }) if peer_id == peer_id2
+        ));
+
+        for _ in 0..10 {
+            controller.state = SyncState::AwaitingDownload {
+                since: Instant::now().into(),
+            };
+            controller.try_request_next();
+            assert!(matches!(
+                network_recv.try_recv(),
+                Ok(NetworkMessage::SendRequest { peer_id, .. }) if peer_id == peer_id2
+            ));
+        }
+    }
+
     #[tokio::test]
     async fn test_ban_peer() {
         let runtime = TestRuntime::default();
@@ -1115,7 +1809,7 @@ mod tests {
         let init_peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
         let runtime = TestRuntime::default();
         let task_executor = runtime.task_executor.clone();
-        let (controller, mut network_recv) =
+        let (mut controller, mut network_recv) =
             create_default_controller(task_executor, Some(init_peer_id));
 
         assert!(controller.handle_on_response_mismatch(init_peer_id));
@@ -1201,6 +1895,39 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_chunks_unavailable_not_penalized() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, mut network_recv) = create_default_controller(task_executor, None);
+
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        controller.peers.add_new_peer(peer_id, addr);
+        controller
+            .peers
+            .update_state_force(&peer_id, PeerState::Connected);
+
+        for i in 0..(controller.config.max_request_failures + 1) {
+            controller.state = SyncState::Downloading {
+                peer_id,
+                from_chunk: 0,
+                to_chunk: 1,
+                since: Instant::now().into(),
+            };
+            controller.on_chunks_unavailable(peer_id);
+            assert_eq!(controller.failures, i + 1);
+        }
+
+        // peer no longer requested for the file, but never reported
+        assert_eq!(*controller.get_status(), SyncState::Idle);
+        assert_eq!(
+            controller.peers.peer_state(&peer_id),
+            Some(PeerState::Disconnecting)
+        );
+        assert!(network_recv.try_recv().is_err());
+    }
+
     #[tokio::test]
     #[should_panic(expected = "Invalid chunk boundaries")]
     #[ignore = "only panic in debug mode"]
@@ -1403,6 +2130,16 @@ mod tests {
 
         controller.on_response(peer_id, chunks).await;
         assert_eq!(*controller.get_status(), SyncState::Idle);
+        assert_eq!(
+            controller.peer_stats.stats(),
+            vec![(
+                peer_id,
+                PeerContribution {
+                    failed_verifications: 1,
+                    ..Default::default()
+                }
+            )]
+        );
         if let Some(msg) = network_recv.recv().await {
             match msg {
                 NetworkMessage::ReportPeer {
@@ -1533,6 +2270,7 @@ mod tests {
         let tx_seq = 0;
         let chunk_count = 123;
         let (store, peer_store, txs, _) = create_2_store(vec![chunk_count]);
+        let mut finalization_recv = store.subscribe_finalization();
 
         let runtime = TestRuntime::default();
         let task_executor = runtime.task_executor.clone();
@@ -1558,11 +2296,127 @@ mod tests {
 
         controller.on_response(peer_id, chunks).await;
         assert_eq!(*controller.get_status(), SyncState::Completed);
+        assert_eq!(
+            finalization_recv.recv().await,
+            FinalizationEvent::Finalized(vec![txs[0].id()])
+        );
+        assert!(network_recv.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reject_peer_lied_about_file_status() {
+        let honest_peer = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let lying_peer = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+
+        let tx_seq = 0;
+        let chunk_count = 2058;
+        let (store, peer_store, txs, _) = create_2_store(vec![chunk_count]);
+
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, mut network_recv) = create_controller(
+            task_executor,
+            Some(honest_peer),
+            store,
+            txs[0].id(),
+            chunk_count,
+        );
+        controller.config.peer_file_status_check_enabled = true;
+
+        for peer_id in [honest_peer, lying_peer] {
+            controller.peers.add_new_peer(peer_id, addr.clone());
+            controller
+                .peers
+                .update_state_force(&peer_id, PeerState::Connected);
+        }
+
+        // chunks are not requested until the file status of peers checked
+        controller.state = SyncState::AwaitingDownload {
+            since: Instant::now().into(),
+        };
+        controller.try_request_next();
         assert!(matches!(
-            network_recv.try_recv().unwrap(),
-            NetworkMessage::AnnounceLocalFile { .. }
+            controller.state,
+            SyncState::AwaitingDownload { .. }
         ));
+
+        let mut sample_segments = HashMap::new();
+        while let Ok(msg) = network_recv.try_recv() {
+            match msg {
+                NetworkMessage::SendRequest {
+                    peer_id,
+                    request: Request::QueryFileStatus(request),
+                    ..
+                } => {
+                    assert_eq!(request.tx_id, controller.tx_id);
+                    assert!(request.sample_segment < 3);
+                    sample_segments.insert(peer_id, request.sample_segment);
+                }
+                msg => panic!("Not expected message: {:?}", msg),
+            }
+        }
+        assert_eq!(sample_segments.len(), 2);
+
+        let file_status = |sample_segment: u64| {
+            let index_start = sample_segment as usize * PORA_CHUNK_SIZE;
+            let index_end = std::cmp::min(index_start + PORA_CHUNK_SIZE, chunk_count);
+            FileStatus {
+                tx_id: txs[0].id(),
+                data_root: txs[0].data_merkle_root,
+                available_segments: FileStatus::segments_bitmap(3, |_| true),
+                sample: peer_store
+                    .get_chunks_with_proof_by_tx_and_index_range(
+                        tx_seq,
+                        index_start,
+                        index_end,
+                        None,
+                    )
+                    .unwrap(),
+            }
+        };
+
+        // the lying peer claims all segments available, but the sampled one is forged
+        let mut forged = file_status(sample_segments[&lying_peer]);
+        forged.sample.as_mut().unwrap().chunks.data[0] ^= 1;
+        controller.on_file_status(lying_peer, forged).await;
+        assert_eq!(
+            controller.peers.file_status_check(&lying_peer),
+            Some(FileStatusCheck::Rejected)
+        );
+        match network_recv.try_recv().unwrap() {
+            NetworkMessage::ReportPeer {
+                peer_id, action, ..
+            } => {
+                assert_eq!(peer_id, lying_peer);
+                assert!(matches!(action, PeerAction::LowToleranceError));
+            }
+            msg => panic!("Not expected message: {:?}", msg),
+        }
+
+        controller
+            .on_file_status(honest_peer, file_status(sample_segments[&honest_peer]))
+            .await;
+        assert_eq!(
+            controller.peers.file_status_check(&honest_peer),
+            Some(FileStatusCheck::Verified)
+        );
         assert!(network_recv.try_recv().is_err());
+
+        // only the honest peer is selected to sync chunks
+        controller.transition();
+        match controller.state {
+            SyncState::Downloading { peer_id, .. } => assert_eq!(peer_id, honest_peer),
+            state => panic!("Not expected SyncState, {:?}", state),
+        }
+        match network_recv.try_recv().unwrap() {
+            NetworkMessage::SendRequest {
+                peer_id,
+                request: Request::GetChunks(_),
+                ..
+            } => assert_eq!(peer_id, honest_peer),
+            msg => panic!("Not expected message: {:?}", msg),
+        }
     }
 
     // FIXME(zz): enable.
@@ -1693,17 +2547,22 @@ mod tests {
 
         let file_location_cache = create_file_location_cache(peer_id, vec![tx_id]);
 
+        let config = Config {
+            neighbors_only: false,
+            ..Default::default()
+        };
+        let scheduler = RequestScheduler::new(&config, ctx.clone()).register(SyncPriority::Normal);
+        let store = Store::new(store, task_executor);
         let controller = SerialSyncController::new(
-            Config {
-                neighbors_only: false,
-                ..Default::default()
-            },
+            config,
             tx_id,
             0,
             FileSyncGoal::new_file(num_chunks as u64),
             ctx,
-            Store::new(store, task_executor),
+            scheduler,
+            store,
             file_location_cache,
+            Default::default(),
         );
 
         (controller, network_recv)
@@ -0,0 +1,229 @@
+//! Detailed events of a single file sync, e.g. to debug why a file is not synced, which are only
+//! recorded for the files traced via admin RPC. A controller without trace only checks an
+//! `Option` per event, and the event is not even constructed.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Maximum number of events kept for a file, beyond which the oldest events are dropped.
+const MAX_TRACE_EVENTS: usize = 1024;
+
+/// Maximum number of files traced, beyond which the trace enabled first is dropped.
+const MAX_TRACED_FILES: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProofVerification {
+    Valid,
+    /// The flow root of the proof is not found locally, e.g. the peer is ahead in log sync.
+    RootNotFound,
+    Invalid,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum SyncTraceEvent {
+    /// The sync state changed, e.g. `Downloading` or `Failed(TimeoutFindFile)`.
+    #[serde(rename_all = "camelCase")]
+    StateChanged { state: String },
+    #[serde(rename_all = "camelCase")]
+    PeerFound { peer_id: String },
+    #[serde(rename_all = "camelCase")]
+    PeerConnected { peer_id: String },
+    #[serde(rename_all = "camelCase")]
+    PeerDisconnected { peer_id: String },
+    /// The peer chosen to request chunks `[from_chunk, to_chunk)`, and the request is queued if
+    /// the peer is busy with other file syncs.
+    #[serde(rename_all = "camelCase")]
+    RequestSent {
+        peer_id: String,
+        from_chunk: u64,
+        to_chunk: u64,
+        queued: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    ResponseReceived { peer_id: String, bytes: usize },
+    /// The response is dropped before verified, e.g. of an unexpected range.
+    #[serde(rename_all = "camelCase")]
+    ResponseRejected { peer_id: String, reason: String },
+    #[serde(rename_all = "camelCase")]
+    ResponseVerified {
+        peer_id: String,
+        result: ProofVerification,
+        error: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    RequestFailed { peer_id: String, reason: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncTraceRecord {
+    /// Milliseconds since the trace enabled.
+    pub elapsed_ms: u64,
+    #[serde(flatten)]
+    pub event: SyncTraceEvent,
+}
+
+/// Events recorded for a file sync, returned by `admin_getFileSyncTrace`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileSyncTrace {
+    pub tx_seq: u64,
+    /// `false` once disabled, but the recorded events are kept.
+    pub enabled: bool,
+    /// Number of the oldest events dropped from the bounded ring.
+    pub dropped_events: u64,
+    pub events: Vec<SyncTraceRecord>,
+}
+
+/// Bounded ring of the events of a file sync.
+pub struct SyncTrace {
+    since: Instant,
+    events: VecDeque<SyncTraceRecord>,
+    dropped_events: u64,
+    /// Latest state recorded, so that only the state changes are recorded.
+    last_state: Option<String>,
+}
+
+impl SyncTrace {
+    fn new() -> Self {
+        Self {
+            since: Instant::now(),
+            events: VecDeque::new(),
+            dropped_events: 0,
+            last_state: None,
+        }
+    }
+
+    pub fn record(&mut self, event: SyncTraceEvent) {
+        if let SyncTraceEvent::StateChanged { state } = &event {
+            if self.last_state.as_ref() == Some(state) {
+                return;
+            }
+            self.last_state = Some(state.clone());
+        }
+
+        if self.events.len() >= MAX_TRACE_EVENTS {
+            self.events.pop_front();
+            self.dropped_events += 1;
+        }
+        self.events.push_back(SyncTraceRecord {
+            elapsed_ms: self.since.elapsed().as_millis() as u64,
+            event,
+        });
+    }
+}
+
+/// Traces of the files enabled via admin RPC, which are shared with the file sync controllers.
+#[derive(Default)]
+pub struct SyncTraces {
+    traces: HashMap<u64, (Arc<Mutex<SyncTrace>>, bool)>,
+    /// Seqs of the traced files in the order enabled.
+    order: VecDeque<u64>,
+}
+
+impl SyncTraces {
+    /// Starts a new trace of the file, and returns the trace dropped, if any, to detach from its
+    /// controller.
+    pub fn enable(&mut self, tx_seq: u64) -> Option<u64> {
+        self.order.retain(|seq| *seq != tx_seq);
+        let mut evicted = None;
+        if self.order.len() >= MAX_TRACED_FILES {
+            evicted = self.order.pop_front();
+            if let Some(seq) = &evicted {
+                self.traces.remove(seq);
+            }
+        }
+
+        self.traces
+            .insert(tx_seq, (Arc::new(Mutex::new(SyncTrace::new())), true));
+        self.order.push_back(tx_seq);
+        evicted
+    }
+
+    /// Stops recording the file sync, of which the events are still readable.
+    pub fn disable(&mut self, tx_seq: u64) {
+        if let Some((_, enabled)) = self.traces.get_mut(&tx_seq) {
+            *enabled = false;
+        }
+    }
+
+    /// Returns the trace to record the file sync, if enabled.
+    pub fn get(&self, tx_seq: u64) -> Option<Arc<Mutex<SyncTrace>>> {
+        match self.traces.get(&tx_seq) {
+            Some((trace, true)) => Some(trace.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn snapshot(&self, tx_seq: u64) -> Option<FileSyncTrace> {
+        let (trace, enabled) = self.traces.get(&tx_seq)?;
+        let trace = trace.lock();
+        Some(FileSyncTrace {
+            tx_seq,
+            enabled: *enabled,
+            dropped_events: trace.dropped_events,
+            events: trace.events.iter().cloned().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(state: &str) -> SyncTraceEvent {
+        SyncTraceEvent::StateChanged {
+            state: state.into(),
+        }
+    }
+
+    #[test]
+    fn test_trace_ring() {
+        let mut traces = SyncTraces::default();
+        assert_eq!(traces.enable(1), None);
+        let trace = traces.get(1).unwrap();
+        trace.lock().record(state("Idle"));
+        trace.lock().record(state("Idle"));
+        trace.lock().record(state("FindingPeers"));
+        let events: Vec<_> = traces
+            .snapshot(1)
+            .unwrap()
+            .events
+            .into_iter()
+            .map(|record| record.event)
+            .collect();
+        assert_eq!(events, vec![state("Idle"), state("FindingPeers")]);
+
+        for i in 0..MAX_TRACE_EVENTS {
+            trace.lock().record(SyncTraceEvent::PeerFound {
+                peer_id: i.to_string(),
+            });
+        }
+        let snapshot = traces.snapshot(1).unwrap();
+        assert_eq!(snapshot.events.len(), MAX_TRACE_EVENTS);
+        assert_eq!(snapshot.dropped_events, 2);
+
+        // disabled trace is still readable
+        traces.disable(1);
+        assert!(traces.get(1).is_none());
+        assert!(!traces.snapshot(1).unwrap().enabled);
+    }
+
+    #[test]
+    fn test_max_traced_files() {
+        let mut traces = SyncTraces::default();
+        for tx_seq in 0..MAX_TRACED_FILES as u64 {
+            assert_eq!(traces.enable(tx_seq), None);
+        }
+        // enabled again as the latest
+        assert_eq!(traces.enable(0), None);
+        assert_eq!(traces.enable(100), Some(1));
+        assert!(traces.snapshot(1).is_none());
+        assert!(traces.snapshot(0).is_some());
+    }
+}
@@ -0,0 +1,99 @@
+//! Repair of the local data in flow ranges rather than whole files, e.g. after the data dir is
+//! migrated to a new disk. The corrupted entry batches are removed, and only the chunks of the
+//! removed batches are synced again from peers, one chunk range after another for each file.
+
+use shared_types::{bytes_to_chunks, Transaction};
+use std::collections::VecDeque;
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+use storage::H256;
+
+/// Chunk ranges of a file to sync one after another, and the file is finalized once all synced.
+pub(crate) struct ChunkRangesSync {
+    pub tx_hash: H256,
+    /// Ranges not synced yet, of chunk indices in the file.
+    pub pending: VecDeque<(u64, u64)>,
+}
+
+impl ChunkRangesSync {
+    /// Creates the chunk ranges of the removed entry batches of `tx`, in ascending order. The
+    /// consecutive batches are merged, and the rear padding of the file is excluded, which is
+    /// filled once the file finalized.
+    pub fn new(tx: &Transaction, batch_list: &[u64]) -> Self {
+        let num_chunks = bytes_to_chunks(tx.size as usize) as u64;
+        let mut pending: VecDeque<(u64, u64)> = VecDeque::new();
+        for batch_index in batch_list {
+            let batch_start = batch_index * PORA_CHUNK_SIZE as u64;
+            let start = batch_start.saturating_sub(tx.start_entry_index);
+            let end = (batch_start + PORA_CHUNK_SIZE as u64)
+                .saturating_sub(tx.start_entry_index)
+                .min(num_chunks);
+            if start >= end {
+                continue;
+            }
+
+            match pending.back_mut() {
+                Some(last) if last.1 == start => last.1 = end,
+                _ => pending.push_back((start, end)),
+            }
+        }
+
+        Self {
+            tx_hash: tx.hash(),
+            pending,
+        }
+    }
+
+    /// Creates the given chunk ranges of `tx` in ascending order, of which the overlapped or
+    /// consecutive ones are merged, and the empty ones are dropped.
+    pub fn from_ranges(tx: &Transaction, mut ranges: Vec<(u64, u64)>) -> Self {
+        ranges.retain(|(start, end)| start < end);
+        ranges.sort_unstable();
+        let mut pending: VecDeque<(u64, u64)> = VecDeque::new();
+        for (start, end) in ranges {
+            match pending.back_mut() {
+                Some(last) if last.1 >= start => last.1 = last.1.max(end),
+                _ => pending.push_back((start, end)),
+            }
+        }
+
+        Self {
+            tx_hash: tx.hash(),
+            pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_types::{TransactionBuilder, CHUNK_SIZE};
+
+    #[test]
+    fn test_chunk_ranges() {
+        let batch_size = PORA_CHUNK_SIZE as u64;
+        // The file fills batch 2, 3, 4 and a part of batch 5.
+        let tx = TransactionBuilder::new(0)
+            .start_entry_index(2 * batch_size)
+            .size((3 * batch_size + 10) * CHUNK_SIZE as u64)
+            .build_unchecked();
+
+        let ranges = ChunkRangesSync::new(&tx, &[2, 3, 5]);
+        assert_eq!(ranges.tx_hash, tx.hash());
+        assert_eq!(
+            Vec::from(ranges.pending),
+            vec![(0, 2 * batch_size), (3 * batch_size, 3 * batch_size + 10)]
+        );
+
+        // The batch of the rear padding only
+        let tx = TransactionBuilder::from(tx)
+            .size(10 * CHUNK_SIZE as u64)
+            .build_unchecked();
+        let ranges = ChunkRangesSync::new(&tx, &[2, 3]);
+        assert_eq!(Vec::from(ranges.pending), vec![(0, 10)]);
+
+        let ranges =
+            ChunkRangesSync::from_ranges(&tx, vec![(8, 10), (0, 2), (1, 3), (3, 4), (5, 5)]);
+        assert_eq!(ranges.tx_hash, tx.hash());
+        assert_eq!(Vec::from(ranges.pending), vec![(0, 4), (8, 10)]);
+    }
+}
@@ -1,15 +1,23 @@
 #[macro_use]
 extern crate tracing;
 
+mod announcements;
 pub mod auto_sync;
 mod context;
 mod controllers;
+mod flow_repair;
+mod metrics;
+mod peer_stats;
+mod self_audit;
 mod service;
 pub mod test_util;
 
 use auto_sync::{batcher_random::RandomBatcherState, batcher_serial::SerialBatcherState};
-pub use controllers::FileSyncInfo;
+pub use controllers::{
+    FileSyncInfo, FileSyncTrace, ProofVerification, SyncTraceEvent, SyncTraceRecord,
+};
 use duration_str::deserialize_duration;
+pub use peer_stats::{PeerContribution, PeerStats};
 use serde::{Deserialize, Serialize};
 pub use service::{SyncMessage, SyncReceiver, SyncRequest, SyncResponse, SyncSender, SyncService};
 use std::{
@@ -31,6 +39,18 @@ pub struct Config {
     pub max_sync_files: usize,
     pub sync_file_by_rpc_enabled: bool,
     pub sync_file_on_announcement_enabled: bool,
+    /// Indicates whether to serve chunks of files not finalized yet to peers, as long as the
+    /// requested chunks are stored locally, so that files propagate before downloaded entirely.
+    pub serve_partial_files: bool,
+    /// Indicates whether to exchange the files recently announced with peers once connected,
+    /// so that new nodes could sync the files announced before they joined.
+    pub announcement_exchange_enabled: bool,
+    /// Maximum number of files exchanged with a peer in one request.
+    pub max_exchanged_announcements: usize,
+    /// Minimum interval to exchange announcements with the same peer, in which requests from
+    /// the peer are rejected as rate limited.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub announcement_exchange_interval: Duration,
 
     // serial sync config
     pub max_chunks_to_request: u64,
@@ -50,6 +70,26 @@ pub struct Config {
     pub max_bandwidth_bytes: u64,
     #[serde(deserialize_with = "deserialize_duration")]
     pub bandwidth_wait_timeout: Duration,
+    /// Maximum number of in-flight requests to a peer, which are shared by all file syncs.
+    pub max_requests_per_peer: usize,
+    /// Number of requests dispatched to a peer in turn for each high priority file sync.
+    pub high_priority_request_weight: usize,
+    /// Number of requests dispatched to a peer in turn for each normal priority file sync.
+    pub normal_priority_request_weight: usize,
+    /// Interval to ping peers of active file syncs, so as to detect dead peers in time.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub peer_ping_interval: Duration,
+    /// Peers are considered dead after this number of pings missed in a row, and 0 indicates
+    /// ping disabled.
+    pub max_missed_pings: usize,
+    /// Indicates whether to query the file status of peers before downloading chunks from them,
+    /// so that peers lied about the file availability are not selected to sync.
+    pub peer_file_status_check_enabled: bool,
+    /// Interval to audit the stored data by random chunks verified against the flow root.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub self_audit_interval: Duration,
+    /// Number of chunks sampled in each self-audit, and 0 indicates self-audit disabled.
+    pub self_audit_samples: usize,
 
     // auto sync config
     #[serde(deserialize_with = "deserialize_duration")]
@@ -74,6 +114,10 @@ impl Default for Config {
             max_sync_files: 8,
             sync_file_by_rpc_enabled: true,
             sync_file_on_announcement_enabled: false,
+            serve_partial_files: true,
+            announcement_exchange_enabled: true,
+            max_exchanged_announcements: 256,
+            announcement_exchange_interval: Duration::from_secs(300),
 
             // serial sync config
             max_chunks_to_request: 2 * 1024,
@@ -86,6 +130,14 @@ impl Default for Config {
             peer_next_chunks_request_wait_timeout: Duration::from_secs(3),
             max_bandwidth_bytes: 0,
             bandwidth_wait_timeout: Duration::from_secs(5),
+            max_requests_per_peer: 4,
+            high_priority_request_weight: 4,
+            normal_priority_request_weight: 1,
+            peer_ping_interval: Duration::from_secs(10),
+            max_missed_pings: 2,
+            peer_file_status_check_enabled: false,
+            self_audit_interval: Duration::from_secs(3600),
+            self_audit_samples: 16,
 
             // auto sync config
             auto_sync_idle_interval: Duration::from_secs(3),
@@ -98,6 +150,37 @@ impl Default for Config {
     }
 }
 
+/// Parameters of sync config that could be reloaded at runtime without restarting the node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DynamicConfig {
+    pub max_sync_files: usize,
+    pub max_bandwidth_bytes: u64,
+    pub max_requests_per_peer: usize,
+}
+
+impl Config {
+    pub fn dynamic(&self) -> DynamicConfig {
+        DynamicConfig {
+            max_sync_files: self.max_sync_files,
+            max_bandwidth_bytes: self.max_bandwidth_bytes,
+            max_requests_per_peer: self.max_requests_per_peer,
+        }
+    }
+
+    pub fn set_dynamic(&mut self, dynamic: DynamicConfig) {
+        self.max_sync_files = dynamic.max_sync_files;
+        self.max_bandwidth_bytes = dynamic.max_bandwidth_bytes;
+        self.max_requests_per_peer = dynamic.max_requests_per_peer;
+    }
+
+    /// Whether files are downloaded from peers, either automatically or on request.
+    pub fn syncs_files(&self) -> bool {
+        self.auto_sync_enabled
+            || self.sync_file_by_rpc_enabled
+            || self.sync_file_on_announcement_enabled
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct InstantWrapper(Instant);
 
@@ -127,3 +210,60 @@ pub struct SyncServiceState {
     pub auto_sync_serial: Option<SerialBatcherState>,
     pub auto_sync_random: Option<RandomBatcherState>,
 }
+
+/// Result of a file resync, of which the file sync could be tracked by `tx_seq`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResyncFileInfo {
+    pub tx_seq: u64,
+    /// Indices of the entry batches of which the file data is removed to sync again.
+    pub removed_batches: Vec<u64>,
+    /// `true` if the file is in sync.
+    pub in_sync: bool,
+}
+
+/// Result of a flow range repair, of which the file syncs could be tracked by `tx_seqs`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairFlowRangeInfo {
+    /// Indices of the entry batches removed to sync again.
+    pub removed_batches: Vec<u64>,
+    /// Indices of the corrupted entry batches left as is, which are shared by several files or
+    /// padding, or of the files pruned or already in sync.
+    pub skipped_batches: Vec<u64>,
+    /// Seqs of the files to sync the removed batches.
+    pub tx_seqs: Vec<u64>,
+}
+
+/// Rolling results of the latest self-audits of the stored data.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfAuditStatus {
+    /// Number of the latest audit rounds in the window.
+    pub rounds: usize,
+    /// Number of chunks sampled in the window.
+    pub sampled: usize,
+    /// Number of chunks failed to verify in the window.
+    pub failed: usize,
+    /// Ratio of the sampled chunks verified in the window, or `None` if nothing sampled, e.g. no
+    /// file stored yet.
+    pub success_ratio: Option<f64>,
+    /// Number of chunks sampled since startup.
+    pub total_sampled: u64,
+    /// Number of chunks failed to verify since startup.
+    pub total_failed: u64,
+}
+
+/// Result of terminating or retrying a file sync manually.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FileSyncControlStatus {
+    /// The file sync is cancelled with its requests and peers released.
+    Cancelled,
+    /// The file sync is restarted at high priority.
+    Requeued,
+    /// The file sync is completed or failed already, or the file is already stored.
+    AlreadyFinished,
+    /// Neither the file sync nor the tx is found.
+    NotFound,
+}
@@ -0,0 +1,10 @@
+use std::sync::Arc;
+
+use metrics::{Counter, CounterUsize, Gauge, GaugeUsize};
+
+lazy_static::lazy_static! {
+    // self-audit of the stored data
+    pub static ref SELF_AUDIT_SUCCESS_RATIO_PERMILLE: Arc<dyn Gauge<usize>> = GaugeUsize::register("sync_self_audit_success_ratio_permille");
+    pub static ref SELF_AUDIT_CHUNKS_SAMPLED: Arc<dyn Counter<usize>> = CounterUsize::register("sync_self_audit_chunks_sampled");
+    pub static ref SELF_AUDIT_CHUNKS_FAILED: Arc<dyn Counter<usize>> = CounterUsize::register("sync_self_audit_chunks_failed");
+}
@@ -0,0 +1,180 @@
+//! Contribution of peers to file sync, i.e. the chunks served to and by the local node, so that
+//! peers serving data could be credited and freeloaders detected.
+//!
+//! Counters are persisted along with the known peers of router, which loads them on startup, and
+//! saturate rather than overflow.
+
+use network::PeerId;
+use parking_lot::Mutex;
+use ssz_derive::{Decode, Encode};
+use std::collections::{BTreeSet, HashMap};
+
+/// Maximum number of peers to account, beyond which the peer of the least bytes is dropped.
+const MAX_TRACKED_PEERS: usize = 1024;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub struct PeerContribution {
+    /// Chunk responses received from the peer that passed verification.
+    pub segments_received: u64,
+    /// Bytes of the verified chunks received from the peer.
+    pub bytes_received: u64,
+    /// Chunk responses received from the peer that failed verification.
+    pub failed_verifications: u64,
+    /// Chunk responses served to the peer.
+    pub segments_served: u64,
+    /// Bytes of the chunks served to the peer.
+    pub bytes_served: u64,
+}
+
+impl PeerContribution {
+    fn total_bytes(&self) -> u64 {
+        self.bytes_received.saturating_add(self.bytes_served)
+    }
+}
+
+#[derive(Default)]
+struct Peers {
+    contributions: HashMap<PeerId, PeerContribution>,
+    /// Peers ordered by the total bytes, to drop the least one.
+    by_bytes: BTreeSet<(u64, PeerId)>,
+}
+
+impl Peers {
+    fn update(&mut self, peer_id: PeerId, f: impl FnOnce(&mut PeerContribution)) {
+        let contribution = self.contributions.entry(peer_id).or_default();
+        self.by_bytes.remove(&(contribution.total_bytes(), peer_id));
+        f(contribution);
+        self.by_bytes.insert((contribution.total_bytes(), peer_id));
+
+        if self.contributions.len() > MAX_TRACKED_PEERS {
+            if let Some((_, least)) = self.by_bytes.pop_first() {
+                self.contributions.remove(&least);
+            }
+        }
+    }
+}
+
+/// Per-peer contribution to file sync since the node first started, shared by the sync service,
+/// the file sync controllers and the known peers of router.
+#[derive(Default)]
+pub struct PeerStats {
+    peers: Mutex<Peers>,
+}
+
+impl PeerStats {
+    /// Creates the peer stats with the contribution persisted before restart.
+    pub fn new(persisted: impl IntoIterator<Item = (PeerId, PeerContribution)>) -> Self {
+        let mut peers = Peers::default();
+        for (peer_id, contribution) in persisted {
+            peers.update(peer_id, |c| *c = contribution);
+        }
+        Self {
+            peers: Mutex::new(peers),
+        }
+    }
+
+    /// Records chunks received from the peer, of `bytes` if verified, or `None` if failed.
+    pub fn on_received(&self, peer_id: PeerId, bytes: Option<usize>) {
+        self.update(peer_id, |c| match bytes {
+            Some(bytes) => {
+                c.segments_received = c.segments_received.saturating_add(1);
+                c.bytes_received = c.bytes_received.saturating_add(bytes as u64);
+            }
+            None => c.failed_verifications = c.failed_verifications.saturating_add(1),
+        });
+    }
+
+    /// Records chunks of `bytes` served to the peer.
+    pub fn on_served(&self, peer_id: PeerId, bytes: usize) {
+        self.update(peer_id, |c| {
+            c.segments_served = c.segments_served.saturating_add(1);
+            c.bytes_served = c.bytes_served.saturating_add(bytes as u64);
+        });
+    }
+
+    fn update(&self, peer_id: PeerId, f: impl FnOnce(&mut PeerContribution)) {
+        self.peers.lock().update(peer_id, f);
+    }
+
+    /// Returns the contribution of the peer, if accounted.
+    pub fn get(&self, peer_id: &PeerId) -> Option<PeerContribution> {
+        self.peers.lock().contributions.get(peer_id).copied()
+    }
+
+    /// Returns the contribution of all peers, in no particular order.
+    pub fn stats(&self) -> Vec<(PeerId, PeerContribution)> {
+        self.peers
+            .lock()
+            .contributions
+            .iter()
+            .map(|(peer_id, c)| (*peer_id, *c))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_contribution() {
+        let (peer1, peer2) = (PeerId::random(), PeerId::random());
+        let stats = PeerStats::new([(
+            peer1,
+            PeerContribution {
+                bytes_received: 1024,
+                segments_received: 1,
+                ..Default::default()
+            },
+        )]);
+        stats.on_received(peer1, Some(512));
+        stats.on_received(peer1, None);
+        stats.on_served(peer2, 256);
+
+        let peers: HashMap<_, _> = stats.stats().into_iter().collect();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(
+            peers[&peer1],
+            PeerContribution {
+                segments_received: 2,
+                bytes_received: 1536,
+                failed_verifications: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            stats.get(&peer2),
+            Some(PeerContribution {
+                segments_served: 1,
+                bytes_served: 256,
+                ..Default::default()
+            })
+        );
+        assert_eq!(stats.get(&PeerId::random()), None);
+
+        // counters saturate
+        stats.update(peer2, |c| c.bytes_served = u64::MAX - 1);
+        stats.on_served(peer2, 256);
+        let contribution = stats.get(&peer2).unwrap();
+        assert_eq!(contribution.bytes_served, u64::MAX);
+        assert_eq!(contribution.total_bytes(), u64::MAX);
+    }
+
+    #[test]
+    fn test_max_tracked_peers() {
+        let stats = PeerStats::default();
+        let heavy = PeerId::random();
+        stats.on_served(heavy, 1000);
+        let light = PeerId::random();
+        stats.on_served(light, 1);
+        for _ in 1..MAX_TRACKED_PEERS {
+            stats.on_served(PeerId::random(), 2);
+        }
+
+        let peers = stats.stats();
+        assert_eq!(peers.len(), MAX_TRACKED_PEERS);
+        assert!(stats.get(&heavy).is_some());
+        assert!(stats.get(&light).is_none());
+        assert_eq!(stats.peers.lock().by_bytes.len(), MAX_TRACKED_PEERS);
+    }
+}
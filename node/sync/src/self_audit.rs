@@ -0,0 +1,81 @@
+//! Periodic self-audit of the stored data, in which random chunks are read through the proof
+//! path and verified against the flow root, in the same way as challenged by proof of custody.
+//! The corrupted entry batches found are repaired from peers, see [`crate::flow_repair`].
+
+use crate::SelfAuditStatus;
+use std::collections::VecDeque;
+
+/// Number of the latest audit rounds of the rolling success ratio, i.e. a day by default.
+pub(crate) const SELF_AUDIT_WINDOW: usize = 24;
+
+/// Results of the latest audit rounds, as the number of chunks sampled and failed in each round.
+#[derive(Default)]
+pub(crate) struct SelfAudit {
+    rounds: VecDeque<(usize, usize)>,
+    total_sampled: u64,
+    total_failed: u64,
+}
+
+impl SelfAudit {
+    pub fn on_round(&mut self, sampled: usize, failed: usize) {
+        self.rounds.push_back((sampled, failed));
+        while self.rounds.len() > SELF_AUDIT_WINDOW {
+            self.rounds.pop_front();
+        }
+
+        self.total_sampled += sampled as u64;
+        self.total_failed += failed as u64;
+    }
+
+    /// Returns the status of the rolling window, or `None` if no round audited yet.
+    pub fn status(&self) -> Option<SelfAuditStatus> {
+        if self.rounds.is_empty() {
+            return None;
+        }
+
+        let (sampled, failed) = self
+            .rounds
+            .iter()
+            .fold((0, 0), |(s, f), (sampled, failed)| {
+                (s + sampled, f + failed)
+            });
+        Some(SelfAuditStatus {
+            rounds: self.rounds.len(),
+            sampled,
+            failed,
+            success_ratio: (sampled > 0).then(|| (sampled - failed) as f64 / sampled as f64),
+            total_sampled: self.total_sampled,
+            total_failed: self.total_failed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_window() {
+        let mut audit = SelfAudit::default();
+        assert!(audit.status().is_none());
+
+        // nothing stored to sample yet
+        audit.on_round(0, 0);
+        assert_eq!(audit.status().unwrap().success_ratio, None);
+
+        audit.on_round(10, 1);
+        audit.on_round(10, 0);
+        let status = audit.status().unwrap();
+        assert_eq!((status.rounds, status.sampled, status.failed), (3, 20, 1));
+        assert_eq!(status.success_ratio, Some(0.95));
+
+        // the failed round is out of the window, but still counted in total
+        for _ in 0..SELF_AUDIT_WINDOW {
+            audit.on_round(10, 0);
+        }
+        let status = audit.status().unwrap();
+        assert_eq!(status.rounds, SELF_AUDIT_WINDOW);
+        assert_eq!(status.success_ratio, Some(1.0));
+        assert_eq!((status.total_sampled, status.total_failed), (260, 1));
+    }
+}
@@ -1,31 +1,46 @@
-use crate::auto_sync::manager::AutoSyncManager;
+use crate::announcements::{AnnouncementExchange, KNOWN_TXS_FILTER_BYTES, KNOWN_TXS_WINDOW};
+use crate::auto_sync::manager::{AutoSyncManager, NewFileEvent};
 use crate::context::SyncNetworkContext;
 use crate::controllers::{
-    FailureReason, FileSyncGoal, FileSyncInfo, SerialSyncController, SyncState,
+    CancelReason, FailureReason, FileSyncGoal, FileSyncInfo, FileSyncTrace, PeerLiveness,
+    RequestScheduler, SerialSyncController, SyncPriority, SyncState, SyncTraces,
+};
+use crate::flow_repair::ChunkRangesSync;
+use crate::metrics;
+use crate::peer_stats::PeerStats;
+use crate::self_audit::SelfAudit;
+use crate::{
+    Config, DynamicConfig, FileSyncControlStatus, PeerContribution, RepairFlowRangeInfo,
+    ResyncFileInfo, SelfAuditStatus, SyncServiceState,
 };
-use crate::{Config, SyncServiceState};
 use anyhow::{anyhow, bail, Result};
 use file_location_cache::FileLocationCache;
 use libp2p::swarm::DialError;
 use log_entry_sync::LogSyncEvent;
 use network::types::{AnnounceChunks, FindFile};
 use network::{
-    rpc::GetChunksRequest, rpc::RPCResponseErrorCode, Multiaddr, NetworkMessage, NetworkSender,
-    PeerId, PeerRequestId, PubsubMessage, SyncId as RequestId,
+    rpc::Announcements, rpc::FileStatus, rpc::GetAnnouncementsRequest, rpc::GetChunksRequest,
+    rpc::QueryFileStatusRequest, rpc::RPCError, rpc::RPCResponseErrorCode, rpc::TxSeqFilter,
+    Multiaddr, NetworkMessage, NetworkSender, PeerAction, PeerId, PeerRequestId, PubsubMessage,
+    SyncId as RequestId,
 };
 use shared_types::{bytes_to_chunks, ChunkArrayWithProof, ShardedFile, Transaction, TxID};
 use std::sync::atomic::Ordering;
 use std::{
     cmp,
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
     sync::Arc,
+    time::{Duration, Instant},
 };
 use storage::config::ShardConfig;
 use storage::error::Result as StorageResult;
 use storage::log_store::log_manager::{sector_to_segment, segment_to_sector, PORA_CHUNK_SIZE};
+use storage::log_store::tx_store::TxStatus;
 use storage::log_store::Store as LogStore;
 use storage_async::Store;
-use tokio::sync::{broadcast, oneshot};
+use task_executor::shutdown::ShutdownToken;
+use tokio::sync::{broadcast, oneshot, watch};
+use tracing::{Instrument, Span};
 
 pub type SyncSender = channel::Sender<SyncMessage, SyncRequest, SyncResponse>;
 pub type SyncReceiver = channel::Receiver<SyncMessage, SyncRequest, SyncResponse>;
@@ -52,9 +67,33 @@ pub enum SyncMessage {
         request_id: RequestId,
         response: ChunkArrayWithProof,
     },
+    QueryFileStatus {
+        peer_id: PeerId,
+        request_id: PeerRequestId,
+        request: QueryFileStatusRequest,
+    },
+    FileStatusResponse {
+        peer_id: PeerId,
+        request_id: RequestId,
+        response: Box<FileStatus>,
+    },
+    GetAnnouncements {
+        peer_id: PeerId,
+        request_id: PeerRequestId,
+        request: GetAnnouncementsRequest,
+    },
+    AnnouncementsResponse {
+        peer_id: PeerId,
+        request_id: RequestId,
+        response: Announcements,
+    },
     RpcError {
         peer_id: PeerId,
         request_id: RequestId,
+        error: RPCError,
+    },
+    Pong {
+        peer_id: PeerId,
     },
     AnnounceFileGossip {
         tx_id: TxID,
@@ -72,6 +111,19 @@ pub enum SyncMessage {
         from: PeerId,
         file: ShardedFile,
     },
+    RetractFileGossip {
+        tx_ids: Vec<TxID>,
+        peer_id: PeerId,
+    },
+    /// Files announced to peers by this node, which are cached to exchange with peers once
+    /// connected.
+    FilesAnnounced {
+        tx_ids: Vec<TxID>,
+    },
+    /// Files retracted by this node once reverted on chain.
+    FilesRetracted {
+        tx_ids: Vec<TxID>,
+    },
     AnswerFile {
         peer_id: PeerId,
         file: ShardedFile,
@@ -92,6 +144,12 @@ pub enum SyncRequest {
         start_index: u64,
         end_index: u64,
     },
+    /// Syncs the chunk ranges of a file one after another, and finalizes the file once all
+    /// synced.
+    SyncChunkRanges {
+        tx_seq: u64,
+        ranges: Vec<(u64, u64)>,
+    },
     FileSyncInfo {
         tx_seq: Option<u64>,
     },
@@ -102,21 +160,84 @@ pub enum SyncRequest {
         tx_seq: u64,
         is_reverted: bool,
     },
+    ResyncFile {
+        tx_seq: u64,
+        verify_first: bool,
+    },
+    CancelFileSync {
+        tx_seq: u64,
+    },
+    RetryFileSync {
+        tx_seq: u64,
+    },
+    /// Repairs the corrupted entry batches, which are verified by the requester, so that the
+    /// verification never blocks the service.
+    RepairFlowBatches {
+        batches: Vec<u64>,
+    },
+    PeerStats,
+    SelfAuditStatus,
+    TraceFileSync {
+        tx_seq: u64,
+        enable: bool,
+    },
+    GetFileSyncTrace {
+        tx_seq: u64,
+    },
 }
 
 #[derive(Debug)]
 pub enum SyncResponse {
-    SyncState { state: SyncServiceState },
-    SyncStatus { status: Option<SyncState> },
-    SyncFile { err: String },
-    FileSyncInfo { result: HashMap<u64, FileSyncInfo> },
-    FindFile { err: String },
-    TerminateFileSync { count: usize },
+    SyncState {
+        state: SyncServiceState,
+    },
+    SyncStatus {
+        status: Option<SyncState>,
+    },
+    SyncFile {
+        err: String,
+    },
+    FileSyncInfo {
+        result: HashMap<u64, FileSyncInfo>,
+    },
+    FindFile {
+        err: String,
+    },
+    TerminateFileSync {
+        count: usize,
+    },
+    ResyncFile {
+        result: Result<ResyncFileInfo, String>,
+    },
+    CancelFileSync {
+        status: FileSyncControlStatus,
+    },
+    RetryFileSync {
+        result: Result<FileSyncControlStatus, String>,
+    },
+    RepairFlowBatches {
+        result: Result<RepairFlowRangeInfo, String>,
+    },
+    PeerStats {
+        stats: Vec<(PeerId, PeerContribution)>,
+    },
+    SelfAuditStatus {
+        status: Option<SelfAuditStatus>,
+    },
+    TraceFileSync {
+        syncing: bool,
+    },
+    FileSyncTrace {
+        trace: Option<FileSyncTrace>,
+    },
 }
 
 pub struct SyncService {
     config: Config,
 
+    /// Receives the dynamic parameters of sync config reloaded at runtime.
+    config_recv: watch::Receiver<DynamicConfig>,
+
     /// A receiving channel sent by the message processor thread.
     msg_recv: channel::Receiver<SyncMessage, SyncRequest, SyncResponse>,
 
@@ -132,7 +253,28 @@ pub struct SyncService {
     /// A collection of file sync controllers.
     controllers: HashMap<u64, SerialSyncController>,
 
+    /// Chunk ranges of the files to sync after the ranges being synced by controllers.
+    chunk_range_syncs: HashMap<u64, ChunkRangesSync>,
+
+    /// Schedules the outbound requests of all file sync controllers.
+    scheduler: RequestScheduler,
+
+    /// Detects dead peers of file syncs.
+    liveness: PeerLiveness,
+
+    /// Contribution of peers to file sync, persisted along with the known peers of router.
+    peer_stats: Arc<PeerStats>,
+
     auto_sync_manager: Option<AutoSyncManager>,
+
+    /// Files announced recently to exchange with peers once connected.
+    announcements: AnnouncementExchange,
+
+    /// Results of the latest self-audits of the stored data.
+    self_audit: SelfAudit,
+
+    /// Detailed events of the file syncs traced via admin RPC.
+    traces: SyncTraces,
 }
 
 impl SyncService {
@@ -143,27 +285,36 @@ impl SyncService {
         file_location_cache: Arc<FileLocationCache>,
         event_recv: broadcast::Receiver<LogSyncEvent>,
         catch_up_end_recv: oneshot::Receiver<()>,
+        shutdown: ShutdownToken,
     ) -> Result<SyncSender> {
+        let config = Config::default();
         Self::spawn_with_config(
-            Config::default(),
+            config,
             executor,
             network_send,
             store,
             file_location_cache,
+            Default::default(),
             event_recv,
             catch_up_end_recv,
+            watch::channel(config.dynamic()).1,
+            shutdown,
         )
         .await
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn spawn_with_config(
         config: Config,
         executor: task_executor::TaskExecutor,
         network_send: NetworkSender,
         store: Arc<dyn LogStore>,
         file_location_cache: Arc<FileLocationCache>,
+        peer_stats: Arc<PeerStats>,
         event_recv: broadcast::Receiver<LogSyncEvent>,
         catch_up_end_recv: oneshot::Receiver<()>,
+        config_recv: watch::Receiver<DynamicConfig>,
+        shutdown: ShutdownToken,
     ) -> Result<SyncSender> {
         let (sync_send, sync_recv) = channel::Channel::unbounded("sync");
         let store = Store::new(store, executor.clone());
@@ -185,39 +336,90 @@ impl SyncService {
             None
         };
 
+        let ctx = Arc::new(SyncNetworkContext::new(network_send));
         let mut sync = SyncService {
             config,
+            config_recv,
             msg_recv: sync_recv,
-            ctx: Arc::new(SyncNetworkContext::new(network_send)),
+            ctx: ctx.clone(),
             store,
             file_location_cache,
             controllers: Default::default(),
+            chunk_range_syncs: Default::default(),
+            scheduler: RequestScheduler::new(&config, ctx),
+            liveness: PeerLiveness::new(&config),
+            peer_stats,
             auto_sync_manager,
+            announcements: AnnouncementExchange::new(config.announcement_exchange_interval),
+            self_audit: Default::default(),
+            traces: Default::default(),
         };
 
         info!("Starting sync service");
-        executor.spawn(async move { Box::pin(sync.main()).await }, "sync");
+        executor.spawn(async move { Box::pin(sync.main(shutdown)).await }, "sync");
 
         Ok(sync_send)
     }
 
-    async fn main(&mut self) {
+    async fn main(&mut self, mut shutdown: ShutdownToken) {
         let mut heartbeat = tokio::time::interval(self.config.heartbeat_interval);
+        // the zero interval panics even if ping disabled
+        let ping_interval = self.config.peer_ping_interval.max(Duration::from_millis(1));
+        let mut ping =
+            tokio::time::interval_at(tokio::time::Instant::now() + ping_interval, ping_interval);
+        let ping_enabled = self.liveness.is_enabled();
+        let self_audit_enabled = self.config.self_audit_samples > 0;
+        let self_audit_interval = self.config.self_audit_interval.max(Duration::from_secs(1));
+        let mut self_audit = tokio::time::interval_at(
+            tokio::time::Instant::now() + self_audit_interval,
+            self_audit_interval,
+        );
 
         loop {
             tokio::select! {
+                // messages are handled one by one, so that received chunks are always written
+                _ = shutdown.requested() => break,
+
                 // received sync message
                 Some(msg) = self.msg_recv.recv() => {
                     match msg {
                         channel::Message::Notification(msg) => self.on_sync_msg(msg).await,
-                        channel::Message::Request(req, sender) => self.on_sync_request(req, sender).await,
+                        channel::Message::Request(req, sender, span) => self.on_sync_request(req, sender).instrument(span).await,
                     }
                 }
 
+                // dynamic config reloaded
+                Ok(()) = self.config_recv.changed() => {
+                    let dynamic = *self.config_recv.borrow_and_update();
+                    self.on_config_reloaded(dynamic);
+                }
+
                 // heartbeat
-                _ = heartbeat.tick() => self.on_heartbeat(),
+                _ = heartbeat.tick() => self.on_heartbeat().await,
+
+                // ping peers of file syncs
+                _ = ping.tick(), if ping_enabled => self.on_ping_round(),
+
+                // audit the stored data
+                _ = self_audit.tick(), if self_audit_enabled => self.on_self_audit().await,
             }
         }
+
+        info!("Sync service stopped for shutdown");
+        shutdown.ack();
+    }
+
+    /// Applies the reloaded config to new and ongoing file syncs. File syncs beyond the new
+    /// `max_sync_files` are not terminated, but no more file sync is started until below.
+    fn on_config_reloaded(&mut self, dynamic: DynamicConfig) {
+        info!(?dynamic, "Sync config reloaded");
+
+        self.config.set_dynamic(dynamic);
+        self.scheduler
+            .set_max_requests_per_peer(dynamic.max_requests_per_peer);
+        for controller in self.controllers.values_mut() {
+            controller.set_dynamic_config(dynamic);
+        }
     }
 
     async fn on_sync_msg(&mut self, msg: SyncMessage) {
@@ -229,6 +431,7 @@ impl SyncService {
             }
             SyncMessage::PeerConnected { peer_id } => {
                 self.on_peer_connected(peer_id);
+                self.request_announcements(peer_id);
             }
 
             SyncMessage::PeerDisconnected { peer_id } => {
@@ -252,13 +455,50 @@ impl SyncService {
                 self.on_chunks_response(peer_id, request_id, response).await;
             }
 
+            SyncMessage::QueryFileStatus {
+                peer_id,
+                request_id,
+                request,
+            } => {
+                self.on_query_file_status_request(peer_id, request_id, request)
+                    .await;
+            }
+
+            SyncMessage::FileStatusResponse {
+                peer_id,
+                request_id,
+                response,
+            } => {
+                self.on_file_status_response(peer_id, request_id, *response)
+                    .await;
+            }
+
+            SyncMessage::GetAnnouncements {
+                peer_id,
+                request_id,
+                request,
+            } => {
+                self.on_get_announcements_request(peer_id, request_id, request);
+            }
+
+            SyncMessage::AnnouncementsResponse {
+                peer_id,
+                request_id,
+                response,
+            } => {
+                self.on_announcements_response(peer_id, request_id, response);
+            }
+
             SyncMessage::RpcError {
                 peer_id,
                 request_id,
+                error,
             } => {
-                self.on_rpc_error(peer_id, request_id);
+                self.on_rpc_error(peer_id, request_id, error);
             }
 
+            SyncMessage::Pong { peer_id } => self.on_pong(peer_id),
+
             SyncMessage::AnnounceFileGossip {
                 tx_id,
                 peer_id,
@@ -268,11 +508,17 @@ impl SyncService {
             }
 
             SyncMessage::AnnounceChunksGossip { msg } => self.on_announce_chunks_gossip(msg).await,
-            SyncMessage::AnnounceShardConfig { .. } => {
-                // FIXME: Check if controllers need to be reset?
-            }
+            SyncMessage::AnnounceShardConfig {
+                shard_config,
+                peer_id,
+            } => self.on_announce_shard_config(peer_id, shard_config),
             SyncMessage::NewFile { from, file } => self.on_new_file_gossip(from, file).await,
+            SyncMessage::RetractFileGossip { tx_ids, peer_id } => {
+                self.on_retract_file_gossip(peer_id, tx_ids).await
+            }
             SyncMessage::AnswerFile { peer_id, file } => self.on_answer_file(peer_id, file).await,
+            SyncMessage::FilesAnnounced { tx_ids } => self.announcements.on_announced(tx_ids),
+            SyncMessage::FilesRetracted { tx_ids } => self.announcements.on_retracted(&tx_ids),
         }
     }
 
@@ -329,6 +575,14 @@ impl SyncService {
                 let _ = sender.send(SyncResponse::SyncFile { err: result });
             }
 
+            SyncRequest::SyncChunkRanges { tx_seq, ranges } => {
+                let result = match self.on_sync_chunk_ranges(tx_seq, ranges).await {
+                    Ok(()) => "".into(),
+                    Err(e) => e.to_string(),
+                };
+                let _ = sender.send(SyncResponse::SyncFile { err: result });
+            }
+
             SyncRequest::FileSyncInfo { tx_seq } => {
                 let mut result = HashMap::default();
 
@@ -359,6 +613,58 @@ impl SyncService {
                 let result = self.on_find_file_request(tx_seq).await;
                 let _ = sender.send(SyncResponse::FindFile { err: result });
             }
+
+            SyncRequest::ResyncFile {
+                tx_seq,
+                verify_first,
+            } => {
+                let result = self
+                    .on_resync_file(tx_seq, verify_first)
+                    .await
+                    .map_err(|e| e.to_string());
+                let _ = sender.send(SyncResponse::ResyncFile { result });
+            }
+
+            SyncRequest::CancelFileSync { tx_seq } => {
+                let status = self.on_cancel_file_sync(tx_seq);
+                let _ = sender.send(SyncResponse::CancelFileSync { status });
+            }
+
+            SyncRequest::RetryFileSync { tx_seq } => {
+                let result = self
+                    .on_retry_file_sync(tx_seq)
+                    .await
+                    .map_err(|e| e.to_string());
+                let _ = sender.send(SyncResponse::RetryFileSync { result });
+            }
+
+            SyncRequest::RepairFlowBatches { batches } => {
+                let result = self
+                    .on_repair_flow_batches(batches)
+                    .await
+                    .map_err(|e| e.to_string());
+                let _ = sender.send(SyncResponse::RepairFlowBatches { result });
+            }
+
+            SyncRequest::PeerStats => {
+                let stats = self.peer_stats.stats();
+                let _ = sender.send(SyncResponse::PeerStats { stats });
+            }
+
+            SyncRequest::SelfAuditStatus => {
+                let status = self.self_audit.status();
+                let _ = sender.send(SyncResponse::SelfAuditStatus { status });
+            }
+
+            SyncRequest::TraceFileSync { tx_seq, enable } => {
+                let syncing = self.on_trace_file_sync(tx_seq, enable);
+                let _ = sender.send(SyncResponse::TraceFileSync { syncing });
+            }
+
+            SyncRequest::GetFileSyncTrace { tx_seq } => {
+                let trace = self.traces.snapshot(tx_seq);
+                let _ = sender.send(SyncResponse::FileSyncTrace { trace });
+            }
         }
     }
 
@@ -383,12 +689,23 @@ impl SyncService {
     fn on_peer_disconnected(&mut self, peer_id: PeerId) {
         info!(%peer_id, "Peer disconnected");
 
+        self.liveness.on_peer_disconnected(&peer_id);
+
         for controller in self.controllers.values_mut() {
             controller.on_peer_disconnected(peer_id);
             controller.transition();
         }
     }
 
+    fn on_announce_shard_config(&mut self, peer_id: PeerId, shard_config: ShardConfig) {
+        debug!(%peer_id, ?shard_config, "Peer announced shard config");
+
+        for controller in self.controllers.values_mut() {
+            controller.on_peer_shard_config_changed(peer_id, shard_config);
+            controller.transition();
+        }
+    }
+
     async fn on_get_chunks_request(
         &mut self,
         peer_id: PeerId,
@@ -446,6 +763,7 @@ impl SyncService {
                 reason: "Tx not found (Reverted)".into(),
                 id: request_id,
             });
+            return Ok(());
         }
 
         // ban peer if chunk index out of bound
@@ -455,23 +773,49 @@ impl SyncService {
             return Ok(());
         }
 
-        // file may be removed, but remote peer still find one from the file location cache
-        // let finalized = self.store.check_tx_completed(request.tx_id.seq).await?;
-        // if !finalized {
-        //     info!(%request.tx_id.seq, "Failed to handle chunks request due to tx not finalized");
-        //     // FIXME(zz): If remote removes a file, we will also get failure here.
-        //     // self.ctx
-        //     //     .report_peer(peer_id, PeerAction::HighToleranceError, "Tx not finalized");
-        //     self.ctx.send(NetworkMessage::SendErrorResponse {
-        //         peer_id,
-        //         error: RPCResponseErrorCode::InvalidRequest,
-        //         reason: "Tx not finalized".into(),
-        //         id: request_id,
-        //     });
-        //     return Ok(());
-        // }
-
-        let result = self
+        // Chunks of unfinalized files are served along with proofs if stored locally, which
+        // peers verify anyway. Note, file may be removed, but remote peer still find one from
+        // the file location cache, in which case chunks not found.
+        // files rejected by the ingest policy are never served, even partially
+        if self.store.check_tx_invalid(request.tx_id.seq).await? {
+            debug!(%request.tx_id.seq, "Failed to handle chunks request due to tx invalid");
+            self.ctx.send(NetworkMessage::SendErrorResponse {
+                peer_id,
+                error: RPCResponseErrorCode::InvalidRequest,
+                reason: "Tx rejected by ingest policy".into(),
+                id: request_id,
+            });
+            return Ok(());
+        }
+
+        let finalized = self.store.check_tx_completed(request.tx_id.seq).await?;
+        if !finalized && !self.config.serve_partial_files {
+            debug!(%request.tx_id.seq, "Failed to handle chunks request due to tx not finalized");
+            self.ctx.send(NetworkMessage::SendErrorResponse {
+                peer_id,
+                error: RPCResponseErrorCode::InvalidRequest,
+                reason: "Tx not finalized".into(),
+                id: request_id,
+            });
+            return Ok(());
+        }
+
+        // unfinalized files may be truncated by an in-progress revert
+        let flow_version = match self.stable_flow_version(finalized) {
+            Some(version) => version,
+            None => {
+                debug!(%request.tx_id.seq, "Failed to handle chunks request due to tx in revert");
+                self.ctx.send(NetworkMessage::SendErrorResponse {
+                    peer_id,
+                    error: RPCResponseErrorCode::InvalidRequest,
+                    reason: "Tx in revert".into(),
+                    id: request_id,
+                });
+                return Ok(());
+            }
+        };
+
+        let mut result = self
             .store
             .get_chunks_with_proof_by_tx_and_index_range(
                 request.tx_id.seq,
@@ -480,15 +824,29 @@ impl SyncService {
                 Some(request.merkle_tx_seq),
             )
             .await?;
+        if !finalized && self.store.get_store().get_flow_version() != flow_version {
+            result = None;
+        }
 
         match result {
             Some(chunks) => {
+                self.peer_stats.on_served(peer_id, chunks.chunks.data.len());
                 self.ctx.send(NetworkMessage::SendResponse {
                     peer_id,
                     id: request_id,
                     response: network::Response::Chunks(chunks),
                 });
             }
+            // the file is still being downloaded, which is not the fault of requester
+            None if !finalized => {
+                debug!(%request.tx_id.seq, "Failed to handle chunks request due to chunks not stored yet");
+                self.ctx.send(NetworkMessage::SendErrorResponse {
+                    peer_id,
+                    error: RPCResponseErrorCode::ResourceUnavailable,
+                    reason: "Chunks not stored yet".into(),
+                    id: request_id,
+                });
+            }
             None => {
                 // file may be removed during downloading
                 warn!(%request.tx_id.seq, "Failed to handle chunks request due to chunks not found");
@@ -514,12 +872,21 @@ impl SyncService {
 
         let tx_seq = match request_id {
             RequestId::SerialSync { tx_id } => tx_id.seq,
+            RequestId::FileStatus { .. } | RequestId::Ping | RequestId::Announcements => {
+                warn!(%peer_id, ?request_id, "Received chunks response for other requests");
+                return;
+            }
         };
 
         match self.controllers.get_mut(&tx_seq) {
             Some(controller) => {
-                controller.on_response(peer_id, response).await;
-                controller.transition();
+                let span = info_span!(parent: controller.span(), "chunks_response", %peer_id);
+                async {
+                    controller.on_response(peer_id, response).await;
+                    controller.transition();
+                }
+                .instrument(span)
+                .await;
             }
             None => {
                 warn!("Received chunks response for non-existent controller tx_seq={tx_seq}");
@@ -527,83 +894,458 @@ impl SyncService {
         }
     }
 
-    fn on_rpc_error(&mut self, peer_id: PeerId, request_id: RequestId) {
-        info!(%peer_id, ?request_id, "Received RPC error");
-
-        let tx_seq = match request_id {
-            RequestId::SerialSync { tx_id } => tx_id.seq,
-        };
+    async fn on_query_file_status_request(
+        &mut self,
+        peer_id: PeerId,
+        request_id: PeerRequestId,
+        request: QueryFileStatusRequest,
+    ) {
+        debug!(?request, %peer_id, ?request_id, "Received QueryFileStatus request");
 
-        match self.controllers.get_mut(&tx_seq) {
-            Some(controller) => {
-                controller.on_request_failed(peer_id);
-                controller.transition();
+        match self.get_file_status(peer_id, &request).await {
+            Ok(Some(status)) => {
+                self.ctx.send(NetworkMessage::SendResponse {
+                    peer_id,
+                    id: request_id,
+                    response: network::Response::FileStatus(Box::new(status)),
+                });
             }
-            None => {
-                warn!("Received rpc error for non-existent controller tx_seq={tx_seq}");
+            // peer banned for invalid request
+            Ok(None) => {}
+            Err(err) => {
+                error!(%err, "Failed to handle file status request due to db error");
+                self.ctx.send(NetworkMessage::SendErrorResponse {
+                    peer_id,
+                    id: request_id,
+                    error: RPCResponseErrorCode::ServerError,
+                    reason: "DB error".into(),
+                });
             }
         }
     }
 
-    async fn on_sync_file_request(
+    /// Returns the availability of the requested file in the local shard, along with the
+    /// sampled segment if available, or `None` if the request is invalid.
+    async fn get_file_status(
         &mut self,
-        tx_seq: u64,
-        maybe_range: Option<(u64, u64)>,
-    ) -> String {
-        if maybe_range.is_none() && !self.config.sync_file_by_rpc_enabled {
-            return "Disabled to sync file".into();
+        peer_id: PeerId,
+        request: &QueryFileStatusRequest,
+    ) -> StorageResult<Option<FileStatus>> {
+        let mut status = FileStatus {
+            tx_id: request.tx_id,
+            data_root: Default::default(),
+            available_segments: vec![],
+            sample: None,
+        };
+
+        // tx may be not synced yet or reverted
+        let tx = match self.store.get_tx_by_seq_number(request.tx_id.seq).await? {
+            Some(tx) if tx.id() == request.tx_id => tx,
+            _ => return Ok(Some(status)),
+        };
+        status.data_root = tx.data_merkle_root;
+
+        // ban peer if segment index out of bound
+        let num_chunks = bytes_to_chunks(tx.size as usize);
+        let num_segments = (num_chunks + PORA_CHUNK_SIZE - 1) / PORA_CHUNK_SIZE;
+        if request.sample_segment as usize >= num_segments {
+            self.ctx.ban_peer(peer_id, "Segment index out of bound");
+            return Ok(None);
         }
 
-        if !self.controllers.contains_key(&tx_seq)
-            && self.controllers.len() >= self.config.max_sync_files
+        let tx_status = self.store.get_tx_status(tx.seq).await?;
+        let finalized = matches!(
+            tx_status,
+            Some(TxStatus::Finalized) | Some(TxStatus::ShardFinalized)
+        );
+        // files rejected by the ingest policy are never served, even partially
+        if tx_status == Some(TxStatus::Invalid) || (!finalized && !self.config.serve_partial_files)
         {
-            return format!(
-                "Max sync file limitation reached: {}",
-                self.config.max_sync_files
-            );
+            return Ok(Some(status));
         }
+        let flow_version = match self.stable_flow_version(finalized) {
+            Some(version) => version,
+            None => return Ok(Some(status)),
+        };
 
-        match self.on_start_sync_file(tx_seq, maybe_range, None).await {
-            Ok(()) => "".into(),
-            Err(e) => e.to_string(),
+        let shard_config = self.store.get_store().get_shard_config();
+        let in_shard = |index: usize| {
+            let sector = tx.start_entry_index + segment_to_sector(index) as u64;
+            shard_config.in_range(sector_to_segment(sector) as u64)
+        };
+        if finalized {
+            status.available_segments = FileStatus::segments_bitmap(num_segments, in_shard);
+        } else {
+            // only the sampled segment is checked for unfinalized files, which is cheap enough
+            let index_start = segment_to_sector(request.sample_segment as usize);
+            let index_end = cmp::min(index_start + PORA_CHUNK_SIZE, num_chunks);
+            let sampled = in_shard(request.sample_segment as usize)
+                && self
+                    .store
+                    .has_chunks_by_tx_and_index_range(tx.seq, index_start, index_end)
+                    .await?;
+            status.available_segments = FileStatus::segments_bitmap(num_segments, |index| {
+                sampled && index as u64 == request.sample_segment
+            });
         }
-    }
 
-    async fn on_find_file_request(&mut self, tx_seq: u64) -> String {
-        match self.on_find_file(tx_seq).await {
-            Ok(()) => "".into(),
-            Err(e) => e.to_string(),
+        if status.is_segment_available(request.sample_segment) {
+            let index_start = segment_to_sector(request.sample_segment as usize);
+            let index_end = cmp::min(index_start + PORA_CHUNK_SIZE, num_chunks);
+            status.sample = self
+                .store
+                .get_chunks_with_proof_by_tx_and_index_range(
+                    tx.seq,
+                    index_start,
+                    index_end,
+                    Some(request.merkle_tx_seq),
+                )
+                .await?;
+
+            // file may be removed
+            if status.sample.is_none()
+                || (!finalized && self.store.get_store().get_flow_version() != flow_version)
+            {
+                status.available_segments = vec![];
+                status.sample = None;
+            }
         }
+
+        Ok(Some(status))
     }
 
-    async fn on_find_file(&mut self, tx_seq: u64) -> Result<()> {
-        // file already exists
-        if self.store.get_store().get_tx_status(tx_seq)?.is_some() {
-            return Ok(());
+    /// Returns the current flow version, or `None` if the data of unfinalized files could be
+    /// truncated by an in-progress revert or prune, which bumps the flow version both before
+    /// and after the flow changes. Data read is valid if the version unchanged after read.
+    fn stable_flow_version(&self, finalized: bool) -> Option<u64> {
+        let version = self.store.get_store().get_flow_version();
+        if !finalized && version % 2 == 1 {
+            None
+        } else {
+            Some(version)
         }
-        // broadcast find file
-        let tx = match self.store.get_tx_by_seq_number(tx_seq).await? {
-            Some(tx) => tx,
-            None => bail!("Transaction not found"),
-        };
-        self.ctx.publish(PubsubMessage::FindFile(
-            FindFile {
-                tx_id: tx.id(),
-                maybe_shard_config: None,
-            }
-            .into(),
-        ));
-        Ok(())
     }
 
-    async fn on_start_sync_file(
+    async fn on_file_status_response(
         &mut self,
-        tx_seq: u64,
-        maybe_range: Option<(u64, u64)>,
-        maybe_peer: Option<(PeerId, Multiaddr)>,
-    ) -> Result<()> {
-        info!(%tx_seq, ?maybe_range, ?maybe_peer, "Start to sync file");
-
+        peer_id: PeerId,
+        request_id: RequestId,
+        response: FileStatus,
+    ) {
+        debug!(?response.tx_id, %peer_id, ?request_id, "Received file status response");
+
+        let tx_seq = match request_id {
+            RequestId::FileStatus { tx_id } => tx_id.seq,
+            RequestId::SerialSync { .. } | RequestId::Ping | RequestId::Announcements => {
+                warn!(%peer_id, ?request_id, "Received file status response for other requests");
+                return;
+            }
+        };
+
+        match self.controllers.get_mut(&tx_seq) {
+            Some(controller) => {
+                let span = info_span!(parent: controller.span(), "file_status_response", %peer_id);
+                async {
+                    controller.on_file_status(peer_id, response).await;
+                    controller.transition();
+                }
+                .instrument(span)
+                .await;
+            }
+            None => {
+                debug!("Received file status response for non-existent controller tx_seq={tx_seq}");
+            }
+        }
+    }
+
+    /// Requests the files recently announced by the newly connected peer, except the files
+    /// known locally, so as to queue the files announced before connected for auto sync.
+    fn request_announcements(&mut self, peer_id: PeerId) {
+        if !self.config.announcement_exchange_enabled || self.auto_sync_manager.is_none() {
+            return;
+        }
+
+        if !self.announcements.should_request(peer_id, Instant::now()) {
+            return;
+        }
+
+        let store = self.store.get_store();
+        let next_tx_seq = store.next_tx_seq();
+        let mut known_txs = TxSeqFilter::new(KNOWN_TXS_FILTER_BYTES);
+        for tx_seq in next_tx_seq.saturating_sub(KNOWN_TXS_WINDOW)..next_tx_seq {
+            if matches!(store.get_tx_status(tx_seq), Ok(Some(_))) {
+                known_txs.insert(tx_seq);
+            }
+        }
+
+        debug!(%peer_id, "Request announcements from peer");
+        self.ctx.send(NetworkMessage::SendRequest {
+            peer_id,
+            request_id: network::RequestId::Sync(Instant::now(), RequestId::Announcements),
+            request: network::Request::GetAnnouncements(GetAnnouncementsRequest {
+                known_txs,
+                max_announcements: self.config.max_exchanged_announcements as u64,
+            }),
+            span: Span::current(),
+        });
+    }
+
+    fn on_get_announcements_request(
+        &mut self,
+        peer_id: PeerId,
+        request_id: PeerRequestId,
+        request: GetAnnouncementsRequest,
+    ) {
+        debug!(
+            %peer_id, ?request_id, %request.max_announcements,
+            "Received GetAnnouncements request"
+        );
+
+        let rejected = if !self.config.announcement_exchange_enabled {
+            Some((
+                RPCResponseErrorCode::ResourceUnavailable,
+                "Exchange disabled",
+            ))
+        } else if !self.announcements.admit_request(peer_id, Instant::now()) {
+            Some((RPCResponseErrorCode::RateLimited, "Too many requests"))
+        } else {
+            None
+        };
+        if let Some((error, reason)) = rejected {
+            self.ctx.send(NetworkMessage::SendErrorResponse {
+                peer_id,
+                id: request_id,
+                error,
+                reason: reason.into(),
+            });
+            return;
+        }
+
+        // skip the files reverted since finalized
+        let store = self.store.get_store();
+        let max_announcements = cmp::min(
+            request.max_announcements,
+            self.config.max_exchanged_announcements as u64,
+        );
+        let tx_ids = self
+            .announcements
+            .recent()
+            .filter(|tx_id| !request.known_txs.contains(tx_id.seq))
+            .filter(|tx_id| {
+                matches!(store.get_tx_by_seq_number(tx_id.seq), Ok(Some(tx)) if tx.id() == **tx_id)
+            })
+            .take(max_announcements as usize)
+            .copied()
+            .collect();
+
+        self.ctx.send(NetworkMessage::SendResponse {
+            peer_id,
+            id: request_id,
+            response: network::Response::Announcements(Announcements {
+                shard_config: store.get_shard_config().into(),
+                tx_ids,
+            }),
+        });
+    }
+
+    /// Verifies the files exchanged against the local log entries, and queues the files not
+    /// synced yet for auto sync if the peer serves the local shard.
+    fn on_announcements_response(
+        &mut self,
+        peer_id: PeerId,
+        request_id: RequestId,
+        response: Announcements,
+    ) {
+        debug!(%peer_id, num_files = response.tx_ids.len(), "Received announcements response");
+
+        if !matches!(request_id, RequestId::Announcements) {
+            warn!(%peer_id, ?request_id, "Received announcements response for other requests");
+            return;
+        }
+
+        if response.tx_ids.len() > self.config.max_exchanged_announcements {
+            self.ctx.report_peer(
+                peer_id,
+                PeerAction::LowToleranceError,
+                "Too many announcements exchanged",
+            );
+            return;
+        }
+
+        let shard_config = match ShardConfig::try_from(response.shard_config) {
+            Ok(v) => v,
+            Err(_) => {
+                self.ctx.report_peer(
+                    peer_id,
+                    PeerAction::Fatal,
+                    "Invalid shard config in announcements",
+                );
+                return;
+            }
+        };
+        self.file_location_cache
+            .insert_peer_config(peer_id, shard_config);
+
+        let store = self.store.get_store();
+        if !store.get_shard_config().intersect(&shard_config) {
+            debug!(%peer_id, ?shard_config, "Ignore announcements out of the local shard");
+            return;
+        }
+
+        let mut num_queued = 0;
+        for tx_id in response.tx_ids {
+            // tx may be not synced from chain yet, or the peer is on a minority fork
+            match store.get_tx_by_seq_number(tx_id.seq) {
+                Ok(Some(tx)) if tx.id() == tx_id => {}
+                Ok(_) => continue,
+                Err(err) => {
+                    warn!(%tx_id.seq, %err, "Failed to get tx to verify announcement");
+                    continue;
+                }
+            }
+
+            if let Some(controller) = self.controllers.get_mut(&tx_id.seq) {
+                controller.on_peer_announced(peer_id, shard_config);
+                controller.transition();
+            } else if matches!(store.get_tx_status(tx_id.seq), Ok(None)) {
+                if let Some(manager) = &self.auto_sync_manager {
+                    let _ = manager
+                        .new_file_send
+                        .send(NewFileEvent::Announced(tx_id.seq));
+                    num_queued += 1;
+                }
+            }
+        }
+
+        if num_queued > 0 {
+            info!(%peer_id, %num_queued, "Queued files exchanged by peer for auto sync");
+        }
+    }
+
+    fn on_rpc_error(&mut self, peer_id: PeerId, request_id: RequestId, error: RPCError) {
+        info!(%peer_id, ?request_id, %error, "Received RPC error");
+
+        let (tx_seq, file_status) = match request_id {
+            RequestId::SerialSync { tx_id } => (tx_id.seq, false),
+            RequestId::FileStatus { tx_id } => (tx_id.seq, true),
+            RequestId::Ping => {
+                if self.liveness.on_ping_failed(&peer_id) {
+                    self.on_peer_dead(peer_id);
+                }
+                return;
+            }
+            // e.g. not supported by peers of older versions, which is harmless
+            RequestId::Announcements => return,
+        };
+
+        match self.controllers.get_mut(&tx_seq) {
+            Some(controller) => {
+                let _span = info_span!(parent: controller.span(), "rpc_error", %peer_id).entered();
+                if file_status {
+                    controller.on_file_status_failed(peer_id);
+                } else if matches!(
+                    error,
+                    RPCError::ErrorResponse(RPCResponseErrorCode::ResourceUnavailable, _)
+                ) {
+                    controller.on_chunks_unavailable(peer_id);
+                } else {
+                    controller.on_request_failed(peer_id);
+                }
+                controller.transition();
+            }
+            None => {
+                warn!("Received rpc error for non-existent controller tx_seq={tx_seq}");
+            }
+        }
+    }
+
+    async fn on_sync_file_request(
+        &mut self,
+        tx_seq: u64,
+        maybe_range: Option<(u64, u64)>,
+    ) -> String {
+        if maybe_range.is_none() && !self.config.sync_file_by_rpc_enabled {
+            return "Disabled to sync file".into();
+        }
+
+        if !self.controllers.contains_key(&tx_seq)
+            && self.controllers.len() >= self.config.max_sync_files
+        {
+            return format!(
+                "Max sync file limitation reached: {}",
+                self.config.max_sync_files
+            );
+        }
+
+        // chunks sync is usually requested by clients, e.g. uploading file segments
+        let priority = match maybe_range {
+            Some(_) => SyncPriority::High,
+            None => SyncPriority::Normal,
+        };
+
+        match self
+            .on_start_sync_file(tx_seq, maybe_range, None, priority)
+            .await
+        {
+            Ok(()) => "".into(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    async fn on_sync_chunk_ranges(&mut self, tx_seq: u64, ranges: Vec<(u64, u64)>) -> Result<()> {
+        if !self.controllers.contains_key(&tx_seq)
+            && self.controllers.len() >= self.config.max_sync_files
+        {
+            bail!(
+                "Max sync file limitation reached: {}",
+                self.config.max_sync_files
+            );
+        }
+
+        let tx = match self.store.get_tx_by_seq_number(tx_seq).await? {
+            Some(tx) => tx,
+            None => bail!("Transaction not found"),
+        };
+        let ranges = ChunkRangesSync::from_ranges(&tx, ranges);
+        self.on_start_sync_chunk_ranges(tx_seq, ranges).await
+    }
+
+    async fn on_find_file_request(&mut self, tx_seq: u64) -> String {
+        match self.on_find_file(tx_seq).await {
+            Ok(()) => "".into(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    async fn on_find_file(&mut self, tx_seq: u64) -> Result<()> {
+        // file already exists
+        if self.store.get_store().get_tx_status(tx_seq)?.is_some() {
+            return Ok(());
+        }
+        // broadcast find file
+        let tx = match self.store.get_tx_by_seq_number(tx_seq).await? {
+            Some(tx) => tx,
+            None => bail!("Transaction not found"),
+        };
+        self.ctx.publish(PubsubMessage::FindFile(
+            FindFile {
+                tx_id: tx.id(),
+                maybe_shard_config: None,
+            }
+            .into(),
+        ));
+        Ok(())
+    }
+
+    async fn on_start_sync_file(
+        &mut self,
+        tx_seq: u64,
+        maybe_range: Option<(u64, u64)>,
+        maybe_peer: Option<(PeerId, Multiaddr)>,
+        priority: SyncPriority,
+    ) -> Result<()> {
+        info!(%tx_seq, ?maybe_range, ?maybe_peer, "Start to sync file");
+
         // remove failed entry if caused by tx reverted, so as to re-sync
         // file with latest tx_id.
         let mut tx_reverted = false;
@@ -652,10 +1394,7 @@ impl SyncService {
                             Some(s) => s,
                             None => {
                                 debug!(%tx.seq, "No more data needed");
-                                if self.store.finalize_tx_with_hash(tx.seq, tx.hash()).await? {
-                                    self.ctx
-                                        .send(NetworkMessage::AnnounceLocalFile { tx_id: tx.id() });
-                                }
+                                self.store.finalize_tx_batched(tx.seq, tx.hash()).await?;
                                 return Ok(());
                             }
                         };
@@ -667,15 +1406,19 @@ impl SyncService {
                     bail!("Invalid chunk range");
                 }
 
-                entry.insert(SerialSyncController::new(
+                let controller = entry.insert(SerialSyncController::new(
                     self.config,
                     tx.id(),
                     tx.start_entry_index(),
                     FileSyncGoal::new(num_chunks, index_start, index_end, all_chunks),
                     self.ctx.clone(),
+                    self.scheduler.register(priority),
                     self.store.clone(),
                     self.file_location_cache.clone(),
-                ))
+                    self.peer_stats.clone(),
+                ));
+                controller.set_trace(self.traces.get(tx_seq));
+                controller
             }
         };
 
@@ -694,6 +1437,307 @@ impl SyncService {
         Ok(())
     }
 
+    /// Remove the corrupted data of a file (all data if `verify_first` is `false`), and sync
+    /// the file again. Different from the file sync via RPC, it is not limited by
+    /// `max_sync_files`.
+    async fn on_resync_file(&mut self, tx_seq: u64, verify_first: bool) -> Result<ResyncFileInfo> {
+        info!(%tx_seq, %verify_first, "Start to resync file");
+
+        // Resync is in progress, so just return the same handle.
+        if let Some(controller) = self.controllers.get(&tx_seq) {
+            if !controller.is_completed_or_failed() {
+                debug!(%tx_seq, "File already in sync");
+                return Ok(ResyncFileInfo {
+                    tx_seq,
+                    removed_batches: vec![],
+                    in_sync: true,
+                });
+            }
+        }
+
+        let tx = match self.store.get_tx_by_seq_number(tx_seq).await? {
+            Some(tx) => tx,
+            None => bail!("Transaction not found"),
+        };
+
+        // The data out of the local shard or pruned is owned by the pruner.
+        if self.store.check_tx_pruned(tx_seq).await? {
+            bail!("File already pruned");
+        }
+        if self.store.check_tx_invalid(tx_seq).await? {
+            bail!("File rejected by ingest policy");
+        }
+
+        let batch_list = if verify_first {
+            self.store.verify_tx_data(tx_seq).await?
+        } else {
+            // The batches shared with other files are kept, except the data of this file.
+            let shard_config = self.store.get_store().get_shard_config();
+            let start_batch = tx.start_entry_index() / PORA_CHUNK_SIZE as u64;
+            let end_batch =
+                (tx.start_entry_index() + tx.num_entries() as u64).div_ceil(PORA_CHUNK_SIZE as u64);
+            (start_batch..end_batch)
+                .filter(|batch_index| shard_config.in_range(*batch_index))
+                .collect()
+        };
+
+        if verify_first && batch_list.is_empty() {
+            debug!(%tx_seq, "No corrupted data found");
+            return Ok(ResyncFileInfo {
+                tx_seq,
+                removed_batches: vec![],
+                in_sync: false,
+            });
+        }
+
+        self.store.reset_tx_data(tx_seq, &batch_list).await?;
+        info!(%tx_seq, ?batch_list, "Removed file data to resync");
+
+        self.on_start_sync_file(tx_seq, None, None, SyncPriority::High)
+            .await?;
+
+        Ok(ResyncFileInfo {
+            tx_seq,
+            removed_batches: batch_list,
+            in_sync: self.controllers.contains_key(&tx_seq),
+        })
+    }
+
+    /// Removes the corrupted entry batches, and syncs only the chunks of the removed batches
+    /// again, see [`crate::flow_repair`]. Only the batches filled by the data of a single file
+    /// are removed.
+    async fn on_repair_flow_batches(&mut self, batches: Vec<u64>) -> Result<RepairFlowRangeInfo> {
+        info!(?batches, "Start to repair flow batches");
+
+        let mut repair = RepairFlowRangeInfo::default();
+        let mut batches_by_tx: BTreeMap<u64, (Transaction, Vec<u64>)> = BTreeMap::new();
+        for batch_index in batches {
+            let batch_start = batch_index * PORA_CHUNK_SIZE as u64;
+            let tx = match self.store.get_tx_seq_by_flow_index(batch_start).await? {
+                Some(tx_seq) => self.store.get_tx_by_seq_number(tx_seq).await?,
+                None => None,
+            };
+            let tx = match tx {
+                Some(tx)
+                    if batch_start + PORA_CHUNK_SIZE as u64
+                        <= tx.start_entry_index() + tx.num_entries() as u64 =>
+                {
+                    tx
+                }
+                _ => {
+                    repair.skipped_batches.push(batch_index);
+                    continue;
+                }
+            };
+            if batch_start < tx.start_entry_index()
+                || self.store.check_tx_pruned(tx.seq).await?
+                || self.store.check_tx_invalid(tx.seq).await?
+                || self
+                    .controllers
+                    .get(&tx.seq)
+                    .map_or(false, |c| !c.is_completed_or_failed())
+            {
+                repair.skipped_batches.push(batch_index);
+                continue;
+            }
+
+            batches_by_tx
+                .entry(tx.seq)
+                .or_insert_with(|| (tx, vec![]))
+                .1
+                .push(batch_index);
+        }
+
+        for (tx_seq, (tx, batch_list)) in batches_by_tx {
+            self.store.reset_tx_data(tx_seq, &batch_list).await?;
+            info!(%tx_seq, ?batch_list, "Removed flow data to repair");
+            repair.removed_batches.extend(batch_list.iter());
+            repair.tx_seqs.push(tx_seq);
+
+            let ranges = ChunkRangesSync::new(&tx, &batch_list);
+            self.on_start_sync_chunk_ranges(tx_seq, ranges).await?;
+        }
+
+        Ok(repair)
+    }
+
+    /// Audits the random chunks stored locally against the flow root, and repairs the entry
+    /// batches of the chunks failed to verify, see [`crate::self_audit`].
+    async fn on_self_audit(&mut self) {
+        let failed_batches = match self.self_audit_round().await {
+            Ok(Some(failed_batches)) => failed_batches,
+            Ok(None) => return,
+            Err(e) => {
+                warn!(error = ?e, "Failed to audit the stored data");
+                return;
+            }
+        };
+
+        let batches = failed_batches.into_iter().collect::<Vec<_>>();
+        match self.on_repair_flow_batches(batches.clone()).await {
+            Ok(repair) => info!(?batches, ?repair, "Repairing the batches failed self-audit"),
+            Err(e) => {
+                warn!(?batches, error = ?e, "Failed to repair the batches failed self-audit")
+            }
+        }
+    }
+
+    /// Verifies the sampled chunks, and returns the entry batches of the chunks failed, or
+    /// `None` if the flow changed during the audit, e.g. reverted or pruned.
+    async fn self_audit_round(&mut self) -> Result<Option<BTreeSet<u64>>> {
+        let flow_version = match self.stable_flow_version(false) {
+            Some(version) => version,
+            None => {
+                debug!("Skip self-audit during revert or prune");
+                return Ok(None);
+            }
+        };
+
+        let indices = self
+            .store
+            .sample_stored_chunks(self.config.self_audit_samples)
+            .await?;
+        let mut failed_batches = BTreeSet::new();
+        let mut failed = 0;
+        for index in indices.iter() {
+            if !self
+                .store
+                .verify_flow_range(*index, index + 1)
+                .await?
+                .is_empty()
+            {
+                failed += 1;
+                failed_batches.insert(index / PORA_CHUNK_SIZE as u64);
+            }
+        }
+        if self.store.get_store().get_flow_version() != flow_version {
+            debug!("Discard self-audit as the flow changed");
+            return Ok(None);
+        }
+
+        self.self_audit.on_round(indices.len(), failed);
+        metrics::SELF_AUDIT_CHUNKS_SAMPLED.inc(indices.len());
+        metrics::SELF_AUDIT_CHUNKS_FAILED.inc(failed);
+        if let Some(ratio) = self.self_audit.status().and_then(|s| s.success_ratio) {
+            metrics::SELF_AUDIT_SUCCESS_RATIO_PERMILLE.update((ratio * 1000.0).round() as usize);
+        }
+        if failed > 0 {
+            warn!(
+                sampled = indices.len(),
+                %failed,
+                ?failed_batches,
+                "Stored data failed self-audit"
+            );
+        } else {
+            debug!(sampled = indices.len(), "Stored data passed self-audit");
+        }
+
+        Ok(Some(failed_batches))
+    }
+
+    /// Syncs the chunk ranges of a file one after another, which is the range-based entry point
+    /// of file sync, and finalizes the file once all ranges synced.
+    async fn on_start_sync_chunk_ranges(
+        &mut self,
+        tx_seq: u64,
+        mut ranges: ChunkRangesSync,
+    ) -> Result<()> {
+        let range = match ranges.pending.pop_front() {
+            Some(range) => range,
+            None => {
+                // only the rear padding removed
+                self.store
+                    .finalize_tx_batched(tx_seq, ranges.tx_hash)
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        self.on_start_sync_file(tx_seq, Some(range), None, SyncPriority::High)
+            .await?;
+        self.chunk_range_syncs.insert(tx_seq, ranges);
+        Ok(())
+    }
+
+    /// Cancels the file sync manually, which is kept as failed so that the status could be
+    /// queried until the file synced again.
+    fn on_cancel_file_sync(&mut self, tx_seq: u64) -> FileSyncControlStatus {
+        match self.controllers.get_mut(&tx_seq) {
+            None => FileSyncControlStatus::NotFound,
+            Some(controller) if controller.is_completed_or_failed() => {
+                FileSyncControlStatus::AlreadyFinished
+            }
+            Some(controller) => {
+                controller.cancel(CancelReason::Manual);
+                FileSyncControlStatus::Cancelled
+            }
+        }
+    }
+
+    /// Starts a new trace of the file sync, which also records the file synced later, or stops
+    /// recording the file sync. Returns whether the file is in sync.
+    fn on_trace_file_sync(&mut self, tx_seq: u64, enable: bool) -> bool {
+        info!(%tx_seq, %enable, "Trace file sync");
+
+        let trace = if enable {
+            if let Some(evicted) = self.traces.enable(tx_seq) {
+                if let Some(controller) = self.controllers.get_mut(&evicted) {
+                    controller.set_trace(None);
+                }
+            }
+            self.traces.get(tx_seq)
+        } else {
+            self.traces.disable(tx_seq);
+            None
+        };
+
+        match self.controllers.get_mut(&tx_seq) {
+            Some(controller) => {
+                controller.set_trace(trace);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Restarts the file sync at high priority, e.g. stuck or failed, or starts to sync the
+    /// file if not in sync. Same as resync, it is not limited by `max_sync_files`.
+    async fn on_retry_file_sync(&mut self, tx_seq: u64) -> Result<FileSyncControlStatus> {
+        info!(%tx_seq, "Retry file sync manually");
+
+        if let Some(controller) = self.controllers.get_mut(&tx_seq) {
+            match controller.get_status() {
+                SyncState::Completed => return Ok(FileSyncControlStatus::AlreadyFinished),
+                // Synced again with the latest tx, see `on_start_sync_file`.
+                SyncState::Failed {
+                    reason: FailureReason::TxReverted(..),
+                } => {}
+                _ => {
+                    controller.retry(self.scheduler.register(SyncPriority::High));
+                    controller.transition();
+                    return Ok(FileSyncControlStatus::Requeued);
+                }
+            }
+        } else {
+            if self.store.get_tx_by_seq_number(tx_seq).await?.is_none() {
+                return Ok(FileSyncControlStatus::NotFound);
+            }
+            if self.store.get_store().get_tx_status(tx_seq)?.is_some() {
+                return Ok(FileSyncControlStatus::AlreadyFinished);
+            }
+        }
+
+        self.on_start_sync_file(tx_seq, None, None, SyncPriority::High)
+            .await?;
+
+        // No more data needed, and the file finalized already.
+        if self.controllers.contains_key(&tx_seq) {
+            Ok(FileSyncControlStatus::Requeued)
+        } else {
+            Ok(FileSyncControlStatus::AlreadyFinished)
+        }
+    }
+
     async fn on_announce_file_gossip(&mut self, tx_id: TxID, peer_id: PeerId, addr: Multiaddr) {
         let tx_seq = tx_id.seq;
         trace!(%tx_seq, %peer_id, %addr, "Received AnnounceFile gossip");
@@ -729,7 +1773,7 @@ impl SyncService {
 
         // Now, always sync files among all nodes
         if let Err(err) = self
-            .on_start_sync_file(tx_seq, None, Some((peer_id, addr)))
+            .on_start_sync_file(tx_seq, None, Some((peer_id, addr)), SyncPriority::Normal)
             .await
         {
             // FIXME(zz): This is possible for tx missing. Is it expected?
@@ -763,7 +1807,42 @@ impl SyncService {
                 controller.transition();
             }
         } else if let Some(manager) = &self.auto_sync_manager {
-            let _ = manager.new_file_send.send(file.tx_id.seq);
+            let _ = manager
+                .new_file_send
+                .send(NewFileEvent::Announced(file.tx_id.seq));
+        }
+    }
+
+    /// Handle on `RetractFile` gossip message received, which retracts the files announced by
+    /// `peer_id` but reverted on chain.
+    async fn on_retract_file_gossip(&mut self, peer_id: PeerId, tx_ids: Vec<TxID>) {
+        debug!(%peer_id, ?tx_ids, "Received RetractFile gossip");
+
+        for tx_id in tx_ids {
+            // validate against the local log entries, e.g. the peer reorged to a minority fork
+            let local_tx = match self.store.get_tx_by_seq_number(tx_id.seq).await {
+                Ok(tx) => tx,
+                Err(err) => {
+                    warn!(?err, %tx_id.seq, "Failed to get tx to retract file");
+                    continue;
+                }
+            };
+            if matches!(&local_tx, Some(tx) if tx.id() == tx_id) {
+                debug!(%peer_id, ?tx_id, "Ignore retracted file that is valid locally");
+                continue;
+            }
+
+            self.file_location_cache.remove(&tx_id, &peer_id);
+
+            // deprioritize the queued sync if the tx is not synced locally yet, which is
+            // unverifiable, unless still announced by other peers
+            if local_tx.is_none() && self.file_location_cache.get_all(tx_id).is_empty() {
+                if let Some(manager) = &self.auto_sync_manager {
+                    let _ = manager
+                        .new_file_send
+                        .send(NewFileEvent::Retracted(tx_id.seq));
+                }
+            }
         }
     }
 
@@ -800,6 +1879,7 @@ impl SyncService {
 
         for tx_seq in to_terminate.iter() {
             self.controllers.remove(tx_seq);
+            self.chunk_range_syncs.remove(tx_seq);
         }
 
         let num_terminated = to_terminate.len();
@@ -810,7 +1890,7 @@ impl SyncService {
         num_terminated
     }
 
-    fn on_heartbeat(&mut self) {
+    async fn on_heartbeat(&mut self) {
         let mut completed = vec![];
         let mut incompleted = vec![];
 
@@ -832,10 +1912,81 @@ impl SyncService {
         }
 
         for tx_seq in completed {
+            // sync the next chunk range of the file if any
+            if let Some(ranges) = self.chunk_range_syncs.get_mut(&tx_seq) {
+                if let Some(range) = ranges.pending.pop_front() {
+                    debug!(%tx_seq, ?range, "Sync next chunk range");
+                    if let Some(controller) = self.controllers.get_mut(&tx_seq) {
+                        controller.reset(Some(range));
+                        controller.transition();
+                    }
+                    continue;
+                }
+
+                let tx_hash = ranges.tx_hash;
+                self.chunk_range_syncs.remove(&tx_seq);
+                match self.store.finalize_tx_batched(tx_seq, tx_hash).await {
+                    Ok(true) => info!(%tx_seq, "Succeeded to finalize file of chunk ranges"),
+                    Ok(false) => warn!(%tx_seq, "Transaction reverted during finalize_tx"),
+                    Err(e) => warn!(%tx_seq, "Failed to finalize file of chunk ranges: {:?}", e),
+                }
+            }
+
             self.controllers.remove(&tx_seq);
         }
     }
 
+    /// Pings peers of active or recently active file syncs, and reassigns the outstanding
+    /// requests of dead peers to other peers.
+    fn on_ping_round(&mut self) {
+        for controller in self.controllers.values() {
+            for peer_id in controller.active_peers() {
+                self.liveness.on_active(peer_id);
+            }
+        }
+
+        let round = self.liveness.next_round();
+
+        for peer_id in round.missed {
+            debug!(%peer_id, "Sync peer missed ping");
+            self.ctx
+                .report_peer(peer_id, PeerAction::HighToleranceError, "Missed sync ping");
+        }
+
+        for peer_id in round.dead {
+            self.on_peer_dead(peer_id);
+        }
+
+        for peer_id in round.to_ping {
+            self.ctx.send(NetworkMessage::SendRequest {
+                peer_id,
+                request_id: network::RequestId::Sync(Instant::now(), RequestId::Ping),
+                request: network::Request::Ping,
+                span: Span::current(),
+            });
+        }
+    }
+
+    fn on_pong(&mut self, peer_id: PeerId) {
+        let missed = self.liveness.on_pong(&peer_id);
+        if missed > 0 {
+            debug!(%peer_id, %missed, "Sync peer responded ping again");
+        }
+    }
+
+    fn on_peer_dead(&mut self, peer_id: PeerId) {
+        info!(%peer_id, "Sync peer dead due to pings missed");
+
+        self.ctx
+            .report_peer(peer_id, PeerAction::MidToleranceError, "Sync peer dead");
+        self.ctx.send(NetworkMessage::DisconnectPeer { peer_id });
+
+        for controller in self.controllers.values_mut() {
+            controller.on_peer_dead(peer_id);
+            controller.transition();
+        }
+    }
+
     async fn tx_sync_start_index(store: &Store, tx: &Transaction) -> Result<Option<u64>> {
         let shard_config = store.get_store().get_shard_config();
         let start_segment = sector_to_segment(tx.start_entry_index());
@@ -871,6 +2022,8 @@ mod tests {
     use super::*;
     use crate::test_util::create_2_store;
     use crate::test_util::tests::create_file_location_cache;
+    use crate::{ProofVerification, SyncTraceEvent};
+    use file_location_cache::test_util::AnnounceFileBuilder;
     use libp2p::identity;
     use network::discovery::ConnectionId;
     use network::new_network_channel;
@@ -879,12 +2032,13 @@ mod tests {
     use network::ReportSource;
     use shared_types::ChunkArray;
     use shared_types::Transaction;
+    use shared_types::CHUNK_SIZE;
     use std::thread;
     use std::time::Duration;
     use std::time::Instant;
     use storage::log_store::log_manager::LogConfig;
     use storage::log_store::log_manager::LogManager;
-    use storage::log_store::LogStoreRead;
+    use storage::log_store::{LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
     use storage::H256;
     use task_executor::test_utils::TestRuntime;
 
@@ -904,6 +2058,7 @@ mod tests {
         network_recv: NetworkReceiver,
         event_send: broadcast::Sender<LogSyncEvent>,
         catch_up_end_recv: Option<oneshot::Receiver<()>>,
+        config_send: Option<watch::Sender<DynamicConfig>>,
     }
 
     impl Default for TestSyncRuntime {
@@ -914,6 +2069,10 @@ mod tests {
 
     impl TestSyncRuntime {
         fn new(chunk_counts: Vec<usize>, seq_size: usize) -> Self {
+            Self::with_runtime(chunk_counts, seq_size, TestRuntime::default())
+        }
+
+        fn with_runtime(chunk_counts: Vec<usize>, seq_size: usize, runtime: TestRuntime) -> Self {
             let chunk_count = chunk_counts[0];
             let (store, peer_store, txs, data) = create_2_store(chunk_counts);
             let init_data = data[0].clone();
@@ -925,7 +2084,7 @@ mod tests {
             let tx_ids = txs.iter().take(seq_size).map(|tx| tx.id()).collect();
 
             Self {
-                runtime: TestRuntime::default(),
+                runtime,
                 chunk_count,
                 store,
                 peer_store,
@@ -937,6 +2096,7 @@ mod tests {
                 network_recv,
                 event_send,
                 catch_up_end_recv: Some(catch_up_end_recv),
+                config_send: None,
             }
         }
 
@@ -961,6 +2121,8 @@ mod tests {
             } else {
                 self.store.clone()
             };
+            let (config_send, config_recv) = watch::channel(config.dynamic());
+            self.config_send = Some(config_send);
 
             SyncService::spawn_with_config(
                 config,
@@ -968,8 +2130,11 @@ mod tests {
                 self.network_send.clone(),
                 store,
                 self.file_location_cache.clone(),
+                Default::default(),
                 self.event_send.subscribe(),
                 self.catch_up_end_recv.take().unwrap(),
+                config_recv,
+                self.runtime.task_executor.shutdown_token("sync"),
             )
             .await
             .unwrap()
@@ -991,14 +2156,24 @@ mod tests {
         let (network_send, mut network_recv) = new_network_channel();
         let (_, sync_recv) = channel::Channel::unbounded("test");
 
+        let ctx = Arc::new(SyncNetworkContext::new(network_send));
+        let peer_stats = Arc::new(PeerStats::default());
         let mut sync = SyncService {
             config: Config::default(),
+            config_recv: watch::channel(Config::default().dynamic()).1,
             msg_recv: sync_recv,
-            ctx: Arc::new(SyncNetworkContext::new(network_send)),
+            ctx: ctx.clone(),
             store,
             file_location_cache,
             controllers: Default::default(),
+            chunk_range_syncs: Default::default(),
+            scheduler: RequestScheduler::new(&Config::default(), ctx),
+            liveness: PeerLiveness::new(&Config::default()),
+            peer_stats,
             auto_sync_manager: None,
+            announcements: AnnouncementExchange::new(Duration::from_secs(60)),
+            self_audit: Default::default(),
+            traces: Default::default(),
         };
 
         sync.on_peer_connected(init_peer_id);
@@ -1020,14 +2195,24 @@ mod tests {
         let (network_send, mut network_recv) = new_network_channel();
         let (_, sync_recv) = channel::Channel::unbounded("test");
 
+        let ctx = Arc::new(SyncNetworkContext::new(network_send));
+        let peer_stats = Arc::new(PeerStats::default());
         let mut sync = SyncService {
             config: Config::default(),
+            config_recv: watch::channel(Config::default().dynamic()).1,
             msg_recv: sync_recv,
-            ctx: Arc::new(SyncNetworkContext::new(network_send)),
+            ctx: ctx.clone(),
             store,
             file_location_cache,
             controllers: Default::default(),
+            chunk_range_syncs: Default::default(),
+            scheduler: RequestScheduler::new(&Config::default(), ctx),
+            liveness: PeerLiveness::new(&Config::default()),
+            peer_stats,
             auto_sync_manager: None,
+            announcements: AnnouncementExchange::new(Duration::from_secs(60)),
+            self_audit: Default::default(),
+            traces: Default::default(),
         };
 
         sync.on_peer_disconnected(init_peer_id);
@@ -1345,73 +2530,338 @@ mod tests {
             network_send,
             store.clone(),
             file_location_cache,
+            Default::default(),
             event_recv,
             catch_up_end_recv,
+            watch::channel(Config::default().dynamic()).1,
+            runtime.task_executor.shutdown_token("sync"),
         )
         .await
         .unwrap();
 
-        let tx_seq = 0u64;
-        sync_send
-            .request(SyncRequest::SyncFile { tx_seq })
+        let tx_seq = 0u64;
+        sync_send
+            .request(SyncRequest::SyncFile { tx_seq })
+            .await
+            .unwrap();
+
+        thread::sleep(Duration::from_millis(1000));
+        assert_eq!(store.get_tx_by_seq_number(tx_seq).unwrap(), None);
+        assert!(network_recv.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sync_file_exist_in_store() {
+        let mut runtime = TestSyncRuntime::default();
+        let sync_send = runtime.spawn_sync_service(true).await;
+
+        let tx_seq = 0u64;
+        sync_send
+            .request(SyncRequest::SyncFile { tx_seq })
+            .await
+            .unwrap();
+
+        thread::sleep(Duration::from_millis(1000));
+        assert!(runtime.peer_store.check_tx_completed(tx_seq).unwrap());
+        assert!(runtime.network_recv.try_recv().is_err());
+    }
+
+    async fn wait_for_tx_finalized(store: Arc<LogManager>, tx_seq: u64) {
+        let deadline = Instant::now() + Duration::from_millis(5000);
+        while !store.check_tx_completed(tx_seq).unwrap() {
+            if Instant::now() >= deadline {
+                panic!("Failed to wait tx completed");
+            }
+
+            thread::sleep(Duration::from_millis(300));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_file_success() {
+        let mut runtime = TestSyncRuntime::default();
+        let sync_send = runtime.spawn_sync_service(false).await;
+
+        let tx_seq = 0u64;
+        sync_send
+            .request(SyncRequest::SyncFile { tx_seq })
+            .await
+            .unwrap();
+
+        receive_dial(&mut runtime, &sync_send).await;
+
+        assert!(!runtime.store.check_tx_completed(tx_seq).unwrap());
+
+        assert!(!matches!(
+            sync_send
+                .request(SyncRequest::SyncStatus { tx_seq })
+                .await
+                .unwrap(),
+            SyncResponse::SyncStatus { status } if status == Some(SyncState::Completed)
+        ));
+
+        receive_chunk_request(
+            &mut runtime.network_recv,
+            &sync_send,
+            runtime.peer_store.clone(),
+            runtime.init_peer_id,
+            tx_seq,
+            0,
+            runtime.chunk_count as u64,
+        )
+        .await;
+
+        wait_for_tx_finalized(runtime.store, tx_seq).await;
+
+        // test heartbeat
+        let deadline =
+            Instant::now() + Config::default().heartbeat_interval + Duration::from_secs(1);
+        while !matches!(sync_send
+            .request(SyncRequest::SyncStatus { tx_seq })
+            .await
+            .unwrap(),
+            SyncResponse::SyncStatus {status} if status.is_none()
+        ) {
+            if Instant::now() >= deadline {
+                panic!("Failed to wait heartbeat");
+            }
+
+            thread::sleep(Duration::from_millis(300));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trace_file_sync() {
+        let mut runtime = TestSyncRuntime::default();
+        let sync_send = runtime.spawn_sync_service(false).await;
+        let tx_seq = 0u64;
+
+        // traced before the file sync started
+        match sync_send
+            .request(SyncRequest::TraceFileSync {
+                tx_seq,
+                enable: true,
+            })
+            .await
+            .unwrap()
+        {
+            SyncResponse::TraceFileSync { syncing } => assert!(!syncing),
+            response => panic!("Unexpected response: {:?}", response),
+        }
+
+        sync_send
+            .request(SyncRequest::SyncFile { tx_seq })
+            .await
+            .unwrap();
+        receive_dial(&mut runtime, &sync_send).await;
+        receive_chunk_request(
+            &mut runtime.network_recv,
+            &sync_send,
+            runtime.peer_store.clone(),
+            runtime.init_peer_id,
+            tx_seq,
+            0,
+            runtime.chunk_count as u64,
+        )
+        .await;
+        wait_for_tx_finalized(runtime.store.clone(), tx_seq).await;
+
+        let trace = match sync_send
+            .request(SyncRequest::GetFileSyncTrace { tx_seq })
+            .await
+            .unwrap()
+        {
+            SyncResponse::FileSyncTrace { trace } => trace.unwrap(),
+            response => panic!("Unexpected response: {:?}", response),
+        };
+        assert!(trace.enabled);
+        assert_eq!(trace.dropped_events, 0);
+
+        let peer_id = runtime.init_peer_id.to_string();
+        let expected = vec![
+            SyncTraceEvent::StateChanged {
+                state: "Idle".into(),
+            },
+            // found from the file location cache when finding peers
+            SyncTraceEvent::PeerFound {
+                peer_id: peer_id.clone(),
+            },
+            SyncTraceEvent::StateChanged {
+                state: "FindingPeers".into(),
+            },
+            SyncTraceEvent::PeerConnected {
+                peer_id: peer_id.clone(),
+            },
+            SyncTraceEvent::RequestSent {
+                peer_id: peer_id.clone(),
+                from_chunk: 0,
+                to_chunk: runtime.chunk_count as u64,
+                queued: false,
+            },
+            SyncTraceEvent::StateChanged {
+                state: "Downloading".into(),
+            },
+            SyncTraceEvent::ResponseReceived {
+                peer_id: peer_id.clone(),
+                bytes: runtime.chunk_count * CHUNK_SIZE,
+            },
+            SyncTraceEvent::ResponseVerified {
+                peer_id,
+                result: ProofVerification::Valid,
+                error: None,
+            },
+            SyncTraceEvent::StateChanged {
+                state: "Completed".into(),
+            },
+        ];
+        // expected events in order, among the intermediate state changes
+        let mut events = trace.events.into_iter().map(|record| record.event);
+        for event in expected.iter() {
+            assert!(
+                events.any(|e| &e == event),
+                "event not traced in order: {:?}",
+                event
+            );
+        }
+
+        // events are kept once disabled
+        match sync_send
+            .request(SyncRequest::TraceFileSync {
+                tx_seq,
+                enable: false,
+            })
             .await
-            .unwrap();
+            .unwrap()
+        {
+            SyncResponse::TraceFileSync { .. } => {}
+            response => panic!("Unexpected response: {:?}", response),
+        }
+        match sync_send
+            .request(SyncRequest::GetFileSyncTrace { tx_seq })
+            .await
+            .unwrap()
+        {
+            SyncResponse::FileSyncTrace { trace } => assert!(!trace.unwrap().enabled),
+            response => panic!("Unexpected response: {:?}", response),
+        }
 
-        thread::sleep(Duration::from_millis(1000));
-        assert_eq!(store.get_tx_by_seq_number(tx_seq).unwrap(), None);
-        assert!(network_recv.try_recv().is_err());
+        // not traced by default
+        match sync_send
+            .request(SyncRequest::GetFileSyncTrace { tx_seq: 1 })
+            .await
+            .unwrap()
+        {
+            SyncResponse::FileSyncTrace { trace } => assert!(trace.is_none()),
+            response => panic!("Unexpected response: {:?}", response),
+        }
     }
 
     #[tokio::test]
-    async fn test_sync_file_exist_in_store() {
-        let mut runtime = TestSyncRuntime::default();
-        let sync_send = runtime.spawn_sync_service(true).await;
-
-        let tx_seq = 0u64;
-        sync_send
-            .request(SyncRequest::SyncFile { tx_seq })
-            .await
+    async fn test_resync_file() {
+        // The file of tx 1 fills the entry batch 2 and 3.
+        let mut runtime = TestSyncRuntime::new(vec![1, 2 * PORA_CHUNK_SIZE], 2);
+        let tx_seq = 1u64;
+        let chunks = runtime
+            .peer_store
+            .get_chunks_by_tx_and_index_range(tx_seq, 0, 2 * PORA_CHUNK_SIZE)
+            .unwrap()
             .unwrap();
+        runtime.store.put_chunks(tx_seq, chunks).unwrap();
+        runtime.store.finalize_tx(tx_seq).unwrap();
 
-        thread::sleep(Duration::from_millis(1000));
-        assert!(runtime.peer_store.check_tx_completed(tx_seq).unwrap());
-        assert!(runtime.network_recv.try_recv().is_err());
-    }
+        // Corrupt the second segment of file.
+        runtime.store.remove_chunks_batch(&[3]).unwrap();
+        runtime
+            .store
+            .put_chunks(
+                tx_seq,
+                ChunkArray {
+                    data: vec![1u8; PORA_CHUNK_SIZE / 2 * CHUNK_SIZE],
+                    start_index: PORA_CHUNK_SIZE as u64,
+                },
+            )
+            .unwrap();
+        assert_eq!(runtime.store.verify_tx_data(tx_seq).unwrap(), vec![3]);
 
-    async fn wait_for_tx_finalized(store: Arc<LogManager>, tx_seq: u64) {
-        let deadline = Instant::now() + Duration::from_millis(5000);
-        while !store.check_tx_completed(tx_seq).unwrap() {
-            if Instant::now() >= deadline {
-                panic!("Failed to wait tx completed");
+        let sync_send = runtime.spawn_sync_service(false).await;
+        let request = || SyncRequest::ResyncFile {
+            tx_seq,
+            verify_first: true,
+        };
+        match sync_send.request(request()).await.unwrap() {
+            SyncResponse::ResyncFile { result } => {
+                let info = result.unwrap();
+                assert_eq!(info.tx_seq, tx_seq);
+                assert_eq!(info.removed_batches, vec![3]);
+                assert!(info.in_sync);
             }
+            response => panic!("Unexpected response: {:?}", response),
+        }
+        assert!(!runtime.store.check_tx_completed(tx_seq).unwrap());
 
-            thread::sleep(Duration::from_millis(300));
+        // Resync again during file sync takes no effect.
+        match sync_send.request(request()).await.unwrap() {
+            SyncResponse::ResyncFile { result } => {
+                let info = result.unwrap();
+                assert!(info.removed_batches.is_empty());
+                assert!(info.in_sync);
+            }
+            response => panic!("Unexpected response: {:?}", response),
         }
+
+        receive_dial(&mut runtime, &sync_send).await;
+        receive_chunk_request(
+            &mut runtime.network_recv,
+            &sync_send,
+            runtime.peer_store.clone(),
+            runtime.init_peer_id,
+            tx_seq,
+            PORA_CHUNK_SIZE as u64,
+            2 * PORA_CHUNK_SIZE as u64,
+        )
+        .await;
+
+        wait_for_tx_finalized(runtime.store.clone(), tx_seq).await;
+        assert!(runtime.store.verify_tx_data(tx_seq).unwrap().is_empty());
     }
 
     #[tokio::test]
-    async fn test_sync_file_success() {
-        let mut runtime = TestSyncRuntime::default();
-        let sync_send = runtime.spawn_sync_service(false).await;
+    async fn test_resync_file_shared_batch() {
+        // The files of tx 0 and 1 share the entry batch 0.
+        let mut runtime = TestSyncRuntime::new(vec![3, 3], 2);
+        for tx_seq in 0..2 {
+            let chunks = runtime
+                .peer_store
+                .get_chunks_by_tx_and_index_range(tx_seq, 0, 3)
+                .unwrap()
+                .unwrap();
+            runtime.store.put_chunks(tx_seq, chunks).unwrap();
+            runtime.store.finalize_tx(tx_seq).unwrap();
+        }
 
-        let tx_seq = 0u64;
-        sync_send
-            .request(SyncRequest::SyncFile { tx_seq })
+        let tx_seq = 1u64;
+        let sync_send = runtime.spawn_sync_service(false).await;
+        match sync_send
+            .request(SyncRequest::ResyncFile {
+                tx_seq,
+                verify_first: false,
+            })
             .await
-            .unwrap();
-
-        receive_dial(&mut runtime, &sync_send).await;
-
+            .unwrap()
+        {
+            SyncResponse::ResyncFile { result } => {
+                let info = result.unwrap();
+                assert_eq!(info.removed_batches, vec![0]);
+                assert!(info.in_sync);
+            }
+            response => panic!("Unexpected response: {:?}", response),
+        }
         assert!(!runtime.store.check_tx_completed(tx_seq).unwrap());
+        // The data of the other file is kept.
+        assert!(runtime.store.check_tx_completed(0).unwrap());
+        assert!(runtime.store.verify_tx_data(0).unwrap().is_empty());
 
-        assert!(!matches!(
-            sync_send
-                .request(SyncRequest::SyncStatus { tx_seq })
-                .await
-                .unwrap(),
-            SyncResponse::SyncStatus { status } if status == Some(SyncState::Completed)
-        ));
-
+        receive_dial(&mut runtime, &sync_send).await;
         receive_chunk_request(
             &mut runtime.network_recv,
             &sync_send,
@@ -1419,26 +2869,78 @@ mod tests {
             runtime.init_peer_id,
             tx_seq,
             0,
-            runtime.chunk_count as u64,
+            3,
         )
         .await;
 
-        wait_for_tx_finalized(runtime.store, tx_seq).await;
+        wait_for_tx_finalized(runtime.store.clone(), tx_seq).await;
+        assert!(runtime.store.verify_tx_data(tx_seq).unwrap().is_empty());
+    }
 
-        // test heartbeat
-        let deadline =
-            Instant::now() + Config::default().heartbeat_interval + Duration::from_secs(1);
-        while !matches!(sync_send
-            .request(SyncRequest::SyncStatus { tx_seq })
+    #[tokio::test]
+    async fn test_sync_chunk_ranges() {
+        // The file of tx 1 fills the entry batch 2 and 3, and misses 2 discrete ranges.
+        let mut runtime = TestSyncRuntime::new(vec![1, 2 * PORA_CHUNK_SIZE], 2);
+        let tx_seq = 1u64;
+        let num_chunks = 2 * PORA_CHUNK_SIZE;
+        for (start, end) in [(10, PORA_CHUNK_SIZE), (PORA_CHUNK_SIZE + 10, num_chunks)] {
+            let chunks = runtime
+                .peer_store
+                .get_chunks_by_tx_and_index_range(tx_seq, start, end)
+                .unwrap()
+                .unwrap();
+            runtime.store.put_chunks(tx_seq, chunks).unwrap();
+        }
+
+        let sync_send = runtime.spawn_sync_service(false).await;
+        let batch_size = PORA_CHUNK_SIZE as u64;
+        match sync_send
+            .request(SyncRequest::SyncChunkRanges {
+                tx_seq,
+                ranges: vec![(batch_size, batch_size + 10), (0, 5), (5, 10)],
+            })
             .await
-            .unwrap(),
-            SyncResponse::SyncStatus {status} if status.is_none()
-        ) {
-            if Instant::now() >= deadline {
-                panic!("Failed to wait heartbeat");
-            }
+            .unwrap()
+        {
+            SyncResponse::SyncFile { err } => assert!(err.is_empty(), "{}", err),
+            response => panic!("Unexpected response: {:?}", response),
+        }
 
-            thread::sleep(Duration::from_millis(300));
+        // Only the missing ranges are synced, one after another.
+        receive_dial(&mut runtime, &sync_send).await;
+        for (start, end) in [(0, 10), (batch_size, batch_size + 10)] {
+            receive_chunk_request(
+                &mut runtime.network_recv,
+                &sync_send,
+                runtime.peer_store.clone(),
+                runtime.init_peer_id,
+                tx_seq,
+                start,
+                end,
+            )
+            .await;
+        }
+
+        wait_for_tx_finalized(runtime.store.clone(), tx_seq).await;
+        assert!(runtime.store.verify_tx_data(tx_seq).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resync_file_pruned() {
+        let mut runtime = TestSyncRuntime::default();
+        runtime.store.prune_tx(0).unwrap();
+        let sync_send = runtime.spawn_sync_service(false).await;
+
+        match sync_send
+            .request(SyncRequest::ResyncFile {
+                tx_seq: 0,
+                verify_first: false,
+            })
+            .await
+            .unwrap()
+        {
+            SyncResponse::ResyncFile { result } => assert!(result.is_err()),
+            response => panic!("Unexpected response: {:?}", response),
         }
     }
 
@@ -1538,11 +3040,6 @@ mod tests {
         .await;
 
         wait_for_tx_finalized(runtime.store.clone(), tx_seq).await;
-        assert!(matches!(
-            runtime.network_recv.try_recv().unwrap(),
-            NetworkMessage::AnnounceLocalFile { .. }
-        ));
-
         assert!(!runtime.store.check_tx_completed(0).unwrap());
 
         // first file
@@ -1566,11 +3063,6 @@ mod tests {
         .await;
 
         wait_for_tx_finalized(runtime.store, tx_seq).await;
-        assert!(matches!(
-            runtime.network_recv.try_recv().unwrap(),
-            NetworkMessage::AnnounceLocalFile { .. }
-        ));
-
         sync_send
             .notify(SyncMessage::PeerDisconnected {
                 peer_id: runtime.init_peer_id,
@@ -1581,6 +3073,42 @@ mod tests {
         assert!(runtime.network_recv.try_recv().is_err());
     }
 
+    #[tokio::test]
+    async fn test_reload_max_sync_files() {
+        let mut runtime = TestSyncRuntime::new(vec![1023, 1023], 2);
+        let config = Config {
+            neighbors_only: false,
+            max_sync_files: 1,
+            ..Default::default()
+        };
+        let sync_send = runtime.spawn_sync_service_with_config(false, config).await;
+
+        let sync_file = |tx_seq| {
+            let sync_send = sync_send.clone();
+            async move {
+                match sync_send.request(SyncRequest::SyncFile { tx_seq }).await {
+                    Ok(SyncResponse::SyncFile { err }) => err,
+                    _ => panic!("Unexpected sync response"),
+                }
+            }
+        };
+
+        assert_eq!(sync_file(0).await, "");
+        assert_eq!(sync_file(1).await, "Max sync file limitation reached: 1");
+
+        runtime
+            .config_send
+            .as_ref()
+            .unwrap()
+            .send_replace(DynamicConfig {
+                max_sync_files: 2,
+                ..config.dynamic()
+            });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(sync_file(1).await, "");
+    }
+
     #[tokio::test]
     async fn test_rpc_error() {
         let mut runtime = TestSyncRuntime::default();
@@ -1592,6 +3120,7 @@ mod tests {
                     tx_id: runtime.txs[0].id(),
                 },
                 peer_id: runtime.init_peer_id,
+                error: RPCError::StreamTimeout,
             })
             .unwrap();
 
@@ -1693,6 +3222,48 @@ mod tests {
         assert!(runtime.network_recv.try_recv().is_err());
     }
 
+    #[tokio::test]
+    async fn test_retract_file() {
+        let mut runtime = TestSyncRuntime::default();
+        let sync_send = runtime.spawn_sync_service(false).await;
+
+        // announced files of the local tx and txs not synced from chain yet, one of which is
+        // announced by another peer as well
+        let local_tx_id = runtime.txs[0].id();
+        let remote_tx_id = TxID::random_hash(5);
+        let shared_tx_id = TxID::random_hash(6);
+        let other_peer_id = PeerId::random();
+        for (tx_id, peer_id) in [
+            (remote_tx_id, runtime.init_peer_id),
+            (shared_tx_id, runtime.init_peer_id),
+            (shared_tx_id, other_peer_id),
+        ] {
+            runtime.file_location_cache.insert(
+                AnnounceFileBuilder::default()
+                    .with_tx_id(tx_id)
+                    .with_peer_id(peer_id)
+                    .build(),
+            );
+        }
+
+        // retracted once reorged on the announcer, while the file valid locally is kept
+        sync_send
+            .notify(SyncMessage::RetractFileGossip {
+                tx_ids: vec![local_tx_id, remote_tx_id, shared_tx_id],
+                peer_id: runtime.init_peer_id,
+            })
+            .unwrap();
+
+        // handled in order with the notification above
+        sync_send
+            .request(SyncRequest::SyncStatus { tx_seq: 0 })
+            .await
+            .unwrap();
+        assert_eq!(runtime.file_location_cache.get_all(local_tx_id).len(), 1);
+        assert!(runtime.file_location_cache.get_all(remote_tx_id).is_empty());
+        assert_eq!(runtime.file_location_cache.get_all(shared_tx_id).len(), 1);
+    }
+
     #[tokio::test]
     async fn test_sync_status_unknown() {
         let mut runtime = TestSyncRuntime::default();
@@ -1707,6 +3278,168 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn test_terminate_and_retry_file_sync() {
+        let mut runtime = TestSyncRuntime::default();
+        let sync_send = runtime.spawn_sync_service(false).await;
+        let tx_seq = 0u64;
+
+        assert_eq!(
+            cancel_file_sync(&sync_send, tx_seq).await,
+            FileSyncControlStatus::NotFound
+        );
+
+        sync_send
+            .request(SyncRequest::SyncFile { tx_seq })
+            .await
+            .unwrap();
+
+        // Stuck in connecting peer, which never connected.
+        assert!(matches!(
+            runtime.network_recv.recv().await,
+            Some(NetworkMessage::DialPeer { .. })
+        ));
+
+        assert_eq!(
+            cancel_file_sync(&sync_send, tx_seq).await,
+            FileSyncControlStatus::Cancelled
+        );
+        assert!(matches!(
+            sync_send
+                .request(SyncRequest::SyncStatus { tx_seq })
+                .await
+                .unwrap(),
+            SyncResponse::SyncStatus { status } if status == Some(SyncState::Failed {
+                reason: FailureReason::Cancelled(CancelReason::Manual),
+            })
+        ));
+        assert_eq!(
+            cancel_file_sync(&sync_send, tx_seq).await,
+            FileSyncControlStatus::AlreadyFinished
+        );
+
+        // Synced from scratch with peers found again.
+        assert_eq!(
+            retry_file_sync(&sync_send, tx_seq).await,
+            FileSyncControlStatus::Requeued
+        );
+        receive_dial(&mut runtime, &sync_send).await;
+        receive_chunk_request(
+            &mut runtime.network_recv,
+            &sync_send,
+            runtime.peer_store.clone(),
+            runtime.init_peer_id,
+            tx_seq,
+            0,
+            runtime.chunk_count as u64,
+        )
+        .await;
+        wait_for_tx_finalized(runtime.store.clone(), tx_seq).await;
+
+        assert_eq!(
+            retry_file_sync(&sync_send, tx_seq).await,
+            FileSyncControlStatus::AlreadyFinished
+        );
+        assert_eq!(
+            retry_file_sync(&sync_send, 100).await,
+            FileSyncControlStatus::NotFound
+        );
+    }
+
+    async fn cancel_file_sync(sync_send: &SyncSender, tx_seq: u64) -> FileSyncControlStatus {
+        match sync_send
+            .request(SyncRequest::CancelFileSync { tx_seq })
+            .await
+            .unwrap()
+        {
+            SyncResponse::CancelFileSync { status } => status,
+            response => panic!("Unexpected response: {:?}", response),
+        }
+    }
+
+    async fn retry_file_sync(sync_send: &SyncSender, tx_seq: u64) -> FileSyncControlStatus {
+        match sync_send
+            .request(SyncRequest::RetryFileSync { tx_seq })
+            .await
+            .unwrap()
+        {
+            SyncResponse::RetryFileSync { result } => result.unwrap(),
+            response => panic!("Unexpected response: {:?}", response),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sync_peer_dead() {
+        let mut runtime = TestSyncRuntime::with_runtime(vec![1023], 1, TestRuntime::current());
+        let ping_interval = Duration::from_millis(200);
+        let config = Config {
+            neighbors_only: false,
+            peer_ping_interval: ping_interval,
+            max_missed_pings: 2,
+            ..Default::default()
+        };
+        let sync_send = runtime.spawn_sync_service_with_config(false, config).await;
+
+        let tx_seq = 0u64;
+        sync_send
+            .request(SyncRequest::SyncFile { tx_seq })
+            .await
+            .unwrap();
+
+        receive_dial(&mut runtime, &sync_send).await;
+
+        // the transport of peer dropped silently once chunks requested
+        match runtime.network_recv.recv().await {
+            Some(NetworkMessage::SendRequest {
+                peer_id,
+                request: network::Request::GetChunks(_),
+                ..
+            }) => assert_eq!(peer_id, runtime.init_peer_id),
+            msg => panic!("Not expected message: {:?}", msg),
+        }
+        let since = tokio::time::Instant::now();
+
+        // pinged until dead
+        let mut num_pings = 0;
+        loop {
+            match runtime.network_recv.recv().await {
+                Some(NetworkMessage::SendRequest {
+                    peer_id,
+                    request: network::Request::Ping,
+                    ..
+                }) => {
+                    assert_eq!(peer_id, runtime.init_peer_id);
+                    num_pings += 1;
+                }
+                Some(NetworkMessage::ReportPeer {
+                    peer_id, action, ..
+                }) => {
+                    assert_eq!(peer_id, runtime.init_peer_id);
+                    assert!(matches!(
+                        action,
+                        PeerAction::HighToleranceError | PeerAction::MidToleranceError
+                    ));
+                }
+                Some(NetworkMessage::DisconnectPeer { peer_id }) => {
+                    assert_eq!(peer_id, runtime.init_peer_id);
+                    break;
+                }
+                msg => panic!("Not expected message: {:?}", msg),
+            }
+        }
+        assert_eq!(num_pings, config.max_missed_pings);
+
+        // find other peers to download immediately
+        assert!(matches!(
+            runtime.network_recv.recv().await,
+            Some(NetworkMessage::Publish { .. })
+        ));
+
+        // bounded by `max_missed_pings + 1` ping intervals
+        let max_latency = ping_interval * (config.max_missed_pings as u32 + 1);
+        assert!(since.elapsed() <= max_latency);
+    }
+
     async fn receive_dial(runtime: &mut TestSyncRuntime, sync_send: &SyncSender) {
         if let Some(msg) = runtime.network_recv.recv().await {
             match msg {
@@ -1777,6 +3510,7 @@ mod tests {
                     peer_id,
                     request,
                     request_id,
+                    ..
                 } => {
                     assert_eq!(peer_id, init_peer_id);
 
@@ -1,5 +1,7 @@
 use rand::random;
-use shared_types::{compute_padded_chunk_size, ChunkArray, Transaction, CHUNK_SIZE};
+use shared_types::{
+    compute_padded_chunk_size, ChunkArray, Transaction, TransactionBuilder, CHUNK_SIZE,
+};
 use std::{cmp, sync::Arc};
 use storage::{
     log_store::{
@@ -62,15 +64,13 @@ fn generate_data(
     };
 
     let merkle = sub_merkle_tree(&data).unwrap();
-    let tx = Transaction {
-        stream_ids: vec![],
-        size: data_size as u64,
-        data_merkle_root: merkle.root().into(),
-        seq,
-        data: vec![],
-        start_entry_index: start_offset,
-        merkle_nodes: merkel_nodes,
-    };
+    let tx = TransactionBuilder::new(seq)
+        .size(data_size as u64)
+        .data_merkle_root(merkle.root().into())
+        .start_entry_index(start_offset)
+        .merkle_nodes(merkel_nodes)
+        .build()
+        .unwrap();
     store.put_tx(tx.clone()).unwrap();
     peer_store.put_tx(tx.clone()).unwrap();
     for start_index in (0..chunk_count).step_by(PORA_CHUNK_SIZE) {
@@ -0,0 +1,109 @@
+use anyhow::{bail, Result};
+use log_entry_sync::{LogSyncEvent, LogSyncManager};
+use parking_lot::Mutex;
+use shared_types::{compute_padded_chunk_size, Transaction, TransactionBuilder, CHUNK_SIZE};
+use std::sync::Arc;
+use storage::log_store::log_manager::{sub_merkle_tree, tx_subtree_root_list_padded};
+use storage::log_store::Store as LogStore;
+use tokio::sync::broadcast;
+
+/// Store and log sync events of a node that the flow submits txs to.
+pub(crate) struct FlowNode {
+    pub store: Arc<dyn LogStore>,
+    pub event_send: broadcast::Sender<LogSyncEvent>,
+}
+
+struct FlowState {
+    /// Flow entry index of the next tx, along with that before each submitted tx, for revert.
+    next_offsets: Vec<u64>,
+}
+
+/// Mock of the flow contract on L1 shared by all nodes of the cluster, which puts the submitted
+/// txs into the stores of all nodes as if the log entries were synced from the same chain.
+pub struct MockFlow {
+    nodes: Vec<FlowNode>,
+    state: Mutex<FlowState>,
+}
+
+impl MockFlow {
+    pub(crate) fn new(nodes: Vec<FlowNode>) -> Self {
+        Self {
+            nodes,
+            // The first entry of flow is reserved, see `create_2_store` of sync.
+            state: Mutex::new(FlowState {
+                next_offsets: vec![1],
+            }),
+        }
+    }
+
+    /// Number of txs submitted and not reverted.
+    pub fn num_txs(&self) -> u64 {
+        self.state.lock().next_offsets.len() as u64 - 1
+    }
+
+    /// Submits a file, which is padded with zeros to whole chunks, and returns the tx synced by
+    /// all nodes.
+    pub fn submit(&self, data: &[u8]) -> Result<Transaction> {
+        if data.is_empty() {
+            bail!("file is empty");
+        }
+
+        let mut state = self.state.lock();
+        let seq = state.next_offsets.len() as u64 - 1;
+        let offset = *state.next_offsets.last().expect("initialized");
+
+        let mut padded = data.to_vec();
+        padded.resize(data.len().div_ceil(CHUNK_SIZE) * CHUNK_SIZE, 0);
+
+        let merkle_nodes = tx_subtree_root_list_padded(&padded);
+        let first_tree_size = 1 << (merkle_nodes[0].0 - 1);
+        let start_entry_index = if offset % first_tree_size == 0 {
+            offset
+        } else {
+            (offset / first_tree_size + 1) * first_tree_size
+        };
+
+        let tx = TransactionBuilder::new(seq)
+            .size(data.len() as u64)
+            .data_merkle_root(sub_merkle_tree(&padded)?.root().into())
+            .start_entry_index(start_entry_index)
+            .merkle_nodes(merkle_nodes)
+            .build()?;
+
+        for node in &self.nodes {
+            node.store.put_tx(tx.clone())?;
+            // Restored in background if reverted before, the same as log sync.
+            LogSyncManager::notify_tx_synced(
+                node.store.clone(),
+                node.event_send.clone(),
+                tx.clone(),
+            );
+        }
+
+        let (padded_chunks, _) = compute_padded_chunk_size(data.len());
+        state
+            .next_offsets
+            .push(start_entry_index + padded_chunks as u64);
+
+        Ok(tx)
+    }
+
+    /// Reverts the txs from `tx_seq` on all nodes, as if reorged on L1.
+    pub fn revert(&self, tx_seq: u64) -> Result<()> {
+        let mut state = self.state.lock();
+        if tx_seq >= state.next_offsets.len() as u64 - 1 {
+            bail!("tx {} not submitted", tx_seq);
+        }
+
+        for node in &self.nodes {
+            let _ = node.event_send.send(LogSyncEvent::ReorgDetected { tx_seq });
+            // Same as log sync, which reverts to the tx before the first reverted one.
+            node.store.revert_to(tx_seq.wrapping_sub(1))?;
+            let _ = node.event_send.send(LogSyncEvent::Reverted { tx_seq });
+        }
+
+        state.next_offsets.truncate(tx_seq as usize + 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,536 @@
+//! In-process cluster of storage nodes for crate-level integration tests, so that scenarios
+//! across nodes, e.g. upload on a node and sync to another, reorg or prune, are scripted in Rust
+//! instead of the python test framework.
+//!
+//! All nodes use memory dbs and share a [`MockFlow`] instead of the flow contract on L1. Nodes
+//! talk over a memory network instead of libp2p, which routes the messages of all nodes in order.
+//! Nodes could instead use rocksdb under a directory by [`ClusterBuilder::with_db_dir`], so that
+//! a cluster built on the same directory after [`Cluster::shutdown`] restarts the nodes.
+//!
+//! Services of nodes are spawned on the tokio runtime that builds the cluster, so their timers
+//! follow the mock clock of tokio, e.g. in `#[tokio::test(start_paused = true)]`, where time only
+//! advances by [`Cluster::advance`] or once all nodes are idle. Scenarios with RPC servers should
+//! use the real clock, since the idle time waiting for sockets is skipped by the mock clock.
+
+#[macro_use]
+extern crate tracing;
+
+mod flow;
+mod memory_network;
+
+pub use flow::MockFlow;
+pub use memory_network::PeerReport;
+
+use crate::flow::FlowNode;
+use crate::memory_network::{MemoryNetwork, NetworkPeer};
+use anyhow::{anyhow, bail, Result};
+use chunk_pool::MemoryChunkPool;
+use file_location_cache::FileLocationCache;
+use hyper::header::{ACCEPT, CONTENT_TYPE};
+use hyper::{Body, Client, Request, StatusCode};
+use jsonrpsee::http_client::{HttpClient, HttpClientBuilder};
+use log_entry_sync::LogSyncEvent;
+use network::{new_network_channel, Multiaddr, NetworkGlobals, PeerId};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use shared_types::{ChunkArray, TxID};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use storage::log_store::log_manager::LogConfig;
+use storage::log_store::{LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
+use storage::LogManager;
+use storage_async::Store;
+use sync::{SyncMessage, SyncRequest, SyncResponse, SyncSender, SyncService};
+use task_executor::{ShutdownReason, TaskExecutor};
+use tokio::sync::{broadcast, oneshot, watch};
+
+/// Interval to poll the store when waiting for a tx.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Timeout to wait for the stores of nodes closed once the cluster is shut down.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct ClusterBuilder {
+    num_nodes: usize,
+    sync_config: sync::Config,
+    chunk_pool_config: chunk_pool::Config,
+    rpc_config: Option<rpc::RPCConfig>,
+    log_config: LogConfig,
+    db_dir: Option<PathBuf>,
+}
+
+impl Default for ClusterBuilder {
+    fn default() -> Self {
+        Self {
+            num_nodes: 2,
+            // Files are found from all nodes instead of neighbors only in the memory network.
+            sync_config: sync::Config {
+                neighbors_only: false,
+                ..Default::default()
+            },
+            chunk_pool_config: chunk_pool::Config {
+                write_window_size: 4,
+                max_cached_chunks_all: 4 * 1024 * 1024,
+                max_cached_chunks_per_file: 1024 * 1024,
+                max_spilled_chunks_all: 1024 * 1024,
+                max_writings: 16,
+                expiration_time_secs: 300,
+                shard_config: Default::default(),
+                spill_dir: None,
+                read_only: false,
+            },
+            rpc_config: Some(Default::default()),
+            log_config: LogConfig::default(),
+            db_dir: None,
+        }
+    }
+}
+
+impl ClusterBuilder {
+    pub fn with_num_nodes(mut self, num_nodes: usize) -> Self {
+        self.num_nodes = num_nodes;
+        self
+    }
+
+    pub fn with_sync_config(mut self, sync_config: sync::Config) -> Self {
+        self.sync_config = sync_config;
+        self
+    }
+
+    pub fn with_chunk_pool_config(mut self, chunk_pool_config: chunk_pool::Config) -> Self {
+        self.chunk_pool_config = chunk_pool_config;
+        self
+    }
+
+    pub fn with_log_config(mut self, log_config: LogConfig) -> Self {
+        self.log_config = log_config;
+        self
+    }
+
+    /// Stores the data of node `i` in rocksdb under `db_dir/node_i` instead of memory dbs, which
+    /// is reopened by the next cluster built on the same directory.
+    pub fn with_db_dir(mut self, db_dir: impl Into<PathBuf>) -> Self {
+        self.db_dir = Some(db_dir.into());
+        self
+    }
+
+    /// Serves RPC of every node on an unused local port with `rpc_config`, or disables RPC
+    /// servers if `None`. The listen addresses of `rpc_config` are ignored.
+    pub fn with_rpc_config(mut self, rpc_config: Option<rpc::RPCConfig>) -> Self {
+        self.rpc_config = rpc_config;
+        self
+    }
+
+    /// Starts all nodes on the current tokio runtime.
+    pub async fn build(self) -> Result<Cluster> {
+        if self.num_nodes == 0 {
+            bail!("cluster requires at least one node");
+        }
+
+        let (exit_signal, exit) = exit_future::signal();
+        let (shutdown_sender, shutdown_recv) = futures::channel::mpsc::channel(1);
+        let executor = TaskExecutor::new(tokio::runtime::Handle::current(), exit, shutdown_sender);
+
+        let mut nodes = Vec::with_capacity(self.num_nodes);
+        let mut network_recvs = Vec::with_capacity(self.num_nodes);
+        for index in 0..self.num_nodes {
+            let (node, network_recv) = self.start_node(index, &executor).await?;
+            network_recvs.push((node.peer_id, network_recv));
+            nodes.push(node);
+        }
+
+        let peers = nodes
+            .iter()
+            .map(|node| {
+                let peer = NetworkPeer {
+                    addr: node.addr.clone(),
+                    network_globals: node.network_globals.clone(),
+                    sync_send: node.sync_send.clone(),
+                    store: node.store.clone(),
+                    file_location_cache: node.file_location_cache.clone(),
+                    serve_partial_files: self.sync_config.serve_partial_files,
+                };
+                (node.peer_id, peer)
+            })
+            .collect::<HashMap<_, _>>();
+        let reports = Arc::new(Mutex::new(vec![]));
+        let banned = Arc::new(Mutex::new(HashSet::new()));
+        executor.spawn(
+            MemoryNetwork::new(peers, reports.clone(), banned.clone()).run(network_recvs),
+            "memory_network",
+        );
+
+        let flow = MockFlow::new(
+            nodes
+                .iter()
+                .map(|node| FlowNode {
+                    store: node.store.clone(),
+                    event_send: node.event_send.clone(),
+                })
+                .collect(),
+        );
+
+        info!(num_nodes = %nodes.len(), "Cluster started");
+
+        Ok(Cluster {
+            nodes,
+            flow,
+            reports,
+            banned,
+            executor,
+            _exit_signal: exit_signal,
+            _shutdown_recv: shutdown_recv,
+        })
+    }
+
+    async fn start_node(
+        &self,
+        index: usize,
+        executor: &TaskExecutor,
+    ) -> Result<(TestNode, network::NetworkReceiver)> {
+        let startup_phases = Arc::new(rpc::StartupPhases::default());
+        let store = Arc::new(match &self.db_dir {
+            Some(db_dir) => {
+                let dir = db_dir.join(format!("node_{}", index));
+                LogManager::rocksdb(self.log_config.clone(), dir.join("flow"), dir.join("data"))?
+            }
+            None => LogManager::memorydb(self.log_config.clone())?,
+        });
+        for (name, duration) in store.startup_phases() {
+            startup_phases.record(name, duration);
+        }
+        let async_store = Arc::new(Store::new(store.clone(), executor.clone()));
+        let network_globals = Arc::new(NetworkGlobals::new_test_globals());
+        let peer_id = network_globals.local_peer_id();
+        let addr: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", 10000 + index).parse()?;
+        let file_location_cache = Arc::new(FileLocationCache::default());
+        let (network_send, network_recv) = new_network_channel();
+        let (event_send, _) = broadcast::channel(1024);
+
+        let (chunk_pool, chunk_pool_handler) =
+            chunk_pool::unbounded(self.chunk_pool_config.clone(), async_store.clone());
+        executor.spawn(
+            chunk_pool_handler.run(executor.shutdown_token("chunk_pool")),
+            "chunk_pool_handler",
+        );
+        executor.spawn(
+            MemoryChunkPool::monitor_log_entry(chunk_pool.clone(), event_send.subscribe()),
+            "chunk_pool_log_monitor",
+        );
+
+        // Txs are synced by the mock flow at once, so log sync never catches up.
+        let (_, catch_up_end_recv) = oneshot::channel();
+        let sync_send = SyncService::spawn_with_config(
+            self.sync_config,
+            executor.clone(),
+            network_send.clone(),
+            store.clone(),
+            file_location_cache.clone(),
+            Default::default(),
+            event_send.subscribe(),
+            catch_up_end_recv,
+            watch::channel(self.sync_config.dynamic()).1,
+            executor.shutdown_token("sync"),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to start sync service: {:?}", e))?;
+
+        let mut node = TestNode {
+            index,
+            peer_id,
+            addr,
+            store,
+            async_store,
+            file_location_cache,
+            network_globals,
+            sync_send,
+            chunk_pool,
+            startup_phases,
+            jobs: None,
+            event_send,
+            rpc_addr: None,
+        };
+
+        if let Some(rpc_config) = &self.rpc_config {
+            let port = unused_port::unused_tcp_port().map_err(|e| anyhow!(e))?;
+            let listen_address = SocketAddr::from(([127, 0, 0, 1], port));
+            let jobs = Arc::new(
+                rpc::JobManager::new(
+                    node.async_store.clone(),
+                    executor.clone(),
+                    rpc_config.job_config(),
+                )
+                .await?,
+            );
+            jobs.resume().await?;
+            node.jobs = Some(jobs.clone());
+            let ctx = node.rpc_context(
+                rpc::RPCConfig {
+                    listen_address,
+                    listen_address_admin: listen_address,
+                    ..rpc_config.clone()
+                },
+                network_send,
+                executor,
+                jobs,
+            );
+            let shutdown = executor.shutdown_token("rpc");
+            let (rpc_handle, _) = rpc::run_server(ctx, shutdown.signal())
+                .map_err(|e| anyhow!("Unable to start HTTP RPC server: {:?}", e))?;
+            executor.spawn(
+                async move {
+                    rpc_handle.await;
+                    shutdown.ack();
+                },
+                "rpc",
+            );
+            node.rpc_addr = Some(listen_address);
+        }
+
+        Ok((node, network_recv))
+    }
+}
+
+/// Nodes started in process, which are stopped once dropped.
+pub struct Cluster {
+    pub nodes: Vec<TestNode>,
+    /// Flow contract shared by all nodes.
+    pub flow: MockFlow,
+    reports: Arc<Mutex<Vec<PeerReport>>>,
+    banned: Arc<Mutex<HashSet<(PeerId, PeerId)>>>,
+    executor: TaskExecutor,
+    _exit_signal: exit_future::Signal,
+    _shutdown_recv: futures::channel::mpsc::Receiver<ShutdownReason>,
+}
+
+impl Cluster {
+    pub fn builder() -> ClusterBuilder {
+        ClusterBuilder::default()
+    }
+
+    pub fn node(&self, index: usize) -> &TestNode {
+        &self.nodes[index]
+    }
+
+    pub fn executor(&self) -> &TaskExecutor {
+        &self.executor
+    }
+
+    /// Returns the peers reported by nodes so far, in the order reported.
+    pub fn peer_reports(&self) -> Vec<PeerReport> {
+        self.reports.lock().clone()
+    }
+
+    /// Returns whether the node banned the peer, once reported enough.
+    pub fn is_banned(&self, node: usize, peer_id: PeerId) -> bool {
+        self.banned
+            .lock()
+            .contains(&(self.nodes[node].peer_id, peer_id))
+    }
+
+    /// Stops all nodes, and waits until the stores of nodes are closed, e.g. to restart the nodes
+    /// by a cluster built on the same db directory. RPC clients of nodes should be dropped
+    /// before, since their connections keep the RPC servers alive.
+    pub async fn shutdown(self) -> Result<()> {
+        let stores: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|node| Arc::downgrade(&node.store))
+            .collect();
+        drop(self);
+
+        let wait = async {
+            while stores.iter().any(|store| store.strong_count() > 0) {
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        };
+        tokio::time::timeout(SHUTDOWN_TIMEOUT, wait)
+            .await
+            .map_err(|_| anyhow!("stores of nodes not closed in {:?}", SHUTDOWN_TIMEOUT))?;
+        info!("Cluster shut down");
+        Ok(())
+    }
+
+    /// Advances the mock clock, which requires the clock of the runtime paused.
+    pub async fn advance(&self, duration: Duration) {
+        tokio::time::advance(duration).await;
+    }
+}
+
+/// Response of [`TestNode::rpc_call_raw`].
+pub struct RawResponse {
+    pub status: StatusCode,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// Handles of a node in the cluster.
+pub struct TestNode {
+    pub index: usize,
+    pub peer_id: PeerId,
+    /// Address that the node is announced at in the memory network.
+    pub addr: Multiaddr,
+    pub store: Arc<LogManager>,
+    pub async_store: Arc<Store>,
+    pub file_location_cache: Arc<FileLocationCache>,
+    pub network_globals: Arc<NetworkGlobals>,
+    pub sync_send: SyncSender,
+    pub chunk_pool: Arc<MemoryChunkPool>,
+    /// Startup phases of the node, of the store only since the network is in memory.
+    pub startup_phases: Arc<rpc::StartupPhases>,
+    /// Admin jobs served by RPC, or `None` if RPC disabled.
+    pub jobs: Option<Arc<rpc::JobManager>>,
+    event_send: broadcast::Sender<LogSyncEvent>,
+    rpc_addr: Option<SocketAddr>,
+}
+
+impl TestNode {
+    /// Returns the URL of the RPC server, or `None` if RPC disabled.
+    pub fn rpc_url(&self) -> Option<String> {
+        self.rpc_addr.map(|addr| format!("http://{}", addr))
+    }
+
+    /// Returns a client of all RPC namespaces, e.g. `ZgsRPCClient` and `ZgsAdminRpcClient`.
+    pub fn rpc_client(&self) -> Result<HttpClient> {
+        let url = self.rpc_url().ok_or_else(|| anyhow!("RPC disabled"))?;
+        Ok(HttpClientBuilder::default().build(url)?)
+    }
+
+    /// Calls a method over HTTP, in SSZ instead of JSON if `binary`, and returns the raw
+    /// response, e.g. to measure the response size.
+    pub async fn rpc_call_raw(
+        &self,
+        method: &str,
+        params: Value,
+        binary: bool,
+    ) -> Result<RawResponse> {
+        let url = self.rpc_url().ok_or_else(|| anyhow!("RPC disabled"))?;
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let mut request = Request::post(url).header(CONTENT_TYPE, "application/json");
+        if binary {
+            request = request.header(ACCEPT, rpc::BINARY_CONTENT_TYPE);
+        }
+
+        let response = Client::new()
+            .request(request.body(Body::from(body.to_string()))?)
+            .await?;
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        let body = hyper::body::to_bytes(response.into_body()).await?.to_vec();
+
+        Ok(RawResponse {
+            status,
+            content_type,
+            body,
+        })
+    }
+
+    /// Notifies sync of the files announced to peers, as if published by router, since the
+    /// memory network has no router. Messages to sync are handled in order, so the files are
+    /// cached before any later message, e.g. the announcements requested by a peer.
+    pub fn announce_files(&self, tx_ids: Vec<TxID>) -> Result<()> {
+        self.sync_send
+            .notify(SyncMessage::FilesAnnounced { tx_ids })
+            .map_err(|e| anyhow!("Failed to notify sync: {:?}", e))
+    }
+
+    /// Stores the whole file of the tx and finalizes it, as if uploaded to this node.
+    pub fn put_file(&self, tx_seq: u64, data: &[u8]) -> Result<()> {
+        let chunks = ChunkArray {
+            data: data.to_vec(),
+            start_index: 0,
+        };
+        self.store.put_chunks(tx_seq, chunks)?;
+        self.store.finalize_tx(tx_seq)?;
+        Ok(())
+    }
+
+    /// Starts to sync the file of the tx from peers, as requested by the admin RPC.
+    pub async fn sync_file(&self, tx_seq: u64) -> Result<()> {
+        let response = self
+            .sync_send
+            .request(SyncRequest::SyncFile { tx_seq })
+            .await
+            .map_err(|e| anyhow!("Failed to request sync: {:?}", e))?;
+        match response {
+            SyncResponse::SyncFile { err } if err.is_empty() => Ok(()),
+            SyncResponse::SyncFile { err } => bail!("Failed to sync tx {}: {}", tx_seq, err),
+            _ => bail!("Unexpected sync response: {:?}", response),
+        }
+    }
+
+    /// Subscribes the log sync events delivered by the mock flow.
+    pub fn subscribe_log_sync(&self) -> broadcast::Receiver<LogSyncEvent> {
+        self.event_send.subscribe()
+    }
+
+    /// Waits until the tx is finalized in the store of this node.
+    pub async fn wait_for_tx_finalized(&self, tx_seq: u64, timeout: Duration) -> Result<()> {
+        let wait = async {
+            while !self.store.check_tx_completed(tx_seq)? {
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+            Ok(())
+        };
+
+        match tokio::time::timeout(timeout, wait).await {
+            Ok(result) => result,
+            Err(_) => bail!(
+                "tx {} not finalized on node {} in {:?}",
+                tx_seq,
+                self.index,
+                timeout
+            ),
+        }
+    }
+
+    fn rpc_context(
+        &self,
+        config: rpc::RPCConfig,
+        network_send: network::NetworkSender,
+        executor: &TaskExecutor,
+        jobs: Arc<rpc::JobManager>,
+    ) -> rpc::Context {
+        let segment_cache = Arc::new(rpc::SegmentCache::new(config.segment_cache_max_bytes));
+        let upload_sessions = Arc::new(rpc::UploadSessions::new(
+            Duration::from_secs(config.upload_session_ttl_secs),
+            config.max_upload_sessions_per_ip,
+        ));
+        let proof_verifier = Arc::new(rpc::ProofVerifier::new(
+            config.proof_verify_threads,
+            config.max_queued_proof_verifications,
+        ));
+        let replica_query_limiter = Arc::new(rpc::ReplicaQueryLimiter::new(
+            config.max_replica_queries,
+            Duration::from_millis(config.replica_query_wait_ms),
+        ));
+        rpc::Context {
+            config,
+            file_location_cache: self.file_location_cache.clone(),
+            network_globals: self.network_globals.clone(),
+            network_send,
+            sync_send: self.sync_send.clone(),
+            chunk_pool: self.chunk_pool.clone(),
+            log_store: self.async_store.clone(),
+            shutdown_sender: executor.shutdown_sender(),
+            mine_service_sender: None,
+            log_sync: None,
+            known_peers: None,
+            config_reloader: None,
+            log_filter: None,
+            segment_cache,
+            upload_sessions,
+            proof_verifier,
+            replica_query_limiter,
+            startup_phases: self.startup_phases.clone(),
+            jobs,
+            read_only: false,
+        }
+    }
+}
@@ -0,0 +1,598 @@
+use file_location_cache::test_util::AnnounceFileBuilder;
+use file_location_cache::FileLocationCache;
+use network::libp2p::core::connection::ConnectionId;
+use network::libp2p::swarm::DialError;
+use network::peer_manager::peerdb::score::Score;
+use network::rpc::{RPCError, SubstreamId};
+use network::types::{AnnounceChunks, FindChunks};
+use network::{
+    Multiaddr, NetworkGlobals, NetworkMessage, NetworkReceiver, PeerAction, PeerId, PeerRequestId,
+    PubsubMessage, Request, RequestId, Response, SyncId,
+};
+use parking_lot::Mutex;
+use shared_types::{bytes_to_chunks, ShardedFile, TxID};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+use storage::log_store::{LogStoreRead, Store as LogStore};
+use sync::{SyncMessage, SyncSender};
+use tokio::sync::mpsc;
+
+/// Peer of the memory network, which is a node of the cluster.
+pub(crate) struct NetworkPeer {
+    pub addr: Multiaddr,
+    /// Peers of the node, whose connection state is updated by the memory network as well.
+    pub network_globals: Arc<NetworkGlobals>,
+    pub sync_send: SyncSender,
+    pub store: Arc<dyn LogStore>,
+    pub file_location_cache: Arc<FileLocationCache>,
+    /// Whether the peer answers queries of files not finalized yet, see `serve_partial_files`
+    /// of the sync config.
+    pub serve_partial_files: bool,
+}
+
+impl NetworkPeer {
+    /// Returns whether the peer answers the query of the file, i.e. finalized locally, or the
+    /// leading chunks stored if serving partial files.
+    fn has_file(&self, tx_id: TxID) -> bool {
+        let tx = match self.store.get_tx_by_seq_number(tx_id.seq) {
+            Ok(Some(tx)) if tx.id() == tx_id => tx,
+            _ => return false,
+        };
+
+        if matches!(self.store.check_tx_completed(tx_id.seq), Ok(true)) {
+            return true;
+        }
+
+        let index_end = std::cmp::min(bytes_to_chunks(tx.size as usize), PORA_CHUNK_SIZE);
+        self.serve_partial_files && self.has_chunks(tx_id, 0, index_end as u64)
+    }
+
+    /// Returns whether the peer answers the query of the chunks.
+    fn has_chunks(&self, tx_id: TxID, index_start: u64, index_end: u64) -> bool {
+        let completed = matches!(self.store.check_tx_completed(tx_id.seq), Ok(true));
+        (completed || self.serve_partial_files)
+            && matches!(
+                self.store.has_chunks_by_tx_and_index_range(
+                    tx_id.seq,
+                    index_start as usize,
+                    index_end as usize
+                ),
+                Ok(true)
+            )
+    }
+}
+
+/// Peer reported by a node, e.g. penalized or banned by sync.
+#[derive(Clone, Debug)]
+pub struct PeerReport {
+    /// The node that reported the peer.
+    pub from: PeerId,
+    pub peer_id: PeerId,
+    pub action: PeerAction,
+    pub msg: &'static str,
+}
+
+/// Network of the in-process nodes, which delivers the messages that nodes send to the network
+/// service as if they were delivered by libp2p between the nodes.
+///
+/// All messages are routed by a single task in the order sent, so that scenarios are replayed
+/// the same way every time. Only the messages that sync needs are routed, e.g. dials, chunk
+/// requests, announcement exchanges, pings, `FindFile`, `AskFile` and `FindChunks`, while the
+/// others are dropped. Peers reported by nodes are recorded, so that scenarios could assert
+/// honest peers never penalized.
+///
+/// Reported peers are scored as the peer manager of libp2p does. Once banned, the two peers are
+/// disconnected, and never connect or talk to each other again. Connections are recorded in the
+/// peers of the network globals of nodes as well, e.g. for the admin RPCs of peers.
+pub(crate) struct MemoryNetwork {
+    peers: HashMap<PeerId, NetworkPeer>,
+    reports: Arc<Mutex<Vec<PeerReport>>>,
+    /// Score of the peers reported, by the reporting node and the reported peer.
+    scores: HashMap<(PeerId, PeerId), Score>,
+    /// Peers banned, by the banning node and the banned peer.
+    banned: Arc<Mutex<HashSet<(PeerId, PeerId)>>>,
+    /// Requester and its request id of the requests being served, by the serving peer and the
+    /// id generated for the serving peer.
+    pending: HashMap<(PeerId, PeerRequestId), (PeerId, SyncId)>,
+    next_request_id: usize,
+}
+
+impl MemoryNetwork {
+    pub fn new(
+        peers: HashMap<PeerId, NetworkPeer>,
+        reports: Arc<Mutex<Vec<PeerReport>>>,
+        banned: Arc<Mutex<HashSet<(PeerId, PeerId)>>>,
+    ) -> Self {
+        Self {
+            peers,
+            reports,
+            scores: Default::default(),
+            banned,
+            pending: Default::default(),
+            next_request_id: 0,
+        }
+    }
+
+    /// Routes the messages sent by nodes until all nodes stopped.
+    pub async fn run(mut self, receivers: Vec<(PeerId, NetworkReceiver)>) {
+        let (send, mut recv) = mpsc::unbounded_channel();
+        for (peer_id, mut receiver) in receivers {
+            let send = send.clone();
+            tokio::spawn(async move {
+                while let Some(msg) = receiver.recv().await {
+                    if send.send((peer_id, msg)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(send);
+
+        while let Some((from, msg)) = recv.recv().await {
+            self.on_message(from, msg);
+        }
+    }
+
+    fn on_message(&mut self, from: PeerId, msg: NetworkMessage) {
+        trace!(%from, ?msg, "Route network message");
+
+        match msg {
+            NetworkMessage::DialPeer { peer_id, .. } => {
+                self.connect(from, peer_id);
+            }
+            NetworkMessage::ConnectPeer {
+                peer_id, sender, ..
+            } => {
+                let result = if self.connect(from, peer_id) {
+                    Ok(())
+                } else {
+                    Err(format!("unknown peer {}", peer_id))
+                };
+                let _ = sender.send(result);
+            }
+            NetworkMessage::DisconnectPeer { peer_id }
+            | NetworkMessage::GoodbyePeer { peer_id, .. } => self.disconnect(from, peer_id),
+            NetworkMessage::SendRequest {
+                peer_id,
+                request,
+                request_id: RequestId::Sync(_, sync_id),
+                ..
+            } => self.on_request(from, peer_id, request, sync_id),
+            NetworkMessage::SendResponse {
+                peer_id,
+                response,
+                id,
+            } => self.on_response(from, peer_id, id, Ok(response)),
+            NetworkMessage::SendErrorResponse {
+                peer_id,
+                id,
+                error,
+                reason,
+            } => self.on_response(
+                from,
+                peer_id,
+                id,
+                Err(RPCError::ErrorResponse(error, reason)),
+            ),
+            NetworkMessage::ReportPeer {
+                peer_id,
+                action,
+                msg,
+                ..
+            } => self.on_report(from, peer_id, action, msg),
+            NetworkMessage::Publish { messages } => {
+                for msg in messages {
+                    match msg {
+                        PubsubMessage::FindFile(msg) => self.on_find_file(from, msg.inner.tx_id),
+                        PubsubMessage::AskFile(msg) => self.on_ask_file(from, msg.inner.tx_id),
+                        PubsubMessage::FindChunks(msg) => self.on_find_chunks(from, msg.inner),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Connects the two peers, or notifies the dial failure if the dialed peer is unknown or
+    /// banned.
+    fn connect(&self, from: PeerId, peer_id: PeerId) -> bool {
+        let err = if !self.peers.contains_key(&peer_id) {
+            Some(DialError::NoAddresses)
+        } else if self.is_banned(from, peer_id) {
+            Some(DialError::Banned)
+        } else {
+            None
+        };
+
+        if let Some(err) = err {
+            self.notify(&from, SyncMessage::DialFailed { peer_id, err });
+            return false;
+        }
+
+        for (local, remote, outgoing) in [(from, peer_id, true), (peer_id, from, false)] {
+            if let (Some(peer), Some(addr)) = (self.peers.get(&local), self.addr(&remote)) {
+                peer.network_globals
+                    .peers
+                    .write()
+                    .__connect_testing_only(&remote, addr, outgoing);
+            }
+        }
+
+        self.notify(&from, SyncMessage::PeerConnected { peer_id });
+        self.notify(&peer_id, SyncMessage::PeerConnected { peer_id: from });
+        true
+    }
+
+    fn addr(&self, peer_id: &PeerId) -> Option<Multiaddr> {
+        self.peers.get(peer_id).map(|peer| peer.addr.clone())
+    }
+
+    /// Records the reported peer, and bans it once the score is low enough.
+    fn on_report(&mut self, from: PeerId, peer_id: PeerId, action: PeerAction, msg: &'static str) {
+        self.reports.lock().push(PeerReport {
+            from,
+            peer_id,
+            action,
+            msg,
+        });
+
+        let score = self.scores.entry((from, peer_id)).or_default();
+        score.apply_peer_action(action);
+        if score.is_banned() && self.banned.lock().insert((from, peer_id)) {
+            debug!(%from, %peer_id, "Peer banned");
+            self.disconnect(from, peer_id);
+        }
+    }
+
+    /// Disconnects the two peers, and fails the requests in flight between them.
+    fn disconnect(&mut self, peer1: PeerId, peer2: PeerId) {
+        let failed = self
+            .pending
+            .iter()
+            .filter(|((to, _), (from, _))| {
+                (*from, *to) == (peer1, peer2) || (*from, *to) == (peer2, peer1)
+            })
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        for key in failed {
+            if let Some((requester, request_id)) = self.pending.remove(&key) {
+                self.notify(
+                    &requester,
+                    SyncMessage::RpcError {
+                        peer_id: key.0,
+                        request_id,
+                        error: RPCError::Disconnected,
+                    },
+                );
+            }
+        }
+
+        for (local, remote) in [(peer1, peer2), (peer2, peer1)] {
+            if let Some(peer) = self.peers.get(&local) {
+                peer.network_globals
+                    .peers
+                    .write()
+                    .__disconnect_testing_only(&remote);
+            }
+        }
+
+        self.notify(&peer1, SyncMessage::PeerDisconnected { peer_id: peer2 });
+        self.notify(&peer2, SyncMessage::PeerDisconnected { peer_id: peer1 });
+    }
+
+    /// Returns the other peers that talk to the peer, i.e. not banned either way.
+    fn connected_peers(&self, peer_id: PeerId) -> impl Iterator<Item = (&PeerId, &NetworkPeer)> {
+        self.peers
+            .iter()
+            .filter(move |(id, _)| **id != peer_id && !self.is_banned(peer_id, **id))
+    }
+
+    /// Returns whether either of the two peers banned the other.
+    fn is_banned(&self, peer1: PeerId, peer2: PeerId) -> bool {
+        let banned = self.banned.lock();
+        banned.contains(&(peer1, peer2)) || banned.contains(&(peer2, peer1))
+    }
+
+    fn on_request(&mut self, from: PeerId, to: PeerId, request: Request, sync_id: SyncId) {
+        if !self.peers.contains_key(&to) || self.is_banned(from, to) {
+            self.notify(
+                &from,
+                SyncMessage::RpcError {
+                    peer_id: to,
+                    request_id: sync_id,
+                    error: RPCError::Disconnected,
+                },
+            );
+            return;
+        }
+
+        let request_id = (
+            ConnectionId::new(self.next_request_id),
+            SubstreamId(self.next_request_id),
+        );
+        self.next_request_id += 1;
+
+        let msg = match request {
+            Request::GetChunks(request) => SyncMessage::RequestChunks {
+                peer_id: from,
+                request_id,
+                request,
+            },
+            Request::QueryFileStatus(request) => SyncMessage::QueryFileStatus {
+                peer_id: from,
+                request_id,
+                request,
+            },
+            Request::GetAnnouncements(request) => SyncMessage::GetAnnouncements {
+                peer_id: from,
+                request_id,
+                request,
+            },
+            // Pings are always responded by the network service.
+            Request::Ping => {
+                self.notify(&from, SyncMessage::Pong { peer_id: to });
+                return;
+            }
+            _ => return,
+        };
+
+        self.pending.insert((to, request_id), (from, sync_id));
+        self.notify(&to, msg);
+    }
+
+    /// Delivers the response of `from` to the requester, or an RPC error if the peer responded
+    /// with an error.
+    fn on_response(
+        &mut self,
+        from: PeerId,
+        to: PeerId,
+        id: PeerRequestId,
+        response: Result<Response, RPCError>,
+    ) {
+        let (requester, request_id) = match self.pending.remove(&(from, id)) {
+            Some(pending) if pending.0 == to => pending,
+            _ => return,
+        };
+
+        let msg = match response {
+            Ok(Response::Chunks(response)) => SyncMessage::ChunksResponse {
+                peer_id: from,
+                request_id,
+                response,
+            },
+            Ok(Response::FileStatus(response)) => SyncMessage::FileStatusResponse {
+                peer_id: from,
+                request_id,
+                response,
+            },
+            Ok(Response::Announcements(response)) => SyncMessage::AnnouncementsResponse {
+                peer_id: from,
+                request_id,
+                response,
+            },
+            Ok(_) => return,
+            Err(error) => SyncMessage::RpcError {
+                peer_id: from,
+                request_id,
+                error,
+            },
+        };
+
+        self.notify(&requester, msg);
+    }
+
+    /// Announces the file to the finder from all other peers that have it finalized, as if the
+    /// peers responded to the `FindFile` gossip.
+    fn on_find_file(&self, from: PeerId, tx_id: TxID) {
+        let finder = match self.peers.get(&from) {
+            Some(finder) => finder,
+            None => return,
+        };
+
+        for (peer_id, peer) in self.connected_peers(from) {
+            let has_file = match peer.store.get_tx_by_seq_number(tx_id.seq) {
+                Ok(Some(tx)) => {
+                    tx.id() == tx_id && matches!(peer.store.check_tx_completed(tx_id.seq), Ok(true))
+                }
+                _ => false,
+            };
+            if !has_file {
+                continue;
+            }
+
+            let announcement = AnnounceFileBuilder::default()
+                .with_tx_id(tx_id)
+                .with_peer_id(*peer_id)
+                .build();
+            finder.file_location_cache.insert(announcement);
+            finder
+                .file_location_cache
+                .insert_peer_config(*peer_id, peer.store.get_shard_config());
+
+            self.notify(
+                &from,
+                SyncMessage::AnnounceFileGossip {
+                    tx_id,
+                    peer_id: *peer_id,
+                    addr: peer.addr.clone(),
+                },
+            );
+        }
+    }
+
+    /// Answers the file to the asker from all other peers that have it, as if the peers
+    /// responded to the `AskFile` gossip of neighbors.
+    fn on_ask_file(&self, from: PeerId, tx_id: TxID) {
+        for (peer_id, peer) in self.connected_peers(from) {
+            if !peer.has_file(tx_id) {
+                continue;
+            }
+
+            self.notify(
+                &from,
+                SyncMessage::AnswerFile {
+                    peer_id: *peer_id,
+                    file: ShardedFile {
+                        tx_id,
+                        shard_config: peer.store.get_shard_config().into(),
+                    },
+                },
+            );
+        }
+    }
+
+    /// Announces the chunks to the finder from all other peers that have them, as if the peers
+    /// responded to the `FindChunks` gossip.
+    fn on_find_chunks(&self, from: PeerId, msg: FindChunks) {
+        let finder = match self.peers.get(&from) {
+            Some(finder) => finder,
+            None => return,
+        };
+
+        for (peer_id, peer) in self.connected_peers(from) {
+            let has_chunks = match peer.store.get_tx_by_seq_number(msg.tx_id.seq) {
+                Ok(Some(tx)) => {
+                    tx.id() == msg.tx_id
+                        && peer.has_chunks(msg.tx_id, msg.index_start, msg.index_end)
+                }
+                _ => false,
+            };
+            if !has_chunks {
+                continue;
+            }
+
+            finder
+                .file_location_cache
+                .insert_peer_config(*peer_id, peer.store.get_shard_config());
+
+            self.notify(
+                &from,
+                SyncMessage::AnnounceChunksGossip {
+                    msg: AnnounceChunks {
+                        tx_id: msg.tx_id,
+                        index_start: msg.index_start,
+                        index_end: msg.index_end,
+                        peer_id: (*peer_id).into(),
+                        at: peer.addr.clone().into(),
+                    },
+                },
+            );
+        }
+    }
+
+    fn notify(&self, peer_id: &PeerId, msg: SyncMessage) {
+        if let Some(peer) = self.peers.get(peer_id) {
+            let _ = peer.sync_send.notify(msg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use channel::Message::Notification;
+    use network::ReportSource;
+    use std::time::Instant;
+    use storage::log_store::log_manager::LogConfig;
+    use storage::LogManager;
+    use sync::SyncReceiver;
+
+    fn new_peer(index: usize) -> (PeerId, NetworkPeer, SyncReceiver) {
+        let (sync_send, sync_recv) = channel::Channel::unbounded("test");
+        let peer = NetworkPeer {
+            addr: format!("/ip4/127.0.0.1/tcp/{}", 10000 + index)
+                .parse()
+                .unwrap(),
+            network_globals: Arc::new(NetworkGlobals::new_test_globals()),
+            sync_send,
+            store: Arc::new(LogManager::memorydb(LogConfig::default()).unwrap()),
+            file_location_cache: Default::default(),
+            serve_partial_files: false,
+        };
+        (PeerId::random(), peer, sync_recv)
+    }
+
+    fn report(peer_id: PeerId, action: PeerAction) -> NetworkMessage {
+        NetworkMessage::ReportPeer {
+            peer_id,
+            action,
+            source: ReportSource::SyncService,
+            msg: "test",
+        }
+    }
+
+    fn next_notification(recv: &mut SyncReceiver) -> SyncMessage {
+        match recv.try_recv() {
+            Ok(Notification(msg)) => msg,
+            Ok(_) => panic!("Unexpected sync message type received"),
+            Err(e) => panic!("No sync message received: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_ban_peer() {
+        let (alice, alice_peer, mut alice_recv) = new_peer(0);
+        let (bob, bob_peer, mut bob_recv) = new_peer(1);
+        let banned = Arc::new(Mutex::new(HashSet::new()));
+        let mut network = MemoryNetwork::new(
+            HashMap::from([(alice, alice_peer), (bob, bob_peer)]),
+            Default::default(),
+            banned.clone(),
+        );
+
+        // penalized only
+        network.on_message(alice, report(bob, PeerAction::MidToleranceError));
+        assert!(banned.lock().is_empty());
+        assert!(alice_recv.try_recv().is_err());
+
+        // disconnected once banned
+        network.on_message(alice, report(bob, PeerAction::Fatal));
+        assert!(banned.lock().contains(&(alice, bob)));
+        for (recv, peer) in [(&mut alice_recv, bob), (&mut bob_recv, alice)] {
+            match next_notification(recv) {
+                SyncMessage::PeerDisconnected { peer_id } => assert_eq!(peer_id, peer),
+                msg => panic!("Unexpected sync message: {:?}", msg),
+            }
+        }
+
+        // never connected again by either peer
+        let address = network.peers[&alice].addr.clone();
+        network.on_message(
+            bob,
+            NetworkMessage::DialPeer {
+                address,
+                peer_id: alice,
+            },
+        );
+        match next_notification(&mut bob_recv) {
+            SyncMessage::DialFailed {
+                peer_id,
+                err: DialError::Banned,
+            } => assert_eq!(peer_id, alice),
+            msg => panic!("Unexpected sync message: {:?}", msg),
+        }
+        assert!(alice_recv.try_recv().is_err());
+
+        // requests fail as disconnected
+        network.on_message(
+            alice,
+            NetworkMessage::SendRequest {
+                peer_id: bob,
+                request: Request::Ping,
+                request_id: RequestId::Sync(Instant::now(), SyncId::Ping),
+                span: tracing::Span::none(),
+            },
+        );
+        match next_notification(&mut alice_recv) {
+            SyncMessage::RpcError {
+                peer_id,
+                error: RPCError::Disconnected,
+                ..
+            } => assert_eq!(peer_id, bob),
+            msg => panic!("Unexpected sync message: {:?}", msg),
+        }
+        assert!(bob_recv.try_recv().is_err());
+    }
+}
@@ -0,0 +1,336 @@
+use rand::random;
+use rpc::types::{FlowBatchMismatch, JobInfo, JobParams, JobResult, JobState};
+use rpc::{JobManager, ZgsAdminRpcClient};
+use shared_types::{ChunkArray, Transaction, TxSeqOrRoot, CHUNK_SIZE};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use storage::log_store::job_store::{AdminJob, JOB_STATE_RUNNING};
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+use storage::log_store::{LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
+use test_cluster::Cluster;
+
+/// Chunks of the file, which takes several steps to scrub or export.
+const NUM_CHUNKS: usize = 64 * PORA_CHUNK_SIZE;
+/// Pause between steps, so that a job stays after its first step until cancelled or the
+/// cluster shuts down.
+const PARKED_STEP_INTERVAL_MS: u64 = 3_600_000;
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Builds a node on the db under `dir`, which exports files into `dir/export`.
+async fn build_cluster(dir: &Path, job_step_interval_ms: u64) -> Cluster {
+    Cluster::builder()
+        .with_num_nodes(1)
+        .with_db_dir(dir.join("db"))
+        .with_rpc_config(Some(rpc::RPCConfig {
+            export_dir: Some(dir.join("export")),
+            job_step_interval_ms,
+            max_finished_jobs: 1,
+            ..Default::default()
+        }))
+        .build()
+        .await
+        .unwrap()
+}
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("zgs_jobs_test_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("export")).unwrap();
+    dir
+}
+
+fn submit_finalized_file(cluster: &Cluster) -> (Transaction, Vec<u8>) {
+    let data: Vec<u8> = (0..NUM_CHUNKS * CHUNK_SIZE).map(|_| random()).collect();
+    let tx = cluster.flow.submit(&data).unwrap();
+    let store = &cluster.node(0).store;
+    store
+        .put_chunks(
+            tx.seq,
+            ChunkArray {
+                data: data.clone(),
+                start_index: 0,
+            },
+        )
+        .unwrap();
+    store.finalize_tx(tx.seq).unwrap();
+    (tx, data)
+}
+
+/// Waits until the job is accepted by `until`, which is checked once any job is updated.
+async fn wait_for_job(
+    jobs: &JobManager,
+    id: u64,
+    mut until: impl FnMut(&JobInfo) -> bool,
+) -> JobInfo {
+    let mut updates = jobs.subscribe();
+    let wait = async {
+        loop {
+            let job = jobs.get(id).await.unwrap().unwrap();
+            if until(&job) {
+                return job;
+            }
+            updates.changed().await.unwrap();
+        }
+    };
+    tokio::time::timeout(TIMEOUT, wait)
+        .await
+        .unwrap_or_else(|_| panic!("job {} not updated in time", id))
+}
+
+/// Shuts down the cluster once the job made progress, and restarts the node on the same db
+/// without pause between steps. Returns the job persisted before shutdown.
+async fn restart_mid_job(cluster: Cluster, dir: &Path, id: u64) -> (JobInfo, Cluster) {
+    let jobs = cluster.node(0).jobs.clone().unwrap();
+    let parked = wait_for_job(&jobs, id, |job| job.progress_done > 0).await;
+    assert_eq!(parked.state, JobState::Running);
+    assert!(parked.progress_done < parked.progress_total);
+    drop(jobs);
+    cluster.shutdown().await.unwrap();
+
+    let restarted = build_cluster(dir, 0).await;
+    (parked, restarted)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_scrub_job_resumed_after_restart() {
+    let dir = test_dir("scrub");
+    let cluster = build_cluster(&dir, PARKED_STEP_INTERVAL_MS).await;
+    let (tx, _) = submit_finalized_file(&cluster);
+    let node = cluster.node(0);
+    let client = node.rpc_client().unwrap();
+
+    // the flow is scrubbed to the end by default
+    let flow_length = node.store.get_context().unwrap().1;
+    let default_range = client
+        .start_job(JobParams::Scrub {
+            start_chunk: 0,
+            end_chunk: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        default_range.params,
+        JobParams::Scrub {
+            start_chunk: 0,
+            end_chunk: Some(flow_length),
+        }
+    );
+    // cancelled at once instead of after the pause between steps
+    assert!(client.cancel_job(default_range.id).await.unwrap());
+    let jobs = node.jobs.as_ref().unwrap();
+    wait_for_job(jobs, default_range.id, |job| {
+        job.state == JobState::Cancelled
+    })
+    .await;
+
+    // the batch lost is only scrubbed after restart
+    let start_chunk = tx.start_entry_index;
+    let lost_batch = start_chunk / PORA_CHUNK_SIZE as u64 + 60;
+    node.store.remove_chunks_batch(&[lost_batch]).unwrap();
+    let started = client
+        .start_job(JobParams::Scrub {
+            start_chunk,
+            end_chunk: Some(start_chunk + NUM_CHUNKS as u64),
+        })
+        .await
+        .unwrap();
+    assert_eq!(started.state, JobState::Running);
+    assert_eq!(started.progress_total, NUM_CHUNKS as u64);
+
+    drop(client);
+    let (parked, cluster) = restart_mid_job(cluster, &dir, started.id).await;
+    let node = cluster.node(0);
+    let mut progress = parked.progress_done;
+    let completed = wait_for_job(node.jobs.as_ref().unwrap(), started.id, |job| {
+        // resumed from the checkpoint instead of the start
+        assert!(job.progress_done >= progress);
+        progress = job.progress_done;
+        job.state != JobState::Running
+    })
+    .await;
+    assert_eq!(completed.state, JobState::Completed);
+    assert_eq!(completed.progress_done, completed.progress_total);
+    match completed.result {
+        Some(JobResult::Scrub { mismatches }) => {
+            assert_eq!(mismatches, vec![FlowBatchMismatch::new(lost_batch)])
+        }
+        result => panic!("unexpected result {:?}", result),
+    }
+
+    // finished job could not be cancelled
+    let client = node.rpc_client().unwrap();
+    assert!(!client.cancel_job(started.id).await.unwrap());
+    assert!(client.get_job(started.id + 1).await.unwrap().is_none());
+    // only the latest finished job is kept
+    assert!(client.get_job(default_range.id).await.unwrap().is_none());
+    assert!(client.get_job(started.id).await.unwrap().is_some());
+    drop(client);
+    cluster.shutdown().await.unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_export_job_resumed_after_restart() {
+    let dir = test_dir("export");
+    let cluster = build_cluster(&dir, PARKED_STEP_INTERVAL_MS).await;
+    let (tx, data) = submit_finalized_file(&cluster);
+    let tx_seq = tx.seq;
+    let client = cluster.node(0).rpc_client().unwrap();
+
+    let started = client
+        .start_job(JobParams::ExportFile {
+            tx_seq_or_root: TxSeqOrRoot::TxSeq(tx_seq),
+            path: "file.bin".into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(started.progress_total, data.len() as u64);
+
+    drop(client);
+    let (_, cluster) = restart_mid_job(cluster, &dir, started.id).await;
+    let node = cluster.node(0);
+    let completed = wait_for_job(node.jobs.as_ref().unwrap(), started.id, |job| {
+        job.state != JobState::Running
+    })
+    .await;
+    assert_eq!(
+        completed.state,
+        JobState::Completed,
+        "{:?}",
+        completed.error
+    );
+    let exported = match completed.result {
+        Some(JobResult::ExportFile(exported)) => exported,
+        result => panic!("unexpected result {:?}", result),
+    };
+    assert_eq!(exported.tx_seq, tx_seq);
+    assert_eq!(exported.size, data.len() as u64);
+    assert_eq!(exported.sha256.len(), 64);
+    assert_eq!(std::fs::read(&exported.path).unwrap(), data);
+
+    // existing files are never overwritten
+    let client = node.rpc_client().unwrap();
+    assert!(client
+        .start_job(JobParams::ExportFile {
+            tx_seq_or_root: TxSeqOrRoot::TxSeq(tx_seq),
+            path: "file.bin".into(),
+        })
+        .await
+        .is_err());
+    drop(client);
+    cluster.shutdown().await.unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_export_job_resumed_before_first_checkpoint() {
+    let dir = test_dir("export_created");
+    let cluster = Cluster::builder()
+        .with_num_nodes(1)
+        .with_db_dir(dir.join("db"))
+        .with_rpc_config(None)
+        .build()
+        .await
+        .unwrap();
+    let (tx, data) = submit_finalized_file(&cluster);
+
+    // stopped once the file is created by the first step, but before its checkpoint persisted
+    let path = dir.join("export/file.bin");
+    let params = JobParams::ExportFile {
+        tx_seq_or_root: TxSeqOrRoot::TxSeq(tx.seq),
+        path: path.to_string_lossy().into_owned(),
+    };
+    cluster
+        .node(0)
+        .store
+        .put_admin_job(AdminJob {
+            id: 0,
+            state: JOB_STATE_RUNNING,
+            params: serde_json::to_vec(&params).unwrap(),
+            checkpoint: vec![],
+            progress_done: 0,
+            progress_total: data.len() as u64,
+            error: None,
+            created_at: 1,
+            updated_at: 1,
+        })
+        .unwrap();
+    std::fs::write(&path, &data[..CHUNK_SIZE / 2]).unwrap();
+    cluster.shutdown().await.unwrap();
+
+    // the partial file is taken as its own instead of failing the job
+    let cluster = build_cluster(&dir, 0).await;
+    let node = cluster.node(0);
+    let completed = wait_for_job(node.jobs.as_ref().unwrap(), 0, |job| {
+        job.state != JobState::Running
+    })
+    .await;
+    assert_eq!(
+        completed.state,
+        JobState::Completed,
+        "{:?}",
+        completed.error
+    );
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+    cluster.shutdown().await.unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cancel_export_job() {
+    let dir = test_dir("cancel");
+    let cluster = build_cluster(&dir, PARKED_STEP_INTERVAL_MS).await;
+    let (tx, _) = submit_finalized_file(&cluster);
+    let tx_seq = tx.seq;
+    let node = cluster.node(0);
+    let client = node.rpc_client().unwrap();
+    let jobs = node.jobs.clone().unwrap();
+    let export = |path: &str| {
+        client.start_job(JobParams::ExportFile {
+            tx_seq_or_root: TxSeqOrRoot::TxSeq(tx_seq),
+            path: path.into(),
+        })
+    };
+
+    // cancelled while running on this node
+    let started = export("file.bin").await.unwrap();
+    wait_for_job(&jobs, started.id, |job| job.progress_done > 0).await;
+    assert!(dir.join("export/file.bin").exists());
+    assert!(client.cancel_job(started.id).await.unwrap());
+    let cancelled = wait_for_job(&jobs, started.id, |job| job.state != JobState::Running).await;
+    assert_eq!(cancelled.state, JobState::Cancelled);
+    assert!(cancelled.result.is_none());
+    // the partially exported file is removed
+    assert!(!dir.join("export/file.bin").exists());
+
+    // cancelled by a manager that has not resumed the job, e.g. once restarted
+    let started = export("other.bin").await.unwrap();
+    wait_for_job(&jobs, started.id, |job| job.progress_done > 0).await;
+    drop((client, jobs));
+    cluster.shutdown().await.unwrap();
+    let cluster = Cluster::builder()
+        .with_num_nodes(1)
+        .with_db_dir(dir.join("db"))
+        .with_rpc_config(None)
+        .build()
+        .await
+        .unwrap();
+    let jobs = Arc::new(
+        JobManager::new(
+            cluster.node(0).async_store.clone(),
+            cluster.executor().clone(),
+            rpc::RPCConfig::default().job_config(),
+        )
+        .await
+        .unwrap(),
+    );
+    assert!(jobs.cancel(started.id).await.unwrap());
+    let cancelled = jobs.get(started.id).await.unwrap().unwrap();
+    assert_eq!(cancelled.state, JobState::Cancelled);
+    assert!(!dir.join("export/other.bin").exists());
+    assert!(!jobs.cancel(started.id).await.unwrap());
+    drop(jobs);
+    cluster.shutdown().await.unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,67 @@
+use rpc::ZgsAdminRpcClient;
+use std::time::{Duration, Instant};
+use test_cluster::Cluster;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_connect_inspect_and_disconnect_peer() {
+    let cluster = Cluster::builder().build().await.unwrap();
+    let (node_a, node_b) = (cluster.node(0), cluster.node(1));
+    let (peer_a, peer_b) = (node_a.peer_id.to_base58(), node_b.peer_id.to_base58());
+    let (client_a, client_b) = (node_a.rpc_client().unwrap(), node_b.rpc_client().unwrap());
+    assert!(client_b
+        .get_peer_info(peer_a.clone())
+        .await
+        .unwrap()
+        .is_none());
+
+    // peer id is required
+    assert!(client_b
+        .connect_peer(node_a.addr.to_string())
+        .await
+        .is_err());
+
+    let address = format!("{}/p2p/{}", node_a.addr, peer_a);
+    assert_eq!(client_b.connect_peer(address).await.unwrap(), peer_a);
+
+    // connected on both sides
+    let info = client_b
+        .get_peer_info(peer_a.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(info.peer_id, peer_a);
+    assert_eq!(info.info.connection_status.status, "connected");
+    assert_eq!(info.info.connection_direction.as_deref(), Some("Outgoing"));
+    let info = client_a
+        .get_peer_info(peer_b.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(info.info.connection_status.status, "connected");
+    assert_eq!(info.info.connection_direction.as_deref(), Some("Incoming"));
+
+    // unknown peer fails to connect
+    let unknown = network::PeerId::random();
+    let address = format!("/ip4/127.0.0.1/tcp/1/p2p/{}", unknown);
+    assert!(client_b.connect_peer(address).await.is_err());
+
+    assert!(client_b.disconnect_peer(peer_a.clone()).await.unwrap());
+    let started_at = Instant::now();
+    loop {
+        let info = client_b
+            .get_peer_info(peer_a.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        if info.info.connection_status.status != "connected" {
+            break;
+        }
+        assert!(started_at.elapsed() < TIMEOUT, "peer not disconnected");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(!client_b.disconnect_peer(peer_a).await.unwrap());
+    let info = client_a.get_peer_info(peer_b).await.unwrap().unwrap();
+    assert_ne!(info.info.connection_status.status, "connected");
+}
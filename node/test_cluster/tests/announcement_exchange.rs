@@ -0,0 +1,57 @@
+use rand::random;
+use rpc::ZgsAdminRpcClient;
+use shared_types::{ChunkArray, CHUNK_SIZE};
+use std::time::{Duration, Instant};
+use storage::log_store::{LogStoreChunkWrite, LogStoreWrite};
+use test_cluster::Cluster;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_discover_old_file_once_connected() {
+    // files are only queued for auto sync by announcements of neighbors
+    let cluster = Cluster::builder()
+        .with_sync_config(sync::Config {
+            neighbors_only: true,
+            auto_sync_enabled: true,
+            ..Default::default()
+        })
+        .build()
+        .await
+        .unwrap();
+    let (node_a, node_b) = (cluster.node(0), cluster.node(1));
+
+    // node A finalized the file before node B joins
+    let data: Vec<u8> = (0..1024 * CHUNK_SIZE).map(|_| random()).collect();
+    let tx = cluster.flow.submit(&data).unwrap();
+    node_a
+        .store
+        .put_chunks(
+            tx.seq,
+            ChunkArray {
+                data,
+                start_index: 0,
+            },
+        )
+        .unwrap();
+    node_a.store.finalize_tx(tx.seq).unwrap();
+    node_a.announce_files(vec![tx.id()]).unwrap();
+
+    let client_b = node_b.rpc_client().unwrap();
+    let ready_txs = |state: sync::SyncServiceState| state.auto_sync_random.unwrap().ready_txs;
+    assert_eq!(
+        ready_txs(client_b.get_sync_service_state().await.unwrap()),
+        0
+    );
+
+    // node B queues the file once connected, without the file announced again
+    client_b
+        .connect_peer(format!("{}/p2p/{}", node_a.addr, node_a.peer_id))
+        .await
+        .unwrap();
+    let started_at = Instant::now();
+    while ready_txs(client_b.get_sync_service_state().await.unwrap()) == 0 {
+        assert!(started_at.elapsed() < TIMEOUT, "old file not discovered");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
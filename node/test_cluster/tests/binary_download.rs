@@ -0,0 +1,111 @@
+use hyper::StatusCode;
+use rand::random;
+use rpc::types::SegmentWithProof;
+use serde_json::{json, Value};
+use shared_types::{ChunkArray, CHUNK_SIZE};
+use ssz::Decode;
+use std::time::Instant;
+use storage::log_store::{LogStoreChunkWrite, LogStoreWrite};
+use test_cluster::Cluster;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_download_segment_in_binary() {
+    let cluster = Cluster::builder().with_num_nodes(1).build().await.unwrap();
+    let node = cluster.node(0);
+    let chunks_per_segment = rpc::RPCConfig::default().chunks_per_segment;
+
+    // 3 segments, so that the middle one comes with a non-trivial proof
+    let data: Vec<u8> = (0..3 * chunks_per_segment * CHUNK_SIZE)
+        .map(|_| random())
+        .collect();
+    let tx = cluster.flow.submit(&data).unwrap();
+    node.store
+        .put_chunks(
+            tx.seq,
+            ChunkArray {
+                data: data.clone(),
+                start_index: 0,
+            },
+        )
+        .unwrap();
+    node.store.finalize_tx(tx.seq).unwrap();
+
+    let params = json!([tx.data_merkle_root, 1]);
+    let json_response = node
+        .rpc_call_raw("zgs_downloadSegmentWithProof", params.clone(), false)
+        .await
+        .unwrap();
+    let binary_response = node
+        .rpc_call_raw("zgs_downloadSegmentWithProof", params, true)
+        .await
+        .unwrap();
+    assert_eq!(
+        binary_response.content_type.as_deref(),
+        Some(rpc::BINARY_CONTENT_TYPE)
+    );
+
+    let started_at = Instant::now();
+    let response: Value = serde_json::from_slice(&json_response.body).unwrap();
+    let json_segment: SegmentWithProof =
+        serde_json::from_value(response["result"].clone()).unwrap();
+    let json_elapsed = started_at.elapsed();
+
+    let started_at = Instant::now();
+    let segment = SegmentWithProof::from_ssz_bytes(&binary_response.body).unwrap();
+    let binary_elapsed = started_at.elapsed();
+
+    println!(
+        "segment of {} bytes, json: {} bytes decoded in {:?}, ssz: {} bytes decoded in {:?}",
+        segment.data.len(),
+        json_response.body.len(),
+        json_elapsed,
+        binary_response.body.len(),
+        binary_elapsed,
+    );
+
+    // base64 inflates the data by a third, while SSZ only adds the proof and a few fields
+    assert!(binary_response.body.len() * 4 < json_response.body.len() * 3 + 1024);
+    assert!(binary_response.body.len() < segment.data.len() + 1024);
+
+    // same segment, and the proof still verifies on client side
+    assert_eq!(segment.root, json_segment.root);
+    assert_eq!(segment.data, json_segment.data);
+    assert_eq!(segment.proof, json_segment.proof);
+    assert_eq!(
+        segment.data,
+        data[chunks_per_segment * CHUNK_SIZE..][..segment.data.len()]
+    );
+    assert!(!segment.proof.path.is_empty());
+    segment.validate(chunks_per_segment).unwrap();
+
+    // raw chunks
+    let response = node
+        .rpc_call_raw("zgs_downloadSegmentByTxSeq", json!([tx.seq, 0, 4]), true)
+        .await
+        .unwrap();
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body, data[..4 * CHUNK_SIZE]);
+
+    // not available
+    let response = node
+        .rpc_call_raw(
+            "zgs_downloadSegmentByTxSeq",
+            json!([tx.seq + 1, 0, 4]),
+            true,
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    // errors in JSON
+    let response = node
+        .rpc_call_raw(
+            "zgs_downloadSegmentWithProofByTxSeq",
+            json!([tx.seq, 3]),
+            true,
+        )
+        .await
+        .unwrap();
+    let response: Value = serde_json::from_slice(&response.body).unwrap();
+    assert_eq!(response["error"]["code"], 101);
+}
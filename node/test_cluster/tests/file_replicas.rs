@@ -0,0 +1,92 @@
+use rpc::ZgsRPCClient;
+use shared_types::{ChunkArray, CHUNK_SIZE};
+use storage::log_store::{LogStoreChunkWrite, LogStoreWrite};
+use test_cluster::Cluster;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_check_file_replicas() {
+    // Queries wait until the expected peers answered instead of the wait time, which only
+    // bounds a stuck query.
+    let cluster = Cluster::builder()
+        .with_num_nodes(6)
+        .with_rpc_config(Some(rpc::RPCConfig {
+            max_replica_queries: 1,
+            replica_query_wait_ms: 60_000,
+            ..Default::default()
+        }))
+        .build()
+        .await
+        .unwrap();
+
+    // stored on all nodes but node A, more than cached per file
+    let data = vec![3u8; 4 * CHUNK_SIZE];
+    let tx = cluster.flow.submit(&data).unwrap();
+    for node in &cluster.nodes[1..] {
+        node.store
+            .put_chunks(
+                tx.seq,
+                ChunkArray {
+                    data: data.clone(),
+                    start_index: 0,
+                },
+            )
+            .unwrap();
+        node.store.finalize_tx(tx.seq).unwrap();
+    }
+
+    let client = cluster.node(0).rpc_client().unwrap();
+
+    // nothing cached yet
+    let replicas = client
+        .check_file_replicas(tx.data_merkle_root, None, None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(replicas.tx_seq, tx.seq);
+    assert!(!replicas.local);
+    assert_eq!(replicas.peers, 0);
+    assert!(!replicas.network_queried);
+
+    // answered by all the 5 announcing nodes
+    let replicas = client
+        .check_file_replicas(tx.data_merkle_root, Some(true), Some(5))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(replicas.network_queried);
+    assert_eq!(replicas.peers, 5);
+    assert_eq!(replicas.replication_factor, 5.0);
+    assert!(replicas.all_shards_covered);
+    assert_eq!(replicas.shards.len(), 1);
+    assert_eq!(replicas.shards[0].peers, 5);
+    assert!(replicas.oldest_announcement.is_some());
+
+    // limited, and counted from the cache of `max_entries_per_file` peers only
+    let replicas = client
+        .check_file_replicas(tx.data_merkle_root, Some(true), Some(5))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!replicas.network_queried);
+    assert_eq!(replicas.peers, 4);
+
+    // unknown root
+    assert!(client
+        .check_file_replicas(Default::default(), Some(true), None)
+        .await
+        .unwrap()
+        .is_none());
+
+    // a node with the file counts the others only
+    let replicas = cluster
+        .node(1)
+        .rpc_client()
+        .unwrap()
+        .check_file_replicas(tx.data_merkle_root, Some(true), Some(4))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(replicas.network_queried);
+    assert!(replicas.local);
+    assert_eq!(replicas.peers, 4);
+}
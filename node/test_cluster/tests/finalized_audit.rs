@@ -0,0 +1,65 @@
+use rand::random;
+use shared_types::{ChunkArray, CHUNK_SIZE};
+use std::time::Duration;
+use storage::log_store::audit::FinalizedAudit;
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+use storage::log_store::{LogStoreChunkRead, LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
+use sync::{SyncRequest, SyncResponse};
+use test_cluster::Cluster;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_resync_demoted_file() {
+    let cluster = Cluster::builder().with_num_nodes(2).build().await.unwrap();
+
+    // fills 2 entry batches, so that one of them is only of the file
+    let data: Vec<u8> = (0..2 * PORA_CHUNK_SIZE * CHUNK_SIZE)
+        .map(|_| random())
+        .collect();
+    let tx = cluster.flow.submit(&data).unwrap();
+    for node in &cluster.nodes {
+        node.store
+            .put_chunks(
+                tx.seq,
+                ChunkArray {
+                    data: data.clone(),
+                    start_index: 0,
+                },
+            )
+            .unwrap();
+        node.store.finalize_tx(tx.seq).unwrap();
+    }
+
+    // node B loses the last batch of the file while it is still finalized
+    let node = cluster.node(1);
+    let batch_index = tx.start_entry_index / PORA_CHUNK_SIZE as u64 + 1;
+    node.store.remove_chunks_batch(&[batch_index]).unwrap();
+    assert!(node.store.check_tx_completed(tx.seq).unwrap());
+
+    let report = node
+        .async_store
+        .audit_finalized_txs(FinalizedAudit::Sampled { samples: 16 })
+        .await
+        .unwrap();
+    assert_eq!(report.audited, 1);
+    assert_eq!(report.demoted, vec![tx.seq]);
+    assert_eq!(node.store.get_tx_status(tx.seq).unwrap(), None);
+
+    // synced again from node A, as the node does on startup
+    let response = node
+        .sync_send
+        .request(SyncRequest::SyncFile { tx_seq: tx.seq })
+        .await
+        .unwrap();
+    assert!(matches!(response, SyncResponse::SyncFile { err } if err.is_empty()));
+    node.wait_for_tx_finalized(tx.seq, TIMEOUT).await.unwrap();
+    assert_eq!(
+        node.store
+            .get_chunks_by_tx_and_index_range(tx.seq, 0, 2 * PORA_CHUNK_SIZE)
+            .unwrap()
+            .unwrap()
+            .data,
+        data
+    );
+}
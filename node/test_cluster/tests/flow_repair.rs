@@ -0,0 +1,101 @@
+use rand::random;
+use rpc::ZgsAdminRpcClient;
+use shared_types::{ChunkArray, CHUNK_SIZE};
+use std::time::Duration;
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+use storage::log_store::{LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
+use test_cluster::Cluster;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_repair_corrupted_flow_range() {
+    let cluster = Cluster::builder().with_num_nodes(2).build().await.unwrap();
+    let (node_a, node_b) = (cluster.node(0), cluster.node(1));
+
+    // the file fills 2 entry batches, and stored on both nodes
+    let num_chunks = 2 * PORA_CHUNK_SIZE;
+    let data: Vec<u8> = (0..num_chunks * CHUNK_SIZE).map(|_| random()).collect();
+    let tx = cluster.flow.submit(&data).unwrap();
+    for node in [node_a, node_b] {
+        node.store
+            .put_chunks(
+                tx.seq,
+                ChunkArray {
+                    data: data.clone(),
+                    start_index: 0,
+                },
+            )
+            .unwrap();
+        node.store.finalize_tx(tx.seq).unwrap();
+    }
+
+    // corrupt the second batch of file on node B, e.g. bad sectors of the replaced disk
+    let start_chunk = tx.start_entry_index;
+    let end_chunk = start_chunk + num_chunks as u64;
+    let corrupted_batch = start_chunk / PORA_CHUNK_SIZE as u64 + 1;
+    node_b
+        .store
+        .remove_chunks_batch(&[corrupted_batch])
+        .unwrap();
+    node_b
+        .store
+        .put_chunks(
+            tx.seq,
+            ChunkArray {
+                data: vec![1u8; PORA_CHUNK_SIZE / 2 * CHUNK_SIZE],
+                start_index: PORA_CHUNK_SIZE as u64,
+            },
+        )
+        .unwrap();
+
+    let client_b = node_b.rpc_client().unwrap();
+    let mismatches = client_b
+        .verify_flow_range(start_chunk, end_chunk)
+        .await
+        .unwrap();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].batch_index, corrupted_batch);
+    assert_eq!(
+        mismatches[0].start_chunk,
+        start_chunk + PORA_CHUNK_SIZE as u64
+    );
+    assert_eq!(mismatches[0].end_chunk, end_chunk);
+
+    // node A is intact
+    let client_a = node_a.rpc_client().unwrap();
+    assert!(client_a
+        .verify_flow_range(start_chunk, end_chunk)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // invalid range
+    assert!(client_b
+        .repair_flow_range(end_chunk, start_chunk)
+        .await
+        .is_err());
+
+    // repair only the corrupted batch from node A
+    let repair = client_b
+        .repair_flow_range(start_chunk, end_chunk)
+        .await
+        .unwrap();
+    assert_eq!(repair.removed_batches, vec![corrupted_batch]);
+    assert!(repair.skipped_batches.is_empty());
+    assert_eq!(repair.tx_seqs, vec![tx.seq]);
+    assert!(!node_b.store.check_tx_completed(tx.seq).unwrap());
+
+    node_b.wait_for_tx_finalized(tx.seq, TIMEOUT).await.unwrap();
+    let chunks = node_b
+        .store
+        .get_chunks_by_tx_and_index_range(tx.seq, 0, num_chunks)
+        .unwrap()
+        .unwrap();
+    assert_eq!(chunks.data, data);
+    assert!(client_b
+        .verify_flow_range(start_chunk, end_chunk)
+        .await
+        .unwrap()
+        .is_empty());
+}
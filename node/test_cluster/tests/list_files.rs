@@ -0,0 +1,105 @@
+use rpc::types::{FileFilter, StoredFileStatus};
+use rpc::ZgsAdminRpcClient;
+use shared_types::{ChunkArray, CHUNK_SIZE};
+use storage::log_store::{LogStoreChunkWrite, LogStoreWrite};
+use test_cluster::Cluster;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_list_files_by_pages() {
+    let cluster = Cluster::builder().with_num_nodes(1).build().await.unwrap();
+    let node = cluster.node(0);
+
+    // 5 files of a single chunk, of which the even ones are finalized
+    for seq in 0..5u64 {
+        let data = vec![seq as u8 + 1; CHUNK_SIZE];
+        let tx = cluster.flow.submit(&data).unwrap();
+        assert_eq!(tx.seq, seq);
+        if seq % 2 == 0 {
+            node.store
+                .put_chunks(
+                    tx.seq,
+                    ChunkArray {
+                        data,
+                        start_index: 0,
+                    },
+                )
+                .unwrap();
+            node.store.finalize_tx(tx.seq).unwrap();
+        }
+    }
+
+    let client = node.rpc_client().unwrap();
+    let list = |start_tx_seq: u64, limit: usize, filter: Option<FileFilter>| {
+        let client = client.clone();
+        async move {
+            let page = client
+                .list_files(start_tx_seq, limit, filter)
+                .await
+                .unwrap();
+            let files: Vec<(u64, StoredFileStatus)> = page
+                .files
+                .iter()
+                .map(|file| (file.tx_seq, file.status))
+                .collect();
+            (files, page.next_cursor)
+        }
+    };
+
+    // all files by pages of 2, following the cursor
+    assert_eq!(
+        list(0, 2, None).await,
+        (
+            vec![
+                (0, StoredFileStatus::Finalized),
+                (1, StoredFileStatus::Syncing)
+            ],
+            Some(2)
+        )
+    );
+    assert_eq!(
+        list(2, 2, None).await,
+        (
+            vec![
+                (2, StoredFileStatus::Finalized),
+                (3, StoredFileStatus::Syncing)
+            ],
+            Some(4)
+        )
+    );
+    assert_eq!(
+        list(4, 2, None).await,
+        (vec![(4, StoredFileStatus::Finalized)], None)
+    );
+
+    // the last page ends at the last file
+    assert_eq!(
+        list(3, 2, None).await,
+        (
+            vec![
+                (3, StoredFileStatus::Syncing),
+                (4, StoredFileStatus::Finalized)
+            ],
+            None
+        )
+    );
+
+    // filtered files, and the cursor is right after the last one listed
+    assert_eq!(
+        list(0, 2, Some(FileFilter::Finalized)).await,
+        (
+            vec![
+                (0, StoredFileStatus::Finalized),
+                (2, StoredFileStatus::Finalized)
+            ],
+            Some(3)
+        )
+    );
+    assert_eq!(
+        list(3, 2, Some(FileFilter::Finalized)).await,
+        (vec![(4, StoredFileStatus::Finalized)], None)
+    );
+    assert_eq!(list(0, 10, Some(FileFilter::Pruned)).await, (vec![], None));
+
+    // beyond the last file
+    assert_eq!(list(5, 2, None).await, (vec![], None));
+}
@@ -0,0 +1,44 @@
+use rand::random;
+use rpc::ZgsAdminRpcClient;
+use shared_types::CHUNK_SIZE;
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+use storage::log_store::merkle_state::{diff_merkle_states, MerkleState};
+use storage::log_store::LogStoreRead;
+use test_cluster::Cluster;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_export_merkle_state_inline() {
+    let cluster = Cluster::builder().build().await.unwrap();
+    for num_chunks in [3 * PORA_CHUNK_SIZE + 5, 10] {
+        let data: Vec<u8> = (0..num_chunks * CHUNK_SIZE).map(|_| random()).collect();
+        cluster.flow.submit(&data).unwrap();
+    }
+
+    let mut states = vec![];
+    for node in &cluster.nodes {
+        let exported = node
+            .rpc_client()
+            .unwrap()
+            .export_merkle_state(None)
+            .await
+            .unwrap();
+        let (flow_root, flow_length) = node.store.get_context().unwrap();
+        assert_eq!(exported.flow_root, Some(flow_root));
+        assert_eq!(exported.flow_length, flow_length);
+        assert_eq!(exported.path, None);
+        assert_eq!(exported.size, exported.data.len() as u64);
+
+        let state = MerkleState::from_bytes(&exported.data).unwrap();
+        assert_eq!(state, node.store.get_merkle_state(usize::MAX).unwrap());
+        states.push(state);
+    }
+    // nodes of the same flow have the same tree
+    assert_eq!(diff_merkle_states(&states[0], &states[1]), None);
+
+    // files are exported only into the export directory
+    let client = cluster.node(0).rpc_client().unwrap();
+    assert!(client
+        .export_merkle_state(Some("state.ssz".into()))
+        .await
+        .is_err());
+}
@@ -0,0 +1,103 @@
+use rand::random;
+use rpc::ZgsAdminRpcClient;
+use shared_types::{ChunkArray, CHUNK_SIZE};
+use std::time::{Duration, Instant};
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+use storage::log_store::{LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
+use sync::SyncTraceEvent;
+use test_cluster::Cluster;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sync_from_partial_file() {
+    let cluster = Cluster::builder()
+        .with_sync_config(sync::Config {
+            max_chunks_to_request: PORA_CHUNK_SIZE as u64,
+            ..Default::default()
+        })
+        .build()
+        .await
+        .unwrap();
+    let (node_a, node_b) = (cluster.node(0), cluster.node(1));
+
+    // node A downloaded the first half of file only
+    let num_chunks = 4 * PORA_CHUNK_SIZE;
+    let half = num_chunks / 2;
+    let data: Vec<u8> = (0..num_chunks * CHUNK_SIZE).map(|_| random()).collect();
+    let tx = cluster.flow.submit(&data).unwrap();
+    node_a
+        .store
+        .put_chunks(
+            tx.seq,
+            ChunkArray {
+                data: data[..half * CHUNK_SIZE].to_vec(),
+                start_index: 0,
+            },
+        )
+        .unwrap();
+
+    // node B pulls the downloaded chunks from node A
+    let client_b = node_b.rpc_client().unwrap();
+    client_b.trace_file_sync(tx.seq, true).await.unwrap();
+    client_b.start_sync_file(tx.seq).await.unwrap();
+    let started_at = Instant::now();
+    while !node_b
+        .store
+        .has_chunks_by_tx_and_index_range(tx.seq, 0, half)
+        .unwrap()
+    {
+        assert!(started_at.elapsed() < TIMEOUT, "first half not synced");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(!node_a.store.check_tx_completed(tx.seq).unwrap());
+    assert!(!node_b.store.check_tx_completed(tx.seq).unwrap());
+
+    // node A responds that the second half is not stored yet
+    let unavailable = SyncTraceEvent::RequestFailed {
+        peer_id: node_a.peer_id.to_string(),
+        reason: "Chunks unavailable".into(),
+    };
+    loop {
+        let trace = client_b.get_file_sync_trace(tx.seq).await.unwrap().unwrap();
+        if trace
+            .events
+            .iter()
+            .any(|record| record.event == unavailable)
+        {
+            break;
+        }
+        assert!(started_at.elapsed() < TIMEOUT, "second half requested");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // node B completes once node A finishes downloading
+    node_a
+        .store
+        .put_chunks(
+            tx.seq,
+            ChunkArray {
+                data: data[half * CHUNK_SIZE..].to_vec(),
+                start_index: half as u64,
+            },
+        )
+        .unwrap();
+    node_a.store.finalize_tx(tx.seq).unwrap();
+    node_b.wait_for_tx_finalized(tx.seq, TIMEOUT).await.unwrap();
+    let chunks = node_b
+        .store
+        .get_chunks_by_tx_and_index_range(tx.seq, 0, num_chunks)
+        .unwrap()
+        .unwrap();
+    assert_eq!(chunks.data, data);
+
+    // node A is never penalized for the chunks not downloaded yet
+    let reports = cluster.peer_reports();
+    assert!(
+        reports
+            .iter()
+            .all(|report| report.peer_id != node_a.peer_id),
+        "{:?}",
+        reports
+    );
+}
@@ -0,0 +1,67 @@
+use rand::random;
+use rpc::types::PeerStatsSort;
+use rpc::ZgsAdminRpcClient;
+use shared_types::{ChunkArray, CHUNK_SIZE};
+use std::time::Duration;
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+use storage::log_store::{LogStoreChunkWrite, LogStoreWrite};
+use sync::{SyncRequest, SyncResponse};
+use test_cluster::Cluster;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_peer_stats_of_file_sync() {
+    let cluster = Cluster::builder().with_num_nodes(2).build().await.unwrap();
+    let (node_a, node_b) = (cluster.node(0), cluster.node(1));
+
+    // more than one segment, so that it is synced in several requests
+    let num_chunks = PORA_CHUNK_SIZE + 10;
+    let data: Vec<u8> = (0..num_chunks * CHUNK_SIZE).map(|_| random()).collect();
+    let tx = cluster.flow.submit(&data).unwrap();
+    node_a
+        .store
+        .put_chunks(
+            tx.seq,
+            ChunkArray {
+                data: data.clone(),
+                start_index: 0,
+            },
+        )
+        .unwrap();
+    node_a.store.finalize_tx(tx.seq).unwrap();
+
+    // sync to node B from node A
+    let response = node_b
+        .sync_send
+        .request(SyncRequest::SyncFile { tx_seq: tx.seq })
+        .await
+        .unwrap();
+    assert!(matches!(response, SyncResponse::SyncFile { err } if err.is_empty()));
+    node_b.wait_for_tx_finalized(tx.seq, TIMEOUT).await.unwrap();
+
+    let stats_b = node_b
+        .rpc_client()
+        .unwrap()
+        .get_peer_stats(None, None)
+        .await
+        .unwrap();
+    assert_eq!(stats_b.len(), 1);
+    assert_eq!(stats_b[0].peer_id, node_a.peer_id.to_string());
+    assert_eq!(stats_b[0].bytes_received, data.len() as u64);
+    assert_eq!(stats_b[0].segments_received, 2);
+    assert_eq!(stats_b[0].failed_verifications, 0);
+    assert_eq!(stats_b[0].bytes_served, 0);
+
+    let stats_a = node_a
+        .rpc_client()
+        .unwrap()
+        .get_peer_stats(Some(PeerStatsSort::BytesServed), Some(1))
+        .await
+        .unwrap();
+    assert_eq!(stats_a.len(), 1);
+    assert_eq!(stats_a[0].peer_id, node_b.peer_id.to_string());
+    assert_eq!(stats_a[0].bytes_served, data.len() as u64);
+    assert_eq!(stats_a[0].segments_served, 2);
+    assert_eq!(stats_a[0].bytes_received, 0);
+}
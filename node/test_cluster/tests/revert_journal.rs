@@ -0,0 +1,91 @@
+use log_entry_sync::LogSyncEvent;
+use rand::random;
+use rpc::ZgsAdminRpcClient;
+use shared_types::{ChunkArray, CHUNK_SIZE};
+use std::time::Duration;
+use storage::log_store::log_manager::{LogConfig, PORA_CHUNK_SIZE};
+use storage::log_store::revert_journal::RevertJournalConfig;
+use storage::log_store::{LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
+use sync::{SyncRequest, SyncResponse};
+use test_cluster::Cluster;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_restore_reverted_file_without_sync() {
+    let cluster = Cluster::builder()
+        .with_log_config(LogConfig {
+            revert_journal: RevertJournalConfig {
+                max_chunks: 4 * PORA_CHUNK_SIZE,
+                expiry_blocks: 1000,
+            },
+            ..Default::default()
+        })
+        .build()
+        .await
+        .unwrap();
+
+    // the file is finalized on both nodes
+    let num_chunks = 2 * PORA_CHUNK_SIZE + 10;
+    let data: Vec<u8> = (0..num_chunks * CHUNK_SIZE).map(|_| random()).collect();
+    let tx = cluster.flow.submit(&data).unwrap();
+    for node in &cluster.nodes {
+        node.store
+            .put_chunks(
+                tx.seq,
+                ChunkArray {
+                    data: data.clone(),
+                    start_index: 0,
+                },
+            )
+            .unwrap();
+        node.store.finalize_tx(tx.seq).unwrap();
+    }
+
+    // the reorg reverts the file and re-inserts it at the same position
+    cluster.flow.revert(tx.seq).unwrap();
+    let mut events: Vec<_> = cluster
+        .nodes
+        .iter()
+        .map(|node| node.subscribe_log_sync())
+        .collect();
+    let reinserted = cluster.flow.submit(&data).unwrap();
+    assert_eq!(reinserted.start_entry_index, tx.start_entry_index);
+
+    // finalized in background from the journal on both nodes, without syncing from peers
+    for (node, events) in cluster.nodes.iter().zip(events.iter_mut()) {
+        node.wait_for_tx_finalized(tx.seq, Duration::from_secs(30))
+            .await
+            .unwrap();
+        while let Ok(event) = events.try_recv() {
+            assert!(!matches!(event, LogSyncEvent::TxSynced { .. }));
+        }
+        match node
+            .sync_send
+            .request(SyncRequest::SyncStatus { tx_seq: tx.seq })
+            .await
+            .unwrap()
+        {
+            SyncResponse::SyncStatus { status } => assert!(status.is_none(), "{:?}", status),
+            response => panic!("unexpected response {:?}", response),
+        }
+        let chunks = node
+            .store
+            .get_chunks_by_tx_and_index_range(tx.seq, 0, num_chunks)
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunks.data, data);
+    }
+
+    // nothing is synced between nodes
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    for node in &cluster.nodes {
+        let stats = node
+            .rpc_client()
+            .unwrap()
+            .get_peer_stats(None, None)
+            .await
+            .unwrap();
+        assert!(stats
+            .iter()
+            .all(|stats| stats.segments_received == 0 && stats.segments_served == 0));
+    }
+}
@@ -0,0 +1,96 @@
+use rand::random;
+use rpc::ZgsAdminRpcClient;
+use shared_types::{ChunkArray, CHUNK_SIZE};
+use std::time::{Duration, Instant};
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+use storage::log_store::{LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
+use test_cluster::Cluster;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_self_audit_repairs_corrupted_batch() {
+    let cluster = Cluster::builder()
+        .with_sync_config(sync::Config {
+            self_audit_interval: Duration::from_secs(1),
+            self_audit_samples: 64,
+            ..Default::default()
+        })
+        .build()
+        .await
+        .unwrap();
+    let (node_a, node_b) = (cluster.node(0), cluster.node(1));
+
+    // the file fills 2 entry batches, and stored on both nodes
+    let num_chunks = 2 * PORA_CHUNK_SIZE;
+    let data: Vec<u8> = (0..num_chunks * CHUNK_SIZE).map(|_| random()).collect();
+    let tx = cluster.flow.submit(&data).unwrap();
+    for node in [node_a, node_b] {
+        node.store
+            .put_chunks(
+                tx.seq,
+                ChunkArray {
+                    data: data.clone(),
+                    start_index: 0,
+                },
+            )
+            .unwrap();
+        node.store.finalize_tx(tx.seq).unwrap();
+    }
+
+    // corrupt the second batch of file on node B
+    let start_chunk = tx.start_entry_index;
+    let end_chunk = start_chunk + num_chunks as u64;
+    let corrupted_batch = start_chunk / PORA_CHUNK_SIZE as u64 + 1;
+    node_b
+        .store
+        .remove_chunks_batch(&[corrupted_batch])
+        .unwrap();
+    node_b
+        .store
+        .put_chunks(
+            tx.seq,
+            ChunkArray {
+                data: vec![1u8; PORA_CHUNK_SIZE / 2 * CHUNK_SIZE],
+                start_index: PORA_CHUNK_SIZE as u64,
+            },
+        )
+        .unwrap();
+
+    // the sampled chunks of the corrupted batch fail
+    let client_b = node_b.rpc_client().unwrap();
+    let started_at = Instant::now();
+    let status = loop {
+        let status = client_b.get_status().await.unwrap().self_audit;
+        match status {
+            Some(status) if status.total_failed > 0 => break status,
+            _ => {
+                assert!(started_at.elapsed() < TIMEOUT, "corruption not audited");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    };
+    assert!(status.success_ratio.unwrap() < 1.0);
+    assert!(status.failed <= status.sampled);
+
+    // the corrupted batch is repaired from node A automatically
+    while !client_b
+        .verify_flow_range(start_chunk, end_chunk)
+        .await
+        .unwrap()
+        .is_empty()
+    {
+        assert!(
+            started_at.elapsed() < 2 * TIMEOUT,
+            "corruption not repaired"
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    node_b.wait_for_tx_finalized(tx.seq, TIMEOUT).await.unwrap();
+    let chunks = node_b
+        .store
+        .get_chunks_by_tx_and_index_range(tx.seq, 0, num_chunks)
+        .unwrap()
+        .unwrap();
+    assert_eq!(chunks.data, data);
+}
@@ -0,0 +1,24 @@
+use rpc::ZgsAdminRpcClient;
+use test_cluster::Cluster;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_startup_phases() {
+    let cluster = Cluster::builder().with_num_nodes(1).build().await.unwrap();
+
+    let status = cluster
+        .node(0)
+        .rpc_client()
+        .unwrap()
+        .get_status()
+        .await
+        .unwrap();
+    let names: Vec<_> = status
+        .startup_phases
+        .iter()
+        .map(|phase| phase.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        vec!["tx_store_init", "last_chunk_rebuild", "flow_tree_load"]
+    );
+}
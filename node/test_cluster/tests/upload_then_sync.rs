@@ -0,0 +1,147 @@
+use log_entry_sync::LogSyncEvent;
+use rand::random;
+use shared_types::CHUNK_SIZE;
+use std::time::Duration;
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+use storage::log_store::{LogStoreChunkRead, LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
+use test_cluster::Cluster;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test(start_paused = true)]
+async fn test_upload_then_sync() {
+    let cluster = Cluster::builder()
+        .with_num_nodes(3)
+        .with_rpc_config(None)
+        .build()
+        .await
+        .unwrap();
+
+    let data: Vec<u8> = (0..4 * CHUNK_SIZE).map(|_| random()).collect();
+    let tx = cluster.flow.submit(&data).unwrap();
+
+    // uploaded to node A
+    cluster.node(0).put_file(tx.seq, &data).unwrap();
+
+    // sync to node B from node A
+    let node_b = cluster.node(1);
+    node_b.sync_file(tx.seq).await.unwrap();
+    node_b.wait_for_tx_finalized(tx.seq, TIMEOUT).await.unwrap();
+    let chunks = node_b
+        .store
+        .get_chunks_by_tx_and_index_range(tx.seq, 0, 4)
+        .unwrap()
+        .unwrap();
+    assert_eq!(chunks.data, data);
+    let node_a = cluster.node(0);
+    assert!(cluster
+        .peer_reports()
+        .iter()
+        .all(|report| report.peer_id != node_a.peer_id));
+
+    // never synced without request
+    assert!(!cluster.node(2).store.check_tx_completed(tx.seq).unwrap());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_revert_on_all_nodes() {
+    let cluster = Cluster::builder()
+        .with_rpc_config(None)
+        .build()
+        .await
+        .unwrap();
+    let mut events = cluster.node(1).subscribe_log_sync();
+
+    let txs = (0..3u8)
+        .map(|i| cluster.flow.submit(&[i; CHUNK_SIZE]).unwrap())
+        .collect::<Vec<_>>();
+    cluster
+        .node(0)
+        .put_file(txs[1].seq, &[1; CHUNK_SIZE])
+        .unwrap();
+    cluster.flow.revert(1).unwrap();
+    assert_eq!(cluster.flow.num_txs(), 1);
+
+    // reverted txs are removed on all nodes, along with the finalized file
+    for node in &cluster.nodes {
+        assert_eq!(node.store.next_tx_seq(), 1);
+        let tx = node.store.get_tx_by_seq_number(0).unwrap().unwrap();
+        assert_eq!(tx.id(), txs[0].id());
+        assert!(node.store.get_tx_by_seq_number(1).unwrap().is_none());
+        assert!(!node.store.check_tx_completed(1).unwrap());
+    }
+
+    // log sync events are delivered in order
+    let mut received = vec![];
+    while let Ok(event) = events.try_recv() {
+        received.push(event);
+    }
+    assert_eq!(received.len(), 5);
+    assert!(received[..3]
+        .iter()
+        .all(|event| matches!(event, LogSyncEvent::TxSynced { .. })));
+    assert!(matches!(
+        received[3],
+        LogSyncEvent::ReorgDetected { tx_seq: 1 }
+    ));
+    assert!(matches!(received[4], LogSyncEvent::Reverted { tx_seq: 1 }));
+
+    // the file resubmitted at the reverted seq is synced with the new data only
+    let data = vec![9; CHUNK_SIZE];
+    let tx = cluster.flow.submit(&data).unwrap();
+    assert_eq!(tx.seq, 1);
+    assert_eq!(tx.start_entry_index, txs[1].start_entry_index);
+    cluster.node(0).put_file(tx.seq, &data).unwrap();
+    let node_b = cluster.node(1);
+    node_b.sync_file(tx.seq).await.unwrap();
+    node_b.wait_for_tx_finalized(tx.seq, TIMEOUT).await.unwrap();
+    let chunks = node_b
+        .store
+        .get_chunks_by_tx_and_index_range(tx.seq, 0, 1)
+        .unwrap()
+        .unwrap();
+    assert_eq!(chunks.data, data);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_sync_from_unpruned_peer() {
+    let cluster = Cluster::builder()
+        .with_num_nodes(3)
+        .with_rpc_config(None)
+        .build()
+        .await
+        .unwrap();
+
+    // the file fills 2 entry batches, and stored on nodes A and C
+    let num_chunks = 2 * PORA_CHUNK_SIZE;
+    let data: Vec<u8> = (0..num_chunks * CHUNK_SIZE).map(|_| random()).collect();
+    let tx = cluster.flow.submit(&data).unwrap();
+    for index in [0, 2] {
+        cluster.node(index).put_file(tx.seq, &data).unwrap();
+    }
+
+    // node A prunes the file as the pruner does
+    let node_a = cluster.node(0);
+    let start_batch = tx.start_entry_index / PORA_CHUNK_SIZE as u64;
+    node_a
+        .store
+        .remove_chunks_batch(&[start_batch, start_batch + 1])
+        .unwrap();
+    node_a.store.prune_tx(tx.seq).unwrap();
+    assert!(node_a.store.check_tx_pruned(tx.seq).unwrap());
+
+    // synced from node C only, since node A never answers the pruned file
+    let node_b = cluster.node(1);
+    node_b.sync_file(tx.seq).await.unwrap();
+    node_b.wait_for_tx_finalized(tx.seq, TIMEOUT).await.unwrap();
+    let chunks = node_b
+        .store
+        .get_chunks_by_tx_and_index_range(tx.seq, 0, num_chunks)
+        .unwrap()
+        .unwrap();
+    assert_eq!(chunks.data, data);
+    assert!(cluster
+        .peer_reports()
+        .iter()
+        .all(|report| report.peer_id != node_a.peer_id));
+}
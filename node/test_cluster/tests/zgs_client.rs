@@ -0,0 +1,41 @@
+use rand::random;
+use shared_types::CHUNK_SIZE;
+use std::time::Duration;
+use storage::log_store::LogStoreRead;
+use test_cluster::Cluster;
+use zgs_client::{Client, File, DEFAULT_CHUNKS_PER_SEGMENT};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_upload_and_download_by_client() {
+    let cluster = Cluster::builder().with_num_nodes(1).build().await.unwrap();
+    let node = cluster.node(0);
+
+    // 3 segments, of which the last chunk is partial
+    let data: Vec<u8> = (0..3 * DEFAULT_CHUNKS_PER_SEGMENT * CHUNK_SIZE - 3)
+        .map(|_| random())
+        .collect();
+    let file = File::new(data.clone()).unwrap();
+    assert_eq!(file.num_segments(), 3);
+
+    // the root computed by client is the same as submitted
+    let tx = cluster.flow.submit(&data).unwrap();
+    assert_eq!(file.root(), tx.data_merkle_root);
+
+    let client = Client::new(&node.rpc_url().unwrap())
+        .unwrap()
+        .with_segments_per_request(2);
+    client.upload(&file).await.unwrap();
+    client
+        .wait_for_finalized(file.root(), TIMEOUT)
+        .await
+        .unwrap();
+    assert!(node.store.check_tx_completed(tx.seq).unwrap());
+
+    // never uploaded again once finalized
+    assert!(client.upload(&file).await.is_err());
+
+    assert_eq!(client.download(file.root()).await.unwrap(), data);
+    assert!(client.download(Default::default()).await.is_err());
+}
@@ -0,0 +1,134 @@
+use crate::file::File;
+use crate::DEFAULT_CHUNKS_PER_SEGMENT;
+use anyhow::{anyhow, bail, Result};
+use jsonrpsee::http_client::{HttpClient, HttpClientBuilder};
+use rpc::types::{FileInfo, SegmentWithProof};
+use rpc::ZgsRPCClient;
+use shared_types::DataRoot;
+use std::time::{Duration, Instant};
+
+/// Number of segments uploaded in one request by default, i.e. 4 MB by default segment size.
+const DEFAULT_SEGMENTS_PER_REQUEST: usize = 16;
+
+/// Interval to poll the file info while waiting for the file finalized.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Client of the `zgs` RPC namespace of a storage node.
+pub struct Client {
+    rpc: HttpClient,
+    chunks_per_segment: usize,
+    segments_per_request: usize,
+}
+
+impl Client {
+    /// Creates the client of the node RPC at `url`, e.g. `http://127.0.0.1:5678`.
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self::from_rpc(HttpClientBuilder::default().build(url)?))
+    }
+
+    pub fn from_rpc(rpc: HttpClient) -> Self {
+        Self {
+            rpc,
+            chunks_per_segment: DEFAULT_CHUNKS_PER_SEGMENT,
+            segments_per_request: DEFAULT_SEGMENTS_PER_REQUEST,
+        }
+    }
+
+    /// Sets the number of chunks in a segment, which should be the same as
+    /// `rpc.chunks_per_segment` of the node.
+    pub fn with_chunks_per_segment(mut self, chunks_per_segment: usize) -> Self {
+        self.chunks_per_segment = chunks_per_segment;
+        self
+    }
+
+    pub fn with_segments_per_request(mut self, segments_per_request: usize) -> Self {
+        self.segments_per_request = segments_per_request.max(1);
+        self
+    }
+
+    /// Returns the underlying RPC client, e.g. to call the methods not wrapped by this client.
+    pub fn rpc(&self) -> &HttpClient {
+        &self.rpc
+    }
+
+    /// Uploads all segments of the file, which should be submitted to the flow contract and
+    /// synced by the node beforehand. Uploading the file again before finalized succeeds
+    /// immediately, e.g. to resume the failed upload, but fails once finalized.
+    pub async fn upload(&self, file: &File) -> Result<()> {
+        if file.chunks_per_segment() != self.chunks_per_segment {
+            bail!(
+                "chunks per segment mismatch, file = {}, client = {}",
+                file.chunks_per_segment(),
+                self.chunks_per_segment
+            );
+        }
+
+        let mut segments = Vec::with_capacity(self.segments_per_request);
+        for segment in file.segments() {
+            segments.push(segment?);
+            if segments.len() == self.segments_per_request {
+                self.rpc
+                    .upload_segments(std::mem::take(&mut segments))
+                    .await?;
+            }
+        }
+        if !segments.is_empty() {
+            self.rpc.upload_segments(segments).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_file_info(&self, root: DataRoot) -> Result<Option<FileInfo>> {
+        Ok(self.rpc.get_file_info(root).await?)
+    }
+
+    /// Waits for the file finalized on the node, e.g. after uploaded, or fails on timeout.
+    pub async fn wait_for_finalized(&self, root: DataRoot, timeout: Duration) -> Result<()> {
+        let started_at = Instant::now();
+        loop {
+            match self.get_file_info(root).await? {
+                Some(info) if info.pruned => bail!("file pruned"),
+                Some(info) if info.finalized => return Ok(()),
+                _ => {}
+            }
+
+            if started_at.elapsed() >= timeout {
+                bail!("file not finalized in {:?}", timeout);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Downloads the file finalized on the node, of which every segment is verified against
+    /// the file root.
+    pub async fn download(&self, root: DataRoot) -> Result<Vec<u8>> {
+        let info = self
+            .get_file_info(root)
+            .await?
+            .ok_or_else(|| anyhow!("file not found"))?;
+        if !info.finalized || info.shard_finalized {
+            bail!("file not entirely available on the node");
+        }
+
+        let file_size = info.tx.size as usize;
+        let (num_segments, _) =
+            SegmentWithProof::split_file_into_segments(file_size, self.chunks_per_segment)?;
+        let mut data = Vec::with_capacity(file_size);
+        for index in 0..num_segments {
+            let segment = self
+                .rpc
+                .download_segment_with_proof(root, index)
+                .await?
+                .ok_or_else(|| anyhow!("segment {} not available", index))?;
+            if segment.root != root || segment.index != index || segment.file_size != file_size {
+                bail!("segment {} of another file", index);
+            }
+            segment.validate(self.chunks_per_segment)?;
+            data.extend_from_slice(&segment.data);
+        }
+        data.truncate(file_size);
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,172 @@
+use crate::DEFAULT_CHUNKS_PER_SEGMENT;
+use anyhow::{bail, Result};
+use rpc::types::SegmentWithProof;
+use shared_types::{compute_padded_chunk_size, compute_segment_size, DataRoot, CHUNK_SIZE};
+use storage::log_store::log_manager::{sub_merkle_tree, FileMerkleTree};
+
+/// File to upload, along with the merkle tree of its segment roots.
+pub struct File {
+    data: Vec<u8>,
+    chunks_per_segment: usize,
+    /// Tree of the segments of the padded file, including the segments of padding only, which
+    /// are not uploaded.
+    tree: FileMerkleTree,
+}
+
+impl File {
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        Self::with_chunks_per_segment(data, DEFAULT_CHUNKS_PER_SEGMENT)
+    }
+
+    /// Creates the file of segments in `chunks_per_segment` chunks, which should be the same as
+    /// `rpc.chunks_per_segment` of the nodes to upload to.
+    pub fn with_chunks_per_segment(data: Vec<u8>, chunks_per_segment: usize) -> Result<Self> {
+        if data.is_empty() {
+            bail!("file is empty");
+        }
+        if !chunks_per_segment.is_power_of_two() {
+            bail!("chunks per segment not power of 2");
+        }
+
+        // the file is padded with zeros the same as the flow
+        let (padded_chunks, _) = compute_padded_chunk_size(data.len());
+        let (num_segments, last_segment_chunks) =
+            compute_segment_size(padded_chunks, chunks_per_segment);
+        let mut segment_roots = Vec::with_capacity(num_segments);
+        for index in 0..num_segments {
+            let num_chunks = if index == num_segments - 1 {
+                last_segment_chunks
+            } else {
+                chunks_per_segment
+            };
+            let mut segment = segment_data(&data, index, chunks_per_segment);
+            segment.resize(num_chunks * CHUNK_SIZE, 0);
+            segment_roots.push(sub_merkle_tree(&segment)?.root());
+        }
+
+        Ok(Self {
+            data,
+            chunks_per_segment,
+            tree: FileMerkleTree::new(segment_roots),
+        })
+    }
+
+    /// Returns the file merkle root to submit.
+    pub fn root(&self) -> DataRoot {
+        self.tree.root().into()
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn chunks_per_segment(&self) -> usize {
+        self.chunks_per_segment
+    }
+
+    /// Returns the number of segments to upload, without the segments of padding only.
+    pub fn num_segments(&self) -> usize {
+        self.data
+            .len()
+            .div_ceil(self.chunks_per_segment * CHUNK_SIZE)
+    }
+
+    /// Returns the segment of `index` along with its proof, in which the last chunk of file is
+    /// padded with zeros.
+    pub fn segment(&self, index: usize) -> Result<SegmentWithProof> {
+        if index >= self.num_segments() {
+            bail!(
+                "segment index out of range, index = {}, num_segments = {}",
+                index,
+                self.num_segments()
+            );
+        }
+
+        let mut data = segment_data(&self.data, index, self.chunks_per_segment);
+        data.resize(data.len().div_ceil(CHUNK_SIZE) * CHUNK_SIZE, 0);
+
+        Ok(SegmentWithProof {
+            root: self.root(),
+            data,
+            index,
+            proof: self.tree.gen_proof(index).into(),
+            file_size: self.data.len(),
+        })
+    }
+
+    /// Returns all segments to upload in order.
+    pub fn segments(&self) -> impl Iterator<Item = Result<SegmentWithProof>> + '_ {
+        (0..self.num_segments()).map(|index| self.segment(index))
+    }
+}
+
+/// Returns the data of segment `index` without padding, which is empty for the segments of
+/// padding only.
+fn segment_data(data: &[u8], index: usize, chunks_per_segment: usize) -> Vec<u8> {
+    let segment_size = chunks_per_segment * CHUNK_SIZE;
+    let start = (index * segment_size).min(data.len());
+    let end = ((index + 1) * segment_size).min(data.len());
+    data[start..end].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::random;
+
+    fn random_data(size: usize) -> Vec<u8> {
+        (0..size).map(|_| random()).collect()
+    }
+
+    #[test]
+    fn test_segments_validated_by_node() {
+        // chunks padded or not, and the last chunk partial or not
+        for size in [
+            1,
+            CHUNK_SIZE,
+            4 * CHUNK_SIZE,
+            17 * CHUNK_SIZE - 3,
+            64 * CHUNK_SIZE,
+            // with a segment of padding only
+            65 * CHUNK_SIZE,
+            100 * CHUNK_SIZE + 1,
+        ] {
+            let file = File::with_chunks_per_segment(random_data(size), 4).unwrap();
+            assert_eq!(file.num_segments(), size.div_ceil(4 * CHUNK_SIZE));
+            let (num_segments, last_segment_size) =
+                SegmentWithProof::split_file_into_segments(size, 4).unwrap();
+            assert_eq!(file.num_segments(), num_segments);
+
+            for segment in file.segments() {
+                let segment = segment.unwrap();
+                segment.validate(4).unwrap();
+                if segment.index == num_segments - 1 {
+                    assert_eq!(segment.data.len(), last_segment_size);
+                }
+            }
+            assert!(file.segment(num_segments).is_err());
+        }
+    }
+
+    #[test]
+    fn test_root() {
+        // the same as the root verified by node for small files, with 1 padding chunk
+        let data = random_data(17 * CHUNK_SIZE - 3);
+        let file = File::new(data.clone()).unwrap();
+        SegmentWithProof::from_small_file(data, file.root(), DEFAULT_CHUNKS_PER_SEGMENT).unwrap();
+
+        // the same as the tree of chunks if not padded
+        let mut data = random_data(3 * DEFAULT_CHUNKS_PER_SEGMENT * CHUNK_SIZE - 3);
+        let file = File::new(data.clone()).unwrap();
+        data.resize(3 * DEFAULT_CHUNKS_PER_SEGMENT * CHUNK_SIZE, 0);
+        let root: DataRoot = sub_merkle_tree(&data).unwrap().root().into();
+        assert_eq!(file.root(), root);
+
+        assert!(File::new(vec![]).is_err());
+        assert!(File::with_chunks_per_segment(vec![1], 3).is_err());
+    }
+}
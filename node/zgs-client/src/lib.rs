@@ -0,0 +1,33 @@
+//! Client of storage nodes to upload and download files over RPC, which splits files into
+//! segments along with the file merkle proofs computed by the same primitives as the node.
+//!
+//! A file is uploaded once submitted to the flow contract with its root:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use zgs_client::{Client, File};
+//!
+//! # async fn example(data: Vec<u8>) -> anyhow::Result<()> {
+//! let client = Client::new("http://127.0.0.1:5678")?;
+//! let file = File::new(data)?;
+//!
+//! // submit `file.root()` and `file.size()` to the flow contract here
+//!
+//! client.upload(&file).await?;
+//! client
+//!     .wait_for_finalized(file.root(), Duration::from_secs(60))
+//!     .await?;
+//! assert_eq!(client.download(file.root()).await?, file.data());
+//! # Ok(())
+//! # }
+//! ```
+
+mod client;
+mod file;
+
+pub use client::Client;
+pub use file::File;
+
+/// Number of chunks in a segment, which is the same as `rpc.chunks_per_segment` of nodes by
+/// default.
+pub const DEFAULT_CHUNKS_PER_SEGMENT: usize = 1024;
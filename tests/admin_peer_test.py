@@ -0,0 +1,62 @@
+#!/usr/bin/env python3
+
+from config.node_config import ZGS_KEY_FILE, ZGS_NODEID
+from test_framework.test_framework import TestFramework
+from utility.utils import p2p_port, wait_until
+
+class AdminPeerTest(TestFramework):
+    """
+    This is to test admin RPCs to connect, inspect and disconnect a specific peer.
+    """
+
+    def setup_params(self):
+        self.num_nodes = 2
+
+        # node 0 with the well known peer id
+        self.zgs_node_key_files = [ZGS_KEY_FILE]
+        for i in range(self.num_nodes):
+            self.zgs_node_configs[i] = {
+                "network_enr_address": "127.0.0.1",
+                "network_enr_tcp_port": p2p_port(i),
+                "network_enr_udp_port": p2p_port(i),
+
+                # disable trusted nodes, so that nodes are connected by admin only
+                "network_libp2p_nodes": [],
+            }
+
+    def run_test(self):
+        client = self.nodes[1]
+        assert client.admin_get_peer_info(ZGS_NODEID) is None
+
+        # peer id is required
+        self.__assert_rpc_error(lambda: client.admin_connect_peer(f"/ip4/127.0.0.1/tcp/{p2p_port(0)}"))
+
+        address = f"/ip4/127.0.0.1/tcp/{p2p_port(0)}/p2p/{ZGS_NODEID}"
+        assert client.admin_connect_peer(address) == ZGS_NODEID
+        self.log.info("Node 1 connected to node 0")
+
+        info = client.admin_get_peer_info(ZGS_NODEID)
+        assert info["peerId"] == ZGS_NODEID
+        assert info["connectionStatus"]["status"] == "connected"
+        assert info["connectionDirection"] == "Outgoing"
+
+        # status exchanged once connected
+        wait_until(lambda: client.admin_get_peer_info(ZGS_NODEID)["requestsSent"] > 0)
+        info = client.admin_get_peer_info(ZGS_NODEID)
+        assert "status" in info["protocols"]
+        self.log.info("Peer info of node 0: %s", info)
+
+        assert client.admin_disconnect_peer(ZGS_NODEID)
+        wait_until(lambda: client.admin_get_peer_info(ZGS_NODEID)["connectionStatus"]["status"] != "connected")
+        assert not client.admin_disconnect_peer(ZGS_NODEID)
+        self.log.info("Node 1 disconnected from node 0")
+
+    def __assert_rpc_error(self, call):
+        try:
+            call()
+        except Exception:
+            return
+        raise AssertionError("RPC error expected")
+
+if __name__ == "__main__":
+    AdminPeerTest().main()
@@ -0,0 +1,44 @@
+#!/usr/bin/env python3
+
+import re
+
+from test_framework.test_framework import TestFramework
+from utility.utils import wait_until
+
+AGENT_VERSION_PATTERN = r"^zgs/v(\d+\.\d+\.\d+)-([0-9a-f]{7}|unknown)/[^/]+-[^/]+/(\d+\.\d+\.\d+)$"
+
+
+class ClientVersionTest(TestFramework):
+    """
+    This is to test the node version over RPC, and the peer versions advertised via identify.
+    """
+
+    def setup_params(self):
+        self.num_blockchain_nodes = 1
+        self.num_nodes = 2
+
+    def run_test(self):
+        client = self.nodes[1]
+
+        version = client.zgs_client_version()
+        self.log.info("Client version: %s", version)
+        assert re.match(r"^\d+\.\d+\.\d+$", version["version"])
+        assert version["buildTimestamp"] > 0
+
+        matched = re.match(AGENT_VERSION_PATTERN, version["agentVersion"])
+        assert matched is not None, version["agentVersion"]
+        assert matched.group(1) == version["version"]
+        assert matched.group(2) == version["gitCommit"]
+        p2p = version["p2pProtocolVersion"]
+        assert matched.group(3) == "%d.%d.%d" % (p2p["major"], p2p["minor"], p2p["build"])
+
+        # versions of connected peers recorded as known peers
+        peer_version = "v%s-%s" % (version["version"], version["gitCommit"])
+        wait_until(lambda: self.__known_peer_versions(client) == [peer_version], timeout=90)
+
+    def __known_peer_versions(self, client):
+        return [peer["version"] for peer in client.admin_get_known_peers()]
+
+
+if __name__ == "__main__":
+    ClientVersionTest().main()
@@ -0,0 +1,75 @@
+#!/usr/bin/env python3
+
+import signal
+import time
+
+from config.node_config import update_config
+from test_framework.test_framework import TestFramework
+from utility.utils import initialize_toml_config
+
+
+class ConfigReloadTest(TestFramework):
+    """
+    This is to test that dynamic options are applied when the config file reloaded at runtime,
+    and static options are rejected.
+    """
+
+    def setup_params(self):
+        self.num_blockchain_nodes = 1
+        self.num_nodes = 1
+
+    def run_test(self):
+        client = self.nodes[0]
+
+        # nothing changed
+        assert client.admin_reload_config() == {"applied": [], "rejected": []}
+
+        # dynamic options accepted
+        self.__update_config_file(client, {
+            "miner_cpu_percentage": 50,
+            "sync": {"max_sync_files": 16},
+            "router": {"drip_announce_rate": 5},
+        })
+        report = client.admin_reload_config()
+        assert report == {
+            "applied": [
+                "miner_cpu_percentage",
+                "sync.max_sync_files",
+                "router.drip_announce_rate",
+            ],
+            "rejected": [],
+        }, report
+
+        # static options rejected, while dynamic ones still applied
+        self.__update_config_file(client, {
+            "network_target_peers": 10,
+            "db_dir": "db2",
+            "sync": {"max_requests_per_peer": 8},
+        })
+        report = client.admin_reload_config()
+        assert report == {
+            "applied": ["sync.max_requests_per_peer"],
+            "rejected": ["network_target_peers", "db_dir"],
+        }, report
+
+        # rejected options are not applied until restart
+        report = client.admin_reload_config()
+        assert report == {
+            "applied": [],
+            "rejected": ["network_target_peers", "db_dir"],
+        }, report
+
+        # config also reloaded on SIGHUP instead of shutdown
+        self.log.info("Send SIGHUP to reload config")
+        client.process.send_signal(signal.SIGHUP)
+        time.sleep(1)
+        assert client.process.poll() is None
+        assert client.zgs_get_status() is not None
+
+    def __update_config_file(self, client, updated_config):
+        update_config(client.config, updated_config)
+        initialize_toml_config(client.config_file, client.config)
+
+
+if __name__ == "__main__":
+    ConfigReloadTest().main()
@@ -0,0 +1,104 @@
+#!/usr/bin/env python3
+
+import hashlib
+import os
+import random
+
+import requests
+
+from config.node_config import update_config
+from test_framework.test_framework import TestFramework
+from utility.submission import create_submission, data_to_segments
+from utility.utils import initialize_toml_config, wait_until
+
+INVALID_PARAMS_CODE = -32602
+
+
+class ExportFileTest(TestFramework):
+    """
+    This is to test that a stored file could be exported to the local export directory, and the
+    paths out of the directory or already existing are rejected.
+    """
+
+    def setup_params(self):
+        self.num_blockchain_nodes = 1
+        self.num_nodes = 1
+
+    def run_test(self):
+        client = self.nodes[0]
+
+        # 3 segments with the last one partially filled
+        chunk_data = random.randbytes(256 * 1024 * 2 + 1000)
+        data_root = self.__upload(chunk_data)
+        tx_seq = client.zgs_get_file_info(data_root)["tx"]["seq"]
+        expected_hash = hashlib.sha256(chunk_data).hexdigest()
+
+        # not supported without export directory
+        error = self.__export(client, tx_seq, "a.bin")["error"]
+        assert error["code"] == -32601, error
+
+        export_dir = os.path.join(client.data_dir, "export")
+        os.makedirs(os.path.join(export_dir, "sub"))
+        client.shutdown()
+        update_config(client.config, {"rpc": {"export_dir": export_dir}})
+        initialize_toml_config(client.config_file, client.config)
+        self.start_storage_node(0)
+        client.wait_for_rpc_connection()
+
+        # export by tx seq
+        exported = self.__export(client, tx_seq, "a.bin")["result"]
+        assert exported["txSeq"] == tx_seq
+        assert exported["size"] == len(chunk_data)
+        assert exported["sha256"] == expected_hash
+        with open(os.path.join(export_dir, "a.bin"), "rb") as f:
+            assert f.read() == chunk_data
+
+        # export by data root into sub directory
+        exported = self.__export(client, data_root, "sub/b.bin")["result"]
+        assert exported["sha256"] == expected_hash
+        with open(os.path.join(export_dir, "sub", "b.bin"), "rb") as f:
+            assert hashlib.sha256(f.read()).hexdigest() == expected_hash
+
+        # never overwritten
+        error = self.__export(client, tx_seq, "a.bin")["error"]
+        assert error["code"] == INVALID_PARAMS_CODE, error
+
+        # out of export directory
+        for path in ["../c.bin", os.path.join(client.data_dir, "c.bin")]:
+            error = self.__export(client, tx_seq, path)["error"]
+            assert error["code"] == INVALID_PARAMS_CODE, error
+        assert not os.path.exists(os.path.join(client.data_dir, "c.bin"))
+
+        # file not found
+        error = self.__export(client, tx_seq + 1, "d.bin")["error"]
+        assert error["code"] == 102, error
+        assert not os.path.exists(os.path.join(export_dir, "d.bin"))
+
+    def __upload(self, chunk_data):
+        client = self.nodes[0]
+        submissions, data_root = create_submission(chunk_data)
+        self.contract.submit(submissions)
+        wait_until(lambda: self.contract.num_submissions() == 1)
+        wait_until(lambda: client.zgs_get_file_info(data_root) is not None)
+
+        for segment in data_to_segments(chunk_data):
+            client.zgs_upload_segment(segment)
+        wait_until(lambda: client.zgs_get_file_info(data_root)["finalized"])
+
+        return data_root
+
+    def __export(self, client, tx_seq_or_root, path):
+        return requests.post(
+            client.rpc_url,
+            json={
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "admin_exportFile",
+                "params": [tx_seq_or_root, path],
+            },
+            timeout=30,
+        ).json()
+
+
+if __name__ == "__main__":
+    ExportFileTest().main()
@@ -0,0 +1,64 @@
+#!/usr/bin/env python3
+
+from test_framework.test_framework import TestFramework
+from utility.submission import create_submission, data_to_segments, submit_data
+from utility.utils import wait_until
+
+UNKNOWN_ROOT = "0x" + "11" * 32
+
+
+class FileInfoBatchTest(TestFramework):
+    """
+    This is to test that zgs_getFileInfoBatch reports the availability of finalized, partially
+    uploaded and unknown files in the order of the roots, including the segments written into
+    store before restart.
+    """
+
+    def setup_params(self):
+        self.num_blockchain_nodes = 1
+        self.num_nodes = 1
+
+    def run_test(self):
+        client = self.nodes[0]
+
+        finalized_data = b"\x02" * 256 * 1024 * 2
+        submissions, finalized_root = create_submission(finalized_data)
+        self.contract.submit(submissions)
+        partial_data = b"\x03" * 256 * 1024 * 3
+        submissions, partial_root = create_submission(partial_data)
+        self.contract.submit(submissions)
+        wait_until(lambda: self.contract.num_submissions() == 2)
+        wait_until(lambda: client.zgs_get_file_info(partial_root) is not None)
+
+        submit_data(client, finalized_data)
+        wait_until(lambda: client.zgs_get_file_info(finalized_root)["finalized"])
+        client.zgs_upload_segment(data_to_segments(partial_data)[0])
+
+        batch = client.zgs_get_file_info_batch(
+            [partial_root, UNKNOWN_ROOT, finalized_root]
+        )
+        assert [f["root"] for f in batch] == [partial_root, UNKNOWN_ROOT, finalized_root]
+        assert not batch[0]["neverSubmitted"]
+        assert not batch[0]["finalized"]
+        assert batch[0]["uploadedSegments"] == 1
+        assert batch[0]["totalSegments"] == 3
+        assert batch[1]["neverSubmitted"]
+        assert batch[1]["tx"] is None
+        assert batch[2]["finalized"]
+        assert batch[2]["uploadedSegments"] == batch[2]["totalSegments"] == 2
+
+        # The uploaded segment is counted from store once not tracked in the chunk pool.
+        self.stop_storage_node(0)
+        self.start_storage_node(0)
+        self.nodes[0].wait_for_rpc_connection()
+        wait_until(
+            lambda: client.zgs_get_file_info_batch([partial_root])[0]["uploadedSegments"]
+            == 1
+        )
+        info = client.zgs_get_file_info_batch([partial_root])[0]
+        assert not info["finalized"]
+        assert not info["isCached"]
+
+
+if __name__ == "__main__":
+    FileInfoBatchTest().main()
@@ -0,0 +1,72 @@
+#!/usr/bin/env python3
+
+import random
+import subprocess
+import threading
+
+from test_framework.test_framework import TestFramework
+from utility.submission import create_submission, submit_data
+from utility.utils import wait_until
+
+NUM_FILES = 10
+FILE_SIZE = 256 * 1024 * 4
+
+
+class GracefulShutdownTest(TestFramework):
+    """
+    This is to test that the db opens without repair after the node is shut down by SIGTERM
+    during heavy writes.
+    """
+
+    def setup_params(self):
+        self.num_blockchain_nodes = 1
+        self.num_nodes = 1
+        self.zgs_node_configs[0] = {
+            "shutdown_grace_period_secs": 5,
+        }
+
+    def run_test(self):
+        client = self.nodes[0]
+
+        files = []
+        for _ in range(NUM_FILES):
+            chunk_data = random.randbytes(FILE_SIZE)
+            submissions, data_root = create_submission(chunk_data)
+            self.contract.submit(submissions)
+            files.append((chunk_data, data_root))
+        wait_until(lambda: self.contract.num_submissions() == NUM_FILES)
+        wait_until(lambda: client.zgs_get_file_info(files[-1][1]) is not None)
+
+        # keep uploading files until the node is shut down
+        uploader = threading.Thread(target=self.__upload, args=(client, files), daemon=True)
+        uploader.start()
+        wait_until(lambda: client.zgs_get_file_info(files[0][1])["finalized"])
+
+        self.log.info("Send SIGTERM during uploading")
+        client.stop()
+        uploader.join()
+
+        result = subprocess.run(
+            client.args + ["check"],
+            cwd=client.data_dir,
+            capture_output=True,
+            text=True,
+        )
+        self.log.info("DB check report:\n%s", result.stdout)
+        assert result.returncode == 0, "DB check failed: %s" % result.stdout
+
+        # files finalized before shutdown are still available after restart
+        self.start_storage_node(0)
+        client.wait_for_rpc_connection()
+        assert client.zgs_get_file_info(files[0][1])["finalized"]
+
+    def __upload(self, client, files):
+        try:
+            for chunk_data, _ in files:
+                submit_data(client, chunk_data)
+        except Exception as e:
+            self.log.info("Uploading stopped: %s", e)
+
+
+if __name__ == "__main__":
+    GracefulShutdownTest().main()
@@ -0,0 +1,66 @@
+#!/usr/bin/env python3
+
+import glob
+import json
+import os
+import time
+
+from test_framework.test_framework import TestFramework
+from utility.utils import wait_until
+
+
+class LogFormatTest(TestFramework):
+    """
+    This is to test logs in JSON format, and the log filter changed at runtime.
+    """
+
+    def setup_params(self):
+        self.num_blockchain_nodes = 1
+        self.num_nodes = 1
+        self.zgs_node_configs[0] = {
+            "log": {"format": "json"},
+        }
+
+    def run_test(self):
+        client = self.nodes[0]
+
+        logs = self.__read_logs(client)
+        assert len(logs) > 0
+        assert all("level" in log and "target" in log and "message" in log for log in logs)
+
+        # info logs of admin RPC filtered
+        client.admin_set_log_filter("warn")
+        client.admin_reload_config()
+        time.sleep(1)
+        assert not self.__has_log(client, "admin_reloadConfig()")
+
+        # invalid filter rejected, and the current filter kept
+        self.__assert_rpc_error(lambda: client.admin_set_log_filter("rpc=verbose"))
+        client.admin_reload_config()
+        time.sleep(1)
+        assert not self.__has_log(client, "admin_reloadConfig()")
+
+        client.admin_set_log_filter("rpc=info")
+        client.admin_reload_config()
+        wait_until(lambda: self.__has_log(client, "admin_reloadConfig()"))
+
+    def __read_logs(self, client):
+        logs = []
+        for file in sorted(glob.glob(os.path.join(client.data_dir, "log", "zgs.log*"))):
+            with open(file) as f:
+                logs.extend(json.loads(line) for line in f if line.strip())
+        return logs
+
+    def __has_log(self, client, message):
+        return any(log["message"] == message for log in self.__read_logs(client))
+
+    def __assert_rpc_error(self, call):
+        try:
+            call()
+        except Exception:
+            return
+        raise AssertionError("RPC error expected")
+
+
+if __name__ == "__main__":
+    LogFormatTest().main()
@@ -0,0 +1,49 @@
+#!/usr/bin/env python3
+
+import requests
+
+from test_framework.test_framework import TestFramework
+from utility.submission import create_submission, submit_data
+from utility.utils import metrics_port, wait_until
+
+
+class MetricsExporterTest(TestFramework):
+    def setup_params(self):
+        self.num_blockchain_nodes = 1
+        self.num_nodes = 1
+        self.zgs_node_configs[0] = {
+            "metrics": {
+                "enabled": True,
+                "listen": f"127.0.0.1:{metrics_port(0)}",
+            },
+        }
+
+    def run_test(self):
+        client = self.nodes[0]
+
+        chunk_data = b"\x02" * 256 * 4
+        submissions, data_root = create_submission(chunk_data)
+        self.contract.submit(submissions)
+        wait_until(lambda: self.contract.num_submissions() == 1)
+        wait_until(lambda: client.zgs_get_file_info(data_root) is not None)
+
+        submit_data(client, chunk_data)
+        wait_until(lambda: client.zgs_get_file_info(data_root)["finalized"])
+
+        url = f"http://127.0.0.1:{metrics_port(0)}/metrics"
+        wait_until(lambda: "zgs_db_column_keys" in requests.get(url).text)
+
+        text = requests.get(url).text
+        for name in [
+            "zgs_log_store_tx_store_put_tx",
+            "zgs_log_store_put_chunks",
+            "zgs_log_entry_sync_manager_put_tx_speed_in_bytes",
+            'zgs_db_column_keys{db="flow",column="tx"}',
+        ]:
+            assert name in text, "metric %s not found" % name
+
+        assert requests.get(f"http://127.0.0.1:{metrics_port(0)}/unknown").status_code == 404
+
+
+if __name__ == "__main__":
+    MetricsExporterTest().main()
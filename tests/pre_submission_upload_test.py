@@ -0,0 +1,37 @@
+#!/usr/bin/env python3
+
+from test_framework.test_framework import TestFramework
+from utility.submission import create_submission, data_to_segments
+from utility.utils import wait_until
+
+
+class PreSubmissionUploadTest(TestFramework):
+    def setup_params(self):
+        self.num_blockchain_nodes = 1
+        self.num_nodes = 1
+
+    def run_test(self):
+        client = self.nodes[0]
+
+        chunk_data = b"\x03" * 256 * (1024 * 3 + 5)
+        submissions, data_root = create_submission(chunk_data)
+
+        # Upload all segments out of order before the submission is observed.
+        segments = data_to_segments(chunk_data)
+        for segment in reversed(segments):
+            client.zgs_upload_segment(segment)
+        assert client.zgs_get_file_info(data_root) is None
+
+        self.contract.submit(submissions)
+        wait_until(lambda: self.contract.num_submissions() == 1)
+        wait_until(lambda: client.zgs_get_file_info(data_root) is not None)
+
+        # Finalized without any re-upload.
+        wait_until(lambda: client.zgs_get_file_info(data_root)["finalized"])
+        info = client.zgs_get_file_info(data_root)
+        assert not info["isCached"]
+        assert info["uploadedSegNum"] == len(segments)
+
+
+if __name__ == "__main__":
+    PreSubmissionUploadTest().main()
@@ -0,0 +1,87 @@
+#!/usr/bin/env python3
+
+import random
+
+import requests
+
+from config.node_config import update_config
+from test_framework.test_framework import TestFramework
+from utility.submission import create_submission, data_to_segments
+from utility.utils import initialize_toml_config, wait_until
+
+NODE_READ_ONLY_CODE = 205
+
+
+class ReadOnlyModeTest(TestFramework):
+    """
+    This is to test that the db of a stopped node could be served read-only, which rejects
+    uploads and file syncs without writing into the db.
+    """
+
+    def setup_params(self):
+        self.num_blockchain_nodes = 1
+        self.num_nodes = 1
+
+    def run_test(self):
+        client = self.nodes[0]
+
+        chunk_data = random.randbytes(64 * 1024)
+        data_root = self.__upload(chunk_data)
+        tx_seq = client.zgs_get_file_info(data_root)["tx"]["seq"]
+
+        # open the same db read-only
+        client.shutdown()
+        update_config(client.config, {"node_mode": "readonly"})
+        initialize_toml_config(client.config_file, client.config)
+        self.start_storage_node(0)
+        client.wait_for_rpc_connection()
+
+        # file and proofs served
+        assert client.zgs_get_file_info(data_root)["finalized"]
+        assert client.zgs_download_segment_decoded(data_root, 0, 256) == chunk_data
+        assert client.zgs_get_flow_proof(0, 1) is not None
+
+        # new files neither synced from blockchain nor uploaded
+        new_data = random.randbytes(256)
+        new_submissions, new_root = create_submission(new_data)
+        self.contract.submit(new_submissions)
+        wait_until(lambda: self.contract.num_submissions() == 2)
+        assert client.zgs_get_file_info(new_root) is None
+
+        segment = data_to_segments(new_data)[0]
+        error = self.__call(client, "zgs_uploadSegment", [segment])["error"]
+        assert error["code"] == NODE_READ_ONLY_CODE, error
+
+        error = self.__call(client, "admin_startSyncFile", [tx_seq])["error"]
+        assert error["code"] == NODE_READ_ONLY_CODE, error
+
+        # still read-only after restart
+        client.shutdown()
+        self.start_storage_node(0)
+        client.wait_for_rpc_connection()
+        assert client.zgs_download_segment_decoded(data_root, 0, 256) == chunk_data
+        assert client.zgs_get_file_info(new_root) is None
+
+    def __upload(self, chunk_data):
+        client = self.nodes[0]
+        submissions, data_root = create_submission(chunk_data)
+        self.contract.submit(submissions)
+        wait_until(lambda: self.contract.num_submissions() == 1)
+        wait_until(lambda: client.zgs_get_file_info(data_root) is not None)
+
+        for segment in data_to_segments(chunk_data):
+            client.zgs_upload_segment(segment)
+        wait_until(lambda: client.zgs_get_file_info(data_root)["finalized"])
+
+        return data_root
+
+    def __call(self, client, method, params):
+        return requests.post(
+            client.rpc_url,
+            json={"jsonrpc": "2.0", "id": 1, "method": method, "params": params},
+            timeout=10,
+        ).json()
+
+
+if __name__ == "__main__":
+    ReadOnlyModeTest().main()
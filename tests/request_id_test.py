@@ -0,0 +1,78 @@
+#!/usr/bin/env python3
+
+import glob
+import os
+
+import requests
+
+from test_framework.test_framework import TestFramework
+from utility.utils import wait_until
+
+REQUEST_ID = "sync-file-0a1b2c"
+
+
+class RequestIdTest(TestFramework):
+    """
+    This is to test the correlation id of RPC requests, which is echoed back to client, and
+    recorded in logs of the sync requests and storage operations on behalf of the request.
+    """
+
+    def setup_params(self):
+        self.num_nodes = 2
+
+    def run_test(self):
+        client1 = self.nodes[0]
+        client2 = self.nodes[1]
+
+        # stop client2, preventing it from receiving AnnounceFile
+        client2.shutdown()
+        data_root = self.__upload_file__(0, 256 * 1024)
+        client2.start()
+        client2.wait_for_rpc_connection()
+        wait_until(lambda: client2.zgs_get_file_info(data_root) is not None)
+
+        # correlation id echoed back
+        response = self.__call(client2, "admin_startSyncFile", [0], REQUEST_ID)
+        assert response.headers["X-Request-Id"] == REQUEST_ID
+        assert "error" not in response.json(), response.json()
+        wait_until(lambda: client2.zgs_get_file_info(data_root)["finalized"])
+
+        # generated if not provided
+        response = self.__call(client2, "zgs_getStatus", [], None)
+        assert len(response.headers["X-Request-Id"]) == 16
+
+        # and in error data
+        response = self.__call(client2, "admin_startSyncFile", [100], "not-found")
+        assert response.json()["error"]["data"]["request_id"] == "not-found"
+
+        # the full lifecycle of file sync found by the correlation id
+        logs = self.__grep_logs(client2, REQUEST_ID)
+        self.log.info("Found %d logs of request %s", len(logs), REQUEST_ID)
+        for message in [
+            "admin_startSyncFile(0)",
+            "Start to sync file",
+            "Sending request",
+            "Async storage operation completed",
+        ]:
+            assert any(message in line for line in logs), "log not found: %s" % message
+        assert any("file_sync" in line for line in logs)
+
+    def __call(self, client, method, params, request_id):
+        headers = {} if request_id is None else {"X-Request-Id": request_id}
+        return requests.post(
+            client.rpc_url,
+            json={"jsonrpc": "2.0", "id": 1, "method": method, "params": params},
+            headers=headers,
+            timeout=10,
+        )
+
+    def __grep_logs(self, client, request_id):
+        lines = []
+        for file in sorted(glob.glob(os.path.join(client.data_dir, "log", "zgs.log*"))):
+            with open(file) as f:
+                lines.extend(line for line in f if request_id in line)
+        return lines
+
+
+if __name__ == "__main__":
+    RequestIdTest().main()
@@ -1,17 +1,25 @@
 #!/usr/bin/env python3
 
+import requests
+
 from test_framework.blockchain_node import BlockChainNodeType
 from test_framework.test_framework import TestFramework
 from test_framework.conflux_node import connect_nodes, disconnect_nodes, sync_blocks
 from config.node_config import CONFLUX_CONFIG, TX_PARAMS1
 from utility.submission import create_submission, submit_data
-from utility.utils import wait_until
+from utility.utils import metrics_port, wait_until
 
 
 class RevertTest(TestFramework):
     def setup_params(self):
         self.num_blockchain_nodes = 2
         self.num_nodes = 1
+        self.zgs_node_configs[0] = {
+            "metrics": {
+                "enabled": True,
+                "listen": f"127.0.0.1:{metrics_port(0)}",
+            },
+        }
 
         del CONFLUX_CONFIG["dev_block_interval_ms"]
 
@@ -66,6 +74,20 @@ class RevertTest(TestFramework):
         self.log.info("Node 2 epoch {}".format(blockchain_client2.cfx_epochNumber()))
 
         wait_until(lambda: client.zgs_get_file_info(data_root) is None)
+        history = client.admin_get_reorg_history()
+        assert len(history) > 0, history
+        event = history[-1]
+        assert event["minSeq"] == 0 and event["maxSeq"] == 1, event
+        assert event["depth"] == 1 and event["numTxs"] == 1, event
+
+        text = requests.get(f"http://127.0.0.1:{metrics_port(0)}/metrics").text
+        for name in [
+            "zgs_log_store_revert_depth",
+            "zgs_log_store_revert_truncated_batches",
+            "zgs_log_store_reverted_txs",
+            "zgs_log_store_tx_store_remove_tx_after",
+        ]:
+            assert name in text, "metric %s not found" % name
         wait_until(lambda: client.zgs_get_file_info(data_root1) is not None)
         wait_until(lambda: not client.zgs_get_file_info(data_root1)["finalized"])
 
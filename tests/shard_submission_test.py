@@ -77,5 +77,15 @@ class ShardSubmitTest(TestFramework):
             submit_data(client, chunk_data)
             wait_until(lambda: client.zgs_get_file_info(data_root)["finalized"])
 
+        # Every node only stores its portion of the file, unless the whole file is in its shard.
+        shard_finalized = [
+            self.nodes[i].zgs_get_file_info(data_root)["shardFinalized"]
+            for i in range(4)
+        ]
+        if size <= ENTRY_SIZE:
+            assert_equal(shard_finalized.count(False), 1)
+        elif size >= ENTRY_SIZE * 1024 * 4:
+            assert_equal(shard_finalized, [True] * 4)
+
 if __name__ == "__main__":
     ShardSubmitTest().main()
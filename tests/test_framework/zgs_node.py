@@ -93,9 +93,21 @@ class ZgsNode(TestNode):
     def zgs_get_status(self):
         return self.rpc.zgs_getStatus()["connectedPeers"]
 
+    def zgs_client_version(self):
+        return self.rpc.zgs_clientVersion()
+
     def zgs_upload_segment(self, segment):
         return self.rpc.zgs_uploadSegment([segment])
 
+    def zgs_begin_upload(self, data_root, size):
+        return self.rpc.zgs_beginUpload([data_root, size])
+
+    def zgs_upload_segments_with_token(self, token, segments):
+        return self.rpc.zgs_uploadSegmentsWithToken([token, segments])
+
+    def zgs_complete_upload(self, token):
+        return self.rpc.zgs_completeUpload([token])
+
     def zgs_download_segment(self, data_root, start_index, end_index):
         return self.rpc.zgs_downloadSegment([data_root, start_index, end_index])
     
@@ -108,10 +120,22 @@ class ZgsNode(TestNode):
 
     def zgs_get_file_info_by_tx_seq(self, tx_seq):
         return self.rpc.zgs_getFileInfoByTxSeq([tx_seq])
+
+    def zgs_get_file_info_batch(self, roots):
+        return self.rpc.zgs_getFileInfoBatch([roots])
     
     def zgs_get_flow_context(self, tx_seq):
         return self.rpc.zgs_getFlowContext([tx_seq])
 
+    def zgs_get_flow_proof(self, entry_index, count):
+        return self.rpc.zgs_getFlowProof([entry_index, count])
+
+    def zgs_get_transaction(self, tx_seq):
+        return self.rpc.zgs_getTransaction([tx_seq])
+
+    def zgs_get_transactions(self, start_seq, limit):
+        return self.rpc.zgs_getTransactions([start_seq, limit])
+
     def shutdown(self):
         self.rpc.admin_shutdown()
         self.wait_until_stopped()
@@ -132,5 +156,74 @@ class ZgsNode(TestNode):
     def admin_get_file_location(self, tx_seq, all_shards = True):
         return self.rpc.admin_getFileLocation([tx_seq, all_shards])
 
+    def admin_resync_file(self, tx_seq, verify_first = True):
+        return self.rpc.admin_resyncFile([tx_seq, verify_first])
+
+    def admin_terminate_file_sync(self, tx_seq):
+        return self.rpc.admin_terminateFileSync([tx_seq])
+
+    def admin_retry_file_sync(self, tx_seq):
+        return self.rpc.admin_retryFileSync([tx_seq])
+
+    def admin_trace_file_sync(self, tx_seq, enable=True):
+        return self.rpc.admin_traceFileSync([tx_seq, enable])
+
+    def admin_get_file_sync_trace(self, tx_seq):
+        return self.rpc.admin_getFileSyncTrace([tx_seq])
+
+    def admin_get_chunk_pool_status(self):
+        return self.rpc.admin_getChunkPoolStatus()
+
+    def admin_evict_pending_file(self, data_root):
+        return self.rpc.admin_evictPendingFile([data_root])
+
+    def admin_get_log_sync_status(self):
+        return self.rpc.admin_getLogSyncStatus()
+
+    def admin_get_earnings(self):
+        return self.rpc.admin_getEarnings()
+
+    def admin_get_miner_rewards(self, from_epoch, to_epoch):
+        return self.rpc.admin_getMinerRewards([from_epoch, to_epoch])
+
+    def admin_get_miner_status(self):
+        return self.rpc.admin_getMinerStatus()
+
+    def admin_stream_sealed_batches(self, from_chunk, to_chunk):
+        return self.rpc.admin_streamSealedBatches([from_chunk, to_chunk])
+
+    def admin_get_sealed_chunk(self, chunk_index):
+        return self.rpc.admin_getSealedChunk([chunk_index])
+
+    def admin_submit_external_answer(self, answer):
+        return self.rpc.admin_submitExternalAnswer([answer])
+
+    def admin_get_known_peers(self):
+        return self.rpc.admin_getKnownPeers()
+
+    def admin_get_reorg_history(self):
+        return self.rpc.admin_getReorgHistory()
+
+    def admin_get_block_progress(self, from_block, limit):
+        return self.rpc.admin_getBlockProgress([from_block, limit])
+
+    def admin_reload_config(self):
+        return self.rpc.admin_reloadConfig()
+
+    def admin_set_log_filter(self, filter):
+        return self.rpc.admin_setLogFilter([filter])
+
+    def admin_update_peer_policy(self, allowlist=[], denylist=[]):
+        return self.rpc.admin_updatePeerPolicy([{"allowlist": allowlist, "denylist": denylist}])
+
+    def admin_connect_peer(self, address):
+        return self.rpc.admin_connectPeer([address])
+
+    def admin_disconnect_peer(self, peer_id):
+        return self.rpc.admin_disconnectPeer([peer_id])
+
+    def admin_get_peer_info(self, peer_id):
+        return self.rpc.admin_getPeerInfo([peer_id])
+
     def clean_data(self):
         shutil.rmtree(os.path.join(self.data_dir, "db"))
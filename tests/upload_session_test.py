@@ -0,0 +1,80 @@
+#!/usr/bin/env python3
+
+import base64
+
+import requests
+
+from test_framework.test_framework import TestFramework
+from utility.submission import create_submission, data_to_segments
+from utility.utils import wait_until
+
+UPLOAD_SESSION_NOT_FOUND_CODE = 119
+
+
+def uploaded_segments(session):
+    bitmap = base64.b64decode(session["uploaded"])
+    return [
+        i for i in range(session["totalSegments"]) if bitmap[i // 8] & (1 << (i % 8))
+    ]
+
+
+class UploadSessionTest(TestFramework):
+    """
+    This is to test that an interrupted upload is resumed with the server-assigned token, and
+    the uploaded segments are skipped.
+    """
+
+    def setup_params(self):
+        self.num_blockchain_nodes = 1
+        self.num_nodes = 1
+
+    def run_test(self):
+        client = self.nodes[0]
+
+        chunk_data = b"\x02" * 256 * 1024 * 3
+        submissions, data_root = create_submission(chunk_data)
+        self.contract.submit(submissions)
+        wait_until(lambda: self.contract.num_submissions() == 1)
+        wait_until(lambda: client.zgs_get_file_info(data_root) is not None)
+
+        segments = data_to_segments(chunk_data)
+        assert len(segments) == 3
+
+        session = client.zgs_begin_upload(data_root, len(chunk_data))
+        token = session["token"]
+        assert uploaded_segments(session) == []
+
+        # upload interrupted after the first segment
+        client.zgs_upload_segments_with_token(token, segments[:1])
+        completion = client.zgs_complete_upload(token)
+        assert not completion["finalized"]
+        assert completion["missing"] == [[1, 3]]
+
+        # resumed with the same token and the uploaded segments
+        session = client.zgs_begin_upload(data_root, len(chunk_data))
+        assert session["token"] == token
+        assert uploaded_segments(session) == [0]
+
+        # uploaded segments are skipped
+        client.zgs_upload_segments_with_token(token, segments)
+        completion = client.zgs_complete_upload(token)
+        if not completion["finalized"]:
+            wait_until(lambda: client.zgs_get_file_info(data_root)["finalized"])
+            completion = client.zgs_complete_upload(token)
+        assert completion["finalized"]
+        assert completion["missing"] == []
+
+        # session ends once completed
+        error = self.__call(client, "zgs_completeUpload", [token])["error"]
+        assert error["code"] == UPLOAD_SESSION_NOT_FOUND_CODE, error
+
+    def __call(self, client, method, params):
+        return requests.post(
+            client.rpc_url,
+            json={"jsonrpc": "2.0", "id": 1, "method": method, "params": params},
+            timeout=10,
+        ).json()
+
+
+if __name__ == "__main__":
+    UploadSessionTest().main()
@@ -44,6 +44,9 @@ def blockchain_rpc_port_tendermint(n):
 def pprof_port(n):
     return PortMin.n + MAX_NODES + 6 * MAX_BLOCKCHAIN_NODES + n
 
+def metrics_port(n):
+    return PortMin.n + MAX_NODES + 7 * MAX_BLOCKCHAIN_NODES + n
+
 def wait_until(predicate, *, attempts=float("inf"), timeout=float("inf"), lock=None):
     if attempts == float("inf") and timeout == float("inf"):
         timeout = 60
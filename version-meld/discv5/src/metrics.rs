@@ -16,6 +16,10 @@ pub struct InternalMetrics {
     pub bytes_sent: AtomicUsize,
     /// The number of bytes received.
     pub bytes_recv: AtomicUsize,
+    /// The number of packets sent.
+    pub packets_sent: AtomicUsize,
+    /// The number of packets received.
+    pub packets_recv: AtomicUsize,
 }
 
 impl Default for InternalMetrics {
@@ -26,6 +30,8 @@ impl Default for InternalMetrics {
             unsolicited_requests_per_window: AtomicUsize::new(0),
             bytes_sent: AtomicUsize::new(0),
             bytes_recv: AtomicUsize::new(0),
+            packets_sent: AtomicUsize::new(0),
+            packets_recv: AtomicUsize::new(0),
         }
     }
 }
@@ -35,12 +41,14 @@ impl InternalMetrics {
         let current_bytes_recv = self.bytes_recv.load(Ordering::Relaxed);
         self.bytes_recv
             .store(current_bytes_recv.saturating_add(bytes), Ordering::Relaxed);
+        self.packets_recv.fetch_add(1, Ordering::Relaxed);
     }
 
     pub fn add_sent_bytes(&self, bytes: usize) {
         let current_bytes_sent = self.bytes_sent.load(Ordering::Relaxed);
         self.bytes_sent
             .store(current_bytes_sent.saturating_add(bytes), Ordering::Relaxed);
+        self.packets_sent.fetch_add(1, Ordering::Relaxed);
     }
 }
 
@@ -55,6 +63,10 @@ pub struct Metrics {
     pub bytes_sent: usize,
     /// The number of bytes received.
     pub bytes_recv: usize,
+    /// The number of packets sent.
+    pub packets_sent: usize,
+    /// The number of packets received.
+    pub packets_recv: usize,
 }
 
 impl From<&METRICS> for Metrics {
@@ -67,6 +79,8 @@ impl From<&METRICS> for Metrics {
                 / internal_metrics.moving_window as f64,
             bytes_sent: internal_metrics.bytes_sent.load(Ordering::Relaxed),
             bytes_recv: internal_metrics.bytes_recv.load(Ordering::Relaxed),
+            packets_sent: internal_metrics.packets_sent.load(Ordering::Relaxed),
+            packets_recv: internal_metrics.packets_recv.load(Ordering::Relaxed),
         }
     }
 }